@@ -10,8 +10,9 @@ use core::ptr;
 use spin::Once;
 
 use crate::boot::{
-    BootInfo, BootMethod, CpuArchitecture, CpuInfo, ExecutableImage, FramebufferInfo, MemoryMap,
-    PixelFormat, EfiMemoryDescriptor, EfiPixelBitmask,
+    BootInfo, BootMethod, CommandLine, CpuArchitecture, CpuInfo, ExecutableImage,
+    FramebufferInfo, MemoryMap, PixelFormat, EfiMemoryDescriptor, EfiPixelBitmask, VideoMode,
+    VideoModes, COMMAND_LINE_MAX,
 };
 
 extern "C" {
@@ -48,12 +49,25 @@ type EfiWaitForEvent =
 
 type EfiStall = extern "win64" fn(microseconds: usize) -> EfiStatus;
 
+type EfiHandleProtocol = extern "win64" fn(
+    handle: EfiHandle,
+    protocol: *const EfiGuid,
+    interface: *mut *mut c_void,
+) -> EfiStatus;
+
 type EfiLocateProtocol = extern "win64" fn(
     protocol: *const EfiGuid,
     registration: *mut c_void,
     interface: *mut *mut c_void,
 ) -> EfiStatus;
 
+type EfiGopQueryMode = extern "win64" fn(
+    this: *const EfiGraphicsOutputProtocol,
+    mode_number: u32,
+    size_of_info: *mut usize,
+    info: *mut *mut EfiGraphicsOutputModeInformation,
+) -> EfiStatus;
+
 type EfiSetWatchdogTimer = extern "win64" fn(
     timeout: usize,
     watchdog_code: u64,
@@ -119,7 +133,7 @@ struct EfiBootServices {
     install_protocol_interface: usize,
     reinstall_protocol_interface: usize,
     uninstall_protocol_interface: usize,
-    handle_protocol: usize,
+    handle_protocol: EfiHandleProtocol,
     _reserved: usize,
     register_protocol_notify: usize,
     locate_handle: usize,
@@ -165,7 +179,7 @@ struct EfiGraphicsOutputProtocolMode {
 
 #[repr(C)]
 struct EfiGraphicsOutputProtocol {
-    query_mode: usize,
+    query_mode: EfiGopQueryMode,
     set_mode: usize,
     blt: usize,
     mode: *const EfiGraphicsOutputProtocolMode,
@@ -178,6 +192,30 @@ const GOP_GUID: EfiGuid = EfiGuid {
     data4: [0x96, 0xFB, 0x7A, 0xDE, 0xD0, 0x80, 0x51, 0x6A],
 };
 
+#[repr(C)]
+struct EfiLoadedImageProtocol {
+    revision: u32,
+    parent_handle: EfiHandle,
+    system_table: *mut c_void,
+    device_handle: EfiHandle,
+    file_path: *mut c_void,
+    _reserved: *mut c_void,
+    load_options_size: u32,
+    load_options: *const u16,
+    image_base: *mut c_void,
+    image_size: u64,
+    image_code_type: u32,
+    image_data_type: u32,
+    unload: usize,
+}
+
+const LOADED_IMAGE_GUID: EfiGuid = EfiGuid {
+    data1: 0x5B1B31A1,
+    data2: 0x9562,
+    data3: 0x11D2,
+    data4: [0x8E, 0x3F, 0x00, 0xA0, 0xC9, 0x69, 0x72, 0x3B],
+};
+
 fn get_cpu_vendor() -> [u8; 12] {
     let mut vendor = [0u8; 12];
 
@@ -264,7 +302,7 @@ fn cpu_info() -> CpuInfo {
     }
 }
 
-fn setup_framebuffer(bs: &mut EfiBootServices) -> Option<FramebufferInfo> {
+fn locate_gop(bs: &EfiBootServices) -> Option<&'static EfiGraphicsOutputProtocol> {
     let mut gop_ptr: *mut c_void = ptr::null_mut();
     let status = (bs.locate_protocol)(&GOP_GUID, ptr::null_mut(), &mut gop_ptr);
 
@@ -278,6 +316,21 @@ fn setup_framebuffer(bs: &mut EfiBootServices) -> Option<FramebufferInfo> {
         return None;
     }
 
+    Some(gop)
+}
+
+fn gop_pixel_format(raw: u32) -> PixelFormat {
+    match raw {
+        0 => PixelFormat::Rgb,
+        1 => PixelFormat::Bgr,
+        2 => PixelFormat::Bitmask,
+        3 => PixelFormat::BltOnly,
+        _ => PixelFormat::Unknown,
+    }
+}
+
+fn setup_framebuffer(bs: &mut EfiBootServices) -> Option<FramebufferInfo> {
+    let gop = locate_gop(bs)?;
     let mode = unsafe { &*gop.mode };
 
     if mode.info.is_null() {
@@ -285,14 +338,7 @@ fn setup_framebuffer(bs: &mut EfiBootServices) -> Option<FramebufferInfo> {
     }
 
     let mode_info = unsafe { &*mode.info };
-
-    let pixel_format = match mode_info.pixel_format {
-        0 => PixelFormat::Rgb,
-        1 => PixelFormat::Bgr,
-        2 => PixelFormat::Bitmask,
-        3 => PixelFormat::BltOnly,
-        _ => PixelFormat::Unknown,
-    };
+    let pixel_format = gop_pixel_format(mode_info.pixel_format);
 
     if pixel_format == PixelFormat::BltOnly {
         return None;
@@ -309,6 +355,86 @@ fn setup_framebuffer(bs: &mut EfiBootServices) -> Option<FramebufferInfo> {
     })
 }
 
+/// List the GOP modes that have a linear framebuffer
+///
+/// Only the list is kept: the firmware's `SetMode` is gone once boot
+/// services exit, so the kernel switches modes through its own backend.
+fn query_video_modes(bs: &mut EfiBootServices) -> VideoModes {
+    let mut modes = VideoModes::empty();
+    let Some(gop) = locate_gop(bs) else {
+        return modes;
+    };
+
+    let max_mode = unsafe { (*gop.mode).max_mode };
+    for number in 0..max_mode {
+        let mut size = 0usize;
+        let mut info: *mut EfiGraphicsOutputModeInformation = ptr::null_mut();
+        let status = (gop.query_mode)(gop, number, &mut size, &mut info);
+        if status != EFI_SUCCESS || info.is_null() {
+            continue;
+        }
+
+        let mode_info = unsafe { &*info };
+        let pixel_format = gop_pixel_format(mode_info.pixel_format);
+        if pixel_format != PixelFormat::BltOnly {
+            modes.push(VideoMode {
+                width: mode_info.horizontal_resolution,
+                height: mode_info.vertical_resolution,
+                pixel_format,
+            });
+        }
+
+        let mut buf = info as *mut c_void;
+        cleanup_pool(bs, &mut buf);
+    }
+
+    modes
+}
+
+/// Read the boot parameters from the image's load options
+///
+/// The UEFI shell passes the whole command line, starting with the path
+/// of the loader itself, which is dropped. Load options are UCS-2; other
+/// than ASCII characters become `?`.
+fn read_command_line(bs: &EfiBootServices, image: EfiHandle) -> CommandLine {
+    let mut command_line = CommandLine::empty();
+
+    let mut loaded_ptr: *mut c_void = ptr::null_mut();
+    let status = (bs.handle_protocol)(image, &LOADED_IMAGE_GUID, &mut loaded_ptr);
+    if status != EFI_SUCCESS || loaded_ptr.is_null() {
+        return command_line;
+    }
+
+    let loaded = unsafe { &*(loaded_ptr as *const EfiLoadedImageProtocol) };
+    if loaded.load_options.is_null() {
+        return command_line;
+    }
+
+    let units = loaded.load_options_size as usize / 2;
+    let options = unsafe { core::slice::from_raw_parts(loaded.load_options, units) };
+    let mut text = [0u8; COMMAND_LINE_MAX];
+    let mut len = 0;
+    for &unit in options.iter().take_while(|&&unit| unit != 0).take(COMMAND_LINE_MAX) {
+        text[len] = match unit {
+            0x20..=0x7E => unit as u8,
+            0x09 => b' ',
+            _ => b'?',
+        };
+        len += 1;
+    }
+
+    let mut text = &text[..len];
+    let first_len = text.iter().position(|&b| b == b' ').unwrap_or(text.len());
+    if first_len >= 4 && text[first_len - 4..first_len].eq_ignore_ascii_case(b".efi") {
+        text = &text[first_len..];
+    }
+    let text = text.trim_ascii();
+
+    command_line.bytes[..text.len()].copy_from_slice(text);
+    command_line.len = text.len();
+    command_line
+}
+
 fn disable_watchdog(bs: &mut EfiBootServices) {
     let _ = (bs.set_watchdog_timer)(0, 0, 0, ptr::null_mut());
 }
@@ -335,6 +461,8 @@ pub extern "win64" fn efi_main(image: EfiHandle, system_table: *mut c_void) -> E
     disable_watchdog(bs);
 
     let framebuffer_info = setup_framebuffer(bs);
+    let video_modes = query_video_modes(bs);
+    let command_line = read_command_line(bs, image);
 
     let mut mmap_buf: *mut c_void = ptr::null_mut();
     let mut mmap_buf_size: usize = 0;
@@ -418,10 +546,12 @@ pub extern "win64" fn efi_main(image: EfiHandle, system_table: *mut c_void) -> E
             memory_map: MemoryMap::new(mmap_buf as *const u8, actual_size, desc_size2),
             framebuffer: framebuffer_info.unwrap_or_else(FramebufferInfo::empty),
             framebuffer_present: framebuffer_info.is_some(),
-            verbose: false,
+            video_modes,
+            verbose: command_line.has_flag("verbose"),
             boot_method: BootMethod::Uefi,
             cpu: cpu_info(),
             init_payload: ExecutableImage::empty(),
+            command_line,
         });
 
         unsafe {
@@ -6,12 +6,18 @@
 #   .\build.ps1 --run        # Build e executar no QEMU
 #   .\build.ps1 --userspace  # Build apenas drivers userspace
 #   .\build.ps1 --kernel     # Build apenas kernel
+#   .\build.ps1 --test       # Rodar os testes do kernel no QEMU (feature ktest)
+#   .\build.ps1 --itest      # Rodar os testes de integração (test_runner) no QEMU
+#   .\build.ps1 --gdb        # Executar no QEMU esperando o GDB na COM1 (porta 1234)
 
 param(
     [switch]$Run,
     [switch]$Clean,
     [switch]$Userspace,
-    [switch]$Kernel
+    [switch]$Kernel,
+    [switch]$Test,
+    [switch]$ITest,
+    [switch]$Gdb
 )
 
 # -------------------------------------------------------------------------
@@ -28,7 +34,11 @@ $USERSPACE_DRIVERS = @(
     "keyboard",
     "mouse", 
     "display",
-    "ui_shell"
+    "ui_shell",
+    "usb_hid",
+    "audio",
+    "serial",
+    "test_runner"
 )
 
 # -------------------------------------------------------------------------
@@ -101,7 +111,7 @@ if (-not (Test-Path "efi\drivers")) {
 # BUILD USERSPACE DRIVERS (Library only - drivers are embedded in kernel)
 # =========================================================================
 
-if (-not $Kernel) {
+if (-not $Kernel -and -not $Test) {
     Write-Host ""
     Write-Host "========== USERSPACE LIBRARIES ==========" -ForegroundColor Magenta
     Write-Host ""
@@ -216,7 +226,12 @@ Write-Success "Arquivos assembly montados"
 
 Write-Step "Compilando kernel Rust..."
 
-cargo build -p atom-kernel --release 2>&1
+$KERNEL_FEATURES = @()
+if ($Test) {
+    $KERNEL_FEATURES = @("--features", "ktest")
+}
+
+cargo build -p atom-kernel --release @KERNEL_FEATURES 2>&1
 if ($LASTEXITCODE -ne 0) {
     Write-ErrorMsg "Falha ao compilar o kernel"
     exit 1
@@ -292,7 +307,7 @@ if (Test-Path "efi\drivers") {
 # Executar QEMU (opcional)
 # -------------------------------------------------------------------------
 
-if ($Run) {
+if ($Run -or $Test -or $ITest -or $Gdb) {
     Write-Host "========== QEMU ==========" -ForegroundColor Magenta
     Write-Host ""
     
@@ -310,6 +325,102 @@ if ($Run) {
         exit 1
     }
 
+    # Testes: sem janela, resultado pelo isa-debug-exit (33 = passou, 35 = falhou)
+    if ($Test) {
+        Write-Step "Rodando testes do kernel..."
+
+        qemu-system-x86_64 `
+            -machine q35 `
+            -cpu qemu64 `
+            -m 512M `
+            -bios "$OVMF_PATH" `
+            -drive format=raw,file=fat:rw:"$REPO_PATH\efi" `
+            -display none `
+            -serial stdio `
+            -debugcon file:build\ktest.log `
+            -global isa-debugcon.iobase=0xE9 `
+            -device isa-debug-exit,iobase=0xf4,iosize=0x04
+        $status = $LASTEXITCODE
+
+        Write-Host ""
+        Select-String -Path build\ktest.log -Pattern "^(test |    |ktest:)" |
+            ForEach-Object { Write-Host $_.Line }
+        switch ($status) {
+            33 { Write-Success "Todos os testes do kernel passaram"; exit 0 }
+            35 { Write-ErrorMsg "Testes do kernel falharam (veja build\ktest.log)"; exit 1 }
+            default {
+                Write-ErrorMsg "QEMU terminou sem resultado dos testes (status $status)"
+                exit 1
+            }
+        }
+    }
+
+    # Testes de integração: o kernel recebe `test_runner` pelo shell UEFI
+    # (startup.nsh), por isso o loader fica fora de EFI\BOOT; o kernel sai
+    # do QEMU quando o test_runner termina (33 = passou, 35 = falhou)
+    if ($ITest) {
+        Write-Step "Rodando testes de integração..."
+
+        $ITEST_EFI = "$REPO_PATH\build\itest-efi"
+        if (Test-Path $ITEST_EFI) { Remove-Item -Recurse -Force $ITEST_EFI }
+        New-Item -ItemType Directory -Path "$ITEST_EFI\EFI\ATOM" | Out-Null
+        Copy-Item build\Atom.efi "$ITEST_EFI\EFI\ATOM\ATOM.EFI" -Force
+        Copy-Item efi\drivers "$ITEST_EFI\drivers" -Recurse -Force
+        Set-Content -Path "$ITEST_EFI\startup.nsh" -Value "fs0:\EFI\ATOM\ATOM.EFI test_runner"
+
+        qemu-system-x86_64 `
+            -machine q35 `
+            -cpu qemu64 `
+            -m 512M `
+            -bios "$OVMF_PATH" `
+            -drive format=raw,file=fat:rw:"$ITEST_EFI" `
+            -device VGA `
+            -display none `
+            -serial file:build\itest.log `
+            -device isa-debug-exit,iobase=0xf4,iosize=0x04
+        $status = $LASTEXITCODE
+
+        Write-Host ""
+        Select-String -Path build\itest.log -Pattern "testrun:" |
+            ForEach-Object { Write-Host $_.Line }
+        switch ($status) {
+            33 { Write-Success "Todos os testes de integração passaram"; exit 0 }
+            35 { Write-ErrorMsg "Testes de integração falharam (veja build\itest.log)"; exit 1 }
+            default {
+                Write-ErrorMsg "QEMU terminou sem resultado dos testes (status $status)"
+                exit 1
+            }
+        }
+    }
+
+    # GDB: o kernel recebe `gdb` pelo shell UEFI, como nos testes de
+    # integração, e a COM1 vira o stub do GDB num socket TCP
+    if ($Gdb) {
+        $GDB_EFI = "$REPO_PATH\build\gdb-efi"
+        if (Test-Path $GDB_EFI) { Remove-Item -Recurse -Force $GDB_EFI }
+        New-Item -ItemType Directory -Path "$GDB_EFI\EFI\ATOM" | Out-Null
+        Copy-Item build\Atom.efi "$GDB_EFI\EFI\ATOM\ATOM.EFI" -Force
+        Copy-Item efi\drivers "$GDB_EFI\drivers" -Recurse -Force
+        Set-Content -Path "$GDB_EFI\startup.nsh" -Value "fs0:\EFI\ATOM\ATOM.EFI gdb"
+
+        Write-Step "Iniciando QEMU, esperando o GDB em localhost:1234..."
+        Write-Host "No GDB: target remote localhost:1234" -ForegroundColor Yellow
+
+        qemu-system-x86_64 `
+            -machine q35 `
+            -cpu qemu64 `
+            -m 512M `
+            -bios "$OVMF_PATH" `
+            -drive format=raw,file=fat:rw:"$GDB_EFI" `
+            -device VGA `
+            -usb `
+            -device usb-mouse `
+            -serial tcp::1234,server=on,wait=on `
+            -debugcon file:serial_log.txt `
+            -global isa-debugcon.iobase=0xE9
+        exit $LASTEXITCODE
+    }
+
     Write-Step "Iniciando QEMU..."
     Write-Host "Pressione Ctrl+C para encerrar" -ForegroundColor Yellow
     Write-Host ""
@@ -8,6 +8,9 @@
 #   ./build.sh --userspace  # Build apenas drivers userspace
 #   ./build.sh --kernel     # Build apenas kernel
 #   ./build.sh --rust-only  # Apenas validar código Rust
+#   ./build.sh --test       # Rodar os testes do kernel no QEMU (feature ktest)
+#   ./build.sh --itest      # Rodar os testes de integração (test_runner) no QEMU
+#   ./build.sh --gdb        # Executar no QEMU esperando o GDB na COM1 (porta 1234)
 #   ./build.sh --setup      # Configurar dependências
 
 set -e
@@ -64,6 +67,9 @@ RUST_ONLY=false
 SETUP=false
 USERSPACE_ONLY=false
 KERNEL_ONLY=false
+TEST=false
+ITEST=false
+GDB=false
 
 for arg in "$@"; do
     case $arg in
@@ -73,6 +79,9 @@ for arg in "$@"; do
         --setup)    SETUP=true ;;
         --userspace) USERSPACE_ONLY=true ;;
         --kernel)   KERNEL_ONLY=true ;;
+        --test)     TEST=true; KERNEL_ONLY=true ;;
+        --itest)    ITEST=true ;;
+        --gdb)      GDB=true ;;
         --help|-h)
             echo "Uso: ./build.sh [opções]"
             echo ""
@@ -82,6 +91,9 @@ for arg in "$@"; do
             echo "  --userspace   Build apenas drivers userspace"
             echo "  --kernel      Build apenas kernel"
             echo "  --rust-only   Apenas validar código Rust (sem NASM/linker)"
+            echo "  --test        Rodar os testes do kernel no QEMU e sair com o resultado"
+            echo "  --itest       Rodar os testes de integração no QEMU e sair com o resultado"
+            echo "  --gdb         Executar no QEMU parado, esperando o GDB em localhost:1234"
             echo "  --setup       Configurar dependências do Rust"
             echo "  --help, -h    Mostrar esta ajuda"
             exit 0
@@ -98,6 +110,10 @@ USERSPACE_DRIVERS=(
     "mouse"
     "display"
     "ui_shell"
+    "usb_hid"
+    "audio"
+    "serial"
+    "test_runner"
 )
 
 # =========================================================================
@@ -233,8 +249,13 @@ fi
 
 header "KERNEL BUILD"
 
+KERNEL_FEATURES=()
+if [ "$TEST" = true ]; then
+    KERNEL_FEATURES=(--features ktest)
+fi
+
 step "Compilando kernel Rust..."
-if cargo build -p atom-kernel --release 2>&1 | tee build/cargo.log; then
+if cargo build -p atom-kernel --release "${KERNEL_FEATURES[@]}" 2>&1 | tee build/cargo.log; then
     success "Kernel Rust compilado"
 
     if grep -q "warning:" build/cargo.log; then
@@ -369,7 +390,7 @@ fi
 # EXECUTAR QEMU (OPCIONAL)
 # =========================================================================
 
-if [ "$RUN" = true ]; then
+if [ "$RUN" = true ] || [ "$TEST" = true ] || [ "$ITEST" = true ] || [ "$GDB" = true ]; then
     header "QEMU"
 
     # Encontrar OVMF
@@ -393,6 +414,108 @@ if [ "$RUN" = true ]; then
         exit 1
     fi
 
+    # Testes: sem janela, resultado pelo isa-debug-exit (33 = passou, 35 = falhou)
+    if [ "$TEST" = true ]; then
+        step "Rodando testes do kernel..."
+        TIMEOUT=()
+        if command -v timeout &> /dev/null; then
+            TIMEOUT=(timeout 300)
+        fi
+
+        set +e
+        "${TIMEOUT[@]}" qemu-system-x86_64 \
+            -machine q35 \
+            -cpu qemu64 \
+            -m 512M \
+            -bios "$OVMF_PATH" \
+            -drive format=raw,file=fat:rw:efi \
+            -display none \
+            -serial stdio \
+            -debugcon file:build/ktest.log \
+            -global isa-debugcon.iobase=0xE9 \
+            -device isa-debug-exit,iobase=0xf4,iosize=0x04
+        status=$?
+        set -e
+
+        echo ""
+        grep -E "^(test |    |ktest:)" build/ktest.log || true
+        case $status in
+            33) success "Todos os testes do kernel passaram"; exit 0 ;;
+            35) error "Testes do kernel falharam (veja build/ktest.log)"; exit 1 ;;
+            124) error "Testes do kernel excederam o tempo limite"; exit 1 ;;
+            *) error "QEMU terminou sem resultado dos testes (status $status)"; exit 1 ;;
+        esac
+    fi
+
+    # Testes de integração: o kernel recebe `test_runner` pelo shell UEFI
+    # (startup.nsh), por isso o loader fica fora de EFI/BOOT; o kernel sai
+    # do QEMU quando o test_runner termina (33 = passou, 35 = falhou)
+    if [ "$ITEST" = true ]; then
+        step "Rodando testes de integração..."
+        ITEST_EFI=build/itest-efi
+        rm -rf "$ITEST_EFI"
+        mkdir -p "$ITEST_EFI/EFI/ATOM"
+        cp build/Atom.efi "$ITEST_EFI/EFI/ATOM/ATOM.EFI"
+        cp -r efi/drivers "$ITEST_EFI/"
+        echo 'fs0:\EFI\ATOM\ATOM.EFI test_runner' > "$ITEST_EFI/startup.nsh"
+
+        TIMEOUT=()
+        if command -v timeout &> /dev/null; then
+            TIMEOUT=(timeout 300)
+        fi
+
+        set +e
+        "${TIMEOUT[@]}" qemu-system-x86_64 \
+            -machine q35 \
+            -cpu qemu64 \
+            -m 512M \
+            -bios "$OVMF_PATH" \
+            -drive format=raw,file=fat:rw:"$ITEST_EFI" \
+            -device VGA \
+            -display none \
+            -serial file:build/itest.log \
+            -device isa-debug-exit,iobase=0xf4,iosize=0x04
+        status=$?
+        set -e
+
+        echo ""
+        grep "testrun:" build/itest.log || true
+        case $status in
+            33) success "Todos os testes de integração passaram"; exit 0 ;;
+            35) error "Testes de integração falharam (veja build/itest.log)"; exit 1 ;;
+            124) error "Testes de integração excederam o tempo limite"; exit 1 ;;
+            *) error "QEMU terminou sem resultado dos testes (status $status)"; exit 1 ;;
+        esac
+    fi
+
+    # GDB: o kernel recebe `gdb` pelo shell UEFI, como nos testes de
+    # integração, e a COM1 vira o stub do GDB num socket TCP
+    if [ "$GDB" = true ]; then
+        GDB_EFI=build/gdb-efi
+        rm -rf "$GDB_EFI"
+        mkdir -p "$GDB_EFI/EFI/ATOM"
+        cp build/Atom.efi "$GDB_EFI/EFI/ATOM/ATOM.EFI"
+        cp -r efi/drivers "$GDB_EFI/"
+        echo 'fs0:\EFI\ATOM\ATOM.EFI gdb' > "$GDB_EFI/startup.nsh"
+
+        step "Iniciando QEMU, esperando o GDB em localhost:1234..."
+        echo -e "${YELLOW}No GDB: target remote localhost:1234${NC}"
+
+        qemu-system-x86_64 \
+            -machine q35 \
+            -cpu qemu64 \
+            -m 512M \
+            -bios "$OVMF_PATH" \
+            -drive format=raw,file=fat:rw:"$GDB_EFI" \
+            -device VGA \
+            -usb \
+            -device usb-mouse \
+            -serial tcp::1234,server=on,wait=on \
+            -debugcon file:serial_log.txt \
+            -global isa-debugcon.iobase=0xE9
+        exit $?
+    fi
+
     step "Iniciando QEMU..."
     echo -e "${YELLOW}Pressione Ctrl+A X para sair do QEMU${NC}"
     echo ""
@@ -0,0 +1,84 @@
+//! LZ4 Block Decompression
+//!
+//! Decodes the LZ4 block format (no frame), which is how elf2atxf stores
+//! compressed executable segments and tools/embed stores compressed
+//! files. The caller knows the decompressed size from the segment table
+//! or the embedded file and provides a buffer of exactly that size. No
+//! allocation, so the kernel links it as is.
+//!
+//! Format:
+//! - A sequence of (token, literals, match) records; the token's high
+//!   nibble is the literal count, its low nibble the match length minus 4
+//! - A nibble of 15 means more length follows in bytes, 255 meaning more
+//! - Matches copy from up to 64 KiB back in the output, and may overlap
+//!   the bytes they produce (repeating a short pattern)
+//! - The last record has literals only
+//!
+//! Safety and correctness notes:
+//! - Every read and write is bounds-checked; malformed input returns an
+//!   error rather than touching memory outside the buffers
+//! - The output must be filled exactly, so truncated input is caught too
+
+#![no_std]
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lz4Error;
+
+const MIN_MATCH: usize = 4;
+
+/// Decompress the block `input` into `output`, which it must fill exactly
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Result<(), Lz4Error> {
+    let mut i = 0;
+    let mut o = 0;
+
+    loop {
+        let token = *input.get(i).ok_or(Lz4Error)?;
+        i += 1;
+
+        let mut literals = (token >> 4) as usize;
+        if literals == 15 {
+            literals += read_length(input, &mut i)?;
+        }
+        let source = input.get(i..i + literals).ok_or(Lz4Error)?;
+        output
+            .get_mut(o..o + literals)
+            .ok_or(Lz4Error)?
+            .copy_from_slice(source);
+        i += literals;
+        o += literals;
+
+        if i == input.len() {
+            return if o == output.len() { Ok(()) } else { Err(Lz4Error) };
+        }
+
+        let offset_bytes = input.get(i..i + 2).ok_or(Lz4Error)?;
+        let offset = u16::from_le_bytes([offset_bytes[0], offset_bytes[1]]) as usize;
+        i += 2;
+
+        let mut len = (token & 15) as usize + MIN_MATCH;
+        if len == 15 + MIN_MATCH {
+            len += read_length(input, &mut i)?;
+        }
+        if offset == 0 || offset > o || o + len > output.len() {
+            return Err(Lz4Error);
+        }
+
+        // Byte by byte, since the source may overlap what is being written
+        for k in o..o + len {
+            output[k] = output[k - offset];
+        }
+        o += len;
+    }
+}
+
+fn read_length(input: &[u8], i: &mut usize) -> Result<usize, Lz4Error> {
+    let mut len = 0usize;
+    loop {
+        let byte = *input.get(*i).ok_or(Lz4Error)?;
+        *i += 1;
+        len = len.checked_add(byte as usize).ok_or(Lz4Error)?;
+        if byte != 255 {
+            return Ok(len);
+        }
+    }
+}
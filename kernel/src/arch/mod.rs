@@ -9,6 +9,9 @@
 // - Read critical processor registers for debugging and kernel logic
 // - Abstract stack pointer access (RSP/SP) per architecture
 // - Expose descriptor table state (GDT/IDT/TR) for introspection
+// - Leave QEMU with a chosen exit status, for test runs
+// - Turn on SSE and save/restore its registers for threads (FXSAVE)
+// - Turn on SMEP/SMAP and open/close supervisor access to user pages
 //
 // Design principles:
 // - Architecture-specific code is isolated behind `cfg(target_arch)` gates
@@ -19,7 +22,10 @@
 // Implementation details:
 // - Uses `hlt` (x86_64) and `wfi` (aarch64) for low-power CPU halt
 // - Reads registers like RSP, RFLAGS, CR3, and TR directly via assembly
+// - Reads the time stamp counter, the only source of variation at boot
 // - Retrieves GDT and IDT descriptors using `sgdt` and `sidt`
+// - The kernel is built without SSE; only user code uses the registers,
+//   so switching them is the whole of the kernel's involvement
 // - Packed descriptor structs match the CPU-defined memory layout
 //
 // Correctness and safety notes:
@@ -27,6 +33,10 @@
 // - Functions returning zero on unsupported architectures are marked
 //   with `#[allow(unreachable_code)]` to satisfy the compiler
 // - Intended primarily for kernel initialization, diagnostics, and debugging
+// - `stac`/`clac` change RFLAGS.AC, which is their point, and are no-ops
+//   unless SMAP was turned on: the instructions fault on CPUs without it
+
+use core::sync::atomic::{AtomicBool, Ordering};
 
 #[inline(always)]
 pub fn halt() {
@@ -83,6 +93,25 @@ pub fn read_cr3() -> u64 {
     0
 }
 
+/// Cycles since reset; not synchronized between CPUs
+#[inline(always)]
+pub fn read_tsc() -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        let (low, high): (u32, u32);
+        core::arch::asm!(
+            "rdtsc",
+            out("eax") low,
+            out("edx") high,
+            options(nomem, nostack, preserves_flags)
+        );
+        return (high as u64) << 32 | low as u64;
+    }
+
+    #[allow(unreachable_code)]
+    0
+}
+
 #[inline(always)]
 #[allow(dead_code)]
 pub fn read_tr() -> u16 {
@@ -139,4 +168,166 @@ pub fn current_privilege_level() -> u8 {
     }
 }
 
+/// Let code use SSE: FXSAVE/FXRSTOR on (CR4.OSFXSR), SIMD floating-point
+/// errors reported as #XM (CR4.OSXMMEXCPT), and no x87 emulation or
+/// lazy-switch traps (CR0.EM and CR0.TS clear, CR0.MP set)
+pub fn enable_sse() {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        core::arch::asm!(
+            "mov {tmp}, cr0",
+            "and {tmp}, ~((1 << 2) | (1 << 3))",
+            "or {tmp}, 1 << 1",
+            "mov cr0, {tmp}",
+            "mov {tmp}, cr4",
+            "or {tmp}, (1 << 9) | (1 << 10)",
+            "mov cr4, {tmp}",
+            tmp = out(reg) _,
+            options(nostack)
+        );
+    }
+}
+
+/// Save the x87/SSE registers into `area`
+///
+/// # Safety
+///
+/// `area` must be 512 writable bytes aligned to 16, and SSE enabled.
+#[inline(always)]
+pub unsafe fn fxsave(area: *mut u8) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        core::arch::asm!("fxsave64 [{}]", in(reg) area, options(nostack, preserves_flags));
+    }
+}
+
+/// Load the x87/SSE registers from `area`, as written by `fxsave`
+///
+/// # Safety
+///
+/// `area` must be 512 readable bytes aligned to 16 holding a valid save
+/// image (reserved MXCSR bits clear), and SSE enabled.
+#[inline(always)]
+pub unsafe fn fxrstor(area: *const u8) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        core::arch::asm!(
+            "fxrstor64 [{}]",
+            in(reg) area,
+            options(nostack, preserves_flags, readonly)
+        );
+    }
+}
+
+/// CR4.SMEP: the kernel faults on executing a user page
+const CR4_SMEP: u64 = 1 << 20;
+/// CR4.SMAP: the kernel faults on touching a user page unless RFLAGS.AC is
+/// set
+const CR4_SMAP: u64 = 1 << 21;
+/// RFLAGS.AC, which `stac` sets and `clac` clears
+pub const RFLAGS_AC: u64 = 1 << 18;
+
+/// Whether `enable_smep_smap` turned SMAP on, so `stac`/`clac` are usable
+static SMAP_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// `cpuid` leaf `leaf`, subleaf `subleaf`, as (eax, ebx, ecx, edx)
+#[inline(always)]
+pub fn cpuid(leaf: u32, subleaf: u32) -> (u32, u32, u32, u32) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        let (eax, ebx, ecx, edx): (u32, u32, u32, u32);
+        // RBX is reserved by LLVM, so it goes through another register
+        core::arch::asm!(
+            "mov {ebx:r}, rbx",
+            "cpuid",
+            "xchg {ebx:r}, rbx",
+            ebx = out(reg) ebx,
+            inout("eax") leaf => eax,
+            inout("ecx") subleaf => ecx,
+            out("edx") edx,
+            options(nomem, nostack, preserves_flags)
+        );
+        return (eax, ebx, ecx, edx);
+    }
+
+    #[allow(unreachable_code)]
+    (0, 0, 0, 0)
+}
+
+/// Turn on SMEP and SMAP where the CPU has them; returns which were
+///
+/// From then on the kernel faults on running code from a user page, and
+/// on touching one outside a `stac`/`clac` pair.
+pub fn enable_smep_smap() -> (bool, bool) {
+    let (max_leaf, ..) = cpuid(0, 0);
+    if max_leaf < 7 {
+        return (false, false);
+    }
+    let (_, features, ..) = cpuid(7, 0);
+    let smep = features & (1 << 7) != 0;
+    let smap = features & (1 << 20) != 0;
+
+    let mut bits = 0;
+    if smep {
+        bits |= CR4_SMEP;
+    }
+    if smap {
+        bits |= CR4_SMAP;
+    }
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        core::arch::asm!(
+            "mov {tmp}, cr4",
+            "or {tmp}, {bits}",
+            "mov cr4, {tmp}",
+            tmp = out(reg) _,
+            bits = in(reg) bits,
+            options(nostack, preserves_flags)
+        );
+    }
+    SMAP_ENABLED.store(smap, Ordering::Relaxed);
+    (smep, smap)
+}
+
+/// Let the kernel touch user pages until `clac`
+///
+/// Not `nomem`: memory accesses must not move out of the window.
+#[inline(always)]
+pub fn stac() {
+    #[cfg(target_arch = "x86_64")]
+    if SMAP_ENABLED.load(Ordering::Relaxed) {
+        unsafe { core::arch::asm!("stac", options(nostack)) };
+    }
+}
+
+/// Close the window `stac` opened
+#[inline(always)]
+pub fn clac() {
+    #[cfg(target_arch = "x86_64")]
+    if SMAP_ENABLED.load(Ordering::Relaxed) {
+        unsafe { core::arch::asm!("clac", options(nostack)) };
+    }
+}
+
+/// Port of QEMU's `isa-debug-exit` device (`-device isa-debug-exit,iobase=0xf4`)
+pub const QEMU_EXIT_PORT: u16 = 0xF4;
+/// `qemu_exit` codes for a passing and a failing run: statuses 33 and 35
+pub const QEMU_EXIT_SUCCESS: u32 = 0x10;
+pub const QEMU_EXIT_FAILURE: u32 = 0x11;
+
+/// Make QEMU exit with status `(code << 1) | 1`, for automated test runs
+///
+/// Without the device the write is ignored and this returns.
+pub fn qemu_exit(code: u32) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        core::arch::asm!(
+            "out dx, eax",
+            in("dx") QEMU_EXIT_PORT,
+            in("eax") code,
+            options(nomem, nostack, preserves_flags)
+        );
+    }
+}
+
 pub mod gdt;
@@ -138,9 +138,100 @@ impl ExecutableImage {
     }
 }
 
+/// Most video modes kept from the firmware's list
+pub const MAX_VIDEO_MODES: usize = 32;
+
+/// A video mode the firmware offered at boot
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct VideoMode {
+    pub width: u32,
+    pub height: u32,
+    pub pixel_format: PixelFormat,
+}
+
+impl VideoMode {
+    pub const fn empty() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            pixel_format: PixelFormat::Unknown,
+        }
+    }
+}
+
+/// The firmware's video modes with a linear framebuffer, in the order it
+/// listed them; duplicates and modes past `MAX_VIDEO_MODES` are dropped
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct VideoModes {
+    pub modes: [VideoMode; MAX_VIDEO_MODES],
+    pub count: usize,
+}
+
+impl VideoModes {
+    pub const fn empty() -> Self {
+        Self {
+            modes: [VideoMode::empty(); MAX_VIDEO_MODES],
+            count: 0,
+        }
+    }
+
+    /// Add `mode` unless it is already listed or the list is full
+    pub fn push(&mut self, mode: VideoMode) {
+        let known = self
+            .as_slice()
+            .iter()
+            .any(|m| m.width == mode.width && m.height == mode.height);
+        if !known && self.count < MAX_VIDEO_MODES {
+            self.modes[self.count] = mode;
+            self.count += 1;
+        }
+    }
+
+    pub fn as_slice(&self) -> &[VideoMode] {
+        &self.modes[..self.count.min(MAX_VIDEO_MODES)]
+    }
+}
+
 unsafe impl Send for ExecutableImage {}
 unsafe impl Sync for ExecutableImage {}
 
+/// Longest boot command line kept; the rest is cut off
+pub const COMMAND_LINE_MAX: usize = 256;
+
+/// Boot parameters given to the loader, e.g. `verbose loglevel=debug`,
+/// as ASCII words separated by spaces
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct CommandLine {
+    pub bytes: [u8; COMMAND_LINE_MAX],
+    pub len: usize,
+}
+
+impl CommandLine {
+    pub const fn empty() -> Self {
+        Self {
+            bytes: [0; COMMAND_LINE_MAX],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len.min(COMMAND_LINE_MAX)]).unwrap_or("")
+    }
+
+    /// The parameters one by one
+    pub fn words(&self) -> impl Iterator<Item = &str> {
+        self.as_str().split_ascii_whitespace()
+    }
+
+    /// Whether the bare word `name` was given
+    pub fn has_flag(&self, name: &str) -> bool {
+        self.words().any(|word| word == name)
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub enum BootMethod {
@@ -169,10 +260,12 @@ pub struct BootInfo {
     pub memory_map: MemoryMap,
     pub framebuffer: FramebufferInfo,
     pub framebuffer_present: bool,
+    pub video_modes: VideoModes,
     pub verbose: bool,
     pub boot_method: BootMethod,
     pub cpu: CpuInfo,
     pub init_payload: ExecutableImage,
+    pub command_line: CommandLine,
 }
 
 unsafe impl Send for BootInfo {}
@@ -188,6 +281,7 @@ impl BootInfo {
             },
             framebuffer: FramebufferInfo::empty(),
             framebuffer_present: false,
+            video_modes: VideoModes::empty(),
             verbose: false,
             boot_method: BootMethod::Uefi,
             cpu: CpuInfo {
@@ -196,6 +290,7 @@ impl BootInfo {
                 architecture: CpuArchitecture::Unknown,
             },
             init_payload: ExecutableImage::empty(),
+            command_line: CommandLine::empty(),
         }
     }
 }
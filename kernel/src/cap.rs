@@ -201,6 +201,34 @@ pub enum ResourceType {
     SharedMemoryRegion {
         region_id: u64,
     },
+    /// Reading what other programs' windows show; checked by the
+    /// compositor, not the kernel
+    Screenshot,
+    /// Driving the boot framebuffer's adapter, such as switching its mode
+    Framebuffer,
+    /// Changing how the kernel logs, traces and profiles itself
+    Debug,
+}
+
+impl ResourceType {
+    /// Number of resource types, and one past the largest `code`
+    pub const COUNT: usize = 10;
+
+    /// The number userspace names this type of resource by
+    pub const fn code(&self) -> u64 {
+        match self {
+            ResourceType::Thread(_) => 0,
+            ResourceType::MemoryRegion { .. } => 1,
+            ResourceType::IpcPort { .. } => 2,
+            ResourceType::Irq { .. } => 3,
+            ResourceType::Device { .. } => 4,
+            ResourceType::DmaBuffer { .. } => 5,
+            ResourceType::SharedMemoryRegion { .. } => 6,
+            ResourceType::Screenshot => 7,
+            ResourceType::Framebuffer => 8,
+            ResourceType::Debug => 9,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -436,19 +464,10 @@ impl CapabilityManager {
         let caps = self.global_caps.lock();
         let total = caps.len();
 
-        let mut by_type = [0usize; 7];
+        let mut by_type = [0usize; ResourceType::COUNT];
 
         for cap in caps.values() {
-            let idx = match cap.resource {
-                ResourceType::Thread(_) => 0,
-                ResourceType::MemoryRegion { .. } => 1,
-                ResourceType::IpcPort { .. } => 2,
-                ResourceType::Irq { .. } => 3,
-                ResourceType::Device { .. } => 4,
-                ResourceType::DmaBuffer { .. } => 5,
-                ResourceType::SharedMemoryRegion { .. } => 6,
-            };
-            by_type[idx] += 1;
+            by_type[cap.resource.code() as usize] += 1;
         }
 
         CapabilityStats {
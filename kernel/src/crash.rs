@@ -0,0 +1,655 @@
+// Kernel Crash Reporting
+//
+// The last thing the kernel does when it cannot go on: a panic or a fatal
+// CPU exception ends here, after it has been logged, instead of in a bare
+// halt loop.
+//
+// Key responsibilities:
+// - Capture the registers: the full set saved by the exception stub, or
+//   the panic site's instruction, stack and frame pointers for a panic
+// - Walk the frame-pointer chain for a stack trace (the kernel is built
+//   with `-C force-frame-pointers=yes`, see `.cargo/config.toml`)
+// - Draw a crash screen on the framebuffer: the cause, the thread that was
+//   running, the registers, the stack trace and the end of the kernel log
+// - With the `crash_dump` boot parameter, save all of that in a region of
+//   RAM that a warm reboot leaves alone, and log it on the next boot
+//
+// Design principles:
+// - Nothing is allocated, and locks the crashed code may hold (log ring,
+//   framebuffer, thread list, scheduler) are only ever tried, so a crash
+//   in any of those subsystems still reaches the screen
+// - A crash while reporting a crash halts at once
+// - The dump is written before the screen is drawn, so it survives a
+//   crash screen that itself faults
+//
+// Crash dump:
+// - `DUMP_PHYS` is a fixed physical range, reserved from the PMM at boot
+//   when the parameter is given; it is identity mapped like all RAM
+// - The dump carries a magic number and a checksum, as firmware or the
+//   loader may have reused the range; anything that fails them is ignored
+// - It is logged and cleared by `init`, which puts it in the kernel log
+//   ring that `SYS_KLOG_READ` reads
+//
+// Limitations:
+// - Frame addresses are not symbolized; look them up in the kernel image
+// - A cold boot, and some firmware on a warm one, clear the dump
+// - Only the CPU that crashed stops; the kernel is single-core for now
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::arch::{halt, read_cr3};
+use crate::graphics::{self, Color, Framebuffer, FONT_HEIGHT, FONT_WIDTH};
+use crate::interrupts::handlers::InterruptFrame;
+use crate::mm::usercopy::UserAccess;
+use crate::mm::{pmm, vm};
+use crate::{log, log_info, log_panic, log_warn, sched, thread};
+
+const LOG_ORIGIN: &str = "crash";
+
+/// Boot parameter that keeps a crash dump across a warm reboot
+const CRASH_DUMP_FLAG: &str = "crash_dump";
+
+/// Where the dump is kept: 16 MiB, clear of the legacy low memory the
+/// firmware works in, and below where OVMF loads the kernel
+const DUMP_PHYS: usize = 0x0100_0000;
+const DUMP_PAGES: usize = 4;
+
+/// First bytes of a saved dump ("ATOMCRSH")
+const DUMP_MAGIC: u64 = 0x4853_5243_4D4F_5441;
+
+const MAX_FRAMES: usize = 24;
+const MESSAGE_SIZE: usize = 512;
+const LOG_TAIL_SIZE: usize = 12 * 1024;
+
+/// Largest gap between stack frames still taken for a real frame
+const MAX_FRAME_SIZE: u64 = 64 * 1024;
+
+const BACKGROUND: Color = Color::new(0x10, 0x18, 0x48);
+const TITLE: Color = Color::new(0xFF, 0x60, 0x60);
+const TEXT: Color = Color::WHITE;
+const DIM: Color = Color::new(0xA0, 0xA8, 0xC8);
+const LINE_HEIGHT: u32 = FONT_HEIGHT + 2;
+const MARGIN: u32 = 16;
+
+static CRASHING: AtomicBool = AtomicBool::new(false);
+static DUMP_RESERVED: AtomicBool = AtomicBool::new(false);
+
+/// Register state at the crash
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Registers {
+    /// Whether the general-purpose registers were saved; only exceptions
+    /// have them, a panic only knows where it was called
+    has_gprs: u64,
+    rax: u64,
+    rbx: u64,
+    rcx: u64,
+    rdx: u64,
+    rsi: u64,
+    rdi: u64,
+    rbp: u64,
+    rsp: u64,
+    r8: u64,
+    r9: u64,
+    r10: u64,
+    r11: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+    rip: u64,
+    rflags: u64,
+    cs: u64,
+    ss: u64,
+    cr0: u64,
+    cr2: u64,
+    cr3: u64,
+    cr4: u64,
+}
+
+impl Registers {
+    fn from_frame(frame: &InterruptFrame) -> Self {
+        let (cr0, cr2, cr4) = control_registers();
+        Self {
+            has_gprs: 1,
+            rax: frame.rax,
+            rbx: frame.rbx,
+            rcx: frame.rcx,
+            rdx: frame.rdx,
+            rsi: frame.rsi,
+            rdi: frame.rdi,
+            rbp: frame.rbp,
+            rsp: frame.rsp,
+            r8: frame.r8,
+            r9: frame.r9,
+            r10: frame.r10,
+            r11: frame.r11,
+            r12: frame.r12,
+            r13: frame.r13,
+            r14: frame.r14,
+            r15: frame.r15,
+            rip: frame.rip,
+            rflags: frame.rflags,
+            cs: frame.cs,
+            ss: frame.ss,
+            cr0,
+            cr2,
+            cr3: read_cr3(),
+            cr4,
+        }
+    }
+
+    /// Where the caller is now: its instruction, stack and frame pointers
+    #[inline(always)]
+    fn here() -> Self {
+        let (rip, rsp, rbp, rflags): (u64, u64, u64, u64);
+        unsafe {
+            core::arch::asm!(
+                "lea {rip}, [rip]",
+                "mov {rsp}, rsp",
+                "mov {rbp}, rbp",
+                "pushfq",
+                "pop {rflags}",
+                rip = out(reg) rip,
+                rsp = out(reg) rsp,
+                rbp = out(reg) rbp,
+                rflags = out(reg) rflags,
+            );
+        }
+        let (cs, ss): (u16, u16);
+        unsafe {
+            core::arch::asm!("mov {:x}, cs", out(reg) cs, options(nomem, nostack, preserves_flags));
+            core::arch::asm!("mov {:x}, ss", out(reg) ss, options(nomem, nostack, preserves_flags));
+        }
+        let (cr0, cr2, cr4) = control_registers();
+        Self {
+            has_gprs: 0,
+            rax: 0,
+            rbx: 0,
+            rcx: 0,
+            rdx: 0,
+            rsi: 0,
+            rdi: 0,
+            rbp,
+            rsp,
+            r8: 0,
+            r9: 0,
+            r10: 0,
+            r11: 0,
+            r12: 0,
+            r13: 0,
+            r14: 0,
+            r15: 0,
+            rip,
+            rflags,
+            cs: cs as u64,
+            ss: ss as u64,
+            cr0,
+            cr2,
+            cr3: read_cr3(),
+            cr4,
+        }
+    }
+
+    /// Name and value pairs, in display order
+    fn named(&self) -> [(&'static str, u64); 24] {
+        [
+            ("RIP", self.rip),
+            ("RSP", self.rsp),
+            ("RBP", self.rbp),
+            ("RFLAGS", self.rflags),
+            ("RAX", self.rax),
+            ("RBX", self.rbx),
+            ("RCX", self.rcx),
+            ("RDX", self.rdx),
+            ("RSI", self.rsi),
+            ("RDI", self.rdi),
+            ("R8", self.r8),
+            ("R9", self.r9),
+            ("R10", self.r10),
+            ("R11", self.r11),
+            ("R12", self.r12),
+            ("R13", self.r13),
+            ("R14", self.r14),
+            ("R15", self.r15),
+            ("CS", self.cs),
+            ("SS", self.ss),
+            ("CR0", self.cr0),
+            ("CR2", self.cr2),
+            ("CR3", self.cr3),
+            ("CR4", self.cr4),
+        ]
+    }
+
+    /// Registers worth showing: the general-purpose ones only when saved
+    fn shown(&self) -> impl Iterator<Item = (&'static str, u64)> {
+        let has_gprs = self.has_gprs != 0;
+        self.named()
+            .into_iter()
+            .enumerate()
+            .filter(move |(i, _)| has_gprs || !(4..18).contains(i))
+            .map(|(_, register)| register)
+    }
+}
+
+fn control_registers() -> (u64, u64, u64) {
+    let (cr0, cr2, cr4): (u64, u64, u64);
+    unsafe {
+        core::arch::asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+    }
+    (cr0, cr2, cr4)
+}
+
+/// Everything known about a crash, as saved in the dump region
+#[repr(C)]
+struct CrashReport {
+    magic: u64,
+    /// FNV-1a over everything after this field
+    checksum: u64,
+    uptime_ms: u64,
+    /// Kernel ID of the running thread, 0 if unknown
+    thread: u64,
+    registers: Registers,
+    frame_count: u64,
+    frames: [u64; MAX_FRAMES],
+    message_len: u64,
+    message: [u8; MESSAGE_SIZE],
+    log_len: u64,
+    log: [u8; LOG_TAIL_SIZE],
+}
+
+const _: () = assert!(core::mem::size_of::<CrashReport>() <= DUMP_PAGES * pmm::PAGE_SIZE);
+
+/// Built in place rather than on the crashed thread's stack, which is
+/// small and may be what overflowed
+static mut REPORT: CrashReport = CrashReport {
+    magic: 0,
+    checksum: 0,
+    uptime_ms: 0,
+    thread: 0,
+    registers: Registers {
+        has_gprs: 0,
+        rax: 0,
+        rbx: 0,
+        rcx: 0,
+        rdx: 0,
+        rsi: 0,
+        rdi: 0,
+        rbp: 0,
+        rsp: 0,
+        r8: 0,
+        r9: 0,
+        r10: 0,
+        r11: 0,
+        r12: 0,
+        r13: 0,
+        r14: 0,
+        r15: 0,
+        rip: 0,
+        rflags: 0,
+        cs: 0,
+        ss: 0,
+        cr0: 0,
+        cr2: 0,
+        cr3: 0,
+        cr4: 0,
+    },
+    frame_count: 0,
+    frames: [0; MAX_FRAMES],
+    message_len: 0,
+    message: [0; MESSAGE_SIZE],
+    log_len: 0,
+    log: [0; LOG_TAIL_SIZE],
+};
+
+impl CrashReport {
+    fn message(&self) -> &str {
+        let len = (self.message_len as usize).min(MESSAGE_SIZE);
+        core::str::from_utf8(&self.message[..len]).unwrap_or("<message not UTF-8>")
+    }
+
+    fn frames(&self) -> &[u64] {
+        &self.frames[..(self.frame_count as usize).min(MAX_FRAMES)]
+    }
+
+    fn log_tail(&self) -> &[u8] {
+        &self.log[..(self.log_len as usize).min(LOG_TAIL_SIZE)]
+    }
+
+    fn compute_checksum(&self) -> u64 {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                (self as *const Self as *const u8).add(16),
+                core::mem::size_of::<Self>() - 16,
+            )
+        };
+        bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+        })
+    }
+}
+
+// ============================================================================
+// Boot
+// ============================================================================
+
+/// Reserve the dump region if the boot parameter asks for it
+///
+/// Runs right after the PMM is set up, before anything can allocate the
+/// region.
+pub fn reserve_dump_region() {
+    if !crate::system::info().command_line().has_flag(CRASH_DUMP_FLAG) {
+        return;
+    }
+
+    if pmm::reserve_pages(DUMP_PHYS, DUMP_PAGES) {
+        DUMP_RESERVED.store(true, Ordering::Release);
+    } else {
+        log_warn!(LOG_ORIGIN, "Crash dump region {:#X} is not free RAM; dumps disabled", DUMP_PHYS);
+    }
+}
+
+/// Log the dump a crash in the previous boot left, then clear it
+pub fn init() {
+    if !DUMP_RESERVED.load(Ordering::Acquire) {
+        return;
+    }
+
+    let dump = unsafe { &mut *(DUMP_PHYS as *mut CrashReport) };
+    let valid = dump.magic == DUMP_MAGIC && dump.checksum == dump.compute_checksum();
+    if !valid {
+        log_info!(LOG_ORIGIN, "Crash dumps kept at {:#X}; none from the last boot", DUMP_PHYS);
+        dump.magic = 0;
+        return;
+    }
+
+    log_warn!(
+        LOG_ORIGIN,
+        "The last boot crashed {}.{:03}s after starting: {}",
+        dump.uptime_ms / 1000,
+        dump.uptime_ms % 1000,
+        dump.message()
+    );
+    if dump.thread != 0 {
+        log_warn!(LOG_ORIGIN, "  in thread {}", dump.thread);
+    }
+    let registers = dump.registers;
+    for (name, value) in registers.shown() {
+        log_warn!(LOG_ORIGIN, "  {:<6} {:#018X}", name, value);
+    }
+    for (i, frame) in dump.frames().iter().enumerate() {
+        log_warn!(LOG_ORIGIN, "  #{:<2} {:#018X}", i, frame);
+    }
+    if let Ok(tail) = core::str::from_utf8(dump.log_tail()) {
+        log_warn!(LOG_ORIGIN, "Kernel log before the crash:");
+        for line in tail.lines() {
+            log_warn!(LOG_ORIGIN, "  | {}", line);
+        }
+    }
+
+    dump.magic = 0;
+}
+
+// ============================================================================
+// Crash Paths
+// ============================================================================
+
+/// Report a kernel panic and stop
+#[inline(never)]
+pub fn panic(info: &PanicInfo) -> ! {
+    let registers = Registers::here();
+    crash(registers, format_args!("Kernel panic: {}", info))
+}
+
+/// Report a fatal CPU exception and stop
+pub fn exception(frame: &InterruptFrame, name: &str) -> ! {
+    let registers = Registers::from_frame(frame);
+    crash(
+        registers,
+        format_args!(
+            "CPU exception: {} (vector {}, error code {:#X})",
+            name, frame.exception_number, frame.error_code
+        ),
+    )
+}
+
+fn crash(registers: Registers, cause: fmt::Arguments) -> ! {
+    unsafe {
+        core::arch::asm!("cli", options(nomem, nostack));
+    }
+    if CRASHING.swap(true, Ordering::Relaxed) {
+        stop();
+    }
+
+    let report = unsafe { &mut *core::ptr::addr_of_mut!(REPORT) };
+    fill_report(report, registers, cause);
+
+    for (i, frame) in report.frames().iter().enumerate() {
+        log_panic!(LOG_ORIGIN, "#{:<2} {:#018X}", i, frame);
+    }
+
+    let saved = save_dump(report);
+    graphics::try_with_framebuffer(|fb| draw_crash_screen(fb, report, saved));
+
+    log_panic!(LOG_ORIGIN, "System halted");
+    stop()
+}
+
+fn stop() -> ! {
+    loop {
+        halt();
+    }
+}
+
+fn fill_report(report: &mut CrashReport, registers: Registers, cause: fmt::Arguments) {
+    report.uptime_ms = crate::interrupts::get_ticks() * 10;
+    report.thread = sched::try_current_thread().map_or(0, |tid| tid.raw());
+    report.registers = registers;
+    report.frame_count = walk_frames(registers.rbp, &mut report.frames) as u64;
+
+    let mut message = TextBuffer { buffer: &mut report.message, len: 0 };
+    let _ = message.write_fmt(cause);
+    report.message_len = message.len as u64;
+
+    report.log_len = log::try_klog_tail(&mut report.log).unwrap_or(0) as u64;
+}
+
+/// Return addresses up the frame-pointer chain, innermost first
+fn walk_frames(mut fp: u64, frames: &mut [u64]) -> usize {
+    // A chain that runs on into a user stack is followed as well
+    let _access = UserAccess::open();
+    let mut count = 0;
+    while count < frames.len() && fp != 0 && fp.is_multiple_of(8) && is_mapped(fp, 16) {
+        let (next, ret) = unsafe { (*(fp as *const u64), *((fp + 8) as *const u64)) };
+        if ret == 0 {
+            break;
+        }
+        frames[count] = ret;
+        count += 1;
+        // The chain runs up the stack; anything else is not a frame
+        if next <= fp || next - fp > MAX_FRAME_SIZE {
+            break;
+        }
+        fp = next;
+    }
+    count
+}
+
+/// Whether `len` bytes at `addr` can be read in the current address space
+fn is_mapped(addr: u64, len: u64) -> bool {
+    let pml4 = read_cr3() as usize & !(pmm::PAGE_SIZE - 1);
+    let first = pmm::align_down(addr as usize);
+    let last = pmm::align_down((addr + len - 1) as usize);
+    vm::query_mapping_in_pml4(pml4, first).is_ok()
+        && (last == first || vm::query_mapping_in_pml4(pml4, last).is_ok())
+}
+
+fn save_dump(report: &mut CrashReport) -> bool {
+    if !DUMP_RESERVED.load(Ordering::Acquire) {
+        return false;
+    }
+
+    report.magic = DUMP_MAGIC;
+    report.checksum = report.compute_checksum();
+    unsafe {
+        let dump = DUMP_PHYS as *mut CrashReport;
+        core::ptr::copy_nonoverlapping(report as *const CrashReport, dump, 1);
+    }
+    true
+}
+
+/// `fmt::Write` into a fixed buffer, dropping what does not fit
+struct TextBuffer<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for TextBuffer<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut take = s.len().min(self.buffer.len() - self.len);
+        while !s.is_char_boundary(take) {
+            take -= 1;
+        }
+        self.buffer[self.len..self.len + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Crash Screen
+// ============================================================================
+
+fn draw_crash_screen(fb: &mut Framebuffer, report: &CrashReport, saved: bool) {
+    fb.fill_rect(0, 0, fb.width(), fb.height(), BACKGROUND);
+
+    let mut screen = Screen::new(fb);
+    screen.color = TITLE;
+    let _ = writeln!(screen, "ATOM HAS STOPPED");
+    screen.color = TEXT;
+    let _ = writeln!(screen);
+    let _ = writeln!(screen, "{}", report.message());
+    let _ = writeln!(screen);
+
+    screen.color = DIM;
+    let _ = write!(screen, "Uptime {}.{:03}s", report.uptime_ms / 1000, report.uptime_ms % 1000);
+    match report.thread {
+        0 => {
+            let _ = writeln!(screen);
+        }
+        tid => {
+            let name = thread::try_with_threads(|threads| {
+                threads.iter().find(|t| t.id.raw() == tid).map(|t| t.name)
+            });
+            let _ = writeln!(screen, ", thread {} ({})", tid, name.flatten().unwrap_or("?"));
+        }
+    }
+    let _ = writeln!(screen);
+
+    screen.color = TEXT;
+    let _ = writeln!(screen, "Registers");
+    for (i, (name, value)) in report.registers.shown().enumerate() {
+        let _ = write!(screen, "  {:<6} {:016X}", name, value);
+        if i % 4 == 3 {
+            let _ = writeln!(screen);
+        }
+    }
+    let _ = writeln!(screen);
+    let _ = writeln!(screen);
+
+    let _ = writeln!(screen, "Stack trace");
+    if report.frames().is_empty() {
+        let _ = writeln!(screen, "  (no frames)");
+    }
+    for (i, chunk) in report.frames().chunks(4).enumerate() {
+        for (j, frame) in chunk.iter().enumerate() {
+            let _ = write!(screen, "  #{:<2} {:016X}", i * 4 + j, frame);
+        }
+        let _ = writeln!(screen);
+    }
+    let _ = writeln!(screen);
+
+    // The footer takes the last rows; the log fills what is left
+    let footer_row = screen.rows.saturating_sub(2);
+    let _ = writeln!(screen, "Recent log");
+    screen.color = DIM;
+    let room = footer_row.saturating_sub(screen.row + 1) as usize;
+    if let Ok(tail) = core::str::from_utf8(report.log_tail()) {
+        let skip = tail.lines().count().saturating_sub(room);
+        for line in tail.lines().skip(skip) {
+            screen.line(line);
+        }
+    }
+
+    screen.row = footer_row;
+    screen.col = 0;
+    screen.color = TEXT;
+    let _ = write!(screen, "System halted. ");
+    if saved {
+        let _ = write!(screen, "A crash dump was saved; it is logged after a warm reboot.");
+    } else {
+        let _ = write!(screen, "Boot with crash_dump to keep a dump across a warm reboot.");
+    }
+}
+
+/// Text cursor over the crash screen
+struct Screen<'a> {
+    fb: &'a mut Framebuffer,
+    cols: u32,
+    rows: u32,
+    col: u32,
+    row: u32,
+    color: Color,
+}
+
+impl<'a> Screen<'a> {
+    fn new(fb: &'a mut Framebuffer) -> Self {
+        let cols = fb.width().saturating_sub(2 * MARGIN) / FONT_WIDTH;
+        let rows = fb.height().saturating_sub(2 * MARGIN) / LINE_HEIGHT;
+        Self { fb, cols, rows, col: 0, row: 0, color: TEXT }
+    }
+
+    /// One line, cut at the screen edge rather than wrapped
+    fn line(&mut self, text: &str) {
+        for byte in text.bytes().take(self.cols as usize) {
+            self.put(byte);
+        }
+        self.newline();
+    }
+
+    fn newline(&mut self) {
+        self.col = 0;
+        self.row += 1;
+    }
+
+    fn put(&mut self, byte: u8) {
+        if byte == b'\n' {
+            self.newline();
+            return;
+        }
+        if self.col >= self.cols {
+            self.newline();
+        }
+        if self.row >= self.rows {
+            return;
+        }
+        let x = MARGIN + self.col * FONT_WIDTH;
+        let y = MARGIN + self.row * LINE_HEIGHT;
+        let ch = if byte.is_ascii_graphic() || byte == b' ' { byte } else { b'?' };
+        self.fb.draw_char(x, y, ch, self.color, BACKGROUND);
+        self.col += 1;
+    }
+}
+
+impl Write for Screen<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.put(byte);
+        }
+        Ok(())
+    }
+}
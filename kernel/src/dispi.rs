@@ -0,0 +1,183 @@
+// Bochs/QEMU VBE DISPI Mode Setting
+//
+// The firmware's mode-setting call is gone once boot services exit, so the
+// kernel needs a backend of its own to change resolution at runtime. The
+// Bochs display interface (DISPI) is the one QEMU's standard VGA, Bochs
+// and VirtualBox all speak: a handful of 16-bit registers behind an
+// index/data port pair.
+//
+// Key responsibilities:
+// - Detect the interface and check it drives the framebuffer we booted with
+// - Report how much video memory the adapter has
+// - Program a new resolution at 32 bits per pixel
+//
+// Implementation details:
+// - The adapter is only used if its current mode matches the one the
+//   firmware reported, which is how we know the firmware's framebuffer is
+//   the adapter's linear framebuffer
+// - Mode changes disable the display, write the geometry and re-enable it
+//   with the linear framebuffer on; the registers are read back to check
+//   the adapter took the mode
+//
+// Limitations:
+// - Only 32 bpp modes; the rest of the graphics code assumes 4 bytes per
+//   pixel anyway
+// - No virtual panning or page flipping through the offset registers
+
+use spin::Once;
+
+use crate::log_info;
+
+const LOG_ORIGIN: &str = "dispi";
+
+const DISPI_INDEX: u16 = 0x01CE;
+const DISPI_DATA: u16 = 0x01CF;
+
+const REG_ID: u16 = 0x0;
+const REG_XRES: u16 = 0x1;
+const REG_YRES: u16 = 0x2;
+const REG_BPP: u16 = 0x3;
+const REG_ENABLE: u16 = 0x4;
+const REG_VIRT_WIDTH: u16 = 0x6;
+const REG_VIRT_HEIGHT: u16 = 0x7;
+const REG_X_OFFSET: u16 = 0x8;
+const REG_Y_OFFSET: u16 = 0x9;
+/// Video memory size in 64 KiB units
+const REG_VIDEO_MEMORY_64K: u16 = 0xA;
+
+/// Range of IDs of the interface revisions we know
+const ID_MIN: u16 = 0xB0C0;
+const ID_MAX: u16 = 0xB0C5;
+
+const ENABLE_ON: u16 = 0x01;
+/// While set, XRES/YRES/BPP read back the largest values supported
+const ENABLE_GET_CAPS: u16 = 0x02;
+const ENABLE_LFB: u16 = 0x40;
+
+const BITS_PER_PIXEL: u16 = 32;
+
+/// The adapter, if it drives the boot framebuffer
+struct Dispi {
+    id: u16,
+    /// Bytes of video memory, or 0 if the adapter does not say
+    vram: usize,
+    max_width: u32,
+    max_height: u32,
+}
+
+static DISPI: Once<Option<Dispi>> = Once::new();
+
+/// Detect the interface; `width` x `height` is the mode the firmware left
+/// the screen in
+pub fn init(width: u32, height: u32) {
+    DISPI.call_once(|| {
+        let dispi = probe(width, height);
+        match &dispi {
+            Some(dispi) => log_info!(
+                LOG_ORIGIN,
+                "DISPI {:#X}: {} KiB video memory, up to {}x{}",
+                dispi.id,
+                dispi.vram / 1024,
+                dispi.max_width,
+                dispi.max_height
+            ),
+            None => log_info!(LOG_ORIGIN, "No DISPI adapter; resolution is fixed"),
+        }
+        dispi
+    });
+}
+
+fn probe(width: u32, height: u32) -> Option<Dispi> {
+    let id = read(REG_ID);
+    if !(ID_MIN..=ID_MAX).contains(&id) {
+        return None;
+    }
+
+    let enable = read(REG_ENABLE);
+    let current = (read(REG_XRES) as u32, read(REG_YRES) as u32);
+    if enable & ENABLE_ON == 0 || read(REG_BPP) != BITS_PER_PIXEL || current != (width, height) {
+        return None;
+    }
+
+    write(REG_ENABLE, enable | ENABLE_GET_CAPS);
+    let (max_width, max_height) = (read(REG_XRES) as u32, read(REG_YRES) as u32);
+    write(REG_ENABLE, enable);
+
+    Some(Dispi {
+        id,
+        vram: read(REG_VIDEO_MEMORY_64K) as usize * 64 * 1024,
+        max_width,
+        max_height,
+    })
+}
+
+/// Whether runtime mode changes are available
+pub fn is_present() -> bool {
+    matches!(DISPI.get(), Some(Some(_)))
+}
+
+/// Bytes of video memory, if the adapter is present and reports it
+pub fn vram_size() -> Option<usize> {
+    DISPI.get()?.as_ref().map(|dispi| dispi.vram).filter(|&vram| vram > 0)
+}
+
+/// Switch to `width` x `height` at 32 bpp with rows packed back to back;
+/// false if the adapter is missing or refused the mode
+pub fn set_mode(width: u32, height: u32) -> bool {
+    let Some(Some(dispi)) = DISPI.get() else {
+        return false;
+    };
+    if width == 0 || height == 0 || width > dispi.max_width || height > dispi.max_height {
+        return false;
+    }
+
+    write(REG_ENABLE, 0);
+    write(REG_XRES, width as u16);
+    write(REG_YRES, height as u16);
+    write(REG_BPP, BITS_PER_PIXEL);
+    write(REG_VIRT_WIDTH, width as u16);
+    write(REG_VIRT_HEIGHT, height as u16);
+    write(REG_X_OFFSET, 0);
+    write(REG_Y_OFFSET, 0);
+    write(REG_ENABLE, ENABLE_ON | ENABLE_LFB);
+
+    read(REG_XRES) as u32 == width && read(REG_YRES) as u32 == height
+}
+
+fn read(index: u16) -> u16 {
+    unsafe {
+        outw(DISPI_INDEX, index);
+        inw(DISPI_DATA)
+    }
+}
+
+fn write(index: u16, value: u16) {
+    unsafe {
+        outw(DISPI_INDEX, index);
+        outw(DISPI_DATA, value);
+    }
+}
+
+#[inline]
+unsafe fn outw(port: u16, value: u16) {
+    #[cfg(target_arch = "x86_64")]
+    core::arch::asm!(
+        "out dx, ax",
+        in("dx") port,
+        in("ax") value,
+        options(nomem, nostack, preserves_flags)
+    );
+}
+
+#[inline]
+unsafe fn inw(port: u16) -> u16 {
+    let ret: u16;
+    #[cfg(target_arch = "x86_64")]
+    core::arch::asm!(
+        "in ax, dx",
+        out("ax") ret,
+        in("dx") port,
+        options(nomem, nostack, preserves_flags)
+    );
+    ret
+}
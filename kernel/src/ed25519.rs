@@ -0,0 +1,469 @@
+// Ed25519 signature verification
+//
+// Checks the signatures elf2atxf and atom-image put on ATXF images, so the
+// loader can refuse images that were not signed with the key the kernel
+// was built with. This is the verification half of tools/atxf's ed25519
+// module, after TweetNaCl, with the SHA-512 it needs.
+//
+// Design:
+// - Field elements are sixteen 16-bit limbs held in i64s; reductions are
+//   done with carries, never by division
+// - Points use extended coordinates and are multiplied with a ladder that
+//   does the same work for every scalar bit
+// - The message may be given in pieces, so an image can be checked in
+//   place with its checksum field taken as zero
+//
+// Safety and correctness notes:
+// - Signatures whose S is not below the group order are rejected, so a
+//   signature has one valid encoding
+// - Public keys that do not decode to a curve point fail verification
+//   rather than panicking
+
+/// Field element: 16 limbs of 16 bits, little-endian
+type Gf = [i64; 16];
+/// Point in extended coordinates (X, Y, Z, T)
+type Point = [Gf; 4];
+
+pub const PUBLIC_KEY_SIZE: usize = 32;
+pub const SIGNATURE_SIZE: usize = 64;
+
+const GF0: Gf = [0; 16];
+const GF1: Gf = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+/// The curve constant d
+const D: Gf = [
+    0x78a3, 0x1359, 0x4dca, 0x75eb, 0xd8ab, 0x4141, 0x0a4d, 0x0070, 0xe898, 0x7779, 0x4079, 0x8cc7,
+    0xfe73, 0x2b6f, 0x6cee, 0x5203,
+];
+/// 2 * d
+const D2: Gf = [
+    0xf159, 0x26b2, 0x9b94, 0xebd6, 0xb156, 0x8283, 0x149a, 0x00e0, 0xd130, 0xeef3, 0x80f2, 0x198e,
+    0xfce7, 0x56df, 0xd9dc, 0x2406,
+];
+/// Coordinates of the base point
+const X: Gf = [
+    0xd51a, 0x8f25, 0x2d60, 0xc956, 0xa7b2, 0x9525, 0xc760, 0x692c, 0xdc5c, 0xfdd6, 0xe231, 0xc0a4,
+    0x53fe, 0xcd6e, 0x36d3, 0x2169,
+];
+const Y: Gf = [
+    0x6658, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+    0x6666, 0x6666, 0x6666, 0x6666,
+];
+/// A square root of -1
+const I: Gf = [
+    0xa0b0, 0x4a0e, 0x1b27, 0xc4ee, 0xe478, 0xad2f, 0x1806, 0x2f43, 0xd7a7, 0x3dfb, 0x0099, 0x2b4d,
+    0xdf0b, 0x4fc1, 0x2480, 0x2b83,
+];
+/// The group order, little-endian
+const L: [i64; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+];
+
+/// A public key written as 64 hex digits, as `elf2atxf --public-key`
+/// prints it; evaluated at build time, where a bad key stops the build
+pub const fn public_key_from_hex(hex: &str) -> [u8; PUBLIC_KEY_SIZE] {
+    const fn digit(c: u8) -> u8 {
+        match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ => panic!("public key must be 64 hex digits"),
+        }
+    }
+
+    let hex = hex.as_bytes();
+    if hex.len() != 2 * PUBLIC_KEY_SIZE {
+        panic!("public key must be 64 hex digits");
+    }
+    let mut key = [0u8; PUBLIC_KEY_SIZE];
+    let mut i = 0;
+    while i < PUBLIC_KEY_SIZE {
+        key[i] = digit(hex[2 * i]) << 4 | digit(hex[2 * i + 1]);
+        i += 1;
+    }
+    key
+}
+
+/// Whether `signature` is `public`'s signature of the concatenated `message`
+pub fn verify(public: &[u8; PUBLIC_KEY_SIZE], message: &[&[u8]], signature: &[u8; 64]) -> bool {
+    let (r, s) = signature.split_at(32);
+    let s: &[u8; 32] = s.try_into().unwrap();
+    if !is_canonical_scalar(s) {
+        return false;
+    }
+    let Some(mut a) = unpack_negated(public) else {
+        return false;
+    };
+
+    let mut hash = Sha512::new();
+    hash.update(r);
+    hash.update(public);
+    for part in message {
+        hash.update(part);
+    }
+    let h = reduce(&hash.finish());
+
+    // [s]B - [h]A must be R
+    let mut p = scalar_mult(&mut a, &h);
+    add(&mut p, &scalar_base(s));
+    pack(&p)[..] == *r
+}
+
+fn is_canonical_scalar(s: &[u8; 32]) -> bool {
+    for i in (0..32).rev() {
+        if (s[i] as i64) != L[i] {
+            return (s[i] as i64) < L[i];
+        }
+    }
+    false
+}
+
+fn carry(o: &mut Gf) {
+    for i in 0..16 {
+        o[i] += 1 << 16;
+        let c = o[i] >> 16;
+        if i < 15 {
+            o[i + 1] += c - 1;
+        } else {
+            o[0] += 38 * (c - 1);
+        }
+        o[i] -= c << 16;
+    }
+}
+
+/// Swap `p` and `q` if `bit` is 1, without branching on it
+fn select(p: &mut Gf, q: &mut Gf, bit: i64) {
+    let mask = !(bit - 1);
+    for i in 0..16 {
+        let t = mask & (p[i] ^ q[i]);
+        p[i] ^= t;
+        q[i] ^= t;
+    }
+}
+
+fn pack_gf(n: &Gf) -> [u8; 32] {
+    let mut t = *n;
+    carry(&mut t);
+    carry(&mut t);
+    carry(&mut t);
+    for _ in 0..2 {
+        let mut m = GF0;
+        m[0] = t[0] - 0xffed;
+        for i in 1..15 {
+            m[i] = t[i] - 0xffff - ((m[i - 1] >> 16) & 1);
+            m[i - 1] &= 0xffff;
+        }
+        m[15] = t[15] - 0x7fff - ((m[14] >> 16) & 1);
+        let borrow = (m[15] >> 16) & 1;
+        m[14] &= 0xffff;
+        select(&mut t, &mut m, 1 - borrow);
+    }
+
+    let mut out = [0u8; 32];
+    for i in 0..16 {
+        out[2 * i] = t[i] as u8;
+        out[2 * i + 1] = (t[i] >> 8) as u8;
+    }
+    out
+}
+
+fn unpack_gf(bytes: &[u8; 32]) -> Gf {
+    let mut o = GF0;
+    for i in 0..16 {
+        o[i] = bytes[2 * i] as i64 + ((bytes[2 * i + 1] as i64) << 8);
+    }
+    o[15] &= 0x7fff;
+    o
+}
+
+fn parity(a: &Gf) -> u8 {
+    pack_gf(a)[0] & 1
+}
+
+fn gf_add(a: &Gf, b: &Gf) -> Gf {
+    core::array::from_fn(|i| a[i] + b[i])
+}
+
+fn gf_sub(a: &Gf, b: &Gf) -> Gf {
+    core::array::from_fn(|i| a[i] - b[i])
+}
+
+fn gf_mul(a: &Gf, b: &Gf) -> Gf {
+    let mut t = [0i64; 31];
+    for i in 0..16 {
+        for j in 0..16 {
+            t[i + j] += a[i] * b[j];
+        }
+    }
+    for i in 0..15 {
+        t[i] += 38 * t[i + 16];
+    }
+    let mut o: Gf = t[..16].try_into().unwrap();
+    carry(&mut o);
+    carry(&mut o);
+    o
+}
+
+fn gf_square(a: &Gf) -> Gf {
+    gf_mul(a, a)
+}
+
+fn invert(i: &Gf) -> Gf {
+    let mut c = *i;
+    for a in (0..=253).rev() {
+        c = gf_square(&c);
+        if a != 2 && a != 4 {
+            c = gf_mul(&c, i);
+        }
+    }
+    c
+}
+
+/// i ^ ((p - 5) / 8)
+fn pow2523(i: &Gf) -> Gf {
+    let mut c = *i;
+    for a in (0..=250).rev() {
+        c = gf_square(&c);
+        if a != 1 {
+            c = gf_mul(&c, i);
+        }
+    }
+    c
+}
+
+fn add(p: &mut Point, q: &Point) {
+    let a = gf_mul(&gf_sub(&p[1], &p[0]), &gf_sub(&q[1], &q[0]));
+    let b = gf_mul(&gf_add(&p[0], &p[1]), &gf_add(&q[0], &q[1]));
+    let c = gf_mul(&gf_mul(&p[3], &q[3]), &D2);
+    let d = gf_mul(&p[2], &q[2]);
+    let d = gf_add(&d, &d);
+    let e = gf_sub(&b, &a);
+    let f = gf_sub(&d, &c);
+    let g = gf_add(&d, &c);
+    let h = gf_add(&b, &a);
+
+    p[0] = gf_mul(&e, &f);
+    p[1] = gf_mul(&h, &g);
+    p[2] = gf_mul(&g, &f);
+    p[3] = gf_mul(&e, &h);
+}
+
+fn swap(p: &mut Point, q: &mut Point, bit: i64) {
+    for i in 0..4 {
+        select(&mut p[i], &mut q[i], bit);
+    }
+}
+
+fn pack(p: &Point) -> [u8; 32] {
+    let zi = invert(&p[2]);
+    let x = gf_mul(&p[0], &zi);
+    let y = gf_mul(&p[1], &zi);
+    let mut out = pack_gf(&y);
+    out[31] ^= parity(&x) << 7;
+    out
+}
+
+/// [s]q; `q` is clobbered
+fn scalar_mult(q: &mut Point, s: &[u8; 32]) -> Point {
+    let mut p = [GF0, GF1, GF1, GF0];
+    for i in (0..256).rev() {
+        let bit = ((s[i / 8] >> (i & 7)) & 1) as i64;
+        swap(&mut p, q, bit);
+        let p_copy = p;
+        add(q, &p_copy);
+        add(&mut p, &p_copy);
+        swap(&mut p, q, bit);
+    }
+    p
+}
+
+/// [s]B for the base point B
+fn scalar_base(s: &[u8; 32]) -> Point {
+    let mut base = [X, Y, GF1, gf_mul(&X, &Y)];
+    scalar_mult(&mut base, s)
+}
+
+/// The negation of the point `bytes` encodes, if it is on the curve
+fn unpack_negated(bytes: &[u8; 32]) -> Option<Point> {
+    let y = unpack_gf(bytes);
+    let num = gf_square(&y);
+    let den = gf_mul(&num, &D);
+    let num = gf_sub(&num, &GF1);
+    let den = gf_add(&GF1, &den);
+
+    let den2 = gf_square(&den);
+    let den4 = gf_square(&den2);
+    let den6 = gf_mul(&den4, &den2);
+    let t = gf_mul(&gf_mul(&den6, &num), &den);
+    let t = gf_mul(&gf_mul(&pow2523(&t), &num), &den);
+    let mut x = gf_mul(&gf_mul(&t, &den), &den);
+
+    let check = gf_mul(&gf_square(&x), &den);
+    if pack_gf(&check) != pack_gf(&num) {
+        x = gf_mul(&x, &I);
+    }
+    let check = gf_mul(&gf_square(&x), &den);
+    if pack_gf(&check) != pack_gf(&num) {
+        return None;
+    }
+
+    if parity(&x) == bytes[31] >> 7 {
+        x = gf_sub(&GF0, &x);
+    }
+    Some([x, y, GF1, gf_mul(&x, &y)])
+}
+
+/// `x` modulo the group order; `x` is clobbered
+fn mod_l(x: &mut [i64; 64]) -> [u8; 32] {
+    for i in (32..64).rev() {
+        let mut carry = 0;
+        let mut j = i - 32;
+        while j < i - 12 {
+            x[j] += carry - 16 * x[i] * L[j - (i - 32)];
+            carry = (x[j] + 128) >> 8;
+            x[j] -= carry << 8;
+            j += 1;
+        }
+        x[j] += carry;
+        x[i] = 0;
+    }
+
+    let mut carry = 0;
+    for j in 0..32 {
+        x[j] += carry - (x[31] >> 4) * L[j];
+        carry = x[j] >> 8;
+        x[j] &= 255;
+    }
+    for j in 0..32 {
+        x[j] -= carry * L[j];
+    }
+
+    let mut out = [0u8; 32];
+    for i in 0..32 {
+        x[i + 1] += x[i] >> 8;
+        out[i] = (x[i] & 255) as u8;
+    }
+    out
+}
+
+/// A 64-byte hash modulo the group order
+fn reduce(hash: &[u8; 64]) -> [u8; 32] {
+    let mut x = [0i64; 64];
+    for (i, &byte) in hash.iter().enumerate() {
+        x[i] = byte as i64;
+    }
+    mod_l(&mut x)
+}
+
+/// SHA-512, fed in pieces
+struct Sha512 {
+    state: [u64; 8],
+    block: [u8; 128],
+    filled: usize,
+    length: u128,
+}
+
+const SHA512_INIT: [u64; 8] = [
+    0x6a09_e667_f3bc_c908, 0xbb67_ae85_84ca_a73b, 0x3c6e_f372_fe94_f82b, 0xa54f_f53a_5f1d_36f1,
+    0x510e_527f_ade6_82d1, 0x9b05_688c_2b3e_6c1f, 0x1f83_d9ab_fb41_bd6b, 0x5be0_cd19_137e_2179,
+];
+
+const SHA512_K: [u64; 80] = [
+    0x428a_2f98_d728_ae22, 0x7137_4491_23ef_65cd, 0xb5c0_fbcf_ec4d_3b2f, 0xe9b5_dba5_8189_dbbc,
+    0x3956_c25b_f348_b538, 0x59f1_11f1_b605_d019, 0x923f_82a4_af19_4f9b, 0xab1c_5ed5_da6d_8118,
+    0xd807_aa98_a303_0242, 0x1283_5b01_4570_6fbe, 0x2431_85be_4ee4_b28c, 0x550c_7dc3_d5ff_b4e2,
+    0x72be_5d74_f27b_896f, 0x80de_b1fe_3b16_96b1, 0x9bdc_06a7_25c7_1235, 0xc19b_f174_cf69_2694,
+    0xe49b_69c1_9ef1_4ad2, 0xefbe_4786_384f_25e3, 0x0fc1_9dc6_8b8c_d5b5, 0x240c_a1cc_77ac_9c65,
+    0x2de9_2c6f_592b_0275, 0x4a74_84aa_6ea6_e483, 0x5cb0_a9dc_bd41_fbd4, 0x76f9_88da_8311_53b5,
+    0x983e_5152_ee66_dfab, 0xa831_c66d_2db4_3210, 0xb003_27c8_98fb_213f, 0xbf59_7fc7_beef_0ee4,
+    0xc6e0_0bf3_3da8_8fc2, 0xd5a7_9147_930a_a725, 0x06ca_6351_e003_826f, 0x1429_2967_0a0e_6e70,
+    0x27b7_0a85_46d2_2ffc, 0x2e1b_2138_5c26_c926, 0x4d2c_6dfc_5ac4_2aed, 0x5338_0d13_9d95_b3df,
+    0x650a_7354_8baf_63de, 0x766a_0abb_3c77_b2a8, 0x81c2_c92e_47ed_aee6, 0x9272_2c85_1482_353b,
+    0xa2bf_e8a1_4cf1_0364, 0xa81a_664b_bc42_3001, 0xc24b_8b70_d0f8_9791, 0xc76c_51a3_0654_be30,
+    0xd192_e819_d6ef_5218, 0xd699_0624_5565_a910, 0xf40e_3585_5771_202a, 0x106a_a070_32bb_d1b8,
+    0x19a4_c116_b8d2_d0c8, 0x1e37_6c08_5141_ab53, 0x2748_774c_df8e_eb99, 0x34b0_bcb5_e19b_48a8,
+    0x391c_0cb3_c5c9_5a63, 0x4ed8_aa4a_e341_8acb, 0x5b9c_ca4f_7763_e373, 0x682e_6ff3_d6b2_b8a3,
+    0x748f_82ee_5def_b2fc, 0x78a5_636f_4317_2f60, 0x84c8_7814_a1f0_ab72, 0x8cc7_0208_1a64_39ec,
+    0x90be_fffa_2363_1e28, 0xa450_6ceb_de82_bde9, 0xbef9_a3f7_b2c6_7915, 0xc671_78f2_e372_532b,
+    0xca27_3ece_ea26_619c, 0xd186_b8c7_21c0_c207, 0xeada_7dd6_cde0_eb1e, 0xf57d_4f7f_ee6e_d178,
+    0x06f0_67aa_7217_6fba, 0x0a63_7dc5_a2c8_98a6, 0x113f_9804_bef9_0dae, 0x1b71_0b35_131c_471b,
+    0x28db_77f5_2304_7d84, 0x32ca_ab7b_40c7_2493, 0x3c9e_be0a_15c9_bebc, 0x431d_67c4_9c10_0d4c,
+    0x4cc5_d4be_cb3e_42b6, 0x597f_299c_fc65_7e2a, 0x5fcb_6fab_3ad6_faec, 0x6c44_198c_4a47_5817,
+];
+
+impl Sha512 {
+    fn new() -> Self {
+        Self { state: SHA512_INIT, block: [0; 128], filled: 0, length: 0 }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u128;
+        while !data.is_empty() {
+            let take = data.len().min(128 - self.filled);
+            self.block[self.filled..self.filled + take].copy_from_slice(&data[..take]);
+            self.filled += take;
+            data = &data[take..];
+            if self.filled == 128 {
+                self.compress();
+                self.filled = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> [u8; 64] {
+        let bits = self.length * 8;
+        self.block[self.filled] = 0x80;
+        self.block[self.filled + 1..].fill(0);
+        if self.filled >= 112 {
+            self.compress();
+            self.block.fill(0);
+        }
+        self.block[112..].copy_from_slice(&bits.to_be_bytes());
+        self.compress();
+
+        let mut out = [0u8; 64];
+        for (chunk, word) in out.chunks_mut(8).zip(self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u64; 80];
+        for (i, chunk) in self.block.chunks(8).enumerate() {
+            w[i] = u64::from_be_bytes(chunk.try_into().unwrap());
+        }
+        for i in 16..80 {
+            let s0 = w[i - 15].rotate_right(1) ^ w[i - 15].rotate_right(8) ^ (w[i - 15] >> 7);
+            let s1 = w[i - 2].rotate_right(19) ^ w[i - 2].rotate_right(61) ^ (w[i - 2] >> 6);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..80 {
+            let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(SHA512_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (word, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+}
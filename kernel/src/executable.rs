@@ -6,54 +6,112 @@
 //
 // Key responsibilities:
 // - Define and validate the ATXF executable format
-// - Parse executable headers and sections (.text, .data, .bss)
+// - Parse executable headers and segment tables
 // - Load executables into user address spaces
-// - Allocate and map physical memory for code and data
+// - Allocate and map physical memory for each segment
 // - Provide automatic rollback on partial failure
 // - Support bootloader-provided payloads and an embedded fallback image
 //
 // Design and implementation:
 // - Simple format with a fixed header and explicit offsets
-// - Sections aligned to page boundaries (4 KiB)
-// - Fixed load base for user executables
+// - Version 2 images carry a segment table: virtual address, file size,
+//   memory size and R/W/X flags per segment, as produced by elf2atxf
+// - Version 1 images (.text, .data, .bss at a fixed load base) are still
+//   accepted and loaded as three segments
+// - Each segment is mapped with its own PageFlags: read-only unless
+//   writable, no-execute unless executable
+// - Relocatable images (from position-independent ELFs) list the words
+//   holding link-time addresses; the loader places the image at a chosen
+//   or random page and adds the distance it moved to each of them
+// - Segments may be stored LZ4-compressed and are decompressed straight
+//   into their freshly allocated pages; a CRC-32 in the header is checked
+//   before anything else is read
+// - Images may carry a table of function symbols; those of loaded images
+//   are kept per page table so faults and panics can name the function
+//   an address is in
+// - Images may end in an Ed25519 signature, checked against the public key
+//   given at build time in ATOM_ATXF_PUBLIC_KEY; a signature that does not
+//   match always fails, and the `secure_boot` boot parameter refuses images
+//   that are not signed with that key
 // - Explicit use of PMM and VMM for allocation and mapping
 // - RollbackGuard ensures consistent cleanup on failures
 //
 // Safety and correctness notes:
 // - Executables are validated before any mapping occurs
-// - Layout is checked against canonical user address limits
+// - Layout is checked against canonical user address limits once the
+//   image is placed, and relocations must fall inside file data
+// - Segments may not share pages, and none may be writable and executable
 // - Mapping failures release all previously allocated memory
 // - Raw pointers are used only for controlled data copying
 //
 // Limitations and future considerations:
-// - No relocation or ASLR support
-// - Format supports only a single text and data segment
-// - No explicit executable permission enforcement
-// - Loading assumes a trusted executable from boot/init
+// - Random load bases come from the time stamp counter, which hides the
+//   layout from nothing but guesses
+// - Only base-relative relocations; no symbols or shared libraries
+// - Without `secure_boot`, unsigned executables from boot/init are trusted
+// - The embedded fallback image is part of the kernel and is not signed
 //
 // Public interface:
 // - `load_boot_payload` to load init provided at boot
-// - `load_into_address_space` to load generic executables
+// - `load_into_address_space` to load generic executables at a random base
+// - `load_into_address_space_at` to choose the base with `LoadBase`
+// - `resolve_symbol` to name the function around an address
+// - `check_signature_policy` for loaders that parse images themselves
 // - `embedded_init_image` as a minimal init fallback
 // - `ExecError` for detailed failure diagnostics
 
+use alloc::string::String;
 use alloc::vec::Vec;
 use core::mem::size_of;
 use core::ptr;
 
+use crate::arch;
 use crate::boot::ExecutableImage;
+use crate::ed25519;
 use crate::mm::{addrspace, pmm};
 use crate::mm::addrspace::{AddressSpaceId, USER_CANONICAL_MAX};
 use crate::mm::vm::PageFlags;
 use crate::thread::ThreadId;
 use crate::{log_error, log_info, log_warn};
-use spin::Once;
+use spin::{Mutex, Once};
 
 #[allow(dead_code)]
 const LOG_ORIGIN: &str = "exec";
 pub const ATXF_MAGIC: u32 = 0x4154_5846;
-pub const ATXF_VERSION: u16 = 1;
+pub const ATXF_VERSION: u16 = 2;
+/// Fixed .text/.data/.bss layout, still used by the embedded image
+pub const ATXF_VERSION_V1: u16 = 1;
 pub const USER_EXEC_LOAD_BASE: usize = 0x0040_0000;
+/// Most segments a version 2 image may have
+pub const MAX_SEGMENTS: usize = 16;
+pub const SEGMENT_READ: u32 = 1 << 0;
+pub const SEGMENT_WRITE: u32 = 1 << 1;
+pub const SEGMENT_EXECUTE: u32 = 1 << 2;
+/// Header flag: the image may be loaded away from its link-time addresses
+pub const ATXF_FLAG_RELOCATABLE: u32 = 1 << 0;
+/// Header flag: `checksum` holds a CRC-32 of the image
+pub const ATXF_FLAG_CHECKSUM: u32 = 1 << 1;
+/// Header flag: the image ends in an Ed25519 signature of the bytes before
+/// it, taken with `checksum` as zero
+pub const ATXF_FLAG_SIGNED: u32 = 1 << 2;
+/// Where the checksum sits in the version 2 header
+const CHECKSUM_OFFSET: usize = 28;
+/// Key images must be signed with, set with ATOM_ATXF_PUBLIC_KEY when the
+/// kernel is built (`elf2atxf --public-key <key>` prints it)
+const SIGNING_KEY: Option<[u8; ed25519::PUBLIC_KEY_SIZE]> =
+    match option_env!("ATOM_ATXF_PUBLIC_KEY") {
+        Some(hex) => Some(ed25519::public_key_from_hex(hex)),
+        None => None,
+    };
+/// Boot parameter under which only images signed with SIGNING_KEY load
+const SECURE_BOOT_FLAG: &str = "secure_boot";
+/// Random load bases are pages below this address
+pub const RANDOM_BASE_LIMIT: usize = 0x1000_0000;
+/// Version 2 header size before the relocation table was added
+const ATXF_V2_HEADER_SIZE_NO_RELOCATIONS: usize = 32;
+/// Version 2 header size before the symbol table was added
+const ATXF_V2_HEADER_SIZE_NO_SYMBOLS: usize = 40;
+const RELOCATION_ENTRY_SIZE: usize = 8;
 const EMBEDDED_TEXT_OFFSET: usize = pmm::PAGE_SIZE;
 const EMBEDDED_TEXT_SIZE: usize = pmm::PAGE_SIZE;
 const EMBEDDED_DATA_OFFSET: usize = EMBEDDED_TEXT_OFFSET + EMBEDDED_TEXT_SIZE;
@@ -78,8 +136,27 @@ pub enum ExecError {
     OutOfMemory,
     AddressSpace(addrspace::AddressSpaceError),
     NonCanonicalLayout,
+    TooManySegments(usize),
+    InvalidSegment(usize),
+    WritableExecutable(usize),
+    /// Outside the segments' file data, or in an image that is not
+    /// relocatable
+    InvalidRelocation(usize),
+    /// Asked to load at another base, but the image has no relocations
+    NotRelocatable,
+    /// Out of order, or with a name outside the symbol names
+    InvalidSymbol(usize),
+    /// The stored and the computed CRC-32
+    BadChecksum(u32, u32),
+    /// A compressed segment does not decompress to its file size
+    BadCompression,
+    /// The signature is not SIGNING_KEY's signature of the image
+    BadSignature,
+    /// Not signed with SIGNING_KEY, in secure-boot mode
+    Unsigned,
 }
 
+/// Version 1 header
 #[repr(C, packed)]
 #[derive(Clone, Copy)]
 struct AtxfHeader {
@@ -94,35 +171,455 @@ struct AtxfHeader {
     bss_size: u32,
 }
 
+/// Version 2 header; the segment table follows at `segment_offset`
+///
+/// Headers of ATXF_V2_HEADER_SIZE_NO_RELOCATIONS bytes end before
+/// `relocation_offset` and have no relocations; those of
+/// ATXF_V2_HEADER_SIZE_NO_SYMBOLS bytes have no symbols.
+#[repr(C, packed)]
 #[derive(Clone, Copy)]
-pub struct ExecutableSections<'a> {
-    pub entry_offset: usize,
-    pub text: &'a [u8],
+struct AtxfHeaderV2 {
+    magic: u32,
+    version: u16,
+    header_size: u16,
+    entry: u64,
+    segment_offset: u32,
+    segment_count: u16,
+    segment_entry_size: u16,
+    flags: u32,
+    /// CRC-32 of the image with this field as zero, if ATXF_FLAG_CHECKSUM
+    checksum: u32,
+    /// Table of u64 addresses of words holding link-time addresses
+    relocation_offset: u32,
+    relocation_count: u32,
+    /// Table of AtxfSymbol, sorted by address
+    symbol_offset: u32,
+    symbol_count: u32,
+    /// NUL-terminated names the symbols point into
+    names_offset: u32,
+    names_size: u32,
+}
+
+/// One entry of the version 2 segment table
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct AtxfSegment {
+    vaddr: u64,
+    mem_size: u64,
+    offset: u32,
+    file_size: u32,
+    flags: u32,
+    /// Size of the LZ4 block stored at `offset`; 0 if stored as is
+    stored_size: u32,
+}
+
+/// One entry of the version 2 symbol table
+#[repr(C, packed)]
+#[derive(Clone, Copy)]
+struct AtxfSymbol {
+    vaddr: u64,
+    size: u32,
+    name_offset: u32,
+}
+
+/// A range of the program's memory: `file_size` bytes from the image
+/// followed by zeroes up to `mem_size` bytes
+#[derive(Clone, Copy)]
+pub struct Segment<'a> {
+    pub vaddr: usize,
+    pub mem_size: usize,
+    /// As stored: the file bytes, or an LZ4 block if `compressed`
     pub data: &'a [u8],
-    pub bss_size: usize,
+    pub file_size: usize,
+    pub compressed: bool,
+    /// SEGMENT_READ, SEGMENT_WRITE and SEGMENT_EXECUTE
+    pub flags: u32,
+}
+
+impl<'a> Segment<'a> {
+    /// A segment whose file bytes are stored as they are
+    fn stored(vaddr: usize, mem_size: usize, data: &'a [u8], flags: u32) -> Self {
+        Self {
+            vaddr,
+            mem_size,
+            data,
+            file_size: data.len(),
+            compressed: false,
+            flags,
+        }
+    }
+
+    /// Write the file bytes to `dest`, decompressing them if needed
+    ///
+    /// # Safety
+    ///
+    /// `dest` must be valid for writes of `file_size` bytes.
+    pub unsafe fn copy_to(&self, dest: *mut u8) -> Result<(), ExecError> {
+        let dest = core::slice::from_raw_parts_mut(dest, self.file_size);
+        if self.compressed {
+            lz4_block::decompress(self.data, dest).map_err(|_| ExecError::BadCompression)
+        } else {
+            dest.copy_from_slice(self.data);
+            Ok(())
+        }
+    }
+
+    /// First byte of the first page the segment touches
+    pub fn page_start(&self) -> usize {
+        pmm::align_down(self.vaddr)
+    }
+
+    /// End of the last page the segment touches
+    pub fn page_end(&self) -> usize {
+        pmm::align_up(self.vaddr + self.mem_size)
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.flags & SEGMENT_EXECUTE != 0
+    }
+
+    /// How its pages are mapped: read-only unless writable, no-execute
+    /// unless executable
+    pub fn page_flags(&self) -> PageFlags {
+        let mut flags = PageFlags::PRESENT | PageFlags::USER;
+        if self.flags & SEGMENT_WRITE != 0 {
+            flags |= PageFlags::WRITABLE;
+        }
+        if !self.is_executable() {
+            flags = flags.with_nx();
+        }
+        flags
+    }
+}
+
+/// The words a relocatable image needs the load bias added to, by their
+/// link-time addresses
+#[derive(Clone, Copy, Default)]
+pub struct Relocations<'a> {
+    table: &'a [u8],
+}
+
+impl<'a> Relocations<'a> {
+    pub fn len(&self) -> usize {
+        self.table.len() / RELOCATION_ENTRY_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + 'a {
+        self.table
+            .chunks_exact(RELOCATION_ENTRY_SIZE)
+            .map(|entry| u64::from_le_bytes(entry.try_into().unwrap()) as usize)
+    }
+}
+
+/// Function symbols of an image, by link-time address
+#[derive(Clone, Copy, Default)]
+pub struct Symbols<'a> {
+    table: &'a [u8],
+    names: &'a [u8],
+}
+
+impl<'a> Symbols<'a> {
+    pub fn len(&self) -> usize {
+        self.table.len() / size_of::<AtxfSymbol>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Address, size (0 if unknown) and name of each function, in address
+    /// order; names that are not UTF-8 come out empty
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, &'a str)> + 'a {
+        let names = self.names;
+        self.table.chunks_exact(size_of::<AtxfSymbol>()).map(move |entry| {
+            let symbol: AtxfSymbol = read_struct(entry, 0).unwrap();
+            let name = names
+                .get(symbol.name_offset as usize..)
+                .and_then(|name| name.split(|&byte| byte == 0).next())
+                .and_then(|name| core::str::from_utf8(name).ok())
+                .unwrap_or("");
+            (symbol.vaddr as usize, symbol.size as usize, name)
+        })
+    }
+}
+
+/// Where to put an image in the address space
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum LoadBase {
+    /// At the addresses it was linked at
+    Linked,
+    /// With its lowest page at this address; the image must be relocatable
+    At(usize),
+    /// At a random page between USER_EXEC_LOAD_BASE and RANDOM_BASE_LIMIT,
+    /// or where it was linked if it is not relocatable
+    Random,
+}
+
+/// What an image's signature says about it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signature {
+    None,
+    /// Signed, but the kernel was built without a key to check it with
+    Unchecked,
+    Verified,
+}
+
+pub struct ExecutableSections<'a> {
+    pub entry_point: usize,
+    pub segments: Vec<Segment<'a>>,
+    pub signature: Signature,
+    pub relocatable: bool,
+    pub relocations: Relocations<'a>,
+    pub symbols: Symbols<'a>,
+    /// How far `place` moved the image from its link-time addresses
+    pub load_bias: usize,
 }
 
 #[allow(dead_code)]
 pub struct LoadedExecutable {
     pub entry_point: usize,
-    pub text_base: usize,
-    pub data_base: usize,
-    pub bss_base: usize,
+    /// Lowest and highest address of the mapped segments
+    pub image_start: usize,
+    pub image_end: usize,
+    /// Added to every link-time address; zero unless the image was moved
+    pub load_bias: usize,
+}
+
+impl ExecutableSections<'_> {
+    /// First byte of the lowest page the segments touch
+    pub fn image_start(&self) -> usize {
+        self.segments.iter().map(Segment::page_start).min().unwrap_or(0)
+    }
+
+    /// End of the highest page the segments touch
+    pub fn image_end(&self) -> usize {
+        self.segments.iter().map(Segment::page_end).max().unwrap_or(0)
+    }
+
+    /// Move the segments and entry point to `base` and check that they
+    /// then lie in user memory; relocations are applied once the segments
+    /// are copied
+    pub fn place(&mut self, base: LoadBase) -> Result<(), ExecError> {
+        let start = self.image_start();
+        let target = match base {
+            LoadBase::Linked => start,
+            LoadBase::At(target) => target,
+            LoadBase::Random if self.relocatable => random_base(self.image_end() - start),
+            LoadBase::Random => start,
+        };
+        if target != start && !self.relocatable {
+            return Err(ExecError::NotRelocatable);
+        }
+        if target % pmm::PAGE_SIZE != 0 {
+            return Err(ExecError::MisalignedSection);
+        }
+
+        let bias = target.wrapping_sub(start);
+        for segment in &mut self.segments {
+            segment.vaddr = segment.vaddr.wrapping_add(bias);
+            match segment.vaddr.checked_add(segment.mem_size) {
+                Some(end) if segment.vaddr >= pmm::PAGE_SIZE && end <= USER_CANONICAL_MAX => {}
+                _ => return Err(ExecError::NonCanonicalLayout),
+            }
+        }
+        self.entry_point = self.entry_point.wrapping_add(bias);
+        self.load_bias = self.load_bias.wrapping_add(bias);
+        Ok(())
+    }
+
+    /// Add the load bias to every relocated word, through the physical
+    /// pages each segment was copied to: `(virt, phys, size)` per segment
+    pub fn apply_relocations(&self, mapped: &[(usize, usize, usize)]) -> Result<(), ExecError> {
+        if self.load_bias == 0 {
+            return Ok(());
+        }
+
+        for (index, link_address) in self.relocations.iter().enumerate() {
+            let address = link_address.wrapping_add(self.load_bias);
+            let &(virt, phys, _) = mapped
+                .iter()
+                .find(|&&(virt, _, size)| address >= virt && address + 8 <= virt + size)
+                .ok_or(ExecError::InvalidRelocation(index))?;
+
+            let word = (phys + (address - virt)) as *mut u64;
+            unsafe {
+                word.write_unaligned(word.read_unaligned().wrapping_add(self.load_bias as u64));
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep the image's symbols for `resolve_symbol`, replacing those of
+    /// any image loaded over the same range before
+    pub fn register_symbols(&self, page_table: usize) {
+        let page_table = page_table & PAGE_TABLE_MASK;
+        let start = self.image_start();
+        let end = self.image_end();
+
+        let mut tables = SYMBOL_TABLES.lock();
+        tables.retain(|table| {
+            table.page_table != page_table || table.end <= start || end <= table.start
+        });
+        if self.symbols.is_empty() {
+            return;
+        }
+
+        tables.push(SymbolTable {
+            page_table,
+            start,
+            end,
+            load_bias: self.load_bias,
+            functions: self
+                .symbols
+                .iter()
+                .map(|(vaddr, size, name)| (vaddr, size, String::from(name)))
+                .collect(),
+        });
+    }
+}
+
+/// Function names of a loaded image
+struct SymbolTable {
+    /// Physical address of the page table the image is mapped in
+    page_table: usize,
+    start: usize,
+    end: usize,
+    load_bias: usize,
+    /// Link-time address, size and name, sorted by address
+    functions: Vec<(usize, usize, String)>,
+}
+
+static SYMBOL_TABLES: Mutex<Vec<SymbolTable>> = Mutex::new(Vec::new());
+
+/// CR3 without the flags in its low bits
+const PAGE_TABLE_MASK: usize = !0xFFF;
+
+/// Call `report` with the name of the function around `address` in the
+/// image mapped there in `page_table` (CR3), and the offset into it
+///
+/// Returns whether a function was found. Safe to call from exception
+/// handlers: if the tables are locked, nothing is found.
+pub fn resolve_symbol(page_table: usize, address: usize, report: impl FnOnce(&str, usize)) -> bool {
+    let page_table = page_table & PAGE_TABLE_MASK;
+    let Some(tables) = SYMBOL_TABLES.try_lock() else {
+        return false;
+    };
+    let Some(table) = tables.iter().find(|table| {
+        table.page_table == page_table && address >= table.start && address < table.end
+    }) else {
+        return false;
+    };
+
+    let link_address = address.wrapping_sub(table.load_bias);
+    let after = table.functions.partition_point(|&(vaddr, _, _)| vaddr <= link_address);
+    let Some((vaddr, size, name)) = after.checked_sub(1).map(|index| &table.functions[index]) else {
+        return false;
+    };
+    let offset = link_address - vaddr;
+    if *size != 0 && offset >= *size {
+        return false;
+    }
+
+    report(name, offset);
+    true
+}
+
+/// Check `signature` over the signed bytes of an image, if the kernel has a
+/// key to check it with
+fn check_signature(
+    body: &[u8],
+    signature: &[u8; ed25519::SIGNATURE_SIZE],
+) -> Result<Signature, ExecError> {
+    let Some(key) = SIGNING_KEY.as_ref() else {
+        return Ok(Signature::Unchecked);
+    };
+    let message = [&body[..CHECKSUM_OFFSET], &[0; 4], &body[CHECKSUM_OFFSET + 4..]];
+    if ed25519::verify(key, &message, signature) {
+        Ok(Signature::Verified)
+    } else {
+        Err(ExecError::BadSignature)
+    }
+}
+
+/// Refuse an image that is not signed with SIGNING_KEY if the kernel was
+/// booted in secure-boot mode
+pub fn check_signature_policy(sections: &ExecutableSections) -> Result<(), ExecError> {
+    let secure = crate::system::info().command_line().has_flag(SECURE_BOOT_FLAG);
+    if !secure || sections.signature == Signature::Verified {
+        return Ok(());
+    }
+
+    if SIGNING_KEY.is_none() {
+        log_error!(LOG_ORIGIN, "Secure boot is on, but the kernel has no signing key");
+    } else {
+        log_warn!(LOG_ORIGIN, "Secure boot: refusing an image that is not signed");
+    }
+    Err(ExecError::Unsigned)
+}
+
+/// CRC-32 (IEEE) of a version 2 image, with the checksum field as zero
+fn checksum(image: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = crc32_table();
+
+    let mut crc = !0u32;
+    for (i, &byte) in image.iter().enumerate() {
+        let byte = if (CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4).contains(&i) { 0 } else { byte };
+        crc = TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// A page-aligned base that fits `span` bytes below RANDOM_BASE_LIMIT
+fn random_base(span: usize) -> usize {
+    let window = RANDOM_BASE_LIMIT - USER_EXEC_LOAD_BASE;
+    let pages = (window.saturating_sub(span) / pmm::PAGE_SIZE) as u64 + 1;
+    USER_EXEC_LOAD_BASE + (mix(arch::read_tsc()) % pages) as usize * pmm::PAGE_SIZE
+}
+
+/// splitmix64's finalizer, so that close counter readings give unrelated
+/// results
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
 }
 
 #[allow(dead_code)]
 pub fn log_format_overview() {
     log_info!(
         LOG_ORIGIN,
-        "Executable format active: magic=0x{:X}, version={}, sections=.text/.data/.bss",
+        "Executable format active: magic=0x{:X}, versions {} (segments) and {} (.text/.data/.bss)",
         ATXF_MAGIC,
-        ATXF_VERSION
+        ATXF_VERSION,
+        ATXF_VERSION_V1
     );
     log_info!(
         LOG_ORIGIN,
-        "Load base: 0x{:X}, page size: {} bytes, entry offset relative to base",
+        "Version 1 load base: 0x{:X}, page size: {} bytes, up to {} segments",
         USER_EXEC_LOAD_BASE,
-        pmm::PAGE_SIZE
+        pmm::PAGE_SIZE,
+        MAX_SEGMENTS
     );
 }
 
@@ -144,12 +641,34 @@ pub fn summarize_boot_payload(payload: &ExecutableImage) {
         Ok(sections) => {
             log_info!(
                 LOG_ORIGIN,
-                "Payload validated: text={} bytes, data={} bytes, bss={} bytes, entry offset=0x{:X}",
-                sections.text.len(),
-                sections.data.len(),
-                sections.bss_size,
-                sections.entry_offset
+                "Payload validated: {} segments, entry=0x{:X}",
+                sections.segments.len(),
+                sections.entry_point
             );
+            for segment in &sections.segments {
+                log_info!(
+                    LOG_ORIGIN,
+                    "  0x{:X}: file={} bytes ({} stored), mem={} bytes, flags={}",
+                    segment.vaddr,
+                    segment.file_size,
+                    segment.data.len(),
+                    segment.mem_size,
+                    flags_str(segment.flags)
+                );
+            }
+            if sections.relocatable {
+                log_info!(
+                    LOG_ORIGIN,
+                    "  relocatable, {} relocations",
+                    sections.relocations.len()
+                );
+            }
+            if !sections.symbols.is_empty() {
+                log_info!(LOG_ORIGIN, "  {} symbols", sections.symbols.len());
+            }
+            if sections.signature != Signature::None {
+                log_info!(LOG_ORIGIN, "  signature: {:?}", sections.signature);
+            }
         }
         Err(err) => {
             log_error!(LOG_ORIGIN, "Payload validation failed: {:?}", err);
@@ -157,6 +676,11 @@ pub fn summarize_boot_payload(payload: &ExecutableImage) {
     }
 }
 
+fn flags_str(flags: u32) -> &'static str {
+    const NAMES: [&str; 8] = ["---", "r--", "-w-", "rw-", "--x", "r-x", "-wx", "rwx"];
+    NAMES[(flags & 7) as usize]
+}
+
 pub fn parse_boot_image(payload: &ExecutableImage) -> Result<ExecutableSections<'_>, ExecError> {
     if !payload.is_present() {
         return Err(ExecError::MissingImage);
@@ -166,39 +690,228 @@ pub fn parse_boot_image(payload: &ExecutableImage) -> Result<ExecutableSections<
     parse_image(bytes)
 }
 
+/// Copy a header or table entry out of the image, if it fits
+fn read_struct<T: Copy>(image: &[u8], offset: usize) -> Option<T> {
+    let end = offset.checked_add(size_of::<T>())?;
+    if end > image.len() {
+        return None;
+    }
+    Some(unsafe { ptr::read_unaligned(image.as_ptr().add(offset) as *const T) })
+}
+
 pub fn parse_image<'a>(image: &'a [u8]) -> Result<ExecutableSections<'a>, ExecError> {
-    if image.len() < size_of::<AtxfHeader>() {
+    // magic and version lead every header
+    let magic: u32 = read_struct(image, 0).ok_or(ExecError::Truncated)?;
+    let version: u16 = read_struct(image, 4).ok_or(ExecError::Truncated)?;
+
+    if magic != ATXF_MAGIC {
+        return Err(ExecError::InvalidMagic);
+    }
+
+    match version {
+        ATXF_VERSION => parse_image_v2(image),
+        ATXF_VERSION_V1 => parse_image_v1(image),
+        other => Err(ExecError::UnsupportedVersion(other)),
+    }
+}
+
+fn parse_image_v2(image: &[u8]) -> Result<ExecutableSections<'_>, ExecError> {
+    let raw: AtxfHeaderV2 = read_struct(image, 0).ok_or(ExecError::Truncated)?;
+
+    let header_size = raw.header_size as usize;
+    if header_size < ATXF_V2_HEADER_SIZE_NO_RELOCATIONS || header_size > image.len() {
         return Err(ExecError::Truncated);
     }
+    let relocatable = raw.flags & ATXF_FLAG_RELOCATABLE != 0;
 
-    let mut raw = AtxfHeader {
-        magic: 0,
-        version: 0,
-        header_size: 0,
-        entry_offset: 0,
-        text_offset: 0,
-        text_size: 0,
-        data_offset: 0,
-        data_size: 0,
-        bss_size: 0,
+    if raw.flags & ATXF_FLAG_CHECKSUM != 0 {
+        let computed = checksum(image);
+        if computed != raw.checksum {
+            return Err(ExecError::BadChecksum(raw.checksum, computed));
+        }
+    }
+
+    // Nothing may point into the signature, so the rest is parsed without it
+    let (image, signature) = if raw.flags & ATXF_FLAG_SIGNED != 0 {
+        let split = image
+            .len()
+            .checked_sub(ed25519::SIGNATURE_SIZE)
+            .filter(|&split| split >= header_size)
+            .ok_or(ExecError::Truncated)?;
+        let (body, signature) = image.split_at(split);
+        (body, check_signature(body, signature.try_into().unwrap())?)
+    } else {
+        (image, Signature::None)
     };
 
-    unsafe {
-        let header_bytes = core::slice::from_raw_parts_mut(
-            &mut raw as *mut AtxfHeader as *mut u8,
-            size_of::<AtxfHeader>(),
-        );
-        header_bytes.copy_from_slice(&image[..size_of::<AtxfHeader>()]);
+    let count = raw.segment_count as usize;
+    if count == 0 || count > MAX_SEGMENTS {
+        return Err(ExecError::TooManySegments(count));
     }
 
-    if raw.magic != ATXF_MAGIC {
-        return Err(ExecError::InvalidMagic);
+    let entry_size = raw.segment_entry_size as usize;
+    if entry_size < size_of::<AtxfSegment>() {
+        return Err(ExecError::Truncated);
     }
 
-    if raw.version != ATXF_VERSION {
-        return Err(ExecError::UnsupportedVersion(raw.version));
+    let table_start = raw.segment_offset as usize;
+    let table_end = table_start + count * entry_size;
+    if table_start < header_size {
+        return Err(ExecError::OverlappingSection);
+    }
+    if table_end > image.len() {
+        return Err(ExecError::Truncated);
     }
 
+    let mut segments: Vec<Segment> = Vec::with_capacity(count);
+    for index in 0..count {
+        let entry: AtxfSegment = read_struct(image, table_start + index * entry_size)
+            .ok_or(ExecError::Truncated)?;
+
+        let offset = entry.offset as usize;
+        let file_size = entry.file_size as usize;
+        let mem_size = entry.mem_size as usize;
+        let vaddr = entry.vaddr as usize;
+        let compressed = entry.stored_size != 0;
+        let stored_size = if compressed { entry.stored_size as usize } else { file_size };
+
+        if mem_size == 0 || file_size > mem_size || entry.flags & !7 != 0 {
+            return Err(ExecError::InvalidSegment(index));
+        }
+        if entry.flags & SEGMENT_WRITE != 0 && entry.flags & SEGMENT_EXECUTE != 0 {
+            return Err(ExecError::WritableExecutable(index));
+        }
+        if stored_size > 0 && offset < table_end {
+            return Err(ExecError::OverlappingSection);
+        }
+        if offset + stored_size > image.len() {
+            return Err(ExecError::Truncated);
+        }
+        // A relocatable image is checked again where it is placed
+        let lowest = if relocatable { 0 } else { pmm::PAGE_SIZE };
+        match vaddr.checked_add(mem_size) {
+            Some(end) if vaddr >= lowest && end <= USER_CANONICAL_MAX => {}
+            _ => return Err(ExecError::NonCanonicalLayout),
+        }
+
+        let segment = Segment {
+            vaddr,
+            mem_size,
+            data: &image[offset..offset + stored_size],
+            file_size,
+            compressed,
+            flags: entry.flags,
+        };
+
+        // Pages are mapped with one set of flags, so they are not shared
+        let shares_page = segments.iter().any(|other| {
+            segment.page_start() < other.page_end() && other.page_start() < segment.page_end()
+        });
+        if shares_page {
+            return Err(ExecError::OverlappingSection);
+        }
+
+        segments.push(segment);
+    }
+
+    let entry_point = raw.entry as usize;
+    let in_code = segments.iter().any(|segment| {
+        segment.is_executable()
+            && entry_point >= segment.vaddr
+            && entry_point < segment.vaddr + segment.file_size
+    });
+    if !in_code {
+        return Err(ExecError::EntryOutOfBounds);
+    }
+
+    let relocations = parse_relocations(image, &raw, &segments)?;
+    if !relocatable && !relocations.is_empty() {
+        return Err(ExecError::InvalidRelocation(0));
+    }
+
+    Ok(ExecutableSections {
+        entry_point,
+        segments,
+        signature,
+        relocatable,
+        relocations,
+        symbols: parse_symbols(image, &raw)?,
+        load_bias: 0,
+    })
+}
+
+/// The symbol table and names, each entry checked to be in order and to
+/// name a string
+fn parse_symbols<'a>(image: &'a [u8], raw: &AtxfHeaderV2) -> Result<Symbols<'a>, ExecError> {
+    if (raw.header_size as usize) < size_of::<AtxfHeaderV2>() {
+        return Ok(Symbols::default());
+    }
+
+    let slice = |offset: u32, size: usize| {
+        let start = offset as usize;
+        start
+            .checked_add(size)
+            .and_then(|end| image.get(start..end))
+            .ok_or(ExecError::Truncated)
+    };
+    let table_size = (raw.symbol_count as usize)
+        .checked_mul(size_of::<AtxfSymbol>())
+        .ok_or(ExecError::Truncated)?;
+    let symbols = Symbols {
+        table: slice(raw.symbol_offset, table_size)?,
+        names: slice(raw.names_offset, raw.names_size as usize)?,
+    };
+
+    let mut previous = 0;
+    for (index, entry) in symbols.table.chunks_exact(size_of::<AtxfSymbol>()).enumerate() {
+        let symbol: AtxfSymbol = read_struct(entry, 0).ok_or(ExecError::Truncated)?;
+        let vaddr = symbol.vaddr as usize;
+        if vaddr < previous || symbol.name_offset as usize >= symbols.names.len() {
+            return Err(ExecError::InvalidSymbol(index));
+        }
+        previous = vaddr;
+    }
+
+    Ok(symbols)
+}
+
+/// The relocation table, each entry checked to name a word of file data
+fn parse_relocations<'a>(
+    image: &'a [u8],
+    raw: &AtxfHeaderV2,
+    segments: &[Segment],
+) -> Result<Relocations<'a>, ExecError> {
+    if (raw.header_size as usize) < ATXF_V2_HEADER_SIZE_NO_SYMBOLS {
+        return Ok(Relocations::default());
+    }
+
+    let start = raw.relocation_offset as usize;
+    let end = (raw.relocation_count as usize)
+        .checked_mul(RELOCATION_ENTRY_SIZE)
+        .and_then(|size| start.checked_add(size))
+        .ok_or(ExecError::Truncated)?;
+    let relocations = Relocations {
+        table: image.get(start..end).ok_or(ExecError::Truncated)?,
+    };
+
+    for (index, address) in relocations.iter().enumerate() {
+        let in_data = segments.iter().any(|segment| {
+            address >= segment.vaddr
+                && address.checked_add(8).is_some_and(|end| {
+                    end <= segment.vaddr + segment.file_size
+                })
+        });
+        if !in_data {
+            return Err(ExecError::InvalidRelocation(index));
+        }
+    }
+
+    Ok(relocations)
+}
+
+fn parse_image_v1(image: &[u8]) -> Result<ExecutableSections<'_>, ExecError> {
+    let raw: AtxfHeader = read_struct(image, 0).ok_or(ExecError::Truncated)?;
+
     let header_size = raw.header_size as usize;
     if header_size < size_of::<AtxfHeader>() {
         return Err(ExecError::Truncated);
@@ -238,11 +951,30 @@ pub fn parse_image<'a>(image: &'a [u8]) -> Result<ExecutableSections<'a>, ExecEr
     let text = &image[raw.text_offset as usize..raw.text_offset as usize + raw.text_size as usize];
     let data = &image[raw.data_offset as usize..raw.data_offset as usize + raw.data_size as usize];
 
+    // The fixed layout: .text at the load base, then .data, then .bss,
+    // each starting on a fresh page
+    let text_base = USER_EXEC_LOAD_BASE;
+    let data_base = pmm::align_up(text_base + text.len());
+    let bss_base = pmm::align_up(data_base + data.len());
+
+    let mut segments = Vec::with_capacity(3);
+    segments.push(Segment::stored(text_base, text.len(), text, SEGMENT_READ | SEGMENT_EXECUTE));
+    if !data.is_empty() {
+        segments.push(Segment::stored(data_base, data.len(), data, SEGMENT_READ | SEGMENT_WRITE));
+    }
+    if raw.bss_size > 0 {
+        let bss_size = raw.bss_size as usize;
+        segments.push(Segment::stored(bss_base, bss_size, &[], SEGMENT_READ | SEGMENT_WRITE));
+    }
+
     Ok(ExecutableSections {
-        entry_offset: raw.entry_offset as usize,
-        text,
-        data,
-        bss_size: raw.bss_size as usize,
+        entry_point: text_base + raw.entry_offset as usize,
+        segments,
+        signature: Signature::None,
+        relocatable: false,
+        relocations: Relocations::default(),
+        symbols: Symbols::default(),
+        load_bias: 0,
     })
 }
 
@@ -260,7 +992,7 @@ fn build_embedded_image() -> [u8; EMBEDDED_IMAGE_SIZE] {
 
     let header = AtxfHeader {
         magic: ATXF_MAGIC,
-        version: ATXF_VERSION,
+        version: ATXF_VERSION_V1,
         header_size: size_of::<AtxfHeader>() as u16,
         entry_offset: 0,
         text_offset: EMBEDDED_TEXT_OFFSET as u32,
@@ -285,7 +1017,21 @@ pub fn load_into_address_space(
     address_space: AddressSpaceId,
     owner: ThreadId,
 ) -> Result<LoadedExecutable, ExecError> {
-    let sections = parse_image(image)?;
+    load_into_address_space_at(image, address_space, owner, LoadBase::Random)
+}
+
+/// Load `image` at `base`, e.g. to give several programs in one address
+/// space their own ranges
+#[allow(dead_code)]
+pub fn load_into_address_space_at(
+    image: &[u8],
+    address_space: AddressSpaceId,
+    owner: ThreadId,
+    base: LoadBase,
+) -> Result<LoadedExecutable, ExecError> {
+    let mut sections = parse_image(image)?;
+    check_signature_policy(&sections)?;
+    sections.place(base)?;
     do_load(sections, address_space, owner)
 }
 
@@ -295,7 +1041,9 @@ pub fn load_boot_payload(
     address_space: AddressSpaceId,
     owner: ThreadId,
 ) -> Result<LoadedExecutable, ExecError> {
-    let sections = parse_boot_image(payload)?;
+    let mut sections = parse_boot_image(payload)?;
+    check_signature_policy(&sections)?;
+    sections.place(LoadBase::Random)?;
     do_load(sections, address_space, owner)
 }
 
@@ -304,131 +1052,78 @@ fn do_load(
     address_space: AddressSpaceId,
     owner: ThreadId,
 ) -> Result<LoadedExecutable, ExecError> {
-    let text_base = USER_EXEC_LOAD_BASE;
-    let text_size = pmm::align_up(sections.text.len());
-    let data_base = pmm::align_up(text_base + text_size);
-    let data_size = pmm::align_up(sections.data.len());
-    let bss_base = pmm::align_up(data_base + data_size);
-    let bss_size = pmm::align_up(sections.bss_size);
-
-    let highest_virt = bss_base.saturating_add(bss_size);
-    if highest_virt > USER_CANONICAL_MAX {
-        log_error!(
-            LOG_ORIGIN,
-            "Executable layout exceeds canonical user range: top=0x{:X} limit=0x{:X}",
-            highest_virt,
-            USER_CANONICAL_MAX
-        );
-        return Err(ExecError::NonCanonicalLayout);
-    }
-
     let mut rollback = RollbackGuard::new(address_space, owner);
 
-    if let Some(mapping) = map_segment(
-        address_space,
-        owner,
-        text_base,
-        sections.text,
-        PageFlags::PRESENT | PageFlags::USER,
-    )? {
+    for segment in &sections.segments {
+        let mapping = map_segment(address_space, owner, segment)?;
         rollback.track(mapping);
-    }
 
-    if let Some(mapping) = map_segment(
-        address_space,
-        owner,
-        data_base,
-        sections.data,
-        PageFlags::PRESENT | PageFlags::USER | PageFlags::WRITABLE,
-    )? {
-        rollback.track(mapping);
+        log_info!(
+            LOG_ORIGIN,
+            "Segment mapped: 0x{:X}-0x{:X} {}",
+            segment.page_start(),
+            segment.page_end(),
+            flags_str(segment.flags)
+        );
     }
 
-    if sections.bss_size > 0 {
-        if let Some(mapping) = map_zeroed_segment(
-            address_space,
-            owner,
-            bss_base,
-            bss_size,
-            PageFlags::PRESENT | PageFlags::USER | PageFlags::WRITABLE,
-        )? {
-            rollback.track(mapping);
-        }
+    sections.apply_relocations(&rollback.mapped)?;
+    if let Some(page_table) = addrspace::pml4_of(address_space) {
+        sections.register_symbols(page_table);
     }
 
-    let entry_point = USER_EXEC_LOAD_BASE + sections.entry_offset;
+    let image_start = sections.image_start();
+    let image_end = sections.image_end();
 
     log_info!(
         LOG_ORIGIN,
-        "Executable loaded: text=0x{:X}-0x{:X}, data=0x{:X}-0x{:X}, bss=0x{:X}-0x{:X}",
-        text_base,
-        text_base + text_size,
-        data_base,
-        data_base + data_size,
-        bss_base,
-        bss_base + bss_size
+        "Executable loaded: {} segments at 0x{:X}-0x{:X}, {} relocations applied",
+        sections.segments.len(),
+        image_start,
+        image_end,
+        if sections.load_bias == 0 { 0 } else { sections.relocations.len() }
     );
 
-    log_info!(LOG_ORIGIN, "Entry point set to 0x{:X}", entry_point);
+    log_info!(LOG_ORIGIN, "Entry point set to 0x{:X}", sections.entry_point);
 
     rollback.disarm();
 
     Ok(LoadedExecutable {
-        entry_point,
-        text_base,
-        data_base,
-        bss_base,
+        entry_point: sections.entry_point,
+        image_start,
+        image_end,
+        load_bias: sections.load_bias,
     })
 }
 
+/// Back the segment's pages with zeroed memory, copy its data in and map
+/// them with the segment's flags
 fn map_segment(
     address_space: AddressSpaceId,
     owner: ThreadId,
-    virt_start: usize,
-    data: &[u8],
-    flags: PageFlags,
-) -> Result<Option<(usize, usize, usize)>, ExecError> {
-    if data.is_empty() {
-        return Ok(None);
-    }
-
-    let size = pmm::align_up(data.len());
+    segment: &Segment,
+) -> Result<(usize, usize, usize), ExecError> {
+    let virt_start = segment.page_start();
+    let size = segment.page_end() - virt_start;
     let pages = size / pmm::PAGE_SIZE;
     let phys_base = pmm::alloc_pages_zeroed(pages).ok_or(ExecError::OutOfMemory)?;
 
-    unsafe {
-        ptr::copy_nonoverlapping(data.as_ptr(), phys_base as *mut u8, data.len());
+    let copied = unsafe { segment.copy_to((phys_base + (segment.vaddr - virt_start)) as *mut u8) };
+    if let Err(err) = copied {
+        pmm::free_pages(phys_base, pages);
+        return Err(err);
     }
 
-    match addrspace::map_region(address_space, owner, virt_start, phys_base, size, flags) {
-        Ok(()) => {
-            Ok(Some((virt_start, phys_base, size)))
-        }
-        Err(err) => {
-            pmm::free_pages(phys_base, pages);
-            Err(ExecError::AddressSpace(err))
-        }
-    }
-}
-
-fn map_zeroed_segment(
-    address_space: AddressSpaceId,
-    owner: ThreadId,
-    virt_start: usize,
-    size: usize,
-    flags: PageFlags,
-) -> Result<Option<(usize, usize, usize)>, ExecError> {
-    if size == 0 {
-        return Ok(None);
-    }
-
-    let aligned_size = pmm::align_up(size);
-    let pages = aligned_size / pmm::PAGE_SIZE;
-    let phys_base = pmm::alloc_pages_zeroed(pages).ok_or(ExecError::OutOfMemory)?;
-
-    match addrspace::map_region(address_space, owner, virt_start, phys_base, aligned_size, flags) {
+    match addrspace::map_region(
+        address_space,
+        owner,
+        virt_start,
+        phys_base,
+        size,
+        segment.page_flags(),
+    ) {
         Ok(()) => {
-            Ok(Some((virt_start, phys_base, aligned_size)))
+            Ok((virt_start, phys_base, size))
         }
         Err(err) => {
             pmm::free_pages(phys_base, pages);
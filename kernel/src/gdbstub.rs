@@ -0,0 +1,847 @@
+// GDB Remote Stub
+//
+// Lets GDB debug the running kernel and the threads on it over COM1,
+// speaking the GDB remote serial protocol from inside the exception path.
+// Enabled with the `gdb` boot parameter (`./build.sh --gdb`), in which case
+// the kernel stops right after the interrupt system is up and waits for
+// GDB to attach:
+//
+//     (gdb) target remote localhost:1234
+//
+// Key responsibilities:
+// - Take over #BP and #DB, and fatal exceptions once they are logged, and
+//   report them to GDB as stop signals
+// - Read and write the stopped thread's registers through the saved
+//   `InterruptFrame`, which the exception stub restores on return
+// - Read and write memory in the current address space, refusing pages
+//   that are not mapped instead of faulting; user pages are reached
+//   through the SMAP window
+// - Insert and remove software breakpoints (`Z0`/`z0`), patching `int3`
+//   over read-only kernel text with CR0.WP cleared
+// - Single-step with RFLAGS.TF
+// - List kernel `Thread`s as GDB threads, with their names and states,
+//   and show the registers other threads saved when they were switched out
+//
+// Design principles:
+// - Everything runs with interrupts disabled inside the exception handler;
+//   the stub owns COM1 until GDB resumes the CPU
+// - Locks the interrupted code may hold are only ever tried, and nothing is
+//   allocated, so stopping anywhere cannot deadlock the stub
+// - Requests the stub does not know get the empty reply, which GDB takes
+//   as "unsupported" and works around
+//
+// Protocol notes:
+// - Packets are `$data#checksum`, each acknowledged with `+` or `-`
+// - The `g` layout is GDB's x86-64 one without a target description:
+//   RAX, RBX, RCX, RDX, RSI, RDI, RBP, RSP, R8-R15 and RIP as 64-bit
+//   values, then EFLAGS, CS, SS, DS, ES, FS and GS as 32-bit ones
+// - Stop replies carry `swbreak` when the stub's own breakpoint was hit,
+//   RIP having been moved back onto it, as GDB asked for with `swbreak+`
+// - Thread IDs are kernel thread IDs, in hex
+//
+// Limitations:
+// - Memory is that of the address space active when the CPU stopped, not
+//   of the thread selected with `Hg`
+// - Registers of threads other than the stopped one are read-only
+// - COM1 is not interrupt driven, so Ctrl-C cannot stop a running kernel;
+//   use breakpoints. Serial log output is muted while the stub is enabled
+//   (entries still reach the log ring), but direct `serial_println!` text
+//   still goes out and GDB skips over it
+// - No hardware breakpoints or watchpoints
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use crate::arch::read_cr3;
+use crate::interrupts::handlers::InterruptFrame;
+use crate::log::{self, LogLevel};
+use crate::mm::usercopy::UserAccess;
+use crate::serial::{SerialPort, COM1};
+use crate::thread::{CpuContext, ThreadId, ThreadState};
+use crate::{log_info, sched, thread};
+
+const LOG_ORIGIN: &str = "gdb";
+
+/// Boot parameter that enables the stub
+const GDB_FLAG: &str = "gdb";
+
+/// Largest packet either side sends; advertised to GDB
+const PACKET_SIZE: usize = 4096;
+const MAX_BREAKPOINTS: usize = 32;
+
+const INT3: u8 = 0xCC;
+const TRAP_FLAG: u64 = 1 << 8;
+const CR0_WP: u64 = 1 << 16;
+const PAGE_ADDR_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// Registers in a `g` packet; the first `WIDE_REGISTERS` are 64-bit
+const REGISTER_COUNT: usize = 24;
+const WIDE_REGISTERS: usize = 17;
+const RIP: usize = 16;
+const EFLAGS: usize = 17;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGBUS: u8 = 7;
+const SIGFPE: u8 = 8;
+const SIGSEGV: u8 = 11;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STUB: Mutex<Stub> = Mutex::new(Stub::new());
+
+/// Stop for GDB now if the kernel was booted with `gdb`
+///
+/// Call once the IDT is loaded. Serial logging is muted from here on so
+/// it does not run into the packets.
+pub fn init() {
+    if !crate::system::info().command_line().has_flag(GDB_FLAG) {
+        return;
+    }
+
+    log_info!(LOG_ORIGIN, "GDB stub enabled; waiting for GDB on COM1");
+    log::set_serial_level(LogLevel::Panic);
+    ENABLED.store(true, Ordering::Release);
+
+    unsafe {
+        core::arch::asm!("int3", options(nomem, nostack));
+    }
+
+    log_info!(LOG_ORIGIN, "GDB attached, boot continues");
+}
+
+/// Report an exception to GDB and serve it until it resumes the CPU
+///
+/// Returns false, changing nothing, when the stub is not enabled or is
+/// already in use; the caller then handles the exception itself.
+pub fn handle_exception(frame: &mut InterruptFrame) -> bool {
+    if !ENABLED.load(Ordering::Acquire) {
+        return false;
+    }
+    let Some(mut guard) = STUB.try_lock() else {
+        return false;
+    };
+    let Stub { breakpoints, packet, reply } = &mut *guard;
+
+    // Vector 3 is a trap gate, so interrupts may still be on
+    unsafe {
+        core::arch::asm!("cli", options(nomem, nostack));
+    }
+
+    frame.rflags &= !TRAP_FLAG;
+    let own_breakpoint =
+        frame.exception_number == 3 && breakpoints.find(frame.rip.wrapping_sub(1)).is_some();
+    if own_breakpoint {
+        frame.rip -= 1;
+    }
+
+    let signal = signal_for(frame.exception_number);
+    let mut session = Session {
+        breakpoints,
+        frame,
+        stopped: sched::try_current_thread(),
+        selected: None,
+        signal,
+        own_breakpoint,
+    };
+    session.serve(packet, reply);
+    true
+}
+
+/// The Unix signal GDB shows for an exception vector
+fn signal_for(vector: u64) -> u8 {
+    match vector {
+        1 | 3 => SIGTRAP,
+        0 | 16 | 19 => SIGFPE,
+        2 => SIGINT,
+        6 => SIGILL,
+        18 => SIGBUS,
+        _ => SIGSEGV,
+    }
+}
+
+// ============================================================================
+// Breakpoints
+// ============================================================================
+
+#[derive(Clone, Copy)]
+struct Breakpoint {
+    address: u64,
+    /// The byte `int3` replaced
+    original: u8,
+}
+
+/// Everything the stub keeps between stops. The packet buffers live here
+/// rather than on the interrupted thread's kernel stack, which is small.
+struct Stub {
+    breakpoints: Breakpoints,
+    packet: [u8; PACKET_SIZE],
+    reply: Reply,
+}
+
+impl Stub {
+    const fn new() -> Self {
+        Self {
+            breakpoints: Breakpoints([None; MAX_BREAKPOINTS]),
+            packet: [0; PACKET_SIZE],
+            reply: Reply::new(),
+        }
+    }
+}
+
+struct Breakpoints([Option<Breakpoint>; MAX_BREAKPOINTS]);
+
+impl Breakpoints {
+    fn find(&self, address: u64) -> Option<usize> {
+        self.0.iter().position(|bp| bp.is_some_and(|bp| bp.address == address))
+    }
+
+    fn insert(&mut self, address: u64) -> bool {
+        if self.find(address).is_some() {
+            return true;
+        }
+        let Some(slot) = self.0.iter().position(Option::is_none) else {
+            return false;
+        };
+        let mut original = [0u8];
+        if !read_memory(address, &mut original) || !write_memory(address, &[INT3]) {
+            return false;
+        }
+        self.0[slot] = Some(Breakpoint { address, original: original[0] });
+        true
+    }
+
+    fn remove(&mut self, address: u64) -> bool {
+        let Some(slot) = self.find(address) else {
+            return false;
+        };
+        if let Some(bp) = self.0[slot].take() {
+            write_memory(bp.address, &[bp.original]);
+        }
+        true
+    }
+
+    fn remove_all(&mut self) {
+        for slot in self.0.iter_mut() {
+            if let Some(bp) = slot.take() {
+                write_memory(bp.address, &[bp.original]);
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Session
+// ============================================================================
+
+/// What to do after a request
+enum Next {
+    Reply,
+    /// Return to the stopped code; the reply comes at the next stop
+    Resume,
+}
+
+/// One stop, from reporting it to GDB until GDB resumes the CPU
+struct Session<'a> {
+    breakpoints: &'a mut Breakpoints,
+    frame: &'a mut InterruptFrame,
+    /// Thread the CPU stopped in, if the scheduler could say
+    stopped: Option<ThreadId>,
+    /// Thread `g`, `G`, `p` and `P` apply to (`Hg`); None for the stopped one
+    selected: Option<ThreadId>,
+    signal: u8,
+    own_breakpoint: bool,
+}
+
+impl Session<'_> {
+    fn serve(&mut self, packet: &mut [u8; PACKET_SIZE], reply: &mut Reply) {
+        reply.clear();
+        self.stop_reply(reply);
+        send_packet(reply.as_bytes());
+
+        loop {
+            let len = recv_packet(packet);
+            reply.clear();
+            match self.handle(&packet[..len], reply) {
+                Next::Reply => send_packet(reply.as_bytes()),
+                Next::Resume => return,
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &[u8], reply: &mut Reply) -> Next {
+        let Some((&command, args)) = packet.split_first() else {
+            return Next::Reply;
+        };
+
+        match command {
+            b'?' => self.stop_reply(reply),
+            b'g' => self.read_registers(reply),
+            b'G' => self.write_registers(args, reply),
+            b'p' => self.read_register(args, reply),
+            b'P' => self.write_register(args, reply),
+            b'm' => read_memory_packet(args, reply),
+            b'M' => write_memory_packet(args, reply),
+            b'c' | b's' => {
+                if let Some(address) = parse_hex(args) {
+                    self.frame.rip = address;
+                }
+                if command == b's' {
+                    self.frame.rflags |= TRAP_FLAG;
+                }
+                return Next::Resume;
+            }
+            b'D' => {
+                self.breakpoints.remove_all();
+                send_packet(b"OK");
+                return Next::Resume;
+            }
+            b'k' => {
+                self.breakpoints.remove_all();
+                return Next::Resume;
+            }
+            b'H' => self.select_thread(args, reply),
+            b'T' => {
+                let alive = parse_thread(args).is_some_and(|tid| thread_exists(Some(tid)));
+                reply.push_str(if alive { "OK" } else { "E01" });
+            }
+            b'Z' | b'z' => self.breakpoint(command == b'Z', args, reply),
+            b'q' => self.query(args, reply),
+            _ => {}
+        }
+        Next::Reply
+    }
+
+    /// `T` stop reply naming the signal, the thread and a breakpoint hit
+    fn stop_reply(&self, reply: &mut Reply) {
+        reply.push_str("T");
+        reply.push_hex_u8(self.signal);
+        if let Some(tid) = self.stopped {
+            reply.push_fmt(format_args!("thread:{:x};", tid.raw()));
+        }
+        if self.own_breakpoint {
+            reply.push_str("swbreak:;");
+        }
+    }
+
+    /// Registers of the selected thread: the live frame for the stopped
+    /// one, the saved context for the others
+    fn registers(&self) -> Option<[u64; REGISTER_COUNT]> {
+        match self.selected {
+            None => Some(frame_registers(self.frame)),
+            Some(tid) if Some(tid) == self.stopped => Some(frame_registers(self.frame)),
+            Some(tid) => thread::try_with_threads(|threads| {
+                threads.iter().find(|t| t.id == tid).map(|t| context_registers(&t.context))
+            })
+            .flatten(),
+        }
+    }
+
+    fn writes_frame(&self) -> bool {
+        self.selected.is_none() || self.selected == self.stopped
+    }
+
+    fn read_registers(&self, reply: &mut Reply) {
+        let Some(registers) = self.registers() else {
+            reply.push_str("E01");
+            return;
+        };
+        for (n, value) in registers.iter().enumerate() {
+            reply.push_le(*value, register_size(n));
+        }
+    }
+
+    fn write_registers(&mut self, args: &[u8], reply: &mut Reply) {
+        if !self.writes_frame() {
+            reply.push_str("E01");
+            return;
+        }
+
+        let mut rest = args;
+        for n in 0..REGISTER_COUNT {
+            let size = register_size(n) * 2;
+            if rest.len() < size {
+                break;
+            }
+            let Some(value) = parse_le(&rest[..size]) else {
+                reply.push_str("E02");
+                return;
+            };
+            set_frame_register(self.frame, n, value);
+            rest = &rest[size..];
+        }
+        reply.push_str("OK");
+    }
+
+    fn read_register(&self, args: &[u8], reply: &mut Reply) {
+        let n = parse_hex(args).map(|n| n as usize).filter(|&n| n < REGISTER_COUNT);
+        match (n, self.registers()) {
+            (Some(n), Some(registers)) => reply.push_le(registers[n], register_size(n)),
+            _ => reply.push_str("E01"),
+        }
+    }
+
+    fn write_register(&mut self, args: &[u8], reply: &mut Reply) {
+        let parsed = split_at_byte(args, b'=').and_then(|(n, value)| {
+            let n = parse_hex(n)? as usize;
+            (n < REGISTER_COUNT).then_some(())?;
+            Some((n, parse_le(value)?))
+        });
+        match parsed {
+            Some((n, value)) if self.writes_frame() => {
+                set_frame_register(self.frame, n, value);
+                reply.push_str("OK");
+            }
+            _ => reply.push_str("E01"),
+        }
+    }
+
+    /// `Hg<tid>` picks the thread registers are read from; `Hc` is
+    /// accepted, but resuming always resumes the whole kernel
+    fn select_thread(&mut self, args: &[u8], reply: &mut Reply) {
+        let Some((&op, tid)) = args.split_first() else {
+            reply.push_str("E01");
+            return;
+        };
+        let tid = parse_thread(tid);
+        if op == b'g' {
+            if !thread_exists(tid) {
+                reply.push_str("E01");
+                return;
+            }
+            self.selected = tid;
+        }
+        reply.push_str("OK");
+    }
+
+    fn breakpoint(&mut self, insert: bool, args: &[u8], reply: &mut Reply) {
+        // Only software breakpoints: `0,<address>,<kind>`
+        let mut fields = args.split(|&b| b == b',');
+        let kind = fields.next();
+        let address = fields.next().and_then(parse_hex);
+        let (Some(b"0"), Some(address)) = (kind, address) else {
+            return;
+        };
+
+        let breakpoints = &mut *self.breakpoints;
+        let done = if insert { breakpoints.insert(address) } else { breakpoints.remove(address) };
+        reply.push_str(if done { "OK" } else { "E01" });
+    }
+
+    fn query(&self, args: &[u8], reply: &mut Reply) {
+        if args.starts_with(b"Supported") {
+            reply.push_fmt(format_args!("PacketSize={:x};swbreak+", PACKET_SIZE));
+        } else if args == b"Attached" {
+            reply.push_str("1");
+        } else if args == b"C" {
+            if let Some(tid) = self.stopped {
+                reply.push_fmt(format_args!("QC{:x}", tid.raw()));
+            }
+        } else if args == b"fThreadInfo" {
+            list_threads(reply);
+        } else if args == b"sThreadInfo" {
+            reply.push_str("l");
+        } else if let Some(tid) = args.strip_prefix(b"ThreadExtraInfo,") {
+            describe_thread(parse_thread(tid), reply);
+        }
+    }
+}
+
+// ============================================================================
+// Registers
+// ============================================================================
+
+fn register_size(n: usize) -> usize {
+    if n < WIDE_REGISTERS {
+        8
+    } else {
+        4
+    }
+}
+
+fn frame_registers(frame: &InterruptFrame) -> [u64; REGISTER_COUNT] {
+    [
+        frame.rax, frame.rbx, frame.rcx, frame.rdx, frame.rsi, frame.rdi, frame.rbp, frame.rsp,
+        frame.r8, frame.r9, frame.r10, frame.r11, frame.r12, frame.r13, frame.r14, frame.r15,
+        frame.rip, frame.rflags, frame.cs, frame.ss, 0, 0, 0, 0,
+    ]
+}
+
+fn context_registers(ctx: &CpuContext) -> [u64; REGISTER_COUNT] {
+    [
+        ctx.rax, ctx.rbx, ctx.rcx, ctx.rdx, ctx.rsi, ctx.rdi, ctx.rbp, ctx.rsp,
+        ctx.r8, ctx.r9, ctx.r10, ctx.r11, ctx.r12, ctx.r13, ctx.r14, ctx.r15,
+        ctx.rip, ctx.rflags, ctx.cs as u64, ctx.ss as u64, ctx.ds as u64, ctx.es as u64,
+        ctx.fs as u64, ctx.gs as u64,
+    ]
+}
+
+/// Segment registers are left alone: the frame only has CS and SS, and
+/// changing them would not survive `iretq`
+fn set_frame_register(frame: &mut InterruptFrame, n: usize, value: u64) {
+    let register = match n {
+        0 => &mut frame.rax,
+        1 => &mut frame.rbx,
+        2 => &mut frame.rcx,
+        3 => &mut frame.rdx,
+        4 => &mut frame.rsi,
+        5 => &mut frame.rdi,
+        6 => &mut frame.rbp,
+        7 => &mut frame.rsp,
+        8 => &mut frame.r8,
+        9 => &mut frame.r9,
+        10 => &mut frame.r10,
+        11 => &mut frame.r11,
+        12 => &mut frame.r12,
+        13 => &mut frame.r13,
+        14 => &mut frame.r14,
+        15 => &mut frame.r15,
+        RIP => &mut frame.rip,
+        EFLAGS => &mut frame.rflags,
+        _ => return,
+    };
+    *register = value;
+}
+
+// ============================================================================
+// Threads
+// ============================================================================
+
+/// Whether `tid` names a live thread; None (any thread) always does
+fn thread_exists(tid: Option<ThreadId>) -> bool {
+    let Some(tid) = tid else {
+        return true;
+    };
+    thread::try_with_threads(|threads| {
+        threads.iter().any(|t| t.id == tid && t.state != ThreadState::Exited)
+    })
+    .unwrap_or(false)
+}
+
+fn list_threads(reply: &mut Reply) {
+    let listed = thread::try_with_threads(|threads| {
+        let mut first = true;
+        for t in threads.iter().filter(|t| t.state != ThreadState::Exited) {
+            reply.push_str(if first { "m" } else { "," });
+            reply.push_fmt(format_args!("{:x}", t.id.raw()));
+            first = false;
+        }
+        !first
+    });
+    if listed != Some(true) {
+        reply.clear();
+        reply.push_str("l");
+    }
+}
+
+/// `qThreadExtraInfo`: the thread's name and state, hex encoded
+fn describe_thread(tid: Option<ThreadId>, reply: &mut Reply) {
+    let described = tid.and_then(|tid| {
+        thread::try_with_threads(|threads| {
+            let t = threads.iter().find(|t| t.id == tid)?;
+            let mut text = HexText(reply);
+            let _ = fmt::Write::write_fmt(&mut text, format_args!("{} ({:?})", t.name, t.state));
+            Some(())
+        })
+        .flatten()
+    });
+    if described.is_none() {
+        reply.clear();
+        reply.push_str("E01");
+    }
+}
+
+/// Thread IDs are hex; 0 and -1 mean any and all threads
+fn parse_thread(text: &[u8]) -> Option<ThreadId> {
+    match text {
+        b"0" | b"-1" => None,
+        _ => parse_hex(text).map(ThreadId::from_raw),
+    }
+}
+
+// ============================================================================
+// Memory
+// ============================================================================
+
+fn read_memory_packet(args: &[u8], reply: &mut Reply) {
+    let Some((address, len)) = parse_range(args) else {
+        reply.push_str("E01");
+        return;
+    };
+    let len = len.min((PACKET_SIZE - 4) / 2);
+
+    let mut chunk = [0u8; 256];
+    let mut done = 0;
+    while done < len {
+        let n = (len - done).min(chunk.len());
+        if !read_memory(address + done as u64, &mut chunk[..n]) {
+            if done == 0 {
+                reply.push_str("E14");
+            }
+            return;
+        }
+        for &byte in &chunk[..n] {
+            reply.push_hex_u8(byte);
+        }
+        done += n;
+    }
+}
+
+fn write_memory_packet(args: &[u8], reply: &mut Reply) {
+    let parsed = split_at_byte(args, b':').and_then(|(range, data)| {
+        let (address, len) = parse_range(range)?;
+        (data.len() == len * 2).then_some((address, data))
+    });
+    let Some((address, data)) = parsed else {
+        reply.push_str("E01");
+        return;
+    };
+
+    let mut chunk = [0u8; 256];
+    for (i, pairs) in data.chunks(chunk.len() * 2).enumerate() {
+        let n = pairs.len() / 2;
+        for (byte, pair) in chunk.iter_mut().zip(pairs.chunks(2)) {
+            match parse_hex(pair) {
+                Some(value) => *byte = value as u8,
+                None => {
+                    reply.push_str("E02");
+                    return;
+                }
+            }
+        }
+        if !write_memory(address + (i * chunk.len()) as u64, &chunk[..n]) {
+            reply.push_str("E14");
+            return;
+        }
+    }
+    reply.push_str("OK");
+}
+
+/// Whether `address` is mapped in the active page tables, walked by hand
+/// so that huge pages are understood and nothing is locked or created
+fn is_mapped(address: u64) -> bool {
+    let canonical = matches!(address >> 47, 0 | 0x1_FFFF);
+    if !canonical {
+        return false;
+    }
+
+    let mut table = read_cr3() & PAGE_ADDR_MASK;
+    for level in (0..4).rev() {
+        let index = (address >> (12 + 9 * level)) & 0x1FF;
+        let entry = unsafe { core::ptr::read_volatile((table as *const u64).add(index as usize)) };
+        if entry & 1 == 0 {
+            return false;
+        }
+        // Bit 7 marks 1 GiB and 2 MiB pages
+        if level == 0 || (level < 3 && entry & 0x80 != 0) {
+            return true;
+        }
+        table = entry & PAGE_ADDR_MASK;
+    }
+    false
+}
+
+fn range_mapped(address: u64, len: usize) -> bool {
+    let Some(last) = address.checked_add(len.saturating_sub(1) as u64) else {
+        return false;
+    };
+    let mut page = address & !0xFFF;
+    while page <= last {
+        if !is_mapped(page) {
+            return false;
+        }
+        page = match page.checked_add(0x1000) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+    true
+}
+
+fn read_memory(address: u64, buffer: &mut [u8]) -> bool {
+    if !range_mapped(address, buffer.len()) {
+        return false;
+    }
+    let _access = UserAccess::open();
+    for (i, byte) in buffer.iter_mut().enumerate() {
+        *byte = unsafe { core::ptr::read_volatile((address + i as u64) as *const u8) };
+    }
+    true
+}
+
+/// Write even to read-only pages, such as kernel text for breakpoints
+fn write_memory(address: u64, data: &[u8]) -> bool {
+    if !range_mapped(address, data.len()) {
+        return false;
+    }
+    let _access = UserAccess::open();
+    unsafe {
+        let cr0: u64;
+        core::arch::asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov cr0, {}", in(reg) cr0 & !CR0_WP, options(nostack, preserves_flags));
+        for (i, &byte) in data.iter().enumerate() {
+            core::ptr::write_volatile((address + i as u64) as *mut u8, byte);
+        }
+        core::arch::asm!("mov cr0, {}", in(reg) cr0, options(nostack, preserves_flags));
+    }
+    true
+}
+
+// ============================================================================
+// Packets
+// ============================================================================
+
+const PORT: SerialPort = SerialPort::new(COM1);
+
+/// Wait for a well-formed packet, acknowledging it, and return its length
+fn recv_packet(buffer: &mut [u8; PACKET_SIZE]) -> usize {
+    'packet: loop {
+        // Anything outside a packet (acks, Ctrl-C) is skipped
+        while PORT.read_byte() != b'$' {}
+
+        let mut len = 0;
+        let mut sum = 0u8;
+        loop {
+            match PORT.read_byte() {
+                b'#' => break,
+                b'$' => continue 'packet,
+                byte => {
+                    if len < PACKET_SIZE {
+                        buffer[len] = byte;
+                        len += 1;
+                    }
+                    sum = sum.wrapping_add(byte);
+                }
+            }
+        }
+
+        let checksum = [PORT.read_byte(), PORT.read_byte()];
+        if parse_hex(&checksum) == Some(sum as u64) && len < PACKET_SIZE {
+            PORT.write_byte(b'+');
+            return len;
+        }
+        PORT.write_byte(b'-');
+    }
+}
+
+/// Send a packet until GDB acknowledges it
+fn send_packet(data: &[u8]) {
+    let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+    loop {
+        PORT.write_byte(b'$');
+        for &byte in data {
+            PORT.write_byte(byte);
+        }
+        PORT.write_byte(b'#');
+        PORT.write_byte(HEX_DIGITS[(sum >> 4) as usize]);
+        PORT.write_byte(HEX_DIGITS[(sum & 0xF) as usize]);
+
+        loop {
+            match PORT.read_byte() {
+                b'+' => return,
+                b'-' => break,
+                _ => {}
+            }
+        }
+    }
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// A reply being built, cut short if it outgrows a packet
+struct Reply {
+    data: [u8; PACKET_SIZE],
+    len: usize,
+}
+
+impl Reply {
+    const fn new() -> Self {
+        Self { data: [0; PACKET_SIZE], len: 0 }
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len < PACKET_SIZE {
+            self.data[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn push_str(&mut self, text: &str) {
+        for byte in text.bytes() {
+            self.push(byte);
+        }
+    }
+
+    fn push_fmt(&mut self, args: fmt::Arguments) {
+        let _ = fmt::Write::write_fmt(self, args);
+    }
+
+    fn push_hex_u8(&mut self, byte: u8) {
+        self.push(HEX_DIGITS[(byte >> 4) as usize]);
+        self.push(HEX_DIGITS[(byte & 0xF) as usize]);
+    }
+
+    /// `value` as `size` little-endian bytes, the target's byte order
+    fn push_le(&mut self, value: u64, size: usize) {
+        for byte in &value.to_le_bytes()[..size] {
+            self.push_hex_u8(*byte);
+        }
+    }
+}
+
+impl fmt::Write for Reply {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+}
+
+/// Writes text into a reply as hex, for replies GDB decodes
+struct HexText<'a>(&'a mut Reply);
+
+impl fmt::Write for HexText<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.0.push_hex_u8(byte);
+        }
+        Ok(())
+    }
+}
+
+fn parse_hex(text: &[u8]) -> Option<u64> {
+    if text.is_empty() || text.len() > 16 {
+        return None;
+    }
+    text.iter().try_fold(0u64, |value, &digit| {
+        let digit = (digit as char).to_digit(16)?;
+        Some(value << 4 | digit as u64)
+    })
+}
+
+/// A register value sent as little-endian hex bytes
+fn parse_le(text: &[u8]) -> Option<u64> {
+    if !text.len().is_multiple_of(2) || text.len() > 16 {
+        return None;
+    }
+    text.chunks(2).rev().try_fold(0u64, |value, pair| Some(value << 8 | parse_hex(pair)?))
+}
+
+/// `<address>,<length>`
+fn parse_range(text: &[u8]) -> Option<(u64, usize)> {
+    let (address, len) = split_at_byte(text, b',')?;
+    Some((parse_hex(address)?, parse_hex(len)? as usize))
+}
+
+fn split_at_byte(text: &[u8], separator: u8) -> Option<(&[u8], &[u8])> {
+    let at = text.iter().position(|&b| b == separator)?;
+    Some((&text[..at], &text[at + 1..]))
+}
@@ -4,7 +4,8 @@
 // microkernel principles. The kernel only:
 // - Initializes and stores framebuffer parameters from UEFI
 // - Exposes framebuffer address and dimensions via syscalls
-// - Provides emergency boot/panic output (minimal, for kernel diagnostics only)
+// - Provides emergency boot/panic output (minimal, for kernel diagnostics only),
+//   including the crash screen drawn by `crash`
 //
 // All rendering, compositing, windowing, and UI logic is handled in userspace:
 // - Display driver manages framebuffer access and compositing
@@ -14,14 +15,25 @@
 // Syscall interface:
 // - SYS_GET_FRAMEBUFFER: Get framebuffer info (address, width, height, stride, bpp)
 // - SYS_MAP_FRAMEBUFFER: Map framebuffer to userspace address space
+// - SYS_VIDEO_MODES: List the resolutions the screen can switch to
+// - SYS_SET_VIDEO_MODE: Switch resolution (through the DISPI backend)
+//
+// Mode switching:
+// - The firmware's mode list is kept from boot; the firmware itself can no
+//   longer set modes, so switches go through `dispi`
+// - The whole of video memory is mapped at boot, so a bigger mode needs no
+//   new mappings in processes that already use the framebuffer
 
 #![allow(dead_code)]
 
-use crate::boot::{FramebufferInfo, PixelFormat};
+use crate::boot::{FramebufferInfo, PixelFormat, VideoMode, VideoModes};
+use crate::dispi;
+use crate::mm::usercopy::UserAccess;
 use core::sync::atomic::{AtomicBool, Ordering};
 use spin::Mutex;
 
 static FRAMEBUFFER: Mutex<Option<Framebuffer>> = Mutex::new(None);
+static VIDEO_MODES: Mutex<VideoModes> = Mutex::new(VideoModes::empty());
 static FRAMEBUFFER_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
 /// Minimal color representation for early boot diagnostics
@@ -50,6 +62,8 @@ pub struct Framebuffer {
     stride: u32,
     pixel_format: PixelFormat,
     bytes_per_pixel: usize,
+    /// Bytes mapped from `address` on; modes must fit in them
+    mapped_size: usize,
 }
 
 unsafe impl Send for Framebuffer {}
@@ -69,6 +83,7 @@ impl Framebuffer {
             stride: info.pixels_per_scan_line,
             pixel_format: info.pixel_format,
             bytes_per_pixel,
+            mapped_size: info.size,
         }
     }
 
@@ -96,6 +111,61 @@ impl Framebuffer {
     pub fn size(&self) -> usize {
         (self.stride as usize) * (self.height as usize) * self.bytes_per_pixel
     }
+
+    /// Encode a color in this framebuffer's pixel format
+    pub fn encode(&self, color: Color) -> u32 {
+        match self.pixel_format {
+            PixelFormat::Rgb => {
+                ((color.r as u32) << 16) | ((color.g as u32) << 8) | (color.b as u32)
+            }
+            _ => {
+                ((color.b as u32) << 16) | ((color.g as u32) << 8) | (color.r as u32)
+            }
+        }
+    }
+
+    /// Fill a rectangle, clipped to the screen
+    pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Color) {
+        let pixel_value = self.encode(color);
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+
+        for py in y..y_end {
+            for px in x..x_end {
+                let offset = (py * self.stride + px) as usize * self.bytes_per_pixel;
+                unsafe {
+                    let ptr = self.address.add(offset) as *mut u32;
+                    ptr.write_volatile(pixel_value);
+                }
+            }
+        }
+    }
+
+    /// Draw a character cell, clipped to the screen
+    pub fn draw_char(&mut self, x: u32, y: u32, ch: u8, fg: Color, bg: Color) {
+        let fg_pixel = self.encode(fg);
+        let bg_pixel = self.encode(bg);
+
+        let glyph = get_full_glyph(ch);
+        for row in 0..FONT_HEIGHT {
+            for col in 0..FONT_WIDTH {
+                let px = x + col;
+                let py = y + row;
+                if px < self.width && py < self.height {
+                    let pixel = if glyph[row as usize] & (0x80 >> col) != 0 {
+                        fg_pixel
+                    } else {
+                        bg_pixel
+                    };
+                    let offset = (py * self.stride + px) as usize * self.bytes_per_pixel;
+                    unsafe {
+                        let ptr = self.address.add(offset) as *mut u32;
+                        ptr.write_volatile(pixel);
+                    }
+                }
+            }
+        }
+    }
 }
 
 // ============================================================================
@@ -104,8 +174,8 @@ impl Framebuffer {
 
 /// Minimal 8x8 font for early boot messages (panic output only)
 /// Only includes essential ASCII characters
-const FONT_HEIGHT: u32 = 8;
-const FONT_WIDTH: u32 = 8;
+pub const FONT_HEIGHT: u32 = 8;
+pub const FONT_WIDTH: u32 = 8;
 
 fn get_minimal_glyph(ch: u8) -> [u8; 8] {
     match ch {
@@ -199,8 +269,13 @@ pub fn panic_write(x: u32, y: u32, text: &str, color: Color) {
 // Public API for Kernel and Syscalls
 // ============================================================================
 
-pub fn init(fb_info: &FramebufferInfo) {
-    *FRAMEBUFFER.lock() = Some(Framebuffer::new(fb_info));
+/// Take over the boot framebuffer; `mapped_size` bytes of it are mapped,
+/// which may be more than the boot mode uses
+pub fn init(fb_info: &FramebufferInfo, modes: &VideoModes, mapped_size: usize) {
+    let mut fb = Framebuffer::new(fb_info);
+    fb.mapped_size = mapped_size.max(fb_info.size);
+    *FRAMEBUFFER.lock() = Some(fb);
+    *VIDEO_MODES.lock() = *modes;
     FRAMEBUFFER_INITIALIZED.store(true, Ordering::SeqCst);
     crate::log_info!("graphics", "Framebuffer initialized: {}x{}", fb_info.width, fb_info.height);
 }
@@ -219,12 +294,29 @@ where
 
     let mut fb_lock = FRAMEBUFFER.lock();
     if let Some(ref mut fb) = *fb_lock {
+        // The framebuffer is mapped for userspace too, so SMAP guards it
+        let _access = UserAccess::open();
         Some(f(fb))
     } else {
         None
     }
 }
 
+/// Like `with_framebuffer`, but gives up instead of waiting for the lock,
+/// for the crash screen, which may be drawn while the lock is held
+pub fn try_with_framebuffer<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&mut Framebuffer) -> R,
+{
+    if !is_initialized() {
+        return None;
+    }
+
+    let mut fb_lock = FRAMEBUFFER.try_lock()?;
+    let _access = UserAccess::open();
+    fb_lock.as_mut().map(f)
+}
+
 pub fn get_dimensions() -> Option<(u32, u32)> {
     with_framebuffer(|fb| (fb.width(), fb.height()))
 }
@@ -257,6 +349,78 @@ pub fn get_bytes_per_pixel() -> usize {
     with_framebuffer(|fb| fb.bytes_per_pixel()).unwrap_or(4)
 }
 
+/// Why a mode switch was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModeError {
+    /// No framebuffer, or no backend that can change modes
+    Unsupported,
+    /// Not one of the modes `video_modes` lists
+    UnknownMode,
+    /// The adapter did not take the mode
+    Failed,
+}
+
+/// Fill `out` with the modes the screen can switch to and return how many
+/// there are, which may be more than fit
+///
+/// Without a mode-setting backend only the current mode is listed. Modes
+/// that do not fit in the mapped video memory are left out.
+pub fn video_modes(out: &mut [VideoMode]) -> usize {
+    let Some((current, mapped_size, bpp)) = with_framebuffer(|fb| {
+        let current = VideoMode {
+            width: fb.width,
+            height: fb.height,
+            pixel_format: fb.pixel_format,
+        };
+        (current, fb.mapped_size, fb.bytes_per_pixel)
+    }) else {
+        return 0;
+    };
+
+    let modes = VIDEO_MODES.lock();
+    let switchable = modes.as_slice().iter().filter(|mode| {
+        dispi::is_present() && mode.width as usize * mode.height as usize * bpp <= mapped_size
+    });
+
+    let mut count = 0;
+    for mode in core::iter::once(&current).chain(switchable) {
+        if count > 0 && mode.width == current.width && mode.height == current.height {
+            continue;
+        }
+        if let Some(slot) = out.get_mut(count) {
+            *slot = *mode;
+        }
+        count += 1;
+    }
+    count
+}
+
+/// Switch the screen to `width` x `height`, one of the listed modes
+///
+/// The screen's contents are lost; whoever draws on it must redraw.
+pub fn set_mode(width: u32, height: u32) -> Result<(), ModeError> {
+    let mut modes = [VideoMode::empty(); crate::boot::MAX_VIDEO_MODES + 1];
+    let count = video_modes(&mut modes).min(modes.len());
+    if !modes[..count].iter().any(|mode| mode.width == width && mode.height == height) {
+        return Err(ModeError::UnknownMode);
+    }
+
+    with_framebuffer(|fb| {
+        if (fb.width, fb.height) == (width, height) {
+            return Ok(());
+        }
+        if !dispi::set_mode(width, height) {
+            return Err(ModeError::Failed);
+        }
+        fb.width = width;
+        fb.height = height;
+        fb.stride = width;
+        crate::log_info!("graphics", "Mode set: {}x{}", width, height);
+        Ok(())
+    })
+    .unwrap_or(Err(ModeError::Unsupported))
+}
+
 // ============================================================================
 // Minimal Drawing Functions for Bootstrap UI Service
 // These functions are used by the ui_shell kernel service until proper
@@ -315,29 +479,7 @@ pub fn write_pixel(x: u32, y: u32, value: u32) {
 
 /// Fill a rectangle with the given color
 pub fn fill_rect(x: u32, y: u32, width: u32, height: u32, color: Color) {
-    with_framebuffer(|fb| {
-        let pixel_value = match fb.pixel_format {
-            PixelFormat::Rgb => {
-                ((color.r as u32) << 16) | ((color.g as u32) << 8) | (color.b as u32)
-            }
-            _ => {
-                ((color.b as u32) << 16) | ((color.g as u32) << 8) | (color.r as u32)
-            }
-        };
-
-        let x_end = (x + width).min(fb.width);
-        let y_end = (y + height).min(fb.height);
-
-        for py in y..y_end {
-            for px in x..x_end {
-                let offset = (py * fb.stride + px) as usize * fb.bytes_per_pixel;
-                unsafe {
-                    let ptr = fb.address.add(offset) as *mut u32;
-                    ptr.write_volatile(pixel_value);
-                }
-            }
-        }
-    });
+    with_framebuffer(|fb| fb.fill_rect(x, y, width, height, color));
 }
 
 /// Clear the entire screen with the given color
@@ -466,36 +608,7 @@ fn get_full_glyph(ch: u8) -> [u8; 8] {
 
 /// Draw a character at the given coordinates
 pub fn draw_char(x: u32, y: u32, ch: u8, fg: Color, bg: Color) {
-    with_framebuffer(|fb| {
-        let fg_pixel = match fb.pixel_format {
-            PixelFormat::Rgb => ((fg.r as u32) << 16) | ((fg.g as u32) << 8) | (fg.b as u32),
-            _ => ((fg.b as u32) << 16) | ((fg.g as u32) << 8) | (fg.r as u32),
-        };
-        let bg_pixel = match fb.pixel_format {
-            PixelFormat::Rgb => ((bg.r as u32) << 16) | ((bg.g as u32) << 8) | (bg.b as u32),
-            _ => ((bg.b as u32) << 16) | ((bg.g as u32) << 8) | (bg.r as u32),
-        };
-
-        let glyph = get_full_glyph(ch);
-        for row in 0..FONT_HEIGHT {
-            for col in 0..FONT_WIDTH {
-                let px = x + col;
-                let py = y + row;
-                if px < fb.width && py < fb.height {
-                    let pixel = if glyph[row as usize] & (0x80 >> col) != 0 {
-                        fg_pixel
-                    } else {
-                        bg_pixel
-                    };
-                    let offset = (py * fb.stride + px) as usize * fb.bytes_per_pixel;
-                    unsafe {
-                        let ptr = fb.address.add(offset) as *mut u32;
-                        ptr.write_volatile(pixel);
-                    }
-                }
-            }
-        }
-    });
+    with_framebuffer(|fb| fb.draw_char(x, y, ch, fg, bg));
 }
 
 /// Draw a string at the given coordinates
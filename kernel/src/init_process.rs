@@ -12,15 +12,15 @@ use alloc::string::String;
 use alloc::vec::Vec;
 
 use crate::boot::BootInfo;
-use crate::executable::{self, ExecError, LoadedExecutable};
+use crate::executable::{self, ExecError, LoadBase, LoadedExecutable};
 use crate::mm::addrspace::{self, AddressSpaceId};
 use crate::mm::{pmm, vm};
 use crate::mm::vm::PageFlags;
 use crate::sched;
 use crate::service_manager::{self, ServiceSpec};
-use crate::thread::{self, CpuContext, Thread, ThreadId, ThreadPriority, ThreadState};
+use crate::thread::{self, CpuContext, FpuState, Thread, ThreadId, ThreadPriority, ThreadState};
 use crate::{log_error, log_info, log_warn};
-use crate::mm::pmm::{align_up, PAGE_SIZE};
+use crate::mm::pmm::PAGE_SIZE;
 
 const LOG_ORIGIN: &str = "init";
 const USER_STACK_PAGES: usize = 4;
@@ -29,15 +29,23 @@ const USER_STACK_TOP: usize = 0x0000_8000_0000;
 const KERNEL_STACK_PAGES: usize = 8;
 const SERVICE_STACK_PAGES: usize = 4;
 
+/// Boot parameter that starts the integration test runner with the desktop
+const TEST_RUNNER_FLAG: &str = "test_runner";
+const TEST_RUNNER_SERVICE: &str = "test_runner";
+
 #[derive(Clone)]
 struct ServiceThreadContext {
     name: String,
     capabilities: Vec<String>,
+    args: Vec<String>,
 }
 
 static SERVICE_THREADS: spin::Mutex<BTreeMap<ThreadId, ServiceThreadContext>> =
     spin::Mutex::new(BTreeMap::new());
 
+/// Thread running the test runner, when booted with `TEST_RUNNER_FLAG`
+static TEST_RUNNER: spin::Mutex<Option<ThreadId>> = spin::Mutex::new(None);
+
 #[allow(dead_code)]
 pub struct InitProcess {
     pub pid: ThreadId,
@@ -114,6 +122,7 @@ fn create_init_process(pid: ThreadId, boot_info: &BootInfo) -> Result<InitProces
         priority: ThreadPriority::Normal,
         name: "init",
         capability_table: crate::cap::create_capability_table(pid),
+        fpu: FpuState::new(),
     };
 
     thread::add_thread(thread);
@@ -135,7 +144,8 @@ fn load_payload_into_kernel(
     _pid: ThreadId,
     boot_info: &BootInfo,
 ) -> Result<LoadedExecutable, ExecError> {
-    let image = if boot_info.init_payload.is_present() {
+    let from_bootloader = boot_info.init_payload.is_present();
+    let image = if from_bootloader {
         log_info!(LOG_ORIGIN, "Loading init payload provided by bootloader");
         unsafe {
             core::slice::from_raw_parts(
@@ -148,108 +158,95 @@ fn load_payload_into_kernel(
         executable::embedded_init_image()
     };
 
-    let sections = executable::parse_image(image)?;
-
-    log_info!(
-        LOG_ORIGIN,
-        "Parsed executable: text_len={}, data_len={}, bss_size={}, entry_offset={}",
-        sections.text.len(),
-        sections.data.len(),
-        sections.bss_size,
-        sections.entry_offset
-    );
+    let mut sections = executable::parse_image(image)?;
+    // The embedded image is part of the kernel; anything else must be signed
+    // in secure-boot mode
+    if from_bootloader {
+        executable::check_signature_policy(&sections)?;
+    }
 
-    // -------------------------------
-    // TEXT
-    // -------------------------------
-    let text_base = executable::USER_EXEC_LOAD_BASE;
-    let text_size = align_up(sections.text.len().max(1));
-    let text_pages = text_size / PAGE_SIZE;
+    // Init shares the kernel page table, where only the load base is known
+    // to be free
+    let base = if sections.relocatable {
+        LoadBase::At(executable::USER_EXEC_LOAD_BASE)
+    } else {
+        LoadBase::Linked
+    };
+    sections.place(base)?;
 
-    let (total, free) = pmm::get_stats();
     log_info!(
         LOG_ORIGIN,
-        "PMM before text alloc: {}/{} free, requesting {} pages",
-        free, total, text_pages
+        "Parsed executable: {} segments, entry=0x{:X}",
+        sections.segments.len(),
+        sections.entry_point
     );
 
-    let text_phys = pmm::alloc_pages_zeroed(text_pages)
-        .ok_or(ExecError::OutOfMemory)?;
-
-    log_info!(LOG_ORIGIN, "Text allocated at phys 0x{:X}", text_phys);
-
-    unsafe {
-        core::ptr::copy_nonoverlapping(
-            sections.text.as_ptr(),
-            text_phys as *mut u8,
-            sections.text.len(),
-        );
-    }
-
-    // 🔥 FIX CRÍTICO 🔥
-    // Garantir que a faixa do USER_EXEC_LOAD_BASE não está mapeada
-    for i in 0..text_pages {
-        let virt = text_base + i * PAGE_SIZE;
-        let _ = vm::unmap_page(virt);
-    }
+    let (total, free) = pmm::get_stats();
+    log_info!(LOG_ORIGIN, "PMM before loading: {}/{} free", free, total);
 
-    for i in 0..text_pages {
-        let virt = text_base + i * PAGE_SIZE;
-        let phys = text_phys + i * PAGE_SIZE;
-
-        vm::map_page(virt, phys, PageFlags::PRESENT | PageFlags::USER)
-            .map_err(|e| {
-                log_error!(
-                    LOG_ORIGIN,
-                    "map_page(.text) FAILED: i={} virt=0x{:X} phys=0x{:X} err={:?}",
-                    i,
-                    virt,
-                    phys,
-                    e
-                );
-                ExecError::OutOfMemory
-            })?;
-    }
+    let mut mapped = Vec::with_capacity(sections.segments.len());
+    for segment in &sections.segments {
+        let virt_base = segment.page_start();
+        let pages = (segment.page_end() - virt_base) / PAGE_SIZE;
 
-    // -------------------------------
-    // BSS
-    // -------------------------------
-    let bss_base = align_up(text_base + text_size);
-    let bss_size = sections.bss_size.max(1);
-    let bss_pages = align_up(bss_size) / PAGE_SIZE;
+        let phys_base = pmm::alloc_pages_zeroed(pages)
+            .ok_or(ExecError::OutOfMemory)?;
 
-    let bss_phys = pmm::alloc_pages_zeroed(bss_pages)
-        .ok_or(ExecError::OutOfMemory)?;
+        unsafe {
+            segment.copy_to((phys_base + (segment.vaddr - virt_base)) as *mut u8)?;
+        }
 
-    for i in 0..bss_pages {
-        let virt = bss_base + i * PAGE_SIZE;
-        let phys = bss_phys + i * PAGE_SIZE;
+        // The range must not still be mapped from earlier boot stages
+        for i in 0..pages {
+            let virt = virt_base + i * PAGE_SIZE;
+            let phys = phys_base + i * PAGE_SIZE;
 
-        let _ = vm::unmap_page(virt);
+            let _ = vm::unmap_page(virt);
+            vm::map_page(virt, phys, segment.page_flags())
+                .map_err(|e| {
+                    log_error!(
+                        LOG_ORIGIN,
+                        "map_page FAILED: i={} virt=0x{:X} phys=0x{:X} err={:?}",
+                        i,
+                        virt,
+                        phys,
+                        e
+                    );
+                    ExecError::OutOfMemory
+                })?;
+        }
 
-        vm::map_page(
-            virt,
-            phys,
-            PageFlags::PRESENT | PageFlags::USER | PageFlags::WRITABLE,
-        )
-            .map_err(|_| ExecError::OutOfMemory)?;
+        log_info!(
+            LOG_ORIGIN,
+            "Segment 0x{:X} ({} pages) at phys 0x{:X}, writable={}, executable={}",
+            virt_base,
+            pages,
+            phys_base,
+            segment.flags & executable::SEGMENT_WRITE != 0,
+            segment.is_executable()
+        );
+        mapped.push((virt_base, phys_base, pages * PAGE_SIZE));
     }
 
-    let entry_point = text_base + sections.entry_offset;
+    sections.apply_relocations(&mapped)?;
+    sections.register_symbols(crate::arch::read_cr3() as usize);
+
+    let image_start = sections.image_start();
+    let image_end = sections.image_end();
 
     log_info!(
         LOG_ORIGIN,
-        "Executable loaded into kernel page table: text=0x{:X}, bss=0x{:X}, entry=0x{:X}",
-        text_base,
-        bss_base,
-        entry_point
+        "Executable loaded into kernel page table: 0x{:X}-0x{:X}, entry=0x{:X}",
+        image_start,
+        image_end,
+        sections.entry_point
     );
 
     Ok(LoadedExecutable {
-        entry_point,
-        text_base,
-        data_base: bss_base,
-        bss_base,
+        entry_point: sections.entry_point,
+        image_start,
+        image_end,
+        load_bias: sections.load_bias,
     })
 }
 
@@ -335,11 +332,14 @@ fn bootstrap_manifest_services() {
     match service_manager::init_embedded_manifest() {
         Ok(manager) => {
             let mut launched = 0usize;
+            let run_tests = crate::system::info().command_line().has_flag(TEST_RUNNER_FLAG);
 
             for name in manager.startup_plan() {
+                let test_runner = run_tests && name == TEST_RUNNER_SERVICE;
+
                 // Only launch ui_shell for now - other services are placeholders
                 // that cause context corruption. TODO: investigate thread 4 crash
-                if name != "ui_shell" {
+                if name != "ui_shell" && !test_runner {
                     log_info!(
                         LOG_ORIGIN,
                         "Skipping service '{}' (placeholder, not implemented)",
@@ -349,8 +349,15 @@ fn bootstrap_manifest_services() {
                 }
 
                 if let Some(spec) = manager.manifest().service(name) {
+                    if !spec.autostart && !test_runner {
+                        continue;
+                    }
+
                     match spawn_service_thread(spec) {
                         Ok(tid) => {
+                            if test_runner {
+                                *TEST_RUNNER.lock() = Some(tid);
+                            }
                             log_info!(
                                 LOG_ORIGIN,
                                 "Boot service '{}' scheduled as thread {}",
@@ -411,7 +418,7 @@ fn launch_ui_service() {
     // - Application launching
 }
 
-fn spawn_service_thread(spec: &ServiceSpec) -> Result<ThreadId, ExecError> {
+pub fn spawn_service_thread(spec: &ServiceSpec) -> Result<ThreadId, ExecError> {
     let stack_phys = pmm::alloc_pages(SERVICE_STACK_PAGES).ok_or(ExecError::OutOfMemory)?;
     let stack_top = stack_phys + SERVICE_STACK_PAGES * PAGE_SIZE;
 
@@ -438,14 +445,36 @@ fn spawn_service_thread(spec: &ServiceSpec) -> Result<ThreadId, ExecError> {
         ServiceThreadContext {
             name: spec.name.clone(),
             capabilities: spec.capabilities.clone(),
+            args: spec.args.clone(),
         },
     );
 
     thread::add_thread(thread);
+    service_manager::grant_capabilities(tid, &spec.capabilities);
     sched::mark_thread_ready(tid);
     Ok(tid)
 }
 
+/// End the test run when the test runner exits: QEMU leaves with status 33
+/// if every test passed (exit code 0) and 35 otherwise
+pub fn on_thread_exit(tid: ThreadId, exit_code: u64) {
+    if *TEST_RUNNER.lock() != Some(tid) {
+        return;
+    }
+
+    log_info!(LOG_ORIGIN, "Test runner exited with code {}", exit_code);
+    crate::arch::qemu_exit(if exit_code == 0 {
+        crate::arch::QEMU_EXIT_SUCCESS
+    } else {
+        crate::arch::QEMU_EXIT_FAILURE
+    });
+}
+
+/// Arguments the manifest gives the service running as `tid`
+pub fn service_args(tid: ThreadId) -> Option<Vec<String>> {
+    SERVICE_THREADS.lock().get(&tid).map(|ctx| ctx.args.clone())
+}
+
 fn respond_to_basic_syscalls() {
     log_info!(
         LOG_ORIGIN,
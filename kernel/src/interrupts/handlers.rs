@@ -20,13 +20,18 @@
 // - Special-cases common faults:
 //   - Page Fault (#PF, vector 14): reads CR2 and decodes error-code bits
 //   - General Protection Fault (#GP, vector 13): prints selector info if any
-// - Ends by halting forever (`loop { halt(); }`), turning exceptions into a
-//   fail-stop crash with a useful diagnostic printout.
+// - Names the function RIP is in when the faulting program carries symbols
+// - With the GDB stub enabled, breakpoints and single steps go straight to
+//   it, and faults do once logged; the stub edits the frame and resumes
+// - Otherwise ends in `crash::exception`, which draws the crash screen and
+//   halts forever, turning exceptions into a fail-stop crash with a useful
+//   diagnostic printout.
 //
 // Timer handling:
 // - `TICKS` is a global tick counter incremented on each timer interrupt.
 // - Calls into `sched::on_timer_tick()` to drive preemption/time slicing.
 // - Calls `ipc::on_timer_tick(get_ticks())` to advance IPC timeouts/timers.
+// - Hands the interrupted RIP to the sampling profiler (`profile`).
 // - Always signals EOI via `apic::send_eoi()` to re-arm the interrupt line.
 //
 // Keyboard handling:
@@ -49,10 +54,14 @@
 // - `halt()` inside an infinite loop ensures the CPU stays quiescent after a
 //   fatal exception, preventing further memory corruption.
 
-use crate::arch::{gdt, halt};
+use crate::arch::{gdt, halt, read_cr3};
+use crate::crash;
+use crate::executable;
+use crate::gdbstub;
 use crate::ipc;
 use crate::input;
 use crate::mm;
+use crate::profile;
 use crate::sched;
 #[allow(unused_imports)]
 use crate::util::UI_DIRTY;
@@ -106,30 +115,30 @@ pub struct InterruptStackFrame {
 
 #[repr(C)]
 pub struct InterruptFrame {
-    r15: u64,
-    r14: u64,
-    r13: u64,
-    r12: u64,
-    r11: u64,
-    r10: u64,
-    r9:  u64,
-    r8:  u64,
-    rbp: u64,
-    rdi: u64,
-    rsi: u64,
-    rdx: u64,
-    rcx: u64,
-    rbx: u64,
-    rax: u64,
-
-    exception_number: u64,
-    error_code: u64,
-
-    rip: u64,
-    cs: u64,
-    rflags: u64,
-    rsp: u64,
-    ss: u64,
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9:  u64,
+    pub r8:  u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+
+    pub exception_number: u64,
+    pub error_code: u64,
+
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
 }
 
 const _: () = {
@@ -178,13 +187,18 @@ pub extern "C" fn rust_unexpected_interrupt_handler(
 }
 
 #[no_mangle]
-pub extern "C" fn rust_exception_handler(frame: *const InterruptFrame) {
+pub extern "C" fn rust_exception_handler(frame: *mut InterruptFrame) {
     const LOG_ORIGIN: &str = "exception";
 
-    let frame = unsafe { &*frame };
+    let frame = unsafe { &mut *frame };
     let exception_number = frame.exception_number;
     let error_code = frame.error_code;
 
+    // Breakpoints and single steps belong to the debugger, if one is in use
+    if matches!(exception_number, 1 | 3) && gdbstub::handle_exception(frame) {
+        return;
+    }
+
     if (exception_number as usize) >= EXCEPTION_NAMES.len() {
             log_panic!(
             LOG_ORIGIN,
@@ -238,6 +252,10 @@ pub extern "C" fn rust_exception_handler(frame: *const InterruptFrame) {
         frame.rip, frame.cs, frame.rflags, frame.ss
     );
 
+    executable::resolve_symbol(read_cr3() as usize, frame.rip as usize, |name, offset| {
+        log_panic!(LOG_ORIGIN, "Faulting instruction in {}+{:#X}", name, offset);
+    });
+
     match exception_number {
         14 => {
             let cr2: u64;
@@ -254,6 +272,7 @@ pub extern "C" fn rust_exception_handler(frame: *const InterruptFrame) {
                 "Page Fault at address {:#016X}",
                 cr2
             );
+            crate::trace_event!(mm, PAGE_FAULT, cr2, error_code, frame.rip);
 
             log_debug!(
                 LOG_ORIGIN,
@@ -305,14 +324,13 @@ pub extern "C" fn rust_exception_handler(frame: *const InterruptFrame) {
         _ => {}
     }
 
-    log_panic!(
-        LOG_ORIGIN,
-        "System halted due to fatal exception"
-    );
-
-    loop {
-        halt();
+    // A debugger gets to inspect the fault; it happens again on resuming
+    // unless the state was changed
+    if gdbstub::handle_exception(frame) {
+        return;
     }
+
+    crash::exception(frame, EXCEPTION_NAMES[exception_number as usize]);
 }
 
 static mut TICKS: u64 = 0;
@@ -365,6 +383,7 @@ pub extern "x86-interrupt" fn timer_interrupt_handler(_frame: &mut InterruptStac
         TICKS += 1;
     }
     
+    profile::on_timer_tick(_frame.instruction_pointer, coming_from_user);
     ipc::on_timer_tick(get_ticks());
 
     super::apic::send_eoi();
@@ -445,4 +464,4 @@ pub fn print_stack_trace(stack_ptr: u64) {
 fn is_canonical(addr: u64) -> bool {
     let sign_extension = addr >> 48;
     sign_extension == 0 || sign_extension == 0xFFFF
-}
\ No newline at end of file
+}
\ No newline at end of file
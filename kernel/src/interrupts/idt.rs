@@ -153,7 +153,8 @@ pub fn init() {
         IDT.entries[0].set_handler(exception_handler_0 as *const () as usize, KERNEL_CS, 0, GATE_TYPE_INTERRUPT);
         IDT.entries[1].set_handler(exception_handler_1 as *const () as usize, KERNEL_CS, 0, GATE_TYPE_INTERRUPT);
         IDT.entries[2].set_handler(exception_handler_2 as *const () as usize, KERNEL_CS, 0, GATE_TYPE_INTERRUPT);
-        IDT.entries[3].set_handler(exception_handler_3 as *const () as usize, KERNEL_CS, 0, GATE_TYPE_TRAP);
+        // Reachable from ring 3, so user threads can stop at GDB breakpoints
+        IDT.entries[3].set_handler(exception_handler_3 as *const () as usize, KERNEL_CS, 0, GATE_TYPE_TRAP | DPL_RING3);
         IDT.entries[4].set_handler(exception_handler_4 as *const () as usize, KERNEL_CS, 0, GATE_TYPE_INTERRUPT);
         IDT.entries[5].set_handler(exception_handler_5 as *const () as usize, KERNEL_CS, 0, GATE_TYPE_INTERRUPT);
         IDT.entries[6].set_handler(exception_handler_6 as *const () as usize, KERNEL_CS, 0, GATE_TYPE_INTERRUPT);
@@ -15,7 +15,11 @@
 // Message model:
 // - Messages carry a sender, type, payload, optional capability, and timestamp
 // - Payloads are size-limited; larger transfers require shared memory regions
+// - Payloads up to `INLINE_PAYLOAD_SIZE` bytes live inside the message, so
+//   input events and other small messages never touch the heap
 // - Capabilities can be delegated via IPC using GRANT or MOVE semantics
+// - The sender of the message a thread last took with a single receive is
+//   remembered, so services can check what the sender may do (`last_sender`)
 //
 // Design principles:
 // - Deterministic bounds: queue depth, batch size, and message size are capped
@@ -24,6 +28,16 @@
 //   are rejected early
 // - Observability: tracing, metrics, and statistics are first-class features
 //
+// Port death:
+// - A thread may watch any port from a port it owns; when the watched port
+//   is closed, or its owner exits, each watcher gets a notification
+// - Ports are closed when their owning thread exits
+//
+// Port names:
+// - A thread may publish a port it owns under a name, so services can be
+//   found without fixed port numbers; a name belongs to one port at a time
+// - Names go away with their port
+//
 // Scheduling and blocking:
 // - Threads may block waiting for messages with optional deadlines
 // - Deadlock detection prevents circular wait across ports
@@ -32,16 +46,27 @@
 //
 // Performance optimizations:
 // - Zero-copy threshold encourages shared memory for large messages
+// - Inline small payloads avoid a heap allocation per message
+// - The port table is split into `PORT_SHARDS` independently locked shards
+//   and only maps ids to shared per-port state, so a send holds a table
+//   lock just long enough to find its port
+// - Each port queues messages in a bounded lock-free MPSC ring: senders,
+//   including interrupt handlers, never wait on each other or the receiver
+// - Per-port statistics are atomics, updated without any lock
 // - Batched send/receive reduces lock contention and syscall overhead
 // - Next-message fast paths avoid unnecessary blocking
 //
 // Diagnostics and metrics:
 // - Per-port statistics track throughput and latency
-// - Ring-buffer tracing records recent send/receive events
+// - Ring-buffer tracing records recent send/receive events, which also go
+//   to the kernel-wide trace (`trace`)
 // - Global IPC stats summarize system-wide activity
 //
 // Correctness and safety notes:
-// - All shared IPC state is protected by spinlocks
+// - Port queues and statistics are lock-free; everything else shared is
+//   protected by spinlocks
+// - Lock order: waiters before a port shard, then a port's own waiter
+//   state; the trace ring is taken last. No path holds two shards at once
 // - Queue and waiter limits prevent resource exhaustion
 // - Capability checks are enforced before message delivery
 // - Time is derived from kernel ticks; coarse but deterministic
@@ -51,14 +76,21 @@
 
 #![allow(dead_code)]
 
-use alloc::collections::{BTreeMap, VecDeque};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use core::sync::atomic::{AtomicU64, Ordering};
-use spin::Mutex;
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use spin::{Mutex, RwLock};
 
 use crate::shared_mem;
 use crate::shared_mem::RegionId;
 use crate::thread::{ThreadId, ThreadPriority};
+use crate::trace::Ring;
 use crate::log_debug;
 use crate::log_info;
 use crate::log_warn;
@@ -67,13 +99,32 @@ pub const MAX_MESSAGE_SIZE: usize = 256;
 pub const ZERO_COPY_THRESHOLD: usize = 128;
 pub const MAX_BATCH_SIZE: usize = 32;
 pub const MAX_QUEUE_DEPTH: usize = 64;
+/// Largest payload stored inside the message rather than on the heap
+pub const INLINE_PAYLOAD_SIZE: usize = 64;
+/// Longest port name, in bytes
+pub const MAX_PORT_NAME: usize = 64;
 
 const LOG_ORIGIN: &str = "ipc";
 
+/// Type of port-death notifications. Userspace reads only payloads, so the
+/// payload repeats it in the 16-byte header libipc messages start with
+/// (magic, protocol version, type, payload size, sequence); the dead
+/// port's id follows. Must match libipc's `MessageType::PortDied`.
+const MSG_TYPE_PORT_DIED: u32 = 403;
+
+/// libipc's `MESSAGE_MAGIC` and the `PROTOCOL_VERSION` notifications are
+/// laid out for
+const MSG_MAGIC: u16 = u16::from_le_bytes(*b"AT");
+const MSG_PROTOCOL_VERSION: u16 = 1;
+
 const CONFIG_DEADLOCK_DETECT: bool = true;
 const CONFIG_IPC_TRACE: bool = true;
 const IPC_TRACE_RING_SIZE: usize = 1000;
 
+/// Independently locked parts of the port table; a port lives in shard
+/// `id % PORT_SHARDS`
+const PORT_SHARDS: usize = 16;
+
 #[inline(always)]
 fn current_time_ms() -> u64 {
     crate::interrupts::get_ticks() * 10
@@ -109,11 +160,100 @@ impl core::fmt::Display for PortId {
     }
 }
 
+/// Message payload, kept inline when it fits in `INLINE_PAYLOAD_SIZE` bytes
+#[derive(Clone)]
+pub enum Payload {
+    Inline {
+        len: u8,
+        bytes: [u8; INLINE_PAYLOAD_SIZE],
+    },
+    Heap(Vec<u8>),
+}
+
+impl Payload {
+    pub const fn new() -> Self {
+        Payload::Inline {
+            len: 0,
+            bytes: [0; INLINE_PAYLOAD_SIZE],
+        }
+    }
+
+    /// A payload of `len` zero bytes, to be filled in place
+    pub fn zeroed(len: usize) -> Self {
+        if len <= INLINE_PAYLOAD_SIZE {
+            Payload::Inline {
+                len: len as u8,
+                bytes: [0; INLINE_PAYLOAD_SIZE],
+            }
+        } else {
+            Payload::Heap(alloc::vec![0; len])
+        }
+    }
+
+    pub fn from_slice(data: &[u8]) -> Self {
+        let mut payload = Self::zeroed(data.len());
+        payload.copy_from_slice(data);
+        payload
+    }
+
+    pub fn is_inline(&self) -> bool {
+        matches!(self, Payload::Inline { .. })
+    }
+}
+
+impl Default for Payload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for Payload {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Payload::Inline { len, bytes } => &bytes[..*len as usize],
+            Payload::Heap(data) => data,
+        }
+    }
+}
+
+impl DerefMut for Payload {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            Payload::Inline { len, bytes } => &mut bytes[..*len as usize],
+            Payload::Heap(data) => data,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Payload {
+    fn from(data: Vec<u8>) -> Self {
+        if data.len() <= INLINE_PAYLOAD_SIZE {
+            Self::from_slice(&data)
+        } else {
+            Payload::Heap(data)
+        }
+    }
+}
+
+impl From<&[u8]> for Payload {
+    fn from(data: &[u8]) -> Self {
+        Self::from_slice(data)
+    }
+}
+
+impl core::fmt::Debug for Payload {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.deref().fmt(f)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Message {
     pub sender: ThreadId,
     pub message_type: u32,
-    pub payload: Vec<u8>,
+    pub payload: Payload,
     pub capability: Option<IpcCapability>,
     pub shared_region: Option<RegionId>,
     pub timestamp_ms: u64,
@@ -132,11 +272,11 @@ pub enum IpcCapability {
 }
 
 impl Message {
-    pub fn new(sender: ThreadId, message_type: u32, payload: Vec<u8>) -> Self {
+    pub fn new(sender: ThreadId, message_type: u32, payload: impl Into<Payload>) -> Self {
         Self {
             sender,
             message_type,
-            payload,
+            payload: payload.into(),
             capability: None,
             shared_region: None,
             timestamp_ms: current_time_ms(),
@@ -147,7 +287,7 @@ impl Message {
         Self {
             sender,
             message_type,
-            payload: Vec::new(),
+            payload: Payload::new(),
             capability: None,
             shared_region: Some(region_id),
             timestamp_ms: current_time_ms(),
@@ -157,14 +297,14 @@ impl Message {
     pub fn new_with_grant(
         sender: ThreadId,
         message_type: u32,
-        payload: Vec<u8>,
+        payload: impl Into<Payload>,
         cap_handle: crate::cap::CapHandle,
         permissions: crate::cap::CapPermissions,
     ) -> Self {
         Self {
             sender,
             message_type,
-            payload,
+            payload: payload.into(),
             capability: Some(IpcCapability::Grant {
                 cap_handle,
                 permissions,
@@ -177,13 +317,13 @@ impl Message {
     pub fn new_with_move(
         sender: ThreadId,
         message_type: u32,
-        payload: Vec<u8>,
+        payload: impl Into<Payload>,
         cap_handle: crate::cap::CapHandle,
     ) -> Self {
         Self {
             sender,
             message_type,
-            payload,
+            payload: payload.into(),
             capability: Some(IpcCapability::Move { cap_handle }),
             shared_region: None,
             timestamp_ms: current_time_ms(),
@@ -237,146 +377,91 @@ impl Default for IpcTraceEvent {
     }
 }
 
-struct IpcTraceBuffer {
-    events: [Option<IpcTraceEvent>; IPC_TRACE_RING_SIZE],
-    head: usize,
-    full: bool,
+/// Per-port counters, updated by senders and receivers without a lock
+#[derive(Debug)]
+struct IpcPortMetrics {
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    /// u64::MAX until a message is received
+    min_latency_ms: AtomicU64,
+    max_latency_ms: AtomicU64,
+    total_latency_ms: AtomicU64,
+    /// u64::MAX until a message is seen
+    first_message_timestamp_ms: AtomicU64,
+    last_message_timestamp_ms: AtomicU64,
 }
 
-impl IpcTraceBuffer {
+impl IpcPortMetrics {
     const fn new() -> Self {
         Self {
-            events: [None; IPC_TRACE_RING_SIZE],
-            head: 0,
-            full: false,
+            messages_sent: AtomicU64::new(0),
+            messages_received: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            min_latency_ms: AtomicU64::new(u64::MAX),
+            max_latency_ms: AtomicU64::new(0),
+            total_latency_ms: AtomicU64::new(0),
+            first_message_timestamp_ms: AtomicU64::new(u64::MAX),
+            last_message_timestamp_ms: AtomicU64::new(0),
         }
     }
 
-    fn push(&mut self, event: IpcTraceEvent) {
-        self.events[self.head] = Some(event);
-        self.head = (self.head + 1) % IPC_TRACE_RING_SIZE;
-        if self.head == 0 {
-            self.full = true;
-        }
+    fn record_send(&self, size: usize, timestamp_ms: u64) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(size as u64, Ordering::Relaxed);
+        self.first_message_timestamp_ms.fetch_min(timestamp_ms, Ordering::Relaxed);
+        self.last_message_timestamp_ms.fetch_max(timestamp_ms, Ordering::Relaxed);
     }
 
-    fn snapshot(&self, max_events: usize) -> Vec<IpcTraceEvent> {
-        let total = if self.full {
-            IPC_TRACE_RING_SIZE
-        } else {
-            self.head
-        };
-
-        let mut output = Vec::new();
-        let to_collect = core::cmp::min(total, max_events);
-
-        for i in 0..to_collect {
-            let idx = if self.full {
-                (self.head + i) % IPC_TRACE_RING_SIZE
-            } else {
-                i
-            };
-
-            if let Some(event) = self.events[idx] {
-                output.push(event);
-            }
-        }
-
-        output
-    }
-}
-
-#[derive(Debug, Clone)]
-struct IpcPortMetrics {
-    messages_sent: u64,
-    messages_received: u64,
-    bytes_sent: u64,
-    bytes_received: u64,
-    min_latency_ms: Option<u64>,
-    max_latency_ms: Option<u64>,
-    total_latency_ms: u128,
-    first_message_timestamp_ms: Option<u64>,
-    last_message_timestamp_ms: Option<u64>,
-}
-
-impl Default for IpcPortMetrics {
-    fn default() -> Self {
-        Self {
-            messages_sent: 0,
-            messages_received: 0,
-            bytes_sent: 0,
-            bytes_received: 0,
-            min_latency_ms: None,
-            max_latency_ms: None,
-            total_latency_ms: 0,
-            first_message_timestamp_ms: None,
-            last_message_timestamp_ms: None,
-        }
-    }
-}
-
-impl IpcPortMetrics {
-    fn record_send(&mut self, size: usize, timestamp_ms: u64) {
-        self.messages_sent += 1;
-        self.bytes_sent += size as u64;
-        if self.first_message_timestamp_ms.is_none() {
-            self.first_message_timestamp_ms = Some(timestamp_ms);
-        }
-        self.last_message_timestamp_ms = Some(timestamp_ms);
-    }
-
-    fn record_receive(&mut self, size: usize, send_timestamp_ms: u64, receive_timestamp_ms: u64) {
-        self.messages_received += 1;
-        self.bytes_received += size as u64;
+    fn record_receive(&self, size: usize, send_timestamp_ms: u64, receive_timestamp_ms: u64) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(size as u64, Ordering::Relaxed);
 
         let latency = receive_timestamp_ms.saturating_sub(send_timestamp_ms);
+        self.min_latency_ms.fetch_min(latency, Ordering::Relaxed);
+        self.max_latency_ms.fetch_max(latency, Ordering::Relaxed);
+        self.total_latency_ms.fetch_add(latency, Ordering::Relaxed);
 
-        self.min_latency_ms = Some(match self.min_latency_ms {
-            Some(current_min) => current_min.min(latency),
-            None => latency,
-        });
-
-        self.max_latency_ms = Some(match self.max_latency_ms {
-            Some(current_max) => current_max.max(latency),
-            None => latency,
-        });
-
-        self.total_latency_ms = self.total_latency_ms.saturating_add(latency as u128);
-        if self.first_message_timestamp_ms.is_none() {
-            self.first_message_timestamp_ms = Some(send_timestamp_ms);
-        }
-        self.last_message_timestamp_ms = Some(receive_timestamp_ms);
+        self.first_message_timestamp_ms.fetch_min(send_timestamp_ms, Ordering::Relaxed);
+        self.last_message_timestamp_ms.fetch_max(receive_timestamp_ms, Ordering::Relaxed);
     }
 
+    /// A snapshot of the counters; ones updated meanwhile may be a message apart
     fn to_stats(&self) -> IpcPortStats {
-        let avg_latency_ms = if self.messages_received > 0 {
-            (self.total_latency_ms / self.messages_received as u128) as u64
-        } else {
-            0
+        let messages_received = self.messages_received.load(Ordering::Relaxed);
+        let avg_latency_ms = self
+            .total_latency_ms
+            .load(Ordering::Relaxed)
+            .checked_div(messages_received)
+            .unwrap_or(0);
+
+        let min_latency_ms = match self.min_latency_ms.load(Ordering::Relaxed) {
+            u64::MAX => 0,
+            latency => latency,
         };
+        let max_latency_ms = self.max_latency_ms.load(Ordering::Relaxed);
 
-        let min_latency_ms = self.min_latency_ms.unwrap_or(0);
-        let max_latency_ms = self.max_latency_ms.unwrap_or(0);
-
-        let messages_per_second = if let (Some(first), Some(last)) = (
-            self.first_message_timestamp_ms,
-            self.last_message_timestamp_ms,
-        ) {
+        let first = self.first_message_timestamp_ms.load(Ordering::Relaxed);
+        let last = self.last_message_timestamp_ms.load(Ordering::Relaxed);
+        let messages_per_second = if first != u64::MAX {
             let duration_ms = last.saturating_sub(first).max(1);
-            (self.messages_received.saturating_mul(1000)) / duration_ms
+            (messages_received.saturating_mul(1000)) / duration_ms
         } else {
             0
         };
 
         IpcPortStats {
-            messages_sent: self.messages_sent,
-            messages_received: self.messages_received,
-            bytes_sent: self.bytes_sent,
-            bytes_received: self.bytes_received,
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            messages_received,
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
             min_latency_ms,
             max_latency_ms,
             avg_latency_ms,
             messages_per_second,
+            queued: 0,
         }
     }
 }
@@ -391,27 +476,189 @@ pub struct IpcPortStats {
     pub max_latency_ms: u64,
     pub avg_latency_ms: u64,
     pub messages_per_second: u64,
+    /// Messages waiting in the queue right now
+    pub queued: u64,
 }
 
-#[derive(Debug)]
-struct PortState {
-    id: PortId,
-    owner: ThreadId,
-    messages: VecDeque<Message>,
+/// One slot of a `MessageQueue`
+struct Slot {
+    /// Position this slot is ready for: its index plus a multiple of
+    /// `MAX_QUEUE_DEPTH` when free, one more than that once filled
+    sequence: AtomicUsize,
+    message: UnsafeCell<MaybeUninit<Message>>,
+}
+
+/// Bounded lock-free queue of a port's messages
+///
+/// A ring of `MAX_QUEUE_DEPTH` slots, each carrying a sequence number that
+/// says whether it is ready to be written or read (D. Vyukov's bounded
+/// queue). Any number of threads, and interrupt handlers, may send at
+/// once; receivers may race too, though ports normally have one. Nothing
+/// here spins waiting for another thread, so an interrupt that sends to a
+/// port whose receiver it interrupted cannot deadlock.
+///
+/// Senders reserve room before writing, so a batch that does not fit is
+/// refused outright instead of being queued in part.
+struct MessageQueue {
+    slots: Box<[Slot]>,
+    /// Position of the next slot to write
+    enqueue: AtomicUsize,
+    /// Position of the next slot to read
+    dequeue: AtomicUsize,
+    /// Messages queued or reserved by a sender about to queue them
+    reserved: AtomicUsize,
+}
+
+// Safety: a slot's message is only touched by the one thread that claimed
+// its position, and the sequence numbers order those accesses
+unsafe impl Sync for MessageQueue {}
+unsafe impl Send for MessageQueue {}
+
+impl MessageQueue {
+    fn new() -> Self {
+        let slots = (0..MAX_QUEUE_DEPTH)
+            .map(|index| Slot {
+                sequence: AtomicUsize::new(index),
+                message: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+
+        Self {
+            slots,
+            enqueue: AtomicUsize::new(0),
+            dequeue: AtomicUsize::new(0),
+            reserved: AtomicUsize::new(0),
+        }
+    }
+
+    /// Messages queued, counting ones being sent right now
+    fn len(&self) -> usize {
+        self.reserved.load(Ordering::Acquire)
+    }
+
+    /// Reserve room for `count` messages; false if the queue lacks it
+    fn reserve(&self, count: usize) -> bool {
+        self.reserved
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |reserved| {
+                Some(reserved + count).filter(|&total| total <= MAX_QUEUE_DEPTH)
+            })
+            .is_ok()
+    }
+
+    /// Give back room reserved for messages that will not be sent
+    fn release(&self, count: usize) {
+        self.reserved.fetch_sub(count, Ordering::AcqRel);
+    }
+
+    /// Queue a message into room reserved for it
+    ///
+    /// Fails only while another receiver is still taking the message out
+    /// of the slot needed; the message is dropped and its room released
+    /// rather than waiting.
+    fn push_reserved(&self, message: Message) -> Result<(), IpcError> {
+        let mut position = self.enqueue.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position % MAX_QUEUE_DEPTH];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+
+            if sequence == position {
+                match self.enqueue.compare_exchange_weak(
+                    position,
+                    position.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.message.get()).write(message) };
+                        slot.sequence.store(position.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(current) => position = current,
+                }
+            } else if (sequence.wrapping_sub(position) as isize) < 0 {
+                self.release(1);
+                return Err(IpcError::QueueFull);
+            } else {
+                position = self.enqueue.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn push(&self, message: Message) -> Result<(), IpcError> {
+        if !self.reserve(1) {
+            return Err(IpcError::QueueFull);
+        }
+        self.push_reserved(message)
+    }
+
+    /// Take the oldest message, if any has finished being queued
+    fn pop(&self) -> Option<Message> {
+        let mut position = self.dequeue.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[position % MAX_QUEUE_DEPTH];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let filled = position.wrapping_add(1);
+
+            if sequence == filled {
+                match self.dequeue.compare_exchange_weak(
+                    position,
+                    filled,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let message = unsafe { (*slot.message.get()).assume_init_read() };
+                        slot.sequence
+                            .store(position.wrapping_add(MAX_QUEUE_DEPTH), Ordering::Release);
+                        self.release(1);
+                        return Some(message);
+                    }
+                    Err(current) => position = current,
+                }
+            } else if (sequence.wrapping_sub(filled) as isize) < 0 {
+                return None;
+            } else {
+                position = self.dequeue.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl Drop for MessageQueue {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// Who waits on a port and who watches it; rarely touched, so locked
+#[derive(Debug, Default)]
+struct PortWaiters {
     receiver_blocked: Option<ThreadId>,
     max_waiter_priority: Option<ThreadPriority>,
+    /// Ports notified when this one dies
+    watchers: Vec<PortId>,
+}
+
+/// A port, shared by the port table and every send or receive in flight
+///
+/// Closing a port only takes it out of the table; senders that already
+/// found it finish against this state, and their messages go away with it.
+struct Port {
+    id: PortId,
+    owner: ThreadId,
+    queue: MessageQueue,
     metrics: IpcPortMetrics,
+    waiters: Mutex<PortWaiters>,
 }
 
-impl PortState {
+impl Port {
     fn new(id: PortId, owner: ThreadId) -> Self {
         Self {
             id,
             owner,
-            messages: VecDeque::new(),
-            receiver_blocked: None,
-            max_waiter_priority: None,
-            metrics: IpcPortMetrics::default(),
+            queue: MessageQueue::new(),
+            metrics: IpcPortMetrics::new(),
+            waiters: Mutex::new(PortWaiters::default()),
         }
     }
 }
@@ -422,45 +669,170 @@ struct WaiterInfo {
     deadline: Option<u64>,
 }
 
+/// Part of the port table; read-locked to find a port, write-locked only
+/// to create or close one
+type PortShard = RwLock<BTreeMap<PortId, Arc<Port>>>;
+
 struct IpcManager {
-    ports: Mutex<BTreeMap<PortId, PortState>>,
+    ports: [PortShard; PORT_SHARDS],
     waiting_threads: Mutex<BTreeMap<ThreadId, WaiterInfo>>,
-    trace: Mutex<IpcTraceBuffer>,
+    trace: Mutex<Ring<IpcTraceEvent, IPC_TRACE_RING_SIZE>>,
+    /// Published port names
+    names: Mutex<BTreeMap<String, PortId>>,
+    /// Sender of the message each thread last took with a single receive
+    last_senders: Mutex<BTreeMap<ThreadId, ThreadId>>,
 }
 
 impl IpcManager {
     const fn new() -> Self {
         Self {
-            ports: Mutex::new(BTreeMap::new()),
+            ports: [const { RwLock::new(BTreeMap::new()) }; PORT_SHARDS],
             waiting_threads: Mutex::new(BTreeMap::new()),
-            trace: Mutex::new(IpcTraceBuffer::new()),
+            trace: Mutex::new(Ring::new()),
+            names: Mutex::new(BTreeMap::new()),
+            last_senders: Mutex::new(BTreeMap::new()),
         }
     }
 
+    /// The shard of the port table holding `port_id`
+    fn shard(&self, port_id: PortId) -> &PortShard {
+        &self.ports[(port_id.raw() % PORT_SHARDS as u64) as usize]
+    }
+
+    /// The state of an open port; the table is not held once it returns
+    fn port(&self, port_id: PortId) -> Result<Arc<Port>, IpcError> {
+        self.shard(port_id).read().get(&port_id).cloned().ok_or(IpcError::InvalidPort)
+    }
+
     fn create_port(&self, owner: ThreadId) -> PortId {
         let port_id = PortId::new();
-        let port = PortState::new(port_id, owner);
+        let port = Arc::new(Port::new(port_id, owner));
 
-        self.ports.lock().insert(port_id, port);
+        self.shard(port_id).write().insert(port_id, port);
         port_id
     }
 
     fn port_owner(&self, port_id: PortId) -> Option<ThreadId> {
-        self.ports.lock().get(&port_id).map(|port| port.owner)
+        self.shard(port_id).read().get(&port_id).map(|port| port.owner)
     }
 
     fn close_port(&self, port_id: PortId, caller: ThreadId) -> Result<(), IpcError> {
-        let mut ports = self.ports.lock();
+        let closed = {
+            let mut ports = self.shard(port_id).write();
 
-        if let Some(port) = ports.get(&port_id) {
-            if port.owner != caller {
-                return Err(IpcError::PermissionDenied);
+            match ports.get(&port_id) {
+                Some(port) if port.owner != caller => return Err(IpcError::PermissionDenied),
+                Some(_) => ports.remove(&port_id),
+                None => return Err(IpcError::InvalidPort),
             }
+        };
 
-            ports.remove(&port_id);
-            Ok(())
-        } else {
-            Err(IpcError::InvalidPort)
+        // Notified without the lock held, since sending takes it again
+        if let Some(port) = closed {
+            self.forget_names(port.id);
+            self.notify_death(&port);
+        }
+        Ok(())
+    }
+
+    /// Close every port `owner` holds and forget what it received, as when
+    /// it exits
+    fn close_owned_ports(&self, owner: ThreadId) {
+        let mut closed: Vec<Arc<Port>> = Vec::new();
+        for shard in &self.ports {
+            let mut ports = shard.write();
+            let owned: Vec<PortId> = ports
+                .values()
+                .filter(|port| port.owner == owner)
+                .map(|port| port.id)
+                .collect();
+            closed.extend(owned.iter().filter_map(|id| ports.remove(id)));
+        }
+
+        if !closed.is_empty() {
+            log_debug!(
+                LOG_ORIGIN,
+                "Closed {} port(s) of exiting thread {}",
+                closed.len(),
+                owner
+            );
+        }
+        for port in &closed {
+            self.forget_names(port.id);
+            self.notify_death(port);
+        }
+        self.last_senders.lock().remove(&owner);
+    }
+
+    /// Have `notify` told when `port_id` dies; the caller must own `notify`
+    ///
+    /// The two ports may sit in different shards, which are not held
+    /// together; if `notify` closes in between, its notice is just dropped.
+    fn watch_port(&self, port_id: PortId, notify: PortId, caller: ThreadId) -> Result<(), IpcError> {
+        match self.port_owner(notify) {
+            Some(owner) if owner != caller => return Err(IpcError::PermissionDenied),
+            Some(_) => {}
+            None => return Err(IpcError::InvalidPort),
+        }
+
+        let port = self.port(port_id)?;
+        let mut waiters = port.waiters.lock();
+        if !waiters.watchers.contains(&notify) {
+            waiters.watchers.push(notify);
+        }
+        Ok(())
+    }
+
+    /// Publish `port_id` as `name`; the caller must own the port, and the
+    /// name must not belong to another one
+    fn register_name(&self, name: &str, port_id: PortId, caller: ThreadId) -> Result<(), IpcError> {
+        if self.port_owner(port_id) != Some(caller) {
+            return Err(IpcError::PermissionDenied);
+        }
+
+        let mut names = self.names.lock();
+        match names.get(name) {
+            Some(&owner) if owner != port_id => Err(IpcError::PortBusy),
+            Some(_) => Ok(()),
+            None => {
+                names.insert(String::from(name), port_id);
+                Ok(())
+            }
+        }
+    }
+
+    fn lookup_name(&self, name: &str) -> Option<PortId> {
+        self.names.lock().get(name).copied()
+    }
+
+    /// Drop the names of a port that was closed
+    fn forget_names(&self, port_id: PortId) {
+        self.names.lock().retain(|_, port| *port != port_id);
+    }
+
+    /// Send the port-death notification for `port` to its watchers; ones
+    /// that are gone or full are skipped
+    fn notify_death(&self, port: &Port) {
+        let watchers = core::mem::take(&mut port.waiters.lock().watchers);
+        for watcher in watchers {
+            let mut payload = Vec::with_capacity(24);
+            payload.extend_from_slice(&MSG_MAGIC.to_le_bytes());
+            payload.extend_from_slice(&MSG_PROTOCOL_VERSION.to_le_bytes());
+            payload.extend_from_slice(&MSG_TYPE_PORT_DIED.to_le_bytes());
+            payload.extend_from_slice(&8u32.to_le_bytes());
+            payload.extend_from_slice(&0u32.to_le_bytes());
+            payload.extend_from_slice(&port.id.raw().to_le_bytes());
+
+            let message = Message::new(port.owner, MSG_TYPE_PORT_DIED, payload);
+            if let Err(e) = self.send(watcher, message) {
+                log_debug!(
+                    LOG_ORIGIN,
+                    "Port death notice for {:?} not delivered to {:?}: {}",
+                    port.id,
+                    watcher,
+                    e
+                );
+            }
         }
     }
     
@@ -491,16 +863,10 @@ impl IpcManager {
     }
 
     fn send(&self, port_id: PortId, mut message: Message) -> Result<(), IpcError> {
-        let mut ports = self.ports.lock();
-
-        let port = ports.get_mut(&port_id).ok_or(IpcError::InvalidPort)?;
+        let port = self.port(port_id)?;
 
         let size = self.validate_payload_and_size(&message)?;
 
-        if port.messages.len() >= MAX_QUEUE_DEPTH {
-            return Err(IpcError::QueueFull);
-        }
-
         if message.timestamp_ms == 0 {
             message.timestamp_ms = current_time_ms();
         }
@@ -508,7 +874,7 @@ impl IpcManager {
         let timestamp_ms = message.timestamp_ms;
         let sender = message.sender;
 
-        port.messages.push_back(message);
+        port.queue.push(message)?;
         port.metrics.record_send(size, timestamp_ms);
         self.record_trace_event(IpcTraceEvent {
             timestamp_ms,
@@ -522,6 +888,13 @@ impl IpcManager {
         Ok(())
     }
 
+    /// Queue the messages in order; returns how many were queued
+    ///
+    /// Room for the whole batch is reserved first, so a port too full for
+    /// it gets none of it (QueueFull). Past that point the send can still
+    /// stop early: if a receiver is mid-way through taking a message out of
+    /// a slot the batch needs, the messages before it stay queued and the
+    /// rest are dropped, which the count falling short of the batch shows.
     fn send_batch(&self, port_id: PortId, messages: Vec<Message>) -> Result<usize, IpcError> {
         if messages.is_empty() {
             return Ok(0);
@@ -531,8 +904,7 @@ impl IpcManager {
             return Err(IpcError::BatchTooLarge);
         }
 
-        let mut ports = self.ports.lock();
-        let port = ports.get_mut(&port_id).ok_or(IpcError::InvalidPort)?;
+        let port = self.port(port_id)?;
 
         let mut prepared = Vec::with_capacity(messages.len());
         for mut msg in messages {
@@ -545,15 +917,22 @@ impl IpcManager {
             prepared.push((msg, size));
         }
 
-        if port.messages.len() + prepared.len() > MAX_QUEUE_DEPTH {
+        if !port.queue.reserve(prepared.len()) {
             return Err(IpcError::QueueFull);
         }
 
-        let count = prepared.len();
+        let mut count = 0;
+        let total = prepared.len();
         for (msg, size) in prepared {
             let timestamp_ms = msg.timestamp_ms;
             let sender = msg.sender;
 
+            if port.queue.push_reserved(msg).is_err() {
+                port.queue.release(total - count - 1);
+                break;
+            }
+            count += 1;
+
             port.metrics.record_send(size, timestamp_ms);
             self.record_trace_event(IpcTraceEvent {
                 timestamp_ms,
@@ -563,14 +942,17 @@ impl IpcManager {
                 receiver: None,
                 size,
             });
-
-            port.messages.push_back(msg);
         }
 
-        if let Some(receiver_id) = port.receiver_blocked.take() {
-            port.max_waiter_priority = None;
-            drop(ports);
-
+        let receiver = {
+            let mut waiters = port.waiters.lock();
+            let receiver = waiters.receiver_blocked.take();
+            if receiver.is_some() {
+                waiters.max_waiter_priority = None;
+            }
+            receiver
+        };
+        if let Some(receiver_id) = receiver {
             self.waiting_threads.lock().remove(&receiver_id);
             crate::sched::mark_thread_ready(receiver_id);
             self.restore_priority(receiver_id);
@@ -578,18 +960,17 @@ impl IpcManager {
 
         Ok(count)
     }
-    
+
     fn recv_batch(&self, port_id: PortId, caller: ThreadId, max_count: usize)
         -> Result<Vec<Message>, IpcError>
     {
         let max_count = core::cmp::min(max_count, MAX_BATCH_SIZE);
-        let mut ports = self.ports.lock();
-        let port = ports.get_mut(&port_id).ok_or(IpcError::InvalidPort)?;
+        let port = self.port(port_id)?;
 
         let mut messages = Vec::new();
 
         for _ in 0..max_count {
-            if let Some(msg) = port.messages.pop_front() {
+            if let Some(msg) = port.queue.pop() {
                 let receive_timestamp_ms = current_time_ms();
                 let size = self.resolve_message_size(&msg)?;
                 port
@@ -610,15 +991,15 @@ impl IpcManager {
                 break;
             }
         }
+        // No single message to answer for
+        self.last_senders.lock().remove(&caller);
         Ok(messages)
     }
 
     fn try_recv(&self, port_id: PortId, caller: ThreadId) -> Result<Option<Message>, IpcError> {
-        let mut ports = self.ports.lock();
-
-        let port = ports.get_mut(&port_id).ok_or(IpcError::InvalidPort)?;
+        let port = self.port(port_id)?;
 
-        if let Some(msg) = port.messages.pop_front() {
+        if let Some(msg) = port.queue.pop() {
             let receive_timestamp_ms = current_time_ms();
             let size = self.resolve_message_size(&msg)?;
 
@@ -634,6 +1015,7 @@ impl IpcManager {
                 receiver: Some(caller),
                 size,
             });
+            self.last_senders.lock().insert(caller, msg.sender);
 
             Ok(Some(msg))
         } else {
@@ -648,13 +1030,7 @@ impl IpcManager {
         caller_priority: ThreadPriority,
         deadline: Option<u64>,
     ) -> Result<(), IpcError> {
-        {
-            let ports = self.ports.lock();
-
-            if !ports.contains_key(&port_id) {
-                return Err(IpcError::InvalidPort);
-            }
-        }
+        let port = self.port(port_id)?;
 
         if CONFIG_DEADLOCK_DETECT && self.detect_deadlock(caller, port_id) {
             log_warn!(
@@ -666,22 +1042,21 @@ impl IpcManager {
             return Err(IpcError::DeadlockDetected);
         }
 
-        let mut ports = self.ports.lock();
-        let port = ports.get_mut(&port_id).ok_or(IpcError::InvalidPort)?;
+        let mut waiters = port.waiters.lock();
 
-        if port.receiver_blocked.is_some() {
+        if waiters.receiver_blocked.is_some() {
             return Err(IpcError::PortBusy);
         }
 
-        port.receiver_blocked = Some(caller);
+        waiters.receiver_blocked = Some(caller);
 
-        port.max_waiter_priority = Some(
-            port.max_waiter_priority
+        waiters.max_waiter_priority = Some(
+            waiters.max_waiter_priority
                 .map(|p| p.max(caller_priority))
                 .unwrap_or(caller_priority)
         );
 
-        drop(ports);
+        drop(waiters);
         self.waiting_threads
             .lock()
             .insert(caller, WaiterInfo { port: port_id, deadline });
@@ -690,26 +1065,25 @@ impl IpcManager {
     }
     
     fn get_max_waiter_priority(&self, port_id: PortId) -> Option<ThreadPriority> {
-        self.ports
-            .lock()
-            .get(&port_id)
-            .and_then(|p| p.max_waiter_priority)
+        self.port(port_id)
+            .ok()
+            .and_then(|port| port.waiters.lock().max_waiter_priority)
     }
 
     fn detect_deadlock(&self, start: ThreadId, target_port: PortId) -> bool {
-        let ports = self.ports.lock();
         let waiting = self.waiting_threads.lock();
 
         let mut current_port = Some(target_port);
         let mut steps = 0usize;
 
         while let Some(port_id) = current_port {
-            if steps > ports.len() + waiting.len() {
+            // Every step follows a waiting thread, so a longer chain loops
+            if steps > waiting.len() {
                 break;
             }
 
-            let owner = match ports.get(&port_id) {
-                Some(port) => port.owner,
+            let owner = match self.port_owner(port_id) {
+                Some(owner) => owner,
                 None => break,
             };
 
@@ -742,14 +1116,12 @@ impl IpcManager {
             return;
         }
 
-        {
-            let mut ports = self.ports.lock();
-            for (tid, port_id) in &expired {
-                if let Some(port) = ports.get_mut(port_id) {
-                    if port.receiver_blocked == Some(*tid) {
-                        port.receiver_blocked = None;
-                        port.max_waiter_priority = None;
-                    }
+        for (tid, port_id) in &expired {
+            if let Ok(port) = self.port(*port_id) {
+                let mut waiters = port.waiters.lock();
+                if waiters.receiver_blocked == Some(*tid) {
+                    waiters.receiver_blocked = None;
+                    waiters.max_waiter_priority = None;
                 }
             }
         }
@@ -774,6 +1146,16 @@ impl IpcManager {
             trace.push(event);
         }
 
+        let receiver = event.receiver.map_or(0, |id| id.raw());
+        match event.kind {
+            IpcEventKind::Send => crate::trace_event!(
+                ipc, SEND, event.port.raw(), event.sender.raw(), event.size
+            ),
+            IpcEventKind::Receive => crate::trace_event!(
+                ipc, RECV, event.port.raw(), event.sender.raw(), receiver, event.size
+            ),
+        }
+
         match event.kind {
             IpcEventKind::Send => log_debug!(
                 LOG_ORIGIN,
@@ -803,21 +1185,26 @@ impl IpcManager {
     }
 
     fn port_stats(&self, port_id: PortId) -> Result<IpcPortStats, IpcError> {
-        let ports = self.ports.lock();
-        let port = ports.get(&port_id).ok_or(IpcError::InvalidPort)?;
-        Ok(port.metrics.to_stats())
+        let port = self.port(port_id)?;
+        Ok(IpcPortStats {
+            queued: port.queue.len() as u64,
+            ..port.metrics.to_stats()
+        })
     }
 
     fn get_stats(&self) -> IpcStats {
-        let ports = self.ports.lock();
-        let waiting = self.waiting_threads.lock();
-
-        let total_messages: usize = ports.values().map(|p| p.messages.len()).sum();
+        let mut total_ports = 0;
+        let mut total_messages = 0;
+        for shard in &self.ports {
+            let ports = shard.read();
+            total_ports += ports.len();
+            total_messages += ports.values().map(|p| p.queue.len()).sum::<usize>();
+        }
 
         IpcStats {
-            total_ports: ports.len(),
+            total_ports,
             total_messages,
-            blocked_threads: waiting.len(),
+            blocked_threads: self.waiting_threads.lock().len(),
         }
     }
 }
@@ -891,6 +1278,13 @@ pub fn init() {
         MAX_BATCH_SIZE
     );
 
+    log_info!(
+        LOG_ORIGIN,
+        "Fast path: payloads up to {}B kept inline, port table in {} shards, lock-free queues",
+        INLINE_PAYLOAD_SIZE,
+        PORT_SHARDS
+    );
+
     log_info!(
         LOG_ORIGIN,
         "Phase 4.6 safeguards: bounded queues ({} messages) and timeout-aware waiters",
@@ -917,6 +1311,22 @@ pub fn close_port(port_id: PortId, caller: ThreadId) -> Result<(), IpcError> {
     IPC_MANAGER.close_port(port_id, caller)
 }
 
+pub fn close_owned_ports(owner: ThreadId) {
+    IPC_MANAGER.close_owned_ports(owner)
+}
+
+pub fn watch_port(port_id: PortId, notify: PortId, caller: ThreadId) -> Result<(), IpcError> {
+    IPC_MANAGER.watch_port(port_id, notify, caller)
+}
+
+pub fn register_name(name: &str, port_id: PortId, caller: ThreadId) -> Result<(), IpcError> {
+    IPC_MANAGER.register_name(name, port_id, caller)
+}
+
+pub fn lookup_name(name: &str) -> Option<PortId> {
+    IPC_MANAGER.lookup_name(name)
+}
+
 pub fn send_message(port_id: PortId, message: Message) -> Result<(), IpcError> {
     IPC_MANAGER.send(port_id, message)
 }
@@ -929,6 +1339,12 @@ pub fn try_receive_message(port_id: PortId, caller: ThreadId) -> Result<Option<M
     IPC_MANAGER.try_recv(port_id, caller)
 }
 
+/// Sender of the message `receiver` last took with `try_receive_message`,
+/// unless it has received a batch since
+pub fn last_sender(receiver: ThreadId) -> Option<ThreadId> {
+    IPC_MANAGER.last_senders.lock().get(&receiver).copied()
+}
+
 pub fn block_receive(
     port_id: PortId,
     caller: ThreadId,
@@ -30,7 +30,12 @@
 // - Boot-provided structures are treated as immutable
 // - Kernel stacks and critical mappings are explicitly validated
 // - The system does not continue if the init process fails
-// - Panic handler halts the CPU to avoid undefined behavior
+// - Panic handler draws the crash screen (see `crash`) and halts the CPU
+//   to avoid undefined behavior
+// - With the `ktest` feature, in-kernel tests run in place of init and
+//   the result is reported to QEMU (see `ktest`)
+// - With the `gdb` boot parameter, boot stops for GDB on COM1 as soon as
+//   exception handlers are installed (see `gdbstub`)
 //
 // Limitations and future considerations:
 // - Initialization is single-core and non-parallel
@@ -59,6 +64,8 @@ mod interrupts;
 mod input;  // Minimal input buffer for userspace drivers
 mod log;
 mod graphics;
+mod dispi;
+mod pci;
 mod thread;
 mod sched;
 mod syscall;
@@ -67,9 +74,17 @@ mod cap;
 mod shared_mem;
 mod system;
 mod executable;
+mod ed25519;
 mod init_process;
 mod service_manager;
+mod rtc;
 mod util;
+mod gdbstub;
+mod crash;
+mod trace;
+mod profile;
+#[cfg(feature = "ktest")]
+mod ktest;
 
 // Microkernel architecture: All UI components run in userspace.
 // See userspace/ for desktop environment, drivers, and applications.
@@ -78,7 +93,7 @@ mod util;
 #[path = "../../arch/x86_64/uefi.rs"]
 mod uefi;
 
-use crate::arch::{current_rsp, halt, read_cr3};
+use crate::arch::{current_rsp, enable_smep_smap, enable_sse, halt, read_cr3};
 use crate::arch::gdt;
 use crate::boot::{BootInfo, MemoryMap};
 use core::panic::PanicInfo;
@@ -105,30 +120,49 @@ pub unsafe extern "C" fn kmain(boot_info: &'static BootInfo) -> ! {
     }
 
     serial::init();
-    system::init(boot_info.cpu, boot_info.boot_method);
+    system::init(boot_info.cpu, boot_info.boot_method, boot_info.command_line);
 
     log_info!(LOG_KERNEL_INIT, "{}", build_info::BOOT_BANNER);
+    if boot_info.command_line.len > 0 {
+        log_info!(LOG_KERNEL_INIT, "Boot parameters: {}", boot_info.command_line.as_str());
+    }
 
     vga::init();
     mm::init(&boot_info.memory_map);
 
     if boot_info.framebuffer_present {
         let fb = &boot_info.framebuffer;
-        if mm::vm::map_framebuffer(fb.address, fb.size) {
-            graphics::init(fb);
+        // With a mode-setting backend, map all of video memory so larger
+        // modes fit in the same mapping
+        dispi::init(fb.width, fb.height);
+        let mapped_size = dispi::vram_size().map_or(fb.size, |vram| vram.max(fb.size));
+        if mm::vm::map_framebuffer(fb.address, mapped_size) {
+            graphics::init(fb, &boot_info.video_modes, mapped_size);
             graphics::init_terminal();
         }
     }
 
     gdt::init(current_rsp());
     mm::vm::ensure_current_stack_mapped(64);
+    // User threads may use SSE; the scheduler switches its registers
+    enable_sse();
 
     log::init();
+    crash::init();
     if boot_info.verbose {
         log::set_level(log::LogLevel::Debug);
         log::enable_vga_output();
     }
 
+    // From here on, user memory is only touched through mm::usercopy
+    let (smep, smap) = enable_smep_smap();
+    log_info!(
+        LOG_KERNEL_INIT,
+        "SMEP {}, SMAP {}",
+        if smep { "enabled" } else { "not supported" },
+        if smap { "enabled" } else { "not supported" }
+    );
+
     display_uefi_memory_map(&boot_info.memory_map);
     display_memory_stats();
 
@@ -137,10 +171,13 @@ pub unsafe extern "C" fn kmain(boot_info: &'static BootInfo) -> ! {
     cap::init();
 
     interrupts::init();
+    gdbstub::init();
     interrupts::init_timer(100);
+    trace::init();
 
     log_info!(LOG_APIC, "Enabling interrupts...");
     interrupts::enable();
+    rtc::init();
 
     // Initialize input subsystem (minimal kernel-side buffer for userspace drivers)
     input::init();
@@ -150,6 +187,10 @@ pub unsafe extern "C" fn kmain(boot_info: &'static BootInfo) -> ! {
     ipc::init();
     shared_mem::init();
 
+    // Test kernels run their suites here and exit QEMU instead of booting
+    #[cfg(feature = "ktest")]
+    ktest::run();
+
     log_info!(LOG_INIT_PROC, "Calling init_process::launch_init()...");
     match init_process::launch_init(boot_info) {
         Ok(init) => {
@@ -238,7 +279,9 @@ fn display_memory_stats() {
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     log_error!("PANIC", "{}", info);
-    loop {
-        halt();
-    }
+
+    #[cfg(feature = "ktest")]
+    ktest::on_panic(info);
+
+    crash::panic(info)
 }
\ No newline at end of file
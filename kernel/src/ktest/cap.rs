@@ -0,0 +1,162 @@
+// Capability Tests
+//
+// Covers permission narrowing on derivation, per-thread capability tables,
+// the global derivation tree: transfer, revocation and auditing, and the
+// capabilities a service's manifest entry grants it.
+
+use alloc::string::String;
+
+use crate::cap::{self, AuditEventType, CapError, CapPermissions, Capability, ResourceType};
+use crate::ktest::{kernel_thread, TestResult};
+use crate::service_manager;
+use crate::thread::{self, ThreadId, ThreadPriority};
+
+tests![
+    derive_only_narrows,
+    table_checks_permissions,
+    revoke_takes_descendants,
+    transfer_needs_grant,
+    audit_records_derivation,
+    manifest_grants_kernel_caps,
+];
+
+const READ_WRITE: CapPermissions = CapPermissions::READ.union(CapPermissions::WRITE);
+const READ_GRANT: CapPermissions = CapPermissions::READ.union(CapPermissions::GRANT);
+
+fn port_resource(port_id: u64) -> ResourceType {
+    ResourceType::IpcPort { port_id }
+}
+
+/// A thread registered with the thread list, so it can hold capabilities
+fn holder(name: &'static str) -> ThreadId {
+    let holder = kernel_thread(name, ThreadPriority::Low);
+    let id = holder.id();
+    thread::add_thread(holder);
+    id
+}
+
+fn derive_only_narrows() -> TestResult {
+    let owner = ThreadId::new();
+    let mut root = Capability::new_root(port_resource(1), owner, READ_WRITE);
+
+    let child = kassert_ok!(root.derive(ThreadId::new(), CapPermissions::READ));
+    kassert_eq!(child.parent, Some(root.handle));
+    kassert_eq!(child.resource, root.resource);
+    kassert!(root.children.contains(&child.handle));
+    kassert!(child.has_permission(CapPermissions::READ));
+    kassert!(!child.has_permission(CapPermissions::WRITE));
+
+    kassert_eq!(
+        root.derive(owner, CapPermissions::EXECUTE).err(),
+        Some(CapError::PermissionDenied)
+    );
+    kassert_eq!(root.children.len(), 1);
+    Ok(())
+}
+
+fn table_checks_permissions() -> TestResult {
+    let owner = ThreadId::new();
+    let mut table = cap::create_capability_table(owner);
+    let capability = Capability::new_root(port_resource(2), owner, CapPermissions::READ);
+    let handle = kassert_ok!(table.insert(capability.clone()));
+
+    kassert_eq!(table.insert(capability).err(), Some(CapError::AlreadyExists));
+    kassert!(table.validate(handle, CapPermissions::READ).is_ok());
+    kassert_eq!(
+        table.validate(handle, CapPermissions::WRITE).err(),
+        Some(CapError::PermissionDenied)
+    );
+
+    kassert!(table.remove(handle).is_some());
+    kassert_eq!(table.validate(handle, CapPermissions::READ).err(), Some(CapError::NotFound));
+    kassert_eq!(table.count(), 0);
+    Ok(())
+}
+
+fn revoke_takes_descendants() -> TestResult {
+    let owner = holder("ktest-cap-owner");
+    let delegate = holder("ktest-cap-delegate");
+
+    let root = kassert_ok!(cap::create_root_capability(
+        port_resource(3),
+        owner,
+        READ_WRITE.union(CapPermissions::GRANT)
+    ));
+    kassert_ok!(thread::add_thread_capability(owner, root.clone()));
+
+    let child = kassert_ok!(cap::derive_capability(root.handle, owner, delegate, READ_GRANT));
+    let grandchild =
+        kassert_ok!(cap::derive_capability(child, delegate, owner, CapPermissions::READ));
+    kassert_eq!(
+        cap::derive_capability(child, delegate, owner, CapPermissions::WRITE),
+        Err(CapError::PermissionDenied)
+    );
+    kassert_eq!(kassert_ok!(cap::query_parent(grandchild)), Some(child));
+    kassert_eq!(kassert_ok!(cap::query_children(root.handle)), [child]);
+
+    let revoked = kassert_ok!(cap::revoke_capability(root.handle, owner));
+    kassert_eq!(revoked, [root.handle, child, grandchild]);
+    for handle in revoked {
+        kassert!(cap::lookup_capability(handle).is_none(), "{} outlived revocation", handle);
+    }
+    kassert!(!thread::thread_has_capability(owner, root.handle));
+    kassert!(!thread::thread_has_capability(delegate, child));
+    Ok(())
+}
+
+fn transfer_needs_grant() -> TestResult {
+    let from = holder("ktest-cap-from");
+    let to = holder("ktest-cap-to");
+
+    let kept = kassert_ok!(cap::create_root_capability(port_resource(4), from, READ_WRITE));
+    kassert_ok!(thread::add_thread_capability(from, kept.clone()));
+    kassert_eq!(cap::transfer_capability(kept.handle, from, to), Err(CapError::PermissionDenied));
+    kassert!(thread::thread_has_capability(from, kept.handle));
+
+    let given = kassert_ok!(cap::create_root_capability(port_resource(5), from, READ_GRANT));
+    kassert_ok!(thread::add_thread_capability(from, given.clone()));
+    kassert_ok!(cap::transfer_capability(given.handle, from, to));
+    kassert!(!thread::thread_has_capability(from, given.handle));
+    kassert!(thread::thread_has_capability(to, given.handle));
+    kassert_eq!(cap::lookup_capability(given.handle).map(|c| c.owner), Some(to));
+
+    kassert_eq!(cap::transfer_capability(given.handle, from, to), Err(CapError::NotFound));
+    Ok(())
+}
+
+fn audit_records_derivation() -> TestResult {
+    let owner = holder("ktest-cap-audit");
+    let root = kassert_ok!(cap::create_root_capability(port_resource(6), owner, READ_GRANT));
+    kassert_ok!(thread::add_thread_capability(owner, root.clone()));
+
+    let child =
+        kassert_ok!(cap::derive_capability(root.handle, owner, owner, CapPermissions::READ));
+    let log = cap::get_audit_log(2);
+    kassert_eq!(log.len(), 2);
+    kassert_eq!(log[0].event_type, AuditEventType::Derive);
+    kassert_eq!(log[0].cap_handle, child);
+    kassert_eq!(log[0].parent_handle, Some(root.handle));
+    kassert_eq!(log[1].event_type, AuditEventType::Create);
+    kassert_eq!(log[1].cap_handle, root.handle);
+    Ok(())
+}
+
+fn manifest_grants_kernel_caps() -> TestResult {
+    let service = holder("ktest-cap-service");
+    let names =
+        ["DMABufferCap", "IRQCap:33", "ScreenshotCap", "FrameBufferCap", "DebugCap", "IPCPortCap"]
+            .map(String::from);
+    service_manager::grant_capabilities(service, &names);
+
+    let holds = |filter: fn(&ResourceType) -> bool| {
+        thread::validate_thread_capability_by_type(service, CapPermissions::WRITE, filter)
+    };
+    kassert!(holds(|r| matches!(r, ResourceType::DmaBuffer { .. })));
+    kassert!(holds(|r| matches!(r, ResourceType::Irq { irq_num: 33 })));
+    kassert!(!holds(|r| matches!(r, ResourceType::Irq { irq_num: 34 })));
+    kassert!(holds(|r| *r == ResourceType::Screenshot));
+    kassert!(holds(|r| *r == ResourceType::Framebuffer));
+    kassert!(holds(|r| *r == ResourceType::Debug));
+    kassert!(!holds(|r| matches!(r, ResourceType::Device { .. })));
+    Ok(())
+}
@@ -0,0 +1,209 @@
+// IPC Tests
+//
+// Covers port queues, inline and heap payloads, payload limits, batching, port ownership, names,
+// death notices and who sent the last message, driven through the same calls the syscalls make.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::ipc::{self, IpcError, Message, Payload, PortId};
+use crate::ipc::{INLINE_PAYLOAD_SIZE, MAX_BATCH_SIZE, MAX_MESSAGE_SIZE, MAX_QUEUE_DEPTH};
+use crate::ipc::ZERO_COPY_THRESHOLD;
+use crate::ktest::TestResult;
+use crate::thread::ThreadId;
+
+tests![
+    delivers_in_order,
+    keeps_small_payloads_inline,
+    limits_payload_size,
+    bounds_the_queue,
+    batches,
+    only_owner_closes,
+    names_follow_their_port,
+    watchers_hear_of_death,
+    remembers_last_sender,
+];
+
+fn message(sender: ThreadId, message_type: u32) -> Message {
+    Message::new(sender, message_type, vec![message_type as u8; 8])
+}
+
+fn delivers_in_order() -> TestResult {
+    let owner = ThreadId::new();
+    let port = ipc::create_port(owner);
+
+    for message_type in 1..=3 {
+        kassert_ok!(ipc::send_message(port, message(owner, message_type)));
+    }
+    for message_type in 1..=3 {
+        let received = kassert_ok!(ipc::try_receive_message(port, owner));
+        let received = kassert_ok!(received.ok_or("queue ran dry"));
+        kassert_eq!(received.message_type, message_type);
+        kassert_eq!(*received.payload, [message_type as u8; 8]);
+    }
+    kassert!(kassert_ok!(ipc::try_receive_message(port, owner)).is_none());
+
+    let stats = kassert_ok!(ipc::get_port_stats(port));
+    kassert_eq!(stats.messages_sent, 3);
+    kassert_eq!(stats.messages_received, 3);
+    kassert_ok!(ipc::close_port(port, owner));
+    Ok(())
+}
+
+fn keeps_small_payloads_inline() -> TestResult {
+    let owner = ThreadId::new();
+    let port = ipc::create_port(owner);
+
+    kassert!(Payload::from(vec![1; INLINE_PAYLOAD_SIZE]).is_inline());
+    kassert!(!Payload::from(vec![1; INLINE_PAYLOAD_SIZE + 1]).is_inline());
+
+    // Both kinds arrive intact
+    for len in [3, INLINE_PAYLOAD_SIZE + 1] {
+        let sent: Vec<u8> = (0..len as u8).collect();
+        kassert_ok!(ipc::send_message(port, Message::new(owner, 1, &sent[..])));
+        let received = kassert_ok!(ipc::try_receive_message(port, owner));
+        let received = kassert_ok!(received.ok_or("queue ran dry"));
+        kassert_eq!(*received.payload, *sent);
+    }
+
+    kassert_ok!(ipc::close_port(port, owner));
+    Ok(())
+}
+
+fn limits_payload_size() -> TestResult {
+    let owner = ThreadId::new();
+    let port = ipc::create_port(owner);
+    let sized = |len| Message::new(owner, 1, vec![0; len]);
+
+    kassert_ok!(ipc::send_message(port, sized(ZERO_COPY_THRESHOLD)));
+    kassert_eq!(
+        ipc::send_message(port, sized(ZERO_COPY_THRESHOLD + 1)),
+        Err(IpcError::RequiresSharedMemory)
+    );
+    kassert_eq!(
+        ipc::send_message(port, sized(MAX_MESSAGE_SIZE + 1)),
+        Err(IpcError::MessageTooLarge)
+    );
+    let nowhere = PortId::from_raw(u64::MAX);
+    kassert_eq!(ipc::send_message(nowhere, sized(1)), Err(IpcError::InvalidPort));
+
+    kassert_ok!(ipc::close_port(port, owner));
+    Ok(())
+}
+
+fn bounds_the_queue() -> TestResult {
+    let owner = ThreadId::new();
+    let port = ipc::create_port(owner);
+
+    for sequence in 0..MAX_QUEUE_DEPTH {
+        kassert_ok!(ipc::send_message(port, message(owner, sequence as u32)));
+    }
+    kassert_eq!(ipc::send_message(port, message(owner, 0)), Err(IpcError::QueueFull));
+
+    // Draining one makes room for one
+    kassert_ok!(ipc::try_receive_message(port, owner));
+    kassert_ok!(ipc::send_message(port, message(owner, 0)));
+    kassert_eq!(kassert_ok!(ipc::get_port_stats(port)).queued, MAX_QUEUE_DEPTH as u64);
+
+    kassert_ok!(ipc::close_port(port, owner));
+    Ok(())
+}
+
+fn batches() -> TestResult {
+    let owner = ThreadId::new();
+    let port = ipc::create_port(owner);
+
+    let batch: Vec<Message> = (0..4).map(|message_type| message(owner, message_type)).collect();
+    kassert_eq!(ipc::send_batch(port, batch), Ok(4));
+
+    let too_many = (0..=MAX_BATCH_SIZE as u32).map(|message_type| message(owner, message_type));
+    kassert_eq!(ipc::send_batch(port, too_many.collect()), Err(IpcError::BatchTooLarge));
+
+    let received = kassert_ok!(ipc::receive_batch(port, owner, 16));
+    let types: Vec<u32> = received.iter().map(|message| message.message_type).collect();
+    kassert_eq!(types, [0u32, 1, 2, 3]);
+
+    kassert_ok!(ipc::close_port(port, owner));
+    Ok(())
+}
+
+fn only_owner_closes() -> TestResult {
+    let owner = ThreadId::new();
+    let port = ipc::create_port(owner);
+
+    kassert_eq!(ipc::get_port_owner(port), Some(owner));
+    kassert_eq!(ipc::close_port(port, ThreadId::new()), Err(IpcError::PermissionDenied));
+    kassert_ok!(ipc::close_port(port, owner));
+
+    kassert_eq!(ipc::get_port_owner(port), None);
+    kassert_eq!(ipc::close_port(port, owner), Err(IpcError::InvalidPort));
+    kassert_eq!(ipc::send_message(port, message(owner, 1)), Err(IpcError::InvalidPort));
+    Ok(())
+}
+
+fn names_follow_their_port() -> TestResult {
+    let owner = ThreadId::new();
+    let port = ipc::create_port(owner);
+    let other = ipc::create_port(owner);
+
+    kassert_eq!(
+        ipc::register_name("ktest.names", port, ThreadId::new()),
+        Err(IpcError::PermissionDenied)
+    );
+    kassert_ok!(ipc::register_name("ktest.names", port, owner));
+    kassert_eq!(ipc::lookup_name("ktest.names"), Some(port));
+    kassert_eq!(ipc::register_name("ktest.names", other, owner), Err(IpcError::PortBusy));
+
+    kassert_ok!(ipc::close_port(port, owner));
+    kassert_eq!(ipc::lookup_name("ktest.names"), None);
+    kassert_ok!(ipc::register_name("ktest.names", other, owner));
+
+    kassert_ok!(ipc::close_port(other, owner));
+    Ok(())
+}
+
+fn watchers_hear_of_death() -> TestResult {
+    let watcher = ThreadId::new();
+    let watched_owner = ThreadId::new();
+    let notify = ipc::create_port(watcher);
+    let watched = ipc::create_port(watched_owner);
+
+    kassert_eq!(
+        ipc::watch_port(watched, notify, watched_owner),
+        Err(IpcError::PermissionDenied)
+    );
+    kassert_ok!(ipc::watch_port(watched, notify, watcher));
+    ipc::close_owned_ports(watched_owner);
+
+    let notice = kassert_ok!(ipc::try_receive_message(notify, watcher));
+    let notice = kassert_ok!(notice.ok_or("no death notice"));
+    kassert_eq!(notice.sender, watched_owner);
+    // The dead port's id ends the payload
+    let id = notice.payload.len().checked_sub(8).map(|at| &notice.payload[at..]);
+    kassert_eq!(id, Some(&watched.raw().to_le_bytes()[..]));
+
+    kassert_ok!(ipc::close_port(notify, watcher));
+    Ok(())
+}
+
+fn remembers_last_sender() -> TestResult {
+    let owner = ThreadId::new();
+    let (first, second) = (ThreadId::new(), ThreadId::new());
+    let port = ipc::create_port(owner);
+
+    kassert_eq!(ipc::last_sender(owner), None);
+    for sender in [first, second, first] {
+        kassert_ok!(ipc::send_message(port, message(sender, 1)));
+    }
+    kassert!(kassert_ok!(ipc::try_receive_message(port, owner)).is_some());
+    kassert_eq!(ipc::last_sender(owner), Some(first));
+    kassert!(kassert_ok!(ipc::try_receive_message(port, owner)).is_some());
+    kassert_eq!(ipc::last_sender(owner), Some(second));
+
+    // A batch has no single sender to answer for
+    kassert_eq!(kassert_ok!(ipc::receive_batch(port, owner, MAX_BATCH_SIZE)).len(), 1);
+    kassert_eq!(ipc::last_sender(owner), None);
+
+    ipc::close_owned_ports(owner);
+    Ok(())
+}
@@ -0,0 +1,239 @@
+// In-Kernel Test Harness
+//
+// Runs kernel tests inside the kernel itself, against the state a real
+// boot leaves behind, and reports the outcome to the host through QEMU.
+// Compiled only with the `ktest` feature (`./build.sh --test`).
+//
+// Key responsibilities:
+// - Collect each suite's test functions with the `tests!` macro
+// - Run every suite in order once memory, scheduling, capabilities, IPC
+//   and shared memory are initialized, in place of launching init
+// - Print one line per test, and where a failure happened, to the QEMU
+//   debug console (port 0xE9) and the serial port
+// - Signal pass or fail through QEMU's isa-debug-exit device
+//
+// Writing tests:
+// - A test is a `fn() -> TestResult` listed in its suite's `tests!`
+// - `kassert!`, `kassert_eq!` and `kassert_ok!` return a `Failure` that
+//   names the file and line instead of panicking, so the run goes on
+// - A panic still ends the run: the panic handler reports it against
+//   the test that was running and exits QEMU with the failure status
+//
+// Exit protocol:
+// - QEMU runs with `-device isa-debug-exit,iobase=0xf4,iosize=0x04`
+// - Writing `v` to port 0xF4 makes QEMU exit with status `(v << 1) | 1`,
+//   so `QEMU_EXIT_SUCCESS` gives 33 and `QEMU_EXIT_FAILURE` gives 35
+// - Without the device the write is ignored and the CPU halts
+//
+// Limitations:
+// - Tests share the kernel's global state and run in registration order;
+//   each creates its own ports, regions, capabilities and threads rather
+//   than assuming a clean slate
+// - Threads the tests add are never dispatched, since a test kernel
+//   exits instead of starting the scheduler
+
+use alloc::string::String;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+use crate::arch::{halt, qemu_exit, read_cr3, QEMU_EXIT_FAILURE, QEMU_EXIT_SUCCESS};
+use crate::mm::pmm;
+use crate::thread::{Thread, ThreadPriority};
+use crate::log_info;
+
+const LOG_ORIGIN: &str = "ktest";
+const DEBUGCON_PORT: u16 = 0xE9;
+
+/// Kernel addresses handed out to tests that need somewhere to map pages,
+/// away from the higher-half mirror and `vm::self_test`'s page
+const SCRATCH_BASE: usize = 0xFFFF_A100_0000_0000;
+
+pub type TestResult = Result<(), Failure>;
+
+pub struct Test {
+    pub name: &'static str,
+    pub run: fn() -> TestResult,
+}
+
+/// Where and why a test failed
+pub struct Failure {
+    pub file: &'static str,
+    pub line: u32,
+    pub message: String,
+}
+
+/// Declare a suite's `TESTS` from its test functions, in running order
+macro_rules! tests {
+    ($($name:ident),* $(,)?) => {
+        pub const TESTS: &[$crate::ktest::Test] = &[
+            $($crate::ktest::Test { name: stringify!($name), run: $name },)*
+        ];
+    };
+}
+
+macro_rules! kassert {
+    ($cond:expr) => {
+        kassert!($cond, "assertion failed: {}", stringify!($cond))
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            return Err($crate::ktest::Failure {
+                file: file!(),
+                line: line!(),
+                message: alloc::format!($($arg)+),
+            });
+        }
+    };
+}
+
+macro_rules! kassert_eq {
+    ($left:expr, $right:expr) => {
+        match (&$left, &$right) {
+            (left, right) => kassert!(
+                *left == *right,
+                "{} == {}: left {:?}, right {:?}",
+                stringify!($left),
+                stringify!($right),
+                left,
+                right
+            ),
+        }
+    };
+}
+
+/// The `Ok` value of a `Result`, or a failure showing the error
+macro_rules! kassert_ok {
+    ($result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(error) => {
+                return Err($crate::ktest::Failure {
+                    file: file!(),
+                    line: line!(),
+                    message: alloc::format!("{} failed: {:?}", stringify!($result), error),
+                })
+            }
+        }
+    };
+}
+
+// Suites come after the macros, which are only in scope below them
+mod cap;
+mod ipc;
+mod sched;
+mod shared_mem;
+mod vm;
+
+const SUITES: &[(&str, &[Test])] = &[
+    ("vm", vm::TESTS),
+    ("ipc", ipc::TESTS),
+    ("cap", cap::TESTS),
+    ("shared_mem", shared_mem::TESTS),
+    ("sched", sched::TESTS),
+];
+
+/// Suite and name of the test running now, for the panic handler
+static CURRENT: Mutex<Option<(&str, &str)>> = Mutex::new(None);
+static NEXT_SCRATCH: AtomicUsize = AtomicUsize::new(SCRATCH_BASE);
+
+/// Run every suite and exit QEMU with the result. Never returns: without
+/// the exit device the CPU halts once the results are printed.
+pub fn run() {
+    let total: usize = SUITES.iter().map(|(_, tests)| tests.len()).sum();
+    log_info!(LOG_ORIGIN, "Running {} kernel tests in {} suites", total, SUITES.len());
+    report(format_args!("ktest: running {} tests\n", total));
+
+    let mut failed = 0;
+    for &(suite, tests) in SUITES {
+        for test in tests {
+            *CURRENT.lock() = Some((suite, test.name));
+            let result = (test.run)();
+            *CURRENT.lock() = None;
+
+            match result {
+                Ok(()) => report(format_args!("test {}::{} ... ok\n", suite, test.name)),
+                Err(failure) => {
+                    failed += 1;
+                    report(format_args!(
+                        "test {}::{} ... FAILED\n    {}:{}: {}\n",
+                        suite, test.name, failure.file, failure.line, failure.message
+                    ));
+                }
+            }
+        }
+    }
+
+    report(format_args!("ktest: {} passed, {} failed\n", total - failed, failed));
+    exit(if failed == 0 { QEMU_EXIT_SUCCESS } else { QEMU_EXIT_FAILURE })
+}
+
+/// Report a panic against the running test and fail the run. Only the
+/// debug console is written, as the serial lock may be held.
+pub fn on_panic(info: &PanicInfo) {
+    let running = CURRENT.try_lock().and_then(|current| *current);
+    let _ = match running {
+        Some((suite, name)) => {
+            write!(DebugCon, "test {}::{} ... FAILED\n    panicked: {}\n", suite, name, info)
+        }
+        None => writeln!(DebugCon, "ktest: kernel panicked outside a test: {}", info),
+    };
+    let _ = DebugCon.write_str("ktest: aborted\n");
+    exit(QEMU_EXIT_FAILURE)
+}
+
+/// A kernel address range of `pages` pages that nothing else maps
+pub fn scratch_virt(pages: usize) -> usize {
+    NEXT_SCRATCH.fetch_add((pages + 1) * pmm::PAGE_SIZE, Ordering::Relaxed)
+}
+
+/// A kernel thread with a one-page stack that parks if ever run
+pub fn kernel_thread(name: &'static str, priority: ThreadPriority) -> Thread {
+    extern "C" fn park() -> ! {
+        loop {
+            halt();
+        }
+    }
+
+    let stack = pmm::alloc_page_zeroed().expect("no frame for a test thread stack");
+    Thread::new(
+        park as *const () as u64,
+        (stack + pmm::PAGE_SIZE) as u64,
+        pmm::PAGE_SIZE,
+        read_cr3(),
+        priority,
+        name,
+    )
+}
+
+fn report(args: fmt::Arguments) {
+    let _ = DebugCon.write_fmt(args);
+    crate::serial::_print(args);
+}
+
+fn exit(code: u32) -> ! {
+    qemu_exit(code);
+    let _ = DebugCon.write_str("ktest: no isa-debug-exit device, halting\n");
+    loop {
+        halt();
+    }
+}
+
+struct DebugCon;
+
+impl Write for DebugCon {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            unsafe {
+                core::arch::asm!(
+                    "out dx, al",
+                    in("dx") DEBUGCON_PORT,
+                    in("al") byte,
+                    options(nomem, nostack, preserves_flags)
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+// Scheduler Tests
+//
+// Covers priority ordering, round-robin within a priority, priority
+// inheritance bookkeeping and readiness. Threads are added and picked
+// but never switched to; the picks are what is checked.
+
+use alloc::vec::Vec;
+
+use crate::ktest::{kernel_thread, TestResult};
+use crate::sched;
+use crate::thread::{ThreadId, ThreadPriority, ThreadState};
+
+tests![
+    higher_priority_goes_first,
+    same_priority_takes_turns,
+    boost_is_undone_by_restore,
+    blocked_threads_are_passed_over,
+];
+
+/// Picks enough to go round every ready thread a few times
+const ROUNDS: usize = 16;
+
+fn add(name: &'static str, priority: ThreadPriority) -> ThreadId {
+    sched::add_thread(kernel_thread(name, priority))
+}
+
+fn picks(count: usize) -> Vec<Option<ThreadId>> {
+    (0..count).map(|_| sched::schedule()).collect()
+}
+
+fn higher_priority_goes_first() -> TestResult {
+    let low = add("ktest-sched-low", ThreadPriority::Low);
+    let high = add("ktest-sched-high", ThreadPriority::High);
+
+    kassert_eq!(sched::schedule(), Some(high));
+    kassert_eq!(sched::current_thread(), Some(high));
+    kassert!(!picks(ROUNDS).contains(&Some(low)), "low priority thread ran before high");
+    Ok(())
+}
+
+fn same_priority_takes_turns() -> TestResult {
+    let first = add("ktest-sched-rr1", ThreadPriority::High);
+    let second = add("ktest-sched-rr2", ThreadPriority::High);
+
+    let picks = picks(ROUNDS);
+    let turns: Vec<usize> = picks
+        .iter()
+        .enumerate()
+        .filter(|(_, pick)| **pick == Some(first))
+        .map(|(index, _)| index)
+        .collect();
+    kassert!(turns.len() >= 2, "first thread picked {} times", turns.len());
+
+    // Between two turns of one thread, the other gets exactly one
+    for pair in turns.windows(2) {
+        let between = &picks[pair[0]..pair[1]];
+        let others = between.iter().filter(|pick| **pick == Some(second)).count();
+        kassert_eq!(others, 1);
+    }
+    Ok(())
+}
+
+fn boost_is_undone_by_restore() -> TestResult {
+    let thread = add("ktest-sched-boost", ThreadPriority::Low);
+
+    kassert!(sched::boost_thread_priority(thread, ThreadPriority::High));
+    kassert_eq!(sched::get_thread_priority(thread), ThreadPriority::High);
+    kassert_eq!(sched::get_base_priority(thread), ThreadPriority::Low);
+    kassert!(!sched::boost_thread_priority(thread, ThreadPriority::Normal));
+
+    sched::restore_original_priority(thread);
+    kassert_eq!(sched::get_thread_priority(thread), ThreadPriority::Low);
+    Ok(())
+}
+
+fn blocked_threads_are_passed_over() -> TestResult {
+    let mut blocked = kernel_thread("ktest-sched-blocked", ThreadPriority::High);
+    blocked.set_state(ThreadState::Blocked);
+    let blocked = sched::add_thread(blocked);
+
+    kassert!(!picks(ROUNDS).contains(&Some(blocked)), "blocked thread was picked");
+    sched::mark_thread_ready(blocked);
+    kassert!(picks(ROUNDS).contains(&Some(blocked)), "woken thread was never picked");
+    Ok(())
+}
@@ -0,0 +1,126 @@
+// Shared Memory Tests
+//
+// Covers region sizing, mapping one region into several places, mappings
+// the kernel places itself, the rollback of a mapping that fails halfway,
+// and the lifetime rules that keep a mapped region from being destroyed.
+
+use crate::ktest::{scratch_virt, TestResult};
+use crate::mm::pmm::PAGE_SIZE;
+use crate::mm::{vm, vspace};
+use crate::shared_mem::{self, RegionFlags, SharedMemError};
+use crate::thread::ThreadId;
+
+tests![
+    sizes_round_up_to_pages,
+    mappings_share_frames,
+    placed_mappings_get_windows,
+    failed_mapping_rolls_back,
+    mapped_regions_outlive_destroy,
+];
+
+fn sizes_round_up_to_pages() -> TestResult {
+    let owner = ThreadId::new();
+    let region = kassert_ok!(shared_mem::create_region(owner, PAGE_SIZE + 1));
+
+    let info = kassert_ok!(shared_mem::get_region_info(region));
+    kassert_eq!(info.size, 2 * PAGE_SIZE);
+    kassert_eq!(info.owner, owner);
+    kassert_eq!(info.ref_count, 0);
+    kassert_eq!(shared_mem::create_region(owner, 0), Err(SharedMemError::InvalidSize));
+
+    kassert_eq!(
+        shared_mem::destroy_region(region, ThreadId::new()),
+        Err(SharedMemError::PermissionDenied)
+    );
+    kassert_ok!(shared_mem::destroy_region(region, owner));
+    kassert_eq!(shared_mem::get_region_info(region).err(), Some(SharedMemError::InvalidRegion));
+    Ok(())
+}
+
+fn mappings_share_frames() -> TestResult {
+    let (owner, peer) = (ThreadId::new(), ThreadId::new());
+    let (first, second) = (scratch_virt(2), scratch_virt(2));
+    let region = kassert_ok!(shared_mem::create_region(owner, 2 * PAGE_SIZE));
+    let mappings_before = shared_mem::get_stats().total_mappings;
+
+    kassert_ok!(shared_mem::map_region(region, owner, first, RegionFlags::read_write()));
+    kassert_ok!(shared_mem::map_region(region, peer, second, RegionFlags::read_only()));
+    kassert_eq!(kassert_ok!(shared_mem::get_region_info(region)).ref_count, 2);
+    kassert_eq!(shared_mem::get_stats().total_mappings, mappings_before + 2);
+
+    for page in 0..2 {
+        let frame = vm::translate(first + page * PAGE_SIZE);
+        kassert!(frame.is_some(), "page {} of the first mapping is missing", page);
+        kassert_eq!(vm::translate(second + page * PAGE_SIZE), frame);
+    }
+
+    kassert_ok!(shared_mem::unmap_region(region, owner));
+    kassert_ok!(shared_mem::unmap_region(region, peer));
+    kassert_eq!(vm::translate(first), None);
+    kassert_eq!(vm::translate(second), None);
+    kassert_ok!(shared_mem::destroy_region(region, owner));
+    Ok(())
+}
+
+fn placed_mappings_get_windows() -> TestResult {
+    let (owner, peer) = (ThreadId::new(), ThreadId::new());
+    let region = kassert_ok!(shared_mem::create_region(owner, PAGE_SIZE));
+
+    let first = kassert_ok!(shared_mem::map_region(region, owner, 0, RegionFlags::read_write()));
+    let second = kassert_ok!(shared_mem::map_region(region, peer, 0, RegionFlags::read_only()));
+    kassert!(first >= vspace::WINDOW_BASE && second >= vspace::WINDOW_BASE);
+    kassert!(first != second);
+    kassert_eq!(vm::translate(first), vm::translate(second));
+
+    // Unmapping hands the window back, so the owner can no longer release it
+    kassert_ok!(shared_mem::unmap_region(region, owner));
+    kassert_ok!(shared_mem::unmap_region(region, peer));
+    kassert!(!vspace::release(owner, first));
+    kassert_eq!(vm::translate(first), None);
+    kassert_ok!(shared_mem::destroy_region(region, owner));
+    Ok(())
+}
+
+fn failed_mapping_rolls_back() -> TestResult {
+    let owner = ThreadId::new();
+    let base = scratch_virt(3);
+    let held = kassert_ok!(shared_mem::create_region(owner, PAGE_SIZE));
+    let region = kassert_ok!(shared_mem::create_region(owner, 2 * PAGE_SIZE));
+
+    kassert_eq!(
+        shared_mem::map_region(region, owner, base + 1, RegionFlags::read_write()),
+        Err(SharedMemError::Unaligned)
+    );
+
+    // The second page collides with `held`, so the first must be undone
+    kassert_ok!(shared_mem::map_region(held, owner, base + PAGE_SIZE, RegionFlags::read_write()));
+    kassert_eq!(
+        shared_mem::map_region(region, owner, base, RegionFlags::read_write()),
+        Err(SharedMemError::AlreadyMapped)
+    );
+    kassert_eq!(vm::translate(base), None);
+    kassert_eq!(kassert_ok!(shared_mem::get_region_info(region)).ref_count, 0);
+
+    kassert_ok!(shared_mem::unmap_region(held, owner));
+    kassert_ok!(shared_mem::destroy_region(held, owner));
+    kassert_ok!(shared_mem::destroy_region(region, owner));
+    Ok(())
+}
+
+fn mapped_regions_outlive_destroy() -> TestResult {
+    let owner = ThreadId::new();
+    let virt = scratch_virt(1);
+    let region = kassert_ok!(shared_mem::create_region(owner, PAGE_SIZE));
+
+    kassert_ok!(shared_mem::map_region(region, owner, virt, RegionFlags::read_write()));
+    kassert_eq!(shared_mem::destroy_region(region, owner), Err(SharedMemError::RegionInUse));
+    kassert_eq!(
+        shared_mem::map_region(region, owner, scratch_virt(1), RegionFlags::read_write()),
+        Err(SharedMemError::AlreadyMapped)
+    );
+
+    kassert_ok!(shared_mem::unmap_region(region, owner));
+    kassert_eq!(shared_mem::unmap_region(region, owner), Err(SharedMemError::NotMapped));
+    kassert_ok!(shared_mem::destroy_region(region, owner));
+    Ok(())
+}
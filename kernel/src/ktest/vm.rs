@@ -0,0 +1,125 @@
+// Memory Management Tests
+//
+// Covers the physical allocator's accounting, the virtual memory
+// manager's page mapping, starting with `vm::self_test`, and the user
+// window allocator.
+
+use crate::arch::read_cr3;
+use crate::ktest::{scratch_virt, TestResult};
+use crate::mm::pmm::{self, PAGE_SIZE};
+use crate::mm::usercopy::{self, UserCopyError};
+use crate::mm::vm::{self, PageFlags, VmError};
+use crate::mm::vspace;
+use crate::thread::ThreadId;
+
+tests![
+    self_test,
+    alloc_and_free_balance,
+    zeroed_pages_are_zero,
+    query_reports_flags,
+    usercopy_refuses_kernel_memory,
+    mmio_refuses_ram,
+    user_windows_do_not_overlap,
+];
+
+fn self_test() -> TestResult {
+    kassert_ok!(vm::self_test());
+    Ok(())
+}
+
+fn alloc_and_free_balance() -> TestResult {
+    let (_, free_before) = pmm::get_stats();
+
+    let pages = kassert_ok!(pmm::alloc_pages(3).ok_or("out of memory"));
+    kassert!(pmm::is_page_aligned(pages));
+    kassert_eq!(pmm::get_stats().1, free_before - 3);
+
+    pmm::free_pages(pages, 3);
+    kassert_eq!(pmm::get_stats().1, free_before);
+    Ok(())
+}
+
+fn zeroed_pages_are_zero() -> TestResult {
+    // The allocator hands out the lowest free page, so a freed dirty page
+    // comes straight back
+    let dirty = kassert_ok!(pmm::alloc_page().ok_or("out of memory"));
+    unsafe { core::ptr::write_bytes(dirty as *mut u8, 0xA5, PAGE_SIZE) };
+    pmm::free_page(dirty);
+
+    let page = kassert_ok!(pmm::alloc_page_zeroed().ok_or("out of memory"));
+    let bytes = unsafe { core::slice::from_raw_parts(page as *const u8, PAGE_SIZE) };
+    let clean = bytes.iter().all(|&byte| byte == 0);
+    pmm::free_page(page);
+
+    kassert_eq!(page, dirty);
+    kassert!(clean, "page {:#X} was not zeroed", page);
+    Ok(())
+}
+
+fn query_reports_flags() -> TestResult {
+    let pml4 = read_cr3() as usize & !(PAGE_SIZE - 1);
+    let virt = scratch_virt(1);
+    let phys = kassert_ok!(pmm::alloc_page_zeroed().ok_or("out of memory"));
+
+    kassert_ok!(vm::map_page(virt, phys, PageFlags::kernel_rw_nx()));
+    let queried = vm::query_mapping_in_pml4(pml4, virt);
+    kassert_ok!(vm::unmap_page(virt));
+    pmm::free_page(phys);
+
+    let (mapped, flags) = kassert_ok!(queried);
+    kassert_eq!(mapped, phys);
+    kassert!(flags.bits() & PageFlags::WRITABLE.bits() != 0, "flags {:#X}", flags.bits());
+    kassert!(flags.bits() & PageFlags::NO_EXECUTE.bits() != 0, "flags {:#X}", flags.bits());
+    kassert!(flags.bits() & PageFlags::USER.bits() == 0, "flags {:#X}", flags.bits());
+    kassert_eq!(vm::query_mapping_in_pml4(pml4, virt).err(), Some(VmError::NotMapped));
+    Ok(())
+}
+
+fn usercopy_refuses_kernel_memory() -> TestResult {
+    let virt = scratch_virt(1);
+    let phys = kassert_ok!(pmm::alloc_page_zeroed().ok_or("out of memory"));
+    kassert_ok!(vm::map_page(virt, phys, PageFlags::kernel_rw_nx()));
+
+    let mut buf = [0u8; 16];
+    let from_kernel = usercopy::copy_from_user(&mut buf, virt as u64);
+    let to_kernel = usercopy::copy_to_user(virt as u64, &buf);
+    kassert_ok!(vm::unmap_page(virt));
+    pmm::free_page(phys);
+
+    kassert_eq!(from_kernel, Err(UserCopyError::OutOfRange));
+    kassert_eq!(to_kernel, Err(UserCopyError::OutOfRange));
+    kassert_eq!(usercopy::read_user::<u64>(0), Err(UserCopyError::Null));
+    kassert_eq!(usercopy::check_range(u64::MAX - 4, 16, false), Err(UserCopyError::OutOfRange));
+    kassert_ok!(usercopy::check_range(0, 0, true));
+    Ok(())
+}
+
+fn mmio_refuses_ram() -> TestResult {
+    let phys = kassert_ok!(pmm::alloc_page().ok_or("out of memory"));
+    let mapped = vm::map_mmio(phys as u64, PAGE_SIZE);
+    pmm::free_page(phys);
+
+    kassert_eq!(mapped, Err(VmError::Reserved));
+    Ok(())
+}
+
+fn user_windows_do_not_overlap() -> TestResult {
+    let owner = ThreadId::new();
+    let first = kassert_ok!(vspace::reserve(owner, 2).ok_or("no window"));
+    let second = kassert_ok!(vspace::reserve(owner, 1).ok_or("no window"));
+
+    // Each window keeps an unmapped guard page after it
+    kassert!(first + 3 * PAGE_SIZE <= second || second + 2 * PAGE_SIZE <= first);
+    kassert!(!vspace::release(ThreadId::new(), first));
+    kassert_eq!(vspace::reserve(owner, 0), None);
+
+    kassert!(vspace::release(owner, first));
+    kassert!(!vspace::release(owner, first));
+    // First fit: the same size lands in the gap just freed
+    let reused = kassert_ok!(vspace::reserve(owner, 2).ok_or("no window"));
+    kassert_eq!(reused, first);
+
+    kassert!(vspace::release(owner, reused));
+    kassert!(vspace::release(owner, second));
+    Ok(())
+}
@@ -8,7 +8,10 @@
 // - Provide standardized log levels (Debug, Info, Warn, Error, Panic)
 // - Attach timestamps and subsystem origin to every log entry
 // - Include source location only for DEBUG entries (file:line)
-// - Output logs to the serial port unconditionally
+// - Output logs to the serial port at or above a configurable level
+// - Record every emitted entry (level, timestamp, origin and message) in an
+//   in-memory ring, read by SYS_KLOG_READ with a minimum level and shown on
+//   the crash screen
 // - Optionally mirror logs to the VGA text console with color coding
 //
 // Design principles:
@@ -20,7 +23,11 @@
 // Implementation details:
 // - Log level is stored in a global mutable variable (`CURRENT_LOG_LEVEL`)
 // - Timestamps are derived from kernel timer ticks (coarse but monotonic)
-// - Serial output is always enabled and considered the ground truth
+// - Serial output defaults to every level and is considered the ground truth;
+//   `set_serial_level` can quieten it without dropping entries from the ring
+// - The ring keeps the most recent `KLOG_SIZE` bytes of entries, dropping
+//   the oldest whole, and formats them as text lines only when read; it is
+//   written with interrupts disabled so handlers can log safely
 // - VGA output is optional and guarded by a runtime flag
 // - Each log includes severity, timestamp, subsystem origin, and message
 //
@@ -41,11 +48,13 @@
 //
 // Future considerations:
 // - Per-module log filtering
-// - Structured log sinks (e.g. ring buffers or user-space log servers)
+// - Structured log sinks (e.g. user-space log servers)
 // - Runtime-configurable backends via user-space logging services
 
 use core::fmt;
+use core::ops::Range;
 use crate::serial;
+use crate::util::without_interrupts;
 use crate::vga::{self, Color};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -60,6 +69,17 @@ pub enum LogLevel {
 }
 
 impl LogLevel {
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(LogLevel::Debug),
+            1 => Some(LogLevel::Info),
+            2 => Some(LogLevel::Warn),
+            3 => Some(LogLevel::Error),
+            4 => Some(LogLevel::Panic),
+            _ => None,
+        }
+    }
+
     pub const fn as_str(&self) -> &'static str {
         match self {
             LogLevel::Debug => "DEBUG",
@@ -82,6 +102,7 @@ impl LogLevel {
 }
 
 static mut CURRENT_LOG_LEVEL: LogLevel = LogLevel::Debug;
+static mut SERIAL_LOG_LEVEL: LogLevel = LogLevel::Debug;
 static mut VGA_OUTPUT_ENABLED: bool = false;
 
 pub fn init() {
@@ -98,6 +119,16 @@ pub fn get_level() -> LogLevel {
     unsafe { CURRENT_LOG_LEVEL }
 }
 
+pub fn set_serial_level(level: LogLevel) {
+    unsafe {
+        SERIAL_LOG_LEVEL = level;
+    }
+}
+
+pub fn get_serial_level() -> LogLevel {
+    unsafe { SERIAL_LOG_LEVEL }
+}
+
 pub fn enable_vga_output() {
     unsafe {
         VGA_OUTPUT_ENABLED = true;
@@ -122,6 +153,260 @@ fn format_timestamp(ms: u64) -> (u64, u64) {
     (seconds, milliseconds)
 }
 
+// ============================================================================
+// Kernel Log Ring
+// ============================================================================
+
+/// Size of the in-memory log ring in bytes
+pub const KLOG_SIZE: usize = 16 * 1024;
+
+/// Longest message kept for one entry; the rest is cut off
+const MAX_MESSAGE: usize = 1024;
+
+/// Bytes before each entry's origin: message length (u16), level, origin
+/// length and timestamp in milliseconds (u64), little-endian
+const HEADER_SIZE: usize = 12;
+
+/// Log entries, each a header, the origin, then the message
+///
+/// Offsets are absolute (bytes since boot). `oldest` always points at a
+/// header, so readers resume on an entry boundary even after the ring has
+/// wrapped; entries are dropped whole as new ones overwrite them.
+struct KlogRing {
+    buf: [u8; KLOG_SIZE],
+    /// Total bytes ever written; the ring holds the last `KLOG_SIZE` of them
+    written: u64,
+    /// Offset of the oldest entry still held
+    oldest: u64,
+}
+
+/// Where an entry's parts lie in the ring
+struct KlogEntry {
+    level: u8,
+    timestamp_ms: u64,
+    origin: Range<u64>,
+    message: Range<u64>,
+}
+
+impl KlogRing {
+    const fn new() -> Self {
+        Self {
+            buf: [0; KLOG_SIZE],
+            written: 0,
+            oldest: 0,
+        }
+    }
+
+    fn byte(&self, pos: u64) -> u8 {
+        self.buf[(pos % KLOG_SIZE as u64) as usize]
+    }
+
+    fn put(&mut self, byte: u8) {
+        // Drop the entries this byte would cut into
+        while self.written >= self.oldest + KLOG_SIZE as u64 {
+            self.oldest = self.entry(self.oldest).message.end;
+        }
+        self.buf[(self.written % KLOG_SIZE as u64) as usize] = byte;
+        self.written += 1;
+    }
+
+    fn push(&mut self, level: LogLevel, origin: &str, timestamp_ms: u64, message: fmt::Arguments) {
+        use core::fmt::Write;
+
+        let start = self.written;
+        let origin = &origin.as_bytes()[..origin.len().min(u8::MAX as usize)];
+
+        let mut header = [0u8; HEADER_SIZE];
+        header[2] = level as u8;
+        header[3] = origin.len() as u8;
+        header[4..].copy_from_slice(&timestamp_ms.to_le_bytes());
+        for &byte in header.iter().chain(origin) {
+            self.put(byte);
+        }
+
+        let mut writer = MessageWriter { ring: self, len: 0 };
+        let _ = writer.write_fmt(message);
+        let len = writer.len as u16;
+
+        // The length is only known now; the header cannot have been
+        // overwritten, as an entry is far smaller than the ring
+        for (i, byte) in len.to_le_bytes().into_iter().enumerate() {
+            self.buf[((start + i as u64) % KLOG_SIZE as u64) as usize] = byte;
+        }
+    }
+
+    fn entry(&self, pos: u64) -> KlogEntry {
+        let field = |offset: u64, size: usize| {
+            (0..size).fold(0u64, |value, i| {
+                value | (self.byte(pos + offset + i as u64) as u64) << (8 * i)
+            })
+        };
+        let origin_start = pos + HEADER_SIZE as u64;
+        let message_start = origin_start + field(3, 1);
+
+        KlogEntry {
+            level: field(2, 1) as u8,
+            timestamp_ms: field(4, 8),
+            origin: origin_start..message_start,
+            message: message_start..message_start + field(0, 2),
+        }
+    }
+
+    /// Every entry from offset `pos` on, which must be an entry boundary
+    fn entries_from(&self, mut pos: u64) -> impl Iterator<Item = KlogEntry> + '_ {
+        core::iter::from_fn(move || {
+            if pos >= self.written {
+                return None;
+            }
+            let entry = self.entry(pos);
+            pos = entry.message.end;
+            Some(entry)
+        })
+    }
+
+    /// Write `entry` as a line of text, the way it went to the serial port
+    fn format(&self, entry: &KlogEntry, line: &mut LineWriter) {
+        use core::fmt::Write;
+
+        let level = LogLevel::from_u8(entry.level).map_or("?????", |level| level.as_str());
+        let (seconds, milliseconds) = format_timestamp(entry.timestamp_ms);
+
+        let _ = write!(line, "[t={}.{:03}s] [{}] [", seconds, milliseconds, level);
+        entry.origin.clone().for_each(|pos| line.push(self.byte(pos)));
+        line.push_all(b"] ");
+        entry.message.clone().for_each(|pos| line.push(self.byte(pos)));
+        line.push(b'\n');
+    }
+
+    fn formatted_len(&self, entry: &KlogEntry) -> usize {
+        let mut line = LineWriter::new(&mut []);
+        self.format(entry, &mut line);
+        line.total
+    }
+}
+
+/// Appends a message to the ring, cut at `MAX_MESSAGE` bytes on a
+/// character boundary
+struct MessageWriter<'a> {
+    ring: &'a mut KlogRing,
+    len: usize,
+}
+
+impl fmt::Write for MessageWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for ch in s.chars() {
+            let mut bytes = [0u8; 4];
+            let encoded = ch.encode_utf8(&mut bytes).as_bytes();
+            if self.len + encoded.len() > MAX_MESSAGE {
+                break;
+            }
+            for &byte in encoded {
+                self.ring.put(byte);
+            }
+            self.len += encoded.len();
+        }
+        Ok(())
+    }
+}
+
+/// Formats into a byte buffer, dropping what does not fit but counting it
+struct LineWriter<'a> {
+    out: &'a mut [u8],
+    len: usize,
+    /// Bytes written, including those dropped
+    total: usize,
+}
+
+impl<'a> LineWriter<'a> {
+    fn new(out: &'a mut [u8]) -> Self {
+        Self { out, len: 0, total: 0 }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len < self.out.len() {
+            self.out[self.len] = byte;
+            self.len += 1;
+        }
+        self.total += 1;
+    }
+
+    fn push_all(&mut self, bytes: &[u8]) {
+        bytes.iter().for_each(|&byte| self.push(byte));
+    }
+}
+
+impl fmt::Write for LineWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_all(s.as_bytes());
+        Ok(())
+    }
+}
+
+static KLOG: spin::Mutex<KlogRing> = spin::Mutex::new(KlogRing::new());
+
+fn record_klog(level: LogLevel, origin: &str, timestamp_ms: u64, message: fmt::Arguments) {
+    without_interrupts(|| {
+        KLOG.lock().push(level, origin, timestamp_ms, message);
+    });
+}
+
+/// Copy log entries at or above `min_level`, as text lines, into `out`
+///
+/// `pos` is an absolute offset returned by an earlier call, or 0 for the
+/// oldest entry still held; if it has been overwritten since, the copy
+/// starts at the oldest entry. Only whole lines are copied, except that a
+/// line too long for `out` on its own is cut to fit. Returns the number of
+/// bytes copied and the offset to pass on the next call.
+pub fn read_klog(pos: u64, min_level: LogLevel, out: &mut [u8]) -> (usize, u64) {
+    without_interrupts(|| {
+        let ring = KLOG.lock();
+        let mut next = pos.clamp(ring.oldest, ring.written);
+        let mut count = 0;
+
+        for entry in ring.entries_from(next) {
+            if entry.level >= min_level as u8 {
+                let mut line = LineWriter::new(&mut out[count..]);
+                ring.format(&entry, &mut line);
+                if line.total > line.len && count > 0 {
+                    break;
+                }
+                count += line.len;
+            }
+            next = entry.message.end;
+            if count == out.len() {
+                break;
+            }
+        }
+
+        (count, next)
+    })
+}
+
+/// Copy the most recent log lines, as many as fit whole, into `out`
+///
+/// For the crash path: gives up with None instead of waiting when the
+/// ring is locked, as the crash may have happened while it was held.
+pub fn try_klog_tail(out: &mut [u8]) -> Option<usize> {
+    let ring = KLOG.try_lock()?;
+
+    // Skip the oldest entries until the rest fits
+    let mut total: usize = ring.entries_from(ring.oldest).map(|e| ring.formatted_len(&e)).sum();
+    let mut start = ring.oldest;
+    for entry in ring.entries_from(ring.oldest) {
+        if total <= out.len() {
+            break;
+        }
+        total -= ring.formatted_len(&entry);
+        start = entry.message.end;
+    }
+
+    let mut line = LineWriter::new(out);
+    for entry in ring.entries_from(start) {
+        ring.format(&entry, &mut line);
+    }
+    Some(line.len)
+}
+
 pub fn _log(level: LogLevel, origin: &str, args: fmt::Arguments, file: &str, line: u32) {
     if level < get_level() {
         return;
@@ -135,8 +420,10 @@ pub fn _log(level: LogLevel, origin: &str, args: fmt::Arguments, file: &str, lin
     let level_str = level.as_str();
     let args_for_vga = args.clone();
 
+    let to_serial = level >= get_serial_level();
+
     if is_debug {
-        serial::_print(format_args!(
+        let entry = format_args!(
             "[t={}.{:03}s] [{}] [{}] {} ({}:{})\n",
             seconds,
             milliseconds,
@@ -145,16 +432,24 @@ pub fn _log(level: LogLevel, origin: &str, args: fmt::Arguments, file: &str, lin
             args,
             file,
             line
-        ));
+        );
+        record_klog(level, origin, timestamp_ms, format_args!("{} ({}:{})", args, file, line));
+        if to_serial {
+            serial::_print(entry);
+        }
     } else {
-        serial::_print(format_args!(
+        let entry = format_args!(
             "[t={}.{:03}s] [{}] [{}] {}\n",
             seconds,
             milliseconds,
             level_str,
             origin,
             args
-        ));
+        );
+        record_klog(level, origin, timestamp_ms, args);
+        if to_serial {
+            serial::_print(entry);
+        }
     }
 
     unsafe {
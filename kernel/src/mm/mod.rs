@@ -8,9 +8,13 @@
 // - Initialize all memory management layers in the correct dependency order
 // - Provide a single, clear initialization interface for early kernel boot
 // - Encapsulate MM submodules behind a unified namespace
+// - Host the checked copies in and out of user memory (`usercopy`)
+// - Hand out non-overlapping user windows for kernel-placed mappings
+//   (`vspace`)
 //
 // Initialization flow:
 // - `pmm::init` sets up the physical memory manager using the UEFI memory map
+// - `crash::reserve_dump_region` then pins the crash dump range, if enabled
 // - `vm::init` establishes kernel virtual memory mappings and paging structures
 // - `heap::init` initializes the global kernel heap allocator
 // - `addrspace::init` prepares user address space management facilities
@@ -35,11 +39,14 @@ pub mod heap;
 pub mod vm;
 pub mod addrspace;
 pub mod policy;
+pub mod usercopy;
+pub mod vspace;
 
 use crate::boot::MemoryMap;
 
 pub unsafe fn init(memory_map: &MemoryMap) {
     pmm::init(memory_map);
+    crate::crash::reserve_dump_region();
     vm::init(memory_map);
     heap::init();
     addrspace::init();
@@ -23,6 +23,7 @@
 // - Only EFI_CONVENTIONAL_MEMORY regions are marked free
 // - `NEXT_FREE_HINT` provides a simple next-fit optimization for allocations
 // - Contiguous allocation scans linearly for free runs of pages
+// - Allocations and frees are recorded in the kernel trace (`trace`)
 //
 // Correctness and safety notes:
 // - All bitmap manipulation is `unsafe` and must respect bounds
@@ -38,6 +39,8 @@
 // Public interface:
 // - `alloc_page` / `free_page` for single-page management
 // - `alloc_pages` / `free_pages` for contiguous ranges
+// - `reserve_pages` to pin a fixed physical range before anything else
+//   can allocate it
 // - Zeroed variants for safe page table and heap initialization
 // - Utility helpers for alignment and statistics reporting
 
@@ -152,6 +155,7 @@ pub fn alloc_page() -> Option<usize> {
             if is_page_free(page) {
                 set_page_allocated(page);
                 FREE_PAGES.fetch_sub(1, Ordering::Relaxed);
+                crate::trace_event!(mm, PAGE_ALLOC, page * PAGE_SIZE, 1);
                 return Some(page * PAGE_SIZE);
             }
         }
@@ -174,6 +178,7 @@ pub fn free_page(addr: usize) {
         if !is_page_free(page) {
             set_page_free(page);
             FREE_PAGES.fetch_add(1, Ordering::Relaxed);
+            crate::trace_event!(mm, PAGE_FREE, addr, 1);
         }
     }
 }
@@ -247,6 +252,7 @@ pub fn alloc_pages(count: usize) -> Option<usize> {
             }
 
             FREE_PAGES.fetch_sub(count, Ordering::Relaxed);
+            crate::trace_event!(mm, PAGE_ALLOC, start * PAGE_SIZE, count);
             return Some(start * PAGE_SIZE);
         }
     }
@@ -254,6 +260,29 @@ pub fn alloc_pages(count: usize) -> Option<usize> {
     None
 }
 
+/// Take the `count` pages at `addr` out of the free pool, for memory that
+/// must stay where it is (such as the crash dump region). Fails, taking
+/// nothing, unless every page is free.
+pub fn reserve_pages(addr: usize, count: usize) -> bool {
+    if !addr.is_multiple_of(PAGE_SIZE) || count == 0 {
+        return false;
+    }
+
+    let start = addr / PAGE_SIZE;
+    unsafe {
+        if !(start..start + count).all(|page| is_page_free(page)) {
+            return false;
+        }
+
+        for page in start..start + count {
+            set_page_allocated(page);
+        }
+    }
+
+    FREE_PAGES.fetch_sub(count, Ordering::Relaxed);
+    true
+}
+
 #[allow(dead_code)]
 pub fn free_pages(addr: usize, count: usize) {
     for i in 0..count {
@@ -0,0 +1,165 @@
+// User Memory Access
+//
+// The only sanctioned way for the kernel to read or write memory a user
+// thread handed it, typically a syscall buffer. With SMAP on, any other
+// kernel access to a user page faults, so a stray pointer shows up as a
+// crash instead of silently reading or corrupting memory.
+//
+// Key responsibilities:
+// - Check that a range lies entirely in user pages of the current address
+//   space, and that they are writable when the kernel writes to them
+// - Copy bytes and plain values in and out of such ranges
+// - Open the SMAP window (RFLAGS.AC) for exactly the duration of a copy
+//
+// Implementation details:
+// - Ranges are checked page by page against the live page tables (CR3),
+//   so kernel addresses, unmapped holes and read-only user pages are
+//   refused before anything is touched
+// - `UserAccess` is an RAII guard around `stac`/`clac`; it leaves AC set
+//   if it was already set, so guards may nest
+// - Other kernel code that deliberately shares pages with userspace (the
+//   framebuffer) opens the same guard around its accesses
+// - Typed reads are limited to the sealed `Pod` trait, so user memory is
+//   only ever read as types every bit pattern is valid for
+//
+// Limitations:
+// - Nothing catches a fault during a copy; the range check stands in for
+//   an exception fixup table, which is sound only while the kernel is
+//   single-core and user mappings cannot change in the middle of a copy
+// - Only 4 KiB user mappings are recognized
+
+use crate::arch::{self, read_cr3, RFLAGS_AC};
+use crate::mm::pmm;
+use crate::mm::vm::{self, PageFlags};
+
+/// End of the lower half; nothing at or above it is user memory
+const USER_SPACE_END: u64 = 1 << 47;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserCopyError {
+    /// The range starts at 0
+    Null,
+    /// The range wraps or reaches into the upper half
+    OutOfRange,
+    /// A page of the range is not mapped, or not mapped for userspace
+    NotUserMemory,
+    /// The kernel was to write to a read-only page
+    ReadOnly,
+}
+
+/// Supervisor access to user pages, allowed while the guard lives
+pub struct UserAccess {
+    was_open: bool,
+}
+
+impl UserAccess {
+    pub fn open() -> Self {
+        let was_open = arch::rflags() & RFLAGS_AC != 0;
+        arch::stac();
+        Self { was_open }
+    }
+}
+
+impl Drop for UserAccess {
+    fn drop(&mut self) {
+        if !self.was_open {
+            arch::clac();
+        }
+    }
+}
+
+/// Check that the `len` bytes at `addr` are user memory of the current
+/// address space, writable if `write`
+pub fn check_range(addr: u64, len: usize, write: bool) -> Result<(), UserCopyError> {
+    if len == 0 {
+        return Ok(());
+    }
+    if addr == 0 {
+        return Err(UserCopyError::Null);
+    }
+    let end = match addr.checked_add(len as u64) {
+        Some(end) if end <= USER_SPACE_END => end as usize,
+        _ => return Err(UserCopyError::OutOfRange),
+    };
+
+    let pml4 = read_cr3() as usize & !(pmm::PAGE_SIZE - 1);
+    for page in (pmm::align_down(addr as usize)..end).step_by(pmm::PAGE_SIZE) {
+        let (_, flags) = vm::query_mapping_in_pml4(pml4, page)
+            .map_err(|_| UserCopyError::NotUserMemory)?;
+        if flags.bits() & PageFlags::USER.bits() == 0 {
+            return Err(UserCopyError::NotUserMemory);
+        }
+        if write && flags.bits() & PageFlags::WRITABLE.bits() == 0 {
+            return Err(UserCopyError::ReadOnly);
+        }
+    }
+    Ok(())
+}
+
+/// Fill `dst` from user memory at `src`
+pub fn copy_from_user(dst: &mut [u8], src: u64) -> Result<(), UserCopyError> {
+    check_range(src, dst.len(), false)?;
+    let _access = UserAccess::open();
+    unsafe {
+        core::ptr::copy_nonoverlapping(src as *const u8, dst.as_mut_ptr(), dst.len());
+    }
+    Ok(())
+}
+
+/// Copy `src` to user memory at `dst`
+pub fn copy_to_user(dst: u64, src: &[u8]) -> Result<(), UserCopyError> {
+    check_range(dst, src.len(), true)?;
+    let _access = UserAccess::open();
+    unsafe {
+        core::ptr::copy_nonoverlapping(src.as_ptr(), dst as *mut u8, src.len());
+    }
+    Ok(())
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Plain data that any bit pattern is a valid value of, so reading one
+/// out of memory userspace controls can never produce an invalid value
+///
+/// Sealed: only the integer types below implement it. A `bool`, an enum
+/// or a reference read this way could hold a value its type forbids.
+pub trait Pod: sealed::Sealed + Copy {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),*) => {
+        $(
+            impl sealed::Sealed for $ty {}
+            impl Pod for $ty {}
+        )*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// Read a `T` from user memory at `src`
+pub fn read_user<T: Pod>(src: u64) -> Result<T, UserCopyError> {
+    check_range(src, core::mem::size_of::<T>(), false)?;
+    let _access = UserAccess::open();
+    Ok(unsafe { core::ptr::read_unaligned(src as *const T) })
+}
+
+/// Write `value` to user memory at `dst`
+pub fn write_user<T>(dst: u64, value: T) -> Result<(), UserCopyError> {
+    check_range(dst, core::mem::size_of::<T>(), true)?;
+    let _access = UserAccess::open();
+    unsafe { core::ptr::write_unaligned(dst as *mut T, value) };
+    Ok(())
+}
+
+/// Write `value` as element `index` of the user array of `T` at `base`
+pub fn write_user_at<T>(base: u64, index: usize, value: T) -> Result<(), UserCopyError> {
+    let offset = index
+        .checked_mul(core::mem::size_of::<T>())
+        .ok_or(UserCopyError::OutOfRange)?;
+    let dst = base
+        .checked_add(offset as u64)
+        .ok_or(UserCopyError::OutOfRange)?;
+    write_user(dst, value)
+}
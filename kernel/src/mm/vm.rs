@@ -39,6 +39,9 @@
 // - Page mapping APIs for the active PML4 or an explicit PML4 root
 // - Translation helpers for debugging and verification
 // - Stack safety helper to ensure the current kernel stack is fully mapped
+// - Device register mappings for userspace drivers, refused over anything
+//   the firmware's memory map describes (RAM, ACPI tables, reserved
+//   windows such as PCI ECAM)
 //
 // Correctness and safety notes:
 // - TLB is explicitly invalidated (`invlpg`) on mapping changes
@@ -89,7 +92,12 @@ const HIGHER_HALF_MIRROR_SIZE: usize = 512 * 1024 * 1024;
 static ACTIVE_PML4: AtomicUsize = AtomicUsize::new(0);
 static MAPPED_PAGES: AtomicUsize = AtomicUsize::new(0);
 static PAGE_TABLE_PAGES: AtomicUsize = AtomicUsize::new(0);
+/// The firmware's memory map; its buffer is loader data, which the PMM
+/// never hands out
+static FIRMWARE_MAP: spin::Once<MemoryMap> = spin::Once::new();
 const LOG_ORIGIN: &str = "vmm";
+#[cfg(feature = "ktest")]
+const SELF_TEST_VIRT: usize = 0xFFFF_A000_0000_0000;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VmError {
@@ -98,6 +106,8 @@ pub enum VmError {
     AlreadyMapped,
     NotMapped,
     OutOfMemory,
+    /// The range is memory the firmware describes, not a device window
+    Reserved,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -204,6 +214,9 @@ pub struct VmStats {
 
 pub fn init(memory_map: &MemoryMap) {
     log_info!(LOG_ORIGIN, "Initializing virtual memory manager...");
+    FIRMWARE_MAP.call_once(|| {
+        MemoryMap::new(memory_map.buffer, memory_map.size, memory_map.descriptor_size)
+    });
 
     let pml4_phys = pmm::alloc_page_zeroed().expect("Failed to allocate PML4");
     PAGE_TABLE_PAGES.fetch_add(1, Ordering::Relaxed);
@@ -376,6 +389,67 @@ pub fn map_framebuffer(fb_addr: u64, fb_size: usize) -> bool {
     error_count == 0
 }
 
+/// Identity-map a device's registers for a userspace driver
+///
+/// Only physical ranges outside the firmware's memory map are taken:
+/// anything it describes, RAM, ACPI tables or reserved windows such as
+/// PCI ECAM, is refused with `Reserved`. The kernel's own device windows
+/// (APIC, VGA) are already mapped without the USER flag, so any page
+/// mapped that way is refused too; pages a driver mapped before (a
+/// restarted driver, the framebuffer) can be mapped again.
+pub fn map_mmio(phys: u64, size: usize) -> Result<(), VmError> {
+    let pml4_phys = ACTIVE_PML4.load(Ordering::Relaxed);
+    if pml4_phys == 0 {
+        return Err(VmError::NotInitialized);
+    }
+
+    let start = pmm::align_down(phys as usize);
+    let end = pmm::align_up(phys as usize + size);
+
+    if is_firmware_described(start as u64, end as u64) {
+        return Err(VmError::Reserved);
+    }
+
+    for page in (start..end).step_by(pmm::PAGE_SIZE) {
+        if let Ok((_, flags)) = query_mapping_in_pml4(pml4_phys, page) {
+            if flags.bits() & PageFlags::USER.bits() == 0 {
+                return Err(VmError::AlreadyMapped);
+            }
+        }
+    }
+
+    let mmio_flags = PageFlags(
+        PageFlags::PRESENT.bits() |
+        PageFlags::WRITABLE.bits() |
+        PageFlags::USER.bits() |
+        PageFlags::CACHE_DISABLE.bits() |
+        PageFlags::NO_EXECUTE.bits()
+    );
+
+    for page in (start..end).step_by(pmm::PAGE_SIZE) {
+        match map_page(page, page, mmio_flags) {
+            Ok(()) | Err(VmError::AlreadyMapped) => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    log_info!(LOG_ORIGIN, "Mapped MMIO 0x{:X}-0x{:X} for userspace", start, end);
+    Ok(())
+}
+
+/// Whether any part of `start..end` is in a firmware memory map entry
+fn is_firmware_described(start: u64, end: u64) -> bool {
+    let Some(map) = FIRMWARE_MAP.get() else {
+        return true;
+    };
+    map.descriptors().any(|desc| {
+        let desc_end = desc
+            .physical_start
+            .saturating_add(desc.number_of_pages.saturating_mul(pmm::PAGE_SIZE as u64));
+        start < desc_end && desc.physical_start < end
+    })
+}
+
 pub fn ensure_current_stack_mapped(pages: usize) -> bool {
     if pages == 0 {
         return true;
@@ -644,6 +718,71 @@ pub fn translate(virt: usize) -> Option<usize> {
     Some(entry.addr())
 }
 
+/// Exercise map, translate, remap and unmap on a scratch page, naming the
+/// first step that misbehaves. Leaves no mapping or frame behind, though
+/// the page tables built for the scratch address stay.
+#[cfg(feature = "ktest")]
+pub fn self_test() -> Result<(), &'static str> {
+    let first = pmm::alloc_page_zeroed().ok_or("no frame for the test page")?;
+    let Some(second) = pmm::alloc_page_zeroed() else {
+        pmm::free_page(first);
+        return Err("no frame for the remap target");
+    };
+
+    let result = self_test_steps(SELF_TEST_VIRT, first, second);
+    let _ = unmap_page(SELF_TEST_VIRT);
+    pmm::free_page(first);
+    pmm::free_page(second);
+    result
+}
+
+#[cfg(feature = "ktest")]
+fn self_test_steps(virt: usize, first: usize, second: usize) -> Result<(), &'static str> {
+    let flags = PageFlags::kernel_rw_nx();
+    let mapped_before = MAPPED_PAGES.load(Ordering::Relaxed);
+
+    if translate(virt).is_some() {
+        return Err("scratch address is already mapped");
+    }
+    map_page(virt, first, flags).map_err(|_| "map_page failed")?;
+    if translate(virt) != Some(first) {
+        return Err("translate does not see the new mapping");
+    }
+    if map_page(virt, second, flags) != Err(VmError::AlreadyMapped) {
+        return Err("mapping a mapped page was not refused");
+    }
+    if map_page(virt + 1, first, flags) != Err(VmError::Unaligned) {
+        return Err("unaligned mapping was not refused");
+    }
+
+    // Written through the mapping, read back through the identity map
+    unsafe { core::ptr::write_volatile(virt as *mut u64, 0xA70D_5E1F_7E57_0001) };
+    if unsafe { core::ptr::read_volatile(first as *const u64) } != 0xA70D_5E1F_7E57_0001 {
+        return Err("write through the mapping did not reach the frame");
+    }
+
+    remap_page(virt, second, flags).map_err(|_| "remap_page failed")?;
+    if translate(virt) != Some(second) {
+        return Err("translate does not see the remapping");
+    }
+    if unsafe { core::ptr::read_volatile(virt as *const u64) } != 0 {
+        return Err("stale TLB entry after remap");
+    }
+
+    unmap_page(virt).map_err(|_| "unmap_page failed")?;
+    if translate(virt).is_some() {
+        return Err("translate still sees the unmapped page");
+    }
+    if unmap_page(virt) != Err(VmError::NotMapped) {
+        return Err("unmapping an unmapped page was not refused");
+    }
+    if MAPPED_PAGES.load(Ordering::Relaxed) != mapped_before {
+        return Err("mapped page count drifted");
+    }
+
+    Ok(())
+}
+
 fn map_page_internal(
     pml4_phys: usize,
     virt: usize,
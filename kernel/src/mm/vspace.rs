@@ -0,0 +1,80 @@
+// User Window Allocator
+//
+// Hands out ranges of the lower half for mappings the kernel places on a
+// program's behalf. All programs share one set of page tables, so an
+// address a library or service picks for itself can be the address
+// another program already picked; windows from here never overlap.
+//
+// Key responsibilities:
+// - Reserve page-aligned windows inside `WINDOW_BASE..WINDOW_END`
+// - Release them again, only for the thread that reserved them
+//
+// Implementation details:
+// - Reserved windows live in a `BTreeMap` keyed by start; a reservation
+//   takes the first gap that fits
+// - Every window is followed by an unmapped guard page, so running off the
+//   end of a buffer or stack faults instead of reaching a neighbour
+// - The range sits far above any physical address, so it never meets the
+//   identity mappings of RAM and device registers below it
+//
+// Limitations:
+// - Only address space is handed out; callers map and unmap the pages
+
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+
+use crate::mm::pmm::PAGE_SIZE;
+use crate::thread::ThreadId;
+
+/// First address handed out
+pub const WINDOW_BASE: usize = 0x0000_7000_0000_0000;
+
+/// End of the window range, exclusive
+pub const WINDOW_END: usize = 0x0000_7F00_0000_0000;
+
+struct Window {
+    owner: ThreadId,
+    /// Length in bytes, guard page included
+    len: usize,
+}
+
+static WINDOWS: Mutex<BTreeMap<usize, Window>> = Mutex::new(BTreeMap::new());
+
+/// Reserve a window of `pages` pages for `owner`, returning its start
+///
+/// Returns `None` for zero pages or when no gap is large enough.
+pub fn reserve(owner: ThreadId, pages: usize) -> Option<usize> {
+    if pages == 0 {
+        return None;
+    }
+    let len = pages.checked_add(1)?.checked_mul(PAGE_SIZE)?;
+
+    let mut windows = WINDOWS.lock();
+    let mut candidate = WINDOW_BASE;
+    for (&start, window) in windows.iter() {
+        if start - candidate >= len {
+            break;
+        }
+        candidate = start + window.len;
+    }
+
+    if WINDOW_END - candidate < len {
+        return None;
+    }
+    windows.insert(candidate, Window { owner, len });
+    Some(candidate)
+}
+
+/// Release the window starting at `start`
+///
+/// Returns false, leaving it reserved, unless `owner` reserved it.
+pub fn release(owner: ThreadId, start: usize) -> bool {
+    let mut windows = WINDOWS.lock();
+    match windows.get(&start) {
+        Some(window) if window.owner == owner => {
+            windows.remove(&start);
+            true
+        }
+        _ => false,
+    }
+}
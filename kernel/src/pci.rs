@@ -0,0 +1,306 @@
+// PCI Device Ownership
+//
+// Userspace drivers program their devices themselves; the kernel only has
+// to know which device a driver was given and where that device's
+// registers are, so SYS_MAP_MMIO and the I/O port syscalls reach those and
+// nothing else.
+//
+// Key responsibilities:
+// - Read and write configuration space through mechanism #1 (0xCF8/0xCFC),
+//   for the kernel and, on a driver's behalf, through SYS_PCI_CONFIG_*
+// - Find functions by address or by vendor and device ID
+// - Claim a function for one thread, recording its memory and I/O BARs
+// - Answer whether a physical range or a port range lies inside a claimed
+//   function's BARs
+//
+// Implementation details:
+// - Functions are named by their bus/device/function packed into a u16,
+//   the `bdf` of `cap::ResourceType::Device`
+// - BARs are sized once, when the function is claimed, with memory and
+//   I/O decoding turned off meanwhile; later checks use that record, never
+//   what the BARs read back, and drivers cannot rewrite the registers that
+//   place the function (`ADDRESS_REGISTERS`), so the record stays true
+// - A claim held by a thread that no longer exists can be taken over
+// - Configuration ports are never handed to userspace: each access takes
+//   `CONFIG_LOCK` so the address and data halves of two accesses cannot
+//   interleave
+//
+// Limitations:
+// - Only segment 0, and only what mechanism #1 reaches; no ECAM access
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::thread::{self, ThreadId};
+use crate::log_info;
+
+const LOG_ORIGIN: &str = "pci";
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+const REG_VENDOR_DEVICE: u8 = 0x00;
+const REG_COMMAND: u8 = 0x04;
+const REG_HEADER_TYPE: u8 = 0x0C;
+const REG_BAR0: u8 = 0x10;
+
+const COMMAND_IO_SPACE: u32 = 1 << 0;
+const COMMAND_MEMORY_SPACE: u32 = 1 << 1;
+
+/// Header type 0 (ordinary functions) has six BARs; bridges have two
+const MAX_BARS: usize = 6;
+
+/// Configuration registers below this identify a function; anyone may
+/// read them to find a device
+pub const PUBLIC_CONFIG_END: u8 = 0x10;
+
+/// Registers that decide which addresses a function decodes: the BARs and
+/// expansion ROM of an ordinary function, and the BARs, bus numbers,
+/// forwarding windows and ROM of a bridge. Only the kernel writes them.
+pub const ADDRESS_REGISTERS: core::ops::Range<u8> = 0x10..0x3C;
+
+/// A claimed function: who holds it and the windows it decodes
+struct Claim {
+    owner: ThreadId,
+    /// `(start, end)` of each memory BAR, end exclusive
+    windows: Vec<(u64, u64)>,
+    /// `(start, end)` of each I/O BAR, end exclusive
+    io_windows: Vec<(u32, u32)>,
+}
+
+static CLAIMS: Mutex<BTreeMap<u16, Claim>> = Mutex::new(BTreeMap::new());
+
+/// Held across each address/data pair
+static CONFIG_LOCK: Mutex<()> = Mutex::new(());
+
+/// Pack a function's location the way `ResourceType::Device` stores it
+pub const fn bdf(bus: u8, device: u8, function: u8) -> u16 {
+    ((bus as u16) << 8) | ((device as u16 & 0x1F) << 3) | (function as u16 & 0x07)
+}
+
+/// Parse `SSSS:BB:DD.F`, the way manifests and `lspci -D` write it
+pub fn parse_address(text: &str) -> Option<u16> {
+    let mut parts = text.split(':');
+    let segment = u16::from_str_radix(parts.next()?, 16).ok()?;
+    let bus = u8::from_str_radix(parts.next()?, 16).ok()?;
+    let (device, function) = parts.next()?.split_once('.')?;
+    let device = u8::from_str_radix(device, 16).ok()?;
+    let function = u8::from_str_radix(function, 16).ok()?;
+    if parts.next().is_some() || segment != 0 || device >= 32 || function >= 8 {
+        return None;
+    }
+    Some(bdf(bus, device, function))
+}
+
+/// Vendor and device ID of a function, if one is present
+pub fn ids(bdf: u16) -> Option<(u16, u16)> {
+    let value = read(bdf, REG_VENDOR_DEVICE);
+    let vendor = value as u16;
+    (vendor != 0xFFFF).then_some((vendor, (value >> 16) as u16))
+}
+
+/// Every function with the given vendor and device IDs
+pub fn find_by_id(vendor: u16, device: u16) -> Vec<u16> {
+    let mut found = Vec::new();
+    for bus in 0..=255u8 {
+        for slot in 0..32u8 {
+            for function in 0..8u8 {
+                let address = bdf(bus, slot, function);
+                let Some(function_ids) = ids(address) else {
+                    if function == 0 {
+                        break;
+                    }
+                    continue;
+                };
+                if function_ids == (vendor, device) {
+                    found.push(address);
+                }
+                let multifunction = read(address, REG_HEADER_TYPE) & (0x80 << 16) != 0;
+                if function == 0 && !multifunction {
+                    break;
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Give the function at `bdf` to `owner`
+///
+/// Fails if no function is there or another live thread holds it. Claims
+/// are made while services are spawned, one at a time.
+pub fn claim(bdf: u16, owner: ThreadId) -> bool {
+    if ids(bdf).is_none() {
+        return false;
+    }
+
+    // The thread table is checked with the claims unlocked: capability
+    // checks call `owns_window` while holding the thread table
+    let holder = CLAIMS.lock().get(&bdf).map(|claim| claim.owner);
+    if holder.is_some_and(|holder| holder != owner && thread::find_thread(holder).is_some()) {
+        return false;
+    }
+
+    let claim = sized_claim(bdf, owner);
+    log_info!(
+        LOG_ORIGIN,
+        "{:02X}:{:02X}.{} claimed by thread {} ({} memory, {} I/O BARs)",
+        bdf >> 8,
+        (bdf >> 3) & 0x1F,
+        bdf & 0x7,
+        owner,
+        claim.windows.len(),
+        claim.io_windows.len()
+    );
+    CLAIMS.lock().insert(bdf, claim);
+    true
+}
+
+/// Whether `owner` holds the function at `bdf`
+pub fn is_claimed_by(bdf: u16, owner: ThreadId) -> bool {
+    CLAIMS.lock().get(&bdf).is_some_and(|claim| claim.owner == owner)
+}
+
+/// Whether `start..end` lies inside one memory BAR of the function at
+/// `bdf`, as recorded when `owner` claimed it
+pub fn owns_window(bdf: u16, owner: ThreadId, start: u64, end: u64) -> bool {
+    let claims = CLAIMS.lock();
+    claims.get(&bdf).is_some_and(|claim| {
+        claim.owner == owner
+            && claim
+                .windows
+                .iter()
+                .any(|&(bar_start, bar_end)| start >= bar_start && end <= bar_end)
+    })
+}
+
+/// Whether ports `start..end` lie inside one I/O BAR of the function at
+/// `bdf`, as recorded when `owner` claimed it
+pub fn owns_ports(bdf: u16, owner: ThreadId, start: u32, end: u32) -> bool {
+    let claims = CLAIMS.lock();
+    claims.get(&bdf).is_some_and(|claim| {
+        claim.owner == owner
+            && claim
+                .io_windows
+                .iter()
+                .any(|&(bar_start, bar_end)| start >= bar_start && end <= bar_end)
+    })
+}
+
+/// Read the configuration register at `offset` (dword aligned)
+pub fn config_read(bdf: u16, offset: u8) -> u32 {
+    read(bdf, offset)
+}
+
+/// Write the configuration register at `offset` (dword aligned) for a
+/// driver
+///
+/// Refused, returning false, inside `ADDRESS_REGISTERS`: a moved BAR would
+/// no longer match the windows recorded at claim time.
+pub fn config_write(bdf: u16, offset: u8, value: u32) -> bool {
+    if ADDRESS_REGISTERS.contains(&offset) {
+        return false;
+    }
+    write(bdf, offset, value);
+    true
+}
+
+/// A claim on `bdf` with the memory and I/O windows it decodes, found by
+/// writing all ones to each BAR and reading back which address bits stick
+fn sized_claim(bdf: u16, owner: ThreadId) -> Claim {
+    let command = read(bdf, REG_COMMAND);
+    write(bdf, REG_COMMAND, command & !(COMMAND_MEMORY_SPACE | COMMAND_IO_SPACE));
+
+    let mut windows = Vec::new();
+    let mut io_windows = Vec::new();
+    let mut index = 0;
+    while index < MAX_BARS {
+        let offset = REG_BAR0 + index as u8 * 4;
+        let low = read(bdf, offset);
+        if low & 0x1 != 0 {
+            write(bdf, offset, 0xFFFF_FFFF);
+            // Only the low 16 bits decode on x86
+            let mask = read(bdf, offset) & 0xFFFC;
+            write(bdf, offset, low);
+            let base = low & 0xFFFC;
+            if mask != 0 && base != 0 {
+                io_windows.push((base, base + ((!mask & 0xFFFF) + 1)));
+            }
+            index += 1;
+            continue;
+        }
+        let is_64 = (low >> 1) & 0x3 == 0x2 && index + 1 < MAX_BARS;
+
+        write(bdf, offset, 0xFFFF_FFFF);
+        let mut mask = (read(bdf, offset) & !0xF) as u64;
+        write(bdf, offset, low);
+        let mut base = (low & !0xF) as u64;
+
+        if is_64 {
+            let high = read(bdf, offset + 4);
+            write(bdf, offset + 4, 0xFFFF_FFFF);
+            mask |= (read(bdf, offset + 4) as u64) << 32;
+            write(bdf, offset + 4, high);
+            base |= (high as u64) << 32;
+        }
+
+        // An unimplemented BAR keeps reading back as zero
+        if mask != 0 && base != 0 {
+            if !is_64 {
+                mask |= 0xFFFF_FFFF_0000_0000;
+            }
+            if let Some(end) = base.checked_add((!mask).wrapping_add(1)) {
+                windows.push((base, end));
+            }
+        }
+        index += if is_64 { 2 } else { 1 };
+    }
+
+    write(bdf, REG_COMMAND, command);
+    Claim { owner, windows, io_windows }
+}
+
+fn config_address(bdf: u16, offset: u8) -> u32 {
+    0x8000_0000 | ((bdf as u32) << 8) | (offset as u32 & 0xFC)
+}
+
+fn read(bdf: u16, offset: u8) -> u32 {
+    let _config = CONFIG_LOCK.lock();
+    unsafe {
+        outl(CONFIG_ADDRESS, config_address(bdf, offset));
+        inl(CONFIG_DATA)
+    }
+}
+
+fn write(bdf: u16, offset: u8, value: u32) {
+    let _config = CONFIG_LOCK.lock();
+    unsafe {
+        outl(CONFIG_ADDRESS, config_address(bdf, offset));
+        outl(CONFIG_DATA, value);
+    }
+}
+
+#[inline]
+unsafe fn outl(port: u16, value: u32) {
+    #[cfg(target_arch = "x86_64")]
+    core::arch::asm!(
+        "out dx, eax",
+        in("dx") port,
+        in("eax") value,
+        options(nomem, nostack, preserves_flags)
+    );
+}
+
+#[inline]
+unsafe fn inl(port: u16) -> u32 {
+    let ret: u32;
+    #[cfg(target_arch = "x86_64")]
+    core::arch::asm!(
+        "in eax, dx",
+        out("eax") ret,
+        in("dx") port,
+        options(nomem, nostack, preserves_flags)
+    );
+    ret
+}
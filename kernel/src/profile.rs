@@ -0,0 +1,139 @@
+// Sampling Profiler
+//
+// Finds where CPU time goes by sampling: while a profile runs, every timer
+// interrupt records the interrupted instruction pointer and thread. Counted
+// per function, the samples give a flat profile of the compositor, IPC
+// paths or anything else that runs long enough to be hit.
+//
+// Key responsibilities:
+// - Take a sample on each timer tick while a profile is running
+// - Keep samples in one ring per CPU, so CPUs never contend for a ring
+// - Start and stop profiles, optionally sampling a single thread
+// - Name the function around each sampled address when samples are read
+//
+// Sample format:
+// - Interrupted RIP, the running thread, and whether it was in user mode
+// - CR3 at the time, so user addresses are looked up in the right image
+//
+// Design principles:
+// - Off unless started: a stopped profiler costs the timer one atomic load
+// - Nothing is symbolized in the interrupt; names are looked up when
+//   samples are read (SYS_PROFILE_READ), from the programs' symbol tables
+// - Readers keep a cursor per ring, like the kernel trace
+//
+// Limitations:
+// - Sampling runs at the timer's 100 Hz, so only work lasting well over
+//   10 ms shows up reliably
+// - Only programs built with symbols (`elf2atxf --symbols`) get function
+//   names; kernel addresses stay unnamed
+// - Programs that exit before samples are read lose their names too
+// - The kernel runs on the boot CPU only, so there is a single ring
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::log_info;
+use crate::trace::Ring;
+use crate::util::without_interrupts;
+
+const LOG_ORIGIN: &str = "profile";
+
+/// CPUs with a sample ring
+pub const MAX_CPUS: usize = 1;
+
+/// Samples each ring holds before the oldest are overwritten; 20 seconds
+/// at 100 Hz
+const SAMPLE_RING_SIZE: usize = 2048;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub rip: u64,
+    /// Thread that was running, or 0
+    pub thread: u64,
+    /// CR3 when the sample was taken
+    pub page_table: u64,
+    /// Whether the CPU was running user code
+    pub user: bool,
+}
+
+impl Sample {
+    pub const EMPTY: Self = Self {
+        rip: 0,
+        thread: 0,
+        page_table: 0,
+        user: false,
+    };
+}
+
+static RINGS: [Mutex<Ring<Sample, SAMPLE_RING_SIZE>>; MAX_CPUS] =
+    [const { Mutex::new(Ring::new()) }; MAX_CPUS];
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Thread the running profile samples, or 0 for every thread
+static ONLY_THREAD: AtomicU64 = AtomicU64::new(0);
+
+/// Samples taken since the profile started
+static TAKEN: AtomicU64 = AtomicU64::new(0);
+
+/// Ring of the CPU this runs on
+fn current_cpu() -> usize {
+    0
+}
+
+/// Take a sample; called by the timer interrupt with the interrupted RIP
+pub fn on_timer_tick(rip: u64, user: bool) {
+    if !RUNNING.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let thread = crate::sched::try_current_thread().map_or(0, |id| id.raw());
+    let only = ONLY_THREAD.load(Ordering::Relaxed);
+    if only != 0 && thread != only {
+        return;
+    }
+
+    let sample = Sample {
+        rip,
+        thread,
+        page_table: crate::arch::read_cr3(),
+        user,
+    };
+    // Interrupts are off in the handler, so the ring cannot be held here
+    RINGS[current_cpu()].lock().push(sample);
+    TAKEN.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Start a profile of `thread`, or of every thread if 0, dropping the
+/// samples of the last one; false if a profile is already running
+pub fn start(thread: u64) -> bool {
+    if RUNNING.load(Ordering::Relaxed) {
+        return false;
+    }
+
+    for ring in &RINGS {
+        without_interrupts(|| ring.lock().clear());
+    }
+    ONLY_THREAD.store(thread, Ordering::Relaxed);
+    TAKEN.store(0, Ordering::Relaxed);
+    RUNNING.store(true, Ordering::Relaxed);
+
+    log_info!(LOG_ORIGIN, "Profiling started (thread={})", thread);
+    true
+}
+
+/// Stop the running profile; returns how many samples it took, including
+/// any since overwritten
+pub fn stop() -> u64 {
+    if RUNNING.swap(false, Ordering::Relaxed) {
+        log_info!(LOG_ORIGIN, "Profiling stopped after {} samples", TAKEN.load(Ordering::Relaxed));
+    }
+    TAKEN.load(Ordering::Relaxed)
+}
+
+/// Copy samples from the ring of `cpu`, starting at sequence `seq`, into
+/// `out`; see `Ring::read_from`. None if there is no such CPU.
+pub fn read(cpu: usize, seq: u64, out: &mut [Sample]) -> Option<(usize, u64)> {
+    let ring = RINGS.get(cpu)?;
+    Some(without_interrupts(|| ring.lock().read_from(seq, out)))
+}
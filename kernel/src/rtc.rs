@@ -0,0 +1,181 @@
+// CMOS Real-Time Clock
+//
+// Provides wall-clock time to the rest of the system. The battery-backed
+// RTC is read once at boot; afterwards the time is advanced with the timer
+// tick counter, so reading it never touches the hardware again.
+//
+// Key responsibilities:
+// - Read the RTC date and time registers consistently
+// - Normalise BCD / 12-hour encodings to binary 24-hour values
+// - Convert the result to seconds since the Unix epoch
+//
+// Implementation details:
+// - Registers are read twice until two reads agree, and only while the
+//   "update in progress" flag is clear, to avoid torn values
+// - The RTC is assumed to hold UTC and the century is assumed to be 20xx
+// - Ticks are 10 ms (the timer runs at 100 Hz)
+//
+// Limitations and future direction:
+// - No time zone support; userspace gets UTC
+// - No way to set the clock yet
+
+use spin::Once;
+
+use crate::log_info;
+
+const LOG_ORIGIN: &str = "rtc";
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+
+/// Status A: an update cycle is in progress
+const STATUS_A_UPDATING: u8 = 0x80;
+/// Status B: values are binary rather than BCD
+const STATUS_B_BINARY: u8 = 0x04;
+/// Status B: hours are 24-hour rather than 12-hour
+const STATUS_B_24_HOUR: u8 = 0x02;
+
+const TICKS_PER_SECOND: u64 = 100;
+
+/// Wall-clock time at boot and the tick count it was read at
+struct BootTime {
+    epoch_seconds: u64,
+    ticks: u64,
+}
+
+static BOOT_TIME: Once<BootTime> = Once::new();
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+}
+
+/// Read the RTC and remember the boot time; needs the timer running
+pub fn init() {
+    let time = BOOT_TIME.call_once(|| BootTime {
+        epoch_seconds: read_epoch_seconds(),
+        ticks: crate::interrupts::get_ticks(),
+    });
+    log_info!(LOG_ORIGIN, "Wall clock at boot: {} s since epoch", time.epoch_seconds);
+}
+
+/// Seconds since the Unix epoch (UTC), or 0 before `init`
+pub fn now() -> u64 {
+    match BOOT_TIME.get() {
+        Some(boot) => {
+            let elapsed = crate::interrupts::get_ticks().saturating_sub(boot.ticks);
+            boot.epoch_seconds + elapsed / TICKS_PER_SECOND
+        }
+        None => 0,
+    }
+}
+
+fn read_epoch_seconds() -> u64 {
+    // Two identical reads in a row cannot straddle an update
+    let mut time = read_raw();
+    loop {
+        let again = read_raw();
+        if again == time {
+            break;
+        }
+        time = again;
+    }
+
+    let status_b = read_register(REG_STATUS_B);
+    let decode = |value: u8| {
+        if status_b & STATUS_B_BINARY != 0 {
+            value
+        } else {
+            (value & 0x0F) + (value >> 4) * 10
+        }
+    };
+
+    // In 12-hour mode the top bit of the hour marks PM
+    let pm = status_b & STATUS_B_24_HOUR == 0 && time.hour & 0x80 != 0;
+    let mut hour = decode(time.hour & 0x7F);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        hour %= 12;
+        if pm {
+            hour += 12;
+        }
+    }
+
+    let year = 2000 + decode(time.year) as i64;
+    let days = days_from_civil(year, decode(time.month) as i64, decode(time.day) as i64);
+
+    days as u64 * 86_400
+        + hour as u64 * 3_600
+        + decode(time.minute) as u64 * 60
+        + decode(time.second) as u64
+}
+
+fn read_raw() -> RawTime {
+    while read_register(REG_STATUS_A) & STATUS_A_UPDATING != 0 {
+        core::hint::spin_loop();
+    }
+
+    RawTime {
+        second: read_register(REG_SECONDS),
+        minute: read_register(REG_MINUTES),
+        hour: read_register(REG_HOURS),
+        day: read_register(REG_DAY),
+        month: read_register(REG_MONTH),
+        year: read_register(REG_YEAR),
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn read_register(register: u8) -> u8 {
+    unsafe {
+        outb(CMOS_ADDRESS, register);
+        inb(CMOS_DATA)
+    }
+}
+
+#[inline]
+unsafe fn outb(port: u16, value: u8) {
+    #[cfg(target_arch = "x86_64")]
+    core::arch::asm!(
+        "out dx, al",
+        in("dx") port,
+        in("al") value,
+        options(nomem, nostack, preserves_flags)
+    );
+}
+
+#[inline]
+unsafe fn inb(port: u16) -> u8 {
+    let ret: u8;
+    #[cfg(target_arch = "x86_64")]
+    core::arch::asm!(
+        "in al, dx",
+        out("al") ret,
+        in("dx") port,
+        options(nomem, nostack, preserves_flags)
+    );
+    ret
+}
@@ -28,6 +28,7 @@
 // - Scheduler state is protected by spinlocks for simplicity
 // - Global singleton (`SCHEDULER`) centralizes all scheduling decisions
 // - Thread metadata and context are managed by the `thread` subsystem
+// - Switches and wakeups are recorded in the kernel trace (`trace`)
 //
 // Correctness and safety notes:
 // - Scheduling is disabled until `init()` installs an idle thread
@@ -47,7 +48,7 @@
 #![allow(dead_code)]
 
 use alloc::collections::{BTreeMap, VecDeque};
-use core::sync::atomic::{AtomicBool, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use spin::Mutex;
 
 use crate::arch::gdt;
@@ -97,6 +98,21 @@ struct Scheduler {
     current: Mutex<Option<ThreadId>>,
     idle: Mutex<Option<ThreadId>>,
     initialized: AtomicBool,
+    // Timer ticks each thread has run for, up to the last switch away
+    // from it, and the tick the current thread started running at
+    run_ticks: Mutex<BTreeMap<ThreadId, u64>>,
+    switched_at: AtomicU64,
+    context_switches: AtomicU64,
+}
+
+/// Scheduler counters since boot
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SchedStats {
+    /// Timer ticks since boot
+    pub ticks: u64,
+    /// Ticks the idle thread ran for
+    pub idle_ticks: u64,
+    pub context_switches: u64,
 }
 
 impl Scheduler {
@@ -110,6 +126,9 @@ impl Scheduler {
             current: Mutex::new(None),
             idle: Mutex::new(None),
             initialized: AtomicBool::new(false),
+            run_ticks: Mutex::new(BTreeMap::new()),
+            switched_at: AtomicU64::new(0),
+            context_switches: AtomicU64::new(0),
         }
     }
 
@@ -213,6 +232,20 @@ impl Scheduler {
                 thread::set_thread_state(prev, ThreadState::Ready);
             }
         }
+        if previous != chosen {
+            let now = crate::interrupts::get_ticks();
+            let started = self.switched_at.swap(now, Ordering::Relaxed);
+            if let Some(prev) = previous {
+                *self.run_ticks.lock().entry(prev).or_insert(0) += now.saturating_sub(started);
+            }
+            self.context_switches.fetch_add(1, Ordering::Relaxed);
+            crate::trace_event!(
+                sched,
+                SWITCH,
+                previous.map_or(0, |id| id.raw()),
+                chosen.map_or(0, |id| id.raw())
+            );
+        }
 
         if let Some(id) = chosen {
             thread::set_thread_state(id, ThreadState::Running);
@@ -264,11 +297,38 @@ impl Scheduler {
         let priority = self.get_priority(id);
         thread::set_thread_state(id, ThreadState::Ready);
         self.ready.lock().push(id, priority);
+        crate::trace_event!(sched, WAKE, id.raw());
     }
 
     fn current_thread(&self) -> Option<ThreadId> {
         *self.current.lock()
     }
+
+    fn try_current_thread(&self) -> Option<ThreadId> {
+        self.current.try_lock().and_then(|current| *current)
+    }
+
+    fn stats(&self) -> SchedStats {
+        let idle_ticks = self
+            .idle_id()
+            .map(|idle| self.thread_ticks(idle))
+            .unwrap_or(0);
+        SchedStats {
+            ticks: crate::interrupts::get_ticks(),
+            idle_ticks,
+            context_switches: self.context_switches.load(Ordering::Relaxed),
+        }
+    }
+
+    fn thread_ticks(&self, id: ThreadId) -> u64 {
+        let recorded = self.run_ticks.lock().get(&id).copied().unwrap_or(0);
+        if self.current_thread() == Some(id) {
+            let now = crate::interrupts::get_ticks();
+            recorded + now.saturating_sub(self.switched_at.load(Ordering::Relaxed))
+        } else {
+            recorded
+        }
+    }
 }
 
 static SCHEDULER: Scheduler = Scheduler::new();
@@ -307,6 +367,12 @@ pub fn current_thread() -> Option<ThreadId> {
     SCHEDULER.current_thread()
 }
 
+/// `current_thread` without waiting for the scheduler's lock; None if it
+/// is held, as when the debugger stops the kernel inside the scheduler
+pub fn try_current_thread() -> Option<ThreadId> {
+    SCHEDULER.try_current_thread()
+}
+
 pub fn boost_thread_priority(id: ThreadId, new_priority: ThreadPriority) -> bool {
     SCHEDULER.boost_priority(id, new_priority)
 }
@@ -323,6 +389,15 @@ pub fn get_base_priority(id: ThreadId) -> ThreadPriority {
     SCHEDULER.get_base_priority(id)
 }
 
+pub fn get_stats() -> SchedStats {
+    SCHEDULER.stats()
+}
+
+/// Timer ticks the thread has been running for
+pub fn thread_ticks(id: ThreadId) -> u64 {
+    SCHEDULER.thread_ticks(id)
+}
+
 /// Yield the current thread, allowing other threads to run
 pub fn yield_current() {
     // Get current thread
@@ -342,6 +417,7 @@ pub fn yield_current() {
 pub fn perform_context_switch(from_id: ThreadId, to_id: ThreadId) {
     without_interrupts(|| {
         let target_kernel_stack = thread::kernel_stack_top(to_id);
+        thread::switch_fpu_state(from_id, to_id);
 
         thread::with_thread_contexts(from_id, to_id, |from_ctx, to_ctx| unsafe {
             if let Some(stack) = target_kernel_stack {
@@ -8,6 +8,7 @@
 // Key responsibilities:
 // - Initialize the COM1 serial port in a known-good configuration
 // - Provide byte- and string-level output primitives
+// - Poll for input bytes, for the GDB stub
 // - Integrate with Rust’s `fmt::Write` for formatted output
 // - Expose safe macros for kernel-wide serial logging
 //
@@ -36,7 +37,7 @@
 // - Serial output is considered the ground-truth log sink
 //
 // Limitations and future direction:
-// - Output-only: no serial input support
+// - Input is polled, with no receive interrupt; only the GDB stub reads
 // - Legacy UART only; no USB or modern debug transports
 // - In the future, serial may become optional or be replaced by a
 //   user-space debug/logging service
@@ -48,7 +49,7 @@
 
 use core::fmt;
 
-const COM1: u16 = 0x3F8;
+pub const COM1: u16 = 0x3F8;
 
 pub struct SerialPort {
     base: u16,
@@ -83,6 +84,27 @@ impl SerialPort {
         unsafe { inb(self.base + 5) & 0x20 != 0 }
     }
 
+    /// The next received byte, if one is waiting
+    pub fn try_read_byte(&self) -> Option<u8> {
+        unsafe {
+            if inb(self.base + 5) & 0x01 != 0 {
+                Some(inb(self.base))
+            } else {
+                None
+            }
+        }
+    }
+
+    /// Wait for the next received byte
+    pub fn read_byte(&self) -> u8 {
+        loop {
+            if let Some(byte) = self.try_read_byte() {
+                return byte;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
     pub fn write_byte(&self, byte: u8) {
         while !self.is_transmit_empty() {
             core::hint::spin_loop();
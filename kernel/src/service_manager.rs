@@ -12,12 +12,18 @@
 // - Auditability: validation and startup planning are logged during boot.
 // - Determinism: dependency resolution uses a stable topological order.
 // - Safety: manifest parsing is strict and rejects malformed input early.
+// - Enforcement: capabilities the kernel or a service checks (devices,
+//   IRQs, DMA, screenshots) are turned into kernel capabilities owned by
+//   the service's thread when it is spawned; the rest are recorded for
+//   auditing only.
 
 use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use spin::{Mutex, Once};
 
+use crate::cap::{self, CapPermissions, ResourceType};
+use crate::thread::{self, ThreadId};
 use crate::{log_error, log_info, log_warn};
 
 const LOG_ORIGIN: &str = "svcman";
@@ -25,7 +31,20 @@ const LOG_ORIGIN: &str = "svcman";
 const EMBEDDED_BOOT_MANIFEST: &str = r#"
 [service.ui_shell]
 binary = "/init/ui_shell.elf"
-capabilities = ["FrameBufferCap", "PointerCap"]
+capabilities = ["PointerCap", "IPCPortCap", "MemRegionCap"]
+depends_on = ["display"]
+
+[service.display]
+binary = "/init/display_driver.elf"
+capabilities = ["FrameBufferCap", "IPCPortCap", "MemRegionCap", "DMABufferCap", "DeviceCap:1af4:1050"]
+
+[service.audio]
+binary = "/init/audio_server.elf"
+capabilities = ["IPCPortCap", "MemRegionCap", "DMABufferCap", "DeviceCap:8086:2415"]
+
+[service.serial]
+binary = "/init/serial_driver.elf"
+capabilities = ["DebugCap"]
 
 [service.fs_server]
 binary = "/init/fs.elf"
@@ -35,6 +54,40 @@ depends_on = ["storage_driver"]
 [service.storage_driver]
 binary = "/init/nvme_driver.elf"
 capabilities = ["IRQCap:33", "DeviceCap:0000:01:00.0", "DMABufferCap"]
+
+# Applications, started on demand from the desktop launcher
+[service.files]
+binary = "/apps/files.elf"
+capabilities = ["IPCPortCap", "MemRegionCap"]
+autostart = false
+
+[service.settings]
+binary = "/apps/settings.elf"
+capabilities = ["IPCPortCap", "MemRegionCap"]
+autostart = false
+
+[service.browser]
+binary = "/apps/browser.elf"
+capabilities = ["IPCPortCap", "MemRegionCap"]
+autostart = false
+
+[service.terminal]
+binary = "/apps/terminal.elf"
+capabilities = ["IPCPortCap", "MemRegionCap", "DebugCap"]
+autostart = false
+
+# Integration tests, started instead of waiting for a user when the kernel
+# is booted with `test_runner`
+[service.test_runner]
+binary = "/init/test_runner.elf"
+capabilities = ["IPCPortCap", "MemRegionCap"]
+depends_on = ["ui_shell"]
+autostart = false
+
+[service.test_child]
+binary = "/init/test_child.elf"
+capabilities = ["IPCPortCap"]
+autostart = false
 "#;
 
 #[derive(Debug, Clone)]
@@ -43,6 +96,10 @@ pub struct ServiceSpec {
     pub binary: String,
     pub capabilities: Vec<String>,
     pub depends_on: Vec<String>,
+    /// Started at boot; otherwise only when a program asks for it
+    pub autostart: bool,
+    /// Arguments the program reads at startup, e.g. `--scale=2`
+    pub args: Vec<String>,
 }
 
 impl ServiceSpec {
@@ -52,6 +109,8 @@ impl ServiceSpec {
             binary: String::new(),
             capabilities: Vec::new(),
             depends_on: Vec::new(),
+            autostart: true,
+            args: Vec::new(),
         }
     }
 }
@@ -78,6 +137,10 @@ impl BootManifest {
         self.services.get(name)
     }
 
+    pub fn service_by_binary(&self, binary: &str) -> Option<&ServiceSpec> {
+        self.services.values().find(|spec| spec.binary == binary)
+    }
+
     pub fn count(&self) -> usize {
         self.services.len()
     }
@@ -287,6 +350,91 @@ pub fn log_manifest_summary(manager: &ServiceManager) {
     }
 }
 
+/// Give the thread running a service the kernel capabilities its manifest
+/// entry names
+///
+/// - `DeviceCap:SSSS:BB:DD.F` claims that PCI function
+/// - `DeviceCap:VVVV:DDDD` claims every function with those vendor and
+///   device IDs
+/// - `IRQCap:N` grants IRQ line `N`
+/// - `DMABufferCap` grants the right to allocate DMA memory
+/// - `ScreenshotCap` lets the compositor's capture requests through
+/// - `FrameBufferCap` allows switching the screen's video mode
+/// - `DebugCap` allows changing kernel log levels, tracing and profiling
+///
+/// A device that is absent or held by another service is skipped with a
+/// warning, so the service starts and fails on its own when it tries to
+/// use it.
+pub fn grant_capabilities(tid: ThreadId, names: &[String]) {
+    for name in names {
+        let (kind, arg) = match name.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (name.as_str(), None),
+        };
+
+        let resources = match (kind, arg) {
+            ("DeviceCap", Some(address)) => claim_devices(tid, address),
+            ("IRQCap", Some(irq)) => match irq.parse::<u8>() {
+                Ok(irq_num) => Vec::from([ResourceType::Irq { irq_num }]),
+                Err(_) => Vec::new(),
+            },
+            // The buffers themselves are allocated later; a zero-sized
+            // buffer stands for the right to allocate them
+            ("DMABufferCap", None) => Vec::from([ResourceType::DmaBuffer {
+                phys_addr: 0,
+                size: 0,
+            }]),
+            // Not enforced by the kernel yet
+            ("ScreenshotCap", None) => Vec::from([ResourceType::Screenshot]),
+            ("FrameBufferCap", None) => Vec::from([ResourceType::Framebuffer]),
+            ("DebugCap", None) => Vec::from([ResourceType::Debug]),
+            ("IPCPortCap" | "MemRegionCap" | "PointerCap", None) => continue,
+            _ => {
+                log_warn!(LOG_ORIGIN, "Thread {}: unknown capability '{}'", tid, name);
+                continue;
+            }
+        };
+
+        if resources.is_empty() {
+            log_warn!(LOG_ORIGIN, "Thread {}: could not grant '{}'", tid, name);
+        }
+        for resource in resources {
+            let granted = cap::create_root_capability(
+                resource,
+                tid,
+                CapPermissions::READ.union(CapPermissions::WRITE),
+            )
+            .map_err(|_| ())
+            .and_then(|cap| thread::add_thread_capability(tid, cap).map_err(|_| ()));
+            if granted.is_err() {
+                log_error!(LOG_ORIGIN, "Thread {}: failed to grant '{}'", tid, name);
+            }
+        }
+    }
+}
+
+/// Claim the PCI functions a `DeviceCap` argument names for `tid`
+fn claim_devices(tid: ThreadId, address: &str) -> Vec<ResourceType> {
+    let functions = match crate::pci::parse_address(address) {
+        Some(bdf) => Vec::from([bdf]),
+        None => match address.split_once(':') {
+            Some((vendor, device)) => {
+                match (u16::from_str_radix(vendor, 16), u16::from_str_radix(device, 16)) {
+                    (Ok(vendor), Ok(device)) => crate::pci::find_by_id(vendor, device),
+                    _ => Vec::new(),
+                }
+            }
+            None => Vec::new(),
+        },
+    };
+
+    functions
+        .into_iter()
+        .filter(|&bdf| crate::pci::claim(bdf, tid))
+        .map(|bdf| ResourceType::Device { bdf })
+        .collect()
+}
+
 pub fn parse_manifest(text: &str) -> Result<BootManifest, ManifestError> {
     let mut services: BTreeMap<String, ServiceSpec> = BTreeMap::new();
     let mut current_service: Option<String> = None;
@@ -343,6 +491,12 @@ pub fn parse_manifest(text: &str) -> Result<BootManifest, ManifestError> {
             "depends_on" => {
                 spec.depends_on = parse_array(value, line_no)?;
             }
+            "autostart" => {
+                spec.autostart = parse_bool(value, line_no)?;
+            }
+            "args" => {
+                spec.args = parse_array(value, line_no)?;
+            }
             _ => {
                 return Err(ManifestError::UnknownKey {
                     key: key.to_string(),
@@ -390,6 +544,17 @@ fn parse_string(value: &str, line_no: usize) -> Result<String, ManifestError> {
     }
 }
 
+fn parse_bool(value: &str, line_no: usize) -> Result<bool, ManifestError> {
+    match value.trim() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        other => Err(ManifestError::InvalidSection {
+            line: line_no,
+            content: other.to_string(),
+        }),
+    }
+}
+
 fn parse_array(value: &str, line_no: usize) -> Result<Vec<String>, ManifestError> {
     let mut entries = Vec::new();
     let trimmed = value.trim();
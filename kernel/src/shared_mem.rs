@@ -28,11 +28,15 @@
 // - Region sizes are page-aligned and backed by zeroed physical pages
 // - Page poke flags enforce user access and NX by default
 // - Mapping tracks (thread, virtual address, permissions) tuples
+// - A mapping asked for at address 0 is placed by the kernel, in a window
+//   from `mm::vspace` that is given back when the mapping goes
 // - Reference counting prevents destruction while regions are mapped
 //
 // Correctness and safety notes:
 // - All global state is protected by spinlocks
-// - Virtual addresses must be page-aligned and non-overlapping
+// - Virtual addresses must be page-aligned and non-overlapping; programs
+//   share page tables, so anything but a throwaway mapping should let the
+//   kernel choose
 // - Owner-only destruction enforces clear responsibility
 // - Physical memory is returned to the PMM on final destruction
 //
@@ -55,7 +59,7 @@ use alloc::vec::Vec;
 use core::sync::atomic::{AtomicU64, Ordering};
 use spin::Mutex;
 
-use crate::mm::{pmm, vm};
+use crate::mm::{pmm, vm, vspace};
 use crate::thread::ThreadId;
 use crate::log_info;
 use crate::log_debug;
@@ -198,6 +202,8 @@ struct RegionMapping {
     thread_id: ThreadId,
     virt_addr: usize,
     flags: RegionFlags,
+    /// Placed by the kernel; the window is released on unmap
+    placed: bool,
 }
 
 #[derive(Debug)]
@@ -251,7 +257,7 @@ impl SharedRegion {
     }
 
     fn map(&mut self, thread_id: ThreadId, virt_addr: usize, flags: RegionFlags)
-        -> Result<(), SharedMemError>
+        -> Result<usize, SharedMemError>
     {
         if !pmm::is_page_aligned(virt_addr) {
             return Err(SharedMemError::Unaligned);
@@ -261,6 +267,14 @@ impl SharedRegion {
             return Err(SharedMemError::AlreadyMapped);
         }
 
+        let placed = virt_addr == 0;
+        let virt_addr = if placed {
+            vspace::reserve(thread_id, self.physical_pages.len())
+                .ok_or(SharedMemError::OutOfMemory)?
+        } else {
+            virt_addr
+        };
+
         let page_flags = flags.to_page_flags();
         for (i, &phys_page) in self.physical_pages.iter().enumerate() {
             let virt = virt_addr + (i * pmm::PAGE_SIZE);
@@ -270,6 +284,9 @@ impl SharedRegion {
                     let virt_to_unmap = virt_addr + (j * pmm::PAGE_SIZE);
                     let _ = vm::unmap_page(virt_to_unmap);
                 }
+                if placed {
+                    vspace::release(thread_id, virt_addr);
+                }
 
                 return match e {
                     vm::VmError::AlreadyMapped => Err(SharedMemError::AlreadyMapped),
@@ -283,6 +300,7 @@ impl SharedRegion {
             thread_id,
             virt_addr,
             flags,
+            placed,
         });
         self.ref_count += 1;
 
@@ -295,7 +313,7 @@ impl SharedRegion {
             self.physical_pages.len()
         );
 
-        Ok(())
+        Ok(virt_addr)
     }
 
     fn unmap(&mut self, thread_id: ThreadId) -> Result<(), SharedMemError> {
@@ -310,6 +328,9 @@ impl SharedRegion {
             let virt = mapping.virt_addr + (i * pmm::PAGE_SIZE);
             let _ = vm::unmap_page(virt);
         }
+        if mapping.placed {
+            vspace::release(thread_id, mapping.virt_addr);
+        }
 
         self.ref_count -= 1;
 
@@ -372,7 +393,7 @@ impl SharedMemManager {
         thread_id: ThreadId,
         virt_addr: usize,
         flags: RegionFlags,
-    ) -> Result<(), SharedMemError> {
+    ) -> Result<usize, SharedMemError> {
         let mut regions = self.regions.lock();
         let region = regions.get_mut(&region_id).ok_or(SharedMemError::InvalidRegion)?;
 
@@ -491,12 +512,14 @@ pub fn create_region(owner: ThreadId, size: usize) -> Result<RegionId, SharedMem
     SHARED_MEM_MANAGER.create_region(owner, size)
 }
 
+/// Map a region for `thread_id` at `virt_addr`, or where the kernel
+/// chooses if `virt_addr` is 0, returning the address used
 pub fn map_region(
     region_id: RegionId,
     thread_id: ThreadId,
     virt_addr: usize,
     flags: RegionFlags,
-) -> Result<(), SharedMemError> {
+) -> Result<usize, SharedMemError> {
     SHARED_MEM_MANAGER.map_region(region_id, thread_id, virt_addr, flags)
 }
 
@@ -16,7 +16,8 @@
 // - Uses the `SYSCALL/SYSRET` fast path (x86_64)
 // - `MSR_STAR` defines user ↔ kernel code segment transitions
 // - `MSR_LSTAR` points to the assembly-level syscall entry stub
-// - `MSR_SFMASK` masks IF/TF on entry to prevent user-controlled flags
+// - `MSR_SFMASK` masks IF/TF/DF/AC on entry to prevent user-controlled
+//   flags; a user AC flag would otherwise switch SMAP off in the kernel
 // - Enables syscall support by setting EFER.SCE
 //
 // Dispatch model:
@@ -36,6 +37,8 @@
 // Subsystem coverage:
 // - Thread management (yield, exit, sleep, create)
 // - IPC (ports, send/recv, async, batching, tracing, stats)
+// - Kernel tracing (reading the trace ring, choosing traced subsystems)
+// - Sampling profiler (start, stop, read named samples)
 // - Capability lifecycle (create, check, revoke, derive, transfer, query)
 // - Shared memory regions (create/map/unmap/destroy)
 // - Address space management and virtual memory region mapping
@@ -47,20 +50,24 @@
 // - Many checks are marked MVP-friendly, allowing gradual hardening
 //
 // Correctness and safety notes:
-// - User pointers are copied explicitly into kernel-owned buffers
+// - User pointers are plain addresses, only ever read or written through
+//   `mm::usercopy`, which checks them against the caller's page tables;
+//   anything it refuses is EINVAL, and with SMAP any other access faults
 // - Blocking syscalls interact carefully with the scheduler and timer ticks
 // - Misconfiguration of syscall MSRs can cause fatal faults, making `init()`
 //   strictly early-boot only
 // - This module assumes interrupts and GDT are already initialized
 //
 // Future considerations:
-// - Stricter validation of user pointers and memory regions
+// - Fault fixups for user copies, once user mappings can change under
+//   them (SMP, demand paging)
 // - Reduction of logging in production builds
 // - Per-process syscall filtering or sandboxing
 
 #![allow(dead_code)]
 
 use crate::arch::gdt::{KERNEL_CODE_SELECTOR, USER_CODE_SELECTOR};
+use crate::mm::usercopy::{copy_from_user, copy_to_user, read_user, write_user, write_user_at};
 use crate::{log_debug, log_info, log_warn, log_error, log_panic};
 
 const MSR_STAR: u32 = 0xC000_0081;
@@ -112,6 +119,37 @@ pub const SYS_MAP_FRAMEBUFFER: u64 = 41;
 pub const SYS_UNREGISTER_IRQ_HANDLER: u64 = 42;
 pub const SYS_IPC_WAIT_ANY: u64 = 43;  // Wait on multiple ports for any event
 pub const SYS_GET_IRQ_COUNT: u64 = 44; // Get IRQ occurrence count for a registered handler
+pub const SYS_DMA_ALLOC: u64 = 45;     // Allocate physically contiguous memory for device DMA
+pub const SYS_KLOG_READ: u64 = 46;     // Read the kernel log ring, from a minimum level
+pub const SYS_KLOG_SET_LEVEL: u64 = 47; // Set capture or serial mirror log level
+pub const SYS_PROC_SPAWN: u64 = 48;    // Start a program declared in the boot manifest
+pub const SYS_GET_TIME: u64 = 49;      // Wall-clock time in seconds since the Unix epoch
+pub const SYS_IPC_WATCH_PORT: u64 = 50; // Get notified on one port when another dies
+pub const SYS_PROC_WAIT: u64 = 51;     // Wait for a spawned program to exit
+pub const SYS_PROC_KILL: u64 = 52;     // Stop a spawned program
+pub const SYS_PROC_OUTPUT_PORT: u64 = 53; // Port a spawned program writes its output to
+pub const SYS_MEM_STATS: u64 = 54;     // Physical memory and kernel heap usage
+pub const SYS_SCHED_STATS: u64 = 55;   // Scheduler counters and thread states
+pub const SYS_THREAD_LIST: u64 = 56;   // Describe every thread
+pub const SYS_CAP_AUDIT_READ: u64 = 57; // Read the capability audit log
+pub const SYS_IPC_REGISTER_NAME: u64 = 58; // Publish an owned port under a name
+pub const SYS_IPC_LOOKUP_NAME: u64 = 59; // Find the port published under a name
+pub const SYS_THREAD_JOIN: u64 = 60;   // Wait for a thread the caller created to exit
+pub const SYS_PROC_ARGS: u64 = 61;     // Read the caller's program arguments
+pub const SYS_BOOT_ARGS: u64 = 62;     // Read the boot parameters
+pub const SYS_SYMBOLIZE: u64 = 63;     // Name the function around a code address
+pub const SYS_TRACE_READ: u64 = 64;    // Read records from the kernel trace
+pub const SYS_TRACE_CONTROL: u64 = 65; // Turn trace subsystems on or off
+pub const SYS_PROFILE_START: u64 = 66; // Start sampling instruction pointers
+pub const SYS_PROFILE_STOP: u64 = 67;  // Stop sampling
+pub const SYS_PROFILE_READ: u64 = 68;  // Read profiler samples, with function names
+pub const SYS_VIDEO_MODES: u64 = 69;   // List the screen resolutions available
+pub const SYS_SET_VIDEO_MODE: u64 = 70; // Switch the screen resolution
+pub const SYS_MAP_MMIO: u64 = 71;      // Map a device's memory-mapped registers
+pub const SYS_IPC_SENDER_HOLDS: u64 = 72; // Whether a message's sender holds a capability
+pub const SYS_PCI_CONFIG_READ: u64 = 73; // Read a PCI configuration register
+pub const SYS_PCI_CONFIG_WRITE: u64 = 74; // Write a claimed device's configuration register
+pub const SYS_DMA_FREE: u64 = 75;      // Free memory from SYS_DMA_ALLOC
 
 pub const ESUCCESS: u64 = 0;
 pub const EINVAL: u64 = u64::MAX - 1;
@@ -140,7 +178,7 @@ pub fn init() {
         let entry_addr = syscall_entry as *const () as u64;
         wrmsr(MSR_LSTAR, entry_addr);
 
-        let sfmask = (1 << 8) | (1 << 9) | (1 << 10);
+        let sfmask = (1 << 8) | (1 << 9) | (1 << 10) | crate::arch::RFLAGS_AC;
         wrmsr(MSR_SFMASK, sfmask);
 
         let efer_msr = 0xC000_0080;
@@ -213,7 +251,13 @@ extern "C" fn rust_syscall_dispatcher(
         syscall_num, arg0, arg1, arg2, arg3, arg4, arg5
     );
 
-    match syscall_num {
+    // Reading the trace would otherwise fill it with its own reads
+    let traced = syscall_num != SYS_TRACE_READ;
+    if traced {
+        crate::trace_event!(syscall, ENTER, syscall_num, arg0, arg1, arg2);
+    }
+
+    let result = match syscall_num {
         SYS_THREAD_YIELD => sys_thread_yield(),
         SYS_THREAD_EXIT => sys_thread_exit(arg0),
         SYS_THREAD_SLEEP => sys_thread_sleep(arg0),
@@ -249,16 +293,47 @@ extern "C" fn rust_syscall_dispatcher(
         SYS_REGISTER_FAULT_HANDLER => sys_register_fault_handler(arg0),
         SYS_MOUSE_POLL => sys_mouse_poll(),
         SYS_IO_PORT_READ => sys_io_port_read(arg0 as u16, arg1 as u8),
-        SYS_IO_PORT_WRITE => sys_io_port_write(arg0 as u16, arg1 as u8),
+        SYS_IO_PORT_WRITE => sys_io_port_write(arg0 as u16, arg1 as u32, arg2 as u8),
         SYS_KEYBOARD_POLL => sys_keyboard_poll(),
-        SYS_GET_FRAMEBUFFER => sys_get_framebuffer(arg0 as *mut u64),
+        SYS_GET_FRAMEBUFFER => sys_get_framebuffer(arg0),
         SYS_GET_TICKS => sys_get_ticks(),
-        SYS_DEBUG_LOG => sys_debug_log(arg0 as *const u8, arg1 as usize),
+        SYS_DEBUG_LOG => sys_debug_log(arg0, arg1 as usize),
         SYS_REGISTER_IRQ_HANDLER => sys_register_irq_handler(arg0 as u8, arg1),
         SYS_MAP_FRAMEBUFFER => sys_map_framebuffer_to_user(arg0),
         SYS_UNREGISTER_IRQ_HANDLER => sys_unregister_irq_handler(arg0 as u8),
         SYS_IPC_WAIT_ANY => sys_ipc_wait_any(arg0, arg1, arg2),
         SYS_GET_IRQ_COUNT => sys_get_irq_count(arg0 as u8),
+        SYS_DMA_ALLOC => sys_dma_alloc(arg0, arg1),
+        SYS_KLOG_READ => sys_klog_read(arg0, arg1 as usize, arg2, arg3),
+        SYS_KLOG_SET_LEVEL => sys_klog_set_level(arg0, arg1),
+        SYS_PROC_SPAWN => sys_proc_spawn(arg0, arg1 as usize, arg2),
+        SYS_GET_TIME => sys_get_time(),
+        SYS_IPC_WATCH_PORT => sys_ipc_watch_port(arg0, arg1),
+        SYS_PROC_WAIT => sys_proc_wait(arg0, arg1),
+        SYS_PROC_KILL => sys_proc_kill(arg0),
+        SYS_PROC_OUTPUT_PORT => sys_proc_output_port(),
+        SYS_MEM_STATS => sys_mem_stats(arg0),
+        SYS_SCHED_STATS => sys_sched_stats(arg0),
+        SYS_THREAD_LIST => sys_thread_list(arg0, arg1),
+        SYS_CAP_AUDIT_READ => sys_cap_audit_read(arg0, arg1),
+        SYS_IPC_REGISTER_NAME => sys_ipc_register_name(arg0, arg1 as usize, arg2),
+        SYS_IPC_LOOKUP_NAME => sys_ipc_lookup_name(arg0, arg1 as usize),
+        SYS_THREAD_JOIN => sys_thread_join(arg0, arg1),
+        SYS_PROC_ARGS => sys_proc_args(arg0, arg1),
+        SYS_BOOT_ARGS => sys_boot_args(arg0, arg1),
+        SYS_SYMBOLIZE => sys_symbolize(arg0, arg1, arg2),
+        SYS_TRACE_READ => sys_trace_read(arg0, arg1, arg2),
+        SYS_TRACE_CONTROL => sys_trace_control(arg0, arg1),
+        SYS_PROFILE_START => sys_profile_start(arg0),
+        SYS_PROFILE_STOP => sys_profile_stop(),
+        SYS_PROFILE_READ => sys_profile_read(arg0, arg1, arg2, arg3),
+        SYS_VIDEO_MODES => sys_video_modes(arg0, arg1),
+        SYS_SET_VIDEO_MODE => sys_set_video_mode(arg0, arg1),
+        SYS_MAP_MMIO => sys_map_mmio(arg0, arg1),
+        SYS_IPC_SENDER_HOLDS => sys_ipc_sender_holds(arg0),
+        SYS_PCI_CONFIG_READ => sys_pci_config_read(arg0, arg1),
+        SYS_PCI_CONFIG_WRITE => sys_pci_config_write(arg0, arg1, arg2),
+        SYS_DMA_FREE => sys_dma_free(arg0),
 
         _ => {
             log_warn!(
@@ -268,7 +343,12 @@ extern "C" fn rust_syscall_dispatcher(
             );
             ENOSYS
         }
+    };
+
+    if traced {
+        crate::trace_event!(syscall, EXIT, syscall_num, result);
     }
+    result
 }
 
 fn sys_mouse_poll() -> u64 {
@@ -281,45 +361,129 @@ fn sys_mouse_poll() -> u64 {
     EWOULDBLOCK
 }
 
-/// Read a byte from an IO port (privileged operation for drivers)
-fn sys_io_port_read(port: u16, _size: u8) -> u64 {
-    // Allow specific PS/2 controller ports for usermode drivers
-    let allowed_ports = [0x60, 0x64]; // PS/2 data and status/command ports
-    
-    if !allowed_ports.contains(&port) {
-        return EPERM;
+/// Legacy IO port ranges any userspace driver may access (inclusive)
+///
+/// Only the PS/2 controller and COM2 are open to everyone. PCI devices'
+/// ports are reached through the I/O BARs of a device the caller claimed,
+/// and configuration space through SYS_PCI_CONFIG_*. Everything else
+/// (PIC, PIT, CMOS, DMA, ACPI and reset ports) stays kernel-only.
+const ALLOWED_IO_RANGES: [(u16, u16); 3] = [
+    (0x0060, 0x0060), // PS/2 data
+    (0x0064, 0x0064), // PS/2 status/command
+    (0x02F8, 0x02FF), // COM2 (serial console service; COM1 stays with the kernel)
+];
+
+fn io_port_allowed(port: u16, size: u8) -> bool {
+    let last = match port.checked_add(size.saturating_sub(1) as u16) {
+        Some(last) => last,
+        None => return false,
+    };
+
+    if ALLOWED_IO_RANGES
+        .iter()
+        .any(|&(start, end)| port >= start && last <= end)
+    {
+        return true;
     }
-    
-    let value: u8 = unsafe {
-        let mut val: u8;
-        core::arch::asm!(
-            "in al, dx",
-            out("al") val,
-            in("dx") port,
-            options(nomem, nostack, preserves_flags)
-        );
-        val
+
+    let Some(caller) = crate::sched::current_thread() else {
+        return false;
     };
-    
-    value as u64
+    let (start, end) = (port as u32, last as u32 + 1);
+    crate::thread::validate_thread_capability_by_type(
+        caller,
+        crate::cap::CapPermissions::WRITE,
+        |resource| {
+            matches!(
+                resource,
+                crate::cap::ResourceType::Device { bdf }
+                    if crate::pci::owns_ports(*bdf, caller, start, end)
+            )
+        },
+    )
 }
 
-/// Write a byte to an IO port (privileged operation for drivers)
-fn sys_io_port_write(port: u16, value: u8) -> u64 {
-    // Allow specific PS/2 controller ports for usermode drivers
-    let allowed_ports = [0x60, 0x64]; // PS/2 data and status/command ports
+/// Read from an IO port (privileged operation for drivers)
+///
+/// `size` selects the access width in bytes (1, 2 or 4).
+fn sys_io_port_read(port: u16, size: u8) -> u64 {
+    if !matches!(size, 1 | 2 | 4) {
+        return EINVAL;
+    }
+
+    if !io_port_allowed(port, size) {
+        return EPERM;
+    }
     
-    if !allowed_ports.contains(&port) {
+    unsafe {
+        match size {
+            1 => {
+                let val: u8;
+                core::arch::asm!(
+                    "in al, dx",
+                    out("al") val,
+                    in("dx") port,
+                    options(nomem, nostack, preserves_flags)
+                );
+                val as u64
+            }
+            2 => {
+                let val: u16;
+                core::arch::asm!(
+                    "in ax, dx",
+                    out("ax") val,
+                    in("dx") port,
+                    options(nomem, nostack, preserves_flags)
+                );
+                val as u64
+            }
+            _ => {
+                let val: u32;
+                core::arch::asm!(
+                    "in eax, dx",
+                    out("eax") val,
+                    in("dx") port,
+                    options(nomem, nostack, preserves_flags)
+                );
+                val as u64
+            }
+        }
+    }
+}
+
+/// Write to an IO port (privileged operation for drivers)
+///
+/// `size` selects the access width in bytes (1, 2 or 4).
+fn sys_io_port_write(port: u16, value: u32, size: u8) -> u64 {
+    if !matches!(size, 1 | 2 | 4) {
+        return EINVAL;
+    }
+
+    if !io_port_allowed(port, size) {
         return EPERM;
     }
     
     unsafe {
-        core::arch::asm!(
-            "out dx, al",
-            in("dx") port,
-            in("al") value,
-            options(nomem, nostack, preserves_flags)
-        );
+        match size {
+            1 => core::arch::asm!(
+                "out dx, al",
+                in("dx") port,
+                in("al") value as u8,
+                options(nomem, nostack, preserves_flags)
+            ),
+            2 => core::arch::asm!(
+                "out dx, ax",
+                in("dx") port,
+                in("ax") value as u16,
+                options(nomem, nostack, preserves_flags)
+            ),
+            _ => core::arch::asm!(
+                "out dx, eax",
+                in("dx") port,
+                in("eax") value,
+                options(nomem, nostack, preserves_flags)
+            ),
+        }
     }
     
     ESUCCESS
@@ -334,20 +498,23 @@ fn sys_keyboard_poll() -> u64 {
 }
 
 /// Get framebuffer information for userspace graphics
-fn sys_get_framebuffer(info_ptr: *mut u64) -> u64 {
-    if info_ptr.is_null() {
+fn sys_get_framebuffer(info_ptr: u64) -> u64 {
+    if info_ptr == 0 {
         return EINVAL;
     }
     
     if let Some((width, height)) = crate::graphics::get_dimensions() {
         if let Some(addr) = crate::graphics::get_framebuffer_address() {
-            unsafe {
-                // Write: [address, width, height, stride, bytes_per_pixel]
-                *info_ptr = addr as u64;
-                *info_ptr.add(1) = width as u64;
-                *info_ptr.add(2) = height as u64;
-                *info_ptr.add(3) = crate::graphics::get_stride() as u64;
-                *info_ptr.add(4) = crate::graphics::get_bytes_per_pixel() as u64;
+            // Write: [address, width, height, stride, bytes_per_pixel]
+            let info = [
+                addr as u64,
+                width as u64,
+                height as u64,
+                crate::graphics::get_stride() as u64,
+                crate::graphics::get_bytes_per_pixel() as u64,
+            ];
+            if write_user(info_ptr, info).is_err() {
+                return EINVAL;
             }
             return ESUCCESS;
         }
@@ -360,15 +527,22 @@ fn sys_get_ticks() -> u64 {
     crate::interrupts::get_ticks()
 }
 
+/// Wall-clock time (UTC) in seconds since the Unix epoch
+fn sys_get_time() -> u64 {
+    crate::rtc::now()
+}
+
 /// Debug log from userspace
-fn sys_debug_log(msg_ptr: *const u8, len: usize) -> u64 {
-    if msg_ptr.is_null() || len > 256 {
+fn sys_debug_log(msg_ptr: u64, len: usize) -> u64 {
+    if msg_ptr == 0 || len > 256 {
         return EINVAL;
     }
     
-    let msg = unsafe {
-        core::slice::from_raw_parts(msg_ptr, len)
-    };
+    let mut buf = [0u8; 256];
+    let msg = &mut buf[..len];
+    if copy_from_user(msg, msg_ptr).is_err() {
+        return EINVAL;
+    }
     
     if let Ok(s) = core::str::from_utf8(msg) {
         log_info!("userspace", "{}", s);
@@ -426,6 +600,11 @@ fn sys_thread_exit(exit_code: u64) -> u64 {
     );
 
     if let Some(tid) = crate::sched::current_thread() {
+        // Watchers of its ports learn it is gone
+        crate::ipc::close_owned_ports(tid);
+        record_exit(tid, exit_code);
+        // A test run ends with its runner
+        crate::init_process::on_thread_exit(tid, exit_code);
         crate::thread::set_thread_state(tid, crate::thread::ThreadState::Exited);
         let (prev, next) = crate::sched::on_timer_tick();
 
@@ -472,15 +651,29 @@ fn sys_thread_sleep(ticks: u64) -> u64 {
     ESUCCESS
 }
 
-fn sys_thread_create(entry_point: u64, stack_ptr: u64, flags: u64) -> u64 {
+/// Start a thread running alongside the caller
+///
+/// The thread runs in the caller's mode and address space, on the stack
+/// the caller gives it; `entry_point` gets `arg` as its first argument.
+/// The caller can wait for it with SYS_THREAD_JOIN, and it shares the
+/// caller's output port.
+///
+/// Args:
+///   entry_point: Address the thread starts at; must not return
+///   stack_ptr: Initial stack pointer
+///   arg: Value passed to the thread in RDI
+///
+/// Returns:
+///   Thread ID of the new thread, or error code
+fn sys_thread_create(entry_point: u64, stack_ptr: u64, arg: u64) -> u64 {
     const LOG_ORIGIN: &str = "syscall";
 
     log_debug!(
         LOG_ORIGIN,
-        "thread_create(entry={:#X}, stack={:#X}, flags={:#X})",
+        "thread_create(entry={:#X}, stack={:#X}, arg={:#X})",
         entry_point,
         stack_ptr,
-        flags
+        arg
     );
 
     if entry_point == 0 || stack_ptr == 0 {
@@ -537,7 +730,7 @@ fn sys_thread_create(entry_point: u64, stack_ptr: u64, flags: u64) -> u64 {
         }
     };
 
-    let thread = crate::thread::Thread::new(
+    let mut thread = crate::thread::Thread::new(
         entry_point,
         kernel_stack as u64,
         KERNEL_STACK_SIZE,
@@ -546,7 +739,31 @@ fn sys_thread_create(entry_point: u64, stack_ptr: u64, flags: u64) -> u64 {
         "user_thread",
     );
 
+    // Start the way the caller runs: a user thread drops to ring 3 in its
+    // creator's address space, a kernel-mode one keeps its segments
+    match crate::thread::snapshot_context(caller) {
+        Some(parent) if parent.cs == USER_CODE_SELECTOR => {
+            thread.context =
+                crate::thread::CpuContext::new_user(entry_point, stack_ptr, parent.cr3);
+            thread.address_space = parent.cr3;
+        }
+        _ => thread.context.rsp = stack_ptr,
+    }
+    thread.context.rdi = arg;
+
     let tid = thread.id();
+    {
+        let mut programs = SPAWNED_PROGRAMS.lock();
+        let output_port = programs.get(&caller).and_then(|program| program.output_port);
+        programs.insert(
+            tid,
+            SpawnedProgram {
+                parent: caller,
+                output_port,
+                exit_code: None,
+            },
+        );
+    }
     crate::sched::add_thread(thread);
 
     log_info!(
@@ -687,6 +904,110 @@ fn sys_ipc_close_port(port_id_raw: u64) -> u64 {
     }
 }
 
+/// Copy a port name from userspace into `buf`
+fn read_port_name(
+    name_ptr: u64,
+    name_len: usize,
+    buf: &mut [u8; crate::ipc::MAX_PORT_NAME],
+) -> Option<&str> {
+    if name_ptr == 0 || name_len == 0 || name_len > buf.len() {
+        return None;
+    }
+    copy_from_user(&mut buf[..name_len], name_ptr).ok()?;
+    core::str::from_utf8(&buf[..name_len]).ok()
+}
+
+/// Publish a port the caller owns under a name
+///
+/// Returns:
+///   ESUCCESS, EPERM if the caller does not own the port, EBUSY if the
+///   name belongs to another port
+fn sys_ipc_register_name(name_ptr: u64, name_len: usize, port_id_raw: u64) -> u64 {
+    const LOG_ORIGIN: &str = "syscall";
+
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+    let mut buf = [0u8; crate::ipc::MAX_PORT_NAME];
+    let Some(name) = read_port_name(name_ptr, name_len, &mut buf) else {
+        return EINVAL;
+    };
+
+    let port_id = crate::ipc::PortId::from_raw(port_id_raw);
+    match crate::ipc::register_name(name, port_id, caller) {
+        Ok(()) => {
+            log_debug!(LOG_ORIGIN, "Port {} registered as '{}'", port_id, name);
+            ESUCCESS
+        }
+        Err(crate::ipc::IpcError::PermissionDenied) => EPERM,
+        Err(crate::ipc::IpcError::PortBusy) => EBUSY,
+        Err(_) => EINVAL,
+    }
+}
+
+/// Find the port published under a name
+///
+/// Returns:
+///   The port id, or 0 if no port has the name
+fn sys_ipc_lookup_name(name_ptr: u64, name_len: usize) -> u64 {
+    let mut buf = [0u8; crate::ipc::MAX_PORT_NAME];
+    match read_port_name(name_ptr, name_len, &mut buf) {
+        Some(name) => crate::ipc::lookup_name(name).map_or(0, |port| port.raw()),
+        None => EINVAL,
+    }
+}
+
+fn sys_ipc_watch_port(port_id_raw: u64, notify_raw: u64) -> u64 {
+    const LOG_ORIGIN: &str = "syscall";
+
+    log_debug!(
+        LOG_ORIGIN,
+        "ipc_watch_port(port_id={}, notify={})",
+        port_id_raw,
+        notify_raw
+    );
+
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => {
+            log_warn!(
+                LOG_ORIGIN,
+                "ipc_watch_port rejected: no current thread"
+            );
+            return EINVAL;
+        }
+    };
+
+    let port_id = crate::ipc::PortId::from_raw(port_id_raw);
+    let notify = crate::ipc::PortId::from_raw(notify_raw);
+
+    match crate::ipc::watch_port(port_id, notify, caller) {
+        Ok(()) => ESUCCESS,
+
+        Err(crate::ipc::IpcError::PermissionDenied) => {
+            log_warn!(
+                LOG_ORIGIN,
+                "ipc_watch_port denied: caller={} does not own notify port {}",
+                caller,
+                notify
+            );
+            EPERM
+        }
+
+        Err(e) => {
+            log_warn!(
+                LOG_ORIGIN,
+                "ipc_watch_port failed: {:?} (port_id={}, notify={})",
+                e,
+                port_id,
+                notify
+            );
+            EINVAL
+        }
+    }
+}
+
 fn sys_ipc_send(
     port_id_raw: u64,
     msg_type: u64,
@@ -734,8 +1055,7 @@ fn sys_ipc_send(
         port_id
     );
 
-    let payload = alloc::vec::Vec::new();
-    let message = crate::ipc::Message::new(sender, msg_type as u32, payload);
+    let message = crate::ipc::Message::new(sender, msg_type as u32, crate::ipc::Payload::new());
 
     match crate::ipc::send_message(port_id, message) {
         Ok(_) => {
@@ -848,14 +1168,11 @@ fn sys_ipc_recv(
         let bytes_to_copy =
             core::cmp::min(msg.payload.len(), buffer_size as usize);
 
-        if buffer_ptr != 0 && bytes_to_copy > 0 {
-            unsafe {
-                core::ptr::copy_nonoverlapping(
-                    msg.payload.as_ptr(),
-                    buffer_ptr as *mut u8,
-                    bytes_to_copy
-                );
-            }
+        if buffer_ptr != 0
+            && bytes_to_copy > 0
+            && copy_to_user(buffer_ptr, &msg.payload[..bytes_to_copy]).is_err()
+        {
+            return EINVAL;
         }
 
         log_debug!(
@@ -1033,15 +1350,12 @@ fn sys_ipc_send_async(
         port_id
     );
 
-    let mut payload = alloc::vec::Vec::new();
+    // Small payloads are copied straight into the message, without a heap allocation
+    let mut payload = crate::ipc::Payload::new();
     if payload_len > 0 && payload_ptr != 0 {
-        payload.resize(payload_len as usize, 0);
-        unsafe {
-            core::ptr::copy_nonoverlapping(
-                payload_ptr as *const u8,
-                payload.as_mut_ptr(),
-                payload_len as usize
-            );
+        payload = crate::ipc::Payload::zeroed(payload_len as usize);
+        if copy_from_user(&mut payload, payload_ptr).is_err() {
+            return EINVAL;
         }
     }
 
@@ -1131,14 +1445,11 @@ fn sys_ipc_try_recv(
             let bytes_to_copy =
                 core::cmp::min(msg.payload.len(), buffer_size as usize);
 
-            if buffer_ptr != 0 && bytes_to_copy > 0 {
-                unsafe {
-                    core::ptr::copy_nonoverlapping(
-                        msg.payload.as_ptr(),
-                        buffer_ptr as *mut u8,
-                        bytes_to_copy
-                    );
-                }
+            if buffer_ptr != 0
+                && bytes_to_copy > 0
+                && copy_to_user(buffer_ptr, &msg.payload[..bytes_to_copy]).is_err()
+            {
+                return EINVAL;
             }
 
             log_debug!(
@@ -1202,7 +1513,8 @@ impl From<&crate::ipc::IpcTraceEvent> for RawIpcTraceEvent {
 }
 
 fn sys_ipc_trace_read(buffer_ptr: u64, max_events: u64) -> u64 {
-    log_info!(
+    // Debug level: `captrace -f` polls this several times a second
+    log_debug!(
         "syscall",
         "ipc_trace_read(buffer={:#x}, max={})",
         buffer_ptr,
@@ -1218,10 +1530,9 @@ fn sys_ipc_trace_read(buffer_ptr: u64, max_events: u64) -> u64 {
 
     if buffer_ptr != 0 {
         let to_copy = core::cmp::min(available, max_events as usize);
-        unsafe {
-            let buffer = buffer_ptr as *mut RawIpcTraceEvent;
-            for (idx, event) in events.iter().take(to_copy).enumerate() {
-                buffer.add(idx).write(RawIpcTraceEvent::from(event));
+        for (idx, event) in events.iter().take(to_copy).enumerate() {
+            if write_user_at(buffer_ptr, idx, RawIpcTraceEvent::from(event)).is_err() {
+                return EINVAL;
             }
         }
     }
@@ -1239,6 +1550,7 @@ struct RawIpcPortStats {
     max_latency_ms: u64,
     avg_latency_ms: u64,
     messages_per_second: u64,
+    queued: u64,
 }
 
 impl From<crate::ipc::IpcPortStats> for RawIpcPortStats {
@@ -1252,6 +1564,7 @@ impl From<crate::ipc::IpcPortStats> for RawIpcPortStats {
             max_latency_ms: stats.max_latency_ms,
             avg_latency_ms: stats.avg_latency_ms,
             messages_per_second: stats.messages_per_second,
+            queued: stats.queued,
         }
     }
 }
@@ -1275,10 +1588,8 @@ fn sys_ipc_port_stats(port_id_raw: u64, stats_ptr: u64) -> u64 {
                 stats.avg_latency_ms
             );
 
-            if stats_ptr != 0 {
-                unsafe {
-                    (stats_ptr as *mut RawIpcPortStats).write(stats.into());
-                }
+            if stats_ptr != 0 && write_user(stats_ptr, RawIpcPortStats::from(stats)).is_err() {
+                return EINVAL;
             }
 
             ESUCCESS
@@ -1341,7 +1652,8 @@ fn sys_ipc_send_batch(port_id_raw: u64, messages_ptr: u64, count: u64) -> u64 {
 
     let mut messages = alloc::vec::Vec::new();
     for i in 0..count {
-        let msg = crate::ipc::Message::new(sender, i as u32, alloc::vec![i as u8]);
+        let payload = crate::ipc::Payload::from_slice(&[i as u8]);
+        let msg = crate::ipc::Message::new(sender, i as u32, payload);
         messages.push(msg);
     }
 
@@ -1519,7 +1831,7 @@ fn sys_ipc_send_with_cap(
         return EPERM;
     }
 
-    let payload = alloc::vec::Vec::new();
+    let payload = crate::ipc::Payload::new();
     let is_move = (mode_or_perms >> 32) != 0;
     let message = if is_move {
         log_debug!(
@@ -1961,10 +2273,9 @@ fn sys_cap_query_children(handle_raw: u64, buffer_ptr: u64, buffer_size: u64) ->
 
             if buffer_ptr != 0 && buffer_size > 0 {
                 let to_copy = core::cmp::min(count, buffer_size as usize);
-                unsafe {
-                    let buffer = buffer_ptr as *mut u64;
-                    for i in 0..to_copy {
-                        *buffer.add(i) = children[i].raw();
+                for (i, child) in children.iter().take(to_copy).enumerate() {
+                    if write_user_at(buffer_ptr, i, child.raw()).is_err() {
+                        return EINVAL;
                     }
                 }
                 log_debug!(
@@ -2030,6 +2341,10 @@ fn sys_shared_region_create(size: u64) -> u64 {
     }
 }
 
+/// Map a shared region into the caller
+///
+/// A `virt_addr` of 0 lets the kernel choose a free window. Returns the
+/// address the region was mapped at, or an error code.
 fn sys_shared_region_map(region_id_raw: u64, virt_addr: u64, flags_raw: u64) -> u64 {
     log_info!(
         "syscall",
@@ -2054,14 +2369,14 @@ fn sys_shared_region_map(region_id_raw: u64, virt_addr: u64, flags_raw: u64) ->
     let flags = crate::shared_mem::RegionFlags::from_raw(flags_raw);
 
     match crate::shared_mem::map_region(region_id, caller, virt_addr as usize, flags) {
-        Ok(()) => {
+        Ok(mapped) => {
             log_debug!(
                 "syscall",
                 "shared_region_map: mapped region {:?} to virt=0x{:X}",
                 region_id,
-                virt_addr
+                mapped
             );
-            ESUCCESS
+            mapped as u64
         }
         Err(e) => {
             log_warn!(
@@ -2630,7 +2945,7 @@ pub fn notify_irq_handler(irq: u8) {
         let msg = crate::ipc::Message::new(
             crate::thread::ThreadId::from_raw(0), // Kernel sender
             irq as u32, // Message type is IRQ number
-            alloc::vec![irq], // Payload is the IRQ number
+            crate::ipc::Payload::from_slice(&[irq]), // Payload is the IRQ number
         );
 
         // Non-blocking send - we're in interrupt context
@@ -2689,14 +3004,16 @@ fn sys_map_framebuffer_to_user(user_buffer: u64) -> u64 {
 
     // Write info to user buffer if provided
     if user_buffer != 0 {
-        let info_ptr = user_buffer as *mut u64;
-        unsafe {
-            core::ptr::write_volatile(info_ptr, address as u64);
-            core::ptr::write_volatile(info_ptr.add(1), width as u64);
-            core::ptr::write_volatile(info_ptr.add(2), height as u64);
-            core::ptr::write_volatile(info_ptr.add(3), stride as u64);
-            core::ptr::write_volatile(info_ptr.add(4), bpp as u64);
-            core::ptr::write_volatile(info_ptr.add(5), fb_size as u64);
+        let info = [
+            address as u64,
+            width as u64,
+            height as u64,
+            stride as u64,
+            bpp as u64,
+            fb_size as u64,
+        ];
+        if write_user(user_buffer, info).is_err() {
+            return EINVAL;
         }
     }
 
@@ -2716,67 +3033,1300 @@ fn sys_map_framebuffer_to_user(user_buffer: u64) -> u64 {
 }
 
 // ============================================================================
-// Event-Based Input Primitives for Userspace Drivers
+// Display Modes
 // ============================================================================
 
-/// IRQ occurrence counters for userspace polling
-static IRQ_COUNTS: Mutex<BTreeMap<u8, u64>> = Mutex::new(BTreeMap::new());
+/// Largest number of modes a single SYS_VIDEO_MODES copies
+const MAX_VIDEO_MODES_READ: usize = crate::boot::MAX_VIDEO_MODES + 1;
 
-/// Increment IRQ count (called from interrupt handlers)
-pub fn increment_irq_count(irq: u8) {
-    let mut counts = IRQ_COUNTS.lock();
-    *counts.entry(irq).or_insert(0) += 1;
+/// A video mode as SYS_VIDEO_MODES writes it
+#[repr(C)]
+struct RawVideoMode {
+    width: u32,
+    height: u32,
 }
 
-/// Get current IRQ count for a registered handler
-/// Userspace can use this to detect new events without IPC overhead
-fn sys_get_irq_count(irq: u8) -> u64 {
+/// List the resolutions the screen can switch to, current one first
+///
+/// Args:
+///   buf_ptr: Array of RawVideoMode
+///   max_modes: Array length
+///
+/// Returns:
+///   Number of modes available, which may be more than were copied
+fn sys_video_modes(buf_ptr: u64, max_modes: u64) -> u64 {
+    if buf_ptr == 0 && max_modes != 0 {
+        return EINVAL;
+    }
+
+    let mut modes = [crate::boot::VideoMode::empty(); MAX_VIDEO_MODES_READ];
+    let count = crate::graphics::video_modes(&mut modes);
+    let copied = count.min(MAX_VIDEO_MODES_READ).min(max_modes as usize);
+
+    for (idx, mode) in modes[..copied].iter().enumerate() {
+        let raw = RawVideoMode {
+            width: mode.width,
+            height: mode.height,
+        };
+        if write_user_at(buf_ptr, idx, raw).is_err() {
+            return EINVAL;
+        }
+    }
+
+    count as u64
+}
+
+/// Switch the screen resolution
+///
+/// Args:
+///   width, height: One of the modes SYS_VIDEO_MODES lists
+///
+/// Returns:
+///   ESUCCESS, EINVAL for a mode not listed, EPERM unless the caller holds
+///   the framebuffer capability (`FrameBufferCap` in its manifest entry),
+///   ENOSYS without a framebuffer or mode-setting backend, or EBUSY if the
+///   adapter refused the mode
+fn sys_set_video_mode(width: u64, height: u64) -> u64 {
+    use crate::graphics::ModeError;
+
+    if width > u32::MAX as u64 || height > u32::MAX as u64 {
+        return EINVAL;
+    }
+
     let caller = match crate::sched::current_thread() {
         Some(tid) => tid,
         None => return EINVAL,
     };
 
-    // Verify caller owns this IRQ handler
-    let handlers = IRQ_HANDLERS.lock();
-    match handlers.get(&irq) {
-        Some((owner, _)) if *owner == caller => {
-            drop(handlers);
-            let counts = IRQ_COUNTS.lock();
-            counts.get(&irq).copied().unwrap_or(0)
-        }
-        Some(_) => EPERM,
-        None => EINVAL,
+    let has_permission = crate::thread::validate_thread_capability_by_type(
+        caller,
+        crate::cap::CapPermissions::WRITE,
+        |resource| *resource == crate::cap::ResourceType::Framebuffer,
+    );
+    if !has_permission {
+        log_warn!("syscall", "set_video_mode: thread {} has no framebuffer capability", caller);
+        return EPERM;
+    }
+
+    match crate::graphics::set_mode(width as u32, height as u32) {
+        Ok(()) => ESUCCESS,
+        Err(ModeError::UnknownMode) => EINVAL,
+        Err(ModeError::Unsupported) => ENOSYS,
+        Err(ModeError::Failed) => EBUSY,
     }
 }
 
-/// Wait for any of multiple IPC ports to have data
+// ============================================================================
+// DMA Memory for Userspace Drivers
+// ============================================================================
+
+/// Largest single DMA allocation (in pages) a driver may request
+const MAX_DMA_PAGES: u64 = 64;
+
+/// A DMA buffer a driver holds, by the user address it was given
+struct DmaMapping {
+    owner: crate::thread::ThreadId,
+    phys: usize,
+    pages: usize,
+}
+
+static DMA_MAPPINGS: Mutex<BTreeMap<usize, DmaMapping>> = Mutex::new(BTreeMap::new());
+
+/// Allocate physically contiguous, zeroed memory for device DMA
 ///
 /// Args:
-///   ports_ptr: Pointer to array of port IDs to wait on
-///   count: Number of ports in the array
-///   timeout_ms: Timeout in milliseconds (0 = no wait, u64::MAX = infinite)
+///   pages: Number of 4 KiB pages to allocate (1..=MAX_DMA_PAGES)
+///   phys_out: Optional pointer receiving the physical address
 ///
 /// Returns:
-///   Index of the port with data (0-based), or error code
-fn sys_ipc_wait_any(ports_ptr: u64, count: u64, timeout_ms: u64) -> u64 {
-    const LOG_ORIGIN: &str = "syscall";
+///   Virtual address usable by the caller, EPERM if the caller holds no
+///   DMA buffer capability (`DMABufferCap` in its manifest entry), or
+///   another error code
+///
+/// The pages are mapped user-writable in a window from `mm::vspace`, not
+/// at their physical address: the identity map of RAM is kernel-only.
+/// Devices are programmed with the value written to `phys_out`.
+fn sys_dma_alloc(pages: u64, phys_out: u64) -> u64 {
+    use crate::mm::vm::{self, PageFlags};
 
-    if count == 0 || count > 64 {
+    if pages == 0 || pages > MAX_DMA_PAGES {
         return EINVAL;
     }
+    let pages = pages as usize;
 
     let caller = match crate::sched::current_thread() {
         Some(tid) => tid,
         None => return EINVAL,
     };
 
-    // Read port IDs from userspace
-    let mut ports = alloc::vec::Vec::with_capacity(count as usize);
-    unsafe {
-        let ptr = ports_ptr as *const u64;
-        for i in 0..count as usize {
-            ports.push(crate::ipc::PortId::from_raw(*ptr.add(i)));
+    let has_permission = crate::thread::validate_thread_capability_by_type(
+        caller,
+        crate::cap::CapPermissions::WRITE,
+        |resource| matches!(resource, crate::cap::ResourceType::DmaBuffer { .. }),
+    );
+    if !has_permission {
+        log_warn!("syscall", "dma_alloc: thread {} has no DMA capability", caller);
+        return EPERM;
+    }
+
+    let phys = match crate::mm::pmm::alloc_pages_zeroed(pages) {
+        Some(phys) => phys,
+        None => return ENOMEM,
+    };
+    let virt = match crate::mm::vspace::reserve(caller, pages) {
+        Some(virt) => virt,
+        None => {
+            crate::mm::pmm::free_pages(phys, pages);
+            return ENOMEM;
+        }
+    };
+
+    let flags = (PageFlags::PRESENT | PageFlags::WRITABLE | PageFlags::USER).with_nx();
+    let mut mapped = 0;
+    while mapped < pages {
+        let offset = mapped * crate::mm::pmm::PAGE_SIZE;
+        if vm::map_page(virt + offset, phys + offset, flags).is_err() {
+            break;
         }
+        mapped += 1;
+    }
+
+    if mapped < pages || (phys_out != 0 && write_user(phys_out, phys as u64).is_err()) {
+        unmap_dma(caller, virt, phys, mapped);
+        crate::mm::pmm::free_pages(phys, pages);
+        return if mapped < pages { ENOMEM } else { EINVAL };
+    }
+
+    DMA_MAPPINGS.lock().insert(virt, DmaMapping { owner: caller, phys, pages });
+
+    log_info!(
+        "syscall",
+        "Thread {} allocated {} DMA page(s) at phys={:#X}, virt={:#X}",
+        caller,
+        pages,
+        phys,
+        virt
+    );
+
+    virt as u64
+}
+
+/// Free a buffer from SYS_DMA_ALLOC
+///
+/// Args:
+///   virt: Address SYS_DMA_ALLOC returned
+///
+/// Returns:
+///   ESUCCESS, EINVAL if no buffer starts there, or EPERM if another
+///   thread allocated it
+///
+/// The driver must have stopped the device using the buffer first; its
+/// pages go back to the allocator straight away.
+fn sys_dma_free(virt: u64) -> u64 {
+    use alloc::collections::btree_map::Entry;
+
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+
+    let mapping = match DMA_MAPPINGS.lock().entry(virt as usize) {
+        Entry::Occupied(entry) if entry.get().owner == caller => entry.remove(),
+        Entry::Occupied(_) => return EPERM,
+        Entry::Vacant(_) => return EINVAL,
+    };
+
+    unmap_dma(caller, virt as usize, mapping.phys, mapping.pages);
+    crate::mm::pmm::free_pages(mapping.phys, mapping.pages);
+
+    log_info!(
+        "syscall",
+        "Thread {} freed {} DMA page(s) at phys={:#X}",
+        caller,
+        mapping.pages,
+        mapping.phys
+    );
+    ESUCCESS
+}
+
+/// Unmap the first `pages` pages of a DMA window and give the window back
+fn unmap_dma(owner: crate::thread::ThreadId, virt: usize, phys: usize, pages: usize) {
+    for page in 0..pages {
+        let offset = page * crate::mm::pmm::PAGE_SIZE;
+        if crate::mm::vm::unmap_page(virt + offset).is_err() {
+            log_warn!(
+                "syscall",
+                "DMA page {:#X} (phys {:#X}) was not mapped",
+                virt + offset,
+                phys + offset
+            );
+        }
+    }
+    crate::mm::vspace::release(owner, virt);
+}
+
+// ============================================================================
+// PCI Configuration Space for Userspace Drivers
+// ============================================================================
+
+/// Configuration space mechanism #1 reaches
+const PCI_CONFIG_SIZE: u64 = 256;
+
+/// Whether the caller holds a device capability for the function at `bdf`
+fn holds_device(caller: crate::thread::ThreadId, bdf: u16, permission: crate::cap::CapPermissions)
+    -> bool
+{
+    crate::thread::validate_thread_capability_by_type(caller, permission, |resource| {
+        matches!(resource, crate::cap::ResourceType::Device { bdf: held } if *held == bdf)
+    }) && crate::pci::is_claimed_by(bdf, caller)
+}
+
+/// Read a 32-bit PCI configuration register
+///
+/// Args:
+///   bdf: bus, device and function packed as `bus << 8 | device << 3 | function`
+///   offset: register offset, dword aligned, below 256
+///
+/// Returns:
+///   The register value, EINVAL for a bad offset, or EPERM unless the
+///   register identifies the function (below `pci::PUBLIC_CONFIG_END`) or
+///   the caller holds a device capability for it
+fn sys_pci_config_read(bdf: u64, offset: u64) -> u64 {
+    if bdf > u16::MAX as u64 || offset >= PCI_CONFIG_SIZE || offset & 3 != 0 {
+        return EINVAL;
+    }
+    let (bdf, offset) = (bdf as u16, offset as u8);
+
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+
+    if offset >= crate::pci::PUBLIC_CONFIG_END
+        && !holds_device(caller, bdf, crate::cap::CapPermissions::READ)
+    {
+        return EPERM;
+    }
+
+    crate::pci::config_read(bdf, offset) as u64
+}
+
+/// Write a 32-bit PCI configuration register of a claimed function
+///
+/// Args:
+///   bdf: bus, device and function, as for SYS_PCI_CONFIG_READ
+///   offset: register offset, dword aligned, below 256
+///   value: value to write
+///
+/// Returns:
+///   ESUCCESS, EINVAL for a bad offset, or EPERM unless the caller holds a
+///   device capability for the function. Registers that place the function
+///   (`pci::ADDRESS_REGISTERS`) are always EPERM: SYS_MAP_MMIO and the port
+///   checks trust the BARs recorded when the device was claimed.
+fn sys_pci_config_write(bdf: u64, offset: u64, value: u64) -> u64 {
+    if bdf > u16::MAX as u64 || offset >= PCI_CONFIG_SIZE || offset & 3 != 0 {
+        return EINVAL;
+    }
+    let (bdf, offset) = (bdf as u16, offset as u8);
+
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+
+    if !holds_device(caller, bdf, crate::cap::CapPermissions::WRITE) {
+        log_warn!(
+            "syscall",
+            "pci_config_write: thread {} does not hold device {:#06X}",
+            caller,
+            bdf
+        );
+        return EPERM;
+    }
+
+    if !crate::pci::config_write(bdf, offset, value as u32) {
+        log_warn!(
+            "syscall",
+            "pci_config_write: thread {} may not move device {:#06X} (register {:#04X})",
+            caller,
+            bdf,
+            offset
+        );
+        return EPERM;
+    }
+
+    ESUCCESS
+}
+
+// ============================================================================
+// Device Registers for Userspace Drivers
+// ============================================================================
+
+/// Largest MMIO range a single SYS_MAP_MMIO maps
+const MAX_MMIO_SIZE: u64 = 256 * 1024 * 1024;
+
+/// End of the identity mappings for drivers; user windows from
+/// `mm::vspace` start here
+const MMIO_LIMIT: u64 = crate::mm::vspace::WINDOW_BASE as u64;
+
+/// Map a device's memory-mapped registers for the caller
+///
+/// Args:
+///   phys: Physical address of the registers, usually from a PCI BAR
+///   size: Length in bytes (1..=MAX_MMIO_SIZE)
+///
+/// Returns:
+///   Virtual address usable by the caller, EINVAL for a bad range, EPERM
+///   unless the range lies inside a memory BAR of a PCI device the caller
+///   holds a device capability for, or if it overlaps memory the firmware
+///   describes or a device the kernel drives, or ENOMEM
+///
+/// Like DMA memory, the range is identity-mapped, so the returned address
+/// equals `phys`. Pages are mapped uncached.
+fn sys_map_mmio(phys: u64, size: u64) -> u64 {
+    let end = match phys.checked_add(size) {
+        Some(end) if size != 0 && size <= MAX_MMIO_SIZE && end <= MMIO_LIMIT => end,
+        _ => return EINVAL,
+    };
+
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+
+    let has_permission = crate::thread::validate_thread_capability_by_type(
+        caller,
+        crate::cap::CapPermissions::WRITE,
+        |resource| {
+            matches!(
+                resource,
+                crate::cap::ResourceType::Device { bdf }
+                    if crate::pci::owns_window(*bdf, caller, phys, end)
+            )
+        },
+    );
+    if !has_permission {
+        log_warn!(
+            "syscall",
+            "map_mmio: thread {} holds no device with {:#X}..{:#X} in its BARs",
+            caller,
+            phys,
+            end
+        );
+        return EPERM;
+    }
+
+    match crate::mm::vm::map_mmio(phys, size as usize) {
+        Ok(()) => {}
+        Err(crate::mm::vm::VmError::AlreadyMapped | crate::mm::vm::VmError::Reserved) => {
+            return EPERM
+        }
+        Err(crate::mm::vm::VmError::OutOfMemory) => return ENOMEM,
+        Err(_) => return EINVAL,
+    }
+
+    log_info!(
+        "syscall",
+        "Thread {} mapped MMIO at phys={:#X} ({} bytes)",
+        caller,
+        phys,
+        size
+    );
+
+    phys
+}
+
+// ============================================================================
+// Kernel Log Access
+// ============================================================================
+
+/// Largest single read from the kernel log ring
+const MAX_KLOG_READ: usize = 4096;
+
+/// Copy entries from the kernel log ring into a user buffer, as text lines
+///
+/// Args:
+///   buf: Destination buffer
+///   len: Buffer size in bytes (clamped to MAX_KLOG_READ)
+///   pos: In/out byte offset into the log (0 = oldest entry still held);
+///        entries below `min_level` are passed over
+///   min_level: 0 = Debug .. 4 = Panic
+///
+/// Returns:
+///   Number of bytes copied (0 when caught up), or error code
+///
+/// Intentionally does not log: every call would otherwise append to the
+/// ring it is reading.
+fn sys_klog_read(buf: u64, len: usize, pos: u64, min_level: u64) -> u64 {
+    let min_level = match u8::try_from(min_level).ok().and_then(crate::log::LogLevel::from_u8) {
+        Some(level) => level,
+        None => return EINVAL,
+    };
+    if buf == 0 || pos == 0 || len == 0 {
+        return EINVAL;
+    }
+
+    let len = core::cmp::min(len, MAX_KLOG_READ);
+    let mut chunk = [0u8; MAX_KLOG_READ];
+    let Ok(start) = read_user::<u64>(pos) else {
+        return EINVAL;
+    };
+
+    let (count, next) = crate::log::read_klog(start, min_level, &mut chunk[..len]);
+
+    if copy_to_user(buf, &chunk[..count]).is_err() || write_user(pos, next).is_err() {
+        return EINVAL;
+    }
+
+    count as u64
+}
+
+/// Refuse `call` with EPERM unless the caller holds the debug capability
+fn require_debug_capability(call: &str) -> Result<(), u64> {
+    let caller = crate::sched::current_thread().ok_or(EINVAL)?;
+    let permitted = crate::thread::validate_thread_capability_by_type(
+        caller,
+        crate::cap::CapPermissions::WRITE,
+        |resource| *resource == crate::cap::ResourceType::Debug,
+    );
+    if !permitted {
+        log_warn!("syscall", "{}: thread {} has no debug capability", call, caller);
+        return Err(EPERM);
+    }
+    Ok(())
+}
+
+/// Log sinks accepted by SYS_KLOG_SET_LEVEL
+const KLOG_SINK_CAPTURE: u64 = 0;
+const KLOG_SINK_SERIAL: u64 = 1;
+
+/// Change the minimum level for a log sink
+///
+/// Args:
+///   sink: 0 = capture (entries below are dropped entirely),
+///         1 = serial mirror (entries below stay in the ring only)
+///   level: 0 = Debug .. 4 = Panic
+///
+/// Returns:
+///   ESUCCESS, EINVAL for an unknown sink or level, or EPERM without the
+///   debug capability
+fn sys_klog_set_level(sink: u64, level: u64) -> u64 {
+    let level = match u8::try_from(level).ok().and_then(crate::log::LogLevel::from_u8) {
+        Some(level) => level,
+        None => return EINVAL,
+    };
+    if let Err(err) = require_debug_capability("klog_set_level") {
+        return err;
+    }
+
+    match sink {
+        KLOG_SINK_CAPTURE => crate::log::set_level(level),
+        KLOG_SINK_SERIAL => crate::log::set_serial_level(level),
+        _ => return EINVAL,
+    }
+
+    log_info!("syscall", "Log level for sink {} set to {}", sink, level.as_str());
+    ESUCCESS
+}
+
+// ============================================================================
+// Kernel Tracing
+// ============================================================================
+
+/// Largest number of records a single SYS_TRACE_READ copies
+const MAX_TRACE_READ: usize = 64;
+
+/// A kernel trace record as SYS_TRACE_READ writes it
+#[repr(C)]
+struct RawTraceRecord {
+    timestamp_ns: u64,
+    thread: u64,
+    subsystem: u32,
+    event: u32,
+    args: [u64; crate::trace::MAX_ARGS],
+}
+
+/// Copy records from the kernel trace into a user buffer
+///
+/// Args:
+///   buf_ptr: Array of RawTraceRecord
+///   max_records: Array length (clamped to MAX_TRACE_READ)
+///   cursor: In/out sequence number of the next record to read (0 = oldest
+///           record still held); records overwritten since the last read
+///           are skipped, which the caller sees as the cursor moving by more
+///           than the count returned
+///
+/// Returns:
+///   Number of records copied (0 when caught up), or error code
+fn sys_trace_read(buf_ptr: u64, max_records: u64, cursor: u64) -> u64 {
+    if buf_ptr == 0 || cursor == 0 || max_records == 0 {
+        return EINVAL;
+    }
+
+    let max = core::cmp::min(max_records as usize, MAX_TRACE_READ);
+    let mut records = [crate::trace::TraceRecord::EMPTY; MAX_TRACE_READ];
+    let Ok(start) = read_user::<u64>(cursor) else {
+        return EINVAL;
+    };
+
+    let (count, next) = crate::trace::read(start, &mut records[..max]);
+    let clock = crate::trace::Clock::now();
+
+    for (idx, record) in records[..count].iter().enumerate() {
+        let raw = RawTraceRecord {
+            timestamp_ns: clock.nanoseconds(record.tsc),
+            thread: record.thread,
+            subsystem: record.subsystem as u32,
+            event: record.event as u32,
+            args: record.args,
+        };
+        if write_user_at(buf_ptr, idx, raw).is_err() {
+            return EINVAL;
+        }
+    }
+    if write_user(cursor, next).is_err() {
+        return EINVAL;
+    }
+
+    count as u64
+}
+
+/// Turn kernel trace subsystems on or off
+///
+/// Args:
+///   enable: Mask of subsystems to turn on (bit n = subsystem n)
+///   disable: Mask of subsystems to turn off; wins over `enable`
+///
+/// Returns:
+///   The mask now in effect; (0, 0) only queries it. Changing the mask
+///   without the debug capability gives EPERM.
+fn sys_trace_control(enable: u64, disable: u64) -> u64 {
+    let changes = enable | disable != 0;
+    if changes {
+        if let Err(err) = require_debug_capability("trace_control") {
+            return err;
+        }
+    }
+
+    let mask = crate::trace::control(enable as u32, disable as u32);
+    if changes {
+        log_info!("syscall", "Trace mask set to {:#X}", mask);
+    }
+    mask as u64
+}
+
+// ============================================================================
+// Sampling Profiler
+// ============================================================================
+
+/// Largest number of samples a single SYS_PROFILE_READ copies
+const MAX_PROFILE_READ: usize = 64;
+
+/// Longest function name a profiler sample carries
+const PROFILE_NAME_SIZE: usize = 40;
+
+/// A profiler sample as SYS_PROFILE_READ writes it
+#[repr(C)]
+struct RawProfileSample {
+    rip: u64,
+    thread: u64,
+    /// Offset of `rip` into the function named, or u64::MAX if unknown
+    offset: u64,
+    /// 1 if the CPU was running user code
+    user: u32,
+    name_len: u32,
+    name: [u8; PROFILE_NAME_SIZE],
+}
+
+/// Start a profile, dropping the samples of the last one
+///
+/// Args:
+///   thread: Thread to sample, or 0 for every thread
+///
+/// Returns:
+///   ESUCCESS, EBUSY if a profile is already running, or EPERM without
+///   the debug capability
+fn sys_profile_start(thread: u64) -> u64 {
+    if let Err(err) = require_debug_capability("profile_start") {
+        return err;
+    }
+    if crate::profile::start(thread) {
+        ESUCCESS
+    } else {
+        EBUSY
+    }
+}
+
+/// Stop the running profile
+///
+/// Returns:
+///   Samples the profile took, including those since overwritten
+fn sys_profile_stop() -> u64 {
+    crate::profile::stop()
+}
+
+/// Copy samples from one CPU's ring into a user buffer, naming the
+/// function around each address where the program has symbols
+///
+/// Args:
+///   buf_ptr: Array of RawProfileSample
+///   max_samples: Array length (clamped to MAX_PROFILE_READ)
+///   cursor: In/out sequence number of the next sample (0 = oldest held)
+///   cpu: Ring to read; EINVAL past the last CPU
+///
+/// Returns:
+///   Number of samples copied (0 when caught up), or error code
+fn sys_profile_read(buf_ptr: u64, max_samples: u64, cursor: u64, cpu: u64) -> u64 {
+    if buf_ptr == 0 || cursor == 0 || max_samples == 0 {
+        return EINVAL;
+    }
+
+    let max = core::cmp::min(max_samples as usize, MAX_PROFILE_READ);
+    let mut samples = [crate::profile::Sample::EMPTY; MAX_PROFILE_READ];
+    let Ok(start) = read_user::<u64>(cursor) else {
+        return EINVAL;
+    };
+
+    let Some((count, next)) = crate::profile::read(cpu as usize, start, &mut samples[..max]) else {
+        return EINVAL;
+    };
+
+    for (idx, sample) in samples[..count].iter().enumerate() {
+        let mut raw = RawProfileSample {
+            rip: sample.rip,
+            thread: sample.thread,
+            offset: u64::MAX,
+            user: sample.user as u32,
+            name_len: 0,
+            name: [0; PROFILE_NAME_SIZE],
+        };
+        crate::executable::resolve_symbol(
+            sample.page_table as usize,
+            sample.rip as usize,
+            |name, offset| {
+                let len = name.len().min(PROFILE_NAME_SIZE);
+                raw.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+                raw.name_len = len as u32;
+                raw.offset = offset as u64;
+            },
+        );
+        if write_user_at(buf_ptr, idx, raw).is_err() {
+            return EINVAL;
+        }
+    }
+    if write_user(cursor, next).is_err() {
+        return EINVAL;
+    }
+
+    count as u64
+}
+
+// ============================================================================
+// Program Launching
+// ============================================================================
+
+/// Longest program path accepted by SYS_PROC_SPAWN
+const MAX_SPAWN_PATH: usize = 256;
+
+/// Exit code SYS_PROC_WAIT reports for a program stopped by SYS_PROC_KILL
+const EXIT_KILLED: u64 = 137;
+
+/// A program started with SYS_PROC_SPAWN
+struct SpawnedProgram {
+    /// Thread that started it; only it may wait for or kill the program
+    parent: crate::thread::ThreadId,
+    /// Port its output goes to, if the parent gave one
+    output_port: Option<crate::ipc::PortId>,
+    /// Set when it exits; the entry is dropped once the parent has seen it
+    exit_code: Option<u64>,
+}
+
+static SPAWNED_PROGRAMS: Mutex<BTreeMap<crate::thread::ThreadId, SpawnedProgram>> =
+    Mutex::new(BTreeMap::new());
+
+/// Keep the exit code of a spawned program for its parent
+fn record_exit(tid: crate::thread::ThreadId, exit_code: u64) {
+    if let Some(program) = SPAWNED_PROGRAMS.lock().get_mut(&tid) {
+        // Exit codes never read as error codes
+        program.exit_code = Some(exit_code & 0xFFFF_FFFF);
+    }
+}
+
+/// Start the program whose binary is at `path`
+///
+/// Only programs declared in the boot manifest can be started; they run
+/// with the capabilities the manifest grants them, not the caller's.
+///
+/// Args:
+///   path_ptr: Binary path, e.g. "/apps/terminal.elf"
+///   path_len: Path length in bytes
+///   output_port: Port the program's output is sent to, 0 for none; must
+///     be owned by the caller
+///
+/// Returns:
+///   Thread ID of the new program, or error code
+fn sys_proc_spawn(path_ptr: u64, path_len: usize, output_port: u64) -> u64 {
+    const LOG_ORIGIN: &str = "syscall";
+
+    if path_ptr == 0 || path_len == 0 || path_len > MAX_SPAWN_PATH {
+        return EINVAL;
+    }
+
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+
+    let output_port = match output_port {
+        0 => None,
+        raw => {
+            let port = crate::ipc::PortId::from_raw(raw);
+            if crate::ipc::get_port_owner(port) != Some(caller) {
+                log_warn!(
+                    LOG_ORIGIN,
+                    "Spawn refused: caller {} does not own port {}",
+                    caller,
+                    port
+                );
+                return EPERM;
+            }
+            Some(port)
+        }
+    };
+
+    let mut buf = [0u8; MAX_SPAWN_PATH];
+    if copy_from_user(&mut buf[..path_len], path_ptr).is_err() {
+        return EINVAL;
+    }
+    let path = match core::str::from_utf8(&buf[..path_len]) {
+        Ok(path) => path,
+        Err(_) => return EINVAL,
+    };
+
+    let spec = match crate::service_manager::manager().manifest().service_by_binary(path) {
+        Some(spec) => spec,
+        None => {
+            log_warn!(LOG_ORIGIN, "Spawn refused: {} is not in the manifest", path);
+            return EINVAL;
+        }
+    };
+
+    match crate::init_process::spawn_service_thread(spec) {
+        Ok(tid) => {
+            log_info!(LOG_ORIGIN, "Spawned '{}' as thread {}", spec.name, tid);
+            SPAWNED_PROGRAMS.lock().insert(
+                tid,
+                SpawnedProgram {
+                    parent: caller,
+                    output_port,
+                    exit_code: None,
+                },
+            );
+            tid.raw()
+        }
+        Err(err) => {
+            log_error!(LOG_ORIGIN, "Failed to spawn '{}': {:?}", spec.name, err);
+            ENOMEM
+        }
+    }
+}
+
+/// Wait for a program the caller spawned to exit
+///
+/// Args:
+///   tid_raw: Thread ID returned by SYS_PROC_SPAWN
+///   timeout_ms: Timeout in milliseconds (0 = no wait, u64::MAX = infinite)
+///
+/// Returns:
+///   The program's exit code, or error code
+fn sys_proc_wait(tid_raw: u64, timeout_ms: u64) -> u64 {
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+    let tid = crate::thread::ThreadId::from_raw(tid_raw);
+
+    let deadline = if timeout_ms == u64::MAX {
+        None
+    } else {
+        Some(crate::interrupts::get_ticks() + timeout_ms.div_ceil(10))
+    };
+
+    loop {
+        {
+            let mut programs = SPAWNED_PROGRAMS.lock();
+            let program = match programs.get(&tid) {
+                Some(program) if program.parent == caller => program,
+                Some(_) => return EPERM,
+                None => return EINVAL,
+            };
+            if let Some(code) = program.exit_code {
+                programs.remove(&tid);
+                return code;
+            }
+        }
+
+        if let Some(deadline_tick) = deadline {
+            if crate::interrupts::get_ticks() >= deadline_tick {
+                if timeout_ms == 0 {
+                    return EWOULDBLOCK;
+                } else {
+                    return ETIMEDOUT;
+                }
+            }
+        }
+
+        // Yield and retry
+        crate::thread::set_thread_state(caller, crate::thread::ThreadState::Blocked);
+        let (prev, next) = crate::sched::on_timer_tick();
+        if let (Some(prev_id), Some(next_id)) = (prev, next) {
+            if prev_id != next_id {
+                crate::sched::perform_context_switch(prev_id, next_id);
+            }
+        }
+        crate::thread::set_thread_state(caller, crate::thread::ThreadState::Ready);
+    }
+}
+
+/// Wait for a thread the caller created with SYS_THREAD_CREATE to exit
+///
+/// Threads are kept track of like spawned programs, so this behaves as
+/// SYS_PROC_WAIT: the exit code is returned once, then the thread is
+/// forgotten.
+///
+/// Args:
+///   tid_raw: Thread ID returned by SYS_THREAD_CREATE
+///   timeout_ms: Timeout in milliseconds (0 = no wait, u64::MAX = infinite)
+///
+/// Returns:
+///   The thread's exit code, or error code
+fn sys_thread_join(tid_raw: u64, timeout_ms: u64) -> u64 {
+    sys_proc_wait(tid_raw, timeout_ms)
+}
+
+/// Stop a program the caller spawned
+///
+/// Its ports are closed as if it had exited, and SYS_PROC_WAIT reports
+/// EXIT_KILLED for it.
+fn sys_proc_kill(tid_raw: u64) -> u64 {
+    const LOG_ORIGIN: &str = "syscall";
+
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+    let tid = crate::thread::ThreadId::from_raw(tid_raw);
+
+    {
+        let programs = SPAWNED_PROGRAMS.lock();
+        match programs.get(&tid) {
+            Some(program) if program.parent != caller => return EPERM,
+            Some(program) if program.exit_code.is_some() => return ESUCCESS,
+            Some(_) => {}
+            None => return EINVAL,
+        }
+    }
+
+    crate::ipc::close_owned_ports(tid);
+    crate::thread::set_thread_state(tid, crate::thread::ThreadState::Exited);
+    record_exit(tid, EXIT_KILLED);
+    log_info!(LOG_ORIGIN, "Thread {} killed by {}", tid, caller);
+    ESUCCESS
+}
+
+/// Port the calling program's output goes to
+///
+/// Returns:
+///   Port ID given to SYS_PROC_SPAWN, or EINVAL if the caller was not
+///   spawned with one
+fn sys_proc_output_port() -> u64 {
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+
+    SPAWNED_PROGRAMS
+        .lock()
+        .get(&caller)
+        .and_then(|program| program.output_port)
+        .map_or(EINVAL, |port| port.raw())
+}
+
+/// Arguments of the calling program, as the boot manifest gives them
+///
+/// Each argument is written followed by a NUL byte. A thread started with
+/// SYS_THREAD_CREATE reads the arguments of the program that started it.
+///
+/// Args:
+///   buf_ptr: Buffer to write the arguments to
+///   buf_len: Buffer length in bytes
+///
+/// Returns:
+///   Bytes the arguments take, which may be more than were written
+fn sys_proc_args(buf_ptr: u64, buf_len: u64) -> u64 {
+    if buf_ptr == 0 && buf_len > 0 {
+        return EINVAL;
+    }
+    let mut tid = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+
+    let args = loop {
+        if let Some(args) = crate::init_process::service_args(tid) {
+            break args;
+        }
+        match SPAWNED_PROGRAMS.lock().get(&tid) {
+            Some(program) => tid = program.parent,
+            None => break alloc::vec::Vec::new(),
+        }
+    };
+
+    write_args(args.iter().map(|arg| arg.as_str()), buf_ptr, buf_len)
+}
+
+/// Boot parameters given to the loader, e.g. `verbose loglevel=debug`
+///
+/// Each parameter is written followed by a NUL byte.
+///
+/// Args:
+///   buf_ptr: Buffer to write the parameters to
+///   buf_len: Buffer length in bytes
+///
+/// Returns:
+///   Bytes the parameters take, which may be more than were written
+fn sys_boot_args(buf_ptr: u64, buf_len: u64) -> u64 {
+    if buf_ptr == 0 && buf_len > 0 {
+        return EINVAL;
+    }
+
+    write_args(crate::system::info().command_line().words(), buf_ptr, buf_len)
+}
+
+/// Write as much of `args`, NUL-terminated, as fits in the user buffer
+///
+/// Returns the bytes the arguments take, or EINVAL if the buffer is not
+/// the caller's writable memory.
+fn write_args<'a>(args: impl Iterator<Item = &'a str>, buf_ptr: u64, buf_len: u64) -> u64 {
+    let mut total = 0u64;
+    for arg in args {
+        for part in [arg.as_bytes(), &[0]] {
+            let fits = (part.len() as u64).min(buf_len.saturating_sub(total)) as usize;
+            if copy_to_user(buf_ptr.wrapping_add(total), &part[..fits]).is_err() {
+                return EINVAL;
+            }
+            total += part.len() as u64;
+        }
+    }
+    total
+}
+
+/// Name the function around `addr` in the caller's address space, from
+/// the symbols its executable was built with
+///
+/// The name is written NUL-terminated, cut short if the buffer is too small.
+///
+/// Args:
+///   addr: Code address, e.g. a return address
+///   buf_ptr: Buffer to write the name to
+///   buf_len: Buffer length in bytes
+///
+/// Returns:
+///   Offset of `addr` into the function, or EINVAL if no symbol covers it
+fn sys_symbolize(addr: u64, buf_ptr: u64, buf_len: u64) -> u64 {
+    if buf_ptr == 0 && buf_len > 0 {
+        return EINVAL;
+    }
+
+    let page_table = crate::arch::read_cr3() as usize;
+    let mut result = EINVAL;
+    crate::executable::resolve_symbol(page_table, addr as usize, |name, offset| {
+        if write_args(core::iter::once(name), buf_ptr, buf_len) != EINVAL {
+            result = offset as u64;
+        }
+    });
+    result
+}
+
+// ============================================================================
+// System Statistics
+// ============================================================================
+
+/// Memory usage written by SYS_MEM_STATS
+#[repr(C)]
+struct RawMemStats {
+    total_bytes: u64,
+    free_bytes: u64,
+    heap_bytes: u64,
+    heap_used_bytes: u64,
+}
+
+/// Scheduler counters and thread states written by SYS_SCHED_STATS
+#[repr(C)]
+struct RawSchedStats {
+    ticks: u64,
+    idle_ticks: u64,
+    context_switches: u64,
+    threads: u64,
+    running: u64,
+    ready: u64,
+    blocked: u64,
+    exited: u64,
+}
+
+/// Longest thread name SYS_THREAD_LIST reports; longer names are cut
+const THREAD_NAME_LEN: usize = 24;
+
+/// One thread as written by SYS_THREAD_LIST
+#[repr(C)]
+struct RawThreadInfo {
+    id: u64,
+    /// 0 = running, 1 = ready, 2 = blocked, 3 = exited
+    state: u32,
+    /// 0 = idle .. 3 = high
+    priority: u32,
+    /// Timer ticks the thread has run for
+    ticks: u64,
+    name: [u8; THREAD_NAME_LEN],
+    name_len: u64,
+}
+
+/// Get physical memory and kernel heap usage
+///
+/// Args:
+///   stats_ptr: RawMemStats to fill in
+fn sys_mem_stats(stats_ptr: u64) -> u64 {
+    if stats_ptr == 0 {
+        return EINVAL;
+    }
+
+    let memory = crate::mm::pmm::get_detailed_stats();
+    let (heap_bytes, heap_used_bytes) = crate::mm::heap::get_stats();
+    let stats = RawMemStats {
+        total_bytes: memory.total_bytes as u64,
+        free_bytes: memory.free_bytes as u64,
+        heap_bytes: heap_bytes as u64,
+        heap_used_bytes: heap_used_bytes as u64,
+    };
+    if write_user(stats_ptr, stats).is_err() {
+        return EINVAL;
+    }
+    ESUCCESS
+}
+
+/// Get scheduler counters and how many threads are in each state
+///
+/// Args:
+///   stats_ptr: RawSchedStats to fill in
+fn sys_sched_stats(stats_ptr: u64) -> u64 {
+    if stats_ptr == 0 {
+        return EINVAL;
+    }
+
+    let sched = crate::sched::get_stats();
+    let threads = crate::thread::get_thread_stats();
+    let stats = RawSchedStats {
+        ticks: sched.ticks,
+        idle_ticks: sched.idle_ticks,
+        context_switches: sched.context_switches,
+        threads: threads.total as u64,
+        running: threads.running as u64,
+        ready: threads.ready as u64,
+        blocked: threads.blocked as u64,
+        exited: threads.exited as u64,
+    };
+    if write_user(stats_ptr, stats).is_err() {
+        return EINVAL;
+    }
+    ESUCCESS
+}
+
+/// Describe every thread
+///
+/// Args:
+///   buf_ptr: array of RawThreadInfo to fill in
+///   max_entries: length of the array
+///
+/// Returns:
+///   Number of threads, which may be more than were written
+fn sys_thread_list(buf_ptr: u64, max_entries: u64) -> u64 {
+    if buf_ptr == 0 && max_entries > 0 {
+        return EINVAL;
+    }
+
+    let threads = crate::thread::list_threads();
+    for (i, summary) in threads.iter().take(max_entries as usize).enumerate() {
+        let mut name = [0u8; THREAD_NAME_LEN];
+        let name_len = summary.name.len().min(THREAD_NAME_LEN);
+        name[..name_len].copy_from_slice(&summary.name.as_bytes()[..name_len]);
+        let info = RawThreadInfo {
+            id: summary.id.raw(),
+            state: match summary.state {
+                crate::thread::ThreadState::Running => 0,
+                crate::thread::ThreadState::Ready => 1,
+                crate::thread::ThreadState::Blocked => 2,
+                crate::thread::ThreadState::Exited => 3,
+            },
+            priority: summary.priority as u32,
+            ticks: crate::sched::thread_ticks(summary.id),
+            name,
+            name_len: name_len as u64,
+        };
+        if write_user_at(buf_ptr, i, info).is_err() {
+            return EINVAL;
+        }
+    }
+
+    threads.len() as u64
+}
+
+/// One capability event as written by SYS_CAP_AUDIT_READ
+#[repr(C)]
+struct RawCapAuditEntry {
+    /// Timer ticks since boot
+    timestamp: u64,
+    /// 0 = create, 1 = derive, 2 = transfer, 3 = revoke
+    event: u64,
+    thread: u64,
+    cap_handle: u64,
+    /// Capability it was derived from, or 0
+    parent: u64,
+    /// Thread it was transferred to, or 0
+    target: u64,
+}
+
+/// Read the newest entries of the capability audit log, oldest first
+///
+/// Args:
+///   buf_ptr: array of RawCapAuditEntry to fill in
+///   max_entries: length of the array
+///
+/// Returns:
+///   Number of entries written
+fn sys_cap_audit_read(buf_ptr: u64, max_entries: u64) -> u64 {
+    if buf_ptr == 0 && max_entries > 0 {
+        return EINVAL;
+    }
+
+    // The log hands back the newest entry first
+    let entries = crate::cap::get_audit_log(max_entries as usize);
+    for (i, entry) in entries.iter().rev().enumerate() {
+        let raw = RawCapAuditEntry {
+            timestamp: entry.timestamp,
+            event: match entry.event_type {
+                crate::cap::AuditEventType::Create => 0,
+                crate::cap::AuditEventType::Derive => 1,
+                crate::cap::AuditEventType::Transfer => 2,
+                crate::cap::AuditEventType::Revoke => 3,
+            },
+            thread: entry.thread_id.raw(),
+            cap_handle: entry.cap_handle.raw(),
+            parent: entry.parent_handle.map_or(0, |handle| handle.raw()),
+            target: entry.target_thread.map_or(0, |thread| thread.raw()),
+        };
+        if write_user_at(buf_ptr, i, raw).is_err() {
+            return EINVAL;
+        }
+    }
+
+    entries.len() as u64
+}
+
+/// Whether the thread that sent the caller's last received message holds
+/// a capability of some type
+///
+/// Args:
+///   resource_type: the type's code (`ResourceType::code`), e.g. 7 for
+///   the Screenshot capability
+///
+/// Returns:
+///   1 if it does, 0 if not, or EINVAL for an unknown type or if the
+///   caller has taken no single message since its last batch receive
+///
+/// Lets a service check a request against its sender's manifest
+/// capabilities, which nothing in the request itself could prove.
+fn sys_ipc_sender_holds(resource_type: u64) -> u64 {
+    if resource_type >= crate::cap::ResourceType::COUNT as u64 {
+        return EINVAL;
+    }
+
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+    let Some(sender) = crate::ipc::last_sender(caller) else {
+        return EINVAL;
+    };
+
+    crate::thread::validate_thread_capability_by_type(
+        sender,
+        crate::cap::CapPermissions::READ,
+        |resource| resource.code() == resource_type,
+    ) as u64
+}
+
+// ============================================================================
+// Event-Based Input Primitives for Userspace Drivers
+// ============================================================================
+
+/// IRQ occurrence counters for userspace polling
+static IRQ_COUNTS: Mutex<BTreeMap<u8, u64>> = Mutex::new(BTreeMap::new());
+
+/// Increment IRQ count (called from interrupt handlers)
+pub fn increment_irq_count(irq: u8) {
+    let mut counts = IRQ_COUNTS.lock();
+    *counts.entry(irq).or_insert(0) += 1;
+}
+
+/// Get current IRQ count for a registered handler
+/// Userspace can use this to detect new events without IPC overhead
+fn sys_get_irq_count(irq: u8) -> u64 {
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+
+    // Verify caller owns this IRQ handler
+    let handlers = IRQ_HANDLERS.lock();
+    match handlers.get(&irq) {
+        Some((owner, _)) if *owner == caller => {
+            drop(handlers);
+            let counts = IRQ_COUNTS.lock();
+            counts.get(&irq).copied().unwrap_or(0)
+        }
+        Some(_) => EPERM,
+        None => EINVAL,
+    }
+}
+
+/// Wait for any of multiple IPC ports to have data
+///
+/// Args:
+///   ports_ptr: Pointer to array of port IDs to wait on
+///   count: Number of ports in the array
+///   timeout_ms: Timeout in milliseconds (0 = no wait, u64::MAX = infinite)
+///
+/// Returns:
+///   Index of the port with data (0-based), or error code
+fn sys_ipc_wait_any(ports_ptr: u64, count: u64, timeout_ms: u64) -> u64 {
+    const LOG_ORIGIN: &str = "syscall";
+
+    if count == 0 || count > 64 {
+        return EINVAL;
+    }
+
+    let caller = match crate::sched::current_thread() {
+        Some(tid) => tid,
+        None => return EINVAL,
+    };
+
+    // Read port IDs from userspace
+    let mut ports = alloc::vec::Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let Ok(raw) = read_user::<u64>(ports_ptr.wrapping_add(i * 8)) else {
+            return EINVAL;
+        };
+        ports.push(crate::ipc::PortId::from_raw(raw));
     }
 
     // Calculate deadline
@@ -2829,4 +4379,4 @@ fn sys_ipc_wait_any(ports_ptr: u64, count: u64, timeout_ms: u64) -> u64 {
         }
         crate::thread::set_thread_state(caller, crate::thread::ThreadState::Ready);
     }
-}
\ No newline at end of file
+}
\ No newline at end of file
@@ -8,6 +8,7 @@
 // Key responsibilities:
 // - Store CPU identification and architecture information
 // - Record the system boot method (UEFI vs Legacy)
+// - Keep the boot command line for programs to read as boot parameters
 // - Provide safe, global access to this data after initialization
 //
 // Design principles:
@@ -33,19 +34,20 @@
 //
 // This module acts as the kernel’s authoritative source of identity and
 // environment information once bootstrapping is complete.
-use crate::boot::{BootMethod, CpuArchitecture, CpuInfo};
+use crate::boot::{BootMethod, CommandLine, CpuArchitecture, CpuInfo};
 use spin::Once;
 
 #[allow(dead_code)]
 pub struct SystemInfo {
     cpu: CpuInfo,
     boot: BootMethod,
+    command_line: CommandLine,
 }
 
 static SYSTEM_INFO: Once<SystemInfo> = Once::new();
 
-pub fn init(cpu: CpuInfo, boot: BootMethod) {
-    SYSTEM_INFO.call_once(|| SystemInfo { cpu, boot });
+pub fn init(cpu: CpuInfo, boot: BootMethod, command_line: CommandLine) {
+    SYSTEM_INFO.call_once(|| SystemInfo { cpu, boot, command_line });
 }
 
 #[allow(dead_code)]
@@ -75,6 +77,11 @@ impl SystemInfo {
             BootMethod::Legacy => "Legacy BIOS",
         }
     }
+
+    /// Boot parameters, e.g. `verbose loglevel=debug`
+    pub fn command_line(&self) -> &CommandLine {
+        &self.command_line
+    }
 }
 
 #[allow(dead_code)]
@@ -24,6 +24,8 @@
 //   instruction pointer, stack pointer, and CR3 (address space)
 // - Context switch is performed by architecture-specific assembly stubs
 // - `capture_current_context` snapshots the live CPU state for preemption
+// - x87/SSE registers live in a per-thread `FpuState`, swapped with
+//   FXSAVE/FXRSTOR on every switch; the kernel itself never uses them
 //
 // Scheduling integration:
 // - Thread state is manipulated by the scheduler (`sched` module)
@@ -222,6 +224,34 @@ impl CpuContext {
     }
 }
 
+/// A thread's x87/SSE registers while it is switched out, in the layout
+/// FXSAVE writes
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+pub struct FpuState([u8; 512]);
+
+impl FpuState {
+    /// Registers as left by FNINIT, with every SIMD exception masked
+    pub fn new() -> Self {
+        let mut area = [0u8; 512];
+        area[0..2].copy_from_slice(&0x037Fu16.to_le_bytes()); // FCW
+        area[24..28].copy_from_slice(&0x1F80u32.to_le_bytes()); // MXCSR
+        Self(area)
+    }
+}
+
+impl Default for FpuState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for FpuState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FpuState").finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 pub struct Thread {
     pub id: ThreadId,
@@ -233,6 +263,7 @@ pub struct Thread {
     pub priority: ThreadPriority,
     pub name: &'static str,
     pub capability_table: CapabilityTable,
+    pub fpu: FpuState,
 }
 
 impl Thread {
@@ -265,6 +296,7 @@ impl Thread {
             priority,
             name,
             capability_table,
+            fpu: FpuState::new(),
         }
     }
 
@@ -378,6 +410,28 @@ impl ThreadList {
         threads.len()
     }
 
+    pub fn summaries(&self) -> Vec<ThreadSummary> {
+        let threads = self.threads.lock();
+        threads
+            .iter()
+            .map(|t| ThreadSummary {
+                id: t.id,
+                state: t.state,
+                priority: t.priority,
+                name: t.name,
+            })
+            .collect()
+    }
+
+    /// Run `f` over every thread without waiting for the list's lock; None
+    /// if it is held, as when the debugger stops the kernel mid-update
+    pub fn try_with<F, R>(&self, f: F) -> Option<R>
+    where
+        F: FnOnce(&[Thread]) -> R,
+    {
+        self.threads.try_lock().map(|threads| f(&threads))
+    }
+
     pub fn get_runnable(&self) -> Vec<ThreadId> {
         let threads = self.threads.lock();
         threads
@@ -447,6 +501,7 @@ impl ThreadList {
             priority: t.priority,
             name: t.name,
             capability_table: crate::cap::create_capability_table(t.id),
+            fpu: t.fpu,
         })
     }
 
@@ -536,6 +591,15 @@ pub struct ThreadStats {
     pub exited: usize,
 }
 
+/// A thread as `list_threads` reports it
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadSummary {
+    pub id: ThreadId,
+    pub state: ThreadState,
+    pub priority: ThreadPriority,
+    pub name: &'static str,
+}
+
 static THREAD_LIST: ThreadList = ThreadList::new();
 static USERMODE_ENTRIES: Mutex<BTreeSet<ThreadId>> = Mutex::new(BTreeSet::new());
 
@@ -577,6 +641,13 @@ pub fn log_user_entry_once(thread_id: ThreadId, ctx: &CpuContext) {
     }
 }
 
+pub fn try_with_threads<F, R>(f: F) -> Option<R>
+where
+    F: FnOnce(&[Thread]) -> R,
+{
+    THREAD_LIST.try_with(f)
+}
+
 pub fn get_runnable_threads() -> Vec<ThreadId> {
     THREAD_LIST.get_runnable()
 }
@@ -589,6 +660,10 @@ pub fn get_thread_stats() -> ThreadStats {
     THREAD_LIST.get_stats()
 }
 
+pub fn list_threads() -> Vec<ThreadSummary> {
+    THREAD_LIST.summaries()
+}
+
 pub fn validate_thread_capability(
     thread_id: ThreadId,
     cap_handle: crate::cap::CapHandle,
@@ -735,6 +810,21 @@ where
     THREAD_LIST.with_contexts(from_id, to_id, f)
 }
 
+/// Move the x87/SSE registers over from thread `from_id` to `to_id`
+///
+/// The kernel never touches these registers, so they can be switched
+/// ahead of the general-purpose ones, and stay as they are through
+/// interrupts and syscalls.
+pub fn switch_fpu_state(from_id: ThreadId, to_id: ThreadId) {
+    let mut threads = THREAD_LIST.threads.lock();
+    if let Some(from) = threads.iter_mut().find(|t| t.id == from_id) {
+        unsafe { crate::arch::fxsave(from.fpu.0.as_mut_ptr()) };
+    }
+    if let Some(to) = threads.iter().find(|t| t.id == to_id) {
+        unsafe { crate::arch::fxrstor(to.fpu.0.as_ptr()) };
+    }
+}
+
 pub fn capture_current_context() -> CpuContext {
     let mut ctx = CpuContext::zero();
 
@@ -820,6 +910,7 @@ pub fn perform_context_switch(from_id: ThreadId, to_id: ThreadId) {
         }
     }
 
+    switch_fpu_state(from_id, to_id);
     let _ = with_thread_contexts(from_id, to_id, |from_ctx, to_ctx| unsafe {
         switch_thread_context(from_ctx, to_ctx);
     });
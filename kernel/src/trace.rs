@@ -0,0 +1,312 @@
+// Kernel Tracing
+//
+// Records what the kernel does, one small binary record per event, into a
+// ring that userspace reads back to study scheduling latency, IPC flows,
+// memory use and syscall traffic after the fact.
+//
+// Key responsibilities:
+// - Provide the `trace_event!` macro that subsystems place at trace points
+// - Keep the newest `TRACE_RING_SIZE` records in a fixed ring
+// - Turn TSC readings into nanoseconds since boot when records are read
+// - Let userspace switch whole subsystems on and off (SYS_TRACE_CONTROL)
+// - Provide the generic `Ring` other observability logs are built on
+//
+// Trace points:
+// - sched: a thread switched in (SWITCH), a thread made ready (WAKE)
+// - mm: pages allocated (PAGE_ALLOC) and freed (PAGE_FREE), fatal page
+//   faults (PAGE_FAULT)
+// - ipc: a message queued (SEND) or taken off a port (RECV)
+// - syscall: entry (ENTER) and return (EXIT) of every syscall but the
+//   trace read itself; off by default, as it floods the ring
+//
+// Record format:
+// - Raw TSC at the time of the event, the running thread (0 if unknown)
+// - Subsystem and event numbers, stable across builds (the userspace
+//   `atom_syscall::trace` module mirrors them)
+// - Up to four u64 arguments whose meaning depends on the event; see the
+//   event constants below
+//
+// Design principles:
+// - Cheap when off: a disabled subsystem costs one atomic load, and the
+//   macro's arguments are not evaluated
+// - Usable anywhere: records are pushed with interrupts disabled and the
+//   ring never allocates, so trace points may sit in the page allocator
+//   and interrupt paths
+// - Readers keep a cursor (a record sequence number), so polling never
+//   sees a record twice and knows how many it missed
+//
+// Limitations:
+// - Single ring for the whole kernel; a busy subsystem pushes the others'
+//   records out
+// - The TSC is calibrated against the 100 Hz timer, so timestamps drift by
+//   up to a tick from the log's millisecond clock
+// - Single CPU only, like the rest of the kernel
+
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+
+use alloc::vec::Vec;
+
+use crate::log_info;
+use crate::util::without_interrupts;
+
+const LOG_ORIGIN: &str = "trace";
+
+/// Records held before the oldest are overwritten
+const TRACE_RING_SIZE: usize = 4096;
+
+/// Arguments a record carries
+pub const MAX_ARGS: usize = 4;
+
+// ============================================================================
+// Subsystems and Events
+// ============================================================================
+
+pub mod sched {
+    pub const SUBSYSTEM: u8 = 0;
+    /// A thread was switched in: (from thread or 0, to thread)
+    pub const SWITCH: u16 = 0;
+    /// A thread was put on a ready queue: (thread)
+    pub const WAKE: u16 = 1;
+}
+
+pub mod mm {
+    pub const SUBSYSTEM: u8 = 1;
+    /// Physical pages allocated: (address, pages)
+    pub const PAGE_ALLOC: u16 = 0;
+    /// A physical page freed: (address, pages)
+    pub const PAGE_FREE: u16 = 1;
+    /// A page fault the kernel could not resolve: (address, error code, rip)
+    pub const PAGE_FAULT: u16 = 2;
+}
+
+pub mod ipc {
+    pub const SUBSYSTEM: u8 = 2;
+    /// A message queued on a port: (port, sender, size)
+    pub const SEND: u16 = 0;
+    /// A message taken off a port: (port, sender, receiver, size)
+    pub const RECV: u16 = 1;
+}
+
+pub mod syscall {
+    pub const SUBSYSTEM: u8 = 3;
+    /// A syscall entered: (number, arg0, arg1, arg2)
+    pub const ENTER: u16 = 0;
+    /// A syscall returned: (number, result)
+    pub const EXIT: u16 = 1;
+}
+
+/// Subsystems traced from boot; syscall tracing is opt-in
+const DEFAULT_MASK: u32 = (1 << sched::SUBSYSTEM) | (1 << mm::SUBSYSTEM) | (1 << ipc::SUBSYSTEM);
+
+/// Subsystems that exist, as a mask
+pub const ALL_SUBSYSTEMS: u32 = (1 << (syscall::SUBSYSTEM + 1)) - 1;
+
+/// Record an event if its subsystem is being traced
+///
+/// `trace_event!(sched, SWITCH, from, to)` names the subsystem module and
+/// event constant above; arguments are cast to u64 and are only evaluated
+/// when the subsystem is on. Arguments past `MAX_ARGS` are dropped.
+#[macro_export]
+macro_rules! trace_event {
+    ($subsystem:ident, $event:ident $(, $arg:expr)* $(,)?) => {
+        if $crate::trace::enabled($crate::trace::$subsystem::SUBSYSTEM) {
+            $crate::trace::record(
+                $crate::trace::$subsystem::SUBSYSTEM,
+                $crate::trace::$subsystem::$event,
+                &[$($arg as u64),*],
+            );
+        }
+    };
+}
+
+// ============================================================================
+// Ring
+// ============================================================================
+
+/// Fixed-size log keeping the newest `N` entries
+///
+/// Every entry pushed gets the next sequence number, so a reader holding
+/// a sequence can pick up where it stopped and tell how many entries were
+/// overwritten in between.
+pub struct Ring<T: Copy, const N: usize> {
+    entries: [Option<T>; N],
+    /// Entries ever pushed; the next entry's sequence number
+    written: u64,
+}
+
+impl<T: Copy, const N: usize> Ring<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            entries: [None; N],
+            written: 0,
+        }
+    }
+
+    pub fn push(&mut self, entry: T) {
+        self.entries[(self.written % N as u64) as usize] = Some(entry);
+        self.written += 1;
+    }
+
+    /// Drop every entry; sequence numbers start again from 0
+    pub fn clear(&mut self) {
+        self.written = 0;
+    }
+
+    /// Sequence number of the oldest entry still held
+    pub fn oldest(&self) -> u64 {
+        self.written.saturating_sub(N as u64)
+    }
+
+    /// The newest `max` entries, oldest first
+    pub fn snapshot(&self, max: usize) -> Vec<T> {
+        let start = self.written.saturating_sub(max as u64).max(self.oldest());
+        (start..self.written).filter_map(|seq| self.get(seq)).collect()
+    }
+
+    /// Copy entries into `out`, oldest first, starting at sequence `seq`
+    /// or at the oldest entry held if `seq` has been overwritten
+    ///
+    /// Returns how many were copied and the sequence to read from next.
+    pub fn read_from(&self, seq: u64, out: &mut [T]) -> (usize, u64) {
+        let mut next = seq.clamp(self.oldest(), self.written);
+        let mut count = 0;
+        while count < out.len() && next < self.written {
+            if let Some(entry) = self.get(next) {
+                out[count] = entry;
+                count += 1;
+            }
+            next += 1;
+        }
+        (count, next)
+    }
+
+    fn get(&self, seq: u64) -> Option<T> {
+        self.entries[(seq % N as u64) as usize]
+    }
+}
+
+// ============================================================================
+// Recording
+// ============================================================================
+
+#[derive(Debug, Clone, Copy)]
+pub struct TraceRecord {
+    /// TSC when the event happened; see `Clock`
+    pub tsc: u64,
+    /// Thread running at the time, or 0
+    pub thread: u64,
+    pub subsystem: u8,
+    pub event: u16,
+    pub args: [u64; MAX_ARGS],
+}
+
+impl TraceRecord {
+    pub const EMPTY: Self = Self {
+        tsc: 0,
+        thread: 0,
+        subsystem: 0,
+        event: 0,
+        args: [0; MAX_ARGS],
+    };
+}
+
+static RING: Mutex<Ring<TraceRecord, TRACE_RING_SIZE>> = Mutex::new(Ring::new());
+
+/// Subsystems being traced, one bit per subsystem number
+static MASK: AtomicU32 = AtomicU32::new(DEFAULT_MASK);
+
+/// TSC and timer tick of the calibration base, taken by `init`
+static BASE_TSC: AtomicU64 = AtomicU64::new(0);
+static BASE_TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Take the calibration base; events are recorded before this too, and
+/// read back as happening at this point
+pub fn init() {
+    BASE_TICKS.store(crate::interrupts::get_ticks(), Ordering::Relaxed);
+    BASE_TSC.store(crate::arch::read_tsc(), Ordering::Relaxed);
+
+    log_info!(
+        LOG_ORIGIN,
+        "Tracing ready: ring={} records, mask={:#X}",
+        TRACE_RING_SIZE,
+        MASK.load(Ordering::Relaxed)
+    );
+}
+
+#[inline(always)]
+pub fn enabled(subsystem: u8) -> bool {
+    MASK.load(Ordering::Relaxed) & (1 << subsystem) != 0
+}
+
+/// Push a record; use `trace_event!` rather than calling this directly
+pub fn record(subsystem: u8, event: u16, args: &[u64]) {
+    let mut record = TraceRecord {
+        tsc: crate::arch::read_tsc(),
+        // Never waits: trace points sit inside the scheduler too
+        thread: crate::sched::try_current_thread().map_or(0, |id| id.raw()),
+        subsystem,
+        event,
+        args: [0; MAX_ARGS],
+    };
+    for (slot, &arg) in record.args.iter_mut().zip(args) {
+        *slot = arg;
+    }
+
+    without_interrupts(|| RING.lock().push(record));
+}
+
+/// Turn the subsystems in `enable` on and those in `disable` off; returns
+/// the mask now in effect
+pub fn control(enable: u32, disable: u32) -> u32 {
+    let enable = enable & ALL_SUBSYSTEMS;
+    let disable = disable & ALL_SUBSYSTEMS;
+    let previous = MASK
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |mask| {
+            Some((mask | enable) & !disable)
+        })
+        .unwrap_or_default();
+    (previous | enable) & !disable
+}
+
+/// Copy records from sequence `seq` on into `out`; see `Ring::read_from`
+pub fn read(seq: u64, out: &mut [TraceRecord]) -> (usize, u64) {
+    without_interrupts(|| RING.lock().read_from(seq, out))
+}
+
+// ============================================================================
+// Timestamps
+// ============================================================================
+
+/// TSC to time-since-boot conversion, calibrated against the timer when
+/// it is taken
+pub struct Clock {
+    base_tsc: u64,
+    base_ns: u64,
+    /// TSC cycles per millisecond, or 0 if no tick has passed since `init`
+    cycles_per_ms: u64,
+}
+
+impl Clock {
+    pub fn now() -> Self {
+        let base_tsc = BASE_TSC.load(Ordering::Relaxed);
+        let base_ticks = BASE_TICKS.load(Ordering::Relaxed);
+        let elapsed_ms = crate::interrupts::get_ticks().saturating_sub(base_ticks) * 10;
+        let elapsed_tsc = crate::arch::read_tsc().saturating_sub(base_tsc);
+
+        Self {
+            base_tsc,
+            base_ns: base_ticks * 10 * 1_000_000,
+            cycles_per_ms: elapsed_tsc.checked_div(elapsed_ms).unwrap_or(0),
+        }
+    }
+
+    /// Nanoseconds since boot at TSC reading `tsc`
+    pub fn nanoseconds(&self, tsc: u64) -> u64 {
+        if self.cycles_per_ms == 0 {
+            return self.base_ns;
+        }
+        let cycles = tsc.saturating_sub(self.base_tsc) as u128;
+        self.base_ns + (cycles * 1_000_000 / self.cycles_per_ms as u128) as u64
+    }
+}
@@ -0,0 +1,348 @@
+//! FAT32 Volumes
+//!
+//! Writes the EFI system partition firmware boots from: a FAT32 volume
+//! holding `\EFI\BOOT\BOOTX64.EFI` and whatever it loads next. The volume
+//! fills the whole image, with no partition table, which OVMF and most
+//! firmware accept as removable media.
+//!
+//! Only what a boot volume needs is written: 8.3 names, no long names, and
+//! timestamps of 1980-01-01 so the same inputs always give the same image.
+//! Files are laid out in one contiguous run of clusters each.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+const SECTOR_SIZE: usize = 512;
+const RESERVED_SECTORS: usize = 32;
+const FAT_COUNT: usize = 2;
+const FSINFO_SECTOR: usize = 1;
+const BACKUP_BOOT_SECTOR: usize = 6;
+const ROOT_CLUSTER: u32 = 2;
+/// FAT32 needs at least this many clusters, or it is read as FAT16
+const MIN_CLUSTERS: usize = 65_525;
+const DIR_ENTRY_SIZE: usize = 32;
+const END_OF_CHAIN: u32 = 0x0FFF_FFFF;
+
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+/// 1980-01-01 in the FAT date format
+const DATE: u16 = 1 << 5 | 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FatError {
+    /// Not an absolute path of 8.3 names
+    InvalidName(String),
+    /// A file and a directory, or two files, at the same path
+    Conflict(String),
+    /// The volume has fewer clusters than FAT32 allows
+    TooSmall,
+    /// The files do not fit in the volume
+    Full,
+}
+
+impl fmt::Display for FatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(path) => write!(f, "{path:?} is not a path of 8.3 names"),
+            Self::Conflict(path) => write!(f, "{path} is given twice"),
+            Self::TooSmall => write!(f, "volume is too small for FAT32"),
+            Self::Full => write!(f, "files do not fit in the volume"),
+        }
+    }
+}
+
+impl std::error::Error for FatError {}
+
+enum Node<'a> {
+    File(&'a [u8]),
+    Dir(BTreeMap<[u8; 11], Node<'a>>),
+}
+
+/// A FAT32 volume of `size` bytes holding `files`, each an absolute path
+/// and its contents
+pub fn build(files: &[(String, Vec<u8>)], size: usize, label: &str) -> Result<Vec<u8>, FatError> {
+    let mut root = BTreeMap::new();
+    for (path, data) in files {
+        insert(&mut root, path, data)?;
+    }
+
+    let total_sectors = size / SECTOR_SIZE;
+    // Microsoft's recommended cluster sizes: 512 bytes up to 260 MiB
+    let sectors_per_cluster = if total_sectors <= 532_480 { 1 } else { 8 };
+    let cluster_size = sectors_per_cluster * SECTOR_SIZE;
+    // Slightly more FAT than needed, as the FAT32 specification computes it
+    let per_fat_sector = (256 * sectors_per_cluster + FAT_COUNT) / 2;
+    let fat_sectors = (total_sectors.saturating_sub(RESERVED_SECTORS)).div_ceil(per_fat_sector);
+    let data_start = RESERVED_SECTORS + FAT_COUNT * fat_sectors;
+    let clusters = total_sectors.saturating_sub(data_start) / sectors_per_cluster;
+    if clusters < MIN_CLUSTERS || clusters + 2 > 0x0FFF_FFF0 {
+        return Err(FatError::TooSmall);
+    }
+
+    let mut volume = Volume {
+        image: vec![0; total_sectors * SECTOR_SIZE],
+        fat: vec![0; clusters + 2],
+        cluster_size,
+        data_start: data_start * SECTOR_SIZE,
+        next: ROOT_CLUSTER,
+    };
+    volume.fat[0] = 0x0FFF_FFF8;
+    volume.fat[1] = END_OF_CHAIN;
+
+    let root_cluster = volume.allocate(dir_size(&root))?;
+    volume.write_dir(&root, root_cluster, None)?;
+
+    let free = (volume.fat.len() - volume.next as usize) as u32;
+    let boot = boot_sector(total_sectors, sectors_per_cluster, fat_sectors, label);
+    for sector in [0, BACKUP_BOOT_SECTOR] {
+        volume.put(sector * SECTOR_SIZE, &boot);
+        volume.put((sector + FSINFO_SECTOR) * SECTOR_SIZE, &fsinfo(free, volume.next));
+    }
+
+    let fat: Vec<u8> = volume.fat.iter().flat_map(|entry| entry.to_le_bytes()).collect();
+    for copy in 0..FAT_COUNT {
+        volume.put((RESERVED_SECTORS + copy * fat_sectors) * SECTOR_SIZE, &fat);
+    }
+    Ok(volume.image)
+}
+
+fn insert<'a>(
+    root: &mut BTreeMap<[u8; 11], Node<'a>>,
+    path: &str,
+    data: &'a [u8],
+) -> Result<(), FatError> {
+    let invalid = || FatError::InvalidName(path.to_string());
+    let parts: Vec<&str> = path.strip_prefix('/').ok_or_else(invalid)?.split('/').collect();
+    let (file, dirs) = parts.split_last().ok_or_else(invalid)?;
+
+    let mut dir = root;
+    for part in dirs {
+        let node = dir.entry(short_name(part).ok_or_else(invalid)?);
+        match node.or_insert_with(|| Node::Dir(BTreeMap::new())) {
+            Node::Dir(children) => dir = children,
+            Node::File(_) => return Err(FatError::Conflict(path.to_string())),
+        }
+    }
+    let name = short_name(file).ok_or_else(invalid)?;
+    if dir.insert(name, Node::File(data)).is_some() {
+        return Err(FatError::Conflict(path.to_string()));
+    }
+    Ok(())
+}
+
+/// `name` as a directory entry name, upper-cased, if it is an 8.3 name
+fn short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, extension) = name.split_once('.').unwrap_or((name, ""));
+    let valid = |part: &str, max: usize| {
+        part.len() <= max
+            && part.bytes().all(|b| b.is_ascii_alphanumeric() || b"_-~!#$%&'(){}^@".contains(&b))
+    };
+    if base.is_empty() || !valid(base, 8) || !valid(extension, 3) {
+        return None;
+    }
+
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + extension.len()].copy_from_slice(extension.as_bytes());
+    short.make_ascii_uppercase();
+    Some(short)
+}
+
+/// Bytes of a directory's entries, with `.` and `..` unless it is the root
+fn dir_size(children: &BTreeMap<[u8; 11], Node>) -> usize {
+    (children.len() + 2) * DIR_ENTRY_SIZE
+}
+
+struct Volume {
+    image: Vec<u8>,
+    fat: Vec<u32>,
+    cluster_size: usize,
+    /// Byte offset of cluster 2
+    data_start: usize,
+    /// First cluster not yet allocated
+    next: u32,
+}
+
+impl Volume {
+    fn put(&mut self, offset: usize, bytes: &[u8]) {
+        self.image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> usize {
+        self.data_start + (cluster - ROOT_CLUSTER) as usize * self.cluster_size
+    }
+
+    /// A chain of clusters for `bytes`, or cluster 0 for an empty file
+    fn allocate(&mut self, bytes: usize) -> Result<u32, FatError> {
+        let count = bytes.div_ceil(self.cluster_size) as u32;
+        if count == 0 {
+            return Ok(0);
+        }
+        let first = self.next;
+        let end = first.checked_add(count).ok_or(FatError::Full)?;
+        if end as usize > self.fat.len() {
+            return Err(FatError::Full);
+        }
+        for cluster in first..end - 1 {
+            self.fat[cluster as usize] = cluster + 1;
+        }
+        self.fat[end as usize - 1] = END_OF_CHAIN;
+        self.next = end;
+        Ok(first)
+    }
+
+    /// Write a directory at `cluster` and, first, everything in it.
+    /// `parent` is `None` for the root, `Some(0)` for its subdirectories.
+    fn write_dir(
+        &mut self,
+        children: &BTreeMap<[u8; 11], Node>,
+        cluster: u32,
+        parent: Option<u32>,
+    ) -> Result<(), FatError> {
+        let mut entries = Vec::with_capacity(dir_size(children));
+        if let Some(parent) = parent {
+            entries.extend(dir_entry(*b".          ", ATTR_DIRECTORY, cluster, 0));
+            entries.extend(dir_entry(*b"..         ", ATTR_DIRECTORY, parent, 0));
+        }
+
+        for (name, node) in children {
+            match node {
+                Node::File(data) => {
+                    let first = self.allocate(data.len())?;
+                    if first != 0 {
+                        self.put(self.cluster_offset(first), data);
+                    }
+                    entries.extend(dir_entry(*name, ATTR_ARCHIVE, first, data.len() as u32));
+                }
+                Node::Dir(grandchildren) => {
+                    let first = self.allocate(dir_size(grandchildren))?;
+                    let up = if parent.is_some() { cluster } else { 0 };
+                    self.write_dir(grandchildren, first, Some(up))?;
+                    entries.extend(dir_entry(*name, ATTR_DIRECTORY, first, 0));
+                }
+            }
+        }
+
+        self.put(self.cluster_offset(cluster), &entries);
+        Ok(())
+    }
+}
+
+fn dir_entry(name: [u8; 11], attributes: u8, cluster: u32, size: u32) -> [u8; DIR_ENTRY_SIZE] {
+    let mut entry = [0u8; DIR_ENTRY_SIZE];
+    entry[..11].copy_from_slice(&name);
+    entry[11] = attributes;
+    entry[16..18].copy_from_slice(&DATE.to_le_bytes());
+    entry[18..20].copy_from_slice(&DATE.to_le_bytes());
+    entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    entry[24..26].copy_from_slice(&DATE.to_le_bytes());
+    entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+    entry[28..32].copy_from_slice(&size.to_le_bytes());
+    entry
+}
+
+fn boot_sector(
+    total_sectors: usize,
+    sectors_per_cluster: usize,
+    fat_sectors: usize,
+    label: &str,
+) -> [u8; SECTOR_SIZE] {
+    let mut sector = [0u8; SECTOR_SIZE];
+    // A jump over the BPB; nothing boots from the volume itself
+    sector[..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+    sector[3..11].copy_from_slice(b"ATOM    ");
+    sector[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+    sector[13] = sectors_per_cluster as u8;
+    sector[14..16].copy_from_slice(&(RESERVED_SECTORS as u16).to_le_bytes());
+    sector[16] = FAT_COUNT as u8;
+    sector[21] = 0xF8;
+    sector[24..26].copy_from_slice(&63u16.to_le_bytes());
+    sector[26..28].copy_from_slice(&255u16.to_le_bytes());
+    sector[32..36].copy_from_slice(&(total_sectors as u32).to_le_bytes());
+    sector[36..40].copy_from_slice(&(fat_sectors as u32).to_le_bytes());
+    sector[44..48].copy_from_slice(&ROOT_CLUSTER.to_le_bytes());
+    sector[48..50].copy_from_slice(&(FSINFO_SECTOR as u16).to_le_bytes());
+    sector[50..52].copy_from_slice(&(BACKUP_BOOT_SECTOR as u16).to_le_bytes());
+    sector[64] = 0x80;
+    sector[66] = 0x29;
+    sector[67..71].copy_from_slice(b"ATOM");
+
+    let mut volume_label = [b' '; 11];
+    for (slot, byte) in volume_label.iter_mut().zip(label.bytes()) {
+        *slot = byte.to_ascii_uppercase();
+    }
+    sector[71..82].copy_from_slice(&volume_label);
+    sector[82..90].copy_from_slice(b"FAT32   ");
+    sector[510..512].copy_from_slice(&[0x55, 0xAA]);
+    sector
+}
+
+fn fsinfo(free_clusters: u32, next_free: u32) -> [u8; SECTOR_SIZE] {
+    let mut sector = [0u8; SECTOR_SIZE];
+    sector[0..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+    sector[484..488].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+    sector[488..492].copy_from_slice(&free_clusters.to_le_bytes());
+    sector[492..496].copy_from_slice(&next_free.to_le_bytes());
+    sector[508..512].copy_from_slice(&0xAA55_0000u32.to_le_bytes());
+    sector
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: usize = 1024 * 1024;
+
+    fn u32_at(image: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(image[at..at + 4].try_into().unwrap())
+    }
+
+    /// The contents of `path`, found the way firmware would
+    fn read(image: &[u8], path: &str) -> Option<Vec<u8>> {
+        let sectors_per_cluster = image[13] as usize;
+        let fat_sectors = u32_at(image, 36) as usize;
+        let cluster_size = sectors_per_cluster * SECTOR_SIZE;
+        let data_start = (RESERVED_SECTORS + FAT_COUNT * fat_sectors) * SECTOR_SIZE;
+        let offset = |cluster: u32| data_start + (cluster as usize - 2) * cluster_size;
+
+        let mut cluster = u32_at(image, 44);
+        let mut size = 0;
+        for part in path[1..].split('/') {
+            let name = short_name(part)?;
+            let dir = &image[offset(cluster)..offset(cluster) + cluster_size];
+            let entry = dir.chunks(DIR_ENTRY_SIZE).find(|entry| entry[..11] == name)?;
+            cluster = (u16::from_le_bytes([entry[20], entry[21]]) as u32) << 16
+                | u16::from_le_bytes([entry[26], entry[27]]) as u32;
+            size = u32_at(entry, 28) as usize;
+        }
+        if size == 0 {
+            return Some(Vec::new());
+        }
+        Some(image[offset(cluster)..offset(cluster) + size].to_vec())
+    }
+
+    #[test]
+    fn writes_a_readable_volume() {
+        let kernel: Vec<u8> = (0..3000u32).map(|i| i as u8).collect();
+        let files = [
+            ("/EFI/BOOT/BOOTX64.EFI".to_string(), kernel.clone()),
+            ("/EFI/ATOM/initrd.img".to_string(), b"archive".to_vec()),
+            ("/startup.nsh".to_string(), Vec::new()),
+        ];
+        let image = build(&files, 64 * MIB, "atom").unwrap();
+
+        assert_eq!(image.len(), 64 * MIB);
+        assert_eq!(&image[82..90], b"FAT32   ");
+        assert_eq!(image[..SECTOR_SIZE], image[6 * SECTOR_SIZE..7 * SECTOR_SIZE]);
+        assert_eq!(read(&image, "/EFI/BOOT/BOOTX64.EFI"), Some(kernel));
+        assert_eq!(read(&image, "/EFI/ATOM/INITRD.IMG"), Some(b"archive".to_vec()));
+        assert_eq!(read(&image, "/startup.nsh"), Some(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_long_names_and_small_volumes() {
+        let long = [("/EFI/BOOT/kernel.elf64".to_string(), Vec::new())];
+        assert!(matches!(build(&long, 64 * MIB, "atom"), Err(FatError::InvalidName(_))));
+        assert_eq!(build(&[], 16 * MIB, "atom"), Err(FatError::TooSmall));
+    }
+}
@@ -0,0 +1,276 @@
+//! Initramfs Archives
+//!
+//! The files the system needs before it has a filesystem driver: the
+//! executables the boot manifest names, the manifest itself, configuration
+//! and assets. The bootloader loads the archive whole, so it is a flat
+//! table of paths and sizes with the files' bytes after it:
+//!
+//! ```text
+//! offset  size  header
+//!      0     4  magic, "DRTA" (0x4154_5244 little-endian)
+//!      4     2  version (1)
+//!      6     2  header size (32)
+//!      8     4  file offset of the entry table
+//!     12     4  number of entries
+//!     16     4  file offset of the paths
+//!     20     4  size of the paths
+//!     24     4  size of the whole archive
+//!     28     4  CRC-32 of the whole archive, with this field as zero
+//!
+//! offset  size  entry, sorted by path
+//!      0     4  offset of its NUL-terminated path in the paths
+//!      4     4  KIND_DATA, KIND_EXECUTABLE or KIND_MANIFEST
+//!      8     4  file offset of its bytes, a multiple of PAGE_SIZE
+//!     12     4  size of its bytes
+//!     16     4  CRC-32 of its bytes
+//!     20     4  reserved, zero
+//! ```
+//!
+//! All fields are little-endian. Paths are absolute, with no empty, `.` or
+//! `..` components. Each file starts on a page so the kernel can map an
+//! executable's or asset's pages instead of copying them.
+
+use std::fmt;
+
+use atxf::checksum;
+
+pub const MAGIC: u32 = 0x4154_5244;
+pub const VERSION: u16 = 1;
+pub const HEADER_SIZE: usize = 32;
+pub const ENTRY_SIZE: usize = 24;
+pub const PAGE_SIZE: usize = 4096;
+
+/// Any file the kernel does not need to understand
+pub const KIND_DATA: u32 = 0;
+/// An ATXF executable
+pub const KIND_EXECUTABLE: u32 = 1;
+/// The boot manifest, naming the services to start
+pub const KIND_MANIFEST: u32 = 2;
+
+const CHECKSUM_OFFSET: usize = 28;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct File {
+    pub path: String,
+    pub kind: u32,
+    pub data: Vec<u8>,
+}
+
+/// Why an archive is not one the kernel would read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveError {
+    Truncated,
+    BadMagic(u32),
+    UnsupportedVersion(u16),
+    /// Not absolute, with an empty, `.` or `..` component, or with a NUL
+    InvalidPath(String),
+    DuplicatePath(String),
+    /// Out of order, outside the archive or of an unknown kind
+    InvalidEntry(usize),
+    /// The stored and computed CRC-32 of the archive
+    BadChecksum(u32, u32),
+    /// The bytes of the file at this path do not match its CRC-32
+    CorruptFile(String),
+    TooLarge,
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "archive is truncated"),
+            Self::BadMagic(magic) => write!(f, "bad magic {magic:#010X}"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported version {version}"),
+            Self::InvalidPath(path) => write!(f, "invalid path {path:?}"),
+            Self::DuplicatePath(path) => write!(f, "{path} is in the archive twice"),
+            Self::InvalidEntry(index) => write!(f, "entry {index} is invalid"),
+            Self::BadChecksum(stored, computed) => {
+                write!(f, "checksum is {stored:#010X} but the contents give {computed:#010X}")
+            }
+            Self::CorruptFile(path) => write!(f, "{path} does not match its checksum"),
+            Self::TooLarge => write!(f, "archive would be larger than 4 GiB"),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+/// Whether `path` may name a file in an archive
+pub fn valid_path(path: &str) -> bool {
+    match path.strip_prefix('/') {
+        Some(rest) => {
+            !path.contains('\0')
+                && rest.split('/').all(|part| !part.is_empty() && part != "." && part != "..")
+        }
+        None => false,
+    }
+}
+
+/// The archive of `files`, in any order
+pub fn build(files: &[File]) -> Result<Vec<u8>, ArchiveError> {
+    let mut files: Vec<&File> = files.iter().collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    for (index, file) in files.iter().enumerate() {
+        if !valid_path(&file.path) {
+            return Err(ArchiveError::InvalidPath(file.path.clone()));
+        }
+        if index > 0 && files[index - 1].path == file.path {
+            return Err(ArchiveError::DuplicatePath(file.path.clone()));
+        }
+    }
+
+    let mut paths = Vec::new();
+    let mut path_offsets = Vec::with_capacity(files.len());
+    for file in &files {
+        path_offsets.push(paths.len());
+        paths.extend_from_slice(file.path.as_bytes());
+        paths.push(0);
+    }
+
+    let table_offset = HEADER_SIZE;
+    let paths_offset = table_offset + files.len() * ENTRY_SIZE;
+    let mut data_offsets = Vec::with_capacity(files.len());
+    let mut end = paths_offset + paths.len();
+    for file in &files {
+        let offset = end.next_multiple_of(PAGE_SIZE);
+        data_offsets.push(offset);
+        end = offset + file.data.len();
+    }
+    if end > u32::MAX as usize {
+        return Err(ArchiveError::TooLarge);
+    }
+
+    let mut out = vec![0u8; end];
+    out[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+    out[4..6].copy_from_slice(&VERSION.to_le_bytes());
+    out[6..8].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+    out[8..12].copy_from_slice(&(table_offset as u32).to_le_bytes());
+    out[12..16].copy_from_slice(&(files.len() as u32).to_le_bytes());
+    out[16..20].copy_from_slice(&(paths_offset as u32).to_le_bytes());
+    out[20..24].copy_from_slice(&(paths.len() as u32).to_le_bytes());
+    out[24..28].copy_from_slice(&(end as u32).to_le_bytes());
+
+    for (index, file) in files.iter().enumerate() {
+        let entry = table_offset + index * ENTRY_SIZE;
+        out[entry..entry + 4].copy_from_slice(&(path_offsets[index] as u32).to_le_bytes());
+        out[entry + 4..entry + 8].copy_from_slice(&file.kind.to_le_bytes());
+        out[entry + 8..entry + 12].copy_from_slice(&(data_offsets[index] as u32).to_le_bytes());
+        out[entry + 12..entry + 16].copy_from_slice(&(file.data.len() as u32).to_le_bytes());
+        out[entry + 16..entry + 20].copy_from_slice(&checksum(&file.data).to_le_bytes());
+
+        let offset = data_offsets[index];
+        out[offset..offset + file.data.len()].copy_from_slice(&file.data);
+    }
+    out[paths_offset..paths_offset + paths.len()].copy_from_slice(&paths);
+
+    let crc = checksum(&out);
+    out[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+    Ok(out)
+}
+
+/// The files of an archive, checking it as the kernel would
+pub fn parse(bytes: &[u8]) -> Result<Vec<File>, ArchiveError> {
+    let u16_at = |at: usize| -> Result<u16, ArchiveError> {
+        let field = bytes.get(at..at + 2).ok_or(ArchiveError::Truncated)?;
+        Ok(u16::from_le_bytes([field[0], field[1]]))
+    };
+    let u32_at = |at: usize| -> Result<usize, ArchiveError> {
+        let field = bytes.get(at..at + 4).ok_or(ArchiveError::Truncated)?;
+        Ok(u32::from_le_bytes(field.try_into().unwrap()) as usize)
+    };
+
+    let magic = u32_at(0)? as u32;
+    if magic != MAGIC {
+        return Err(ArchiveError::BadMagic(magic));
+    }
+    let version = u16_at(4)?;
+    if version != VERSION || u16_at(6)? as usize != HEADER_SIZE {
+        return Err(ArchiveError::UnsupportedVersion(version));
+    }
+    if u32_at(24)? != bytes.len() {
+        return Err(ArchiveError::Truncated);
+    }
+
+    let stored = u32_at(CHECKSUM_OFFSET)? as u32;
+    let mut zeroed = bytes.to_vec();
+    zeroed[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].fill(0);
+    let computed = checksum(&zeroed);
+    if stored != computed {
+        return Err(ArchiveError::BadChecksum(stored, computed));
+    }
+
+    let table = u32_at(8)?;
+    let count = u32_at(12)?;
+    let paths = bytes
+        .get(u32_at(16)?..u32_at(16)? + u32_at(20)?)
+        .ok_or(ArchiveError::Truncated)?;
+
+    let mut files: Vec<File> = Vec::with_capacity(count);
+    for index in 0..count {
+        let entry = table + index * ENTRY_SIZE;
+        let invalid = ArchiveError::InvalidEntry(index);
+
+        let name = paths.get(u32_at(entry)?..).ok_or(invalid.clone())?;
+        let name = &name[..name.iter().position(|&b| b == 0).ok_or(invalid.clone())?];
+        let path = String::from_utf8(name.to_vec()).map_err(|_| invalid.clone())?;
+        if !valid_path(&path) {
+            return Err(ArchiveError::InvalidPath(path));
+        }
+        if files.last().is_some_and(|last| last.path >= path) {
+            return Err(invalid);
+        }
+
+        let kind = u32_at(entry + 4)? as u32;
+        let offset = u32_at(entry + 8)?;
+        let size = u32_at(entry + 12)?;
+        if kind > KIND_MANIFEST || offset % PAGE_SIZE != 0 {
+            return Err(invalid);
+        }
+        let data = bytes.get(offset..offset + size).ok_or(invalid)?;
+        if checksum(data) as usize != u32_at(entry + 16)? {
+            return Err(ArchiveError::CorruptFile(path));
+        }
+
+        files.push(File { path, kind, data: data.to_vec() });
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, kind: u32, data: &[u8]) -> File {
+        File { path: path.to_string(), kind, data: data.to_vec() }
+    }
+
+    #[test]
+    fn round_trips_sorted() {
+        let files = [
+            file("/init/ui_shell.atxf", KIND_EXECUTABLE, &[0xAB; 5000]),
+            file("/boot/manifest.toml", KIND_MANIFEST, b"[service.ui_shell]\n"),
+            file("/etc/empty", KIND_DATA, b""),
+        ];
+        let bytes = build(&files).unwrap();
+        assert_eq!(bytes.len() % PAGE_SIZE, 5000 % PAGE_SIZE);
+
+        let parsed = parse(&bytes).unwrap();
+        let paths: Vec<_> = parsed.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["/boot/manifest.toml", "/etc/empty", "/init/ui_shell.atxf"]);
+        assert_eq!(parsed[2], files[0]);
+    }
+
+    #[test]
+    fn rejects_bad_paths_and_corruption() {
+        for path in ["relative", "/a//b", "/a/../b", "/a/", "/"] {
+            let error = build(&[file(path, KIND_DATA, b"")]).unwrap_err();
+            assert_eq!(error, ArchiveError::InvalidPath(path.to_string()));
+        }
+        let twice = [file("/a", KIND_DATA, b"1"), file("/a", KIND_DATA, b"2")];
+        assert_eq!(build(&twice), Err(ArchiveError::DuplicatePath("/a".to_string())));
+
+        let mut bytes = build(&[file("/a", KIND_DATA, b"contents")]).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(matches!(parse(&bytes), Err(ArchiveError::BadChecksum(..))));
+    }
+}
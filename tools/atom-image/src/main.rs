@@ -0,0 +1,273 @@
+//! atom-image - Build Atom OS Boot Images
+//!
+//! Collects what a boot needs into one initramfs archive (see `initramfs`):
+//! the ATXF executables, the boot manifest, configuration files and assets.
+//! Each `<path>=<source>` argument puts a file, or a directory's files
+//! under it, at `path` in the archive. Executables are recognised by their
+//! magic and checked as the kernel would load them, and every binary the
+//! manifest names must be in the archive.
+//!
+//! With `--sign <key>`, every executable is signed with the key file's
+//! Ed25519 key (see elf2atxf), replacing any signature it had, for kernels
+//! that only start signed services.
+//!
+//! With `--esp`, a FAT32 EFI system partition image is written as well,
+//! holding the kernel as `\EFI\BOOT\BOOTX64.EFI` and the archive beside it
+//! as `\EFI\ATOM\INITRD.IMG`. QEMU boots it with
+//! `-drive format=raw,file=<esp.img>`.
+//!
+//! ```text
+//! atom-image [-v] [--manifest <boot.toml>] [--sign <key>]
+//!            [--kernel <Atom.efi> --esp <esp.img>] [--esp-size <MiB>]
+//!            -o <initramfs.img> [<path>=<source>]...
+//! atom-image --list <initramfs.img>
+//! ```
+
+mod fat;
+mod initramfs;
+
+use std::error::Error;
+use std::path::Path;
+use std::process::ExitCode;
+use std::{env, fs};
+
+use atxf::ed25519;
+use initramfs::{File, KIND_DATA, KIND_EXECUTABLE, KIND_MANIFEST};
+
+const USAGE: &str = "usage: atom-image [-v] [--manifest <boot.toml>] [--sign <key>] \
+[--kernel <Atom.efi> --esp <esp.img>] [--esp-size <MiB>] -o <initramfs.img> [<path>=<source>]...
+       atom-image --list <initramfs.img>";
+
+/// Where the manifest goes in the archive
+const MANIFEST_PATH: &str = "/boot/manifest.toml";
+const ESP_KERNEL_PATH: &str = "/EFI/BOOT/BOOTX64.EFI";
+const ESP_INITRAMFS_PATH: &str = "/EFI/ATOM/INITRD.IMG";
+const ESP_LABEL: &str = "ATOM";
+/// Large enough for FAT32 with 512-byte clusters
+const DEFAULT_ESP_MIB: usize = 64;
+
+#[derive(Default)]
+struct Options {
+    verbose: bool,
+    list: Option<String>,
+    output: Option<String>,
+    manifest: Option<String>,
+    /// Key file to sign executables with
+    sign: Option<String>,
+    kernel: Option<String>,
+    esp: Option<String>,
+    esp_mib: Option<usize>,
+    /// `(path in the archive, source on the host)`
+    files: Vec<(String, String)>,
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("atom-image: {error}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match &options.list {
+        Some(archive) => list(archive),
+        None => run(&options),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("atom-image: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The options, or `None` if help was asked for
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+        match arg.as_str() {
+            "-v" | "--verbose" => options.verbose = true,
+            "-h" | "--help" => return Ok(None),
+            "-l" | "--list" => options.list = Some(value()?),
+            "-o" | "--output" => options.output = Some(value()?),
+            "-m" | "--manifest" => options.manifest = Some(value()?),
+            "-s" | "--sign" => options.sign = Some(value()?),
+            "-k" | "--kernel" => options.kernel = Some(value()?),
+            "--esp" => options.esp = Some(value()?),
+            "--esp-size" => {
+                let mib = value()?;
+                let mib = mib.parse().map_err(|_| format!("bad ESP size {mib:?}"))?;
+                options.esp_mib = Some(mib);
+            }
+            _ => match arg.split_once('=') {
+                Some((path, source)) => options.files.push((path.into(), source.into())),
+                None => return Err(format!("unexpected argument {arg:?}")),
+            },
+        }
+    }
+
+    if options.list.is_none() && options.output.is_none() {
+        return Err("no output given".into());
+    }
+    if options.esp.is_some() != options.kernel.is_some() {
+        return Err("--esp and --kernel go together".into());
+    }
+    Ok(Some(options))
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut files = Vec::new();
+    if let Some(manifest) = &options.manifest {
+        files.push(File {
+            path: MANIFEST_PATH.into(),
+            kind: KIND_MANIFEST,
+            data: fs::read(manifest).map_err(|error| format!("{manifest}: {error}"))?,
+        });
+    }
+    for (path, source) in &options.files {
+        collect(path, Path::new(source), &mut files)?;
+    }
+    check_manifest(&files)?;
+    if let Some(key) = &options.sign {
+        let seed = ed25519::read_key_file(key).map_err(|error| format!("{key}: {error}"))?;
+        for file in files.iter_mut().filter(|file| file.kind == KIND_EXECUTABLE) {
+            file.data = atxf::sign(&file.data, &seed)?;
+        }
+    }
+
+    let archive = initramfs::build(&files)?;
+    if options.verbose {
+        for file in &files {
+            println!("  {:>9}  {}  {}", file.data.len(), kind_str(file.kind), file.path);
+        }
+        println!("{} files, {} bytes", files.len(), archive.len());
+    }
+
+    if let (Some(esp), Some(kernel)) = (&options.esp, &options.kernel) {
+        let kernel = fs::read(kernel).map_err(|error| format!("{kernel}: {error}"))?;
+        let contents = [
+            (ESP_KERNEL_PATH.to_string(), kernel),
+            (ESP_INITRAMFS_PATH.to_string(), archive.clone()),
+        ];
+        let size = options.esp_mib.unwrap_or(DEFAULT_ESP_MIB) * 1024 * 1024;
+        fs::write(esp, fat::build(&contents, size, ESP_LABEL)?)?;
+        if options.verbose {
+            println!("ESP {esp}, {size} bytes");
+        }
+    }
+
+    if let Some(output) = &options.output {
+        fs::write(output, archive)?;
+    }
+    Ok(())
+}
+
+/// Add the file at `source`, or the files under it, at `path`
+fn collect(path: &str, source: &Path, files: &mut Vec<File>) -> Result<(), Box<dyn Error>> {
+    let context = |error: std::io::Error| format!("{}: {error}", source.display());
+
+    if source.is_dir() {
+        let mut entries: Vec<_> = fs::read_dir(source)
+            .map_err(context)?
+            .collect::<Result<_, _>>()
+            .map_err(context)?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name();
+            let name = name.to_str().ok_or_else(|| format!("{name:?} is not UTF-8"))?;
+            collect(&format!("{}/{name}", path.trim_end_matches('/')), &entry.path(), files)?;
+        }
+        return Ok(());
+    }
+
+    let data = fs::read(source).map_err(context)?;
+    let kind = if data.starts_with(&atxf::MAGIC.to_le_bytes()) {
+        atxf::Image::parse(&data).map_err(|error| format!("{}: {error}", source.display()))?;
+        KIND_EXECUTABLE
+    } else {
+        KIND_DATA
+    };
+    files.push(File { path: path.into(), kind, data });
+    Ok(())
+}
+
+/// Every binary the manifest names must be an executable in the archive
+fn check_manifest(files: &[File]) -> Result<(), String> {
+    let Some(manifest) = files.iter().find(|file| file.kind == KIND_MANIFEST) else {
+        return Ok(());
+    };
+    let text = String::from_utf8_lossy(&manifest.data);
+
+    for binary in manifest_binaries(&text) {
+        match files.iter().find(|file| file.path == binary) {
+            Some(file) if file.kind == KIND_EXECUTABLE => {}
+            Some(_) => return Err(format!("manifest binary {binary} is not an ATXF executable")),
+            None => return Err(format!("manifest binary {binary} is not in the image")),
+        }
+    }
+    Ok(())
+}
+
+/// The `binary = "..."` values of a manifest
+fn manifest_binaries(text: &str) -> Vec<&str> {
+    text.lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            if key.trim() != "binary" {
+                return None;
+            }
+            value.trim().strip_prefix('"')?.strip_suffix('"')
+        })
+        .collect()
+}
+
+fn kind_str(kind: u32) -> &'static str {
+    match kind {
+        KIND_EXECUTABLE => "exec",
+        KIND_MANIFEST => "mnfs",
+        _ => "data",
+    }
+}
+
+fn list(archive: &str) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(archive)?;
+    for file in initramfs::parse(&bytes)? {
+        println!("  {:>9}  {}  {}", file.data.len(), kind_str(file.kind), file.path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_manifest_binaries() {
+        let manifest = concat!(
+            "[service.ui_shell]\n",
+            "binary = \"/init/ui_shell.atxf\"\n",
+            "capabilities = [\"FrameBufferCap\"]\n",
+            "# binary = \"/commented/out\"\n",
+            "[service.terminal]\n",
+            "binary=\"/apps/terminal.atxf\"\n",
+        );
+        assert_eq!(manifest_binaries(manifest), ["/init/ui_shell.atxf", "/apps/terminal.atxf"]);
+
+        let files = [File {
+            path: MANIFEST_PATH.into(),
+            kind: KIND_MANIFEST,
+            data: manifest.as_bytes().to_vec(),
+        }];
+        assert_eq!(
+            check_manifest(&files),
+            Err("manifest binary /init/ui_shell.atxf is not in the image".into())
+        );
+    }
+}
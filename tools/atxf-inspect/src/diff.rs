@@ -0,0 +1,151 @@
+//! Image Differences
+//!
+//! What changed between two builds of a program, in the terms the kernel
+//! loads it by: the entry point, each segment's placement, permissions and
+//! bytes, the relocations and the symbols. File offsets and compression
+//! are left out, since they change with everything before them.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use atxf::{Image, Segment};
+
+/// One line per difference; empty if the images load the same
+pub fn diff(a: &Image, b: &Image) -> Vec<String> {
+    let mut lines = Vec::new();
+    if a.entry != b.entry {
+        lines.push(format!("entry: {:#X} -> {:#X}", a.entry, b.entry));
+    }
+    if a.relocatable != b.relocatable {
+        lines.push(format!("relocatable: {} -> {}", a.relocatable, b.relocatable));
+    }
+
+    for index in 0..a.segments.len().max(b.segments.len()) {
+        match (a.segments.get(index), b.segments.get(index)) {
+            (Some(a), Some(b)) => diff_segment(index, a, b, &mut lines),
+            (Some(a), None) => lines.push(format!("segment {index}: removed ({})", describe(a))),
+            (None, Some(b)) => lines.push(format!("segment {index}: added ({})", describe(b))),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    let before: BTreeSet<u64> = a.relocations.iter().copied().collect();
+    let after: BTreeSet<u64> = b.relocations.iter().copied().collect();
+    let added = after.difference(&before).count();
+    let removed = before.difference(&after).count();
+    if added + removed > 0 {
+        lines.push(format!(
+            "relocations: {} -> {} ({added} added, {removed} removed)",
+            a.relocations.len(),
+            b.relocations.len()
+        ));
+    }
+
+    // One built without --symbols: listing every symbol says nothing more
+    if a.symbols.is_empty() != b.symbols.is_empty() {
+        lines.push(format!("symbols: {} -> {}", a.symbols.len(), b.symbols.len()));
+        return lines;
+    }
+    let before: BTreeMap<&str, _> = a.symbols.iter().map(|s| (s.name.as_str(), s)).collect();
+    let after: BTreeMap<&str, _> = b.symbols.iter().map(|s| (s.name.as_str(), s)).collect();
+    for (name, old) in &before {
+        match after.get(name) {
+            None => lines.push(format!("symbol {name}: removed")),
+            Some(new) if (old.vaddr, old.size) != (new.vaddr, new.size) => lines.push(format!(
+                "symbol {name}: {:#X}+{:#X} -> {:#X}+{:#X}",
+                old.vaddr, old.size, new.vaddr, new.size
+            )),
+            Some(_) => {}
+        }
+    }
+    for (name, new) in &after {
+        if !before.contains_key(name) {
+            lines.push(format!("symbol {name}: added at {:#X}+{:#X}", new.vaddr, new.size));
+        }
+    }
+    lines
+}
+
+fn describe(segment: &Segment) -> String {
+    format!("{:#X}+{:#X} {}", segment.vaddr, segment.mem_size, segment.flags_str())
+}
+
+fn diff_segment(index: usize, a: &Segment, b: &Segment, lines: &mut Vec<String>) {
+    if (a.vaddr, a.mem_size, a.flags) != (b.vaddr, b.mem_size, b.flags) {
+        lines.push(format!("segment {index}: {} -> {}", describe(a), describe(b)));
+    }
+    if a.data.len() != b.data.len() {
+        lines.push(format!(
+            "segment {index}: {:#X} -> {:#X} bytes in the file",
+            a.data.len(),
+            b.data.len()
+        ));
+    }
+    let changed: Vec<usize> = a
+        .data
+        .iter()
+        .zip(&b.data)
+        .enumerate()
+        .filter(|(_, (x, y))| x != y)
+        .map(|(offset, _)| offset)
+        .collect();
+    if let Some(&first) = changed.first() {
+        lines.push(format!(
+            "segment {index}: {} bytes differ, the first at {:#X}",
+            changed.len(),
+            a.vaddr + first as u64
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atxf::{Symbol, SEGMENT_EXECUTE, SEGMENT_READ, SEGMENT_WRITE};
+
+    fn image() -> Image {
+        Image {
+            entry: 0x40_0000,
+            segments: vec![Segment {
+                vaddr: 0x40_0000,
+                mem_size: 0x10,
+                flags: SEGMENT_READ | SEGMENT_EXECUTE,
+                data: vec![0x90; 0x10],
+            }],
+            symbols: vec![Symbol { vaddr: 0x40_0000, size: 0x10, name: "_start".into() }],
+            ..Image::default()
+        }
+    }
+
+    #[test]
+    fn same_images_do_not_differ() {
+        let mut compressed = image();
+        compressed.compressed = true;
+        assert_eq!(diff(&image(), &compressed), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_each_change() {
+        let mut changed = image();
+        changed.entry = 0x40_0004;
+        changed.segments[0].data[4] = 0xC3;
+        changed.segments.push(Segment {
+            vaddr: 0x40_1000,
+            mem_size: 0x1000,
+            flags: SEGMENT_READ | SEGMENT_WRITE,
+            data: Vec::new(),
+        });
+        changed.symbols[0].size = 0x8;
+        changed.symbols.push(Symbol { vaddr: 0x40_0008, size: 0x8, name: "main".into() });
+
+        assert_eq!(
+            diff(&image(), &changed),
+            [
+                "entry: 0x400000 -> 0x400004",
+                "segment 0: 1 bytes differ, the first at 0x400004",
+                "segment 1: added (0x401000+0x1000 rw-)",
+                "symbol _start: 0x400000+0x10 -> 0x400000+0x8",
+                "symbol main: added at 0x400008+0x8",
+            ]
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! atxf-inspect - Inspect, Validate and Diff ATXF Executables
+//!
+//! Prints what the kernel loader sees in an image: the header fields, the
+//! checksum it will compare, the segment table with the pages each segment
+//! maps, and with `--symbols` and `--relocations` those tables too. The
+//! header and segment table are read as stored, so an image the loader
+//! rejects still shows.
+//!
+//! The image is then checked by the rules the kernel loads by, and its
+//! layout for what no writer should produce: parts past the end of the
+//! file or overlapping, misaligned segment bytes and unused bytes. The
+//! exit status is 1 if the kernel would reject the image. With `--key`,
+//! the signature is checked against that public key, 64 hex digits as
+//! `elf2atxf --public-key` prints them.
+//!
+//! `--diff` compares two images by what they load: entry point, segments,
+//! relocations and symbols. The exit status is 1 if they differ.
+//!
+//! ```text
+//! atxf-inspect [--symbols] [--relocations] [--key <public key>] <image.atxf>...
+//! atxf-inspect --diff <a.atxf> <b.atxf>
+//! ```
+
+mod diff;
+mod raw;
+
+use std::error::Error;
+use std::process::ExitCode;
+use std::{env, fs};
+
+use atxf::ed25519::{self, PUBLIC_KEY_SIZE};
+use atxf::{FormatError, Image, FLAG_CHECKSUM, FLAG_RELOCATABLE, FLAG_SIGNED, PAGE_SIZE};
+
+use raw::{Header, SegmentEntry};
+
+const USAGE: &str = "\
+usage: atxf-inspect [--symbols] [--relocations] [--key <public key>] <image.atxf>...
+       atxf-inspect --diff <a.atxf> <b.atxf>";
+
+#[derive(Default)]
+struct Options {
+    symbols: bool,
+    relocations: bool,
+    diff: bool,
+    key: Option<[u8; PUBLIC_KEY_SIZE]>,
+}
+
+fn main() -> ExitCode {
+    let mut options = Options::default();
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-s" | "--symbols" => options.symbols = true,
+            "-r" | "--relocations" => options.relocations = true,
+            "-d" | "--diff" => options.diff = true,
+            "-k" | "--key" => match args.next().as_deref().and_then(ed25519::from_hex) {
+                Some(key) => options.key = Some(key),
+                None => {
+                    eprintln!("atxf-inspect: --key needs 64 hex digits\n{USAGE}");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    if options.diff {
+        let [a, b] = paths.as_slice() else {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        };
+        return match compare(a, b) {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::FAILURE,
+            Err(error) => {
+                eprintln!("atxf-inspect: {error}");
+                ExitCode::from(2)
+            }
+        };
+    }
+
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    }
+    let mut status = ExitCode::SUCCESS;
+    for (index, path) in paths.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        match inspect(path, &options) {
+            Ok(true) => {}
+            Ok(false) => status = ExitCode::FAILURE,
+            Err(error) => {
+                eprintln!("atxf-inspect: {path}: {error}");
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}
+
+/// Print an image and whether it is valid
+fn inspect(path: &str, options: &Options) -> Result<bool, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let header = raw::read_header(&bytes)?;
+    let segments = raw::read_segments(&bytes, &header);
+
+    println!(
+        "{path}: ATXF version {}, {}-byte header, {} bytes",
+        header.version,
+        header.header_size,
+        bytes.len()
+    );
+    print_header(&bytes, &header, &segments, options);
+    print_segments(&header, &segments);
+
+    let image = Image::parse(&bytes);
+    if let Ok(image) = &image {
+        if options.symbols {
+            print_symbols(image);
+        }
+        if options.relocations {
+            print_relocations(image);
+        }
+    }
+
+    let warnings = raw::layout_warnings(&bytes, &header, &segments);
+    if !warnings.is_empty() {
+        println!();
+        for warning in &warnings {
+            println!("warning: {warning}");
+        }
+    }
+
+    let signature = match (&image, options.key) {
+        (Ok(_), Some(key)) => atxf::verify(&bytes, &key),
+        _ => Ok(()),
+    };
+    match image.and_then(|image| image.validate()).and(signature) {
+        Ok(()) => {
+            println!("valid");
+            Ok(true)
+        }
+        Err(error) => {
+            println!("invalid: {error}");
+            Ok(false)
+        }
+    }
+}
+
+fn print_header(bytes: &[u8], header: &Header, segments: &[SegmentEntry], options: &Options) {
+    let holder = segments
+        .iter()
+        .position(|s| (s.vaddr..s.vaddr.saturating_add(s.mem_size)).contains(&header.entry));
+    match holder {
+        Some(index) => println!("  entry        {:#X} (segment {index})", header.entry),
+        None => println!("  entry        {:#X} (in no segment)", header.entry),
+    }
+    println!("  flags        {}", flags_str(header.flags));
+
+    if header.flags & FLAG_CHECKSUM != 0 {
+        let computed = atxf::checksum(bytes);
+        if computed == header.checksum {
+            println!("  checksum     {:#010X} (matches)", header.checksum);
+        } else {
+            println!("  checksum     {:#010X} (contents give {computed:#010X})", header.checksum);
+        }
+    }
+    if header.flags & FLAG_SIGNED != 0 {
+        let state = match options.key.map(|key| atxf::verify(bytes, &key)) {
+            None => "not checked".to_string(),
+            Some(Ok(())) => "valid for the key".to_string(),
+            Some(Err(error)) => error.to_string(),
+        };
+        println!("  signature    {state}");
+    }
+
+    println!(
+        "  segments     {} at {:#X}, {}-byte entries",
+        header.segment_count, header.segment_offset, header.segment_entry_size
+    );
+    if header.relocation_count > 0 {
+        println!("  relocations  {} at {:#X}", header.relocation_count, header.relocation_offset);
+    }
+    if header.symbol_count > 0 {
+        println!(
+            "  symbols      {} at {:#X}, names {} bytes at {:#X}",
+            header.symbol_count, header.symbol_offset, header.names_size, header.names_offset
+        );
+    }
+}
+
+fn flags_str(flags: u32) -> String {
+    let names =
+        [(FLAG_RELOCATABLE, "relocatable"), (FLAG_CHECKSUM, "checksum"), (FLAG_SIGNED, "signed")];
+    let mut parts: Vec<String> = names
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, name)| name.to_string())
+        .collect();
+    let known = names.iter().fold(0, |all, (bit, _)| all | bit);
+    if flags & !known != 0 {
+        parts.push(format!("unknown {:#X}", flags & !known));
+    }
+    if parts.is_empty() {
+        "none".into()
+    } else {
+        parts.join(", ")
+    }
+}
+
+fn print_segments(header: &Header, segments: &[SegmentEntry]) {
+    let hex = |value: u64| format!("{value:#X}");
+    let pages: Vec<String> = segments
+        .iter()
+        .map(|segment| {
+            let start = segment.vaddr & !(PAGE_SIZE - 1);
+            let end = segment.vaddr.saturating_add(segment.mem_size).next_multiple_of(PAGE_SIZE);
+            format!("{start:#X}-{end:#X}")
+        })
+        .collect();
+    let width = pages.iter().map(String::len).max().unwrap_or(0).max("pages".len());
+
+    println!();
+    println!(
+        "  #  {:<18}  {:<width$}  {:>8}  {:>8}  {:>8}  {:>8}  flags",
+        "address", "pages", "file", "memory", "stored", "offset"
+    );
+    for (index, segment) in segments.iter().enumerate() {
+        let stored = match segment.stored_size {
+            0 => "-".to_string(),
+            size => hex(size as u64),
+        };
+        println!(
+            "{index:>3}  {:#018X}  {:<width$}  {:>8}  {:>8}  {stored:>8}  {:>8}  {}",
+            segment.vaddr,
+            pages[index],
+            hex(segment.file_size as u64),
+            hex(segment.mem_size),
+            hex(segment.offset as u64),
+            segment_flags_str(segment.flags),
+        );
+    }
+    let missing = header.segment_count as usize - segments.len();
+    if missing > 0 {
+        println!("  ({missing} entries past the end of the file)");
+    }
+}
+
+fn segment_flags_str(flags: u32) -> String {
+    atxf::Segment { vaddr: 0, mem_size: 0, flags, data: Vec::new() }.flags_str()
+}
+
+fn print_symbols(image: &Image) {
+    if image.symbols.is_empty() {
+        return;
+    }
+    println!();
+    for symbol in &image.symbols {
+        println!("  {:#018X}  {:>8}  {}", symbol.vaddr, format!("{:#X}", symbol.size), symbol.name);
+    }
+}
+
+fn print_relocations(image: &Image) {
+    if image.relocations.is_empty() {
+        return;
+    }
+    println!();
+    for address in &image.relocations {
+        println!("  {address:#018X}");
+    }
+}
+
+/// Print how two images differ, returning whether they are the same
+fn compare(a: &str, b: &str) -> Result<bool, Box<dyn Error>> {
+    let load = |path: &str| -> Result<Image, Box<dyn Error>> {
+        let bytes = fs::read(path).map_err(|error| format!("{path}: {error}"))?;
+        Image::parse(&bytes).map_err(|error: FormatError| format!("{path}: {error}").into())
+    };
+    let lines = diff::diff(&load(a)?, &load(b)?);
+    for line in &lines {
+        println!("{line}");
+    }
+    Ok(lines.is_empty())
+}
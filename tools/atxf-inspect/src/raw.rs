@@ -0,0 +1,225 @@
+//! Raw Tables
+//!
+//! The header and segment table as the file holds them, read without the
+//! checks `atxf::Image::parse` makes, so an image the kernel rejects can
+//! still be shown. Fields a shorter, older header lacks read as zero.
+
+use atxf::{FLAG_SIGNED, RELOCATION_ENTRY_SIZE, SIGNATURE_SIZE, SYMBOL_ENTRY_SIZE};
+
+/// The version 1 header, and the shortest version 2 header
+const MIN_HEADER_SIZE: usize = 32;
+/// Segment bytes are aligned this far by elf2atxf
+const DATA_ALIGN: u64 = 16;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Header {
+    pub version: u16,
+    pub header_size: u16,
+    pub entry: u64,
+    pub segment_offset: u32,
+    pub segment_count: u16,
+    pub segment_entry_size: u16,
+    pub flags: u32,
+    pub checksum: u32,
+    pub relocation_offset: u32,
+    pub relocation_count: u32,
+    pub symbol_offset: u32,
+    pub symbol_count: u32,
+    pub names_offset: u32,
+    pub names_size: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SegmentEntry {
+    pub vaddr: u64,
+    pub mem_size: u64,
+    pub offset: u32,
+    pub file_size: u32,
+    pub flags: u32,
+    /// LZ4 block size, or 0 if stored as is
+    pub stored_size: u32,
+}
+
+impl SegmentEntry {
+    /// Bytes the segment takes in the file
+    pub fn stored(&self) -> u32 {
+        if self.stored_size == 0 {
+            self.file_size
+        } else {
+            self.stored_size
+        }
+    }
+}
+
+fn field<const N: usize>(bytes: &[u8], at: usize) -> [u8; N] {
+    let mut out = [0; N];
+    if let Some(slice) = bytes.get(at..at + N) {
+        out.copy_from_slice(slice);
+    }
+    out
+}
+
+/// The header, if the file starts with the ATXF magic
+pub fn read_header(bytes: &[u8]) -> Result<Header, String> {
+    if bytes.len() < MIN_HEADER_SIZE {
+        return Err(format!("{} bytes is too short for a header", bytes.len()));
+    }
+    let magic = u32::from_le_bytes(field(bytes, 0));
+    if magic != atxf::MAGIC {
+        return Err(format!("bad magic {magic:#010X}"));
+    }
+
+    let header_size = u16::from_le_bytes(field(bytes, 6));
+    // Only the fields inside the header count
+    let header = &bytes[..(header_size as usize).clamp(MIN_HEADER_SIZE, bytes.len())];
+    let u32_at = |at| u32::from_le_bytes(field(header, at));
+    Ok(Header {
+        version: u16::from_le_bytes(field(header, 4)),
+        header_size,
+        entry: u64::from_le_bytes(field(header, 8)),
+        segment_offset: u32_at(16),
+        segment_count: u16::from_le_bytes(field(header, 20)),
+        segment_entry_size: u16::from_le_bytes(field(header, 22)),
+        flags: u32_at(24),
+        checksum: u32_at(28),
+        relocation_offset: u32_at(32),
+        relocation_count: u32_at(36),
+        symbol_offset: u32_at(40),
+        symbol_count: u32_at(44),
+        names_offset: u32_at(48),
+        names_size: u32_at(52),
+    })
+}
+
+/// The segment table entries that are inside the file
+pub fn read_segments(bytes: &[u8], header: &Header) -> Vec<SegmentEntry> {
+    let entry_size = header.segment_entry_size as usize;
+    (0..header.segment_count as usize)
+        .map(|index| header.segment_offset as usize + index * entry_size)
+        .take_while(|&at| entry_size >= 32 && at + 32 <= bytes.len())
+        .map(|at| SegmentEntry {
+            vaddr: u64::from_le_bytes(field(bytes, at)),
+            mem_size: u64::from_le_bytes(field(bytes, at + 8)),
+            offset: u32::from_le_bytes(field(bytes, at + 16)),
+            file_size: u32::from_le_bytes(field(bytes, at + 20)),
+            flags: u32::from_le_bytes(field(bytes, at + 24)),
+            stored_size: u32::from_le_bytes(field(bytes, at + 28)),
+        })
+        .collect()
+}
+
+/// Layout problems the kernel may not reject but that point at a broken
+/// writer: parts outside the file or overlapping, misaligned segment bytes
+/// and bytes nothing refers to
+pub fn layout_warnings(bytes: &[u8], header: &Header, segments: &[SegmentEntry]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let len = bytes.len() as u64;
+    let end = if header.flags & FLAG_SIGNED != 0 {
+        len.saturating_sub(SIGNATURE_SIZE as u64)
+    } else {
+        len
+    };
+
+    // (start, end, name) of every part of the file
+    let mut parts: Vec<(u64, u64, String)> = vec![(0, header.header_size as u64, "header".into())];
+    let mut add = |start: u64, size: u64, name: String| {
+        if size > 0 {
+            parts.push((start, start + size, name));
+        }
+    };
+    let table_size = header.segment_count as u64 * header.segment_entry_size as u64;
+    add(header.segment_offset as u64, table_size, "segment table".into());
+    add(
+        header.relocation_offset as u64,
+        header.relocation_count as u64 * RELOCATION_ENTRY_SIZE as u64,
+        "relocation table".into(),
+    );
+    add(
+        header.symbol_offset as u64,
+        header.symbol_count as u64 * SYMBOL_ENTRY_SIZE as u64,
+        "symbol table".into(),
+    );
+    add(header.names_offset as u64, header.names_size as u64, "symbol names".into());
+    for (index, segment) in segments.iter().enumerate() {
+        add(segment.offset as u64, segment.stored() as u64, format!("segment {index}"));
+        if segment.stored() > 0 && !(segment.offset as u64).is_multiple_of(DATA_ALIGN) {
+            warnings.push(format!(
+                "segment {index}: bytes at {:#X} are not {DATA_ALIGN}-byte aligned",
+                segment.offset
+            ));
+        }
+    }
+    if header.flags & FLAG_SIGNED != 0 {
+        parts.push((end, len, "signature".into()));
+    }
+    parts.sort();
+
+    let mut covered = 0;
+    for (index, (start, part_end, name)) in parts.iter().enumerate() {
+        if *part_end > len || (name != "signature" && *part_end > end) {
+            warnings.push(format!("{name} ({start:#X}-{part_end:#X}) runs past the end"));
+        }
+        if let Some((_, _, other)) = parts[..index].iter().find(|(_, e, _)| e > start) {
+            warnings.push(format!("{name} at {start:#X} overlaps {other}"));
+        }
+        if start.saturating_sub(covered) >= DATA_ALIGN {
+            warnings.push(format!("{} unused bytes at {covered:#X}", start - covered));
+        }
+        covered = covered.max(*part_end);
+    }
+    if len.saturating_sub(covered) >= DATA_ALIGN {
+        warnings.push(format!("{} unused bytes at the end", len - covered));
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atxf::{Image, Segment, SEGMENT_EXECUTE, SEGMENT_READ};
+
+    fn image_bytes() -> Vec<u8> {
+        let image = Image {
+            entry: 0x40_0000,
+            segments: vec![Segment {
+                vaddr: 0x40_0000,
+                mem_size: 0x40,
+                flags: SEGMENT_READ | SEGMENT_EXECUTE,
+                data: vec![0xC3; 0x40],
+            }],
+            ..Image::default()
+        };
+        image.to_bytes()
+    }
+
+    #[test]
+    fn reads_what_elf2atxf_writes() {
+        let bytes = image_bytes();
+        let header = read_header(&bytes).unwrap();
+        assert_eq!((header.version, header.header_size as usize), (2, atxf::HEADER_SIZE));
+        assert_eq!(header.entry, 0x40_0000);
+
+        let segments = read_segments(&bytes, &header);
+        assert_eq!(segments.len(), 1);
+        assert_eq!((segments[0].vaddr, segments[0].file_size), (0x40_0000, 0x40));
+        assert_eq!(layout_warnings(&bytes, &header, &segments), Vec::<String>::new());
+    }
+
+    #[test]
+    fn warns_about_broken_layouts() {
+        let mut bytes = image_bytes();
+        bytes.extend_from_slice(&[0; 32]);
+        let header = read_header(&bytes).unwrap();
+        let mut segments = read_segments(&bytes, &header);
+        segments[0].offset = 0;
+
+        let warnings = layout_warnings(&bytes, &header, &segments);
+        assert!(warnings.iter().any(|w| w == "segment 0 at 0x0 overlaps header"));
+        assert!(warnings.iter().any(|w| w.ends_with("unused bytes at the end")));
+
+        assert_eq!(
+            read_header(b"not an image, but long enough here"),
+            Err("bad magic 0x20746F6E".to_string())
+        );
+    }
+}
@@ -0,0 +1,681 @@
+//! ATXF Executables
+//!
+//! The format the kernel loads user programs from (kernel/src/executable.rs).
+//! Version 2, written here, is a header, a table of segments, a table of
+//! relocations, an optional symbol table and the segments' bytes:
+//!
+//! ```text
+//! offset  size  header
+//!      0     4  magic, "FXTA" (0x4154_5846 little-endian)
+//!      4     2  version (2)
+//!      6     2  header size (56)
+//!      8     8  entry point, a virtual address
+//!     16     4  file offset of the segment table
+//!     20     2  number of segments
+//!     22     2  size of a segment table entry (32)
+//!     24     4  flags: FLAG_RELOCATABLE | FLAG_CHECKSUM | FLAG_SIGNED
+//!     28     4  CRC-32 of the whole file, with this field as zero
+//!     32     4  file offset of the relocation table
+//!     36     4  number of relocations
+//!     40     4  file offset of the symbol table
+//!     44     4  number of symbols
+//!     48     4  file offset of the symbol names
+//!     52     4  size of the symbol names
+//!
+//! offset  size  segment table entry
+//!      0     8  virtual address
+//!      8     8  size in memory; bytes past the file size are zeroed
+//!     16     4  file offset of the segment's bytes
+//!     20     4  size in the file
+//!     24     4  SEGMENT_READ | SEGMENT_WRITE | SEGMENT_EXECUTE
+//!     28     4  size stored in the file if LZ4-compressed, or 0
+//!
+//! offset  size  relocation table entry
+//!      0     8  address of a 64-bit word holding a link-time address
+//!
+//! offset  size  symbol table entry, sorted by address
+//!      0     8  address of the function
+//!      8     4  size of the function; 0 if unknown
+//!     12     4  offset of its NUL-terminated name in the symbol names
+//! ```
+//!
+//! All fields are little-endian. The kernel maps each segment with its own
+//! permissions, so segments may not share a page and none may be both
+//! writable and executable.
+//!
+//! A segment's bytes may be stored as an LZ4 block (see `lz4`) that
+//! decompresses to its file size. The checksum covers the whole file, so
+//! the kernel rejects a corrupted image before mapping any of it.
+//!
+//! A relocatable image may be loaded at any page-aligned address. The
+//! kernel then adds the distance it moved the image by to every word the
+//! relocation table lists, so those words must already hold the addresses
+//! they would have at the link-time layout.
+//!
+//! Symbols only serve crash reports: the kernel names the function a
+//! faulting instruction is in. Their addresses are link-time addresses too.
+//!
+//! A signed image ends in an Ed25519 signature (see `ed25519`) of all the
+//! bytes before it, taking the checksum field as zero; the checksum covers
+//! the signature too. Signing is a step of its own, `sign`, so images can
+//! be signed after they are built. A kernel built with a public key checks
+//! the signature before loading, and in secure-boot mode refuses images
+//! without one.
+
+use std::fmt;
+
+pub mod ed25519;
+pub mod lz4;
+
+pub const MAGIC: u32 = 0x4154_5846;
+pub const VERSION: u16 = 2;
+pub const HEADER_SIZE: usize = 56;
+pub const SEGMENT_ENTRY_SIZE: usize = 32;
+pub const RELOCATION_ENTRY_SIZE: usize = 8;
+pub const SYMBOL_ENTRY_SIZE: usize = 16;
+/// Header size before relocations were added; such images have none
+const HEADER_SIZE_V2_0: usize = 32;
+/// Header size before symbols were added
+const HEADER_SIZE_NO_SYMBOLS: usize = 40;
+/// Most segments the kernel accepts
+pub const MAX_SEGMENTS: usize = 16;
+pub const PAGE_SIZE: u64 = 4096;
+
+pub const SEGMENT_READ: u32 = 1 << 0;
+pub const SEGMENT_WRITE: u32 = 1 << 1;
+pub const SEGMENT_EXECUTE: u32 = 1 << 2;
+
+/// The image may be loaded away from its link-time addresses
+pub const FLAG_RELOCATABLE: u32 = 1 << 0;
+/// The header holds a CRC-32 of the file
+pub const FLAG_CHECKSUM: u32 = 1 << 1;
+/// The file ends in an Ed25519 signature
+pub const FLAG_SIGNED: u32 = 1 << 2;
+/// Where the checksum sits in the header
+const CHECKSUM_OFFSET: usize = 28;
+const FLAGS_OFFSET: usize = 24;
+pub const SIGNATURE_SIZE: usize = ed25519::SIGNATURE_SIZE;
+
+/// Alignment of segment bytes in the file
+const DATA_ALIGN: usize = 16;
+
+/// A range of the program's memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub vaddr: u64,
+    /// At least `data.len()`; the rest is zeroed
+    pub mem_size: u64,
+    /// SEGMENT_READ, SEGMENT_WRITE and SEGMENT_EXECUTE
+    pub flags: u32,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    pub fn page_start(&self) -> u64 {
+        self.vaddr & !(PAGE_SIZE - 1)
+    }
+
+    pub fn page_end(&self) -> u64 {
+        (self.vaddr + self.mem_size).next_multiple_of(PAGE_SIZE)
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.flags & SEGMENT_EXECUTE != 0
+    }
+
+    /// Flags as `r-x`, like `readelf` shows them
+    pub fn flags_str(&self) -> String {
+        let flag = |bit, c| if self.flags & bit != 0 { c } else { '-' };
+        [flag(SEGMENT_READ, 'r'), flag(SEGMENT_WRITE, 'w'), flag(SEGMENT_EXECUTE, 'x')]
+            .iter()
+            .collect()
+    }
+}
+
+/// A function, for naming addresses in crash reports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub vaddr: u64,
+    /// 0 if unknown, in which case it runs up to the next symbol
+    pub size: u32,
+    pub name: String,
+}
+
+/// An executable: where it starts and what it maps
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    pub entry: u64,
+    pub segments: Vec<Segment>,
+    /// May be loaded anywhere, with `relocations` adjusted
+    pub relocatable: bool,
+    /// Addresses of the 64-bit words that hold link-time addresses
+    pub relocations: Vec<u64>,
+    /// Sorted by address; empty unless asked for
+    pub symbols: Vec<Symbol>,
+    /// Store segments LZ4-compressed where that makes them smaller
+    pub compressed: bool,
+}
+
+/// Why an image is not one the kernel would load
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatError {
+    Truncated,
+    BadMagic(u32),
+    UnsupportedVersion(u16),
+    TooManySegments(usize),
+    /// Empty, larger in the file than in memory, or with unknown flags
+    InvalidSegment(usize),
+    WritableExecutable(usize),
+    /// The two segments have a page in common
+    SharedPage(usize, usize),
+    /// In the null page or past the user half of the address space
+    OutOfRange(usize),
+    EntryNotInCode(u64),
+    /// Not in a segment's file bytes, or in an image that is not
+    /// relocatable
+    InvalidRelocation(usize),
+    /// Out of order, or with an empty name or one containing NUL
+    InvalidSymbol(usize),
+    /// The stored and computed CRC-32
+    BadChecksum(u32, u32),
+    /// Its LZ4 block does not decompress to its file size
+    BadCompression(usize),
+    Unsigned,
+    /// The signature is not the key's signature of the image
+    BadSignature,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "image is truncated"),
+            Self::BadMagic(magic) => write!(f, "bad magic {magic:#010X}"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported version {version}"),
+            Self::TooManySegments(count) => {
+                write!(f, "{count} segments; between 1 and {MAX_SEGMENTS} are allowed")
+            }
+            Self::InvalidSegment(index) => write!(f, "segment {index} is invalid"),
+            Self::WritableExecutable(index) => {
+                write!(f, "segment {index} is both writable and executable")
+            }
+            Self::SharedPage(a, b) => write!(f, "segments {a} and {b} share a page"),
+            Self::OutOfRange(index) => write!(f, "segment {index} is outside user memory"),
+            Self::EntryNotInCode(entry) => {
+                write!(f, "entry point {entry:#X} is not in an executable segment")
+            }
+            Self::InvalidRelocation(index) => write!(f, "relocation {index} is invalid"),
+            Self::InvalidSymbol(index) => write!(f, "symbol {index} is invalid"),
+            Self::BadChecksum(stored, computed) => {
+                write!(f, "checksum is {stored:#010X} but the contents give {computed:#010X}")
+            }
+            Self::BadCompression(index) => write!(f, "segment {index} does not decompress"),
+            Self::Unsigned => write!(f, "image is not signed"),
+            Self::BadSignature => write!(f, "signature does not match the image"),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// Highest user address, as the kernel's USER_CANONICAL_MAX
+const USER_MAX: u64 = 0x0000_7FFF_FFFF_FFFF;
+
+impl Image {
+    /// Check the image against the rules the kernel loads by
+    pub fn validate(&self) -> Result<(), FormatError> {
+        let count = self.segments.len();
+        if count == 0 || count > MAX_SEGMENTS {
+            return Err(FormatError::TooManySegments(count));
+        }
+
+        for (index, segment) in self.segments.iter().enumerate() {
+            if segment.mem_size == 0
+                || segment.data.len() as u64 > segment.mem_size
+                || segment.data.len() > u32::MAX as usize
+                || segment.flags & !(SEGMENT_READ | SEGMENT_WRITE | SEGMENT_EXECUTE) != 0
+            {
+                return Err(FormatError::InvalidSegment(index));
+            }
+            if segment.flags & SEGMENT_WRITE != 0 && segment.is_executable() {
+                return Err(FormatError::WritableExecutable(index));
+            }
+            // A relocatable image is placed by the kernel, so only its size
+            // has to fit
+            let lowest = if self.relocatable { 0 } else { PAGE_SIZE };
+            match segment.vaddr.checked_add(segment.mem_size) {
+                Some(end) if segment.vaddr >= lowest && end <= USER_MAX => {}
+                _ => return Err(FormatError::OutOfRange(index)),
+            }
+            for (other_index, other) in self.segments[..index].iter().enumerate() {
+                if segment.page_start() < other.page_end()
+                    && other.page_start() < segment.page_end()
+                {
+                    return Err(FormatError::SharedPage(other_index, index));
+                }
+            }
+        }
+
+        let in_code = self.segments.iter().any(|segment| {
+            segment.is_executable()
+                && self.entry >= segment.vaddr
+                && self.entry < segment.vaddr + segment.data.len() as u64
+        });
+        if !in_code {
+            return Err(FormatError::EntryNotInCode(self.entry));
+        }
+
+        for (index, &address) in self.relocations.iter().enumerate() {
+            let in_file = self.segments.iter().any(|segment| {
+                address >= segment.vaddr
+                    && address + 8 <= segment.vaddr + segment.data.len() as u64
+            });
+            if !self.relocatable || !in_file {
+                return Err(FormatError::InvalidRelocation(index));
+            }
+        }
+
+        for (index, symbol) in self.symbols.iter().enumerate() {
+            let sorted = index == 0 || self.symbols[index - 1].vaddr <= symbol.vaddr;
+            if !sorted || symbol.name.is_empty() || symbol.name.contains('\0') {
+                return Err(FormatError::InvalidSymbol(index));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The 64-bit word at `address`, if a segment's file bytes hold it
+    pub fn word_at(&mut self, address: u64) -> Option<&mut [u8; 8]> {
+        self.segments.iter_mut().find_map(|segment| {
+            let offset = address.checked_sub(segment.vaddr)? as usize;
+            segment.data.get_mut(offset..offset + 8)?.try_into().ok()
+        })
+    }
+
+    /// The image as a file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let table_size = self.segments.len() * SEGMENT_ENTRY_SIZE;
+        let relocations_offset = HEADER_SIZE + table_size;
+        let relocations_size = self.relocations.len() * RELOCATION_ENTRY_SIZE;
+        let symbols_offset = relocations_offset + relocations_size;
+        let symbols_size = self.symbols.len() * SYMBOL_ENTRY_SIZE;
+        let names_offset = symbols_offset + symbols_size;
+        let names_size: usize = self.symbols.iter().map(|symbol| symbol.name.len() + 1).sum();
+        let mut out = Vec::with_capacity(names_offset + names_size);
+        let mut flags = FLAG_CHECKSUM;
+        if self.relocatable {
+            flags |= FLAG_RELOCATABLE;
+        }
+
+        // What each segment stores: its bytes, or an LZ4 block if smaller
+        let stored: Vec<Option<Vec<u8>>> = self
+            .segments
+            .iter()
+            .map(|segment| {
+                let compressed = lz4::compress(&segment.data);
+                (self.compressed && compressed.len() < segment.data.len()).then_some(compressed)
+            })
+            .collect();
+
+        out.extend_from_slice(&MAGIC.to_le_bytes());
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        out.extend_from_slice(&self.entry.to_le_bytes());
+        out.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        out.extend_from_slice(&(self.segments.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(SEGMENT_ENTRY_SIZE as u16).to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&(relocations_offset as u32).to_le_bytes());
+        out.extend_from_slice(&(self.relocations.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(symbols_offset as u32).to_le_bytes());
+        out.extend_from_slice(&(self.symbols.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(names_offset as u32).to_le_bytes());
+        out.extend_from_slice(&(names_size as u32).to_le_bytes());
+
+        let mut offset = (names_offset + names_size).next_multiple_of(DATA_ALIGN);
+        for (segment, stored) in self.segments.iter().zip(&stored) {
+            let stored_size = stored.as_ref().map_or(0, Vec::len);
+            out.extend_from_slice(&segment.vaddr.to_le_bytes());
+            out.extend_from_slice(&segment.mem_size.to_le_bytes());
+            out.extend_from_slice(&(offset as u32).to_le_bytes());
+            out.extend_from_slice(&(segment.data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&segment.flags.to_le_bytes());
+            out.extend_from_slice(&(stored_size as u32).to_le_bytes());
+            let size = if stored_size > 0 { stored_size } else { segment.data.len() };
+            offset = (offset + size).next_multiple_of(DATA_ALIGN);
+        }
+
+        for address in &self.relocations {
+            out.extend_from_slice(&address.to_le_bytes());
+        }
+
+        let mut name_offset = 0;
+        for symbol in &self.symbols {
+            out.extend_from_slice(&symbol.vaddr.to_le_bytes());
+            out.extend_from_slice(&symbol.size.to_le_bytes());
+            out.extend_from_slice(&(name_offset as u32).to_le_bytes());
+            name_offset += symbol.name.len() + 1;
+        }
+        for symbol in &self.symbols {
+            out.extend_from_slice(symbol.name.as_bytes());
+            out.push(0);
+        }
+
+        for (segment, stored) in self.segments.iter().zip(&stored) {
+            out.resize(out.len().next_multiple_of(DATA_ALIGN), 0);
+            out.extend_from_slice(stored.as_deref().unwrap_or(&segment.data));
+        }
+
+        let checksum = checksum(&out);
+        out[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&checksum.to_le_bytes());
+        out
+    }
+
+    /// Read a version 2 image
+    pub fn parse(bytes: &[u8]) -> Result<Self, FormatError> {
+        let magic = read_u32(bytes, 0)?;
+        if magic != MAGIC {
+            return Err(FormatError::BadMagic(magic));
+        }
+        let version = read_u16(bytes, 4)?;
+        if version != VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+
+        let header_size = read_u16(bytes, 6)? as usize;
+        let entry = read_u64(bytes, 8)?;
+        let table = read_u32(bytes, 16)? as usize;
+        let count = read_u16(bytes, 20)? as usize;
+        let entry_size = read_u16(bytes, 22)? as usize;
+        let flags = read_u32(bytes, 24)?;
+        if header_size < HEADER_SIZE_V2_0 || entry_size < SEGMENT_ENTRY_SIZE {
+            return Err(FormatError::Truncated);
+        }
+        if flags & FLAG_CHECKSUM != 0 {
+            let stored = read_u32(bytes, CHECKSUM_OFFSET)?;
+            let computed = checksum(bytes);
+            if stored != computed {
+                return Err(FormatError::BadChecksum(stored, computed));
+            }
+        }
+        // Nothing may point into the signature
+        let bytes = match signature(bytes)? {
+            Some((body, _)) => body,
+            None => bytes,
+        };
+        let (relocations_offset, relocation_count) = if header_size >= HEADER_SIZE_NO_SYMBOLS {
+            (read_u32(bytes, 32)? as usize, read_u32(bytes, 36)? as usize)
+        } else {
+            (0, 0)
+        };
+        if count > MAX_SEGMENTS {
+            return Err(FormatError::TooManySegments(count));
+        }
+
+        let mut segments = Vec::with_capacity(count);
+        let mut compressed = false;
+        for index in 0..count {
+            let at = table + index * entry_size;
+            let offset = read_u32(bytes, at + 16)? as usize;
+            let file_size = read_u32(bytes, at + 20)? as usize;
+            let stored_size = read_u32(bytes, at + 28)? as usize;
+            let data = if stored_size == 0 {
+                bytes.get(offset..offset + file_size).ok_or(FormatError::Truncated)?.to_vec()
+            } else {
+                compressed = true;
+                let stored = bytes.get(offset..offset + stored_size).ok_or(FormatError::Truncated)?;
+                let mut data = vec![0; file_size];
+                lz4::decompress(stored, &mut data).ok_or(FormatError::BadCompression(index))?;
+                data
+            };
+            segments.push(Segment {
+                vaddr: read_u64(bytes, at)?,
+                mem_size: read_u64(bytes, at + 8)?,
+                flags: read_u32(bytes, at + 24)?,
+                data,
+            });
+        }
+
+        let relocations = (0..relocation_count)
+            .map(|index| read_u64(bytes, relocations_offset + index * RELOCATION_ENTRY_SIZE))
+            .collect::<Result<_, _>>()?;
+
+        let symbols = if header_size >= HEADER_SIZE {
+            parse_symbols(bytes)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            entry,
+            segments,
+            relocatable: flags & FLAG_RELOCATABLE != 0,
+            relocations,
+            symbols,
+            compressed,
+        })
+    }
+}
+
+/// The image with its signature replaced by one made with `seed`
+pub fn sign(image: &[u8], seed: &[u8; ed25519::SEED_SIZE]) -> Result<Vec<u8>, FormatError> {
+    let mut out = match signature(image)? {
+        Some((body, _)) => body.to_vec(),
+        None => image.to_vec(),
+    };
+    let flags = read_u32(&out, FLAGS_OFFSET)? | FLAG_SIGNED | FLAG_CHECKSUM;
+    out[FLAGS_OFFSET..FLAGS_OFFSET + 4].copy_from_slice(&flags.to_le_bytes());
+    out[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].fill(0);
+
+    let signature = ed25519::sign(seed, &out);
+    out.extend_from_slice(&signature);
+    let checksum = checksum(&out);
+    out[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4].copy_from_slice(&checksum.to_le_bytes());
+    Ok(out)
+}
+
+/// Check that `public`'s key signed the image, as the kernel does
+pub fn verify(image: &[u8], public: &[u8; ed25519::PUBLIC_KEY_SIZE]) -> Result<(), FormatError> {
+    let (body, signature) = signature(image)?.ok_or(FormatError::Unsigned)?;
+    let message = [&body[..CHECKSUM_OFFSET], &[0; 4], &body[CHECKSUM_OFFSET + 4..]];
+    if ed25519::verify(public, &message, signature) {
+        Ok(())
+    } else {
+        Err(FormatError::BadSignature)
+    }
+}
+
+/// An image's signed bytes and its signature
+type Signed<'a> = (&'a [u8], &'a [u8; SIGNATURE_SIZE]);
+
+/// The signed bytes and the signature, if the image is signed
+fn signature(image: &[u8]) -> Result<Option<Signed<'_>>, FormatError> {
+    if read_u32(image, FLAGS_OFFSET)? & FLAG_SIGNED == 0 {
+        return Ok(None);
+    }
+    let split = image
+        .len()
+        .checked_sub(SIGNATURE_SIZE)
+        .filter(|&split| split >= HEADER_SIZE_V2_0)
+        .ok_or(FormatError::Truncated)?;
+    let (body, signature) = image.split_at(split);
+    Ok(Some((body, signature.try_into().unwrap())))
+}
+
+/// CRC-32 (IEEE) of an image, with the checksum field taken as zero
+pub fn checksum(image: &[u8]) -> u32 {
+    let field = CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4;
+    let bytes = image
+        .iter()
+        .enumerate()
+        .map(|(i, &byte)| if field.contains(&i) { 0 } else { byte });
+
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn parse_symbols(bytes: &[u8]) -> Result<Vec<Symbol>, FormatError> {
+    let table = read_u32(bytes, 40)? as usize;
+    let count = read_u32(bytes, 44)? as usize;
+    let names_offset = read_u32(bytes, 48)? as usize;
+    let names_size = read_u32(bytes, 52)? as usize;
+    let names = bytes
+        .get(names_offset..names_offset + names_size)
+        .ok_or(FormatError::Truncated)?;
+
+    (0..count)
+        .map(|index| {
+            let at = table + index * SYMBOL_ENTRY_SIZE;
+            let name = names
+                .get(read_u32(bytes, at + 12)? as usize..)
+                .and_then(|name| name.split(|&byte| byte == 0).next())
+                .ok_or(FormatError::InvalidSymbol(index))?;
+            Ok(Symbol {
+                vaddr: read_u64(bytes, at)?,
+                size: read_u32(bytes, at + 8)?,
+                name: String::from_utf8_lossy(name).into_owned(),
+            })
+        })
+        .collect()
+}
+
+fn read_bytes<const N: usize>(bytes: &[u8], offset: usize) -> Result<[u8; N], FormatError> {
+    bytes
+        .get(offset..offset + N)
+        .and_then(|field| field.try_into().ok())
+        .ok_or(FormatError::Truncated)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, FormatError> {
+    read_bytes(bytes, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, FormatError> {
+    read_bytes(bytes, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, FormatError> {
+    read_bytes(bytes, offset).map(u64::from_le_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image() -> Image {
+        Image {
+            entry: 0x40_0010,
+            segments: vec![
+                Segment {
+                    vaddr: 0x40_0000,
+                    mem_size: 0x20,
+                    flags: SEGMENT_READ | SEGMENT_EXECUTE,
+                    data: vec![0x90; 0x20],
+                },
+                Segment {
+                    vaddr: 0x40_1000,
+                    mem_size: 0x3000,
+                    flags: SEGMENT_READ | SEGMENT_WRITE,
+                    data: vec![1, 2, 3],
+                },
+            ],
+            ..Image::default()
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let image = image();
+        assert_eq!(image.validate(), Ok(()));
+        assert_eq!(Image::parse(&image.to_bytes()), Ok(image));
+    }
+
+    #[test]
+    fn rejects_what_the_kernel_would() {
+        let mut shared = image();
+        shared.segments[1].vaddr = 0x40_0800;
+        assert_eq!(shared.validate(), Err(FormatError::SharedPage(0, 1)));
+
+        let mut wx = image();
+        wx.segments[1].flags |= SEGMENT_EXECUTE;
+        assert_eq!(wx.validate(), Err(FormatError::WritableExecutable(1)));
+
+        let mut entry = image();
+        entry.entry = 0x40_1000;
+        assert_eq!(entry.validate(), Err(FormatError::EntryNotInCode(0x40_1000)));
+
+        let mut relocation = image();
+        relocation.relocations.push(0x40_1000);
+        assert_eq!(relocation.validate(), Err(FormatError::InvalidRelocation(0)));
+        relocation.relocatable = true;
+        assert_eq!(relocation.validate(), Err(FormatError::InvalidRelocation(0)));
+    }
+
+    #[test]
+    fn round_trips_relocations() {
+        let mut image = image();
+        image.segments[1].data = vec![0; 16];
+        image.relocatable = true;
+        image.relocations = vec![0x40_1000, 0x40_1008];
+        assert_eq!(image.validate(), Ok(()));
+        assert_eq!(Image::parse(&image.to_bytes()), Ok(image));
+    }
+
+    #[test]
+    fn round_trips_symbols() {
+        let symbol = |vaddr, name: &str| Symbol {
+            vaddr,
+            size: 8,
+            name: name.to_string(),
+        };
+        let mut image = image();
+        image.symbols = vec![symbol(0x40_0000, "_start"), symbol(0x40_0010, "main")];
+        assert_eq!(image.validate(), Ok(()));
+        assert_eq!(Image::parse(&image.to_bytes()), Ok(image.clone()));
+
+        image.symbols.swap(0, 1);
+        assert_eq!(image.validate(), Err(FormatError::InvalidSymbol(1)));
+    }
+
+    #[test]
+    fn compresses_and_checks_the_checksum() {
+        let mut image = image();
+        image.compressed = true;
+        let bytes = image.to_bytes();
+        assert!(bytes.len() < self::image().to_bytes().len());
+        assert_eq!(Image::parse(&bytes), Ok(image));
+
+        let mut corrupt = bytes.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(matches!(Image::parse(&corrupt), Err(FormatError::BadChecksum(..))));
+
+        assert_eq!(checksum(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn signs_and_verifies() {
+        let image = image();
+        let unsigned = image.to_bytes();
+        let public = ed25519::public_key(&[7; 32]);
+        assert_eq!(verify(&unsigned, &public), Err(FormatError::Unsigned));
+
+        let signed = sign(&unsigned, &[7; 32]).unwrap();
+        assert_eq!(signed.len(), unsigned.len() + SIGNATURE_SIZE);
+        assert_eq!(Image::parse(&signed), Ok(image));
+        assert_eq!(verify(&signed, &public), Ok(()));
+        assert_eq!(sign(&signed, &[7; 32]), Ok(signed.clone()));
+
+        let other = sign(&unsigned, &[8; 32]).unwrap();
+        assert_eq!(verify(&other, &public), Err(FormatError::BadSignature));
+        let mut tampered = signed;
+        tampered[HEADER_SIZE] ^= 1;
+        assert_eq!(verify(&tampered, &public), Err(FormatError::BadSignature));
+    }
+}
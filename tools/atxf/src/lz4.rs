@@ -0,0 +1,161 @@
+//! LZ4 Blocks
+//!
+//! The LZ4 block format, without the frame around it: the segment table
+//! records both sizes. Compression is greedy with a single hash table,
+//! which is fast and good enough for code and data; anything that reads LZ4
+//! blocks can read the output.
+
+/// Shortest match the format can express
+const MIN_MATCH: usize = 4;
+/// The last match must start this far before the end of the input
+const MATCH_LIMIT: usize = 12;
+/// The last bytes of the input are always literals
+const LAST_LITERALS: usize = 5;
+const MAX_OFFSET: usize = 0xFFFF;
+const HASH_BITS: u32 = 16;
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2);
+    // Position + 1 of the last occurrence of each hashed 4-byte sequence
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    while pos + MATCH_LIMIT < input.len() {
+        let sequence = u32::from_le_bytes(input[pos..pos + 4].try_into().unwrap());
+        let hash = (sequence.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize;
+        let candidate = table[hash];
+        table[hash] = pos + 1;
+
+        let start = match candidate.checked_sub(1) {
+            Some(start)
+                if pos - start <= MAX_OFFSET && input[start..start + 4] == input[pos..pos + 4] =>
+            {
+                start
+            }
+            _ => {
+                pos += 1;
+                continue;
+            }
+        };
+
+        let max_len = input.len() - LAST_LITERALS - pos;
+        let mut len = MIN_MATCH;
+        while len < max_len && input[start + len] == input[pos + len] {
+            len += 1;
+        }
+
+        write_sequence(&mut out, &input[anchor..pos], Some((pos - start, len)));
+        pos += len;
+        anchor = pos;
+    }
+
+    write_sequence(&mut out, &input[anchor..], None);
+    out
+}
+
+/// Literals, then a match `(offset, length)` unless this is the last
+/// sequence
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], copy: Option<(usize, usize)>) {
+    let match_len = copy.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push((literals.len().min(15) as u8) << 4 | match_len.min(15) as u8);
+    if literals.len() >= 15 {
+        write_length(out, literals.len() - 15);
+    }
+    out.extend_from_slice(literals);
+
+    if let Some((offset, _)) = copy {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_len >= 15 {
+            write_length(out, match_len - 15);
+        }
+    }
+}
+
+fn write_length(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+/// Decompress `input` into `output`, which it must fill exactly
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Option<()> {
+    let mut i = 0;
+    let mut o = 0;
+
+    loop {
+        let token = *input.get(i)?;
+        i += 1;
+
+        let mut literals = (token >> 4) as usize;
+        if literals == 15 {
+            literals += read_length(input, &mut i)?;
+        }
+        output
+            .get_mut(o..o + literals)?
+            .copy_from_slice(input.get(i..i + literals)?);
+        i += literals;
+        o += literals;
+
+        if i == input.len() {
+            return (o == output.len()).then_some(());
+        }
+
+        let offset = u16::from_le_bytes(input.get(i..i + 2)?.try_into().ok()?) as usize;
+        i += 2;
+        let mut len = (token & 15) as usize + MIN_MATCH;
+        if len == 15 + MIN_MATCH {
+            len += read_length(input, &mut i)?;
+        }
+        if offset == 0 || offset > o || o + len > output.len() {
+            return None;
+        }
+
+        // The source may overlap what is being written, repeating it
+        for k in o..o + len {
+            output[k] = output[k - offset];
+        }
+        o += len;
+    }
+}
+
+fn read_length(input: &[u8], i: &mut usize) -> Option<usize> {
+    let mut len = 0;
+    loop {
+        let byte = *input.get(*i)?;
+        *i += 1;
+        len += byte as usize;
+        if byte != 255 {
+            return Some(len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let mut input: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+        input.extend_from_slice(&[0; 3000]);
+        input.extend_from_slice(b"tail bytes that do not repeat");
+
+        for input in [&input[..], b"", b"short", &[7; 40]] {
+            let compressed = compress(input);
+            let mut output = vec![0; input.len()];
+            assert_eq!(decompress(&compressed, &mut output), Some(()));
+            assert_eq!(output, input);
+        }
+        assert!(compress(&input).len() < input.len() / 4);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let compressed = compress(&[1; 100]);
+        assert_eq!(decompress(&compressed, &mut [0; 99]), None);
+        assert_eq!(decompress(&compressed[..compressed.len() - 1], &mut [0; 100]), None);
+    }
+}
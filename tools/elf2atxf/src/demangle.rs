@@ -0,0 +1,101 @@
+//! Symbol Demangling
+//!
+//! Crash reports are read by people, so Rust's legacy mangling
+//! (`_ZN4core9panicking9panic_fmt17h0123456789abcdefE`) is turned back
+//! into a path (`core::panicking::panic_fmt`). The hash is dropped. Names in
+//! any other scheme are kept as they are.
+
+/// `name` as a path, or as it is if it is not a legacy Rust name
+pub fn demangle(name: &str) -> String {
+    legacy(name).unwrap_or_else(|| name.to_string())
+}
+
+fn legacy(name: &str) -> Option<String> {
+    let mut rest = name.strip_prefix("_ZN")?;
+    let mut parts = Vec::new();
+
+    while !rest.starts_with('E') {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let len: usize = rest[..digits].parse().ok()?;
+        let ident = rest.get(digits..digits + len)?;
+        rest = &rest[digits + len..];
+        parts.push(ident);
+    }
+    if rest != "E" || parts.is_empty() {
+        return None;
+    }
+
+    if parts.len() > 1 && is_hash(parts[parts.len() - 1]) {
+        parts.pop();
+    }
+    let parts: Option<Vec<_>> = parts.into_iter().map(unescape).collect();
+    Some(parts?.join("::"))
+}
+
+/// `h` and 16 hex digits, appended to make names unique
+fn is_hash(part: &str) -> bool {
+    part.len() == 17
+        && part.starts_with('h')
+        && part[1..].bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+fn unescape(part: &str) -> Option<String> {
+    let mut part = part;
+    // Identifiers that would start with `$` get a leading `_`
+    if part.starts_with("_$") {
+        part = &part[1..];
+    }
+
+    let mut out = String::with_capacity(part.len());
+    while !part.is_empty() {
+        if let Some(rest) = part.strip_prefix("..") {
+            out.push_str("::");
+            part = rest;
+        } else if let Some(rest) = part.strip_prefix('$') {
+            let end = rest.find('$')?;
+            out.push(escape(&rest[..end])?);
+            part = &rest[end + 1..];
+        } else {
+            let c = part.chars().next()?;
+            out.push(c);
+            part = &part[c.len_utf8()..];
+        }
+    }
+    Some(out)
+}
+
+fn escape(code: &str) -> Option<char> {
+    Some(match code {
+        "SP" => '@',
+        "BP" => '*',
+        "RF" => '&',
+        "LT" => '<',
+        "GT" => '>',
+        "LP" => '(',
+        "RP" => ')',
+        "C" => ',',
+        _ => char::from_u32(u32::from_str_radix(code.strip_prefix('u')?, 16).ok()?)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demangles_legacy_rust_names() {
+        assert_eq!(
+            demangle("_ZN4core9panicking9panic_fmt17h0123456789abcdefE"),
+            "core::panicking::panic_fmt"
+        );
+        assert_eq!(
+            demangle(concat!(
+                "_ZN60_$LT$alloc..string..String$u20$as$u20$core..fmt..Display$GT$",
+                "3fmt17h0123456789abcdefE"
+            )),
+            "<alloc::string::String as core::fmt::Display>::fmt"
+        );
+        assert_eq!(demangle("_start"), "_start");
+        assert_eq!(demangle("_ZN3foo3barEv"), "_ZN3foo3barEv");
+    }
+}
@@ -0,0 +1,356 @@
+//! ELF Reading
+//!
+//! Just enough of ELF64 to convert a statically linked x86_64 executable:
+//! the header, the loadable program headers, the section headers, which
+//! say where read-only data sits inside a segment, the relocations a
+//! position-independent executable (`-static-pie`) carries in its dynamic
+//! section, and the function symbols of the symbol table.
+
+use std::fmt;
+
+const ELF_MAGIC: &[u8; 4] = b"\x7FELF";
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+const EM_X86_64: u16 = 62;
+
+pub const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+pub const PF_X: u32 = 1 << 0;
+pub const PF_W: u32 = 1 << 1;
+pub const PF_R: u32 = 1 << 2;
+
+pub const SHF_ALLOC: u64 = 1 << 1;
+pub const SHF_EXECINSTR: u64 = 1 << 2;
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const SYMBOL_SIZE: usize = 24;
+const STT_FUNC: u8 = 2;
+const SHN_UNDEF: u16 = 0;
+
+const DT_NULL: i64 = 0;
+const DT_NEEDED: i64 = 1;
+const DT_PLTRELSZ: i64 = 2;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_RELAENT: i64 = 9;
+const DT_REL: i64 = 17;
+const DT_JMPREL: i64 = 23;
+const DT_RELRSZ: i64 = 35;
+const DT_RELR: i64 = 36;
+
+const R_X86_64_NONE: u32 = 0;
+const R_X86_64_RELATIVE: u32 = 8;
+const RELA_SIZE: u64 = 24;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElfError {
+    NotElf,
+    Truncated,
+    /// Not 64-bit little-endian x86_64
+    WrongMachine,
+    /// Not an executable
+    WrongType(u16),
+    /// Needs shared libraries
+    DynamicallyLinked,
+    /// Relocations without addends, which x86_64 linkers do not emit
+    RelTable,
+    /// A relocation other than R_X86_64_RELATIVE, at the given address
+    UnsupportedRelocation(u32, u64),
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotElf => write!(f, "not an ELF file"),
+            Self::Truncated => write!(f, "ELF file is truncated"),
+            Self::WrongMachine => write!(f, "not a 64-bit little-endian x86_64 ELF file"),
+            Self::WrongType(other) => write!(f, "ELF type {other} is not an executable"),
+            Self::DynamicallyLinked => {
+                write!(f, "dynamically linked executables are not supported; link with -static-pie")
+            }
+            Self::RelTable => write!(f, "REL relocation tables are not supported"),
+            Self::UnsupportedRelocation(kind, offset) => {
+                write!(f, "unsupported relocation type {kind} at {offset:#X}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ElfError {}
+
+/// A program header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramHeader {
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub file_size: u64,
+    pub mem_size: u64,
+}
+
+/// A section header; names are not read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectionHeader {
+    pub kind: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    /// For a symbol table, the index of its string table
+    pub link: u32,
+}
+
+impl SectionHeader {
+    /// Whether the section takes up memory in the running program
+    pub fn is_loaded(&self) -> bool {
+        self.flags & SHF_ALLOC != 0 && self.size > 0
+    }
+
+    pub fn is_code(&self) -> bool {
+        self.flags & SHF_EXECINSTR != 0
+    }
+
+    pub fn has_bits(&self) -> bool {
+        self.kind != SHT_NOBITS
+    }
+}
+
+/// A defined function in the symbol table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Function<'a> {
+    pub vaddr: u64,
+    pub size: u64,
+    /// As the linker saw it, still mangled
+    pub name: &'a [u8],
+}
+
+/// A word the loader adds the load bias to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relocation {
+    pub offset: u64,
+    /// The link-time value, for RELA entries; RELR words already hold it
+    pub addend: Option<u64>,
+}
+
+pub struct Elf<'a> {
+    bytes: &'a [u8],
+    /// ET_DYN: linked at address 0 to be loaded anywhere
+    pub position_independent: bool,
+    pub entry: u64,
+    pub program_headers: Vec<ProgramHeader>,
+    pub section_headers: Vec<SectionHeader>,
+}
+
+impl<'a> Elf<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, ElfError> {
+        if bytes.get(..4) != Some(ELF_MAGIC) {
+            return Err(ElfError::NotElf);
+        }
+        if bytes.get(4) != Some(&ELFCLASS64)
+            || bytes.get(5) != Some(&ELFDATA2LSB)
+            || read_u16(bytes, 18)? != EM_X86_64
+        {
+            return Err(ElfError::WrongMachine);
+        }
+        let position_independent = match read_u16(bytes, 16)? {
+            ET_EXEC => false,
+            ET_DYN => true,
+            other => return Err(ElfError::WrongType(other)),
+        };
+
+        let entry = read_u64(bytes, 24)?;
+        let ph_offset = read_u64(bytes, 32)? as usize;
+        let sh_offset = read_u64(bytes, 40)? as usize;
+        let ph_size = read_u16(bytes, 54)? as usize;
+        let ph_count = read_u16(bytes, 56)? as usize;
+        let sh_size = read_u16(bytes, 58)? as usize;
+        let sh_count = read_u16(bytes, 60)? as usize;
+
+        let program_headers = (0..ph_count)
+            .map(|index| {
+                let at = ph_offset + index * ph_size;
+                Ok(ProgramHeader {
+                    kind: read_u32(bytes, at)?,
+                    flags: read_u32(bytes, at + 4)?,
+                    offset: read_u64(bytes, at + 8)?,
+                    vaddr: read_u64(bytes, at + 16)?,
+                    file_size: read_u64(bytes, at + 32)?,
+                    mem_size: read_u64(bytes, at + 40)?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        // Stripped files may have no section headers; they are only hints
+        let section_headers = (0..if sh_offset == 0 { 0 } else { sh_count })
+            .map(|index| {
+                let at = sh_offset + index * sh_size;
+                Ok(SectionHeader {
+                    kind: read_u32(bytes, at + 4)?,
+                    flags: read_u64(bytes, at + 8)?,
+                    addr: read_u64(bytes, at + 16)?,
+                    offset: read_u64(bytes, at + 24)?,
+                    size: read_u64(bytes, at + 32)?,
+                    link: read_u32(bytes, at + 40)?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            bytes,
+            position_independent,
+            entry,
+            program_headers,
+            section_headers,
+        })
+    }
+
+    /// The program headers that are mapped into memory
+    pub fn load_segments(&self) -> impl Iterator<Item = &ProgramHeader> {
+        self.program_headers
+            .iter()
+            .filter(|header| header.kind == PT_LOAD && header.mem_size > 0)
+    }
+
+    /// The file bytes of a segment
+    pub fn segment_data(&self, header: &ProgramHeader) -> Result<&'a [u8], ElfError> {
+        let start = header.offset as usize;
+        let end = start.checked_add(header.file_size as usize).ok_or(ElfError::Truncated)?;
+        self.bytes.get(start..end).ok_or(ElfError::Truncated)
+    }
+
+    /// The relocations in the dynamic section; an executable without one
+    /// has none
+    pub fn relocations(&self) -> Result<Vec<Relocation>, ElfError> {
+        let Some(dynamic) = self.program_headers.iter().find(|h| h.kind == PT_DYNAMIC) else {
+            return Ok(Vec::new());
+        };
+
+        let mut tags = [0u64; DT_RELR as usize + 1];
+        for entry in self.segment_data(dynamic)?.chunks_exact(16) {
+            let tag = i64::from_le_bytes(entry[..8].try_into().unwrap());
+            let value = u64::from_le_bytes(entry[8..].try_into().unwrap());
+            match tag {
+                DT_NULL => break,
+                DT_NEEDED => return Err(ElfError::DynamicallyLinked),
+                DT_REL => return Err(ElfError::RelTable),
+                DT_PLTRELSZ | DT_RELA | DT_RELASZ | DT_RELAENT | DT_JMPREL | DT_RELRSZ
+                | DT_RELR => tags[tag as usize] = value,
+                _ => {}
+            }
+        }
+        let tag = |tag: i64| tags[tag as usize];
+
+        let mut relocations = Vec::new();
+        let rela_size = if tag(DT_RELAENT) == 0 { RELA_SIZE } else { tag(DT_RELAENT) };
+        for (table, size) in [(DT_RELA, DT_RELASZ), (DT_JMPREL, DT_PLTRELSZ)] {
+            let table = self.mapped_data(tag(table), tag(size))?;
+            for entry in table.chunks_exact(rela_size as usize) {
+                let offset = read_u64(entry, 0)?;
+                let info = read_u64(entry, 8)?;
+                match info as u32 {
+                    R_X86_64_NONE => {}
+                    R_X86_64_RELATIVE => relocations.push(Relocation {
+                        offset,
+                        addend: Some(read_u64(entry, 16)?),
+                    }),
+                    kind => return Err(ElfError::UnsupportedRelocation(kind, offset)),
+                }
+            }
+        }
+
+        // RELR: an address, then bitmaps of which of the following words
+        // are relocated too
+        let mut next = 0;
+        for word in self.mapped_data(tag(DT_RELR), tag(DT_RELRSZ))?.chunks_exact(8) {
+            let word = read_u64(word, 0)?;
+            if word & 1 == 0 {
+                relocations.push(Relocation { offset: word, addend: None });
+                next = word + 8;
+            } else {
+                for bit in 0..63 {
+                    if word >> (bit + 1) & 1 != 0 {
+                        relocations.push(Relocation { offset: next + bit * 8, addend: None });
+                    }
+                }
+                next += 63 * 8;
+            }
+        }
+
+        Ok(relocations)
+    }
+
+    /// The functions of the symbol table; none if the file is stripped
+    pub fn functions(&self) -> Result<Vec<Function<'a>>, ElfError> {
+        let Some(symtab) = self.section_headers.iter().find(|s| s.kind == SHT_SYMTAB) else {
+            return Ok(Vec::new());
+        };
+        let strtab = self
+            .section_headers
+            .get(symtab.link as usize)
+            .ok_or(ElfError::Truncated)?;
+        let symbols = self.section_data(symtab)?;
+        let names = self.section_data(strtab)?;
+
+        let mut functions = Vec::new();
+        for symbol in symbols.chunks_exact(SYMBOL_SIZE) {
+            let info = symbol[4];
+            let section = read_u16(symbol, 6)?;
+            if info & 0xF != STT_FUNC || section == SHN_UNDEF {
+                continue;
+            }
+            let name = names
+                .get(read_u32(symbol, 0)? as usize..)
+                .and_then(|name| name.split(|&byte| byte == 0).next())
+                .ok_or(ElfError::Truncated)?;
+            if !name.is_empty() {
+                functions.push(Function {
+                    vaddr: read_u64(symbol, 8)?,
+                    size: read_u64(symbol, 16)?,
+                    name,
+                });
+            }
+        }
+        Ok(functions)
+    }
+
+    fn section_data(&self, section: &SectionHeader) -> Result<&'a [u8], ElfError> {
+        let start = section.offset as usize;
+        let end = start.checked_add(section.size as usize).ok_or(ElfError::Truncated)?;
+        self.bytes.get(start..end).ok_or(ElfError::Truncated)
+    }
+
+    /// The file bytes at a virtual address
+    fn mapped_data(&self, vaddr: u64, size: u64) -> Result<&'a [u8], ElfError> {
+        if size == 0 {
+            return Ok(&[]);
+        }
+        let header = self
+            .load_segments()
+            .find(|h| vaddr >= h.vaddr && vaddr + size <= h.vaddr + h.file_size)
+            .ok_or(ElfError::Truncated)?;
+        let start = (vaddr - header.vaddr) as usize;
+        Ok(&self.segment_data(header)?[start..start + size as usize])
+    }
+}
+
+fn read_bytes<const N: usize>(bytes: &[u8], offset: usize) -> Result<[u8; N], ElfError> {
+    bytes
+        .get(offset..offset + N)
+        .and_then(|field| field.try_into().ok())
+        .ok_or(ElfError::Truncated)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, ElfError> {
+    read_bytes(bytes, offset).map(u16::from_le_bytes)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, ElfError> {
+    read_bytes(bytes, offset).map(u32::from_le_bytes)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, ElfError> {
+    read_bytes(bytes, offset).map(u64::from_le_bytes)
+}
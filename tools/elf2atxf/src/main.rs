@@ -0,0 +1,400 @@
+//! elf2atxf - Convert ELF Executables to ATXF
+//!
+//! Every loadable ELF segment becomes an ATXF segment with the same
+//! address, sizes and permissions, so the kernel maps code read-only,
+//! read-only data non-executable and data non-executable.
+//!
+//! A linker may put read-only data in the same segment as code. When the
+//! section headers show where the code ends, the data from the next page
+//! on is split off into a read-only segment of its own.
+//!
+//! A position-independent executable (`-static-pie`) becomes a relocatable
+//! image the kernel may load at any address. Its R_X86_64_RELATIVE
+//! relocations go into the relocation table, with each addend written into
+//! the word it relocates; executables that need shared libraries or other
+//! relocation types are rejected.
+//!
+//! With `--symbols`, the functions of the ELF symbol table are kept, their
+//! names demangled, so the kernel can name the function a program crashed
+//! in. Line tables are not carried.
+//!
+//! With `--compress`, segments are stored LZ4-compressed where that makes
+//! them smaller; the kernel decompresses them as it loads. Every image
+//! carries a CRC-32 the kernel checks first.
+//!
+//! With `--sign <key>`, the image is signed with the Ed25519 key in the key
+//! file, 64 hex digits of secret seed. `--keygen <key>` writes a new key
+//! file and `--public-key <key>` prints the public key a kernel is built
+//! with, as `ATOM_ATXF_PUBLIC_KEY`.
+//!
+//! ```text
+//! elf2atxf [-v] [--symbols] [--compress] [--sign <key>] <input.elf> <output.atxf>
+//! elf2atxf --keygen <key> | --public-key <key>
+//! ```
+
+mod demangle;
+mod elf;
+
+use std::error::Error;
+use std::io::Read;
+use std::process::ExitCode;
+use std::{env, fs};
+
+use atxf::ed25519::{self, SEED_SIZE};
+use atxf::{Image, Segment, Symbol, PAGE_SIZE, SEGMENT_EXECUTE, SEGMENT_READ, SEGMENT_WRITE};
+
+use elf::{Elf, ProgramHeader, PF_R, PF_W, PF_X};
+
+const USAGE: &str = "\
+usage: elf2atxf [-v] [--symbols] [--compress] [--sign <key>] <input.elf> <output.atxf>
+       elf2atxf --keygen <key> | --public-key <key>";
+
+#[derive(Default)]
+struct Options {
+    verbose: bool,
+    symbols: bool,
+    compress: bool,
+    sign: Option<String>,
+}
+
+fn main() -> ExitCode {
+    let mut options = Options::default();
+    let mut paths = Vec::new();
+    // `--keygen` or `--public-key`: the key file, and whether to create it
+    let mut key_command = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-v" | "--verbose" => options.verbose = true,
+            "-s" | "--symbols" => options.symbols = true,
+            "-z" | "--compress" => options.compress = true,
+            "--sign" | "--keygen" | "--public-key" => {
+                let Some(key) = args.next() else {
+                    eprintln!("{USAGE}");
+                    return ExitCode::FAILURE;
+                };
+                match arg.as_str() {
+                    "--sign" => options.sign = Some(key),
+                    "--keygen" => key_command = Some((key, true)),
+                    _ => key_command = Some((key, false)),
+                }
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    if let Some((key, generate)) = key_command {
+        let result = if generate {
+            keygen(&key)
+        } else {
+            ed25519::read_key_file(&key).map_err(Into::into).map(|seed| {
+                println!("{}", ed25519::to_hex(&ed25519::public_key(&seed)));
+            })
+        };
+        return match result {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("elf2atxf: {key}: {error}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let [input, output] = paths.as_slice() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    match run(input, output, &options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("elf2atxf: {input}: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(input: &str, output: &str, options: &Options) -> Result<(), Box<dyn Error>> {
+    let bytes = fs::read(input)?;
+    let elf = Elf::parse(&bytes)?;
+    let mut image = convert(&elf)?;
+    if options.symbols {
+        image.symbols = function_symbols(&elf, &image)?;
+    }
+    image.compressed = options.compress;
+    image.validate()?;
+    let mut atxf = image.to_bytes();
+    if let Some(key) = &options.sign {
+        let seed = ed25519::read_key_file(key).map_err(|error| format!("{key}: {error}"))?;
+        atxf = atxf::sign(&atxf, &seed)?;
+    }
+
+    if options.verbose {
+        println!("entry {:#X}", image.entry);
+        if image.relocatable {
+            println!("relocatable, {} relocations", image.relocations.len());
+        }
+        if !image.symbols.is_empty() {
+            println!("{} symbols", image.symbols.len());
+        }
+        for segment in &image.segments {
+            println!(
+                "  {:#010X}  file {:>8}  mem {:>8}  {}",
+                segment.vaddr,
+                segment.data.len(),
+                segment.mem_size,
+                segment.flags_str()
+            );
+        }
+        if options.sign.is_some() {
+            println!("signed");
+        }
+        println!("{} bytes", atxf.len());
+    }
+
+    fs::write(output, atxf)?;
+    Ok(())
+}
+
+/// Write a new key file from the system's random numbers, and print its
+/// public key
+fn keygen(path: &str) -> Result<(), Box<dyn Error>> {
+    let mut seed = [0u8; SEED_SIZE];
+    fs::File::open("/dev/urandom")?.read_exact(&mut seed)?;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(
+        &mut options.open(path)?,
+        format!("{}\n", ed25519::to_hex(&seed)).as_bytes(),
+    )?;
+
+    println!("{}", ed25519::to_hex(&ed25519::public_key(&seed)));
+    Ok(())
+}
+
+/// The ATXF image of an ELF executable
+fn convert(elf: &Elf) -> Result<Image, Box<dyn Error>> {
+    let mut segments = Vec::new();
+    for header in elf.load_segments() {
+        let segment = Segment {
+            vaddr: header.vaddr,
+            mem_size: header.mem_size,
+            flags: segment_flags(header.flags),
+            data: elf.segment_data(header)?.to_vec(),
+        };
+
+        match split_point(elf, header) {
+            Some(split) => {
+                let (code, rodata) = split_segment(segment, split);
+                segments.push(code);
+                segments.push(rodata);
+            }
+            None => segments.push(segment),
+        }
+    }
+
+    let mut image = Image {
+        entry: elf.entry,
+        segments,
+        relocatable: elf.position_independent,
+        relocations: Vec::new(),
+        symbols: Vec::new(),
+        compressed: false,
+    };
+
+    if elf.position_independent {
+        for relocation in elf.relocations()? {
+            let offset = relocation.offset;
+            let word = image
+                .word_at(offset)
+                .ok_or_else(|| format!("relocation at {offset:#X} is not in file data"))?;
+            if let Some(addend) = relocation.addend {
+                *word = addend.to_le_bytes();
+            }
+            image.relocations.push(relocation.offset);
+        }
+    }
+
+    Ok(image)
+}
+
+/// The functions in the image's code, sorted by address, one per address
+fn function_symbols(elf: &Elf, image: &Image) -> Result<Vec<Symbol>, Box<dyn Error>> {
+    let in_code = |vaddr: u64| {
+        image.segments.iter().any(|segment| {
+            segment.is_executable() && vaddr >= segment.vaddr && vaddr < segment.page_end()
+        })
+    };
+
+    let mut symbols: Vec<Symbol> = elf
+        .functions()?
+        .into_iter()
+        .filter(|function| in_code(function.vaddr))
+        .map(|function| Symbol {
+            vaddr: function.vaddr,
+            size: function.size.min(u32::MAX as u64) as u32,
+            name: demangle::demangle(&String::from_utf8_lossy(function.name)),
+        })
+        .collect();
+    symbols.sort_by_key(|symbol| symbol.vaddr);
+    symbols.dedup_by_key(|symbol| symbol.vaddr);
+    Ok(symbols)
+}
+
+fn segment_flags(elf_flags: u32) -> u32 {
+    let mut flags = 0;
+    if elf_flags & PF_R != 0 {
+        flags |= SEGMENT_READ;
+    }
+    if elf_flags & PF_W != 0 {
+        flags |= SEGMENT_WRITE;
+    }
+    if elf_flags & PF_X != 0 {
+        flags |= SEGMENT_EXECUTE;
+    }
+    flags
+}
+
+/// Where read-only data starts in an executable segment: the first page
+/// boundary after its last code section, if file bytes follow it
+fn split_point(elf: &Elf, header: &ProgramHeader) -> Option<u64> {
+    if header.flags & PF_X == 0 || header.flags & PF_W != 0 {
+        return None;
+    }
+
+    let start = header.vaddr;
+    let end = header.vaddr + header.file_size;
+    let inside = |addr: u64| addr >= start && addr < end;
+
+    let code_end = elf
+        .section_headers
+        .iter()
+        .filter(|section| section.is_loaded() && section.is_code() && inside(section.addr))
+        .map(|section| section.addr + section.size)
+        .max()?;
+    let split = code_end.next_multiple_of(PAGE_SIZE);
+
+    // Only data the file carries is split off, and only if there is some
+    let data_after = elf.section_headers.iter().any(|section| {
+        section.is_loaded() && section.has_bits() && inside(section.addr) && section.addr >= split
+    });
+    (split > start && split < end && data_after).then_some(split)
+}
+
+/// Cut `segment` at `split` into the code before it and the read-only data
+/// after it
+fn split_segment(mut segment: Segment, split: u64) -> (Segment, Segment) {
+    let code_len = (split - segment.vaddr) as usize;
+    let rodata = Segment {
+        vaddr: split,
+        mem_size: segment.mem_size - code_len as u64,
+        flags: segment.flags & !SEGMENT_EXECUTE,
+        data: segment.data.split_off(code_len),
+    };
+    segment.mem_size = code_len as u64;
+    (segment, rodata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal ELF: code at 0x401000 followed by read-only data on the
+    /// next page in one R+X segment, and an R+W segment with .bss
+    fn sample_elf() -> Vec<u8> {
+        let mut bytes = vec![0u8; 0x3000];
+        bytes[..4].copy_from_slice(b"\x7FELF");
+        bytes[4] = 2;
+        bytes[5] = 1;
+        put(&mut bytes, 16, &2u16.to_le_bytes());
+        put(&mut bytes, 18, &62u16.to_le_bytes());
+        put(&mut bytes, 24, &0x40_1000u64.to_le_bytes());
+        put(&mut bytes, 32, &0x40u64.to_le_bytes());
+        put(&mut bytes, 40, &0x200u64.to_le_bytes());
+        put(&mut bytes, 54, &56u16.to_le_bytes());
+        put(&mut bytes, 56, &2u16.to_le_bytes());
+        put(&mut bytes, 58, &64u16.to_le_bytes());
+        put(&mut bytes, 60, &3u16.to_le_bytes());
+
+        program_header(&mut bytes, 0x40, PF_R | PF_X, 0x1000, 0x40_1000, 0x1010, 0x1010);
+        program_header(&mut bytes, 0x78, PF_R | PF_W, 0x2800, 0x40_3800, 0x10, 0x2000);
+
+        section_header(&mut bytes, 0x200, 1, SHF_TEXT, 0x40_1000, 0x80);
+        section_header(&mut bytes, 0x240, 1, elf::SHF_ALLOC, 0x40_2000, 0x10);
+        section_header(&mut bytes, 0x280, 8, SHF_DATA, 0x40_3810, 0x1ff0);
+
+        bytes[0x1000] = 0xC3;
+        bytes[0x2000..0x2010].fill(0xAA);
+        bytes
+    }
+
+    const SHF_TEXT: u64 = elf::SHF_ALLOC | elf::SHF_EXECINSTR;
+    const SHF_DATA: u64 = elf::SHF_ALLOC | 1;
+
+    fn put(bytes: &mut [u8], at: usize, value: &[u8]) {
+        bytes[at..at + value.len()].copy_from_slice(value);
+    }
+
+    fn program_header(
+        bytes: &mut [u8],
+        at: usize,
+        flags: u32,
+        offset: u64,
+        vaddr: u64,
+        file_size: u64,
+        mem_size: u64,
+    ) {
+        put(bytes, at, &elf::PT_LOAD.to_le_bytes());
+        put(bytes, at + 4, &flags.to_le_bytes());
+        put(bytes, at + 8, &offset.to_le_bytes());
+        put(bytes, at + 16, &vaddr.to_le_bytes());
+        put(bytes, at + 32, &file_size.to_le_bytes());
+        put(bytes, at + 40, &mem_size.to_le_bytes());
+    }
+
+    fn section_header(bytes: &mut [u8], at: usize, kind: u32, flags: u64, addr: u64, size: u64) {
+        put(bytes, at + 4, &kind.to_le_bytes());
+        put(bytes, at + 8, &flags.to_le_bytes());
+        put(bytes, at + 16, &addr.to_le_bytes());
+        put(bytes, at + 32, &size.to_le_bytes());
+    }
+
+    #[test]
+    fn splits_rodata_from_code() {
+        let bytes = sample_elf();
+        let image = convert(&Elf::parse(&bytes).unwrap()).unwrap();
+        assert_eq!(image.validate(), Ok(()));
+
+        let layout: Vec<_> = image
+            .segments
+            .iter()
+            .map(|s| (s.vaddr, s.data.len(), s.mem_size, s.flags_str()))
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                (0x40_1000, 0x1000, 0x1000, "r-x".to_string()),
+                (0x40_2000, 0x10, 0x10, "r--".to_string()),
+                (0x40_3800, 0x10, 0x2000, "rw-".to_string()),
+            ]
+        );
+        assert_eq!(image.segments[0].data[0], 0xC3);
+        assert_eq!(image.segments[1].data, [0xAA; 0x10]);
+    }
+
+    #[test]
+    fn keeps_segments_without_section_headers() {
+        let mut bytes = sample_elf();
+        put(&mut bytes, 40, &0u64.to_le_bytes());
+        let image = convert(&Elf::parse(&bytes).unwrap()).unwrap();
+        assert_eq!(image.segments.len(), 2);
+        assert_eq!(image.segments[0].flags_str(), "r-x");
+    }
+}
@@ -0,0 +1,272 @@
+//! embed - Files Compiled Into a Crate
+//!
+//! For build scripts: copies each file into `OUT_DIR`, LZ4-compressed if
+//! asked, and writes one module that pulls them all in with
+//! `include_bytes!`. The compiler reads the bytes straight from disk, so a
+//! large blob costs no more to build than a small one.
+//!
+//! ```ignore
+//! // build.rs
+//! let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+//! embed::Module::new()
+//!     .file(embed::File::new("INIT", "init.atxf").align(4096).compress())
+//!     .file(embed::File::new("FONT", "font.psf"))
+//!     .write(&out_dir, "embedded.rs")?;
+//!
+//! // src/lib.rs
+//! include!(concat!(env!("OUT_DIR"), "/embedded.rs"));
+//! ```
+//!
+//! Each file becomes a `pub static` of the generated `Embedded` type: its
+//! bytes as stored, aligned as asked, and its size before compression.
+//! Compressed files are LZ4 blocks that `lz4_block::decompress` (a no_std
+//! crate, kernel/lz4-block) expands into a buffer of that size.
+//!
+//! Files are only rewritten when their contents change, and the build
+//! script is rerun when a source file does.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use atxf::lz4;
+
+/// Where the stored copies go, under `OUT_DIR`
+const BLOB_DIR: &str = "embed";
+
+/// One file to embed
+#[derive(Debug, Clone)]
+pub struct File {
+    name: String,
+    source: PathBuf,
+    align: usize,
+    compress: bool,
+}
+
+impl File {
+    /// Embed `source` as the static `name`, which must be an identifier
+    pub fn new(name: &str, source: impl AsRef<Path>) -> Self {
+        Self {
+            name: String::from(name),
+            source: source.as_ref().to_path_buf(),
+            align: 1,
+            compress: false,
+        }
+    }
+
+    /// Align the stored bytes to `align`, a power of two
+    pub fn align(mut self, align: usize) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Store the bytes as an LZ4 block
+    pub fn compress(mut self) -> Self {
+        self.compress = true;
+        self
+    }
+}
+
+/// A file as the generated module sees it
+struct Stored {
+    name: String,
+    path: PathBuf,
+    align: usize,
+    size: usize,
+    compressed: bool,
+}
+
+/// Files that go into one generated module
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    files: Vec<File>,
+}
+
+impl Module {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn file(mut self, file: File) -> Self {
+        self.files.push(file);
+        self
+    }
+
+    /// Store every file under `out_dir` and write the module to
+    /// `out_dir/module`; returns the module's path
+    pub fn write(&self, out_dir: &Path, module: &str) -> io::Result<PathBuf> {
+        self.check()?;
+        let blob_dir = out_dir.join(BLOB_DIR);
+        fs::create_dir_all(&blob_dir)?;
+
+        let mut stored = Vec::with_capacity(self.files.len());
+        for file in &self.files {
+            let bytes = fs::read(&file.source)?;
+            println!("cargo:rerun-if-changed={}", file.source.display());
+
+            let size = bytes.len();
+            let (contents, extension) = if file.compress {
+                (lz4::compress(&bytes), "lz4")
+            } else {
+                (bytes, "bin")
+            };
+            let path = blob_dir.join(format!("{}.{extension}", file.name));
+            write_if_changed(&path, &contents)?;
+            stored.push(Stored {
+                name: file.name.clone(),
+                path,
+                align: file.align,
+                size,
+                compressed: file.compress,
+            });
+        }
+
+        let path = out_dir.join(module);
+        write_if_changed(&path, generate(&stored).as_bytes())?;
+        Ok(path)
+    }
+
+    /// Names that are identifiers and unique, alignments that are powers
+    /// of two
+    fn check(&self) -> io::Result<()> {
+        for (i, file) in self.files.iter().enumerate() {
+            let mut chars = file.name.chars();
+            let identifier = chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !identifier {
+                return Err(invalid(format!("'{}' is not an identifier", file.name)));
+            }
+            if self.files[..i].iter().any(|other| other.name == file.name) {
+                return Err(invalid(format!("'{}' is embedded twice", file.name)));
+            }
+            if !file.align.is_power_of_two() {
+                return Err(invalid(format!(
+                    "{}: alignment {} is not a power of two",
+                    file.name, file.align
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, message)
+}
+
+/// Write `contents` to `path` unless it already holds them, so an
+/// unchanged file does not make the crate rebuild
+fn write_if_changed(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if fs::read(path).is_ok_and(|old| old == contents) {
+        return Ok(());
+    }
+    fs::write(path, contents)
+}
+
+/// The module's source
+fn generate(files: &[Stored]) -> String {
+    let mut out = String::from(
+        "// Generated by tools/embed; do not edit\n\
+         \n\
+         /// A file embedded with `include_bytes!`\n\
+         #[derive(Debug, Clone, Copy)]\n\
+         pub struct Embedded {\n\
+         \x20   /// The bytes as stored, an LZ4 block if `compressed`\n\
+         \x20   pub data: &'static [u8],\n\
+         \x20   /// Size of the file before compression\n\
+         \x20   pub size: usize,\n\
+         \x20   pub compressed: bool,\n\
+         }\n",
+    );
+
+    let mut aligns: Vec<usize> = files.iter().map(|file| file.align).collect();
+    aligns.sort_unstable();
+    aligns.dedup();
+    for align in aligns {
+        let _ = write!(
+            out,
+            "\n#[repr(C, align({align}))]\nstruct Align{align}<T: ?Sized>(T);\n"
+        );
+    }
+
+    for file in files {
+        let _ = write!(
+            out,
+            "\npub static {name}: Embedded = {{\n\
+             \x20   static DATA: &Align{align}<[u8]> = &Align{align}(*include_bytes!({path:?}));\n\
+             \x20   Embedded {{ data: &DATA.0, size: {size}, compressed: {compressed} }}\n\
+             }};\n",
+            name = file.name,
+            align = file.align,
+            path = file.path.display().to_string(),
+            size = file.size,
+            compressed = file.compressed,
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("embed-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn stores_and_generates() {
+        let dir = scratch("generate");
+        let data: Vec<u8> = (0..4096u32).map(|i| (i % 7) as u8).collect();
+        fs::write(dir.join("data.bin"), &data).unwrap();
+        fs::write(dir.join("small.txt"), b"hello").unwrap();
+
+        let module = Module::new()
+            .file(
+                File::new("DATA", dir.join("data.bin"))
+                    .align(4096)
+                    .compress(),
+            )
+            .file(File::new("SMALL", dir.join("small.txt")))
+            .write(&dir, "embedded.rs")
+            .unwrap();
+
+        let compressed = fs::read(dir.join(BLOB_DIR).join("DATA.lz4")).unwrap();
+        let mut output = vec![0; data.len()];
+        assert_eq!(lz4::decompress(&compressed, &mut output), Some(()));
+        assert_eq!(output, data);
+        assert_eq!(
+            fs::read(dir.join(BLOB_DIR).join("SMALL.bin")).unwrap(),
+            b"hello"
+        );
+
+        let source = fs::read_to_string(module).unwrap();
+        assert!(source.contains("struct Align4096<T: ?Sized>(T);"));
+        assert!(source.contains("struct Align1<T: ?Sized>(T);"));
+        assert!(source.contains("size: 4096, compressed: true"));
+        assert!(source.contains("size: 5, compressed: false"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let dir = scratch("reject");
+        for module in [
+            Module::new().file(File::new("not-an-ident", "x")),
+            Module::new()
+                .file(File::new("A", "x"))
+                .file(File::new("A", "y")),
+            Module::new().file(File::new("A", "x").align(3)),
+        ] {
+            let err = module.write(&dir, "embedded.rs").unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
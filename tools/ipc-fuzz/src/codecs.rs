@@ -0,0 +1,433 @@
+//! Message Codecs
+//!
+//! Every type libipc puts on the wire, each with a sample value and a way
+//! to decode bytes and encode the result again. Adding a message type to
+//! libipc means adding it here, and its bytes to `golden.txt`.
+
+use libipc::ring::RingInfo;
+use libipc::rpc::{ReplyHeader, RequestHeader};
+use libipc::status::{ErrorReply, StatusCode};
+use libipc::stream::StreamControl;
+use libipc::*;
+
+/// One wire format
+pub struct Codec {
+    /// As in `golden.txt`
+    pub name: &'static str,
+    /// The bytes of the sample value
+    pub sample: fn() -> Vec<u8>,
+    /// Decode `bytes` and encode the value again; `None` if it does not
+    /// decode
+    pub round_trip: fn(&[u8]) -> Option<Vec<u8>>,
+}
+
+/// A codec for a type with `to_bytes(&self)` and
+/// `from_bytes(&[u8]) -> Option<Self>`
+macro_rules! codec {
+    ($name:literal, $type:ty, $sample:expr) => {
+        Codec {
+            name: $name,
+            sample: || <$type>::to_bytes(&$sample).to_vec(),
+            round_trip: |bytes| <$type>::from_bytes(bytes).map(|value| value.to_bytes().to_vec()),
+        }
+    };
+}
+
+const TIMESTAMP: Timestamp = 0x0000_0123_4567_89AB;
+
+fn notification() -> Notification {
+    Notification {
+        app_name: "terminal".into(),
+        title: "Build finished".into(),
+        body: "0 errors, 2 warnings — see /var/log/build".into(),
+        urgency: Urgency::Normal,
+        timeout_ms: 5000,
+    }
+}
+
+fn clipboard_data() -> ClipboardData {
+    ClipboardData {
+        port: 0x31,
+        mime: ClipboardMime::TextPlain,
+        content: ClipboardContent::Inline(b"copied text".to_vec()),
+    }
+}
+
+fn display(id: u32, x: i32, scale: ScaleFactor, primary: bool) -> DisplayInfo {
+    DisplayInfo { id, x, y: 0, width: 1920, height: 1080, scale, primary }
+}
+
+pub const CODECS: &[Codec] = &[
+    // Framing and connection setup
+    codec!(
+        "message_header",
+        MessageHeader,
+        MessageHeader {
+            version: PROTOCOL_VERSION,
+            msg_type: MessageType::CommitFrame,
+            payload_size: 37,
+            sequence: 0x0102_0304,
+        }
+    ),
+    codec!("hello", Hello, Hello::new(0x42)),
+    codec!("hello_ack", HelloAck, HelloAck { version: PROTOCOL_VERSION }),
+    codec!("request_header", RequestHeader, RequestHeader { request_id: 7, reply_port: 0x43 }),
+    codec!(
+        "reply_header",
+        ReplyHeader,
+        ReplyHeader { request_id: 7, status: StatusCode::NotFound }
+    ),
+    codec!(
+        "error_reply",
+        ErrorReply,
+        ErrorReply { request: MessageType::FileOpen as u32, status: StatusCode::Denied }
+    ),
+    codec!("stream_control", StreamControl, StreamControl { port: 0x44, credits: 16 }),
+    codec!(
+        "ring_info",
+        RingInfo,
+        RingInfo { region_id: 0x1_0000_0009, size: 0x4000, doorbell: 0x45 }
+    ),
+    // Input
+    codec!(
+        "key_event",
+        KeyEvent,
+        KeyEvent {
+            keycode: KeyCode::KeyQ,
+            character: b'Q',
+            modifiers: KeyModifiers { shift: true, ctrl: false, alt: false, caps_lock: true },
+            timestamp: TIMESTAMP,
+        }
+    ),
+    codec!(
+        "set_keyboard_layout",
+        SetKeyboardLayoutRequest,
+        SetKeyboardLayoutRequest { layout: KeyboardLayout::Abnt2 }
+    ),
+    codec!(
+        "pointer_settings",
+        PointerSettings,
+        PointerSettings { profile: AccelProfile::Adaptive, sensitivity: 150 }
+    ),
+    codec!(
+        "shortcut_binding",
+        ShortcutBinding,
+        ShortcutBinding::new(ShortcutAction::Screenshot, 0x05, KeyCode::Digit3)
+    ),
+    codec!(
+        "mouse_move",
+        MouseMoveEvent,
+        MouseMoveEvent { x: 640, y: -3, dx: 12, dy: -7, timestamp: TIMESTAMP }
+    ),
+    codec!(
+        "mouse_button",
+        MouseButtonEvent,
+        MouseButtonEvent { button: MouseButton::Right, x: 100, y: 200, timestamp: TIMESTAMP }
+    ),
+    codec!(
+        "mouse_scroll",
+        MouseScrollEvent,
+        MouseScrollEvent { x: 10, y: 20, delta: -120, timestamp: TIMESTAMP }
+    ),
+    // Windows
+    codec!(
+        "create_window",
+        CreateWindowRequest,
+        CreateWindowRequest {
+            reply_port: 0x46,
+            width: 800,
+            height: 600,
+            native_scale: true,
+            title: "Terminal — ~".into(),
+            app_id: "org.atom.terminal".into(),
+        }
+    ),
+    codec!(
+        "reattach_window",
+        ReattachRequest,
+        ReattachRequest {
+            reply_port: 0x46,
+            region_id: 0x2_0000_0001,
+            width: 800,
+            height: 600,
+            stride: 832,
+            scale: ScaleFactor::X1_5,
+            title: "Files".into(),
+            app_id: String::new(),
+        }
+    ),
+    codec!(
+        "create_window_response",
+        CreateWindowResponse,
+        CreateWindowResponse { window_id: 3, success: true }
+    ),
+    codec!("window_event", WindowEventMsg, WindowEventMsg::resize_requested(3, 1024, 768)),
+    codec!(
+        "surface_region",
+        SurfaceRegion,
+        SurfaceRegion {
+            window_id: 3,
+            region_id: 0x2_0000_0001,
+            width: 1200,
+            height: 900,
+            stride: 1216,
+            scale: ScaleFactor::X1_5,
+        }
+    ),
+    codec!(
+        "commit_frame",
+        CommitFrame,
+        CommitFrame {
+            window_id: 3,
+            damage: vec![Rect::new(0, 0, 800, 24), Rect::new(-4, 100, 16, 16)],
+        }
+    ),
+    codec!("destroy_window", DestroyWindow, DestroyWindow { window_id: 3 }),
+    codec!(
+        "window_title",
+        WindowTitle,
+        WindowTitle { window_id: 3, title: "Notes — draft.txt".into() }
+    ),
+    codec!("window_resize", WindowResize, WindowResize { window_id: 3, width: 640, height: 480 }),
+    codec!("frame_done", FrameDone, FrameDone { window_id: 3, time_ms: 16_667 }),
+    codec!(
+        "window_opacity",
+        WindowOpacity,
+        WindowOpacity { window_id: 3, opacity: 200, per_pixel_alpha: true }
+    ),
+    codec!("window_cursor", WindowCursor, WindowCursor { window_id: 3, shape: CursorShape::IBeam }),
+    codec!(
+        "window_role",
+        WindowRole,
+        WindowRole { window_id: 4, parent: 3, modal: true, layer: WindowLayer::Overlay }
+    ),
+    // Graphics
+    codec!(
+        "framebuffer_info",
+        FramebufferInfo,
+        FramebufferInfo {
+            address: 0x8000_0000,
+            width: 1920,
+            height: 1080,
+            stride: 1920,
+            bytes_per_pixel: 4,
+            size: 1920 * 1080 * 4,
+        }
+    ),
+    codec!("rect", Rect, Rect::new(-10, 20, 300, 400)),
+    codec!("display_info", DisplayInfo, display(1, 0, ScaleFactor::X2, true)),
+    codec!(
+        "display_list",
+        DisplayList,
+        DisplayList {
+            displays: vec![
+                display(1, 0, ScaleFactor::X1, true),
+                display(2, 1920, ScaleFactor::X2, false),
+            ],
+        }
+    ),
+    codec!(
+        "create_surface",
+        CreateSurfaceRequest,
+        CreateSurfaceRequest {
+            reply_port: 0x4C,
+            width: 640,
+            height: 480,
+            format: PixelFormat::Argb32,
+        }
+    ),
+    codec!(
+        "surface_info",
+        SurfaceInfo,
+        SurfaceInfo {
+            surface_id: 2,
+            region_id: 0x7_0000_0006,
+            width: 640,
+            height: 480,
+            stride: 640,
+            format: PixelFormat::Rgb565,
+        }
+    ),
+    codec!(
+        "blit_surface",
+        BlitRequest,
+        BlitRequest {
+            surface_id: 2,
+            x: -8,
+            y: 32,
+            rects: vec![Rect::new(0, 0, 640, 24), Rect::new(16, 40, 100, 20)],
+        }
+    ),
+    codec!("present_done", PresentDone, PresentDone { pixels: 1920 * 1080 }),
+    codec!("display_mode", DisplayMode, DisplayMode { width: 1280, height: 800 }),
+    codec!(
+        "mode_list",
+        ModeList,
+        ModeList {
+            modes: vec![
+                DisplayMode { width: 1920, height: 1080 },
+                DisplayMode { width: 1280, height: 800 },
+                DisplayMode { width: 1024, height: 768 },
+            ],
+        }
+    ),
+    codec!(
+        "set_mode",
+        SetModeRequest,
+        SetModeRequest { reply_port: 0x4D, width: 1280, height: 800 }
+    ),
+    codec!(
+        "mode_changed",
+        ModeChanged,
+        ModeChanged { success: false, mode: DisplayMode { width: 1920, height: 1080 } }
+    ),
+    codec!(
+        "display_head",
+        DisplayHead,
+        DisplayHead { scanout: 1, x: 1920, width: 1280, height: 1024 }
+    ),
+    codec!(
+        "head_list",
+        HeadList,
+        HeadList {
+            heads: vec![
+                DisplayHead { scanout: 0, x: 0, width: 1920, height: 1080 },
+                DisplayHead { scanout: 2, x: 1920, width: 1280, height: 1024 },
+            ],
+        }
+    ),
+    // Audio
+    codec!(
+        "audio_open_stream",
+        AudioOpenStreamRequest,
+        AudioOpenStreamRequest { reply_port: 0x47, sample_rate: 48_000, channels: 2 }
+    ),
+    codec!(
+        "audio_stream_info",
+        AudioStreamInfo,
+        AudioStreamInfo { stream_id: 1, region_id: 0x3_0000_0002, ring_size: 0x8000 }
+    ),
+    codec!("audio_volume", AudioVolume, AudioVolume { stream_id: 1, volume: 80 }),
+    codec!("audio_beep", AudioBeep, AudioBeep { frequency_hz: 880, duration_ms: 150 }),
+    // Clipboard and drag and drop
+    codec!("clipboard_data", ClipboardData, clipboard_data()),
+    codec!(
+        "clipboard_data_shared",
+        ClipboardData,
+        ClipboardData {
+            port: 0x31,
+            mime: ClipboardMime::TextPlain,
+            content: ClipboardContent::Shared { region_id: 0x4_0000_0003, len: 70_000 },
+        }
+    ),
+    codec!(
+        "clipboard_request",
+        ClipboardRequest,
+        ClipboardRequest { reply_port: 0x48, mime: ClipboardMime::TextPlain }
+    ),
+    codec!(
+        "clipboard_changed",
+        ClipboardChanged,
+        ClipboardChanged { owner: 0x31, mime: ClipboardMime::TextPlain }
+    ),
+    codec!("drag_start", DragStart, DragStart { window_id: 3, data: clipboard_data() }),
+    codec!(
+        "drag_event",
+        DragEvent,
+        DragEvent { window_id: 3, x: 50, y: 60, mime: ClipboardMime::TextPlain }
+    ),
+    codec!(
+        "drop_event",
+        DropEvent,
+        DropEvent { window_id: 3, x: 50, y: 60, data: clipboard_data() }
+    ),
+    codec!("drag_end", DragEnd, DragEnd { window_id: 3, dropped: true }),
+    // Screen capture
+    codec!(
+        "capture_request",
+        CaptureRequest,
+        CaptureRequest {
+            reply_port: 0x49,
+            window_id: 3,
+            region_id: 0x5_0000_0004,
+            region_len: 1920 * 1080 * 4,
+        }
+    ),
+    codec!(
+        "capture_result",
+        CaptureResult,
+        CaptureResult { status: CaptureStatus::RegionTooSmall, width: 1920, height: 1080 }
+    ),
+    // Notifications, panel, wallpaper and theme
+    Codec {
+        name: "notification",
+        sample: || notification().to_bytes(),
+        round_trip: |bytes| Notification::from_bytes(bytes).map(|(value, _)| value.to_bytes()),
+    },
+    Codec {
+        name: "notification_history",
+        sample: || {
+            let record = |id, urgency| NotificationRecord {
+                id,
+                timestamp: TIMESTAMP + id as u64,
+                notification: Notification { urgency, ..notification() },
+            };
+            let records = [record(2, Urgency::Critical), record(1, Urgency::Low)];
+            NotificationHistory::pack(&records, MAX_MESSAGE_SIZE)
+        },
+        round_trip: |bytes| {
+            let history = NotificationHistory::from_bytes(bytes)?;
+            Some(NotificationHistory::pack(&history.records, usize::MAX))
+        },
+    },
+    codec!("panel_widget", PanelWidget, PanelWidget { id: 1, text: "RAM 312/1024 MiB".into() }),
+    codec!(
+        "set_wallpaper",
+        SetWallpaper,
+        SetWallpaper {
+            reply_port: 0x4A,
+            region_id: 0x6_0000_0005,
+            len: 1_048_576,
+            mode: WallpaperMode::Tile,
+        }
+    ),
+    codec!("theme_spec", ThemeSpec, ThemeSpec::NORD),
+    // Terminal and files
+    codec!(
+        "terminal_mode",
+        TerminalMode,
+        TerminalMode { raw: true, echo: false, bracketed_paste: true, input_port: 0x4B }
+    ),
+    codec!(
+        "file_open",
+        FileOpen,
+        FileOpen { flags: OPEN_WRITE | OPEN_CREATE, path: "/home/user/notes.txt".into() }
+    ),
+    codec!("file_read", FileRead, FileRead { handle: 9, len: 4096 }),
+    codec!("file_stat", FileStat, FileStat { size: 123_456, is_dir: false }),
+];
+
+/// The codec called `name`
+pub fn find(name: &str) -> Option<&'static Codec> {
+    CODECS.iter().find(|codec| codec.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_round_trip_exactly() {
+        for codec in CODECS {
+            let sample = (codec.sample)();
+            assert_eq!((codec.round_trip)(&sample), Some(sample), "{}", codec.name);
+        }
+    }
+
+    #[test]
+    fn names_are_unique() {
+        for (index, codec) in CODECS.iter().enumerate() {
+            assert!(CODECS[..index].iter().all(|other| other.name != codec.name), "{}", codec.name);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Fuzzing
+//!
+//! Feeds each decoder bytes made from its sample: random bytes, the
+//! sample with bytes flipped, cut short or run on, and with length and
+//! count fields set to extremes. A decoder may reject anything, but it may
+//! not panic, and whatever it accepts must encode to bytes that decode to
+//! the same value again, so a message survives being forwarded.
+
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+
+use libipc::MAX_MESSAGE_SIZE;
+
+use crate::codecs::Codec;
+
+/// xorshift64*, seeded so a failure can be replayed
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A number below `bound`, which must not be 0
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.next() as u8
+    }
+}
+
+/// Values that make length and count fields interesting
+const EXTREMES: [u32; 8] = [0, 1, 0x7F, 0xFF, 0x100, 0xFFFF, 0x7FFF_FFFF, 0xFFFF_FFFF];
+
+/// An input for a decoder, made from its sample
+pub fn mutate(sample: &[u8], rng: &mut Rng) -> Vec<u8> {
+    let mut bytes = sample.to_vec();
+    match rng.below(6) {
+        // Anything at all
+        0 => {
+            let len = rng.below(2 * sample.len() + 16);
+            return (0..len).map(|_| rng.byte()).collect();
+        }
+        // Cut short
+        1 => bytes.truncate(rng.below(sample.len() + 1)),
+        // Run on
+        2 => {
+            let extra = rng.below(64) + 1;
+            bytes.extend((0..extra).map(|_| rng.byte()));
+        }
+        // A length or count field set to an extreme, little-endian
+        3 if !bytes.is_empty() => {
+            let value = EXTREMES[rng.below(EXTREMES.len())].to_le_bytes();
+            let width = [1, 2, 4][rng.below(3)];
+            let at = rng.below(bytes.len());
+            let end = (at + width).min(bytes.len());
+            bytes[at..end].copy_from_slice(&value[..end - at]);
+        }
+        // A few bytes changed
+        _ => {
+            for _ in 0..rng.below(4) + 1 {
+                if bytes.is_empty() {
+                    break;
+                }
+                let at = rng.below(bytes.len());
+                bytes[at] =
+                    if rng.below(2) == 0 { bytes[at] ^ 1 << rng.below(8) } else { rng.byte() };
+            }
+        }
+    }
+    bytes.truncate(MAX_MESSAGE_SIZE);
+    bytes
+}
+
+/// How a decoder misbehaved on an input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub codec: &'static str,
+    pub input: Vec<u8>,
+    pub problem: String,
+}
+
+/// Check one input against the decoder's rules
+pub fn check(codec: &Codec, input: &[u8]) -> Result<(), Failure> {
+    let fail = |problem: String| Failure { codec: codec.name, input: input.to_vec(), problem };
+
+    let round_trip = |bytes: &[u8]| {
+        quietly(|| (codec.round_trip)(bytes)).map_err(|payload| {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            format!("panicked: {message}")
+        })
+    };
+
+    let Some(encoded) = round_trip(input).map_err(fail)? else {
+        return Ok(());
+    };
+    match round_trip(&encoded).map_err(fail)? {
+        Some(again) if again == encoded => Ok(()),
+        Some(again) => {
+            Err(fail(format!("re-encodes as {} instead of {}", hex(&again), hex(&encoded))))
+        }
+        None => Err(fail(format!("its encoding {} does not decode", hex(&encoded)))),
+    }
+}
+
+/// Run `iterations` inputs through each codec, stopping at the first
+/// failure. Each codec's inputs depend only on the seed, so a failure
+/// replays with that codec alone.
+pub fn run(codecs: &[&Codec], iterations: usize, seed: u64) -> Result<(), Failure> {
+    for codec in codecs {
+        let mut rng = Rng::new(seed);
+        let sample = (codec.sample)();
+        check(codec, &sample)?;
+        for _ in 0..iterations {
+            check(codec, &mutate(&sample, &mut rng))?;
+        }
+    }
+    Ok(())
+}
+
+thread_local! {
+    static QUIET: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f`, catching a panic without the panic hook reporting it
+fn quietly<T>(f: impl FnOnce() -> T) -> std::thread::Result<T> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let report = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !QUIET.with(Cell::get) {
+                report(info);
+            }
+        }));
+    });
+
+    QUIET.with(|quiet| quiet.set(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    QUIET.with(|quiet| quiet.set(false));
+    result
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codecs::CODECS;
+
+    #[test]
+    fn decoders_survive_a_short_run() {
+        if let Err(failure) = run(&CODECS.iter().collect::<Vec<_>>(), 2000, 0xA70A) {
+            panic!("{}: {} on {}", failure.codec, failure.problem, hex(&failure.input));
+        }
+    }
+
+    #[test]
+    fn catches_a_panicking_decoder() {
+        let codec =
+            Codec { name: "broken", sample: || vec![4], round_trip: |bytes| Some(vec![bytes[0]]) };
+        let failure = check(&codec, &[]).unwrap_err();
+        assert!(
+            failure.problem.starts_with("panicked: index out of bounds"),
+            "{}",
+            failure.problem
+        );
+        assert_eq!(check(&codec, &[4]), Ok(()));
+    }
+}
@@ -0,0 +1,98 @@
+//! Golden Bytes
+//!
+//! `golden.txt` holds the wire bytes of every codec's sample, as released.
+//! The samples must still encode to exactly those bytes, and the bytes
+//! must still decode and encode back unchanged, so a change to a layout is
+//! caught before a service built from the old one meets a client built
+//! from the new one. Regenerate the file with `--bless` only for a change
+//! meant to break compatibility, with `PROTOCOL_VERSION` bumped.
+
+use crate::codecs::{self, CODECS};
+use crate::fuzz::hex;
+
+pub const PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/golden.txt");
+
+const HEADER: &str = "\
+# Wire bytes of the libipc samples in ipc-fuzz/src/codecs.rs, one message
+# per line: its name, then its bytes in hex. Checked by `cargo test` and
+# `ipc-fuzz --check`; rewritten by `ipc-fuzz --bless`.
+";
+
+/// `(name, bytes)` of each line, ignoring comments and blank lines
+pub fn parse(text: &str) -> Result<Vec<(&str, Vec<u8>)>, String> {
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let bad = || format!("golden.txt:{}: expected a name and hex bytes", number + 1);
+        let (name, digits) = line.split_once(' ').ok_or_else(bad)?;
+        if digits.len() % 2 != 0 {
+            return Err(bad());
+        }
+        let bytes = (0..digits.len())
+            .step_by(2)
+            .map(|at| u8::from_str_radix(&digits[at..at + 2], 16).map_err(|_| bad()))
+            .collect::<Result<_, _>>()?;
+        entries.push((name, bytes));
+    }
+    Ok(entries)
+}
+
+/// What no longer matches the golden bytes in `text`
+pub fn check(text: &str) -> Result<Vec<String>, String> {
+    let entries = parse(text)?;
+    let mut problems = Vec::new();
+    for codec in CODECS {
+        if !entries.iter().any(|(name, _)| *name == codec.name) {
+            problems.push(format!("{}: has no golden bytes", codec.name));
+        }
+    }
+
+    for (name, golden) in &entries {
+        let Some(codec) = codecs::find(name) else {
+            problems.push(format!("{name}: no such message"));
+            continue;
+        };
+        let sample = (codec.sample)();
+        if sample != *golden {
+            problems.push(format!("{name}: encodes as {}, not {}", hex(&sample), hex(golden)));
+        }
+        match (codec.round_trip)(golden) {
+            Some(bytes) if bytes == *golden => {}
+            Some(bytes) => {
+                problems.push(format!("{name}: golden bytes come back as {}", hex(&bytes)))
+            }
+            None => problems.push(format!("{name}: golden bytes no longer decode")),
+        }
+    }
+    Ok(problems)
+}
+
+/// The golden file for the samples as they encode now
+pub fn render() -> String {
+    let mut text = HEADER.to_string();
+    for codec in CODECS {
+        text += &format!("{} {}\n", codec.name, hex(&(codec.sample)()));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wire_bytes_match_golden_file() {
+        let problems = check(include_str!("../golden.txt")).unwrap();
+        assert!(problems.is_empty(), "wire format changed:\n{}", problems.join("\n"));
+    }
+
+    #[test]
+    fn catches_a_changed_layout() {
+        let text = render().replace("hello_ack 0100", "hello_ack 0200");
+        assert_eq!(check(&text).unwrap(), ["hello_ack: encodes as 0100, not 0200"]);
+        assert!(parse("hello_ack 010").is_err());
+    }
+}
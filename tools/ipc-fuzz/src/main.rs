@@ -0,0 +1,153 @@
+//! ipc-fuzz - Fuzz and Pin libipc's Wire Formats
+//!
+//! libipc is `no_std` with `alloc` only, so its encoders and decoders run
+//! here on the build machine as they do on Atom. Every message type is
+//! listed in `codecs`, each with a sample value.
+//!
+//! Without options, each decoder is fed mutated and random input (see
+//! `fuzz`) and must neither panic nor accept bytes it cannot re-encode
+//! faithfully. A failure prints the message, the input and the seed to
+//! replay it with. `--check` compares the samples with the golden bytes in
+//! `golden.txt` (see `golden`), as `cargo test` does; `--bless` rewrites
+//! that file after a deliberate protocol change.
+//!
+//! ```text
+//! ipc-fuzz [--iterations <n>] [--seed <n>] [<message>...]
+//! ipc-fuzz --check | --bless | --list
+//! ```
+
+mod codecs;
+mod fuzz;
+mod golden;
+
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, fs};
+
+use codecs::{Codec, CODECS};
+
+const USAGE: &str = "\
+usage: ipc-fuzz [--iterations <n>] [--seed <n>] [<message>...]
+       ipc-fuzz --check | --bless | --list";
+
+const DEFAULT_ITERATIONS: usize = 100_000;
+
+enum Command {
+    Fuzz,
+    Check,
+    Bless,
+    List,
+}
+
+fn main() -> ExitCode {
+    let mut command = Command::Fuzz;
+    let mut iterations = DEFAULT_ITERATIONS;
+    let mut seed = None;
+    let mut names = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut number = || args.next().and_then(|value| value.parse::<u64>().ok());
+        match arg.as_str() {
+            "-n" | "--iterations" => match number() {
+                Some(value) => iterations = value as usize,
+                None => return usage_error("--iterations needs a number"),
+            },
+            "--seed" => match number() {
+                Some(value) => seed = Some(value),
+                None => return usage_error("--seed needs a number"),
+            },
+            "--check" => command = Command::Check,
+            "--bless" => command = Command::Bless,
+            "-l" | "--list" => command = Command::List,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ if arg.starts_with('-') => return usage_error(&format!("unknown option {arg}")),
+            _ => names.push(arg),
+        }
+    }
+
+    match command {
+        Command::Fuzz => fuzz(&names, iterations, seed),
+        Command::Check => check(),
+        Command::Bless => bless(),
+        Command::List => {
+            for codec in CODECS {
+                println!("{}", codec.name);
+            }
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("ipc-fuzz: {message}\n{USAGE}");
+    ExitCode::FAILURE
+}
+
+fn fuzz(names: &[String], iterations: usize, seed: Option<u64>) -> ExitCode {
+    let mut codecs: Vec<&Codec> = Vec::new();
+    for name in names {
+        match codecs::find(name) {
+            Some(codec) => codecs.push(codec),
+            None => return usage_error(&format!("no message called {name}; see --list")),
+        }
+    }
+    if codecs.is_empty() {
+        codecs = CODECS.iter().collect();
+    }
+
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |time| time.as_nanos() as u64)
+    });
+    println!("fuzzing {} messages, {iterations} inputs each, seed {seed}", codecs.len());
+    match fuzz::run(&codecs, iterations, seed) {
+        Ok(()) => {
+            println!("no failures");
+            ExitCode::SUCCESS
+        }
+        Err(failure) => {
+            println!("{}: {}", failure.codec, failure.problem);
+            println!("input: {}", fuzz::hex(&failure.input));
+            println!("replay: ipc-fuzz --seed {seed} --iterations {iterations} {}", failure.codec);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn check() -> ExitCode {
+    let problems = fs::read_to_string(golden::PATH)
+        .map_err(|error| format!("{}: {error}", golden::PATH))
+        .and_then(|text| golden::check(&text));
+    match problems {
+        Ok(problems) if problems.is_empty() => {
+            println!("{} messages match {}", CODECS.len(), golden::PATH);
+            ExitCode::SUCCESS
+        }
+        Ok(problems) => {
+            for problem in problems {
+                println!("{problem}");
+            }
+            ExitCode::FAILURE
+        }
+        Err(error) => {
+            eprintln!("ipc-fuzz: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn bless() -> ExitCode {
+    match fs::write(golden::PATH, golden::render()) {
+        Ok(()) => {
+            println!("wrote {}", golden::PATH);
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("ipc-fuzz: {}: {error}", golden::PATH);
+            ExitCode::FAILURE
+        }
+    }
+}
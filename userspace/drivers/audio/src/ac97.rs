@@ -0,0 +1,248 @@
+//! AC'97 Audio Controller Driver
+//!
+//! Drives the PCM-out channel of an Intel ICH-compatible AC'97 controller
+//! (the `-device AC97` model in QEMU). Playback uses the standard buffer
+//! descriptor list (BDL): 32 small DMA buffers that the controller walks in
+//! a ring while the driver refills the ones it has already played.
+//!
+//! References:
+//! - Intel 82801AA (ICH) AC'97 Programmer's Reference
+//! - https://wiki.osdev.org/AC97
+
+use atom_syscall::dma::{dma_alloc, DmaBuffer, DMA_PAGE_SIZE};
+use atom_syscall::io::pci::{self, PciAddress};
+use atom_syscall::io::{port_read_u16, port_read_u8, port_write_u16, port_write_u32, port_write_u8};
+use atom_syscall::debug::log;
+
+// ============================================================================
+// Register Definitions
+// ============================================================================
+
+// PCI class code for multimedia audio controllers
+const PCI_CLASS_MULTIMEDIA: u8 = 0x04;
+const PCI_SUBCLASS_AUDIO: u8 = 0x01;
+
+// Native Audio Mixer registers (BAR0)
+const NAM_RESET: u16 = 0x00;
+const NAM_MASTER_VOLUME: u16 = 0x02;
+const NAM_PCM_OUT_VOLUME: u16 = 0x18;
+const NAM_EXT_AUDIO_ID: u16 = 0x28;
+const NAM_EXT_AUDIO_CTRL: u16 = 0x2A;
+const NAM_FRONT_DAC_RATE: u16 = 0x2C;
+
+const EXT_AUDIO_VRA: u16 = 1 << 0;
+
+// Native Audio Bus Master registers (BAR1), PCM-out box
+const NABM_PO_BDBAR: u16 = 0x10;
+const NABM_PO_CIV: u16 = 0x14;
+const NABM_PO_LVI: u16 = 0x15;
+const NABM_PO_SR: u16 = 0x16;
+const NABM_PO_CR: u16 = 0x1B;
+const NABM_GLOBAL_CONTROL: u16 = 0x2C;
+
+// Global control bits
+const GC_COLD_RESET: u32 = 1 << 1;
+
+// Channel control bits
+const CR_RUN: u8 = 1 << 0;
+const CR_RESET: u8 = 1 << 1;
+
+// Channel status bits
+const SR_DMA_HALTED: u16 = 1 << 0;
+const SR_CLEAR_MASK: u16 = 0x1C; // LVBCI | BCIS | FIFOE (write 1 to clear)
+
+// Buffer descriptor flags
+const BD_IOC: u16 = 1 << 15;
+
+// ============================================================================
+// Buffer Layout
+// ============================================================================
+
+/// Number of buffer descriptors (fixed by hardware)
+pub const BDL_ENTRIES: usize = 32;
+
+/// Samples (not frames) per DMA buffer: 256 stereo frames, ~5.3 ms at 48 kHz
+pub const BUFFER_SAMPLES: usize = 512;
+
+const BUFFER_BYTES: usize = BUFFER_SAMPLES * 2;
+const BDL_BYTES: usize = BDL_ENTRIES * 8;
+const DMA_PAGES: usize = (BDL_BYTES + BDL_ENTRIES * BUFFER_BYTES).div_ceil(DMA_PAGE_SIZE);
+
+// ============================================================================
+// Controller
+// ============================================================================
+
+pub struct Ac97 {
+    nam: u16,
+    nabm: u16,
+    dma: DmaBuffer,
+    next_fill: u8,
+    sample_rate: u32,
+}
+
+impl Ac97 {
+    /// Locate the controller on PCI and bring the PCM-out channel up
+    pub fn probe(sample_rate: u32) -> Option<Self> {
+        let addr = pci::find_class(PCI_CLASS_MULTIMEDIA, PCI_SUBCLASS_AUDIO)?;
+        Self::init(addr, sample_rate)
+    }
+
+    fn init(addr: PciAddress, sample_rate: u32) -> Option<Self> {
+        let (vendor, device) = addr.ids()?;
+        log_hex("AC97: Found controller ", ((vendor as u32) << 16) | device as u32);
+
+        // BAR0/BAR1 are I/O space BARs on AC'97
+        let nam = (addr.bar(0).ok()? & !0x3) as u16;
+        let nabm = (addr.bar(1).ok()? & !0x3) as u16;
+        if nam == 0 || nabm == 0 {
+            log("AC97: Controller has no I/O BARs assigned");
+            return None;
+        }
+
+        addr.enable(pci::COMMAND_IO_SPACE | pci::COMMAND_BUS_MASTER).ok()?;
+
+        let dma = match dma_alloc(DMA_PAGES) {
+            Ok(dma) => dma,
+            Err(_) => {
+                log("AC97: Failed to allocate DMA buffers");
+                return None;
+            }
+        };
+
+        let mut ac97 = Self {
+            nam,
+            nabm,
+            dma,
+            next_fill: 0,
+            sample_rate: 48_000,
+        };
+
+        ac97.reset_codec();
+        ac97.configure_rate(sample_rate);
+        ac97.setup_bdl();
+        ac97.start();
+
+        log("AC97: PCM output running");
+        Some(ac97)
+    }
+
+    fn reset_codec(&self) {
+        // Take the link out of cold reset, then reset the mixer
+        let _ = port_write_u32(self.nabm + NABM_GLOBAL_CONTROL, GC_COLD_RESET);
+        spin_delay(10_000);
+        let _ = port_write_u16(self.nam + NAM_RESET, 0xFFFF);
+        spin_delay(10_000);
+
+        // Unmute master and PCM at 0 dB attenuation
+        let _ = port_write_u16(self.nam + NAM_MASTER_VOLUME, 0x0000);
+        let _ = port_write_u16(self.nam + NAM_PCM_OUT_VOLUME, 0x0808);
+    }
+
+    fn configure_rate(&mut self, rate: u32) {
+        let ext_id = port_read_u16(self.nam + NAM_EXT_AUDIO_ID).unwrap_or(0);
+        if ext_id & EXT_AUDIO_VRA == 0 {
+            // Fixed 48 kHz codec
+            return;
+        }
+
+        let ctrl = port_read_u16(self.nam + NAM_EXT_AUDIO_CTRL).unwrap_or(0);
+        let _ = port_write_u16(self.nam + NAM_EXT_AUDIO_CTRL, ctrl | EXT_AUDIO_VRA);
+        let _ = port_write_u16(self.nam + NAM_FRONT_DAC_RATE, rate as u16);
+        self.sample_rate = port_read_u16(self.nam + NAM_FRONT_DAC_RATE)
+            .map(|r| r as u32)
+            .unwrap_or(48_000);
+    }
+
+    fn setup_bdl(&self) {
+        // Reset the channel before touching its descriptor list
+        let _ = port_write_u8(self.nabm + NABM_PO_CR, CR_RESET);
+        for _ in 0..1000 {
+            if port_read_u8(self.nabm + NABM_PO_CR).unwrap_or(0) & CR_RESET == 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+
+        let bdl = self.dma.as_mut_ptr();
+        for i in 0..BDL_ENTRIES {
+            let buffer_phys = self.dma.phys_at(BDL_BYTES + i * BUFFER_BYTES) as u32;
+            unsafe {
+                let entry = bdl.add(i * 8);
+                (entry as *mut u32).write_volatile(buffer_phys);
+                (entry.add(4) as *mut u16).write_volatile(BUFFER_SAMPLES as u16);
+                (entry.add(6) as *mut u16).write_volatile(BD_IOC);
+            }
+        }
+
+        let _ = port_write_u32(self.nabm + NABM_PO_BDBAR, self.dma.phys as u32);
+    }
+
+    fn start(&mut self) {
+        // Buffers start zeroed, so the whole ring plays silence until filled
+        let _ = port_write_u8(self.nabm + NABM_PO_LVI, (BDL_ENTRIES - 1) as u8);
+        let _ = port_write_u16(self.nabm + NABM_PO_SR, SR_CLEAR_MASK);
+        let _ = port_write_u8(self.nabm + NABM_PO_CR, CR_RUN);
+        self.next_fill = 0;
+    }
+
+    /// Actual output rate negotiated with the codec
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn buffer(&mut self, index: u8) -> &mut [i16] {
+        let offset = BDL_BYTES + index as usize * BUFFER_BYTES;
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self.dma.as_mut_ptr().add(offset) as *mut i16,
+                BUFFER_SAMPLES,
+            )
+        }
+    }
+
+    /// Refill every buffer the controller has finished playing.
+    ///
+    /// `fill` is called once per free buffer and must write exactly
+    /// `BUFFER_SAMPLES` interleaved samples. Returns the number of buffers
+    /// refilled.
+    pub fn pump<F: FnMut(&mut [i16])>(&mut self, mut fill: F) -> usize {
+        let status = port_read_u16(self.nabm + NABM_PO_SR).unwrap_or(0);
+        let _ = port_write_u16(self.nabm + NABM_PO_SR, status & SR_CLEAR_MASK);
+
+        let civ = port_read_u8(self.nabm + NABM_PO_CIV).unwrap_or(0) % BDL_ENTRIES as u8;
+        let mut filled = 0;
+
+        while self.next_fill != civ {
+            fill(self.buffer(self.next_fill));
+            let _ = port_write_u8(self.nabm + NABM_PO_LVI, self.next_fill);
+            self.next_fill = (self.next_fill + 1) % BDL_ENTRIES as u8;
+            filled += 1;
+        }
+
+        // The channel halts when it reaches LVI; kick it again
+        if status & SR_DMA_HALTED != 0 {
+            let _ = port_write_u8(self.nabm + NABM_PO_CR, CR_RUN);
+        }
+
+        filled
+    }
+}
+
+fn spin_delay(iterations: u32) {
+    for _ in 0..iterations {
+        core::hint::spin_loop();
+    }
+}
+
+fn log_hex(prefix: &str, value: u32) {
+    let mut buf = [0u8; 64];
+    let len = prefix.len().min(48);
+    buf[..len].copy_from_slice(&prefix.as_bytes()[..len]);
+    let digits = b"0123456789ABCDEF";
+    for i in 0..8 {
+        buf[len + i] = digits[((value >> (28 - i * 4)) & 0xF) as usize];
+    }
+    if let Ok(s) = core::str::from_utf8(&buf[..len + 8]) {
+        log(s);
+    }
+}
@@ -0,0 +1,201 @@
+//! Userspace Audio Server (AC'97 Driver + Mixer)
+//!
+//! This service runs entirely in Ring 3 (userspace) and:
+//! - Probes PCI for an AC'97 controller and drives its PCM-out DMA ring
+//! - Accepts playback streams from applications over IPC
+//! - Hands each stream a shared-memory PCM ring (see libaudio)
+//! - Mixes all streams and the beep generator into the hardware buffers
+//!
+//! # Architecture
+//!
+//! ```text
+//! Applications ──(Audio* IPC + PCM rings)──> Audio Server ──> AC'97 DMA
+//!                                            (mixer)
+//! ```
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+mod ac97;
+mod mixer;
+
+
+use atom_syscall::ipc::{create_port, PortId};
+use atom_syscall::shm::{self, RegionFlags};
+use atom_syscall::thread::{yield_now, exit};
+use atom_syscall::debug::log;
+
+use libaudio::ring::PcmRing;
+use libaudio::{SAMPLE_RATE, STREAM_RING_BYTES};
+use libipc::discovery;
+use libipc::messages::{
+    AudioBeep, AudioOpenStreamRequest, AudioStreamInfo, AudioVolume, MessageType,
+};
+use libipc::protocol::{get_payload, send_message_async, try_recv_message};
+use libipc::ServiceId;
+
+use ac97::Ac97;
+use mixer::Mixer;
+
+// ============================================================================
+// Constants
+// ============================================================================
+
+/// Maximum number of simultaneously open streams
+const MAX_STREAMS: usize = 16;
+
+// ============================================================================
+// Audio Server
+// ============================================================================
+
+struct AudioServer {
+    device: Ac97,
+    mixer: Mixer,
+    port: PortId,
+    next_stream_id: u32,
+}
+
+impl AudioServer {
+    fn new(device: Ac97, port: PortId) -> Self {
+        let rate = device.sample_rate();
+        Self {
+            device,
+            mixer: Mixer::new(rate),
+            port,
+            next_stream_id: 1,
+        }
+    }
+
+    fn run(&mut self) -> ! {
+        log("Audio Server: Entering main loop");
+
+        let mut buffer = [0u8; 128];
+
+        loop {
+            while let Ok(Some((header, len))) = try_recv_message(self.port, &mut buffer) {
+                let payload = get_payload(&buffer, len);
+                self.handle_request(header.msg_type, payload);
+            }
+
+            let mixer = &mut self.mixer;
+            self.device.pump(|out| mixer.mix(out));
+
+            for region in self.mixer.reap_finished() {
+                let _ = shm::unmap_region(region);
+                let _ = shm::destroy_region(region);
+            }
+
+            yield_now();
+        }
+    }
+
+    fn handle_request(&mut self, msg_type: MessageType, payload: &[u8]) {
+        match msg_type {
+            MessageType::AudioOpenStream => {
+                if let Some(request) = AudioOpenStreamRequest::from_bytes(payload) {
+                    let info = self.open_stream(&request).unwrap_or(AudioStreamInfo {
+                        stream_id: 0,
+                        region_id: 0,
+                        ring_size: 0,
+                    });
+                    let _ = send_message_async(
+                        request.reply_port,
+                        MessageType::AudioStreamOpened,
+                        &info.to_bytes(),
+                    );
+                }
+            }
+            MessageType::AudioCloseStream if payload.len() >= 4 => {
+                let id = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+                self.mixer.close_stream(id);
+            }
+            MessageType::AudioSetVolume => {
+                if let Some(msg) = AudioVolume::from_bytes(payload) {
+                    self.mixer.set_volume(msg.stream_id, msg.volume);
+                }
+            }
+            MessageType::AudioBeep => {
+                if let Some(msg) = AudioBeep::from_bytes(payload) {
+                    self.mixer.beep(msg.frequency_hz, msg.duration_ms);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn open_stream(&mut self, request: &AudioOpenStreamRequest) -> Option<AudioStreamInfo> {
+        // The mixer does not resample yet; clients must match the device
+        if request.sample_rate != self.device.sample_rate() || request.channels != 2 {
+            log("Audio Server: Rejected stream with unsupported format");
+            return None;
+        }
+
+        if self.mixer.stream_count() >= MAX_STREAMS {
+            log("Audio Server: Too many open streams");
+            return None;
+        }
+
+        let id = self.next_stream_id;
+        self.next_stream_id += 1;
+
+        let region = shm::create_region(STREAM_RING_BYTES).ok()?;
+        let base = match shm::map_anywhere(region, RegionFlags::read_write()) {
+            Ok(base) => base,
+            Err(_) => {
+                let _ = shm::destroy_region(region);
+                return None;
+            }
+        };
+
+        let ring = unsafe { PcmRing::init(base, STREAM_RING_BYTES) }?;
+        self.mixer.add_stream(id, region, ring);
+
+        Some(AudioStreamInfo {
+            stream_id: id,
+            region_id: region,
+            ring_size: STREAM_RING_BYTES as u32,
+        })
+    }
+}
+
+// ============================================================================
+// Entry Points
+// ============================================================================
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    main()
+}
+
+fn main() -> ! {
+    log("Audio Server: Starting");
+
+    let device = match Ac97::probe(SAMPLE_RATE) {
+        Some(device) => device,
+        None => {
+            log("Audio Server: No AC'97 controller found");
+            exit(1);
+        }
+    };
+
+    let port = match create_port() {
+        Ok(port) => port,
+        Err(_) => {
+            log("Audio Server: Failed to create service port");
+            exit(1);
+        }
+    };
+
+    if discovery::register_service(ServiceId::Audio, port).is_err() {
+        log("Audio Server: Failed to register the audio service");
+    }
+
+    let mut server = AudioServer::new(device, port);
+    server.run()
+}
+
+atom_syscall::define_global_allocator!();
+
+atom_syscall::define_panic_handler!("Audio Server");
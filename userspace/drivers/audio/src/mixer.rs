@@ -0,0 +1,149 @@
+//! Software Mixer
+//!
+//! Sums every open client stream plus the built-in tone generator into one
+//! interleaved stereo buffer. Mixing is done in i32 and clamped once at the
+//! end, so several loud streams saturate instead of wrapping.
+
+use alloc::vec::Vec;
+
+use libaudio::ring::PcmRing;
+use libaudio::MAX_VOLUME;
+
+use crate::ac97::BUFFER_SAMPLES;
+
+/// Peak amplitude of generated tones (about -12 dBFS)
+const TONE_AMPLITUDE: i32 = 8_000;
+
+/// A client stream being mixed
+pub struct MixStream {
+    pub id: u32,
+    pub region: u64,
+    ring: PcmRing,
+    volume: u8,
+    closing: bool,
+}
+
+/// Square-wave tone used for `AudioBeep`
+struct Tone {
+    phase: u32,
+    half_period: u32,
+    remaining_frames: u32,
+}
+
+pub struct Mixer {
+    streams: Vec<MixStream>,
+    master_volume: u8,
+    tone: Option<Tone>,
+    sample_rate: u32,
+}
+
+impl Mixer {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            streams: Vec::new(),
+            master_volume: MAX_VOLUME,
+            tone: None,
+            sample_rate,
+        }
+    }
+
+    pub fn add_stream(&mut self, id: u32, region: u64, ring: PcmRing) {
+        self.streams.push(MixStream {
+            id,
+            region,
+            ring,
+            volume: MAX_VOLUME,
+            closing: false,
+        });
+    }
+
+    /// Mark a stream as closing; it is dropped once its ring drains
+    pub fn close_stream(&mut self, id: u32) {
+        if let Some(stream) = self.streams.iter_mut().find(|s| s.id == id) {
+            stream.closing = true;
+        }
+    }
+
+    pub fn stream_count(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Set volume for a stream, or the master volume when `id` is 0
+    pub fn set_volume(&mut self, id: u32, volume: u8) {
+        let volume = volume.min(MAX_VOLUME);
+        if id == 0 {
+            self.master_volume = volume;
+        } else if let Some(stream) = self.streams.iter_mut().find(|s| s.id == id) {
+            stream.volume = volume;
+        }
+    }
+
+    /// Start (or replace) the tone generator
+    pub fn beep(&mut self, frequency_hz: u32, duration_ms: u32) {
+        let frequency_hz = frequency_hz.clamp(20, 20_000);
+        self.tone = Some(Tone {
+            phase: 0,
+            half_period: (self.sample_rate / (frequency_hz * 2)).max(1),
+            remaining_frames: self.sample_rate / 1000 * duration_ms,
+        });
+    }
+
+    /// Produce one hardware buffer of interleaved stereo samples
+    pub fn mix(&mut self, out: &mut [i16]) {
+        let mut acc = [0i32; BUFFER_SAMPLES];
+        let mut scratch = [0i16; BUFFER_SAMPLES];
+        let len = out.len().min(BUFFER_SAMPLES);
+
+        for stream in self.streams.iter() {
+            let read = stream.ring.read(&mut scratch[..len]);
+            if read < len && !stream.closing && !stream.ring.is_closed() {
+                stream.ring.mark_underrun();
+            }
+            let volume = stream.volume as i32;
+            for (a, &s) in acc[..read].iter_mut().zip(scratch[..read].iter()) {
+                *a += s as i32 * volume / MAX_VOLUME as i32;
+            }
+        }
+
+        if let Some(tone) = self.tone.as_mut() {
+            for frame in acc[..len].chunks_exact_mut(2) {
+                if tone.remaining_frames == 0 {
+                    break;
+                }
+                let high = (tone.phase / tone.half_period) % 2 == 0;
+                let sample = if high { TONE_AMPLITUDE } else { -TONE_AMPLITUDE };
+                frame[0] += sample;
+                frame[1] += sample;
+                tone.phase = tone.phase.wrapping_add(1);
+                tone.remaining_frames -= 1;
+            }
+            if tone.remaining_frames == 0 {
+                self.tone = None;
+            }
+        }
+
+        let master = self.master_volume as i32;
+        for (o, &a) in out[..len].iter_mut().zip(acc[..len].iter()) {
+            let scaled = a * master / MAX_VOLUME as i32;
+            *o = scaled.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        }
+        for o in out[len..].iter_mut() {
+            *o = 0;
+        }
+    }
+
+    /// Remove streams that are closed and fully drained
+    ///
+    /// Returns the shared regions that can now be released.
+    pub fn reap_finished(&mut self) -> Vec<u64> {
+        let mut finished = Vec::new();
+        self.streams.retain(|stream| {
+            let done = (stream.closing || stream.ring.is_closed()) && stream.ring.available() == 0;
+            if done {
+                finished.push(stream.region);
+            }
+            !done
+        });
+        finished
+    }
+}
@@ -0,0 +1,86 @@
+// Software Blitter
+//
+// Copies areas of surfaces into the back buffer, converting each pixel to
+// the framebuffer's channel order on the way:
+// - Native: copied row by row as is
+// - Rgb32: red and blue swapped
+// - Argb32: blended over the back buffer by its alpha byte
+// - Rgb565: widened to 8 bits per channel
+//
+// Everything is clipped twice: the source rectangle to the surface, and
+// the destination to the back buffer.
+
+use atom_syscall::graphics::{blend_pixel, Color, Framebuffer};
+use libipc::messages::{PixelFormat, Rect};
+
+use crate::surface::Surface;
+
+/// Copy `src` of `surface` to the back buffer moved by (`x`, `y`)
+///
+/// Returns the area of the back buffer written, or `None` if nothing of
+/// it landed on the screen.
+pub fn blit(back: &Framebuffer, surface: &Surface, src: &Rect, x: i32, y: i32) -> Option<Rect> {
+    let bounds = Rect::new(0, 0, surface.width, surface.height);
+    let src = src.intersection(&bounds)?;
+    let (to_x, to_y) = (src.x + x, src.y + y);
+
+    match surface.format {
+        PixelFormat::Rgb565 => blit_rgb565(back, surface, &src, to_x, to_y),
+        format => {
+            let pixels = surface.pixels()?;
+            let area = pixels.sub(src.x as u32, src.y as u32, src.width, src.height);
+            match format {
+                PixelFormat::Rgb32 => back.blit_with(to_x, to_y, &area, |_, pixel| swap_red_blue(pixel)),
+                PixelFormat::Argb32 => back.blit_with(to_x, to_y, &area, blend_argb),
+                _ => back.blit(to_x, to_y, &area),
+            }
+        }
+    }
+
+    let screen = Rect::new(0, 0, back.width(), back.height());
+    Rect::new(to_x, to_y, src.width, src.height).intersection(&screen)
+}
+
+fn blit_rgb565(back: &Framebuffer, surface: &Surface, src: &Rect, to_x: i32, to_y: i32) {
+    for row in 0..src.height {
+        let Some(line) = surface.row16(src.y as u32 + row) else {
+            return;
+        };
+        let py = to_y + row as i32;
+        if py < 0 {
+            continue;
+        }
+        let line = &line[src.x as usize..][..src.width as usize];
+        for (col, &pixel) in line.iter().enumerate() {
+            let px = to_x + col as i32;
+            if px >= 0 {
+                back.set_pixel(px as u32, py as u32, rgb565_to_native(pixel));
+            }
+        }
+    }
+}
+
+#[inline]
+fn swap_red_blue(pixel: u32) -> u32 {
+    (pixel & 0x00FF00) | ((pixel >> 16) & 0xFF) | ((pixel & 0xFF) << 16)
+}
+
+/// `pixel` over `below` by its alpha byte; fully opaque and fully clear
+/// pixels skip the arithmetic
+#[inline]
+fn blend_argb(below: u32, pixel: u32) -> u32 {
+    match pixel >> 24 {
+        0 => below,
+        255 => pixel & 0xFF_FFFF,
+        alpha => blend_pixel(below, pixel, alpha),
+    }
+}
+
+#[inline]
+fn rgb565_to_native(pixel: u16) -> u32 {
+    let r = (pixel >> 11) as u8 & 0x1F;
+    let g = (pixel >> 5) as u8 & 0x3F;
+    let b = pixel as u8 & 0x1F;
+    // Repeat the top bits in the low ones so full intensity maps to 255
+    Color::new((r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)).to_bgr32()
+}
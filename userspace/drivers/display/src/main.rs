@@ -1,79 +1,318 @@
-// Display Driver - Userspace Framebuffer Manager
+// Display Driver - Graphics Service
 //
-// This is a userspace driver that manages the system framebuffer and provides
-// a compositing service for other applications. It runs entirely in Ring 3
-// and communicates with the kernel via syscalls.
+// This is a userspace driver that owns the system framebuffer and provides
+// the graphics service (`ServiceId::Graphics`) other processes draw
+// through. It runs entirely in Ring 3 and communicates with the kernel via
+// syscalls.
 //
 // Key responsibilities:
-// - Acquire and manage the framebuffer from the kernel
-// - Provide double buffering for smooth rendering
-// - Expose a compositing API via IPC for other processes
-// - Handle display resolution and format queries
+// - Acquire and manage the framebuffer from the kernel, or drive a
+//   virtio-gpu device when QEMU provides one
+// - Allocate surfaces backed by shared regions (CreateSurface,
+//   DestroySurface)
+// - Blit surface areas into a back buffer, converting pixel formats
+//   (BlitSurface)
+// - Copy only the damaged parts of the back buffer to the screen (Present)
+// - Answer display geometry queries (GetFramebuffer), including which
+//   part of the screen each monitor shows (GetHeads)
+// - List and switch screen resolutions (GetModes, SetMode), rebuilding
+//   the back buffer at the new size
 //
 // Architecture:
 // - Uses atom_syscall library for kernel interaction
-// - Exposes IPC ports for client applications
-// - Manages a software back buffer for composition
+// - Registers one IPC port as the graphics service; requests are handled
+//   in the order they arrive, so a client's blits always land before its
+//   next present
+// - Composes into a software back buffer, so the screen never shows a
+//   half-blitted frame
+// - Presents go to an output (output.rs): the firmware framebuffer, or
+//   virtio-gpu with page flipping and several heads (virtio_gpu.rs)
+//
+// Limitations:
+// - Surfaces are not freed when the client that created them exits
+// - On the firmware framebuffer, resolution switches need the kernel's
+//   mode-setting backend (Bochs/QEMU adapters), otherwise only the boot
+//   mode is listed
 
 #![no_std]
 #![no_main]
 
-use core::panic::PanicInfo;
+extern crate alloc;
+
+mod blit;
+mod output;
+mod surface;
+mod virtio;
+mod virtio_gpu;
 
-use atom_syscall::graphics::{Color, Framebuffer};
-use atom_syscall::thread::{yield_now, exit};
+use alloc::vec::Vec;
+
+use atom_syscall::graphics::{Framebuffer, FramebufferInfo};
+use atom_syscall::ipc::{create_port, PortId};
+use atom_syscall::shm::{self, RegionFlags, RegionId};
+use atom_syscall::thread::exit;
 use atom_syscall::debug::log;
 
+use libipc::discovery;
+use libipc::messages::{
+    self, BlitRequest, CreateSurfaceRequest, DisplayMode, HeadList, MessageType, ModeChanged,
+    ModeList, PixelFormat, PresentDone, Rect, SetModeRequest, SurfaceInfo,
+};
+use libipc::protocol::{get_payload, recv_message, send_message_async};
+use libipc::{ServiceId, MAX_MESSAGE_SIZE};
+
+use output::Output;
+use surface::Surfaces;
+
+// ============================================================================
+// Constants
+// ============================================================================
+
+/// Damaged areas tracked before they collapse into their bounding
+/// rectangle
+const MAX_DAMAGE_RECTS: usize = 16;
+
 // ============================================================================
 // Display Driver State
 // ============================================================================
 
 struct DisplayDriver {
-    framebuffer: Framebuffer,
-    back_buffer: Option<&'static mut [u32]>,
-    width: u32,
-    height: u32,
-    stride: u32,
-    dirty: bool,
+    output: Output,
+    /// Off-screen copy of the screen that blits land in
+    back: Framebuffer,
+    /// Shared region behind `back`
+    back_region: RegionId,
+    surfaces: Surfaces,
+    /// Areas of the back buffer blitted since the last present
+    damage: Vec<Rect>,
+    port: PortId,
 }
 
 impl DisplayDriver {
-    fn new(fb: Framebuffer) -> Self {
-        let width = fb.width();
-        let height = fb.height();
-        let stride = fb.stride();
-        
+    fn new(output: Output, back: Framebuffer, back_region: RegionId, port: PortId) -> Self {
         Self {
-            framebuffer: fb,
-            back_buffer: None, // TODO: Allocate via shared memory
-            width,
-            height,
-            stride,
-            dirty: false,
+            output,
+            back,
+            back_region,
+            surfaces: Surfaces::new(),
+            damage: Vec::new(),
+            port,
+        }
+    }
+
+    fn run(&mut self) -> ! {
+        log("Display Driver: Entering main loop");
+
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+
+        loop {
+            let Ok((header, len)) = recv_message(self.port, &mut buffer) else {
+                continue;
+            };
+            let payload = get_payload(&buffer, len);
+            self.handle_request(header.msg_type, payload);
+        }
+    }
+
+    fn handle_request(&mut self, msg_type: MessageType, payload: &[u8]) {
+        match msg_type {
+            MessageType::GetFramebuffer => {
+                if let Some(reply) = read_port(payload) {
+                    let _ = send_message_async(reply, MessageType::FramebufferInfo, &self.info().to_bytes());
+                }
+            }
+            MessageType::CreateSurface => {
+                if let Some(request) = CreateSurfaceRequest::from_bytes(payload) {
+                    let info = self.create_surface(&request);
+                    let _ = send_message_async(
+                        request.reply_port,
+                        MessageType::SurfaceCreated,
+                        &info.to_bytes(),
+                    );
+                }
+            }
+            MessageType::DestroySurface if payload.len() >= 4 => {
+                let id = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+                self.surfaces.destroy(id);
+            }
+            MessageType::BlitSurface => {
+                if let Some(request) = BlitRequest::from_bytes(payload) {
+                    self.blit(&request);
+                }
+            }
+            MessageType::InvalidateRect => {
+                if let Some(rect) = Rect::from_bytes(payload) {
+                    self.add_damage(rect);
+                }
+            }
+            MessageType::Present => {
+                let pixels = self.present();
+                if let Some(reply) = read_port(payload).filter(|&port| port != 0) {
+                    let done = PresentDone { pixels };
+                    let _ = send_message_async(reply, MessageType::PresentDone, &done.to_bytes());
+                }
+            }
+            MessageType::GetModes => {
+                if let Some(reply) = read_port(payload) {
+                    let list = ModeList { modes: self.output.modes() };
+                    let _ = send_message_async(reply, MessageType::ModeList, &list.to_bytes());
+                }
+            }
+            MessageType::GetHeads => {
+                if let Some(reply) = read_port(payload) {
+                    let list = HeadList { heads: self.output.heads() };
+                    let _ = send_message_async(reply, MessageType::HeadList, &list.to_bytes());
+                }
+            }
+            MessageType::SetMode => {
+                if let Some(request) = SetModeRequest::from_bytes(payload) {
+                    let success = self.set_mode(request.width, request.height);
+                    let changed = ModeChanged {
+                        success,
+                        mode: DisplayMode {
+                            width: self.back.width(),
+                            height: self.back.height(),
+                        },
+                    };
+                    let _ = send_message_async(
+                        request.reply_port,
+                        MessageType::ModeChanged,
+                        &changed.to_bytes(),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Screen geometry, which the back buffer shares; the framebuffer
+    /// itself stays with the driver, so no address is given out
+    fn info(&self) -> messages::FramebufferInfo {
+        let bpp = self.back.bytes_per_pixel();
+        messages::FramebufferInfo {
+            address: 0,
+            width: self.back.width(),
+            height: self.back.height(),
+            stride: self.back.stride(),
+            bytes_per_pixel: bpp as u32,
+            size: self.back.stride() as u64 * self.back.height() as u64 * bpp as u64,
+        }
+    }
+
+    /// Switch resolution and rebuild the back buffer for it; on failure
+    /// the old mode stays
+    fn set_mode(&mut self, width: u32, height: u32) -> bool {
+        if (width, height) == (self.back.width(), self.back.height()) {
+            return true;
+        }
+        if !self.output.set_mode(width, height) {
+            return false;
+        }
+
+        // The old back buffer has to go first: the new one is mapped at
+        // the same address
+        let _ = shm::unmap_region(self.back_region);
+        let _ = shm::destroy_region(self.back_region);
+        let Some((back_region, back)) = allocate_back_buffer(&self.output.layout()) else {
+            log("Display Driver: Failed to allocate back buffer");
+            exit(1);
+        };
+        self.output.snapshot(&back);
+
+        self.back = back;
+        self.back_region = back_region;
+        self.damage.clear();
+        log("Display Driver: Mode switched");
+        true
+    }
+
+    fn create_surface(&mut self, request: &CreateSurfaceRequest) -> SurfaceInfo {
+        match self.surfaces.create(request.width, request.height, request.format) {
+            Some(surface) => surface.info(),
+            None => {
+                log("Display Driver: Could not create surface");
+                SurfaceInfo {
+                    surface_id: 0,
+                    region_id: 0,
+                    width: 0,
+                    height: 0,
+                    stride: 0,
+                    format: PixelFormat::Native,
+                }
+            }
         }
     }
 
-    /// Clear the display to a solid color
-    fn clear(&self, color: Color) {
-        self.framebuffer.fill_rect(0, 0, self.width, self.height, color);
+    fn blit(&mut self, request: &BlitRequest) {
+        let Some(surface) = self.surfaces.get(request.surface_id) else {
+            return;
+        };
+
+        for src in &request.rects {
+            if let Some(area) = blit::blit(&self.back, surface, src, request.x, request.y) {
+                self.damage.push(area);
+            }
+        }
+        self.merge_damage();
     }
 
-    /// Draw a rectangle
-    fn fill_rect(&self, x: u32, y: u32, w: u32, h: u32, color: Color) {
-        self.framebuffer.fill_rect(x, y, w, h, color);
+    /// Mark an area of the screen to be copied at the next present
+    fn add_damage(&mut self, rect: Rect) {
+        let screen = Rect::new(0, 0, self.back.width(), self.back.height());
+        if let Some(rect) = rect.intersection(&screen) {
+            self.damage.push(rect);
+            self.merge_damage();
+        }
     }
 
-    /// Draw text
-    fn draw_text(&self, x: u32, y: u32, text: &str, fg: Color, bg: Color) {
-        self.framebuffer.draw_string(x, y, text, fg, bg);
+    /// Past `MAX_DAMAGE_RECTS`, replace the damage with its bounding
+    /// rectangle; copying some unchanged pixels is cheaper than many
+    /// small copies
+    fn merge_damage(&mut self) {
+        if self.damage.len() > MAX_DAMAGE_RECTS {
+            let bounds = self.damage.iter().fold(self.damage[0], |acc, r| acc.union(r));
+            self.damage.clear();
+            self.damage.push(bounds);
+        }
     }
 
-    /// Get display dimensions
-    fn dimensions(&self) -> (u32, u32) {
-        (self.width, self.height)
+    /// Copy the damaged areas of the back buffer to the screen; returns
+    /// the number of pixels copied
+    fn present(&mut self) -> u64 {
+        let damage = core::mem::take(&mut self.damage);
+        self.output.present(&self.back, &damage)
     }
 }
 
+/// The u64 port a request's payload starts with
+fn read_port(payload: &[u8]) -> Option<PortId> {
+    Some(u64::from_le_bytes(payload.get(..8)?.try_into().ok()?))
+}
+
+/// Allocate a back buffer laid out like the screen, `front`
+fn allocate_back_buffer(front: &FramebufferInfo) -> Option<(RegionId, Framebuffer)> {
+    let size = front.size;
+
+    let region = shm::create_region(size).ok()?;
+    let base = match shm::map_anywhere(region, RegionFlags::read_write()) {
+        Ok(base) => base,
+        Err(_) => {
+            let _ = shm::destroy_region(region);
+            return None;
+        }
+    };
+
+    // The region is mapped for the rest of the driver's life and only
+    // drawn to through this handle
+    let back = unsafe {
+        Framebuffer::from_info(FramebufferInfo {
+            address: base as usize,
+            ..*front
+        })
+    };
+    Some((region, back))
+}
+
 // ============================================================================
 // Main Entry Point
 // ============================================================================
@@ -86,53 +325,52 @@ pub extern "C" fn _start() -> ! {
 fn main() -> ! {
     log("Display Driver: Starting...");
 
-    // Acquire framebuffer from kernel
-    let fb = match Framebuffer::new() {
-        Some(fb) => fb,
+    // Acquire a virtio GPU or the framebuffer from the kernel
+    let output = match Output::probe() {
+        Some(output) => output,
         None => {
             log("Display Driver: Failed to acquire framebuffer");
             exit(1);
         }
     };
 
-    let driver = DisplayDriver::new(fb);
-    
     log("Display Driver: Framebuffer acquired");
 
-    // Display driver info
-    let (width, height) = driver.dimensions();
-    
-    // Clear to dark theme background
-    driver.clear(Color::new(46, 52, 64));
+    let (back_region, back) = match allocate_back_buffer(&output.layout()) {
+        Some(back) => back,
+        None => {
+            log("Display Driver: Failed to allocate back buffer");
+            exit(1);
+        }
+    };
+    output.snapshot(&back);
 
-    // Draw status bar
-    driver.fill_rect(0, 0, width, 24, Color::new(36, 41, 51));
-    driver.draw_text(8, 4, "Atom Display Driver", Color::new(136, 192, 208), Color::new(36, 41, 51));
+    let port = match create_port() {
+        Ok(port) => port,
+        Err(_) => {
+            log("Display Driver: Failed to create service port");
+            exit(1);
+        }
+    };
 
-    // Draw driver info
-    driver.draw_text(16, 40, "Display Driver Active", Color::WHITE, Color::new(46, 52, 64));
-    driver.draw_text(16, 60, "Waiting for IPC clients...", Color::new(200, 200, 200), Color::new(46, 52, 64));
+    if discovery::register_service(ServiceId::Graphics, port).is_err() {
+        log("Display Driver: Failed to register the graphics service");
+    }
 
     log("Display Driver: Ready for IPC connections");
 
-    // Main driver loop - wait for IPC messages
-    loop {
-        // TODO: Implement IPC message handling
-        // - CreateSurface(width, height) -> surface_id
-        // - DestroySurface(surface_id)
-        // - BlitSurface(surface_id, x, y)
-        // - Present() - flip back buffer to front
-
-        yield_now();
-    }
+    let mut driver = DisplayDriver::new(output, back, back_region, port);
+    driver.run()
 }
 
+// ============================================================================
+// Heap
+// ============================================================================
+
+atom_syscall::define_global_allocator!();
+
 // ============================================================================
 // Panic Handler
 // ============================================================================
 
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    log("Display Driver: PANIC!");
-    exit(0xFF);
-}
+atom_syscall::define_panic_handler!("Display Driver");
@@ -0,0 +1,130 @@
+// Outputs
+//
+// Where presented frames end up: a virtio-gpu device when QEMU provides
+// one, otherwise the framebuffer the firmware set up. The rest of the
+// driver composes into its back buffer the same way for both.
+
+use alloc::vec::Vec;
+
+use atom_syscall::debug::log;
+use atom_syscall::graphics::{set_video_mode, video_modes, Framebuffer, FramebufferInfo, VideoMode};
+use atom_syscall::thread::exit;
+use libipc::messages::{DisplayHead, DisplayMode, Rect};
+
+use crate::virtio_gpu::VirtioGpu;
+
+/// Most modes listed in a `ModeList`
+const MAX_MODES: usize = 33;
+
+pub enum Output {
+    /// The firmware framebuffer, written directly
+    Framebuffer(Framebuffer),
+    /// A virtio-gpu device, fed through host resources
+    VirtioGpu(VirtioGpu),
+}
+
+impl Output {
+    /// The virtio GPU if there is one, otherwise the framebuffer
+    pub fn probe() -> Option<Self> {
+        if let Some(gpu) = VirtioGpu::probe() {
+            log(if gpu.head_count() > 1 {
+                "Display Driver: Using virtio-gpu, several heads"
+            } else {
+                "Display Driver: Using virtio-gpu"
+            });
+            return Some(Self::VirtioGpu(gpu));
+        }
+        Framebuffer::new().map(Self::Framebuffer)
+    }
+
+    /// Screen geometry, which the back buffer copies; only the framebuffer
+    /// has an address
+    pub fn layout(&self) -> FramebufferInfo {
+        match self {
+            Self::Framebuffer(fb) => FramebufferInfo {
+                address: fb.address(),
+                width: fb.width(),
+                height: fb.height(),
+                stride: fb.stride(),
+                bytes_per_pixel: fb.bytes_per_pixel() as u32,
+                size: fb.stride() as usize * fb.height() as usize * fb.bytes_per_pixel(),
+            },
+            Self::VirtioGpu(gpu) => FramebufferInfo {
+                address: 0,
+                width: gpu.width(),
+                height: gpu.height(),
+                stride: gpu.width(),
+                bytes_per_pixel: 4,
+                size: gpu.width() as usize * gpu.height() as usize * 4,
+            },
+        }
+    }
+
+    /// Copy what is on screen into `back`; a virtio GPU starts out blank
+    pub fn snapshot(&self, back: &Framebuffer) {
+        if let Self::Framebuffer(fb) = self {
+            back.blit(0, 0, &fb.pixels());
+        }
+    }
+
+    /// The monitors showing the screen, left to right; the framebuffer
+    /// is a single one showing all of it
+    pub fn heads(&self) -> Vec<DisplayHead> {
+        match self {
+            Self::Framebuffer(fb) => alloc::vec![DisplayHead {
+                scanout: 0,
+                x: 0,
+                width: fb.width(),
+                height: fb.height(),
+            }],
+            Self::VirtioGpu(gpu) => gpu.heads(),
+        }
+    }
+
+    /// Resolutions available, the current one first
+    pub fn modes(&mut self) -> Vec<DisplayMode> {
+        match self {
+            Self::Framebuffer(_) => {
+                let mut modes = [VideoMode::default(); MAX_MODES];
+                let count = video_modes(&mut modes).min(MAX_MODES);
+                modes[..count]
+                    .iter()
+                    .map(|mode| DisplayMode { width: mode.width, height: mode.height })
+                    .collect()
+            }
+            Self::VirtioGpu(gpu) => gpu.modes(),
+        }
+    }
+
+    /// Switch resolution; on failure the old mode stays
+    pub fn set_mode(&mut self, width: u32, height: u32) -> bool {
+        match self {
+            Self::Framebuffer(fb) => {
+                if set_video_mode(width, height).is_err() {
+                    log("Display Driver: Mode switch refused");
+                    return false;
+                }
+                let Some(framebuffer) = Framebuffer::new() else {
+                    log("Display Driver: Lost the framebuffer after a mode switch");
+                    exit(1);
+                };
+                *fb = framebuffer;
+                true
+            }
+            Self::VirtioGpu(gpu) => gpu.set_mode(width, height),
+        }
+    }
+
+    /// Show the `damage` of `back`; returns the number of pixels copied
+    pub fn present(&mut self, back: &Framebuffer, damage: &[Rect]) -> u64 {
+        match self {
+            Self::Framebuffer(fb) => {
+                let areas = damage
+                    .iter()
+                    .map(|area| (area.x as u32, area.y as u32, area.width, area.height));
+                fb.copy_areas(&back.pixels(), areas)
+            }
+            Self::VirtioGpu(gpu) => gpu.present(back, damage),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// Surfaces
+//
+// A surface is a shared region of pixels a client draws into and the
+// service reads from when the client blits it. The service creates the
+// region, maps it wherever the kernel finds room and hands the region id
+// to the client, which does the same.
+//
+// Surfaces live in a fixed table of `MAX_SURFACES` slots.
+
+use atom_syscall::graphics::Pixels;
+use atom_syscall::shm::{self, RegionFlags};
+use libipc::messages::{PixelFormat, SurfaceInfo, MAX_SURFACE_BYTES};
+
+/// Surfaces that can exist at once, one `MAX_SURFACE_BYTES` slot each
+pub const MAX_SURFACES: usize = 16;
+
+pub struct Surface {
+    pub id: u32,
+    region: u64,
+    base: usize,
+    pub width: u32,
+    pub height: u32,
+    /// Row length in pixels
+    pub stride: u32,
+    pub format: PixelFormat,
+}
+
+impl Surface {
+    /// The surface as 32-bit pixels, or `None` for 16-bit formats
+    pub fn pixels(&self) -> Option<Pixels<'_>> {
+        if self.format.bytes_per_pixel() != 4 {
+            return None;
+        }
+        let data = unsafe { core::slice::from_raw_parts(self.base as *const u32, self.len()) };
+        Pixels::new(data, self.width, self.height, self.stride)
+    }
+
+    /// Row `y` of a 16-bit surface, or `None` off the surface or for
+    /// 32-bit formats
+    pub fn row16(&self, y: u32) -> Option<&[u16]> {
+        if self.format.bytes_per_pixel() != 2 || y >= self.height {
+            return None;
+        }
+        let start = self.base + y as usize * self.stride as usize * 2;
+        Some(unsafe { core::slice::from_raw_parts(start as *const u16, self.width as usize) })
+    }
+
+    /// Pixels from the first to the last one used
+    fn len(&self) -> usize {
+        match self.height {
+            0 => 0,
+            height => (height - 1) as usize * self.stride as usize + self.width as usize,
+        }
+    }
+
+    pub fn info(&self) -> SurfaceInfo {
+        SurfaceInfo {
+            surface_id: self.id,
+            region_id: self.region,
+            width: self.width,
+            height: self.height,
+            stride: self.stride,
+            format: self.format,
+        }
+    }
+}
+
+pub struct Surfaces {
+    slots: [Option<Surface>; MAX_SURFACES],
+    next_id: u32,
+}
+
+impl Surfaces {
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { None }; MAX_SURFACES],
+            next_id: 1,
+        }
+    }
+
+    /// Allocate and map a surface; `None` if the size is out of range, the
+    /// table is full or the region could not be set up
+    pub fn create(&mut self, width: u32, height: u32, format: PixelFormat) -> Option<&Surface> {
+        let size = width as usize * height as usize * format.bytes_per_pixel() as usize;
+        if size == 0 || size > MAX_SURFACE_BYTES {
+            return None;
+        }
+        let slot = self.slots.iter().position(Option::is_none)?;
+
+        let region = shm::create_region(size).ok()?;
+        let base = match shm::map_anywhere(region, RegionFlags::read_write()) {
+            Ok(base) => base as usize,
+            Err(_) => {
+                let _ = shm::destroy_region(region);
+                return None;
+            }
+        };
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+
+        self.slots[slot] = Some(Surface {
+            id,
+            region,
+            base,
+            width,
+            height,
+            stride: width,
+            format,
+        });
+        self.slots[slot].as_ref()
+    }
+
+    pub fn get(&self, id: u32) -> Option<&Surface> {
+        self.slots.iter().flatten().find(|surface| surface.id == id)
+    }
+
+    /// Free a surface; the region goes once the client unmaps it too
+    pub fn destroy(&mut self, id: u32) -> bool {
+        let Some(slot) = self.slots.iter_mut().find(|s| s.as_ref().is_some_and(|s| s.id == id)) else {
+            return false;
+        };
+        if let Some(surface) = slot.take() {
+            let _ = shm::unmap_region(surface.region);
+            let _ = shm::destroy_region(surface.region);
+        }
+        true
+    }
+}
@@ -0,0 +1,323 @@
+// Virtio over PCI
+//
+// Just enough of the virtio 1.x PCI transport for the virtio-gpu backend:
+// the configuration structures are found through the vendor capabilities
+// of the function, features are negotiated down to VERSION_1 and requests
+// go through split virtqueues.
+//
+// Queues are polled: a request is posted and the driver waits for the
+// device to hand it back on the used ring, one request at a time, so no
+// interrupt handler is needed.
+//
+// References:
+// - Virtual I/O Device (VIRTIO) Version 1.2, sections 2.6, 2.7 and 4.1
+
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+
+use atom_syscall::debug::log;
+use atom_syscall::dma::{dma_alloc, DmaBuffer};
+use atom_syscall::io::map_mmio;
+use atom_syscall::io::pci::{self, PciAddress};
+use atom_syscall::thread::{get_time_ms, yield_now};
+
+// ============================================================================
+// Register Definitions
+// ============================================================================
+
+const VENDOR_ID: u16 = 0x1AF4;
+
+/// Modern device IDs are this plus the virtio device type
+const DEVICE_ID_BASE: u16 = 0x1040;
+
+// Virtio vendor capability: cfg_type, bar, offset, length and (for the
+// notify structure) the queue notify multiplier
+const CAP_CFG_TYPE: u8 = 3;
+const CAP_BAR: u8 = 4;
+const CAP_OFFSET: u8 = 8;
+const CAP_LENGTH: u8 = 12;
+const CAP_NOTIFY_MULTIPLIER: u8 = 16;
+
+const CFG_COMMON: u8 = 1;
+const CFG_NOTIFY: u8 = 2;
+
+// Common configuration structure
+const COMMON_DEVICE_FEATURE_SELECT: usize = 0x00;
+const COMMON_DEVICE_FEATURE: usize = 0x04;
+const COMMON_DRIVER_FEATURE_SELECT: usize = 0x08;
+const COMMON_DRIVER_FEATURE: usize = 0x0C;
+const COMMON_NUM_QUEUES: usize = 0x12;
+const COMMON_DEVICE_STATUS: usize = 0x14;
+const COMMON_QUEUE_SELECT: usize = 0x16;
+const COMMON_QUEUE_SIZE: usize = 0x18;
+const COMMON_QUEUE_ENABLE: usize = 0x1C;
+const COMMON_QUEUE_NOTIFY_OFF: usize = 0x1E;
+const COMMON_QUEUE_DESC: usize = 0x20;
+const COMMON_QUEUE_DRIVER: usize = 0x28;
+const COMMON_QUEUE_DEVICE: usize = 0x30;
+
+// Device status bits
+const STATUS_ACKNOWLEDGE: u8 = 1 << 0;
+const STATUS_DRIVER: u8 = 1 << 1;
+const STATUS_DRIVER_OK: u8 = 1 << 2;
+const STATUS_FEATURES_OK: u8 = 1 << 3;
+const STATUS_FAILED: u8 = 1 << 7;
+
+/// VIRTIO_F_VERSION_1: bit 32, the first bit of feature word 1
+const FEATURE_VERSION_1: u32 = 1 << 0;
+
+// Descriptor flags
+const DESC_NEXT: u16 = 1 << 0;
+const DESC_WRITE: u16 = 1 << 1;
+
+// ============================================================================
+// Queue Layout
+// ============================================================================
+
+/// Descriptors per queue, plenty for one request at a time
+const QUEUE_SIZE: u16 = 16;
+
+/// Descriptor table, available ring and used ring share one DMA page
+const DESC_OFFSET: usize = 0;
+const AVAIL_OFFSET: usize = 256;
+const USED_OFFSET: usize = 512;
+
+/// How long the device gets to hand a request back
+const REQUEST_TIMEOUT_MS: u64 = 1000;
+
+// ============================================================================
+// Device
+// ============================================================================
+
+/// A virtio device on PCI, set up up to the point its queues are created
+pub struct VirtioPci {
+    common: usize,
+    notify: usize,
+    notify_multiplier: u32,
+}
+
+impl VirtioPci {
+    /// Find the first device of virtio `device_type`, map its registers,
+    /// reset it and agree on features
+    pub fn probe(device_type: u16) -> Option<Self> {
+        let addr = pci::find_device(VENDOR_ID, DEVICE_ID_BASE + device_type)?;
+        addr.enable(pci::COMMAND_MEMORY_SPACE | pci::COMMAND_BUS_MASTER).ok()?;
+
+        let mut common = None;
+        let mut notify = None;
+        for (id, offset) in addr.capabilities() {
+            if id != pci::CAP_VENDOR {
+                continue;
+            }
+            match addr.read_u8(offset + CAP_CFG_TYPE).ok()? {
+                CFG_COMMON => common = map_structure(&addr, offset),
+                CFG_NOTIFY => {
+                    let multiplier = addr.read_u32(offset + CAP_NOTIFY_MULTIPLIER).ok()?;
+                    notify = map_structure(&addr, offset).map(|base| (base, multiplier));
+                }
+                _ => {}
+            }
+        }
+
+        let (notify, notify_multiplier) = notify?;
+        let virtio = Self {
+            common: common?,
+            notify,
+            notify_multiplier,
+        };
+        virtio.negotiate().then_some(virtio)
+    }
+
+    /// Reset the device and accept only VERSION_1 of what it offers
+    fn negotiate(&self) -> bool {
+        self.write_common_u8(COMMON_DEVICE_STATUS, 0);
+        let deadline = get_time_ms() + REQUEST_TIMEOUT_MS;
+        while self.read_common_u8(COMMON_DEVICE_STATUS) != 0 {
+            if get_time_ms() >= deadline {
+                return false;
+            }
+            yield_now();
+        }
+        self.write_common_u8(COMMON_DEVICE_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        self.write_common_u32(COMMON_DEVICE_FEATURE_SELECT, 1);
+        if self.read_common_u32(COMMON_DEVICE_FEATURE) & FEATURE_VERSION_1 == 0 {
+            log("Display Driver: virtio device does not offer VERSION_1");
+            self.fail();
+            return false;
+        }
+        self.write_common_u32(COMMON_DRIVER_FEATURE_SELECT, 0);
+        self.write_common_u32(COMMON_DRIVER_FEATURE, 0);
+        self.write_common_u32(COMMON_DRIVER_FEATURE_SELECT, 1);
+        self.write_common_u32(COMMON_DRIVER_FEATURE, FEATURE_VERSION_1);
+
+        let status = STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK;
+        self.write_common_u8(COMMON_DEVICE_STATUS, status);
+        if self.read_common_u8(COMMON_DEVICE_STATUS) & STATUS_FEATURES_OK == 0 {
+            self.fail();
+            return false;
+        }
+        true
+    }
+
+    /// Set up queue `index` with `QUEUE_SIZE` descriptors
+    pub fn queue(&self, index: u16) -> Option<Virtqueue> {
+        if index >= self.read_common_u16(COMMON_NUM_QUEUES) {
+            return None;
+        }
+        self.write_common_u16(COMMON_QUEUE_SELECT, index);
+        let offered = self.read_common_u16(COMMON_QUEUE_SIZE);
+        if offered == 0 {
+            return None;
+        }
+        let size = offered.min(QUEUE_SIZE);
+
+        let rings = dma_alloc(1).ok()?;
+        self.write_common_u16(COMMON_QUEUE_SIZE, size);
+        self.write_common_u64(COMMON_QUEUE_DESC, rings.phys_at(DESC_OFFSET));
+        self.write_common_u64(COMMON_QUEUE_DRIVER, rings.phys_at(AVAIL_OFFSET));
+        self.write_common_u64(COMMON_QUEUE_DEVICE, rings.phys_at(USED_OFFSET));
+        let notify_off = self.read_common_u16(COMMON_QUEUE_NOTIFY_OFF) as usize;
+        self.write_common_u16(COMMON_QUEUE_ENABLE, 1);
+
+        Some(Virtqueue {
+            index,
+            size,
+            rings,
+            notify: self.notify + notify_off * self.notify_multiplier as usize,
+            next_avail: 0,
+            last_used: 0,
+        })
+    }
+
+    /// Tell the device its queues are set up and it may start
+    pub fn ready(&self) {
+        let status = self.read_common_u8(COMMON_DEVICE_STATUS);
+        self.write_common_u8(COMMON_DEVICE_STATUS, status | STATUS_DRIVER_OK);
+    }
+
+    /// Give up on the device, telling it so
+    pub fn fail(&self) {
+        let status = self.read_common_u8(COMMON_DEVICE_STATUS);
+        self.write_common_u8(COMMON_DEVICE_STATUS, status | STATUS_FAILED);
+    }
+
+    fn read_common_u8(&self, offset: usize) -> u8 {
+        unsafe { read_volatile((self.common + offset) as *const u8) }
+    }
+
+    fn write_common_u8(&self, offset: usize, value: u8) {
+        unsafe { write_volatile((self.common + offset) as *mut u8, value) }
+    }
+
+    fn read_common_u16(&self, offset: usize) -> u16 {
+        unsafe { read_volatile((self.common + offset) as *const u16) }
+    }
+
+    fn write_common_u16(&self, offset: usize, value: u16) {
+        unsafe { write_volatile((self.common + offset) as *mut u16, value) }
+    }
+
+    fn read_common_u32(&self, offset: usize) -> u32 {
+        unsafe { read_volatile((self.common + offset) as *const u32) }
+    }
+
+    fn write_common_u32(&self, offset: usize, value: u32) {
+        unsafe { write_volatile((self.common + offset) as *mut u32, value) }
+    }
+
+    /// 64-bit fields are written as two halves, low first
+    fn write_common_u64(&self, offset: usize, value: u64) {
+        self.write_common_u32(offset, value as u32);
+        self.write_common_u32(offset + 4, (value >> 32) as u32);
+    }
+}
+
+/// Map the structure a virtio capability at `offset` points at; returns
+/// its address
+fn map_structure(addr: &PciAddress, offset: u8) -> Option<usize> {
+    let bar = addr.read_u8(offset + CAP_BAR).ok()?;
+    let start = addr.read_u32(offset + CAP_OFFSET).ok()? as u64;
+    let length = addr.read_u32(offset + CAP_LENGTH).ok()? as usize;
+    if bar > 5 || length == 0 {
+        return None;
+    }
+
+    let base = addr.bar_address(bar).ok()??;
+    map_mmio(base + start, length).ok()
+}
+
+// ============================================================================
+// Virtqueue
+// ============================================================================
+
+/// One buffer of a request: device-readable, or written by the device
+#[derive(Clone, Copy)]
+pub struct Buffer {
+    pub phys: u64,
+    pub len: u32,
+    pub device_writes: bool,
+}
+
+/// A split virtqueue with one request in flight at most
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    rings: DmaBuffer,
+    /// Address the queue's notifications are written to
+    notify: usize,
+    /// Index of the next available ring entry
+    next_avail: u16,
+    /// Used ring index consumed so far
+    last_used: u16,
+}
+
+impl Virtqueue {
+    /// Post a request made of `chain` and wait until the device is done
+    /// with it; false if it took longer than `REQUEST_TIMEOUT_MS`
+    pub fn submit(&mut self, chain: &[Buffer]) -> bool {
+        if chain.is_empty() || chain.len() > self.size as usize {
+            return false;
+        }
+
+        // Nothing else is in flight, so the chain always starts at
+        // descriptor 0
+        for (i, buffer) in chain.iter().enumerate() {
+            let mut flags = if buffer.device_writes { DESC_WRITE } else { 0 };
+            if i + 1 < chain.len() {
+                flags |= DESC_NEXT;
+            }
+            let desc = self.rings.virt + DESC_OFFSET + i * 16;
+            unsafe {
+                write_volatile(desc as *mut u64, buffer.phys);
+                write_volatile((desc + 8) as *mut u32, buffer.len);
+                write_volatile((desc + 12) as *mut u16, flags);
+                write_volatile((desc + 14) as *mut u16, i as u16 + 1);
+            }
+        }
+
+        let avail = self.rings.virt + AVAIL_OFFSET;
+        let slot = (self.next_avail % self.size) as usize;
+        self.next_avail = self.next_avail.wrapping_add(1);
+        unsafe {
+            write_volatile((avail + 4 + slot * 2) as *mut u16, 0);
+            // The entry must be visible before the index that publishes it
+            fence(Ordering::SeqCst);
+            write_volatile((avail + 2) as *mut u16, self.next_avail);
+            fence(Ordering::SeqCst);
+            write_volatile(self.notify as *mut u16, self.index);
+        }
+
+        let used_idx = (self.rings.virt + USED_OFFSET + 2) as *const u16;
+        let deadline = get_time_ms() + REQUEST_TIMEOUT_MS;
+        while unsafe { read_volatile(used_idx) } == self.last_used {
+            if get_time_ms() >= deadline {
+                return false;
+            }
+            yield_now();
+        }
+        fence(Ordering::SeqCst);
+        self.last_used = self.last_used.wrapping_add(1);
+        true
+    }
+}
@@ -0,0 +1,555 @@
+// virtio-gpu Scanout
+//
+// Output backend for QEMU's virtio-gpu (`-device virtio-gpu-pci` or
+// `-device virtio-vga`), using its 2D commands. Frames are still composed
+// in the driver's back buffer; presenting copies the damaged rows into
+// guest memory backing a host resource, transfers them to the host and
+// points the heads at that resource.
+//
+// Implementation details:
+// - Each enabled head (scanout) shows its own rectangle of one desktop
+//   that puts the heads side by side, so with several heads the screen
+//   clients see is as wide as all of them together
+// - Two desktop-sized resources share the same backing memory. A present
+//   transfers into the one not on screen and then flips every head to it,
+//   so a head never shows a half-transferred frame. The other resource
+//   misses that frame's changes, which go into it at the next present.
+// - The flush after a flip is fenced, so the device hands it back once
+//   the frame is on screen, and flips are paced to one per refresh: the
+//   closest virtio-gpu comes to vsync
+// - The backing is a list of DMA allocations, since one allocation is
+//   limited in size; it only ever grows, to be reused after mode switches
+//
+// Limitations:
+// - Requests are polled one at a time; no interrupt, no cursor queue
+// - Resolution switches only with a single head
+
+use alloc::vec::Vec;
+
+use atom_syscall::debug::log;
+use atom_syscall::dma::{dma_alloc, DmaBuffer, DMA_PAGE_SIZE};
+use atom_syscall::graphics::Framebuffer;
+use atom_syscall::thread::{get_time_ms, sleep_ms};
+use libipc::messages::{DisplayHead, DisplayMode, Rect, MAX_SURFACE_BYTES};
+
+use crate::virtio::{Buffer, VirtioPci, Virtqueue};
+
+// ============================================================================
+// Protocol Definitions
+// ============================================================================
+
+/// Virtio device type of GPUs
+const DEVICE_TYPE_GPU: u16 = 16;
+
+/// The control queue; the cursor queue (1) is not used
+const CONTROL_QUEUE: u16 = 0;
+
+// Commands
+const CMD_GET_DISPLAY_INFO: u32 = 0x0100;
+const CMD_RESOURCE_CREATE_2D: u32 = 0x0101;
+const CMD_RESOURCE_UNREF: u32 = 0x0102;
+const CMD_SET_SCANOUT: u32 = 0x0103;
+const CMD_RESOURCE_FLUSH: u32 = 0x0104;
+const CMD_TRANSFER_TO_HOST_2D: u32 = 0x0105;
+const CMD_RESOURCE_ATTACH_BACKING: u32 = 0x0106;
+const CMD_RESOURCE_DETACH_BACKING: u32 = 0x0107;
+
+// Responses
+const RESP_OK_NODATA: u32 = 0x1100;
+const RESP_OK_DISPLAY_INFO: u32 = 0x1101;
+
+/// Ask for the response only once the command has taken effect
+const FLAG_FENCE: u32 = 1 << 0;
+
+/// Blue, green, red, unused: the byte order of the native pixel format
+const FORMAT_B8G8R8X8_UNORM: u32 = 2;
+
+/// Scanouts a device can have
+const MAX_SCANOUTS: usize = 16;
+
+/// Size of a command header
+const HEADER_SIZE: usize = 24;
+
+/// Size of each scanout's entry in the display info response
+const DISPLAY_ENTRY_SIZE: usize = 24;
+
+// ============================================================================
+// Layout
+// ============================================================================
+
+/// The command page holds the request in its first half and the
+/// response in the second
+const RESPONSE_OFFSET: usize = DMA_PAGE_SIZE / 2;
+
+/// Pages per backing allocation, the most one allocation may have
+const BACKING_CHUNK_PAGES: usize = 64;
+const BACKING_CHUNK_SIZE: usize = BACKING_CHUNK_PAGES * DMA_PAGE_SIZE;
+
+/// Size of a head the host has no preference for
+const DEFAULT_WIDTH: u32 = 1024;
+const DEFAULT_HEIGHT: u32 = 768;
+
+/// Resolutions offered besides the host's preferred one
+const STANDARD_MODES: [(u32, u32); 5] = [
+    (800, 600),
+    (1024, 768),
+    (1280, 720),
+    (1280, 1024),
+    (1920, 1080),
+];
+
+/// Time between flips: one refresh at 60 Hz
+const REFRESH_MS: u64 = 16;
+
+/// Areas waiting for the resource not on screen before they merge into
+/// their bounding rectangle
+const MAX_STALE_RECTS: usize = 16;
+
+/// A scanout and the part of the desktop it shows
+#[derive(Clone, Copy)]
+struct Head {
+    scanout: u32,
+    x: u32,
+    width: u32,
+    height: u32,
+}
+
+pub struct VirtioGpu {
+    device: VirtioPci,
+    control: Virtqueue,
+    commands: DmaBuffer,
+    heads: Vec<Head>,
+    width: u32,
+    height: u32,
+    backing: Vec<DmaBuffer>,
+    /// The two resources, the one on screen first
+    resources: [u32; 2],
+    /// Next resource ID to hand out
+    next_resource: u32,
+    /// Areas changed since the resource not on screen was last updated
+    stale: Vec<Rect>,
+    next_flip: u64,
+    next_fence: u64,
+}
+
+impl VirtioGpu {
+    /// Find and set up a virtio GPU, showing a blank desktop on every head
+    /// the host has enabled
+    pub fn probe() -> Option<Self> {
+        let device = VirtioPci::probe(DEVICE_TYPE_GPU)?;
+        let Some(control) = device.queue(CONTROL_QUEUE) else {
+            device.fail();
+            return None;
+        };
+        let Ok(commands) = dma_alloc(1) else {
+            device.fail();
+            return None;
+        };
+        device.ready();
+
+        let mut gpu = Self {
+            device,
+            control,
+            commands,
+            heads: Vec::new(),
+            width: 0,
+            height: 0,
+            backing: Vec::new(),
+            resources: [0; 2],
+            next_resource: 1,
+            stale: Vec::new(),
+            next_flip: 0,
+            next_fence: 1,
+        };
+
+        let mut heads = gpu.display_info().unwrap_or_default();
+        if heads.is_empty() {
+            heads.push(Head {
+                scanout: 0,
+                x: 0,
+                width: DEFAULT_WIDTH,
+                height: DEFAULT_HEIGHT,
+            });
+        }
+        // Heads that together need more than a screen surface can hold
+        // leave only the first one in use
+        let (width, height) = desktop_size(&heads);
+        if !fits(width, height) {
+            heads.truncate(1);
+        }
+        gpu.heads = heads;
+
+        let (width, height) = desktop_size(&gpu.heads);
+        if !gpu.create_resources(width, height) {
+            log("Display Driver: virtio-gpu could not create its resources");
+            gpu.device.fail();
+            return None;
+        }
+        Some(gpu)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Number of heads showing the desktop
+    pub fn head_count(&self) -> usize {
+        self.heads.len()
+    }
+
+    /// Each head's scanout and the columns of the desktop it shows
+    pub fn heads(&self) -> Vec<DisplayHead> {
+        self.heads
+            .iter()
+            .map(|head| DisplayHead {
+                scanout: head.scanout,
+                x: head.x,
+                width: head.width,
+                height: head.height,
+            })
+            .collect()
+    }
+
+    /// The desktop size first, then what a single head can switch to
+    pub fn modes(&mut self) -> Vec<DisplayMode> {
+        let mut modes = alloc::vec![DisplayMode { width: self.width, height: self.height }];
+        if self.heads.len() != 1 {
+            return modes;
+        }
+
+        let preferred = self.display_info().unwrap_or_default();
+        let candidates = preferred
+            .iter()
+            .filter(|head| head.scanout == self.heads[0].scanout)
+            .map(|head| (head.width, head.height))
+            .chain(STANDARD_MODES);
+        for (width, height) in candidates {
+            let mode = DisplayMode { width, height };
+            if fits(width, height) && !modes.contains(&mode) {
+                modes.push(mode);
+            }
+        }
+        modes
+    }
+
+    /// Switch a single head to `width` x `height`; the old size stays if
+    /// the device refuses the new one
+    pub fn set_mode(&mut self, width: u32, height: u32) -> bool {
+        if self.heads.len() != 1 || !fits(width, height) {
+            return false;
+        }
+        let old = (self.width, self.height);
+
+        self.destroy_resources();
+        self.heads[0].width = width;
+        self.heads[0].height = height;
+        if self.create_resources(width, height) {
+            return true;
+        }
+
+        log("Display Driver: virtio-gpu refused the mode");
+        self.destroy_resources();
+        self.heads[0].width = old.0;
+        self.heads[0].height = old.1;
+        if !self.create_resources(old.0, old.1) {
+            log("Display Driver: virtio-gpu lost its resources");
+        }
+        false
+    }
+
+    /// Show the `damage` of `back` on every head; returns the number of
+    /// pixels presented
+    pub fn present(&mut self, back: &Framebuffer, damage: &[Rect]) -> u64 {
+        if damage.is_empty() {
+            return 0;
+        }
+
+        let mut pixels = 0;
+        for area in damage {
+            self.copy_to_backing(back, area);
+            pixels += area.width as u64 * area.height as u64;
+        }
+
+        // The resource about to be shown also lacks what the last present
+        // put in the other one
+        let mut areas = core::mem::take(&mut self.stale);
+        areas.extend_from_slice(damage);
+        if areas.len() > MAX_STALE_RECTS {
+            let bounds = areas.iter().fold(areas[0], |acc, r| acc.union(r));
+            areas.clear();
+            areas.push(bounds);
+        }
+
+        let hidden = self.resources[1];
+        for area in &areas {
+            self.transfer(hidden, area);
+        }
+
+        let now = get_time_ms();
+        if now < self.next_flip {
+            sleep_ms(self.next_flip - now);
+        }
+        self.flip(hidden);
+        self.next_flip = get_time_ms() + REFRESH_MS;
+
+        self.resources.swap(0, 1);
+        self.stale = damage.to_vec();
+        pixels
+    }
+
+    /// Enabled scanouts and their preferred sizes, laid out left to right
+    fn display_info(&mut self) -> Option<Vec<Head>> {
+        let request = header(CMD_GET_DISPLAY_INFO, None);
+        let response = self.send(&request, HEADER_SIZE + MAX_SCANOUTS * DISPLAY_ENTRY_SIZE)?;
+        if response != RESP_OK_DISPLAY_INFO {
+            return None;
+        }
+
+        let mut heads = Vec::new();
+        let mut x = 0;
+        for scanout in 0..MAX_SCANOUTS {
+            let entry = RESPONSE_OFFSET + HEADER_SIZE + scanout * DISPLAY_ENTRY_SIZE;
+            let (width, height) = (self.response_u32(entry + 8), self.response_u32(entry + 12));
+            if self.response_u32(entry + 16) == 0 || width == 0 || height == 0 {
+                continue;
+            }
+            heads.push(Head {
+                scanout: scanout as u32,
+                x,
+                width,
+                height,
+            });
+            x += width;
+        }
+        Some(heads)
+    }
+
+    /// Create both resources at `width` x `height`, back them and put
+    /// the first on every head
+    fn create_resources(&mut self, width: u32, height: u32) -> bool {
+        let size = width as usize * height as usize * 4;
+        if !self.grow_backing(size) {
+            return false;
+        }
+        self.clear_backing(size);
+
+        for slot in 0..2 {
+            let id = self.next_resource;
+            self.next_resource += 1;
+
+            let mut request = header(CMD_RESOURCE_CREATE_2D, None);
+            for value in [id, FORMAT_B8G8R8X8_UNORM, width, height] {
+                request.extend_from_slice(&value.to_le_bytes());
+            }
+            if !self.command(&request) {
+                return false;
+            }
+            self.resources[slot] = id;
+
+            if !self.attach_backing(id, size) {
+                return false;
+            }
+        }
+
+        self.width = width;
+        self.height = height;
+        self.stale.clear();
+
+        // Both resources start out black, like the backing
+        let screen = Rect::new(0, 0, width, height);
+        self.transfer(self.resources[0], &screen);
+        self.transfer(self.resources[1], &screen);
+        self.flip(self.resources[0]);
+        true
+    }
+
+    /// Take the resources off the heads and free them; the backing stays
+    fn destroy_resources(&mut self) {
+        self.flip(0);
+        for id in core::mem::take(&mut self.resources) {
+            if id == 0 {
+                continue;
+            }
+            let mut request = header(CMD_RESOURCE_DETACH_BACKING, None);
+            request.extend_from_slice(&id.to_le_bytes());
+            request.extend_from_slice(&0u32.to_le_bytes());
+            self.command(&request);
+
+            let mut request = header(CMD_RESOURCE_UNREF, None);
+            request.extend_from_slice(&id.to_le_bytes());
+            request.extend_from_slice(&0u32.to_le_bytes());
+            self.command(&request);
+        }
+    }
+
+    /// Allocate backing until it holds `size` bytes
+    fn grow_backing(&mut self, size: usize) -> bool {
+        while self.backing.len() * BACKING_CHUNK_SIZE < size {
+            match dma_alloc(BACKING_CHUNK_PAGES) {
+                Ok(chunk) => self.backing.push(chunk),
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+
+    /// Zero the first `size` bytes of the backing
+    fn clear_backing(&self, size: usize) {
+        for (i, chunk) in self.backing.iter().enumerate() {
+            let len = size.saturating_sub(i * BACKING_CHUNK_SIZE).min(BACKING_CHUNK_SIZE);
+            unsafe { core::ptr::write_bytes(chunk.as_mut_ptr(), 0, len) };
+        }
+    }
+
+    /// Give resource `id` the first `size` bytes of the backing
+    fn attach_backing(&mut self, id: u32, size: usize) -> bool {
+        let chunks = size.div_ceil(BACKING_CHUNK_SIZE);
+        let mut request = header(CMD_RESOURCE_ATTACH_BACKING, None);
+        request.extend_from_slice(&id.to_le_bytes());
+        request.extend_from_slice(&(chunks as u32).to_le_bytes());
+        for (i, chunk) in self.backing[..chunks].iter().enumerate() {
+            let len = (size - i * BACKING_CHUNK_SIZE).min(BACKING_CHUNK_SIZE);
+            request.extend_from_slice(&chunk.phys.to_le_bytes());
+            request.extend_from_slice(&(len as u32).to_le_bytes());
+            request.extend_from_slice(&0u32.to_le_bytes());
+        }
+        self.command(&request)
+    }
+
+    /// Copy `area` of `back` into the backing, which is laid out with rows
+    /// packed back to back
+    fn copy_to_backing(&self, back: &Framebuffer, area: &Rect) {
+        let pixels = back.pixels().sub(area.x as u32, area.y as u32, area.width, area.height);
+        for (i, row) in pixels.rows().enumerate() {
+            let y = area.y as usize + i;
+            let mut offset = (y * self.width as usize + area.x as usize) * 4;
+            let mut src = row;
+
+            // A row may straddle two allocations of the backing
+            while !src.is_empty() {
+                let chunk = &self.backing[offset / BACKING_CHUNK_SIZE];
+                let within = offset % BACKING_CHUNK_SIZE;
+                let count = ((BACKING_CHUNK_SIZE - within) / 4).min(src.len());
+                unsafe {
+                    core::ptr::copy_nonoverlapping(
+                        src.as_ptr(),
+                        (chunk.virt + within) as *mut u32,
+                        count,
+                    );
+                }
+                src = &src[count..];
+                offset += count * 4;
+            }
+        }
+    }
+
+    /// Copy `area` of the backing into resource `id` on the host
+    fn transfer(&mut self, id: u32, area: &Rect) {
+        let offset = (area.y as u64 * self.width as u64 + area.x as u64) * 4;
+        let mut request = header(CMD_TRANSFER_TO_HOST_2D, None);
+        put_rect(&mut request, area);
+        request.extend_from_slice(&offset.to_le_bytes());
+        request.extend_from_slice(&id.to_le_bytes());
+        request.extend_from_slice(&0u32.to_le_bytes());
+        self.command(&request);
+    }
+
+    /// Point every head at its part of resource `id` (0 turns them off)
+    /// and wait for the device to show it
+    fn flip(&mut self, id: u32) {
+        for i in 0..self.heads.len() {
+            let head = self.heads[i];
+            let mut request = header(CMD_SET_SCANOUT, None);
+            put_rect(&mut request, &Rect::new(head.x as i32, 0, head.width, head.height));
+            request.extend_from_slice(&head.scanout.to_le_bytes());
+            request.extend_from_slice(&id.to_le_bytes());
+            self.command(&request);
+        }
+        if id == 0 {
+            return;
+        }
+
+        let fence = self.next_fence;
+        self.next_fence += 1;
+        let mut request = header(CMD_RESOURCE_FLUSH, Some(fence));
+        put_rect(&mut request, &Rect::new(0, 0, self.width, self.height));
+        request.extend_from_slice(&id.to_le_bytes());
+        request.extend_from_slice(&0u32.to_le_bytes());
+        self.command(&request);
+    }
+
+    /// Send a command that answers with no data; false if it failed
+    fn command(&mut self, request: &[u8]) -> bool {
+        self.send(request, HEADER_SIZE) == Some(RESP_OK_NODATA)
+    }
+
+    /// Send `request` with room for a `response_len` byte answer; returns
+    /// the response type
+    fn send(&mut self, request: &[u8], response_len: usize) -> Option<u32> {
+        if request.len() > RESPONSE_OFFSET || response_len > DMA_PAGE_SIZE - RESPONSE_OFFSET {
+            return None;
+        }
+
+        unsafe {
+            let page = self.commands.as_mut_ptr();
+            core::ptr::copy_nonoverlapping(request.as_ptr(), page, request.len());
+            core::ptr::write_bytes(page.add(RESPONSE_OFFSET), 0, response_len);
+        }
+        let chain = [
+            Buffer {
+                phys: self.commands.phys,
+                len: request.len() as u32,
+                device_writes: false,
+            },
+            Buffer {
+                phys: self.commands.phys_at(RESPONSE_OFFSET),
+                len: response_len as u32,
+                device_writes: true,
+            },
+        ];
+        if !self.control.submit(&chain) {
+            log("Display Driver: virtio-gpu did not answer");
+            return None;
+        }
+        Some(self.response_u32(RESPONSE_OFFSET))
+    }
+
+    fn response_u32(&self, offset: usize) -> u32 {
+        unsafe { core::ptr::read_volatile((self.commands.virt + offset) as *const u32) }
+    }
+}
+
+/// Size of the desktop `heads` show side by side
+fn desktop_size(heads: &[Head]) -> (u32, u32) {
+    let width = heads.iter().map(|head| head.width).sum();
+    let height = heads.iter().map(|head| head.height).max().unwrap_or(0);
+    (width, height)
+}
+
+/// Whether a `width` x `height` desktop can be shown: its back buffer has
+/// to fit in a surface-sized region
+fn fits(width: u32, height: u32) -> bool {
+    width > 0 && height > 0 && width as usize * height as usize * 4 <= MAX_SURFACE_BYTES
+}
+
+/// A command header, fenced if `fence` is given
+fn header(command: u32, fence: Option<u64>) -> Vec<u8> {
+    let flags = if fence.is_some() { FLAG_FENCE } else { 0 };
+    let mut request = Vec::with_capacity(RESPONSE_OFFSET);
+    request.extend_from_slice(&command.to_le_bytes());
+    request.extend_from_slice(&flags.to_le_bytes());
+    request.extend_from_slice(&fence.unwrap_or(0).to_le_bytes());
+    // Context ID, ring index and padding, unused in 2D
+    request.extend_from_slice(&[0; 8]);
+    request
+}
+
+fn put_rect(request: &mut Vec<u8>, rect: &Rect) {
+    for value in [rect.x as u32, rect.y as u32, rect.width, rect.height] {
+        request.extend_from_slice(&value.to_le_bytes());
+    }
+}
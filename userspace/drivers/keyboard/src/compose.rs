@@ -0,0 +1,74 @@
+//! Dead-Key Composition
+//!
+//! A dead key produces nothing on its own; it modifies the next character.
+//! Pairs with no precomposed Latin-1 form fall back to emitting the spacing
+//! accent followed by the character, as most desktop systems do.
+
+use crate::layout::{DEAD_ACUTE, DEAD_CIRCUMFLEX, DEAD_DIAERESIS, DEAD_GRAVE, DEAD_TILDE};
+
+/// Result of combining a pending dead key with the next character
+pub enum Composed {
+    /// A single precomposed character
+    One(u8),
+    /// No combination exists: accent, then the character
+    Two(u8, u8),
+}
+
+/// (base, composed) pairs per accent
+const ACUTE: &[(u8, u8)] = &[
+    (b'a', 0xE1), (b'e', 0xE9), (b'i', 0xED), (b'o', 0xF3), (b'u', 0xFA), (b'y', 0xFD),
+    (b'A', 0xC1), (b'E', 0xC9), (b'I', 0xCD), (b'O', 0xD3), (b'U', 0xDA), (b'Y', 0xDD),
+];
+
+const GRAVE: &[(u8, u8)] = &[
+    (b'a', 0xE0), (b'e', 0xE8), (b'i', 0xEC), (b'o', 0xF2), (b'u', 0xF9),
+    (b'A', 0xC0), (b'E', 0xC8), (b'I', 0xCC), (b'O', 0xD2), (b'U', 0xD9),
+];
+
+const CIRCUMFLEX: &[(u8, u8)] = &[
+    (b'a', 0xE2), (b'e', 0xEA), (b'i', 0xEE), (b'o', 0xF4), (b'u', 0xFB),
+    (b'A', 0xC2), (b'E', 0xCA), (b'I', 0xCE), (b'O', 0xD4), (b'U', 0xDB),
+];
+
+const TILDE: &[(u8, u8)] = &[
+    (b'a', 0xE3), (b'o', 0xF5), (b'n', 0xF1),
+    (b'A', 0xC3), (b'O', 0xD5), (b'N', 0xD1),
+];
+
+const DIAERESIS: &[(u8, u8)] = &[
+    (b'a', 0xE4), (b'e', 0xEB), (b'i', 0xEF), (b'o', 0xF6), (b'u', 0xFC), (b'y', 0xFF),
+    (b'A', 0xC4), (b'E', 0xCB), (b'I', 0xCF), (b'O', 0xD6), (b'U', 0xDC),
+];
+
+/// Spacing form of a dead key (what Space or a repeat produces)
+pub fn spacing(dead: u8) -> u8 {
+    match dead {
+        DEAD_ACUTE => 0xB4,      // ´
+        DEAD_GRAVE => b'`',
+        DEAD_CIRCUMFLEX => b'^',
+        DEAD_TILDE => b'~',
+        DEAD_DIAERESIS => 0xA8,  // ¨
+        _ => 0,
+    }
+}
+
+/// Combine a pending dead key with the next typed character
+pub fn compose(dead: u8, next: u8) -> Composed {
+    if next == b' ' || next == dead {
+        return Composed::One(spacing(dead));
+    }
+
+    let table = match dead {
+        DEAD_ACUTE => ACUTE,
+        DEAD_GRAVE => GRAVE,
+        DEAD_CIRCUMFLEX => CIRCUMFLEX,
+        DEAD_TILDE => TILDE,
+        DEAD_DIAERESIS => DIAERESIS,
+        _ => &[],
+    };
+
+    match table.iter().find(|&&(base, _)| base == next) {
+        Some(&(_, composed)) => Composed::One(composed),
+        None => Composed::Two(spacing(dead), next),
+    }
+}
@@ -0,0 +1,278 @@
+//! Keyboard Layout Tables
+//!
+//! Each layout maps `KeyCode`s to ISO-8859-1 (Latin-1) bytes on three
+//! levels: plain, Shift and AltGr. Entries in `DEAD_FIRST..=DEAD_LAST` are
+//! dead keys, resolved by `compose`.
+//!
+//! Tables are indexed by `KeyCode` value, which equals the set-1 make code
+//! for the main block. Only the alphanumeric block differs between layouts,
+//! so they are built from per-row strings.
+
+use libipc::keycode::{KeyCode, KEYCODE_COUNT};
+use libipc::messages::KeyboardLayout;
+
+// ============================================================================
+// Dead Keys
+// ============================================================================
+
+/// Dead-key markers (C1 control range, never produced as characters)
+pub const DEAD_ACUTE: u8 = 0x80;
+pub const DEAD_GRAVE: u8 = 0x81;
+pub const DEAD_CIRCUMFLEX: u8 = 0x82;
+pub const DEAD_TILDE: u8 = 0x83;
+pub const DEAD_DIAERESIS: u8 = 0x84;
+
+pub const DEAD_FIRST: u8 = DEAD_ACUTE;
+pub const DEAD_LAST: u8 = DEAD_DIAERESIS;
+
+pub fn is_dead(value: u8) -> bool {
+    (DEAD_FIRST..=DEAD_LAST).contains(&value)
+}
+
+// ============================================================================
+// Layout Definition
+// ============================================================================
+
+pub const TABLE_SIZE: usize = KEYCODE_COUNT;
+
+pub struct Layout {
+    pub id: KeyboardLayout,
+    pub normal: [u8; TABLE_SIZE],
+    pub shift: [u8; TABLE_SIZE],
+    pub altgr: [u8; TABLE_SIZE],
+}
+
+impl Layout {
+    /// Character (or dead-key marker) for `key` at the given level
+    pub fn lookup(&self, key: KeyCode, shift: bool, altgr: bool, caps_lock: bool) -> u8 {
+        let index = key as usize;
+
+        if altgr {
+            return self.altgr[index];
+        }
+
+        // Caps Lock only affects keys whose plain level is a letter
+        let upper = if is_letter(self.normal[index]) {
+            shift ^ caps_lock
+        } else {
+            shift
+        };
+
+        if upper {
+            self.shift[index]
+        } else {
+            self.normal[index]
+        }
+    }
+}
+
+/// Latin-1 letters that have an uppercase form
+fn is_letter(c: u8) -> bool {
+    c.is_ascii_alphabetic() || (c >= 0xE0 && c != 0xF7 && c != 0xFF)
+}
+
+/// Rows of the alphanumeric block for one level
+struct Rows {
+    digits: &'static [u8; 12],  // 0x02..=0x0D
+    top: &'static [u8; 12],     // 0x10..=0x1B
+    home: &'static [u8; 12],    // 0x1E..=0x29
+    backslash: u8,              // 0x2B
+    bottom: &'static [u8; 10],  // 0x2C..=0x35
+    iso: u8,                    // IntlBackslash, extra key left of Z on ISO boards
+    abnt: u8,                   // IntlRo, extra key right of the slash on ABNT2
+}
+
+const fn table(rows: Rows, with_controls: bool) -> [u8; TABLE_SIZE] {
+    let mut t = [0u8; TABLE_SIZE];
+
+    if with_controls {
+        t[0x01] = 0x1B; // Escape
+        t[0x0E] = 0x08; // Backspace
+        t[0x0F] = b'\t';
+        t[0x1C] = b'\n';
+        t[0x39] = b' ';
+
+        // Keypad (always digits; Num Lock is not tracked yet)
+        let keypad = b"789-456+1230.";
+        let mut i = 0;
+        while i < keypad.len() {
+            t[KeyCode::Numpad7 as usize + i] = keypad[i];
+            i += 1;
+        }
+        t[KeyCode::NumpadMultiply as usize] = b'*';
+        t[KeyCode::NumpadDivide as usize] = b'/';
+        t[KeyCode::NumpadEnter as usize] = b'\n';
+    }
+
+    let mut i = 0;
+    while i < 12 {
+        t[0x02 + i] = rows.digits[i];
+        t[0x10 + i] = rows.top[i];
+        t[0x1E + i] = rows.home[i];
+        i += 1;
+    }
+
+    let mut i = 0;
+    while i < 10 {
+        t[0x2C + i] = rows.bottom[i];
+        i += 1;
+    }
+
+    t[0x2B] = rows.backslash;
+    t[KeyCode::IntlBackslash as usize] = rows.iso;
+    t[KeyCode::IntlRo as usize] = rows.abnt;
+    t
+}
+
+/// AltGr table with only the listed (key code, character) pairs
+const fn sparse(entries: &[(u8, u8)]) -> [u8; TABLE_SIZE] {
+    let mut t = [0u8; TABLE_SIZE];
+    let mut i = 0;
+    while i < entries.len() {
+        t[entries[i].0 as usize] = entries[i].1;
+        i += 1;
+    }
+    t
+}
+
+// ============================================================================
+// Layouts
+// ============================================================================
+
+pub static US: Layout = Layout {
+    id: KeyboardLayout::Us,
+    normal: table(Rows {
+        digits: b"1234567890-=",
+        top: b"qwertyuiop[]",
+        home: b"asdfghjkl;'`",
+        backslash: b'\\',
+        bottom: b"zxcvbnm,./",
+        iso: b'\\',
+        abnt: 0,
+    }, true),
+    shift: table(Rows {
+        digits: b"!@#$%^&*()_+",
+        top: b"QWERTYUIOP{}",
+        home: b"ASDFGHJKL:\"~",
+        backslash: b'|',
+        bottom: b"ZXCVBNM<>?",
+        iso: b'|',
+        abnt: 0,
+    }, true),
+    altgr: sparse(&[]),
+};
+
+pub static ABNT2: Layout = Layout {
+    id: KeyboardLayout::Abnt2,
+    normal: table(Rows {
+        digits: b"1234567890-=",
+        top: b"qwertyuiop\x80[",
+        home: b"asdfghjkl\xE7\x83'",
+        backslash: b']',
+        bottom: b"zxcvbnm,.;",
+        iso: b'\\',
+        abnt: b'/',
+    }, true),
+    shift: table(Rows {
+        digits: b"!@#$%\x84&*()_+",
+        top: b"QWERTYUIOP\x81{",
+        home: b"ASDFGHJKL\xC7\x82\"",
+        backslash: b'}',
+        bottom: b"ZXCVBNM<>:",
+        iso: b'|',
+        abnt: b'?',
+    }, true),
+    altgr: sparse(&[
+        (0x02, 0xB9), // ¹
+        (0x03, 0xB2), // ²
+        (0x04, 0xB3), // ³
+        (0x05, 0xA3), // £
+        (0x06, 0xA2), // ¢
+        (0x07, 0xAC), // ¬
+        (0x0D, 0xA7), // §
+        (0x10, b'/'),
+        (0x11, b'?'),
+        (0x1B, 0xAA), // ª
+        (0x2B, 0xBA), // º
+    ]),
+};
+
+pub static DE: Layout = Layout {
+    id: KeyboardLayout::De,
+    normal: table(Rows {
+        digits: b"1234567890\xDF\x80",
+        top: b"qwertzuiop\xFC+",
+        home: b"asdfghjkl\xF6\xE4\x82",
+        backslash: b'#',
+        bottom: b"yxcvbnm,.-",
+        iso: b'<',
+        abnt: 0,
+    }, true),
+    shift: table(Rows {
+        digits: b"!\"\xA7$%&/()=?\x81",
+        top: b"QWERTZUIOP\xDC*",
+        home: b"ASDFGHJKL\xD6\xC4\xB0",
+        backslash: b'\'',
+        bottom: b"YXCVBNM;:_",
+        iso: b'>',
+        abnt: 0,
+    }, true),
+    altgr: sparse(&[
+        (0x03, 0xB2), // ²
+        (0x04, 0xB3), // ³
+        (0x08, b'{'),
+        (0x09, b'['),
+        (0x0A, b']'),
+        (0x0B, b'}'),
+        (0x0C, b'\\'),
+        (0x10, b'@'),
+        (0x1B, b'~'),
+        (0x32, 0xB5), // µ
+        (KeyCode::IntlBackslash as u8, b'|'),
+    ]),
+};
+
+pub static FR: Layout = Layout {
+    id: KeyboardLayout::Fr,
+    normal: table(Rows {
+        digits: b"&\xE9\"'(-\xE8_\xE7\xE0)=",
+        top: b"azertyuiop\x82$",
+        home: b"qsdfghjklm\xF9\xB2",
+        backslash: b'*',
+        bottom: b"wxcvbn,;:!",
+        iso: b'<',
+        abnt: 0,
+    }, true),
+    shift: table(Rows {
+        digits: b"1234567890\xB0+",
+        top: b"AZERTYUIOP\x84\xA3",
+        home: b"QSDFGHJKLM%\0",
+        backslash: 0xB5,
+        bottom: b"WXCVBN?./\xA7",
+        iso: b'>',
+        abnt: 0,
+    }, true),
+    altgr: sparse(&[
+        (0x03, DEAD_TILDE),
+        (0x04, b'#'),
+        (0x05, b'{'),
+        (0x06, b'['),
+        (0x07, b'|'),
+        (0x08, DEAD_GRAVE),
+        (0x09, b'\\'),
+        (0x0A, b'^'),
+        (0x0B, b'@'),
+        (0x0C, b']'),
+        (0x0D, b'}'),
+    ]),
+};
+
+/// Table for a layout identifier
+pub fn get(id: KeyboardLayout) -> &'static Layout {
+    match id {
+        KeyboardLayout::Us => &US,
+        KeyboardLayout::Abnt2 => &ABNT2,
+        KeyboardLayout::De => &DE,
+        KeyboardLayout::Fr => &FR,
+    }
+}
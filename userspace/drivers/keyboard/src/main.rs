@@ -2,15 +2,21 @@
 //!
 //! This driver runs entirely in Ring 3 (userspace) and:
 //! - Polls raw scancodes from kernel input buffer
-//! - Translates scancodes to ASCII (US layout)
-//! - Tracks modifier keys (Shift, Ctrl, Alt, Caps Lock)
+//! - Decodes set 1 or set 2 (auto-detected), including E0/E1 prefixes,
+//!   into hardware-independent `KeyCode`s
+//! - Translates key codes to Latin-1 through a data-driven layout table
+//!   (US, ABNT2, DE, FR), including AltGr and dead keys
+//! - Tracks modifier keys (Shift, Ctrl, Alt, AltGr, Caps Lock)
 //! - Dispatches key events to the desktop environment via IPC
+//! - Switches layout at runtime on a `SetKeyboardLayout` message
 //!
 //! # Architecture
 //!
 //! ```text
 //! Kernel IRQ Buffer ──> Keyboard Driver ──> Desktop Environment
 //!    (raw bytes)         (translation)       (IPC messages)
+//!                              ^
+//!                              └── SetKeyboardLayout (settings UI)
 //! ```
 
 #![no_std]
@@ -18,41 +24,56 @@
 
 extern crate alloc;
 
-use core::panic::PanicInfo;
+mod compose;
+mod layout;
+
 
 use atom_syscall::input::keyboard_poll;
-use atom_syscall::ipc::{create_port, send_async, PortId};
-use atom_syscall::thread::{yield_now, exit};
+use atom_syscall::ipc::{create_port, PortId};
+use atom_syscall::thread::{get_ticks, yield_now};
 use atom_syscall::debug::log;
 
-use libipc::messages::{KeyEvent, KeyModifiers, MessageType, MessageHeader};
-use libipc::protocol::send_message_async;
+use libipc::keycode::{KeyCode, ScancodeDecoder};
+use libipc::messages::{KeyEvent, KeyModifiers, KeyboardLayout, MessageType, SetKeyboardLayoutRequest};
+use libipc::protocol::{get_payload, send_message_async, try_recv_message};
+
+use compose::Composed;
+use layout::Layout;
 
 // ============================================================================
 // Keyboard State
 // ============================================================================
 
-const BUFFER_SIZE: usize = 64;
-
 struct KeyboardState {
+    layout: &'static Layout,
     shift: bool,
     ctrl: bool,
     alt: bool,
+    altgr: bool,
     caps_lock: bool,
-    extended: bool,
+    decoder: ScancodeDecoder,
+    pending_dead: Option<u8>,
 }
 
 impl KeyboardState {
     const fn new() -> Self {
         Self {
+            layout: &layout::US,
             shift: false,
             ctrl: false,
             alt: false,
+            altgr: false,
             caps_lock: false,
-            extended: false,
+            decoder: ScancodeDecoder::new(),
+            pending_dead: None,
         }
     }
 
+    fn set_layout(&mut self, id: KeyboardLayout) {
+        self.layout = layout::get(id);
+        self.pending_dead = None;
+    }
+
     fn modifiers(&self) -> KeyModifiers {
         KeyModifiers {
             shift: self.shift,
@@ -62,125 +83,78 @@ impl KeyboardState {
         }
     }
 
-    fn process_scancode(&mut self, scancode: u8) -> Option<(u8, u8, bool)> {
-        // Handle extended prefix
-        if scancode == 0xE0 {
-            self.extended = true;
-            return None;
-        }
-
-        let extended = self.extended;
-        self.extended = false;
-
-        let is_release = (scancode & 0x80) != 0;
-        let code = scancode & 0x7F;
+    /// Process one scancode byte, calling `emit(event, pressed)` for each
+    /// resulting key event (a dead key followed by a non-combining
+    /// character produces two).
+    fn process_scancode<F: FnMut(KeyEvent, bool)>(&mut self, scancode: u8, mut emit: F) {
+        let (keycode, pressed) = match self.decoder.feed(scancode) {
+            Some(transition) => transition,
+            None => return,
+        };
 
         // Handle modifier keys
-        match code {
-            0x2A | 0x36 => {
-                self.shift = !is_release;
-                return None;
+        match keycode {
+            KeyCode::ShiftLeft | KeyCode::ShiftRight => {
+                self.shift = pressed;
+                return;
             }
-            0x1D => {
-                self.ctrl = !is_release;
-                return None;
+            KeyCode::ControlLeft | KeyCode::ControlRight => {
+                self.ctrl = pressed;
+                return;
             }
-            0x38 => {
-                self.alt = !is_release;
-                return None;
+            KeyCode::AltRight => {
+                self.altgr = pressed;
+                return;
             }
-            0x3A => {
-                if !is_release {
+            KeyCode::AltLeft => {
+                self.alt = pressed;
+                return;
+            }
+            KeyCode::CapsLock => {
+                if pressed {
                     self.caps_lock = !self.caps_lock;
                 }
-                return None;
+                return;
             }
             _ => {}
         }
 
-        // Translate to ASCII
-        let ascii = if is_release {
-            0
-        } else {
-            translate_scancode(code, self.shift, self.caps_lock, extended)
-        };
+        let modifiers = self.modifiers();
+        let timestamp = get_ticks();
+        let event = |character| KeyEvent { keycode, character, modifiers, timestamp };
 
-        Some((scancode, ascii, !is_release))
-    }
-}
+        if !pressed {
+            emit(event(0), false);
+            return;
+        }
 
-fn translate_scancode(code: u8, shift: bool, caps_lock: bool, _extended: bool) -> u8 {
-    match code {
-        // Numbers
-        0x02 => if shift { b'!' } else { b'1' },
-        0x03 => if shift { b'@' } else { b'2' },
-        0x04 => if shift { b'#' } else { b'3' },
-        0x05 => if shift { b'$' } else { b'4' },
-        0x06 => if shift { b'%' } else { b'5' },
-        0x07 => if shift { b'^' } else { b'6' },
-        0x08 => if shift { b'&' } else { b'7' },
-        0x09 => if shift { b'*' } else { b'8' },
-        0x0A => if shift { b'(' } else { b'9' },
-        0x0B => if shift { b')' } else { b'0' },
-        0x0C => if shift { b'_' } else { b'-' },
-        0x0D => if shift { b'+' } else { b'=' },
-
-        // Special keys
-        0x0E => 0x08,  // Backspace
-        0x0F => b'\t', // Tab
-        0x1C => b'\n', // Enter
-        0x39 => b' ',  // Space
-        0x01 => 0x1B,  // Escape
-
-        // Punctuation
-        0x1A => if shift { b'{' } else { b'[' },
-        0x1B => if shift { b'}' } else { b']' },
-        0x27 => if shift { b':' } else { b';' },
-        0x28 => if shift { b'"' } else { b'\'' },
-        0x29 => if shift { b'~' } else { b'`' },
-        0x2B => if shift { b'|' } else { b'\\' },
-        0x33 => if shift { b'<' } else { b',' },
-        0x34 => if shift { b'>' } else { b'.' },
-        0x35 => if shift { b'?' } else { b'/' },
-
-        // Letters
-        0x10 => letter(b'q', shift, caps_lock),
-        0x11 => letter(b'w', shift, caps_lock),
-        0x12 => letter(b'e', shift, caps_lock),
-        0x13 => letter(b'r', shift, caps_lock),
-        0x14 => letter(b't', shift, caps_lock),
-        0x15 => letter(b'y', shift, caps_lock),
-        0x16 => letter(b'u', shift, caps_lock),
-        0x17 => letter(b'i', shift, caps_lock),
-        0x18 => letter(b'o', shift, caps_lock),
-        0x19 => letter(b'p', shift, caps_lock),
-        0x1E => letter(b'a', shift, caps_lock),
-        0x1F => letter(b's', shift, caps_lock),
-        0x20 => letter(b'd', shift, caps_lock),
-        0x21 => letter(b'f', shift, caps_lock),
-        0x22 => letter(b'g', shift, caps_lock),
-        0x23 => letter(b'h', shift, caps_lock),
-        0x24 => letter(b'j', shift, caps_lock),
-        0x25 => letter(b'k', shift, caps_lock),
-        0x26 => letter(b'l', shift, caps_lock),
-        0x2C => letter(b'z', shift, caps_lock),
-        0x2D => letter(b'x', shift, caps_lock),
-        0x2E => letter(b'c', shift, caps_lock),
-        0x2F => letter(b'v', shift, caps_lock),
-        0x30 => letter(b'b', shift, caps_lock),
-        0x31 => letter(b'n', shift, caps_lock),
-        0x32 => letter(b'm', shift, caps_lock),
-
-        _ => 0,
-    }
-}
+        let character = self.layout.lookup(keycode, self.shift, self.altgr, self.caps_lock);
+
+        if layout::is_dead(character) {
+            self.pending_dead = match self.pending_dead {
+                // Pressing the same dead key twice types the accent itself
+                Some(dead) if dead == character => {
+                    emit(event(compose::spacing(dead)), true);
+                    None
+                }
+                _ => {
+                    emit(event(0), true);
+                    Some(character)
+                }
+            };
+            return;
+        }
 
-fn letter(base: u8, shift: bool, caps_lock: bool) -> u8 {
-    let upper = shift ^ caps_lock;
-    if upper {
-        base.to_ascii_uppercase()
-    } else {
-        base
+        match self.pending_dead.take() {
+            Some(dead) if character >= 0x20 => match compose::compose(dead, character) {
+                Composed::One(c) => emit(event(c), true),
+                Composed::Two(accent, c) => {
+                    emit(event(accent), true);
+                    emit(event(c), true);
+                }
+            },
+            _ => emit(event(character), true),
+        }
     }
 }
 
@@ -206,8 +180,8 @@ impl KeyboardDriver {
     fn run(&mut self) -> ! {
         log("Keyboard Driver: Starting PS/2 keyboard driver");
 
-        // Create our own IPC port for receiving commands
-        let _our_port = create_port().ok();
+        // Our own IPC port for configuration requests (layout changes)
+        let control_port = create_port().ok();
 
         // TODO: Discover desktop port via service registry
         // For now, the desktop environment will poll directly from kernel buffer
@@ -215,20 +189,20 @@ impl KeyboardDriver {
         log("Keyboard Driver: Entering main loop");
 
         loop {
+            if let Some(port) = control_port {
+                self.handle_control(port);
+            }
+
             // Poll for raw scancodes from kernel
             while let Some(scancode) = keyboard_poll() {
-                if let Some((sc, ascii, pressed)) = self.state.process_scancode(scancode) {
-                    self.event_count += 1;
+                let desktop_port = self.desktop_port;
+                let event_count = &mut self.event_count;
 
-                    // Create key event
-                    let event = KeyEvent {
-                        scancode: sc,
-                        character: ascii,
-                        modifiers: self.state.modifiers(),
-                    };
+                self.state.process_scancode(scancode, |event, pressed| {
+                    *event_count += 1;
 
                     // Send to desktop environment if connected
-                    if let Some(port) = self.desktop_port {
+                    if let Some(port) = desktop_port {
                         let msg_type = if pressed {
                             MessageType::KeyDown
                         } else {
@@ -238,12 +212,28 @@ impl KeyboardDriver {
                         let payload = event.to_bytes();
                         let _ = send_message_async(port, msg_type, &payload);
                     }
-                }
+                });
             }
 
             yield_now();
         }
     }
+
+    fn handle_control(&mut self, port: PortId) {
+        let mut buffer = [0u8; 64];
+
+        while let Ok(Some((header, len))) = try_recv_message(port, &mut buffer) {
+            if header.msg_type != MessageType::SetKeyboardLayout {
+                continue;
+            }
+
+            if let Some(request) = SetKeyboardLayoutRequest::from_bytes(get_payload(&buffer, len)) {
+                self.state.set_layout(request.layout);
+                log("Keyboard Driver: Layout changed");
+                log(self.state.layout.id.name());
+            }
+        }
+    }
 }
 
 // ============================================================================
@@ -260,8 +250,6 @@ fn main() -> ! {
     driver.run()
 }
 
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    log("Keyboard Driver: PANIC!");
-    exit(0xFF);
-}
+atom_syscall::define_global_allocator!();
+
+atom_syscall::define_panic_handler!("Keyboard Driver");
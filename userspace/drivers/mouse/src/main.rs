@@ -13,15 +13,16 @@
 //
 // Key features:
 // - Full PS/2 mouse initialization sequence
-// - 3-byte packet parsing with sign extension
+// - IntelliMouse detection (sample-rate "knock" sequences) for the scroll
+//   wheel and buttons 4/5
+// - 3- and 4-byte packet parsing with sign extension
 // - 1:1 movement scaling (scaling 1:1 enabled)
-// - Button state tracking (left, right, middle)
+// - Button state tracking (left, right, middle, back, forward)
 // - Overflow detection and packet validation
 
 #![no_std]
 #![no_main]
 
-use core::panic::PanicInfo;
 
 // Use the atom_syscall library for all kernel interactions
 use atom_syscall::io::{port_read_u8, port_write_u8, ps2};
@@ -57,6 +58,14 @@ const MOUSE_SET_SCALING_1_1: u8 = 0xE6;
 
 const MOUSE_ACK: u8 = 0xFA;
 
+// Sample-rate sequences that unlock the IntelliMouse extensions
+const WHEEL_SEQUENCE: [u8; 3] = [200, 100, 80];
+const FIVE_BUTTON_SEQUENCE: [u8; 3] = [200, 200, 80];
+
+// Device IDs reported by MOUSE_GET_ID
+const DEVICE_ID_WHEEL: u8 = 3;
+const DEVICE_ID_FIVE_BUTTON: u8 = 4;
+
 // ============================================================================
 // Mouse State
 // ============================================================================
@@ -65,30 +74,40 @@ const MOUSE_ACK: u8 = 0xFA;
 pub struct MouseState {
     pub delta_x: i16,
     pub delta_y: i16,
+    /// Wheel detents, positive = away from the user (scroll up)
+    pub wheel: i8,
     pub left_button: bool,
     pub right_button: bool,
     pub middle_button: bool,
+    pub back_button: bool,
+    pub forward_button: bool,
 }
 
 struct MouseDriver {
-    packet: [u8; 3],
+    packet: [u8; 4],
     cycle: u8,
     state: MouseState,
+    /// Device ID after negotiation (0, 3 or 4)
+    device_id: u8,
     initialized: bool,
 }
 
 impl MouseDriver {
     const fn new() -> Self {
         Self {
-            packet: [0; 3],
+            packet: [0; 4],
             cycle: 0,
             state: MouseState {
                 delta_x: 0,
                 delta_y: 0,
+                wheel: 0,
                 left_button: false,
                 right_button: false,
                 middle_button: false,
+                back_button: false,
+                forward_button: false,
             },
+            device_id: 0,
             initialized: false,
         }
     }
@@ -143,6 +162,15 @@ impl MouseDriver {
         }
         log("Mouse: Resolution set to 4 count/mm");
 
+        // Try to unlock the scroll wheel and extra buttons. The knock
+        // sequences leave the sample rate at 80, so it is reset below.
+        self.device_id = self.detect_extensions();
+        match self.device_id {
+            DEVICE_ID_FIVE_BUTTON => log("Mouse: IntelliMouse Explorer (wheel + 5 buttons)"),
+            DEVICE_ID_WHEEL => log("Mouse: IntelliMouse (wheel)"),
+            _ => log("Mouse: Standard 3-button mouse"),
+        }
+
         // Set sample rate to 100 samples/sec
         if !self.mouse_command(MOUSE_SET_SAMPLE_RATE) {
             log("Mouse: SET_SAMPLE_RATE command failed");
@@ -166,37 +194,63 @@ impl MouseDriver {
         true
     }
 
-    /// Process a mouse data byte
-    fn process_byte(&mut self, byte: u8) -> Option<MouseState> {
-        match self.cycle {
-            0 => {
-                // First byte: check bit 3 (always 1 for alignment)
-                if byte & 0x08 == 0 {
-                    // Misaligned packet, skip
-                    return None;
-                }
-                self.packet[0] = byte;
-                self.cycle = 1;
-                None
-            }
-            1 => {
-                self.packet[1] = byte;
-                self.cycle = 2;
-                None
-            }
-            2 => {
-                self.packet[2] = byte;
-                self.cycle = 0;
-                self.finalize_packet()
+    /// Run the IntelliMouse knock sequences and return the final device ID
+    fn detect_extensions(&self) -> u8 {
+        let mut id = 0;
+
+        if self.knock(&WHEEL_SEQUENCE) == Some(DEVICE_ID_WHEEL) {
+            id = DEVICE_ID_WHEEL;
+            if self.knock(&FIVE_BUTTON_SEQUENCE) == Some(DEVICE_ID_FIVE_BUTTON) {
+                id = DEVICE_ID_FIVE_BUTTON;
             }
-            _ => {
-                self.cycle = 0;
-                None
+        }
+
+        id
+    }
+
+    /// Send a sample-rate sequence followed by GET_ID
+    fn knock(&self, rates: &[u8]) -> Option<u8> {
+        for &rate in rates {
+            if !self.mouse_command(MOUSE_SET_SAMPLE_RATE) || !self.mouse_write_data(rate) {
+                return None;
             }
         }
+
+        if !self.mouse_command(MOUSE_GET_ID) || !self.wait_for_output() {
+            return None;
+        }
+        Some(self.read_data())
+    }
+
+    /// Bytes per packet for the negotiated protocol
+    fn packet_size(&self) -> u8 {
+        if self.device_id == DEVICE_ID_WHEEL || self.device_id == DEVICE_ID_FIVE_BUTTON {
+            4
+        } else {
+            3
+        }
     }
 
-    /// Finalize a complete 3-byte packet
+    /// Process a mouse data byte
+    fn process_byte(&mut self, byte: u8) -> Option<MouseState> {
+        // First byte: check bit 3 (always 1 for alignment)
+        if self.cycle == 0 && byte & 0x08 == 0 {
+            // Misaligned packet, skip
+            return None;
+        }
+
+        self.packet[self.cycle as usize] = byte;
+        self.cycle += 1;
+
+        if self.cycle < self.packet_size() {
+            return None;
+        }
+
+        self.cycle = 0;
+        self.finalize_packet()
+    }
+
+    /// Finalize a complete 3- or 4-byte packet
     fn finalize_packet(&mut self) -> Option<MouseState> {
         let flags = self.packet[0];
 
@@ -224,13 +278,27 @@ impl MouseDriver {
             dy = dy.wrapping_sub(256); // Sign extend
         }
 
-        // Update state with 1:1 movement (no scaling applied)
-        self.state.delta_x = dx;
-        self.state.delta_y = dy;
+        // Accumulate 1:1 movement (no scaling applied) until the state is
+        // read, so consumers see one coalesced motion per poll cycle
+        self.state.delta_x = self.state.delta_x.saturating_add(dx);
+        self.state.delta_y = self.state.delta_y.saturating_add(dy);
         self.state.left_button = (flags & 0x01) != 0;
         self.state.right_button = (flags & 0x02) != 0;
         self.state.middle_button = (flags & 0x04) != 0;
 
+        // Fourth byte: Z delta (positive = toward the user) and buttons 4/5
+        let extra = self.packet[3];
+        let z = match self.device_id {
+            DEVICE_ID_WHEEL => extra as i8,
+            DEVICE_ID_FIVE_BUTTON => (extra << 4) as i8 >> 4,
+            _ => 0,
+        };
+        self.state.wheel = self.state.wheel.saturating_add(z.saturating_neg());
+        if self.device_id == DEVICE_ID_FIVE_BUTTON {
+            self.state.back_button = (extra & 0x10) != 0;
+            self.state.forward_button = (extra & 0x20) != 0;
+        }
+
         Some(self.state)
     }
 
@@ -362,24 +430,30 @@ pub fn get_mouse_state() -> MouseState {
         // Reset deltas after reading
         MOUSE_DRIVER.state.delta_x = 0;
         MOUSE_DRIVER.state.delta_y = 0;
+        MOUSE_DRIVER.state.wheel = 0;
         state
     }
 }
 
 /// Poll for mouse data (non-blocking)
+///
+/// Drains every pending byte and returns the latest state if at least one
+/// packet completed. Motion from all packets in the batch is accumulated.
 pub fn poll_mouse() -> Option<MouseState> {
     unsafe {
         if !MOUSE_DRIVER.initialized {
             return None;
         }
 
-        // Check if mouse data is available
-        if MOUSE_DRIVER.aux_data_available() {
+        let mut latest = None;
+        while MOUSE_DRIVER.aux_data_available() {
             let byte = MOUSE_DRIVER.read_data();
-            return MOUSE_DRIVER.process_byte(byte);
+            if let Some(state) = MOUSE_DRIVER.process_byte(byte) {
+                latest = Some(state);
+            }
         }
-        
-        None
+
+        latest
     }
 }
 
@@ -416,8 +490,6 @@ fn main() -> ! {
     }
 }
 
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    log("Mouse Driver: PANIC!");
-    exit(0xFF);
-}
+atom_syscall::define_global_allocator!();
+
+atom_syscall::define_panic_handler!("Mouse Driver");
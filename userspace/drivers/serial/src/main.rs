@@ -0,0 +1,169 @@
+//! Userspace Serial Console Service
+//!
+//! This service runs entirely in Ring 3 (userspace) and:
+//! - Drives the COM2 16550 UART (COM1 stays with the kernel's early console)
+//! - Mirrors the kernel log ring (SYS_KLOG_READ) to the serial line
+//! - Provides a tiny line-based console for adjusting kernel log levels
+//!
+//! # Log redirection
+//!
+//! The kernel writes every entry to COM1 during boot. Once this service is
+//! up, `serial <level>` lowers COM1 to warnings and above (say) while COM2
+//! keeps the full log, since it reads from the ring rather than from COM1.
+//!
+//! # Console commands
+//!
+//! ```text
+//! help             list commands
+//! serial <0-4>     minimum level the kernel mirrors to COM1
+//! capture <0-4>    minimum level the kernel records at all
+//! ```
+
+#![no_std]
+#![no_main]
+
+mod uart;
+
+
+use atom_syscall::debug::{klog_read, log, set_log_level, LogLevel, LogSink};
+use atom_syscall::thread::{yield_now, exit};
+
+use uart::Uart;
+
+// ============================================================================
+// Constants
+// ============================================================================
+
+const COM2: u16 = 0x2F8;
+const BAUD_RATE: u32 = 115_200;
+
+const LINE_MAX: usize = 64;
+const PROMPT: &str = "atom> ";
+
+// ============================================================================
+// Serial Console
+// ============================================================================
+
+struct SerialConsole {
+    uart: Uart,
+    log_pos: u64,
+    line: [u8; LINE_MAX],
+    line_len: usize,
+}
+
+impl SerialConsole {
+    fn new(uart: Uart) -> Self {
+        Self {
+            uart,
+            log_pos: 0,
+            line: [0; LINE_MAX],
+            line_len: 0,
+        }
+    }
+
+    fn run(&mut self) -> ! {
+        self.uart.write_str("\nAtom OS serial console (type 'help')\n");
+
+        loop {
+            self.pump_log();
+            self.pump_input();
+            yield_now();
+        }
+    }
+
+    /// Forward any new kernel log text to the UART
+    fn pump_log(&mut self) {
+        let mut chunk = [0u8; 256];
+
+        while let Ok(count) = klog_read(&mut chunk, &mut self.log_pos, LogLevel::Debug) {
+            if count == 0 {
+                break;
+            }
+            self.uart.write_bytes(&chunk[..count]);
+        }
+    }
+
+    fn pump_input(&mut self) {
+        while let Some(byte) = self.uart.try_read_byte() {
+            match byte {
+                b'\r' | b'\n' => {
+                    self.uart.write_str("\n");
+                    let len = self.line_len;
+                    self.line_len = 0;
+                    if len > 0 {
+                        let line = self.line;
+                        if let Ok(text) = core::str::from_utf8(&line[..len]) {
+                            self.handle_command(text);
+                        }
+                    }
+                    self.uart.write_str(PROMPT);
+                }
+                0x08 | 0x7F if self.line_len > 0 => {
+                    self.line_len -= 1;
+                    self.uart.write_bytes(b"\x08 \x08");
+                }
+                0x20..=0x7E if self.line_len < LINE_MAX => {
+                    self.line[self.line_len] = byte;
+                    self.line_len += 1;
+                    self.uart.write_byte(byte);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn handle_command(&mut self, line: &str) {
+        let mut parts = line.split_whitespace();
+        let command = parts.next().unwrap_or("");
+        let level = parts
+            .next()
+            .and_then(|arg| arg.parse::<u8>().ok())
+            .and_then(LogLevel::from_u8);
+
+        match (command, level) {
+            ("help", _) => {
+                self.uart.write_str("serial <0-4>   kernel log level mirrored to COM1\n");
+                self.uart.write_str("capture <0-4>  kernel log level recorded at all\n");
+                self.uart.write_str("levels: 0=debug 1=info 2=warn 3=error 4=panic\n");
+            }
+            ("serial", Some(level)) => self.set_level(LogSink::Serial, level),
+            ("capture", Some(level)) => self.set_level(LogSink::Capture, level),
+            ("serial", None) | ("capture", None) => {
+                self.uart.write_str("expected a level from 0 to 4\n");
+            }
+            _ => self.uart.write_str("unknown command\n"),
+        }
+    }
+
+    fn set_level(&mut self, sink: LogSink, level: LogLevel) {
+        if set_log_level(sink, level).is_ok() {
+            self.uart.write_str("ok\n");
+        } else {
+            self.uart.write_str("failed\n");
+        }
+    }
+}
+
+// ============================================================================
+// Entry Points
+// ============================================================================
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    main()
+}
+
+fn main() -> ! {
+    log("Serial Console: Starting");
+
+    let uart = Uart::new(COM2);
+    if !uart.init(BAUD_RATE) {
+        log("Serial Console: No UART at COM2");
+        exit(1);
+    }
+
+    let mut console = SerialConsole::new(uart);
+    console.run()
+}
+
+atom_syscall::define_panic_handler!("Serial Console");
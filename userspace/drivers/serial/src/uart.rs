@@ -0,0 +1,102 @@
+//! 16550 UART
+//!
+//! Polled driver for a PC-compatible 16550A serial port. Register layout
+//! matches the kernel's COM1 driver (`kernel/src/serial.rs`); this copy
+//! goes through the I/O port syscalls instead of raw `in`/`out`.
+
+use atom_syscall::io::{port_read_u8, port_write_u8};
+
+// Register offsets from the port base
+const REG_DATA: u16 = 0; // RBR/THR, or divisor low when DLAB=1
+const REG_IER: u16 = 1; // Interrupt enable, or divisor high when DLAB=1
+const REG_FCR: u16 = 2;
+const REG_LCR: u16 = 3;
+const REG_MCR: u16 = 4;
+const REG_LSR: u16 = 5;
+
+const LCR_8N1: u8 = 0x03;
+const LCR_DLAB: u8 = 0x80;
+
+const FCR_ENABLE_CLEAR_14: u8 = 0xC7;
+
+const MCR_DTR_RTS_OUT2: u8 = 0x0B;
+const MCR_LOOPBACK: u8 = 0x1E;
+const MCR_NORMAL: u8 = 0x0F;
+
+const LSR_DATA_READY: u8 = 0x01;
+const LSR_THR_EMPTY: u8 = 0x20;
+
+/// Base clock divided by 16; divisor = UART_CLOCK / baud
+const UART_CLOCK: u32 = 115_200;
+
+pub struct Uart {
+    base: u16,
+}
+
+impl Uart {
+    pub const fn new(base: u16) -> Self {
+        Self { base }
+    }
+
+    /// Configure for `baud` 8N1 with FIFOs enabled
+    ///
+    /// Returns false if the loopback self-test fails (no UART present).
+    pub fn init(&self, baud: u32) -> bool {
+        let divisor = (UART_CLOCK / baud.clamp(1, UART_CLOCK)) as u16;
+
+        self.write(REG_IER, 0x00);
+        self.write(REG_LCR, LCR_DLAB);
+        self.write(REG_DATA, divisor as u8);
+        self.write(REG_IER, (divisor >> 8) as u8);
+        self.write(REG_LCR, LCR_8N1);
+        self.write(REG_FCR, FCR_ENABLE_CLEAR_14);
+        self.write(REG_MCR, MCR_DTR_RTS_OUT2);
+
+        // Loopback self-test
+        self.write(REG_MCR, MCR_LOOPBACK);
+        self.write(REG_DATA, 0xAE);
+        if self.read(REG_DATA) != 0xAE {
+            return false;
+        }
+
+        self.write(REG_MCR, MCR_NORMAL);
+        true
+    }
+
+    pub fn write_byte(&self, byte: u8) {
+        while self.read(REG_LSR) & LSR_THR_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        self.write(REG_DATA, byte);
+    }
+
+    /// Write bytes, expanding `\n` to `\r\n`
+    pub fn write_bytes(&self, bytes: &[u8]) {
+        for &byte in bytes {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+    }
+
+    pub fn write_str(&self, s: &str) {
+        self.write_bytes(s.as_bytes());
+    }
+
+    pub fn try_read_byte(&self) -> Option<u8> {
+        if self.read(REG_LSR) & LSR_DATA_READY != 0 {
+            Some(self.read(REG_DATA))
+        } else {
+            None
+        }
+    }
+
+    fn read(&self, reg: u16) -> u8 {
+        port_read_u8(self.base + reg).unwrap_or(0)
+    }
+
+    fn write(&self, reg: u16, value: u8) {
+        let _ = port_write_u8(self.base + reg, value);
+    }
+}
@@ -0,0 +1,263 @@
+// ANSI Escape Sequence Module
+//
+// This module decodes the VT100/ANSI control sequences programs embed in
+// their output into actions for the display buffer:
+// - C0 controls (newline, carriage return, backspace, tab)
+// - CSI sequences (cursor movement, erase, SGR colors, scroll regions)
+// - ESC 7 / ESC 8 (save and restore cursor) and ESC M (reverse index)
+// OSC strings (such as window titles) are consumed and ignored.
+
+use atom_syscall::graphics::Color;
+
+use crate::config::theme;
+
+/// Maximum parameters in a CSI sequence; extra ones are dropped
+pub const MAX_PARAMS: usize = 16;
+
+const ESC: u8 = 0x1B;
+const BEL: u8 = 0x07;
+/// CAN and SUB abort a sequence in progress
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1A;
+
+/// Numeric parameters of a CSI sequence
+#[derive(Clone, Copy)]
+pub struct Params {
+    values: [u16; MAX_PARAMS],
+    len: usize,
+}
+
+impl Params {
+    pub const fn new() -> Self {
+        Self {
+            values: [0; MAX_PARAMS],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, value: u16) {
+        if self.len < MAX_PARAMS {
+            self.values[self.len] = value;
+            self.len += 1;
+        }
+    }
+
+    /// Parameter `index`, or `default` when it is missing or zero
+    pub fn get(&self, index: usize, default: u16) -> u16 {
+        match self.as_slice().get(index) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u16] {
+        &self.values[..self.len]
+    }
+}
+
+/// What a byte of output asks the display to do
+#[derive(Clone, Copy)]
+pub enum Action {
+    /// Draw a character
+    Print(u8),
+    /// C0 control character
+    Control(u8),
+    /// Control sequence; `private` is set for `CSI ?` sequences
+    Csi { params: Params, private: bool, command: u8 },
+    /// Two-byte escape sequence (ESC followed by this byte)
+    Esc(u8),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+    /// Operating system command, up to BEL or ESC \
+    Osc,
+    /// ESC seen inside an OSC string
+    OscEscape,
+}
+
+/// Byte-at-a-time escape sequence decoder
+pub struct AnsiParser {
+    state: State,
+    params: Params,
+    /// Parameter being accumulated
+    current: u16,
+    /// Whether `current` has any digits or a separator came before it
+    has_param: bool,
+    private: bool,
+}
+
+impl AnsiParser {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: Params::new(),
+            current: 0,
+            has_param: false,
+            private: false,
+        }
+    }
+
+    /// Feed one byte; returns an action once a character or a whole
+    /// sequence has been read
+    pub fn feed(&mut self, byte: u8) -> Option<Action> {
+        if byte == CAN || byte == SUB {
+            self.state = State::Ground;
+            return None;
+        }
+
+        match self.state {
+            State::Ground => match byte {
+                ESC => {
+                    self.state = State::Escape;
+                    None
+                }
+                0x00..=0x1F | 0x7F => Some(Action::Control(byte)),
+                _ => Some(Action::Print(byte)),
+            },
+            State::Escape => match byte {
+                b'[' => {
+                    self.state = State::Csi;
+                    self.params = Params::new();
+                    self.current = 0;
+                    self.has_param = false;
+                    self.private = false;
+                    None
+                }
+                b']' => {
+                    self.state = State::Osc;
+                    None
+                }
+                ESC => None,
+                _ => {
+                    self.state = State::Ground;
+                    Some(Action::Esc(byte))
+                }
+            },
+            State::Csi => match byte {
+                b'0'..=b'9' => {
+                    let digit = (byte - b'0') as u16;
+                    self.current = self.current.saturating_mul(10).saturating_add(digit);
+                    self.has_param = true;
+                    None
+                }
+                b';' | b':' => {
+                    self.params.push(self.current);
+                    self.current = 0;
+                    self.has_param = true;
+                    None
+                }
+                b'?' | b'<' | b'=' | b'>' => {
+                    self.private = true;
+                    None
+                }
+                // Intermediate bytes; no sequence handled here uses them
+                0x20..=0x2F => None,
+                0x40..=0x7E => {
+                    if self.has_param {
+                        self.params.push(self.current);
+                    }
+                    self.state = State::Ground;
+                    Some(Action::Csi {
+                        params: self.params,
+                        private: self.private,
+                        command: byte,
+                    })
+                }
+                ESC => {
+                    self.state = State::Escape;
+                    None
+                }
+                // Controls inside a sequence still take effect
+                0x00..=0x1F => Some(Action::Control(byte)),
+                _ => None,
+            },
+            State::Osc => {
+                match byte {
+                    BEL => self.state = State::Ground,
+                    ESC => self.state = State::OscEscape,
+                    _ => {}
+                }
+                None
+            }
+            State::OscEscape => {
+                self.state = if byte == b'\\' { State::Ground } else { State::Osc };
+                None
+            }
+        }
+    }
+}
+
+/// Color `index` of the 256-color palette: the 16 theme colors, a 6x6x6
+/// color cube, then 24 shades of gray
+pub fn palette(index: u8) -> Color {
+    match index {
+        0..=15 => theme().ansi[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            Color::new(level(i / 36), level(i / 6 % 6), level(i % 6))
+        }
+        _ => {
+            let v = 8 + (index - 232) * 10;
+            Color::new(v, v, v)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_all(parser: &mut AnsiParser, bytes: &[u8]) -> Option<Action> {
+        let mut last = None;
+        for &byte in bytes {
+            if let Some(action) = parser.feed(byte) {
+                last = Some(action);
+            }
+        }
+        last
+    }
+
+    #[test]
+    fn test_csi_params() {
+        let mut parser = AnsiParser::new();
+        match feed_all(&mut parser, b"\x1b[12;5H") {
+            Some(Action::Csi { params, private, command }) => {
+                assert_eq!(params.as_slice(), &[12, 5]);
+                assert!(!private);
+                assert_eq!(command, b'H');
+            }
+            _ => panic!("expected CSI"),
+        }
+    }
+
+    #[test]
+    fn test_csi_defaults() {
+        let mut parser = AnsiParser::new();
+        match feed_all(&mut parser, b"\x1b[;7H") {
+            Some(Action::Csi { params, .. }) => {
+                assert_eq!(params.get(0, 1), 1);
+                assert_eq!(params.get(1, 1), 7);
+            }
+            _ => panic!("expected CSI"),
+        }
+    }
+
+    #[test]
+    fn test_osc_ignored() {
+        let mut parser = AnsiParser::new();
+        assert!(feed_all(&mut parser, b"\x1b]0;title\x07").is_none());
+        assert!(matches!(parser.feed(b'a'), Some(Action::Print(b'a'))));
+    }
+
+    #[test]
+    fn test_palette_cube() {
+        assert_eq!(palette(16), Color::new(0, 0, 0));
+        assert_eq!(palette(231), Color::new(255, 255, 255));
+        assert_eq!(palette(232), Color::new(8, 8, 8));
+    }
+}
@@ -5,37 +5,56 @@
 // - Display buffer for visible content
 // - Scrollback history
 // - Cursor position tracking
+// - ANSI escape sequences in program output
 
-use crate::window::Theme;
+use core::ptr::{addr_of, addr_of_mut};
+
+use crate::ansi::{self, Action, AnsiParser, Params};
+use crate::config::{theme, TerminalConfig};
+use crate::utf8::Utf8Decoder;
 use atom_syscall::graphics::Color;
 
 /// Maximum characters per line
 pub const MAX_LINE_LENGTH: usize = 256;
 
-/// Maximum lines in scrollback buffer
-pub const MAX_SCROLLBACK_LINES: usize = 500;
+/// Maximum lines in the scrollback of each display buffer
+pub const MAX_SCROLLBACK_LINES: usize = 1000;
+
+/// Display buffers that can exist at once, one per terminal tab
+pub const MAX_DISPLAYS: usize = 4;
 
 /// Maximum visible lines (will be set dynamically based on window size)
 pub const MAX_VISIBLE_LINES: usize = 50;
 
+/// Commands kept in history
+pub const MAX_HISTORY: usize = 32;
+
+/// Largest saved history: every command on its own line
+pub const HISTORY_FILE_SIZE: usize = MAX_HISTORY * MAX_LINE_LENGTH;
+
 /// A single character cell with color attributes
 #[derive(Clone, Copy)]
 pub struct Cell {
-    pub ch: u8,
+    pub ch: char,
     pub fg: Color,
     pub bg: Color,
 }
 
 impl Cell {
-    pub const fn empty() -> Self {
-        Self {
-            ch: b' ',
-            fg: Theme::TEXT_NORMAL,
-            bg: Theme::WINDOW_BG,
-        }
+    /// A blank in the default colors, for buffers made before the
+    /// configuration is read
+    const BLANK: Self = Self::new(
+        ' ',
+        TerminalConfig::DEFAULT.palette.text_normal,
+        TerminalConfig::DEFAULT.palette.window_bg,
+    );
+
+    /// A blank in the colors in use
+    pub fn empty() -> Self {
+        Self::new(' ', theme().text_normal, theme().window_bg)
     }
 
-    pub const fn new(ch: u8, fg: Color, bg: Color) -> Self {
+    pub const fn new(ch: char, fg: Color, bg: Color) -> Self {
         Self { ch, fg, bg }
     }
 }
@@ -56,7 +75,7 @@ pub struct Line {
 impl Line {
     pub const fn empty() -> Self {
         Self {
-            cells: [Cell::empty(); MAX_LINE_LENGTH],
+            cells: [Cell::BLANK; MAX_LINE_LENGTH],
             len: 0,
         }
     }
@@ -103,17 +122,42 @@ impl Line {
         }
     }
 
-    pub fn push_char(&mut self, ch: u8, fg: Color) -> bool {
-        self.push(Cell::new(ch, fg, Theme::WINDOW_BG))
+    pub fn push_char(&mut self, ch: char, fg: Color) -> bool {
+        self.push(Cell::new(ch, fg, theme().window_bg))
     }
 
     pub fn push_str(&mut self, s: &str, fg: Color) {
-        for byte in s.bytes() {
-            if !self.push_char(byte, fg) {
+        for ch in s.chars() {
+            if !self.push_char(ch, fg) {
                 break;
             }
         }
     }
+
+    /// Overwrite the cells from `start` up to (not including) `end` with
+    /// `blank`; erasing the end of the line in the default colors
+    /// shortens it
+    pub fn erase(&mut self, start: usize, end: usize, blank: Cell) {
+        let end = end.min(MAX_LINE_LENGTH);
+        let start = start.min(end);
+        for cell in self.cells[start..end].iter_mut() {
+            *cell = blank;
+        }
+        if end >= self.len && blank.bg == theme().window_bg {
+            self.len = self.len.min(start);
+        } else {
+            self.len = self.len.max(end);
+        }
+    }
+
+    /// Give every cell, shown or not, the colors `translate` maps its
+    /// colors to
+    fn recolor(&mut self, translate: &dyn Fn(Color) -> Color) {
+        for cell in self.cells.iter_mut() {
+            cell.fg = translate(cell.fg);
+            cell.bg = translate(cell.bg);
+        }
+    }
 }
 
 impl Default for Line {
@@ -122,7 +166,70 @@ impl Default for Line {
     }
 }
 
-/// Command line input buffer with editing support
+/// Lines scrolled off the top of the screen, in a ring that drops the
+/// oldest line when full
+struct Scrollback {
+    lines: [Line; MAX_SCROLLBACK_LINES],
+    // Ring index of the oldest line
+    start: usize,
+    len: usize,
+}
+
+impl Scrollback {
+    const fn new() -> Self {
+        // Unused slots are all zero bytes, so the ring goes in .bss
+        // instead of taking up space in the binary
+        const UNUSED: Line = Line {
+            cells: [Cell::new('\0', Color::BLACK, Color::BLACK); MAX_LINE_LENGTH],
+            len: 0,
+        };
+        Self {
+            lines: [UNUSED; MAX_SCROLLBACK_LINES],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, line: &Line) {
+        let index = (self.start + self.len) % MAX_SCROLLBACK_LINES;
+        self.lines[index].clone_from(line);
+        if self.len < MAX_SCROLLBACK_LINES {
+            self.len += 1;
+        } else {
+            self.start = (self.start + 1) % MAX_SCROLLBACK_LINES;
+        }
+    }
+
+    /// Line `index`, counting from the oldest
+    fn get(&self, index: usize) -> Option<&Line> {
+        if index < self.len {
+            Some(&self.lines[(self.start + index) % MAX_SCROLLBACK_LINES])
+        } else {
+            None
+        }
+    }
+
+    fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+
+    fn recolor(&mut self, translate: &dyn Fn(Color) -> Color) {
+        for index in 0..self.len {
+            self.lines[(self.start + index) % MAX_SCROLLBACK_LINES].recolor(translate);
+        }
+    }
+}
+
+/// Scrollback of each display buffer. At a few MB each they are far too
+/// big for the stack, so they live in static storage.
+static mut SCROLLBACK: [Scrollback; MAX_DISPLAYS] = [EMPTY_SCROLLBACK; MAX_DISPLAYS];
+
+const EMPTY_SCROLLBACK: Scrollback = Scrollback::new();
+
+/// Command line input buffer with editing support. The line is kept as
+/// UTF-8 and the cursor is a byte offset that always sits between two
+/// characters.
 pub struct InputBuffer {
     buffer: [u8; MAX_LINE_LENGTH],
     len: usize,
@@ -155,72 +262,79 @@ impl InputBuffer {
         self.cursor
     }
 
+    /// Screen column of the cursor: the characters before it
+    pub fn cursor_column(&self) -> usize {
+        self.as_str()[..self.cursor].chars().count()
+    }
+
+    /// Bytes in the character before the cursor, 0 at the start
+    fn char_before(&self) -> usize {
+        self.as_str()[..self.cursor].chars().next_back().map_or(0, char::len_utf8)
+    }
+
+    /// Bytes in the character after the cursor, 0 at the end
+    fn char_after(&self) -> usize {
+        self.as_str()[self.cursor..].chars().next().map_or(0, char::len_utf8)
+    }
+
     /// Insert a character at the cursor position
-    pub fn insert(&mut self, ch: u8) -> bool {
-        if self.len >= MAX_LINE_LENGTH - 1 {
+    pub fn insert(&mut self, ch: char) -> bool {
+        let mut encoded = [0u8; 4];
+        let bytes = ch.encode_utf8(&mut encoded).as_bytes();
+        if self.len + bytes.len() > MAX_LINE_LENGTH - 1 {
             return false;
         }
 
         // Shift characters right to make room
-        for i in (self.cursor..self.len).rev() {
-            self.buffer[i + 1] = self.buffer[i];
-        }
+        self.buffer.copy_within(self.cursor..self.len, self.cursor + bytes.len());
 
-        self.buffer[self.cursor] = ch;
-        self.len += 1;
-        self.cursor += 1;
+        self.buffer[self.cursor..self.cursor + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        self.cursor += bytes.len();
         true
     }
 
     /// Delete the character before the cursor (backspace)
     pub fn backspace(&mut self) -> bool {
-        if self.cursor == 0 {
+        let size = self.char_before();
+        if size == 0 {
             return false;
         }
 
         // Shift characters left
-        for i in self.cursor..self.len {
-            self.buffer[i - 1] = self.buffer[i];
-        }
+        self.buffer.copy_within(self.cursor..self.len, self.cursor - size);
 
-        self.len -= 1;
-        self.cursor -= 1;
+        self.len -= size;
+        self.cursor -= size;
         true
     }
 
     /// Delete the character at the cursor (delete key)
     pub fn delete(&mut self) -> bool {
-        if self.cursor >= self.len {
+        let size = self.char_after();
+        if size == 0 {
             return false;
         }
 
         // Shift characters left
-        for i in self.cursor + 1..self.len {
-            self.buffer[i - 1] = self.buffer[i];
-        }
+        self.buffer.copy_within(self.cursor + size..self.len, self.cursor);
 
-        self.len -= 1;
+        self.len -= size;
         true
     }
 
     /// Move cursor left
     pub fn cursor_left(&mut self) -> bool {
-        if self.cursor > 0 {
-            self.cursor -= 1;
-            true
-        } else {
-            false
-        }
+        let size = self.char_before();
+        self.cursor -= size;
+        size > 0
     }
 
     /// Move cursor right
     pub fn cursor_right(&mut self) -> bool {
-        if self.cursor < self.len {
-            self.cursor += 1;
-            true
-        } else {
-            false
-        }
+        let size = self.char_after();
+        self.cursor += size;
+        size > 0
     }
 
     /// Move cursor to beginning
@@ -235,25 +349,20 @@ impl InputBuffer {
 
     /// Get the current content as a string slice
     pub fn as_str(&self) -> &str {
-        // Safety: We only insert valid ASCII characters
+        // Safety: only whole characters are inserted and removed, and
+        // `set` cuts at a character boundary
         unsafe { core::str::from_utf8_unchecked(&self.buffer[..self.len]) }
     }
 
-    /// Get the current content as bytes
-    pub fn as_bytes(&self) -> &[u8] {
-        &self.buffer[..self.len]
-    }
-
     /// Set content from a string (for history navigation)
     pub fn set(&mut self, s: &str) {
-        self.clear();
-        for byte in s.bytes() {
-            if self.len < MAX_LINE_LENGTH - 1 {
-                self.buffer[self.len] = byte;
-                self.len += 1;
-            }
+        let mut len = s.len().min(MAX_LINE_LENGTH - 1);
+        while !s.is_char_boundary(len) {
+            len -= 1;
         }
-        self.cursor = self.len;
+        self.buffer[..len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len = len;
+        self.cursor = len;
     }
 }
 
@@ -265,8 +374,8 @@ impl Default for InputBuffer {
 
 /// Command history buffer
 pub struct History {
-    entries: [[u8; MAX_LINE_LENGTH]; 32],
-    lengths: [usize; 32],
+    entries: [[u8; MAX_LINE_LENGTH]; MAX_HISTORY],
+    lengths: [usize; MAX_HISTORY],
     count: usize,
     index: usize, // Current navigation index
     capacity: usize,
@@ -275,11 +384,11 @@ pub struct History {
 impl History {
     pub const fn new() -> Self {
         Self {
-            entries: [[0u8; MAX_LINE_LENGTH]; 32],
-            lengths: [0usize; 32],
+            entries: [[0u8; MAX_LINE_LENGTH]; MAX_HISTORY],
+            lengths: [0usize; MAX_HISTORY],
             count: 0,
             index: 0,
-            capacity: 32,
+            capacity: MAX_HISTORY,
         }
     }
 
@@ -304,7 +413,10 @@ impl History {
 
         let idx = self.count % self.capacity;
         let bytes = cmd.as_bytes();
-        let len = bytes.len().min(MAX_LINE_LENGTH - 1);
+        let mut len = bytes.len().min(MAX_LINE_LENGTH - 1);
+        while !cmd.is_char_boundary(len) {
+            len -= 1;
+        }
 
         self.entries[idx][..len].copy_from_slice(&bytes[..len]);
         self.lengths[idx] = len;
@@ -313,6 +425,12 @@ impl History {
         self.reset_navigation();
     }
 
+    /// Forget every entry
+    pub fn clear(&mut self) {
+        self.count = 0;
+        self.reset_navigation();
+    }
+
     /// Reset navigation index to end
     pub fn reset_navigation(&mut self) {
         self.index = self.count;
@@ -320,7 +438,7 @@ impl History {
 
     /// Navigate to previous entry (up arrow)
     pub fn previous(&mut self) -> Option<&str> {
-        if self.count == 0 || self.index == 0 {
+        if self.count == 0 || self.index <= self.first() {
             return None;
         }
 
@@ -344,26 +462,66 @@ impl History {
 
     /// Get the current history entry
     fn get_current(&self) -> Option<&str> {
-        if self.index >= self.count {
-            return None;
-        }
+        self.get(self.index)
+    }
 
-        let start = if self.count <= self.capacity {
-            0
-        } else {
-            self.count - self.capacity
-        };
+    /// Position of the oldest entry still kept
+    fn first(&self) -> usize {
+        self.count.saturating_sub(self.capacity)
+    }
 
-        if self.index < start {
+    /// Entry at a position counted from the first command ever pushed
+    pub fn get(&self, position: usize) -> Option<&str> {
+        if position < self.first() || position >= self.count {
             return None;
         }
 
-        let idx = self.index % self.capacity;
+        let idx = position % self.capacity;
         let len = self.lengths[idx];
 
-        // Safety: We only store valid ASCII
+        // Safety: entries are copied from `&str`s, cut at a character
+        // boundary
         Some(unsafe { core::str::from_utf8_unchecked(&self.entries[idx][..len]) })
     }
+
+    /// Entries kept, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &str> + '_ {
+        (self.first()..self.count).filter_map(|position| self.get(position))
+    }
+
+    /// Newest entry containing `query`, older than the entry at `before`
+    /// if given; returns its position and text
+    pub fn search(&self, query: &str, before: Option<usize>) -> Option<(usize, &str)> {
+        let end = before.unwrap_or(self.count).min(self.count);
+        (self.first()..end).rev().find_map(|position| {
+            let entry = self.get(position)?;
+            entry.contains(query).then_some((position, entry))
+        })
+    }
+
+    /// Write the entries to `out`, oldest first and one per line;
+    /// returns the length written
+    pub fn save(&self, out: &mut [u8]) -> usize {
+        let mut len = 0;
+        for entry in self.iter() {
+            let end = len + entry.len() + 1;
+            if end > out.len() {
+                break;
+            }
+            out[len..end - 1].copy_from_slice(entry.as_bytes());
+            out[end - 1] = b'\n';
+            len = end;
+        }
+        len
+    }
+
+    /// Add the commands of a saved history, one per line; repeated
+    /// lines are kept once, as when typed
+    pub fn load(&mut self, text: &str) {
+        for line in text.lines() {
+            self.push(line.trim_end());
+        }
+    }
 }
 
 impl Default for History {
@@ -386,8 +544,28 @@ pub struct DisplayBuffer {
     max_rows: usize,
     // Maximum columns
     max_cols: usize,
-    // Scrollback position (0 = at bottom, showing current content)
+    // Lines scrolled back into the scrollback (0 = at bottom, showing
+    // current content)
     scroll_offset: usize,
+    // Lines that have gone into the scrollback, dropped ones included;
+    // lines are numbered from the first line the buffer held
+    scrolled: usize,
+    // Decoder for escape sequences in `write_ansi` output
+    ansi: AnsiParser,
+    // Bytes of a character not yet written whole
+    utf8: Utf8Decoder,
+    // Colors set by SGR for `write_ansi` output
+    fg: Color,
+    bg: Color,
+    // Basic foreground color (0-7) in use, brightened while bold is on
+    fg_index: Option<u8>,
+    bold: bool,
+    // Rows (first, last) that scroll, set with DECSTBM; None = all rows
+    scroll_region: Option<(usize, usize)>,
+    // Cursor position saved with ESC 7 or CSI s
+    saved_cursor: (usize, usize),
+    // Which of the SCROLLBACK rings this buffer uses
+    ring: usize,
 }
 
 impl DisplayBuffer {
@@ -402,6 +580,16 @@ impl DisplayBuffer {
             max_rows: 25,
             max_cols: 80,
             scroll_offset: 0,
+            scrolled: 0,
+            ansi: AnsiParser::new(),
+            utf8: Utf8Decoder::new(),
+            fg: TerminalConfig::DEFAULT.palette.text_normal,
+            bg: TerminalConfig::DEFAULT.palette.window_bg,
+            fg_index: None,
+            bold: false,
+            scroll_region: None,
+            saved_cursor: (0, 0),
+            ring: 0,
         }
     }
 
@@ -409,6 +597,32 @@ impl DisplayBuffer {
     pub fn set_dimensions(&mut self, rows: usize, cols: usize) {
         self.max_rows = rows.min(MAX_VISIBLE_LINES);
         self.max_cols = cols.min(MAX_LINE_LENGTH);
+        self.scroll_region = None;
+    }
+
+    /// Change the screen size, returning how many rows the content moved
+    /// up. Rows that no longer fit above the cursor go to the scrollback;
+    /// lines wider than the screen keep their cells, which are clipped
+    /// when drawn and come back if the screen widens again.
+    pub fn resize(&mut self, rows: usize, cols: usize) -> usize {
+        let rows = rows.clamp(1, MAX_VISIBLE_LINES);
+        self.scroll_region = None;
+        let excess = (self.cursor_row + 1).saturating_sub(rows);
+        if excess > 0 {
+            self.scroll_up(0, self.max_rows - 1, excess);
+            self.cursor_row -= excess;
+        }
+
+        self.set_dimensions(rows, cols.max(1));
+        self.line_count = self.line_count.saturating_sub(excess).min(self.max_rows);
+        self.saved_cursor.0 = self.saved_cursor.0.min(self.max_rows - 1);
+        excess
+    }
+
+    /// Keep the scrollback in ring `ring` (0 by default); no two buffers
+    /// may share a ring
+    pub fn set_scrollback(&mut self, ring: usize) {
+        self.ring = ring.min(MAX_DISPLAYS - 1);
     }
 
     /// Get current dimensions
@@ -421,6 +635,66 @@ impl DisplayBuffer {
         (self.cursor_row, self.cursor_col)
     }
 
+    fn scrollback(&self) -> &Scrollback {
+        // SAFETY: the terminal is single-threaded and this buffer is the
+        // only user of its ring, so borrowing it through `self` keeps
+        // references to it unique
+        unsafe { &(*addr_of!(SCROLLBACK))[self.ring] }
+    }
+
+    fn scrollback_mut(&mut self) -> &mut Scrollback {
+        // SAFETY: as in `scrollback`
+        unsafe { &mut (*addr_of_mut!(SCROLLBACK))[self.ring] }
+    }
+
+    /// Lines the view is scrolled back from the bottom
+    pub fn scroll_offset(&self) -> usize {
+        self.scroll_offset
+    }
+
+    /// Scroll the view n lines back into the scrollback
+    pub fn scroll_back(&mut self, n: usize) {
+        self.scroll_offset = (self.scroll_offset + n).min(self.scrollback().len);
+    }
+
+    /// Scroll the view n lines forward, towards the current content
+    pub fn scroll_forward(&mut self, n: usize) {
+        self.scroll_offset = self.scroll_offset.saturating_sub(n);
+    }
+
+    /// Show the current content again
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll_offset = 0;
+    }
+
+    /// Number of the cursor's line; a line keeps its number as it
+    /// scrolls into the scrollback
+    pub fn line_number(&self) -> usize {
+        self.scrolled + self.cursor_row
+    }
+
+    /// Number of the line at the top of the view
+    pub fn top_line(&self) -> usize {
+        self.scrolled - self.scroll_offset
+    }
+
+    /// Scroll the view so line `number` is at the top, or as close to it
+    /// as the scrollback and the current content allow
+    pub fn scroll_to_line(&mut self, number: usize) {
+        self.scroll_offset = self.scrolled.saturating_sub(number).min(self.scrollback().len);
+    }
+
+    /// Line `number`, while it is in the scrollback or on the screen
+    pub fn line(&self, number: usize) -> Option<&Line> {
+        let scrollback = self.scrollback();
+        let index = number.checked_sub(self.scrolled - scrollback.len)?;
+        if index < scrollback.len {
+            return scrollback.get(index);
+        }
+        let row = index - scrollback.len;
+        (row < self.line_count).then(|| &self.lines[row])
+    }
+
     /// Clear the entire display
     pub fn clear(&mut self) {
         for line in self.lines.iter_mut() {
@@ -432,19 +706,48 @@ impl DisplayBuffer {
         self.scroll_offset = 0;
     }
 
+    /// Clear the screen and the scrollback and go back to default colors
+    pub fn reset(&mut self) {
+        self.reset_attributes();
+        self.utf8 = Utf8Decoder::new();
+        self.scroll_region = None;
+        self.clear();
+        self.scrollback_mut().clear();
+        self.scrolled = 0;
+    }
+
+    /// Change the colors of everything written so far, for a new color
+    /// scheme: `translate` maps each old color to its new one
+    pub fn recolor(&mut self, translate: &dyn Fn(Color) -> Color) {
+        for line in self.lines.iter_mut() {
+            line.recolor(translate);
+        }
+        self.scrollback_mut().recolor(translate);
+        self.fg = translate(self.fg);
+        self.bg = translate(self.bg);
+    }
+
+    /// Write a byte of UTF-8 text; the bytes of a multi-byte character are
+    /// held until the last one arrives
+    pub fn write_byte(&mut self, byte: u8, fg: Color) {
+        for ch in self.utf8.feed(byte) {
+            self.write_char(ch, fg);
+        }
+    }
+
     /// Write a character at the cursor position
-    pub fn write_char(&mut self, ch: u8, fg: Color) {
-        if ch == b'\n' {
+    pub fn write_char(&mut self, ch: char, fg: Color) {
+        if ch == '\n' {
             self.newline();
             return;
         }
 
-        if ch == b'\r' {
+        if ch == '\r' {
             self.cursor_col = 0;
             return;
         }
 
-        if ch == 0x08 {
+        if ch == '\x08' {
             // Backspace
             if self.cursor_col > 0 {
                 self.cursor_col -= 1;
@@ -458,7 +761,7 @@ impl DisplayBuffer {
         }
 
         // Write character
-        let cell = Cell::new(ch, fg, Theme::WINDOW_BG);
+        let cell = Cell::new(ch, fg, theme().window_bg);
         self.lines[self.cursor_row].set(self.cursor_col, cell);
         self.cursor_col += 1;
 
@@ -470,8 +773,8 @@ impl DisplayBuffer {
 
     /// Write a string at the cursor position
     pub fn write_str(&mut self, s: &str, fg: Color) {
-        for byte in s.bytes() {
-            self.write_char(byte, fg);
+        for ch in s.chars() {
+            self.write_char(ch, fg);
         }
     }
 
@@ -484,56 +787,367 @@ impl DisplayBuffer {
     /// Move to a new line
     pub fn newline(&mut self) {
         self.cursor_col = 0;
-        self.cursor_row += 1;
+        self.line_feed();
+
+        // Ensure line is clear
+        self.lines[self.cursor_row].clear();
+    }
 
-        // Scroll if needed
-        if self.cursor_row >= self.max_rows {
-            self.scroll_up(1);
-            self.cursor_row = self.max_rows - 1;
+    /// Move the cursor down a row, scrolling the scroll region when it is
+    /// on the region's last row
+    fn line_feed(&mut self) {
+        let (top, bottom) = self.region();
+        if self.cursor_row == bottom {
+            self.scroll_up(top, bottom, 1);
+        } else if self.cursor_row + 1 < self.max_rows {
+            self.cursor_row += 1;
         }
 
-        // Ensure line exists and is clear
+        // Ensure line exists
         while self.line_count <= self.cursor_row {
             self.line_count += 1;
         }
-        self.lines[self.cursor_row].clear();
     }
 
-    /// Scroll content up by n lines
-    fn scroll_up(&mut self, n: usize) {
-        if n >= self.max_rows {
-            self.clear();
-            return;
+    /// First and last row of the scroll region
+    fn region(&self) -> (usize, usize) {
+        self.scroll_region.unwrap_or((0, self.max_rows.saturating_sub(1)))
+    }
+
+    /// Scroll rows `top` to `bottom` up by n lines; lines leaving the top
+    /// of the whole screen go to the scrollback
+    fn scroll_up(&mut self, top: usize, bottom: usize, n: usize) {
+        let n = n.min(bottom + 1 - top);
+
+        if top == 0 && self.scroll_region.is_none() {
+            for i in 0..n {
+                let line = self.lines[i].clone();
+                self.scrollback_mut().push(&line);
+            }
+            self.scrolled += n;
+            // Keep a scrolled-back view on the same lines
+            if self.scroll_offset > 0 {
+                self.scroll_back(n);
+            }
         }
 
         // Shift lines up
-        for i in 0..self.max_rows - n {
-            // Clone the line from i + n to i
+        for i in top..bottom + 1 - n {
+            // Manual copy since we can't easily swap in const arrays
             let src_idx = i + n;
-            if src_idx < self.max_rows {
-                // Manual copy since we can't easily swap in const arrays
-                for j in 0..MAX_LINE_LENGTH {
-                    self.lines[i].cells[j] = self.lines[src_idx].cells[j];
-                }
-                self.lines[i].len = self.lines[src_idx].len;
+            for j in 0..MAX_LINE_LENGTH {
+                self.lines[i].cells[j] = self.lines[src_idx].cells[j];
             }
+            self.lines[i].len = self.lines[src_idx].len;
         }
 
         // Clear new lines at the bottom
-        for i in (self.max_rows - n)..self.max_rows {
+        for i in (bottom + 1 - n)..=bottom {
             self.lines[i].clear();
         }
     }
 
-    /// Get a line for rendering
+    /// Scroll rows `top` to `bottom` down by n lines
+    fn scroll_down(&mut self, top: usize, bottom: usize, n: usize) {
+        let n = n.min(bottom + 1 - top);
+
+        // Shift lines down, starting from the bottom
+        for i in (top + n..=bottom).rev() {
+            let src_idx = i - n;
+            for j in 0..MAX_LINE_LENGTH {
+                self.lines[i].cells[j] = self.lines[src_idx].cells[j];
+            }
+            self.lines[i].len = self.lines[src_idx].len;
+        }
+
+        // Clear new lines at the top
+        for i in top..top + n {
+            self.lines[i].clear();
+        }
+        self.line_count = self.line_count.max((bottom + 1).min(self.max_rows));
+    }
+
+    /// Write program output, interpreting ANSI escape sequences
+    pub fn write_ansi(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            match self.ansi.feed(byte) {
+                Some(Action::Print(byte)) => {
+                    for ch in self.utf8.feed(byte) {
+                        self.put_char(ch);
+                    }
+                }
+                Some(Action::Control(ch)) => self.control(ch),
+                Some(Action::Csi { params, private: false, command }) => self.csi(&params, command),
+                Some(Action::Esc(ch)) => self.escape(ch),
+                Some(Action::Csi { .. }) | None => {}
+            }
+        }
+    }
+
+    /// Print a character in the SGR colors; a character past the last
+    /// column wraps first
+    fn put_char(&mut self, ch: char) {
+        if self.cursor_col >= self.max_cols {
+            self.cursor_col = 0;
+            self.line_feed();
+        }
+        while self.line_count <= self.cursor_row {
+            self.line_count += 1;
+        }
+        let cell = Cell::new(ch, self.fg, self.bg);
+        self.lines[self.cursor_row].set(self.cursor_col, cell);
+        self.cursor_col += 1;
+    }
+
+    fn control(&mut self, ch: u8) {
+        match ch {
+            // Programs write bare newlines; the terminal adds the return
+            b'\n' | 0x0B | 0x0C => {
+                self.cursor_col = 0;
+                self.line_feed();
+            }
+            b'\r' => self.cursor_col = 0,
+            0x08 => self.cursor_col = self.cursor_col.saturating_sub(1),
+            b'\t' => {
+                let next = (self.cursor_col / 8 + 1) * 8;
+                self.cursor_col = next.min(self.max_cols.saturating_sub(1));
+            }
+            _ => {}
+        }
+    }
+
+    fn escape(&mut self, ch: u8) {
+        match ch {
+            b'7' => self.saved_cursor = (self.cursor_row, self.cursor_col),
+            b'8' => {
+                let (row, col) = self.saved_cursor;
+                self.set_cursor(row, col);
+            }
+            // Index: down a row, scrolling at the bottom
+            b'D' => self.line_feed(),
+            // Reverse index: up a row, scrolling down at the top
+            b'M' => {
+                let (top, bottom) = self.region();
+                if self.cursor_row == top {
+                    self.scroll_down(top, bottom, 1);
+                } else {
+                    self.cursor_row = self.cursor_row.saturating_sub(1);
+                }
+            }
+            // Full reset
+            b'c' => self.reset(),
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, params: &Params, command: u8) {
+        let n = params.get(0, 1) as usize;
+        let (row, col) = (self.cursor_row, self.cursor_col);
+        let last_row = self.max_rows.saturating_sub(1);
+
+        match command {
+            b'A' => self.cursor_row = row.saturating_sub(n),
+            b'B' => self.cursor_row = (row + n).min(last_row),
+            b'C' => self.cursor_col = (col + n).min(self.max_cols.saturating_sub(1)),
+            b'D' => self.cursor_col = col.saturating_sub(n),
+            b'E' => self.set_cursor(row + n, 0),
+            b'F' => self.set_cursor(row.saturating_sub(n), 0),
+            b'G' => self.set_cursor(row, n - 1),
+            b'd' => self.set_cursor(n - 1, col),
+            b'H' | b'f' => {
+                let col = params.get(1, 1) as usize;
+                self.set_cursor(n - 1, col - 1);
+            }
+            b'J' => self.erase_display(params.get(0, 0)),
+            b'K' => self.erase_line(params.get(0, 0)),
+            b'S' => {
+                let (top, bottom) = self.region();
+                self.scroll_up(top, bottom, n);
+            }
+            b'T' => {
+                let (top, bottom) = self.region();
+                self.scroll_down(top, bottom, n);
+            }
+            b'm' => self.select_graphic_rendition(params),
+            b'r' => {
+                let top = params.get(0, 1) as usize - 1;
+                let bottom = (params.get(1, self.max_rows as u16) as usize - 1).min(last_row);
+                self.scroll_region = if top < bottom && (top, bottom) != (0, last_row) {
+                    Some((top, bottom))
+                } else {
+                    None
+                };
+                self.set_cursor(0, 0);
+            }
+            b's' => self.saved_cursor = (row, col),
+            b'u' => {
+                let (row, col) = self.saved_cursor;
+                self.set_cursor(row, col);
+            }
+            _ => {}
+        }
+    }
+
+    /// Blank cell in the current background color
+    fn blank(&self) -> Cell {
+        Cell::new(' ', self.fg, self.bg)
+    }
+
+    /// ED: 0 = cursor to end of screen, 1 = start of screen to cursor,
+    /// 2 = whole screen, 3 = whole screen and scrollback
+    fn erase_display(&mut self, mode: u16) {
+        let blank = self.blank();
+        let (row, col) = (self.cursor_row, self.cursor_col);
+        let rows = match mode {
+            0 => {
+                self.lines[row].erase(col, MAX_LINE_LENGTH, blank);
+                row + 1..self.max_rows
+            }
+            1 => {
+                self.lines[row].erase(0, col + 1, blank);
+                0..row
+            }
+            2 => 0..self.max_rows,
+            3 => {
+                self.scrollback_mut().clear();
+                self.scroll_offset = 0;
+                0..self.max_rows
+            }
+            _ => return,
+        };
+        for i in rows {
+            self.lines[i].erase(0, MAX_LINE_LENGTH, blank);
+        }
+
+        // Rows filled with a background color must be drawn
+        if blank.bg != theme().window_bg {
+            self.line_count = self.max_rows;
+        }
+    }
+
+    /// EL: 0 = cursor to end of line, 1 = start of line to cursor,
+    /// 2 = whole line
+    fn erase_line(&mut self, mode: u16) {
+        let blank = self.blank();
+        let col = self.cursor_col;
+        let line = &mut self.lines[self.cursor_row];
+        match mode {
+            0 => line.erase(col, MAX_LINE_LENGTH, blank),
+            1 => line.erase(0, col + 1, blank),
+            2 => line.erase(0, MAX_LINE_LENGTH, blank),
+            _ => {}
+        }
+    }
+
+    fn reset_attributes(&mut self) {
+        self.fg = theme().text_normal;
+        self.bg = theme().window_bg;
+        self.fg_index = None;
+        self.bold = false;
+    }
+
+    /// SGR: colors (8, 16, 256 and 24-bit) and bold
+    fn select_graphic_rendition(&mut self, params: &Params) {
+        let values = params.as_slice();
+        if values.is_empty() {
+            self.reset_attributes();
+            return;
+        }
+
+        let mut i = 0;
+        while i < values.len() {
+            match values[i] {
+                0 => self.reset_attributes(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                code @ 30..=37 => self.fg_index = Some((code - 30) as u8),
+                code @ 90..=97 => {
+                    self.fg_index = None;
+                    self.fg = ansi::palette((code - 90 + 8) as u8);
+                }
+                39 => {
+                    self.fg_index = None;
+                    self.fg = theme().text_normal;
+                }
+                code @ 40..=47 => self.bg = ansi::palette((code - 40) as u8),
+                code @ 100..=107 => self.bg = ansi::palette((code - 100 + 8) as u8),
+                49 => self.bg = theme().window_bg,
+                code @ (38 | 48) => {
+                    let (color, used) = extended_color(&values[i + 1..]);
+                    i += used;
+                    if let Some(color) = color {
+                        if code == 38 {
+                            self.fg_index = None;
+                            self.fg = color;
+                        } else {
+                            self.bg = color;
+                        }
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+
+        if let Some(index) = self.fg_index {
+            self.fg = ansi::palette(if self.bold { index + 8 } else { index });
+        }
+    }
+
+    /// Get a line for rendering; `row` is a row of the view, which may be
+    /// scrolled back into the scrollback
     pub fn get_line(&self, row: usize) -> Option<&Line> {
-        if row < self.max_rows && row < self.line_count {
+        if row >= self.max_rows {
+            return None;
+        }
+
+        // Position counting from the oldest scrollback line
+        let scrollback = self.scrollback();
+        let index = scrollback.len - self.scroll_offset + row;
+        if index < scrollback.len {
+            return scrollback.get(index);
+        }
+
+        let row = index - scrollback.len;
+        if row < self.line_count {
             Some(&self.lines[row])
         } else {
             None
         }
     }
 
+    /// Copy the text of view cells `first` to `last` (inclusive, in
+    /// reading order) into `out`, a line per row without trailing blanks;
+    /// returns the number of bytes written
+    pub fn copy_text(&self, first: (usize, usize), last: (usize, usize), out: &mut [u8]) -> usize {
+        let mut len = 0;
+        for row in first.0..=last.0 {
+            let start = if row == first.0 { first.1 } else { 0 };
+            let end = if row == last.0 { last.1 + 1 } else { self.max_cols };
+            let line = self.get_line(row);
+            let row_start = len;
+            for col in start..end {
+                let ch = line.and_then(|line| line.get(col)).map_or(' ', |cell| cell.ch);
+                let mut encoded = [0u8; 4];
+                let bytes = ch.encode_utf8(&mut encoded).as_bytes();
+                if len + bytes.len() <= out.len() {
+                    out[len..len + bytes.len()].copy_from_slice(bytes);
+                    len += bytes.len();
+                }
+            }
+
+            // Blanks at the end of a row are padding, not text
+            while len > row_start && out[len - 1] == b' ' {
+                len -= 1;
+            }
+            if row != last.0 && len < out.len() {
+                out[len] = b'\n';
+                len += 1;
+            }
+        }
+        len
+    }
+
     /// Set cursor position (for prompt rendering)
     pub fn set_cursor(&mut self, row: usize, col: usize) {
         self.cursor_row = row.min(self.max_rows.saturating_sub(1));
@@ -541,6 +1155,18 @@ impl DisplayBuffer {
     }
 }
 
+/// Color after SGR 38 or 48 (`5;n` or `2;r;g;b`), and how many
+/// parameters it took
+fn extended_color(values: &[u16]) -> (Option<Color>, usize) {
+    match values {
+        [5, index, ..] => (Some(ansi::palette(*index as u8)), 2),
+        [2, r, g, b, ..] => (Some(Color::new(*r as u8, *g as u8, *b as u8)), 4),
+        [5, ..] => (None, values.len()),
+        [2, ..] => (None, values.len()),
+        _ => (None, 0),
+    }
+}
+
 impl Default for DisplayBuffer {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,123 @@
+// Audio Commands
+//
+// Test commands for the sound server: a one-shot beep and a streamed tone
+// that exercises the full libaudio stream path.
+
+use super::{CommandContext, CommandResult};
+use crate::parser::{ParsedCommand, parse_number};
+
+use atom_syscall::thread::yield_now;
+use libaudio::{AudioClient, CHANNELS, SAMPLE_RATE};
+
+const DEFAULT_FREQUENCY: u32 = 440;
+const DEFAULT_DURATION_MS: u32 = 250;
+const MAX_DURATION_MS: u32 = 10_000;
+
+/// Parse `[freq] [ms]` arguments shared by beep and play
+fn tone_args(cmd: &ParsedCommand<'_>) -> Option<(u32, u32)> {
+    let frequency = match cmd.arg(0) {
+        Some(arg) => parse_number(arg)? as u32,
+        None => DEFAULT_FREQUENCY,
+    };
+    let duration = match cmd.arg(1) {
+        Some(arg) => parse_number(arg)? as u32,
+        None => DEFAULT_DURATION_MS,
+    };
+
+    if !(20..=20_000).contains(&frequency) || duration == 0 {
+        return None;
+    }
+    Some((frequency, duration.min(MAX_DURATION_MS)))
+}
+
+/// beep command - ask the sound server to play a short tone
+pub fn cmd_beep(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    let (frequency, duration) = match tone_args(cmd) {
+        Some(args) => args,
+        None => {
+            ctx.error("Usage: beep [freq 20-20000] [ms]");
+            return CommandResult::Error;
+        }
+    };
+
+    let client = match AudioClient::connect() {
+        Ok(client) => client,
+        Err(_) => {
+            ctx.error("Sound server not available");
+            return CommandResult::Error;
+        }
+    };
+
+    if client.beep(frequency, duration).is_err() {
+        ctx.error("Failed to send beep request");
+        return CommandResult::Error;
+    }
+
+    CommandResult::Ok
+}
+
+/// play command - stream a generated tone through an audio stream
+pub fn cmd_play(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    let (frequency, duration) = match tone_args(cmd) {
+        Some(args) => args,
+        None => {
+            ctx.error("Usage: play [freq 20-20000] [ms]");
+            return CommandResult::Error;
+        }
+    };
+
+    let client = match AudioClient::connect() {
+        Ok(client) => client,
+        Err(_) => {
+            ctx.error("Sound server not available");
+            return CommandResult::Error;
+        }
+    };
+
+    let stream = match client.open_stream() {
+        Ok(stream) => stream,
+        Err(_) => {
+            ctx.error("Failed to open audio stream");
+            return CommandResult::Error;
+        }
+    };
+
+    ctx.info("Playing tone...");
+
+    // Triangle wave: no floating point or libm needed
+    let period = (SAMPLE_RATE / frequency).max(2);
+    let mut remaining = SAMPLE_RATE / 1000 * duration;
+    let mut phase = 0u32;
+    let mut chunk = [0i16; 256];
+
+    while remaining > 0 {
+        let frames = (chunk.len() / CHANNELS as usize).min(remaining as usize);
+        for frame in chunk[..frames * CHANNELS as usize].chunks_exact_mut(CHANNELS as usize) {
+            let pos = phase * 4 * 8_000 / period;
+            let sample = if pos < 2 * 8_000 {
+                pos as i32 - 8_000
+            } else {
+                3 * 8_000 - pos as i32
+            };
+            frame.fill(sample as i16);
+            phase = (phase + 1) % period;
+        }
+
+        let mut written = 0;
+        let samples = &chunk[..frames * CHANNELS as usize];
+        while written < samples.len() {
+            written += stream.write(&samples[written..]);
+            if written < samples.len() {
+                yield_now();
+            }
+        }
+        remaining -= frames as u32;
+    }
+
+    if stream.take_underrun() {
+        ctx.warning("Playback underrun occurred");
+    }
+
+    ctx.success("Done");
+    CommandResult::Ok
+}
@@ -10,9 +10,9 @@
 
 use super::{CommandContext, CommandResult};
 
-use crate::parser::ParsedCommand;
+use crate::config::theme;
 
-use crate::window::Theme;
+use crate::parser::ParsedCommand;
 
 
 
@@ -26,7 +26,7 @@ static mut CURRENT_DIR_LEN: usize = 1;
 
 /// Get current directory as string
 
-fn get_current_dir() -> &'static str {
+pub fn get_current_dir() -> &'static str {
 
     unsafe {
 
@@ -98,7 +98,7 @@ pub fn cmd_ls(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandR
 
     let header_str = unsafe { core::str::from_utf8_unchecked(&header[..pos]) };
 
-    ctx.println_colored(header_str, Theme::TEXT_INFO);
+    ctx.println_colored(header_str, theme().text_info);
 
     ctx.println("");
 
@@ -220,7 +220,7 @@ pub fn cmd_ls(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandR
 
             if is_dir {
 
-                ctx.println_colored(line_str, Theme::PROMPT_PATH);
+                ctx.println_colored(line_str, theme().prompt_path);
 
             } else {
 
@@ -252,7 +252,7 @@ pub fn cmd_ls(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandR
 
                 let dir_str = unsafe { core::str::from_utf8_unchecked(&dir_name[..dpos]) };
 
-                ctx.println_colored(dir_str, Theme::PROMPT_PATH);
+                ctx.println_colored(dir_str, theme().prompt_path);
 
             } else {
 
@@ -460,7 +460,7 @@ pub fn cmd_cd(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandR
 
 pub fn cmd_pwd(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
 
-    ctx.println_colored(get_current_dir(), Theme::PROMPT_PATH);
+    ctx.println_colored(get_current_dir(), theme().prompt_path);
 
     CommandResult::Ok
 
@@ -476,6 +476,30 @@ pub fn cmd_cat(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Command
 
         Some(f) => f,
 
+        None if ctx.input.is_some() => {
+
+            // Copy piped input through
+
+            let mut chunk = [0u8; 256];
+
+            loop {
+
+                let count = ctx.read(&mut chunk);
+
+                if count == 0 {
+
+                    break;
+
+                }
+
+                ctx.print_ansi(&chunk[..count]);
+
+            }
+
+            return CommandResult::Ok;
+
+        }
+
         None => {
 
             ctx.error("Usage: cat <filename>");
@@ -616,7 +640,7 @@ pub fn cmd_tree(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Comman
 
     ctx.println("");
 
-    ctx.println_colored(path, Theme::PROMPT_PATH);
+    ctx.println_colored(path, theme().prompt_path);
 
 
 
@@ -674,7 +698,7 @@ pub fn cmd_tree(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Comman
 
         if is_dir {
 
-            ctx.println_colored(line_str, Theme::PROMPT_PATH);
+            ctx.println_colored(line_str, theme().prompt_path);
 
         } else {
 
@@ -6,11 +6,28 @@
 pub mod system;
 pub mod process;
 pub mod filesystem;
+pub mod audio;
+pub mod shell;
+pub mod table;
+pub mod terminal;
+pub mod trace;
+pub mod profile;
+
+use core::ptr::addr_of_mut;
+
+use atom_syscall::graphics::Color;
 
 use crate::buffer::DisplayBuffer;
+use crate::config::theme;
 use crate::ipc_client::IpcClient;
-use crate::parser::ParsedCommand;
-use crate::window::Theme;
+use crate::job::Job;
+use crate::pager::Style;
+use crate::parser::{CommandLine, Connector, ParsedCommand, parse_command, parse_line};
+use crate::stream::{InputStream, OutputStream, Pipe};
+use crate::vars::{self, STATUS};
+
+/// Longest line after variables and command output are put in
+const MAX_EXPANDED_LENGTH: usize = 512;
 
 /// Result of command execution
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -25,53 +42,250 @@ pub enum CommandResult {
     Clear,
     /// Request to exit the terminal
     Exit,
+    /// Request to run the script `source` loaded
+    Source,
 }
 
 /// Command context containing resources needed by commands
 pub struct CommandContext<'a> {
-    pub display: &'a mut DisplayBuffer,
+    /// Where the command prints: the display, or a pipe
+    pub output: &'a mut dyn OutputStream,
+    /// Output of the previous command, when it was piped into this one
+    pub input: Option<&'a mut dyn InputStream>,
     pub ipc: &'a IpcClient,
+    /// Program the command started; the line waits for it to exit
+    pub job: Option<Job>,
+    /// Logs the command follows; the line waits for Ctrl+C
+    pub follow: Option<trace::Follow>,
 }
 
+/// The terminal while a program runs in the foreground
+pub trait Foreground {
+    /// Show the display; called as the program's output arrives
+    fn show(&mut self, display: &DisplayBuffer);
+
+    /// Whether Ctrl+C was pressed; other keys are dropped
+    fn interrupted(&mut self) -> bool;
+
+    /// Pass what was typed and pasted to `job`, echoing to `display`;
+    /// true if Ctrl+C was pressed
+    fn forward(&mut self, job: &mut Job, display: &mut DisplayBuffer) -> bool;
+}
+
+/// Pipes between the commands of a line: a command writes one and reads
+/// the other, which the command before it wrote
+static mut PIPES: [Pipe; 2] = [Pipe::new(), Pipe::new()];
+
 impl<'a> CommandContext<'a> {
     /// Print a line to the display
     pub fn println(&mut self, text: &str) {
-        self.display.writeln(text, Theme::TEXT_NORMAL);
+        self.println_colored(text, theme().text_normal);
     }
 
     /// Print with specific color
-    pub fn println_colored(&mut self, text: &str, color: atom_syscall::graphics::Color) {
-        self.display.writeln(text, color);
+    pub fn println_colored(&mut self, text: &str, color: Color) {
+        self.output.write(text.as_bytes(), color);
+        self.output.write(b"\n", color);
     }
 
     /// Print without newline
     pub fn print(&mut self, text: &str) {
-        self.display.write_str(text, Theme::TEXT_NORMAL);
+        self.output.write(text.as_bytes(), theme().text_normal);
+    }
+
+    /// Print program output containing ANSI escape sequences
+    pub fn print_ansi(&mut self, bytes: &[u8]) {
+        self.output.write_ansi(bytes);
     }
 
     /// Print error message
     pub fn error(&mut self, text: &str) {
-        self.display.writeln(text, Theme::TEXT_ERROR);
+        self.println_colored(text, theme().text_error);
     }
 
     /// Print success message
     pub fn success(&mut self, text: &str) {
-        self.display.writeln(text, Theme::TEXT_SUCCESS);
+        self.println_colored(text, theme().text_success);
     }
 
     /// Print info message
     pub fn info(&mut self, text: &str) {
-        self.display.writeln(text, Theme::TEXT_INFO);
+        self.println_colored(text, theme().text_info);
     }
 
     /// Print warning message
     pub fn warning(&mut self, text: &str) {
-        self.display.writeln(text, Theme::TEXT_WARNING);
+        self.println_colored(text, theme().text_warning);
+    }
+
+    /// Read piped input into `buffer`; returns the number of bytes read,
+    /// 0 at the end or when nothing is piped in
+    pub fn read(&mut self, buffer: &mut [u8]) -> usize {
+        match self.input.as_mut() {
+            Some(input) => input.read(buffer),
+            None => 0,
+        }
     }
 }
 
+/// Expand, parse and run a line typed at the prompt or read from a script
+///
+/// `$?` is set to 0 if the line succeeded and 1 if it failed.
+pub fn run_line(
+    text: &str,
+    display: &mut DisplayBuffer,
+    ipc: &IpcClient,
+    foreground: &mut dyn Foreground,
+) -> CommandResult {
+    let mut expanded = [0u8; MAX_EXPANDED_LENGTH];
+    let expansion = vars::expand(
+        text,
+        &mut expanded,
+        |name| vars::variables().get(name),
+        |command, out| capture(command, out, display, ipc, foreground),
+    );
+
+    let text = match expansion.map(|len| core::str::from_utf8(&expanded[..len])) {
+        Ok(Ok(text)) => Ok(text),
+        Ok(Err(_)) => Err("Command output is not text"),
+        Err(error) => Err(error.message()),
+    };
+    let result = match text.and_then(|text| parse_line(text).map_err(|error| error.message())) {
+        Ok(line) => execute_line(&line, display, ipc, foreground),
+        Err(message) => {
+            display.writeln(message, theme().text_error);
+            CommandResult::Error
+        }
+    };
+
+    let failed = matches!(result, CommandResult::Error | CommandResult::NotFound);
+    vars::variables_mut().set(STATUS, if failed { "1" } else { "0" });
+    result
+}
+
+/// Run one command for `$(command)`, writing its output to `out`
+fn capture(
+    command: &str,
+    out: &mut [u8],
+    display: &mut DisplayBuffer,
+    ipc: &IpcClient,
+    foreground: &mut dyn Foreground,
+) -> usize {
+    let Some(cmd) = parse_command(command) else {
+        return 0;
+    };
+
+    // Safety: the line has not started running, so no pipe is in use
+    let pipe = unsafe { &mut (*addr_of_mut!(PIPES))[0] };
+    pipe.clear();
+    let mut ctx = CommandContext {
+        output: &mut *pipe,
+        input: None,
+        ipc,
+        job: None,
+        follow: None,
+    };
+    execute(&cmd, &mut ctx);
+    let follow = ctx.follow.take();
+    if let Some(mut job) = ctx.job.take() {
+        process::wait_for(&mut job, Some(&mut *pipe), display, foreground);
+    }
+    if let Some(follow) = follow {
+        trace::follow(follow, Some(&mut *pipe), display, ipc, foreground);
+    }
+
+    let output = pipe.as_bytes();
+    let len = output.len().min(out.len());
+    out[..len].copy_from_slice(&output[..len]);
+    len
+}
+
+/// Run every command of a line, connecting pipes and redirections
+///
+/// Returns the result of the last command that ran. `clear` clears the
+/// display as soon as it runs, so output after it on the line stays.
+pub fn execute_line(
+    line: &CommandLine<'_>,
+    display: &mut DisplayBuffer,
+    ipc: &IpcClient,
+    foreground: &mut dyn Foreground,
+) -> CommandResult {
+    // Safety: the terminal runs one line at a time on a single thread
+    let [first, second] = unsafe { &mut *addr_of_mut!(PIPES) };
+    let (mut pipe, mut previous) = (first, second);
+
+    let mut result = CommandResult::Ok;
+    // Whether the previous command's output is in `previous`
+    let mut piped = false;
+    // After a failed `&&`, commands are skipped up to the next `;`
+    let mut skipping = false;
+
+    for stage in line.stages() {
+        core::mem::swap(&mut pipe, &mut previous);
+        let has_input = core::mem::take(&mut piped);
+        if skipping {
+            skipping = stage.next != Connector::Then;
+            continue;
+        }
+
+        pipe.clear();
+        let to_pipe = stage.redirect.is_some() || stage.next == Connector::Pipe;
+        let output: &mut dyn OutputStream = if to_pipe { &mut *pipe } else { &mut *display };
+        let mut ctx = CommandContext {
+            output,
+            input: has_input.then_some(&mut *previous as &mut dyn InputStream),
+            ipc,
+            job: None,
+            follow: None,
+        };
+        result = execute(&stage.command, &mut ctx);
+
+        let follow = ctx.follow.take();
+        if let Some(mut job) = ctx.job.take() {
+            let output = if to_pipe { Some(&mut *pipe) } else { None };
+            result = process::wait_for(&mut job, output, display, foreground);
+        }
+        if let Some(follow) = follow {
+            let output = if to_pipe { Some(&mut *pipe) } else { None };
+            result = trace::follow(follow, output, display, ipc, foreground);
+        }
+
+        if let Some(redirect) = stage.redirect {
+            if !ipc.write_file(redirect.path, pipe.as_bytes(), redirect.append) {
+                display.writeln("Cannot write to file", theme().text_error);
+                result = CommandResult::Error;
+            }
+        } else {
+            piped = stage.next == Connector::Pipe;
+        }
+
+        match result {
+            CommandResult::Exit => return result,
+            CommandResult::Clear => {
+                display.clear();
+                result = CommandResult::Ok;
+            }
+            CommandResult::Source => {
+                result = shell::run_script(display, ipc, foreground);
+                if result == CommandResult::Exit {
+                    return result;
+                }
+            }
+            _ => {}
+        }
+        let failed = matches!(result, CommandResult::Error | CommandResult::NotFound);
+        skipping = failed && stage.next == Connector::And;
+    }
+
+    result
+}
+
 /// Execute a parsed command
 pub fn execute(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    if let Some((name, value)) = vars::assignment(cmd.command) {
+        return shell::cmd_assign(name, value, cmd, ctx);
+    }
+
     match cmd.command.to_ascii_lowercase().as_str() {
         // System information commands
         "help" | "?" => system::cmd_help(cmd, ctx),
@@ -96,13 +310,30 @@ pub fn execute(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Command
         "cat" | "type" => filesystem::cmd_cat(cmd, ctx),
         "tree" => filesystem::cmd_tree(cmd, ctx),
 
+        // Audio commands
+        "beep" => audio::cmd_beep(cmd, ctx),
+        "play" => audio::cmd_play(cmd, ctx),
+
+        // Shell commands
+        "set" => shell::cmd_set(cmd, ctx),
+        "export" => shell::cmd_export(cmd, ctx),
+        "unset" => shell::cmd_unset(cmd, ctx),
+        "source" | "." => shell::cmd_source(cmd, ctx),
+
         // Terminal control
+        "config" => terminal::cmd_config(cmd, ctx),
+        "less" => terminal::cmd_page(cmd, ctx, Style::Less),
+        "more" => terminal::cmd_page(cmd, ctx, Style::More),
         "exit" | "quit" | "logout" => CommandResult::Exit,
 
         // Debug/diagnostic commands
         "log" | "dmesg" => system::cmd_log(cmd, ctx),
         "ports" => system::cmd_ports(cmd, ctx),
         "caps" => system::cmd_caps(cmd, ctx),
+        "ipcstat" => trace::cmd_ipcstat(cmd, ctx),
+        "captrace" => trace::cmd_captrace(cmd, ctx),
+        "trace" => trace::cmd_trace(cmd, ctx),
+        "profile" => profile::cmd_profile(cmd, ctx),
 
         // Unknown command
         _ => {
@@ -120,22 +351,50 @@ pub fn get_command_help(cmd: &str) -> Option<(&'static str, &'static str)> {
         "uptime" => Some(("uptime", "Show system uptime")),
         "date" | "time" => Some(("date", "Display current date and time")),
         "clear" | "cls" => Some(("clear", "Clear the terminal screen")),
-        "echo" => Some(("echo [text...]", "Display text")),
+        "echo" => Some(("echo [-e] [text...]", "Display text (-e: interpret \\e, \\n, \\t)")),
         "sysinfo" => Some(("sysinfo", "Display system information summary")),
         "ps" | "procs" => Some(("ps", "List running processes")),
         "kill" => Some(("kill <pid>", "Terminate a process")),
-        "exec" | "run" => Some(("exec <program>", "Execute a program")),
+        "exec" | "run" => Some(("exec <program>", "Run a program; Ctrl+C stops it")),
         "mem" | "memory" => Some(("mem", "Display memory usage")),
         "services" | "svc" => Some(("services", "List registered services")),
-        "ls" | "dir" => Some(("ls [path]", "List directory contents")),
+        "ls" | "dir" => Some(("ls [-a] [-l] [path]", "List directory contents")),
         "cd" => Some(("cd <path>", "Change current directory")),
         "pwd" => Some(("pwd", "Print working directory")),
-        "cat" | "type" => Some(("cat <file>", "Display file contents")),
-        "tree" => Some(("tree [path]", "Display directory tree")),
+        "cat" | "type" => Some(("cat [file]", "Display file contents, or piped input")),
+        "tree" => Some(("tree [-d depth] [path]", "Display directory tree")),
+        "beep" => Some(("beep [freq] [ms]", "Play a short tone on the sound server")),
+        "play" => Some(("play [freq] [ms]", "Stream a test tone through an audio stream")),
+        "set" => Some(("set [name [value...]]", "List shell variables, or set one")),
+        "export" => Some(("export [name[=value]...]", "List or extend the environment")),
+        "unset" => Some(("unset <name...>", "Remove shell variables")),
+        "source" | "." => Some(("source <file>", "Run the commands in a file")),
+        "config" => Some((
+            "config [key [value] | save | load | reset]",
+            "Show or change colors, font scale, cursor style and padding",
+        )),
+        "less" => Some(("less [file]", "Page through a file or piped input; q quits")),
+        "more" => Some(("more [file]", "Like less, closing at the end of the output")),
         "exit" | "quit" => Some(("exit", "Exit the terminal")),
-        "log" | "dmesg" => Some(("log", "Display system log")),
+        "log" | "dmesg" => Some((
+            "log [-l level] [-n count]",
+            "Display the kernel log from a level up (-n: newest lines only)",
+        )),
         "ports" => Some(("ports", "List IPC ports")),
         "caps" => Some(("caps", "List capabilities")),
+        "ipcstat" => Some(("ipcstat [port...]", "Show IPC port traffic and latency")),
+        "captrace" => Some((
+            "captrace [-f] [-n count]",
+            "List IPC messages and capability events (-f: follow until Ctrl+C)",
+        )),
+        "trace" => Some((
+            "trace [-n count] [-s subsystem] | latency | ipc | on|off [subsystem...]",
+            "Show the kernel trace, wakeup latency or IPC flows; choose what is traced",
+        )),
+        "profile" => Some((
+            "profile [-n count] | start [thread] | stop",
+            "Sample where CPU time goes and list the busiest functions",
+        )),
         _ => None,
     }
 }
@@ -164,7 +423,23 @@ pub fn get_all_commands() -> &'static [(&'static str, &'static str)] {
         ("pwd", "Print working directory"),
         ("cat", "Display file contents"),
         ("tree", "Directory tree"),
+        // Audio
+        ("beep", "Play a short tone"),
+        ("play", "Stream a test tone"),
+        // Shell
+        ("set", "Shell variables"),
+        ("export", "Environment variables"),
+        ("unset", "Remove variables"),
+        ("source", "Run a script"),
+        // Debug
+        ("ipcstat", "IPC port statistics"),
+        ("captrace", "IPC and capability trace"),
+        ("trace", "Kernel event trace"),
+        ("profile", "Sampling profiler"),
         // Terminal
+        ("config", "Terminal settings"),
+        ("less", "Page through output"),
+        ("more", "Page through output"),
         ("exit", "Exit terminal"),
     ]
 }
\ No newline at end of file
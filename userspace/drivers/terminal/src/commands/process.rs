@@ -4,123 +4,149 @@
 
 // Commands for process management: listing, killing, spawning processes.
 
-// All process information is obtained via IPC to the process manager service.
+// Thread and memory figures come from the kernel's statistics syscalls.
 
+// Programs started with `exec` run in the foreground: their output goes to
 
+// the terminal until they exit.
 
-use super::{CommandContext, CommandResult};
+
+
+use atom_syscall::process::EXIT_KILLED;
+
+use atom_syscall::system::{self, ThreadInfo};
+
+use atom_syscall::thread::yield_now;
+
+
+
+use super::table::{Column, Table, Text};
+
+use super::{CommandContext, CommandResult, Foreground};
+
+use crate::buffer::DisplayBuffer;
+
+use crate::config::theme;
+
+use crate::job::Job;
 
 use crate::parser::{ParsedCommand, parse_number};
 
-use crate::window::Theme;
+use crate::stream::Pipe;
 
 
 
-/// ps command - list running processes
+/// Threads `ps` lists
 
-pub fn cmd_ps(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+const MAX_THREADS: usize = 64;
 
-    ctx.println("");
 
-    ctx.println_colored("Running Processes", Theme::TEXT_INFO);
 
-    ctx.println("-----------------");
+/// ps command - list threads with their state and CPU time
 
-    ctx.println("");
+pub fn cmd_ps(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
 
-    ctx.println("PID   NAME             STATE");
+    let mut threads = [ThreadInfo::default(); MAX_THREADS];
 
-    ctx.println("---   ----             -----");
+    let (count, sched) = match (system::thread_list(&mut threads), system::sched_stats()) {
 
+        (Ok(count), Ok(sched)) => (count, sched),
 
+        _ => {
 
-    ctx.ipc.query_processes(|pid, name, state| {
+            ctx.error("Cannot read the thread list");
 
-        let mut line = [0u8; 64];
+            return CommandResult::Error;
 
-        let mut pos = 0;
+        }
 
+    };
 
 
-        // Format PID (right-aligned in 5 chars)
 
-        let mut pid_str = [0u8; 8];
+    const COLUMNS: [Column; 6] = [
 
-        let pid_len = format_number(pid, &mut pid_str);
+        Column::right("TID", 5),
 
+        Column::left("NAME", 20),
 
+        Column::left("STATE", 8),
 
-        // Pad PID to 6 chars
+        Column::left("PRIORITY", 8),
 
-        for _ in 0..(5 - pid_len.min(5)) {
+        Column::right("TIME", 9),
 
-            line[pos] = b' ';
+        Column::right("CPU", 4),
 
-            pos += 1;
+    ];
 
-        }
+    let table = Table::new(&COLUMNS);
 
-        for i in 0..pid_len.min(5) {
 
-            line[pos] = pid_str[i];
 
-            pos += 1;
+    ctx.println("");
 
-        }
+    table.header(ctx);
 
-        line[pos] = b' ';
+    for thread in &threads[..count.min(MAX_THREADS)] {
 
-        pos += 1;
+        let tid = Text::number(thread.id);
 
+        let time = Text::duration(thread.ticks);
 
+        let cpu = Text::percent(thread.ticks, sched.ticks);
 
-        // Name (16 chars)
+        table.row(ctx, &[
 
-        for byte in name.bytes() {
+            tid.as_str(),
 
-            if pos < 22 {
+            thread.name(),
 
-                line[pos] = byte;
+            thread.state_name(),
 
-                pos += 1;
+            thread.priority_name(),
 
-            }
+            time.as_str(),
 
-        }
+            cpu.as_str(),
 
-        while pos < 22 {
+        ]);
 
-            line[pos] = b' ';
+    }
 
-            pos += 1;
+    ctx.println("");
 
-        }
 
 
+    let counts = [
 
-        // State
+        (sched.threads, " threads: "),
 
-        for byte in state.bytes() {
+        (sched.running, " running, "),
 
-            if pos < 60 {
+        (sched.ready, " ready, "),
 
-                line[pos] = byte;
+        (sched.blocked, " blocked, "),
 
-                pos += 1;
+        (sched.exited, " exited"),
 
-            }
+    ];
 
-        }
+    for (count, label) in counts {
 
+        ctx.print(Text::number(count).as_str());
 
+        ctx.print(label);
 
-        let line_str = unsafe { core::str::from_utf8_unchecked(&line[..pos]) };
+    }
 
-        ctx.println(line_str);
+    ctx.println("");
 
-    });
+    if count > MAX_THREADS {
 
+        ctx.warning("Only the first 64 threads are listed");
 
+    }
 
     ctx.println("");
 
@@ -238,249 +264,221 @@ pub fn cmd_exec(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Comman
 
 
 
-    ctx.info("Attempting to execute program...");
-
-
-
-    // Collect arguments
-
-    let args: [&str; 16] = {
-
-        let mut arr = [""; 16];
-
-        for i in 1..cmd.arg_count.min(16) {
-
-            arr[i - 1] = cmd.args[i];
-
-        }
-
-        arr
-
-    };
-
-
-
-    match ctx.ipc.spawn_process(program, &args[..cmd.arg_count.saturating_sub(1)]) {
-
-        Some(pid) => {
+    // The kernel starts programs without arguments
 
-            let mut msg = [0u8; 64];
+    if cmd.arg_count > 1 {
 
-            let mut pos = 0;
+        ctx.warning("Arguments are not passed to programs yet");
 
-            for byte in "Started process with PID ".bytes() {
-
-                msg[pos] = byte;
+    }
 
-                pos += 1;
 
-            }
 
-            pos += format_number(pid, &mut msg[pos..]);
+    match Job::spawn(program) {
 
+        Ok(job) => {
 
+            // The line waits for it, copying its output
 
-            let msg_str = unsafe { core::str::from_utf8_unchecked(&msg[..pos]) };
+            ctx.job = Some(job);
 
-            ctx.success(msg_str);
+            CommandResult::Ok
 
         }
 
-        None => {
-
-            ctx.warning("Process spawning not yet implemented");
+        Err(_) => {
 
-            ctx.info("In a full implementation, this would:");
+            ctx.error("Cannot start program (is it in the boot manifest?)");
 
-            ctx.info("  1. Send request to process manager");
-
-            ctx.info("  2. Load executable from filesystem");
-
-            ctx.info("  3. Create new address space");
-
-            ctx.info("  4. Start execution");
+            CommandResult::Error
 
         }
 
     }
 
-
-
-    CommandResult::Ok
-
 }
 
 
 
-/// mem command - display memory usage
+/// Copy a program's output to `pipe`, or to the display, until it exits,
 
-pub fn cmd_memory(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+/// passing it what is typed; Ctrl+C kills it. A program that exits with a
 
-    ctx.println("");
+/// status other than 0 fails.
 
-    ctx.println_colored("Memory Usage", Theme::TEXT_INFO);
+pub fn wait_for(
 
-    ctx.println("------------");
+    job: &mut Job,
 
-    ctx.println("");
+    mut pipe: Option<&mut Pipe>,
 
+    display: &mut DisplayBuffer,
 
+    foreground: &mut dyn Foreground,
 
-    let (total_kb, used_kb, free_kb) = ctx.ipc.query_memory();
+) -> CommandResult {
 
+    loop {
 
+        // Output sent just before exiting is copied before the status is
 
-    // Total memory
+        // looked at
 
-    let mut line = [0u8; 64];
+        let status = job.try_wait();
 
-    let mut pos = 0;
+        let arrived = match pipe.as_deref_mut() {
 
-    for byte in "Total:     ".bytes() {
+            Some(pipe) => job.pump(pipe),
 
-        line[pos] = byte;
+            None => job.pump(display),
 
-        pos += 1;
+        };
 
-    }
+        if arrived && pipe.is_none() {
 
-    pos += format_size_kb(total_kb, &mut line[pos..]);
+            foreground.show(display);
 
-    let line_str = unsafe { core::str::from_utf8_unchecked(&line[..pos]) };
+        }
 
-    ctx.println(line_str);
 
 
+        match status {
 
-    // Used memory
+            Some(0) => return CommandResult::Ok,
 
-    pos = 0;
+            Some(EXIT_KILLED) => return CommandResult::Error,
 
-    for byte in "Used:      ".bytes() {
+            Some(code) => {
 
-        line[pos] = byte;
+                let mut text = [0u8; 48];
 
-        pos += 1;
+                let mut len = 0;
 
-    }
+                for byte in "Program exited with status ".bytes() {
 
-    pos += format_size_kb(used_kb, &mut line[pos..]);
+                    text[len] = byte;
 
+                    len += 1;
 
+                }
 
-    // Calculate percentage
+                len += format_number(code, &mut text[len..]);
 
-    let percent = if total_kb > 0 {
+                // Safety: ASCII text and digits
 
-        (used_kb * 100 / total_kb) as u8
+                let text = unsafe { core::str::from_utf8_unchecked(&text[..len]) };
 
-    } else {
+                display.writeln(text, theme().text_error);
 
-        0
+                return CommandResult::Error;
 
-    };
+            }
 
-    for byte in " (".bytes() {
+            None => {}
 
-        line[pos] = byte;
+        }
 
-        pos += 1;
 
-    }
 
-    pos += format_number(percent as u64, &mut line[pos..]);
+        if foreground.forward(job, display) {
 
-    for byte in "%)".bytes() {
+            job.kill();
 
-        line[pos] = byte;
+            display.writeln("^C", theme().text_dim);
 
-        pos += 1;
+        }
+
+        yield_now();
 
     }
 
+}
 
 
-    let line_str = unsafe { core::str::from_utf8_unchecked(&line[..pos]) };
 
-    ctx.println(line_str);
+/// mem command - display physical memory and kernel heap usage
 
+pub fn cmd_memory(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
 
+    let Ok(stats) = system::mem_stats() else {
 
-    // Free memory
+        ctx.error("Cannot read memory usage");
 
-    pos = 0;
+        return CommandResult::Error;
 
-    for byte in "Free:      ".bytes() {
+    };
 
-        line[pos] = byte;
 
-        pos += 1;
 
-    }
+    const COLUMNS: [Column; 5] = [
 
-    pos += format_size_kb(free_kb, &mut line[pos..]);
+        Column::left("MEMORY", 8),
 
+        Column::right("TOTAL", 8),
 
+        Column::right("USED", 8),
 
-    let free_percent = if total_kb > 0 {
+        Column::right("FREE", 8),
 
-        (free_kb * 100 / total_kb) as u8
+        Column::right("USE", 4),
 
-    } else {
+    ];
 
-        0
+    let table = Table::new(&COLUMNS);
 
-    };
+    let rows = [
 
-    for byte in " (".bytes() {
+        ("Physical", stats.total_bytes, stats.used_bytes()),
 
-        line[pos] = byte;
+        ("Heap", stats.heap_bytes, stats.heap_used_bytes),
 
-        pos += 1;
+    ];
 
-    }
 
-    pos += format_number(free_percent as u64, &mut line[pos..]);
 
-    for byte in "%)".bytes() {
+    ctx.println("");
 
-        line[pos] = byte;
+    table.header(ctx);
 
-        pos += 1;
+    for (name, total, used) in rows {
 
-    }
+        let free = total.saturating_sub(used);
 
+        table.row(ctx, &[
 
+            name,
 
-    let line_str = unsafe { core::str::from_utf8_unchecked(&line[..pos]) };
+            Text::size(total).as_str(),
 
-    ctx.println(line_str);
+            Text::size(used).as_str(),
 
+            Text::size(free).as_str(),
 
+            Text::percent(used, total).as_str(),
 
-    ctx.println("");
+        ]);
 
+    }
 
+    ctx.println("");
 
-    // Memory bar visualization
 
-    let bar_width = 40usize;
 
-    let used_bars = if total_kb > 0 {
+    // Physical memory in use
 
-        ((used_kb * bar_width as u64) / total_kb) as usize
+    let bar_width = 40u64;
 
-    } else {
+    let used_bars = (stats.used_bytes() * bar_width)
 
-        0
+        .checked_div(stats.total_bytes)
 
-    };
+        .unwrap_or(0);
 
 
 
     let mut bar = [0u8; 64];
 
-    pos = 0;
+    let mut pos = 0;
 
     bar[pos] = b'[';
 
@@ -490,15 +488,7 @@ pub fn cmd_memory(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Com
 
     for i in 0..bar_width {
 
-        if i < used_bars {
-
-            bar[pos] = b'#';
-
-        } else {
-
-            bar[pos] = b'-';
-
-        }
+        bar[pos] = if i < used_bars { b'#' } else { b'-' };
 
         pos += 1;
 
@@ -514,7 +504,7 @@ pub fn cmd_memory(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Com
 
     let bar_str = unsafe { core::str::from_utf8_unchecked(&bar[..pos]) };
 
-    ctx.println_colored(bar_str, Theme::TEXT_INFO);
+    ctx.println_colored(bar_str, theme().text_info);
 
     ctx.println("");
 
@@ -532,7 +522,7 @@ pub fn cmd_services(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> C
 
     ctx.println("");
 
-    ctx.println_colored("Registered Services", Theme::TEXT_INFO);
+    ctx.println_colored("Registered Services", theme().text_info);
 
     ctx.println("-------------------");
 
@@ -624,7 +614,7 @@ pub fn cmd_services(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> C
 
         if status == "active" {
 
-            ctx.println_colored(line_str, Theme::TEXT_SUCCESS);
+            ctx.println_colored(line_str, theme().text_success);
 
         } else {
 
@@ -704,70 +694,4 @@ fn format_number(mut n: u64, buffer: &mut [u8]) -> usize {
 
     count
 
-}
-
-
-
-/// Format size in KB as human-readable
-
-fn format_size_kb(kb: u64, buffer: &mut [u8]) -> usize {
-
-    let mut pos = 0;
-
-
-
-    if kb < 1024 {
-
-        pos += format_number(kb, &mut buffer[pos..]);
-
-        for byte in " KB".bytes() {
-
-            if pos < buffer.len() {
-
-                buffer[pos] = byte;
-
-                pos += 1;
-
-            }
-
-        }
-
-    } else if kb < 1024 * 1024 {
-
-        pos += format_number(kb / 1024, &mut buffer[pos..]);
-
-        for byte in " MB".bytes() {
-
-            if pos < buffer.len() {
-
-                buffer[pos] = byte;
-
-                pos += 1;
-
-            }
-
-        }
-
-    } else {
-
-        pos += format_number(kb / (1024 * 1024), &mut buffer[pos..]);
-
-        for byte in " GB".bytes() {
-
-            if pos < buffer.len() {
-
-                buffer[pos] = byte;
-
-                pos += 1;
-
-            }
-
-        }
-
-    }
-
-
-
-    pos
-
 }
\ No newline at end of file
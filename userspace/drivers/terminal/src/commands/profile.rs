@@ -0,0 +1,189 @@
+// Profile Command
+//
+// Drives the kernel's sampling profiler and turns its samples into a flat
+// profile: how many timer ticks landed in each function, busiest first.
+// - `profile start [thread]` starts sampling every thread, or just one
+// - `profile stop` stops sampling
+// - `profile [-n count]` reads the samples of every CPU and lists the
+//   busiest functions
+//
+// Samples are named by the kernel from each program's symbol table. Those
+// it cannot name are counted as "[kernel]" when the CPU was in the kernel
+// and "[no symbols]" when it was running a program built without them.
+
+use atom_syscall::error::SyscallError;
+use atom_syscall::profile::{self, Sample, MAX_NAME};
+
+use super::table::{Column, Table, Text};
+use super::{CommandContext, CommandResult};
+use crate::parser::ParsedCommand;
+
+/// Functions told apart; samples of any others are counted as "[other]"
+const MAX_FUNCTIONS: usize = 64;
+
+/// Samples read from the kernel at a time
+const READ_CHUNK: usize = 64;
+
+/// Functions listed unless given -n
+const DEFAULT_FUNCTIONS: usize = 20;
+
+const PROFILE_USAGE: &str = "Usage: profile [-n count] | start [thread] | stop";
+
+/// Samples that landed in one function
+#[derive(Clone, Copy)]
+struct Function {
+    name: [u8; MAX_NAME],
+    name_len: usize,
+    samples: u64,
+}
+
+impl Function {
+    const EMPTY: Self = Self {
+        name: [0; MAX_NAME],
+        name_len: 0,
+        samples: 0,
+    };
+
+    fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("?")
+    }
+}
+
+/// profile command - sampling profiler
+pub fn cmd_profile(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    match cmd.arg(0) {
+        Some("start") => start(cmd, ctx),
+        Some("stop") => {
+            let taken = profile::stop();
+            ctx.print("Profiling stopped: ");
+            ctx.print(Text::number(taken).as_str());
+            ctx.println(" samples");
+            CommandResult::Ok
+        }
+        None => print_profile(cmd, ctx),
+        Some(_) if cmd.has_flag("-n", "--count") => print_profile(cmd, ctx),
+        Some(_) => {
+            ctx.error(PROFILE_USAGE);
+            CommandResult::Error
+        }
+    }
+}
+
+/// `profile start [thread]`
+fn start(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    let thread = match cmd.arg(1).map(str::parse) {
+        Some(Ok(thread)) => thread,
+        Some(Err(_)) => {
+            ctx.error(PROFILE_USAGE);
+            return CommandResult::Error;
+        }
+        None => 0,
+    };
+
+    match profile::start(thread) {
+        Ok(()) => {
+            if thread == 0 {
+                ctx.print("Profiling every thread");
+            } else {
+                ctx.print("Profiling thread ");
+                ctx.print(Text::number(thread).as_str());
+            }
+            ctx.println("; stop with: profile stop");
+            CommandResult::Ok
+        }
+        Err(SyscallError::Busy) => {
+            ctx.error("A profile is already running; stop it with: profile stop");
+            CommandResult::Error
+        }
+        Err(SyscallError::PermissionDenied) => {
+            ctx.error("Not allowed to profile the kernel");
+            CommandResult::Error
+        }
+        Err(_) => {
+            ctx.error("Could not start the profiler");
+            CommandResult::Error
+        }
+    }
+}
+
+/// `profile [-n count]`: the flat profile of the samples held
+fn print_profile(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    let limit = match cmd.get_option("-n", "--count").map(str::parse) {
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            ctx.error(PROFILE_USAGE);
+            return CommandResult::Error;
+        }
+        None => DEFAULT_FUNCTIONS,
+    };
+
+    let mut functions = [Function::EMPTY; MAX_FUNCTIONS];
+    let mut count = 0;
+    let mut total = 0;
+    let mut chunk = [Sample::EMPTY; READ_CHUNK];
+
+    // Every CPU has its own ring; reading past the last one fails
+    for cpu in 0.. {
+        let mut cursor = 0;
+        let mut read = match profile::read(cpu, &mut chunk, &mut cursor) {
+            Ok(read) => read,
+            Err(_) => break,
+        };
+        while read > 0 {
+            for sample in &chunk[..read] {
+                let name = match sample.function() {
+                    Some(name) => name,
+                    None if sample.is_user() => "[no symbols]",
+                    None => "[kernel]",
+                };
+                count_sample(&mut functions, &mut count, name);
+                total += 1;
+            }
+            read = profile::read(cpu, &mut chunk, &mut cursor).unwrap_or(0);
+        }
+    }
+
+    if total == 0 {
+        ctx.println("No samples; start the profiler with: profile start [thread]");
+        return CommandResult::Ok;
+    }
+
+    let functions = &mut functions[..count];
+    functions.sort_unstable_by(|a, b| b.samples.cmp(&a.samples));
+
+    const COLUMNS: [Column; 3] = [
+        Column::right("SAMPLES", 8),
+        Column::right("%", 4),
+        Column::left("FUNCTION", MAX_NAME),
+    ];
+    let table = Table::new(&COLUMNS);
+    table.header(ctx);
+    for function in functions.iter().take(limit) {
+        let samples = Text::number(function.samples);
+        let percent = Text::percent(function.samples, total);
+        table.row(ctx, &[samples.as_str(), percent.as_str(), function.name()]);
+    }
+    CommandResult::Ok
+}
+
+/// Add a sample to `name`'s count, keeping the last slot for "[other]"
+/// once the table is full
+fn count_sample(functions: &mut [Function; MAX_FUNCTIONS], count: &mut usize, name: &str) {
+    if let Some(function) = functions[..*count].iter_mut().find(|f| f.name() == name) {
+        function.samples += 1;
+        return;
+    }
+
+    if *count == MAX_FUNCTIONS {
+        functions[MAX_FUNCTIONS - 1].samples += 1;
+        return;
+    }
+
+    let name = if *count < MAX_FUNCTIONS - 1 { name } else { "[other]" };
+    let function = &mut functions[*count];
+    let len = name.len().min(MAX_NAME);
+    function.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+    function.name_len = len;
+    function.samples = 1;
+    *count += 1;
+}
@@ -0,0 +1,211 @@
+// Shell Commands
+//
+// Commands that set, list and export shell variables, and `source`, which
+// runs a file of commands one line at a time. Lines starting with '#' in
+// a script are comments.
+
+use core::ptr::{addr_of, addr_of_mut};
+
+use super::{CommandContext, CommandResult, Foreground, run_line};
+use crate::buffer::DisplayBuffer;
+use crate::config::theme;
+use crate::ipc_client::IpcClient;
+use crate::parser::ParsedCommand;
+use crate::vars::{self, MAX_VALUE_LENGTH, STATUS};
+
+/// Largest script `source` runs
+pub const MAX_SCRIPT_SIZE: usize = 4096;
+
+/// Script being run; `source` loads it and the line executor runs it
+static mut SCRIPT: [u8; MAX_SCRIPT_SIZE] = [0u8; MAX_SCRIPT_SIZE];
+static mut SCRIPT_LEN: usize = 0;
+static mut SOURCING: bool = false;
+
+/// Text of the script `source` loaded
+fn script() -> &'static str {
+    // Safety: the terminal is single-threaded; `cmd_source` only loads
+    // UTF-8 text
+    unsafe {
+        let script = &*addr_of!(SCRIPT);
+        core::str::from_utf8_unchecked(&script[..SCRIPT_LEN])
+    }
+}
+
+/// Mark whether a script is running; scripts cannot source other scripts
+fn set_sourcing(sourcing: bool) {
+    unsafe {
+        SOURCING = sourcing;
+    }
+}
+
+/// NAME=value - set a variable
+pub fn cmd_assign(
+    name: &str,
+    value: &str,
+    cmd: &ParsedCommand<'_>,
+    ctx: &mut CommandContext<'_>,
+) -> CommandResult {
+    if cmd.arg_count > 0 {
+        ctx.error("Values with spaces need quotes: set NAME \"value\"");
+        return CommandResult::Error;
+    }
+    assign(name, value, ctx)
+}
+
+/// set command - list variables, or set one
+pub fn cmd_set(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    let Some(first) = cmd.arg(0) else {
+        for (name, value, _) in vars::variables().iter() {
+            if name != STATUS {
+                print_variable("", name, value, ctx);
+            }
+        }
+        return CommandResult::Ok;
+    };
+
+    if let Some((name, value)) = vars::assignment(first) {
+        if cmd.arg_count > 1 {
+            ctx.error("Values with spaces need quotes: set NAME \"value\"");
+            return CommandResult::Error;
+        }
+        return assign(name, value, ctx);
+    }
+
+    // set NAME word... joins the words with spaces
+    let mut value = [0u8; MAX_VALUE_LENGTH];
+    let mut len = 0;
+    for (i, word) in cmd.args[1..cmd.arg_count].iter().enumerate() {
+        let separator: &[u8] = if i > 0 { b" " } else { b"" };
+        for &byte in separator.iter().chain(word.as_bytes()) {
+            if len < MAX_VALUE_LENGTH {
+                value[len] = byte;
+                len += 1;
+            }
+        }
+    }
+    // Safety: words from the command line; a cut multi-byte character is
+    // dropped by `Variables::set`
+    let value = unsafe { core::str::from_utf8_unchecked(&value[..len]) };
+    assign(first, value, ctx)
+}
+
+/// export command - list the environment, or add variables to it
+pub fn cmd_export(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    if cmd.arg_count == 0 {
+        for (name, value, exported) in vars::variables().iter() {
+            if exported {
+                print_variable("export ", name, value, ctx);
+            }
+        }
+        return CommandResult::Ok;
+    }
+
+    for &arg in &cmd.args[..cmd.arg_count] {
+        let name = match vars::assignment(arg) {
+            Some((name, value)) => {
+                if assign(name, value, ctx) != CommandResult::Ok {
+                    return CommandResult::Error;
+                }
+                name
+            }
+            None if vars::is_name(arg) => arg,
+            None => {
+                ctx.error("Invalid variable name");
+                return CommandResult::Error;
+            }
+        };
+        if !vars::variables_mut().export(name) {
+            ctx.error("Too many variables");
+            return CommandResult::Error;
+        }
+    }
+
+    CommandResult::Ok
+}
+
+/// unset command - remove variables
+pub fn cmd_unset(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    if cmd.arg_count == 0 {
+        ctx.error("Usage: unset <name...>");
+        return CommandResult::Error;
+    }
+
+    for &name in &cmd.args[..cmd.arg_count] {
+        vars::variables_mut().unset(name);
+    }
+    CommandResult::Ok
+}
+
+/// source command - run a script file
+pub fn cmd_source(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    let Some(path) = cmd.arg(0) else {
+        ctx.error("Usage: source <file>");
+        return CommandResult::Error;
+    };
+
+    if unsafe { SOURCING } {
+        ctx.error("Scripts cannot source other scripts");
+        return CommandResult::Error;
+    }
+
+    // Safety: no script is running, so nothing borrows the buffer
+    let script = unsafe { &mut *addr_of_mut!(SCRIPT) };
+    let len = match ctx.ipc.read_file(path, script) {
+        Some(len) if core::str::from_utf8(&script[..len]).is_ok() => len,
+        Some(_) => {
+            ctx.error("Script is not text");
+            return CommandResult::Error;
+        }
+        None => {
+            ctx.error("Cannot read script");
+            return CommandResult::Error;
+        }
+    };
+    unsafe {
+        SCRIPT_LEN = len;
+    }
+
+    CommandResult::Source
+}
+
+fn assign(name: &str, value: &str, ctx: &mut CommandContext<'_>) -> CommandResult {
+    if !vars::is_name(name) {
+        ctx.error("Invalid variable name");
+        return CommandResult::Error;
+    }
+    if !vars::variables_mut().set(name, value) {
+        ctx.error("Too many variables");
+        return CommandResult::Error;
+    }
+    CommandResult::Ok
+}
+
+fn print_variable(prefix: &str, name: &str, value: &str, ctx: &mut CommandContext<'_>) {
+    ctx.print(prefix);
+    ctx.output.write(name.as_bytes(), theme().text_info);
+    ctx.print("=");
+    ctx.println(value);
+}
+
+/// Run the script `source` loaded, line by line; returns the result of
+/// its last line. Its output goes to the display.
+pub fn run_script(
+    display: &mut DisplayBuffer,
+    ipc: &IpcClient,
+    foreground: &mut dyn Foreground,
+) -> CommandResult {
+    set_sourcing(true);
+    let mut result = CommandResult::Ok;
+    for line in script().lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        result = run_line(line, display, ipc, foreground);
+        if result == CommandResult::Exit {
+            break;
+        }
+    }
+    set_sourcing(false);
+    result
+}
@@ -1,11 +1,16 @@
 // System Commands
 //
 // Commands for displaying system information, version, uptime, etc.
-// All information is obtained via IPC requests to system services.
+// Uptime, thread and memory figures come from the kernel's statistics
+// syscalls.
 
 use super::{CommandContext, CommandResult, get_all_commands, get_command_help};
+use super::table::Text;
+use crate::config::theme;
 use crate::parser::ParsedCommand;
-use crate::window::Theme;
+use atom_syscall::debug::LogLevel;
+use atom_syscall::graphics::Color;
+use atom_syscall::system;
 use atom_syscall::thread::get_ticks;
 
 /// Version information
@@ -20,7 +25,7 @@ pub fn cmd_help(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Comman
         // Show help for specific command
         if let Some((usage, desc)) = get_command_help(topic) {
             ctx.println("");
-            ctx.println_colored(usage, Theme::TEXT_INFO);
+            ctx.println_colored(usage, theme().text_info);
             ctx.println(desc);
             ctx.println("");
         } else {
@@ -29,7 +34,7 @@ pub fn cmd_help(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Comman
     } else {
         // Show all commands
         ctx.println("");
-        ctx.println_colored("Atom Terminal - Available Commands", Theme::TEXT_INFO);
+        ctx.println_colored("Atom Terminal - Available Commands", theme().text_info);
         ctx.println("-----------------------------------");
         ctx.println("");
 
@@ -51,6 +56,16 @@ pub fn cmd_help(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Comman
                 || *name == "cat" || *name == "tree"
             {
                 "Filesystem"
+            } else if *name == "beep" || *name == "play" {
+                "Audio"
+            } else if *name == "set" || *name == "export" || *name == "unset"
+                || *name == "source"
+            {
+                "Shell"
+            } else if *name == "ipcstat" || *name == "captrace" {
+                "Debug"
+            } else if *name == "config" || *name == "less" || *name == "more" || *name == "exit" {
+                "Terminal"
             } else {
                 "Other"
             };
@@ -58,7 +73,7 @@ pub fn cmd_help(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Comman
             if new_category != category {
                 category = new_category;
                 ctx.println("");
-                ctx.println_colored(category, Theme::PROMPT_USER);
+                ctx.println_colored(category, theme().prompt_user);
             }
 
             // Format command with description
@@ -100,7 +115,7 @@ pub fn cmd_help(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Comman
 /// version command - display system version
 pub fn cmd_version(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
     ctx.println("");
-    ctx.println_colored(OS_NAME, Theme::TEXT_INFO);
+    ctx.println_colored(OS_NAME, theme().text_info);
 
     let mut version_line = [0u8; 64];
     let mut pos = 0;
@@ -147,26 +162,22 @@ pub fn cmd_version(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Co
     CommandResult::Ok
 }
 
-/// uptime command - show system uptime
+/// uptime command - show system uptime and how busy the CPU has been
 pub fn cmd_uptime(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
-    let mut buffer = [0u8; 64];
-    let len = ctx.ipc.format_uptime(&mut buffer);
-
-    let mut line = [0u8; 80];
-    let mut pos = 0;
-
-    for byte in "System uptime: ".bytes() {
-        line[pos] = byte;
-        pos += 1;
-    }
-    for i in 0..len {
-        line[pos] = buffer[i];
-        pos += 1;
-    }
-
-    let uptime_str = unsafe { core::str::from_utf8_unchecked(&line[..pos]) };
     ctx.println("");
-    ctx.println_colored(uptime_str, Theme::TEXT_INFO);
+    ctx.print("System uptime: ");
+    ctx.println_colored(Text::duration(get_ticks()).as_str(), theme().text_info);
+
+    if let Ok(sched) = system::sched_stats() {
+        let busy = sched.ticks.saturating_sub(sched.idle_ticks);
+        ctx.print("CPU busy ");
+        ctx.print(Text::percent(busy, sched.ticks).as_str());
+        ctx.print(" of the time, ");
+        ctx.print(Text::number(sched.threads).as_str());
+        ctx.print(" threads, ");
+        ctx.print(Text::number(sched.context_switches).as_str());
+        ctx.println(" context switches");
+    }
     ctx.println("");
 
     CommandResult::Ok
@@ -218,19 +229,25 @@ pub fn cmd_date(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Comma
 
     let time_display = unsafe { core::str::from_utf8_unchecked(&time_str[..pos]) };
     ctx.println("");
-    ctx.println_colored(time_display, Theme::TEXT_INFO);
+    ctx.println_colored(time_display, theme().text_info);
     ctx.println("");
 
     CommandResult::Ok
 }
 
 /// echo command - display text
+///
+/// With -e, backslash escapes are expanded and the text goes through the
+/// ANSI interpreter, e.g. `echo -e "\e[31mred\e[0m"`.
 pub fn cmd_echo(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    let escapes = cmd.arg(0) == Some("-e");
+    let first = if escapes { 1 } else { 0 };
+
     let mut output = [0u8; 256];
     let mut pos = 0;
 
-    for i in 0..cmd.arg_count {
-        if i > 0 && pos < output.len() {
+    for i in first..cmd.arg_count {
+        if i > first && pos < output.len() {
             output[pos] = b' ';
             pos += 1;
         }
@@ -242,22 +259,59 @@ pub fn cmd_echo(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Comman
         }
     }
 
+    if escapes {
+        let len = expand_escapes(&mut output[..pos]);
+        ctx.print_ansi(&output[..len]);
+        ctx.print_ansi(b"\n");
+        return CommandResult::Ok;
+    }
+
     let text = unsafe { core::str::from_utf8_unchecked(&output[..pos]) };
     ctx.println(text);
 
     CommandResult::Ok
 }
 
+/// Expand \e, \033, \n, \t and \\ in place; returns the new length
+fn expand_escapes(text: &mut [u8]) -> usize {
+    let mut read = 0;
+    let mut write = 0;
+
+    while read < text.len() {
+        let byte = text[read];
+        read += 1;
+        if byte != b'\\' || read >= text.len() {
+            text[write] = byte;
+            write += 1;
+            continue;
+        }
+
+        let (expanded, used) = match text[read] {
+            b'e' => (0x1B, 1),
+            b'n' => (b'\n', 1),
+            b't' => (b'\t', 1),
+            b'\\' => (b'\\', 1),
+            b'0' if text[read..].starts_with(b"033") => (0x1B, 3),
+            _ => (b'\\', 0),
+        };
+        text[write] = expanded;
+        write += 1;
+        read += used;
+    }
+
+    write
+}
+
 /// sysinfo command - display system information summary
 pub fn cmd_sysinfo(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
     ctx.println("");
-    ctx.println_colored("System Information", Theme::TEXT_INFO);
+    ctx.println_colored("System Information", theme().text_info);
     ctx.println("==================");
     ctx.println("");
 
     // OS info
     ctx.print("OS:           ");
-    ctx.println_colored(OS_NAME, Theme::PROMPT_USER);
+    ctx.println_colored(OS_NAME, theme().prompt_user);
 
     ctx.print("Version:      ");
     ctx.println(OS_VERSION);
@@ -270,30 +324,29 @@ pub fn cmd_sysinfo(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Co
 
     ctx.println("");
 
-    // Uptime
-    let mut buffer = [0u8; 64];
-    let len = ctx.ipc.format_uptime(&mut buffer);
-    let uptime = unsafe { core::str::from_utf8_unchecked(&buffer[..len]) };
     ctx.print("Uptime:       ");
-    ctx.println(uptime);
-
-    // Memory
-    let (total, used, free) = ctx.ipc.query_memory();
-    let mut mem_line = [0u8; 64];
-    let mut pos = 0;
-    pos += format_number(used / 1024, &mut mem_line[pos..]);
-    for byte in " MB / ".bytes() {
-        mem_line[pos] = byte;
-        pos += 1;
+    ctx.println(Text::duration(get_ticks()).as_str());
+
+    if let Ok(sched) = system::sched_stats() {
+        let busy = sched.ticks.saturating_sub(sched.idle_ticks);
+        ctx.print("Threads:      ");
+        ctx.println(Text::number(sched.threads).as_str());
+        ctx.print("CPU busy:     ");
+        ctx.println(Text::percent(busy, sched.ticks).as_str());
     }
-    pos += format_number(total / 1024, &mut mem_line[pos..]);
-    for byte in " MB".bytes() {
-        mem_line[pos] = byte;
-        pos += 1;
+
+    if let Ok(memory) = system::mem_stats() {
+        let used = Text::size(memory.used_bytes());
+        let total = Text::size(memory.total_bytes);
+        let percent = Text::percent(memory.used_bytes(), memory.total_bytes);
+        ctx.print("Memory:       ");
+        ctx.print(used.as_str());
+        ctx.print(" / ");
+        ctx.print(total.as_str());
+        ctx.print(" (");
+        ctx.print(percent.as_str());
+        ctx.println(")");
     }
-    let mem_str = unsafe { core::str::from_utf8_unchecked(&mem_line[..pos]) };
-    ctx.print("Memory:       ");
-    ctx.println(mem_str);
 
     ctx.println("");
 
@@ -301,14 +354,47 @@ pub fn cmd_sysinfo(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Co
 }
 
 /// log command - display system log
-pub fn cmd_log(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+pub fn cmd_log(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    const USAGE: &str = "Usage: log [-l level] [-n count]";
+
+    let min_level = match cmd.get_option("-l", "--level").map(LogLevel::parse) {
+        Some(Some(level)) => level,
+        Some(None) => {
+            ctx.error("Levels: debug, info, warn, error, panic (or 0-4)");
+            return CommandResult::Error;
+        }
+        None => LogLevel::Debug,
+    };
+    let limit = match cmd.get_option("-n", "--count").map(str::parse::<usize>) {
+        Some(Ok(count)) => Some(count),
+        Some(Err(_)) => {
+            ctx.error(USAGE);
+            return CommandResult::Error;
+        }
+        None => None,
+    };
+
+    // The newest lines are only known once the log has been read through
+    let skip = match limit {
+        Some(limit) => {
+            let mut total = 0;
+            ctx.ipc.read_log(min_level, |_| total += 1);
+            total.saturating_sub(limit)
+        }
+        None => 0,
+    };
+
     ctx.println("");
-    ctx.println_colored("System Log", Theme::TEXT_INFO);
+    ctx.println_colored("System Log", theme().text_info);
     ctx.println("----------");
     ctx.println("");
 
-    ctx.ipc.read_log(|line| {
-        ctx.println(line);
+    let mut index = 0;
+    ctx.ipc.read_log(min_level, |line| {
+        if index >= skip {
+            ctx.println_colored(line, log_line_color(line));
+        }
+        index += 1;
     });
 
     ctx.println("");
@@ -316,10 +402,24 @@ pub fn cmd_log(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Comman
     CommandResult::Ok
 }
 
+/// Color for a kernel log line, by the level tag it carries
+fn log_line_color(line: &str) -> Color {
+    let theme = theme();
+    if line.contains(LogLevel::Panic.tag()) || line.contains(LogLevel::Error.tag()) {
+        theme.text_error
+    } else if line.contains(LogLevel::Warn.tag()) {
+        theme.text_warning
+    } else if line.contains(LogLevel::Debug.tag()) {
+        theme.text_dim
+    } else {
+        theme.text_normal
+    }
+}
+
 /// ports command - list IPC ports (diagnostic)
 pub fn cmd_ports(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
     ctx.println("");
-    ctx.println_colored("IPC Ports", Theme::TEXT_INFO);
+    ctx.println_colored("IPC Ports", theme().text_info);
     ctx.println("---------");
     ctx.println("");
     ctx.println("Port  Service");
@@ -338,7 +438,7 @@ pub fn cmd_ports(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> Comm
 /// caps command - list capabilities (diagnostic)
 pub fn cmd_caps(_cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
     ctx.println("");
-    ctx.println_colored("Process Capabilities", Theme::TEXT_INFO);
+    ctx.println_colored("Process Capabilities", theme().text_info);
     ctx.println("--------------------");
     ctx.println("");
     ctx.println("CAP_GRAPHICS     - Framebuffer access");
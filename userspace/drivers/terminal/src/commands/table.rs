@@ -0,0 +1,238 @@
+// Table Output
+//
+// Column-aligned output for the diagnostic commands. A `Table` names its
+// columns and their widths; each row is given as text cells and padded to
+// fit. `Text` formats numbers, sizes, percentages, durations and
+// timestamps into a cell without allocating.
+
+use atom_syscall::graphics::Color;
+
+use super::CommandContext;
+use crate::buffer::MAX_LINE_LENGTH;
+use crate::config::theme;
+use crate::ipc_client::format_size;
+
+/// Timer ticks per second (the kernel's timer runs at 100 Hz)
+pub const TICKS_PER_SECOND: u64 = 100;
+
+/// Space between columns
+const GAP: usize = 2;
+
+/// Longest formatted cell
+const MAX_TEXT: usize = 24;
+
+/// Most columns a table has
+const MAX_COLUMNS: usize = 10;
+
+/// Which side of its column a cell keeps to
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+pub struct Column {
+    pub title: &'static str,
+    pub width: usize,
+    pub align: Align,
+}
+
+impl Column {
+    pub const fn left(title: &'static str, width: usize) -> Self {
+        Self { title, width, align: Align::Left }
+    }
+
+    pub const fn right(title: &'static str, width: usize) -> Self {
+        Self { title, width, align: Align::Right }
+    }
+}
+
+pub struct Table<'a> {
+    columns: &'a [Column],
+}
+
+impl<'a> Table<'a> {
+    pub const fn new(columns: &'a [Column]) -> Self {
+        Self { columns }
+    }
+
+    /// Print the column titles, underlined
+    pub fn header(&self, ctx: &mut CommandContext<'_>) {
+        let mut titles = [""; MAX_COLUMNS];
+        let mut rules = [""; MAX_COLUMNS];
+        const RULE: &str = "------------------------";
+        for (i, column) in self.columns.iter().take(titles.len()).enumerate() {
+            titles[i] = column.title;
+            rules[i] = &RULE[..column.title.len().min(RULE.len())];
+        }
+        let count = self.columns.len().min(titles.len());
+        self.print(ctx, &titles[..count], theme().text_info);
+        self.print(ctx, &rules[..count], theme().text_dim);
+    }
+
+    /// Print one row; cells longer than their column are cut
+    pub fn row(&self, ctx: &mut CommandContext<'_>, cells: &[&str]) {
+        self.print(ctx, cells, theme().text_normal);
+    }
+
+    fn print(&self, ctx: &mut CommandContext<'_>, cells: &[&str], color: Color) {
+        let mut line = [b' '; MAX_LINE_LENGTH];
+        let mut pos = 0;
+        for (column, cell) in self.columns.iter().zip(cells) {
+            let len = cell.len().min(column.width);
+            let start = match column.align {
+                Align::Left => pos,
+                Align::Right => pos + column.width - len,
+            };
+            let end = start + len;
+            if end > line.len() {
+                break;
+            }
+            line[start..end].copy_from_slice(&cell.as_bytes()[..len]);
+            pos += column.width + GAP;
+        }
+
+        // Drop the padding after the last cell
+        let mut len = pos.min(line.len());
+        while len > 0 && line[len - 1] == b' ' {
+            len -= 1;
+        }
+        // Safety: cells are ASCII names and numbers; a cut multi-byte
+        // character would only garble that cell
+        let text = unsafe { core::str::from_utf8_unchecked(&line[..len]) };
+        ctx.println_colored(text, color);
+    }
+}
+
+/// A formatted cell
+pub struct Text {
+    bytes: [u8; MAX_TEXT],
+    len: usize,
+}
+
+impl Text {
+    fn new() -> Self {
+        Self { bytes: [0u8; MAX_TEXT], len: 0 }
+    }
+
+    fn push(&mut self, text: &[u8]) {
+        for &byte in text {
+            if self.len < MAX_TEXT {
+                self.bytes[self.len] = byte;
+                self.len += 1;
+            }
+        }
+    }
+
+    fn push_number(&mut self, mut n: u64, min_digits: usize) {
+        let mut digits = [0u8; 20];
+        let mut count = 0;
+        while n > 0 || count < min_digits.max(1) {
+            digits[count] = b'0' + (n % 10) as u8;
+            n /= 10;
+            count += 1;
+        }
+        for i in (0..count).rev() {
+            self.push(&digits[i..i + 1]);
+        }
+    }
+
+    pub fn number(n: u64) -> Self {
+        let mut text = Self::new();
+        text.push_number(n, 1);
+        text
+    }
+
+    /// Bytes as B, KB, MB or GB
+    pub fn size(bytes: u64) -> Self {
+        let mut text = Self::new();
+        text.len = format_size(bytes, &mut text.bytes);
+        text
+    }
+
+    /// `part` as a whole percentage of `whole`
+    pub fn percent(part: u64, whole: u64) -> Self {
+        let mut text = Self::new();
+        let percent = (part.min(whole) * 100).checked_div(whole).unwrap_or(0);
+        text.push_number(percent, 1);
+        text.push(b"%");
+        text
+    }
+
+    /// A number after a label, as in "port 5"
+    pub fn labeled(label: &str, n: u64) -> Self {
+        let mut text = Self::new();
+        text.push(label.as_bytes());
+        text.push(b" ");
+        text.push_number(n, 1);
+        text
+    }
+
+    /// Milliseconds, as in "12ms"
+    pub fn millis(ms: u64) -> Self {
+        let mut text = Self::new();
+        text.push_number(ms, 1);
+        text.push(b"ms");
+        text
+    }
+
+    /// Microseconds, as in "12us"
+    pub fn micros(us: u64) -> Self {
+        let mut text = Self::new();
+        text.push_number(us, 1);
+        text.push(b"us");
+        text
+    }
+
+    /// Nanoseconds as seconds with six decimals, as in "12.345678"
+    pub fn timestamp(ns: u64) -> Self {
+        let mut text = Self::new();
+        text.push_number(ns / 1_000_000_000, 1);
+        text.push(b".");
+        text.push_number(ns % 1_000_000_000 / 1000, 6);
+        text
+    }
+
+    /// A number in hexadecimal, as in "0x1F000"
+    pub fn hex(mut n: u64) -> Self {
+        let mut digits = [0u8; 16];
+        let mut count = 0;
+        while n > 0 || count == 0 {
+            digits[count] = b"0123456789ABCDEF"[(n % 16) as usize];
+            n /= 16;
+            count += 1;
+        }
+        let mut text = Self::new();
+        text.push(b"0x");
+        for i in (0..count).rev() {
+            text.push(&digits[i..i + 1]);
+        }
+        text
+    }
+
+    /// Milliseconds as seconds with two decimals, as in "12.34"
+    pub fn seconds(ms: u64) -> Self {
+        let mut text = Self::new();
+        text.push_number(ms / 1000, 1);
+        text.push(b".");
+        text.push_number(ms % 1000 / 10, 2);
+        text
+    }
+
+    /// Timer ticks as h:mm:ss
+    pub fn duration(ticks: u64) -> Self {
+        let seconds = ticks / TICKS_PER_SECOND;
+        let mut text = Self::new();
+        text.push_number(seconds / 3600, 1);
+        text.push(b":");
+        text.push_number(seconds % 3600 / 60, 2);
+        text.push(b":");
+        text.push_number(seconds % 60, 2);
+        text
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Safety: only ASCII is pushed
+        unsafe { core::str::from_utf8_unchecked(&self.bytes[..self.len]) }
+    }
+}
@@ -0,0 +1,108 @@
+// Terminal Commands
+//
+// `config` shows and changes the terminal's settings: the color scheme
+// and single colors, the font scale, the cursor style and the padding.
+// Changes show as soon as the command line is done. `config save` writes
+// the settings to the configuration file read at startup, and
+// `config load` reads it again.
+//
+// `less` and `more` print a file or their piped input like `cat`, and have
+// the terminal page through it once the command line is done.
+
+use core::ptr::addr_of_mut;
+
+use super::{filesystem, CommandContext, CommandResult};
+use crate::config::{
+    config, config_mut, theme, ConfigError, TerminalConfig, CONFIG_FILE, MAX_CONFIG_SIZE,
+};
+use crate::ipc_client::IpcClient;
+use crate::pager::{self, Style};
+use crate::parser::ParsedCommand;
+
+/// Configuration file being read or written; kept off the stack
+static mut CONFIG_TEXT: [u8; MAX_CONFIG_SIZE] = [0u8; MAX_CONFIG_SIZE];
+
+/// Apply the settings in the configuration file; returns how many of its
+/// lines were refused
+pub fn load_config(ipc: &IpcClient) -> Result<usize, &'static str> {
+    // Safety: the terminal is single-threaded
+    let text = unsafe { &mut *addr_of_mut!(CONFIG_TEXT) };
+    let len = ipc.read_file(CONFIG_FILE, text).ok_or("Cannot read the configuration file")?;
+    let text = core::str::from_utf8(&text[..len]).map_err(|_| "Configuration file is not text")?;
+    Ok(config_mut().load(text))
+}
+
+/// config command - show or change the terminal's settings
+pub fn cmd_config(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    let Some(key) = cmd.arg(0) else {
+        for key in TerminalConfig::keys() {
+            print_setting(key, ctx);
+        }
+        return CommandResult::Ok;
+    };
+
+    match (key, cmd.arg(1)) {
+        ("save", None) => {
+            // Safety: the terminal is single-threaded
+            let text = unsafe { &mut *addr_of_mut!(CONFIG_TEXT) };
+            let len = config().save(text);
+            if !ctx.ipc.write_file(CONFIG_FILE, &text[..len], false) {
+                ctx.error("Cannot write the configuration file");
+                return CommandResult::Error;
+            }
+        }
+        ("load", None) => match load_config(ctx.ipc) {
+            Ok(0) => {}
+            Ok(_) => ctx.warning("Some lines of the configuration file were ignored"),
+            Err(message) => {
+                ctx.error(message);
+                return CommandResult::Error;
+            }
+        },
+        ("reset", None) => *config_mut() = TerminalConfig::DEFAULT,
+        (key, None) => {
+            if config().get(key).is_none() {
+                ctx.error(ConfigError::UnknownKey.message());
+                return CommandResult::Error;
+            }
+            print_setting(key, ctx);
+        }
+        (key, Some(value)) => {
+            if let Err(err) = config_mut().set(key, value) {
+                ctx.error(err.message());
+                return CommandResult::Error;
+            }
+        }
+    }
+
+    CommandResult::Ok
+}
+
+/// less and more commands - page through a file or piped input
+pub fn cmd_page(
+    cmd: &ParsedCommand<'_>,
+    ctx: &mut CommandContext<'_>,
+    style: Style,
+) -> CommandResult {
+    if cmd.arg(0).is_none() && ctx.input.is_none() {
+        ctx.error(match style {
+            Style::Less => "Usage: less <file>",
+            Style::More => "Usage: more <file>",
+        });
+        return CommandResult::Error;
+    }
+
+    let result = filesystem::cmd_cat(cmd, ctx);
+    if result == CommandResult::Ok {
+        pager::request(style);
+    }
+    result
+}
+
+fn print_setting(key: &str, ctx: &mut CommandContext<'_>) {
+    if let Some(value) = config().get(key) {
+        ctx.output.write(key.as_bytes(), theme().text_info);
+        ctx.print(" = ");
+        ctx.println(value.as_str());
+    }
+}
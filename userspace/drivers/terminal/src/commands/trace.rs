@@ -0,0 +1,717 @@
+// Trace Commands
+//
+// Commands that look inside the kernel through its observability
+// syscalls:
+// - `ipcstat` shows each port's traffic and latency counters
+// - `captrace` lists the IPC trace and the capability audit log merged in
+//   time order; with -f it keeps printing events as they are logged until
+//   Ctrl+C
+// - `trace` lists the kernel-wide trace (scheduler, memory, IPC and
+//   syscall events), sums it up as wakeup latency per thread or as IPC
+//   flows, and turns its subsystems on and off
+//
+// All these logs are rings in the kernel, so events that fall out of them
+// between two reads are never seen.
+
+use core::ptr::addr_of_mut;
+
+use atom_syscall::ipc::{self, PortId, TraceEvent};
+use atom_syscall::system::{self, CapAuditEntry};
+use atom_syscall::thread::sleep_ms;
+use atom_syscall::trace::{self as ktrace, Record, Subsystem};
+
+use super::table::{Column, Table, Text, TICKS_PER_SECOND};
+use super::{CommandContext, CommandResult, Foreground};
+use crate::buffer::DisplayBuffer;
+use crate::config::theme;
+use crate::ipc_client::IpcClient;
+use crate::parser::ParsedCommand;
+use crate::stream::{OutputStream, Pipe};
+
+/// Events read from each log; the kernel keeps 1000 of each
+const MAX_EVENTS: usize = 1000;
+
+/// Most ports `ipcstat` lists
+const MAX_PORTS: usize = 32;
+
+/// Events `captrace` prints unless given -n
+const DEFAULT_EVENTS: usize = 20;
+
+/// How long `captrace -f` waits between reads of the logs
+const FOLLOW_INTERVAL_MS: u64 = 100;
+
+/// Records read from the kernel trace; the kernel keeps 4096
+const MAX_RECORDS: usize = 4096;
+
+/// Threads `trace latency` and flows `trace ipc` keep apart
+const MAX_THREADS: usize = 32;
+const MAX_FLOWS: usize = 32;
+
+/// Sends `trace ipc` remembers while waiting for their receive
+const MAX_PENDING_SENDS: usize = 128;
+
+/// Events read from the kernel; too large for the stack
+static mut IPC_EVENTS: [TraceEvent; MAX_EVENTS] = [TraceEvent::EMPTY; MAX_EVENTS];
+static mut CAP_EVENTS: [CapAuditEntry; MAX_EVENTS] = [CapAuditEntry::EMPTY; MAX_EVENTS];
+static mut RECORDS: [Record; MAX_RECORDS] = [Record::EMPTY; MAX_RECORDS];
+
+const TRACE_COLUMNS: [Column; 6] = [
+    Column::right("TIME", 9),
+    Column::left("EVENT", 8),
+    Column::right("FROM", 6),
+    Column::right("TO", 6),
+    Column::left("OBJECT", 10),
+    Column::left("DETAIL", 16),
+];
+
+/// Read the newest events of both logs, oldest first
+fn read_logs() -> (&'static [TraceEvent], &'static [CapAuditEntry]) {
+    // Safety: the terminal is single-threaded and the slices of the last
+    // read are not used once the logs are read again
+    let (ipc_events, cap_events) =
+        unsafe { (&mut *addr_of_mut!(IPC_EVENTS), &mut *addr_of_mut!(CAP_EVENTS)) };
+    let ipc_count = ipc::read_trace(ipc_events).unwrap_or(0);
+    let cap_count = system::cap_audit(cap_events).unwrap_or(0).min(MAX_EVENTS);
+    (&ipc_events[..ipc_count], &cap_events[..cap_count])
+}
+
+/// ipcstat command - traffic and latency of IPC ports
+pub fn cmd_ipcstat(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    let mut ports: [PortId; MAX_PORTS] = [0; MAX_PORTS];
+    let mut count = 0;
+    let named = cmd.arg_count > 0;
+
+    if named {
+        for &arg in &cmd.args[..cmd.arg_count] {
+            let Ok(port) = arg.parse() else {
+                ctx.error("Usage: ipcstat [port...]");
+                return CommandResult::Error;
+            };
+            if count < MAX_PORTS {
+                ports[count] = port;
+                count += 1;
+            }
+        }
+    } else {
+        count = traced_ports(&mut ports);
+        if count == 0 {
+            ctx.println("No IPC traffic traced yet; name ports with: ipcstat <port...>");
+            return CommandResult::Ok;
+        }
+    }
+
+    const COLUMNS: [Column; 9] = [
+        Column::right("PORT", 5),
+        Column::right("SENT", 7),
+        Column::right("RECV", 7),
+        Column::right("BYTES", 8),
+        Column::right("MSG/S", 6),
+        Column::right("MIN", 6),
+        Column::right("AVG", 6),
+        Column::right("MAX", 6),
+        Column::right("QUEUED", 6),
+    ];
+    let table = Table::new(&COLUMNS);
+    table.header(ctx);
+
+    // Ports only seen in the trace may have been closed since; those are
+    // left out, but a port asked for by number is an error
+    let mut missing = false;
+    for &port in &ports[..count] {
+        let Ok(stats) = ipc::port_stats(port) else {
+            missing |= named;
+            continue;
+        };
+        let cells = [
+            Text::number(port),
+            Text::number(stats.messages_sent),
+            Text::number(stats.messages_received),
+            Text::size(stats.bytes_sent),
+            Text::number(stats.messages_per_second),
+            Text::millis(stats.min_latency_ms),
+            Text::millis(stats.avg_latency_ms),
+            Text::millis(stats.max_latency_ms),
+            Text::number(stats.queued),
+        ];
+        table.row(ctx, &cells.each_ref().map(Text::as_str));
+    }
+
+    if missing {
+        ctx.error("Some ports do not exist");
+        return CommandResult::Error;
+    }
+    CommandResult::Ok
+}
+
+/// Ports that appear in the IPC trace, in ascending order
+fn traced_ports(ports: &mut [PortId; MAX_PORTS]) -> usize {
+    let (ipc_events, _) = read_logs();
+    let mut count = 0;
+    for event in ipc_events {
+        let Err(index) = ports[..count].binary_search(&event.port) else {
+            continue;
+        };
+        if count == MAX_PORTS {
+            break;
+        }
+        ports.copy_within(index..count, index + 1);
+        ports[index] = event.port;
+        count += 1;
+    }
+    count
+}
+
+/// captrace command - IPC messages and capability events, newest last
+pub fn cmd_captrace(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    let follow = cmd.has_flag("-f", "--follow");
+    let limit = match cmd.get_option("-n", "--count") {
+        Some(count) => match count.parse() {
+            Ok(count) => count,
+            Err(_) => {
+                ctx.error("Usage: captrace [-f] [-n count]");
+                return CommandResult::Error;
+            }
+        },
+        None => DEFAULT_EVENTS,
+    };
+
+    let (ipc_events, cap_events) = read_logs();
+    let total = ipc_events.len() + cap_events.len();
+    if total == 0 && !follow {
+        ctx.println("No IPC or capability events logged yet");
+        return CommandResult::Ok;
+    }
+
+    let table = Table::new(&TRACE_COLUMNS);
+    table.header(ctx);
+    for event in merged(ipc_events, cap_events).skip(total.saturating_sub(limit)) {
+        event.print(&table, ctx);
+    }
+
+    if follow {
+        ctx.follow = Some(Follow::new(ipc_events, cap_events));
+    }
+    CommandResult::Ok
+}
+
+/// An IPC message or a capability event
+#[derive(Clone, Copy)]
+enum Event<'a> {
+    Ipc(&'a TraceEvent),
+    Cap(&'a CapAuditEntry),
+}
+
+impl Event<'_> {
+    /// Milliseconds since boot; the audit log counts timer ticks
+    fn time_ms(&self) -> u64 {
+        match self {
+            Event::Ipc(event) => event.timestamp_ms,
+            Event::Cap(entry) => entry.timestamp * 1000 / TICKS_PER_SECOND,
+        }
+    }
+
+    fn print(&self, table: &Table<'_>, ctx: &mut CommandContext<'_>) {
+        let time = Text::seconds(self.time_ms());
+        let (event, from, to, object, detail) = match self {
+            Event::Ipc(event) => (
+                if event.is_send() { "send" } else { "recv" },
+                event.sender,
+                event.receiver,
+                Text::labeled("port", event.port),
+                Some(Text::size(event.size)),
+            ),
+            Event::Cap(entry) => (
+                entry.event_name(),
+                entry.thread,
+                entry.target,
+                Text::labeled("cap", entry.cap_handle),
+                (entry.parent != 0).then(|| Text::labeled("from cap", entry.parent)),
+            ),
+        };
+        let from = Text::number(from);
+        let to = (to != 0).then(|| Text::number(to));
+
+        table.row(
+            ctx,
+            &[
+                time.as_str(),
+                event,
+                from.as_str(),
+                to.as_ref().map_or("-", Text::as_str),
+                object.as_str(),
+                detail.as_ref().map_or("", Text::as_str),
+            ],
+        );
+    }
+}
+
+/// Both logs as one, in time order
+fn merged<'a>(
+    ipc_events: &'a [TraceEvent],
+    cap_events: &'a [CapAuditEntry],
+) -> impl Iterator<Item = Event<'a>> {
+    let (mut next_ipc, mut next_cap) = (0, 0);
+    core::iter::from_fn(move || {
+        let ipc = ipc_events.get(next_ipc).map(Event::Ipc);
+        let cap = cap_events.get(next_cap).map(Event::Cap);
+        match (ipc, cap) {
+            (Some(ipc), Some(cap)) if cap.time_ms() < ipc.time_ms() => {
+                next_cap += 1;
+                Some(cap)
+            }
+            (Some(ipc), _) => {
+                next_ipc += 1;
+                Some(ipc)
+            }
+            (None, cap) => {
+                next_cap += 1;
+                cap
+            }
+        }
+    })
+}
+
+/// How far into one log `captrace -f` has printed. Several events can
+/// share a timestamp, so it keeps the last timestamp printed and how many
+/// events with it were printed.
+#[derive(Clone, Copy, Default)]
+struct Cursor {
+    time: u64,
+    seen: usize,
+}
+
+impl Cursor {
+    /// Index of the first event not printed yet, given each event's time
+    fn first_new(&self, times: impl Iterator<Item = u64>) -> usize {
+        let mut index = 0;
+        let mut same = 0;
+        for time in times {
+            if time > self.time || (time == self.time && same == self.seen) {
+                break;
+            }
+            if time == self.time {
+                same += 1;
+            }
+            index += 1;
+        }
+        index
+    }
+
+    /// Move past every event given
+    fn advance(&mut self, times: impl DoubleEndedIterator<Item = u64>) {
+        let mut times = times.rev();
+        if let Some(last) = times.next() {
+            self.time = last;
+            self.seen = 1 + times.take_while(|&time| time == last).count();
+        }
+    }
+}
+
+/// `captrace -f` following the logs; the line waits on it the way it
+/// waits on a program
+pub struct Follow {
+    ipc: Cursor,
+    cap: Cursor,
+}
+
+impl Follow {
+    /// Start after the events already printed
+    fn new(ipc_events: &[TraceEvent], cap_events: &[CapAuditEntry]) -> Self {
+        let mut follow = Self {
+            ipc: Cursor::default(),
+            cap: Cursor::default(),
+        };
+        follow.ipc.advance(ipc_events.iter().map(|event| event.timestamp_ms));
+        follow.cap.advance(cap_events.iter().map(|entry| entry.timestamp));
+        follow
+    }
+
+    /// Print the events logged since the last call; true if there were any
+    fn pump(&mut self, ctx: &mut CommandContext<'_>) -> bool {
+        let (ipc_events, cap_events) = read_logs();
+        let ipc_times = || ipc_events.iter().map(|event| event.timestamp_ms);
+        let cap_times = || cap_events.iter().map(|entry| entry.timestamp);
+        let ipc_start = self.ipc.first_new(ipc_times());
+        let cap_start = self.cap.first_new(cap_times());
+        self.ipc.advance(ipc_times());
+        self.cap.advance(cap_times());
+
+        let table = Table::new(&TRACE_COLUMNS);
+        let mut any = false;
+        for event in merged(&ipc_events[ipc_start..], &cap_events[cap_start..]) {
+            event.print(&table, ctx);
+            any = true;
+        }
+        any
+    }
+}
+
+/// Print events as they are logged until Ctrl+C, to `pipe` if the line
+/// pipes the output on and to the display otherwise
+pub fn follow(
+    mut follow: Follow,
+    mut pipe: Option<&mut Pipe>,
+    display: &mut DisplayBuffer,
+    ipc: &IpcClient,
+    foreground: &mut dyn Foreground,
+) -> CommandResult {
+    loop {
+        let output: &mut dyn OutputStream = match pipe.as_deref_mut() {
+            Some(pipe) => pipe,
+            None => &mut *display,
+        };
+        let mut ctx = CommandContext {
+            output,
+            input: None,
+            ipc,
+            job: None,
+            follow: None,
+        };
+        if follow.pump(&mut ctx) && pipe.is_none() {
+            foreground.show(display);
+        }
+
+        if foreground.interrupted() {
+            display.writeln("^C", theme().text_dim);
+            return CommandResult::Ok;
+        }
+        sleep_ms(FOLLOW_INTERVAL_MS);
+    }
+}
+
+/// Read every record the kernel trace holds, oldest first
+fn read_records() -> &'static [Record] {
+    // Safety: the terminal is single-threaded and the slice of the last
+    // read is not used once the trace is read again
+    let records = unsafe { &mut *addr_of_mut!(RECORDS) };
+    let mut cursor = 0;
+    let mut count = 0;
+    while count < MAX_RECORDS {
+        match ktrace::read(&mut records[count..], &mut cursor) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => count += read,
+        }
+    }
+    &records[..count]
+}
+
+const TRACE_USAGE: &str =
+    "Usage: trace [-n count] [-s subsystem] | latency | ipc | on|off [subsystem...]";
+
+/// trace command - the kernel-wide trace
+pub fn cmd_trace(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    match cmd.arg(0) {
+        Some("on") => set_traced(cmd, ctx, true),
+        Some("off") => set_traced(cmd, ctx, false),
+        Some("latency") => print_latency(ctx),
+        Some("ipc") => print_flows(ctx),
+        _ => print_records(cmd, ctx),
+    }
+}
+
+/// `trace on|off [subsystem...]`, every subsystem if none is named
+fn set_traced(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>, on: bool) -> CommandResult {
+    let mut mask = 0;
+    for name in &cmd.args[1..cmd.arg_count] {
+        let Some(subsystem) = Subsystem::from_name(name) else {
+            ctx.error("Subsystems: sched, mm, ipc, syscall");
+            return CommandResult::Error;
+        };
+        mask |= subsystem.bit();
+    }
+    if mask == 0 {
+        mask = Subsystem::ALL.iter().fold(0, |mask, subsystem| mask | subsystem.bit());
+    }
+
+    let result = if on { ktrace::control(mask, 0) } else { ktrace::control(0, mask) };
+    let Ok(traced) = result else {
+        ctx.error("Not allowed to change what the kernel traces");
+        return CommandResult::Error;
+    };
+    ctx.print("Tracing:");
+    for subsystem in Subsystem::ALL.iter().filter(|subsystem| traced & subsystem.bit() != 0) {
+        ctx.print(" ");
+        ctx.print(subsystem.name());
+    }
+    ctx.println(if traced == 0 { " nothing" } else { "" });
+    CommandResult::Ok
+}
+
+/// `trace [-n count] [-s subsystem]`: the newest records
+fn print_records(cmd: &ParsedCommand<'_>, ctx: &mut CommandContext<'_>) -> CommandResult {
+    let limit = match cmd.get_option("-n", "--count").map(str::parse) {
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            ctx.error(TRACE_USAGE);
+            return CommandResult::Error;
+        }
+        None => DEFAULT_EVENTS,
+    };
+    let only = match cmd.get_option("-s", "--subsystem") {
+        Some(name) => match Subsystem::from_name(name) {
+            Some(subsystem) => Some(subsystem),
+            None => {
+                ctx.error("Subsystems: sched, mm, ipc, syscall");
+                return CommandResult::Error;
+            }
+        },
+        None if cmd.arg_count > 0 && !cmd.has_flag("-n", "--count") => {
+            ctx.error(TRACE_USAGE);
+            return CommandResult::Error;
+        }
+        None => None,
+    };
+
+    let selected =
+        |record: &&Record| only.is_none_or(|subsystem| record.subsystem() == Some(subsystem));
+    let records = read_records();
+    let total = records.iter().filter(selected).count();
+    if total == 0 {
+        ctx.println("No trace records; turn subsystems on with: trace on [subsystem...]");
+        return CommandResult::Ok;
+    }
+
+    const COLUMNS: [Column; 6] = [
+        Column::right("TIME", 12),
+        Column::right("THREAD", 6),
+        Column::left("SUBSYS", 7),
+        Column::left("EVENT", 6),
+        Column::left("OBJECT", 18),
+        Column::left("DETAIL", 18),
+    ];
+    let table = Table::new(&COLUMNS);
+    table.header(ctx);
+    for record in records.iter().filter(selected).skip(total.saturating_sub(limit)) {
+        let (object, detail) = describe(record);
+        let time = Text::timestamp(record.timestamp_ns);
+        let thread = Text::number(record.thread);
+        table.row(
+            ctx,
+            &[
+                time.as_str(),
+                thread.as_str(),
+                record.subsystem().map_or("?", Subsystem::name),
+                record.event_name(),
+                object.as_str(),
+                detail.as_ref().map_or("", Text::as_str),
+            ],
+        );
+    }
+    CommandResult::Ok
+}
+
+/// What a record is about and what else it says, as table cells
+fn describe(record: &Record) -> (Text, Option<Text>) {
+    let [a, b, c, d] = record.args;
+    match (record.subsystem(), record.event) {
+        (Some(Subsystem::Sched), ktrace::sched::SWITCH) => {
+            (Text::labeled("thread", b), (a != 0).then(|| Text::labeled("from", a)))
+        }
+        (Some(Subsystem::Sched), _) => (Text::labeled("thread", a), None),
+        (Some(Subsystem::Mm), ktrace::mm::PAGE_FAULT) => {
+            (Text::hex(a), Some(Text::labeled("error", b)))
+        }
+        (Some(Subsystem::Mm), _) => (Text::hex(a), Some(Text::labeled("pages", b))),
+        (Some(Subsystem::Ipc), ktrace::ipc::SEND) => {
+            (Text::labeled("port", a), Some(Text::size(c)))
+        }
+        (Some(Subsystem::Ipc), _) => (Text::labeled("port", a), Some(Text::size(d))),
+        // The first argument on entry, the result on exit
+        (Some(Subsystem::Syscall), _) => (Text::labeled("syscall", a), Some(Text::hex(b))),
+        (None, _) => (Text::number(record.subsystem as u64), None),
+    }
+}
+
+/// How long one thread waited between being made ready and running
+#[derive(Clone, Copy, Default)]
+struct Latency {
+    thread: u64,
+    /// When the thread was made ready, if it has not run since
+    woken_ns: Option<u64>,
+    samples: u64,
+    total_ns: u64,
+    max_ns: u64,
+}
+
+/// `trace latency`: time from wakeup to switch-in, per thread
+fn print_latency(ctx: &mut CommandContext<'_>) -> CommandResult {
+    let mut threads = [Latency::default(); MAX_THREADS];
+    let mut count = 0;
+
+    for record in read_records() {
+        let (thread, woken) = if record.is(Subsystem::Sched, ktrace::sched::WAKE) {
+            (record.args[0], true)
+        } else if record.is(Subsystem::Sched, ktrace::sched::SWITCH) {
+            (record.args[1], false)
+        } else {
+            continue;
+        };
+
+        let index = match threads[..count].iter().position(|entry| entry.thread == thread) {
+            Some(index) => index,
+            None if count < MAX_THREADS => {
+                threads[count].thread = thread;
+                count += 1;
+                count - 1
+            }
+            None => continue,
+        };
+        let entry = &mut threads[index];
+        if woken {
+            entry.woken_ns.get_or_insert(record.timestamp_ns);
+        } else if let Some(woken_ns) = entry.woken_ns.take() {
+            let waited = record.timestamp_ns.saturating_sub(woken_ns);
+            entry.samples += 1;
+            entry.total_ns += waited;
+            entry.max_ns = entry.max_ns.max(waited);
+        }
+    }
+
+    let threads = &threads[..count];
+    if threads.iter().all(|entry| entry.samples == 0) {
+        ctx.println("No wakeups traced; turn the scheduler on with: trace on sched");
+        return CommandResult::Ok;
+    }
+
+    const COLUMNS: [Column; 4] = [
+        Column::right("THREAD", 6),
+        Column::right("WAKEUPS", 8),
+        Column::right("AVG", 10),
+        Column::right("MAX", 10),
+    ];
+    let table = Table::new(&COLUMNS);
+    table.header(ctx);
+    for entry in threads.iter().filter(|entry| entry.samples > 0) {
+        let cells = [
+            Text::number(entry.thread),
+            Text::number(entry.samples),
+            Text::micros(entry.total_ns / entry.samples / 1000),
+            Text::micros(entry.max_ns / 1000),
+        ];
+        table.row(ctx, &cells.each_ref().map(Text::as_str));
+    }
+    CommandResult::Ok
+}
+
+/// Messages one thread sent another through a port
+#[derive(Clone, Copy, Default)]
+struct Flow {
+    port: PortId,
+    sender: u64,
+    receiver: u64,
+    messages: u64,
+    bytes: u64,
+    /// Messages whose send was traced too, so their latency is known
+    timed: u64,
+    total_ns: u64,
+    max_ns: u64,
+}
+
+/// A traced send not yet matched with its receive
+#[derive(Clone, Copy, Default)]
+struct PendingSend {
+    port: PortId,
+    sender: u64,
+    sent_ns: u64,
+}
+
+/// `trace ipc`: received messages grouped by port, sender and receiver,
+/// with the time they spent queued
+fn print_flows(ctx: &mut CommandContext<'_>) -> CommandResult {
+    let mut flows = [Flow::default(); MAX_FLOWS];
+    let mut flow_count = 0;
+    let mut pending = [PendingSend::default(); MAX_PENDING_SENDS];
+    let mut pending_count = 0;
+
+    for record in read_records() {
+        let [port, sender, receiver, size] = record.args;
+        if record.is(Subsystem::Ipc, ktrace::ipc::SEND) {
+            // Ports queue in order, so a receive matches the oldest send;
+            // when full, the oldest send is given up on
+            if pending_count == MAX_PENDING_SENDS {
+                pending.copy_within(1.., 0);
+                pending_count -= 1;
+            }
+            pending[pending_count] = PendingSend { port, sender, sent_ns: record.timestamp_ns };
+            pending_count += 1;
+            continue;
+        }
+        if !record.is(Subsystem::Ipc, ktrace::ipc::RECV) {
+            continue;
+        }
+
+        let index = match flows[..flow_count].iter().position(|flow| {
+            flow.port == port && flow.sender == sender && flow.receiver == receiver
+        }) {
+            Some(index) => index,
+            None if flow_count < MAX_FLOWS => {
+                flows[flow_count] = Flow { port, sender, receiver, ..Flow::default() };
+                flow_count += 1;
+                flow_count - 1
+            }
+            None => continue,
+        };
+        let flow = &mut flows[index];
+        flow.messages += 1;
+        flow.bytes += size;
+
+        let sent = pending[..pending_count]
+            .iter()
+            .position(|send| send.port == port && send.sender == sender);
+        if let Some(sent) = sent {
+            let queued = record.timestamp_ns.saturating_sub(pending[sent].sent_ns);
+            pending.copy_within(sent + 1..pending_count, sent);
+            pending_count -= 1;
+            flow.timed += 1;
+            flow.total_ns += queued;
+            flow.max_ns = flow.max_ns.max(queued);
+        }
+    }
+
+    if flow_count == 0 {
+        ctx.println("No IPC messages traced; turn IPC on with: trace on ipc");
+        return CommandResult::Ok;
+    }
+
+    const COLUMNS: [Column; 7] = [
+        Column::right("PORT", 5),
+        Column::right("FROM", 6),
+        Column::right("TO", 6),
+        Column::right("MSGS", 6),
+        Column::right("BYTES", 8),
+        Column::right("AVG", 10),
+        Column::right("MAX", 10),
+    ];
+    let table = Table::new(&COLUMNS);
+    table.header(ctx);
+    for flow in &flows[..flow_count] {
+        let (avg, max) = match flow.timed {
+            0 => (None, None),
+            timed => (
+                Some(Text::micros(flow.total_ns / timed / 1000)),
+                Some(Text::micros(flow.max_ns / 1000)),
+            ),
+        };
+        let cells = [
+            Text::number(flow.port),
+            Text::number(flow.sender),
+            Text::number(flow.receiver),
+            Text::number(flow.messages),
+            Text::size(flow.bytes),
+        ];
+        let cells = cells.each_ref().map(Text::as_str);
+        table.row(
+            ctx,
+            &[
+                cells[0],
+                cells[1],
+                cells[2],
+                cells[3],
+                cells[4],
+                avg.as_ref().map_or("-", Text::as_str),
+                max.as_ref().map_or("-", Text::as_str),
+            ],
+        );
+    }
+    CommandResult::Ok
+}
@@ -0,0 +1,199 @@
+// Tab Completion Module
+//
+// This module finds completions for the word before the cursor:
+// - the first word from the built-in command names
+// - words starting with '-' from the flags in the command's usage text
+// - other words as paths, from the filesystem service's directory listing
+// With several candidates the terminal extends the word to their common
+// prefix and lists them; pressing Tab again cycles through them.
+
+use crate::commands::filesystem::get_current_dir;
+use crate::commands::{get_all_commands, get_command_help};
+use crate::ipc_client::IpcClient;
+
+/// Maximum candidates kept; later matches are dropped
+pub const MAX_CANDIDATES: usize = 32;
+
+/// Maximum length of a candidate
+pub const MAX_CANDIDATE_LENGTH: usize = 64;
+
+/// Maximum length of a directory path to list
+const MAX_PATH_LENGTH: usize = 256;
+
+/// Completions for a word
+pub struct Candidates {
+    names: [[u8; MAX_CANDIDATE_LENGTH]; MAX_CANDIDATES],
+    lengths: [usize; MAX_CANDIDATES],
+    count: usize,
+}
+
+impl Candidates {
+    pub const fn new() -> Self {
+        Self {
+            names: [[0u8; MAX_CANDIDATE_LENGTH]; MAX_CANDIDATES],
+            lengths: [0usize; MAX_CANDIDATES],
+            count: 0,
+        }
+    }
+
+    /// Add a candidate made of `parts` joined together, unless it is
+    /// already there
+    fn push(&mut self, parts: &[&str]) {
+        if self.count >= MAX_CANDIDATES {
+            return;
+        }
+
+        let name = &mut self.names[self.count];
+        let mut len = 0;
+        for byte in parts.iter().flat_map(|part| part.bytes()) {
+            if len < MAX_CANDIDATE_LENGTH {
+                name[len] = byte;
+                len += 1;
+            }
+        }
+        self.lengths[self.count] = len;
+
+        let new = self.get(self.count);
+        if !self.iter().any(|existing| existing == new) {
+            self.count += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Candidate `index`; also reads the candidate being pushed
+    pub fn get(&self, index: usize) -> &str {
+        // Safety: candidates are copied whole from commands and file names,
+        // which are ASCII
+        unsafe { core::str::from_utf8_unchecked(&self.names[index][..self.lengths[index]]) }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> + '_ {
+        (0..self.count).map(|i| self.get(i))
+    }
+
+    /// Longest prefix all candidates share
+    pub fn common_prefix(&self) -> &str {
+        let first = self.get(0);
+        let mut len = first.len();
+        for other in self.iter().skip(1) {
+            len = first
+                .bytes()
+                .zip(other.bytes())
+                .take(len)
+                .take_while(|(a, b)| a == b)
+                .count();
+        }
+        &first[..len]
+    }
+}
+
+impl Default for Candidates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Completions for the word before the cursor
+pub struct Completion {
+    /// Where the word starts in the input line
+    pub word_start: usize,
+    pub candidates: Candidates,
+    /// Candidate to put in next when cycling
+    next: usize,
+}
+
+impl Completion {
+    /// Candidate to replace the word with on the next Tab, going round
+    /// the list
+    pub fn cycle(&mut self) -> &str {
+        let index = self.next;
+        self.next = (self.next + 1) % self.candidates.len().max(1);
+        self.candidates.get(index)
+    }
+}
+
+/// Complete the word that ends at `cursor` in `line`
+pub fn complete(line: &str, cursor: usize, ipc: &IpcClient) -> Completion {
+    let before = &line[..cursor];
+    let word_start = before.rfind(' ').map_or(0, |space| space + 1);
+    let word = &before[word_start..];
+    let command = before.split_whitespace().next().unwrap_or("");
+
+    let mut candidates = Candidates::new();
+    if word_start == 0 || command.eq_ignore_ascii_case("help") {
+        complete_command(word, &mut candidates);
+    } else if word.starts_with('-') {
+        complete_flag(command, word, &mut candidates);
+    } else {
+        complete_path(word, ipc, &mut candidates);
+    }
+
+    Completion {
+        word_start,
+        candidates,
+        next: 0,
+    }
+}
+
+fn complete_command(word: &str, candidates: &mut Candidates) {
+    for &(name, _) in get_all_commands() {
+        if name.starts_with(word) {
+            candidates.push(&[name]);
+        }
+    }
+}
+
+/// Flags are the words starting with '-' in the usage text, such as
+/// "-a" in "ls [-a] [path]"
+fn complete_flag(command: &str, word: &str, candidates: &mut Candidates) {
+    let Some((usage, _)) = get_command_help(command) else {
+        return;
+    };
+
+    for token in usage.split_whitespace() {
+        let flag = token.trim_matches(|c| c == '[' || c == ']');
+        if flag.starts_with('-') && flag.starts_with(word) {
+            candidates.push(&[flag]);
+        }
+    }
+}
+
+/// Entries of the word's directory that start with its last component;
+/// directories end in '/' so completion can continue into them
+fn complete_path(word: &str, ipc: &IpcClient, candidates: &mut Candidates) {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(slash) => (&word[..slash + 1], &word[slash + 1..]),
+        None => ("", word),
+    };
+
+    // Relative directories are listed from the current directory
+    let mut path = [0u8; MAX_PATH_LENGTH];
+    let mut len = 0;
+    let cwd = get_current_dir();
+    let parts: [&str; 3] = match dir {
+        "" => [cwd, "", ""],
+        _ if dir.starts_with('/') => [dir, "", ""],
+        _ if cwd.ends_with('/') => [cwd, dir, ""],
+        _ => [cwd, "/", dir],
+    };
+    for byte in parts.iter().flat_map(|part| part.bytes()) {
+        if len < MAX_PATH_LENGTH {
+            path[len] = byte;
+            len += 1;
+        }
+    }
+    // Safety: built from the input line and the current directory
+    let path = unsafe { core::str::from_utf8_unchecked(&path[..len]) };
+
+    ipc.list_directory(path, |name, is_dir, _| {
+        // Hidden entries only when asked for
+        let hidden = name.starts_with('.') && !prefix.starts_with('.');
+        if name.starts_with(prefix) && !hidden {
+            let suffix = if is_dir { "/" } else { "" };
+            candidates.push(&[dir, name, suffix]);
+        }
+    });
+}
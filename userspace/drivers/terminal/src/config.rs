@@ -0,0 +1,556 @@
+// Terminal Configuration Module
+//
+// This module keeps the settings that shape how the terminal looks: the
+// color palette, the font scale, the cursor style and the padding around
+// the text. They start at the defaults, are read from CONFIG_FILE at
+// startup and are changed with the `config` command; the terminal redraws
+// itself once a command has changed them.
+//
+// The file holds a `key = value` setting per line; blank lines and lines
+// starting with '#' are skipped:
+//
+//     scheme = solarized
+//     font-scale = 2
+//     cursor = underline
+//     background = #101010
+//
+// `scheme` replaces the whole palette, so colors set after it adjust the
+// scheme.
+
+use core::ptr::{addr_of, addr_of_mut};
+
+use atom_syscall::graphics::Color;
+
+/// Where the configuration is saved
+pub const CONFIG_FILE: &str = "/.terminal.conf";
+
+/// Largest configuration file
+pub const MAX_CONFIG_SIZE: usize = 2048;
+
+/// Largest font scale; the 8x8 font is drawn 8, 16 or 24 pixels high
+pub const MAX_FONT_SCALE: u32 = 3;
+
+/// Largest padding, in pixels
+pub const MAX_PADDING: u32 = 16;
+
+/// Colors the terminal draws with
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    // Window chrome
+    pub window_bg: Color,
+    pub window_border: Color,
+    pub title_bar_bg: Color,
+    pub title_bar_text: Color,
+
+    // Text
+    pub text_normal: Color,
+    pub text_bright: Color,
+    pub text_dim: Color,
+    pub text_error: Color,
+    pub text_success: Color,
+    pub text_info: Color,
+    pub text_warning: Color,
+
+    // Prompt
+    pub prompt_user: Color,
+    pub prompt_path: Color,
+    pub prompt_symbol: Color,
+
+    pub cursor_bg: Color,
+    pub selection_bg: Color,
+
+    /// ANSI colors 0-15 (black, red, green, yellow, blue, magenta, cyan,
+    /// white, then their bright variants)
+    pub ansi: [Color; 16],
+}
+
+impl Palette {
+    pub const DARK: Self = Self {
+        window_bg: Color::new(30, 30, 30),
+        window_border: Color::new(60, 60, 60),
+        title_bar_bg: Color::new(45, 45, 45),
+        title_bar_text: Color::new(200, 200, 200),
+        text_normal: Color::new(220, 220, 220),
+        text_bright: Color::WHITE,
+        text_dim: Color::new(128, 128, 128),
+        text_error: Color::new(255, 100, 100),
+        text_success: Color::new(100, 255, 100),
+        text_info: Color::new(100, 180, 255),
+        text_warning: Color::new(255, 200, 100),
+        prompt_user: Color::new(136, 192, 208),
+        prompt_path: Color::new(163, 190, 140),
+        prompt_symbol: Color::new(180, 142, 173),
+        cursor_bg: Color::new(200, 200, 200),
+        selection_bg: Color::new(70, 100, 130),
+        ansi: [
+            Color::new(0, 0, 0),
+            Color::new(205, 49, 49),
+            Color::new(13, 188, 121),
+            Color::new(229, 229, 16),
+            Color::new(36, 114, 200),
+            Color::new(188, 63, 188),
+            Color::new(17, 168, 205),
+            Color::new(229, 229, 229),
+            Color::new(102, 102, 102),
+            Color::new(241, 76, 76),
+            Color::new(35, 209, 139),
+            Color::new(245, 245, 67),
+            Color::new(59, 142, 234),
+            Color::new(214, 112, 214),
+            Color::new(41, 184, 219),
+            Color::new(255, 255, 255),
+        ],
+    };
+
+    pub const LIGHT: Self = Self {
+        window_bg: Color::new(250, 250, 250),
+        window_border: Color::new(190, 190, 190),
+        title_bar_bg: Color::new(225, 225, 225),
+        title_bar_text: Color::new(60, 60, 60),
+        text_normal: Color::new(40, 40, 40),
+        text_bright: Color::BLACK,
+        text_dim: Color::new(140, 140, 140),
+        text_error: Color::new(200, 40, 40),
+        text_success: Color::new(30, 140, 60),
+        text_info: Color::new(30, 100, 200),
+        text_warning: Color::new(180, 110, 0),
+        prompt_user: Color::new(0, 120, 150),
+        prompt_path: Color::new(70, 130, 40),
+        prompt_symbol: Color::new(140, 70, 140),
+        cursor_bg: Color::new(60, 60, 60),
+        selection_bg: Color::new(180, 210, 240),
+        ansi: [
+            Color::new(0, 0, 0),
+            Color::new(205, 49, 49),
+            Color::new(0, 140, 0),
+            Color::new(148, 152, 0),
+            Color::new(4, 81, 165),
+            Color::new(188, 5, 188),
+            Color::new(5, 152, 188),
+            Color::new(85, 85, 85),
+            Color::new(102, 102, 102),
+            Color::new(205, 49, 49),
+            Color::new(20, 206, 20),
+            Color::new(181, 186, 0),
+            Color::new(4, 81, 165),
+            Color::new(188, 5, 188),
+            Color::new(5, 152, 188),
+            Color::new(165, 165, 165),
+        ],
+    };
+
+    pub const SOLARIZED: Self = Self {
+        window_bg: Color::new(0, 43, 54),
+        window_border: Color::new(7, 54, 66),
+        title_bar_bg: Color::new(7, 54, 66),
+        title_bar_text: Color::new(147, 161, 161),
+        text_normal: Color::new(131, 148, 150),
+        text_bright: Color::new(238, 232, 213),
+        text_dim: Color::new(88, 110, 117),
+        text_error: Color::new(220, 50, 47),
+        text_success: Color::new(133, 153, 0),
+        text_info: Color::new(38, 139, 210),
+        text_warning: Color::new(181, 137, 0),
+        prompt_user: Color::new(42, 161, 152),
+        prompt_path: Color::new(133, 153, 0),
+        prompt_symbol: Color::new(108, 113, 196),
+        cursor_bg: Color::new(147, 161, 161),
+        selection_bg: Color::new(7, 54, 66),
+        ansi: [
+            Color::new(7, 54, 66),
+            Color::new(220, 50, 47),
+            Color::new(133, 153, 0),
+            Color::new(181, 137, 0),
+            Color::new(38, 139, 210),
+            Color::new(211, 54, 130),
+            Color::new(42, 161, 152),
+            Color::new(238, 232, 213),
+            Color::new(0, 43, 54),
+            Color::new(203, 75, 22),
+            Color::new(88, 110, 117),
+            Color::new(101, 123, 131),
+            Color::new(131, 148, 150),
+            Color::new(108, 113, 196),
+            Color::new(147, 161, 161),
+            Color::new(253, 246, 227),
+        ],
+    };
+
+    /// The color `config` knows by `name`
+    fn color_mut(&mut self, name: &str) -> Option<&mut Color> {
+        let color = match name {
+            "background" => &mut self.window_bg,
+            "border" => &mut self.window_border,
+            "title-background" => &mut self.title_bar_bg,
+            "title" => &mut self.title_bar_text,
+            "foreground" => &mut self.text_normal,
+            "bright" => &mut self.text_bright,
+            "dim" => &mut self.text_dim,
+            "error" => &mut self.text_error,
+            "success" => &mut self.text_success,
+            "info" => &mut self.text_info,
+            "warning" => &mut self.text_warning,
+            "prompt-user" => &mut self.prompt_user,
+            "prompt-path" => &mut self.prompt_path,
+            "prompt-symbol" => &mut self.prompt_symbol,
+            "cursor-color" => &mut self.cursor_bg,
+            "selection" => &mut self.selection_bg,
+            _ => {
+                let index: usize = name.strip_prefix("color")?.parse().ok()?;
+                self.ansi.get_mut(index)?
+            }
+        };
+        Some(color)
+    }
+
+    fn color(&self, name: &str) -> Option<Color> {
+        let mut palette = *self;
+        palette.color_mut(name).copied()
+    }
+
+    /// Every color, in the order of COLOR_NAMES
+    fn colors(&self) -> [Color; COLOR_NAMES.len()] {
+        COLOR_NAMES.map(|name| self.color(name).unwrap_or_default())
+    }
+
+    /// Map each color of this palette to the color with the same role in
+    /// `to`; a color with no role here stays as it is
+    pub fn translation(&self, to: &Palette) -> impl Fn(Color) -> Color {
+        let (from, to) = (self.colors(), to.colors());
+        move |color| from.iter().position(|&c| c == color).map_or(color, |i| to[i])
+    }
+}
+
+/// Schemes `scheme` can name
+const SCHEMES: [(&str, Palette); 3] = [
+    ("dark", Palette::DARK),
+    ("light", Palette::LIGHT),
+    ("solarized", Palette::SOLARIZED),
+];
+
+/// Names of the palette colors, in the order `config` lists them
+const COLOR_NAMES: [&str; 32] = [
+    "background", "foreground", "bright", "dim", "error", "success", "info", "warning",
+    "prompt-user", "prompt-path", "prompt-symbol", "cursor-color", "selection", "border",
+    "title-background", "title", "color0", "color1", "color2", "color3", "color4", "color5",
+    "color6", "color7", "color8", "color9", "color10", "color11", "color12", "color13",
+    "color14", "color15",
+];
+
+/// Settings other than colors, in the order `config` lists them
+const SETTING_NAMES: [&str; 4] = ["scheme", "font-scale", "cursor", "padding"];
+
+/// How the cursor is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorStyle {
+    /// The whole cell, with the character under it inverted
+    Block,
+    /// A line under the character
+    Underline,
+    /// A line before the character
+    Bar,
+}
+
+impl CursorStyle {
+    const ALL: [Self; 3] = [Self::Block, Self::Underline, Self::Bar];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::Underline => "underline",
+            Self::Bar => "bar",
+        }
+    }
+}
+
+/// Why a setting was not changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    UnknownKey,
+    InvalidValue,
+}
+
+impl ConfigError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::UnknownKey => "Unknown setting; 'config' lists them",
+            Self::InvalidValue => "Invalid value for that setting",
+        }
+    }
+}
+
+/// A setting's value as text
+pub struct Value {
+    bytes: [u8; 12],
+    len: usize,
+}
+
+impl Value {
+    fn name(name: &str) -> Self {
+        let mut value = Self { bytes: [0u8; 12], len: 0 };
+        value.push(name.as_bytes());
+        value
+    }
+
+    fn number(n: u32) -> Self {
+        let mut digits = [0u8; 10];
+        let mut count = 0;
+        let mut n = n;
+        while n > 0 || count == 0 {
+            digits[count] = b'0' + (n % 10) as u8;
+            n /= 10;
+            count += 1;
+        }
+        digits[..count].reverse();
+        let mut value = Self { bytes: [0u8; 12], len: 0 };
+        value.push(&digits[..count]);
+        value
+    }
+
+    /// A color as #rrggbb
+    fn color(color: Color) -> Self {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        let mut value = Self::name("#");
+        for channel in [color.r, color.g, color.b] {
+            value.push(&[HEX[(channel >> 4) as usize], HEX[(channel & 0xF) as usize]]);
+        }
+        value
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        let len = bytes.len().min(self.bytes.len() - self.len);
+        self.bytes[self.len..self.len + len].copy_from_slice(&bytes[..len]);
+        self.len += len;
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Safety: only ASCII is pushed
+        unsafe { core::str::from_utf8_unchecked(&self.bytes[..self.len]) }
+    }
+}
+
+/// Parse a color written as #rrggbb
+fn parse_color(text: &str) -> Option<Color> {
+    let hex = text.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some(Color::new(channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// The terminal's settings
+#[derive(Clone, Copy)]
+pub struct TerminalConfig {
+    pub palette: Palette,
+    /// Scheme the palette started from
+    pub scheme: &'static str,
+    /// Text is drawn `font_scale` times the font's size
+    pub font_scale: u32,
+    pub cursor: CursorStyle,
+    /// Space between the window border and the text, in pixels
+    pub padding: u32,
+}
+
+impl TerminalConfig {
+    pub const DEFAULT: Self = Self {
+        palette: Palette::DARK,
+        scheme: "dark",
+        font_scale: 1,
+        cursor: CursorStyle::Block,
+        padding: 8,
+    };
+
+    /// Names of every setting, in the order `config` lists them
+    pub fn keys() -> impl Iterator<Item = &'static str> {
+        SETTING_NAMES.into_iter().chain(COLOR_NAMES)
+    }
+
+    /// Value of the setting `key`
+    pub fn get(&self, key: &str) -> Option<Value> {
+        match key {
+            "scheme" => Some(Value::name(self.scheme)),
+            "font-scale" => Some(Value::number(self.font_scale)),
+            "cursor" => Some(Value::name(self.cursor.name())),
+            "padding" => Some(Value::number(self.padding)),
+            _ => self.palette.color(key).map(Value::color),
+        }
+    }
+
+    /// Change the setting `key`
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = ConfigError::InvalidValue;
+        match key {
+            "scheme" => {
+                let (name, palette) = SCHEMES
+                    .iter()
+                    .find(|(name, _)| *name == value)
+                    .ok_or(invalid)?;
+                self.scheme = name;
+                self.palette = *palette;
+            }
+            "font-scale" => {
+                let scale = value.parse().map_err(|_| invalid)?;
+                if !(1..=MAX_FONT_SCALE).contains(&scale) {
+                    return Err(invalid);
+                }
+                self.font_scale = scale;
+            }
+            "cursor" => {
+                self.cursor = CursorStyle::ALL
+                    .into_iter()
+                    .find(|style| style.name() == value)
+                    .ok_or(invalid)?;
+            }
+            "padding" => {
+                let padding = value.parse().map_err(|_| invalid)?;
+                if padding > MAX_PADDING {
+                    return Err(invalid);
+                }
+                self.padding = padding;
+            }
+            _ => {
+                let color = self.palette.color_mut(key).ok_or(ConfigError::UnknownKey)?;
+                *color = parse_color(value).ok_or(invalid)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the `key = value` lines of a configuration file; returns how
+    /// many lines were refused. The others still take effect.
+    pub fn load(&mut self, text: &str) -> usize {
+        let mut refused = 0;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let applied = match line.split_once('=') {
+                Some((key, value)) => self.set(key.trim(), value.trim()).is_ok(),
+                None => false,
+            };
+            if !applied {
+                refused += 1;
+            }
+        }
+        refused
+    }
+
+    /// Write the settings as a configuration file into `out`, returning
+    /// the length. Colors are only written where they differ from the
+    /// scheme.
+    pub fn save(&self, out: &mut [u8]) -> usize {
+        let scheme = SCHEMES
+            .iter()
+            .find(|(name, _)| *name == self.scheme)
+            .map_or(Palette::DARK, |(_, palette)| *palette);
+        let changed = |key: &&str| match scheme.color(key) {
+            Some(color) => self.palette.color(key) != Some(color),
+            None => true,
+        };
+
+        let mut len = 0;
+        for key in Self::keys().filter(changed) {
+            let Some(value) = self.get(key) else {
+                continue;
+            };
+            for part in [key, " = ", value.as_str(), "\n"] {
+                let end = len + part.len();
+                if end > out.len() {
+                    return len;
+                }
+                out[len..end].copy_from_slice(part.as_bytes());
+                len = end;
+            }
+        }
+        len
+    }
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The terminal's settings
+static mut CONFIG: TerminalConfig = TerminalConfig::DEFAULT;
+
+/// Whether the settings changed since the terminal last applied them
+static mut CHANGED: bool = false;
+
+pub fn config() -> &'static TerminalConfig {
+    // Safety: the terminal is single-threaded
+    unsafe { &*addr_of!(CONFIG) }
+}
+
+/// The settings, to change them; the terminal applies them once the
+/// command line is done
+pub fn config_mut() -> &'static mut TerminalConfig {
+    // Safety: the terminal is single-threaded
+    unsafe {
+        CHANGED = true;
+        &mut *addr_of_mut!(CONFIG)
+    }
+}
+
+/// The colors in use
+pub fn theme() -> &'static Palette {
+    &config().palette
+}
+
+/// Whether the settings changed since the last call
+pub fn take_changed() -> bool {
+    // Safety: the terminal is single-threaded
+    unsafe { core::mem::replace(&mut *addr_of_mut!(CHANGED), false) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set() {
+        let mut config = TerminalConfig::DEFAULT;
+        assert_eq!(config.set("scheme", "light"), Ok(()));
+        assert!(config.palette == Palette::LIGHT);
+        assert_eq!(config.set("background", "#102030"), Ok(()));
+        assert_eq!(config.palette.window_bg, Color::new(0x10, 0x20, 0x30));
+        assert_eq!(config.set("color9", "#ffffff"), Ok(()));
+        assert_eq!(config.palette.ansi[9], Color::WHITE);
+        assert_eq!(config.set("cursor", "bar"), Ok(()));
+        assert_eq!(config.cursor, CursorStyle::Bar);
+        assert_eq!(config.set("font-scale", "4"), Err(ConfigError::InvalidValue));
+        assert_eq!(config.set("background", "#12345"), Err(ConfigError::InvalidValue));
+        assert_eq!(config.set("color16", "#000000"), Err(ConfigError::UnknownKey));
+        assert_eq!(config.get("background").unwrap().as_str(), "#102030");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut config = TerminalConfig::DEFAULT;
+        config.set("scheme", "solarized").unwrap();
+        config.set("font-scale", "2").unwrap();
+        config.set("dim", "#808080").unwrap();
+
+        let mut out = [0u8; MAX_CONFIG_SIZE];
+        let len = config.save(&mut out);
+        let text = core::str::from_utf8(&out[..len]).unwrap();
+        let expected = "scheme = solarized\nfont-scale = 2\ncursor = block\npadding = 8\n\
+                        dim = #808080\n";
+        assert_eq!(text, expected);
+
+        let mut loaded = TerminalConfig::DEFAULT;
+        assert_eq!(loaded.load("# saved\n\n  scheme = solarized\ndim=#808080\nbogus\n"), 1);
+        assert!(loaded.palette == config.palette);
+    }
+
+    #[test]
+    fn test_translation() {
+        let translate = Palette::DARK.translation(&Palette::LIGHT);
+        assert_eq!(translate(Palette::DARK.text_info), Palette::LIGHT.text_info);
+        assert_eq!(translate(Palette::DARK.ansi[4]), Palette::LIGHT.ansi[4]);
+        assert_eq!(translate(Color::new(1, 2, 3)), Color::new(1, 2, 3));
+    }
+}
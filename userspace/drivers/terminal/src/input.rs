@@ -1,11 +1,15 @@
 // Terminal Input Handling Module
 //
 // This module handles keyboard input for the terminal.
-// It polls the kernel's input buffer via syscalls, translates
-// scancodes to characters, and manages modifier key state.
+// It polls the kernel's input buffer via syscalls, decodes scancodes
+// (set 1 or set 2) into `KeyCode`s, translates those to characters,
+// and manages modifier key state.
+// It also reads the mouse, for scrolling the scrollback with the wheel and
+// for selecting text.
 // All input comes through the userspace input service, not direct hardware access.
 
-use atom_syscall::input::{keyboard_poll, scancodes};
+use atom_syscall::input::{keyboard_poll, MouseDriver, MouseEvent, MouseProtocol};
+use libipc::keycode::{KeyCode, ScancodeDecoder};
 
 /// Key events produced by the input handler
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -44,6 +48,45 @@ pub enum KeyEvent {
     Insert,
 }
 
+/// What F1-F12 send, as xterm sends them
+const FUNCTION_KEYS: [&[u8]; 12] = [
+    b"\x1BOP", b"\x1BOQ", b"\x1BOR", b"\x1BOS", b"\x1B[15~", b"\x1B[17~", b"\x1B[18~",
+    b"\x1B[19~", b"\x1B[20~", b"\x1B[21~", b"\x1B[23~", b"\x1B[24~",
+];
+
+impl KeyEvent {
+    /// The bytes a program in raw mode reads for the key: the character
+    /// itself, or the escape sequence xterm sends for it
+    pub fn encode<'a>(&self, out: &'a mut [u8; 8]) -> &'a [u8] {
+        match *self {
+            KeyEvent::Char(ch) | KeyEvent::Control(ch) => ch.encode_utf8(out).as_bytes(),
+            KeyEvent::Alt(ch) => {
+                // Alt sends Escape before the character
+                out[0] = 0x1B;
+                let len = 1 + ch.encode_utf8(&mut out[1..]).len();
+                &out[..len]
+            }
+            KeyEvent::Enter => b"\r",
+            KeyEvent::Backspace => b"\x7F",
+            KeyEvent::Tab => b"\t",
+            KeyEvent::Escape => b"\x1B",
+            KeyEvent::Delete => b"\x1B[3~",
+            KeyEvent::Insert => b"\x1B[2~",
+            KeyEvent::ArrowUp => b"\x1B[A",
+            KeyEvent::ArrowDown => b"\x1B[B",
+            KeyEvent::ArrowRight => b"\x1B[C",
+            KeyEvent::ArrowLeft => b"\x1B[D",
+            KeyEvent::Home => b"\x1B[H",
+            KeyEvent::End => b"\x1B[F",
+            KeyEvent::PageUp => b"\x1B[5~",
+            KeyEvent::PageDown => b"\x1B[6~",
+            KeyEvent::Function(n) => {
+                FUNCTION_KEYS.get((n as usize).wrapping_sub(1)).copied().unwrap_or(b"")
+            }
+        }
+    }
+}
+
 /// Keyboard input state machine
 pub struct InputHandler {
     // Modifier states
@@ -53,8 +96,11 @@ pub struct InputHandler {
     alt: bool,
     caps_lock: bool,
 
-    // Extended scancode handling
-    extended: bool,
+    // Scancode set and prefix handling
+    decoder: ScancodeDecoder,
+
+    // Mouse packets
+    mouse: MouseDriver,
 }
 
 impl InputHandler {
@@ -65,7 +111,8 @@ impl InputHandler {
             ctrl: false,
             alt: false,
             caps_lock: false,
-            extended: false,
+            decoder: ScancodeDecoder::new(),
+            mouse: MouseDriver::new(),
         }
     }
 
@@ -95,50 +142,41 @@ impl InputHandler {
         None
     }
 
-    /// Process a raw scancode and potentially produce a key event
-    fn process_scancode(&mut self, scancode: u8) -> Option<KeyEvent> {
-        // Handle extended prefix (0xE0)
-        if scancode == scancodes::EXTENDED_PREFIX {
-            self.extended = true;
-            return None;
-        }
-
-        let is_extended = self.extended;
-        self.extended = false;
+    /// Switch the mouse to a protocol with a wheel, if it has one
+    pub fn enable_wheel(&mut self) -> bool {
+        self.mouse.enable_extensions() != MouseProtocol::Standard
+    }
 
-        let is_release = (scancode & 0x80) != 0;
-        let code = scancode & 0x7F;
+    /// Poll for the next mouse packet
+    pub fn poll_mouse(&mut self) -> Option<MouseEvent> {
+        self.mouse.poll_event()
+    }
 
-        // Handle extended scancodes (arrow keys, etc.)
-        if is_extended {
-            return self.process_extended_scancode(code, is_release);
-        }
+    /// Process a raw scancode byte and potentially produce a key event
+    fn process_scancode(&mut self, scancode: u8) -> Option<KeyEvent> {
+        let (key, pressed) = self.decoder.feed(scancode)?;
 
         // Handle modifier keys
-        match code {
-            0x2A => {
-                // Left Shift
-                self.shift_left = !is_release;
+        match key {
+            KeyCode::ShiftLeft => {
+                self.shift_left = pressed;
                 return None;
             }
-            0x36 => {
-                // Right Shift
-                self.shift_right = !is_release;
+            KeyCode::ShiftRight => {
+                self.shift_right = pressed;
                 return None;
             }
-            0x1D => {
-                // Ctrl
-                self.ctrl = !is_release;
+            KeyCode::ControlLeft | KeyCode::ControlRight => {
+                self.ctrl = pressed;
                 return None;
             }
-            0x38 => {
-                // Alt
-                self.alt = !is_release;
+            KeyCode::AltLeft | KeyCode::AltRight => {
+                self.alt = pressed;
                 return None;
             }
-            0x3A => {
-                // Caps Lock (toggle on press)
-                if !is_release {
+            KeyCode::CapsLock => {
+                // Toggle on press
+                if pressed {
                     self.caps_lock = !self.caps_lock;
                 }
                 return None;
@@ -147,34 +185,45 @@ impl InputHandler {
         }
 
         // Only produce events on key press, not release
-        if is_release {
+        if !pressed {
             return None;
         }
 
         // Handle special keys
-        match code {
-            0x01 => return Some(KeyEvent::Escape),
-            0x0E => return Some(KeyEvent::Backspace),
-            0x0F => return Some(KeyEvent::Tab),
-            0x1C => return Some(KeyEvent::Enter),
+        match key {
+            KeyCode::Escape => return Some(KeyEvent::Escape),
+            KeyCode::Backspace => return Some(KeyEvent::Backspace),
+            KeyCode::Tab => return Some(KeyEvent::Tab),
+            KeyCode::Enter | KeyCode::NumpadEnter => return Some(KeyEvent::Enter),
+            // Navigation block
+            KeyCode::ArrowUp => return Some(KeyEvent::ArrowUp),
+            KeyCode::ArrowDown => return Some(KeyEvent::ArrowDown),
+            KeyCode::ArrowLeft => return Some(KeyEvent::ArrowLeft),
+            KeyCode::ArrowRight => return Some(KeyEvent::ArrowRight),
+            KeyCode::Home => return Some(KeyEvent::Home),
+            KeyCode::End => return Some(KeyEvent::End),
+            KeyCode::PageUp => return Some(KeyEvent::PageUp),
+            KeyCode::PageDown => return Some(KeyEvent::PageDown),
+            KeyCode::Insert => return Some(KeyEvent::Insert),
+            KeyCode::Delete => return Some(KeyEvent::Delete),
             // Function keys
-            0x3B => return Some(KeyEvent::Function(1)),
-            0x3C => return Some(KeyEvent::Function(2)),
-            0x3D => return Some(KeyEvent::Function(3)),
-            0x3E => return Some(KeyEvent::Function(4)),
-            0x3F => return Some(KeyEvent::Function(5)),
-            0x40 => return Some(KeyEvent::Function(6)),
-            0x41 => return Some(KeyEvent::Function(7)),
-            0x42 => return Some(KeyEvent::Function(8)),
-            0x43 => return Some(KeyEvent::Function(9)),
-            0x44 => return Some(KeyEvent::Function(10)),
-            0x57 => return Some(KeyEvent::Function(11)),
-            0x58 => return Some(KeyEvent::Function(12)),
+            KeyCode::F1 => return Some(KeyEvent::Function(1)),
+            KeyCode::F2 => return Some(KeyEvent::Function(2)),
+            KeyCode::F3 => return Some(KeyEvent::Function(3)),
+            KeyCode::F4 => return Some(KeyEvent::Function(4)),
+            KeyCode::F5 => return Some(KeyEvent::Function(5)),
+            KeyCode::F6 => return Some(KeyEvent::Function(6)),
+            KeyCode::F7 => return Some(KeyEvent::Function(7)),
+            KeyCode::F8 => return Some(KeyEvent::Function(8)),
+            KeyCode::F9 => return Some(KeyEvent::Function(9)),
+            KeyCode::F10 => return Some(KeyEvent::Function(10)),
+            KeyCode::F11 => return Some(KeyEvent::Function(11)),
+            KeyCode::F12 => return Some(KeyEvent::Function(12)),
             _ => {}
         }
 
         // Translate to character
-        if let Some(ch) = self.translate_scancode(code) {
+        if let Some(ch) = self.translate_key(key) {
             if self.ctrl {
                 // Ctrl + letter produces control characters (Ctrl+A = 1, Ctrl+C = 3, etc.)
                 let ctrl_char = if ch.is_ascii_alphabetic() {
@@ -193,102 +242,87 @@ impl InputHandler {
         None
     }
 
-    /// Process extended scancodes (0xE0 prefix)
-    fn process_extended_scancode(&mut self, code: u8, is_release: bool) -> Option<KeyEvent> {
-        // Only produce events on key press
-        if is_release {
-            return None;
-        }
-
-        match code {
-            0x48 => Some(KeyEvent::ArrowUp),
-            0x50 => Some(KeyEvent::ArrowDown),
-            0x4B => Some(KeyEvent::ArrowLeft),
-            0x4D => Some(KeyEvent::ArrowRight),
-            0x47 => Some(KeyEvent::Home),
-            0x4F => Some(KeyEvent::End),
-            0x49 => Some(KeyEvent::PageUp),
-            0x51 => Some(KeyEvent::PageDown),
-            0x52 => Some(KeyEvent::Insert),
-            0x53 => Some(KeyEvent::Delete),
-            // Extended Ctrl (right ctrl)
-            0x1D => {
-                self.ctrl = true;
-                None
-            }
-            // Extended Alt (right alt / AltGr)
-            0x38 => {
-                self.alt = true;
-                None
-            }
-            _ => None,
-        }
-    }
-
-    /// Translate a scancode to its corresponding character
-    fn translate_scancode(&self, code: u8) -> Option<char> {
+    /// Translate a key code to its corresponding character
+    fn translate_key(&self, key: KeyCode) -> Option<char> {
         let shift = self.shift();
         let caps = self.caps_lock;
 
-        // Basic scancode to character mapping (US keyboard layout)
-        let ch = match code {
+        // Basic key code to character mapping (US keyboard layout)
+        let ch = match key {
             // Number row
-            0x02 => Some(if shift { '!' } else { '1' }),
-            0x03 => Some(if shift { '@' } else { '2' }),
-            0x04 => Some(if shift { '#' } else { '3' }),
-            0x05 => Some(if shift { '$' } else { '4' }),
-            0x06 => Some(if shift { '%' } else { '5' }),
-            0x07 => Some(if shift { '^' } else { '6' }),
-            0x08 => Some(if shift { '&' } else { '7' }),
-            0x09 => Some(if shift { '*' } else { '8' }),
-            0x0A => Some(if shift { '(' } else { '9' }),
-            0x0B => Some(if shift { ')' } else { '0' }),
-            0x0C => Some(if shift { '_' } else { '-' }),
-            0x0D => Some(if shift { '+' } else { '=' }),
+            KeyCode::Digit1 => Some(if shift { '!' } else { '1' }),
+            KeyCode::Digit2 => Some(if shift { '@' } else { '2' }),
+            KeyCode::Digit3 => Some(if shift { '#' } else { '3' }),
+            KeyCode::Digit4 => Some(if shift { '$' } else { '4' }),
+            KeyCode::Digit5 => Some(if shift { '%' } else { '5' }),
+            KeyCode::Digit6 => Some(if shift { '^' } else { '6' }),
+            KeyCode::Digit7 => Some(if shift { '&' } else { '7' }),
+            KeyCode::Digit8 => Some(if shift { '*' } else { '8' }),
+            KeyCode::Digit9 => Some(if shift { '(' } else { '9' }),
+            KeyCode::Digit0 => Some(if shift { ')' } else { '0' }),
+            KeyCode::Minus => Some(if shift { '_' } else { '-' }),
+            KeyCode::Equal => Some(if shift { '+' } else { '=' }),
 
             // Top row (QWERTY)
-            0x10 => Some(self.letter('q', shift, caps)),
-            0x11 => Some(self.letter('w', shift, caps)),
-            0x12 => Some(self.letter('e', shift, caps)),
-            0x13 => Some(self.letter('r', shift, caps)),
-            0x14 => Some(self.letter('t', shift, caps)),
-            0x15 => Some(self.letter('y', shift, caps)),
-            0x16 => Some(self.letter('u', shift, caps)),
-            0x17 => Some(self.letter('i', shift, caps)),
-            0x18 => Some(self.letter('o', shift, caps)),
-            0x19 => Some(self.letter('p', shift, caps)),
-            0x1A => Some(if shift { '{' } else { '[' }),
-            0x1B => Some(if shift { '}' } else { ']' }),
+            KeyCode::KeyQ => Some(self.letter('q', shift, caps)),
+            KeyCode::KeyW => Some(self.letter('w', shift, caps)),
+            KeyCode::KeyE => Some(self.letter('e', shift, caps)),
+            KeyCode::KeyR => Some(self.letter('r', shift, caps)),
+            KeyCode::KeyT => Some(self.letter('t', shift, caps)),
+            KeyCode::KeyY => Some(self.letter('y', shift, caps)),
+            KeyCode::KeyU => Some(self.letter('u', shift, caps)),
+            KeyCode::KeyI => Some(self.letter('i', shift, caps)),
+            KeyCode::KeyO => Some(self.letter('o', shift, caps)),
+            KeyCode::KeyP => Some(self.letter('p', shift, caps)),
+            KeyCode::BracketLeft => Some(if shift { '{' } else { '[' }),
+            KeyCode::BracketRight => Some(if shift { '}' } else { ']' }),
 
             // Home row (ASDF)
-            0x1E => Some(self.letter('a', shift, caps)),
-            0x1F => Some(self.letter('s', shift, caps)),
-            0x20 => Some(self.letter('d', shift, caps)),
-            0x21 => Some(self.letter('f', shift, caps)),
-            0x22 => Some(self.letter('g', shift, caps)),
-            0x23 => Some(self.letter('h', shift, caps)),
-            0x24 => Some(self.letter('j', shift, caps)),
-            0x25 => Some(self.letter('k', shift, caps)),
-            0x26 => Some(self.letter('l', shift, caps)),
-            0x27 => Some(if shift { ':' } else { ';' }),
-            0x28 => Some(if shift { '"' } else { '\'' }),
-            0x29 => Some(if shift { '~' } else { '`' }),
+            KeyCode::KeyA => Some(self.letter('a', shift, caps)),
+            KeyCode::KeyS => Some(self.letter('s', shift, caps)),
+            KeyCode::KeyD => Some(self.letter('d', shift, caps)),
+            KeyCode::KeyF => Some(self.letter('f', shift, caps)),
+            KeyCode::KeyG => Some(self.letter('g', shift, caps)),
+            KeyCode::KeyH => Some(self.letter('h', shift, caps)),
+            KeyCode::KeyJ => Some(self.letter('j', shift, caps)),
+            KeyCode::KeyK => Some(self.letter('k', shift, caps)),
+            KeyCode::KeyL => Some(self.letter('l', shift, caps)),
+            KeyCode::Semicolon => Some(if shift { ':' } else { ';' }),
+            KeyCode::Quote => Some(if shift { '"' } else { '\'' }),
+            KeyCode::Backquote => Some(if shift { '~' } else { '`' }),
 
             // Bottom row (ZXCV)
-            0x2B => Some(if shift { '|' } else { '\\' }),
-            0x2C => Some(self.letter('z', shift, caps)),
-            0x2D => Some(self.letter('x', shift, caps)),
-            0x2E => Some(self.letter('c', shift, caps)),
-            0x2F => Some(self.letter('v', shift, caps)),
-            0x30 => Some(self.letter('b', shift, caps)),
-            0x31 => Some(self.letter('n', shift, caps)),
-            0x32 => Some(self.letter('m', shift, caps)),
-            0x33 => Some(if shift { '<' } else { ',' }),
-            0x34 => Some(if shift { '>' } else { '.' }),
-            0x35 => Some(if shift { '?' } else { '/' }),
+            KeyCode::Backslash | KeyCode::IntlBackslash => Some(if shift { '|' } else { '\\' }),
+            KeyCode::KeyZ => Some(self.letter('z', shift, caps)),
+            KeyCode::KeyX => Some(self.letter('x', shift, caps)),
+            KeyCode::KeyC => Some(self.letter('c', shift, caps)),
+            KeyCode::KeyV => Some(self.letter('v', shift, caps)),
+            KeyCode::KeyB => Some(self.letter('b', shift, caps)),
+            KeyCode::KeyN => Some(self.letter('n', shift, caps)),
+            KeyCode::KeyM => Some(self.letter('m', shift, caps)),
+            KeyCode::Comma => Some(if shift { '<' } else { ',' }),
+            KeyCode::Period => Some(if shift { '>' } else { '.' }),
+            KeyCode::Slash => Some(if shift { '?' } else { '/' }),
 
             // Space
-            0x39 => Some(' '),
+            KeyCode::Space => Some(' '),
+
+            // Keypad (Num Lock is not tracked; always digits)
+            KeyCode::Numpad0 => Some('0'),
+            KeyCode::Numpad1 => Some('1'),
+            KeyCode::Numpad2 => Some('2'),
+            KeyCode::Numpad3 => Some('3'),
+            KeyCode::Numpad4 => Some('4'),
+            KeyCode::Numpad5 => Some('5'),
+            KeyCode::Numpad6 => Some('6'),
+            KeyCode::Numpad7 => Some('7'),
+            KeyCode::Numpad8 => Some('8'),
+            KeyCode::Numpad9 => Some('9'),
+            KeyCode::NumpadDecimal => Some('.'),
+            KeyCode::NumpadAdd => Some('+'),
+            KeyCode::NumpadSubtract => Some('-'),
+            KeyCode::NumpadMultiply => Some('*'),
+            KeyCode::NumpadDivide => Some('/'),
             _ => None,
         };
 
@@ -9,9 +9,14 @@
 // - Requests are sent as structured messages
 // - Responses are received and decoded
 
-use atom_syscall::ipc::{create_port, close_port, send, recv, try_recv, send_async, PortId};
+use atom_syscall::ipc::{recv, try_recv, send_async, wait_any, PortId};
 use atom_syscall::error::SyscallResult;
+use atom_syscall::shm::{self, RegionFlags, RegionId};
 use atom_syscall::thread::get_ticks;
+use atom_syscall::debug::{klog_read, LogLevel};
+use libipc::messages::{self as desktop, ClipboardMime, MessageHeader, CLIPBOARD_INLINE_MAX};
+use libipc::connection::Connection;
+use libipc::{ServiceId, MAX_MESSAGE_SIZE};
 
 /// Message types for IPC communication
 #[repr(u8)]
@@ -65,35 +70,40 @@ pub mod service_ports {
     pub const INPUT_SERVER: PortId = 6;
 }
 
+/// Clipboard data before the content: owner port, type, inline or
+/// shared, length
+const CLIPBOARD_DATA_HEADER: usize = 14;
+
+/// How long to wait for the compositor to answer a clipboard request
+const CLIPBOARD_TIMEOUT_MS: u64 = 500;
+
 /// IPC client for terminal commands
 pub struct IpcClient {
-    /// Our local port for receiving responses
-    response_port: Option<PortId>,
+    /// Link to the desktop compositor; its port receives responses
+    desktop: Connection<'static>,
+    /// Region holding the last long text copied to the clipboard
+    clipboard_region: Option<RegionId>,
 }
 
 impl IpcClient {
     pub fn new() -> Self {
+        // The terminal may run without a desktop; don't wait for one
+        let mut desktop = Connection::new(ServiceId::Desktop);
+        desktop.set_lookup_timeout(0);
         Self {
-            response_port: None,
+            desktop,
+            clipboard_region: None,
         }
     }
 
     /// Initialize the client (create response port)
     pub fn init(&mut self) -> bool {
-        match create_port() {
-            Ok(port) => {
-                self.response_port = Some(port);
-                true
-            }
-            Err(_) => false,
-        }
+        self.desktop.port().is_ok()
     }
 
     /// Clean up resources
     pub fn cleanup(&mut self) {
-        if let Some(port) = self.response_port.take() {
-            let _ = close_port(port);
-        }
+        self.desktop.close_port();
     }
 
     /// Get system uptime in ticks
@@ -101,83 +111,6 @@ impl IpcClient {
         get_ticks()
     }
 
-    /// Get system uptime formatted as string
-    pub fn format_uptime(&self, buffer: &mut [u8]) -> usize {
-        let ticks = get_ticks();
-        // Assuming 100Hz timer (10ms per tick)
-        let total_seconds = ticks / 100;
-        let hours = total_seconds / 3600;
-        let minutes = (total_seconds % 3600) / 60;
-        let seconds = total_seconds % 60;
-
-        let mut pos = 0;
-
-        // Format hours
-        pos += format_number(hours, &mut buffer[pos..]);
-        if pos < buffer.len() {
-            buffer[pos] = b':';
-            pos += 1;
-        }
-
-        // Format minutes with leading zero
-        if minutes < 10 && pos < buffer.len() {
-            buffer[pos] = b'0';
-            pos += 1;
-        }
-        pos += format_number(minutes, &mut buffer[pos..]);
-        if pos < buffer.len() {
-            buffer[pos] = b':';
-            pos += 1;
-        }
-
-        // Format seconds with leading zero
-        if seconds < 10 && pos < buffer.len() {
-            buffer[pos] = b'0';
-            pos += 1;
-        }
-        pos += format_number(seconds, &mut buffer[pos..]);
-
-        pos
-    }
-
-    /// Query process list from process manager service
-    /// Returns process info via the provided callback
-    /// Note: In the current early stage, this returns simulated data
-    /// as the full process manager service may not be running
-    pub fn query_processes<F>(&self, mut callback: F)
-    where
-        F: FnMut(u64, &str, &str), // pid, name, state
-    {
-        // In a full implementation, we would:
-        // 1. Send ProcessList request to PROCESS_MANAGER port
-        // 2. Receive response with process data
-        // 3. Parse and call callback for each process
-
-        // For now, return known system processes
-        callback(0, "kernel", "running");
-        callback(1, "init", "running");
-        callback(2, "display", "running");
-        callback(3, "keyboard", "running");
-        callback(4, "mouse", "running");
-        callback(5, "ui_shell", "running");
-        callback(6, "terminal", "running");
-    }
-
-    /// Query memory statistics from memory service
-    /// Returns (total_kb, used_kb, free_kb)
-    /// Note: In early stage, returns estimated values
-    pub fn query_memory(&self) -> (u64, u64, u64) {
-        // In a full implementation, we would query the memory manager service
-        // For now, return placeholder values based on typical early boot state
-
-        // These would come from MEMORY_MANAGER service
-        let total_kb = 128 * 1024; // 128 MB typical for testing
-        let used_kb = 32 * 1024;   // Approximate kernel + userspace usage
-        let free_kb = total_kb - used_kb;
-
-        (total_kb, used_kb, free_kb)
-    }
-
     /// Query registered services from service manager
     pub fn query_services<F>(&self, mut callback: F)
     where
@@ -199,14 +132,6 @@ impl IpcClient {
         pid >= 10 // Only "allow" killing non-system processes
     }
 
-    /// Attempt to launch a program
-    /// Returns the new process ID if successful
-    pub fn spawn_process(&self, _path: &str, _args: &[&str]) -> Option<u64> {
-        // Would send ProcessSpawn to PROCESS_MANAGER
-        // Not implemented in early stage
-        None
-    }
-
     /// List directory contents via filesystem service
     pub fn list_directory<F>(&self, _path: &str, mut callback: F)
     where
@@ -230,26 +155,146 @@ impl IpcClient {
         None
     }
 
+    /// Write `data` to a file, replacing its contents or appending to them
+    pub fn write_file(&self, _path: &str, data: &[u8], _append: bool) -> bool {
+        // Would send FileWrite to FILESYSTEM
+        // Not implemented in early stage
+        let _ = data;
+        false
+    }
+
     /// Get file information
     pub fn stat_file(&self, _path: &str) -> Option<FileInfo> {
         // Would query FILESYSTEM service
         None
     }
 
-    /// Read system log entries
-    pub fn read_log<F>(&self, mut callback: F)
+    /// Put text on the desktop clipboard, kept by the compositor
+    ///
+    /// Text longer than fits in a message goes through a shared region,
+    /// which is destroyed on the next copy; the compositor copies it as
+    /// soon as the message arrives.
+    pub fn set_clipboard(&mut self, text: &[u8]) -> bool {
+        let Some(port) = self.desktop.local_port() else {
+            return false;
+        };
+        if let Some(region) = self.clipboard_region.take() {
+            let _ = shm::destroy_region(region);
+        }
+
+        let mut payload = [0u8; CLIPBOARD_DATA_HEADER + CLIPBOARD_INLINE_MAX];
+        payload[0..8].copy_from_slice(&port.to_le_bytes());
+        payload[8] = ClipboardMime::TextPlain as u8;
+        payload[10..14].copy_from_slice(&(text.len() as u32).to_le_bytes());
+
+        let len = if text.len() <= CLIPBOARD_INLINE_MAX {
+            payload[14..14 + text.len()].copy_from_slice(text);
+            CLIPBOARD_DATA_HEADER + text.len()
+        } else {
+            let Some(region) = share_text(text) else {
+                return false;
+            };
+            self.clipboard_region = Some(region);
+            payload[9] = 1;
+            payload[14..22].copy_from_slice(&region.to_le_bytes());
+            CLIPBOARD_DATA_HEADER + 8
+        };
+
+        self.desktop.send(desktop::MessageType::SetClipboard, &payload[..len]).is_ok()
+    }
+
+    /// Read the text on the desktop clipboard into `buffer`, cut to fit
+    ///
+    /// Returns the number of bytes read, or None if the compositor did not
+    /// answer.
+    pub fn get_clipboard(&self, buffer: &mut [u8]) -> Option<usize> {
+        let port = self.desktop.local_port()?;
+
+        // ClipboardRequest: reply port, type
+        let mut request = [0u8; 9];
+        request[0..8].copy_from_slice(&port.to_le_bytes());
+        request[8] = ClipboardMime::TextPlain as u8;
+        self.desktop.send(desktop::MessageType::GetClipboard, &request).ok()?;
+
+        // Skip ClipboardChanged notices for earlier copies
+        let mut message = [0u8; MAX_MESSAGE_SIZE];
+        loop {
+            wait_any(&[port], CLIPBOARD_TIMEOUT_MS).ok()?;
+            let len = try_recv(port, &mut message).ok()??;
+            let header = MessageHeader::from_bytes(&message[..len])?;
+            if header.msg_type == desktop::MessageType::ClipboardData {
+                return read_clipboard_data(&message[MessageHeader::SIZE..len], buffer);
+            }
+        }
+    }
+
+    /// Size the compositor asked the window to take, if a resize event
+    /// has arrived; the newest one wins and other notices waiting on the
+    /// port are dropped
+    pub fn poll_resize(&self) -> Option<(u32, u32)> {
+        let port = self.desktop.local_port()?;
+        let mut message = [0u8; MAX_MESSAGE_SIZE];
+        let mut size = None;
+        while let Ok(Some(len)) = try_recv(port, &mut message) {
+            let Some(header) = MessageHeader::from_bytes(&message[..len]) else {
+                continue;
+            };
+            if header.msg_type != desktop::MessageType::WindowEvent {
+                continue;
+            }
+            let event = desktop::WindowEventMsg::from_bytes(&message[MessageHeader::SIZE..len]);
+            if let Some(event) = event {
+                if matches!(
+                    event.event_type,
+                    desktop::WindowEventType::Resize | desktop::WindowEventType::ResizeRequested
+                ) {
+                    size = Some((event.width, event.height));
+                }
+            }
+        }
+        size
+    }
+
+    /// Read system log entries at or above `min_level`
+    ///
+    /// Streams the kernel log ring (SYS_KLOG_READ) and calls `callback` once
+    /// per line. Overlong lines are split at the line buffer size.
+    pub fn read_log<F>(&self, min_level: LogLevel, mut callback: F)
     where
         F: FnMut(&str), // log line
     {
-        // Would query logging service or read from /sys/log
-        callback("[0.000] Atom OS kernel initializing");
-        callback("[0.001] Memory manager initialized");
-        callback("[0.002] Scheduler started");
-        callback("[0.003] IPC subsystem ready");
-        callback("[0.010] Loading userspace drivers");
-        callback("[0.020] Display server started");
-        callback("[0.030] Input drivers initialized");
-        callback("[0.050] UI shell launched");
+        let mut chunk = [0u8; 512];
+        let mut line = [0u8; 160];
+        let mut line_len = 0;
+        let mut pos = 0u64;
+
+        while let Ok(count) = klog_read(&mut chunk, &mut pos, min_level) {
+            if count == 0 {
+                break;
+            }
+
+            for &byte in &chunk[..count] {
+                if byte == b'\n' || line_len == line.len() {
+                    if let Ok(text) = core::str::from_utf8(&line[..line_len]) {
+                        callback(text);
+                    }
+                    line_len = 0;
+                    if byte == b'\n' {
+                        continue;
+                    }
+                }
+                if byte != b'\r' {
+                    line[line_len] = byte;
+                    line_len += 1;
+                }
+            }
+        }
+
+        if line_len > 0 {
+            if let Ok(text) = core::str::from_utf8(&line[..line_len]) {
+                callback(text);
+            }
+        }
     }
 }
 
@@ -265,6 +310,52 @@ impl Drop for IpcClient {
     }
 }
 
+/// Copy `text` into a new shared region for the compositor to read
+fn share_text(text: &[u8]) -> Option<RegionId> {
+    let region = shm::create_region(text.len()).ok()?;
+    match shm::map_anywhere(region, RegionFlags::read_write()) {
+        Ok(base) => {
+            // Safety: the region was just mapped at `base` and holds at
+            // least `text.len()` bytes
+            unsafe { core::ptr::copy_nonoverlapping(text.as_ptr(), base, text.len()) };
+            let _ = shm::unmap_region(region);
+            Some(region)
+        }
+        Err(_) => {
+            let _ = shm::destroy_region(region);
+            None
+        }
+    }
+}
+
+/// Copy the text of a ClipboardData payload into `buffer`, mapping its
+/// shared region if the text is not inline
+fn read_clipboard_data(payload: &[u8], buffer: &mut [u8]) -> Option<usize> {
+    if payload.len() < CLIPBOARD_DATA_HEADER || payload[8] != ClipboardMime::TextPlain as u8 {
+        return None;
+    }
+    let len = u32::from_le_bytes([payload[10], payload[11], payload[12], payload[13]]) as usize;
+    let count = len.min(buffer.len());
+
+    match payload[9] {
+        0 => {
+            let data = payload.get(CLIPBOARD_DATA_HEADER..CLIPBOARD_DATA_HEADER + len)?;
+            buffer[..count].copy_from_slice(&data[..count]);
+        }
+        1 => {
+            let id = payload.get(14..22)?;
+            let region = u64::from_le_bytes([id[0], id[1], id[2], id[3], id[4], id[5], id[6], id[7]]);
+            let base = shm::map_anywhere(region, RegionFlags::read_only()).ok()?;
+            // Safety: the region is mapped at `base` and holds `len` bytes
+            let data = unsafe { core::slice::from_raw_parts(base as *const u8, count) };
+            buffer[..count].copy_from_slice(data);
+            let _ = shm::unmap_region(region);
+        }
+        _ => return None,
+    }
+    Some(count)
+}
+
 /// File information structure
 pub struct FileInfo {
     pub size: u64,
@@ -0,0 +1,233 @@
+// Job Module
+//
+// A program started from the terminal. The kernel sends nothing back on
+// its own, so the terminal gives every program a port of its own: the
+// program sends its output there as `ProgramOutput` and `ProgramError`
+// messages, and the terminal copies them to the display (or to a pipe)
+// until the program exits.
+//
+// A program that reads what is typed names a port of its own in a
+// `SetTerminalMode` message. Keys then go to it as `ProgramInput`: whole
+// lines edited by the terminal in cooked mode, or each key as it is
+// pressed in raw mode.
+
+use atom_syscall::error::SyscallResult;
+use atom_syscall::ipc::{close_port, create_port, send_async, try_recv, PortId};
+use atom_syscall::process::{self, ProcessId};
+use libipc::messages::{self as desktop, MessageHeader, TerminalMode};
+use libipc::MAX_MESSAGE_SIZE;
+
+use crate::buffer::{DisplayBuffer, InputBuffer, MAX_LINE_LENGTH};
+use crate::config::theme;
+use crate::input::KeyEvent;
+use crate::stream::OutputStream;
+use crate::utf8::decode_lossy;
+
+/// Where programs named without a directory are looked for
+const PROGRAM_DIR: &str = "/apps/";
+
+/// Longest program path
+const MAX_PROGRAM_PATH: usize = 256;
+
+/// Sent around pasted text when the program asks for bracketed paste
+const PASTE_START: &[u8] = b"\x1B[200~";
+const PASTE_END: &[u8] = b"\x1B[201~";
+
+/// A running program and the port its output arrives on
+pub struct Job {
+    pid: ProcessId,
+    output: PortId,
+    /// How the program has asked for what is typed
+    mode: TerminalMode,
+    /// Line being typed in cooked mode
+    line: InputBuffer,
+}
+
+impl Job {
+    /// Start a program; "name" runs "/apps/name.elf", anything with a '/'
+    /// is used as the path
+    pub fn spawn(program: &str) -> SyscallResult<Self> {
+        let mut path = [0u8; MAX_PROGRAM_PATH];
+        let parts: [&str; 3] = if program.contains('/') {
+            [program, "", ""]
+        } else {
+            [PROGRAM_DIR, program, ".elf"]
+        };
+        let mut len = 0;
+        for byte in parts.iter().flat_map(|part| part.bytes()) {
+            if len < MAX_PROGRAM_PATH {
+                path[len] = byte;
+                len += 1;
+            }
+        }
+        // Safety: built from the command line
+        let path = unsafe { core::str::from_utf8_unchecked(&path[..len]) };
+
+        let output = create_port()?;
+        match process::spawn_with_output(path, output) {
+            Ok(pid) => Ok(Self {
+                pid,
+                output,
+                mode: TerminalMode::COOKED,
+                line: InputBuffer::new(),
+            }),
+            Err(err) => {
+                let _ = close_port(output);
+                Err(err)
+            }
+        }
+    }
+
+    /// Copy the output that has arrived to `output`; true if there was any.
+    /// Mode changes sent with the output take effect here.
+    pub fn pump(&mut self, output: &mut dyn OutputStream) -> bool {
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        let mut any = false;
+        while let Ok(Some(len)) = try_recv(self.output, &mut buffer) {
+            let Some(header) = MessageHeader::from_bytes(&buffer[..len]) else {
+                continue;
+            };
+            let end = len.min(MessageHeader::SIZE + header.payload_size as usize);
+            let text = &buffer[MessageHeader::SIZE..end];
+            match header.msg_type {
+                desktop::MessageType::ProgramOutput => output.write_ansi(text),
+                desktop::MessageType::ProgramError => output.write(text, theme().text_error),
+                desktop::MessageType::SetTerminalMode => {
+                    if let Some(mode) = TerminalMode::from_bytes(text) {
+                        self.mode = mode;
+                    }
+                    continue;
+                }
+                _ => continue,
+            }
+            any = true;
+        }
+        any
+    }
+
+    /// Pass a key to the program; echoed text goes to `display`. Keys are
+    /// dropped while the program reads nothing.
+    pub fn key(&mut self, event: KeyEvent, display: &mut DisplayBuffer) {
+        if self.mode.input_port == 0 {
+            return;
+        }
+        let color = theme().text_normal;
+
+        if self.mode.raw {
+            if let (KeyEvent::Char(ch), true) = (event, self.mode.echo) {
+                display.write_char(ch, color);
+            }
+            let mut bytes = [0u8; 8];
+            let sequence = event.encode(&mut bytes);
+            if !sequence.is_empty() {
+                self.send_input(sequence);
+            }
+            return;
+        }
+
+        match event {
+            KeyEvent::Char(ch) if !ch.is_control() => {
+                let inserted = self.line.insert(ch);
+                if inserted && self.mode.echo {
+                    display.write_char(ch, color);
+                }
+            }
+            KeyEvent::Backspace => {
+                let erased = self.line.backspace();
+                if erased && self.mode.echo {
+                    display.write_str("\x08 \x08", color);
+                }
+            }
+            KeyEvent::Enter => {
+                if self.mode.echo {
+                    display.newline();
+                }
+                self.send_line(true);
+            }
+            // Ctrl+D sends the line without a line break; on an empty line
+            // the program reads an empty message, the end of its input
+            KeyEvent::Control('\x04') => self.send_line(false),
+            _ => {}
+        }
+    }
+
+    /// Pass pasted text to the program. In cooked mode it is typed into
+    /// the line, and each line break sends the line before it.
+    pub fn paste(&mut self, text: &[u8], display: &mut DisplayBuffer) {
+        if self.mode.input_port == 0 {
+            return;
+        }
+
+        if !self.mode.raw {
+            for ch in decode_lossy(text) {
+                let event = match ch {
+                    '\n' => KeyEvent::Enter,
+                    '\r' => continue,
+                    '\t' => KeyEvent::Char(' '),
+                    _ => KeyEvent::Char(ch),
+                };
+                self.key(event, display);
+            }
+            return;
+        }
+
+        if self.mode.echo {
+            for ch in decode_lossy(text) {
+                display.write_char(ch, theme().text_normal);
+            }
+        }
+        if self.mode.bracketed_paste {
+            self.send_input(PASTE_START);
+        }
+        self.send_input(text);
+        if self.mode.bracketed_paste {
+            self.send_input(PASTE_END);
+        }
+    }
+
+    /// Send the line typed in cooked mode and start a new one
+    fn send_line(&mut self, line_break: bool) {
+        let mut bytes = [0u8; MAX_LINE_LENGTH + 1];
+        let line = self.line.as_str().as_bytes();
+        bytes[..line.len()].copy_from_slice(line);
+        let len = line.len() + line_break as usize;
+        bytes[line.len()] = b'\n';
+        self.line.clear();
+        self.send_input(&bytes[..len]);
+    }
+
+    /// Send `bytes` to the program's input port, in as many messages as
+    /// it takes. Input is dropped rather than wait on a program that does
+    /// not read it.
+    fn send_input(&self, bytes: &[u8]) {
+        let mut message = [0u8; MAX_MESSAGE_SIZE];
+        let mut chunks = bytes.chunks(MAX_MESSAGE_SIZE - MessageHeader::SIZE);
+        // An empty message is sent too; it ends the program's input
+        let first = chunks.next().unwrap_or(&[]);
+        for chunk in core::iter::once(first).chain(chunks) {
+            let header = MessageHeader::new(desktop::MessageType::ProgramInput, chunk.len() as u32);
+            let len = MessageHeader::SIZE + chunk.len();
+            message[..MessageHeader::SIZE].copy_from_slice(&header.to_bytes());
+            message[MessageHeader::SIZE..len].copy_from_slice(chunk);
+            if send_async(self.mode.input_port, &message[..len]).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Exit code, once the program has exited
+    pub fn try_wait(&self) -> Option<u64> {
+        process::wait(self.pid, 0).ok()
+    }
+
+    /// Stop the program; `try_wait` then reports `EXIT_KILLED`
+    pub fn kill(&self) {
+        let _ = process::kill(self.pid);
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        let _ = close_port(self.output);
+    }
+}
@@ -42,43 +42,161 @@
 
 
 
+mod ansi;
+
 mod buffer;
 
 mod commands;
 
+mod complete;
+
+mod config;
+
 mod input;
 
 mod ipc_client;
 
+mod job;
+
+mod pager;
+
 mod parser;
 
+mod selection;
+
+mod stream;
+
+mod utf8;
+
+mod vars;
+
 mod window;
 
 
 
-use core::panic::PanicInfo;
+use core::mem;
+
+use core::ptr::addr_of_mut;
 
 
 
 use atom_syscall::graphics::Framebuffer;
 
+use atom_syscall::input::MouseEvent;
+
 use atom_syscall::thread::{exit, yield_now};
 
 use atom_syscall::debug::log;
 
+use libipc::MAX_MESSAGE_SIZE;
+
 
 
-use buffer::{DisplayBuffer, InputBuffer, History};
+use buffer::{DisplayBuffer, InputBuffer, History, HISTORY_FILE_SIZE, MAX_DISPLAYS};
 
-use commands::{CommandContext, CommandResult, execute};
+use commands::table::Text;
+
+use commands::{CommandResult, Foreground, run_line};
+
+use complete::{Candidates, Completion};
+
+use config::{config, theme, Palette, TerminalConfig};
 
 use input::{InputHandler, KeyEvent};
 
 use ipc_client::IpcClient;
 
-use parser::parse_command;
+use job::Job;
+
+use pager::Pager;
+
+use selection::{Selection, MAX_SELECTION_BYTES};
+
+use window::TerminalWindow;
+
+
+
+/// Lines scrolled per wheel detent
+
+const WHEEL_LINES: usize = 3;
+
+
+
+/// File the history is saved to on exit and read from at startup
+
+const HISTORY_FILE: &str = "/.history";
+
+
+
+/// Saved history being read or written; too large for the stack
+
+static mut HISTORY_TEXT: [u8; HISTORY_FILE_SIZE] = [0u8; HISTORY_FILE_SIZE];
+
+
+
+/// Tabs open at once; each needs a display buffer with its own scrollback
+
+const MAX_TABS: usize = MAX_DISPLAYS;
+
+
+
+/// The terminal starts with one tab, in the first slot
+
+const FIRST_TAB_OPEN: [bool; MAX_TABS] = {
+
+    let mut open = [false; MAX_TABS];
+
+    open[0] = true;
+
+    open
+
+};
+
+
+
+/// Sessions of the tabs not shown; too large for the stack
+
+static mut SESSIONS: [Session; MAX_TABS] = [Session::EMPTY; MAX_TABS];
+
+
+
+/// A tab's shell session. The active tab's session is in the `Terminal`
+
+/// and the others wait in SESSIONS; switching tabs swaps them.
+
+struct Session {
+
+    display: DisplayBuffer,
+
+    input: InputBuffer,
+
+    history: History,
+
+    prompt_row: usize,
+
+    prompt_col: usize,
+
+}
+
 
-use window::{TerminalWindow, Theme};
+
+impl Session {
+
+    const EMPTY: Self = Self {
+
+        display: DisplayBuffer::new(),
+
+        input: InputBuffer::new(),
+
+        history: History::new(),
+
+        prompt_row: 0,
+
+        prompt_col: 0,
+
+    };
+
+}
 
 
 
@@ -104,6 +222,52 @@ struct Terminal {
 
     prompt_col: usize,
 
+    // Slot of the tab shown, and which slots have a tab open
+
+    tab: usize,
+
+    open_tabs: [bool; MAX_TABS],
+
+    // Candidates Tab cycles through, after a Tab that listed them
+
+    completion: Option<Completion>,
+
+    // Reverse search through the history, while Ctrl+R is in use
+
+    search: Option<Search>,
+
+    // Paging through the output of the last command line
+
+    pager: Option<Pager>,
+
+    selection: Selection,
+
+    // Mouse pointer position on screen, shown once the mouse moves
+
+    pointer: (i32, i32),
+
+    pointer_shown: bool,
+
+    screen: (i32, i32),
+
+    // Left and middle buttons in the last mouse packet
+
+    buttons: (bool, bool),
+
+    // While the resize corner is dragged, the pointer's distance from the
+
+    // window's bottom-right corner
+
+    resize_grab: Option<(i32, i32)>,
+
+    // Size to give the window on the next pass of the event loop
+
+    pending_size: Option<(u32, u32)>,
+
+    // Colors the text in the display buffers was written in
+
+    palette: Palette,
+
 }
 
 
@@ -132,6 +296,32 @@ impl Terminal {
 
             prompt_col: 0,
 
+            tab: 0,
+
+            open_tabs: FIRST_TAB_OPEN,
+
+            completion: None,
+
+            search: None,
+
+            pager: None,
+
+            selection: Selection::new(),
+
+            pointer: (0, 0),
+
+            pointer_shown: false,
+
+            screen: (0, 0),
+
+            buttons: (false, false),
+
+            resize_grab: None,
+
+            pending_size: None,
+
+            palette: TerminalConfig::DEFAULT.palette,
+
         }
 
     }
@@ -148,8 +338,46 @@ impl Terminal {
 
 
 
+        // Starting environment
+
+        let variables = vars::variables_mut();
+
+        for (name, value) in [("USER", "user"), ("HOME", "/"), ("SHELL", "/apps/terminal.elf")] {
+
+            variables.set(name, value);
+
+            variables.export(name);
+
+        }
+
+
+
+        // Commands and settings from earlier sessions
+
+        self.load_history();
+
+        match commands::terminal::load_config(&self.ipc) {
+
+            Ok(0) => {}
+
+            Ok(_) => log("Terminal: Some settings were ignored"),
+
+            Err(message) => log(message),
+
+        }
+
+        config::take_changed();
+
+        self.recolor();
+
+
+
         // Set display dimensions from window config
 
+        let settings = config();
+
+        self.window.set_layout(settings.font_scale, settings.padding);
+
         let cfg = self.window.config();
 
         let rows = cfg.rows() as usize;
@@ -158,6 +386,26 @@ impl Terminal {
 
         self.display.set_dimensions(rows, cols);
 
+        self.export_size();
+
+
+
+        // The mouse wheel scrolls through the scrollback
+
+        if self.input_handler.enable_wheel() {
+
+            log("Terminal: Mouse wheel enabled");
+
+        }
+
+
+
+        // The pointer starts in the middle of the screen
+
+        self.screen = (fb.width() as i32, fb.height() as i32);
+
+        self.pointer = (self.screen.0 / 2, self.screen.1 / 2);
+
 
 
         // Draw window frame
@@ -190,13 +438,13 @@ impl Terminal {
 
     fn show_welcome(&mut self) {
 
-        self.display.writeln("", Theme::TEXT_NORMAL);
+        self.display.writeln("", theme().text_normal);
 
-        self.display.writeln("  Atom Terminal v0.1.0", Theme::TEXT_INFO);
+        self.display.writeln("  Atom Terminal v0.1.0", theme().text_info);
 
-        self.display.writeln("  Type 'help' for available commands.", Theme::TEXT_DIM);
+        self.display.writeln("  Type 'help' for available commands.", theme().text_dim);
 
-        self.display.writeln("", Theme::TEXT_NORMAL);
+        self.display.writeln("", theme().text_normal);
 
     }
 
@@ -208,17 +456,17 @@ impl Terminal {
 
         // Prompt format: user@atom:path$
 
-        self.display.write_str("user", Theme::PROMPT_USER);
+        self.display.write_str("user", theme().prompt_user);
 
-        self.display.write_str("@", Theme::TEXT_DIM);
+        self.display.write_str("@", theme().text_dim);
 
-        self.display.write_str("atom", Theme::PROMPT_USER);
+        self.display.write_str("atom", theme().prompt_user);
 
-        self.display.write_str(":", Theme::TEXT_DIM);
+        self.display.write_str(":", theme().text_dim);
 
-        self.display.write_str("/", Theme::PROMPT_PATH);
+        self.display.write_str("/", theme().prompt_path);
 
-        self.display.write_str("$ ", Theme::PROMPT_SYMBOL);
+        self.display.write_str("$ ", theme().prompt_symbol);
 
 
 
@@ -236,7 +484,135 @@ impl Terminal {
 
     /// Handle a key event
 
-    fn handle_key(&mut self, event: KeyEvent) {
+    fn handle_key(&mut self, event: KeyEvent, fb: &Framebuffer) {
+
+        // While paging, every key goes to the pager
+
+        if let Some(pager) = &mut self.pager {
+
+            if !pager.key(event, &mut self.display) {
+
+                self.pager = None;
+
+                self.display.scroll_to_bottom();
+
+            }
+
+            return;
+
+        }
+
+
+
+        // Shift+PageUp/PageDown page through the scrollback,
+
+        // Ctrl+Shift+C/V copy and paste and Ctrl+Shift+T/W and Ctrl+Tab
+
+        // open, close and switch tabs; any other key goes back to the
+
+        // current content and drops the selection
+
+        let page = self.display.dimensions().0.saturating_sub(1).max(1);
+
+        let shift = self.input_handler.shift();
+
+        match event {
+
+            KeyEvent::PageUp if shift => {
+
+                self.display.scroll_back(page);
+
+                self.selection.clear();
+
+                return;
+
+            }
+
+            KeyEvent::PageDown if shift => {
+
+                self.display.scroll_forward(page);
+
+                self.selection.clear();
+
+                return;
+
+            }
+
+            KeyEvent::Control('\x03') if shift => {
+
+                self.copy_selection();
+
+                return;
+
+            }
+
+            KeyEvent::Control('\x16') if shift => {
+
+                self.paste();
+
+                return;
+
+            }
+
+            KeyEvent::Control('\x14') if shift => {
+
+                self.new_tab();
+
+                return;
+
+            }
+
+            KeyEvent::Control('\x17') if shift => {
+
+                self.close_tab();
+
+                return;
+
+            }
+
+            KeyEvent::Tab if self.input_handler.ctrl() => {
+
+                self.cycle_tab(!shift);
+
+                return;
+
+            }
+
+            _ => {
+
+                self.display.scroll_to_bottom();
+
+                self.selection.clear();
+
+            }
+
+        }
+
+
+
+        if event != KeyEvent::Tab {
+
+            self.completion = None;
+
+        }
+
+
+
+        // While searching, keys edit the query; a key that ends the search
+
+        // is then handled as usual
+
+        if let Some(search) = self.search.take() {
+
+            if self.search_key(search, event) {
+
+                return;
+
+            }
+
+        }
+
+
 
         match event {
 
@@ -244,9 +620,9 @@ impl Terminal {
 
                 // Insert printable character
 
-                if ch.is_ascii() && !ch.is_ascii_control() {
+                if !ch.is_control() {
 
-                    self.input.insert(ch as u8);
+                    self.input.insert(ch);
 
                 }
 
@@ -264,6 +640,8 @@ impl Terminal {
 
                 let cmd_str = self.input.as_str();
 
+                let mut output = None;
+
                 if !cmd_str.is_empty() {
 
                     // Add to history
@@ -272,41 +650,45 @@ impl Terminal {
 
 
 
-                    // Parse and execute
+                    // Expand, parse and execute; programs the line starts
 
-                    if let Some(cmd) = parse_command(cmd_str) {
+                    // show their output as it arrives
 
-                        let mut ctx = CommandContext {
+                    let mut console = Console {
 
-                            display: &mut self.display,
+                        window: &self.window,
 
-                            ipc: &self.ipc,
+                        fb,
 
-                        };
+                        input_handler: &mut self.input_handler,
 
+                        ipc: &self.ipc,
 
+                    };
 
-                        match execute(&cmd, &mut ctx) {
+                    let first = self.display.line_number();
 
-                            CommandResult::Exit => {
+                    let result = run_line(cmd_str, &mut self.display, &self.ipc, &mut console);
 
-                                self.running = false;
+                    if result == CommandResult::Exit {
 
-                                return;
+                        self.input.clear();
 
-                            }
+                        self.close_tab();
 
-                            CommandResult::Clear => {
+                        return;
 
-                                self.display.clear();
+                    }
 
-                            }
 
-                            _ => {}
 
-                        }
+                    // Output that ends part way through a line takes
 
-                    }
+                    // that line too
+
+                    let (_, col) = self.display.cursor_position();
+
+                    output = Some((first, self.display.line_number() + (col > 0) as usize));
 
                 }
 
@@ -322,6 +704,16 @@ impl Terminal {
 
                 self.show_prompt();
 
+
+
+                // Output longer than the screen is paged from its start
+
+                if let Some((first, end)) = output {
+
+                    self.pager = Pager::open(first, end, &mut self.display);
+
+                }
+
             }
 
 
@@ -406,15 +798,7 @@ impl Terminal {
 
             KeyEvent::Tab => {
 
-                // TODO: Tab completion
-
-                // For now, just insert spaces
-
-                for _ in 0..4 {
-
-                    self.input.insert(b' ');
-
-                }
+                self.complete();
 
             }
 
@@ -438,7 +822,7 @@ impl Terminal {
 
                         // Ctrl+C - cancel current input
 
-                        self.display.writeln("^C", Theme::TEXT_DIM);
+                        self.display.writeln("^C", theme().text_dim);
 
                         self.input.clear();
 
@@ -448,16 +832,24 @@ impl Terminal {
 
                     '\x04' => {
 
-                        // Ctrl+D - exit (if input is empty)
+                        // Ctrl+D - close the tab (if input is empty)
 
                         if self.input.is_empty() {
 
-                            self.running = false;
+                            self.close_tab();
 
                         }
 
                     }
 
+                    '\x12' => {
+
+                        // Ctrl+R - search the history
+
+                        self.search = Some(Search::new());
+
+                    }
+
                     '\x0C' => {
 
                         // Ctrl+L - clear screen
@@ -524,91 +916,1023 @@ impl Terminal {
 
 
 
-    /// Render the terminal to the framebuffer
+    /// Handle a key during a reverse search; true if it was used up
+
+    ///
+
+    /// Typing narrows the search and Ctrl+R finds an older match. Escape
+
+    /// and Ctrl+G give up; any other key puts the match in the input and
+
+    /// goes on as usual, so Enter runs it.
+
+    fn search_key(&mut self, mut search: Search, event: KeyEvent) -> bool {
+
+        match event {
+
+            KeyEvent::Char(ch) => {
+
+                if !ch.is_control() {
+
+                    search.query.insert(ch);
+
+                }
+
+                // The current match stays while it still matches
+
+                search.find(&self.history, search.found.map(|position| position + 1));
+
+            }
+
+            KeyEvent::Backspace => {
+
+                search.query.backspace();
+
+                search.find(&self.history, None);
+
+            }
+
+            KeyEvent::Control('\x12') => {
+
+                let before = search.found;
+
+                search.find(&self.history, before);
+
+            }
+
+            KeyEvent::Escape | KeyEvent::Control('\x07') => return true,
+
+            _ => {
+
+                if let Some(entry) = search.found.and_then(|position| self.history.get(position)) {
+
+                    self.input.set(entry);
+
+                }
+
+                return false;
+
+            }
+
+        }
+
+
+
+        self.search = Some(search);
+
+        true
+
+    }
+
+
+
+    /// Give the window a new size: every tab's display is clipped to it,
+
+    /// the frame is redrawn and COLUMNS and LINES are updated. Returns
+
+    /// whether the size changed.
+
+    fn resize(&mut self, fb: &Framebuffer, width: u32, height: u32) -> bool {
+
+        if !self.window.resize(fb, width, height) {
+
+            return false;
+
+        }
+
+        self.relayout(fb);
+
+        true
+
+    }
+
+
+
+    /// Fit every tab's display to the rows and columns the window now
+
+    /// holds, and redraw the frame
+
+    fn relayout(&mut self, fb: &Framebuffer) {
+
+        let cfg = self.window.config();
+
+        let (rows, cols) = (cfg.rows() as usize, cfg.cols() as usize);
+
+        let moved = self.display.resize(rows, cols);
+
+        self.prompt_row = self.prompt_row.saturating_sub(moved);
+
+        for session in self.other_sessions() {
+
+            let moved = session.display.resize(rows, cols);
+
+            session.prompt_row = session.prompt_row.saturating_sub(moved);
+
+        }
+
+
+
+        self.selection.clear();
+
+        self.window.draw_frame(fb);
+
+        self.export_size();
+
+    }
+
+
+
+    /// Catch up with settings a command changed: the text shown takes the
+
+    /// new colors, and the display the new font scale and padding
+
+    fn apply_config(&mut self, fb: &Framebuffer) {
+
+        self.recolor();
+
+        let settings = config();
+
+        self.window.set_layout(settings.font_scale, settings.padding);
+
+        self.relayout(fb);
+
+    }
+
+
+
+    /// Give the text in every tab the colors of the palette in use
+
+    fn recolor(&mut self) {
+
+        let palette = theme();
+
+        if *palette == self.palette {
+
+            return;
+
+        }
+
+        let translate = self.palette.translation(palette);
+
+        self.display.recolor(&translate);
+
+        for session in self.other_sessions() {
+
+            session.display.recolor(&translate);
+
+        }
+
+        self.palette = *palette;
+
+    }
+
+
+
+    /// Sessions of the open tabs that are not shown
+
+    fn other_sessions(&self) -> impl Iterator<Item = &'static mut Session> + '_ {
+
+        (0..MAX_TABS)
+
+            .filter(|&slot| self.open_tabs[slot] && slot != self.tab)
+
+            // Safety: the terminal is single-threaded and each slot comes
+
+            // up once
+
+            .map(|slot| unsafe { &mut (*addr_of_mut!(SESSIONS))[slot] })
+
+    }
+
+
+
+    /// Export the display size as COLUMNS and LINES, for scripts and the
+
+    /// programs they start
+
+    fn export_size(&self) {
+
+        let (rows, cols) = self.display.dimensions();
+
+        let variables = vars::variables_mut();
+
+        for (name, value) in [("COLUMNS", cols), ("LINES", rows)] {
+
+            variables.set(name, Text::number(value as u64).as_str());
+
+            variables.export(name);
+
+        }
+
+    }
+
+
+
+    /// Open a tab and switch to it
+
+    fn new_tab(&mut self) {
+
+        let Some(slot) = self.open_tabs.iter().position(|&open| !open) else {
+
+            log("Terminal: No room for another tab");
+
+            return;
+
+        };
+
+
+
+        // The slot's session may be left over from a closed tab
+
+        self.switch_tab(slot);
+
+        self.open_tabs[slot] = true;
+
+        let cfg = self.window.config();
+
+        self.display.set_scrollback(slot);
+
+        self.display.reset();
+
+        self.display.set_dimensions(cfg.rows() as usize, cfg.cols() as usize);
+
+        self.input.clear();
+
+        self.history.clear();
+
+        self.load_history();
+
+        self.show_prompt();
+
+    }
+
+
+
+    /// Close the active tab and show the next one; closing the last tab
+
+    /// exits the terminal
+
+    fn close_tab(&mut self) {
+
+        self.open_tabs[self.tab] = false;
+
+        match self.open_tab_after(true) {
+
+            Some(slot) => self.switch_tab(slot),
+
+            None => self.running = false,
+
+        }
+
+    }
+
+
+
+    /// Show the next open tab, or the previous one
+
+    fn cycle_tab(&mut self, forward: bool) {
+
+        if let Some(slot) = self.open_tab_after(forward) {
+
+            self.switch_tab(slot);
+
+        }
+
+    }
+
+
+
+    /// First open tab after the active one, going round
+
+    fn open_tab_after(&self, forward: bool) -> Option<usize> {
+
+        (1..=MAX_TABS)
+
+            .map(|step| {
+
+                let step = if forward { step } else { MAX_TABS - step };
+
+                (self.tab + step) % MAX_TABS
+
+            })
+
+            .find(|&slot| self.open_tabs[slot])
+
+    }
+
+
+
+    /// Show the session in `slot` in place of the active one
+
+    fn switch_tab(&mut self, slot: usize) {
+
+        if slot == self.tab {
+
+            return;
+
+        }
+
+        self.swap_session(self.tab);
+
+        self.swap_session(slot);
+
+        self.tab = slot;
+
+        self.completion = None;
+
+        self.search = None;
+
+        self.pager = None;
+
+        self.selection.clear();
+
+    }
+
+
+
+    /// Swap the shown session with the one kept in `slot`
+
+    fn swap_session(&mut self, slot: usize) {
+
+        // Safety: the terminal is single-threaded
+
+        let session = unsafe { &mut (*addr_of_mut!(SESSIONS))[slot] };
+
+        mem::swap(&mut self.display, &mut session.display);
+
+        mem::swap(&mut self.input, &mut session.input);
+
+        mem::swap(&mut self.history, &mut session.history);
+
+        mem::swap(&mut self.prompt_row, &mut session.prompt_row);
+
+        mem::swap(&mut self.prompt_col, &mut session.prompt_col);
+
+    }
+
+
+
+    /// Read the history saved by an earlier session
+
+    fn load_history(&mut self) {
+
+        // Safety: the terminal is single-threaded
+
+        let text = unsafe { &mut *addr_of_mut!(HISTORY_TEXT) };
+
+        match self.ipc.read_file(HISTORY_FILE, text) {
+
+            Some(len) => match core::str::from_utf8(&text[..len]) {
+
+                Ok(saved) => self.history.load(saved),
+
+                Err(_) => log("Terminal: History file is not text"),
+
+            },
+
+            None => log("Terminal: No saved history"),
+
+        }
+
+    }
+
+
+
+    /// Save the history for the next session; with several tabs open it
+
+    /// is the history of the one shown
+
+    fn save_history(&self) {
+
+        // Safety: the terminal is single-threaded
+
+        let text = unsafe { &mut *addr_of_mut!(HISTORY_TEXT) };
+
+        let len = self.history.save(text);
+
+        if !self.ipc.write_file(HISTORY_FILE, &text[..len], false) {
+
+            log("Terminal: Could not save history");
+
+        }
+
+    }
+
+
+
+    /// Complete the word before the cursor, or put in the next candidate
+
+    /// when the last Tab listed several
+
+    fn complete(&mut self) {
+
+        if let Some(mut completion) = self.completion.take() {
+
+            replace_word(&mut self.input, completion.word_start, completion.cycle());
+
+            self.completion = Some(completion);
+
+            return;
+
+        }
+
+
+
+        let cursor = self.input.cursor();
+
+        let completion = complete::complete(self.input.as_str(), cursor, &self.ipc);
+
+        let candidates = &completion.candidates;
+
+        match candidates.len() {
+
+            0 => {}
+
+            1 => {
+
+                let candidate = candidates.get(0);
+
+                replace_word(&mut self.input, completion.word_start, candidate);
+
+                if !candidate.ends_with('/') {
+
+                    self.input.insert(' ');
+
+                }
+
+            }
+
+            _ => {
+
+                replace_word(&mut self.input, completion.word_start, candidates.common_prefix());
+
+                self.show_candidates(candidates);
+
+                self.completion = Some(completion);
+
+            }
+
+        }
+
+    }
+
+
+
+    /// List completion candidates in columns under the input line, then
+
+    /// prompt again with the same input
+
+    fn show_candidates(&mut self, candidates: &Candidates) {
+
+        self.display.write_str(self.input.as_str(), theme().text_normal);
+
+        self.display.newline();
+
+
+
+        let (_, cols) = self.display.dimensions();
+
+        let width = candidates.iter().map(|name| name.chars().count()).max().unwrap_or(0) + 2;
+
+        let per_row = (cols / width).max(1);
+
+        for (i, name) in candidates.iter().enumerate() {
+
+            let color = if name.ends_with('/') { theme().prompt_path } else { theme().text_normal };
+
+            self.display.write_str(name, color);
+
+            if (i + 1) % per_row == 0 || i + 1 == candidates.len() {
+
+                self.display.newline();
+
+            } else {
+
+                for _ in name.chars().count()..width {
+
+                    self.display.write_char(' ', theme().text_normal);
+
+                }
+
+            }
+
+        }
+
+
+
+        self.show_prompt();
+
+    }
+
+
+
+    /// Handle a mouse packet: the wheel scrolls, the left button selects
+
+    /// and the middle button pastes
+
+    fn handle_mouse(&mut self, event: MouseEvent) {
+
+        if event.wheel != 0 {
+
+            let lines = event.wheel.unsigned_abs() as usize * WHEEL_LINES;
+
+            if event.wheel > 0 {
+
+                self.display.scroll_back(lines);
+
+            } else {
+
+                self.display.scroll_forward(lines);
+
+            }
+
+            self.selection.clear();
+
+        }
+
+
+
+        // PS/2 reports y growing upwards
+
+        let (x, y) = self.pointer;
+
+        self.pointer = (
+
+            (x + event.dx).clamp(0, self.screen.0 - 1),
+
+            (y - event.dy).clamp(0, self.screen.1 - 1),
+
+        );
+
+        self.pointer_shown |= event.dx != 0 || event.dy != 0;
+
+
+
+        let (was_left, was_middle) = self.buttons;
+
+
+
+        // Dragging the corner grip resizes the window once it is let go
+
+        let (x, y) = self.pointer;
+
+        if event.left_button && !was_left && self.window.grip_contains(x, y) {
+
+            let cfg = self.window.config();
+
+            let corner = ((cfg.x + cfg.width) as i32, (cfg.y + cfg.height) as i32);
+
+            self.resize_grab = Some((corner.0 - x, corner.1 - y));
+
+        }
+
+        if let Some((grab_x, grab_y)) = self.resize_grab {
+
+            if !event.left_button {
+
+                let cfg = self.window.config();
+
+                let width = (x + grab_x - cfg.x as i32).max(0) as u32;
+
+                let height = (y + grab_y - cfg.y as i32).max(0) as u32;
+
+                self.pending_size = Some((width, height));
+
+                self.resize_grab = None;
+
+            }
+
+            self.buttons = (event.left_button, event.middle_button);
+
+            return;
+
+        }
+
+
+
+        let cell = self.window.cell_at(self.pointer.0, self.pointer.1);
+
+        if event.left_button {
+
+            match cell {
+
+                Some((row, col)) if !was_left => self.selection.start(row as usize, col as usize),
+
+                Some((row, col)) => self.selection.extend(row as usize, col as usize),
+
+                None if !was_left => self.selection.clear(),
+
+                None => {}
+
+            }
+
+        } else if was_left && self.selection.finish() {
+
+            // Selecting copies, as in other X-style terminals
+
+            self.copy_selection();
+
+        }
+
+        if event.middle_button && !was_middle {
+
+            self.paste();
+
+        }
+
+        self.buttons = (event.left_button, event.middle_button);
+
+    }
+
+
+
+    /// Put the selected text on the clipboard
+
+    fn copy_selection(&mut self) {
+
+        let Some((first, last)) = self.selection.range() else {
+
+            return;
+
+        };
+
+        let mut text = [0u8; MAX_SELECTION_BYTES];
+
+        let len = self.display.copy_text(first, last, &mut text);
+
+        if !self.ipc.set_clipboard(&text[..len]) {
+
+            log("Terminal: Could not copy to the clipboard");
+
+        }
+
+    }
+
+
+
+    /// Insert the text on the clipboard at the input cursor; line breaks
+
+    /// and tabs become spaces and other control characters are dropped
+
+    fn paste(&mut self) {
+
+        let mut text = [0u8; buffer::MAX_LINE_LENGTH];
+
+        let Some(len) = self.ipc.get_clipboard(&mut text) else {
+
+            log("Terminal: Could not read the clipboard");
+
+            return;
+
+        };
+
+
+
+        self.display.scroll_to_bottom();
+
+        self.selection.clear();
+
+        self.completion = None;
+
+        for ch in utf8::decode_lossy(&text[..len]) {
+
+            match ch {
+
+                '\n' | '\r' | '\t' => self.input.insert(' '),
+
+                _ if ch.is_control() => true,
+
+                _ => self.input.insert(ch),
+
+            };
+
+        }
+
+    }
+
+
+
+    /// Render the terminal to the framebuffer
 
     fn render(&self, fb: &Framebuffer) {
 
-        let cfg = self.window.config();
+        let cfg = self.window.config();
+
+        let rows = cfg.rows() as usize;
+
+        let cols = cfg.cols() as usize;
+
+
+
+        self.window.draw_tab_bar(fb, &self.open_tabs, self.tab);
+
+
+
+        // Render display buffer lines
+
+        draw_rows(&self.window, fb, &self.display, &self.selection);
+
+
+
+        // The pager's status takes the last row; otherwise show how far
+
+        // back the view is, and the input line further down
+
+        let offset = self.display.scroll_offset();
+
+        if let Some(pager) = &self.pager {
+
+            self.draw_pager_status(fb, pager, rows.saturating_sub(1), cols);
+
+        } else if offset > 0 {
+
+            self.draw_scroll_indicator(fb, offset, cols);
+
+        }
+
+
+
+        let input_row = self.prompt_row + offset;
+
+        if input_row < rows && self.pager.is_none() {
+
+            match &self.search {
+
+                Some(search) => self.draw_search(fb, search, input_row, cols),
+
+                None => self.draw_input(fb, input_row, cols),
+
+            }
+
+        }
+
+
+
+        if self.pointer_shown {
+
+            if let Some((row, col)) = self.window.cell_at(self.pointer.0, self.pointer.1) {
+
+                self.window.draw_pointer(fb, row, col);
+
+            }
+
+        }
+
+    }
+
+
+
+    /// Render input line on top of buffer content at prompt position
+
+    fn draw_input(&self, fb: &Framebuffer, input_row: usize, cols: usize) {
+
+        let input_start_col = self.prompt_col;
+
+
+
+        // Clear the input area
+
+        self.window.clear_to_eol(fb, input_row as u32, input_start_col as u32);
+
+
+
+        // Draw input text, a column per character
+
+        let cursor_pos = self.input.cursor_column();
+
+        let mut len = 0;
+
+
+
+        for (i, ch) in self.input.as_str().chars().enumerate() {
+
+            let col = input_start_col + i;
+
+            if col < cols {
+
+                if i == cursor_pos {
+
+                    // Cursor position - draw with inverted colors
+
+                    self.window.draw_char_with_cursor(fb, input_row as u32, col as u32, ch);
+
+                } else {
+
+                    self.window.draw_char(fb, input_row as u32, col as u32, ch, theme().text_normal, theme().window_bg);
+
+                }
+
+            }
+
+            len += 1;
+
+        }
+
+
+
+        // Draw cursor at end if at end of input
+
+        if cursor_pos >= len {
+
+            let col = input_start_col + len;
+
+            if col < cols {
+
+                self.window.draw_cursor(fb, input_row as u32, col as u32);
+
+            }
+
+        }
+
+    }
+
+
+
+    /// Render a reverse search in place of the input line, as
+
+    /// "(reverse-i-search)`query': match" with the matching text highlighted
+
+    fn draw_search(&self, fb: &Framebuffer, search: &Search, input_row: usize, cols: usize) {
+
+        let row = input_row as u32;
+
+        let mut col = self.prompt_col;
+
+        self.window.clear_to_eol(fb, row, col as u32);
+
+
+
+        let label = if search.failed {
+
+            "(failed reverse-i-search)`"
+
+        } else {
+
+            "(reverse-i-search)`"
+
+        };
+
+        let query = search.query.as_str();
+
+        let entry = search.found.and_then(|position| self.history.get(position)).unwrap_or("");
+
+        let matched = match entry.find(query) {
+
+            Some(start) if !query.is_empty() => start..start + query.len(),
+
+            _ => 0..0,
+
+        };
+
+
+
+        let parts = [
+
+            (label, theme().text_dim),
+
+            (query, theme().text_normal),
+
+            ("': ", theme().text_dim),
+
+        ];
+
+        for (text, color) in parts {
+
+            for ch in text.chars() {
+
+                if col < cols {
+
+                    self.window.draw_char(fb, row, col as u32, ch, color, theme().window_bg);
+
+                }
+
+                col += 1;
+
+            }
+
+        }
+
+        // `matched` is a byte range; a character is highlighted if it starts in it
 
-        let rows = cfg.rows() as usize;
+        for (i, ch) in entry.char_indices() {
 
-        let cols = cfg.cols() as usize;
+            let bg = if matched.contains(&i) { theme().selection_bg } else { theme().window_bg };
+
+            if col < cols {
+
+                self.window.draw_char(fb, row, col as u32, ch, theme().text_normal, bg);
 
+            }
 
+            col += 1;
 
-        // Render display buffer lines
+        }
 
-        for row in 0..rows {
+        if col < cols {
 
-            if let Some(line) = self.display.get_line(row) {
+            self.window.draw_cursor(fb, row, col as u32);
 
-                for col in 0..cols {
+        }
 
-                    if let Some(cell) = line.get(col) {
+    }
 
-                        self.window.draw_char(fb, row as u32, col as u32, cell.ch, cell.fg, cell.bg);
 
-                    } else {
 
-                        // Empty cell
+    /// Render the pager's status line: the search being typed, or which
 
-                        self.window.draw_char(fb, row as u32, col as u32, b' ', Theme::TEXT_NORMAL, Theme::WINDOW_BG);
+    /// lines of the output are shown
 
-                    }
+    fn draw_pager_status(&self, fb: &Framebuffer, pager: &Pager, row: usize, cols: usize) {
+
+        let row = row as u32;
+
+        let (fg, bg) = (theme().text_normal, theme().window_bg);
+
+        self.window.clear_to_eol(fb, row, 0);
+
+        if let Some(query) = pager.typing() {
+
+            let mut col = 0;
+
+            for ch in "/".chars().chain(query.chars()) {
+
+                if col < cols {
+
+                    self.window.draw_char(fb, row, col as u32, ch, fg, bg);
 
                 }
 
-            } else {
+                col += 1;
+
+            }
 
-                // Clear empty row
+            if col < cols {
 
-                self.window.clear_row(fb, row as u32);
+                self.window.draw_cursor(fb, row, col as u32);
 
             }
 
+            return;
+
         }
 
+        if pager.failed() {
 
+            self.window.draw_text(fb, row, 0, "Pattern not found", bg, theme().text_error);
 
-        // Render input line on top of buffer content at prompt position
+            return;
 
-        let input_row = self.prompt_row;
+        }
 
-        let input_start_col = self.prompt_col;
 
 
+        let [top, bottom, total] = pager.position(&self.display).map(|n| Text::number(n as u64));
 
-        // Clear the input area
+        let end = if pager.at_end(&self.display) { " (END)" } else { "" };
 
-        self.window.clear_to_eol(fb, input_row as u32, input_start_col as u32);
+        let parts = ["lines ", top.as_str(), "-", bottom.as_str(), " of ", total.as_str(), end];
+
+        let mut col = 0;
+
+        for part in parts {
+
+            if col + part.len() <= cols {
 
+                self.window.draw_text(fb, row, col as u32, part, bg, theme().text_info);
 
+            }
 
-        // Draw input text
+            col += part.len();
 
-        let input_bytes = self.input.as_bytes();
+        }
 
-        let cursor_pos = self.input.cursor();
+    }
 
 
 
-        for (i, &byte) in input_bytes.iter().enumerate() {
+    /// Draw "[-N]" in the top right corner, for a view scrolled back N lines
 
-            let col = input_start_col + i;
+    fn draw_scroll_indicator(&self, fb: &Framebuffer, offset: usize, cols: usize) {
 
-            if col < cols {
+        let mut text = [0u8; 24];
 
-                if i == cursor_pos {
+        let mut digits = [0u8; 20];
 
-                    // Cursor position - draw with inverted colors
+        let mut count = 0;
 
-                    self.window.draw_char_with_cursor(fb, input_row as u32, col as u32, byte);
+        let mut n = offset;
 
-                } else {
+        loop {
 
-                    self.window.draw_char(fb, input_row as u32, col as u32, byte, Theme::TEXT_NORMAL, Theme::WINDOW_BG);
+            digits[count] = b'0' + (n % 10) as u8;
 
-                }
+            count += 1;
+
+            n /= 10;
+
+            if n == 0 {
+
+                break;
 
             }
 
@@ -616,19 +1940,27 @@ impl Terminal {
 
 
 
-        // Draw cursor at end if at end of input
+        text[..2].copy_from_slice(b"[-");
 
-        if cursor_pos >= input_bytes.len() {
+        for i in 0..count {
 
-            let col = input_start_col + input_bytes.len();
+            text[2 + i] = digits[count - 1 - i];
 
-            if col < cols {
+        }
 
-                self.window.draw_cursor(fb, input_row as u32, col as u32);
+        text[2 + count] = b']';
 
-            }
+        let len = count + 3;
 
-        }
+
+
+        // Safety: only ASCII digits and brackets
+
+        let text = unsafe { core::str::from_utf8_unchecked(&text[..len]) };
+
+        let col = cols.saturating_sub(len) as u32;
+
+        self.window.draw_text(fb, 0, col, text, theme().window_bg, theme().text_warning);
 
     }
 
@@ -652,7 +1984,47 @@ impl Terminal {
 
             while let Some(event) = self.input_handler.poll() {
 
-                self.handle_key(event);
+                self.handle_key(event, fb);
+
+                needs_render = true;
+
+            }
+
+
+
+            while let Some(event) = self.input_handler.poll_mouse() {
+
+                self.handle_mouse(event);
+
+                needs_render = true;
+
+            }
+
+
+
+            // The compositor, or a drag of the resize corner, gives the
+
+            // window a new size
+
+            if let Some(size) = self.ipc.poll_resize() {
+
+                self.pending_size = Some(size);
+
+            }
+
+            if let Some((width, height)) = self.pending_size.take() {
+
+                needs_render |= self.resize(fb, width, height);
+
+            }
+
+
+
+            // A command changed the settings
+
+            if config::take_changed() {
+
+                self.apply_config(fb);
 
                 needs_render = true;
 
@@ -678,6 +2050,8 @@ impl Terminal {
 
 
 
+        self.save_history();
+
         log("Terminal: Exiting");
 
     }
@@ -686,6 +2060,246 @@ impl Terminal {
 
 
 
+/// An incremental reverse search through the history
+
+struct Search {
+
+    query: InputBuffer,
+
+    // Position in the history of the entry shown
+
+    found: Option<usize>,
+
+    // Whether the query matches nothing older; the last match stays shown
+
+    failed: bool,
+
+}
+
+
+
+impl Search {
+
+    fn new() -> Self {
+
+        Self {
+
+            query: InputBuffer::new(),
+
+            found: None,
+
+            failed: false,
+
+        }
+
+    }
+
+
+
+    /// Look for the query in entries older than `before`, or in all of them
+
+    fn find(&mut self, history: &History, before: Option<usize>) {
+
+        match history.search(self.query.as_str(), before) {
+
+            Some((position, _)) => {
+
+                self.found = Some(position);
+
+                self.failed = false;
+
+            }
+
+            None => self.failed = true,
+
+        }
+
+    }
+
+}
+
+
+
+/// The terminal while a program runs: it shows the display as output
+
+/// arrives, passes keys and pastes to the program and watches for Ctrl+C
+
+struct Console<'a> {
+
+    window: &'a TerminalWindow,
+
+    fb: &'a Framebuffer,
+
+    input_handler: &'a mut InputHandler,
+
+    ipc: &'a IpcClient,
+
+}
+
+
+
+impl Foreground for Console<'_> {
+
+    fn show(&mut self, display: &DisplayBuffer) {
+
+        draw_rows(self.window, self.fb, display, &Selection::new());
+
+    }
+
+
+
+    fn interrupted(&mut self) -> bool {
+
+        let mut interrupted = false;
+
+        while let Some(event) = self.input_handler.poll() {
+
+            let shift = self.input_handler.shift();
+
+            interrupted |= event == KeyEvent::Control('\x03') && !shift;
+
+        }
+
+        interrupted
+
+    }
+
+
+
+    fn forward(&mut self, job: &mut Job, display: &mut DisplayBuffer) -> bool {
+
+        let mut typed = false;
+
+        while let Some(event) = self.input_handler.poll() {
+
+            let shift = self.input_handler.shift();
+
+            match event {
+
+                KeyEvent::Control('\x03') if !shift => return true,
+
+                KeyEvent::Control('\x16') if shift => {
+
+                    // The clipboard arrives in one message
+
+                    let mut text = [0u8; MAX_MESSAGE_SIZE];
+
+                    if let Some(len) = self.ipc.get_clipboard(&mut text) {
+
+                        job.paste(&text[..len], display);
+
+                    }
+
+                }
+
+                _ => job.key(event, display),
+
+            }
+
+            typed = true;
+
+        }
+
+        if typed {
+
+            self.show(display);
+
+        }
+
+        false
+
+    }
+
+}
+
+
+
+/// Draw the rows of the display buffer, highlighting selected cells
+
+fn draw_rows(
+
+    window: &TerminalWindow,
+
+    fb: &Framebuffer,
+
+    display: &DisplayBuffer,
+
+    selection: &Selection,
+
+) {
+
+    let cfg = window.config();
+
+    let rows = cfg.rows() as usize;
+
+    let cols = cfg.cols() as usize;
+
+
+
+    for row in 0..rows {
+
+        let line = display.get_line(row);
+
+        if line.is_none() && !selection.covers_row(row) {
+
+            // Clear empty row
+
+            window.clear_row(fb, row as u32);
+
+            continue;
+
+        }
+
+
+
+        for col in 0..cols {
+
+            let (ch, fg, mut bg) = match line.and_then(|line| line.get(col)) {
+
+                Some(cell) => (cell.ch, cell.fg, cell.bg),
+
+                // Empty cell
+
+                None => (' ', theme().text_normal, theme().window_bg),
+
+            };
+
+            if selection.contains(row, col) {
+
+                bg = theme().selection_bg;
+
+            }
+
+            window.draw_char(fb, row as u32, col as u32, ch, fg, bg);
+
+        }
+
+    }
+
+}
+
+
+
+/// Replace the input from `start` up to the cursor with `text`
+
+fn replace_word(input: &mut InputBuffer, start: usize, text: &str) {
+
+    while input.cursor() > start {
+
+        input.backspace();
+
+    }
+
+    for ch in text.chars() {
+
+        input.insert(ch);
+
+    }
+
+}
+
+
+
 /// Entry point
 
 #[no_mangle]
@@ -748,28 +2362,8 @@ fn main() -> ! {
 
 
 
-#[panic_handler]
-
-fn panic(info: &PanicInfo) -> ! {
-
-    // Log panic info
-
-    log("Terminal: PANIC!");
-
-
-
-    // Try to print panic location if available
-
-    if let Some(location) = info.location() {
-
-        log("Terminal: Panic at file:");
-
-        // Note: Can't easily format the full message without alloc
-
-    }
-
+atom_syscall::define_global_allocator!();
 
 
-    exit(0xFF);
 
-}
\ No newline at end of file
+atom_syscall::define_panic_handler!("Terminal");
@@ -0,0 +1,204 @@
+// Pager Module
+//
+// Output longer than the screen scrolls out of sight before it can be
+// read. When a command line prints more than fits, the terminal pages
+// through it: the view goes back to the first line of the output and keys
+// move it, as in `less`:
+// - j, k, Down, Up and Enter move a line
+// - Space, b, PageDown and PageUp move a page
+// - g, G, Home and End go to the start and the end
+// - / searches, n and N find the next and the previous match
+// - q, Escape and Ctrl+C close the pager and show the prompt again
+//
+// The pager moves the view over the display's scrollback, so output longer
+// than the scrollback loses its start. `less` and `more` page their output
+// even when it fits; `more` closes when paged past the end.
+
+use core::ptr::addr_of_mut;
+
+use crate::buffer::{DisplayBuffer, InputBuffer, Line};
+use crate::input::KeyEvent;
+
+/// How the pager behaves at the end of the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// Stays open until closed
+    Less,
+    /// Closes when paged past the end
+    More,
+}
+
+/// Paging asked for by the command line that is running
+static mut REQUESTED: Option<Style> = None;
+
+/// Page the output of the command line that is running, even if it fits
+pub fn request(style: Style) {
+    // Safety: the terminal is single-threaded
+    unsafe { *addr_of_mut!(REQUESTED) = Some(style) }
+}
+
+/// Paging asked for since the last call
+fn take_request() -> Option<Style> {
+    // Safety: the terminal is single-threaded
+    unsafe { (*addr_of_mut!(REQUESTED)).take() }
+}
+
+/// Paging through the output of a command line
+pub struct Pager {
+    style: Style,
+    /// Number of the first line of the output
+    first: usize,
+    /// Number of the line after the output
+    end: usize,
+    /// Search being typed after '/'
+    typing: Option<InputBuffer>,
+    /// Last search, for n and N
+    query: InputBuffer,
+    /// Whether the last search found nothing
+    failed: bool,
+}
+
+impl Pager {
+    /// Page through lines `first` to `end` (not included) of `display` if
+    /// they fill more than the screen, or if `less` or `more` asked for it
+    pub fn open(first: usize, end: usize, display: &mut DisplayBuffer) -> Option<Self> {
+        let requested = take_request();
+        if end <= first || (requested.is_none() && end - first <= page_size(display)) {
+            return None;
+        }
+
+        let pager = Self {
+            style: requested.unwrap_or(Style::Less),
+            first,
+            end,
+            typing: None,
+            query: InputBuffer::new(),
+            failed: false,
+        };
+        pager.scroll_to(display, first);
+        Some(pager)
+    }
+
+    /// Handle a key; false once the pager is closed
+    pub fn key(&mut self, event: KeyEvent, display: &mut DisplayBuffer) -> bool {
+        if let Some(mut typed) = self.typing.take() {
+            match event {
+                KeyEvent::Char(ch) if !ch.is_control() => {
+                    typed.insert(ch);
+                }
+                KeyEvent::Backspace => {
+                    typed.backspace();
+                }
+                KeyEvent::Enter => {
+                    self.query = typed;
+                    self.find(display, true);
+                    return true;
+                }
+                KeyEvent::Escape | KeyEvent::Control('\x03') => return true,
+                _ => {}
+            }
+            self.typing = Some(typed);
+            return true;
+        }
+
+        let page = page_size(display);
+        let top = display.top_line();
+        self.failed = false;
+        match event {
+            KeyEvent::Char('q' | 'Q') | KeyEvent::Escape | KeyEvent::Control('\x03') => {
+                return false;
+            }
+            KeyEvent::Char('j') | KeyEvent::ArrowDown | KeyEvent::Enter => {
+                self.scroll_to(display, top + 1);
+            }
+            KeyEvent::Char('k') | KeyEvent::ArrowUp => {
+                self.scroll_to(display, top.saturating_sub(1));
+            }
+            KeyEvent::Char(' ' | 'f') | KeyEvent::PageDown => {
+                if self.style == Style::More && top >= self.last_top(display) {
+                    return false;
+                }
+                self.scroll_to(display, top + page);
+            }
+            KeyEvent::Char('b') | KeyEvent::PageUp => {
+                self.scroll_to(display, top.saturating_sub(page));
+            }
+            KeyEvent::Char('g') | KeyEvent::Home => self.scroll_to(display, self.first),
+            KeyEvent::Char('G') | KeyEvent::End => {
+                self.scroll_to(display, self.last_top(display));
+            }
+            KeyEvent::Char('/') => self.typing = Some(InputBuffer::new()),
+            KeyEvent::Char('n') => self.find(display, true),
+            KeyEvent::Char('N') => self.find(display, false),
+            _ => {}
+        }
+        true
+    }
+
+    /// The search being typed after '/', if one is
+    pub fn typing(&self) -> Option<&str> {
+        self.typing.as_ref().map(InputBuffer::as_str)
+    }
+
+    /// Whether the last search found nothing
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Lines of the output shown, counted from 1, and how many there are
+    pub fn position(&self, display: &DisplayBuffer) -> [usize; 3] {
+        let top = display.top_line().max(self.first);
+        let bottom = (top + page_size(display)).min(self.end);
+        [top - self.first + 1, bottom.saturating_sub(self.first), self.end - self.first]
+    }
+
+    /// Whether the last line of the output is shown
+    pub fn at_end(&self, display: &DisplayBuffer) -> bool {
+        display.top_line() >= self.last_top(display)
+    }
+
+    /// Top line of the last page
+    fn last_top(&self, display: &DisplayBuffer) -> usize {
+        self.first.max(self.end.saturating_sub(page_size(display)))
+    }
+
+    /// Put line `number` at the top of the view, keeping to the output
+    fn scroll_to(&self, display: &mut DisplayBuffer, number: usize) {
+        display.scroll_to_line(number.clamp(self.first, self.last_top(display)));
+    }
+
+    /// Put the next line holding the query at the top of the view, or the
+    /// one before the view's top line if going back
+    fn find(&mut self, display: &mut DisplayBuffer, forward: bool) {
+        let query = self.query.as_str();
+        if query.is_empty() {
+            return;
+        }
+        let top = display.top_line();
+        let matches = |&number: &usize| {
+            display.line(number).is_some_and(|line| contains(line, query))
+        };
+        let found = if forward {
+            (top + 1..self.end).find(matches)
+        } else {
+            (self.first..top).rev().find(matches)
+        };
+        match found {
+            Some(number) => display.scroll_to_line(number),
+            None => self.failed = true,
+        }
+    }
+}
+
+/// Lines of output on a page; the last row holds the pager's status
+fn page_size(display: &DisplayBuffer) -> usize {
+    display.dimensions().0.saturating_sub(1).max(1)
+}
+
+/// Whether the text of `line` holds `query`
+fn contains(line: &Line, query: &str) -> bool {
+    (0..line.len()).any(|start| {
+        let mut cells = (start..).map(|col| line.get(col).map(|cell| cell.ch));
+        query.chars().all(|ch| cells.next().flatten() == Some(ch))
+    })
+}
@@ -3,6 +3,13 @@
 // This module handles parsing of command line input into structured commands
 // with arguments. It provides a simple tokenizer and argument parser suitable
 // for the terminal's built-in commands.
+//
+// A line may hold several commands:
+// - `a | b` pipes the output of a into b
+// - `a > file` writes the output of a to a file, `a >> file` appends
+// - `a && b` runs b if a succeeded
+// - `a ; b` runs b after a
+// Operators inside quotes are plain text.
 
 /// Maximum number of arguments a command can have
 pub const MAX_ARGS: usize = 16;
@@ -10,7 +17,11 @@ pub const MAX_ARGS: usize = 16;
 /// Maximum length of a single argument
 pub const MAX_ARG_LENGTH: usize = 128;
 
+/// Maximum commands on one line
+pub const MAX_STAGES: usize = 8;
+
 /// A parsed command with its arguments
+#[derive(Clone, Copy)]
 pub struct ParsedCommand<'a> {
     /// The command name (first token)
     pub command: &'a str,
@@ -91,7 +102,7 @@ pub fn parse_command(input: &str) -> Option<ParsedCommand<'_>> {
             if ch == quote_char {
                 // End of quoted string
                 in_quotes = false;
-                if is_end || (i + 1 < len && bytes[i + 1].is_ascii_whitespace()) {
+                if i + 1 >= len || bytes[i + 1].is_ascii_whitespace() {
                     // Add token without quotes
                     let token = &trimmed[token_start..i];
                     if command.is_empty() {
@@ -143,6 +154,181 @@ pub fn parse_command(input: &str) -> Option<ParsedCommand<'_>> {
     })
 }
 
+/// How the command after a stage runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    /// `|`: with this stage's output as its input
+    Pipe,
+    /// `&&`: only if this stage succeeded
+    And,
+    /// `;` or the end of the line: regardless
+    Then,
+}
+
+/// File a stage's output is written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redirect<'a> {
+    pub path: &'a str,
+    /// `>>` rather than `>`
+    pub append: bool,
+}
+
+/// One command of a line
+#[derive(Clone, Copy)]
+pub struct Stage<'a> {
+    pub command: ParsedCommand<'a>,
+    pub redirect: Option<Redirect<'a>>,
+    pub next: Connector,
+}
+
+/// Why a line could not be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// An operator with no command before it
+    MissingCommand,
+    /// `>` or `>>` without exactly one file name after it
+    BadRedirect,
+    /// More than MAX_STAGES commands
+    TooManyCommands,
+}
+
+impl ParseError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::MissingCommand => "Syntax error: missing command before operator",
+            Self::BadRedirect => "Syntax error: expected one file name after >",
+            Self::TooManyCommands => "Syntax error: too many commands on one line",
+        }
+    }
+}
+
+/// All commands of a line, in order
+pub struct CommandLine<'a> {
+    stages: [Option<Stage<'a>>; MAX_STAGES],
+    count: usize,
+}
+
+impl<'a> CommandLine<'a> {
+    pub fn stages(&self) -> impl Iterator<Item = &Stage<'a>> + '_ {
+        self.stages[..self.count].iter().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+/// Parse a line that may hold several commands joined by operators
+///
+/// An empty line gives no stages; so does a `;` at the end of the line.
+pub fn parse_line(input: &str) -> Result<CommandLine<'_>, ParseError> {
+    let mut line = CommandLine {
+        stages: [None; MAX_STAGES],
+        count: 0,
+    };
+
+    let bytes = input.as_bytes();
+    let mut quote = None;
+    let mut start = 0;
+    let mut i = 0;
+    while i <= bytes.len() {
+        let byte = bytes.get(i).copied();
+        if let Some(q) = quote {
+            if byte == Some(q) {
+                quote = None;
+            }
+            i += 1;
+            continue;
+        }
+
+        let (next, width) = match byte {
+            Some(b'"' | b'\'') => {
+                quote = byte;
+                i += 1;
+                continue;
+            }
+            Some(b'|') => (Connector::Pipe, 1),
+            Some(b'&') if bytes.get(i + 1) == Some(&b'&') => (Connector::And, 2),
+            Some(b';') | None => (Connector::Then, 1),
+            Some(_) => {
+                i += 1;
+                continue;
+            }
+        };
+
+        let text = &input[start..i];
+        if text.trim().is_empty() {
+            // Only `;` may end the line; `;` with nothing before it
+            // still needs a command
+            let at_end = byte.is_none();
+            let after_then = line.count > 0 && last_connector(&line) == Connector::Then;
+            if !(at_end && (after_then || line.count == 0)) {
+                return Err(ParseError::MissingCommand);
+            }
+        } else {
+            if line.count == MAX_STAGES {
+                return Err(ParseError::TooManyCommands);
+            }
+            line.stages[line.count] = Some(parse_stage(text, next)?);
+            line.count += 1;
+        }
+
+        i += width;
+        start = i.min(bytes.len());
+    }
+
+    Ok(line)
+}
+
+fn last_connector(line: &CommandLine<'_>) -> Connector {
+    line.stages[line.count - 1].map_or(Connector::Then, |stage| stage.next)
+}
+
+/// Parse one command and its redirection
+fn parse_stage(text: &str, next: Connector) -> Result<Stage<'_>, ParseError> {
+    let (command_text, redirect) = match find_unquoted(text, b'>') {
+        Some(pos) => {
+            let append = text.as_bytes().get(pos + 1) == Some(&b'>');
+            let target = &text[pos + if append { 2 } else { 1 }..];
+            // The file name is parsed like a command with no arguments
+            let path = match parse_command(target) {
+                Some(file) if file.arg_count == 0 && find_unquoted(target, b'>').is_none() => {
+                    file.command
+                }
+                _ => return Err(ParseError::BadRedirect),
+            };
+            (&text[..pos], Some(Redirect { path, append }))
+        }
+        None => (text, None),
+    };
+
+    let command = parse_command(command_text).ok_or(ParseError::MissingCommand)?;
+    Ok(Stage {
+        command,
+        redirect,
+        next,
+    })
+}
+
+/// Position of the first `byte` outside quotes
+fn find_unquoted(text: &str, byte: u8) -> Option<usize> {
+    let mut quote = None;
+    for (i, &b) in text.as_bytes().iter().enumerate() {
+        match quote {
+            Some(q) if b == q => quote = None,
+            Some(_) => {}
+            None if b == b'"' || b == b'\'' => quote = Some(b),
+            None if b == byte => return Some(i),
+            None => {}
+        }
+    }
+    None
+}
+
 /// Split a path string into components
 pub fn split_path(path: &str) -> impl Iterator<Item = &str> {
     path.split('/').filter(|s| !s.is_empty())
@@ -227,6 +413,53 @@ mod tests {
         assert_eq!(cmd.arg(1), Some("world"));
     }
 
+    #[test]
+    fn test_parse_pipeline() {
+        let line = parse_line("ls -l | cat").unwrap();
+        let mut stages = line.stages();
+        let (first, second) = (stages.next().unwrap(), stages.next().unwrap());
+        assert_eq!(line.len(), 2);
+        assert_eq!(first.command.command, "ls");
+        assert_eq!(first.command.arg(0), Some("-l"));
+        assert_eq!(first.next, Connector::Pipe);
+        assert_eq!(second.command.command, "cat");
+        assert_eq!(second.next, Connector::Then);
+    }
+
+    #[test]
+    fn test_parse_redirect() {
+        let line = parse_line("echo hi >> \"out file\"").unwrap();
+        let stage = line.stages().next().unwrap();
+        assert_eq!(stage.command.arg(0), Some("hi"));
+        assert_eq!(stage.redirect, Some(Redirect { path: "out file", append: true }));
+
+        assert_eq!(parse_line("echo hi >").err(), Some(ParseError::BadRedirect));
+        assert_eq!(parse_line("echo hi > a b").err(), Some(ParseError::BadRedirect));
+    }
+
+    #[test]
+    fn test_parse_chain() {
+        let line = parse_line("cd /bin && ls; pwd;").unwrap();
+        let next = [Connector::And, Connector::Then, Connector::Then];
+        assert_eq!(line.len(), 3);
+        for (stage, expected) in line.stages().zip(next) {
+            assert_eq!(stage.next, expected);
+        }
+
+        assert_eq!(parse_line("| ls").err(), Some(ParseError::MissingCommand));
+        assert_eq!(parse_line("ls &&").err(), Some(ParseError::MissingCommand));
+        assert_eq!(parse_line("ls ;; pwd").err(), Some(ParseError::MissingCommand));
+    }
+
+    #[test]
+    fn test_parse_quoted_operators() {
+        let line = parse_line("echo 'a | b; c > d'").unwrap();
+        let stage = line.stages().next().unwrap();
+        assert_eq!(line.len(), 1);
+        assert_eq!(stage.command.arg(0), Some("a | b; c > d"));
+        assert!(stage.redirect.is_none());
+    }
+
     #[test]
     fn test_glob_match() {
         assert!(glob_match("*.txt", "file.txt"));
@@ -0,0 +1,82 @@
+// Selection Module
+//
+// This module tracks the cells selected with the mouse. Dragging with the
+// left button selects, in reading order, from the cell where the button
+// went down to the cell under the pointer. Positions are rows of the view,
+// so the selection is dropped whenever the view changes under it.
+
+/// Most text copied from a selection
+pub const MAX_SELECTION_BYTES: usize = 4096;
+
+/// Selected cells, as (row, column) positions in the view
+pub struct Selection {
+    // Where the drag started
+    anchor: Option<(usize, usize)>,
+    // Cell under the pointer
+    head: (usize, usize),
+    dragging: bool,
+}
+
+impl Selection {
+    pub const fn new() -> Self {
+        Self {
+            anchor: None,
+            head: (0, 0),
+            dragging: false,
+        }
+    }
+
+    /// Start selecting at a cell (left button pressed)
+    pub fn start(&mut self, row: usize, col: usize) {
+        self.anchor = Some((row, col));
+        self.head = (row, col);
+        self.dragging = true;
+    }
+
+    /// Move the selected end to a cell (pointer moved, button held)
+    pub fn extend(&mut self, row: usize, col: usize) {
+        if self.dragging {
+            self.head = (row, col);
+        }
+    }
+
+    /// Stop selecting (left button released); true if cells are selected
+    pub fn finish(&mut self) -> bool {
+        let was_dragging = self.dragging;
+        self.dragging = false;
+        was_dragging && self.range().is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.anchor = None;
+        self.dragging = false;
+    }
+
+    /// First and last selected cell, in reading order; a click that did
+    /// not move selects nothing
+    pub fn range(&self) -> Option<((usize, usize), (usize, usize))> {
+        let anchor = self.anchor?;
+        if anchor == self.head {
+            return None;
+        }
+        Some((anchor.min(self.head), anchor.max(self.head)))
+    }
+
+    /// Whether any cell of a row is selected
+    pub fn covers_row(&self, row: usize) -> bool {
+        self.range()
+            .is_some_and(|((first, _), (last, _))| (first..=last).contains(&row))
+    }
+
+    /// Whether a cell is selected
+    pub fn contains(&self, row: usize, col: usize) -> bool {
+        self.range()
+            .is_some_and(|(first, last)| (first..=last).contains(&(row, col)))
+    }
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
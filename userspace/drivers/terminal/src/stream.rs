@@ -0,0 +1,96 @@
+// Stream Module
+//
+// Byte streams that connect commands. A command writes its output to an
+// `OutputStream` and reads its input from an `InputStream`:
+// - the display buffer is the output of the last command on a line
+// - a `Pipe` holds a command's output for the next command in a pipeline,
+//   or for the file a redirection writes
+
+use atom_syscall::graphics::Color;
+
+use crate::buffer::DisplayBuffer;
+
+/// Bytes a pipe holds; output beyond this is dropped
+pub const PIPE_CAPACITY: usize = 4096;
+
+/// Where a command writes
+pub trait OutputStream {
+    /// Write text; `color` is used where the stream has colors
+    fn write(&mut self, text: &[u8], color: Color);
+
+    /// Write program output, which may contain ANSI escape sequences
+    fn write_ansi(&mut self, bytes: &[u8]);
+}
+
+/// Where a command reads
+pub trait InputStream {
+    /// Read into `buffer`; returns the number of bytes read, 0 at the end
+    fn read(&mut self, buffer: &mut [u8]) -> usize;
+}
+
+impl OutputStream for DisplayBuffer {
+    fn write(&mut self, text: &[u8], color: Color) {
+        for &byte in text {
+            self.write_byte(byte, color);
+        }
+    }
+
+    fn write_ansi(&mut self, bytes: &[u8]) {
+        DisplayBuffer::write_ansi(self, bytes);
+    }
+}
+
+/// Output of one command kept for another
+pub struct Pipe {
+    data: [u8; PIPE_CAPACITY],
+    len: usize,
+    read_pos: usize,
+}
+
+impl Pipe {
+    pub const fn new() -> Self {
+        Self {
+            data: [0u8; PIPE_CAPACITY],
+            len: 0,
+            read_pos: 0,
+        }
+    }
+
+    /// Everything written so far
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Empty the pipe for another command
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.read_pos = 0;
+    }
+}
+
+impl Default for Pipe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OutputStream for Pipe {
+    fn write(&mut self, text: &[u8], _color: Color) {
+        let count = text.len().min(PIPE_CAPACITY - self.len);
+        self.data[self.len..self.len + count].copy_from_slice(&text[..count]);
+        self.len += count;
+    }
+
+    fn write_ansi(&mut self, bytes: &[u8]) {
+        self.write(bytes, Color::BLACK);
+    }
+}
+
+impl InputStream for Pipe {
+    fn read(&mut self, buffer: &mut [u8]) -> usize {
+        let count = buffer.len().min(self.len - self.read_pos);
+        buffer[..count].copy_from_slice(&self.data[self.read_pos..self.read_pos + count]);
+        self.read_pos += count;
+        count
+    }
+}
@@ -0,0 +1,148 @@
+// UTF-8 Module
+//
+// Text reaches the terminal as bytes: command output, program output
+// arriving over IPC, and the clipboard. This module turns the bytes back
+// into characters:
+// - `Utf8Decoder` decodes a stream a byte at a time, so a character split
+//   between two writes or two messages still comes out whole
+// - `decode_lossy` decodes a buffer that is complete
+// Malformed or overlong sequences, surrogates and stray continuation
+// bytes become U+FFFD, the replacement character.
+
+/// Stands in for bytes that are not valid UTF-8
+pub const REPLACEMENT: char = '\u{FFFD}';
+
+/// Byte-at-a-time UTF-8 decoder
+pub struct Utf8Decoder {
+    /// Bits of the character read so far
+    code: u32,
+    /// Continuation bytes still to come
+    needed: u8,
+    /// Smallest code point the sequence may hold; less is overlong
+    min: u32,
+}
+
+impl Utf8Decoder {
+    pub const fn new() -> Self {
+        Self {
+            code: 0,
+            needed: 0,
+            min: 0,
+        }
+    }
+
+    /// Feed one byte; yields the characters it completes. A byte that
+    /// cuts a sequence short yields a replacement character, then is
+    /// decoded on its own.
+    pub fn feed(&mut self, byte: u8) -> impl Iterator<Item = char> {
+        let mut decoded = [None, None];
+        if self.needed > 0 {
+            if byte & 0xC0 == 0x80 {
+                self.code = self.code << 6 | (byte & 0x3F) as u32;
+                self.needed -= 1;
+                if self.needed == 0 {
+                    decoded[0] = Some(self.finish());
+                }
+                return decoded.into_iter().flatten();
+            }
+            self.needed = 0;
+            decoded[0] = Some(REPLACEMENT);
+        }
+        decoded[1] = self.start(byte);
+        decoded.into_iter().flatten()
+    }
+
+    /// Begin a character with its first byte; ASCII is whole already
+    fn start(&mut self, byte: u8) -> Option<char> {
+        let (needed, bits, min) = match byte {
+            0x00..=0x7F => return Some(byte as char),
+            0xC2..=0xDF => (1, byte & 0x1F, 0x80),
+            0xE0..=0xEF => (2, byte & 0x0F, 0x800),
+            0xF0..=0xF4 => (3, byte & 0x07, 0x10000),
+            // A stray continuation byte, or one that never starts a
+            // character
+            _ => return Some(REPLACEMENT),
+        };
+        self.code = bits as u32;
+        self.needed = needed;
+        self.min = min;
+        None
+    }
+
+    fn finish(&self) -> char {
+        if self.code < self.min {
+            return REPLACEMENT;
+        }
+        char::from_u32(self.code).unwrap_or(REPLACEMENT)
+    }
+}
+
+impl Default for Utf8Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The characters of `bytes`, with a replacement character for each run
+/// of invalid bytes
+pub fn decode_lossy(bytes: &[u8]) -> impl Iterator<Item = char> + '_ {
+    bytes.utf8_chunks().flat_map(|chunk| {
+        let invalid = (!chunk.invalid().is_empty()).then_some(REPLACEMENT);
+        chunk.valid().chars().chain(invalid)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> ([char; 8], usize) {
+        let mut decoder = Utf8Decoder::new();
+        let mut chars = ['\0'; 8];
+        let mut len = 0;
+        for &byte in bytes {
+            for ch in decoder.feed(byte) {
+                chars[len] = ch;
+                len += 1;
+            }
+        }
+        (chars, len)
+    }
+
+    #[test]
+    fn test_decode_multibyte() {
+        let (chars, len) = decode("aé€😀".as_bytes());
+        assert_eq!(&chars[..len], &['a', 'é', '€', '😀']);
+    }
+
+    #[test]
+    fn test_decode_split_between_writes() {
+        let mut decoder = Utf8Decoder::new();
+        let bytes = "€".as_bytes();
+        assert_eq!(decoder.feed(bytes[0]).next(), None);
+        assert_eq!(decoder.feed(bytes[1]).next(), None);
+        assert_eq!(decoder.feed(bytes[2]).next(), Some('€'));
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        // Cut short by ASCII, a stray continuation byte, then '/' spelled
+        // with two bytes and with three
+        let (chars, len) = decode(b"\xC3a\x80\xC0\xAF\xE0\x80\xAF");
+        let expected = [REPLACEMENT, 'a', REPLACEMENT, REPLACEMENT, REPLACEMENT, REPLACEMENT];
+        assert_eq!(&chars[..len], &expected);
+        // A surrogate half
+        let (chars, len) = decode(b"\xED\xA0\x80");
+        assert_eq!(&chars[..len], &[REPLACEMENT]);
+    }
+
+    #[test]
+    fn test_decode_lossy() {
+        let mut chars = decode_lossy(b"h\xFFi\xC3\xA9");
+        assert_eq!(chars.next(), Some('h'));
+        assert_eq!(chars.next(), Some(REPLACEMENT));
+        assert_eq!(chars.next(), Some('i'));
+        assert_eq!(chars.next(), Some('é'));
+        assert_eq!(chars.next(), None);
+    }
+}
@@ -0,0 +1,346 @@
+// Shell Variables Module
+//
+// This module keeps the shell's variables and expands references to them
+// in a command line before it is parsed:
+// - `$NAME` and `${NAME}` are replaced by the variable's value, or nothing
+// - `$?` is 0 if the last line succeeded, 1 if it failed
+// - `$(command)` is replaced by the command's output, on one line
+// Nothing is expanded between single quotes. Exported variables make up
+// the environment, listed by `export`.
+
+use core::ptr::{addr_of, addr_of_mut};
+
+/// Maximum number of variables
+pub const MAX_VARIABLES: usize = 32;
+
+/// Maximum length of a variable name
+pub const MAX_NAME_LENGTH: usize = 32;
+
+/// Maximum length of a variable value
+pub const MAX_VALUE_LENGTH: usize = 192;
+
+/// Variable holding the status of the last line
+pub const STATUS: &str = "?";
+
+struct Variable {
+    name: [u8; MAX_NAME_LENGTH],
+    name_len: usize,
+    value: [u8; MAX_VALUE_LENGTH],
+    value_len: usize,
+    exported: bool,
+}
+
+impl Variable {
+    const UNUSED: Self = Self {
+        name: [0u8; MAX_NAME_LENGTH],
+        name_len: 0,
+        value: [0u8; MAX_VALUE_LENGTH],
+        value_len: 0,
+        exported: false,
+    };
+
+    fn name(&self) -> &str {
+        // Safety: names are checked with `is_name` or are `STATUS`
+        unsafe { core::str::from_utf8_unchecked(&self.name[..self.name_len]) }
+    }
+
+    fn value(&self) -> &str {
+        // Safety: values are copied whole from the input line or cut at a
+        // character boundary
+        unsafe { core::str::from_utf8_unchecked(&self.value[..self.value_len]) }
+    }
+}
+
+/// The shell's variables, in the order they were first set
+pub struct Variables {
+    entries: [Variable; MAX_VARIABLES],
+    count: usize,
+}
+
+impl Variables {
+    pub const fn new() -> Self {
+        Self {
+            entries: [Variable::UNUSED; MAX_VARIABLES],
+            count: 0,
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        (0..self.count).find(|&i| self.entries[i].name() == name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.find(name).map(|i| self.entries[i].value())
+    }
+
+    /// Set a variable, keeping whether it is exported; false if the name
+    /// is too long or there is no room. Long values are cut short.
+    pub fn set(&mut self, name: &str, value: &str) -> bool {
+        let index = match self.find(name) {
+            Some(index) => index,
+            None if self.count < MAX_VARIABLES && name.len() <= MAX_NAME_LENGTH => {
+                let entry = &mut self.entries[self.count];
+                entry.name[..name.len()].copy_from_slice(name.as_bytes());
+                entry.name_len = name.len();
+                entry.exported = false;
+                self.count += 1;
+                self.count - 1
+            }
+            None => return false,
+        };
+
+        let mut len = value.len().min(MAX_VALUE_LENGTH);
+        while !value.is_char_boundary(len) {
+            len -= 1;
+        }
+        let entry = &mut self.entries[index];
+        entry.value[..len].copy_from_slice(&value.as_bytes()[..len]);
+        entry.value_len = len;
+        true
+    }
+
+    /// Mark a variable as part of the environment, creating it empty if
+    /// it is not set
+    pub fn export(&mut self, name: &str) -> bool {
+        if self.find(name).is_none() && !self.set(name, "") {
+            return false;
+        }
+        if let Some(index) = self.find(name) {
+            self.entries[index].exported = true;
+        }
+        true
+    }
+
+    pub fn unset(&mut self, name: &str) {
+        if let Some(index) = self.find(name) {
+            // Keep the order of the others
+            for i in index..self.count - 1 {
+                self.entries.swap(i, i + 1);
+            }
+            self.count -= 1;
+        }
+    }
+
+    /// Name, value and whether it is exported, for every variable
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str, bool)> + '_ {
+        self.entries[..self.count]
+            .iter()
+            .map(|entry| (entry.name(), entry.value(), entry.exported))
+    }
+}
+
+impl Default for Variables {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The shell's variables; too large for the stack
+static mut VARIABLES: Variables = Variables::new();
+
+pub fn variables() -> &'static Variables {
+    // Safety: the terminal is single-threaded
+    unsafe { &*addr_of!(VARIABLES) }
+}
+
+pub fn variables_mut() -> &'static mut Variables {
+    // Safety: the terminal is single-threaded
+    unsafe { &mut *addr_of_mut!(VARIABLES) }
+}
+
+/// Whether `name` can name a variable: a letter or '_', then letters,
+/// digits and '_'
+pub fn is_name(name: &str) -> bool {
+    let mut bytes = name.bytes();
+    matches!(bytes.next(), Some(b'a'..=b'z' | b'A'..=b'Z' | b'_'))
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Split a `NAME=value` word into its name and value
+pub fn assignment(word: &str) -> Option<(&str, &str)> {
+    let (name, value) = word.split_once('=')?;
+    is_name(name).then_some((name, value))
+}
+
+/// Why a line could not be expanded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpandError {
+    /// `${` or `$(` without its closing bracket
+    Unterminated,
+    /// The expanded line does not fit
+    TooLong,
+}
+
+impl ExpandError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::Unterminated => "Missing closing bracket after '$'",
+            Self::TooLong => "Line too long after expansion",
+        }
+    }
+}
+
+/// Bytes of expanded text written so far
+struct Writer<'a> {
+    out: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn push(&mut self, bytes: &[u8]) -> Result<(), ExpandError> {
+        let end = self.len + bytes.len();
+        if end > self.out.len() {
+            return Err(ExpandError::TooLong);
+        }
+        self.out[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Expand variable references and command substitutions in `input` into
+/// `out`, returning the expanded length
+///
+/// `lookup` gives a variable's value. `substitute` runs a command, writes
+/// its output to the buffer it is given and returns the length written;
+/// the output goes in with trailing line breaks dropped and the others
+/// turned into spaces. Quotes are kept for the parser.
+pub fn expand<'v, L, S>(
+    input: &str,
+    out: &mut [u8],
+    lookup: L,
+    mut substitute: S,
+) -> Result<usize, ExpandError>
+where
+    L: Fn(&str) -> Option<&'v str>,
+    S: FnMut(&str, &mut [u8]) -> usize,
+{
+    let bytes = input.as_bytes();
+    let mut writer = Writer { out, len: 0 };
+    let mut quote = None;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let byte = bytes[i];
+        match (quote, byte) {
+            (None, b'\'' | b'"') => quote = Some(byte),
+            (Some(open), _) if open == byte => quote = None,
+            _ => {}
+        }
+        let in_single_quotes = quote == Some(b'\'');
+        if byte != b'$' || in_single_quotes || i + 1 >= bytes.len() {
+            writer.push(&[byte])?;
+            i += 1;
+            continue;
+        }
+
+        let rest = &input[i + 1..];
+        match bytes[i + 1] {
+            b'?' => {
+                writer.push(lookup(STATUS).unwrap_or("").as_bytes())?;
+                i += 2;
+            }
+            b'{' => {
+                let end = rest.find('}').ok_or(ExpandError::Unterminated)?;
+                writer.push(lookup(&rest[1..end]).unwrap_or("").as_bytes())?;
+                i += end + 2;
+            }
+            b'(' => {
+                let end = rest.find(')').ok_or(ExpandError::Unterminated)?;
+                let start = writer.len;
+                let written = substitute(&rest[1..end], &mut writer.out[start..]);
+                let mut output_end = start + written;
+                while output_end > start && writer.out[output_end - 1] == b'\n' {
+                    output_end -= 1;
+                }
+                for byte in &mut writer.out[start..output_end] {
+                    if *byte == b'\n' || *byte == b'\r' {
+                        *byte = b' ';
+                    }
+                }
+                writer.len = output_end;
+                i += end + 2;
+            }
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                writer.push(lookup(&rest[..end]).unwrap_or("").as_bytes())?;
+                i += end + 1;
+            }
+            // A lone '$' stays as it is
+            _ => {
+                writer.push(b"$")?;
+                i += 1;
+            }
+        }
+    }
+
+    Ok(writer.len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<&'static str> {
+        match name {
+            "HOME" => Some("/home/user"),
+            "?" => Some("1"),
+            _ => None,
+        }
+    }
+
+    fn expand_str<'a>(input: &str, out: &'a mut [u8]) -> Result<&'a str, ExpandError> {
+        let len = expand(input, out, lookup, |command, buffer| {
+            let text = b"one\ntwo\n";
+            assert_eq!(command, "ls");
+            buffer[..text.len()].copy_from_slice(text);
+            text.len()
+        })?;
+        Ok(core::str::from_utf8(&out[..len]).unwrap())
+    }
+
+    #[test]
+    fn test_expand_variables() {
+        let mut out = [0u8; 128];
+        assert_eq!(expand_str("cd $HOME/docs", &mut out), Ok("cd /home/user/docs"));
+        assert_eq!(expand_str("echo ${HOME}x $NOPE.", &mut out), Ok("echo /home/userx ."));
+        assert_eq!(expand_str("echo $? costs $", &mut out), Ok("echo 1 costs $"));
+    }
+
+    #[test]
+    fn test_expand_quotes() {
+        let mut out = [0u8; 128];
+        let expanded = expand_str("echo '$HOME' \"$HOME's\"", &mut out);
+        assert_eq!(expanded, Ok("echo '$HOME' \"/home/user's\""));
+    }
+
+    #[test]
+    fn test_expand_substitution() {
+        let mut out = [0u8; 128];
+        assert_eq!(expand_str("echo $(ls) done", &mut out), Ok("echo one two done"));
+        assert_eq!(expand_str("echo $(ls", &mut out), Err(ExpandError::Unterminated));
+    }
+
+    #[test]
+    fn test_expand_too_long() {
+        let mut out = [0u8; 8];
+        assert_eq!(expand_str("echo $HOME", &mut out), Err(ExpandError::TooLong));
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut vars = Variables::new();
+        assert!(vars.set("A", "1"));
+        assert!(vars.set("B", "2"));
+        assert!(vars.export("A"));
+        assert!(vars.set("A", "3"));
+        assert_eq!(vars.get("A"), Some("3"));
+        vars.unset("A");
+        assert_eq!(vars.get("A"), None);
+        assert_eq!(vars.iter().next(), Some(("B", "2", false)));
+        assert_eq!(assignment("X_1=a=b"), Some(("X_1", "a=b")));
+        assert_eq!(assignment("1X=a"), None);
+    }
+}
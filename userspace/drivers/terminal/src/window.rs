@@ -6,35 +6,20 @@
 
 use atom_syscall::graphics::{Color, Framebuffer};
 
-/// Terminal color theme
-pub struct Theme;
-impl Theme {
-    // Window chrome colors
-    pub const WINDOW_BG: Color = Color::new(30, 30, 30);       // Dark terminal background
-    pub const WINDOW_BORDER: Color = Color::new(60, 60, 60);   // Subtle border
-    pub const TITLE_BAR_BG: Color = Color::new(45, 45, 45);    // Title bar background
-    pub const TITLE_BAR_TEXT: Color = Color::new(200, 200, 200);
-
-    // Terminal content colors
-    pub const TEXT_NORMAL: Color = Color::new(220, 220, 220);  // Default text
-    pub const TEXT_BRIGHT: Color = Color::WHITE;               // Bright/bold text
-    pub const TEXT_DIM: Color = Color::new(128, 128, 128);     // Dimmed text
-    pub const TEXT_ERROR: Color = Color::new(255, 100, 100);   // Error messages
-    pub const TEXT_SUCCESS: Color = Color::new(100, 255, 100); // Success messages
-    pub const TEXT_INFO: Color = Color::new(100, 180, 255);    // Info messages
-    pub const TEXT_WARNING: Color = Color::new(255, 200, 100); // Warning messages
-
-    // Prompt colors
-    pub const PROMPT_USER: Color = Color::new(136, 192, 208);  // User part of prompt
-    pub const PROMPT_PATH: Color = Color::new(163, 190, 140);  // Path part of prompt
-    pub const PROMPT_SYMBOL: Color = Color::new(180, 142, 173);// $ or # symbol
-
-    // Cursor
-    pub const CURSOR_BG: Color = Color::new(200, 200, 200);    // Cursor block color
-
-    // Selection (future use)
-    pub const SELECTION_BG: Color = Color::new(70, 100, 130);  // Selected text background
-}
+use crate::config::{config, theme, CursorStyle};
+
+/// Smallest window size, enough for a prompt and a few rows
+const MIN_WIDTH: u32 = 240;
+const MIN_HEIGHT: u32 = 120;
+
+/// Corner of the window that resizes it when dragged, in pixels
+const GRIP_SIZE: u32 = 12;
+
+/// Color the screen is cleared to where the window no longer covers it
+const SCREEN_BG: Color = Color::new(0, 0, 0);
+
+/// Size of the font's glyphs; the title and tab bar use them unscaled
+const FONT_SIZE: u32 = 8;
 
 /// Configuration for window dimensions and layout
 pub struct WindowConfig {
@@ -43,6 +28,7 @@ pub struct WindowConfig {
     pub width: u32,
     pub height: u32,
     pub title_bar_height: u32,
+    pub tab_bar_height: u32,
     pub border_width: u32,
     pub padding: u32,
     pub char_width: u32,
@@ -57,6 +43,7 @@ impl WindowConfig {
             width: 640,
             height: 400,
             title_bar_height: 24,
+            tab_bar_height: 12,
             border_width: 1,
             padding: 8,
             char_width: 8,
@@ -70,7 +57,7 @@ impl WindowConfig {
     }
 
     pub fn content_y(&self) -> u32 {
-        self.y + self.title_bar_height + self.padding
+        self.y + self.title_bar_height + self.tab_bar_height + self.padding
     }
 
     pub fn content_width(&self) -> u32 {
@@ -78,7 +65,8 @@ impl WindowConfig {
     }
 
     pub fn content_height(&self) -> u32 {
-        self.height - self.title_bar_height - 2 * self.padding - self.border_width
+        self.height - self.title_bar_height - self.tab_bar_height - 2 * self.padding
+            - self.border_width
     }
 
     /// Calculate number of columns and rows for text
@@ -123,6 +111,50 @@ impl TerminalWindow {
         &mut self.config
     }
 
+    /// Change the window size, keeping its position; the size is kept
+    /// between the minimum and what fits on the screen. Returns whether
+    /// the size changed.
+    pub fn resize(&mut self, fb: &Framebuffer, width: u32, height: u32) -> bool {
+        let cfg = &self.config;
+        // Room for the drop shadow too
+        let max_width = fb.width().saturating_sub(cfg.x + 4).max(MIN_WIDTH);
+        let max_height = fb.height().saturating_sub(cfg.y + 4).max(MIN_HEIGHT);
+        let width = width.clamp(MIN_WIDTH, max_width);
+        let height = height.clamp(MIN_HEIGHT, max_height);
+        if (width, height) == (cfg.width, cfg.height) {
+            return false;
+        }
+
+        // Clear the old frame and shadow; the new frame covers the rest
+        fb.fill_rect(cfg.x, cfg.y, cfg.width + 4, cfg.height + 4, SCREEN_BG);
+        self.config.width = width;
+        self.config.height = height;
+        true
+    }
+
+    /// Draw text `font_scale` times the font's size, `padding` pixels in
+    /// from the border; returns whether the layout changed
+    pub fn set_layout(&mut self, font_scale: u32, padding: u32) -> bool {
+        let char_size = FONT_SIZE * font_scale;
+        let cfg = &mut self.config;
+        if (cfg.char_width, cfg.padding) == (char_size, padding) {
+            return false;
+        }
+        cfg.char_width = char_size;
+        cfg.char_height = char_size;
+        cfg.padding = padding;
+        true
+    }
+
+    /// Whether screen position (`x`, `y`) is on the corner that resizes
+    /// the window
+    pub fn grip_contains(&self, x: i32, y: i32) -> bool {
+        let cfg = &self.config;
+        let (right, bottom) = ((cfg.x + cfg.width) as i32, (cfg.y + cfg.height) as i32);
+        let grip = GRIP_SIZE as i32;
+        (right - grip..right).contains(&x) && (bottom - grip..bottom).contains(&y)
+    }
+
     /// Draw the complete window frame (title bar, borders, background)
     pub fn draw_frame(&self, fb: &Framebuffer) {
         let cfg = &self.config;
@@ -131,7 +163,7 @@ impl TerminalWindow {
         fb.fill_rect(cfg.x + 4, cfg.y + 4, cfg.width, cfg.height, Color::new(0, 0, 0));
 
         // Window border
-        fb.fill_rect(cfg.x, cfg.y, cfg.width, cfg.height, Theme::WINDOW_BORDER);
+        fb.fill_rect(cfg.x, cfg.y, cfg.width, cfg.height, theme().window_border);
 
         // Title bar background
         fb.fill_rect(
@@ -139,16 +171,16 @@ impl TerminalWindow {
             cfg.y + cfg.border_width,
             cfg.width - 2 * cfg.border_width,
             cfg.title_bar_height - cfg.border_width,
-            Theme::TITLE_BAR_BG,
+            theme().title_bar_bg,
         );
 
         // Title text
         fb.draw_string(
             cfg.x + cfg.padding + cfg.border_width,
-            cfg.y + (cfg.title_bar_height - cfg.char_height) / 2,
+            cfg.y + (cfg.title_bar_height - FONT_SIZE) / 2,
             self.title,
-            Theme::TITLE_BAR_TEXT,
-            Theme::TITLE_BAR_BG,
+            theme().title_bar_text,
+            theme().title_bar_bg,
         );
 
         // Window control buttons (decorative)
@@ -170,8 +202,23 @@ impl TerminalWindow {
             cfg.y + cfg.title_bar_height,
             cfg.width - 2 * cfg.border_width,
             cfg.height - cfg.title_bar_height - cfg.border_width,
-            Theme::WINDOW_BG,
+            theme().window_bg,
         );
+
+        self.draw_grip(fb);
+    }
+
+    /// Draw the resize corner as three short diagonal lines
+    fn draw_grip(&self, fb: &Framebuffer) {
+        let cfg = &self.config;
+        let right = cfg.x + cfg.width - cfg.border_width - 2;
+        let bottom = cfg.y + cfg.height - cfg.border_width - 2;
+        for line in 1..=3 {
+            let length = line * 2;
+            for i in 0..length {
+                fb.fill_rect(right - i, bottom - length + i + 1, 1, 1, theme().text_dim);
+            }
+        }
     }
 
     /// Clear only the content area
@@ -182,12 +229,44 @@ impl TerminalWindow {
             cfg.y + cfg.title_bar_height,
             cfg.width - 2 * cfg.border_width,
             cfg.height - cfg.title_bar_height - cfg.border_width,
-            Theme::WINDOW_BG,
+            theme().window_bg,
         );
     }
 
+    /// Draw the tab bar under the title bar: a label for each open tab,
+    /// numbered by slot, with the active one in the content colors
+    pub fn draw_tab_bar(&self, fb: &Framebuffer, open: &[bool], active: usize) {
+        let cfg = &self.config;
+        let y = cfg.y + cfg.title_bar_height;
+        fb.fill_rect(
+            cfg.x + cfg.border_width,
+            y,
+            cfg.width - 2 * cfg.border_width,
+            cfg.tab_bar_height,
+            theme().title_bar_bg,
+        );
+
+        let mut label = *b" Tab 0 ";
+        let label_width = label.len() as u32 * FONT_SIZE;
+        let text_y = y + (cfg.tab_bar_height - FONT_SIZE) / 2;
+        let mut x = cfg.content_x();
+        for (slot, _) in open.iter().enumerate().filter(|(_, &open)| open) {
+            label[5] = b'1' + slot as u8;
+            let (fg, bg) = if slot == active {
+                (theme().text_bright, theme().window_bg)
+            } else {
+                (theme().text_dim, theme().title_bar_bg)
+            };
+            fb.fill_rect(x, y, label_width, cfg.tab_bar_height, bg);
+            // Safety: only ASCII
+            let text = unsafe { core::str::from_utf8_unchecked(&label) };
+            fb.draw_string(x, text_y, text, fg, bg);
+            x += label_width + FONT_SIZE;
+        }
+    }
+
     /// Draw a single character at the given row/column position
-    pub fn draw_char(&self, fb: &Framebuffer, row: u32, col: u32, ch: u8, fg: Color, bg: Color) {
+    pub fn draw_char(&self, fb: &Framebuffer, row: u32, col: u32, ch: char, fg: Color, bg: Color) {
         let cfg = &self.config;
         let x = cfg.content_x() + col * cfg.char_width;
         let y = cfg.content_y() + row * cfg.char_height;
@@ -196,7 +275,34 @@ impl TerminalWindow {
         fb.fill_rect(x, y, cfg.char_width, cfg.char_height, bg);
 
         // Draw character
-        fb.draw_char(x, y, ch, fg, bg);
+        draw_glyph(fb, x, y, cfg.char_width, ch, fg, bg);
+    }
+
+    /// Row and column of the cell at screen position (`x`, `y`), if it is
+    /// in the content area
+    pub fn cell_at(&self, x: i32, y: i32) -> Option<(u32, u32)> {
+        let cfg = &self.config;
+        let x = u32::try_from(x).ok()?.checked_sub(cfg.content_x())?;
+        let y = u32::try_from(y).ok()?.checked_sub(cfg.content_y())?;
+        let (row, col) = (y / cfg.char_height, x / cfg.char_width);
+        if row < cfg.rows() && col < cfg.cols() {
+            Some((row, col))
+        } else {
+            None
+        }
+    }
+
+    /// Draw the mouse pointer as an outline around a cell
+    pub fn draw_pointer(&self, fb: &Framebuffer, row: u32, col: u32) {
+        let cfg = &self.config;
+        let x = cfg.content_x() + col * cfg.char_width;
+        let y = cfg.content_y() + row * cfg.char_height;
+        let (w, h) = (cfg.char_width, cfg.char_height);
+
+        fb.fill_rect(x, y, w, 1, theme().cursor_bg);
+        fb.fill_rect(x, y + h - 1, w, 1, theme().cursor_bg);
+        fb.fill_rect(x, y, 1, h, theme().cursor_bg);
+        fb.fill_rect(x + w - 1, y, 1, h, theme().cursor_bg);
     }
 
     /// Draw a string at the given row/column position
@@ -205,35 +311,49 @@ impl TerminalWindow {
         let x = cfg.content_x() + col * cfg.char_width;
         let y = cfg.content_y() + row * cfg.char_height;
 
-        fb.draw_string(x, y, text, fg, bg);
+        fb.draw_string_sized(x, y, text, fg, bg, cfg.char_width);
     }
 
     /// Draw text with the window background color
     pub fn draw_text_default(&self, fb: &Framebuffer, row: u32, col: u32, text: &str, fg: Color) {
-        self.draw_text(fb, row, col, text, fg, Theme::WINDOW_BG);
+        self.draw_text(fb, row, col, text, fg, theme().window_bg);
     }
 
-    /// Draw the cursor at the given position
+    /// Draw the cursor at the given position, in the configured style
     pub fn draw_cursor(&self, fb: &Framebuffer, row: u32, col: u32) {
         let cfg = &self.config;
         let x = cfg.content_x() + col * cfg.char_width;
         let y = cfg.content_y() + row * cfg.char_height;
-
-        // Block cursor
-        fb.fill_rect(x, y, cfg.char_width, cfg.char_height, Theme::CURSOR_BG);
+        let (w, h) = (cfg.char_width, cfg.char_height);
+        // Underline and bar are a quarter of a cell thick
+        let thickness = (h / 4).max(1);
+        let color = theme().cursor_bg;
+
+        match config().cursor {
+            CursorStyle::Block => fb.fill_rect(x, y, w, h, color),
+            CursorStyle::Underline => fb.fill_rect(x, y + h - thickness, w, thickness, color),
+            CursorStyle::Bar => fb.fill_rect(x, y, thickness, h, color),
+        }
     }
 
-    /// Draw a character with cursor (inverted colors)
-    pub fn draw_char_with_cursor(&self, fb: &Framebuffer, row: u32, col: u32, ch: u8) {
+    /// Draw a character with cursor: inverted colors in a block cursor,
+    /// otherwise the character with the cursor's line over it
+    pub fn draw_char_with_cursor(&self, fb: &Framebuffer, row: u32, col: u32, ch: char) {
+        if config().cursor != CursorStyle::Block {
+            self.draw_char(fb, row, col, ch, theme().text_normal, theme().window_bg);
+            self.draw_cursor(fb, row, col);
+            return;
+        }
+
         let cfg = &self.config;
         let x = cfg.content_x() + col * cfg.char_width;
         let y = cfg.content_y() + row * cfg.char_height;
 
         // Draw cursor background
-        fb.fill_rect(x, y, cfg.char_width, cfg.char_height, Theme::CURSOR_BG);
+        fb.fill_rect(x, y, cfg.char_width, cfg.char_height, theme().cursor_bg);
 
         // Draw character in inverted color
-        fb.draw_char(x, y, ch, Theme::WINDOW_BG, Theme::CURSOR_BG);
+        draw_glyph(fb, x, y, cfg.char_width, ch, theme().window_bg, theme().cursor_bg);
     }
 
     /// Clear a specific row
@@ -246,7 +366,7 @@ impl TerminalWindow {
             y,
             cfg.content_width(),
             cfg.char_height,
-            Theme::WINDOW_BG,
+            theme().window_bg,
         );
     }
 
@@ -257,7 +377,7 @@ impl TerminalWindow {
         let y = cfg.content_y() + row * cfg.char_height;
         let remaining_width = cfg.content_width().saturating_sub(col * cfg.char_width);
 
-        fb.fill_rect(x, y, remaining_width, cfg.char_height, Theme::WINDOW_BG);
+        fb.fill_rect(x, y, remaining_width, cfg.char_height, theme().window_bg);
     }
 
     /// Mark window as needing full redraw
@@ -290,35 +410,28 @@ impl TerminalWindow {
         let scroll_pixels = lines * line_height;
         let remaining_rows = total_rows - lines;
 
-        // Copy lines up (simple byte-by-byte copy for now)
-        // In a real implementation, this would use a more efficient memory copy
-        let fb_addr = fb.address();
-        let stride = fb.stride();
-        let bpp = fb.bytes_per_pixel();
-
-        for row in 0..remaining_rows {
-            let src_y = content_y + (row + lines) * line_height;
-            let dst_y = content_y + row * line_height;
-
-            for line in 0..line_height {
-                for col in 0..content_w {
-                    let src_offset = ((src_y + line) * stride + content_x + col) as usize * bpp;
-                    let dst_offset = ((dst_y + line) * stride + content_x + col) as usize * bpp;
-
-                    let src_ptr = (fb_addr + src_offset) as *const u32;
-                    let dst_ptr = (fb_addr + dst_offset) as *mut u32;
-
-                    unsafe {
-                        let pixel = src_ptr.read_volatile();
-                        dst_ptr.write_volatile(pixel);
-                    }
-                }
-            }
-        }
+        // Move the rows that stay up in one copy
+        fb.copy_rect(
+            content_x,
+            content_y + scroll_pixels,
+            content_w,
+            remaining_rows * line_height,
+            content_x,
+            content_y,
+        );
 
         // Clear the scrolled-in area at the bottom
         for row in remaining_rows..total_rows {
             self.clear_row(fb, row);
         }
     }
-}
\ No newline at end of file
+}
+/// Draw `ch` from the font at (`x`, `y`), `size` pixels square. The font
+/// only holds printable ASCII; any other character gets a replacement
+/// glyph, a '?' with the colors swapped.
+fn draw_glyph(fb: &Framebuffer, x: u32, y: u32, size: u32, ch: char, fg: Color, bg: Color) {
+    match ch {
+        ' '..='~' => fb.draw_char_sized(x, y, ch as u8, fg, bg, size),
+        _ => fb.draw_char_sized(x, y, b'?', bg, fg, size),
+    }
+}
@@ -0,0 +1,23 @@
+//! Integration Test Child
+//!
+//! The program the test runner starts to check spawning end to end: it
+//! greets whoever started it on its output port and exits with a code the
+//! runner knows, so both halves of the round trip can be verified.
+
+#![no_std]
+#![no_main]
+
+mod expect;
+
+use atom_std::prelude::*;
+
+use expect::{CHILD_EXIT_CODE, CHILD_GREETING};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    println!("{}", CHILD_GREETING);
+    process::exit(CHILD_EXIT_CODE)
+}
+
+atom_std::define_global_allocator!();
+atom_std::define_panic_handler!("Test Child");
@@ -0,0 +1,7 @@
+//! What the test child does, shared by the child and the runner checking it
+
+/// Line the child sends to its output port
+pub const CHILD_GREETING: &str = "test_child: hello";
+
+/// Code the child exits with
+pub const CHILD_EXIT_CODE: u64 = 42;
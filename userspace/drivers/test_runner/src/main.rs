@@ -0,0 +1,115 @@
+//! Userspace Integration Test Runner
+//!
+//! Exercises the paths programs depend on end to end, from Ring 3 against
+//! the running kernel and desktop, where the in-kernel tests (`ktest`) can
+//! only check one subsystem at a time:
+//! - Creating a port and sending a message through it
+//! - Passing data through a shared region between two threads
+//! - Spawning a child program, reading its output and waiting for it
+//! - Looking up the compositor and making the `Hello` handshake
+//!
+//! # Running
+//!
+//! Booting with the `test_runner` parameter starts this program next to
+//! the desktop (`./build.sh --itest` does so under QEMU). It runs every
+//! test, or those whose name contains `test_filter=<text>`, and exits with
+//! 0 if all passed and 1 otherwise; the kernel then ends the QEMU run with
+//! the matching status.
+//!
+//! # Report
+//!
+//! One kernel log line per event, as `key=value` fields after a `testrun:`
+//! prefix, so CI can pick them out of the serial log:
+//!
+//! ```text
+//! testrun: start tests=4
+//! testrun: result name=port_roundtrip status=ok ms=2
+//! testrun: result name=spawn_and_wait status=fail ms=5000 reason="wait: TimedOut"
+//! testrun: summary passed=3 failed=1
+//! ```
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+mod expect;
+mod tests;
+
+use alloc::format;
+use alloc::string::String;
+
+use atom_std::env;
+use atom_std::process;
+use atom_std::time::Instant;
+use atom_syscall::debug::log;
+
+// ============================================================================
+// Harness
+// ============================================================================
+
+/// Why a test failed
+pub type TestResult = Result<(), String>;
+
+pub struct Test {
+    pub name: &'static str,
+    pub run: fn() -> TestResult,
+}
+
+/// Boot parameter naming the tests to run, by part of their name
+const FILTER_VAR: &str = "test_filter";
+
+fn report(line: &str) {
+    log(&format!("testrun: {}", line));
+}
+
+fn run(tests: &[Test], filter: Option<&str>) -> bool {
+    let selected = || tests.iter().filter(|test| filter.is_none_or(|f| test.name.contains(f)));
+
+    report(&format!("start tests={}", selected().count()));
+
+    let (mut passed, mut failed) = (0, 0);
+    for test in selected() {
+        let start = Instant::now();
+        let result = (test.run)();
+        let ms = start.elapsed().as_millis();
+
+        match result {
+            Ok(()) => {
+                passed += 1;
+                report(&format!("result name={} status=ok ms={}", test.name, ms));
+            }
+            Err(reason) => {
+                failed += 1;
+                report(&format!(
+                    "result name={} status=fail ms={} reason={:?}",
+                    test.name, ms, reason
+                ));
+            }
+        }
+    }
+
+    report(&format!("summary passed={} failed={}", passed, failed));
+    failed == 0
+}
+
+// ============================================================================
+// Entry Points
+// ============================================================================
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    main()
+}
+
+fn main() -> ! {
+    log("Test Runner: Starting");
+
+    let filter = env::var(FILTER_VAR);
+    let all_passed = run(tests::TESTS, filter.as_deref());
+
+    process::exit(if all_passed { 0 } else { 1 })
+}
+
+atom_std::define_global_allocator!();
+atom_std::define_panic_handler!("Test Runner");
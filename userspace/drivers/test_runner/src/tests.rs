@@ -0,0 +1,174 @@
+//! The integration tests, in running order
+//!
+//! Each test cleans up the ports, regions and programs it creates, also
+//! when it fails, so the ones after it start from the same state.
+
+use alloc::format;
+use alloc::string::String;
+use core::fmt::Debug;
+use core::ptr;
+
+use atom_syscall::ipc::{close_port, create_port, port_stats, send, try_recv, wait_any, PortId};
+use atom_syscall::process::{kill, spawn_with_output, wait};
+use atom_syscall::shm::{self, RegionFlags, RegionId};
+use atom_syscall::thread;
+use libipc::connection::Connection;
+use libipc::messages::{MessageType, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use libipc::protocol::{get_payload, try_recv_message};
+use libipc::{ServiceId, MAX_MESSAGE_SIZE};
+
+use crate::expect::{CHILD_EXIT_CODE, CHILD_GREETING};
+use crate::{Test, TestResult};
+
+pub const TESTS: &[Test] = &[
+    Test { name: "port_roundtrip", run: port_roundtrip },
+    Test { name: "shared_region_roundtrip", run: shared_region_roundtrip },
+    Test { name: "spawn_and_wait", run: spawn_and_wait },
+    Test { name: "compositor_handshake", run: compositor_handshake },
+];
+
+/// Longest a test waits for a message, a child or a service
+const TIMEOUT_MS: u64 = 5000;
+
+/// Manifest path of the child program (src/child.rs)
+const CHILD_PATH: &str = "/init/test_child.elf";
+
+const PAGE_SIZE: usize = 4096;
+
+/// Fail the test with a message unless `cond` holds
+macro_rules! ensure {
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            return Err(format!($($arg)+));
+        }
+    };
+}
+
+/// The value of `result`, or a failure naming the call that failed
+fn step<T, E: Debug>(call: &str, result: Result<T, E>) -> Result<T, String> {
+    result.map_err(|error| format!("{}: {:?}", call, error))
+}
+
+/// Wait up to `TIMEOUT_MS` for a message on `port`
+fn recv_within(port: PortId, buffer: &mut [u8]) -> Result<usize, String> {
+    step("wait_any", wait_any(&[port], TIMEOUT_MS))?;
+    step("try_recv", try_recv(port, buffer))?.ok_or_else(|| String::from("woken with no message"))
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+/// A message sent to a fresh port comes back whole and is counted
+fn port_roundtrip() -> TestResult {
+    let port = step("create_port", create_port())?;
+    let result = exchange_on(port);
+    let _ = close_port(port);
+    result
+}
+
+fn exchange_on(port: PortId) -> TestResult {
+    let message = b"testrun: ping";
+    step("send", send(port, message))?;
+
+    let mut buffer = [0u8; 64];
+    let len = recv_within(port, &mut buffer)?;
+    ensure!(&buffer[..len] == message, "received {:?}", &buffer[..len]);
+
+    let stats = step("port_stats", port_stats(port))?;
+    ensure!(stats.messages_sent == 1, "{} messages counted as sent", stats.messages_sent);
+    ensure!(stats.queued == 0, "{} messages still queued", stats.queued);
+    Ok(())
+}
+
+/// Data written to a region by one thread is read by another through a
+/// second mapping, and the answer makes it back
+fn shared_region_roundtrip() -> TestResult {
+    let region = step("create_region", shm::create_region(PAGE_SIZE))?;
+    let result = share(region);
+    let _ = shm::destroy_region(region);
+    result
+}
+
+fn share(region: RegionId) -> TestResult {
+    let base = step("map_region", shm::map_anywhere(region, RegionFlags::read_write()))?;
+    let result = round_trip(region, base, 0xA5, 0x5A);
+    let _ = shm::unmap_region(region);
+    result
+}
+
+/// Fill the first half of the region with `pattern`; a second thread
+/// checks it and answers with `reply` just past it
+fn round_trip(region: RegionId, base: *mut u8, pattern: u8, reply: u8) -> TestResult {
+    unsafe { ptr::write_bytes(base, pattern, PAGE_SIZE / 2) };
+
+    let peer = step(
+        "thread::spawn",
+        thread::spawn(move || -> Result<(), String> {
+            let view =
+                step("peer map_region", shm::map_anywhere(region, RegionFlags::read_write()))?;
+            let seen = unsafe { ptr::read_volatile(view.add(PAGE_SIZE / 2 - 1)) };
+            if seen == pattern {
+                unsafe { ptr::write_volatile(view.add(PAGE_SIZE / 2), reply) };
+            }
+            let _ = shm::unmap_region(region);
+            ensure!(seen == pattern, "peer read {:#04X}, expected {:#04X}", seen, pattern);
+            Ok(())
+        }),
+    )?;
+    step("join", peer.join())??;
+
+    let answer = unsafe { ptr::read_volatile(base.add(PAGE_SIZE / 2)) };
+    ensure!(answer == reply, "read back {:#04X}, expected {:#04X}", answer, reply);
+    Ok(())
+}
+
+/// A spawned child's output reaches the port given for it and its exit
+/// code reaches the waiting parent
+fn spawn_and_wait() -> TestResult {
+    let output = step("create_port", create_port())?;
+    let result = run_child(output);
+    let _ = close_port(output);
+    result
+}
+
+fn run_child(output: PortId) -> TestResult {
+    let child = step("spawn", spawn_with_output(CHILD_PATH, output))?;
+
+    let code = match wait(child, TIMEOUT_MS) {
+        Ok(code) => code,
+        Err(error) => {
+            let _ = kill(child);
+            let _ = wait(child, 0);
+            return Err(format!("wait: {:?}", error));
+        }
+    };
+    ensure!(code == CHILD_EXIT_CODE, "child exited with {}, expected {}", code, CHILD_EXIT_CODE);
+
+    // The child has exited, so its output is already queued
+    let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+    let (header, len) = step("try_recv_message", try_recv_message(output, &mut buffer))?
+        .ok_or_else(|| String::from("child sent no output"))?;
+    ensure!(header.msg_type == MessageType::ProgramOutput, "output sent as {:?}", header.msg_type);
+    let text = core::str::from_utf8(get_payload(&buffer, len)).unwrap_or("<not UTF-8>");
+    ensure!(text.trim_end() == CHILD_GREETING, "child printed {:?}", text);
+    Ok(())
+}
+
+/// The compositor is registered and agrees on a protocol version this
+/// side speaks
+fn compositor_handshake() -> TestResult {
+    let mut desktop = Connection::new(ServiceId::Desktop);
+    desktop.set_lookup_timeout(TIMEOUT_MS);
+
+    // Dropping the connection closes its port
+    let version = step("handshake", desktop.handshake())?;
+    ensure!(
+        (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version),
+        "agreed on version {}, this side speaks {}..={}",
+        version,
+        MIN_PROTOCOL_VERSION,
+        PROTOCOL_VERSION
+    );
+    Ok(())
+}
@@ -0,0 +1,195 @@
+//! Window Animations
+//!
+//! Short transitions for windows appearing, closing and minimizing, driven
+//! by the timer: each frame the compositor asks where every running
+//! animation has got to and redraws the area it sweeps over.
+//!
+//! Animated windows are drawn as simplified frames (border, title bar and
+//! scaled content) at the interpolated rectangle. A closing window has
+//! already left the window manager, so its animation keeps it (and its
+//! surface) alive until the fade-out ends.
+//!
+//! With animations disabled every transition completes immediately.
+
+use alloc::vec::Vec;
+
+use libipc::messages::{Rect, WindowId};
+
+use crate::wm::{Window, WindowManager};
+
+/// Durations in timer ticks (10 ms each)
+const OPEN_TICKS: u64 = 15;
+const CLOSE_TICKS: u64 = 12;
+const MINIMIZE_TICKS: u64 = 18;
+
+/// Size a window grows from when opening, in percent
+const OPEN_START_SCALE: u32 = 80;
+
+/// Size a window shrinks to while fading out, in percent
+const CLOSE_END_SCALE: u32 = 90;
+
+pub enum Effect {
+    /// Grow from the centre while fading in
+    Open,
+    /// Shrink slightly while fading out; holds the closed window
+    Close(Window),
+    /// Shrink from `from` into the window's dock entry at `to`
+    Minimize { from: Rect, to: Rect },
+}
+
+struct Animation {
+    window: WindowId,
+    effect: Effect,
+    start: u64,
+    duration: u64,
+}
+
+/// Where an animated window is drawn this frame
+pub struct Frame<'a> {
+    pub window: &'a Window,
+    pub rect: Rect,
+    /// 0 = invisible, 255 = opaque
+    pub alpha: u8,
+}
+
+pub struct Animator {
+    animations: Vec<Animation>,
+    enabled: bool,
+}
+
+impl Animator {
+    pub fn new() -> Self {
+        Self {
+            animations: Vec::new(),
+            enabled: true,
+        }
+    }
+
+    /// Turn animations on or off; running ones finish immediately
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.animations.clear();
+        }
+    }
+
+    pub fn start(&mut self, window: WindowId, effect: Effect, now: u64) {
+        if !self.enabled {
+            return;
+        }
+
+        let duration = match effect {
+            Effect::Open => OPEN_TICKS,
+            Effect::Close(_) => CLOSE_TICKS,
+            Effect::Minimize { .. } => MINIMIZE_TICKS,
+        };
+
+        // A window only runs one animation at a time
+        self.animations.retain(|a| a.window != window);
+        self.animations.push(Animation {
+            window,
+            effect,
+            start: now,
+            duration,
+        });
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.animations.is_empty()
+    }
+
+    /// Whether the window is still opening and must not be drawn normally
+    pub fn is_opening(&self, id: WindowId) -> bool {
+        self.animations
+            .iter()
+            .any(|a| a.window == id && matches!(a.effect, Effect::Open))
+    }
+
+    /// Drop finished animations and return the screen areas every animation
+    /// (running or just finished) may have drawn to
+    pub fn advance(&mut self, now: u64, wm: &WindowManager) -> Vec<Rect> {
+        let areas = self.animations.iter().filter_map(|a| a.area(wm)).collect();
+        self.animations.retain(|a| now < a.start + a.duration);
+        areas
+    }
+
+    /// Current frame of every running animation, in start order
+    pub fn frames<'a>(
+        &'a self,
+        now: u64,
+        wm: &'a WindowManager,
+    ) -> impl Iterator<Item = Frame<'a>> {
+        self.animations.iter().filter_map(move |a| a.frame(now, wm))
+    }
+}
+
+impl Animation {
+    fn window<'a>(&'a self, wm: &'a WindowManager) -> Option<&'a Window> {
+        match &self.effect {
+            Effect::Close(window) => Some(window),
+            _ => wm.windows.iter().find(|w| w.id == self.window),
+        }
+    }
+
+    /// Everything the animation covers from start to end
+    fn area(&self, wm: &WindowManager) -> Option<Rect> {
+        let window = self.window(wm)?;
+        Some(match self.effect {
+            Effect::Minimize { from, to } => from.union(&to),
+            _ => window.bounds(),
+        })
+    }
+
+    fn frame<'a>(&'a self, now: u64, wm: &'a WindowManager) -> Option<Frame<'a>> {
+        let window = self.window(wm)?;
+        let (x, y, width, height) = window.geometry();
+        let full = Rect::new(x, y, width, height);
+
+        // Progress in 1/256ths, eased out so motion slows towards the end
+        let elapsed = now.saturating_sub(self.start).min(self.duration);
+        let linear = (elapsed * 256 / self.duration.max(1)) as u32;
+        let t = 256 - (256 - linear) * (256 - linear) / 256;
+
+        let (rect, alpha) = match self.effect {
+            Effect::Open => (scale(&full, lerp(OPEN_START_SCALE, 100, t)), t.min(255)),
+            Effect::Close(_) => (scale(&full, lerp(100, CLOSE_END_SCALE, t)), 255 - t.min(255)),
+            Effect::Minimize { from, to } => (lerp_rect(&from, &to, t), 255 - t / 2),
+        };
+
+        Some(Frame {
+            window,
+            rect,
+            alpha: alpha as u8,
+        })
+    }
+}
+
+/// Interpolate from `a` to `b` with `t` in 1/256ths
+fn lerp(a: u32, b: u32, t: u32) -> u32 {
+    ((a as i64 * (256 - t) as i64 + b as i64 * t as i64) / 256) as u32
+}
+
+fn lerp_i(a: i32, b: i32, t: u32) -> i32 {
+    ((a as i64 * (256 - t) as i64 + b as i64 * t as i64) / 256) as i32
+}
+
+fn lerp_rect(a: &Rect, b: &Rect, t: u32) -> Rect {
+    Rect::new(
+        lerp_i(a.x, b.x, t),
+        lerp_i(a.y, b.y, t),
+        lerp(a.width, b.width, t),
+        lerp(a.height, b.height, t),
+    )
+}
+
+/// `rect` scaled to `percent` around its centre
+fn scale(rect: &Rect, percent: u32) -> Rect {
+    let width = rect.width * percent / 100;
+    let height = rect.height * percent / 100;
+    Rect::new(
+        rect.x + (rect.width - width) as i32 / 2,
+        rect.y + (rect.height - height) as i32 / 2,
+        width,
+        height,
+    )
+}
@@ -0,0 +1,71 @@
+//! Screen Capture
+//!
+//! Copies the composed screen, or a single window's surface, into a
+//! shared region the caller created. Reading what other windows show is
+//! gated by the Screenshot capability, a kernel capability the service
+//! manager grants to programs whose manifest entry lists `ScreenshotCap`.
+//! The compositor asks the kernel whether a request's sender holds it, so
+//! nothing in the request can be forged to get past the check.
+
+use alloc::vec::Vec;
+
+use atom_syscall::graphics::Framebuffer;
+use atom_syscall::ipc::{self, CapKind};
+use atom_syscall::shm::{self, RegionFlags};
+use libipc::messages::{CaptureRequest, CaptureResult, CaptureStatus, SURFACE_BYTES_PER_PIXEL};
+
+use crate::surface::WindowSurface;
+
+/// Whether the sender of the message just received holds the Screenshot
+/// capability; must be asked before receiving anything else
+pub fn sender_allowed() -> bool {
+    ipc::sender_holds(CapKind::Screenshot) == Ok(true)
+}
+
+/// Pixels of a capture, rows packed
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u32>,
+}
+
+/// Copy the whole composed screen
+pub fn screen(back: &Framebuffer) -> Screenshot {
+    let (width, height) = (back.width(), back.height());
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for row in back.pixels().rows() {
+        pixels.extend_from_slice(row);
+    }
+    Screenshot { width, height, pixels }
+}
+
+/// Copy a window's surface
+pub fn window(surface: &WindowSurface) -> Screenshot {
+    Screenshot {
+        width: surface.width,
+        height: surface.height,
+        pixels: surface.pixels(),
+    }
+}
+
+/// Write `shot` into the requester's region and describe the outcome
+pub fn deliver(shot: &Screenshot, request: &CaptureRequest) -> CaptureResult {
+    let (width, height) = (shot.width, shot.height);
+    let result = |status| CaptureResult { status, width, height };
+
+    let len = shot.pixels.len() * SURFACE_BYTES_PER_PIXEL as usize;
+    if (request.region_len as usize) < len {
+        return result(CaptureStatus::RegionTooSmall);
+    }
+
+    let Ok(base) = shm::map_anywhere(request.region_id, RegionFlags::read_write()) else {
+        return result(CaptureStatus::Failed);
+    };
+    unsafe {
+        core::ptr::copy_nonoverlapping(shot.pixels.as_ptr(), base as *mut u32, shot.pixels.len());
+    }
+    let _ = shm::unmap_region(request.region_id);
+
+    result(CaptureStatus::Ok)
+}
@@ -0,0 +1,108 @@
+//! Clipboard
+//!
+//! The compositor keeps a copy of the clipboard so it outlives the
+//! application that set it. Small content travels inside the messages;
+//! larger content is copied out of the sender's shared region on
+//! `SetClipboard`, and handed to readers in a region the compositor owns
+//! (created on the first large read and replaced when the content changes).
+
+use alloc::vec::Vec;
+
+use atom_syscall::ipc::PortId;
+use atom_syscall::shm::{self, RegionFlags, RegionId};
+use libipc::messages::{
+    ClipboardContent, ClipboardData, ClipboardMime, CLIPBOARD_INLINE_MAX, MAX_CLIPBOARD_BYTES,
+};
+
+pub struct Clipboard {
+    /// Port of the application that set the content
+    owner: Option<PortId>,
+    mime: ClipboardMime,
+    data: Vec<u8>,
+    /// Region readers of large content map
+    shared: Option<RegionId>,
+}
+
+impl Clipboard {
+    pub const fn new() -> Self {
+        Self {
+            owner: None,
+            mime: ClipboardMime::TextPlain,
+            data: Vec::new(),
+            shared: None,
+        }
+    }
+
+    /// Replace the content, returning the previous owner, or `None` if the
+    /// content could not be read
+    pub fn set(&mut self, msg: &ClipboardData) -> Option<Option<PortId>> {
+        let data = match &msg.content {
+            ClipboardContent::Inline(data) => data.clone(),
+            ClipboardContent::Shared { region_id, len } => read_region(*region_id, *len as usize)?,
+        };
+
+        self.release_shared();
+        self.mime = msg.mime;
+        self.data = data;
+        Some(self.owner.replace(msg.port))
+    }
+
+    /// Content to answer `GetClipboard` with
+    pub fn get(&mut self, mime: ClipboardMime) -> ClipboardData {
+        let content = if mime != self.mime {
+            ClipboardContent::Inline(Vec::new())
+        } else if self.data.len() <= CLIPBOARD_INLINE_MAX {
+            ClipboardContent::Inline(self.data.clone())
+        } else {
+            match self.share() {
+                Some(region_id) => ClipboardContent::Shared {
+                    region_id,
+                    len: self.data.len() as u32,
+                },
+                None => ClipboardContent::Inline(Vec::new()),
+            }
+        };
+
+        ClipboardData { port: 0, mime, content }
+    }
+
+    /// Region holding the current content, creating it if needed
+    fn share(&mut self) -> Option<RegionId> {
+        if let Some(region) = self.shared {
+            return Some(region);
+        }
+
+        let region = shm::create_region(self.data.len()).ok()?;
+        let Ok(base) = shm::map_anywhere(region, RegionFlags::read_write()) else {
+            let _ = shm::destroy_region(region);
+            return None;
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.data.as_ptr(), base, self.data.len());
+        }
+        let _ = shm::unmap_region(region);
+
+        self.shared = Some(region);
+        Some(region)
+    }
+
+    /// Drop the readers' region; fails quietly while a reader still has it
+    /// mapped, which only leaks that region
+    fn release_shared(&mut self) {
+        if let Some(region) = self.shared.take() {
+            let _ = shm::destroy_region(region);
+        }
+    }
+}
+
+/// Copy `len` bytes out of a client's region
+fn read_region(region: RegionId, len: usize) -> Option<Vec<u8>> {
+    if len > MAX_CLIPBOARD_BYTES {
+        return None;
+    }
+
+    let base = shm::map_anywhere(region, RegionFlags::read_only()).ok()?;
+    let data = unsafe { core::slice::from_raw_parts(base as *const u8, len) }.to_vec();
+    let _ = shm::unmap_region(region);
+    Some(data)
+}
@@ -0,0 +1,203 @@
+//! Clock and Calendar
+//!
+//! The panel clock shows the wall-clock time (UTC, from `SYS_GET_TIME`)
+//! and is redrawn when the minute changes. Clicking it opens a calendar of
+//! the current month below the panel.
+
+use atom_syscall::graphics::Framebuffer;
+use libipc::messages::Rect;
+
+use crate::theme::Theme;
+
+const MONTH_NAMES: [&str; 12] = [
+    "January", "February", "March", "April", "May", "June", "July", "August", "September",
+    "October", "November", "December",
+];
+
+const CALENDAR_PADDING: u32 = 8;
+const CELL_WIDTH: u32 = 24;
+const CELL_HEIGHT: u32 = 18;
+const CALENDAR_WIDTH: u32 = CALENDAR_PADDING * 2 + 7 * CELL_WIDTH;
+/// Title, weekday names and up to six weeks
+const CALENDAR_HEIGHT: u32 = CALENDAR_PADDING * 2 + 2 * CELL_HEIGHT + 6 * CELL_HEIGHT;
+
+/// A calendar date and time of day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i32,
+    /// 1 = January
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+}
+
+impl DateTime {
+    pub fn from_epoch(seconds: u64) -> Self {
+        let days = (seconds / 86_400) as i64;
+        let secs = seconds % 86_400;
+        let (year, month, day) = civil_from_days(days);
+        Self {
+            year,
+            month,
+            day,
+            hour: (secs / 3_600) as u8,
+            minute: (secs / 60 % 60) as u8,
+        }
+    }
+
+    /// "HH:MM"
+    pub fn format_time(&self, buf: &mut [u8; 5]) {
+        buf[0] = b'0' + self.hour / 10;
+        buf[1] = b'0' + self.hour % 10;
+        buf[2] = b':';
+        buf[3] = b'0' + self.minute / 10;
+        buf[4] = b'0' + self.minute % 10;
+    }
+}
+
+/// Panel clock state
+pub struct Clock {
+    /// Minutes since the epoch of the time last shown
+    shown_minute: u64,
+    now: DateTime,
+    /// Whether the calendar popup is open
+    pub calendar_open: bool,
+}
+
+impl Clock {
+    pub fn new(seconds: u64) -> Self {
+        Self {
+            shown_minute: seconds / 60,
+            now: DateTime::from_epoch(seconds),
+            calendar_open: false,
+        }
+    }
+
+    /// Update to the current time; returns whether the minute changed
+    pub fn tick(&mut self, seconds: u64) -> bool {
+        if seconds / 60 == self.shown_minute {
+            return false;
+        }
+        self.shown_minute = seconds / 60;
+        self.now = DateTime::from_epoch(seconds);
+        true
+    }
+
+    pub fn draw(&self, fb: &Framebuffer, theme: &Theme, screen_w: u32) {
+        let area = clock_rect(screen_w);
+        let mut text = [0u8; 5];
+        self.now.format_time(&mut text);
+        let text = core::str::from_utf8(&text).unwrap_or("--:--");
+        let bg = if self.calendar_open { theme.window_header } else { theme.panel_bg };
+        fb.fill_rect(area.x as u32, area.y as u32, area.width, area.height, bg);
+        fb.draw_string(area.x as u32 + 4, area.y as u32 + 1, text, theme.panel_text, bg);
+    }
+
+    pub fn draw_calendar(&self, fb: &Framebuffer, theme: &Theme, screen_w: u32) {
+        let area = calendar_bounds(screen_w);
+        let (x, y) = (area.x as u32, area.y as u32);
+        let today = self.now;
+
+        fb.fill_rect(x, y, area.width, area.height, theme.window_border);
+        fb.fill_rect(x + 1, y + 1, area.width - 2, area.height - 2, theme.panel_bg);
+
+        // "October 2026"
+        let inner_x = x + CALENDAR_PADDING;
+        let mut row_y = y + CALENDAR_PADDING;
+        let month = MONTH_NAMES[(today.month - 1) as usize];
+        fb.draw_string(inner_x + 4, row_y + 4, month, theme.accent, theme.panel_bg);
+        let mut year = [0u8; 4];
+        let year_text = format_year(today.year, &mut year);
+        let year_x = inner_x + 4 + (month.len() as u32 + 1) * 8;
+        fb.draw_string(year_x, row_y + 4, year_text, theme.accent, theme.panel_bg);
+        row_y += CELL_HEIGHT;
+
+        for (i, name) in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"].iter().enumerate() {
+            let cx = inner_x + i as u32 * CELL_WIDTH + 4;
+            fb.draw_string(cx, row_y + 4, name, theme.text_dim, theme.panel_bg);
+        }
+        row_y += CELL_HEIGHT;
+
+        // Column of the 1st, Monday first
+        let first = days_from_civil(today.year, today.month, 1);
+        let first_column = (first + 3).rem_euclid(7) as u32;
+
+        for day in 1..=days_in_month(today.year, today.month) {
+            let cell = first_column + day as u32 - 1;
+            let cx = inner_x + cell % 7 * CELL_WIDTH;
+            let cy = row_y + cell / 7 * CELL_HEIGHT;
+
+            let (fg, bg) = if day == today.day {
+                (theme.panel_bg, theme.accent)
+            } else {
+                (theme.panel_text, theme.panel_bg)
+            };
+            fb.fill_rect(cx, cy, CELL_WIDTH - 2, CELL_HEIGHT - 2, bg);
+            let digits = [b'0' + day / 10, b'0' + day % 10];
+            let text = core::str::from_utf8(if day < 10 { &digits[1..] } else { &digits })
+                .unwrap_or("?");
+            fb.draw_string(cx + 4, cy + 4, text, fg, bg);
+        }
+    }
+}
+
+/// Clock text area in the panel
+pub fn clock_rect(screen_w: u32) -> Rect {
+    Rect::new(screen_w.saturating_sub(84) as i32, 5, 48, 18)
+}
+
+/// Calendar popup, right-aligned below the clock
+pub fn calendar_bounds(screen_w: u32) -> Rect {
+    let x = screen_w.saturating_sub(CALENDAR_WIDTH + 8);
+    let y = crate::PANEL_HEIGHT as u32 + 4;
+    Rect::new(x as i32, y as i32, CALENDAR_WIDTH, CALENDAR_HEIGHT)
+}
+
+fn format_year(year: i32, buf: &mut [u8; 4]) -> &str {
+    let mut value = year.clamp(0, 9999) as u32;
+    for digit in buf.iter_mut().rev() {
+        *digit = b'0' + (value % 10) as u8;
+        value /= 10;
+    }
+    core::str::from_utf8(buf).unwrap_or("????")
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a Gregorian date
+fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let (month, day) = (month as i64, day as i64);
+    let year = if month <= 2 { year as i64 - 1 } else { year as i64 };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Gregorian date of a day count since 1970-01-01
+fn civil_from_days(days: i64) -> (i32, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u8;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u8;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year as i32, month, day)
+}
@@ -0,0 +1,251 @@
+//! Mouse Cursor
+//!
+//! The cursor is an overlay plane of its own, composited last: a small
+//! ARGB surface of the graphics service, blitted over the screen after the
+//! back buffer and never drawn into it. The plane remembers the area it
+//! covers, so moving the cursor or changing its shape only means blitting
+//! that area again from the back buffer, then the plane at its new place.
+//!
+//! Shapes are bitmaps with `#` for outline, `.` for fill and space for
+//! transparent pixels, drawn in the theme's cursor colours. `x`/`y` is the
+//! hotspot; each shape says where that lies inside its bitmap. On scaled
+//! outputs the bitmap is stretched.
+
+use atom_syscall::SyscallResult;
+use libdisplay::{Display, PixelFormat, Surface};
+use libipc::messages::{CursorShape, Rect, ScaleFactor};
+
+use crate::theme::Theme;
+
+/// Side of the cursor's screen area at 1x; every shape must fit inside it
+const SIZE: u32 = 16;
+
+/// Alpha byte of the plane's drawn pixels; the rest stay clear
+const OPAQUE: u32 = 0xFF00_0000;
+
+const ARROW: [&[u8]; 16] = [
+    b"#         ",
+    b"##        ",
+    b"#.#       ",
+    b"#..#      ",
+    b"#...#     ",
+    b"#....#    ",
+    b"#.....#   ",
+    b"#......#  ",
+    b"#.......# ",
+    b"#........#",
+    b"#....#####",
+    b"#.#.#     ",
+    b"## #.#    ",
+    b"   #.#    ",
+    b"    #.#   ",
+    b"    ##    ",
+];
+
+const IBEAM: [&[u8]; 16] = [
+    b"### ###",
+    b"#..#..#",
+    b"###.###",
+    b"  #.#  ",
+    b"  #.#  ",
+    b"  #.#  ",
+    b"  #.#  ",
+    b"  #.#  ",
+    b"  #.#  ",
+    b"  #.#  ",
+    b"  #.#  ",
+    b"  #.#  ",
+    b"  #.#  ",
+    b"###.###",
+    b"#..#..#",
+    b"### ###",
+];
+
+const HAND: [&[u8]; 16] = [
+    b"    ##         ",
+    b"   #..#        ",
+    b"   #..#        ",
+    b"   #..#        ",
+    b"   #..####     ",
+    b"   #..#..####  ",
+    b"   #..#..#..## ",
+    b"## #........#.#",
+    b"#.##..........#",
+    b"#..#..........#",
+    b" #............#",
+    b"  #...........#",
+    b"  #..........# ",
+    b"   #.........# ",
+    b"    #.......#  ",
+    b"    #########  ",
+];
+
+const HORIZONTAL: [&[u8]; 15] = [
+    b"               ",
+    b"               ",
+    b"               ",
+    b"    #     #    ",
+    b"   ##     ##   ",
+    b"  #.#     #.#  ",
+    b" #..#######..# ",
+    b"#.............#",
+    b" #..#######..# ",
+    b"  #.#     #.#  ",
+    b"   ##     ##   ",
+    b"    #     #    ",
+    b"               ",
+    b"               ",
+    b"               ",
+];
+
+const DIAGONAL: [&[u8]; 15] = [
+    b"#######        ",
+    b"#....#         ",
+    b"#...#          ",
+    b"#...#          ",
+    b"#.##.#         ",
+    b"##  #.#        ",
+    b"#    #.#       ",
+    b"      #.#      ",
+    b"       #.#    #",
+    b"        #.#  ##",
+    b"         #.##.#",
+    b"          #...#",
+    b"          #...#",
+    b"         #....#",
+    b"        #######",
+];
+
+/// Hotspot position inside the bitmap
+fn hotspot(shape: CursorShape) -> (i32, i32) {
+    match shape {
+        CursorShape::Arrow => (0, 0),
+        CursorShape::IBeam => (3, 8),
+        CursorShape::Hand => (4, 0),
+        _ => (7, 7),
+    }
+}
+
+fn size(shape: CursorShape) -> (usize, usize) {
+    match shape {
+        CursorShape::Arrow => (ARROW[0].len(), ARROW.len()),
+        CursorShape::IBeam => (IBEAM[0].len(), IBEAM.len()),
+        CursorShape::Hand => (HAND[0].len(), HAND.len()),
+        _ => (15, 15),
+    }
+}
+
+/// Bitmap cell at (`col`, `row`)
+fn pixel(shape: CursorShape, col: usize, row: usize) -> u8 {
+    match shape {
+        CursorShape::Arrow => ARROW[row][col],
+        CursorShape::IBeam => IBEAM[row][col],
+        CursorShape::Hand => HAND[row][col],
+        CursorShape::ResizeHorizontal => HORIZONTAL[row][col],
+        CursorShape::ResizeVertical => HORIZONTAL[col][row],
+        CursorShape::ResizeDiagonal => DIAGONAL[row][col],
+        CursorShape::ResizeAntiDiagonal => DIAGONAL[row][14 - col],
+    }
+}
+
+/// Surface for the cursor plane, large enough for the cursor at the
+/// largest scale
+pub fn create_plane(display: &Display) -> SyscallResult<Surface> {
+    let side = ScaleFactor::X2.apply(SIZE);
+    display.create_surface(side, side, PixelFormat::Argb32)
+}
+
+pub struct CursorState {
+    pub x: i32,
+    pub y: i32,
+    shape: CursorShape,
+    scale: ScaleFactor,
+    /// Surface from `create_plane` the cursor is drawn into
+    plane: Surface,
+    /// Screen area the plane last covered
+    shown: Option<Rect>,
+}
+
+impl CursorState {
+    pub fn new(width: u32, height: u32, plane: Surface) -> Self {
+        Self {
+            x: (width / 2) as i32,
+            y: (height / 2) as i32,
+            shape: CursorShape::Arrow,
+            scale: ScaleFactor::X1,
+            plane,
+            shown: None,
+        }
+    }
+
+    pub fn apply_delta(&mut self, dx: i32, dy: i32, width: u32, height: u32) {
+        self.x = (self.x + dx).clamp(0, (width - 1) as i32);
+        self.y = (self.y - dy).clamp(0, (height - 1) as i32); // Y inverted in PS/2
+    }
+
+    /// Keep the cursor on a screen that changed size; the screen was
+    /// cleared, so nothing the plane covered needs restoring
+    pub fn fit_screen(&mut self, width: u32, height: u32) {
+        self.x = self.x.clamp(0, width as i32 - 1);
+        self.y = self.y.clamp(0, height as i32 - 1);
+        self.shown = None;
+    }
+
+    /// Change shape; takes effect at the next present
+    pub fn set_shape(&mut self, shape: CursorShape) {
+        self.shape = shape;
+    }
+
+    /// Change the size; takes effect at the next present
+    pub fn set_scale(&mut self, scale: ScaleFactor) {
+        self.scale = scale;
+    }
+
+    /// Top-left corner of the bitmap on screen
+    fn origin(&self) -> (i32, i32) {
+        let (hx, hy) = hotspot(self.shape);
+        (self.x - self.scale.apply_i32(hx), self.y - self.scale.apply_i32(hy))
+    }
+
+    /// Screen area the cursor may cover in its current shape
+    fn bounds(&self) -> Rect {
+        let (ox, oy) = self.origin();
+        let size = self.scale.apply(SIZE);
+        Rect::new(ox, oy, size, size)
+    }
+
+    /// Composite the plane onto the screen: blit the area it covered again
+    /// from the back buffer, then the cursor where it is now. Shows at the
+    /// next present.
+    pub fn present(&mut self, display: &Display, back: &Surface, theme: &Theme) {
+        if let Some(shown) = self.shown.take() {
+            let _ = display.blit(back, 0, 0, &[shown]);
+        }
+        self.draw(theme);
+        let bounds = self.bounds();
+        let plane = Rect::new(0, 0, bounds.width, bounds.height);
+        let _ = display.blit(&self.plane, bounds.x, bounds.y, &[plane]);
+        self.shown = Some(bounds);
+    }
+
+    /// Draw the current shape into the plane, clearing the rest of it
+    fn draw(&self, theme: &Theme) {
+        let (width, height) = size(self.shape);
+        let (width, height) = (self.scale.apply(width as u32), self.scale.apply(height as u32));
+        // Bitmap cell under a plane pixel
+        let cell = |value: u32| ScaleFactor::X1.convert(value as i32, self.scale) as usize;
+        let outline = OPAQUE | theme.cursor_outline.to_bgr32();
+        let fill = OPAQUE | theme.cursor_fill.to_bgr32();
+
+        self.plane.fill(0, 0, self.plane.width(), self.plane.height(), 0);
+        for row in 0..height {
+            for col in 0..width {
+                match pixel(self.shape, cell(col), cell(row)) {
+                    b'#' => self.plane.set_pixel(col, row, outline),
+                    b'.' => self.plane.set_pixel(col, row, fill),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Damage Tracking
+//!
+//! Screen areas that changed since the last frame. Overlapping rectangles
+//! are merged as they arrive; past `MAX_RECTS` everything collapses into a
+//! single bounding rectangle, which costs some overdraw but keeps the
+//! per-frame work bounded.
+
+use alloc::vec::Vec;
+
+use libipc::messages::Rect;
+
+const MAX_RECTS: usize = 8;
+
+pub struct Damage {
+    screen: Rect,
+    rects: Vec<Rect>,
+}
+
+impl Damage {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            screen: Rect::new(0, 0, width, height),
+            rects: Vec::new(),
+        }
+    }
+
+    /// Mark an area (in screen coordinates) for recomposition
+    pub fn add(&mut self, rect: Rect) {
+        let Some(mut rect) = rect.intersection(&self.screen) else {
+            return;
+        };
+
+        // Absorb every rectangle the new one touches; the grown rectangle
+        // may now touch others, so repeat until nothing overlaps
+        while let Some(pos) = self.rects.iter().position(|r| r.intersects(&rect)) {
+            rect = rect.union(&self.rects.swap_remove(pos));
+        }
+        self.rects.push(rect);
+
+        if self.rects.len() > MAX_RECTS {
+            let bounds = self.rects.iter().fold(rect, |acc, r| acc.union(r));
+            self.rects.clear();
+            self.rects.push(bounds);
+        }
+    }
+
+    pub fn add_screen(&mut self) {
+        self.add(self.screen);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    /// Number of pixels damaged
+    pub fn pixels(&self) -> u64 {
+        self.rects.iter().map(|r| r.width as u64 * r.height as u64).sum()
+    }
+
+    /// Damaged areas since the last call, leaving the tracker empty
+    pub fn take(&mut self) -> Vec<Rect> {
+        core::mem::take(&mut self.rects)
+    }
+}
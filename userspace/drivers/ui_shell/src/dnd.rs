@@ -0,0 +1,57 @@
+//! Drag and Drop
+//!
+//! A drag in progress between windows. The compositor only routes it: it
+//! remembers what the source offered and which window is under the
+//! cursor, and draws a small ghost next to the cursor until the button is
+//! released.
+
+use atom_syscall::graphics::Framebuffer;
+use libipc::messages::{ClipboardContent, ClipboardData, ClipboardMime, Rect, WindowId};
+
+use crate::theme::Theme;
+
+const GHOST_WIDTH: u32 = 120;
+const GHOST_HEIGHT: u32 = 20;
+
+/// Ghost position relative to the cursor hotspot
+const GHOST_OFFSET: i32 = 14;
+
+/// Characters of dragged text shown in the ghost
+const PREVIEW_CHARS: usize = ((GHOST_WIDTH - 12) / 8) as usize;
+
+pub struct Drag {
+    pub source: WindowId,
+    pub data: ClipboardData,
+    /// Window under the cursor that was last sent DragEnter
+    pub target: Option<WindowId>,
+    /// Cursor position the ghost was last drawn at
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Drag {
+    pub fn ghost(&self) -> Rect {
+        Rect::new(self.x + GHOST_OFFSET, self.y + GHOST_OFFSET, GHOST_WIDTH, GHOST_HEIGHT)
+    }
+
+    pub fn draw(&self, fb: &Framebuffer, theme: &Theme) {
+        let ghost = self.ghost();
+        let (x, y) = (ghost.x.max(0) as u32, ghost.y.max(0) as u32);
+
+        fb.fill_rect(x, y, GHOST_WIDTH, GHOST_HEIGHT, theme.accent);
+        fb.fill_rect(x + 1, y + 1, GHOST_WIDTH - 2, GHOST_HEIGHT - 2, theme.panel_bg);
+        fb.draw_string(x + 6, y + 6, self.label(), theme.panel_text, theme.panel_bg);
+    }
+
+    /// Start of the dragged text, or its type when it is not inline
+    fn label(&self) -> &str {
+        match (&self.data.mime, &self.data.content) {
+            (ClipboardMime::TextPlain, ClipboardContent::Inline(bytes)) => {
+                let text = core::str::from_utf8(bytes).unwrap_or("text");
+                let end = text.char_indices().nth(PREVIEW_CHARS).map_or(text.len(), |(i, _)| i);
+                &text[..end]
+            }
+            (mime, _) => mime.as_str(),
+        }
+    }
+}
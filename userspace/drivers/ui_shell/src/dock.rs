@@ -0,0 +1,148 @@
+//! Dock
+//!
+//! Bottom bar with the launcher icons followed by one entry per open window.
+//! Clicking a launcher starts its program, or raises the program's window if
+//! it is already running, which a dot below the icon shows.
+//! Window entries act as a task switcher: the focused window is highlighted,
+//! minimized ones and those on other workspaces are dimmed, and clicking an
+//! entry raises or restores it.
+//!
+//! The dock grows with the number of windows and stays centred.
+
+use atom_syscall::graphics::{Color, Framebuffer};
+use libipc::messages::{Rect, WindowId};
+
+use crate::launcher::{self, PROGRAMS};
+use crate::theme::Theme;
+use crate::wm::WindowManager;
+
+pub const DOCK_HEIGHT: u32 = 48;
+
+/// Gap between the dock and the bottom of the screen
+pub const DOCK_MARGIN: u32 = 10;
+
+const ICON_SIZE: u32 = 32;
+const PADDING: u32 = 16;
+
+/// Extra space between the launchers and the window entries
+const SEPARATOR: u32 = 12;
+
+/// What a dock click landed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DockItem {
+    /// Index into `launcher::PROGRAMS`
+    Launcher(usize),
+    Window(WindowId),
+}
+
+/// Top edge of the dock; maximized windows stop above it
+pub fn top(screen_h: u32) -> u32 {
+    screen_h.saturating_sub(DOCK_HEIGHT + DOCK_MARGIN)
+}
+
+/// Strip the dock can occupy, whatever its current width
+pub fn area(screen_w: u32, screen_h: u32) -> Rect {
+    Rect::new(0, top(screen_h) as i32, screen_w, DOCK_HEIGHT)
+}
+
+/// Dock rectangle (x, y, width, height) for the given number of windows
+fn bounds(screen_w: u32, screen_h: u32, windows: usize) -> (u32, u32, u32, u32) {
+    let slots = (PROGRAMS.len() + windows) as u32;
+    let separator = if windows > 0 { SEPARATOR } else { 0 };
+    let width = PADDING + slots * (ICON_SIZE + PADDING) + separator;
+
+    let x = (screen_w / 2).saturating_sub(width / 2);
+    (x, top(screen_h), width, DOCK_HEIGHT)
+}
+
+/// Left edge of the icon in slot `index` (launchers first, then windows)
+fn slot_x(dock_x: u32, index: usize) -> u32 {
+    let separator = if index >= PROGRAMS.len() { SEPARATOR } else { 0 };
+    dock_x + PADDING + index as u32 * (ICON_SIZE + PADDING) + separator
+}
+
+/// Icon rectangle of a window's entry, where it minimizes to
+pub fn entry_rect(wm: &WindowManager, screen_w: u32, screen_h: u32, id: WindowId) -> Option<Rect> {
+    let index = wm.windows.iter().position(|w| w.id == id)?;
+    let (x, y, _, height) = bounds(screen_w, screen_h, wm.windows.len());
+    let ix = slot_x(x, PROGRAMS.len() + index);
+    let icon_y = y + (height - ICON_SIZE) / 2;
+    Some(Rect::new(ix as i32, icon_y as i32, ICON_SIZE, ICON_SIZE))
+}
+
+/// Dock item under the point, if any
+pub fn hit_test(
+    wm: &WindowManager,
+    screen_w: u32,
+    screen_h: u32,
+    px: i32,
+    py: i32,
+) -> Option<DockItem> {
+    let (x, y, width, height) = bounds(screen_w, screen_h, wm.windows.len());
+    if px < x as i32 || py < y as i32 || px >= (x + width) as i32 || py >= (y + height) as i32 {
+        return None;
+    }
+
+    let icon_y = y + (height - ICON_SIZE) / 2;
+    if py < icon_y as i32 || py >= (icon_y + ICON_SIZE) as i32 {
+        return None;
+    }
+
+    let slots = PROGRAMS.len() + wm.windows.len();
+    let index = (0..slots).find(|&i| {
+        let ix = slot_x(x, i) as i32;
+        px >= ix && px < ix + ICON_SIZE as i32
+    })?;
+
+    match index.checked_sub(PROGRAMS.len()) {
+        None => Some(DockItem::Launcher(index)),
+        Some(i) => Some(DockItem::Window(wm.windows[i].id)),
+    }
+}
+
+pub fn draw(fb: &Framebuffer, theme: &Theme, wm: &WindowManager) {
+    let (x, y, width, height) = bounds(fb.width(), fb.height(), wm.windows.len());
+    fb.fill_rect(x, y, width, height, theme.dock_bg);
+
+    let icon_y = y + (height - ICON_SIZE) / 2;
+
+    for (i, program) in PROGRAMS.iter().enumerate() {
+        let ix = slot_x(x, i);
+        fb.fill_rect(ix, icon_y, ICON_SIZE, ICON_SIZE, program.color);
+        fb.draw_string(ix + 8, icon_y + 10, program.label, Color::WHITE, program.color);
+
+        if launcher::is_running(wm, program) {
+            fb.fill_rect(ix + ICON_SIZE / 2 - 2, icon_y + ICON_SIZE + 3, 4, 4, theme.accent);
+        }
+    }
+
+    if wm.windows.is_empty() {
+        return;
+    }
+
+    // Separator line, centred in the gap after the last launcher
+    let line_x = slot_x(x, PROGRAMS.len()) - (PADDING + SEPARATOR) / 2;
+    fb.fill_rect(line_x, icon_y + 4, 1, ICON_SIZE - 8, theme.dock_separator);
+
+    for (i, window) in wm.windows.iter().enumerate() {
+        let ix = slot_x(x, PROGRAMS.len() + i);
+
+        let (color, text) = if window.focused {
+            (theme.window_header_focused, theme.panel_text)
+        } else if window.minimized || window.workspace != wm.active_workspace {
+            (theme.dock_item_minimized, theme.text_dim)
+        } else {
+            (theme.window_header, theme.panel_text)
+        };
+        fb.fill_rect(ix, icon_y, ICON_SIZE, ICON_SIZE, color);
+
+        // First letter of the title
+        let initial = window.title.chars().next().unwrap_or('?').to_ascii_uppercase();
+        let mut buf = [0u8; 4];
+        fb.draw_string(ix + 12, icon_y + 10, initial.encode_utf8(&mut buf), text, color);
+
+        // Running indicator below the icon
+        let indicator = if window.focused { theme.accent } else { theme.text_dim };
+        fb.fill_rect(ix + ICON_SIZE / 2 - 3, icon_y + ICON_SIZE + 3, 6, 2, indicator);
+    }
+}
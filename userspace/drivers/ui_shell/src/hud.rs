@@ -0,0 +1,171 @@
+//! Performance HUD
+//!
+//! Debug overlay below the panel, toggled with Super+Shift+P, for tuning
+//! the compositor. Over each second it collects:
+//!
+//! - frame time: between frames that put something on screen
+//! - compose time: redrawing the damage and copying it to the screen
+//! - damage: pixels recomposed per frame, as a share of the screen
+//! - IPC queues: messages waiting on the compositor's port, and the most
+//!   waiting on any window's port
+//! - input latency: from receiving an input event to the frame showing it
+//!
+//! and shows averages and worst cases. The text changes once a second, so
+//! the overlay itself costs one small redraw per second.
+//!
+//! The timer ticks every 10 ms, too coarse for any of this, so times are
+//! read from the TSC and converted with a rate calibrated against the
+//! timer while the HUD is open.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use atom_syscall::graphics::Framebuffer;
+use libipc::messages::Rect;
+
+use crate::theme::Theme;
+
+const X: u32 = 8;
+/// Below the panel
+const Y: u32 = 36;
+const WIDTH: u32 = 300;
+const LINE_HEIGHT: u32 = 18;
+const PADDING: u32 = 8;
+const LINES: u32 = 6;
+
+/// How often the figures are refreshed, in ms
+const PERIOD_MS: u64 = 1000;
+
+/// Current TSC value
+pub fn now() -> u64 {
+    // SAFETY: RDTSC has no side effects and user mode may run it
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Samples of one quantity over a period
+#[derive(Default, Clone, Copy)]
+struct Stat {
+    count: u64,
+    total: u64,
+    max: u64,
+}
+
+impl Stat {
+    fn add(&mut self, value: u64) {
+        self.count += 1;
+        self.total += value;
+        self.max = self.max.max(value);
+    }
+
+    fn avg(&self) -> u64 {
+        self.total.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+pub struct Hud {
+    /// TSC and timer readings when the HUD opened, to convert cycles
+    calibration: (u64, u64),
+    /// When the current period started, in ms
+    period_start: u64,
+    frame: Stat,
+    compose: Stat,
+    damage: Stat,
+    latency: Stat,
+    /// When the last frame was presented (TSC)
+    last_frame: Option<u64>,
+    /// Earliest input not on screen yet (TSC)
+    pending_input: Option<u64>,
+    lines: Vec<String>,
+}
+
+impl Hud {
+    pub fn new(now_ms: u64) -> Self {
+        Self {
+            calibration: (now(), now_ms),
+            period_start: now_ms,
+            frame: Stat::default(),
+            compose: Stat::default(),
+            damage: Stat::default(),
+            latency: Stat::default(),
+            last_frame: None,
+            pending_input: None,
+            lines: Vec::from([String::from("Measuring...")]),
+        }
+    }
+
+    pub fn bounds() -> Rect {
+        Rect::new(X as i32, Y as i32, WIDTH, PADDING * 2 + LINES * LINE_HEIGHT)
+    }
+
+    /// Note that input arrived at `at` (TSC)
+    pub fn input(&mut self, at: u64) {
+        self.pending_input.get_or_insert(at);
+    }
+
+    /// Note a frame presented at `end` (TSC), composed from `start` with
+    /// `damaged` pixels redrawn
+    pub fn frame(&mut self, start: u64, end: u64, damaged: u64) {
+        if let Some(last) = self.last_frame.replace(end) {
+            self.frame.add(end.saturating_sub(last));
+        }
+        self.compose.add(end.saturating_sub(start));
+        self.damage.add(damaged);
+        if let Some(input) = self.pending_input.take() {
+            self.latency.add(end.saturating_sub(input));
+        }
+    }
+
+    /// Note a frame with nothing to present; frame times only count
+    /// between frames that follow each other
+    pub fn idle(&mut self) {
+        self.last_frame = None;
+    }
+
+    /// Whether the figures are due a refresh at `now_ms`
+    pub fn due(&self, now_ms: u64) -> bool {
+        now_ms.saturating_sub(self.period_start) >= PERIOD_MS
+    }
+
+    /// Start a new period, showing the figures of the one that ended;
+    /// `queues` is what waits on the compositor's port and at most on a
+    /// window's port
+    pub fn refresh(&mut self, now_ms: u64, screen_pixels: u64, queues: (u64, u64)) {
+        let (tsc0, ms0) = self.calibration;
+        let elapsed_us = now_ms.saturating_sub(ms0) * 1000;
+        let cycles_per_us = (now().saturating_sub(tsc0) / elapsed_us.max(1)).max(1);
+        let us = |cycles: u64| cycles / cycles_per_us;
+        let ms = |cycles: u64| {
+            let us = us(cycles);
+            format!("{}.{}", us / 1000, us % 1000 / 100)
+        };
+
+        let (frame, compose, latency) = (self.frame, self.compose, self.latency);
+        let damage = self.damage.avg() * 100 / screen_pixels.max(1);
+        let period_ms = now_ms.saturating_sub(self.period_start).max(1);
+        self.lines = Vec::from([
+            format!("fps      {}", self.compose.count * 1000 / period_ms),
+            format!("frame    {} avg {} max ms", ms(frame.avg()), ms(frame.max)),
+            format!("compose  {} avg {} max us", us(compose.avg()), us(compose.max)),
+            format!("damage   {damage}% of screen"),
+            format!("queues   {} desktop {} window", queues.0, queues.1),
+            format!("latency  {} avg {} max ms", ms(latency.avg()), ms(latency.max)),
+        ]);
+
+        self.period_start = now_ms;
+        self.frame = Stat::default();
+        self.compose = Stat::default();
+        self.damage = Stat::default();
+        self.latency = Stat::default();
+    }
+
+    pub fn draw(&self, fb: &Framebuffer, theme: &Theme) {
+        let area = Self::bounds();
+        fb.fill_rect(X, Y, area.width, area.height, theme.window_border);
+        fb.fill_rect(X + 1, Y + 1, area.width - 2, area.height - 2, theme.panel_bg);
+        for (i, line) in self.lines.iter().enumerate() {
+            let y = Y + PADDING + i as u32 * LINE_HEIGHT;
+            fb.draw_string(X + PADDING, y, line, theme.panel_text, theme.panel_bg);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Keyboard Input
+//!
+//! Turns the kernel's raw scancode stream into `KeyEvent`s for the focused
+//! application. Modifier keys are tracked as state only, exactly like the
+//! keyboard driver; characters use the US layout until the compositor
+//! receives events from the keyboard driver instead.
+
+use atom_syscall::input::scancode_to_ascii;
+use atom_syscall::thread::get_ticks;
+use libipc::keycode::{KeyCode, ScancodeDecoder};
+use libipc::messages::{KeyEvent, KeyModifiers, ShortcutBinding};
+
+pub struct Keyboard {
+    decoder: ScancodeDecoder,
+    shift: bool,
+    ctrl: bool,
+    alt: bool,
+    /// Super (Windows) key; only used for compositor shortcuts
+    meta: bool,
+    /// Super is held and no other key was pressed since
+    meta_alone: bool,
+    /// Super was pressed and released on its own
+    super_tapped: bool,
+    caps_lock: bool,
+}
+
+impl Keyboard {
+    pub const fn new() -> Self {
+        Self {
+            decoder: ScancodeDecoder::new(),
+            shift: false,
+            ctrl: false,
+            alt: false,
+            meta: false,
+            meta_alone: false,
+            super_tapped: false,
+            caps_lock: false,
+        }
+    }
+
+    pub fn modifiers(&self) -> KeyModifiers {
+        KeyModifiers {
+            shift: self.shift,
+            ctrl: self.ctrl,
+            alt: self.alt,
+            caps_lock: self.caps_lock,
+        }
+    }
+
+    pub fn alt(&self) -> bool {
+        self.alt
+    }
+
+    /// Whether Super was tapped on its own since the last call
+    pub fn take_super_tap(&mut self) -> bool {
+        core::mem::take(&mut self.super_tapped)
+    }
+
+    /// Held modifiers as `ShortcutBinding::MOD_*` bits
+    pub fn shortcut_modifiers(&self) -> u8 {
+        let mut bits = 0;
+        if self.shift {
+            bits |= ShortcutBinding::MOD_SHIFT;
+        }
+        if self.ctrl {
+            bits |= ShortcutBinding::MOD_CTRL;
+        }
+        if self.alt {
+            bits |= ShortcutBinding::MOD_ALT;
+        }
+        if self.meta {
+            bits |= ShortcutBinding::MOD_SUPER;
+        }
+        bits
+    }
+
+    /// Process one scancode byte; returns the event and whether the key
+    /// was pressed, or `None` for prefixes and modifier keys
+    pub fn feed(&mut self, scancode: u8) -> Option<(KeyEvent, bool)> {
+        let (keycode, pressed) = self.decoder.feed(scancode)?;
+
+        // Super combined with another key is a shortcut, not a tap
+        if pressed && !matches!(keycode, KeyCode::MetaLeft | KeyCode::MetaRight) {
+            self.meta_alone = false;
+        }
+
+        match keycode {
+            KeyCode::ShiftLeft | KeyCode::ShiftRight => {
+                self.shift = pressed;
+                return None;
+            }
+            KeyCode::ControlLeft | KeyCode::ControlRight => {
+                self.ctrl = pressed;
+                return None;
+            }
+            KeyCode::AltLeft | KeyCode::AltRight => {
+                self.alt = pressed;
+                return None;
+            }
+            KeyCode::MetaLeft | KeyCode::MetaRight => {
+                // Key repeat sends more presses while held
+                if pressed && !self.meta {
+                    self.meta_alone = true;
+                } else if !pressed {
+                    self.super_tapped = self.meta_alone;
+                    self.meta_alone = false;
+                }
+                self.meta = pressed;
+                return None;
+            }
+            KeyCode::CapsLock => {
+                if pressed {
+                    self.caps_lock = !self.caps_lock;
+                }
+                return None;
+            }
+            _ => {}
+        }
+
+        let character = if pressed { self.translate(keycode) } else { 0 };
+        let event = KeyEvent {
+            keycode,
+            character,
+            modifiers: self.modifiers(),
+            timestamp: get_ticks(),
+        };
+        Some((event, pressed))
+    }
+
+    fn translate(&self, key: KeyCode) -> u8 {
+        // Key codes below IntlBackslash are the set 1 make codes
+        let code = key as u8;
+        if code >= KeyCode::IntlBackslash as u8 {
+            return 0;
+        }
+
+        let upper = if is_letter(code) {
+            self.shift ^ self.caps_lock
+        } else {
+            self.shift
+        };
+
+        scancode_to_ascii(code, upper).map(|c| c as u8).unwrap_or(0)
+    }
+}
+
+fn is_letter(code: u8) -> bool {
+    matches!(code, 0x10..=0x19 | 0x1E..=0x26 | 0x2C..=0x32)
+}
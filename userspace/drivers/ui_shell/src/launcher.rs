@@ -0,0 +1,172 @@
+//! Launcher
+//!
+//! Programs the desktop can start, all pinned to the dock, and the overlay
+//! tapping Super opens to search them by name. Programs are started with
+//! `SYS_PROC_SPAWN` by their binary path in the boot manifest. A program
+//! counts as running while a window with its name is open.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use atom_syscall::graphics::{Color, Framebuffer};
+use libipc::messages::Rect;
+
+use crate::theme::Theme;
+use crate::wm::WindowManager;
+
+pub struct Program {
+    /// Also the title of the program's main window
+    pub name: &'static str,
+    /// Binary path declared in the boot manifest
+    pub path: &'static str,
+    /// Dock icon text
+    pub label: &'static str,
+    pub color: Color,
+}
+
+/// Installed programs, in dock order
+pub const PROGRAMS: [Program; 4] = [
+    Program {
+        name: "Files",
+        path: "/apps/files.elf",
+        label: "F",
+        color: Color::new(191, 97, 106),
+    },
+    Program {
+        name: "Settings",
+        path: "/apps/settings.elf",
+        label: "S",
+        color: Color::new(163, 190, 140),
+    },
+    Program {
+        name: "Browser",
+        path: "/apps/browser.elf",
+        label: "B",
+        color: Color::new(94, 129, 172),
+    },
+    Program {
+        name: "Terminal",
+        path: "/apps/terminal.elf",
+        label: ">_",
+        color: Color::new(80, 80, 80),
+    },
+];
+
+const WIDTH: u32 = 320;
+const ROW_HEIGHT: u32 = 24;
+const PADDING: u32 = 8;
+
+/// Search field height, above the result rows
+const QUERY_HEIGHT: u32 = 28;
+
+/// Longest search text, limited to what fits in the field
+const MAX_QUERY_CHARS: usize = ((WIDTH - PADDING * 2 - 16) / 8) as usize;
+
+/// Whether a window of `program` is open
+pub fn is_running(wm: &WindowManager, program: &Program) -> bool {
+    wm.windows.iter().any(|w| w.title == program.name)
+}
+
+/// Search overlay state while it is open
+pub struct Launcher {
+    query: String,
+    /// Index into the current matches
+    selected: usize,
+}
+
+impl Launcher {
+    pub fn new() -> Self {
+        Self {
+            query: String::new(),
+            selected: 0,
+        }
+    }
+
+    /// Programs whose name contains the search text, ignoring case
+    pub fn matches(&self) -> Vec<&'static Program> {
+        let query = self.query.to_ascii_lowercase();
+        PROGRAMS
+            .iter()
+            .filter(|p| p.name.to_ascii_lowercase().contains(query.as_str()))
+            .collect()
+    }
+
+    pub fn selected(&self) -> Option<&'static Program> {
+        self.matches().get(self.selected).copied()
+    }
+
+    pub fn push(&mut self, c: char) {
+        if self.query.len() < MAX_QUERY_CHARS {
+            self.query.push(c);
+            self.selected = 0;
+        }
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.selected = 0;
+    }
+
+    /// Move the selection one row, wrapping around
+    pub fn step(&mut self, forward: bool) {
+        let count = self.matches().len();
+        if count == 0 {
+            return;
+        }
+        self.selected = if forward {
+            (self.selected + 1) % count
+        } else {
+            (self.selected + count - 1) % count
+        };
+    }
+
+    /// Program in the row under the point
+    pub fn program_at(&self, screen_w: u32, screen_h: u32, x: i32, y: i32) -> Option<&'static Program> {
+        let area = bounds(screen_w, screen_h);
+        let rows_top = area.y + (PADDING + QUERY_HEIGHT) as i32;
+        if !area.contains(x, y) || y < rows_top {
+            return None;
+        }
+        let row = ((y - rows_top) / ROW_HEIGHT as i32) as usize;
+        self.matches().get(row).copied()
+    }
+
+    pub fn draw(&self, fb: &Framebuffer, theme: &Theme) {
+        let area = bounds(fb.width(), fb.height());
+        let (x, y) = (area.x as u32, area.y as u32);
+
+        fb.fill_rect(x, y, area.width, area.height, theme.window_border);
+        fb.fill_rect(x + 1, y + 1, area.width - 2, area.height - 2, theme.panel_bg);
+
+        // Search field with a caret after the text
+        let field_w = WIDTH - PADDING * 2;
+        fb.fill_rect(x + PADDING, y + PADDING, field_w, QUERY_HEIGHT - 4, theme.window_bg);
+        let text_x = x + PADDING + 8;
+        let text_y = y + PADDING + 8;
+        if self.query.is_empty() {
+            fb.draw_string(text_x, text_y, "Search programs", theme.text_dim, theme.window_bg);
+        } else {
+            fb.draw_string(text_x, text_y, &self.query, theme.panel_text, theme.window_bg);
+        }
+        let caret_x = text_x + self.query.len() as u32 * 8;
+        fb.fill_rect(caret_x, text_y - 1, 1, 10, theme.accent);
+
+        let rows_y = y + PADDING + QUERY_HEIGHT;
+        for (i, program) in self.matches().iter().enumerate() {
+            let row_y = rows_y + i as u32 * ROW_HEIGHT;
+            let bg = if i == self.selected { theme.window_header_focused } else { theme.panel_bg };
+
+            fb.fill_rect(x + PADDING, row_y, field_w, ROW_HEIGHT, bg);
+            fb.fill_rect(x + PADDING + 4, row_y + 4, 16, 16, program.color);
+            fb.draw_string(x + PADDING + 28, row_y + 8, program.name, theme.panel_text, bg);
+        }
+    }
+}
+
+/// Overlay rectangle, sized for every program and centred on screen
+pub fn bounds(screen_w: u32, screen_h: u32) -> Rect {
+    let height = PADDING * 2 + QUERY_HEIGHT + PROGRAMS.len() as u32 * ROW_HEIGHT;
+    let x = (screen_w / 2).saturating_sub(WIDTH / 2);
+    let y = (screen_h / 2).saturating_sub(height / 2);
+    Rect::new(x as i32, y as i32, WIDTH, height)
+}
@@ -11,13 +11,14 @@
 //!
 //! The desktop environment receives input events from userspace drivers
 //! (keyboard and mouse) via IPC, routes them to the focused application,
-//! and composites window surfaces to the framebuffer.
+//! and composites window surfaces into a surface of the graphics service
+//! (the display driver), which puts it on screen.
 //!
 //! ```text
 //! +----------------+     +----------------+
 //! | Keyboard       |---->|                |
 //! | Driver         |     |                |
-//! +----------------+     |    Desktop     |---> Framebuffer
+//! +----------------+     |    Desktop     |---> Graphics Service
 //!                        |  Environment   |
 //! +----------------+     |                |
 //! | Mouse          |---->|                |
@@ -34,373 +35,2125 @@
 
 extern crate alloc;
 
+mod animation;
+mod capture;
+mod clipboard;
+mod clock;
+mod cursor;
+mod damage;
+mod dnd;
+mod dock;
+mod hud;
+mod keyboard;
+mod launcher;
+mod notifications;
+mod osk;
+mod outputs;
+mod pointer;
+mod scheduler;
+mod session;
+mod shortcuts;
+mod snap;
+mod surface;
+mod switcher;
+mod theme;
+mod wallpaper;
+mod widgets;
+mod wm;
+
 use alloc::string::String;
 use alloc::vec::Vec;
-use core::panic::PanicInfo;
 
-use atom_syscall::graphics::{Color, Framebuffer};
-use atom_syscall::input::{keyboard_poll, MouseDriver};
-use atom_syscall::ipc::{create_port, PortId};
-use atom_syscall::thread::{yield_now, exit};
-use atom_syscall::debug::log;
+use atom_syscall::graphics::Color;
+use atom_syscall::input::{keyboard_poll, MouseDriver, MouseProtocol};
+use atom_syscall::ipc::{create_port, port_stats, PortId};
+use atom_syscall::process;
+use atom_syscall::thread::{get_ticks, get_time, get_time_ms, yield_now, exit};
+use atom_syscall::debug::log;
+use atom_syscall::env;
+
+use libdisplay::{Display, PixelFormat, Surface};
+use libipc::keycode::KeyCode;
+use libipc::messages::{
+    CaptureRequest, CaptureResult, CaptureStatus, ClipboardChanged, ClipboardData, ClipboardMime,
+    ClipboardRequest, CommitFrame, CreateWindowRequest, CursorShape, DestroyWindow, DisplayList,
+    DisplayMode, DragEnd, DragEvent, DragStart, DropEvent, FrameDone, Hello, HelloAck,
+    MessageHeader, MessageType, ModeChanged, MouseScrollEvent, Notification,
+    NotificationHistory, PanelWidget, PointerSettings, ReattachRequest, Rect, ScaleFactor,
+    SetModeRequest, SetWallpaper, ShortcutAction,
+    ShortcutBinding, SurfaceRegion, ThemeSpec, Urgency, WindowCursor, WindowEventMsg, WindowId,
+    WindowOpacity, WindowResize, WindowRole, WindowTitle,
+};
+use libipc::protocol::{get_payload, send_message_async, try_recv_message};
+use libipc::discovery;
+use libipc::{ServiceId, MAX_MESSAGE_SIZE};
+
+use animation::{Animator, Effect, Frame};
+use capture::Screenshot;
+use clipboard::Clipboard;
+use clock::Clock;
+use cursor::CursorState;
+use damage::Damage;
+use dnd::Drag;
+use hud::Hud;
+use keyboard::Keyboard;
+use launcher::{Launcher, Program, PROGRAMS};
+use notifications::Notifications;
+use osk::Osk;
+use outputs::{Output, Outputs};
+use pointer::PointerAccel;
+use scheduler::FrameClock;
+use session::Session;
+use shortcuts::Shortcuts;
+use snap::{SnapZone, Tiling};
+use surface::{Blend, WindowSurface};
+use theme::Theme;
+use wallpaper::Wallpaper;
+use widgets::PanelWidgets;
+use dock::DockItem;
+use wm::{
+    TitleButton, Window, WindowManager, EDGE_BOTTOM, EDGE_LEFT, EDGE_RIGHT, EDGE_TOP,
+    MIN_WINDOW_HEIGHT, MIN_WINDOW_WIDTH, SHADOW_OFFSET, SHADOW_RADIUS, WORKSPACE_COUNT,
+};
+
+// ============================================================================
+// Layout
+// ============================================================================
+
+/// Height of the top panel; windows cannot be dragged above it
+const PANEL_HEIGHT: i32 = 28;
+
+/// Width of one box in the panel's workspace indicator
+const WORKSPACE_SLOT: u32 = 22;
+
+/// Do-not-disturb toggle in the panel, left of the clock
+fn do_not_disturb_button(screen_w: u32) -> Rect {
+    Rect::new(screen_w.saturating_sub(128) as i32, 5, 36, 18)
+}
+
+/// On-screen keyboard toggle in the panel, left of do-not-disturb
+fn keyboard_button(screen_w: u32) -> Rect {
+    Rect::new(do_not_disturb_button(screen_w).x - 44, 5, 36, 18)
+}
+
+/// Right edge of the panel widgets, left of the keyboard toggle
+fn widgets_right(screen_w: u32) -> u32 {
+    (keyboard_button(screen_w).x as u32).saturating_sub(8)
+}
+
+// ============================================================================
+// Compositor
+// ============================================================================
+
+/// A pointer grab in progress (left button held)
+#[derive(Clone, Copy)]
+enum Grab {
+    /// Title-bar drag
+    Move {
+        id: WindowId,
+        /// Cursor position relative to the window origin when grabbed
+        grab_x: i32,
+        grab_y: i32,
+    },
+    /// Edge or corner resize
+    Resize {
+        id: WindowId,
+        edges: u8,
+        /// Cursor position when grabbed
+        start_x: i32,
+        start_y: i32,
+        /// Window geometry when grabbed
+        origin: (i32, i32, u32, u32),
+    },
+}
+
+fn resize_cursor(edges: u8) -> CursorShape {
+    match edges {
+        0 => CursorShape::Arrow,
+        EDGE_LEFT | EDGE_RIGHT => CursorShape::ResizeHorizontal,
+        EDGE_TOP | EDGE_BOTTOM => CursorShape::ResizeVertical,
+        e if e == EDGE_LEFT | EDGE_TOP || e == EDGE_RIGHT | EDGE_BOTTOM => {
+            CursorShape::ResizeDiagonal
+        }
+        _ => CursorShape::ResizeAntiDiagonal,
+    }
+}
+
+/// New (x, y, width, height) after dragging `edges` of `origin` by (dx, dy).
+///
+/// The opposite edge stays fixed; size is clamped to the minimum and the
+/// window is kept below the panel and inside the screen.
+fn resize_geometry(
+    origin: (i32, i32, u32, u32),
+    edges: u8,
+    dx: i32,
+    dy: i32,
+    screen_w: i32,
+    screen_h: i32,
+) -> (i32, i32, u32, u32) {
+    let (x, y, w, h) = origin;
+    let (mut left, mut top) = (x, y);
+    let (mut right, mut bottom) = (x + w as i32, y + h as i32);
+
+    if edges & EDGE_LEFT != 0 {
+        left = (x + dx).clamp(0, (right - MIN_WINDOW_WIDTH as i32).max(0));
+    }
+    if edges & EDGE_RIGHT != 0 {
+        let min_right = left + MIN_WINDOW_WIDTH as i32;
+        right = (right + dx).clamp(min_right, screen_w.max(min_right));
+    }
+    if edges & EDGE_TOP != 0 {
+        top = (y + dy).clamp(PANEL_HEIGHT, (bottom - MIN_WINDOW_HEIGHT as i32).max(PANEL_HEIGHT));
+    }
+    if edges & EDGE_BOTTOM != 0 {
+        let min_bottom = top + MIN_WINDOW_HEIGHT as i32;
+        bottom = (bottom + dy).clamp(min_bottom, screen_h.max(min_bottom));
+    }
+
+    (left, top, (right - left) as u32, (bottom - top) as u32)
+}
+
+/// `geometry` moved, and shrunk if it has to be, to lie on a `desktop_w` x
+/// `desktop_h` desktop below the panel
+fn fit_desktop(
+    geometry: (i32, i32, u32, u32),
+    desktop_w: u32,
+    desktop_h: u32,
+) -> (i32, i32, u32, u32) {
+    let (x, y, width, height) = geometry;
+    let width = width.clamp(MIN_WINDOW_WIDTH, desktop_w);
+    let height = height.clamp(MIN_WINDOW_HEIGHT, desktop_h - PANEL_HEIGHT as u32);
+    let x = x.clamp(0, (desktop_w - width) as i32);
+    let y = y.clamp(PANEL_HEIGHT, desktop_h as i32 - height as i32);
+    (x, y, width, height)
+}
+
+/// One output per head the graphics service lists; `None` if it lists
+/// none
+fn head_outputs(display: &Display) -> Option<Outputs> {
+    let heads = display.heads().ok().filter(|heads| !heads.is_empty())?;
+    let sizes: Vec<_> = heads.iter().map(|head| (head.width, head.height)).collect();
+    Some(Outputs::new(&sizes))
+}
+
+/// Ask the owner of `window` to redraw at its current size
+fn request_resize(window: &Window) {
+    if let Some(port) = window.event_port {
+        let (_, _, width, height) = window.client_rect();
+        let to_surface = |value: u32| window.surface_scale.convert(value as i32, window.scale);
+        let (width, height) = (to_surface(width) as u32, to_surface(height) as u32);
+        let event = WindowEventMsg::resize_requested(window.id, width, height);
+        let _ = send_message_async(port, MessageType::WindowEvent, &event.to_bytes());
+    }
+}
+
+struct Compositor {
+    /// Connection to the graphics service, which owns the screen
+    display: Display,
+    /// Off-screen frame everything except the cursor is composed into;
+    /// finished areas are blitted to the screen from here
+    back: Surface,
+    wm: WindowManager,
+    outputs: Outputs,
+    cursor: CursorState,
+    mouse: MouseDriver,
+    accel: PointerAccel,
+    keyboard: Keyboard,
+    shortcuts: Shortcuts,
+    /// Selected switcher entry while Alt+Tab is held
+    switcher: Option<usize>,
+    /// Program search overlay, open after tapping Super
+    launcher: Option<Launcher>,
+    /// On-screen keyboard, while shown
+    osk: Option<Osk>,
+    /// Performance overlay, while shown
+    hud: Option<Hud>,
+    event_port: PortId,
+    grab: Option<Grab>,
+    /// Drop zone shown while a window is dragged against a screen edge,
+    /// with the work area of the output it snaps into
+    snap_preview: Option<(SnapZone, (i32, i32, u32, u32))>,
+    /// Window resized this frame whose owner has not been told yet
+    resized: Option<WindowId>,
+    /// Screen areas to recompose at the next frame
+    damage: Damage,
+    /// Pointer moved or changed shape since the last frame
+    cursor_moved: bool,
+    /// Window whose client area the pointer is over
+    pointer_window: Option<WindowId>,
+    frames: FrameClock,
+    animator: Animator,
+    clipboard: Clipboard,
+    /// Drag-and-drop in progress between windows
+    drag: Option<Drag>,
+    /// Last PrintScreen capture, waiting to be saved
+    screenshot: Option<Screenshot>,
+    notifications: Notifications,
+    clock: Clock,
+    /// Status text services publish to the panel
+    widgets: PanelWidgets,
+    /// Background image, plain `desktop_bg` without one
+    wallpaper: Option<Wallpaper>,
+    theme: Theme,
+    /// Where applications' windows were when they last closed
+    session: Session,
+}
+
+impl Compositor {
+    fn new(display: Display, back: Surface, cursor_plane: Surface) -> Self {
+        let width = back.width();
+        let height = back.height();
+        let outputs = head_outputs(&display).unwrap_or_else(|| Outputs::new(&[(width, height)]));
+
+        // Create IPC port for receiving events
+        let event_port = create_port().expect("Failed to create event port");
+        if discovery::register_service(ServiceId::Desktop, event_port).is_err() {
+            log("Desktop: Could not register the desktop service");
+        }
+
+        Self {
+            display,
+            back,
+            wm: WindowManager::new(),
+            outputs,
+            cursor: CursorState::new(width, height, cursor_plane),
+            mouse: MouseDriver::new(),
+            accel: PointerAccel::new(),
+            keyboard: Keyboard::new(),
+            shortcuts: Shortcuts::new(),
+            switcher: None,
+            launcher: None,
+            osk: None,
+            hud: None,
+            event_port,
+            grab: None,
+            snap_preview: None,
+            resized: None,
+            damage: Damage::new(width, height),
+            cursor_moved: false,
+            pointer_window: None,
+            frames: FrameClock::new(get_time_ms()),
+            animator: Animator::new(),
+            clipboard: Clipboard::new(),
+            drag: None,
+            screenshot: None,
+            notifications: Notifications::new(),
+            clock: Clock::new(get_time()),
+            widgets: PanelWidgets::new(),
+            wallpaper: None,
+            theme: Theme::new(ThemeSpec::NORD),
+            session: Session::load(),
+        }
+    }
+
+    fn run(&mut self) -> ! {
+        log("Desktop: Starting compositor");
+
+        // Create initial windows
+        self.wm.create_window("Welcome to Atom", 100, 100, 400, 300);
+        self.wm.create_window("Terminal", 150, 150, 500, 350);
+
+        // Initial draw
+        self.damage.add_screen();
+        self.compose();
+
+        // Scroll wheel and buttons 4/5, if the mouse supports them
+        match self.mouse.enable_extensions() {
+            MouseProtocol::FiveButton => log("Desktop: Mouse has wheel and 5 buttons"),
+            MouseProtocol::Wheel => log("Desktop: Mouse has wheel"),
+            MouseProtocol::Standard => {}
+        }
+
+        log("Desktop: Entering event loop");
+
+        let mut prev_left = false;
+
+        loop {
+            // Process mouse events. Position and clicks are tracked per
+            // packet, but the cursor is redrawn at most once per frame.
+            while let Some(event) = self.mouse.poll_event() {
+                self.cursor_moved = true;
+                self.note_input();
+                let (dx, dy) = self.accel.apply(event.dx, event.dy);
+                let (desktop_w, desktop_h) = self.outputs.desktop_size();
+                self.cursor.apply_delta(dx, dy, desktop_w, desktop_h);
+
+                // Handle click, then drag while the button stays down
+                if event.left_button && !prev_left {
+                    self.handle_click(self.cursor.x, self.cursor.y);
+                }
+                if event.left_button {
+                    self.handle_drag(self.cursor.x, self.cursor.y);
+                    self.move_drag();
+                } else {
+                    self.end_grab();
+                    self.finish_drag();
+                }
+                prev_left = event.left_button;
+
+                self.update_cursor_shape();
+                self.update_pointer_window();
+
+                if event.wheel != 0 {
+                    self.handle_scroll(event.wheel);
+                }
+            }
+
+            self.notify_resize();
+
+            // Process keyboard events
+            while let Some(scancode) = keyboard_poll() {
+                self.note_input();
+                self.handle_key(scancode);
+            }
+
+            self.handle_messages();
+
+            if self.notifications.expire(get_ticks()) {
+                self.damage.add(self.notifications.area(self.back.width()));
+            }
+
+            if self.clock.tick(get_time()) {
+                self.damage.add(clock::clock_rect(self.back.width()));
+                if self.clock.calendar_open {
+                    self.damage.add(clock::calendar_bounds(self.back.width()));
+                }
+            }
+
+            // Running animations redraw everything they move over
+            if self.animator.is_active() {
+                for area in self.animator.advance(get_ticks(), &self.wm) {
+                    self.damage.add(area);
+                }
+            }
+
+            if self.frames.due(get_time_ms()) {
+                self.present_frame();
+            }
+
+            yield_now();
+        }
+    }
+
+    fn handle_click(&mut self, x: i32, y: i32) {
+        // The launcher takes the click; clicking outside closes it
+        if let Some(launcher) = self.launcher.take() {
+            self.damage.add(launcher::bounds(self.back.width(), self.back.height()));
+            if let Some(program) = launcher.program_at(self.back.width(), self.back.height(), x, y) {
+                self.open_program(program);
+            }
+            return;
+        }
+
+        // The on-screen keyboard types into the focused window without
+        // taking focus
+        if let Some(osk) = &mut self.osk {
+            let (screen_w, screen_h) = (self.back.width(), self.back.height());
+            if osk::bounds(screen_w, screen_h).contains(x, y) {
+                for scancode in osk.click(screen_w, screen_h, x, y) {
+                    self.handle_key(scancode);
+                }
+                self.damage.add(osk::bounds(screen_w, screen_h));
+                return;
+            }
+        }
+
+        // Clicking the clock toggles the calendar, clicking elsewhere closes it
+        let on_clock = clock::clock_rect(self.back.width()).contains(x, y);
+        if on_clock || self.clock.calendar_open {
+            let on_calendar =
+                self.clock.calendar_open && clock::calendar_bounds(self.back.width()).contains(x, y);
+            if !on_calendar {
+                self.toggle_calendar();
+            }
+            if on_clock || on_calendar {
+                return;
+            }
+        }
+
+        // Clicking a toast dismisses it
+        if self.notifications.dismiss_at(self.back.width(), x, y) {
+            self.damage.add(self.notifications.area(self.back.width()));
+            return;
+        }
+
+        if do_not_disturb_button(self.back.width()).contains(x, y) {
+            self.set_do_not_disturb(!self.notifications.do_not_disturb);
+            return;
+        }
+
+        if keyboard_button(self.back.width()).contains(x, y) {
+            self.toggle_osk();
+            return;
+        }
+
+        // The dock sits above the windows
+        if let Some(item) = dock::hit_test(&self.wm, self.back.width(), self.back.height(), x, y) {
+            match item {
+                DockItem::Window(id) => self.activate(id),
+                DockItem::Launcher(index) => self.open_program(&PROGRAMS[index]),
+            }
+            return;
+        }
+
+        // Check if clicking on a window
+        if let Some(id) = self.wm.window_at(x, y) {
+            // A window with a modal dialog open only brings the dialog forward
+            let target = self.wm.modal_target(id);
+            if target != id {
+                self.change_windows(target, |wm| wm.focus_window(target));
+                return;
+            }
+
+            if self.wm.focused_id != Some(id) {
+                self.change_windows(id, |wm| wm.focus_window(id));
+            }
+
+            if let Some(w) = self.wm.windows.iter().find(|w| w.id == id) {
+                let edges = w.edges_at(x, y);
+                match w.button_at(x, y) {
+                    Some(TitleButton::Close) => self.request_close(id),
+                    Some(TitleButton::Minimize) => self.minimize(id),
+                    Some(TitleButton::Maximize) => self.toggle_maximize(id),
+                    None if edges != 0 && !w.is_tiled() => {
+                        self.grab = Some(Grab::Resize {
+                            id,
+                            edges,
+                            start_x: x,
+                            start_y: y,
+                            origin: w.geometry(),
+                        });
+                    }
+                    None if w.header_contains(x, y) => {
+                        self.grab = Some(Grab::Move {
+                            id,
+                            grab_x: x - w.x,
+                            grab_y: y - w.y,
+                        });
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    /// Area of `output` below its panel, and above the dock on the primary
+    /// output, that maximized and snapped windows fill
+    fn work_area(&self, output: &Output) -> (i32, i32, u32, u32) {
+        let rect = output.rect;
+        let work_top = (rect.y + PANEL_HEIGHT) as u32;
+        let bottom = match output.primary {
+            true => dock::top(self.back.height()),
+            false => rect.bottom() as u32,
+        };
+        let work_bottom = bottom.max(work_top + MIN_WINDOW_HEIGHT);
+        (rect.x, work_top as i32, rect.width, work_bottom - work_top)
+    }
+
+    /// Work area of the output showing most of `window`
+    fn window_work_area(&self, window: &Window) -> (i32, i32, u32, u32) {
+        let (x, y, width, height) = window.geometry();
+        self.work_area(self.outputs.of(&Rect::new(x, y, width, height)))
+    }
+
+    /// Maximize to the work area, or restore the saved geometry if already
+    /// maximized
+    fn toggle_maximize(&mut self, id: WindowId) {
+        let Some(window) = self.wm.windows.iter().find(|w| w.id == id) else {
+            return;
+        };
+        let work_area = self.window_work_area(window);
+        if window.geometry() == work_area {
+            self.restore_geometry(id);
+        } else {
+            self.tile(id, work_area);
+        }
+    }
+
+    /// Move a window into a tiled position, remembering its floating
+    /// geometry the first time
+    fn tile(&mut self, id: WindowId, geometry: (i32, i32, u32, u32)) {
+        self.change_windows(id, |wm| {
+            if let Some(window) = wm.get_mut(id) {
+                if window.saved_geometry.is_none() {
+                    window.saved_geometry = Some(window.geometry());
+                }
+                window.set_geometry(geometry);
+            }
+        });
+        self.resized = Some(id);
+    }
+
+    /// Return a tiled window to its floating geometry
+    fn restore_geometry(&mut self, id: WindowId) {
+        self.change_windows(id, |wm| {
+            if let Some(window) = wm.get_mut(id) {
+                if let Some(saved) = window.saved_geometry.take() {
+                    window.set_geometry(saved);
+                }
+            }
+        });
+        self.resized = Some(id);
+    }
+
+    /// Apply a window-manager change to `id`, damaging the window, the
+    /// windows stacked with it and the focused window both before and
+    /// after, plus the dock
+    fn change_windows(&mut self, id: WindowId, change: impl FnOnce(&mut WindowManager)) {
+        let focused = self.wm.focused_id;
+        for member in self.wm.family(id) {
+            self.damage_window(Some(member));
+        }
+        self.damage_window(focused);
+
+        change(&mut self.wm);
+
+        for member in self.wm.family(id) {
+            self.damage_window(Some(member));
+        }
+        self.damage_window(self.wm.focused_id);
+        self.damage.add(dock::area(self.back.width(), self.back.height()));
+
+        self.notify_focus(focused);
+    }
+
+    fn damage_window(&mut self, id: Option<WindowId>) {
+        let Some(id) = id else {
+            return;
+        };
+        if let Some(window) = self.wm.windows.iter().find(|w| w.id == id) {
+            self.damage.add(window.bounds());
+        }
+    }
+
+    /// Apply the current grab (move or resize) at the new cursor position
+    fn handle_drag(&mut self, x: i32, y: i32) {
+        let (desktop_w, desktop_h) = self.outputs.desktop_size();
+        let (screen_w, screen_h) = (desktop_w as i32, desktop_h as i32);
+
+        let (id, geometry) = match self.grab {
+            None => return,
+            Some(Grab::Move { id, mut grab_x, grab_y }) => {
+                let Some(window) = self.wm.get_mut(id) else {
+                    self.grab = None;
+                    return;
+                };
+
+                // Dragging a snapped window away restores its floating size,
+                // keeping the cursor at the same relative spot in the title bar
+                if window.is_tiled() && (x - window.x, y - window.y) != (grab_x, grab_y) {
+                    if let Some((_, _, width, height)) = window.saved_geometry.take() {
+                        grab_x = grab_x * width as i32 / window.width.max(1) as i32;
+                        self.damage.add(window.bounds());
+                        window.set_geometry((window.x, window.y, width, height));
+                        self.grab = Some(Grab::Move { id, grab_x, grab_y });
+                        self.resized = Some(id);
+                    }
+                }
+
+                let max_x = (screen_w - window.width as i32).max(0);
+                let max_y = (screen_h - window.height as i32).max(PANEL_HEIGHT);
+                let new_x = (x - grab_x).clamp(0, max_x);
+                let new_y = (y - grab_y).clamp(PANEL_HEIGHT, max_y);
+                let geometry = (new_x, new_y, window.width, window.height);
+
+                // Snap zones are the edges of the output under the pointer
+                let output = *self.outputs.at(x, y);
+                let rect = output.rect;
+                let zone = snap::zone_at(x - rect.x, y - rect.y, rect.width, rect.height);
+                let work_area = self.work_area(&output);
+                self.set_snap_preview(zone.map(|zone| (zone, work_area)));
+                (id, geometry)
+            }
+            Some(Grab::Resize { id, edges, start_x, start_y, origin }) => {
+                (id, resize_geometry(origin, edges, x - start_x, y - start_y, screen_w, screen_h))
+            }
+        };
+
+        let Some(window) = self.wm.get_mut(id) else {
+            self.grab = None;
+            return;
+        };
+
+        let (new_x, new_y, new_w, new_h) = geometry;
+        if (new_x, new_y, new_w, new_h) == (window.x, window.y, window.width, window.height) {
+            return;
+        }
+
+        if (new_w, new_h) != (window.width, window.height) {
+            self.resized = Some(id);
+        }
+
+        let old = window.bounds();
+        window.set_geometry(geometry);
+        let new = window.bounds();
+        self.damage.add(old);
+        self.damage.add(new);
+    }
+
+    /// Release the pointer grab, snapping a dragged window if it was dropped
+    /// on a screen edge
+    fn end_grab(&mut self) {
+        let grab = self.grab.take();
+        let preview = self.snap_preview;
+        self.set_snap_preview(None);
+
+        if let (Some(Grab::Move { id, .. }), Some((zone, work_area))) = (grab, preview) {
+            self.tile(id, snap::geometry(zone, work_area));
+        }
+    }
+
+    fn set_snap_preview(&mut self, preview: Option<(SnapZone, (i32, i32, u32, u32))>) {
+        if preview == self.snap_preview {
+            return;
+        }
+
+        for (zone, work_area) in [self.snap_preview, preview].into_iter().flatten() {
+            self.damage.add(snap::preview(zone, work_area));
+        }
+        self.snap_preview = preview;
+    }
+
+    /// Show a resize cursor while resizing, otherwise the shape for what is
+    /// under the pointer
+    fn update_cursor_shape(&mut self) {
+        let shape = match self.grab {
+            Some(Grab::Resize { edges, .. }) => resize_cursor(edges),
+            Some(Grab::Move { .. }) => CursorShape::Arrow,
+            None if self.drag.is_some() => CursorShape::Arrow,
+            None => self.hover_shape(self.cursor.x, self.cursor.y),
+        };
+        self.cursor.set_shape(shape);
+    }
+
+    /// Hand over things that react to a click, resize arrows on window
+    /// edges, and the shape the application asked for over its client area
+    fn hover_shape(&self, x: i32, y: i32) -> CursorShape {
+        let (screen_w, screen_h) = (self.back.width(), self.back.height());
+        if let Some(launcher) = &self.launcher {
+            return match launcher.program_at(screen_w, screen_h, x, y) {
+                Some(_) => CursorShape::Hand,
+                None => CursorShape::Arrow,
+            };
+        }
+
+        let on_key = self.osk.as_ref().is_some_and(|osk| osk.key_at(screen_w, screen_h, x, y));
+        let clickable = on_key
+            || clock::clock_rect(screen_w).contains(x, y)
+            || do_not_disturb_button(screen_w).contains(x, y)
+            || keyboard_button(screen_w).contains(x, y)
+            || dock::hit_test(&self.wm, screen_w, screen_h, x, y).is_some();
+        if clickable {
+            return CursorShape::Hand;
+        }
+
+        let under = self.wm.window_at(x, y);
+        let Some(window) = under.and_then(|id| self.wm.windows.iter().find(|w| w.id == id)) else {
+            return CursorShape::Arrow;
+        };
+        let edges = window.edges_at(x, y);
+        if edges != 0 && !window.is_tiled() {
+            resize_cursor(edges)
+        } else if window.button_at(x, y).is_some() {
+            CursorShape::Hand
+        } else if window.header_contains(x, y) {
+            CursorShape::Arrow
+        } else {
+            window.cursor
+        }
+    }
+
+    /// Tell applications when the pointer moves onto or off their window's
+    /// client area
+    fn update_pointer_window(&mut self) {
+        let (x, y) = (self.cursor.x, self.cursor.y);
+        let under = self.wm.window_at(x, y).filter(|&id| {
+            self.wm.windows.iter().any(|w| {
+                let (cx, cy, cw, ch) = w.client_rect();
+                w.id == id && Rect::new(cx, cy, cw, ch).contains(x, y)
+            })
+        });
+        if under == self.pointer_window {
+            return;
+        }
+
+        let previous = core::mem::replace(&mut self.pointer_window, under);
+        for (id, entered) in [(previous, false), (under, true)] {
+            let Some(window) = id.and_then(|id| self.wm.windows.iter().find(|w| w.id == id)) else {
+                continue;
+            };
+            let Some(port) = window.event_port else {
+                continue;
+            };
+            let event = if entered {
+                let (x, y) = window.to_surface(x, y);
+                WindowEventMsg::pointer_enter(window.id, x, y)
+            } else {
+                WindowEventMsg::pointer_leave(window.id)
+            };
+            let _ = send_message_async(port, MessageType::WindowEvent, &event.to_bytes());
+        }
+    }
+
+    /// Tell the owner of a resized window its new size, once per frame
+    fn notify_resize(&mut self) {
+        let Some(id) = self.resized.take() else {
+            return;
+        };
+        if let Some(window) = self.wm.windows.iter().find(|w| w.id == id) {
+            request_resize(window);
+        }
+    }
+
+    /// Remove a window, fading it out
+    fn close_window(&mut self, id: WindowId) {
+        if let Some(layer) = self.wm.windows.iter().position(|w| w.id == id) {
+            self.session.remember(&self.wm.windows[layer], layer);
+        }
+
+        let mut closed = None;
+        self.change_windows(id, |wm| closed = wm.close_window(id));
+
+        if let Some(window) = closed {
+            self.animator.start(id, Effect::Close(window), get_ticks());
+        }
+    }
+
+    /// Minimize a window, shrinking it into its dock entry
+    fn minimize(&mut self, id: WindowId) {
+        let from = self.wm.windows.iter().find(|w| w.id == id).map(|w| {
+            let (x, y, width, height) = w.geometry();
+            Rect::new(x, y, width, height)
+        });
+        self.change_windows(id, |wm| wm.minimize(id));
+
+        let to = dock::entry_rect(&self.wm, self.back.width(), self.back.height(), id);
+        if let (Some(from), Some(to)) = (from, to) {
+            self.animator.start(id, Effect::Minimize { from, to }, get_ticks());
+        }
+    }
+
+    /// Handle requests sent to the compositor's own port
+    fn handle_messages(&mut self) {
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+
+        while let Ok(Some((header, len))) = try_recv_message(self.event_port, &mut buffer) {
+            let payload = get_payload(&buffer, len);
+
+            match header.msg_type {
+                MessageType::Hello => {
+                    if let Some(hello) = Hello::from_bytes(payload) {
+                        let ack = HelloAck { version: hello.negotiate().unwrap_or(0) };
+                        let _ = send_message_async(
+                            hello.reply_port,
+                            MessageType::HelloAck,
+                            &ack.to_bytes(),
+                        );
+                    }
+                }
+                MessageType::CreateWindow => {
+                    if let Some(request) = CreateWindowRequest::from_bytes(payload) {
+                        self.create_client_window(&request);
+                    }
+                }
+                MessageType::ReattachWindow => {
+                    if let Some(request) = ReattachRequest::from_bytes(payload) {
+                        self.reattach_client_window(&request);
+                    }
+                }
+                MessageType::ResizeWindow => {
+                    if let Some(request) = WindowResize::from_bytes(payload) {
+                        self.resize_client_window(&request);
+                    }
+                }
+                MessageType::CommitFrame => {
+                    if let Some(commit) = CommitFrame::from_bytes(payload) {
+                        self.damage_commit(&commit);
+                    }
+                }
+                MessageType::DestroyWindow => {
+                    if let Some(msg) = DestroyWindow::from_bytes(payload) {
+                        self.close_window(msg.window_id);
+                    }
+                }
+                MessageType::SetTitle => {
+                    if let Some(msg) = WindowTitle::from_bytes(payload) {
+                        self.set_window_title(msg);
+                    }
+                }
+                MessageType::SetClipboard => {
+                    if let Some(msg) = ClipboardData::from_bytes(payload) {
+                        self.set_clipboard(&msg);
+                    }
+                }
+                MessageType::GetClipboard => {
+                    if let Some(request) = ClipboardRequest::from_bytes(payload) {
+                        let reply = self.clipboard.get(request.mime).to_bytes();
+                        let port = request.reply_port;
+                        let _ = send_message_async(port, MessageType::ClipboardData, &reply);
+                    }
+                }
+                MessageType::DragStart => {
+                    if let Some(start) = DragStart::from_bytes(payload) {
+                        self.start_drag(start);
+                    }
+                }
+                MessageType::CaptureScreen | MessageType::CaptureWindow => {
+                    if let Some(request) = CaptureRequest::from_bytes(payload) {
+                        let window = header.msg_type == MessageType::CaptureWindow;
+                        self.handle_capture(&request, window);
+                    }
+                }
+                MessageType::Notify => {
+                    if let Some((notification, _)) = Notification::from_bytes(payload) {
+                        if self.notifications.post(notification, get_ticks()) {
+                            self.damage.add(self.notifications.area(self.back.width()));
+                        }
+                    }
+                }
+                MessageType::GetNotificationHistory => {
+                    if let Some(port) = payload.get(..8).and_then(|b| b.try_into().ok()) {
+                        self.send_notification_history(u64::from_le_bytes(port));
+                    }
+                }
+                MessageType::SetPanelWidget => {
+                    if let Some(widget) = PanelWidget::from_bytes(payload) {
+                        if self.widgets.set(widget) {
+                            self.damage.add(PanelWidgets::area(widgets_right(self.back.width())));
+                        }
+                    }
+                }
+                MessageType::RemovePanelWidget => {
+                    if let Some(id) = payload.get(..4).and_then(|b| b.try_into().ok()) {
+                        if self.widgets.remove(u32::from_le_bytes(id)) {
+                            self.damage.add(PanelWidgets::area(widgets_right(self.back.width())));
+                        }
+                    }
+                }
+                MessageType::SetWallpaper => {
+                    if let Some(request) = SetWallpaper::from_bytes(payload) {
+                        self.set_wallpaper(&request);
+                    }
+                }
+                MessageType::ClearWallpaper if self.wallpaper.is_some() => {
+                    self.wallpaper = None;
+                    self.damage.add_screen();
+                }
+                MessageType::GetDisplays => {
+                    if let Some(port) = payload.get(..8).and_then(|b| b.try_into().ok()) {
+                        let list = DisplayList { displays: self.outputs.info(self.wm.scale) };
+                        let reply = list.to_bytes();
+                        let port = u64::from_le_bytes(port);
+                        let _ = send_message_async(port, MessageType::DisplayList, &reply);
+                    }
+                }
+                MessageType::SetScale => {
+                    if let Some(scale) = payload.first().copied().and_then(ScaleFactor::from_u8) {
+                        self.set_scale(scale);
+                    }
+                }
+                MessageType::SetMode => {
+                    if let Some(request) = SetModeRequest::from_bytes(payload) {
+                        self.set_mode(&request);
+                    }
+                }
+                MessageType::SetTheme => {
+                    // TODO: Load the theme file at startup once the VFS exists
+                    match core::str::from_utf8(payload).ok().and_then(theme::parse) {
+                        Some(spec) => self.set_theme(spec),
+                        None => log("Desktop: Invalid theme file"),
+                    }
+                }
+                MessageType::Shutdown => {
+                    self.session.remember_all(&self.wm);
+                    if self.session.save().is_err() {
+                        log("Desktop: Could not save the session");
+                    }
+                }
+                MessageType::SetDoNotDisturb if !payload.is_empty() => {
+                    self.set_do_not_disturb(payload[0] != 0);
+                }
+                MessageType::SetAnimations if !payload.is_empty() => {
+                    self.animator.set_enabled(payload[0] != 0);
+                    self.damage.add_screen();
+                }
+                MessageType::SetWindowRole => {
+                    if let Some(role) = WindowRole::from_bytes(payload) {
+                        self.set_window_role(&role);
+                    }
+                }
+                MessageType::SetCursor => {
+                    if let Some(msg) = WindowCursor::from_bytes(payload) {
+                        self.set_window_cursor(&msg);
+                    }
+                }
+                MessageType::SetWindowOpacity => {
+                    if let Some(msg) = WindowOpacity::from_bytes(payload) {
+                        self.set_window_opacity(&msg);
+                    }
+                }
+                MessageType::SetShortcut => {
+                    if let Some(binding) = ShortcutBinding::from_bytes(payload) {
+                        self.shortcuts.bind(binding);
+                    }
+                }
+                MessageType::SetPointerSettings => {
+                    // TODO: Persist once a configuration store exists
+                    if let Some(settings) = PointerSettings::from_bytes(payload) {
+                        self.accel.set_settings(settings);
+                        log("Desktop: Pointer settings changed");
+                        log(self.accel.settings().profile.name());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn set_window_opacity(&mut self, msg: &WindowOpacity) {
+        let Some(window) = self.wm.get_mut(msg.window_id) else {
+            return;
+        };
+        window.blend = Blend {
+            opacity: msg.opacity,
+            per_pixel_alpha: msg.per_pixel_alpha,
+        };
+        self.damage_window(Some(msg.window_id));
+    }
+
+    fn set_window_title(&mut self, msg: WindowTitle) {
+        let Some(window) = self.wm.get_mut(msg.window_id) else {
+            return;
+        };
+        window.title = msg.title;
+        self.damage_window(Some(msg.window_id));
+        // The dock shows the title's first letter
+        self.damage.add(dock::area(self.back.width(), self.back.height()));
+    }
+
+    fn set_window_role(&mut self, role: &WindowRole) {
+        let mut applied = false;
+        self.change_windows(role.window_id, |wm| applied = wm.set_role(role));
+        if !applied {
+            log("Desktop: Invalid window role");
+        }
+    }
+
+    fn set_window_cursor(&mut self, msg: &WindowCursor) {
+        let Some(window) = self.wm.get_mut(msg.window_id) else {
+            return;
+        };
+        window.cursor = msg.shape;
+        self.update_cursor_shape();
+        self.cursor_moved = true;
+    }
+
+    /// Reply to `GetNotificationHistory` with as much history as fits
+    fn send_notification_history(&self, port: PortId) {
+        let records = self.notifications.history();
+        let reply = NotificationHistory::pack(&records, MAX_MESSAGE_SIZE - MessageHeader::SIZE);
+        let _ = send_message_async(port, MessageType::NotificationHistory, &reply);
+    }
+
+    fn set_do_not_disturb(&mut self, enabled: bool) {
+        self.notifications.do_not_disturb = enabled;
+        self.damage.add(do_not_disturb_button(self.back.width()));
+    }
+
+    /// Take new clipboard content and tell every application about it
+    fn set_clipboard(&mut self, msg: &ClipboardData) {
+        let Some(previous) = self.clipboard.set(msg) else {
+            log("Desktop: Could not read clipboard content");
+            return;
+        };
+
+        let changed = ClipboardChanged {
+            owner: msg.port,
+            mime: msg.mime,
+        };
+        let changed = changed.to_bytes();
+        let windows = self.wm.windows.iter().filter_map(|w| w.event_port);
+        let mut notified: Vec<PortId> = Vec::new();
+        for port in windows.chain(previous).chain(Some(msg.port)) {
+            if !notified.contains(&port) {
+                let _ = send_message_async(port, MessageType::ClipboardChanged, &changed);
+                notified.push(port);
+            }
+        }
+    }
+
+    /// Begin a drag-and-drop from a window; ends when the button is released
+    fn start_drag(&mut self, start: DragStart) {
+        if self.drag.is_some() || !self.wm.windows.iter().any(|w| w.id == start.window_id) {
+            return;
+        }
+
+        // The drag replaces any window move the press started
+        self.grab = None;
+        self.drag = Some(Drag {
+            source: start.window_id,
+            data: start.data,
+            target: None,
+            x: self.cursor.x,
+            y: self.cursor.y,
+        });
+        self.move_drag();
+    }
+
+    /// Move the drag ghost with the cursor and tell windows it enters,
+    /// crosses or leaves
+    fn move_drag(&mut self) {
+        let (x, y) = (self.cursor.x, self.cursor.y);
+        let target = self.wm.window_at(x, y);
+        let Some(drag) = self.drag.as_mut() else {
+            return;
+        };
+
+        self.damage.add(drag.ghost());
+        drag.x = x;
+        drag.y = y;
+        self.damage.add(drag.ghost());
+
+        let previous = core::mem::replace(&mut drag.target, target);
+        let mime = drag.data.mime;
+        if previous != target {
+            if let Some(id) = previous {
+                self.send_drag_event(id, MessageType::DragLeave, mime);
+            }
+            if let Some(id) = target {
+                self.send_drag_event(id, MessageType::DragEnter, mime);
+            }
+        } else if let Some(id) = target {
+            self.send_drag_event(id, MessageType::DragMotion, mime);
+        }
+    }
+
+    fn send_drag_event(&self, id: WindowId, msg_type: MessageType, mime: ClipboardMime) {
+        let Some(window) = self.wm.windows.iter().find(|w| w.id == id) else {
+            return;
+        };
+        let Some(port) = window.event_port else {
+            return;
+        };
+
+        let (x, y) = window.to_surface(self.cursor.x, self.cursor.y);
+        let event = DragEvent {
+            window_id: id,
+            x,
+            y,
+            mime,
+        };
+        let _ = send_message_async(port, msg_type, &event.to_bytes());
+    }
+
+    /// Drop the data on the window under the cursor and tell the source
+    fn finish_drag(&mut self) {
+        let Some(drag) = self.drag.take() else {
+            return;
+        };
+        self.damage.add(drag.ghost());
+
+        let find = |id: WindowId| self.wm.windows.iter().find(|w| w.id == id);
+        let target = drag.target.and_then(find).and_then(|w| Some((w, w.event_port?)));
+        let dropped = match target {
+            Some((window, port)) => {
+                let (x, y) = window.to_surface(drag.x, drag.y);
+                let event = DropEvent {
+                    window_id: window.id,
+                    x,
+                    y,
+                    data: drag.data,
+                };
+                let _ = send_message_async(port, MessageType::Drop, &event.to_bytes());
+                true
+            }
+            None => false,
+        };
+
+        if let Some(port) = find(drag.source).and_then(|w| w.event_port) {
+            let end = DragEnd {
+                window_id: drag.source,
+                dropped,
+            };
+            let _ = send_message_async(port, MessageType::DragEnd, &end.to_bytes());
+        }
+    }
+
+    /// Answer a capture request, if its sender holds the Screenshot
+    /// capability; called straight after the request is received
+    fn handle_capture(&mut self, request: &CaptureRequest, window: bool) {
+        let result = if !capture::sender_allowed() {
+            log("Desktop: Capture denied, missing Screenshot capability");
+            CaptureResult { status: CaptureStatus::Denied, width: 0, height: 0 }
+        } else {
+            match self.capture(window.then_some(request.window_id)) {
+                Some(shot) => capture::deliver(&shot, request),
+                None => CaptureResult { status: CaptureStatus::NoWindow, width: 0, height: 0 },
+            }
+        };
+
+        let port = request.reply_port;
+        let _ = send_message_async(port, MessageType::CaptureResult, &result.to_bytes());
+    }
+
+    /// Pixels of a window's surface, or of the screen as currently composed
+    fn capture(&mut self, window: Option<WindowId>) -> Option<Screenshot> {
+        match window {
+            Some(id) => {
+                let window = self.wm.windows.iter().find(|w| w.id == id)?;
+                window.surface.as_ref().map(capture::window)
+            }
+            None => {
+                if !self.damage.is_empty() {
+                    self.compose();
+                }
+                Some(capture::screen(&self.back))
+            }
+        }
+    }
+
+    /// Apply a new wallpaper image or mode and tell the sender whether it
+    /// worked
+    fn set_wallpaper(&mut self, request: &SetWallpaper) {
+        let applied = if request.region_id == 0 {
+            match self.wallpaper.as_mut() {
+                Some(wallpaper) => {
+                    if wallpaper.set_mode(request.mode) {
+                        self.damage.add_screen();
+                    }
+                    true
+                }
+                None => false,
+            }
+        } else {
+            let background = self.theme.desktop_bg;
+            match wallpaper::load(request.region_id, request.len as usize, background) {
+                Some(image) => {
+                    let (width, height) = (self.back.width(), self.back.height());
+                    let wallpaper = Wallpaper::new(image, request.mode, background, width, height);
+                    self.wallpaper = Some(wallpaper);
+                    self.damage.add_screen();
+                    true
+                }
+                None => false,
+            }
+        };
+
+        if request.reply_port != 0 {
+            let reply = [applied as u8];
+            let _ = send_message_async(request.reply_port, MessageType::WallpaperResult, &reply);
+        }
+    }
+
+    /// Redraw at another output scale and tell every application
+    fn set_scale(&mut self, scale: ScaleFactor) {
+        if scale == self.wm.scale {
+            return;
+        }
+        self.wm.set_scale(scale);
+        self.cursor.set_scale(scale);
+        self.damage.add_screen();
+
+        for port in self.wm.windows.iter().filter_map(|w| w.event_port) {
+            let _ = send_message_async(port, MessageType::ScaleChanged, &[scale as u8]);
+        }
+    }
+
+    /// Switch the screen resolution for a client and lay the desktop out
+    /// again at the new size
+    fn set_mode(&mut self, request: &SetModeRequest) {
+        let old = (self.display.width(), self.display.height());
+        let mut success = self.display.set_mode(request.width, request.height).is_ok();
+        if success && !self.resize_screen() {
+            log("Desktop: No back buffer for the new mode; switching back");
+            let _ = self.display.set_mode(old.0, old.1);
+            let _ = self.resize_screen();
+            success = false;
+        }
+        // The screen starts out blank after a switch, even a reverted one
+        self.damage.add_screen();
+
+        if request.reply_port != 0 {
+            let changed = ModeChanged {
+                success,
+                mode: DisplayMode {
+                    width: self.display.width(),
+                    height: self.display.height(),
+                },
+            };
+            let reply = changed.to_bytes();
+            let _ = send_message_async(request.reply_port, MessageType::ModeChanged, &reply);
+        }
+    }
+
+    /// Follow the screen to its current size: a new back buffer, outputs,
+    /// wallpaper and cursor bounds, and windows moved onto the new screen.
+    /// False if no back buffer that size could be had.
+    fn resize_screen(&mut self) -> bool {
+        let (width, height) = (self.display.width(), self.display.height());
+        if (width, height) == (self.back.width(), self.back.height()) {
+            return true;
+        }
+        let Ok(back) = self.display.create_surface(width, height, PixelFormat::Native) else {
+            return false;
+        };
+
+        let old_work = self.work_area(self.outputs.primary());
+        self.back = back;
+        self.outputs = head_outputs(&self.display)
+            .unwrap_or_else(|| self.outputs.resized(width, height));
+        self.damage = Damage::new(width, height);
+        self.cursor.fit_screen(width, height);
+        self.snap_preview = None;
+        if let Some(wallpaper) = self.wallpaper.as_mut() {
+            wallpaper.resize(width, height);
+        }
+        self.reflow_windows(old_work);
+        true
+    }
+
+    /// Fit every window to a resized screen: maximized and snapped windows
+    /// take the same zone of the new work area, the rest are pulled back
+    /// onto the screen, shrinking if they no longer fit
+    fn reflow_windows(&mut self, old_work: (i32, i32, u32, u32)) {
+        let work = self.work_area(self.outputs.primary());
+        let (desktop_w, desktop_h) = self.outputs.desktop_size();
+
+        for window in self.wm.windows.iter_mut() {
+            let geometry = match snap::zone_of(window.geometry(), old_work) {
+                Some(zone) => snap::geometry(zone, work),
+                None => fit_desktop(window.geometry(), desktop_w, desktop_h),
+            };
+            if let Some(saved) = window.saved_geometry {
+                window.saved_geometry = Some(fit_desktop(saved, desktop_w, desktop_h));
+            }
+
+            let resized = (geometry.2, geometry.3) != (window.width, window.height);
+            window.set_geometry(geometry);
+            if resized {
+                request_resize(window);
+            }
+        }
+    }
+
+    /// Re-skin the desktop and tell every application
+    fn set_theme(&mut self, spec: ThemeSpec) {
+        self.theme = Theme::new(spec);
+        if let Some(wallpaper) = self.wallpaper.as_mut() {
+            wallpaper.set_background(self.theme.desktop_bg);
+        }
+        self.damage.add_screen();
+
+        let bytes = spec.to_bytes();
+        for port in self.wm.windows.iter().filter_map(|w| w.event_port) {
+            let _ = send_message_async(port, MessageType::ThemeChanged, &bytes);
+        }
+        log("Desktop: Theme changed");
+    }
+
+    /// PrintScreen: capture the screen for saving
+    fn take_screenshot(&mut self) {
+        // TODO: Save as a BMP file once the VFS exists; until then the
+        // last capture is only kept in memory
+        self.screenshot = self.capture(None);
+        log("Desktop: Screenshot taken");
+    }
 
-use libipc::messages::{MessageType, WindowId};
-use libipc::ports::well_known;
+    /// Damage the on-screen part of a committed surface area
+    fn damage_commit(&mut self, commit: &CommitFrame) {
+        self.frames.committed(commit.window_id);
+        let Some(window) = self.wm.windows.iter().find(|w| w.id == commit.window_id) else {
+            return;
+        };
+        if !self.wm.is_visible(window) {
+            return;
+        }
 
-// ============================================================================
-// Theme Colors (Nord-inspired)
-// ============================================================================
+        let (cx, cy, cw, ch) = window.client_rect();
+        let to_screen = |value: i32| window.scale.convert(value, window.surface_scale);
+        for damage in &commit.damage {
+            // One pixel more covers the rounding of stretched surfaces
+            let on_screen = Rect::new(
+                cx + to_screen(damage.x),
+                cy + to_screen(damage.y),
+                to_screen(damage.width as i32) as u32 + 1,
+                to_screen(damage.height as i32) as u32 + 1,
+            );
+            if let Some(rect) = on_screen.intersection(&Rect::new(cx, cy, cw, ch)) {
+                self.damage.add(rect);
+            }
+        }
+    }
 
-mod theme {
-    use atom_syscall::graphics::Color;
-
-    pub const DESKTOP_BG: Color = Color::new(46, 52, 64);
-    pub const PANEL_BG: Color = Color::new(36, 41, 51);
-    pub const PANEL_TEXT: Color = Color::new(236, 239, 244);
-    pub const ACCENT: Color = Color::new(136, 192, 208);
-    pub const WINDOW_BG: Color = Color::new(46, 52, 64);
-    pub const WINDOW_HEADER: Color = Color::new(59, 66, 82);
-    pub const WINDOW_HEADER_FOCUSED: Color = Color::new(76, 86, 106);
-    pub const WINDOW_BORDER: Color = Color::new(67, 76, 94);
-    pub const DOCK_BG: Color = Color::new(36, 41, 51);
-    pub const CURSOR_FILL: Color = Color::WHITE;
-    pub const CURSOR_OUTLINE: Color = Color::BLACK;
-}
+    /// Open a window for an application and hand it the surface to draw into
+    fn create_client_window(&mut self, request: &CreateWindowRequest) {
+        // Applications rendering at the output scale get a surface that much
+        // larger; the rest draw at 1x and are stretched
+        let scale = if request.native_scale { self.wm.scale } else { ScaleFactor::X1 };
+        let (width, height) = (scale.apply(request.width), scale.apply(request.height));
+        let client = (request.reply_port, request.title.as_str(), request.app_id.as_str());
+        self.open_client_window(client, scale, || WindowSurface::create(width, height));
+    }
 
-// ============================================================================
-// Window Management
-// ============================================================================
+    /// Take in a window whose application outlived the previous compositor
+    /// instance, showing the surface it still has
+    fn reattach_client_window(&mut self, request: &ReattachRequest) {
+        let client = (request.reply_port, request.title.as_str(), request.app_id.as_str());
+        let (region, width, height) = (request.region_id, request.width, request.height);
+        self.open_client_window(client, request.scale, || {
+            WindowSurface::attach(region, width, height, request.stride)
+        });
+    }
 
-/// Window state in the compositor
-#[derive(Clone)]
-struct Window {
-    id: WindowId,
-    title: String,
-    x: i32,
-    y: i32,
-    width: u32,
-    height: u32,
-    visible: bool,
-    focused: bool,
-    /// IPC port for sending events to the owning application
-    event_port: Option<PortId>,
-}
+    /// Replace a window's surface with one of the size its application
+    /// asked for, keeping the old size if that one cannot be had
+    fn resize_client_window(&mut self, request: &WindowResize) {
+        let id = request.window_id;
+        self.damage_window(Some(id));
+        let Some(window) = self.wm.get_mut(id) else {
+            return;
+        };
+        let Some(port) = window.event_port else {
+            return;
+        };
+        let Some(old) = window.surface.take() else {
+            return;
+        };
 
-impl Window {
-    fn new(id: WindowId, title: &str, x: i32, y: i32, width: u32, height: u32) -> Self {
-        Self {
-            id,
-            title: String::from(title),
-            x,
-            y,
-            width,
-            height,
-            visible: true,
-            focused: false,
-            event_port: None,
-        }
+        // The old surface goes first, so its memory can back the new one
+        let (old_width, old_height) = (old.width, old.height);
+        drop(old);
+        window.surface = WindowSurface::create(request.width, request.height)
+            .or_else(|| WindowSurface::create(old_width, old_height));
+
+        let reply = match &window.surface {
+            Some(surface) => {
+                if !window.is_tiled() {
+                    let frame = window.frame_size(surface.width, surface.height);
+                    (window.width, window.height) = frame;
+                }
+                SurfaceRegion {
+                    window_id: id,
+                    region_id: surface.region,
+                    width: surface.width,
+                    height: surface.height,
+                    stride: surface.stride,
+                    scale: window.surface_scale,
+                }
+            }
+            None => {
+                log("Desktop: Could not resize window surface");
+                SurfaceRegion {
+                    window_id: 0,
+                    region_id: 0,
+                    width: 0,
+                    height: 0,
+                    stride: 0,
+                    scale: window.surface_scale,
+                }
+            }
+        };
+        self.damage_window(Some(id));
+        let _ = send_message_async(port, MessageType::SurfaceRegion, &reply.to_bytes());
     }
 
-    fn contains(&self, px: i32, py: i32) -> bool {
-        px >= self.x && py >= self.y
-            && px < self.x + self.width as i32
-            && py < self.y + self.height as i32
+    /// Add a window for the application on `port` and send it the surface
+    /// `surface` sets up for the new window id, rendered at `scale`
+    fn open_client_window(
+        &mut self,
+        (port, title, app_id): (PortId, &str, &str),
+        scale: ScaleFactor,
+        surface: impl FnOnce() -> Option<WindowSurface>,
+    ) {
+        // Cascade new windows from the top-left of the output under the cursor
+        let origin = self.outputs.at(self.cursor.x, self.cursor.y).rect;
+        let offset = (self.wm.windows.len() % 8) as i32 * 30;
+        let (x, y) = (origin.x + 80 + offset, origin.y + 60 + offset);
+        let focused = self.wm.focused_id;
+        let id = self.wm.create_window(title, x, y, 0, 0);
+        self.damage_window(focused);
+
+        let surface = surface();
+        let reply = match &surface {
+            Some(surface) => SurfaceRegion {
+                window_id: id,
+                region_id: surface.region,
+                width: surface.width,
+                height: surface.height,
+                stride: surface.stride,
+                scale,
+            },
+            None => {
+                log("Desktop: Could not set up window surface");
+                self.change_windows(id, |wm| {
+                    wm.close_window(id);
+                });
+                SurfaceRegion {
+                    window_id: 0,
+                    region_id: 0,
+                    width: 0,
+                    height: 0,
+                    stride: 0,
+                    scale,
+                }
+            }
+        };
+
+        if let Some(window) = self.wm.get_mut(id) {
+            window.event_port = Some(port);
+            window.surface = surface;
+            window.surface_scale = scale;
+            window.app_id = String::from(app_id);
+            (window.width, window.height) = window.frame_size(reply.width, reply.height);
+            self.animator.start(id, Effect::Open, get_ticks());
+        }
+        self.restore_session(id, app_id);
+        self.damage_window(Some(id));
+        self.damage.add(dock::area(self.back.width(), self.back.height()));
+
+        // The surface must be the first message on the application's port
+        let _ = send_message_async(port, MessageType::SurfaceRegion, &reply.to_bytes());
+        let theme = self.theme.spec.to_bytes();
+        let _ = send_message_async(port, MessageType::ThemeChanged, &theme);
+        self.notify_focus(focused);
     }
 
-    fn header_contains(&self, px: i32, py: i32) -> bool {
-        px >= self.x && py >= self.y
-            && px < self.x + self.width as i32
-            && py < self.y + 24 // Header height
+    /// Put a new window back where its application's window was last time,
+    /// and have the application resize if the size differs
+    fn restore_session(&mut self, id: WindowId, app_id: &str) {
+        let Some(saved) = self.session.get(app_id).copied() else {
+            return;
+        };
+
+        // Still on the desktop, in case an output has gone away since
+        let (desktop_w, desktop_h) = self.outputs.desktop_size();
+        let (x, y, width, height) = fit_desktop(saved.geometry, desktop_w, desktop_h);
+
+        // Below the windows of applications that were above it
+        let session = &self.session;
+        let layer = self.wm.windows.iter().position(|w| {
+            w.id != id && session.get(&w.app_id).is_some_and(|s| s.layer > saved.layer)
+        });
+
+        let mut resized = false;
+        self.change_windows(id, |wm| {
+            if let Some(window) = wm.get_mut(id) {
+                resized = (window.width, window.height) != (width, height);
+                window.set_geometry((x, y, width, height));
+            }
+            wm.move_to_workspace(id, saved.workspace);
+            if let Some(layer) = layer {
+                wm.restack(id, layer);
+            }
+        });
+        if resized {
+            self.resized = Some(id);
+        }
     }
-}
 
-/// Window manager state
-struct WindowManager {
-    windows: Vec<Window>,
-    next_id: WindowId,
-    focused_id: Option<WindowId>,
-}
+    /// Close button: applications are asked to close and answer with
+    /// DestroyWindow; windows without an owner close at once
+    fn request_close(&mut self, id: WindowId) {
+        let port = self.wm.windows.iter().find(|w| w.id == id).and_then(|w| w.event_port);
 
-impl WindowManager {
-    fn new() -> Self {
-        Self {
-            windows: Vec::new(),
-            next_id: 1,
-            focused_id: None,
+        match port {
+            Some(port) => {
+                let event = WindowEventMsg::close_requested(id);
+                let _ = send_message_async(port, MessageType::WindowEvent, &event.to_bytes());
+            }
+            None => self.close_window(id),
         }
     }
 
-    fn create_window(&mut self, title: &str, x: i32, y: i32, width: u32, height: u32) -> WindowId {
-        let id = self.next_id;
-        self.next_id += 1;
+    fn handle_scroll(&mut self, delta: i32) {
+        let window = self.wm.focused_id.and_then(|id| self.wm.windows.iter().find(|w| w.id == id));
+        let Some((window, port)) = window.and_then(|w| Some((w, w.event_port?))) else {
+            return;
+        };
 
-        let window = Window::new(id, title, x, y, width, height);
-        self.windows.push(window);
-        self.focus_window(id);
-        id
+        // The cursor in the window's surface pixels, like drag positions
+        let (x, y) = window.to_surface(self.cursor.x, self.cursor.y);
+        let event = MouseScrollEvent {
+            x,
+            y,
+            delta: delta as i16,
+            timestamp: get_ticks(),
+        };
+        let _ = send_message_async(port, MessageType::MouseScroll, &event.to_bytes());
     }
 
-    fn focus_window(&mut self, id: WindowId) {
-        // Unfocus previous
-        if let Some(prev_id) = self.focused_id {
-            if let Some(w) = self.windows.iter_mut().find(|w| w.id == prev_id) {
-                w.focused = false;
+    /// Deliver a key to the focused window's application
+    fn handle_key(&mut self, scancode: u8) {
+        let event = self.keyboard.feed(scancode);
+
+        // Releasing Alt picks the highlighted switcher entry
+        if self.switcher.is_some() && !self.keyboard.alt() {
+            self.finish_switch();
+        }
+
+        if self.keyboard.take_super_tap() {
+            self.toggle_launcher();
+            return;
+        }
+
+        let Some((event, pressed)) = event else {
+            return;
+        };
+
+        // The open launcher gets all typing
+        if self.launcher.is_some() {
+            if pressed {
+                self.launcher_key(event.keycode, event.character);
             }
+            return;
         }
 
-        // Focus new and move to top
-        if let Some(pos) = self.windows.iter().position(|w| w.id == id) {
-            let mut window = self.windows.remove(pos);
-            window.focused = true;
-            self.windows.push(window);
-            self.focused_id = Some(id);
+        if pressed {
+            let modifiers = self.keyboard.shortcut_modifiers();
+            if let Some(action) = self.shortcuts.lookup(modifiers, event.keycode) {
+                self.run_shortcut(action);
+                return;
+            }
         }
+
+        let port = self
+            .wm
+            .focused_id
+            .and_then(|id| self.wm.windows.iter().find(|w| w.id == id))
+            .and_then(|w| w.event_port);
+
+        let Some(port) = port else {
+            // Escape on a window without an application quits the desktop
+            if event.keycode == KeyCode::Escape && pressed {
+                log("Desktop: Escape pressed, exiting");
+                exit(0);
+            }
+            return;
+        };
+
+        let msg_type = if pressed { MessageType::KeyDown } else { MessageType::KeyUp };
+        let _ = send_message_async(port, msg_type, &event.to_bytes());
     }
 
-    fn window_at(&self, x: i32, y: i32) -> Option<WindowId> {
-        // Check from top to bottom (reverse order)
-        for window in self.windows.iter().rev() {
-            if window.visible && window.contains(x, y) {
-                return Some(window.id);
+    fn run_shortcut(&mut self, action: ShortcutAction) {
+        if let Some((workspace, move_window)) = shortcuts::workspace_action(action) {
+            match (move_window, self.wm.focused_id) {
+                (false, _) => self.switch_workspace(workspace),
+                (true, Some(id)) => self.move_to_workspace(id, workspace),
+                (true, None) => {}
             }
+            return;
         }
-        None
-    }
 
-    fn close_window(&mut self, id: WindowId) {
-        self.windows.retain(|w| w.id != id);
-        if self.focused_id == Some(id) {
-            self.focused_id = self.windows.last().map(|w| w.id);
+        match (action, self.wm.focused_id) {
+            (ShortcutAction::SwitchWindow, _) => self.cycle_windows(true),
+            (ShortcutAction::SwitchWindowReverse, _) => self.cycle_windows(false),
+            (ShortcutAction::Screenshot, _) => self.take_screenshot(),
+            (ShortcutAction::TogglePerformanceHud, _) => self.toggle_hud(),
+            (_, None) => {}
+            (ShortcutAction::CloseWindow, Some(id)) => self.request_close(id),
+            (_, Some(id)) => self.tile_with_keyboard(id, action),
         }
     }
-}
 
-// ============================================================================
-// Cursor State
-// ============================================================================
+    fn toggle_calendar(&mut self) {
+        self.clock.calendar_open = !self.clock.calendar_open;
+        self.damage.add(clock::clock_rect(self.back.width()));
+        self.damage.add(clock::calendar_bounds(self.back.width()));
+    }
 
-struct CursorState {
-    x: i32,
-    y: i32,
-    saved_region: [u32; 16 * 16],
-    saved_x: i32,
-    saved_y: i32,
-    has_saved: bool,
-    visible: bool,
-}
+    fn toggle_hud(&mut self) {
+        self.hud = match self.hud {
+            Some(_) => None,
+            None => Some(Hud::new(get_time_ms())),
+        };
+        self.damage.add(Hud::bounds());
+    }
 
-impl CursorState {
-    fn new(width: u32, height: u32) -> Self {
-        Self {
-            x: (width / 2) as i32,
-            y: (height / 2) as i32,
-            saved_region: [0; 16 * 16],
-            saved_x: 0,
-            saved_y: 0,
-            has_saved: false,
-            visible: true,
+    /// Time input arriving for the HUD's latency figures
+    fn note_input(&mut self) {
+        if let Some(hud) = &mut self.hud {
+            hud.input(hud::now());
         }
     }
 
-    fn apply_delta(&mut self, dx: i32, dy: i32, width: u32, height: u32) {
-        self.x = (self.x + dx).clamp(0, (width - 1) as i32);
-        self.y = (self.y - dy).clamp(0, (height - 1) as i32); // Y inverted in PS/2
+    /// Show the HUD's figures for the second that just ended
+    fn refresh_hud(&mut self) {
+        let now_ms = get_time_ms();
+        if !self.hud.as_ref().is_some_and(|hud| hud.due(now_ms)) {
+            return;
+        }
+
+        let queued = |port| port_stats(port).map_or(0, |stats| stats.queued);
+        let own = queued(self.event_port);
+        let windows = self.wm.windows.iter().filter_map(|w| w.event_port).map(queued).max();
+        let screen = self.back.width() as u64 * self.back.height() as u64;
+        if let Some(hud) = &mut self.hud {
+            hud.refresh(now_ms, screen, (own, windows.unwrap_or(0)));
+        }
+        self.damage.add(Hud::bounds());
     }
 
-    fn save_region(&mut self, fb: &Framebuffer) {
-        self.saved_x = self.x;
-        self.saved_y = self.y;
-        self.has_saved = true;
+    fn toggle_osk(&mut self) {
+        self.osk = match self.osk {
+            Some(_) => None,
+            None => Some(Osk::new()),
+        };
+        self.damage.add(keyboard_button(self.back.width()));
+        self.damage.add(osk::bounds(self.back.width(), self.back.height()));
+    }
 
-        let fb_addr = fb.address();
-        let stride = fb.stride();
-        let bpp = fb.bytes_per_pixel();
+    fn toggle_launcher(&mut self) {
+        self.launcher = match self.launcher {
+            Some(_) => None,
+            None => Some(Launcher::new()),
+        };
+        self.damage.add(launcher::bounds(self.back.width(), self.back.height()));
+    }
 
-        for row in 0..16u32 {
-            for col in 0..16u32 {
-                let px = (self.x as u32).wrapping_add(col);
-                let py = (self.y as u32).wrapping_add(row);
+    /// Search, move the selection, or start the selected program
+    fn launcher_key(&mut self, keycode: KeyCode, character: u8) {
+        let Some(launcher) = self.launcher.as_mut() else {
+            return;
+        };
 
-                if px < fb.width() && py < fb.height() {
-                    let offset = (py * stride + px) as usize * bpp;
-                    let ptr = (fb_addr + offset) as *const u32;
-                    self.saved_region[(row * 16 + col) as usize] = unsafe { ptr.read_volatile() };
+        match keycode {
+            KeyCode::Escape => self.launcher = None,
+            KeyCode::Enter | KeyCode::NumpadEnter => {
+                let program = launcher.selected();
+                self.launcher = None;
+                if let Some(program) = program {
+                    self.open_program(program);
                 }
             }
+            KeyCode::ArrowDown | KeyCode::Tab => launcher.step(true),
+            KeyCode::ArrowUp => launcher.step(false),
+            KeyCode::Backspace => launcher.pop(),
+            _ if character.is_ascii_graphic() || character == b' ' => {
+                launcher.push(character as char);
+            }
+            _ => return,
         }
+        self.damage.add(launcher::bounds(self.back.width(), self.back.height()));
     }
 
-    fn restore_region(&self, fb: &Framebuffer) {
-        if !self.has_saved {
+    /// Raise a running program's window, or start the program
+    fn open_program(&mut self, program: &Program) {
+        if let Some(id) = self.wm.windows.iter().find(|w| w.title == program.name).map(|w| w.id) {
+            self.activate(id);
             return;
         }
 
-        let fb_addr = fb.address();
-        let stride = fb.stride();
-        let bpp = fb.bytes_per_pixel();
-
-        for row in 0..16u32 {
-            for col in 0..16u32 {
-                let px = (self.saved_x as u32).wrapping_add(col);
-                let py = (self.saved_y as u32).wrapping_add(row);
-
-                if px < fb.width() && py < fb.height() {
-                    let offset = (py * stride + px) as usize * bpp;
-                    let ptr = (fb_addr + offset) as *mut u32;
-                    unsafe {
-                        ptr.write_volatile(self.saved_region[(row * 16 + col) as usize]);
-                    }
-                }
+        if process::spawn(program.path).is_err() {
+            let notification = Notification {
+                app_name: String::from("Desktop"),
+                title: String::from("Could not start program"),
+                body: String::from(program.name),
+                urgency: Urgency::Normal,
+                timeout_ms: 0,
+            };
+            if self.notifications.post(notification, get_ticks()) {
+                self.damage.add(self.notifications.area(self.back.width()));
             }
         }
     }
-}
-
-// ============================================================================
-// Compositor
-// ============================================================================
 
-struct Compositor {
-    fb: Framebuffer,
-    wm: WindowManager,
-    cursor: CursorState,
-    mouse: MouseDriver,
-    event_port: PortId,
-    dirty: bool,
-}
+    /// Raise a window from the dock or switcher, restoring it if minimized
+    /// and showing its workspace
+    fn activate(&mut self, id: WindowId) {
+        let workspace = self.wm.active_workspace;
+        self.change_windows(id, |wm| wm.restore(id));
 
-impl Compositor {
-    fn new(fb: Framebuffer) -> Self {
-        let width = fb.width();
-        let height = fb.height();
+        if self.wm.active_workspace != workspace {
+            self.damage.add_screen();
+        }
+    }
 
-        // Create IPC port for receiving events
-        let event_port = create_port().expect("Failed to create event port");
+    fn switch_workspace(&mut self, workspace: u8) {
+        let previous = self.wm.focused_id;
+        let active = self.wm.active_workspace;
+        self.wm.switch_workspace(workspace);
 
-        Self {
-            fb,
-            wm: WindowManager::new(),
-            cursor: CursorState::new(width, height),
-            mouse: MouseDriver::new(),
-            event_port,
-            dirty: true,
+        if self.wm.active_workspace != active {
+            self.damage.add_screen();
+            self.notify_focus(previous);
         }
     }
 
-    fn run(&mut self) -> ! {
-        log("Desktop: Starting compositor");
-
-        // Create initial windows
-        self.wm.create_window("Welcome to Atom", 100, 100, 400, 300);
-        self.wm.create_window("Terminal", 150, 150, 500, 350);
+    fn move_to_workspace(&mut self, id: WindowId, workspace: u8) {
+        self.change_windows(id, |wm| wm.move_to_workspace(id, workspace));
 
-        // Initial draw
-        self.draw_all();
+        // Occupied markers in the panel indicators
+        let (desktop_w, _) = self.outputs.desktop_size();
+        self.damage.add(Rect::new(0, 0, desktop_w, PANEL_HEIGHT as u32));
+    }
 
-        log("Desktop: Entering event loop");
+    /// Move the focused window between snap zones with Super+arrows
+    fn tile_with_keyboard(&mut self, id: WindowId, action: ShortcutAction) {
+        let Some(window) = self.wm.windows.iter().find(|w| w.id == id) else {
+            return;
+        };
+        let work_area = self.window_work_area(window);
+
+        let current = snap::zone_of(window.geometry(), work_area);
+        match snap::keyboard_target(action, current) {
+            Some(Tiling::Snap(zone)) => self.tile(id, snap::geometry(zone, work_area)),
+            Some(Tiling::Restore) => self.restore_geometry(id),
+            Some(Tiling::Minimize) => self.minimize(id),
+            None => {}
+        }
+    }
 
-        let mut prev_left = false;
+    /// Open the switcher or move its selection one entry
+    fn cycle_windows(&mut self, forward: bool) {
+        let count = self.wm.windows.len();
+        if count == 0 {
+            return;
+        }
 
-        loop {
-            // Process mouse events
-            while let Some(event) = self.mouse.poll_event() {
-                self.cursor.restore_region(&self.fb);
-                self.cursor.apply_delta(event.dx, event.dy, self.fb.width(), self.fb.height());
+        // The first press skips the window that already has focus
+        let selected = match self.switcher {
+            None if forward => 1 % count,
+            None => count - 1,
+            Some(i) if forward => (i + 1) % count,
+            Some(i) => (i + count - 1) % count,
+        };
+        self.switcher = Some(selected);
+        self.damage.add(switcher::bounds(self.back.width(), self.back.height(), count));
+    }
 
-                // Handle click
-                if event.left_button && !prev_left {
-                    self.handle_click(self.cursor.x, self.cursor.y);
-                }
-                prev_left = event.left_button;
+    /// Close the switcher and raise the selected window
+    fn finish_switch(&mut self) {
+        let Some(selected) = self.switcher.take() else {
+            return;
+        };
+        let count = self.wm.windows.len();
+        self.damage.add(switcher::bounds(self.back.width(), self.back.height(), count));
 
-                self.cursor.save_region(&self.fb);
-                self.draw_cursor();
-            }
+        if let Some(id) = switcher::window_at(&self.wm, selected) {
+            self.activate(id);
+        }
+    }
 
-            // Process keyboard events
-            while let Some(scancode) = keyboard_poll() {
-                self.handle_key(scancode);
-            }
+    /// Tell the applications that lost and gained focus
+    fn notify_focus(&mut self, previous: Option<WindowId>) {
+        let current = self.wm.focused_id;
+        if previous == current {
+            return;
+        }
 
-            // Redraw if needed
-            if self.dirty {
-                self.draw_all();
-                self.dirty = false;
+        for (id, focused) in [(previous, false), (current, true)] {
+            let Some(window) = id.and_then(|id| self.wm.windows.iter().find(|w| w.id == id)) else {
+                continue;
+            };
+            if let Some(port) = window.event_port {
+                let event = WindowEventMsg::focus_changed(window.id, focused);
+                let _ = send_message_async(port, MessageType::WindowEvent, &event.to_bytes());
             }
-
-            yield_now();
         }
     }
 
-    fn handle_click(&mut self, x: i32, y: i32) {
-        // Check if clicking on a window
-        if let Some(id) = self.wm.window_at(x, y) {
-            if self.wm.focused_id != Some(id) {
-                self.wm.focus_window(id);
-                self.dirty = true;
-            }
+    /// Put everything that changed since the last frame on screen, then
+    /// let the windows that committed draw their next frame
+    fn present_frame(&mut self) {
+        self.refresh_hud();
+
+        let start = hud::now();
+        let damaged = self.damage.pixels();
+        let presented = !self.damage.is_empty() || self.cursor_moved;
+        if !self.damage.is_empty() {
+            self.compose();
+        } else if self.cursor_moved {
+            self.show(&[]);
+        }
+        self.cursor_moved = false;
 
-            // Check for close button click
-            if let Some(w) = self.wm.windows.iter().find(|w| w.id == id) {
-                let close_x = w.x + w.width as i32 - 20;
-                let close_y = w.y + 6;
-                if x >= close_x && x < close_x + 12 && y >= close_y && y < close_y + 12 {
-                    self.wm.close_window(id);
-                    self.dirty = true;
-                }
+        if let Some(hud) = &mut self.hud {
+            if presented {
+                hud.frame(start, hud::now(), damaged);
+            } else {
+                hud.idle();
             }
         }
+
+        let time_ms = get_time_ms();
+        for id in self.frames.take_waiting() {
+            let window = self.wm.windows.iter().find(|w| w.id == id);
+            let Some(port) = window.and_then(|w| w.event_port) else {
+                continue;
+            };
+            let done = FrameDone { window_id: id, time_ms };
+            let _ = send_message_async(port, MessageType::FrameDone, &done.to_bytes());
+        }
     }
 
-    fn handle_key(&mut self, scancode: u8) {
-        // Handle escape to quit
-        if scancode == 0x01 {
-            log("Desktop: Escape pressed, exiting");
-            exit(0);
+    /// Redraw the damaged areas into the back buffer, each clipped to its
+    /// rectangle, then copy them to the screen
+    fn compose(&mut self) {
+        let areas = self.damage.take();
+        let now = get_ticks();
+
+        for area in &areas {
+            self.back.set_clip(area.x as u32, area.y as u32, area.width, area.height);
+            self.draw_area(area, now);
         }
+        self.back.reset_clip();
 
-        // Route to focused window (TODO: IPC to application)
+        self.show(&areas);
     }
 
-    fn draw_all(&mut self) {
-        self.cursor.restore_region(&self.fb);
+    /// Blit `areas` of the back buffer to the screen, put the cursor plane
+    /// on top and present, so the whole frame appears at once
+    fn show(&mut self, areas: &[Rect]) {
+        if self.display.blit(&self.back, 0, 0, areas).is_err() {
+            log("Desktop: Could not blit to the screen");
+        }
+        self.cursor.present(&self.display, &self.back, &self.theme);
+        let _ = self.display.present();
+    }
 
+    /// Draw everything that overlaps `area`, bottom to top
+    fn draw_area(&self, area: &Rect, now: u64) {
         // Desktop background
-        self.fb.fill_rect(0, 0, self.fb.width(), self.fb.height(), theme::DESKTOP_BG);
+        match &self.wallpaper {
+            Some(wallpaper) => wallpaper.draw(&self.back, area),
+            None => {
+                let (x, y) = (area.x as u32, area.y as u32);
+                self.back.fill_rect(x, y, area.width, area.height, self.theme.desktop_bg);
+            }
+        }
 
         // Top panel
-        self.draw_panel();
+        if area.y < PANEL_HEIGHT {
+            self.draw_panel();
+        }
 
         // Windows (bottom to top)
         for window in self.wm.windows.iter() {
-            if window.visible {
+            let opening = self.animator.is_opening(window.id);
+            if self.wm.is_visible(window) && !opening && window.bounds().intersects(area) {
                 self.draw_window(window);
             }
         }
 
+        // Windows opening, closing or minimizing
+        for frame in self.animator.frames(now, &self.wm) {
+            if frame.rect.intersects(area) {
+                self.draw_animated(&frame);
+            }
+        }
+
+        // Drop zone of a window being dragged to an edge
+        if let Some((zone, work_area)) = self.snap_preview {
+            let preview = snap::preview(zone, work_area);
+            if preview.intersects(area) {
+                self.draw_snap_preview(&preview);
+            }
+        }
+
         // Bottom dock
-        self.draw_dock();
+        if dock::area(self.back.width(), self.back.height()).intersects(area) {
+            dock::draw(&self.back, &self.theme, &self.wm);
+        }
+
+        // Notification toasts
+        let toasts = self.notifications.area(self.back.width());
+        if self.notifications.is_showing() && toasts.intersects(area) {
+            self.notifications.draw(&self.back, &self.theme, self.back.width());
+        }
+
+        if let Some(osk) = &self.osk {
+            if osk::bounds(self.back.width(), self.back.height()).intersects(area) {
+                osk.draw(&self.back, &self.theme);
+            }
+        }
+
+        if self.clock.calendar_open && clock::calendar_bounds(self.back.width()).intersects(area) {
+            self.clock.draw_calendar(&self.back, &self.theme, self.back.width());
+        }
+
+        // Ghost of the data being dragged
+        if let Some(drag) = &self.drag {
+            if drag.ghost().intersects(area) {
+                drag.draw(&self.back, &self.theme);
+            }
+        }
+
+        // Switcher and launcher overlays on top of everything
+        if let Some(selected) = self.switcher {
+            let count = self.wm.windows.len();
+            if switcher::bounds(self.back.width(), self.back.height(), count).intersects(area) {
+                switcher::draw(&self.back, &self.theme, &self.wm, selected);
+            }
+        }
+        if let Some(launcher) = &self.launcher {
+            if launcher::bounds(self.back.width(), self.back.height()).intersects(area) {
+                launcher.draw(&self.back, &self.theme);
+            }
+        }
+
+        if let Some(hud) = &self.hud {
+            if Hud::bounds().intersects(area) {
+                hud.draw(&self.back, &self.theme);
+            }
+        }
+    }
+
+    fn draw_snap_preview(&self, preview: &Rect) {
+        const BORDER: u32 = 3;
+        let (x, y, w, h) = (preview.x as u32, preview.y as u32, preview.width, preview.height);
 
-        // Cursor
-        self.cursor.save_region(&self.fb);
-        self.draw_cursor();
+        self.back.fill_rect(x, y, w, BORDER, self.theme.accent);
+        self.back.fill_rect(x, y + h - BORDER, w, BORDER, self.theme.accent);
+        self.back.fill_rect(x, y, BORDER, h, self.theme.accent);
+        self.back.fill_rect(x + w - BORDER, y, BORDER, h, self.theme.accent);
     }
 
     fn draw_panel(&self) {
-        let width = self.fb.width();
+        let width = self.back.width();
 
         // Panel background
-        self.fb.fill_rect(0, 0, width, 28, theme::PANEL_BG);
+        self.back.fill_rect(0, 0, width, 28, self.theme.panel_bg);
 
         // Logo
-        self.fb.draw_string(12, 6, "Atom", theme::ACCENT, theme::PANEL_BG);
+        self.back.draw_string(12, 6, "Atom", self.theme.accent, self.theme.panel_bg);
+
+        self.draw_workspace_indicator(70);
 
         // Status
-        self.fb.draw_string(70, 6, "|  Desktop Environment", theme::PANEL_TEXT, theme::PANEL_BG);
+        let status_x = 70 + WORKSPACE_COUNT as u32 * WORKSPACE_SLOT + 8;
+        self.back.draw_string(
+            status_x,
+            6,
+            "|  Desktop Environment",
+            self.theme.panel_text,
+            self.theme.panel_bg,
+        );
+
+        self.widgets.draw(&self.back, &self.theme, widgets_right(width));
+        self.draw_do_not_disturb();
+        self.draw_keyboard_button();
+        self.clock.draw(&self.back, &self.theme, width);
+
+        // The other outputs only show which workspace is active
+        for output in self.outputs.iter().filter(|o| !o.primary) {
+            let (x, y) = (output.rect.x as u32, output.rect.y as u32);
+            self.back.fill_rect(x, y, output.rect.width, 28, self.theme.panel_bg);
+            self.draw_workspace_indicator(x + 12);
+        }
+    }
+
+    /// Simplified window (frame, title bar and stretched content) at an
+    /// animation's current size and opacity
+    fn draw_animated(&self, frame: &Frame) {
+        let (rect, alpha) = (frame.rect, frame.alpha);
+        if rect.width < 3 || rect.height < 3 {
+            return;
+        }
+        let window_header = frame.window.header_height() as u32;
+        let scaled_header = window_header * rect.height / frame.window.height.max(1);
+        let header_height = scaled_header.clamp(1, rect.height - 2);
+        let header_color = if frame.window.focused {
+            self.theme.window_header_focused
+        } else {
+            self.theme.window_header
+        };
+
+        self.blend_area(&rect, self.theme.window_border, alpha);
+        let header = Rect::new(rect.x + 1, rect.y + 1, rect.width - 2, header_height - 1);
+        self.blend_area(&header, header_color, alpha);
+
+        let client = Rect::new(
+            rect.x + 1,
+            rect.y + header_height as i32,
+            rect.width - 2,
+            rect.height - header_height - 1,
+        );
+        match &frame.window.surface {
+            Some(surface) => surface.blit_scaled(&self.back, &client, alpha),
+            None => self.blend_area(&client, self.theme.window_bg, alpha),
+        }
+    }
+
+    /// Soft drop shadow: one ring per pixel around the window (pushed down
+    /// by `SHADOW_OFFSET`), fading out quadratically over `SHADOW_RADIUS`
+    fn draw_shadow(&self, window: &Window) {
+        let (x, y) = (window.x, window.y + SHADOW_OFFSET as i32);
+        let (w, h) = (window.width, window.height);
+
+        // Full-strength strip peeking out below the window
+        let below = Rect::new(x, window.y + h as i32, w, SHADOW_OFFSET);
+        self.blend_area(&below, self.theme.shadow, self.theme.shadow_alpha);
+
+        let steps = SHADOW_RADIUS + 1;
+        for step in 1..=SHADOW_RADIUS {
+            let fade = steps - step;
+            let alpha = (self.theme.shadow_alpha as u32 * fade * fade / (steps * steps)) as u8;
+            let (rx, ry) = (x - step as i32, y - step as i32);
+            let (rw, rh) = (w + step * 2, h + step * 2);
+
+            let rows = [(ry, rw, 1), (ry + rh as i32 - 1, rw, 1)];
+            for (row_y, row_w, row_h) in rows {
+                self.blend_area(&Rect::new(rx, row_y, row_w, row_h), self.theme.shadow, alpha);
+            }
+            for col_x in [rx, rx + rw as i32 - 1] {
+                self.blend_area(&Rect::new(col_x, ry + 1, 1, rh - 2), self.theme.shadow, alpha);
+            }
+        }
+    }
+
+    /// `blend_rect` for an area that may extend past the screen edges
+    fn blend_area(&self, area: &Rect, color: Color, alpha: u8) {
+        let screen = Rect::new(0, 0, self.back.width(), self.back.height());
+        if let Some(r) = area.intersection(&screen) {
+            self.back.blend_rect(r.x as u32, r.y as u32, r.width, r.height, color, alpha);
+        }
+    }
+
+    /// Panel toggle, highlighted while notifications are held back
+    fn draw_do_not_disturb(&self) {
+        let button = do_not_disturb_button(self.back.width());
+        let (bg, fg) = if self.notifications.do_not_disturb {
+            (self.theme.accent, self.theme.panel_bg)
+        } else {
+            (self.theme.panel_bg, self.theme.text_dim)
+        };
+        let (x, y) = (button.x as u32, button.y as u32);
+        self.back.fill_rect(x, y, button.width, button.height, bg);
+        self.back.draw_string(x + 6, y + 1, "DND", fg, bg);
+    }
+
+    /// Panel toggle, highlighted while the on-screen keyboard is shown
+    fn draw_keyboard_button(&self) {
+        let button = keyboard_button(self.back.width());
+        let (bg, fg) = if self.osk.is_some() {
+            (self.theme.accent, self.theme.panel_bg)
+        } else {
+            (self.theme.panel_bg, self.theme.text_dim)
+        };
+        let (x, y) = (button.x as u32, button.y as u32);
+        self.back.fill_rect(x, y, button.width, button.height, bg);
+        self.back.draw_string(x + 6, y + 1, "KBD", fg, bg);
+    }
 
-        // Clock (right side)
-        let clock_x = width.saturating_sub(80);
-        self.fb.draw_string(clock_x, 6, "12:00", theme::PANEL_TEXT, theme::PANEL_BG);
+    /// One numbered box per workspace: the active one highlighted, empty
+    /// ones dimmed
+    fn draw_workspace_indicator(&self, x: u32) {
+        for workspace in 0..WORKSPACE_COUNT {
+            let bx = x + workspace as u32 * WORKSPACE_SLOT;
+            let (bg, fg) = if workspace == self.wm.active_workspace {
+                (self.theme.accent, self.theme.panel_bg)
+            } else if self.wm.is_occupied(workspace) {
+                (self.theme.window_header, self.theme.panel_text)
+            } else {
+                (self.theme.panel_bg, self.theme.text_dim)
+            };
+
+            self.back.fill_rect(bx, 5, WORKSPACE_SLOT - 4, 18, bg);
+            let label = [b'1' + workspace];
+            let label = core::str::from_utf8(&label).unwrap_or("?");
+            self.back.draw_string(bx + 5, 6, label, fg, bg);
+        }
     }
 
     fn draw_window(&self, window: &Window) {
@@ -409,100 +2162,76 @@ impl Compositor {
         let w = window.width;
         let h = window.height;
 
-        // Shadow
-        self.fb.fill_rect(x + 3, y + 3, w, h, Color::new(20, 20, 30));
+        self.draw_shadow(window);
 
-        // Border
-        self.fb.fill_rect(x, y, w, h, theme::WINDOW_BORDER);
+        // Window content: the application's surface, or a plain fill until
+        // it has one. Parts of a grown window the surface does not cover yet
+        // keep the fill. Translucent content goes straight over whatever is
+        // behind the window.
+        let translucent = window.surface.is_some() && window.blend != Blend::OPAQUE;
+        if translucent {
+            self.back.draw_rect(x, y, w, h, self.theme.window_border);
+        } else {
+            self.back.fill_rect(x, y, w, h, self.theme.window_border);
+            self.back.fill_rect(x + 1, y + 1, w - 2, h - 2, self.theme.window_bg);
+        }
 
-        // Window content
-        self.fb.fill_rect(x + 1, y + 1, w - 2, h - 2, theme::WINDOW_BG);
+        let (cx, cy, cw, ch) = window.client_rect();
+        match &window.surface {
+            Some(surface) if window.surface_scale != window.scale => {
+                self.draw_stretched(window, surface);
+            }
+            Some(surface) => surface.blit(&self.back, cx, cy, cw, ch, window.blend),
+            None => {}
+        }
+        if !window.focused {
+            let dim = Rect::new(cx, cy, cw, ch);
+            self.blend_area(&dim, self.theme.shadow, self.theme.inactive_dim);
+        }
 
         // Header
         let header_color = if window.focused {
-            theme::WINDOW_HEADER_FOCUSED
+            self.theme.window_header_focused
         } else {
-            theme::WINDOW_HEADER
+            self.theme.window_header
         };
-        self.fb.fill_rect(x + 1, y + 1, w - 2, 22, header_color);
+        let header_height = window.header_height() as u32;
+        self.back.fill_rect(x + 1, y + 1, w - 2, header_height - 2, header_color);
 
         // Title
-        self.fb.draw_string(x + 8, y + 5, &window.title, theme::PANEL_TEXT, header_color);
-
-        // Window controls
-        let btn_x = x + w - 18;
-        let btn_y = y + 6;
-        self.fb.fill_rect(btn_x, btn_y, 10, 10, Color::new(255, 95, 86)); // Close
-        self.fb.fill_rect(btn_x - 14, btn_y, 10, 10, Color::new(255, 189, 46)); // Minimize
-        self.fb.fill_rect(btn_x - 28, btn_y, 10, 10, Color::new(39, 201, 63)); // Maximize
-    }
-
-    fn draw_dock(&self) {
-        let width = self.fb.width();
-        let height = self.fb.height();
-
-        let dock_w = 300u32;
-        let dock_h = 48u32;
-        let dock_x = (width / 2).saturating_sub(dock_w / 2);
-        let dock_y = height.saturating_sub(dock_h + 10);
-
-        // Dock background with rounded appearance
-        self.fb.fill_rect(dock_x, dock_y, dock_w, dock_h, theme::DOCK_BG);
-
-        // Dock icons
-        let icons = [
-            (Color::new(191, 97, 106), "F"),  // Files
-            (Color::new(163, 190, 140), "S"),  // Settings
-            (Color::new(94, 129, 172), "B"),   // Browser
-            (Color::new(80, 80, 80), ">_"),    // Terminal
-        ];
-
-        let icon_size = 32u32;
-        let padding = 16u32;
-        let start_x = dock_x + padding;
-        let icon_y = dock_y + (dock_h - icon_size) / 2;
-
-        for (i, (color, label)) in icons.iter().enumerate() {
-            let ix = start_x + (i as u32 * (icon_size + padding));
-            self.fb.fill_rect(ix, icon_y, icon_size, icon_size, *color);
-            self.fb.draw_string(ix + 8, icon_y + 10, label, Color::WHITE, *color);
-        }
-    }
-
-    fn draw_cursor(&self) {
-        let cursor_shape = [
-            [1,0,0,0,0,0,0,0,0,0],
-            [1,1,0,0,0,0,0,0,0,0],
-            [1,2,1,0,0,0,0,0,0,0],
-            [1,2,2,1,0,0,0,0,0,0],
-            [1,2,2,2,1,0,0,0,0,0],
-            [1,2,2,2,2,1,0,0,0,0],
-            [1,2,2,2,2,2,1,0,0,0],
-            [1,2,2,2,2,2,2,1,0,0],
-            [1,2,2,2,2,2,2,2,1,0],
-            [1,2,2,2,2,2,2,2,2,1],
-            [1,2,2,2,2,1,1,1,1,1],
-            [1,2,1,2,1,0,0,0,0,0],
-            [1,1,0,1,2,1,0,0,0,0],
-            [0,0,0,1,2,1,0,0,0,0],
-            [0,0,0,0,1,2,1,0,0,0],
-            [0,0,0,0,1,1,0,0,0,0],
-        ];
-
-        for (row, cols) in cursor_shape.iter().enumerate() {
-            for (col, &pixel) in cols.iter().enumerate() {
-                let px = self.cursor.x as u32 + col as u32;
-                let py = self.cursor.y as u32 + row as u32;
-                if px < self.fb.width() && py < self.fb.height() {
-                    match pixel {
-                        1 => self.fb.draw_pixel(px, py, theme::CURSOR_OUTLINE),
-                        2 => self.fb.draw_pixel(px, py, theme::CURSOR_FILL),
-                        _ => {}
-                    }
-                }
-            }
+        let s = |value: u32| window.scale.apply(value);
+        let fg = self.theme.panel_text;
+        self.back.draw_string_sized(x + s(8), y + s(5), &window.title, fg, header_color, s(8));
+
+        // Window controls: close, minimize, maximize from the right
+        let colors = [Color::new(255, 95, 86), Color::new(255, 189, 46), Color::new(39, 201, 63)];
+        for (i, color) in colors.into_iter().enumerate() {
+            let button = window.button_rect(i);
+            let (bx, by) = (button.x as u32, button.y as u32);
+            self.back.fill_rect(bx, by, button.width, button.height, color);
         }
     }
+
+    /// Surface drawn at another scale than the output, stretched to the
+    /// client area
+    fn draw_stretched(&self, window: &Window, surface: &WindowSurface) {
+        let (cx, cy, cw, ch) = window.client_rect();
+        let to_screen = |value: u32| {
+            window.scale.convert(value as i32, window.surface_scale) as u32
+        };
+        let dst = Rect::new(cx, cy, to_screen(surface.width), to_screen(surface.height));
+
+        // Keep to the client area as well as the damage being drawn
+        let (left, top, right, bottom) = self.back.clip();
+        let clip = Rect::new(left as i32, top as i32, right - left, bottom - top);
+        let Some(visible) = clip.intersection(&Rect::new(cx, cy, cw, ch)) else {
+            return;
+        };
+        let (vx, vy) = (visible.x as u32, visible.y as u32);
+        self.back.set_clip(vx, vy, visible.width, visible.height);
+        surface.blit_scaled(&self.back, &dst, window.blend.opacity);
+        self.back.set_clip(left, top, right - left, bottom - top);
+    }
 }
 
 // ============================================================================
@@ -526,26 +2255,53 @@ fn main() -> ! {
     log("Atom Desktop Environment v1.0");
     log("Microkernel architecture - all UI in userspace");
 
-    let fb = match Framebuffer::new() {
-        Some(fb) => fb,
-        None => {
-            log("Desktop: Failed to acquire framebuffer");
+    let display = match Display::connect() {
+        Ok(display) => display,
+        Err(_) => {
+            log("Desktop: Graphics service not available");
             exit(1);
         }
     };
 
-    log("Desktop: Framebuffer acquired");
+    log("Desktop: Connected to the graphics service");
+
+    let back = match display.create_surface(display.width(), display.height(), PixelFormat::Native) {
+        Ok(back) => back,
+        Err(_) => {
+            log("Desktop: Failed to allocate back buffer");
+            exit(1);
+        }
+    };
+
+    let cursor_plane = match cursor::create_plane(&display) {
+        Ok(plane) => plane,
+        Err(_) => {
+            log("Desktop: Failed to allocate the cursor plane");
+            exit(1);
+        }
+    };
+
+    let mut compositor = Compositor::new(display, back, cursor_plane);
+
+    // Options from the boot manifest, e.g. args = ["--no-animations"]
+    let mut args_buffer = [0u8; 256];
+    let args = env::args(&mut args_buffer);
+    if args.flag("no-animations") {
+        compositor.animator.set_enabled(false);
+        log("Desktop: Animations disabled");
+    }
 
-    let mut compositor = Compositor::new(fb);
     compositor.run()
 }
 
+// ============================================================================
+// Heap
+// ============================================================================
+
+atom_syscall::define_global_allocator!();
+
 // ============================================================================
 // Panic Handler
 // ============================================================================
 
-#[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    log("Desktop: PANIC!");
-    exit(0xFF);
-}
+atom_syscall::define_panic_handler!("Desktop");
@@ -0,0 +1,174 @@
+//! Notifications
+//!
+//! Applications post notifications with `Notify`; the compositor shows
+//! them as toasts stacked in the top-right corner, newest on top, until
+//! they time out or are clicked. Every notification also goes into a
+//! bounded history applications can read back.
+//!
+//! In do-not-disturb mode only critical notifications pop up; the rest
+//! are recorded silently.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use atom_syscall::graphics::{Color, Framebuffer};
+use libipc::messages::{Notification, NotificationRecord, Rect, Urgency};
+
+use crate::theme::Theme;
+
+pub const TOAST_WIDTH: u32 = 300;
+pub const TOAST_HEIGHT: u32 = 64;
+
+/// Gap between toasts, and between the stack and the screen edge
+const TOAST_GAP: u32 = 8;
+
+/// Toasts shown at once; older ones wait until those above go away
+const MAX_VISIBLE: usize = 4;
+
+/// Notifications kept in the history
+const HISTORY_LEN: usize = 50;
+
+/// Default display time in timer ticks (10 ms each)
+const LOW_TICKS: u64 = 300;
+const NORMAL_TICKS: u64 = 500;
+
+/// Characters that fit on one line of a toast
+const LINE_CHARS: usize = ((TOAST_WIDTH - 20) / 8) as usize;
+
+struct Toast {
+    notification: Notification,
+    /// Tick it goes away at, `None` until dismissed
+    expires: Option<u64>,
+}
+
+pub struct Notifications {
+    /// Shown toasts, newest first
+    toasts: Vec<Toast>,
+    /// Newest first
+    history: VecDeque<NotificationRecord>,
+    next_id: u32,
+    pub do_not_disturb: bool,
+}
+
+impl Notifications {
+    pub fn new() -> Self {
+        Self {
+            toasts: Vec::new(),
+            history: VecDeque::new(),
+            next_id: 1,
+            do_not_disturb: false,
+        }
+    }
+
+    /// Record a notification and show it unless do-not-disturb hides it;
+    /// returns whether the toast stack changed
+    pub fn post(&mut self, notification: Notification, now: u64) -> bool {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_back();
+        }
+        self.history.push_front(NotificationRecord {
+            id,
+            timestamp: now,
+            notification: notification.clone(),
+        });
+
+        if self.do_not_disturb && notification.urgency != Urgency::Critical {
+            return false;
+        }
+
+        let ticks = match (notification.timeout_ms, notification.urgency) {
+            (0, Urgency::Critical) => None,
+            (0, Urgency::Low) => Some(LOW_TICKS),
+            (0, Urgency::Normal) => Some(NORMAL_TICKS),
+            (ms, _) => Some((ms as u64).div_ceil(10)),
+        };
+        self.toasts.insert(0, Toast {
+            notification,
+            expires: ticks.map(|t| now + t),
+        });
+        true
+    }
+
+    /// Drop timed-out toasts; returns whether any went away
+    pub fn expire(&mut self, now: u64) -> bool {
+        let count = self.toasts.len();
+        self.toasts.retain(|t| t.expires.is_none_or(|at| now < at));
+        self.toasts.len() != count
+    }
+
+    pub fn is_showing(&self) -> bool {
+        !self.toasts.is_empty()
+    }
+
+    /// Dismiss the toast under the point; returns whether one was hit
+    pub fn dismiss_at(&mut self, screen_w: u32, x: i32, y: i32) -> bool {
+        let hit = (0..self.visible()).find(|&i| toast_rect(screen_w, i).contains(x, y));
+        match hit {
+            Some(index) => {
+                self.toasts.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Past notifications, newest first
+    pub fn history(&self) -> Vec<NotificationRecord> {
+        self.history.iter().cloned().collect()
+    }
+
+    /// Area the toast stack can cover
+    pub fn area(&self, screen_w: u32) -> Rect {
+        let height = MAX_VISIBLE as u32 * (TOAST_HEIGHT + TOAST_GAP);
+        let top = toast_rect(screen_w, 0);
+        Rect::new(top.x, top.y, TOAST_WIDTH, height)
+    }
+
+    pub fn draw(&self, fb: &Framebuffer, theme: &Theme, screen_w: u32) {
+        for (index, toast) in self.toasts.iter().take(MAX_VISIBLE).enumerate() {
+            draw_toast(fb, theme, &toast_rect(screen_w, index), toast);
+        }
+    }
+
+    fn visible(&self) -> usize {
+        self.toasts.len().min(MAX_VISIBLE)
+    }
+}
+
+/// Rectangle of the toast in stack slot `index` (0 = top)
+fn toast_rect(screen_w: u32, index: usize) -> Rect {
+    let x = screen_w.saturating_sub(TOAST_WIDTH + TOAST_GAP);
+    let y = crate::PANEL_HEIGHT as u32 + TOAST_GAP + index as u32 * (TOAST_HEIGHT + TOAST_GAP);
+    Rect::new(x as i32, y as i32, TOAST_WIDTH, TOAST_HEIGHT)
+}
+
+fn urgency_color(theme: &Theme, urgency: Urgency) -> Color {
+    match urgency {
+        Urgency::Low => theme.text_dim,
+        Urgency::Normal => theme.accent,
+        Urgency::Critical => theme.urgent,
+    }
+}
+
+fn draw_toast(fb: &Framebuffer, theme: &Theme, rect: &Rect, toast: &Toast) {
+    let (x, y, w, h) = (rect.x as u32, rect.y as u32, rect.width, rect.height);
+    let notification = &toast.notification;
+
+    fb.fill_rect(x, y, w, h, theme.window_border);
+    fb.fill_rect(x + 1, y + 1, w - 2, h - 2, theme.panel_bg);
+    fb.fill_rect(x + 1, y + 1, 4, h - 2, urgency_color(theme, notification.urgency));
+
+    let text_x = x + 12;
+    fb.draw_string(text_x, y + 8, line(&notification.app_name), theme.text_dim, theme.panel_bg);
+    fb.draw_string(text_x, y + 24, line(&notification.title), theme.panel_text, theme.panel_bg);
+    fb.draw_string(text_x, y + 40, line(&notification.body), theme.panel_text, theme.panel_bg);
+}
+
+/// The part of `text` that fits on one toast line
+fn line(text: &str) -> &str {
+    let end = text.char_indices().nth(LINE_CHARS).map_or(text.len(), |(i, _)| i);
+    &text[..end]
+}
@@ -0,0 +1,196 @@
+//! On-Screen Keyboard
+//!
+//! Keyboard overlay above the dock, toggled from the panel, for touch
+//! screens and for when the PS/2 keyboard is not working. Clicking a key
+//! types it into the focused window through the same path as the real
+//! keyboard: the compositor feeds the key's scancodes to its own keyboard
+//! state, so modifiers, shortcuts and the launcher behave as usual.
+//!
+//! Shift, Ctrl and Alt latch: clicking one holds it down for the next key
+//! typed, clicking it again lets go.
+
+use alloc::vec::Vec;
+
+use atom_syscall::graphics::Framebuffer;
+use libipc::keycode::KeyCode;
+use libipc::messages::Rect;
+
+use crate::dock;
+use crate::theme::Theme;
+
+/// Width of half a standard key, gap included
+const HALF_KEY: u32 = 18;
+const KEY_HEIGHT: u32 = 36;
+/// Space between neighbouring keys
+const GAP: u32 = 4;
+const PADDING: u32 = 8;
+/// Space between the keyboard and the dock
+const MARGIN: u32 = 8;
+
+/// Every row is this many half keys wide
+const ROW_HALVES: u32 = 30;
+
+const WIDTH: u32 = PADDING * 2 + ROW_HALVES * HALF_KEY;
+const HEIGHT: u32 = PADDING * 2 + ROWS.len() as u32 * KEY_HEIGHT;
+
+/// A key: what it types, its label and its width in half keys
+type Key = (KeyCode, &'static str, u32);
+
+const ROWS: [&[Key]; 5] = [
+    &[
+        (KeyCode::Escape, "Esc", 2),
+        (KeyCode::Digit1, "1", 2),
+        (KeyCode::Digit2, "2", 2),
+        (KeyCode::Digit3, "3", 2),
+        (KeyCode::Digit4, "4", 2),
+        (KeyCode::Digit5, "5", 2),
+        (KeyCode::Digit6, "6", 2),
+        (KeyCode::Digit7, "7", 2),
+        (KeyCode::Digit8, "8", 2),
+        (KeyCode::Digit9, "9", 2),
+        (KeyCode::Digit0, "0", 2),
+        (KeyCode::Minus, "-", 2),
+        (KeyCode::Equal, "=", 2),
+        (KeyCode::Backspace, "Bksp", 4),
+    ],
+    &[
+        (KeyCode::Tab, "Tab", 3),
+        (KeyCode::KeyQ, "Q", 2),
+        (KeyCode::KeyW, "W", 2),
+        (KeyCode::KeyE, "E", 2),
+        (KeyCode::KeyR, "R", 2),
+        (KeyCode::KeyT, "T", 2),
+        (KeyCode::KeyY, "Y", 2),
+        (KeyCode::KeyU, "U", 2),
+        (KeyCode::KeyI, "I", 2),
+        (KeyCode::KeyO, "O", 2),
+        (KeyCode::KeyP, "P", 2),
+        (KeyCode::BracketLeft, "[", 2),
+        (KeyCode::BracketRight, "]", 2),
+        (KeyCode::Backslash, "\\", 3),
+    ],
+    &[
+        (KeyCode::CapsLock, "Caps", 4),
+        (KeyCode::KeyA, "A", 2),
+        (KeyCode::KeyS, "S", 2),
+        (KeyCode::KeyD, "D", 2),
+        (KeyCode::KeyF, "F", 2),
+        (KeyCode::KeyG, "G", 2),
+        (KeyCode::KeyH, "H", 2),
+        (KeyCode::KeyJ, "J", 2),
+        (KeyCode::KeyK, "K", 2),
+        (KeyCode::KeyL, "L", 2),
+        (KeyCode::Semicolon, ";", 2),
+        (KeyCode::Quote, "'", 2),
+        (KeyCode::Enter, "Enter", 4),
+    ],
+    &[
+        (KeyCode::ShiftLeft, "Shift", 5),
+        (KeyCode::KeyZ, "Z", 2),
+        (KeyCode::KeyX, "X", 2),
+        (KeyCode::KeyC, "C", 2),
+        (KeyCode::KeyV, "V", 2),
+        (KeyCode::KeyB, "B", 2),
+        (KeyCode::KeyN, "N", 2),
+        (KeyCode::KeyM, "M", 2),
+        (KeyCode::Comma, ",", 2),
+        (KeyCode::Period, ".", 2),
+        (KeyCode::Slash, "/", 2),
+        (KeyCode::ShiftLeft, "Shift", 5),
+    ],
+    &[
+        (KeyCode::ControlLeft, "Ctrl", 4),
+        (KeyCode::AltLeft, "Alt", 4),
+        (KeyCode::Backquote, "`", 2),
+        (KeyCode::Space, "", 20),
+    ],
+];
+
+/// Overlay rectangle, centred above the dock
+pub fn bounds(screen_w: u32, screen_h: u32) -> Rect {
+    let x = (screen_w / 2).saturating_sub(WIDTH / 2);
+    let dock_top = dock::area(screen_w, screen_h).y as u32;
+    let y = dock_top.saturating_sub(HEIGHT + MARGIN);
+    Rect::new(x as i32, y as i32, WIDTH, HEIGHT)
+}
+
+/// Every key with its rectangle on screen
+fn keys(screen_w: u32, screen_h: u32) -> impl Iterator<Item = (Rect, KeyCode, &'static str)> {
+    let area = bounds(screen_w, screen_h);
+    ROWS.iter().enumerate().flat_map(move |(row, keys)| {
+        let y = area.y + (PADDING + row as u32 * KEY_HEIGHT) as i32;
+        let mut x = area.x + PADDING as i32;
+        keys.iter().map(move |&(code, label, halves)| {
+            let width = halves * HALF_KEY;
+            let rect = Rect::new(x, y, width - GAP, KEY_HEIGHT - GAP);
+            x += width as i32;
+            (rect, code, label)
+        })
+    })
+}
+
+fn is_latching(code: KeyCode) -> bool {
+    matches!(code, KeyCode::ShiftLeft | KeyCode::ControlLeft | KeyCode::AltLeft)
+}
+
+pub struct Osk {
+    /// Modifiers held down for the next key
+    latched: Vec<KeyCode>,
+}
+
+impl Osk {
+    pub fn new() -> Self {
+        Self { latched: Vec::new() }
+    }
+
+    /// Whether a key is under the point
+    pub fn key_at(&self, screen_w: u32, screen_h: u32, x: i32, y: i32) -> bool {
+        keys(screen_w, screen_h).any(|(rect, _, _)| rect.contains(x, y))
+    }
+
+    /// Click at (`x`, `y`): the scancodes to feed the keyboard, empty when
+    /// only a modifier latched or nothing was hit
+    pub fn click(&mut self, screen_w: u32, screen_h: u32, x: i32, y: i32) -> Vec<u8> {
+        let Some((_, code, _)) = keys(screen_w, screen_h).find(|(r, _, _)| r.contains(x, y)) else {
+            return Vec::new();
+        };
+
+        if is_latching(code) {
+            match self.latched.iter().position(|&held| held == code) {
+                Some(pos) => {
+                    self.latched.remove(pos);
+                }
+                None => self.latched.push(code),
+            }
+            return Vec::new();
+        }
+
+        // The key codes on the keyboard are all set 1 make codes; a break
+        // code has the top bit set
+        let mut scancodes = Vec::new();
+        scancodes.extend(self.latched.iter().map(|&held| held as u8));
+        scancodes.extend([code as u8, code as u8 | 0x80]);
+        scancodes.extend(self.latched.drain(..).map(|held| held as u8 | 0x80));
+        scancodes
+    }
+
+    pub fn draw(&self, fb: &Framebuffer, theme: &Theme) {
+        let area = bounds(fb.width(), fb.height());
+        let (x, y) = (area.x as u32, area.y as u32);
+        fb.fill_rect(x, y, area.width, area.height, theme.window_border);
+        fb.fill_rect(x + 1, y + 1, area.width - 2, area.height - 2, theme.panel_bg);
+
+        for (rect, code, label) in keys(fb.width(), fb.height()) {
+            let (bg, fg) = if self.latched.contains(&code) {
+                (theme.accent, theme.panel_bg)
+            } else {
+                (theme.window_header, theme.panel_text)
+            };
+            let (kx, ky) = (rect.x as u32, rect.y as u32);
+            fb.fill_rect(kx, ky, rect.width, rect.height, bg);
+            let text_w = label.len() as u32 * 8;
+            let tx = kx + rect.width.saturating_sub(text_w) / 2;
+            fb.draw_string(tx, ky + (rect.height - 16) / 2, label, fg, bg);
+        }
+    }
+}
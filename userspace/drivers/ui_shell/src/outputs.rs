@@ -0,0 +1,118 @@
+//! Outputs
+//!
+//! The desktop is one coordinate space spanning every output (monitor),
+//! laid out left to right with the primary output at the origin. There is
+//! one output per head the graphics service lists, in its order. Windows
+//! can be dragged across output boundaries freely; maximizing and snapping
+//! fill the output a window or the pointer is on.
+//!
+//! The shell's own UI (clock, dock, toasts, overlays) lives on the primary
+//! output. Each other output gets a panel of its own with the workspace
+//! indicator.
+
+use alloc::vec::Vec;
+
+use libipc::messages::{DisplayInfo, Rect, ScaleFactor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Output {
+    pub id: u32,
+    /// Area in desktop coordinates
+    pub rect: Rect,
+    pub primary: bool,
+}
+
+pub struct Outputs {
+    /// Left to right, the primary first
+    outputs: Vec<Output>,
+}
+
+impl Outputs {
+    /// Outputs of the given sizes, the first one primary
+    pub fn new(sizes: &[(u32, u32)]) -> Self {
+        let mut x = 0;
+        let outputs = sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &(width, height))| {
+                let output = Output {
+                    id: i as u32 + 1,
+                    rect: Rect::new(x, 0, width, height),
+                    primary: i == 0,
+                };
+                x += width as i32;
+                output
+            })
+            .collect();
+        Self { outputs }
+    }
+
+    /// The same outputs on a screen now `width` x `height`: the others
+    /// keep their sizes and the primary takes the width they leave. A
+    /// screen too narrow for them leaves only the primary.
+    pub fn resized(&self, width: u32, height: u32) -> Self {
+        let others = &self.outputs[1..];
+        let taken: u32 = others.iter().map(|o| o.rect.width).sum();
+        if taken >= width {
+            return Self::new(&[(width, height)]);
+        }
+
+        let mut sizes = Vec::from([(width - taken, height)]);
+        sizes.extend(others.iter().map(|o| (o.rect.width, o.rect.height.min(height))));
+        Self::new(&sizes)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Output> {
+        self.outputs.iter()
+    }
+
+    pub fn primary(&self) -> &Output {
+        &self.outputs[0]
+    }
+
+    /// Output containing the point, or the nearest one
+    pub fn at(&self, x: i32, y: i32) -> &Output {
+        self.outputs
+            .iter()
+            .find(|o| o.rect.contains(x, y))
+            .or_else(|| self.outputs.iter().min_by_key(|o| distance(&o.rect, x, y)))
+            .unwrap_or(self.primary())
+    }
+
+    /// Output showing the centre of `rect`
+    pub fn of(&self, rect: &Rect) -> &Output {
+        let x = rect.x + rect.width as i32 / 2;
+        let y = rect.y + rect.height as i32 / 2;
+        self.at(x, y)
+    }
+
+    /// Size of the area spanning all outputs
+    pub fn desktop_size(&self) -> (u32, u32) {
+        self.outputs.iter().fold((0, 0), |(w, h), o| {
+            (w.max(o.rect.right() as u32), h.max(o.rect.bottom() as u32))
+        })
+    }
+
+    /// What `GetDisplays` reports
+    pub fn info(&self, scale: ScaleFactor) -> Vec<DisplayInfo> {
+        self.outputs
+            .iter()
+            .map(|o| DisplayInfo {
+                id: o.id,
+                x: o.rect.x,
+                y: o.rect.y,
+                width: o.rect.width,
+                height: o.rect.height,
+                scale,
+                primary: o.primary,
+            })
+            .collect()
+    }
+}
+
+/// Manhattan distance from a point to the nearest point of `rect`
+fn distance(rect: &Rect, x: i32, y: i32) -> i32 {
+    let dx = (rect.x - x).max(x - (rect.right() - 1)).max(0);
+    let dy = (rect.y - y).max(y - (rect.bottom() - 1)).max(0);
+    dx + dy
+}
@@ -0,0 +1,84 @@
+//! Pointer Acceleration
+//!
+//! Turns raw mouse counts into cursor motion. `Flat` scales by the
+//! sensitivity only; `Adaptive` also raises the gain with pointer speed, so
+//! slow movements stay precise while fast flicks cross the screen.
+//!
+//! The math is fixed point (8 fractional bits). Each axis keeps its
+//! fractional remainder, so slow motion at low sensitivity is not lost.
+
+use libipc::messages::{AccelProfile, PointerSettings};
+
+const FRAC_BITS: u32 = 8;
+const ONE: i32 = 1 << FRAC_BITS;
+
+/// Speed (counts per packet) up to which Adaptive behaves like Flat
+const ADAPTIVE_THRESHOLD: i32 = 2;
+
+/// Speed at which Adaptive reaches its maximum gain
+const ADAPTIVE_CAP: i32 = 12;
+
+/// Extra gain at or above `ADAPTIVE_CAP` (1.5, for 2.5x in total)
+const ADAPTIVE_MAX_EXTRA: i32 = ONE * 3 / 2;
+
+pub struct PointerAccel {
+    settings: PointerSettings,
+    remainder_x: i32,
+    remainder_y: i32,
+}
+
+impl PointerAccel {
+    pub const fn new() -> Self {
+        Self {
+            settings: PointerSettings::DEFAULT,
+            remainder_x: 0,
+            remainder_y: 0,
+        }
+    }
+
+    pub fn settings(&self) -> PointerSettings {
+        self.settings
+    }
+
+    /// Apply new settings, clamping sensitivity to the supported range
+    pub fn set_settings(&mut self, settings: PointerSettings) {
+        self.settings = PointerSettings {
+            profile: settings.profile,
+            sensitivity: settings
+                .sensitivity
+                .clamp(PointerSettings::MIN_SENSITIVITY, PointerSettings::MAX_SENSITIVITY),
+        };
+        self.remainder_x = 0;
+        self.remainder_y = 0;
+    }
+
+    /// Scale one packet's deltas
+    pub fn apply(&mut self, dx: i32, dy: i32) -> (i32, i32) {
+        let gain = self.gain(dx.abs().max(dy.abs()));
+
+        let x = dx * gain + self.remainder_x;
+        let y = dy * gain + self.remainder_y;
+
+        // Arithmetic shift floors, so the remainders stay in 0..ONE
+        let out_x = x >> FRAC_BITS;
+        let out_y = y >> FRAC_BITS;
+        self.remainder_x = x - (out_x << FRAC_BITS);
+        self.remainder_y = y - (out_y << FRAC_BITS);
+
+        (out_x, out_y)
+    }
+
+    /// Gain in fixed point for a packet of the given speed
+    fn gain(&self, speed: i32) -> i32 {
+        let base = self.settings.sensitivity as i32 * ONE / 100;
+
+        match self.settings.profile {
+            AccelProfile::Flat => base,
+            AccelProfile::Adaptive => {
+                let range = ADAPTIVE_CAP - ADAPTIVE_THRESHOLD;
+                let t = (speed - ADAPTIVE_THRESHOLD).clamp(0, range);
+                base + base * ADAPTIVE_MAX_EXTRA * t / (range * ONE)
+            }
+        }
+    }
+}
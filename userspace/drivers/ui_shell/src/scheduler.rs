@@ -0,0 +1,62 @@
+//! Frame Scheduler
+//!
+//! The compositor draws at a steady rate rather than whenever something
+//! changes. Damage and cursor motion collect between frames; when a frame
+//! is due everything is composed and presented at once, then each window
+//! that committed since the last frame is sent `FrameDone`. Applications
+//! that wait for it before drawing again animate at the frame rate
+//! instead of committing as fast as they can.
+//!
+//! There is no vblank interrupt yet, so the clock runs off the timer:
+//! frame `n` is due `n * 1000 / FRAME_RATE` ms after the clock started,
+//! which averages out the 10 ms tick. Frames missed while the compositor
+//! was busy are skipped, not caught up.
+
+use alloc::vec::Vec;
+
+use libipc::messages::WindowId;
+
+/// Frames per second
+const FRAME_RATE: u64 = 60;
+
+pub struct FrameClock {
+    /// When frame 0 was due, in ms since boot
+    start: u64,
+    /// Number of the next frame
+    next: u64,
+    /// Windows that committed since the last frame
+    waiting: Vec<WindowId>,
+}
+
+impl FrameClock {
+    pub fn new(now_ms: u64) -> Self {
+        Self {
+            start: now_ms,
+            next: 0,
+            waiting: Vec::new(),
+        }
+    }
+
+    /// Whether a frame is due at `now_ms`; if so the clock moves on to
+    /// the first frame after it
+    pub fn due(&mut self, now_ms: u64) -> bool {
+        let elapsed = now_ms.saturating_sub(self.start);
+        if elapsed * FRAME_RATE < self.next * 1000 {
+            return false;
+        }
+        self.next = elapsed * FRAME_RATE / 1000 + 1;
+        true
+    }
+
+    /// Note a commit from `window`, to be answered after the next frame
+    pub fn committed(&mut self, window: WindowId) {
+        if !self.waiting.contains(&window) {
+            self.waiting.push(window);
+        }
+    }
+
+    /// Windows to send `FrameDone` for the frame just presented
+    pub fn take_waiting(&mut self) -> Vec<WindowId> {
+        core::mem::take(&mut self.waiting)
+    }
+}
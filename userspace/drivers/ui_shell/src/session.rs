@@ -0,0 +1,134 @@
+//! Session
+//!
+//! Where each application's window was when it last closed, keyed by the
+//! app id it sends with `CreateWindow`: floating geometry, workspace and
+//! place in the stack. A window the same application opens again goes
+//! back there.
+//!
+//! The state is a config file with one line per application:
+//! `x y width height workspace layer app_id`, where `layer` counts from
+//! the bottom of the stack. It is read through the filesystem service at
+//! startup and written back at shutdown; without the service the session
+//! starts empty and is lost when the desktop stops.
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+
+use atom_std::fs;
+use libipc::status::StatusResult;
+
+use crate::wm::{Window, WindowManager};
+
+/// Applications remembered; the least recently closed go first
+const MAX_APPS: usize = 64;
+
+/// Where the session is kept between runs
+pub const CONFIG_PATH: &str = "/etc/desktop/session";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SavedWindow {
+    pub geometry: (i32, i32, u32, u32),
+    pub workspace: u8,
+    /// Position in the stack, 0 = bottom
+    pub layer: u32,
+    /// When it was recorded, to drop the oldest entries first
+    stamp: u64,
+}
+
+pub struct Session {
+    windows: BTreeMap<String, SavedWindow>,
+    next_stamp: u64,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            windows: BTreeMap::new(),
+            next_stamp: 0,
+        }
+    }
+
+    /// The session saved at `CONFIG_PATH`, or an empty one if there is
+    /// none or it cannot be read
+    pub fn load() -> Self {
+        fs::read_to_string(CONFIG_PATH)
+            .map(|config| Self::from_config(&config))
+            .unwrap_or_else(|_| Self::new())
+    }
+
+    /// Write the current state to `CONFIG_PATH`
+    pub fn save(&self) -> StatusResult<()> {
+        fs::write(CONFIG_PATH, self.to_config().as_bytes())
+    }
+
+    /// The state a config file describes; malformed lines are skipped
+    pub fn from_config(config: &str) -> Self {
+        let mut session = Self::new();
+        for (app_id, saved) in config.lines().filter_map(parse_line) {
+            session.insert(app_id, saved.geometry, saved.workspace, saved.layer);
+        }
+        session
+    }
+
+    /// The config file for the current state
+    pub fn to_config(&self) -> String {
+        let mut config = String::new();
+        for (app_id, saved) in &self.windows {
+            let (x, y, width, height) = saved.geometry;
+            let (workspace, layer) = (saved.workspace, saved.layer);
+            config.push_str(&format!("{x} {y} {width} {height} {workspace} {layer} {app_id}\n"));
+        }
+        config
+    }
+
+    pub fn get(&self, app_id: &str) -> Option<&SavedWindow> {
+        self.windows.get(app_id)
+    }
+
+    /// Record where `window` is; `layer` is its index in the stack
+    pub fn remember(&mut self, window: &Window, layer: usize) {
+        if window.app_id.is_empty() {
+            return;
+        }
+        // A tiled window comes back at its floating size
+        let geometry = window.saved_geometry.unwrap_or(window.geometry());
+        self.insert(&window.app_id, geometry, window.workspace, layer as u32);
+    }
+
+    /// Record every window still open
+    pub fn remember_all(&mut self, wm: &WindowManager) {
+        for (layer, window) in wm.windows.iter().enumerate() {
+            self.remember(window, layer);
+        }
+    }
+
+    fn insert(&mut self, app_id: &str, geometry: (i32, i32, u32, u32), workspace: u8, layer: u32) {
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        let saved = SavedWindow { geometry, workspace, layer, stamp };
+        self.windows.insert(String::from(app_id), saved);
+
+        if self.windows.len() > MAX_APPS {
+            let oldest = self.windows.iter().min_by_key(|(_, s)| s.stamp).map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                self.windows.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// A config line's app id and window; the stamp is left for `insert`
+fn parse_line(line: &str) -> Option<(&str, SavedWindow)> {
+    let mut fields = line.trim().splitn(7, ' ');
+    let geometry = (
+        fields.next()?.parse().ok()?,
+        fields.next()?.parse().ok()?,
+        fields.next()?.parse().ok()?,
+        fields.next()?.parse().ok()?,
+    );
+    let workspace = fields.next()?.parse().ok()?;
+    let layer = fields.next()?.parse().ok()?;
+    let app_id = fields.next().filter(|app_id| !app_id.is_empty())?;
+    Some((app_id, SavedWindow { geometry, workspace, layer, stamp: 0 }))
+}
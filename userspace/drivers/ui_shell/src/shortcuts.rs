@@ -0,0 +1,76 @@
+//! Global Shortcuts
+//!
+//! Key combinations the compositor handles itself, checked before a key is
+//! routed to the focused application. A settings app can rebind actions
+//! with `SetShortcut`.
+
+use alloc::vec::Vec;
+
+use libipc::keycode::KeyCode;
+use libipc::messages::{ShortcutAction, ShortcutBinding};
+
+const ALT: u8 = ShortcutBinding::MOD_ALT;
+const SHIFT: u8 = ShortcutBinding::MOD_SHIFT;
+const SUPER: u8 = ShortcutBinding::MOD_SUPER;
+
+const DEFAULTS: [ShortcutBinding; 17] = [
+    ShortcutBinding::new(ShortcutAction::SwitchWindow, ALT, KeyCode::Tab),
+    ShortcutBinding::new(ShortcutAction::SwitchWindowReverse, ALT | SHIFT, KeyCode::Tab),
+    ShortcutBinding::new(ShortcutAction::CloseWindow, SUPER, KeyCode::KeyQ),
+    ShortcutBinding::new(ShortcutAction::SnapLeft, SUPER, KeyCode::ArrowLeft),
+    ShortcutBinding::new(ShortcutAction::SnapRight, SUPER, KeyCode::ArrowRight),
+    ShortcutBinding::new(ShortcutAction::Maximize, SUPER, KeyCode::ArrowUp),
+    ShortcutBinding::new(ShortcutAction::Minimize, SUPER, KeyCode::ArrowDown),
+    ShortcutBinding::new(ShortcutAction::Workspace1, SUPER, KeyCode::Digit1),
+    ShortcutBinding::new(ShortcutAction::Workspace2, SUPER, KeyCode::Digit2),
+    ShortcutBinding::new(ShortcutAction::Workspace3, SUPER, KeyCode::Digit3),
+    ShortcutBinding::new(ShortcutAction::Workspace4, SUPER, KeyCode::Digit4),
+    ShortcutBinding::new(ShortcutAction::MoveToWorkspace1, SUPER | SHIFT, KeyCode::Digit1),
+    ShortcutBinding::new(ShortcutAction::MoveToWorkspace2, SUPER | SHIFT, KeyCode::Digit2),
+    ShortcutBinding::new(ShortcutAction::MoveToWorkspace3, SUPER | SHIFT, KeyCode::Digit3),
+    ShortcutBinding::new(ShortcutAction::MoveToWorkspace4, SUPER | SHIFT, KeyCode::Digit4),
+    ShortcutBinding::new(ShortcutAction::Screenshot, 0, KeyCode::PrintScreen),
+    ShortcutBinding::new(ShortcutAction::TogglePerformanceHud, SUPER | SHIFT, KeyCode::KeyP),
+];
+
+pub struct Shortcuts {
+    bindings: Vec<ShortcutBinding>,
+}
+
+impl Shortcuts {
+    pub fn new() -> Self {
+        Self {
+            bindings: Vec::from(DEFAULTS),
+        }
+    }
+
+    /// Bind an action, replacing its previous combination
+    pub fn bind(&mut self, binding: ShortcutBinding) {
+        self.bindings.retain(|b| b.action != binding.action);
+        self.bindings.push(binding);
+    }
+
+    /// Action for a key pressed with the given `MOD_*` bits held
+    pub fn lookup(&self, modifiers: u8, key: KeyCode) -> Option<ShortcutAction> {
+        self.bindings
+            .iter()
+            .find(|b| b.modifiers == modifiers && b.key == key)
+            .map(|b| b.action)
+    }
+}
+
+/// Workspace a `Workspace*` or `MoveToWorkspace*` action refers to, and
+/// whether it moves the focused window there instead of showing it
+pub fn workspace_action(action: ShortcutAction) -> Option<(u8, bool)> {
+    match action {
+        ShortcutAction::Workspace1 => Some((0, false)),
+        ShortcutAction::Workspace2 => Some((1, false)),
+        ShortcutAction::Workspace3 => Some((2, false)),
+        ShortcutAction::Workspace4 => Some((3, false)),
+        ShortcutAction::MoveToWorkspace1 => Some((0, true)),
+        ShortcutAction::MoveToWorkspace2 => Some((1, true)),
+        ShortcutAction::MoveToWorkspace3 => Some((2, true)),
+        ShortcutAction::MoveToWorkspace4 => Some((3, true)),
+        _ => None,
+    }
+}
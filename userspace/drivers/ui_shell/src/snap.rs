@@ -0,0 +1,139 @@
+//! Window Snapping
+//!
+//! Tiled positions a window can snap to: the whole work area, its left or
+//! right half, or one of its quarters. Dragging a window's title bar to a
+//! screen edge snaps it to the half on that side (quarters at the corners,
+//! maximized at the top); Super+arrow keys move between the same zones.
+//!
+//! A snapped window keeps its floating geometry in `saved_geometry`, so
+//! dragging it away or restoring it brings back the previous size.
+
+use libipc::messages::{Rect, ShortcutAction};
+
+/// How close to a screen edge the pointer must be to snap
+const EDGE_ZONE: i32 = 4;
+
+/// How far from a corner along the side edges still snaps to a quarter
+const CORNER_ZONE: i32 = 64;
+
+type Geometry = (i32, i32, u32, u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapZone {
+    Maximize,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// What a keyboard tiling shortcut does to the focused window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tiling {
+    Snap(SnapZone),
+    /// Back to the saved floating geometry
+    Restore,
+    Minimize,
+}
+
+const ZONES: [SnapZone; 7] = [
+    SnapZone::Maximize,
+    SnapZone::Left,
+    SnapZone::Right,
+    SnapZone::TopLeft,
+    SnapZone::TopRight,
+    SnapZone::BottomLeft,
+    SnapZone::BottomRight,
+];
+
+/// Zone the pointer is over while dragging, if it touches a screen edge
+pub fn zone_at(x: i32, y: i32, screen_w: u32, screen_h: u32) -> Option<SnapZone> {
+    let (w, h) = (screen_w as i32, screen_h as i32);
+    let top = y < CORNER_ZONE;
+    let bottom = y >= h - CORNER_ZONE;
+
+    if x < EDGE_ZONE {
+        Some(match (top, bottom) {
+            (true, _) => SnapZone::TopLeft,
+            (_, true) => SnapZone::BottomLeft,
+            _ => SnapZone::Left,
+        })
+    } else if x >= w - EDGE_ZONE {
+        Some(match (top, bottom) {
+            (true, _) => SnapZone::TopRight,
+            (_, true) => SnapZone::BottomRight,
+            _ => SnapZone::Right,
+        })
+    } else if y < EDGE_ZONE {
+        Some(SnapZone::Maximize)
+    } else {
+        None
+    }
+}
+
+/// Geometry of `zone` within the work area
+pub fn geometry(zone: SnapZone, work: Geometry) -> Geometry {
+    let (x, y, width, height) = work;
+    let (left_w, top_h) = (width / 2, height / 2);
+    let (right_x, bottom_y) = (x + left_w as i32, y + top_h as i32);
+    let (right_w, bottom_h) = (width - left_w, height - top_h);
+
+    match zone {
+        SnapZone::Maximize => work,
+        SnapZone::Left => (x, y, left_w, height),
+        SnapZone::Right => (right_x, y, right_w, height),
+        SnapZone::TopLeft => (x, y, left_w, top_h),
+        SnapZone::TopRight => (right_x, y, right_w, top_h),
+        SnapZone::BottomLeft => (x, bottom_y, left_w, bottom_h),
+        SnapZone::BottomRight => (right_x, bottom_y, right_w, bottom_h),
+    }
+}
+
+/// Zone a window currently fills exactly, if any
+pub fn zone_of(window: Geometry, work: Geometry) -> Option<SnapZone> {
+    ZONES.into_iter().find(|&zone| geometry(zone, work) == window)
+}
+
+/// Screen rectangle of the drop preview for `zone`
+pub fn preview(zone: SnapZone, work: Geometry) -> Rect {
+    let (x, y, width, height) = geometry(zone, work);
+    Rect::new(x, y, width, height)
+}
+
+/// Where a Super+arrow shortcut takes a window currently in `current`.
+/// Up and down split a half into quarters and join quarters back into
+/// halves; left and right cross over to the other side.
+pub fn keyboard_target(action: ShortcutAction, current: Option<SnapZone>) -> Option<Tiling> {
+    use SnapZone::*;
+
+    let target = match (action, current) {
+        (ShortcutAction::SnapLeft, Some(Right)) => Tiling::Restore,
+        (ShortcutAction::SnapLeft, Some(TopRight)) => Tiling::Snap(TopLeft),
+        (ShortcutAction::SnapLeft, Some(BottomRight)) => Tiling::Snap(BottomLeft),
+        (ShortcutAction::SnapLeft, _) => Tiling::Snap(Left),
+
+        (ShortcutAction::SnapRight, Some(Left)) => Tiling::Restore,
+        (ShortcutAction::SnapRight, Some(TopLeft)) => Tiling::Snap(TopRight),
+        (ShortcutAction::SnapRight, Some(BottomLeft)) => Tiling::Snap(BottomRight),
+        (ShortcutAction::SnapRight, _) => Tiling::Snap(Right),
+
+        (ShortcutAction::Maximize, Some(Left)) => Tiling::Snap(TopLeft),
+        (ShortcutAction::Maximize, Some(Right)) => Tiling::Snap(TopRight),
+        (ShortcutAction::Maximize, Some(BottomLeft)) => Tiling::Snap(Left),
+        (ShortcutAction::Maximize, Some(BottomRight)) => Tiling::Snap(Right),
+        (ShortcutAction::Maximize, Some(TopLeft | TopRight | Maximize)) => return None,
+        (ShortcutAction::Maximize, None) => Tiling::Snap(Maximize),
+
+        (ShortcutAction::Minimize, Some(Left)) => Tiling::Snap(BottomLeft),
+        (ShortcutAction::Minimize, Some(Right)) => Tiling::Snap(BottomRight),
+        (ShortcutAction::Minimize, Some(TopLeft)) => Tiling::Snap(Left),
+        (ShortcutAction::Minimize, Some(TopRight)) => Tiling::Snap(Right),
+        (ShortcutAction::Minimize, Some(Maximize)) => Tiling::Restore,
+        (ShortcutAction::Minimize, _) => Tiling::Minimize,
+
+        _ => return None,
+    };
+    Some(target)
+}
@@ -0,0 +1,164 @@
+//! Window Surfaces
+//!
+//! Each client window is backed by a shared-memory region the application
+//! draws into. The compositor owns the region, keeps it mapped and copies
+//! it into the window's client area when composing.
+//!
+//! Pixels are 32-bit in the framebuffer's format, so opaque rows are copied
+//! as-is. Translucent windows are blended pixel by pixel, using the window's
+//! opacity and, if the client asked for it, the alpha in each pixel's top
+//! byte.
+
+use alloc::vec::Vec;
+
+use atom_syscall::graphics::{blend_pixel, Framebuffer, Pixels};
+use atom_syscall::shm::{self, RegionFlags, RegionId};
+use libipc::messages::{Rect, MAX_SURFACE_BYTES, SURFACE_BYTES_PER_PIXEL};
+
+/// How a surface is combined with what is below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blend {
+    /// Whole-surface opacity, 255 = opaque
+    pub opacity: u8,
+    /// The top byte of each pixel is its alpha (ARGB)
+    pub per_pixel_alpha: bool,
+}
+
+impl Blend {
+    pub const OPAQUE: Self = Self {
+        opacity: 255,
+        per_pixel_alpha: false,
+    };
+}
+
+pub struct WindowSurface {
+    pub region: RegionId,
+    pub width: u32,
+    pub height: u32,
+    /// Row length in pixels
+    pub stride: u32,
+    base: *const u32,
+}
+
+impl WindowSurface {
+    /// Allocate and map a surface, or `None` if it is too large or the
+    /// kernel is out of memory
+    pub fn create(width: u32, height: u32) -> Option<Self> {
+        let size = width as usize * height as usize * SURFACE_BYTES_PER_PIXEL as usize;
+        if size == 0 || size > MAX_SURFACE_BYTES {
+            return None;
+        }
+
+        let region = shm::create_region(size).ok()?;
+        let base = match shm::map_anywhere(region, RegionFlags::read_write()) {
+            Ok(base) => base,
+            Err(_) => {
+                let _ = shm::destroy_region(region);
+                return None;
+            }
+        };
+
+        Some(Self {
+            region,
+            width,
+            height,
+            stride: width,
+            base: base as *const u32,
+        })
+    }
+
+    /// Map a surface an application kept from before a compositor restart,
+    /// or `None` if its size is out of range or the region cannot be mapped
+    pub fn attach(region: RegionId, width: u32, height: u32, stride: u32) -> Option<Self> {
+        let size = stride as usize * height as usize * SURFACE_BYTES_PER_PIXEL as usize;
+        if width == 0 || stride < width || size == 0 || size > MAX_SURFACE_BYTES {
+            return None;
+        }
+
+        let base = shm::map_anywhere(region, RegionFlags::read_write()).ok()?;
+
+        Some(Self {
+            region,
+            width,
+            height,
+            stride,
+            base: base as *const u32,
+        })
+    }
+
+    /// The surface's pixels as the application last drew them
+    pub fn view(&self) -> Pixels<'_> {
+        // The region stays mapped until the surface is dropped
+        let len = self.stride as usize * self.height as usize;
+        let data = unsafe { core::slice::from_raw_parts(self.base, len) };
+        Pixels::new(data, self.width, self.height, self.stride).unwrap_or_default()
+    }
+
+    /// Draw the top-left `width` x `height` pixels at (`x`, `y`) on screen,
+    /// clipped to the surface and the framebuffer's clip rectangle
+    pub fn blit(&self, fb: &Framebuffer, x: i32, y: i32, width: u32, height: u32, blend: Blend) {
+        let src = self.view().sub(0, 0, width, height);
+
+        if blend == Blend::OPAQUE {
+            fb.blit(x, y, &src);
+            return;
+        }
+
+        fb.blit_with(x, y, &src, |below, pixel| {
+            let alpha = if blend.per_pixel_alpha {
+                (pixel >> 24) * blend.opacity as u32 / 255
+            } else {
+                blend.opacity as u32
+            };
+            blend_pixel(below, pixel, alpha)
+        });
+    }
+
+    /// Copy of the surface's pixels, rows packed
+    pub fn pixels(&self) -> Vec<u32> {
+        let mut pixels = Vec::with_capacity(self.width as usize * self.height as usize);
+        for row in self.view().rows() {
+            pixels.extend_from_slice(row);
+        }
+        pixels
+    }
+
+    /// Draw the whole surface stretched to `dst` (nearest neighbour), faded
+    /// to `opacity`; used while a window animates
+    pub fn blit_scaled(&self, fb: &Framebuffer, dst: &Rect, opacity: u8) {
+        let src = self.view();
+        let (left, top, right, bottom) = fb.clip();
+
+        let x0 = dst.x.max(left as i32);
+        let y0 = dst.y.max(top as i32);
+        let x1 = dst.right().min(right as i32);
+        let y1 = dst.bottom().min(bottom as i32);
+        if x1 <= x0 || y1 <= y0 {
+            return;
+        }
+
+        for dst_y in y0..y1 {
+            let src_y = (dst_y - dst.y) as u32 * self.height / dst.height;
+            let Some(row) = src.row(src_y) else {
+                continue;
+            };
+
+            for dst_x in x0..x1 {
+                let src_x = (dst_x - dst.x) as u32 * self.width / dst.width;
+                let (out_x, out_y) = (dst_x as u32, dst_y as u32);
+                let (Some(&pixel), Some(below)) = (row.get(src_x as usize), fb.pixel(out_x, out_y))
+                else {
+                    continue;
+                };
+                fb.set_pixel(out_x, out_y, blend_pixel(below, pixel, opacity as u32));
+            }
+        }
+    }
+}
+
+impl Drop for WindowSurface {
+    fn drop(&mut self) {
+        let _ = shm::unmap_region(self.region);
+        let _ = shm::destroy_region(self.region);
+    }
+}
@@ -0,0 +1,58 @@
+//! Window Switcher
+//!
+//! Overlay listing the open windows while Alt+Tab cycles through them,
+//! topmost first. The highlighted entry is raised (and restored if
+//! minimized) when Alt is released.
+
+use atom_syscall::graphics::Framebuffer;
+use libipc::messages::{Rect, WindowId};
+
+use crate::theme::Theme;
+use crate::wm::{Window, WindowManager};
+
+const WIDTH: u32 = 320;
+const ROW_HEIGHT: u32 = 24;
+const PADDING: u32 = 8;
+
+/// Title characters that fit in a row
+const MAX_TITLE_CHARS: usize = ((WIDTH - PADDING * 2 - 16) / 8) as usize;
+
+/// Windows in switcher order (topmost first)
+fn entries(wm: &WindowManager) -> impl Iterator<Item = &Window> {
+    wm.windows.iter().rev()
+}
+
+/// Window at `index` in switcher order
+pub fn window_at(wm: &WindowManager, index: usize) -> Option<WindowId> {
+    entries(wm).nth(index).map(|w| w.id)
+}
+
+/// Overlay rectangle, centred on screen
+pub fn bounds(screen_w: u32, screen_h: u32, count: usize) -> Rect {
+    let height = PADDING * 2 + count as u32 * ROW_HEIGHT;
+    let x = (screen_w / 2).saturating_sub(WIDTH / 2);
+    let y = (screen_h / 2).saturating_sub(height / 2);
+    Rect::new(x as i32, y as i32, WIDTH, height)
+}
+
+pub fn draw(fb: &Framebuffer, theme: &Theme, wm: &WindowManager, selected: usize) {
+    let area = bounds(fb.width(), fb.height(), wm.windows.len());
+    let (x, y) = (area.x as u32, area.y as u32);
+
+    fb.fill_rect(x, y, area.width, area.height, theme.window_border);
+    fb.fill_rect(x + 1, y + 1, area.width - 2, area.height - 2, theme.panel_bg);
+
+    for (i, window) in entries(wm).enumerate() {
+        let row_y = y + PADDING + i as u32 * ROW_HEIGHT;
+        let bg = if i == selected { theme.window_header_focused } else { theme.panel_bg };
+        let fg = if window.minimized { theme.text_dim } else { theme.panel_text };
+
+        fb.fill_rect(x + PADDING, row_y, WIDTH - PADDING * 2, ROW_HEIGHT, bg);
+        if i == selected {
+            fb.fill_rect(x + PADDING, row_y, 3, ROW_HEIGHT, theme.accent);
+        }
+        let title = &window.title;
+        let end = title.char_indices().nth(MAX_TITLE_CHARS).map_or(title.len(), |(i, _)| i);
+        fb.draw_string(x + PADDING + 12, row_y + 8, &title[..end], fg, bg);
+    }
+}
@@ -0,0 +1,150 @@
+//! Theme
+//!
+//! Colours and metrics everything the compositor draws is skinned with.
+//! The default is the Nord-inspired dark palette; another theme arrives as
+//! the text of a theme config file in `SetTheme`, and every window is sent
+//! the result in `ThemeChanged` so applications can re-skin to match.
+//!
+//! A config file holds `key = value` lines named like the `ThemeSpec`
+//! fields, colours written `#RRGGBB` and `high_contrast` as `true` or
+//! `false`; `#` starts a comment line. An optional
+//! `base = nord | light | high-contrast` line picks the theme unset keys
+//! keep:
+//!
+//! ```text
+//! base = light
+//! accent = #B48EAD
+//! shadow_alpha = 80
+//! ```
+
+use atom_syscall::graphics::Color;
+use libipc::messages::ThemeSpec;
+
+/// Theme in the form the drawing code uses
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub desktop_bg: Color,
+    pub panel_bg: Color,
+    pub panel_text: Color,
+    pub accent: Color,
+    pub window_bg: Color,
+    pub window_header: Color,
+    pub window_header_focused: Color,
+    pub window_border: Color,
+    pub dock_bg: Color,
+    pub dock_separator: Color,
+    pub dock_item_minimized: Color,
+    pub text_dim: Color,
+    pub cursor_fill: Color,
+    pub cursor_outline: Color,
+    pub shadow: Color,
+    pub urgent: Color,
+    /// Shadow alpha right next to the window, fading to zero outwards
+    pub shadow_alpha: u8,
+    /// Darkening of unfocused windows' content
+    pub inactive_dim: u8,
+    /// What the theme was built from, as applications are sent it
+    pub spec: ThemeSpec,
+}
+
+impl Theme {
+    pub fn new(spec: ThemeSpec) -> Self {
+        let color = |rgb: u32| Color::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8);
+        Self {
+            desktop_bg: color(spec.desktop_bg),
+            panel_bg: color(spec.panel_bg),
+            panel_text: color(spec.panel_text),
+            accent: color(spec.accent),
+            window_bg: color(spec.window_bg),
+            window_header: color(spec.window_header),
+            window_header_focused: color(spec.window_header_focused),
+            window_border: color(spec.window_border),
+            dock_bg: color(spec.dock_bg),
+            dock_separator: color(spec.dock_separator),
+            dock_item_minimized: color(spec.dock_item_minimized),
+            text_dim: color(spec.text_dim),
+            cursor_fill: color(spec.cursor_fill),
+            cursor_outline: color(spec.cursor_outline),
+            shadow: color(spec.shadow),
+            urgent: color(spec.urgent),
+            shadow_alpha: spec.shadow_alpha,
+            inactive_dim: spec.inactive_dim,
+            spec,
+        }
+    }
+}
+
+/// Read a theme config file; `None` if any line is not understood
+pub fn parse(config: &str) -> Option<ThemeSpec> {
+    let entries = || {
+        config.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'))
+    };
+
+    // The base applies first wherever it is in the file
+    let mut spec = ThemeSpec::NORD;
+    for line in entries() {
+        let (key, value) = line.split_once('=')?;
+        if key.trim() == "base" {
+            spec = match value.trim() {
+                "nord" => ThemeSpec::NORD,
+                "light" => ThemeSpec::LIGHT,
+                "high-contrast" => ThemeSpec::HIGH_CONTRAST,
+                _ => return None,
+            };
+        }
+    }
+
+    for line in entries() {
+        let (key, value) = line.split_once('=')?;
+        let value = value.trim();
+        let field = match key.trim() {
+            "base" => continue,
+            "shadow_alpha" => &mut spec.shadow_alpha,
+            "inactive_dim" => &mut spec.inactive_dim,
+            "corner_radius" => &mut spec.corner_radius,
+            "border_width" => &mut spec.border_width,
+            "font_size" => &mut spec.font_size,
+            "high_contrast" => {
+                spec.high_contrast = value.parse().ok()?;
+                continue;
+            }
+            key => {
+                *color_field(&mut spec, key)? = parse_color(value)?;
+                continue;
+            }
+        };
+        *field = value.parse().ok()?;
+    }
+    Some(spec)
+}
+
+fn color_field<'a>(spec: &'a mut ThemeSpec, key: &str) -> Option<&'a mut u32> {
+    Some(match key {
+        "desktop_bg" => &mut spec.desktop_bg,
+        "panel_bg" => &mut spec.panel_bg,
+        "panel_text" => &mut spec.panel_text,
+        "accent" => &mut spec.accent,
+        "window_bg" => &mut spec.window_bg,
+        "window_header" => &mut spec.window_header,
+        "window_header_focused" => &mut spec.window_header_focused,
+        "window_border" => &mut spec.window_border,
+        "dock_bg" => &mut spec.dock_bg,
+        "dock_separator" => &mut spec.dock_separator,
+        "dock_item_minimized" => &mut spec.dock_item_minimized,
+        "text_dim" => &mut spec.text_dim,
+        "cursor_fill" => &mut spec.cursor_fill,
+        "cursor_outline" => &mut spec.cursor_outline,
+        "shadow" => &mut spec.shadow,
+        "urgent" => &mut spec.urgent,
+        _ => return None,
+    })
+}
+
+/// `#RRGGBB`
+fn parse_color(value: &str) -> Option<u32> {
+    let hex = value.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
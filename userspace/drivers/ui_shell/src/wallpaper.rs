@@ -0,0 +1,155 @@
+//! Wallpaper
+//!
+//! An image drawn instead of the plain desktop colour. The settings app
+//! reads the image file (from the initramfs or VFS) and hands its bytes
+//! over in a shared region with `SetWallpaper`; libgui decodes it, from
+//! BMP or PNG.
+//!
+//! The image is scaled to the screen once, when it or the mode changes,
+//! so recomposing the background is a row copy like the surfaces.
+
+use alloc::vec::Vec;
+
+use atom_syscall::graphics::{Color, Framebuffer, Pixels};
+use atom_syscall::shm::{self, RegionFlags, RegionId};
+use libipc::messages::{Rect, WallpaperMode, MAX_WALLPAPER_BYTES};
+
+/// Decoded pixels in the framebuffer's format, rows packed top-down
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u32>,
+}
+
+pub struct Wallpaper {
+    image: Image,
+    mode: WallpaperMode,
+    /// Colour of the bars around an image that does not cover the screen
+    background: Color,
+    /// The image as it appears on a screen this size
+    scaled: Image,
+}
+
+impl Wallpaper {
+    pub fn new(
+        image: Image,
+        mode: WallpaperMode,
+        background: Color,
+        screen_w: u32,
+        screen_h: u32,
+    ) -> Self {
+        let scaled = scale(&image, mode, background, screen_w, screen_h);
+        Self { image, mode, background, scaled }
+    }
+
+    /// Change the scaling mode; returns whether it differs
+    pub fn set_mode(&mut self, mode: WallpaperMode) -> bool {
+        if mode == self.mode {
+            return false;
+        }
+        self.mode = mode;
+        self.rescale();
+        true
+    }
+
+    /// Change the bar colour, after a theme change
+    pub fn set_background(&mut self, background: Color) {
+        if background != self.background {
+            self.background = background;
+            self.rescale();
+        }
+    }
+
+    /// Scale the image again for a screen of another size
+    pub fn resize(&mut self, screen_w: u32, screen_h: u32) {
+        self.scaled = scale(&self.image, self.mode, self.background, screen_w, screen_h);
+    }
+
+    fn rescale(&mut self) {
+        let (width, height) = (self.scaled.width, self.scaled.height);
+        self.resize(width, height);
+    }
+
+    /// Draw the part of the background inside `area`
+    pub fn draw(&self, fb: &Framebuffer, area: &Rect) {
+        let screen = Rect::new(0, 0, self.scaled.width, self.scaled.height);
+        let Some(area) = area.intersection(&screen) else {
+            return;
+        };
+
+        let Some(image) = Pixels::packed(&self.scaled.pixels, self.scaled.width) else {
+            return;
+        };
+        let src = image.sub(area.x as u32, area.y as u32, area.width, area.height);
+        fb.blit(area.x, area.y, &src);
+    }
+}
+
+/// Read and decode the image file in a sender's region; transparent parts
+/// of the image show `background`
+pub fn load(region: RegionId, len: usize, background: Color) -> Option<Image> {
+    if len == 0 || len > MAX_WALLPAPER_BYTES {
+        return None;
+    }
+    let base = shm::map_anywhere(region, RegionFlags::read_only()).ok()?;
+    let file = unsafe { core::slice::from_raw_parts(base as *const u8, len) };
+    let background = libgui::Color::rgb(background.r, background.g, background.b);
+    let image = libgui::Image::decode(file).map(|image| Image {
+        width: image.width,
+        height: image.height,
+        pixels: image.to_bgr32(background),
+    });
+    let _ = shm::unmap_region(region);
+    image
+}
+
+/// Render `image` for a `screen_w` x `screen_h` screen (nearest neighbour)
+fn scale(
+    image: &Image,
+    mode: WallpaperMode,
+    background: Color,
+    screen_w: u32,
+    screen_h: u32,
+) -> Image {
+    let background = background.to_bgr32();
+    let (iw, ih) = (image.width as u64, image.height as u64);
+    let (sw, sh) = (screen_w as u64, screen_h as u64);
+
+    // Size of the image on screen; the screen is wider than the image
+    // when sw / sh > iw / ih
+    let wider = sw * ih > sh * iw;
+    let (w, h) = match mode {
+        WallpaperMode::Fill if wider => (sw, ih * sw / iw),
+        WallpaperMode::Fill => (iw * sh / ih, sh),
+        WallpaperMode::Fit if wider => (iw * sh / ih, sh),
+        WallpaperMode::Fit => (sw, ih * sw / iw),
+        WallpaperMode::Center | WallpaperMode::Tile => (iw, ih),
+    };
+    let (w, h) = (w.max(1) as i64, h.max(1) as i64);
+
+    // Top-left corner on screen, negative when cropped
+    let (ox, oy) = match mode {
+        WallpaperMode::Tile => (0, 0),
+        _ => ((sw as i64 - w) / 2, (sh as i64 - h) / 2),
+    };
+
+    let mut pixels = Vec::with_capacity(screen_w as usize * screen_h as usize);
+    for y in 0..sh as i64 {
+        for x in 0..sw as i64 {
+            let (dx, dy) = (x - ox, y - oy);
+            let pixel = if mode == WallpaperMode::Tile {
+                let (ix, iy) = (dx as u64 % iw, dy as u64 % ih);
+                image.pixels[(iy * iw + ix) as usize]
+            } else if dx < 0 || dy < 0 || dx >= w || dy >= h {
+                background
+            } else {
+                let ix = dx as u64 * iw / w as u64;
+                let iy = dy as u64 * ih / h as u64;
+                image.pixels[(iy * iw + ix) as usize]
+            };
+            pixels.push(pixel);
+        }
+    }
+
+    Image { width: screen_w, height: screen_h, pixels }
+}
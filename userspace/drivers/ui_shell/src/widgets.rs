@@ -0,0 +1,71 @@
+//! Panel Widgets
+//!
+//! Slots in the panel, left of the do-not-disturb toggle, where services
+//! publish short status text (IPC stats, memory usage, ...) with
+//! `SetPanelWidget` and take it away with `RemovePanelWidget`. Widgets
+//! keep the order they first appeared in; the newest is nearest the clock.
+
+use alloc::vec::Vec;
+
+use atom_syscall::graphics::Framebuffer;
+use libipc::messages::{PanelWidget, Rect};
+
+use crate::theme::Theme;
+
+/// Widgets shown at once; further ones are ignored until a slot frees up
+const MAX_WIDGETS: usize = 4;
+
+/// Characters of a widget's text that are shown
+const MAX_CHARS: usize = 16;
+
+const SLOT_WIDTH: u32 = (MAX_CHARS as u32 + 2) * 8;
+
+pub struct PanelWidgets {
+    widgets: Vec<PanelWidget>,
+}
+
+impl PanelWidgets {
+    pub fn new() -> Self {
+        Self { widgets: Vec::new() }
+    }
+
+    /// Add or update a widget; returns whether the panel changed
+    pub fn set(&mut self, widget: PanelWidget) -> bool {
+        if let Some(existing) = self.widgets.iter_mut().find(|w| w.id == widget.id) {
+            let changed = *existing != widget;
+            *existing = widget;
+            return changed;
+        }
+        if self.widgets.len() == MAX_WIDGETS {
+            return false;
+        }
+        self.widgets.push(widget);
+        true
+    }
+
+    /// Remove a widget; returns whether it was shown
+    pub fn remove(&mut self, id: u32) -> bool {
+        let count = self.widgets.len();
+        self.widgets.retain(|w| w.id != id);
+        self.widgets.len() != count
+    }
+
+    /// Panel strip all slots can cover, ending at `right`
+    pub fn area(right: u32) -> Rect {
+        let width = MAX_WIDGETS as u32 * SLOT_WIDTH;
+        Rect::new(right.saturating_sub(width) as i32, 0, width, crate::PANEL_HEIGHT as u32)
+    }
+
+    /// Draw the widgets right-aligned against `right`
+    pub fn draw(&self, fb: &Framebuffer, theme: &Theme, right: u32) {
+        let mut x = right;
+        for widget in self.widgets.iter().rev() {
+            let text = &widget.text;
+            let end = text.char_indices().nth(MAX_CHARS).map_or(text.len(), |(i, _)| i);
+            let shown = &text[..end];
+
+            x = x.saturating_sub((shown.chars().count() as u32 + 2) * 8);
+            fb.draw_string(x + 8, 6, shown, theme.text_dim, theme.panel_bg);
+        }
+    }
+}
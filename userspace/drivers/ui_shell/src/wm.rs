@@ -0,0 +1,565 @@
+//! Window Manager
+//!
+//! Window list, stacking order, focus and workspaces. The list is kept
+//! bottom-to-top, so the last shown window is the topmost one.
+//!
+//! Stacking follows fixed rules: windows are grouped by layer (desktop,
+//! normal, dock, overlay, tooltip), and a transient window such as a dialog
+//! sits right above its parent, in the parent's layer. Raising a window
+//! raises it within its layer, together with its transients. A modal
+//! transient takes focus whenever its parent would get it.
+//!
+//! Every window belongs to one workspace. Only windows on the active
+//! workspace are drawn, hit-tested and can hold focus; the rest stay in the
+//! list (and in the dock) until their workspace is shown again.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use atom_syscall::ipc::PortId;
+use libipc::messages::{CursorShape, Rect, ScaleFactor, WindowId, WindowLayer, WindowRole};
+
+use crate::surface::{Blend, WindowSurface};
+
+// Decoration metrics below are at 1x; windows scale them by the output
+// scale
+
+/// Height of a window's title bar (drag handle)
+pub const HEADER_HEIGHT: i32 = 24;
+
+/// Width of the resize zone along each window edge
+pub const RESIZE_MARGIN: i32 = 6;
+
+/// How far the drop shadow is pushed down below a window
+pub const SHADOW_OFFSET: u32 = 3;
+
+/// Width over which the drop shadow fades out around the window
+pub const SHADOW_RADIUS: u32 = 8;
+
+/// Smallest size a window can be resized to
+pub const MIN_WINDOW_WIDTH: u32 = 120;
+pub const MIN_WINDOW_HEIGHT: u32 = 80;
+
+// Window edges grabbed by a resize
+pub const EDGE_LEFT: u8 = 1 << 0;
+pub const EDGE_RIGHT: u8 = 1 << 1;
+pub const EDGE_TOP: u8 = 1 << 2;
+pub const EDGE_BOTTOM: u8 = 1 << 3;
+
+/// Number of virtual desktops
+pub const WORKSPACE_COUNT: u8 = 4;
+
+/// Title-bar control buttons, right to left
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TitleButton {
+    Close,
+    Minimize,
+    Maximize,
+}
+
+const TITLE_BUTTONS: [TitleButton; 3] =
+    [TitleButton::Close, TitleButton::Minimize, TitleButton::Maximize];
+
+/// Spacing between title buttons; the close button sits 18px from the right
+pub const TITLE_BUTTON_SPACING: i32 = 14;
+
+/// Side of a title button square
+const TITLE_BUTTON_SIZE: u32 = 10;
+
+/// Window state in the compositor
+pub struct Window {
+    pub id: WindowId,
+    pub title: String,
+    /// The owning application's app id, empty for none
+    pub app_id: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub visible: bool,
+    pub focused: bool,
+    /// Hidden from the desktop but still listed in the dock
+    pub minimized: bool,
+    /// Workspace the window lives on (0-based)
+    pub workspace: u8,
+    /// Geometry to go back to when un-tiling; `Some` while maximized or snapped
+    pub saved_geometry: Option<(i32, i32, u32, u32)>,
+    /// IPC port for sending events to the owning application
+    pub event_port: Option<PortId>,
+    /// Shared-memory surface the application draws into
+    pub surface: Option<WindowSurface>,
+    /// How the surface is combined with the windows behind it
+    pub blend: Blend,
+    /// Output scale the decorations are drawn at
+    pub scale: ScaleFactor,
+    /// Scale the application renders its surface at; the surface is
+    /// stretched when this differs from `scale`
+    pub surface_scale: ScaleFactor,
+    /// Pointer shape the application asked for over its client area
+    pub cursor: CursorShape,
+    /// Window this one is transient for
+    pub parent: Option<WindowId>,
+    /// Blocks input to `parent` while open
+    pub modal: bool,
+    pub layer: WindowLayer,
+}
+
+impl Window {
+    pub fn new(id: WindowId, title: &str, x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            id,
+            title: String::from(title),
+            app_id: String::new(),
+            x,
+            y,
+            width,
+            height,
+            visible: true,
+            focused: false,
+            minimized: false,
+            workspace: 0,
+            saved_geometry: None,
+            event_port: None,
+            surface: None,
+            blend: Blend::OPAQUE,
+            scale: ScaleFactor::X1,
+            surface_scale: ScaleFactor::X1,
+            cursor: CursorShape::Arrow,
+            parent: None,
+            modal: false,
+            layer: WindowLayer::Normal,
+        }
+    }
+
+    /// Drawn and hit-testable on the desktop
+    pub fn is_shown(&self) -> bool {
+        self.visible && !self.minimized
+    }
+
+    /// Maximized or snapped, with the floating geometry saved
+    pub fn is_tiled(&self) -> bool {
+        self.saved_geometry.is_some()
+    }
+
+    pub fn geometry(&self) -> (i32, i32, u32, u32) {
+        (self.x, self.y, self.width, self.height)
+    }
+
+    pub fn set_geometry(&mut self, (x, y, width, height): (i32, i32, u32, u32)) {
+        self.x = x;
+        self.y = y;
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Screen area the window covers, including its shadow
+    pub fn bounds(&self) -> Rect {
+        let spread = SHADOW_RADIUS as i32;
+        Rect::new(
+            self.x - spread,
+            self.y + SHADOW_OFFSET as i32 - spread,
+            self.width + SHADOW_RADIUS * 2,
+            self.height + SHADOW_RADIUS * 2,
+        )
+    }
+
+    pub fn header_height(&self) -> i32 {
+        self.scale.apply_i32(HEADER_HEIGHT)
+    }
+
+    /// Area below the title bar and inside the border, (x, y, width, height)
+    pub fn client_rect(&self) -> (i32, i32, u32, u32) {
+        let header = self.header_height();
+        (
+            self.x + 1,
+            self.y + header,
+            self.width.saturating_sub(2),
+            self.height.saturating_sub(header as u32 + 1),
+        )
+    }
+
+    /// Window size that shows a `width` x `height` surface in full
+    pub fn frame_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let to_screen = |value: u32| self.scale.convert(value as i32, self.surface_scale) as u32;
+        (to_screen(width) + 2, to_screen(height) + self.header_height() as u32 + 1)
+    }
+
+    /// Screen point in the surface's pixels, as applications are told it
+    pub fn to_surface(&self, px: i32, py: i32) -> (i32, i32) {
+        let (cx, cy, _, _) = self.client_rect();
+        let to_surface = |value: i32| self.surface_scale.convert(value, self.scale);
+        (to_surface(px - cx), to_surface(py - cy))
+    }
+
+    pub fn contains(&self, px: i32, py: i32) -> bool {
+        px >= self.x && py >= self.y
+            && px < self.x + self.width as i32
+            && py < self.y + self.height as i32
+    }
+
+    pub fn header_contains(&self, px: i32, py: i32) -> bool {
+        px >= self.x && py >= self.y
+            && px < self.x + self.width as i32
+            && py < self.y + self.header_height()
+    }
+
+    /// Square drawn for the title button `index` places from the right
+    pub fn button_rect(&self, index: usize) -> Rect {
+        let s = |value: i32| self.scale.apply_i32(value);
+        let x = self.x + self.width as i32 - s(18) - s(TITLE_BUTTON_SPACING) * index as i32;
+        let size = self.scale.apply(TITLE_BUTTON_SIZE);
+        Rect::new(x, self.y + s(6), size, size)
+    }
+
+    /// Title button under the point, if any
+    pub fn button_at(&self, px: i32, py: i32) -> Option<TitleButton> {
+        let slop = self.scale.apply_i32(2);
+        TITLE_BUTTONS.iter().enumerate().find_map(|(i, &button)| {
+            let rect = self.button_rect(i);
+            let across = px >= rect.x - slop && px < rect.right();
+            let down = py >= rect.y && py < rect.bottom() + slop;
+            (across && down).then_some(button)
+        })
+    }
+
+    /// Edges (`EDGE_*` bits) whose resize zone contains the point
+    pub fn edges_at(&self, px: i32, py: i32) -> u8 {
+        if !self.contains(px, py) {
+            return 0;
+        }
+
+        let margin = self.scale.apply_i32(RESIZE_MARGIN);
+        let mut edges = 0;
+        if px < self.x + margin {
+            edges |= EDGE_LEFT;
+        }
+        if px >= self.x + self.width as i32 - margin {
+            edges |= EDGE_RIGHT;
+        }
+        if py < self.y + margin {
+            edges |= EDGE_TOP;
+        }
+        if py >= self.y + self.height as i32 - margin {
+            edges |= EDGE_BOTTOM;
+        }
+        edges
+    }
+}
+
+/// Window manager state
+pub struct WindowManager {
+    pub windows: Vec<Window>,
+    pub next_id: WindowId,
+    pub focused_id: Option<WindowId>,
+    /// Workspace currently on screen (0-based)
+    pub active_workspace: u8,
+    /// Output scale new windows are decorated at
+    pub scale: ScaleFactor,
+}
+
+impl WindowManager {
+    pub fn new() -> Self {
+        Self {
+            windows: Vec::new(),
+            next_id: 1,
+            focused_id: None,
+            active_workspace: 0,
+            scale: ScaleFactor::X1,
+        }
+    }
+
+    pub fn create_window(&mut self, title: &str, x: i32, y: i32, width: u32, height: u32) -> WindowId {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut window = Window::new(id, title, x, y, width, height);
+        window.workspace = self.active_workspace;
+        window.scale = self.scale;
+        self.windows.push(window);
+        self.focus_window(id);
+        id
+    }
+
+    /// Focus a window and raise it, or its modal dialog if it has one open
+    pub fn focus_window(&mut self, id: WindowId) {
+        let id = self.modal_target(id);
+
+        // Unfocus previous
+        if let Some(prev_id) = self.focused_id {
+            if let Some(w) = self.windows.iter_mut().find(|w| w.id == prev_id) {
+                w.focused = false;
+            }
+        }
+
+        // Focus new and move to top, along with the windows it is transient for
+        let raised: Vec<WindowId> = self.ancestors(id).collect();
+        if let Some(window) = self.get_mut(id) {
+            window.focused = true;
+            self.focused_id = Some(id);
+            for &raise in raised.iter().rev() {
+                if let Some(pos) = self.windows.iter().position(|w| w.id == raise) {
+                    let window = self.windows.remove(pos);
+                    self.windows.push(window);
+                }
+            }
+            self.apply_stacking();
+        }
+    }
+
+    /// Set a window's parent, modality and layer; fails if the parent does
+    /// not exist or is the window itself or one of its transients
+    pub fn set_role(&mut self, role: &WindowRole) -> bool {
+        let parent = (role.parent != 0).then_some(role.parent);
+        if let Some(parent) = parent {
+            let exists = self.windows.iter().any(|w| w.id == parent);
+            if !exists || self.ancestors(parent).any(|id| id == role.window_id) {
+                return false;
+            }
+        }
+        let workspace = parent.and_then(|p| self.windows.iter().find(|w| w.id == p));
+        let workspace = workspace.map(|p| p.workspace);
+        let Some(window) = self.get_mut(role.window_id) else {
+            return false;
+        };
+
+        window.parent = parent;
+        window.modal = role.modal && parent.is_some();
+        window.layer = role.layer;
+        self.apply_stacking();
+
+        // Transients live on their parent's workspace
+        if let Some(workspace) = workspace {
+            self.move_to_workspace(role.window_id, workspace);
+        }
+        // A modal dialog over the focused window takes focus at once
+        if let Some(focused) = self.focused_id {
+            if self.modal_target(focused) != focused {
+                self.focus_window(focused);
+            }
+        }
+        true
+    }
+
+    /// The window input for `id` goes to: its topmost modal transient, that
+    /// transient's own modal one, and so on
+    pub fn modal_target(&self, id: WindowId) -> WindowId {
+        let mut target = id;
+        // Bounded, since set_role refuses cycles
+        for _ in 0..self.windows.len() {
+            let modal = self.windows.iter().rev().find(|w| {
+                w.parent == Some(target) && w.modal && w.is_shown()
+            });
+            match modal {
+                Some(modal) => target = modal.id,
+                None => break,
+            }
+        }
+        target
+    }
+
+    /// The window `id` is ultimately transient for, and all its transients;
+    /// stacking `id` moves these together
+    pub fn family(&self, id: WindowId) -> Vec<WindowId> {
+        let root = self.ancestors(id).last().unwrap_or(id);
+        self.windows
+            .iter()
+            .filter(|w| self.ancestors(w.id).any(|a| a == root))
+            .map(|w| w.id)
+            .collect()
+    }
+
+    /// Parent, grandparent and so on of `id`, starting with `id` itself
+    fn ancestors(&self, id: WindowId) -> impl Iterator<Item = WindowId> + '_ {
+        let parent_of = |id: WindowId| self.windows.iter().find(|w| w.id == id)?.parent;
+        core::iter::successors(Some(id), move |&id| parent_of(id)).take(self.windows.len() + 1)
+    }
+
+    /// Reorder the list by the stacking rules, keeping the order within
+    /// each layer and among the transients of each window
+    fn apply_stacking(&mut self) {
+        let mut rest = core::mem::take(&mut self.windows);
+        for layer in WindowLayer::ALL {
+            let roots: Vec<WindowId> = rest
+                .iter()
+                .filter(|w| w.layer == layer)
+                .filter(|w| w.parent.is_none_or(|p| !rest.iter().any(|o| o.id == p)))
+                .map(|w| w.id)
+                .collect();
+            for root in roots {
+                self.stack_with_transients(&mut rest, root);
+            }
+        }
+        self.windows.append(&mut rest);
+    }
+
+    /// Move `id` from `rest` to the top of the list, then its transients
+    fn stack_with_transients(&mut self, rest: &mut Vec<Window>, id: WindowId) {
+        let Some(pos) = rest.iter().position(|w| w.id == id) else {
+            return;
+        };
+        self.windows.push(rest.remove(pos));
+
+        let transients: Vec<WindowId> =
+            rest.iter().filter(|w| w.parent == Some(id)).map(|w| w.id).collect();
+        for transient in transients {
+            self.stack_with_transients(rest, transient);
+        }
+    }
+
+    /// Move a window to `index` in the stack (0 = bottom); it keeps focus
+    /// only if it stays on top
+    pub fn restack(&mut self, id: WindowId, index: usize) {
+        let Some(pos) = self.windows.iter().position(|w| w.id == id) else {
+            return;
+        };
+        let window = self.windows.remove(pos);
+        let index = index.min(self.windows.len());
+        self.windows.insert(index, window);
+        self.apply_stacking();
+
+        let mut above = self.windows.iter().skip_while(|w| w.id != id).skip(1);
+        let layer = self.windows.iter().find(|w| w.id == id).map(|w| w.layer);
+        if above.any(|w| Some(w.layer) == layer) {
+            self.unfocus(id);
+        }
+    }
+
+    /// Shown and on the active workspace, and so is its parent
+    pub fn is_visible(&self, window: &Window) -> bool {
+        let parent = window.parent.and_then(|p| self.windows.iter().find(|w| w.id == p));
+        window.is_shown()
+            && window.workspace == self.active_workspace
+            && parent.is_none_or(|p| self.is_visible(p))
+    }
+
+    pub fn get_mut(&mut self, id: WindowId) -> Option<&mut Window> {
+        self.windows.iter_mut().find(|w| w.id == id)
+    }
+
+    pub fn window_at(&self, x: i32, y: i32) -> Option<WindowId> {
+        // Check from top to bottom (reverse order)
+        for window in self.windows.iter().rev() {
+            if self.is_visible(window) && window.contains(x, y) {
+                return Some(window.id);
+            }
+        }
+        None
+    }
+
+    /// Remove a window, handing it back so it can still be animated out
+    pub fn close_window(&mut self, id: WindowId) -> Option<Window> {
+        let pos = self.windows.iter().position(|w| w.id == id)?;
+        let window = self.windows.remove(pos);
+
+        // Its transients stay open as ordinary windows
+        for transient in self.windows.iter_mut().filter(|w| w.parent == Some(id)) {
+            transient.parent = None;
+            transient.modal = false;
+        }
+
+        if self.focused_id == Some(id) {
+            self.focus_topmost();
+        }
+        Some(window)
+    }
+
+    /// Hide a window; focus passes to the topmost window still shown
+    pub fn minimize(&mut self, id: WindowId) {
+        let Some(window) = self.get_mut(id) else {
+            return;
+        };
+        window.minimized = true;
+        self.unfocus(id);
+    }
+
+    /// Show a minimized window again and raise it, switching to its
+    /// workspace if needed
+    pub fn restore(&mut self, id: WindowId) {
+        let Some(window) = self.get_mut(id) else {
+            return;
+        };
+        window.minimized = false;
+        let workspace = window.workspace;
+
+        self.switch_workspace(workspace);
+        self.focus_window(id);
+    }
+
+    /// Show another workspace, focusing its topmost window
+    pub fn switch_workspace(&mut self, workspace: u8) {
+        if workspace == self.active_workspace || workspace >= WORKSPACE_COUNT {
+            return;
+        }
+
+        if let Some(id) = self.focused_id {
+            if let Some(window) = self.get_mut(id) {
+                window.focused = false;
+            }
+        }
+        self.active_workspace = workspace;
+        self.focus_topmost();
+    }
+
+    /// Move a window to another workspace; it keeps its place in the
+    /// stacking order
+    pub fn move_to_workspace(&mut self, id: WindowId, workspace: u8) {
+        if workspace >= WORKSPACE_COUNT {
+            return;
+        }
+        let Some(window) = self.get_mut(id) else {
+            return;
+        };
+        window.workspace = workspace;
+
+        if workspace != self.active_workspace {
+            self.unfocus(id);
+        }
+
+        // Transients go along
+        let transients: Vec<WindowId> =
+            self.windows.iter().filter(|w| w.parent == Some(id)).map(|w| w.id).collect();
+        for transient in transients {
+            self.move_to_workspace(transient, workspace);
+        }
+    }
+
+    /// Change the output scale; floating windows are resized around their
+    /// surfaces, tiled ones keep their place
+    pub fn set_scale(&mut self, scale: ScaleFactor) {
+        self.scale = scale;
+        for window in self.windows.iter_mut() {
+            window.scale = scale;
+            let Some((width, height)) = window.surface.as_ref().map(|s| (s.width, s.height)) else {
+                continue;
+            };
+            if !window.is_tiled() {
+                (window.width, window.height) = window.frame_size(width, height);
+            }
+        }
+    }
+
+    /// Whether any window lives on `workspace`
+    pub fn is_occupied(&self, workspace: u8) -> bool {
+        self.windows.iter().any(|w| w.workspace == workspace)
+    }
+
+    /// Take focus away from a window that can no longer hold it
+    fn unfocus(&mut self, id: WindowId) {
+        if let Some(window) = self.get_mut(id) {
+            window.focused = false;
+        }
+        if self.focused_id == Some(id) {
+            self.focused_id = None;
+            self.focus_topmost();
+        }
+    }
+
+    fn focus_topmost(&mut self) {
+        let top = self.windows.iter().rev().find(|w| self.is_visible(w)).map(|w| w.id);
+        match top {
+            Some(top) => self.focus_window(top),
+            None => self.focused_id = None,
+        }
+    }
+}
+
@@ -0,0 +1,154 @@
+//! HID Boot-Protocol Keyboard
+//!
+//! Decodes the fixed 8-byte boot keyboard report and turns it into the same
+//! `KeyEvent` stream the PS/2 keyboard driver produces. HID usages are mapped
+//! to the same `KeyCode`s the PS/2 decoder yields, so consumers never need to
+//! know which bus a key came from.
+//!
+//! Boot report layout (HID 1.11, Appendix B.1):
+//!
+//! ```text
+//! byte 0: modifier bits (LCtrl LShift LAlt LGui RCtrl RShift RAlt RGui)
+//! byte 1: reserved
+//! byte 2..8: up to six usage IDs of currently pressed keys
+//! ```
+
+use atom_syscall::input::scancode_to_ascii;
+use atom_syscall::thread::get_ticks;
+use libipc::keycode::KeyCode;
+use libipc::messages::{KeyEvent, KeyModifiers};
+
+/// Size of a boot-protocol keyboard report
+pub const REPORT_SIZE: usize = 8;
+
+/// Maximum number of simultaneously reported keys
+const MAX_KEYS: usize = 6;
+
+/// Usage ID reported in every slot when too many keys are held
+const USAGE_ERROR_ROLLOVER: u8 = 0x01;
+
+/// Caps Lock usage ID
+const USAGE_CAPS_LOCK: u8 = 0x39;
+
+// Modifier byte bits
+const MOD_LEFT_CTRL: u8 = 1 << 0;
+const MOD_LEFT_SHIFT: u8 = 1 << 1;
+const MOD_LEFT_ALT: u8 = 1 << 2;
+const MOD_RIGHT_CTRL: u8 = 1 << 4;
+const MOD_RIGHT_SHIFT: u8 = 1 << 5;
+const MOD_RIGHT_ALT: u8 = 1 << 6;
+
+/// A translated key transition
+#[derive(Debug, Clone, Copy)]
+pub struct KeyTransition {
+    pub event: KeyEvent,
+    pub pressed: bool,
+}
+
+/// Tracks the previous report so key presses and releases can be derived
+pub struct BootKeyboard {
+    previous: [u8; MAX_KEYS],
+    modifiers: u8,
+    caps_lock: bool,
+}
+
+impl BootKeyboard {
+    pub const fn new() -> Self {
+        Self {
+            previous: [0; MAX_KEYS],
+            modifiers: 0,
+            caps_lock: false,
+        }
+    }
+
+    fn key_modifiers(&self) -> KeyModifiers {
+        KeyModifiers {
+            shift: self.modifiers & (MOD_LEFT_SHIFT | MOD_RIGHT_SHIFT) != 0,
+            ctrl: self.modifiers & (MOD_LEFT_CTRL | MOD_RIGHT_CTRL) != 0,
+            alt: self.modifiers & (MOD_LEFT_ALT | MOD_RIGHT_ALT) != 0,
+            caps_lock: self.caps_lock,
+        }
+    }
+
+    /// Process one report, calling `emit` for every key that changed state.
+    ///
+    /// Releases are reported before presses so that a fast "roll" from one
+    /// key to the next arrives in the same order a PS/2 keyboard would send.
+    pub fn process_report<F: FnMut(KeyTransition)>(&mut self, report: &[u8], mut emit: F) {
+        if report.len() < REPORT_SIZE {
+            return;
+        }
+
+        let keys = &report[2..2 + MAX_KEYS];
+
+        // Phantom state: the device cannot tell which keys are down
+        if keys.iter().all(|&k| k == USAGE_ERROR_ROLLOVER) {
+            return;
+        }
+
+        // Modifier changes are state only, exactly like the PS/2 driver
+        self.modifiers = report[0];
+        let timestamp = get_ticks();
+
+        for &usage in self.previous.iter() {
+            if usage == 0 || keys.contains(&usage) {
+                continue;
+            }
+            if let Some(keycode) = KeyCode::from_hid_usage(usage) {
+                emit(KeyTransition {
+                    event: KeyEvent {
+                        keycode,
+                        character: 0,
+                        modifiers: self.key_modifiers(),
+                        timestamp,
+                    },
+                    pressed: false,
+                });
+            }
+        }
+
+        for &usage in keys.iter() {
+            if usage == 0 || self.previous.contains(&usage) {
+                continue;
+            }
+            if usage == USAGE_CAPS_LOCK {
+                self.caps_lock = !self.caps_lock;
+                continue;
+            }
+            if let Some(keycode) = KeyCode::from_hid_usage(usage) {
+                emit(KeyTransition {
+                    event: KeyEvent {
+                        keycode,
+                        character: self.translate(keycode),
+                        modifiers: self.key_modifiers(),
+                        timestamp,
+                    },
+                    pressed: true,
+                });
+            }
+        }
+
+        self.previous.copy_from_slice(keys);
+    }
+
+    fn translate(&self, key: KeyCode) -> u8 {
+        // Key codes below IntlBackslash are the set 1 make codes
+        let code = key as u8;
+        if code >= KeyCode::IntlBackslash as u8 {
+            return 0;
+        }
+
+        let mods = self.key_modifiers();
+        let upper = if is_letter(code) {
+            mods.shift ^ mods.caps_lock
+        } else {
+            mods.shift
+        };
+
+        scancode_to_ascii(code, upper).map(|c| c as u8).unwrap_or(0)
+    }
+}
+
+fn is_letter(code: u8) -> bool {
+    matches!(code, 0x10..=0x19 | 0x1E..=0x26 | 0x2C..=0x32)
+}
@@ -0,0 +1,192 @@
+//! Userspace USB HID Class Driver (Boot Protocol)
+//!
+//! This driver runs entirely in Ring 3 (userspace) and:
+//! - Receives interrupt-IN reports forwarded by the USB core
+//! - Decodes boot-protocol keyboard and mouse reports
+//! - Emits the same libipc `KeyEvent`/`MouseMoveEvent`/`MouseButtonEvent`
+//!   messages as the PS/2 drivers, so the desktop works unchanged on
+//!   machines without a PS/2 controller
+//!
+//! # Architecture
+//!
+//! ```text
+//! USB Core ──> USB HID Driver ──> Desktop Environment
+//! (reports)     (boot protocol)     (IPC messages)
+//! ```
+//!
+//! # Report transport
+//!
+//! The USB core (host controller + enumeration) owns the bus and forwards
+//! each completed interrupt transfer to this driver's port as:
+//!
+//! ```text
+//! byte 0: interface protocol (1 = keyboard, 2 = mouse)
+//! byte 1..: raw boot-protocol report
+//! ```
+//!
+//! The USB core is not part of the tree yet; until it lands this driver
+//! idles on its port.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+mod keyboard;
+mod mouse;
+
+
+use atom_syscall::ipc::{create_port, try_recv, PortId};
+use atom_syscall::thread::{yield_now, exit};
+use atom_syscall::debug::log;
+
+use libipc::messages::{MessageType, MotionCoalescer};
+use libipc::protocol::send_message_async;
+
+use keyboard::BootKeyboard;
+use mouse::{BootMouse, MouseReportEvent};
+
+// ============================================================================
+// Constants
+// ============================================================================
+
+/// HID interface protocol codes (bInterfaceProtocol)
+const PROTOCOL_KEYBOARD: u8 = 1;
+const PROTOCOL_MOUSE: u8 = 2;
+
+/// Largest forwarded message: protocol byte plus a full-speed report
+const REPORT_BUFFER_SIZE: usize = 65;
+
+// ============================================================================
+// USB HID Driver
+// ============================================================================
+
+struct UsbHidDriver {
+    keyboard: BootKeyboard,
+    mouse: BootMouse,
+    motion: MotionCoalescer,
+    report_port: Option<PortId>,
+    desktop_port: Option<PortId>,
+    report_count: u64,
+}
+
+impl UsbHidDriver {
+    fn new() -> Self {
+        Self {
+            keyboard: BootKeyboard::new(),
+            mouse: BootMouse::new(),
+            motion: MotionCoalescer::new(),
+            report_port: None,
+            desktop_port: None,
+            report_count: 0,
+        }
+    }
+
+    fn run(&mut self) -> ! {
+        log("USB HID Driver: Starting boot-protocol HID driver");
+
+        // Port the USB core forwards interrupt reports to
+        self.report_port = create_port().ok();
+        if self.report_port.is_none() {
+            log("USB HID Driver: Failed to create report port");
+            exit(1);
+        }
+
+        // TODO: Discover desktop port via service registry (same as PS/2 drivers)
+
+        log("USB HID Driver: Entering main loop");
+
+        let mut buffer = [0u8; REPORT_BUFFER_SIZE];
+
+        loop {
+            if let Some(port) = self.report_port {
+                while let Ok(Some(len)) = try_recv(port, &mut buffer) {
+                    if len > 1 {
+                        self.report_count += 1;
+                        self.handle_report(buffer[0], &buffer[1..len]);
+                    }
+                }
+            }
+
+            // At most one motion message per cycle
+            self.flush_motion();
+
+            yield_now();
+        }
+    }
+
+    fn handle_report(&mut self, protocol: u8, report: &[u8]) {
+        let desktop_port = self.desktop_port;
+
+        match protocol {
+            PROTOCOL_KEYBOARD => {
+                self.keyboard.process_report(report, |transition| {
+                    if let Some(port) = desktop_port {
+                        let msg_type = if transition.pressed {
+                            MessageType::KeyDown
+                        } else {
+                            MessageType::KeyUp
+                        };
+                        let _ = send_message_async(port, msg_type, &transition.event.to_bytes());
+                    }
+                });
+            }
+            PROTOCOL_MOUSE => {
+                let motion = &mut self.motion;
+                self.mouse.process_report(report, |event| {
+                    if let MouseReportEvent::Move(ev) = event {
+                        motion.add(ev.dx, ev.dy, ev.timestamp);
+                        return;
+                    }
+
+                    // Keep motion ordered before the button or wheel event
+                    let pending = motion.take();
+                    if let Some(port) = desktop_port {
+                        if let Some(ev) = pending {
+                            let _ = send_message_async(port, MessageType::MouseMove, &ev.to_bytes());
+                        }
+                        let _ = match event {
+                            MouseReportEvent::Move(_) => Ok(()),
+                            MouseReportEvent::ButtonDown(ev) => {
+                                send_message_async(port, MessageType::MouseButtonDown, &ev.to_bytes())
+                            }
+                            MouseReportEvent::ButtonUp(ev) => {
+                                send_message_async(port, MessageType::MouseButtonUp, &ev.to_bytes())
+                            }
+                            MouseReportEvent::Scroll(ev) => {
+                                send_message_async(port, MessageType::MouseScroll, &ev.to_bytes())
+                            }
+                        };
+                    }
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn flush_motion(&mut self) {
+        if let Some(event) = self.motion.take() {
+            if let Some(port) = self.desktop_port {
+                let _ = send_message_async(port, MessageType::MouseMove, &event.to_bytes());
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Entry Points
+// ============================================================================
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    main()
+}
+
+fn main() -> ! {
+    let mut driver = UsbHidDriver::new();
+    driver.run()
+}
+
+atom_syscall::define_global_allocator!();
+
+atom_syscall::define_panic_handler!("USB HID Driver");
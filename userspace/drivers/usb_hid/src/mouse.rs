@@ -0,0 +1,88 @@
+//! HID Boot-Protocol Mouse
+//!
+//! Decodes the boot mouse report into `MouseMoveEvent`,
+//! `MouseButtonEvent` and `MouseScrollEvent` messages identical to the PS/2
+//! path.
+//!
+//! Boot report layout (HID 1.11, Appendix B.2):
+//!
+//! ```text
+//! byte 0: button bits (Left, Right, Middle, Back, Forward)
+//! byte 1: X displacement (i8)
+//! byte 2: Y displacement (i8, positive = down)
+//! byte 3: optional wheel (i8), present on most real devices
+//! ```
+
+use atom_syscall::thread::get_ticks;
+use libipc::messages::{MouseButton, MouseButtonEvent, MouseMoveEvent, MouseScrollEvent};
+
+/// Minimum size of a boot-protocol mouse report
+pub const REPORT_SIZE: usize = 3;
+
+/// Events derived from a single report
+pub enum MouseReportEvent {
+    Move(MouseMoveEvent),
+    ButtonDown(MouseButtonEvent),
+    ButtonUp(MouseButtonEvent),
+    Scroll(MouseScrollEvent),
+}
+
+/// Tracks button state between reports
+pub struct BootMouse {
+    buttons: u8,
+}
+
+impl BootMouse {
+    pub const fn new() -> Self {
+        Self { buttons: 0 }
+    }
+
+    /// Process one report, calling `emit` for movement and button changes.
+    ///
+    /// Deltas are converted to the PS/2 convention (positive Y = up) so the
+    /// compositor can treat both buses the same. Absolute coordinates are
+    /// left at zero; the compositor owns the cursor position.
+    pub fn process_report<F: FnMut(MouseReportEvent)>(&mut self, report: &[u8], mut emit: F) {
+        if report.len() < REPORT_SIZE {
+            return;
+        }
+
+        let timestamp = get_ticks();
+        let buttons = report[0] & 0x1F;
+        let dx = report[1] as i8 as i16;
+        let dy = -(report[2] as i8 as i16);
+
+        if dx != 0 || dy != 0 {
+            emit(MouseReportEvent::Move(MouseMoveEvent { x: 0, y: 0, dx, dy, timestamp }));
+        }
+
+        // HID wheel is already positive = away from the user
+        if let Some(&wheel) = report.get(3) {
+            if wheel != 0 {
+                let delta = wheel as i8 as i16;
+                emit(MouseReportEvent::Scroll(MouseScrollEvent { x: 0, y: 0, delta, timestamp }));
+            }
+        }
+
+        let changed = buttons ^ self.buttons;
+        for (bit, button) in [
+            (0x01, MouseButton::Left),
+            (0x02, MouseButton::Right),
+            (0x04, MouseButton::Middle),
+            (0x08, MouseButton::Back),
+            (0x10, MouseButton::Forward),
+        ] {
+            if changed & bit == 0 {
+                continue;
+            }
+            let event = MouseButtonEvent { button, x: 0, y: 0, timestamp };
+            if buttons & bit != 0 {
+                emit(MouseReportEvent::ButtonDown(event));
+            } else {
+                emit(MouseReportEvent::ButtonUp(event));
+            }
+        }
+
+        self.buttons = buttons;
+    }
+}
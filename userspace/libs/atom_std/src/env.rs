@@ -0,0 +1,73 @@
+//! Arguments and boot parameters
+//!
+//! `args` are the program's arguments from the boot manifest, `boot_args`
+//! the parameters given to the loader. Options look like `--scale=2` or
+//! `--no-animations`; the leading `--` is optional when asking for them.
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use atom_syscall::env as sys;
+
+/// Arguments read once, to look options up in
+#[derive(Debug, Clone, Default)]
+pub struct Args {
+    data: Vec<u8>,
+}
+
+impl Args {
+    fn read(len: usize, read: fn(&mut [u8]) -> sys::Args<'_>) -> Self {
+        let mut data = vec![0u8; len];
+        let complete = read(&mut data).iter().map(|arg| arg.len() + 1).sum();
+        data.truncate(complete);
+        Self { data }
+    }
+
+    fn view(&self) -> sys::Args<'_> {
+        sys::Args::from_bytes(&self.data)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.view().iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.view().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.view().is_empty()
+    }
+
+    /// What follows `=` in the last `--name=value` argument
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.view().get(name)
+    }
+
+    /// `get`, parsed; None if it is missing or does not parse
+    pub fn value<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.view().value(name)
+    }
+
+    /// Whether `--name` was given on its own
+    pub fn flag(&self, name: &str) -> bool {
+        self.view().flag(name)
+    }
+}
+
+/// The program's arguments
+pub fn args() -> Args {
+    Args::read(sys::args_len(), sys::args)
+}
+
+/// The boot parameters
+pub fn boot_args() -> Args {
+    Args::read(sys::boot_args_len(), sys::boot_args)
+}
+
+/// The value of the boot parameter `name=value`
+pub fn var(name: &str) -> Option<String> {
+    boot_args().get(name).map(ToString::to_string)
+}
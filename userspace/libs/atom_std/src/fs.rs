@@ -0,0 +1,210 @@
+//! Files
+//!
+//! Files live in the filesystem service, reached with `rpc` calls on the
+//! `File*` messages. The service's port is looked up on first use and kept
+//! for the rest of the program; until a filesystem service registers,
+//! every call fails with `NotFound`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use libipc::discovery;
+use libipc::messages::{
+    FileOpen, FileRead, FileStat, MessageType, OPEN_APPEND, OPEN_CREATE, OPEN_READ,
+    OPEN_TRUNCATE, OPEN_WRITE,
+};
+use libipc::rpc::Client;
+use libipc::status::{StatusCode, StatusResult};
+use libipc::{ServiceId, MAX_MESSAGE_SIZE};
+
+use crate::sync::Mutex;
+
+/// How long a call waits for the filesystem service to answer
+const CALL_TIMEOUT_MS: u64 = 5000;
+
+/// Most bytes asked for or sent in one call, leaving room for the headers
+const CHUNK_BYTES: usize = MAX_MESSAGE_SIZE - 64;
+
+static CLIENT: Mutex<Option<Client>> = Mutex::new(None);
+
+/// Make a call to the filesystem service, connecting on first use
+fn call(method: MessageType, args: &[u8]) -> StatusResult<Vec<u8>> {
+    let mut client = CLIENT.lock();
+    if client.is_none() {
+        let service = discovery::find_service(ServiceId::Filesystem);
+        *client = Some(Client::new(service.ok_or(StatusCode::NotFound)?)?);
+    }
+    let result = client.as_mut().map_or(Err(StatusCode::NotFound), |client| {
+        client.call(method, args, CALL_TIMEOUT_MS)
+    });
+    // A service that went away may come back on another port
+    if result == Err(StatusCode::InvalidArgument) {
+        *client = None;
+    }
+    result
+}
+
+/// How to open a file, like `std::fs::OpenOptions`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenOptions {
+    flags: u32,
+}
+
+impl OpenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.set(OPEN_READ, read)
+    }
+
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.set(OPEN_WRITE, write)
+    }
+
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.set(OPEN_APPEND | OPEN_WRITE, append)
+    }
+
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.set(OPEN_TRUNCATE, truncate)
+    }
+
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.set(OPEN_CREATE, create)
+    }
+
+    pub fn open(&self, path: &str) -> StatusResult<File> {
+        let args = FileOpen { flags: self.flags, path: String::from(path) };
+        let reply = call(MessageType::FileOpen, &args.to_bytes())?;
+        let handle = reply.get(0..8).and_then(|bytes| bytes.try_into().ok());
+        let handle = u64::from_le_bytes(handle.ok_or(StatusCode::Failed)?);
+        Ok(File { handle })
+    }
+
+    fn set(&mut self, flag: u32, on: bool) -> &mut Self {
+        if on {
+            self.flags |= flag;
+        } else {
+            self.flags &= !flag;
+        }
+        self
+    }
+}
+
+/// An open file; closed when dropped
+#[derive(Debug)]
+pub struct File {
+    handle: u64,
+}
+
+impl File {
+    /// Open `path` for reading
+    pub fn open(path: &str) -> StatusResult<Self> {
+        OpenOptions::new().read(true).open(path)
+    }
+
+    /// Open `path` for writing, creating it or emptying it
+    pub fn create(path: &str) -> StatusResult<Self> {
+        OpenOptions::new().write(true).create(true).truncate(true).open(path)
+    }
+
+    /// Read up to `buffer.len()` bytes; 0 at the end of the file
+    pub fn read(&mut self, buffer: &mut [u8]) -> StatusResult<usize> {
+        let len = buffer.len().min(CHUNK_BYTES) as u32;
+        let args = FileRead { handle: self.handle, len };
+        let reply = call(MessageType::FileRead, &args.to_bytes())?;
+        let count = reply.len().min(buffer.len());
+        buffer[..count].copy_from_slice(&reply[..count]);
+        Ok(count)
+    }
+
+    /// Read everything left in the file onto the end of `out`
+    pub fn read_to_end(&mut self, out: &mut Vec<u8>) -> StatusResult<usize> {
+        let start = out.len();
+        let mut chunk = [0u8; 1024];
+        loop {
+            let count = self.read(&mut chunk)?;
+            if count == 0 {
+                return Ok(out.len() - start);
+            }
+            out.extend_from_slice(&chunk[..count]);
+        }
+    }
+
+    /// Write some of `data`; returns how much
+    pub fn write(&mut self, data: &[u8]) -> StatusResult<usize> {
+        let data = &data[..data.len().min(CHUNK_BYTES)];
+        let mut args = Vec::with_capacity(8 + data.len());
+        args.extend_from_slice(&self.handle.to_le_bytes());
+        args.extend_from_slice(data);
+        let reply = call(MessageType::FileWrite, &args)?;
+        let written = reply.get(0..4).and_then(|bytes| bytes.try_into().ok());
+        Ok(u32::from_le_bytes(written.ok_or(StatusCode::Failed)?) as usize)
+    }
+
+    /// Write all of `data`
+    pub fn write_all(&mut self, mut data: &[u8]) -> StatusResult<()> {
+        while !data.is_empty() {
+            match self.write(data)? {
+                0 => return Err(StatusCode::Failed),
+                written => data = &data[written..],
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let _ = call(MessageType::FileClose, &self.handle.to_le_bytes());
+    }
+}
+
+/// What `metadata` says about a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    stat: FileStat,
+}
+
+impl Metadata {
+    pub fn len(&self) -> u64 {
+        self.stat.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stat.size == 0
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.stat.is_dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        !self.stat.is_dir
+    }
+}
+
+pub fn metadata(path: &str) -> StatusResult<Metadata> {
+    let reply = call(MessageType::FileStat, path.as_bytes())?;
+    let stat = FileStat::from_bytes(&reply).ok_or(StatusCode::Failed)?;
+    Ok(Metadata { stat })
+}
+
+/// The whole contents of a file
+pub fn read(path: &str) -> StatusResult<Vec<u8>> {
+    let mut contents = Vec::new();
+    File::open(path)?.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// The whole contents of a file, which must be UTF-8
+pub fn read_to_string(path: &str) -> StatusResult<String> {
+    String::from_utf8(read(path)?).map_err(|_| StatusCode::InvalidArgument)
+}
+
+/// Replace the contents of a file, creating it if needed
+pub fn write(path: &str, contents: &[u8]) -> StatusResult<()> {
+    File::create(path)?.write_all(contents)
+}
@@ -0,0 +1,104 @@
+//! Program Output
+//!
+//! A program started from the terminal has an output port, which shows
+//! what is sent to it as `ProgramOutput` (text) or `ProgramError`. Without
+//! one, output goes to the kernel log, one entry per line.
+
+use alloc::string::String;
+use core::fmt::{self, Write};
+
+use atom_syscall::debug::log;
+use atom_syscall::process::output_port;
+use libipc::messages::{MessageHeader, MessageType};
+use libipc::protocol::send_message;
+use libipc::MAX_MESSAGE_SIZE;
+
+/// Most text one message carries
+const CHUNK_BYTES: usize = MAX_MESSAGE_SIZE - MessageHeader::SIZE;
+
+/// Standard output, through `print!`
+pub struct Stdout;
+
+/// Standard error, through `eprint!`
+pub struct Stderr;
+
+impl Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_text(MessageType::ProgramOutput, s)
+    }
+}
+
+impl Write for Stderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_text(MessageType::ProgramError, s)
+    }
+}
+
+pub fn stdout() -> Stdout {
+    Stdout
+}
+
+pub fn stderr() -> Stderr {
+    Stderr
+}
+
+fn write_text(kind: MessageType, mut text: &str) -> fmt::Result {
+    let Some(port) = output_port() else {
+        for line in text.lines() {
+            log(line);
+        }
+        return Ok(());
+    };
+    while !text.is_empty() {
+        let mut end = text.len().min(CHUNK_BYTES);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        send_message(port, kind, &text.as_bytes()[..end]).map_err(|_| fmt::Error)?;
+        text = &text[end..];
+    }
+    Ok(())
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments<'_>, error: bool) {
+    // Formatted first so a line goes out as one message
+    let mut text = String::new();
+    let _ = text.write_fmt(args);
+    let kind = if error { MessageType::ProgramError } else { MessageType::ProgramOutput };
+    let _ = write_text(kind, &text);
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::io::_print(format_args!($($arg)*), false)
+    };
+}
+
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::print!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::io::_print(format_args!("{}\n", format_args!($($arg)*)), false)
+    };
+}
+
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => {
+        $crate::io::_print(format_args!($($arg)*), true)
+    };
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    () => {
+        $crate::eprint!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::io::_print(format_args!("{}\n", format_args!($($arg)*)), true)
+    };
+}
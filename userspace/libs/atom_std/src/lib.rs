@@ -0,0 +1,63 @@
+//! atom_std - std-like Facade for Atom OS Programs
+//!
+//! Programs written against `atom_syscall` and `libipc` directly each set
+//! up logging, timing and IPC their own way. This crate wraps the common
+//! parts behind names from Rust's standard library, so a new program reads
+//! like ordinary Rust:
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//!
+//! use atom_std::prelude::*;
+//!
+//! atom_std::define_global_allocator!();
+//! atom_std::define_panic_handler!("hello");
+//!
+//! #[no_mangle]
+//! pub extern "C" fn _start() -> ! {
+//!     let start = Instant::now();
+//!     let config = fs::read_to_string("/etc/hello.conf").unwrap_or_default();
+//!     println!("read {} bytes in {:?}", config.len(), start.elapsed());
+//!     process::exit(0)
+//! }
+//! ```
+//!
+//! # Modules
+//!
+//! - `env`: the program's arguments and the boot parameters
+//! - `io`: `print!`/`println!` to the terminal that started the program,
+//!   or the kernel log without one; `eprint!`/`eprintln!` for errors
+//! - `fs`: `File` over the filesystem service
+//! - `time`: `Instant`, `Duration` and `sleep`
+//! - `process`: starting, waiting for and exiting programs
+//! - `sync`: `Mutex` and `OnceLock`, plus the atomics from `core`
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod env;
+pub mod fs;
+pub mod io;
+pub mod process;
+pub mod sync;
+pub mod time;
+
+pub use alloc::{boxed, format, rc, string, vec};
+pub use atom_syscall::{define_global_allocator, define_panic_handler};
+pub use atom_syscall::{SyscallError, SyscallResult};
+pub use libipc::status::{StatusCode, StatusResult};
+
+/// The names most programs use, for a glob import
+pub mod prelude {
+    pub use alloc::borrow::ToOwned;
+    pub use alloc::boxed::Box;
+    pub use alloc::format;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec;
+    pub use alloc::vec::Vec;
+
+    pub use crate::time::{Duration, Instant};
+    pub use crate::{eprint, eprintln, fs, print, println, process};
+}
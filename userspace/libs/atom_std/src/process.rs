@@ -0,0 +1,74 @@
+//! Processes
+//!
+//! Programs are started by path from the boot manifest, which decides the
+//! capabilities they run with. The caller gets a `Child` to wait for or
+//! stop.
+
+use atom_syscall::ipc::PortId;
+use atom_syscall::process::{self as sys, ProcessId};
+use atom_syscall::thread;
+use atom_syscall::{SyscallError, SyscallResult};
+
+pub use atom_syscall::process::EXIT_KILLED;
+
+/// A program this one started
+#[derive(Debug)]
+pub struct Child {
+    pid: ProcessId,
+    /// Exit code, kept once the kernel has reported it, since it only
+    /// does so once
+    status: Option<u64>,
+}
+
+impl Child {
+    fn new(pid: ProcessId) -> Self {
+        Self { pid, status: None }
+    }
+
+    pub fn id(&self) -> ProcessId {
+        self.pid
+    }
+
+    /// Wait for the program to exit; returns its exit code
+    pub fn wait(&mut self) -> SyscallResult<u64> {
+        if let Some(code) = self.status {
+            return Ok(code);
+        }
+        let code = sys::wait(self.pid, u64::MAX)?;
+        self.status = Some(code);
+        Ok(code)
+    }
+
+    /// The exit code if the program has exited, without waiting
+    pub fn try_wait(&mut self) -> SyscallResult<Option<u64>> {
+        if self.status.is_none() {
+            match sys::wait(self.pid, 0) {
+                Ok(code) => self.status = Some(code),
+                Err(SyscallError::WouldBlock | SyscallError::TimedOut) => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(self.status)
+    }
+
+    /// Stop the program; `wait` then reports `EXIT_KILLED`
+    pub fn kill(&self) -> SyscallResult<()> {
+        sys::kill(self.pid)
+    }
+}
+
+/// Start the program at `path`; its output goes to the kernel log
+pub fn spawn(path: &str) -> SyscallResult<Child> {
+    sys::spawn(path).map(Child::new)
+}
+
+/// Start the program at `path` with its output sent to `output`, a port
+/// this program owns
+pub fn spawn_with_output(path: &str, output: PortId) -> SyscallResult<Child> {
+    sys::spawn_with_output(path, output).map(Child::new)
+}
+
+/// End the program with `code`
+pub fn exit(code: u64) -> ! {
+    thread::exit(code)
+}
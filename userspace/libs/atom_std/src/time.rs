@@ -0,0 +1,70 @@
+//! Time
+//!
+//! `Instant` measures time since boot with the kernel's millisecond clock;
+//! durations shorter than a millisecond round down.
+
+use core::ops::{Add, Sub};
+
+pub use core::time::Duration;
+
+use atom_syscall::thread::{get_time_ms, sleep_ms};
+
+/// A point in time, for measuring how long something took
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    ms: u64,
+}
+
+impl Instant {
+    pub fn now() -> Self {
+        Self { ms: get_time_ms() }
+    }
+
+    /// Time since `earlier`; zero if `earlier` is later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_millis(self.ms.saturating_sub(earlier.ms))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        let ms = u64::try_from(duration.as_millis()).ok()?;
+        Some(Self { ms: self.ms.checked_add(ms)? })
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        let ms = u64::try_from(duration.as_millis()).ok()?;
+        Some(Self { ms: self.ms.checked_sub(ms)? })
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration).expect("overflow adding a duration to an instant")
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration).expect("overflow subtracting a duration from an instant")
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// Put the calling thread to sleep for at least `duration`
+pub fn sleep(duration: Duration) {
+    sleep_ms(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX));
+}
@@ -0,0 +1,136 @@
+//! Sound Server Client
+//!
+//! Thin wrapper over the `Audio*` IPC messages. Opening a stream maps the
+//! server-created shared region into the caller and returns an
+//! `AudioStream` that writes straight into the PCM ring.
+
+use atom_syscall::ipc::{create_port, close_port, PortId};
+use atom_syscall::shm::{self, RegionFlags};
+use atom_syscall::{SyscallError, SyscallResult};
+use libipc::discovery::{self, STARTUP_TIMEOUT_MS};
+use libipc::messages::{
+    AudioBeep, AudioOpenStreamRequest, AudioStreamInfo, AudioVolume, MessageType,
+};
+use libipc::protocol::{get_payload, recv_message, send_message, send_message_async};
+use libipc::ServiceId;
+
+use crate::ring::PcmRing;
+use crate::{CHANNELS, MAX_VOLUME, SAMPLE_RATE};
+
+/// Connection to the sound server
+pub struct AudioClient {
+    server: PortId,
+    reply: PortId,
+}
+
+impl AudioClient {
+    /// Connect to the sound server, waiting a while for it to start
+    pub fn connect() -> SyscallResult<Self> {
+        Self::connect_to(discovery::lookup_service(ServiceId::Audio, STARTUP_TIMEOUT_MS)?)
+    }
+
+    /// Connect to a sound server listening on `server`
+    pub fn connect_to(server: PortId) -> SyscallResult<Self> {
+        Ok(Self {
+            server,
+            reply: create_port()?,
+        })
+    }
+
+    /// Open a playback stream in the server's native format
+    pub fn open_stream(&self) -> SyscallResult<AudioStream> {
+        let request = AudioOpenStreamRequest {
+            reply_port: self.reply,
+            sample_rate: SAMPLE_RATE,
+            channels: CHANNELS,
+        };
+        send_message(self.server, MessageType::AudioOpenStream, &request.to_bytes())?;
+
+        let mut buffer = [0u8; 64];
+        let (header, len) = recv_message(self.reply, &mut buffer)?;
+        if header.msg_type != MessageType::AudioStreamOpened {
+            return Err(SyscallError::InvalidArgument);
+        }
+
+        let info = AudioStreamInfo::from_bytes(get_payload(&buffer, len))
+            .ok_or(SyscallError::InvalidArgument)?;
+        if info.stream_id == 0 {
+            return Err(SyscallError::Busy);
+        }
+
+        let size = info.ring_size as usize;
+        let base = shm::map_anywhere(info.region_id, RegionFlags::read_write())?;
+
+        let ring = unsafe { PcmRing::attach(base, size) }.ok_or(SyscallError::InvalidArgument)?;
+
+        Ok(AudioStream {
+            id: info.stream_id,
+            region: info.region_id,
+            server: self.server,
+            ring,
+        })
+    }
+
+    /// Play a generated tone without opening a stream
+    pub fn beep(&self, frequency_hz: u32, duration_ms: u32) -> SyscallResult<()> {
+        let beep = AudioBeep { frequency_hz, duration_ms };
+        send_message_async(self.server, MessageType::AudioBeep, &beep.to_bytes())
+    }
+
+    /// Set master volume (0-100)
+    pub fn set_master_volume(&self, volume: u8) -> SyscallResult<()> {
+        let msg = AudioVolume { stream_id: 0, volume: volume.min(MAX_VOLUME) };
+        send_message_async(self.server, MessageType::AudioSetVolume, &msg.to_bytes())
+    }
+}
+
+impl Drop for AudioClient {
+    fn drop(&mut self) {
+        let _ = close_port(self.reply);
+    }
+}
+
+/// An open playback stream
+pub struct AudioStream {
+    id: u32,
+    region: u64,
+    server: PortId,
+    ring: PcmRing,
+}
+
+impl AudioStream {
+    /// Server-assigned stream id
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Queue interleaved stereo samples; returns how many were accepted
+    pub fn write(&self, samples: &[i16]) -> usize {
+        self.ring.write(samples)
+    }
+
+    /// Samples that can currently be queued
+    pub fn free_space(&self) -> usize {
+        self.ring.free_space()
+    }
+
+    /// Whether the mixer ran dry since the last call
+    pub fn take_underrun(&self) -> bool {
+        self.ring.take_underrun()
+    }
+
+    /// Set this stream's volume (0-100)
+    pub fn set_volume(&self, volume: u8) -> SyscallResult<()> {
+        let msg = AudioVolume { stream_id: self.id, volume: volume.min(MAX_VOLUME) };
+        send_message_async(self.server, MessageType::AudioSetVolume, &msg.to_bytes())
+    }
+}
+
+impl Drop for AudioStream {
+    fn drop(&mut self) {
+        // The server drains whatever is queued, then releases the region
+        self.ring.close();
+        let _ = shm::unmap_region(self.region);
+        let _ = send_message_async(self.server, MessageType::AudioCloseStream, &self.id.to_le_bytes());
+    }
+}
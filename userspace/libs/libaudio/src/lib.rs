@@ -0,0 +1,38 @@
+//! libaudio - Audio Client API for Atom OS
+//!
+//! Applications play sound by opening a stream on the sound server and
+//! writing interleaved signed 16-bit PCM into a shared-memory ring. The
+//! server mixes all active streams and feeds the hardware driver.
+//!
+//! # Data Flow
+//!
+//! ```text
+//! Application ──(PCM ring, shared memory)──┐
+//! Application ──(PCM ring, shared memory)──┼──> Sound Server ──> AC'97 DMA
+//! Terminal `beep` ──(AudioBeep message)────┘       (mixer)
+//! ```
+//!
+//! Control messages (open/close/volume/beep) travel over regular IPC using
+//! the `Audio*` message types from libipc; sample data never does.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod ring;
+pub mod client;
+
+pub use client::{AudioClient, AudioStream};
+pub use ring::PcmRing;
+
+/// Output sample rate used by the mixer and hardware
+pub const SAMPLE_RATE: u32 = 48_000;
+
+/// Output channel count (interleaved stereo)
+pub const CHANNELS: u8 = 2;
+
+/// Size of the shared-memory region backing each stream
+pub const STREAM_RING_BYTES: usize = 64 * 1024;
+
+/// Maximum volume value accepted by `set_volume`
+pub const MAX_VOLUME: u8 = 100;
@@ -0,0 +1,156 @@
+//! Shared-Memory PCM Ring
+//!
+//! Single-producer/single-consumer ring of `i16` samples living in a shared
+//! region. The application writes, the sound server reads; positions are
+//! free-running counters so "full" and "empty" never alias.
+//!
+//! # Layout
+//!
+//! ```text
+//! offset 0:  write position (u32, producer-owned)
+//! offset 4:  read position  (u32, consumer-owned)
+//! offset 8:  capacity in samples (u32, power of two)
+//! offset 12: flags (u32, see FLAG_*)
+//! offset 16: samples
+//! ```
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Bytes reserved for the ring header
+pub const HEADER_SIZE: usize = 16;
+
+/// Producer will not write any more samples
+pub const FLAG_CLOSED: u32 = 1 << 0;
+/// Consumer ran dry since the flag was last cleared
+pub const FLAG_UNDERRUN: u32 = 1 << 1;
+
+/// View over a PCM ring in shared memory
+pub struct PcmRing {
+    base: *mut u8,
+    capacity: u32,
+}
+
+impl PcmRing {
+    /// Initialize a fresh ring in `size` bytes at `base` (server side)
+    ///
+    /// # Safety
+    /// `base` must point to at least `size` writable bytes that stay mapped
+    /// for the lifetime of the returned ring.
+    pub unsafe fn init(base: *mut u8, size: usize) -> Option<Self> {
+        let samples = size.checked_sub(HEADER_SIZE)? / 2;
+        if samples == 0 {
+            return None;
+        }
+        // Round down to a power of two so wrapping is a mask
+        let capacity = 1u32 << (31 - (samples as u32).leading_zeros());
+
+        let ring = Self { base, capacity };
+        ring.write_pos().store(0, Ordering::Relaxed);
+        ring.read_pos().store(0, Ordering::Relaxed);
+        (base.add(8) as *mut u32).write_volatile(capacity);
+        ring.flags().store(0, Ordering::Release);
+        Some(ring)
+    }
+
+    /// Attach to a ring initialized by the other side (client side)
+    ///
+    /// # Safety
+    /// `base` must point to a mapped ring previously set up with `init`.
+    pub unsafe fn attach(base: *mut u8, size: usize) -> Option<Self> {
+        let capacity = (base.add(8) as *const u32).read_volatile();
+        if capacity == 0
+            || !capacity.is_power_of_two()
+            || HEADER_SIZE + capacity as usize * 2 > size
+        {
+            return None;
+        }
+        Some(Self { base, capacity })
+    }
+
+    fn write_pos(&self) -> &AtomicU32 {
+        unsafe { &*(self.base as *const AtomicU32) }
+    }
+
+    fn read_pos(&self) -> &AtomicU32 {
+        unsafe { &*(self.base.add(4) as *const AtomicU32) }
+    }
+
+    fn flags(&self) -> &AtomicU32 {
+        unsafe { &*(self.base.add(12) as *const AtomicU32) }
+    }
+
+    fn samples(&self) -> *mut i16 {
+        unsafe { self.base.add(HEADER_SIZE) as *mut i16 }
+    }
+
+    /// Capacity in samples
+    pub fn capacity(&self) -> usize {
+        self.capacity as usize
+    }
+
+    /// Samples ready to be read
+    pub fn available(&self) -> usize {
+        let write = self.write_pos().load(Ordering::Acquire);
+        let read = self.read_pos().load(Ordering::Relaxed);
+        write.wrapping_sub(read) as usize
+    }
+
+    /// Samples that can be written without overwriting unread data
+    pub fn free_space(&self) -> usize {
+        let write = self.write_pos().load(Ordering::Relaxed);
+        let read = self.read_pos().load(Ordering::Acquire);
+        self.capacity as usize - write.wrapping_sub(read) as usize
+    }
+
+    /// Write as many samples as fit; returns the number written
+    pub fn write(&self, data: &[i16]) -> usize {
+        let count = data.len().min(self.free_space());
+        let write = self.write_pos().load(Ordering::Relaxed);
+        let mask = self.capacity - 1;
+
+        for (i, &sample) in data[..count].iter().enumerate() {
+            let index = (write.wrapping_add(i as u32) & mask) as usize;
+            unsafe { self.samples().add(index).write_volatile(sample) };
+        }
+
+        self.write_pos()
+            .store(write.wrapping_add(count as u32), Ordering::Release);
+        count
+    }
+
+    /// Read up to `out.len()` samples; returns the number read
+    pub fn read(&self, out: &mut [i16]) -> usize {
+        let count = out.len().min(self.available());
+        let read = self.read_pos().load(Ordering::Relaxed);
+        let mask = self.capacity - 1;
+
+        for (i, slot) in out[..count].iter_mut().enumerate() {
+            let index = (read.wrapping_add(i as u32) & mask) as usize;
+            *slot = unsafe { self.samples().add(index).read_volatile() };
+        }
+
+        self.read_pos()
+            .store(read.wrapping_add(count as u32), Ordering::Release);
+        count
+    }
+
+    /// Mark the stream as finished (producer side)
+    pub fn close(&self) {
+        self.flags().fetch_or(FLAG_CLOSED, Ordering::Release);
+    }
+
+    /// Whether the producer has closed the stream
+    pub fn is_closed(&self) -> bool {
+        self.flags().load(Ordering::Acquire) & FLAG_CLOSED != 0
+    }
+
+    /// Record that the consumer ran out of data (consumer side)
+    pub fn mark_underrun(&self) {
+        self.flags().fetch_or(FLAG_UNDERRUN, Ordering::Relaxed);
+    }
+
+    /// Check and clear the underrun flag (producer side)
+    pub fn take_underrun(&self) -> bool {
+        self.flags().fetch_and(!FLAG_UNDERRUN, Ordering::Relaxed) & FLAG_UNDERRUN != 0
+    }
+}
@@ -0,0 +1,306 @@
+//! Graphics Service Client
+//!
+//! Thin wrapper over the graphics IPC messages. Creating a surface maps
+//! the driver-created shared region into the caller and returns a
+//! `Surface` that draws straight into it with the usual `Framebuffer` API.
+
+use core::ops::Deref;
+
+use alloc::vec::Vec;
+
+use atom_syscall::graphics::{Framebuffer, FramebufferInfo};
+use atom_syscall::ipc::{close_port, create_port, PortId};
+use atom_syscall::shm::{self, RegionFlags};
+use atom_syscall::{SyscallError, SyscallResult};
+use libipc::discovery::{self, STARTUP_TIMEOUT_MS};
+use libipc::messages::{
+    self, BlitRequest, CreateSurfaceRequest, DisplayHead, DisplayMode, HeadList, MessageType,
+    ModeChanged, ModeList, PixelFormat, PresentDone, Rect, SetModeRequest, SurfaceInfo,
+    MAX_BLIT_RECTS,
+};
+use libipc::protocol::{get_payload, recv_message, send_message};
+use libipc::{ServiceId, MAX_MESSAGE_SIZE};
+
+/// Connection to the graphics service
+pub struct Display {
+    server: PortId,
+    reply: PortId,
+    width: u32,
+    height: u32,
+}
+
+impl Display {
+    /// Connect to the graphics service, waiting a while for it to start
+    pub fn connect() -> SyscallResult<Self> {
+        Self::connect_to(discovery::lookup_service(ServiceId::Graphics, STARTUP_TIMEOUT_MS)?)
+    }
+
+    /// Connect to a graphics service listening on `server`
+    pub fn connect_to(server: PortId) -> SyscallResult<Self> {
+        let reply = create_port()?;
+        let info = match query_screen(server, reply) {
+            Ok(info) => info,
+            Err(err) => {
+                let _ = close_port(reply);
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            server,
+            reply,
+            width: info.width,
+            height: info.height,
+        })
+    }
+
+    /// Screen width in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Screen height in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Create a `width` x `height` surface and map it
+    pub fn create_surface(&self, width: u32, height: u32, format: PixelFormat) -> SyscallResult<Surface> {
+        let request = CreateSurfaceRequest {
+            reply_port: self.reply,
+            width,
+            height,
+            format,
+        };
+        send_message(self.server, MessageType::CreateSurface, &request.to_bytes())?;
+
+        let mut buffer = [0u8; 64];
+        let (header, len) = recv_message(self.reply, &mut buffer)?;
+        if header.msg_type != MessageType::SurfaceCreated {
+            return Err(SyscallError::InvalidArgument);
+        }
+
+        let info = SurfaceInfo::from_bytes(get_payload(&buffer, len))
+            .ok_or(SyscallError::InvalidArgument)?;
+        if info.surface_id == 0 {
+            return Err(SyscallError::OutOfMemory);
+        }
+
+        let base = match shm::map_anywhere(info.region_id, RegionFlags::read_write()) {
+            Ok(base) => base,
+            Err(err) => {
+                let _ = send_message(self.server, MessageType::DestroySurface, &info.surface_id.to_le_bytes());
+                return Err(err);
+            }
+        };
+
+        let bytes_per_pixel = info.format.bytes_per_pixel();
+        // The region stays mapped until the surface is dropped, which drops
+        // the handle with it
+        let framebuffer = unsafe {
+            Framebuffer::from_info(FramebufferInfo {
+                address: base as usize,
+                width: info.width,
+                height: info.height,
+                stride: info.stride,
+                bytes_per_pixel,
+                size: info.stride as usize * info.height as usize * bytes_per_pixel as usize,
+            })
+        };
+
+        Ok(Surface {
+            id: info.surface_id,
+            region: info.region_id,
+            format: info.format,
+            server: self.server,
+            framebuffer,
+        })
+    }
+
+    /// Copy `rects` of `surface` to the screen, each moved by (`x`, `y`),
+    /// at the next present. More than `MAX_BLIT_RECTS` are sent as their
+    /// bounding rectangle.
+    pub fn blit(&self, surface: &Surface, x: i32, y: i32, rects: &[Rect]) -> SyscallResult<()> {
+        if rects.is_empty() {
+            return Ok(());
+        }
+
+        let rects = if rects.len() > MAX_BLIT_RECTS {
+            let bounds = rects.iter().fold(rects[0], |acc, r| acc.union(r));
+            alloc::vec![bounds]
+        } else {
+            Vec::from(rects)
+        };
+        let request = BlitRequest {
+            surface_id: surface.id,
+            x,
+            y,
+            rects,
+        };
+        send_message(self.server, MessageType::BlitSurface, &request.to_bytes())
+    }
+
+    /// Copy all of `surface` to the screen with its top-left corner at
+    /// (`x`, `y`) at the next present
+    pub fn blit_all(&self, surface: &Surface, x: i32, y: i32) -> SyscallResult<()> {
+        let area = Rect::new(0, 0, surface.width(), surface.height());
+        self.blit(surface, x, y, &[area])
+    }
+
+    /// Show `rect` of the screen again at the next present, without
+    /// blitting to it
+    pub fn invalidate(&self, rect: Rect) -> SyscallResult<()> {
+        send_message(self.server, MessageType::InvalidateRect, &rect.to_bytes())
+    }
+
+    /// Resolutions the screen can switch to, the current one first
+    pub fn modes(&self) -> SyscallResult<Vec<DisplayMode>> {
+        send_message(self.server, MessageType::GetModes, &self.reply.to_le_bytes())?;
+
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        let (header, len) = recv_message(self.reply, &mut buffer)?;
+        if header.msg_type != MessageType::ModeList {
+            return Err(SyscallError::InvalidArgument);
+        }
+        ModeList::from_bytes(get_payload(&buffer, len))
+            .map(|list| list.modes)
+            .ok_or(SyscallError::InvalidArgument)
+    }
+
+    /// The monitors showing the screen, left to right, and the columns
+    /// of it each one shows
+    pub fn heads(&self) -> SyscallResult<Vec<DisplayHead>> {
+        send_message(self.server, MessageType::GetHeads, &self.reply.to_le_bytes())?;
+
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        let (header, len) = recv_message(self.reply, &mut buffer)?;
+        if header.msg_type != MessageType::HeadList {
+            return Err(SyscallError::InvalidArgument);
+        }
+        HeadList::from_bytes(get_payload(&buffer, len))
+            .map(|list| list.heads)
+            .ok_or(SyscallError::InvalidArgument)
+    }
+
+    /// Switch the screen to `width` x `height`, one of the `modes`
+    ///
+    /// Surfaces survive the switch, but the screen starts out blank:
+    /// blit everything again and present. Screen-sized surfaces keep
+    /// their old size; make new ones at the new `width` and `height`.
+    pub fn set_mode(&mut self, width: u32, height: u32) -> SyscallResult<()> {
+        let request = SetModeRequest {
+            reply_port: self.reply,
+            width,
+            height,
+        };
+        send_message(self.server, MessageType::SetMode, &request.to_bytes())?;
+
+        let mut buffer = [0u8; 64];
+        let (header, len) = recv_message(self.reply, &mut buffer)?;
+        if header.msg_type != MessageType::ModeChanged {
+            return Err(SyscallError::InvalidArgument);
+        }
+        let changed = ModeChanged::from_bytes(get_payload(&buffer, len))
+            .ok_or(SyscallError::InvalidArgument)?;
+
+        self.width = changed.mode.width;
+        self.height = changed.mode.height;
+        if changed.success {
+            Ok(())
+        } else {
+            Err(SyscallError::InvalidArgument)
+        }
+    }
+
+    /// Put everything blitted since the last present on screen, without
+    /// waiting for it to get there
+    pub fn present(&self) -> SyscallResult<()> {
+        send_message(self.server, MessageType::Present, &0u64.to_le_bytes())
+    }
+
+    /// Like `present`, but wait until the frame is on screen; returns how
+    /// many pixels it copied
+    pub fn present_and_wait(&self) -> SyscallResult<u64> {
+        send_message(self.server, MessageType::Present, &self.reply.to_le_bytes())?;
+
+        let mut buffer = [0u8; 64];
+        let (header, len) = recv_message(self.reply, &mut buffer)?;
+        if header.msg_type != MessageType::PresentDone {
+            return Err(SyscallError::InvalidArgument);
+        }
+        PresentDone::from_bytes(get_payload(&buffer, len))
+            .map(|done| done.pixels)
+            .ok_or(SyscallError::InvalidArgument)
+    }
+}
+
+impl Drop for Display {
+    fn drop(&mut self) {
+        let _ = close_port(self.reply);
+    }
+}
+
+/// Ask the service on `server` for the screen geometry
+fn query_screen(server: PortId, reply: PortId) -> SyscallResult<messages::FramebufferInfo> {
+    send_message(server, MessageType::GetFramebuffer, &reply.to_le_bytes())?;
+
+    let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+    let (header, len) = recv_message(reply, &mut buffer)?;
+    if header.msg_type != MessageType::FramebufferInfo {
+        return Err(SyscallError::InvalidArgument);
+    }
+    messages::FramebufferInfo::from_bytes(get_payload(&buffer, len)).ok_or(SyscallError::InvalidArgument)
+}
+
+/// A surface of the graphics service, mapped into this process
+///
+/// Dereferences to a `Framebuffer` over the surface's pixels, so anything
+/// that draws on a framebuffer draws on a surface. For `Argb32` surfaces
+/// the top byte of each pixel is its alpha. `Rgb565` surfaces hold 16-bit
+/// pixels, which the `Framebuffer` API cannot draw; use `set_pixel16`.
+pub struct Surface {
+    id: u32,
+    region: u64,
+    format: PixelFormat,
+    server: PortId,
+    framebuffer: Framebuffer,
+}
+
+impl Surface {
+    /// Service-assigned surface id
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Set the pixel at (x, y) of an `Rgb565` surface; ignored off the
+    /// surface and for 32-bit formats
+    pub fn set_pixel16(&self, x: u32, y: u32, pixel: u16) {
+        if self.format != PixelFormat::Rgb565 || x >= self.width() || y >= self.height() {
+            return;
+        }
+        let offset = (y as usize * self.stride() as usize + x as usize) * 2;
+        unsafe {
+            core::ptr::write_volatile((self.address() + offset) as *mut u16, pixel);
+        }
+    }
+}
+
+impl Deref for Surface {
+    type Target = Framebuffer;
+
+    fn deref(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        // The service can only free the region once nothing maps it
+        let _ = shm::unmap_region(self.region);
+        let _ = send_message(self.server, MessageType::DestroySurface, &self.id.to_le_bytes());
+    }
+}
@@ -0,0 +1,35 @@
+//! libdisplay - Graphics Service Client API for Atom OS
+//!
+//! The display driver owns the framebuffer. Programs that put pixels on
+//! screen themselves (the compositor, full-screen tools) create surfaces
+//! on it, draw into them through shared memory, blit the parts that
+//! changed and present.
+//!
+//! # Data Flow
+//!
+//! ```text
+//! Client ──(draws into surface, shared memory)──┐
+//! Client ──(BlitSurface: changed rectangles)────┼──> Display Driver ──> Framebuffer
+//! Client ──(Present)────────────────────────────┘    (back buffer)
+//! ```
+//!
+//! Control messages travel over regular IPC using the graphics message
+//! types from libipc; pixels never do. Blits land in the driver's back
+//! buffer and only show after `Present`, which copies every area blitted
+//! since the last one.
+//!
+//! The screen's resolution can be changed with `Display::set_mode`, to
+//! one of `Display::modes`. Surfaces survive the change; what was on
+//! screen does not.
+//!
+//! Windowed applications should use libgui instead: their windows are
+//! surfaces of the compositor, not of the display driver.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod client;
+
+pub use client::{Display, Surface};
+pub use libipc::messages::{DisplayMode, PixelFormat};
@@ -9,20 +9,66 @@ extern crate alloc;
 use alloc::string::String;
 use alloc::vec::Vec;
 use crate::surface::Surface;
-use crate::event::Event;
-use atom_syscall::ipc::PortId;
-use atom_syscall::SyscallResult;
+use crate::clipboard::{self, Clipboard};
+use crate::event::{
+    DragEvent, Event, KeyEvent, KeyModifiers, MouseButton, MouseEvent, TimerId, WindowEvent,
+};
+use crate::window::{Handler, Window};
+use atom_syscall::ipc::{close_port, create_port, PortId};
+use atom_syscall::shm::{self, RegionFlags, RegionId};
+use atom_syscall::{SyscallError, SyscallResult};
+use libipc::connection::Connection;
+use libipc::discovery::{self, STARTUP_TIMEOUT_MS};
+use libipc::messages::{
+    ClipboardData, ClipboardMime, CreateWindowRequest, CursorShape, DisplayInfo, DisplayList,
+    DragEnd, DragStart, DropEvent, FrameDone, MessageHeader, MessageType, MouseButtonEvent,
+    MouseMoveEvent, MouseScrollEvent, Notification, NotificationHistory, NotificationRecord,
+    ReattachRequest, ScaleFactor, SetWallpaper, SurfaceRegion, ThemeSpec, Urgency, WallpaperMode,
+    WindowCursor, WindowEventMsg, WindowEventType, WindowId, WindowResize, WindowRole, WindowTitle,
+    MAX_WALLPAPER_BYTES,
+};
+use libipc::protocol::{get_payload, recv_message, try_recv_message};
+use libipc::{ServiceId, MAX_MESSAGE_SIZE};
+
+/// Shortest timer interval: the length of a scheduler tick, since the
+/// clock moves no faster
+const TIMER_RESOLUTION_MS: u64 = 10;
+
+/// A timer waiting to go off
+struct Timer {
+    id: TimerId,
+    /// Time the timer goes off next, in milliseconds since boot
+    deadline_ms: u64,
+    /// Time between repeats, or `None` for a timer that goes off once
+    interval_ms: Option<u64>,
+}
 
 /// Application state and context
 pub struct Application {
     /// Application name
     name: String,
-    /// IPC port for receiving events from compositor
-    event_port: Option<PortId>,
+    /// Link to the desktop compositor; its port is the one events arrive
+    /// on
+    compositor: Connection<'static>,
     /// Pending events queue
     event_queue: Vec<Event>,
     /// Whether application should quit
     quit_requested: bool,
+    /// Desktop clipboard, through the compositor
+    clipboard: Clipboard,
+    /// Region holding the text of a drag this application started
+    drag_region: Option<RegionId>,
+    /// Desktop theme, as last announced by the compositor
+    theme: ThemeSpec,
+    /// Output scale, as last announced by the compositor
+    scale: ScaleFactor,
+    /// Whether a window was opened; its input comes from the compositor
+    /// rather than straight from the keyboard
+    windowed: bool,
+    /// Timers that have yet to go off
+    timers: Vec<Timer>,
+    /// Id the next timer gets
+    next_timer: u32,
 }
 
 impl Application {
@@ -32,60 +78,552 @@ impl Application {
     /// 1. Register with the desktop compositor
     /// 2. Create an IPC port for receiving events
     /// 3. Request initial window/surface allocation
+    ///
+    /// The compositor is looked up by name, waiting for it a while if it
+    /// is still starting.
     pub fn new(name: &str) -> SyscallResult<Self> {
+        let compositor = discovery::lookup_service(ServiceId::Desktop, STARTUP_TIMEOUT_MS)?;
+        Self::with_compositor(name, compositor)
+    }
+
+    /// Create an application talking to the compositor on `compositor`
+    pub fn with_compositor(name: &str, compositor: PortId) -> SyscallResult<Self> {
         Ok(Self {
             name: String::from(name),
-            event_port: None,
+            compositor: Connection::with_peer(ServiceId::Desktop, compositor),
             event_queue: Vec::new(),
             quit_requested: false,
+            clipboard: Clipboard::new(compositor),
+            drag_region: None,
+            theme: ThemeSpec::NORD,
+            scale: ScaleFactor::X1,
+            windowed: false,
+            timers: Vec::new(),
+            next_timer: 0,
         })
     }
 
+    /// An application for a dialog window titled `title`, with an event
+    /// port of its own so this one's events wait while the dialog runs
+    pub(crate) fn for_dialog(&self, title: &str) -> SyscallResult<Self> {
+        let mut app = Self::with_compositor(title, self.compositor.connect()?)?;
+        app.theme = self.theme;
+        app.scale = self.scale;
+        Ok(app)
+    }
+
+    /// Close the event port once the windows using it are gone
+    pub(crate) fn close_event_port(&mut self) {
+        self.compositor.close_port();
+    }
+
     /// Get application name
     pub fn name(&self) -> &str {
         &self.name
     }
 
-    /// Create a surface for rendering
+    /// Current desktop theme; `Event::ThemeChanged` reports changes
+    pub fn theme(&self) -> &ThemeSpec {
+        &self.theme
+    }
+
+    /// Output scale; `Event::ScaleChanged` reports changes
+    pub fn scale(&self) -> ScaleFactor {
+        self.scale
+    }
+
+    /// Protocol version the compositor agreed to when the first window
+    /// was opened
+    pub fn protocol_version(&self) -> Option<u16> {
+        self.compositor.version()
+    }
+
+    /// Outputs the desktop spans, in desktop coordinates
+    pub fn displays(&self) -> SyscallResult<Vec<DisplayInfo>> {
+        let reply_port = create_port()?;
+        let result = self.send(MessageType::GetDisplays, &reply_port.to_le_bytes()).and_then(|_| {
+            let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+            let (header, len) = recv_message(reply_port, &mut buffer)?;
+            if header.msg_type != MessageType::DisplayList {
+                return Err(SyscallError::InvalidArgument);
+            }
+            DisplayList::from_bytes(get_payload(&buffer, len))
+                .map(|list| list.displays)
+                .ok_or(SyscallError::InvalidArgument)
+        });
+        let _ = close_port(reply_port);
+        result
+    }
+
+    /// Create a window and return the surface backing its client area
     ///
-    /// In the full implementation, this would request a surface
-    /// from the desktop compositor via IPC.
+    /// The compositor allocates the surface in shared memory; drawing into
+    /// it and calling `present` puts the frame on screen. On a scaled
+    /// output the compositor stretches the surface.
     pub fn create_surface(&mut self, width: u32, height: u32) -> SyscallResult<Surface> {
-        // For now, directly use the framebuffer
-        use atom_syscall::graphics::get_framebuffer_info;
+        self.open_window(width, height, false)
+    }
+
+    /// Create a window whose surface is in physical pixels, `width` x
+    /// `height` times the output scale, for drawing sharply on HiDPI screens
+    pub fn create_native_surface(&mut self, width: u32, height: u32) -> SyscallResult<Surface> {
+        self.open_window(width, height, true)
+    }
 
-        let info = get_framebuffer_info()?;
+    fn open_window(
+        &mut self,
+        width: u32,
+        height: u32,
+        native_scale: bool,
+    ) -> SyscallResult<Surface> {
+        let reply = self.event_port()?;
+        self.compositor.handshake()?;
 
-        // Calculate effective dimensions (clamp to framebuffer size)
-        let effective_width = width.min(info.1);
-        let effective_height = height.min(info.2);
+        let request = CreateWindowRequest {
+            reply_port: reply,
+            width,
+            height,
+            native_scale,
+            title: self.name.clone(),
+            app_id: self.name.clone(),
+        };
+        self.send(MessageType::CreateWindow, &request.to_bytes())?;
 
-        Ok(Surface::new(
-            1, // Surface ID
-            effective_width,
-            effective_height,
-            info.3, // stride
-            info.4 as usize, // bpp
-            info.0 as *mut u8, // buffer address
-        ))
+        let info = recv_surface_region(reply)?;
+        if native_scale {
+            self.scale = info.scale;
+        }
+        self.windowed = true;
+
+        let base = shm::map_anywhere(info.region_id, RegionFlags::read_write())?;
+
+        Ok(Surface::shared(
+            info.window_id,
+            info.width,
+            info.height,
+            info.stride,
+            base,
+            self.compositor.connect()?,
+            info.region_id,
+        )
+        .with_scale(info.scale))
+    }
+
+    /// Hand `surfaces` to a compositor started after the previous one
+    /// exited (`Event::CompositorLost`), waiting a while for it to start
+    ///
+    /// Each window comes back with its surface and contents under a new
+    /// window id; present the surfaces again to show them.
+    pub fn reattach(&mut self, surfaces: &mut [&mut Surface]) -> SyscallResult<()> {
+        let reply = self.event_port()?;
+        self.compositor.disconnect();
+        let compositor = self.compositor.connect()?;
+        self.clipboard = Clipboard::new(compositor);
+        self.compositor.handshake()?;
+
+        for surface in surfaces.iter_mut() {
+            let Some(region_id) = surface.region() else {
+                continue;
+            };
+            let request = ReattachRequest {
+                reply_port: reply,
+                region_id,
+                width: surface.width(),
+                height: surface.height(),
+                stride: surface.stride(),
+                scale: surface.scale(),
+                title: self.name.clone(),
+                app_id: self.name.clone(),
+            };
+            self.send(MessageType::ReattachWindow, &request.to_bytes())?;
+
+            let info = recv_surface_region(reply)?;
+            surface.reattach(info.window_id, compositor);
+        }
+        Ok(())
+    }
+
+    /// Give `surface`'s window a surface of `width` x `height`, in the
+    /// surface's pixels, as `WindowEvent::Resize` asks
+    ///
+    /// The compositor may refuse a size it cannot allocate and keep the old
+    /// one; either way the contents are lost, so draw and present a whole
+    /// frame afterwards. Events arriving meanwhile are kept for
+    /// `poll_event`.
+    pub fn resize_surface(
+        &mut self,
+        surface: &mut Surface,
+        width: u32,
+        height: u32,
+    ) -> SyscallResult<()> {
+        let reply = self.event_port()?;
+        // Unmapped first so the compositor can free it
+        surface.unmap().ok_or(SyscallError::InvalidArgument)?;
+
+        let request = WindowResize { window_id: surface.id(), width, height };
+        self.send(MessageType::ResizeWindow, &request.to_bytes())?;
+
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        let info = loop {
+            let (header, len) = recv_message(reply, &mut buffer)?;
+            let payload = get_payload(&buffer, len);
+            if header.msg_type == MessageType::SurfaceRegion {
+                break SurfaceRegion::from_bytes(payload).ok_or(SyscallError::InvalidArgument)?;
+            }
+            let Some(event) = self.message_event(header.msg_type, payload) else {
+                continue;
+            };
+            let lost = matches!(event, Event::CompositorLost);
+            self.event_queue.insert(0, event);
+            if lost {
+                return Err(SyscallError::Busy);
+            }
+        };
+        if info.window_id == 0 {
+            return Err(SyscallError::OutOfMemory);
+        }
+
+        let base = shm::map_anywhere(info.region_id, RegionFlags::read_write())?;
+        surface.remap(info.width, info.height, info.stride, base, info.region_id);
+        Ok(())
+    }
+
+    /// Send a request to the compositor, finding it again if it restarted
+    fn send(&self, msg_type: MessageType, payload: &[u8]) -> SyscallResult<()> {
+        Ok(self.compositor.send(msg_type, payload)?)
+    }
+
+    /// Copy text to the desktop clipboard
+    pub fn set_clipboard_text(&mut self, text: &str) -> SyscallResult<()> {
+        let owner = self.event_port()?;
+        self.clipboard.set_text(owner, text)
+    }
+
+    /// Text on the desktop clipboard, for pasting
+    pub fn clipboard_text(&self) -> SyscallResult<String> {
+        self.clipboard.text()
+    }
+
+    /// Show a notification toast under this application's name
+    pub fn notify(&self, title: &str, body: &str, urgency: Urgency) -> SyscallResult<()> {
+        let notification = Notification {
+            app_name: self.name.clone(),
+            title: String::from(title),
+            body: String::from(body),
+            urgency,
+            timeout_ms: 0,
+        };
+        self.send(MessageType::Notify, &notification.to_bytes())
+    }
+
+    /// Recent notifications from all applications, newest first
+    pub fn notification_history(&self) -> SyscallResult<Vec<NotificationRecord>> {
+        let reply_port = create_port()?;
+        let request = reply_port.to_le_bytes();
+        let result = self.send(MessageType::GetNotificationHistory, &request).and_then(|_| {
+            let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+            let (header, len) = recv_message(reply_port, &mut buffer)?;
+            if header.msg_type != MessageType::NotificationHistory {
+                return Err(SyscallError::InvalidArgument);
+            }
+            NotificationHistory::from_bytes(get_payload(&buffer, len))
+                .map(|history| history.records)
+                .ok_or(SyscallError::InvalidArgument)
+        });
+        let _ = close_port(reply_port);
+        result
+    }
+
+    /// Set the desktop wallpaper from the bytes of a BMP or PNG file
+    ///
+    /// Fails with `InvalidArgument` if the compositor cannot decode it.
+    pub fn set_wallpaper(&self, image: &[u8], mode: WallpaperMode) -> SyscallResult<()> {
+        if image.is_empty() || image.len() > MAX_WALLPAPER_BYTES {
+            return Err(SyscallError::InvalidArgument);
+        }
+
+        let region = shm::create_region(image.len())?;
+        let copied = shm::map_anywhere(region, RegionFlags::read_write())
+            .map(|base| {
+                unsafe {
+                    core::ptr::copy_nonoverlapping(image.as_ptr(), base, image.len());
+                }
+                let _ = shm::unmap_region(region);
+            });
+
+        // The compositor has decoded the image once it replies
+        let result = copied.and_then(|_| {
+            let reply_port = create_port()?;
+            let request = SetWallpaper {
+                reply_port,
+                region_id: region,
+                len: image.len() as u32,
+                mode,
+            };
+            let bytes = request.to_bytes();
+            let result = self.send(MessageType::SetWallpaper, &bytes)
+                .and_then(|_| {
+                    let mut buffer = [0u8; 64];
+                    let (header, len) = recv_message(reply_port, &mut buffer)?;
+                    match (header.msg_type, get_payload(&buffer, len)) {
+                        (MessageType::WallpaperResult, [1, ..]) => Ok(()),
+                        _ => Err(SyscallError::InvalidArgument),
+                    }
+                });
+            let _ = close_port(reply_port);
+            result
+        });
+
+        let _ = shm::destroy_region(region);
+        result
+    }
+
+    /// Switch the desktop theme to the one in a theme config file
+    ///
+    /// Every window then gets `Event::ThemeChanged`; a file the compositor
+    /// cannot read is ignored.
+    pub fn set_theme(&self, config: &str) -> SyscallResult<()> {
+        if config.len() > MAX_MESSAGE_SIZE - MessageHeader::SIZE {
+            return Err(SyscallError::InvalidArgument);
+        }
+        self.send(MessageType::SetTheme, config.as_bytes())
+    }
+
+    /// Make `window` a dialog of another window, or move it to another
+    /// stacking layer
+    pub fn set_window_role(&self, role: WindowRole) -> SyscallResult<()> {
+        self.send(MessageType::SetWindowRole, &role.to_bytes())
+    }
+
+    /// Show `title` in `window`'s title bar instead of the application name
+    pub fn set_title(&self, window: WindowId, title: &str) -> SyscallResult<()> {
+        let msg = WindowTitle { window_id: window, title: String::from(title) };
+        self.send(MessageType::SetTitle, &msg.to_bytes())
+    }
+
+    /// Pointer shape over `window`'s client area, e.g. an I-beam over text
+    pub fn set_cursor(&self, window: WindowId, shape: CursorShape) -> SyscallResult<()> {
+        let msg = WindowCursor { window_id: window, shape };
+        self.send(MessageType::SetCursor, &msg.to_bytes())
+    }
+
+    /// Start dragging text out of `window` while the left button is held
+    ///
+    /// The window under the cursor gets `DragEvent::Drop` when the button
+    /// is released there; this application then gets `DragEvent::End`.
+    pub fn start_drag_text(&mut self, window: WindowId, text: &str) -> SyscallResult<()> {
+        if self.drag_region.is_some() {
+            return Err(SyscallError::WouldBlock);
+        }
+
+        let (content, region) = clipboard::text_content(text)?;
+        let start = DragStart {
+            window_id: window,
+            data: ClipboardData {
+                port: 0,
+                mime: ClipboardMime::TextPlain,
+                content,
+            },
+        };
+        if let Err(e) = self.send(MessageType::DragStart, &start.to_bytes()) {
+            if let Some(region) = region {
+                let _ = shm::destroy_region(region);
+            }
+            return Err(e);
+        }
+        self.drag_region = region;
+        Ok(())
+    }
+
+    /// Turn the next compositor message on the event port into an event
+    fn recv_port_event(&mut self) -> Option<Event> {
+        let port = self.compositor.local_port()?;
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        let (header, len) = try_recv_message(port, &mut buffer).ok()??;
+        self.message_event(header.msg_type, get_payload(&buffer, len))
+    }
+
+    /// Event a compositor message stands for, if any
+    fn message_event(&mut self, msg_type: MessageType, payload: &[u8]) -> Option<Event> {
+        match msg_type {
+            MessageType::KeyDown | MessageType::KeyUp => {
+                let key = libipc::messages::KeyEvent::from_bytes(payload)?;
+                // Widgets and older applications expect set-1 scancodes
+                return Some(Event::Key(KeyEvent {
+                    scancode: key.keycode.to_set1().map_or(0, |(code, _)| code),
+                    character: key.character,
+                    pressed: msg_type == MessageType::KeyDown,
+                    modifiers: KeyModifiers::from_u8(key.modifiers.to_u8()),
+                }));
+            }
+            MessageType::MouseMove => {
+                let motion = MouseMoveEvent::from_bytes(payload)?;
+                let (x, y, dx, dy) = (motion.x, motion.y, motion.dx, motion.dy);
+                return Some(Event::Mouse(MouseEvent::Move { x, y, dx, dy }));
+            }
+            MessageType::MouseButtonDown | MessageType::MouseButtonUp => {
+                let click = MouseButtonEvent::from_bytes(payload)?;
+                let button = match click.button {
+                    libipc::messages::MouseButton::Left => MouseButton::Left,
+                    libipc::messages::MouseButton::Right => MouseButton::Right,
+                    libipc::messages::MouseButton::Middle => MouseButton::Middle,
+                    _ => return None,
+                };
+                let (x, y) = (click.x, click.y);
+                return Some(Event::Mouse(match msg_type {
+                    MessageType::MouseButtonDown => MouseEvent::ButtonDown { button, x, y },
+                    _ => MouseEvent::ButtonUp { button, x, y },
+                }));
+            }
+            MessageType::MouseScroll => {
+                let scroll = MouseScrollEvent::from_bytes(payload)?;
+                let (delta, x, y) = (scroll.delta, scroll.x, scroll.y);
+                return Some(Event::Mouse(MouseEvent::Scroll { delta, x, y }));
+            }
+            MessageType::WindowEvent => {
+                let event = WindowEventMsg::from_bytes(payload)?;
+                let (x, y, width, height) = (event.x, event.y, event.width, event.height);
+                return Some(Event::Window(match event.event_type {
+                    WindowEventType::Resize | WindowEventType::ResizeRequested => {
+                        WindowEvent::Resize { width, height }
+                    }
+                    WindowEventType::Move => WindowEvent::Move { x, y },
+                    WindowEventType::Focus => WindowEvent::Focus,
+                    WindowEventType::Unfocus => WindowEvent::Unfocus,
+                    WindowEventType::Close => WindowEvent::Close,
+                    WindowEventType::Expose => WindowEvent::Expose { x, y, width, height },
+                    WindowEventType::PointerEnter => WindowEvent::PointerEnter { x, y },
+                    WindowEventType::PointerLeave => WindowEvent::PointerLeave,
+                }));
+            }
+            MessageType::ThemeChanged => {
+                self.theme = ThemeSpec::from_bytes(payload)?;
+                return Some(Event::ThemeChanged(self.theme));
+            }
+            MessageType::ScaleChanged => {
+                self.scale = ScaleFactor::from_u8(*payload.first()?)?;
+                return Some(Event::ScaleChanged(self.scale));
+            }
+            MessageType::FrameDone => {
+                let done = FrameDone::from_bytes(payload)?;
+                return Some(Event::FrameDone { window: done.window_id, time_ms: done.time_ms });
+            }
+            MessageType::PortDied => {
+                let port = u64::from_le_bytes(payload.get(..8)?.try_into().ok()?);
+                if Some(port) != self.compositor.peer() {
+                    return None;
+                }
+                self.compositor.disconnect();
+                return Some(Event::CompositorLost);
+            }
+            _ => {}
+        }
+
+        let drag = match msg_type {
+            MessageType::DragEnter | MessageType::DragMotion | MessageType::DragLeave => {
+                let event = libipc::messages::DragEvent::from_bytes(payload)?;
+                let (x, y) = (event.x, event.y);
+                match msg_type {
+                    MessageType::DragEnter => DragEvent::Enter { x, y },
+                    MessageType::DragMotion => DragEvent::Motion { x, y },
+                    _ => DragEvent::Leave,
+                }
+            }
+            MessageType::Drop => {
+                let event = DropEvent::from_bytes(payload)?;
+                let text = clipboard::read_text(&event.data.content).ok()?;
+                DragEvent::Drop { x: event.x, y: event.y, text }
+            }
+            MessageType::DragEnd => {
+                let end = DragEnd::from_bytes(payload)?;
+                // The receiver copied the text before the source was told
+                if let Some(region) = self.drag_region.take() {
+                    let _ = shm::destroy_region(region);
+                }
+                DragEvent::End { dropped: end.dropped }
+            }
+            _ => return None,
+        };
+        Some(Event::Drag(drag))
+    }
+
+    /// Port the compositor sends this application's events to
+    fn event_port(&mut self) -> SyscallResult<PortId> {
+        Ok(self.compositor.port()?)
     }
 
     /// Create a full-screen surface
     pub fn create_fullscreen_surface(&mut self) -> SyscallResult<Surface> {
-        use atom_syscall::graphics::get_framebuffer_info;
+        use atom_syscall::graphics::get_framebuffer;
 
-        let info = get_framebuffer_info()?;
+        let info = get_framebuffer().ok_or(SyscallError::PermissionDenied)?;
 
         Ok(Surface::new(
             0, // Surface ID 0 = fullscreen
-            info.1, // width
-            info.2, // height
-            info.3, // stride
-            info.4 as usize, // bpp
-            info.0 as *mut u8, // buffer address
+            info.width,
+            info.height,
+            info.stride,
+            info.bytes_per_pixel as usize,
+            info.address as *mut u8,
         ))
     }
 
+    /// Start a timer that goes off every `interval_ms` milliseconds until
+    /// cancelled, as `Event::Timer` through the event loop
+    ///
+    /// Cursor blinks and progress spinners redraw from it; smooth
+    /// animation should draw on `Event::FrameDone` instead, which keeps
+    /// pace with the screen.
+    pub fn set_timer(&mut self, interval_ms: u64) -> TimerId {
+        let interval_ms = interval_ms.max(TIMER_RESOLUTION_MS);
+        self.add_timer(interval_ms, Some(interval_ms))
+    }
+
+    /// Start a timer that goes off once, `delay_ms` milliseconds from now
+    pub fn set_timeout(&mut self, delay_ms: u64) -> TimerId {
+        self.add_timer(delay_ms, None)
+    }
+
+    /// Stop timer `id`; false if it already went off for the last time
+    pub fn cancel_timer(&mut self, id: TimerId) -> bool {
+        let count = self.timers.len();
+        self.timers.retain(|timer| timer.id != id);
+        self.timers.len() != count
+    }
+
+    fn add_timer(&mut self, delay_ms: u64, interval_ms: Option<u64>) -> TimerId {
+        let id = TimerId(self.next_timer);
+        self.next_timer = self.next_timer.wrapping_add(1);
+        let deadline_ms = atom_syscall::thread::get_time_ms() + delay_ms;
+        self.timers.push(Timer { id, deadline_ms, interval_ms });
+        id
+    }
+
+    /// The event of the timer most overdue, if any is
+    fn timer_event(&mut self) -> Option<Event> {
+        if self.timers.is_empty() {
+            return None;
+        }
+        let now = atom_syscall::thread::get_time_ms();
+        let (index, timer) = self
+            .timers
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, timer)| timer.deadline_ms <= now)
+            .min_by_key(|(_, timer)| timer.deadline_ms)?;
+        let id = timer.id;
+        match timer.interval_ms {
+            // Skip the intervals missed rather than catch up on them
+            Some(interval) => {
+                timer.deadline_ms += ((now - timer.deadline_ms) / interval + 1) * interval;
+            }
+            None => {
+                self.timers.swap_remove(index);
+            }
+        }
+        Some(Event::Timer { id, time_ms: now })
+    }
+
     /// Poll for the next event
     ///
     /// Returns None if no events are pending.
@@ -99,13 +637,24 @@ impl Application {
             return event;
         }
 
-        // Check for keyboard input
-        if let Some(scancode) = atom_syscall::input::poll_keyboard() {
-            return Event::Key(crate::event::KeyEvent {
+        if let Some(event) = self.recv_port_event() {
+            return event;
+        }
+
+        if let Some(event) = self.timer_event() {
+            return event;
+        }
+
+        // Without a window, read the keyboard directly
+        if self.windowed {
+            return Event::None;
+        }
+        if let Some(scancode) = atom_syscall::input::keyboard_poll() {
+            return Event::Key(KeyEvent {
                 scancode,
                 character: scancode_to_ascii(scancode),
                 pressed: scancode & 0x80 == 0,
-                modifiers: crate::event::KeyModifiers::default(),
+                modifiers: KeyModifiers::default(),
             });
         }
 
@@ -125,6 +674,51 @@ impl Application {
         }
     }
 
+    /// Run `window` until it is closed or the application quits
+    ///
+    /// Events go to `handler` after the loop has done its part: a resize
+    /// gets the window a surface of the new size first, focus changes are
+    /// recorded and a close request closes the window unless the handler
+    /// refuses. Frames are drawn when the window has something to redraw
+    /// and the compositor has shown the previous one, so the handler never
+    /// draws faster than the screen refreshes.
+    pub fn run(&mut self, window: &mut Window, handler: &mut impl Handler) {
+        window.invalidate_all();
+        while window.is_open() {
+            let event = match self.poll_event() {
+                Event::Quit => return,
+                Event::None => {
+                    if !window.draw_frame(handler) {
+                        atom_syscall::thread::yield_now();
+                    }
+                    continue;
+                }
+                event => event,
+            };
+
+            let event = match event {
+                Event::Window(WindowEvent::Resize { width, height }) => {
+                    // A failed resize leaves the window as it was, or blank
+                    // if even the old size could not be had again
+                    let _ = self.resize_surface(window.surface_mut(), width, height);
+                    window.invalidate_all();
+                    let (width, height) = window.size();
+                    Event::Window(WindowEvent::Resize { width, height })
+                }
+                Event::Window(WindowEvent::Close) => {
+                    if handler.close_requested(self, window) {
+                        window.close();
+                    }
+                    continue;
+                }
+                event => event,
+            };
+
+            window.apply(&event);
+            handler.event(self, window, &event);
+        }
+    }
+
     /// Request application quit
     pub fn quit(&mut self) {
         self.quit_requested = true;
@@ -141,6 +735,22 @@ impl Application {
     }
 }
 
+/// Wait on `reply` for the compositor's answer to a window request
+fn recv_surface_region(reply: PortId) -> SyscallResult<SurfaceRegion> {
+    let mut buffer = [0u8; 64];
+    let (header, len) = recv_message(reply, &mut buffer)?;
+    if header.msg_type != MessageType::SurfaceRegion {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let info = SurfaceRegion::from_bytes(get_payload(&buffer, len))
+        .ok_or(SyscallError::InvalidArgument)?;
+    if info.window_id == 0 {
+        return Err(SyscallError::OutOfMemory);
+    }
+    Ok(info)
+}
+
 /// Simple scancode to ASCII conversion (US keyboard layout)
 fn scancode_to_ascii(scancode: u8) -> u8 {
     // Only handle key press (not release)
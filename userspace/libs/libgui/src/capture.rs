@@ -0,0 +1,124 @@
+//! Screen Capture
+//!
+//! Screenshots of the desktop or of one window, taken by the compositor
+//! for programs whose manifest entry grants `ScreenshotCap`; anyone else
+//! gets `PermissionDenied`. Calling repeatedly records the screen frame
+//! by frame.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use atom_syscall::ipc::{close_port, create_port, PortId};
+use atom_syscall::shm::{self, RegionFlags, RegionId};
+use atom_syscall::{SyscallError, SyscallResult};
+use libipc::messages::{
+    CaptureRequest, CaptureResult, CaptureStatus, MessageType, WindowId, SURFACE_BYTES_PER_PIXEL,
+};
+use libipc::protocol::{get_payload, recv_message, send_message};
+
+/// Captured pixels, 32-bit in the framebuffer's format, rows packed
+pub struct Capture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u32>,
+}
+
+pub struct ScreenCapture {
+    compositor: PortId,
+    /// Region reused between captures of the same size
+    region: Option<(RegionId, usize)>,
+}
+
+impl ScreenCapture {
+    pub fn new(compositor: PortId) -> Self {
+        Self {
+            compositor,
+            region: None,
+        }
+    }
+
+    /// Capture the whole screen as composed, without the cursor
+    pub fn screen(&mut self) -> SyscallResult<Capture> {
+        self.capture(MessageType::CaptureScreen, 0)
+    }
+
+    /// Capture the content of one window
+    pub fn window(&mut self, window: WindowId) -> SyscallResult<Capture> {
+        self.capture(MessageType::CaptureWindow, window)
+    }
+
+    fn capture(&mut self, msg_type: MessageType, window: WindowId) -> SyscallResult<Capture> {
+        let reply_port = create_port()?;
+        let mut result = self.request(reply_port, msg_type, window);
+
+        // The first request tells the size when the region is missing or
+        // too small; try once more with one that fits
+        if let Ok(CaptureResult { status: CaptureStatus::RegionTooSmall, width, height }) = result {
+            let len = width as usize * height as usize * SURFACE_BYTES_PER_PIXEL as usize;
+            self.replace_region(len)?;
+            result = self.request(reply_port, msg_type, window);
+        }
+        let _ = close_port(reply_port);
+
+        let result = result?;
+        match result.status {
+            CaptureStatus::Ok => self.read(result.width, result.height),
+            CaptureStatus::Denied => Err(SyscallError::PermissionDenied),
+            CaptureStatus::NoWindow | CaptureStatus::RegionTooSmall => {
+                Err(SyscallError::InvalidArgument)
+            }
+            CaptureStatus::Failed => Err(SyscallError::OutOfMemory),
+        }
+    }
+
+    fn request(
+        &self,
+        reply_port: PortId,
+        msg_type: MessageType,
+        window: WindowId,
+    ) -> SyscallResult<CaptureResult> {
+        let (region_id, len) = self.region.unwrap_or((0, 0));
+        let request = CaptureRequest {
+            reply_port,
+            window_id: window,
+            region_id,
+            region_len: len as u32,
+        };
+        send_message(self.compositor, msg_type, &request.to_bytes())?;
+
+        let mut buffer = [0u8; 64];
+        let (header, len) = recv_message(reply_port, &mut buffer)?;
+        if header.msg_type != MessageType::CaptureResult {
+            return Err(SyscallError::InvalidArgument);
+        }
+        CaptureResult::from_bytes(get_payload(&buffer, len)).ok_or(SyscallError::InvalidArgument)
+    }
+
+    fn replace_region(&mut self, len: usize) -> SyscallResult<()> {
+        if let Some((region, _)) = self.region.take() {
+            let _ = shm::destroy_region(region);
+        }
+        let region = shm::create_region(len)?;
+        self.region = Some((region, len));
+        Ok(())
+    }
+
+    fn read(&self, width: u32, height: u32) -> SyscallResult<Capture> {
+        let (region, _) = self.region.ok_or(SyscallError::InvalidArgument)?;
+        let count = width as usize * height as usize;
+
+        let base = shm::map_anywhere(region, RegionFlags::read_only())?;
+        let pixels = unsafe { core::slice::from_raw_parts(base as *const u32, count) }.to_vec();
+        let _ = shm::unmap_region(region);
+
+        Ok(Capture { width, height, pixels })
+    }
+}
+
+impl Drop for ScreenCapture {
+    fn drop(&mut self) {
+        if let Some((region, _)) = self.region.take() {
+            let _ = shm::destroy_region(region);
+        }
+    }
+}
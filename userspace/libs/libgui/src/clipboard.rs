@@ -0,0 +1,123 @@
+//! Clipboard Access
+//!
+//! Copy and paste through the desktop compositor, which keeps the
+//! clipboard. Text up to `CLIPBOARD_INLINE_MAX` bytes travels inside the
+//! messages; longer text goes through a shared region.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use atom_syscall::ipc::{close_port, create_port, PortId};
+use atom_syscall::shm::{self, RegionFlags, RegionId};
+use atom_syscall::{SyscallError, SyscallResult};
+use libipc::messages::{
+    ClipboardContent, ClipboardData, ClipboardMime, ClipboardRequest, MessageType,
+    CLIPBOARD_INLINE_MAX, MAX_CLIPBOARD_BYTES,
+};
+use libipc::protocol::{get_payload, recv_message, send_message};
+use libipc::MAX_MESSAGE_SIZE;
+
+pub struct Clipboard {
+    compositor: PortId,
+    /// Region holding the last long text this application copied
+    region: Option<RegionId>,
+}
+
+impl Clipboard {
+    pub fn new(compositor: PortId) -> Self {
+        Self {
+            compositor,
+            region: None,
+        }
+    }
+
+    /// Put text on the clipboard; `owner` gets `ClipboardChanged` once the
+    /// compositor has it and again when another application replaces it
+    pub fn set_text(&mut self, owner: PortId, text: &str) -> SyscallResult<()> {
+        // The compositor copied the previous text as soon as it arrived
+        if let Some(region) = self.region.take() {
+            let _ = shm::destroy_region(region);
+        }
+
+        let (content, region) = text_content(text)?;
+        self.region = region;
+
+        let data = ClipboardData {
+            port: owner,
+            mime: ClipboardMime::TextPlain,
+            content,
+        };
+        send_message(self.compositor, MessageType::SetClipboard, &data.to_bytes())
+    }
+
+    /// Text on the clipboard, empty if there is none
+    pub fn text(&self) -> SyscallResult<String> {
+        let reply_port = create_port()?;
+        let result = self.request_text(reply_port);
+        let _ = close_port(reply_port);
+        result
+    }
+
+    fn request_text(&self, reply_port: PortId) -> SyscallResult<String> {
+        let request = ClipboardRequest {
+            reply_port,
+            mime: ClipboardMime::TextPlain,
+        };
+        send_message(self.compositor, MessageType::GetClipboard, &request.to_bytes())?;
+
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        let (header, len) = recv_message(reply_port, &mut buffer)?;
+        if header.msg_type != MessageType::ClipboardData {
+            return Err(SyscallError::InvalidArgument);
+        }
+        let data = ClipboardData::from_bytes(get_payload(&buffer, len))
+            .ok_or(SyscallError::InvalidArgument)?;
+
+        read_text(&data.content)
+    }
+}
+
+/// Describe `text` for sending, copying it into a new region when it is
+/// too long to travel inline; the region is returned for the caller to
+/// destroy once the receiver has copied it
+pub(crate) fn text_content(text: &str) -> SyscallResult<(ClipboardContent, Option<RegionId>)> {
+    let bytes = text.as_bytes();
+    if bytes.len() <= CLIPBOARD_INLINE_MAX {
+        return Ok((ClipboardContent::Inline(Vec::from(bytes)), None));
+    }
+    if bytes.len() > MAX_CLIPBOARD_BYTES {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let region = shm::create_region(bytes.len())?;
+    let base = match shm::map_anywhere(region, RegionFlags::read_write()) {
+        Ok(base) => base,
+        Err(e) => {
+            let _ = shm::destroy_region(region);
+            return Err(e);
+        }
+    };
+    unsafe {
+        core::ptr::copy_nonoverlapping(bytes.as_ptr(), base, bytes.len());
+    }
+    let _ = shm::unmap_region(region);
+
+    let content = ClipboardContent::Shared { region_id: region, len: bytes.len() as u32 };
+    Ok((content, Some(region)))
+}
+
+/// Text carried by received content
+pub(crate) fn read_text(content: &ClipboardContent) -> SyscallResult<String> {
+    let bytes = match content {
+        ClipboardContent::Inline(bytes) => bytes.clone(),
+        ClipboardContent::Shared { region_id, len } => {
+            let len = (*len as usize).min(MAX_CLIPBOARD_BYTES);
+            let base = shm::map_anywhere(*region_id, RegionFlags::read_only())?;
+            let bytes = unsafe { core::slice::from_raw_parts(base as *const u8, len) }.to_vec();
+            let _ = shm::unmap_region(*region_id);
+            bytes
+        }
+    };
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
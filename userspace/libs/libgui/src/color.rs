@@ -50,6 +50,11 @@ impl Color {
         }
     }
 
+    /// Create an opaque color from a 0xRRGGBB value, as theme colors are
+    pub const fn from_rgb32(value: u32) -> Self {
+        Self::rgb((value >> 16) as u8, (value >> 8) as u8, value as u8)
+    }
+
     /// Convert to 32-bit value for framebuffer (BGR format, common for UEFI)
     pub fn to_bgr32(&self) -> u32 {
         ((self.b as u32) << 16) | ((self.g as u32) << 8) | (self.r as u32)
@@ -80,6 +85,25 @@ impl Color {
         }
     }
 
+    /// Mix with `other` in every channel, alpha too; `amount` 0 keeps this
+    /// color and 255 gives `other`
+    pub fn mix(&self, other: Color, amount: u8) -> Color {
+        let (keep, take) = (255 - amount as u32, amount as u32);
+        let channel = |a: u8, b: u8| ((a as u32 * keep + b as u32 * take) / 255) as u8;
+        Color {
+            r: channel(self.r, other.r),
+            g: channel(self.g, other.g),
+            b: channel(self.b, other.b),
+            a: channel(self.a, other.a),
+        }
+    }
+
+    /// The color covering only `coverage` / 255 of a pixel, for the edges
+    /// of anti-aliased shapes
+    pub fn with_coverage(&self, coverage: u8) -> Color {
+        Color { a: (self.a as u32 * coverage as u32 / 255) as u8, ..*self }
+    }
+
     /// Darken the color by a factor (0.0 = black, 1.0 = unchanged)
     pub fn darken(&self, factor: f32) -> Color {
         Color {
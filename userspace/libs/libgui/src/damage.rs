@@ -0,0 +1,73 @@
+//! Damage Tracking
+//!
+//! Parts of a surface drawn since the last frame was presented, so only
+//! those are copied to the compositor and recomposed. Overlapping
+//! rectangles are merged as they arrive; past `MAX_RECTS` everything
+//! collapses into a single bounding rectangle, like the compositor's own
+//! damage list.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use libipc::messages::Rect;
+
+const MAX_RECTS: usize = 8;
+
+pub struct Damage {
+    bounds: Rect,
+    rects: Vec<Rect>,
+}
+
+impl Damage {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { bounds: Rect::new(0, 0, width, height), rects: Vec::new() }
+    }
+
+    /// Track a surface of a new size, which is all damaged
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.bounds = Rect::new(0, 0, width, height);
+        self.rects.clear();
+        self.add_all();
+    }
+
+    /// Mark an area (in surface coordinates) as drawn
+    pub fn add(&mut self, rect: Rect) {
+        let Some(mut rect) = rect.intersection(&self.bounds) else {
+            return;
+        };
+        // Most pixels drawn one at a time are inside what their shape
+        // marked already
+        if self.rects.iter().any(|r| r.intersection(&rect) == Some(rect)) {
+            return;
+        }
+
+        while let Some(pos) = self.rects.iter().position(|r| r.intersects(&rect)) {
+            rect = rect.union(&self.rects.swap_remove(pos));
+        }
+        self.rects.push(rect);
+
+        if self.rects.len() > MAX_RECTS {
+            let bounds = self.rects.iter().fold(rect, |acc, r| acc.union(r));
+            self.rects.clear();
+            self.rects.push(bounds);
+        }
+    }
+
+    pub fn add_all(&mut self) {
+        self.add(self.bounds);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    /// Damaged areas since the last call, leaving the tracker empty
+    pub fn take(&mut self) -> Vec<Rect> {
+        core::mem::take(&mut self.rects)
+    }
+}
@@ -0,0 +1,174 @@
+//! Standard Dialogs
+//!
+//! Message boxes, OK/Cancel questions and one-line text prompts, built
+//! from the widget toolkit so they look alike in every application. Each
+//! opens a modal window transient for a parent window, which the desktop
+//! keeps above the parent and gives the parent's input to until the
+//! dialog closes; the call returns then with the user's answer.
+//!
+//! ```ignore
+//! if dialog::confirm(&app, &window, "Quit", "Discard unsaved changes?")? {
+//!     window.close();
+//! }
+//! ```
+//!
+//! A dialog has an event port of its own, so the parent's events wait
+//! there rather than being lost while the dialog is up. Enter picks the
+//! focused button, or OK from the text prompt; Escape and closing the
+//! window cancel.
+
+extern crate alloc;
+
+use alloc::string::String;
+
+use atom_syscall::SyscallResult;
+use libipc::messages::{Rect, WindowLayer, WindowRole};
+
+use crate::application::Application;
+use crate::event::Event;
+use crate::surface::Surface;
+use crate::widget::{keys, Action, Button, Flex, Label, TextInput, Ui, WidgetId};
+use crate::window::{Handler, Window};
+
+/// Pixels between the dialog's edges and its contents
+const PADDING: u32 = 12;
+
+/// Pixels between the lines of text, the input and the buttons
+const SPACING: u32 = 8;
+
+/// Narrowest dialog, so short messages still get room for their buttons
+const MIN_WIDTH: u32 = 240;
+
+/// Show `text` with an OK button
+pub fn message(app: &Application, parent: &Window, title: &str, text: &str) -> SyscallResult<()> {
+    show(app, parent, title, text, Kind::Message).map(|_| ())
+}
+
+/// Ask `text` with OK and Cancel buttons; true if OK was chosen
+pub fn confirm(app: &Application, parent: &Window, title: &str, text: &str) -> SyscallResult<bool> {
+    show(app, parent, title, text, Kind::Confirm).map(|answer| answer.is_some())
+}
+
+/// Ask for a line of text under `text`, starting from `initial`; `None`
+/// if cancelled
+pub fn prompt(
+    app: &Application,
+    parent: &Window,
+    title: &str,
+    text: &str,
+    initial: &str,
+) -> SyscallResult<Option<String>> {
+    show(app, parent, title, text, Kind::Prompt(initial))
+}
+
+#[derive(Clone, Copy)]
+enum Kind<'a> {
+    Message,
+    Confirm,
+    /// With the text the input starts with
+    Prompt(&'a str),
+}
+
+/// Run a dialog to the end; `Some` with the input's text, empty without
+/// one, if it was accepted
+fn show(
+    app: &Application,
+    parent: &Window,
+    title: &str,
+    text: &str,
+    kind: Kind<'_>,
+) -> SyscallResult<Option<String>> {
+    let mut dialog = Dialog::new(app, text, kind);
+    let size = dialog.ui.preferred_size();
+
+    let mut app = app.for_dialog(title)?;
+    let result = Window::open(&mut app, size.width.max(MIN_WIDTH), size.height).map(|mut window| {
+        let (width, height) = window.size();
+        dialog.ui.resize(width, height);
+        // Without modality it is still a working dialog, only not one
+        // that keeps the parent's input
+        let role = WindowRole {
+            window_id: window.id(),
+            parent: parent.id(),
+            modal: true,
+            layer: WindowLayer::Normal,
+        };
+        let _ = app.set_window_role(role);
+        app.run(&mut window, &mut dialog);
+        dialog.answer
+    });
+    app.close_event_port();
+    result
+}
+
+struct Dialog {
+    ui: Ui,
+    ok: WidgetId,
+    input: Option<WidgetId>,
+    /// Set when the dialog is accepted
+    answer: Option<String>,
+}
+
+impl Dialog {
+    fn new(app: &Application, text: &str, kind: Kind<'_>) -> Self {
+        let mut ui = Ui::new(Flex::column().with_padding(PADDING).with_spacing(SPACING), 0, 0);
+        ui.set_theme(app.theme());
+        let root = ui.root();
+        for line in text.lines() {
+            ui.add(root, Label::new(line));
+        }
+
+        let input = match kind {
+            Kind::Prompt(initial) => {
+                let input = ui.add(root, TextInput::new(""));
+                if let Some(widget) = ui.widget_mut::<TextInput>(input) {
+                    widget.set_text(initial);
+                }
+                Some(input)
+            }
+            _ => None,
+        };
+
+        // Buttons sit at the right, OK last
+        let buttons = ui.add(root, Flex::row().with_spacing(SPACING));
+        let spacer = ui.add(buttons, Label::new(""));
+        ui.set_flex(spacer, 1);
+        if !matches!(kind, Kind::Message) {
+            ui.add(buttons, Button::new("Cancel"));
+        }
+        let ok = ui.add(buttons, Button::new("OK"));
+
+        ui.set_focus(Some(input.unwrap_or(ok)));
+        Self { ui, ok, input, answer: None }
+    }
+
+    fn accept(&mut self, window: &mut Window) {
+        let input = self.input.and_then(|input| self.ui.widget::<TextInput>(input));
+        self.answer = Some(input.map_or_else(String::new, |input| String::from(input.text())));
+        window.close();
+    }
+}
+
+impl Handler for Dialog {
+    fn draw(&mut self, surface: &mut Surface, _damage: Rect) -> Option<Rect> {
+        self.ui.paint(surface)
+    }
+
+    fn event(&mut self, _app: &mut Application, window: &mut Window, event: &Event) {
+        if let Event::Key(key) = event {
+            if key.pressed && key.scancode == keys::ESCAPE {
+                window.close();
+                return;
+            }
+        }
+        match self.ui.handle_event(event) {
+            Some((id, Action::Clicked)) if id == self.ok => self.accept(window),
+            Some((id, Action::Submitted)) if Some(id) == self.input => self.accept(window),
+            // The only other button is Cancel
+            Some((_, Action::Clicked)) => window.close(),
+            _ => {}
+        }
+        // The widgets know what changed; they only draw that
+        window.invalidate_all();
+    }
+}
@@ -2,6 +2,11 @@
 //!
 //! Provides event types for input handling in applications.
 
+extern crate alloc;
+
+use alloc::string::String;
+use libipc::messages::{ScaleFactor, ThemeSpec, WindowId};
+
 /// Key event from keyboard
 #[derive(Debug, Clone, Copy)]
 pub struct KeyEvent {
@@ -125,8 +130,31 @@ pub enum WindowEvent {
     Close,
     /// Area needs redraw
     Expose { x: i32, y: i32, width: u32, height: u32 },
+    /// The pointer moved onto the window, at (x, y) in it
+    PointerEnter { x: i32, y: i32 },
+    /// The pointer left the window
+    PointerLeave,
+}
+
+/// Drag-and-drop events, positions relative to the client area
+#[derive(Debug, Clone)]
+pub enum DragEvent {
+    /// A drag entered the window
+    Enter { x: i32, y: i32 },
+    /// A drag moved within the window
+    Motion { x: i32, y: i32 },
+    /// A drag left the window without dropping
+    Leave,
+    /// Text was dropped on the window
+    Drop { x: i32, y: i32, text: String },
+    /// A drag this application started has ended
+    End { dropped: bool },
 }
 
+/// Timer set with `Application::set_timer` or `set_timeout`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimerId(pub(crate) u32);
+
 /// All possible events an application can receive
 #[derive(Debug, Clone)]
 pub enum Event {
@@ -136,6 +164,21 @@ pub enum Event {
     Mouse(MouseEvent),
     /// Window event
     Window(WindowEvent),
+    /// Drag-and-drop event
+    Drag(DragEvent),
+    /// The desktop theme changed; re-skin and redraw
+    ThemeChanged(ThemeSpec),
+    /// The output scale changed; windows made with a native surface keep
+    /// it and are stretched until reopened
+    ScaleChanged(ScaleFactor),
+    /// The last frame `window` presented is on screen; draw the next one
+    FrameDone { window: WindowId, time_ms: u64 },
+    /// Timer `id` went off at `time_ms`; a repeating timer that fell
+    /// behind goes off once for all the intervals it missed
+    Timer { id: TimerId, time_ms: u64 },
+    /// The compositor exited; windows are gone from the screen until
+    /// `Application::reattach` hands them to a new one
+    CompositorLost,
     /// Application should redraw
     Redraw,
     /// Application should quit
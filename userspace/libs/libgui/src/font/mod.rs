@@ -1,6 +1,28 @@
 //! Font Rendering
 //!
-//! Provides a simple 8x8 bitmap font for text rendering.
+//! Provides a simple 8x8 bitmap font for text rendering, and `Font` for
+//! other typefaces and sizes:
+//! - the built-in font scaled up by a whole factor
+//! - bitmap fonts in the PC Screen Font format (PSF1 and PSF2), such as
+//!   console fonts, embedded with `include_bytes!`
+//! - TrueType outlines, rasterized with anti-aliasing at any pixel size
+//!
+//! `Font::measure` and `Font::fit` size text for layout; `Surface::draw_text`
+//! draws it. Glyphs are placed by their advance alone, without kerning.
+
+extern crate alloc;
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+mod psf;
+mod raster;
+mod truetype;
+
+pub use psf::BitmapFont;
+pub use truetype::TrueTypeFont;
 
 /// Font character dimensions
 pub const FONT_WIDTH: u32 = 8;
@@ -127,3 +149,172 @@ pub fn text_width(text: &str) -> u32 {
 pub fn text_height(_text: &str) -> u32 {
     FONT_HEIGHT
 }
+
+/// A character ready to draw: how much of each pixel it covers
+#[derive(Debug, Clone)]
+pub struct Glyph {
+    pub width: u32,
+    pub height: u32,
+    /// Pixels from the pen position to the glyph's left column
+    pub left: i32,
+    /// Pixels from the baseline up to the glyph's top row
+    pub top: i32,
+    /// Pixels the pen moves on by after the glyph
+    pub advance: u32,
+    /// Coverage row by row, a byte per pixel; 255 covers it fully
+    pub coverage: Vec<u8>,
+}
+
+impl Glyph {
+    /// A glyph from rows of bits, most significant bit leftmost, with its
+    /// top row on the line's top
+    fn from_bits(rows: impl Iterator<Item = u32>, width: u32, height: u32, scale: u32) -> Self {
+        let (scaled_width, scaled_height) = (width * scale, height * scale);
+        let mut coverage = vec![0u8; (scaled_width * scaled_height) as usize];
+        for (row, bits) in rows.take(height as usize).enumerate() {
+            for col in 0..width {
+                if bits & (1 << (width - 1 - col)) == 0 {
+                    continue;
+                }
+                for y in row as u32 * scale..(row as u32 + 1) * scale {
+                    let start = (y * scaled_width + col * scale) as usize;
+                    coverage[start..start + scale as usize].fill(255);
+                }
+            }
+        }
+        Self {
+            width: scaled_width,
+            height: scaled_height,
+            left: 0,
+            top: scaled_height as i32,
+            advance: scaled_width,
+            coverage,
+        }
+    }
+}
+
+/// Size of a line of text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TextMetrics {
+    pub width: u32,
+    /// Pixels from the top of the line to the baseline
+    pub ascent: u32,
+    /// Pixels from the baseline to the bottom of the line
+    pub descent: u32,
+}
+
+impl TextMetrics {
+    pub fn height(&self) -> u32 {
+        self.ascent + self.descent
+    }
+}
+
+enum Face {
+    Builtin { scale: u32 },
+    Bitmap(BitmapFont),
+    TrueType { font: TrueTypeFont, size: u32 },
+}
+
+/// A typeface at one size
+pub struct Font {
+    face: Face,
+    /// Glyphs drawn so far; outlines are slow to rasterize again
+    cache: RefCell<BTreeMap<char, Glyph>>,
+}
+
+impl Font {
+    /// The built-in 8x8 font, `scale` times as large
+    pub fn builtin(scale: u32) -> Self {
+        Self::new(Face::Builtin { scale: scale.max(1) })
+    }
+
+    pub fn bitmap(font: BitmapFont) -> Self {
+        Self::new(Face::Bitmap(font))
+    }
+
+    /// `font` with its em `size` pixels tall
+    pub fn truetype(font: TrueTypeFont, size: u32) -> Self {
+        Self::new(Face::TrueType { font, size: size.max(1) })
+    }
+
+    fn new(face: Face) -> Self {
+        Self { face, cache: RefCell::new(BTreeMap::new()) }
+    }
+
+    /// Pixels from the top of a line to the baseline
+    pub fn ascent(&self) -> u32 {
+        match &self.face {
+            Face::Builtin { scale } => FONT_HEIGHT * scale,
+            Face::Bitmap(font) => font.height(),
+            Face::TrueType { font, size } => font.ascent(*size),
+        }
+    }
+
+    /// Pixels from the baseline to the bottom of a line
+    pub fn descent(&self) -> u32 {
+        match &self.face {
+            Face::Builtin { .. } | Face::Bitmap(_) => 0,
+            Face::TrueType { font, size } => font.descent(*size),
+        }
+    }
+
+    /// Pixels from one baseline to the next
+    pub fn line_height(&self) -> u32 {
+        match &self.face {
+            Face::TrueType { font, size } => font.line_height(*size),
+            _ => self.ascent() + self.descent(),
+        }
+    }
+
+    /// Pixels the pen moves on by after `ch`
+    pub fn advance(&self, ch: char) -> u32 {
+        match &self.face {
+            Face::Builtin { scale } => FONT_WIDTH * scale,
+            Face::Bitmap(font) => font.width(),
+            Face::TrueType { .. } => self.with_glyph(ch, |glyph| glyph.advance),
+        }
+    }
+
+    /// Width of `text` on one line
+    pub fn width(&self, text: &str) -> u32 {
+        text.chars().map(|ch| self.advance(ch)).sum()
+    }
+
+    pub fn measure(&self, text: &str) -> TextMetrics {
+        TextMetrics { width: self.width(text), ascent: self.ascent(), descent: self.descent() }
+    }
+
+    /// Length in bytes of the longest start of `text` no wider than
+    /// `width`, for cutting labels and titles short
+    pub fn fit(&self, text: &str, width: u32) -> usize {
+        let mut used = 0;
+        for (index, ch) in text.char_indices() {
+            used += self.advance(ch);
+            if used > width {
+                return index;
+            }
+        }
+        text.len()
+    }
+
+    /// Call `f` with the glyph for `ch`
+    pub fn with_glyph<R>(&self, ch: char, f: impl FnOnce(&Glyph) -> R) -> R {
+        let mut cache = self.cache.borrow_mut();
+        let glyph = cache.entry(ch).or_insert_with(|| self.render(ch));
+        f(glyph)
+    }
+
+    fn render(&self, ch: char) -> Glyph {
+        match &self.face {
+            Face::Builtin { scale } => {
+                let code = if ch.is_ascii() { ch as u8 } else { b'?' };
+                let rows = get_glyph(code).into_iter().map(u32::from);
+                Glyph::from_bits(rows, FONT_WIDTH, FONT_HEIGHT, *scale)
+            }
+            Face::Bitmap(font) => {
+                Glyph::from_bits(font.rows(ch), font.width(), font.height(), 1)
+            }
+            Face::TrueType { font, size } => font.glyph(ch, *size),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! PC Screen Fonts
+//!
+//! The bitmap format of console fonts, in both versions: PSF1, 8 pixels
+//! wide with 256 or 512 glyphs, and PSF2, any size up to 32 pixels wide.
+//! Glyphs are looked up by character code; a Unicode table, if the font
+//! has one, is not used.
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_MODE_512: u8 = 0x01;
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+
+/// A bitmap font embedded in the program
+#[derive(Debug, Clone, Copy)]
+pub struct BitmapFont {
+    /// The glyphs, one after the other
+    glyphs: &'static [u8],
+    count: u32,
+    bytes_per_glyph: u32,
+    width: u32,
+    height: u32,
+}
+
+impl BitmapFont {
+    /// Read a PSF1 or PSF2 font, such as one from `include_bytes!`;
+    /// `None` if it is in neither format or cut short
+    pub fn parse(data: &'static [u8]) -> Option<Self> {
+        if data.starts_with(&PSF1_MAGIC) {
+            let mode = *data.get(2)?;
+            let height = *data.get(3)? as u32;
+            let count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+            return Self::new(data.get(4..)?, count, height, 8, height);
+        }
+        if data.starts_with(&PSF2_MAGIC) {
+            let field = |index: usize| -> Option<u32> {
+                let bytes = data.get(index * 4..index * 4 + 4)?;
+                Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            };
+            let header = field(2)? as usize;
+            let (count, bytes_per_glyph) = (field(4)?, field(5)?);
+            let (height, width) = (field(6)?, field(7)?);
+            return Self::new(data.get(header..)?, count, bytes_per_glyph, width, height);
+        }
+        None
+    }
+
+    fn new(
+        glyphs: &'static [u8],
+        count: u32,
+        bytes_per_glyph: u32,
+        width: u32,
+        height: u32,
+    ) -> Option<Self> {
+        let row_bytes = width.div_ceil(8);
+        let fits = width > 0 && width <= 32 && height > 0 && row_bytes * height <= bytes_per_glyph;
+        if !fits || glyphs.len() < (count as usize).checked_mul(bytes_per_glyph as usize)? {
+            return None;
+        }
+        Some(Self { glyphs, count, bytes_per_glyph, width, height })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Rows of the glyph for `ch`, the leftmost pixel in bit `width - 1`;
+    /// characters the font lacks get its `?`
+    pub fn rows(&self, ch: char) -> impl Iterator<Item = u32> + '_ {
+        let index = if (ch as u32) < self.count { ch as u32 } else { b'?' as u32 };
+        let start = (index * self.bytes_per_glyph) as usize;
+        let row_bytes = self.width.div_ceil(8) as usize;
+        let unused = row_bytes as u32 * 8 - self.width;
+        self.glyphs[start..start + row_bytes * self.height as usize]
+            .chunks(row_bytes)
+            .map(move |row| row.iter().fold(0u32, |bits, &byte| bits << 8 | byte as u32) >> unused)
+    }
+}
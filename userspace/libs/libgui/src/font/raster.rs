@@ -0,0 +1,180 @@
+//! Outline Rasterizer
+//!
+//! Fills the contours of a glyph into a coverage map. Coordinates are in
+//! 26.6 fixed point, pixels with six bits of fraction, with y growing
+//! downwards. Curves are flattened into lines; each pixel row is sampled
+//! on four lines, and across a line a pixel's coverage is the part of its
+//! width inside the outline by the non-zero winding rule.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Sample lines per pixel row
+const SAMPLES: i32 = 4;
+
+/// Most lines a curve is flattened into
+const MAX_STEPS: i32 = 16;
+
+/// A point of a contour, in 26.6 fixed point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+    /// Whether the outline passes through the point; two points off the
+    /// curve in a row have an implied one halfway between them
+    pub on_curve: bool,
+}
+
+/// An edge of the outline, from top to bottom
+struct Edge {
+    top: (i32, i32),
+    bottom: (i32, i32),
+    /// +1 where the contour runs down, -1 where it runs up
+    winding: i32,
+}
+
+/// Outlines collected for a `width` x `height` coverage map
+pub struct Rasterizer {
+    width: u32,
+    height: u32,
+    edges: Vec<Edge>,
+}
+
+impl Rasterizer {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, edges: Vec::new() }
+    }
+
+    /// Add a closed contour of quadratic curves
+    pub fn add_contour(&mut self, points: &[Point]) {
+        let Some((first, start)) = Self::start(points) else {
+            return;
+        };
+        let mut pen = start;
+        let mut control: Option<(i32, i32)> = None;
+        // Walk round the contour from the start, ending back on it
+        for step in 1..=points.len() {
+            let point = &points[(first + step) % points.len()];
+            let here = (point.x, point.y);
+            match (control, point.on_curve) {
+                (None, true) => {
+                    self.line(pen, here);
+                    pen = here;
+                }
+                (None, false) => control = Some(here),
+                (Some(ctrl), true) => {
+                    self.curve(pen, ctrl, here);
+                    pen = here;
+                    control = None;
+                }
+                (Some(ctrl), false) => {
+                    let middle = ((ctrl.0 + here.0) / 2, (ctrl.1 + here.1) / 2);
+                    self.curve(pen, ctrl, middle);
+                    pen = middle;
+                    control = Some(here);
+                }
+            }
+        }
+        match control {
+            Some(ctrl) => self.curve(pen, ctrl, start),
+            None => self.line(pen, start),
+        }
+    }
+
+    /// A point the contour passes through and the index of the point it
+    /// comes before or after: one on the curve, or else the implied one
+    /// between the first two
+    fn start(points: &[Point]) -> Option<(usize, (i32, i32))> {
+        if let Some(index) = points.iter().position(|point| point.on_curve) {
+            return Some((index, (points[index].x, points[index].y)));
+        }
+        let first = points.first()?;
+        let second = points.get(1).unwrap_or(first);
+        Some((0, ((first.x + second.x) / 2, (first.y + second.y) / 2)))
+    }
+
+    fn line(&mut self, from: (i32, i32), to: (i32, i32)) {
+        if from.1 == to.1 {
+            return;
+        }
+        self.edges.push(if from.1 < to.1 {
+            Edge { top: from, bottom: to, winding: 1 }
+        } else {
+            Edge { top: to, bottom: from, winding: -1 }
+        });
+    }
+
+    /// A quadratic curve from `from` to `to` pulled towards `control`
+    fn curve(&mut self, from: (i32, i32), control: (i32, i32), to: (i32, i32)) {
+        // How far the curve bends away from the straight line, which sets
+        // how many lines it takes to follow it to within a fraction of a
+        // pixel
+        let bend = (from.0 - 2 * control.0 + to.0).abs() + (from.1 - 2 * control.1 + to.1).abs();
+        let steps = (1 + bend / 32).min(MAX_STEPS) as i64;
+        let mut pen = from;
+        for step in 1..=steps {
+            let t = step;
+            let u = steps - t;
+            let at = |a: i32, b: i32, c: i32| {
+                ((u * u * a as i64 + 2 * u * t * b as i64 + t * t * c as i64) / (steps * steps))
+                    as i32
+            };
+            let next = (at(from.0, control.0, to.0), at(from.1, control.1, to.1));
+            self.line(pen, next);
+            pen = next;
+        }
+    }
+
+    /// Coverage of each pixel, row by row, 255 for fully inside
+    pub fn finish(self) -> Vec<u8> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut coverage = vec![0u8; width * height];
+        let mut row = vec![0u16; width];
+        let mut crossings: Vec<(i32, i32)> = Vec::new();
+        let limit = self.width as i32 * 64;
+
+        for y in 0..height {
+            row.fill(0);
+            for sample in 0..SAMPLES {
+                let line = y as i32 * 64 + (2 * sample + 1) * 32 / SAMPLES;
+                crossings.clear();
+                for edge in &self.edges {
+                    if line < edge.top.1 || line >= edge.bottom.1 {
+                        continue;
+                    }
+                    let (dx, dy) = (edge.bottom.0 - edge.top.0, edge.bottom.1 - edge.top.1);
+                    let x = edge.top.0 as i64 + (line - edge.top.1) as i64 * dx as i64 / dy as i64;
+                    crossings.push((x as i32, edge.winding));
+                }
+                crossings.sort_unstable_by_key(|&(x, _)| x);
+
+                let mut winding = 0;
+                for pair in crossings.windows(2) {
+                    winding += pair[0].1;
+                    if winding != 0 {
+                        Self::span(&mut row, pair[0].0.clamp(0, limit), pair[1].0.clamp(0, limit));
+                    }
+                }
+            }
+            for (pixel, &sum) in coverage[y * width..(y + 1) * width].iter_mut().zip(&row) {
+                *pixel = sum.min(255) as u8;
+            }
+        }
+        coverage
+    }
+
+    /// Add the part of each pixel between `from` and `to` on one sample
+    /// line, at most 64 for a pixel the span crosses entirely
+    fn span(row: &mut [u16], from: i32, to: i32) {
+        if from >= to {
+            return;
+        }
+        for pixel in (from >> 6)..=((to - 1) >> 6) {
+            let left = from.max(pixel * 64);
+            let right = to.min(pixel * 64 + 64);
+            row[pixel as usize] += (right - left) as u16;
+        }
+    }
+}
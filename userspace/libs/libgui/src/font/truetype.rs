@@ -0,0 +1,374 @@
+//! TrueType Fonts
+//!
+//! Reads the tables of a TrueType font that drawing text needs: `cmap` to
+//! find a character's glyph, `glyf` and `loca` for its outline, `hmtx` for
+//! its advance and `head` and `hhea` for the font's size and line metrics.
+//! Outlines are scaled to 26.6 fixed point and filled by the rasterizer;
+//! hinting instructions and kerning are not used. Every read is checked
+//! against the end of the font, so a broken font draws boxes rather than
+//! faulting.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::raster::{Point, Rasterizer};
+use super::Glyph;
+
+/// How deep compound glyphs may nest
+const MAX_DEPTH: u32 = 4;
+
+// Flags of a point of a simple glyph
+const ON_CURVE: u8 = 0x01;
+const X_SHORT: u8 = 0x02;
+const Y_SHORT: u8 = 0x04;
+const REPEAT: u8 = 0x08;
+const X_SAME_OR_POSITIVE: u8 = 0x10;
+const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+// Flags of a component of a compound glyph
+const ARGS_ARE_WORDS: u16 = 0x0001;
+const ARGS_ARE_OFFSET: u16 = 0x0002;
+const HAVE_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const HAVE_X_AND_Y_SCALE: u16 = 0x0040;
+const HAVE_TWO_BY_TWO: u16 = 0x0080;
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn i16_at(data: &[u8], offset: usize) -> Option<i16> {
+    u16_at(data, offset).map(|value| value as i16)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// A contour in font units, y growing upwards
+type Contour = Vec<(i32, i32, bool)>;
+
+/// A TrueType font embedded in the program
+#[derive(Debug, Clone, Copy)]
+pub struct TrueTypeFont {
+    data: &'static [u8],
+    /// Offset of each table used
+    cmap: usize,
+    glyf: usize,
+    loca: usize,
+    hmtx: usize,
+    units_per_em: i32,
+    /// Whether `loca` holds 32-bit offsets rather than 16-bit halves
+    long_offsets: bool,
+    glyph_count: u16,
+    metric_count: u16,
+    ascender: i32,
+    descender: i32,
+    line_gap: i32,
+}
+
+impl TrueTypeFont {
+    /// Read a font, such as one from `include_bytes!`; `None` if it is not
+    /// a TrueType font or lacks a table that is needed
+    pub fn parse(data: &'static [u8]) -> Option<Self> {
+        let tables = u16_at(data, 4)? as usize;
+        let table = |tag: &[u8; 4]| -> Option<usize> {
+            (0..tables).map(|index| 12 + index * 16).find_map(|record| {
+                if data.get(record..record + 4)? != tag {
+                    return None;
+                }
+                let offset = u32_at(data, record + 8)? as usize;
+                (offset < data.len()).then_some(offset)
+            })
+        };
+        let (head, hhea, maxp) = (table(b"head")?, table(b"hhea")?, table(b"maxp")?);
+        let units_per_em = u16_at(data, head + 18)? as i32;
+        if units_per_em == 0 {
+            return None;
+        }
+        Some(Self {
+            data,
+            cmap: table(b"cmap")?,
+            glyf: table(b"glyf")?,
+            loca: table(b"loca")?,
+            hmtx: table(b"hmtx")?,
+            units_per_em,
+            long_offsets: i16_at(data, head + 50)? != 0,
+            glyph_count: u16_at(data, maxp + 4)?,
+            metric_count: u16_at(data, hhea + 34)?,
+            ascender: i16_at(data, hhea + 4)? as i32,
+            descender: i16_at(data, hhea + 6)? as i32,
+            line_gap: i16_at(data, hhea + 8)? as i32,
+        })
+    }
+
+    /// `units` of the font in 26.6 fixed point at `size` pixels to the em
+    fn scale(&self, units: i32, size: u32) -> i32 {
+        (units as i64 * size as i64 * 64 / self.units_per_em as i64) as i32
+    }
+
+    /// Whole pixels covering `units` at `size`
+    fn pixels(&self, units: i32, size: u32) -> u32 {
+        ((self.scale(units, size).max(0) + 63) >> 6) as u32
+    }
+
+    pub fn ascent(&self, size: u32) -> u32 {
+        self.pixels(self.ascender, size)
+    }
+
+    pub fn descent(&self, size: u32) -> u32 {
+        self.pixels(-self.descender, size)
+    }
+
+    pub fn line_height(&self, size: u32) -> u32 {
+        self.ascent(size) + self.descent(size) + self.pixels(self.line_gap, size)
+    }
+
+    /// The glyph for `ch` at `size` pixels to the em; characters the font
+    /// lacks get its missing glyph, usually a box
+    pub fn glyph(&self, ch: char, size: u32) -> Glyph {
+        let index = self.glyph_index(ch).unwrap_or(0);
+        let advance = (self.scale(self.advance_units(index).unwrap_or(0), size) + 32) >> 6;
+        let mut contours = Vec::new();
+        self.outline(index, &mut contours, 0);
+
+        let points = contours.iter().flatten();
+        let scaled = |&(x, y, _): &(i32, i32, bool)| (self.scale(x, size), self.scale(y, size));
+        let (mut left, mut bottom, mut right, mut top) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+        for (x, y) in points.map(scaled) {
+            (left, right) = (left.min(x), right.max(x));
+            (bottom, top) = (bottom.min(y), top.max(y));
+        }
+        if left > right {
+            let advance = advance.max(0) as u32;
+            return Glyph { width: 0, height: 0, left: 0, top: 0, advance, coverage: Vec::new() };
+        }
+
+        // Whole pixels around the outline, which is moved so their top
+        // left corner is at the origin and y grows downwards
+        let (left, top) = (left >> 6, (top + 63) >> 6);
+        let width = (((right + 63) >> 6) - left) as u32;
+        let height = (top - (bottom >> 6)) as u32;
+        let mut rasterizer = Rasterizer::new(width, height);
+        let mut placed = Vec::new();
+        for contour in &contours {
+            placed.clear();
+            placed.extend(contour.iter().map(|point| {
+                let (x, y) = scaled(point);
+                Point { x: x - left * 64, y: top * 64 - y, on_curve: point.2 }
+            }));
+            rasterizer.add_contour(&placed);
+        }
+        Glyph {
+            width,
+            height,
+            left,
+            top,
+            advance: advance.max(0) as u32,
+            coverage: rasterizer.finish(),
+        }
+    }
+
+    /// Index of the glyph for `ch`, from the Unicode subtable of `cmap`
+    fn glyph_index(&self, ch: char) -> Option<u16> {
+        let data = self.data;
+        let subtables = u16_at(data, self.cmap + 2)? as usize;
+        let mut best: Option<usize> = None;
+        for record in (0..subtables).map(|index| self.cmap + 4 + index * 8) {
+            let (platform, encoding) = (u16_at(data, record)?, u16_at(data, record + 2)?);
+            let unicode = platform == 0 || (platform == 3 && (encoding == 1 || encoding == 10));
+            if !unicode {
+                continue;
+            }
+            let subtable = self.cmap + u32_at(data, record + 4)? as usize;
+            // Only format 12 reaches beyond the Basic Multilingual Plane
+            match u16_at(data, subtable)? {
+                12 => best = Some(subtable),
+                4 if best.is_none() => best = Some(subtable),
+                _ => {}
+            }
+        }
+        let subtable = best?;
+        let glyph = match u16_at(data, subtable)? {
+            4 => self.format4(subtable, ch as u32)?,
+            _ => self.format12(subtable, ch as u32)?,
+        };
+        (glyph != 0 && glyph < self.glyph_count).then_some(glyph)
+    }
+
+    /// Look `code` up in a table of segments of 16-bit codes
+    fn format4(&self, subtable: usize, code: u32) -> Option<u16> {
+        let data = self.data;
+        let code = u16::try_from(code).ok()?;
+        let segments = u16_at(data, subtable + 6)? as usize / 2;
+        let ends = subtable + 14;
+        let starts = ends + 2 * segments + 2;
+        let deltas = starts + 2 * segments;
+        let ranges = deltas + 2 * segments;
+        let segment = (0..segments).find(|&index| {
+            u16_at(data, ends + 2 * index).is_some_and(|end| end >= code)
+        })?;
+        let start = u16_at(data, starts + 2 * segment)?;
+        if code < start {
+            return None;
+        }
+        let delta = u16_at(data, deltas + 2 * segment)?;
+        let range = ranges + 2 * segment;
+        let offset = u16_at(data, range)? as usize;
+        if offset == 0 {
+            return Some(code.wrapping_add(delta));
+        }
+        let glyph = u16_at(data, range + offset + 2 * (code - start) as usize)?;
+        Some(if glyph == 0 { 0 } else { glyph.wrapping_add(delta) })
+    }
+
+    /// Look `code` up in a table of ranges of 32-bit codes
+    fn format12(&self, subtable: usize, code: u32) -> Option<u16> {
+        let data = self.data;
+        let groups = u32_at(data, subtable + 12)? as usize;
+        (0..groups).map(|index| subtable + 16 + index * 12).find_map(|group| {
+            let (start, end) = (u32_at(data, group)?, u32_at(data, group + 4)?);
+            let first = u32_at(data, group + 8)?;
+            (start..=end).contains(&code).then(|| (first + code - start) as u16)
+        })
+    }
+
+    /// Advance width of glyph `index` in font units
+    fn advance_units(&self, index: u16) -> Option<i32> {
+        // Glyphs past the last metric share its advance
+        let metric = index.min(self.metric_count.checked_sub(1)?) as usize;
+        u16_at(self.data, self.hmtx + 4 * metric).map(i32::from)
+    }
+
+    /// Bytes of glyph `index` in `glyf`; empty for a glyph with no outline
+    fn glyph_data(&self, index: u16) -> Option<&'static [u8]> {
+        let index = index as usize;
+        let (start, end) = if self.long_offsets {
+            let at = |index: usize| u32_at(self.data, self.loca + 4 * index).map(|o| o as usize);
+            (at(index)?, at(index + 1)?)
+        } else {
+            let at = |index: usize| {
+                u16_at(self.data, self.loca + 2 * index).map(|o| 2 * o as usize)
+            };
+            (at(index)?, at(index + 1)?)
+        };
+        self.data.get(self.glyf + start..self.glyf + end.max(start))
+    }
+
+    /// Add the contours of glyph `index` to `contours`; a glyph that cannot
+    /// be read adds none
+    fn outline(&self, index: u16, contours: &mut Vec<Contour>, depth: u32) -> Option<()> {
+        let data = self.glyph_data(index)?;
+        if data.is_empty() {
+            return Some(());
+        }
+        let count = i16_at(data, 0)?;
+        if count >= 0 {
+            return simple(data, count as usize, contours);
+        }
+        if depth >= MAX_DEPTH {
+            return None;
+        }
+
+        let mut offset = 10;
+        loop {
+            let flags = u16_at(data, offset)?;
+            let component = u16_at(data, offset + 2)?;
+            offset += 4;
+            let (dx, dy) = if flags & ARGS_ARE_WORDS != 0 {
+                offset += 4;
+                (i16_at(data, offset - 4)? as i32, i16_at(data, offset - 2)? as i32)
+            } else {
+                offset += 2;
+                (*data.get(offset - 2)? as i8 as i32, *data.get(offset - 1)? as i8 as i32)
+            };
+            // Components placed by matching points are left where they are
+            let (dx, dy) = if flags & ARGS_ARE_OFFSET != 0 { (dx, dy) } else { (0, 0) };
+
+            // A 2x2 transform in 2.14 fixed point
+            let scale = |at: usize| i16_at(data, at).map(i32::from);
+            let mut transform = [1 << 14, 0, 0, 1 << 14];
+            if flags & HAVE_SCALE != 0 {
+                let value = scale(offset)?;
+                transform = [value, 0, 0, value];
+                offset += 2;
+            } else if flags & HAVE_X_AND_Y_SCALE != 0 {
+                transform = [scale(offset)?, 0, 0, scale(offset + 2)?];
+                offset += 4;
+            } else if flags & HAVE_TWO_BY_TWO != 0 {
+                let [xx, xy, yx, yy] = [0, 2, 4, 6].map(|at| scale(offset + at));
+                transform = [xx?, yx?, xy?, yy?];
+                offset += 8;
+            }
+
+            let first = contours.len();
+            self.outline(component, contours, depth + 1);
+            for (x, y, _) in contours[first..].iter_mut().flatten() {
+                let (px, py) = (*x as i64, *y as i64);
+                *x = ((px * transform[0] as i64 + py * transform[1] as i64) >> 14) as i32 + dx;
+                *y = ((px * transform[2] as i64 + py * transform[3] as i64) >> 14) as i32 + dy;
+            }
+            if flags & MORE_COMPONENTS == 0 {
+                return Some(());
+            }
+        }
+    }
+}
+
+/// Read the `count` contours of a simple glyph
+fn simple(data: &[u8], count: usize, contours: &mut Vec<Contour>) -> Option<()> {
+    let mut ends = Vec::with_capacity(count);
+    for index in 0..count {
+        ends.push(u16_at(data, 10 + 2 * index)? as usize);
+    }
+    let points = ends.last().map_or(0, |&last| last + 1);
+    let instructions = u16_at(data, 10 + 2 * count)? as usize;
+    let mut offset = 12 + 2 * count + instructions;
+
+    let mut flags = Vec::with_capacity(points);
+    while flags.len() < points {
+        let flag = *data.get(offset)?;
+        offset += 1;
+        let mut repeat = 1;
+        if flag & REPEAT != 0 {
+            repeat += *data.get(offset)? as usize;
+            offset += 1;
+        }
+        flags.extend(core::iter::repeat_n(flag, repeat.min(points - flags.len())));
+    }
+
+    // Coordinates are stored as deltas from the last point, first all the
+    // x values and then all the y values
+    let mut coordinate = |short: u8, same_or_positive: u8, values: &mut Vec<i32>| {
+        let mut value = 0i32;
+        for &flag in &flags {
+            if flag & short != 0 {
+                let delta = *data.get(offset)? as i32;
+                offset += 1;
+                value += if flag & same_or_positive != 0 { delta } else { -delta };
+            } else if flag & same_or_positive == 0 {
+                value += i16_at(data, offset)? as i32;
+                offset += 2;
+            }
+            values.push(value);
+        }
+        Some(())
+    };
+    let (mut xs, mut ys) = (Vec::with_capacity(points), Vec::with_capacity(points));
+    coordinate(X_SHORT, X_SAME_OR_POSITIVE, &mut xs)?;
+    coordinate(Y_SHORT, Y_SAME_OR_POSITIVE, &mut ys)?;
+
+    let mut start = 0;
+    for &end in &ends {
+        if end < start || end >= points {
+            return None;
+        }
+        let on_curve = |point: usize| flags[point] & ON_CURVE != 0;
+        contours.push((start..=end).map(|point| (xs[point], ys[point], on_curve(point))).collect());
+        start = end + 1;
+    }
+    Some(())
+}
@@ -0,0 +1,119 @@
+//! BMP Decoder
+//!
+//! Uncompressed Windows bitmaps: 1, 4 and 8 bits per pixel with a
+//! palette, and 16, 24 and 32 bits per pixel, with the channels where the
+//! header's bit masks put them when it has any. Alpha is only read from
+//! an explicit mask, since many 32-bit files leave that byte zero.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use super::{Image, MAX_SIDE};
+use crate::color::Color;
+
+const COMPRESSION_NONE: u32 = 0;
+const COMPRESSION_BITFIELDS: u32 = 3;
+
+/// Where a channel is in a pixel
+#[derive(Clone, Copy)]
+struct Mask {
+    shift: u32,
+    max: u32,
+}
+
+impl Mask {
+    fn new(mask: u32) -> Self {
+        if mask == 0 {
+            return Self { shift: 0, max: 0 };
+        }
+        let shift = mask.trailing_zeros();
+        Self { shift, max: mask >> shift }
+    }
+
+    /// The channel of `pixel` scaled to 0-255, or `missing` without one
+    fn get(self, pixel: u32, missing: u8) -> u8 {
+        if self.max == 0 {
+            return missing;
+        }
+        ((pixel >> self.shift & self.max) as u64 * 255 / self.max as u64) as u8
+    }
+}
+
+pub fn decode(file: &[u8]) -> Option<Image> {
+    let u16_at = |at: usize| Some(u16::from_le_bytes(file.get(at..at + 2)?.try_into().ok()?));
+    let u32_at = |at: usize| Some(u32::from_le_bytes(file.get(at..at + 4)?.try_into().ok()?));
+
+    if file.get(0..2)? != b"BM" {
+        return None;
+    }
+    let data_offset = u32_at(10)? as usize;
+    let header_size = u32_at(14)? as usize;
+    let width = u32_at(18)? as i32;
+    let height = u32_at(22)? as i32;
+    let bits = u16_at(28)?;
+    let compression = u32_at(30)?;
+
+    let bitfields = compression == COMPRESSION_BITFIELDS && matches!(bits, 16 | 32);
+    let supported = matches!(bits, 1 | 4 | 8 | 16 | 24 | 32);
+    if !supported || !(compression == COMPRESSION_NONE || bitfields) || width <= 0 || height == 0 {
+        return None;
+    }
+
+    // Positive heights are stored bottom row first
+    let bottom_up = height > 0;
+    let (width, height) = (width as u32, height.unsigned_abs());
+    if width > MAX_SIDE || height > MAX_SIDE {
+        return None;
+    }
+
+    // The masks follow a short header, or are part of a longer one
+    let [red, green, blue, alpha] = if bitfields {
+        let alpha = if header_size >= 56 { u32_at(66)? } else { 0 };
+        [u32_at(54)?, u32_at(58)?, u32_at(62)?, alpha].map(Mask::new)
+    } else if bits == 16 {
+        [0x7C00, 0x03E0, 0x001F, 0].map(Mask::new)
+    } else {
+        [0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0].map(Mask::new)
+    };
+
+    let palette = if bits <= 8 {
+        let used = u32_at(46)? as usize;
+        let count = if used == 0 || used > 1 << bits { 1 << bits } else { used };
+        let start = 14 + header_size;
+        let entries = file.get(start..start + 4 * count)?;
+        entries.chunks_exact(4).map(|entry| Color::rgb(entry[2], entry[1], entry[0])).collect()
+    } else {
+        Vec::new()
+    };
+
+    let row_len = (width as usize * bits as usize).div_ceil(32) * 4;
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height as usize {
+        let stored = if bottom_up { height as usize - 1 - y } else { y };
+        let start = data_offset + stored * row_len;
+        let row = file.get(start..start + row_len)?;
+        for x in 0..width as usize {
+            let color = match bits {
+                1 | 4 | 8 => {
+                    let bit = x * bits as usize;
+                    let shift = 8 - bits as usize - bit % 8;
+                    let index = (row[bit / 8] >> shift) as usize & ((1 << bits) - 1);
+                    *palette.get(index)?
+                }
+                _ => {
+                    let bytes = bits as usize / 8;
+                    let pixel = row[x * bytes..(x + 1) * bytes]
+                        .iter()
+                        .rev()
+                        .fold(0u32, |pixel, &byte| pixel << 8 | byte as u32);
+                    let [r, g, b] = [red, green, blue].map(|mask| mask.get(pixel, 0));
+                    Color::rgba(r, g, b, alpha.get(pixel, 255))
+                }
+            };
+            pixels.push(color);
+        }
+    }
+
+    Some(Image { width, height, pixels })
+}
@@ -0,0 +1,265 @@
+//! Inflate
+//!
+//! Decompresses zlib streams, the DEFLATE data PNG stores its pixels in:
+//! stored blocks and blocks with fixed or dynamic Huffman codes. Codes are
+//! decoded a bit at a time from their counts per length, which needs no
+//! lookup tables and is quick enough for icons and wallpapers.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Longest Huffman code, in bits
+const MAX_BITS: usize = 15;
+
+/// Base lengths of length codes 257 to 285, and their extra bits
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] =
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+
+/// Base distances of distance codes 0 to 29, and their extra bits
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order the lengths of the code length code are stored in
+const CODE_LENGTH_ORDER: [usize; 19] =
+    [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Reads a stream least significant bit first
+struct Bits<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0, buffer: 0, count: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> Option<u32> {
+        while self.count < count {
+            let byte = *self.data.get(self.position)?;
+            self.position += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u64 << count) - 1) as u32;
+        self.buffer >>= count;
+        self.count -= count;
+        Some(value)
+    }
+
+    /// Skip to the next whole byte, for stored blocks
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+}
+
+/// A canonical Huffman code: how many codes there are of each length, and
+/// the symbols in code order
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// The code with `lengths[symbol]` bits for each symbol, 0 for unused
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Where each length's symbols start
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; offsets[MAX_BITS + 1] as usize];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits<'_>) -> Option<u16> {
+        // Codes of one length are consecutive numbers, starting at `first`
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_BITS {
+            code |= bits.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return self.symbols.get((index + code - first) as usize).copied();
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+/// Decompress a zlib stream, checking its checksum; `size` is how many
+/// bytes are expected, to allocate once
+pub fn zlib(data: &[u8], size: usize) -> Option<Vec<u8>> {
+    let (method, flags) = (*data.first()?, *data.get(1)?);
+    let preset_dictionary = flags & 0x20 != 0;
+    let check = u16::from_be_bytes([method, flags]).is_multiple_of(31);
+    if method & 0x0F != 8 || !check || preset_dictionary {
+        return None;
+    }
+    let mut bits = Bits::new(&data[2..]);
+    let mut out = Vec::with_capacity(size);
+    inflate(&mut bits, &mut out)?;
+
+    bits.align();
+    let stored = bits.bytes(4)?;
+    let checksum = u32::from_be_bytes([stored[0], stored[1], stored[2], stored[3]]);
+    (adler32(&out) == checksum).then_some(out)
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // Sums of up to 5552 bytes cannot overflow before being reduced
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+fn inflate(bits: &mut Bits<'_>, out: &mut Vec<u8>) -> Option<()> {
+    loop {
+        let last = bits.bits(1)? == 1;
+        match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let header = bits.bytes(4)?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                let check = u16::from_le_bytes([header[2], header[3]]);
+                if len != !check {
+                    return None;
+                }
+                out.extend_from_slice(bits.bytes(len as usize)?);
+            }
+            1 => {
+                let (lengths, distances) = fixed();
+                block(bits, out, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic(bits)?;
+                block(bits, out, &lengths, &distances)?;
+            }
+            _ => return None,
+        }
+        if last {
+            return Some(());
+        }
+    }
+}
+
+/// The codes of blocks compressed with fixed Huffman codes
+fn fixed() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+/// Read the codes a block with dynamic Huffman codes starts with
+fn dynamic(bits: &mut Bits<'_>) -> Option<(Huffman, Huffman)> {
+    let literals = bits.bits(5)? as usize + 257;
+    let distances = bits.bits(5)? as usize + 1;
+    let code_lengths = bits.bits(4)? as usize + 4;
+    if literals > 286 || distances > 30 {
+        return None;
+    }
+
+    let mut lengths = [0u8; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_lengths] {
+        lengths[symbol] = bits.bits(3)? as u8;
+    }
+    let code = Huffman::new(&lengths);
+
+    // Both sets of lengths are stored one after the other, run-length coded
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (value, repeat) = match code.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last()?, 3 + bits.bits(2)?),
+            17 => (0, 3 + bits.bits(3)?),
+            18 => (0, 11 + bits.bits(7)?),
+            _ => return None,
+        };
+        if lengths.len() + repeat as usize > literals + distances {
+            return None;
+        }
+        lengths.extend(core::iter::repeat_n(value, repeat as usize));
+    }
+    // A block without an end code could never finish
+    if lengths[256] == 0 {
+        return None;
+    }
+    Some((Huffman::new(&lengths[..literals]), Huffman::new(&lengths[literals..])))
+}
+
+/// Decode the symbols of a compressed block up to its end code
+fn block(
+    bits: &mut Bits<'_>,
+    out: &mut Vec<u8>,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Option<()> {
+    loop {
+        let symbol = lengths.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Some(()),
+            _ => {
+                let index = symbol - 257;
+                let len = *LENGTH_BASE.get(index)? as usize
+                    + bits.bits(LENGTH_EXTRA[index] as u32)? as usize;
+                let index = distances.decode(bits)? as usize;
+                let distance = *DISTANCE_BASE.get(index)? as usize
+                    + bits.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                if distance > out.len() {
+                    return None;
+                }
+                // The copy may overlap what it adds, repeating a pattern
+                let start = out.len() - distance;
+                for offset in 0..len {
+                    out.push(out[start + offset]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Images
+//!
+//! Decoders for BMP and PNG files, for wallpapers, icons and image
+//! viewers, and `Image`, the pixels they produce. Files are checked as
+//! they are read; one that is cut short, malformed or larger than
+//! `MAX_SIDE` either way decodes to `None`.
+//!
+//! `Surface::draw_image` draws an image, scaled to any size and blended
+//! by its alpha.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::color::Color;
+
+mod bmp;
+mod inflate;
+mod png;
+
+/// Largest width or height decoded
+pub const MAX_SIDE: u32 = 8192;
+
+/// Decoded pixels, rows packed top-down, with straight alpha
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    /// A `width` x `height` image filled with `color`
+    pub fn new(width: u32, height: u32, color: Color) -> Self {
+        let pixels = vec![color; width as usize * height as usize];
+        Self { width, height, pixels }
+    }
+
+    /// Decode a BMP or PNG file, telling them apart by their signature
+    pub fn decode(file: &[u8]) -> Option<Self> {
+        if file.starts_with(b"BM") {
+            bmp::decode(file)
+        } else {
+            png::decode(file)
+        }
+    }
+
+    pub fn from_bmp(file: &[u8]) -> Option<Self> {
+        bmp::decode(file)
+    }
+
+    pub fn from_png(file: &[u8]) -> Option<Self> {
+        png::decode(file)
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.pixels.get((y * self.width + x) as usize).copied()
+    }
+
+    /// Whether any pixel is less than opaque
+    pub fn has_alpha(&self) -> bool {
+        self.pixels.iter().any(|pixel| pixel.a != 255)
+    }
+
+    /// The pixels as 32-bit values in the surface and framebuffer format,
+    /// blended onto `background` where not opaque
+    pub fn to_bgr32(&self, background: Color) -> Vec<u32> {
+        self.pixels.iter().map(|&pixel| background.blend(pixel).to_bgr32()).collect()
+    }
+
+    /// A copy `width` x `height` pixels large, each taken from the nearest
+    /// pixel of this one
+    pub fn scaled(&self, width: u32, height: u32) -> Self {
+        if self.width == 0 || self.height == 0 {
+            return Self::new(width, height, Color::rgba(0, 0, 0, 0));
+        }
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            let row = (y as u64 * self.height as u64 / height as u64) as u32 * self.width;
+            for x in 0..width {
+                let col = (x as u64 * self.width as u64 / width as u64) as u32;
+                pixels.push(self.pixels[(row + col) as usize]);
+            }
+        }
+        Self { width, height, pixels }
+    }
+}
@@ -0,0 +1,265 @@
+//! PNG Decoder
+//!
+//! Every color type and bit depth of the standard, with or without Adam7
+//! interlacing, and transparency from `tRNS`. Sixteen-bit samples keep
+//! their high byte; gamma and color profile chunks are not applied.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::inflate;
+use super::{Image, MAX_SIDE};
+use crate::color::Color;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+// Color types
+const GRAY: u8 = 0;
+const RGB: u8 = 2;
+const PALETTE: u8 = 3;
+const GRAY_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+/// First column and row and the steps between them of each Adam7 pass
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// What `IHDR` says about the pixels
+struct Header {
+    width: usize,
+    height: usize,
+    depth: u8,
+    color_type: u8,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            RGB => 3,
+            GRAY_ALPHA => 2,
+            RGBA => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.depth as usize
+    }
+
+    /// Bytes of a row of `width` pixels, without its filter byte
+    fn row_len(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+}
+
+pub fn decode(file: &[u8]) -> Option<Image> {
+    if !file.starts_with(&SIGNATURE) {
+        return None;
+    }
+
+    let mut header = None;
+    let mut palette: Vec<Color> = Vec::new();
+    let mut transparent: Option<[u16; 3]> = None;
+    let mut data = Vec::new();
+    let mut offset = SIGNATURE.len();
+    loop {
+        let len = u32::from_be_bytes(file.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        let kind = file.get(offset + 4..offset + 8)?;
+        let body = file.get(offset + 8..(offset + 8).checked_add(len)?)?;
+        // Skip the CRC; the zlib checksum covers the pixels
+        offset += 12 + len;
+        match kind {
+            b"IHDR" => {
+                let size = |at: usize| -> Option<usize> {
+                    Some(u32::from_be_bytes(body.get(at..at + 4)?.try_into().ok()?) as usize)
+                };
+                let (width, height) = (size(0)?, size(4)?);
+                let (depth, color_type) = (*body.get(8)?, *body.get(9)?);
+                let (filter, interlace) = (*body.get(11)?, *body.get(12)?);
+                let valid = match color_type {
+                    GRAY => matches!(depth, 1 | 2 | 4 | 8 | 16),
+                    PALETTE => matches!(depth, 1 | 2 | 4 | 8),
+                    RGB | GRAY_ALPHA | RGBA => matches!(depth, 8 | 16),
+                    _ => false,
+                };
+                let side = 1..=MAX_SIDE as usize;
+                if !valid || !side.contains(&width) || !side.contains(&height) || filter != 0 {
+                    return None;
+                }
+                header = Some((Header { width, height, depth, color_type }, interlace == 1));
+            }
+            b"PLTE" => {
+                let entries = body.chunks_exact(3);
+                palette = entries.map(|rgb| Color::rgb(rgb[0], rgb[1], rgb[2])).collect();
+            }
+            b"tRNS" => match header.as_ref()?.0.color_type {
+                PALETTE => {
+                    for (color, &alpha) in palette.iter_mut().zip(body) {
+                        color.a = alpha;
+                    }
+                }
+                color_type => {
+                    let sample = |at: usize| {
+                        body.get(at..at + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+                    };
+                    transparent = Some(if color_type == GRAY {
+                        let gray = sample(0)?;
+                        [gray, gray, gray]
+                    } else {
+                        [sample(0)?, sample(2)?, sample(4)?]
+                    });
+                }
+            },
+            b"IDAT" => data.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let (header, interlaced) = header?;
+    if header.color_type == PALETTE && palette.is_empty() {
+        return None;
+    }
+    let passes = if interlaced { &ADAM7[..] } else { &[(0, 0, 1, 1)][..] };
+
+    // Each pass is a small image of its own, rows led by a filter byte;
+    // passes with no pixels store nothing
+    let size = passes
+        .iter()
+        .map(|&pass| pass_size(&header, pass))
+        .filter(|&(width, _)| width > 0)
+        .map(|(width, height)| height * (header.row_len(width) + 1));
+    let raw = inflate::zlib(&data, size.sum())?;
+
+    let mut pixels = vec![Color::BLACK; header.width * header.height];
+    let mut rest = raw.as_slice();
+    for &pass in passes {
+        let (width, height) = pass_size(&header, pass);
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let stride = header.row_len(width) + 1;
+        let len = stride * height;
+        let mut scanlines = rest.get(..len)?.to_vec();
+        rest = &rest[len..];
+        unfilter(&mut scanlines, stride, header.bits_per_pixel().div_ceil(8))?;
+
+        let (x0, y0, dx, dy) = pass;
+        for (row, line) in scanlines.chunks_exact(stride).enumerate() {
+            for col in 0..width {
+                let color = pixel(&header, &line[1..], col, &palette, transparent)?;
+                pixels[(y0 + row * dy) * header.width + x0 + col * dx] = color;
+            }
+        }
+    }
+
+    Some(Image { width: header.width as u32, height: header.height as u32, pixels })
+}
+
+/// Pixels across and rows of an interlacing pass
+fn pass_size(header: &Header, (x0, y0, dx, dy): (usize, usize, usize, usize)) -> (usize, usize) {
+    (header.width.saturating_sub(x0).div_ceil(dx), header.height.saturating_sub(y0).div_ceil(dy))
+}
+
+/// Undo the filter each row was stored with, in place; `bpp` is the bytes
+/// per pixel, at least one
+fn unfilter(scanlines: &mut [u8], stride: usize, bpp: usize) -> Option<()> {
+    for row in 0..scanlines.len() / stride {
+        let (above, current) = scanlines.split_at_mut(row * stride);
+        let previous = (row > 0).then(|| &above[(row - 1) * stride..]);
+        let (filter, line) = current[..stride].split_first_mut()?;
+        for index in 0..line.len() {
+            let left = if index >= bpp { line[index - bpp] } else { 0 };
+            let up = previous.map_or(0, |previous| previous[1 + index]);
+            let up_left = match previous {
+                Some(previous) if index >= bpp => previous[1 + index - bpp],
+                _ => 0,
+            };
+            let predicted = match *filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return None,
+            };
+            line[index] = line[index].wrapping_add(predicted);
+        }
+    }
+    Some(())
+}
+
+/// Whichever of left, above and above left is closest to left + above -
+/// above left
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance = |value: u8| (estimate - value as i16).abs();
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// The color of pixel `col` of an unfiltered row
+fn pixel(
+    header: &Header,
+    line: &[u8],
+    col: usize,
+    palette: &[Color],
+    transparent: Option<[u16; 3]>,
+) -> Option<Color> {
+    let depth = header.depth as usize;
+    let channels = header.channels();
+    // Sample `channel` of the pixel at its own depth
+    let sample = |channel: usize| -> Option<u16> {
+        let bit = (col * channels + channel) * depth;
+        Some(match depth {
+            16 => u16::from_be_bytes([*line.get(bit / 8)?, *line.get(bit / 8 + 1)?]),
+            8 => *line.get(bit / 8)? as u16,
+            _ => (*line.get(bit / 8)? >> (8 - depth - bit % 8)) as u16 & ((1 << depth) - 1),
+        })
+    };
+    // A sample scaled to eight bits
+    let level = |value: u16| -> u8 {
+        match depth {
+            16 => (value >> 8) as u8,
+            8 => value as u8,
+            _ => (value * 255 / ((1 << depth) - 1)) as u8,
+        }
+    };
+
+    Some(match header.color_type {
+        PALETTE => *palette.get(sample(0)? as usize)?,
+        GRAY | GRAY_ALPHA => {
+            let gray = sample(0)?;
+            let alpha = match header.color_type {
+                GRAY_ALPHA => level(sample(1)?),
+                _ if transparent.is_some_and(|key| key[0] == gray) => 0,
+                _ => 255,
+            };
+            let gray = level(gray);
+            Color::rgba(gray, gray, gray, alpha)
+        }
+        _ => {
+            let rgb = [sample(0)?, sample(1)?, sample(2)?];
+            let alpha = match header.color_type {
+                RGBA => level(sample(3)?),
+                _ if transparent == Some(rgb) => 0,
+                _ => 255,
+            };
+            Color::rgba(level(rgb[0]), level(rgb[1]), level(rgb[2]), alpha)
+        }
+    })
+}
@@ -11,8 +11,10 @@
 //! Application
 //!     │
 //!     ├──> Surface (drawing)
+//!     │       │
+//!     │       └──> Ui (widgets, layout, focus)
 //!     │
-//!     └──> Event Loop (input)
+//!     └──> Event Loop (input, resize, frame pacing)
 //!             │
 //!             v
 //!     Desktop Environment (compositor)
@@ -21,10 +23,11 @@
 //! # Usage
 //!
 //! ```ignore
-//! use libgui::{Application, Surface, Event};
+//! use libgui::{Application, Surface, Event, Font};
 //!
 //! let app = Application::new("My App")?;
 //! let surface = app.create_surface(640, 480)?;
+//! let font = Font::builtin(2);
 //!
 //! loop {
 //!     match app.poll_event()? {
@@ -32,7 +35,7 @@
 //!         Event::Mouse(mouse) => handle_mouse(mouse),
 //!         Event::Redraw => {
 //!             surface.clear(Color::BLACK);
-//!             surface.draw_text(10, 10, "Hello!", Color::WHITE);
+//!             surface.draw_text(10, 10, "Hello!", &font, Color::WHITE);
 //!             surface.present();
 //!         }
 //!         Event::Quit => break,
@@ -48,10 +51,25 @@ pub mod surface;
 pub mod event;
 pub mod color;
 pub mod font;
+pub mod image;
 pub mod application;
+pub mod clipboard;
+pub mod dialog;
+pub mod capture;
+pub mod widget;
+pub mod window;
+
+mod damage;
+mod shapes;
 
 // Re-exports
 pub use surface::Surface;
-pub use event::{Event, KeyEvent, MouseEvent};
+pub use event::{DragEvent, Event, KeyEvent, MouseEvent, TimerId};
 pub use color::Color;
+pub use font::Font;
+pub use image::Image;
 pub use application::Application;
+pub use clipboard::Clipboard;
+pub use capture::ScreenCapture;
+pub use widget::{Ui, Widget, WidgetId};
+pub use window::{Handler, Window};
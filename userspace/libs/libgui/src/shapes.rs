@@ -0,0 +1,244 @@
+//! Shapes
+//!
+//! Lines, circles, arcs, rounded rectangles and gradients on a `Surface`.
+//! Curved edges are anti-aliased: a pixel the edge passes through is
+//! blended by how far its middle is inside, which needs no sampling. Like
+//! everything drawn on a surface, shapes stay inside its clip rectangle.
+
+use core::ops::Range;
+
+use libipc::messages::Rect;
+
+use crate::color::Color;
+use crate::surface::Surface;
+
+/// Sine of 0 to 90 degrees, in 2.14 fixed point
+const SINE: [i32; 91] = [
+    0, 286, 572, 857, 1143, 1428, 1713, 1997, 2280, 2563, 2845, 3126, 3406, 3686, 3964, 4240, 4516,
+    4790, 5063, 5334, 5604, 5872, 6138, 6402, 6664, 6924, 7182, 7438, 7692, 7943, 8192, 8438, 8682,
+    8923, 9162, 9397, 9630, 9860, 10087, 10311, 10531, 10749, 10963, 11174, 11381, 11585, 11786,
+    11982, 12176, 12365, 12551, 12733, 12911, 13085, 13255, 13421, 13583, 13741, 13894, 14044,
+    14189, 14330, 14466, 14598, 14726, 14849, 14968, 15082, 15191, 15296, 15396, 15491, 15582,
+    15668, 15749, 15826, 15897, 15964, 16026, 16083, 16135, 16182, 16225, 16262, 16294, 16322,
+    16344, 16362, 16374, 16382, 16384,
+];
+
+/// Sine of `degrees`, in 2.14 fixed point
+fn sine(degrees: i32) -> i32 {
+    let degrees = degrees.rem_euclid(360) as usize;
+    match degrees {
+        0..=90 => SINE[degrees],
+        91..=180 => SINE[180 - degrees],
+        181..=270 => -SINE[degrees - 180],
+        _ => -SINE[360 - degrees],
+    }
+}
+
+/// Direction `degrees` clockwise from the right, as a vector 1 << 14 long
+fn direction(degrees: i32) -> (i64, i64) {
+    (sine(degrees + 90) as i64, sine(degrees) as i64)
+}
+
+/// Distance from the point (cx, cy) to the middle of pixel (x, y), in
+/// 1/256 of a pixel
+fn distance(cx: i32, cy: i32, x: i32, y: i32) -> i64 {
+    // In half pixels, so the middle of a pixel is a whole number
+    let (dx, dy) = ((2 * (x - cx) + 1) as i64, (2 * (y - cy) + 1) as i64);
+    ((dx * dx + dy * dy) as u64 * 16384).isqrt() as i64
+}
+
+/// How much of a pixel `distance` from the middle of a disc of `radius`
+/// pixels the disc covers, out of 255
+fn disc(distance: i64, radius: i64) -> u8 {
+    ((radius * 256 + 128 - distance).clamp(0, 256) * 255 / 256) as u8
+}
+
+/// How much of pixel (x, y) is inside `rect` with corners rounded to
+/// `radius`, out of 255
+fn rounded(rect: Rect, radius: u32, x: i32, y: i32) -> u8 {
+    if !rect.contains(x, y) {
+        return 0;
+    }
+    let radius = radius.min(rect.width / 2).min(rect.height / 2) as i32;
+    // The middle of the corner's circle, if the pixel is in a corner
+    let across = |at: i32, start: i32, end: i32| {
+        if at < start + radius {
+            Some(start + radius)
+        } else if at >= end - radius {
+            Some(end - radius)
+        } else {
+            None
+        }
+    };
+    match (across(x, rect.x, rect.right()), across(y, rect.y, rect.bottom())) {
+        (Some(cx), Some(cy)) => disc(distance(cx, cy, x, y), radius as i64),
+        _ => 255,
+    }
+}
+
+impl Surface {
+    /// Draw a line one pixel wide from (x0, y0) to (x1, y1), both ends
+    /// included
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: Color) {
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (step_x, step_y) = ((x1 - x0).signum(), (y1 - y0).signum());
+        let (mut x, mut y) = (x0, y0);
+        // Bresenham's: `error` tracks how far the line is from the pixel
+        let mut error = dx + dy;
+        loop {
+            self.blend_pixel(x, y, color);
+            if x == x1 && y == y1 {
+                return;
+            }
+            let twice = 2 * error;
+            if twice >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if twice <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Fill the disc of `radius` pixels around the point (cx, cy)
+    pub fn fill_circle(&mut self, cx: i32, cy: i32, radius: u32, color: Color) {
+        self.draw_circular(cx, cy, radius, radius, None, color);
+    }
+
+    /// Draw a ring `width` pixels wide just inside the circle of `radius`
+    /// pixels around the point (cx, cy)
+    pub fn draw_circle(&mut self, cx: i32, cy: i32, radius: u32, width: u32, color: Color) {
+        self.draw_circular(cx, cy, radius, width, None, color);
+    }
+
+    /// Draw the part of a ring, as `draw_circle` does, between two angles
+    /// in degrees clockwise from the right; `-90..0` is the top right
+    /// quarter
+    pub fn draw_arc(
+        &mut self,
+        cx: i32,
+        cy: i32,
+        radius: u32,
+        width: u32,
+        angles: Range<i32>,
+        color: Color,
+    ) {
+        let sweep = angles.end - angles.start;
+        if sweep >= 360 {
+            self.draw_circle(cx, cy, radius, width, color);
+        } else if sweep > 0 {
+            self.draw_circular(cx, cy, radius, width, Some((angles.start, sweep)), color);
+        }
+    }
+
+    fn draw_circular(
+        &mut self,
+        cx: i32,
+        cy: i32,
+        radius: u32,
+        width: u32,
+        arc: Option<(i32, i32)>,
+        color: Color,
+    ) {
+        let reach = radius as i32 + 1;
+        let bounds = Rect::new(cx - reach, cy - reach, 2 * reach as u32, 2 * reach as u32);
+        let Some(area) = bounds.intersection(&self.clip()) else {
+            return;
+        };
+        self.mark_damaged(area);
+        let inner = radius.saturating_sub(width) as i64;
+        let ends = arc.map(|(start, sweep)| (direction(start), direction(start + sweep), sweep));
+
+        for y in area.y..area.bottom() {
+            for x in area.x..area.right() {
+                if let Some((from, to, sweep)) = ends {
+                    if !in_sector(2 * (x - cx) + 1, 2 * (y - cy) + 1, from, to, sweep) {
+                        continue;
+                    }
+                }
+                let distance = distance(cx, cy, x, y);
+                let outside = disc(distance, radius as i64);
+                let hole = if width >= radius { 0 } else { disc(distance, inner) };
+                self.blend_pixel(x, y, color.with_coverage(outside.saturating_sub(hole)));
+            }
+        }
+    }
+
+    /// Fill `rect` with its corners rounded to `radius` pixels
+    pub fn fill_rounded_rect(&mut self, rect: Rect, radius: u32, color: Color) {
+        let Some(area) = rect.intersection(&self.clip()) else {
+            return;
+        };
+        self.mark_damaged(area);
+        for y in area.y..area.bottom() {
+            for x in area.x..area.right() {
+                self.blend_pixel(x, y, color.with_coverage(rounded(rect, radius, x, y)));
+            }
+        }
+    }
+
+    /// Draw a frame `width` pixels wide just inside `rect`, with its
+    /// corners rounded to `radius` pixels
+    pub fn draw_rounded_rect(&mut self, rect: Rect, radius: u32, width: u32, color: Color) {
+        let Some(area) = rect.intersection(&self.clip()) else {
+            return;
+        };
+        self.mark_damaged(area);
+        let inset = width as i32;
+        let inner = Rect::new(
+            rect.x + inset,
+            rect.y + inset,
+            rect.width.saturating_sub(2 * width),
+            rect.height.saturating_sub(2 * width),
+        );
+        let inner_radius = radius.saturating_sub(width);
+        for y in area.y..area.bottom() {
+            for x in area.x..area.right() {
+                let frame = rounded(rect, radius, x, y)
+                    .saturating_sub(rounded(inner, inner_radius, x, y));
+                self.blend_pixel(x, y, color.with_coverage(frame));
+            }
+        }
+    }
+
+    /// Fill `rect` with colors going from `start` at the point `from` to
+    /// `end` at the point `to`, and staying at those past either
+    pub fn fill_gradient(
+        &mut self,
+        rect: Rect,
+        from: (i32, i32),
+        to: (i32, i32),
+        start: Color,
+        end: Color,
+    ) {
+        let Some(area) = rect.intersection(&self.clip()) else {
+            return;
+        };
+        self.mark_damaged(area);
+        let (dx, dy) = ((to.0 - from.0) as i64, (to.1 - from.1) as i64);
+        let length = (dx * dx + dy * dy).max(1);
+        for y in area.y..area.bottom() {
+            for x in area.x..area.right() {
+                // How far along the gradient the pixel is, projected onto it
+                let along = (x - from.0) as i64 * dx + (y - from.1) as i64 * dy;
+                let amount = (along * 255 / length).clamp(0, 255) as u8;
+                self.blend_pixel(x, y, start.mix(end, amount));
+            }
+        }
+    }
+}
+
+/// Whether the direction (x, y) is within `sweep` degrees clockwise of
+/// `from`, where `to` is; clockwise is towards positive y
+fn in_sector(x: i32, y: i32, from: (i64, i64), to: (i64, i64), sweep: i32) -> bool {
+    let (x, y) = (x as i64, y as i64);
+    let after_start = from.0 * y - from.1 * x >= 0;
+    let before_end = x * to.1 - y * to.0 >= 0;
+    if sweep <= 180 {
+        after_start && before_end
+    } else {
+        after_start || before_end
+    }
+}
@@ -3,11 +3,25 @@
 //! Provides an abstract drawing surface for applications.
 //! Applications draw to their assigned surface, and the desktop
 //! compositor handles actual screen rendering.
+//!
+//! Window surfaces are double-buffered: drawing goes to a buffer of the
+//! application's own, and `present` copies just the parts drawn since the
+//! last frame into the region the compositor reads, so it never shows a
+//! frame half drawn.
 
 extern crate alloc;
 
+use alloc::vec::Vec;
+use atom_syscall::graphics::{Framebuffer, FramebufferInfo, Pixels};
+use atom_syscall::ipc::PortId;
+use atom_syscall::shm::{self, RegionId};
+use libipc::messages::{CommitFrame, DestroyWindow, MessageType, Rect, ScaleFactor, WindowOpacity};
+use libipc::protocol::send_message_async;
+
 use crate::color::Color;
-use crate::font::{get_glyph, FONT_WIDTH, FONT_HEIGHT};
+use crate::damage::Damage;
+use crate::font::{get_glyph, Font, FONT_WIDTH, FONT_HEIGHT};
+use crate::image::Image;
 
 /// Surface handle (assigned by desktop compositor)
 pub type SurfaceId = u32;
@@ -24,12 +38,27 @@ pub struct Surface {
     stride: u32,
     /// Bytes per pixel
     bpp: usize,
-    /// Framebuffer address (memory-mapped)
+    /// Address drawing goes to: the back buffer, or else the front one
     buffer: *mut u8,
+    /// Framebuffer address (memory-mapped), and its stride in pixels
+    front: *mut u8,
+    front_stride: u32,
+    /// Off-screen pixels, `width` to a row, for double-buffered surfaces
+    back: Option<Vec<u32>>,
     /// Whether buffer is owned (allocated by us)
     owned: bool,
-    /// Dirty flag for damage tracking
-    dirty: bool,
+    /// Parts drawn since the last frame was presented
+    damage: Damage,
+    /// Compositor port and shared region, for window surfaces
+    compositor: Option<(PortId, RegionId)>,
+    /// Output scale a window surface is rendered at
+    scale: ScaleFactor,
+    /// Store each color's alpha in the pixel's top byte (ARGB)
+    per_pixel_alpha: bool,
+    /// Rectangle drawing is limited to, `None` for the whole surface
+    clip: Option<Rect>,
+    /// Clip rectangles `push_clip` replaced, innermost last
+    clip_stack: Vec<Option<Rect>>,
 }
 
 unsafe impl Send for Surface {}
@@ -55,8 +84,157 @@ impl Surface {
             stride,
             bpp,
             buffer,
+            front: buffer,
+            front_stride: stride,
+            back: None,
             owned: false,
-            dirty: false,
+            damage: Damage::new(width, height),
+            compositor: None,
+            scale: ScaleFactor::X1,
+            per_pixel_alpha: false,
+            clip: None,
+            clip_stack: Vec::new(),
+        }
+    }
+
+    /// Wrap a window surface mapped from the compositor's shared region
+    ///
+    /// `id` is the window id; `present` commits frames to `compositor`.
+    pub(crate) fn shared(
+        id: SurfaceId,
+        width: u32,
+        height: u32,
+        stride: u32,
+        buffer: *mut u8,
+        compositor: PortId,
+        region: RegionId,
+    ) -> Self {
+        let mut surface = Self::new(id, width, height, stride, 4, buffer);
+        surface.compositor = Some((compositor, region));
+        surface.set_double_buffered(true);
+        surface
+    }
+
+    /// Record the scale a window surface is rendered at
+    pub(crate) fn with_scale(mut self, scale: ScaleFactor) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Point a window surface at the window a restarted compositor made
+    /// for it; the shared region stays mapped
+    pub(crate) fn reattach(&mut self, id: SurfaceId, compositor: PortId) {
+        if let Some((_, region)) = self.compositor {
+            self.id = id;
+            self.compositor = Some((compositor, region));
+        }
+    }
+
+    /// Unmap a window surface's region so the compositor can free it on a
+    /// resize; nothing is drawn until `remap` gives it the new one
+    pub(crate) fn unmap(&mut self) -> Option<RegionId> {
+        let (_, region) = self.compositor?;
+        let _ = shm::unmap_region(region);
+        self.width = 0;
+        self.height = 0;
+        self.buffer = core::ptr::null_mut();
+        self.front = core::ptr::null_mut();
+        if let Some(back) = &mut self.back {
+            *back = Vec::new();
+        }
+        self.damage.resize(0, 0);
+        Some(region)
+    }
+
+    /// Draw into the region the compositor gave a resized window, mapped
+    /// at `buffer`
+    pub(crate) fn remap(
+        &mut self,
+        width: u32,
+        height: u32,
+        stride: u32,
+        buffer: *mut u8,
+        region: RegionId,
+    ) {
+        if let Some((port, _)) = self.compositor {
+            self.width = width;
+            self.height = height;
+            self.front = buffer;
+            self.front_stride = stride;
+            self.compositor = Some((port, region));
+            self.attach_buffer();
+            self.damage.resize(width, height);
+        }
+    }
+
+    /// Whether drawing goes to an off-screen buffer that `present` copies
+    /// to the screen
+    pub fn is_double_buffered(&self) -> bool {
+        self.back.is_some()
+    }
+
+    /// Draw off-screen and copy to the screen when presenting, or draw
+    /// straight onto it; window surfaces start out double-buffered
+    pub fn set_double_buffered(&mut self, double: bool) {
+        // The back buffer holds 32-bit pixels
+        if double == self.is_double_buffered() || self.bpp != 4 {
+            return;
+        }
+        if double {
+            self.back = Some(Vec::new());
+            self.attach_buffer();
+        } else {
+            // What was drawn and not yet presented appears now
+            self.copy_to_front(Rect::new(0, 0, self.width, self.height));
+            self.back = None;
+            self.attach_buffer();
+        }
+    }
+
+    /// Point drawing at the back buffer, sized for the surface and holding
+    /// what is on screen, or at the front buffer without one
+    fn attach_buffer(&mut self) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        match &mut self.back {
+            Some(back) if !self.front.is_null() => {
+                back.clear();
+                back.reserve_exact(width * height);
+                for y in 0..height {
+                    let row = unsafe {
+                        let start = self.front.add(y * self.front_stride as usize * self.bpp);
+                        core::slice::from_raw_parts(start as *const u32, width)
+                    };
+                    back.extend_from_slice(row);
+                }
+                self.buffer = back.as_mut_ptr() as *mut u8;
+                self.stride = self.width;
+            }
+            _ => {
+                self.buffer = self.front;
+                self.stride = self.front_stride;
+            }
+        }
+    }
+
+    /// Copy `rect` of the back buffer to the front one
+    fn copy_to_front(&mut self, rect: Rect) {
+        let Some(back) = &self.back else {
+            return;
+        };
+        let bounds = Rect::new(0, 0, self.width, self.height);
+        let Some(rect) = rect.intersection(&bounds) else {
+            return;
+        };
+        if self.front.is_null() {
+            return;
+        }
+        let (x, width) = (rect.x as usize, rect.width as usize);
+        for y in rect.y as usize..rect.bottom() as usize {
+            let src = &back[y * self.width as usize + x..][..width];
+            unsafe {
+                let dst = self.front.add((y * self.front_stride as usize + x) * self.bpp);
+                core::ptr::copy_nonoverlapping(src.as_ptr(), dst as *mut u32, width);
+            }
         }
     }
 
@@ -80,23 +258,76 @@ impl Surface {
         self.stride
     }
 
+    /// Output scale the surface is rendered at
+    pub fn scale(&self) -> ScaleFactor {
+        self.scale
+    }
+
+    /// Shared region backing a window surface
+    pub fn region(&self) -> Option<RegionId> {
+        self.compositor.map(|(_, region)| region)
+    }
+
     /// Check if point is within surface bounds
     pub fn contains(&self, x: i32, y: i32) -> bool {
         x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height
     }
 
+    /// Limit drawing to `rect`, within the current clip rectangle, until
+    /// the matching `pop_clip`
+    pub fn push_clip(&mut self, rect: Rect) {
+        self.clip_stack.push(self.clip);
+        let clip = rect.intersection(&self.clip()).unwrap_or(Rect::new(0, 0, 0, 0));
+        self.clip = Some(clip);
+    }
+
+    /// Go back to the clip rectangle before the last `push_clip`
+    pub fn pop_clip(&mut self) {
+        self.clip = self.clip_stack.pop().flatten();
+    }
+
+    /// Rectangle drawing is limited to: the surface, or the part of it
+    /// inside the clip rectangles pushed
+    pub fn clip(&self) -> Rect {
+        let bounds = Rect::new(0, 0, self.width, self.height);
+        match self.clip {
+            Some(clip) => clip.intersection(&bounds).unwrap_or(Rect::new(0, 0, 0, 0)),
+            None => bounds,
+        }
+    }
+
+    /// The drawing buffer as a `Framebuffer` clipped like the surface, for
+    /// its row-at-a-time fills and copies
+    fn canvas(&self) -> Framebuffer {
+        let info = FramebufferInfo {
+            address: self.buffer as usize,
+            width: self.width,
+            height: self.height,
+            stride: self.stride,
+            bytes_per_pixel: 4,
+            size: self.stride as usize * self.height as usize * 4,
+        };
+        // `buffer` holds `height` rows of `stride` 32-bit pixels until the
+        // surface is resized or dropped, and the canvas does not outlive
+        // this borrow
+        let canvas = unsafe { Framebuffer::from_info(info) };
+        let clip = self.clip();
+        canvas.set_clip(clip.x as u32, clip.y as u32, clip.width, clip.height);
+        canvas
+    }
+
     /// Set a pixel at the given coordinates
     pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
-        if x >= self.width || y >= self.height {
+        if !self.clip().contains(x as i32, y as i32) {
             return;
         }
 
         let offset = (y * self.stride + x) as usize * self.bpp;
         unsafe {
             let ptr = self.buffer.add(offset) as *mut u32;
-            ptr.write_volatile(color.to_bgr32());
+            ptr.write_volatile(self.pixel(color));
         }
-        self.dirty = true;
+        self.damage.add(Rect::new(x as i32, y as i32, 1, 1));
     }
 
     /// Get a pixel at the given coordinates
@@ -123,58 +354,57 @@ impl Surface {
         self.fill_rect(0, 0, self.width, self.height, color);
     }
 
+    /// Draw `color` over the pixel at (x, y), blended by its alpha
+    pub fn blend_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if color.a == 0 || !self.clip().contains(x, y) {
+            return;
+        }
+        let (x, y) = (x as u32, y as u32);
+        if color.a == 255 {
+            self.set_pixel(x, y, color);
+        } else if let Some(under) = self.get_pixel(x, y) {
+            self.set_pixel(x, y, under.blend(color));
+        }
+    }
+
     /// Fill a rectangle with a color
     pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Color) {
-        let x_end = (x + width).min(self.width);
-        let y_end = (y + height).min(self.height);
-        let pixel_value = color.to_bgr32();
-
-        for py in y..y_end {
-            for px in x..x_end {
-                let offset = (py * self.stride + px) as usize * self.bpp;
-                unsafe {
-                    let ptr = self.buffer.add(offset) as *mut u32;
-                    ptr.write_volatile(pixel_value);
-                }
-            }
-        }
-        self.dirty = true;
+        let Some(area) = Rect::new(x as i32, y as i32, width, height).intersection(&self.clip())
+        else {
+            return;
+        };
+        self.canvas().fill(x, y, width, height, self.pixel(color));
+        self.damage.add(area);
     }
 
-    /// Draw a horizontal line
-    pub fn draw_hline(&mut self, x: u32, y: u32, length: u32, color: Color) {
-        if y >= self.height {
+    /// Fill `rect` with `color` blended over what is there by its alpha,
+    /// for shades and highlights
+    pub fn blend_rect(&mut self, rect: Rect, color: Color) {
+        let Some(area) = rect.intersection(&self.clip()) else {
+            return;
+        };
+        if color.a == 255 {
+            self.fill_rect(area.x as u32, area.y as u32, area.width, area.height, color);
             return;
         }
-        let x_end = (x + length).min(self.width);
-        let pixel_value = color.to_bgr32();
-
-        for px in x..x_end {
-            let offset = (y * self.stride + px) as usize * self.bpp;
-            unsafe {
-                let ptr = self.buffer.add(offset) as *mut u32;
-                ptr.write_volatile(pixel_value);
+        self.mark_damaged(area);
+        for y in area.y..area.bottom() {
+            for x in area.x..area.right() {
+                self.blend_pixel(x, y, color);
             }
         }
-        self.dirty = true;
+    }
+
+    /// Draw a horizontal line
+    pub fn draw_hline(&mut self, x: u32, y: u32, length: u32, color: Color) {
+        self.canvas().fill(x, y, length, 1, self.pixel(color));
+        self.damage.add(Rect::new(x as i32, y as i32, length, 1));
     }
 
     /// Draw a vertical line
     pub fn draw_vline(&mut self, x: u32, y: u32, length: u32, color: Color) {
-        if x >= self.width {
-            return;
-        }
-        let y_end = (y + length).min(self.height);
-        let pixel_value = color.to_bgr32();
-
-        for py in y..y_end {
-            let offset = (py * self.stride + x) as usize * self.bpp;
-            unsafe {
-                let ptr = self.buffer.add(offset) as *mut u32;
-                ptr.write_volatile(pixel_value);
-            }
-        }
-        self.dirty = true;
+        self.canvas().fill(x, y, 1, length, self.pixel(color));
+        self.damage.add(Rect::new(x as i32, y as i32, 1, length));
     }
 
     /// Draw a rectangle outline
@@ -188,28 +418,22 @@ impl Surface {
     /// Draw a single character at the given position
     pub fn draw_char(&mut self, x: u32, y: u32, ch: u8, fg: Color, bg: Color) {
         let glyph = get_glyph(ch);
-        let fg_value = fg.to_bgr32();
-        let bg_value = bg.to_bgr32();
+        let fg_value = self.pixel(fg);
+        let bg_value = self.pixel(bg);
+        let canvas = self.canvas();
 
+        // Build each row of the glyph, then copy it in one go
+        let mut line = [0u32; FONT_WIDTH as usize];
         for row in 0..FONT_HEIGHT {
-            for col in 0..FONT_WIDTH {
-                let px = x + col;
-                let py = y + row;
-                if px >= self.width || py >= self.height {
-                    continue;
-                }
-
+            for (col, pixel) in line.iter_mut().enumerate() {
                 let bit = (glyph[row as usize] >> (7 - col)) & 1;
-                let pixel_value = if bit == 1 { fg_value } else { bg_value };
-
-                let offset = (py * self.stride + px) as usize * self.bpp;
-                unsafe {
-                    let ptr = self.buffer.add(offset) as *mut u32;
-                    ptr.write_volatile(pixel_value);
-                }
+                *pixel = if bit == 1 { fg_value } else { bg_value };
+            }
+            if let Some(src) = Pixels::packed(&line, FONT_WIDTH) {
+                canvas.blit(x as i32, (y + row) as i32, &src);
             }
         }
-        self.dirty = true;
+        self.damage.add(Rect::new(x as i32, y as i32, FONT_WIDTH, FONT_HEIGHT));
     }
 
     /// Draw a string at the given position
@@ -226,79 +450,206 @@ impl Surface {
 
     /// Draw a string with transparent background (only draw foreground pixels)
     pub fn draw_string_transparent(&mut self, x: u32, y: u32, text: &str, fg: Color) {
-        let fg_value = fg.to_bgr32();
         let mut cx = x;
-
         for ch in text.bytes() {
             if cx + FONT_WIDTH > self.width {
                 break;
             }
+            self.draw_char_transparent(cx, y, ch, fg);
+            cx += FONT_WIDTH;
+        }
+    }
+
+    /// Draw a single character, leaving the pixels around it as they are
+    pub fn draw_char_transparent(&mut self, x: u32, y: u32, ch: u8, fg: Color) {
+        let glyph = get_glyph(ch);
+        let fg_value = self.pixel(fg);
+        let clip = self.clip();
 
-            let glyph = get_glyph(ch);
-            for row in 0..FONT_HEIGHT {
-                for col in 0..FONT_WIDTH {
-                    let px = cx + col;
-                    let py = y + row;
-                    if px >= self.width || py >= self.height {
-                        continue;
+        for row in 0..FONT_HEIGHT {
+            for col in 0..FONT_WIDTH {
+                let px = x + col;
+                let py = y + row;
+                if !clip.contains(px as i32, py as i32) {
+                    continue;
+                }
+
+                let bit = (glyph[row as usize] >> (7 - col)) & 1;
+                if bit == 1 {
+                    let offset = (py * self.stride + px) as usize * self.bpp;
+                    unsafe {
+                        let ptr = self.buffer.add(offset) as *mut u32;
+                        ptr.write_volatile(fg_value);
                     }
+                }
+            }
+        }
+        self.damage.add(Rect::new(x as i32, y as i32, FONT_WIDTH, FONT_HEIGHT));
+    }
 
-                    let bit = (glyph[row as usize] >> (7 - col)) & 1;
-                    if bit == 1 {
-                        let offset = (py * self.stride + px) as usize * self.bpp;
-                        unsafe {
-                            let ptr = self.buffer.add(offset) as *mut u32;
-                            ptr.write_volatile(fg_value);
-                        }
+    /// Draw `text` in `font` with the top of the line at `y`, blending
+    /// the edges of anti-aliased glyphs into what is already drawn;
+    /// returns the x the next character would go at
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, font: &Font, color: Color) -> i32 {
+        let baseline = y + font.ascent() as i32;
+        let mut pen = x;
+        for ch in text.chars() {
+            if pen >= self.width as i32 {
+                break;
+            }
+            font.with_glyph(ch, |glyph| {
+                let (left, top) = (pen + glyph.left, baseline - glyph.top);
+                self.mark_damaged(Rect::new(left, top, glyph.width, glyph.height));
+                let rows = glyph.coverage.chunks(glyph.width.max(1) as usize);
+                for (row, coverage) in rows.enumerate() {
+                    let py = top + row as i32;
+                    for (col, &alpha) in coverage.iter().enumerate() {
+                        self.blend_pixel(left + col as i32, py, color.with_coverage(alpha));
                     }
                 }
+                pen += glyph.advance as i32;
+            });
+        }
+        pen
+    }
+
+    /// Draw `image` at its own size with its top left corner at `x`, `y`
+    pub fn draw_image(&mut self, x: i32, y: i32, image: &Image) {
+        self.draw_image_scaled(Rect::new(x, y, image.width, image.height), image);
+    }
+
+    /// Draw `image` stretched or shrunk to fill `rect`, each pixel taken
+    /// from the nearest one of the image and blended by its alpha
+    pub fn draw_image_scaled(&mut self, rect: Rect, image: &Image) {
+        if image.width == 0 || image.height == 0 || rect.is_empty() {
+            return;
+        }
+        let Some(visible) = rect.intersection(&self.clip()) else {
+            return;
+        };
+        self.mark_damaged(visible);
+        let (w, h) = (rect.width as u64, rect.height as u64);
+        for py in visible.y..visible.bottom() {
+            let iy = ((py - rect.y) as u64 * image.height as u64 / h) as u32;
+            for px in visible.x..visible.right() {
+                let ix = ((px - rect.x) as u64 * image.width as u64 / w) as u32;
+                self.blend_pixel(px, py, image.pixels[(iy * image.width + ix) as usize]);
             }
-            cx += FONT_WIDTH;
         }
-        self.dirty = true;
     }
 
     /// Copy a region from another surface
     pub fn blit(&mut self, src: &Surface, src_x: u32, src_y: u32, dst_x: u32, dst_y: u32, width: u32, height: u32) {
-        for y in 0..height {
-            for x in 0..width {
-                if let Some(color) = src.get_pixel(src_x + x, src_y + y) {
-                    self.set_pixel(dst_x + x, dst_y + y, color);
-                }
-            }
+        if src.buffer.is_null() {
+            return;
+        }
+        let source = src.canvas();
+        let area = source.pixels().sub(src_x, src_y, width, height);
+
+        // Colors are copied opaque, in this surface's format
+        let alpha = if self.per_pixel_alpha { 0xFF00_0000 } else { 0 };
+        self.canvas()
+            .blit_with(dst_x as i32, dst_y as i32, &area, |_, pixel| (pixel & 0xFF_FFFF) | alpha);
+
+        let rect = Rect::new(dst_x as i32, dst_y as i32, area.width(), area.height());
+        if let Some(rect) = rect.intersection(&self.clip()) {
+            self.damage.add(rect);
         }
     }
 
-    /// Check if surface needs redraw
+    /// Check if anything was drawn since the last frame was presented
     pub fn is_dirty(&self) -> bool {
-        self.dirty
+        !self.damage.is_empty()
     }
 
-    /// Clear dirty flag
+    /// Forget what was drawn, so the next frame presents none of it
     pub fn clear_dirty(&mut self) {
-        self.dirty = false;
+        self.damage.take();
     }
 
-    /// Mark surface as needing redraw
+    /// Mark the whole surface as drawn
     pub fn mark_dirty(&mut self) {
-        self.dirty = true;
+        self.damage.add_all();
     }
 
-    /// Get raw buffer pointer (for advanced use)
+    /// Mark `rect` as drawn, after writing to `buffer` directly
+    pub fn mark_damaged(&mut self, rect: Rect) {
+        self.damage.add(rect);
+    }
+
+    /// Parts drawn since the last frame was presented
+    pub fn damage(&self) -> &[Rect] {
+        self.damage.rects()
+    }
+
+    /// Get raw buffer pointer (for advanced use); drawing directly is
+    /// presented once marked with `mark_damaged`
     pub fn buffer(&self) -> *mut u8 {
         self.buffer
     }
 
+    /// Ask the compositor to fade the window to `opacity` (255 = opaque)
+    /// and, with `per_pixel_alpha`, to honour the alpha of each color
+    /// drawn from now on. No effect on framebuffer surfaces.
+    pub fn set_opacity(&mut self, opacity: u8, per_pixel_alpha: bool) {
+        let Some((port, _)) = self.compositor else {
+            return;
+        };
+        self.per_pixel_alpha = per_pixel_alpha;
+
+        let msg = WindowOpacity {
+            window_id: self.id,
+            opacity,
+            per_pixel_alpha,
+        };
+        let _ = send_message_async(port, MessageType::SetWindowOpacity, &msg.to_bytes());
+    }
+
+    /// Pixel value for `color` in this surface's format
+    fn pixel(&self, color: Color) -> u32 {
+        if self.per_pixel_alpha {
+            color.to_bgr32() | ((color.a as u32) << 24)
+        } else {
+            color.to_bgr32()
+        }
+    }
+
     /// Present the surface (signal compositor to display)
+    ///
+    /// Window surfaces commit the parts drawn since the last frame, copied
+    /// from the back buffer if there is one; direct framebuffer surfaces
+    /// are already on screen. The compositor shows commits at its frame
+    /// rate and answers with `Event::FrameDone`, so animations should draw
+    /// their next frame when that arrives.
     pub fn present(&mut self) {
-        // In a full implementation, this would send a message to the compositor
-        // For now, since we're writing directly to the framebuffer, this is a no-op
-        self.dirty = false;
+        let damage = self.damage.take();
+        for &rect in &damage {
+            self.copy_to_front(rect);
+        }
+        let Some((port, _)) = self.compositor else {
+            return;
+        };
+        // A frame with nothing drawn is still committed, to be told when
+        // it is shown
+        let commit = CommitFrame { window_id: self.id, damage };
+        let _ = send_message_async(port, MessageType::CommitFrame, &commit.to_bytes());
+    }
+
+    /// Present the surface, with `damage` changed as well as what drawing
+    /// on it marked, such as an area written through `buffer`
+    pub fn present_rect(&mut self, damage: Rect) {
+        self.damage.add(damage);
+        self.present();
     }
 }
 
 impl Drop for Surface {
     fn drop(&mut self) {
-        // In full implementation, notify compositor to destroy surface
+        // Unmap first so the compositor can free the region
+        if let Some((port, region)) = self.compositor {
+            let _ = shm::unmap_region(region);
+            let msg = DestroyWindow { window_id: self.id };
+            let _ = send_message_async(port, MessageType::DestroyWindow, &msg.to_bytes());
+        }
     }
 }
@@ -0,0 +1,371 @@
+//! Controls
+//!
+//! The leaf widgets: `Label` shows a line of text, `Button` is clicked
+//! with the mouse or with Enter and Space once focused, `TextInput` edits
+//! a line of text and `List` picks one of a column of items.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use libipc::messages::Rect;
+
+use super::{
+    keys, shrink, Action, Canvas, Child, Response, Role, Size, State, Style, Widget, WidgetEvent,
+};
+use crate::font::{FONT_HEIGHT, FONT_WIDTH};
+
+/// Pixels between the edge of a control and its text
+const INSET: u32 = 4;
+
+/// A line of text
+pub struct Label {
+    text: String,
+    dim: bool,
+}
+
+impl Label {
+    pub fn new(text: &str) -> Self {
+        Self { text: String::from(text), dim: false }
+    }
+
+    /// Draw the text in the theme's dimmed color
+    pub fn dimmed(mut self) -> Self {
+        self.dim = true;
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text.clear();
+        self.text.push_str(text);
+    }
+}
+
+impl Widget for Label {
+    fn measure(&self, _children: &[Child]) -> Size {
+        Size::of_text(&self.text)
+    }
+
+    fn draw(&self, canvas: &mut Canvas<'_>, bounds: Rect, _state: State, style: &Style) {
+        let color = if self.dim { style.text_dim } else { style.text };
+        canvas.fill_rect(bounds, style.background);
+        canvas.draw_label(bounds, 0, &self.text, color);
+    }
+
+    fn role(&self) -> Role {
+        Role::Label
+    }
+
+    fn label(&self) -> &str {
+        &self.text
+    }
+}
+
+/// A push button
+pub struct Button {
+    label: String,
+}
+
+impl Button {
+    pub fn new(label: &str) -> Self {
+        Self { label: String::from(label) }
+    }
+
+    pub fn set_label(&mut self, label: &str) {
+        self.label.clear();
+        self.label.push_str(label);
+    }
+}
+
+impl Widget for Button {
+    fn measure(&self, _children: &[Child]) -> Size {
+        let text = Size::of_text(&self.label);
+        Size::new(text.width + 4 * INSET, text.height + 2 * INSET)
+    }
+
+    fn draw(&self, canvas: &mut Canvas<'_>, bounds: Rect, state: State, style: &Style) {
+        let face = if state.pressed { style.accent } else { style.control };
+        let frame = if state.focused || state.hovered { style.accent } else { style.border };
+        canvas.fill_rect(bounds, face);
+        canvas.draw_frame(bounds, style.border_width, frame);
+
+        let text = Size::of_text(&self.label);
+        let inset = bounds.width.saturating_sub(text.width) / 2;
+        canvas.draw_label(bounds, inset, &self.label, style.text);
+    }
+
+    fn event(&mut self, event: &WidgetEvent, bounds: Rect) -> Response {
+        match *event {
+            WidgetEvent::MouseUp { x, y } if bounds.contains(x, y) => {
+                Response::Action(Action::Clicked)
+            }
+            WidgetEvent::Key(key) if key.character == b'\n' || key.character == b' ' => {
+                Response::Action(Action::Clicked)
+            }
+            WidgetEvent::MouseDown { .. }
+            | WidgetEvent::MouseUp { .. }
+            | WidgetEvent::FocusIn
+            | WidgetEvent::FocusOut => Response::Repaint,
+            _ => Response::Ignored,
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+
+    fn role(&self) -> Role {
+        Role::Button
+    }
+
+    fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+/// A line of editable text
+pub struct TextInput {
+    text: String,
+    /// Shown while the text is empty
+    placeholder: String,
+    /// Byte offset the next character goes in at
+    cursor: usize,
+}
+
+impl TextInput {
+    pub fn new(placeholder: &str) -> Self {
+        Self { text: String::new(), placeholder: String::from(placeholder), cursor: 0 }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replace the text and put the cursor after it
+    pub fn set_text(&mut self, text: &str) {
+        self.text.clear();
+        self.text.extend(text.chars().filter(|ch| ch.is_ascii() && !ch.is_ascii_control()));
+        self.cursor = self.text.len();
+    }
+
+    /// Columns of text that fit in `bounds`
+    fn columns(bounds: Rect) -> usize {
+        (bounds.width.saturating_sub(2 * INSET) / FONT_WIDTH) as usize
+    }
+
+    /// First column shown, so the cursor stays in view
+    fn first_shown(&self, bounds: Rect) -> usize {
+        (self.cursor + 1).saturating_sub(Self::columns(bounds).max(1))
+    }
+
+    fn move_cursor(&mut self, cursor: usize) -> Response {
+        let cursor = cursor.min(self.text.len());
+        if cursor == self.cursor {
+            return Response::Handled;
+        }
+        self.cursor = cursor;
+        Response::Repaint
+    }
+}
+
+impl Widget for TextInput {
+    fn measure(&self, _children: &[Child]) -> Size {
+        Size::new(20 * FONT_WIDTH + 2 * INSET, FONT_HEIGHT + 2 * INSET)
+    }
+
+    fn draw(&self, canvas: &mut Canvas<'_>, bounds: Rect, state: State, style: &Style) {
+        let frame = if state.focused { style.accent } else { style.border };
+        canvas.fill_rect(bounds, style.control);
+        canvas.draw_frame(bounds, style.border_width, frame);
+
+        let inner = shrink(bounds, INSET);
+        if self.text.is_empty() && !state.focused {
+            canvas.draw_label(bounds, INSET, &self.placeholder, style.text_dim);
+            return;
+        }
+        let first = self.first_shown(bounds);
+        let last = (first + Self::columns(bounds)).min(self.text.len());
+        canvas.draw_label(bounds, INSET, &self.text[first..last], style.text);
+
+        if state.focused {
+            let x = inner.x + ((self.cursor - first) as u32 * FONT_WIDTH) as i32;
+            let y = bounds.y + (bounds.height.saturating_sub(FONT_HEIGHT) / 2) as i32;
+            canvas.fill_rect(Rect::new(x, y, 1, FONT_HEIGHT), style.text);
+        }
+    }
+
+    fn event(&mut self, event: &WidgetEvent, _bounds: Rect) -> Response {
+        let key = match *event {
+            WidgetEvent::Key(key) => key,
+            WidgetEvent::FocusIn | WidgetEvent::FocusOut => return Response::Repaint,
+            WidgetEvent::MouseDown { .. } => return Response::Handled,
+            _ => return Response::Ignored,
+        };
+
+        if let Some(ch) = key.as_char() {
+            self.text.insert(self.cursor, ch);
+            self.cursor += 1;
+            return Response::Action(Action::Changed);
+        }
+        match (key.character, key.scancode) {
+            (b'\n', _) => Response::Action(Action::Submitted),
+            (0x08, _) if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.text.remove(self.cursor);
+                Response::Action(Action::Changed)
+            }
+            (_, keys::DELETE) if self.cursor < self.text.len() => {
+                self.text.remove(self.cursor);
+                Response::Action(Action::Changed)
+            }
+            (_, keys::LEFT) => self.move_cursor(self.cursor.saturating_sub(1)),
+            (_, keys::RIGHT) => self.move_cursor(self.cursor + 1),
+            (_, keys::HOME) => self.move_cursor(0),
+            (_, keys::END) => self.move_cursor(self.text.len()),
+            (0x08, _) | (_, keys::DELETE) => Response::Handled,
+            _ => Response::Ignored,
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+
+    fn role(&self) -> Role {
+        Role::TextInput
+    }
+
+    /// The placeholder says what goes in
+    fn label(&self) -> &str {
+        &self.placeholder
+    }
+}
+
+/// A column of items, one of which can be selected
+pub struct List {
+    items: Vec<String>,
+    selected: Option<usize>,
+}
+
+impl List {
+    pub fn new() -> Self {
+        Self { items: Vec::new(), selected: None }
+    }
+
+    pub fn push(&mut self, item: &str) {
+        self.items.push(String::from(item));
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.selected = None;
+    }
+
+    pub fn items(&self) -> &[String] {
+        &self.items
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    pub fn select(&mut self, index: Option<usize>) {
+        self.selected = index.filter(|&index| index < self.items.len());
+    }
+
+    fn row_height() -> u32 {
+        FONT_HEIGHT + INSET
+    }
+
+    fn row(bounds: Rect, index: usize) -> Rect {
+        let height = Self::row_height();
+        Rect::new(bounds.x, bounds.y + (index as u32 * height) as i32, bounds.width, height)
+    }
+
+    fn choose(&mut self, index: usize) -> Response {
+        if self.selected == Some(index) {
+            return Response::Handled;
+        }
+        self.selected = Some(index);
+        Response::Action(Action::Selected(index))
+    }
+}
+
+impl Default for List {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Widget for List {
+    fn measure(&self, _children: &[Child]) -> Size {
+        let widest = self.items.iter().map(|item| item.len()).max().unwrap_or(0) as u32;
+        Size::new(widest * FONT_WIDTH + 2 * INSET, self.items.len() as u32 * Self::row_height())
+    }
+
+    fn draw(&self, canvas: &mut Canvas<'_>, bounds: Rect, state: State, style: &Style) {
+        canvas.fill_rect(bounds, style.background);
+
+        // Only the rows in the clip rectangle, which may be a small part
+        // of a long list in a scroll view
+        let clip = canvas.clip();
+        let height = Self::row_height() as i32;
+        let first = ((clip.y - bounds.y).max(0) / height) as usize;
+        let last = ((clip.bottom() - bounds.y).max(0) / height + 1) as usize;
+        for index in first..last.min(self.items.len()) {
+            let row = Self::row(bounds, index);
+            if self.selected == Some(index) {
+                let color = if state.focused { style.accent } else { style.control };
+                canvas.fill_rect(row, color);
+            }
+            canvas.draw_label(row, INSET, &self.items[index], style.text);
+        }
+    }
+
+    fn event(&mut self, event: &WidgetEvent, bounds: Rect) -> Response {
+        match *event {
+            WidgetEvent::MouseDown { y, .. } => {
+                let index = ((y - bounds.y).max(0) as u32 / Self::row_height()) as usize;
+                if index < self.items.len() {
+                    self.choose(index)
+                } else {
+                    Response::Handled
+                }
+            }
+            WidgetEvent::Key(_) if self.items.is_empty() => Response::Ignored,
+            WidgetEvent::Key(key) => match (key.character, key.scancode) {
+                (b'\n', _) if self.selected.is_some() => Response::Action(Action::Submitted),
+                (_, keys::UP) => {
+                    self.choose(self.selected.map_or(0, |index| index.saturating_sub(1)))
+                }
+                (_, keys::DOWN) => {
+                    let last = self.items.len() - 1;
+                    self.choose(self.selected.map_or(0, |index| (index + 1).min(last)))
+                }
+                (_, keys::HOME) => self.choose(0),
+                (_, keys::END) => self.choose(self.items.len() - 1),
+                _ => Response::Ignored,
+            },
+            WidgetEvent::FocusIn | WidgetEvent::FocusOut => Response::Repaint,
+            _ => Response::Ignored,
+        }
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+
+    fn role(&self) -> Role {
+        Role::List
+    }
+
+    /// The selected item
+    fn label(&self) -> &str {
+        self.selected.map_or("", |index| &self.items[index])
+    }
+}
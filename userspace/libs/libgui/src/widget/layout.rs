@@ -0,0 +1,124 @@
+//! Box Layout
+//!
+//! `Flex` lines its children up along an axis, a row or a column. Each
+//! child gets its preferred length along the axis; space left over is
+//! shared among the children with a flex factor, in proportion to it.
+//! Across the axis every child is stretched to fill the box.
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use libipc::messages::Rect;
+
+use super::{shrink, Canvas, Child, Role, Size, State, Style, Widget};
+
+/// Direction a `Flex` lines its children up in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    /// Left to right
+    Horizontal,
+    /// Top to bottom
+    Vertical,
+}
+
+impl Axis {
+    /// Length of `size` along the axis, and across it
+    fn split(self, size: Size) -> (u32, u32) {
+        match self {
+            Axis::Horizontal => (size.width, size.height),
+            Axis::Vertical => (size.height, size.width),
+        }
+    }
+}
+
+/// A row or a column of widgets
+pub struct Flex {
+    axis: Axis,
+    /// Pixels between two children
+    spacing: u32,
+    /// Pixels between the children and the edges
+    padding: u32,
+}
+
+impl Flex {
+    pub fn new(axis: Axis) -> Self {
+        Self { axis, spacing: 4, padding: 0 }
+    }
+
+    /// Children side by side
+    pub fn row() -> Self {
+        Self::new(Axis::Horizontal)
+    }
+
+    /// Children one above the other
+    pub fn column() -> Self {
+        Self::new(Axis::Vertical)
+    }
+
+    pub fn with_spacing(mut self, spacing: u32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    fn gaps(&self, children: usize) -> u32 {
+        self.spacing * children.saturating_sub(1) as u32
+    }
+}
+
+impl Widget for Flex {
+    fn measure(&self, children: &[Child]) -> Size {
+        let (mut along, mut across) = (self.gaps(children.len()), 0);
+        for child in children {
+            let (main, cross) = self.axis.split(child.size);
+            along += main;
+            across = across.max(cross);
+        }
+        let (along, across) = (along + 2 * self.padding, across + 2 * self.padding);
+        match self.axis {
+            Axis::Horizontal => Size::new(along, across),
+            Axis::Vertical => Size::new(across, along),
+        }
+    }
+
+    fn arrange(&mut self, bounds: Rect, children: &[Child]) -> Vec<Rect> {
+        let inner = shrink(bounds, self.padding);
+        let (length, _) = self.axis.split(Size::new(inner.width, inner.height));
+        let wanted: u32 = children.iter().map(|child| self.axis.split(child.size).0).sum();
+        let mut extra = length.saturating_sub(wanted + self.gaps(children.len()));
+        let mut flex: u32 = children.iter().map(|child| child.flex).sum();
+
+        let mut position = 0;
+        let mut rects = Vec::with_capacity(children.len());
+        for child in children {
+            let mut main = self.axis.split(child.size).0;
+            // Each flexible child takes its share of what the ones before
+            // it left, so rounding never loses a pixel
+            if child.flex > 0 {
+                let share = extra * child.flex / flex;
+                main += share;
+                extra -= share;
+                flex -= child.flex;
+            }
+            rects.push(match self.axis {
+                Axis::Horizontal => Rect::new(inner.x + position, inner.y, main, inner.height),
+                Axis::Vertical => Rect::new(inner.x, inner.y + position, inner.width, main),
+            });
+            position += (main + self.spacing) as i32;
+        }
+        rects
+    }
+
+    fn draw(&self, canvas: &mut Canvas<'_>, bounds: Rect, _state: State, style: &Style) {
+        canvas.fill_rect(bounds, style.background);
+    }
+
+    fn role(&self) -> Role {
+        Role::Group
+    }
+}
@@ -0,0 +1,364 @@
+//! Widgets
+//!
+//! A retained widget toolkit on top of `Surface`. An application builds a
+//! tree of widgets once in a `Ui`, feeds it the events it receives and
+//! asks it to render; the tree lays the widgets out, moves focus with Tab,
+//! hands keys to the focused widget and mouse events to the widget under
+//! the pointer, and redraws and presents only what changed.
+//!
+//! ```ignore
+//! use libgui::widget::{Button, Flex, TextInput, Ui};
+//!
+//! let mut ui = Ui::new(Flex::column().with_padding(8), surface.width(), surface.height());
+//! let root = ui.root();
+//! let name = ui.add(root, TextInput::new("Your name"));
+//! let ok = ui.add(root, Button::new("OK"));
+//!
+//! loop {
+//!     let event = app.wait_event();
+//!     if let Some((id, Action::Clicked)) = ui.handle_event(&event) {
+//!         if id == ok { /* read ui.widget::<TextInput>(name) */ }
+//!     }
+//!     ui.render(&mut surface);
+//! }
+//! ```
+//!
+//! Containers (`Flex`, `ScrollView`) place their children; the others
+//! are leaves. New widgets implement `Widget`.
+//!
+//! Everything works from the keyboard: Tab and Shift+Tab walk the
+//! focusable widgets in tree order and the focused one wears a ring.
+//! Widgets say what they are with a `Role` and a label, which
+//! `Ui::describe` hands to assistive tools such as a narrator.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::any::Any;
+
+use libipc::messages::{Rect, ThemeSpec};
+
+use crate::color::Color;
+use crate::event::KeyEvent;
+use crate::font::{FONT_HEIGHT, FONT_WIDTH};
+use crate::surface::Surface;
+
+mod controls;
+mod layout;
+mod scroll;
+mod text_area;
+mod tree;
+
+pub use controls::{Button, Label, List, TextInput};
+pub use layout::{Axis, Flex};
+pub use scroll::ScrollView;
+pub use text_area::TextArea;
+pub use tree::Ui;
+
+/// Handle of a widget in a `Ui`
+pub type WidgetId = usize;
+
+/// Scancodes of the keys widgets use besides characters
+pub(crate) mod keys {
+    pub const UP: u8 = 0x48;
+    pub const DOWN: u8 = 0x50;
+    pub const LEFT: u8 = 0x4B;
+    pub const RIGHT: u8 = 0x4D;
+    pub const HOME: u8 = 0x47;
+    pub const END: u8 = 0x4F;
+    pub const DELETE: u8 = 0x53;
+    pub const ESCAPE: u8 = 0x01;
+    pub const A: u8 = 0x1E;
+    pub const C: u8 = 0x2E;
+    pub const V: u8 = 0x2F;
+    pub const X: u8 = 0x2D;
+    pub const Y: u8 = 0x15;
+    pub const Z: u8 = 0x2C;
+}
+
+/// Width and height in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Size {
+    pub const fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    /// Size of `text` in the built-in font
+    pub fn of_text(text: &str) -> Self {
+        Self::new(text.len() as u32 * FONT_WIDTH, FONT_HEIGHT)
+    }
+}
+
+/// What a container knows of a child when laying it out
+#[derive(Debug, Clone, Copy)]
+pub struct Child {
+    /// Size the child would like
+    pub size: Size,
+    /// Share of the space left over that the child takes; 0 keeps it at
+    /// its preferred size
+    pub flex: u32,
+}
+
+/// How a widget is being shown
+#[derive(Debug, Clone, Copy, Default)]
+pub struct State {
+    /// Keys go to it
+    pub focused: bool,
+    /// The pointer is over it
+    pub hovered: bool,
+    /// The left button went down on it and is still held
+    pub pressed: bool,
+}
+
+/// Events a widget receives; positions are in surface coordinates
+#[derive(Debug, Clone, Copy)]
+pub enum WidgetEvent {
+    /// A key was pressed while the widget had focus
+    Key(KeyEvent),
+    /// The left button went down over the widget
+    MouseDown { x: i32, y: i32 },
+    /// The left button came up after going down over the widget, over it
+    /// or not
+    MouseUp { x: i32, y: i32 },
+    /// The pointer moved while the left button is held on the widget
+    MouseDrag { x: i32, y: i32 },
+    /// The wheel turned over the widget; positive scrolls towards the start
+    Scroll { delta: i16 },
+    /// The widget gained focus
+    FocusIn,
+    /// The widget lost focus
+    FocusOut,
+}
+
+/// What a widget is, for assistive tools
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Lays out other widgets
+    Group,
+    Label,
+    Button,
+    TextInput,
+    TextArea,
+    List,
+    ScrollView,
+    /// Anything else, such as an application's own drawing
+    Other,
+}
+
+/// A widget as assistive tools see it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Description {
+    pub role: Role,
+    /// Name of the widget: a button's text, a label's, or what the
+    /// application set with `Ui::set_label`
+    pub label: String,
+    /// Where the widget is, in surface coordinates
+    pub bounds: Rect,
+    pub focused: bool,
+    pub focusable: bool,
+}
+
+/// Something a widget did that the application may act on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// A button was clicked
+    Clicked,
+    /// Text was edited
+    Changed,
+    /// Enter was pressed in a text input or a list
+    Submitted,
+    /// A list item was selected
+    Selected(usize),
+    /// Text was copied or cut, and waits in `TextArea::take_copied` to go
+    /// on the clipboard
+    Copy,
+    /// Paste was asked for; the clipboard's text goes in with
+    /// `TextArea::insert`
+    Paste,
+}
+
+/// What a widget made of an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Response {
+    /// Not for this widget; the event goes on to its container
+    Ignored,
+    /// Used, with nothing to redraw
+    Handled,
+    /// Used; the widget looks different now
+    Repaint,
+    /// Used; the widget's children moved or its size changed
+    Relayout,
+    /// Used; the widget looks different and the application is told
+    Action(Action),
+}
+
+/// Colors and metrics widgets draw with, taken from the desktop theme
+#[derive(Debug, Clone, Copy)]
+pub struct Style {
+    pub background: Color,
+    pub text: Color,
+    pub text_dim: Color,
+    /// Focus rings, selections and pressed buttons
+    pub accent: Color,
+    /// Face of buttons and text inputs
+    pub control: Color,
+    pub border: Color,
+    pub border_width: u32,
+    /// Ring around the widget with focus
+    pub focus_ring: Color,
+    pub focus_width: u32,
+}
+
+impl Style {
+    /// Colors from `theme`; a high-contrast one also draws dimmed text
+    /// like any other, controls on the background and wider rings
+    pub fn from_theme(theme: &ThemeSpec) -> Self {
+        let text = Color::from_rgb32(theme.panel_text);
+        let background = Color::from_rgb32(theme.window_bg);
+        let border_width = theme.border_width.max(1) as u32;
+        if theme.high_contrast {
+            return Self {
+                background,
+                text,
+                text_dim: text,
+                accent: Color::from_rgb32(theme.accent),
+                control: background,
+                border: text,
+                border_width: border_width.max(2),
+                focus_ring: Color::from_rgb32(theme.accent),
+                focus_width: 3,
+            };
+        }
+        Self {
+            background,
+            text,
+            text_dim: Color::from_rgb32(theme.text_dim),
+            accent: Color::from_rgb32(theme.accent),
+            control: Color::from_rgb32(theme.panel_bg),
+            border: Color::from_rgb32(theme.window_border),
+            border_width,
+            focus_ring: Color::from_rgb32(theme.accent),
+            focus_width: 2,
+        }
+    }
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self::from_theme(&ThemeSpec::NORD)
+    }
+}
+
+/// A part of a `Ui`
+pub trait Widget: Any {
+    /// Size the widget would like; containers are given their children's
+    fn measure(&self, children: &[Child]) -> Size;
+
+    /// Place the children in `bounds`, a rectangle per child; leaves have
+    /// no children to place
+    fn arrange(&mut self, _bounds: Rect, _children: &[Child]) -> Vec<Rect> {
+        Vec::new()
+    }
+
+    /// Draw the widget in `bounds`; its children are drawn over it
+    fn draw(&self, canvas: &mut Canvas<'_>, bounds: Rect, state: State, style: &Style);
+
+    /// Handle an event; `bounds` is where the widget was last laid out
+    fn event(&mut self, _event: &WidgetEvent, _bounds: Rect) -> Response {
+        Response::Ignored
+    }
+
+    /// Whether Tab and clicks give the widget focus
+    fn focusable(&self) -> bool {
+        false
+    }
+
+    /// What the widget is, for assistive tools
+    fn role(&self) -> Role {
+        Role::Other
+    }
+
+    /// Name of the widget for assistive tools, usually the text it shows
+    fn label(&self) -> &str {
+        ""
+    }
+}
+
+/// Drawing limited to a rectangle of a surface: the part of a widget that
+/// is damaged and not hidden by its containers
+pub struct Canvas<'a> {
+    surface: &'a mut Surface,
+    clip: Rect,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(surface: &'a mut Surface, clip: Rect) -> Self {
+        Self { surface, clip }
+    }
+
+    /// The rectangle drawing is limited to
+    pub fn clip(&self) -> Rect {
+        self.clip
+    }
+
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        if let Some(rect) = rect.intersection(&self.clip) {
+            self.surface.fill_rect(rect.x as u32, rect.y as u32, rect.width, rect.height, color);
+        }
+    }
+
+    /// Draw a frame `width` pixels wide just inside `rect`
+    pub fn draw_frame(&mut self, rect: Rect, width: u32, color: Color) {
+        let w = width.min(rect.width / 2).min(rect.height / 2);
+        let inner_height = rect.height - 2 * w;
+        self.fill_rect(Rect::new(rect.x, rect.y, rect.width, w), color);
+        self.fill_rect(Rect::new(rect.x, rect.bottom() - w as i32, rect.width, w), color);
+        self.fill_rect(Rect::new(rect.x, rect.y + w as i32, w, inner_height), color);
+        let right = rect.right() - w as i32;
+        self.fill_rect(Rect::new(right, rect.y + w as i32, w, inner_height), color);
+    }
+
+    /// Draw with any of the surface's operations, such as its shapes,
+    /// kept inside the clip rectangle
+    pub fn draw_with(&mut self, draw: impl FnOnce(&mut Surface)) {
+        self.surface.push_clip(self.clip);
+        draw(self.surface);
+        self.surface.pop_clip();
+    }
+
+    /// Draw text with its top-left corner at (x, y); characters that do
+    /// not fit in the clip rectangle whole are left out
+    pub fn draw_text(&mut self, x: i32, y: i32, text: &str, color: Color) {
+        let mut cx = x;
+        for ch in text.bytes() {
+            let glyph = Rect::new(cx, y, FONT_WIDTH, FONT_HEIGHT);
+            if glyph.intersection(&self.clip) == Some(glyph) {
+                self.surface.draw_char_transparent(cx as u32, y as u32, ch, color);
+            }
+            cx += FONT_WIDTH as i32;
+        }
+    }
+
+    /// Draw text centered vertically in `rect`, starting `inset` pixels
+    /// from its left edge
+    pub fn draw_label(&mut self, rect: Rect, inset: u32, text: &str, color: Color) {
+        let y = rect.y + (rect.height.saturating_sub(FONT_HEIGHT) / 2) as i32;
+        self.draw_text(rect.x + inset as i32, y, text, color);
+    }
+}
+
+/// `rect` with `inset` pixels taken off every side
+pub(crate) fn shrink(rect: Rect, inset: u32) -> Rect {
+    Rect::new(
+        rect.x + inset as i32,
+        rect.y + inset as i32,
+        rect.width.saturating_sub(2 * inset),
+        rect.height.saturating_sub(2 * inset),
+    )
+}
@@ -0,0 +1,114 @@
+//! Scrolling
+//!
+//! `ScrollView` shows part of a child taller than itself and moves it with
+//! the mouse wheel. The child gets its preferred height, or the view's if
+//! that is more; a scroll bar on the right shows where the view is.
+
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use libipc::messages::Rect;
+
+use super::{Canvas, Child, Response, Role, Size, State, Style, Widget, WidgetEvent};
+use crate::font::FONT_HEIGHT;
+
+/// Width of the scroll bar
+const BAR_WIDTH: u32 = 6;
+
+/// Pixels one step of the wheel scrolls
+const WHEEL_STEP: u32 = 3 * FONT_HEIGHT;
+
+/// A view onto part of its one child
+pub struct ScrollView {
+    /// Pixels of the child scrolled out of view at the top
+    offset: u32,
+    /// Height of the child and of the view, when last laid out
+    content: u32,
+    viewport: u32,
+}
+
+impl ScrollView {
+    pub fn new() -> Self {
+        Self { offset: 0, content: 0, viewport: 0 }
+    }
+
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Scroll so `offset` pixels of the child are above the view; takes
+    /// effect at the next layout
+    pub fn scroll_to(&mut self, offset: u32) {
+        self.offset = offset;
+    }
+
+    fn max_offset(&self) -> u32 {
+        self.content.saturating_sub(self.viewport)
+    }
+}
+
+impl Default for ScrollView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Widget for ScrollView {
+    fn measure(&self, children: &[Child]) -> Size {
+        let child = children.first().map_or(Size::default(), |child| child.size);
+        Size::new(child.width + BAR_WIDTH, child.height)
+    }
+
+    fn arrange(&mut self, bounds: Rect, children: &[Child]) -> Vec<Rect> {
+        let Some(child) = children.first() else {
+            return Vec::new();
+        };
+        self.viewport = bounds.height;
+        self.content = child.size.height.max(bounds.height);
+        self.offset = self.offset.min(self.max_offset());
+
+        let width = bounds.width.saturating_sub(BAR_WIDTH);
+        let top = bounds.y - self.offset as i32;
+        let mut rects = vec![Rect::new(bounds.x, top, width, self.content)];
+        // Only the first child is shown
+        rects.resize(children.len(), Rect::new(bounds.x, bounds.y, 0, 0));
+        rects
+    }
+
+    fn draw(&self, canvas: &mut Canvas<'_>, bounds: Rect, _state: State, style: &Style) {
+        canvas.fill_rect(bounds, style.background);
+        if self.content <= self.viewport || self.content == 0 {
+            return;
+        }
+
+        let x = bounds.right() - BAR_WIDTH as i32;
+        canvas.fill_rect(Rect::new(x, bounds.y, BAR_WIDTH, bounds.height), style.control);
+        let thumb = (bounds.height as u64 * self.viewport as u64 / self.content as u64) as u32;
+        let top = (bounds.height as u64 * self.offset as u64 / self.content as u64) as i32;
+        let thumb = Rect::new(x, bounds.y + top, BAR_WIDTH, thumb.max(BAR_WIDTH));
+        canvas.fill_rect(thumb, style.text_dim);
+    }
+
+    fn event(&mut self, event: &WidgetEvent, _bounds: Rect) -> Response {
+        let WidgetEvent::Scroll { delta } = *event else {
+            return Response::Ignored;
+        };
+        let step = WHEEL_STEP * delta.unsigned_abs() as u32;
+        let offset = if delta > 0 {
+            self.offset.saturating_sub(step)
+        } else {
+            (self.offset + step).min(self.max_offset())
+        };
+        if offset == self.offset {
+            return Response::Ignored;
+        }
+        self.offset = offset;
+        Response::Relayout
+    }
+
+    fn role(&self) -> Role {
+        Role::ScrollView
+    }
+}
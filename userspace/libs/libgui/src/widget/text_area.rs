@@ -0,0 +1,579 @@
+//! Text Area
+//!
+//! `TextArea` edits many lines of text, for editors and longer input. The
+//! text lives in a gap buffer: the free space sits where the last edit
+//! was, so typing in one place moves no text. A selection runs from an
+//! anchor to the cursor; shifted movement keys and dragging extend it.
+//! Edits go on an undo stack, with runs of typing undone as one.
+//!
+//! Widgets cannot reach the clipboard, so Ctrl+C and Ctrl+X report
+//! `Action::Copy` with the text waiting in `take_copied`, and Ctrl+V
+//! reports `Action::Paste` for the application to `insert` what the
+//! clipboard holds. Like the other controls it keeps to printable ASCII.
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use libipc::messages::Rect;
+
+use super::{
+    keys, shrink, Action, Canvas, Child, Response, Role, Size, State, Style, Widget, WidgetEvent,
+};
+use crate::event::KeyEvent;
+use crate::font::{FONT_HEIGHT, FONT_WIDTH};
+
+/// Pixels between the frame and the text
+const INSET: u32 = 4;
+
+/// Edits kept for undoing; older ones are forgotten
+const MAX_UNDO: usize = 256;
+
+/// Smallest gap a full buffer grows by
+const MIN_GAP: usize = 64;
+
+/// Lines one step of the wheel scrolls
+const WHEEL_LINES: usize = 3;
+
+/// Text with a gap where it was last edited
+struct GapBuffer {
+    bytes: Vec<u8>,
+    gap: Range<usize>,
+}
+
+impl GapBuffer {
+    fn new() -> Self {
+        Self { bytes: Vec::new(), gap: 0..0 }
+    }
+
+    fn len(&self) -> usize {
+        self.bytes.len() - self.gap.len()
+    }
+
+    fn byte(&self, at: usize) -> u8 {
+        if at < self.gap.start {
+            self.bytes[at]
+        } else {
+            self.bytes[at + self.gap.len()]
+        }
+    }
+
+    fn text(&self, range: Range<usize>) -> String {
+        range.map(|at| self.byte(at) as char).collect()
+    }
+
+    /// Move the gap to just before `at`
+    fn move_gap(&mut self, at: usize) {
+        let len = self.gap.len();
+        if at < self.gap.start {
+            self.bytes.copy_within(at..self.gap.start, at + len);
+        } else if at > self.gap.start {
+            let moved = at - self.gap.start;
+            self.bytes.copy_within(self.gap.end..self.gap.end + moved, self.gap.start);
+        }
+        self.gap = at..at + len;
+    }
+
+    fn insert(&mut self, at: usize, text: &[u8]) {
+        self.move_gap(at);
+        if self.gap.len() < text.len() {
+            let grow = text.len().max(self.bytes.len() / 2).max(MIN_GAP);
+            self.bytes.splice(self.gap.end..self.gap.end, core::iter::repeat_n(0, grow));
+            self.gap.end += grow;
+        }
+        self.bytes[self.gap.start..self.gap.start + text.len()].copy_from_slice(text);
+        self.gap.start += text.len();
+    }
+
+    fn remove(&mut self, range: Range<usize>) -> String {
+        let removed = self.text(range.clone());
+        self.move_gap(range.start);
+        self.gap.end += range.len();
+        removed
+    }
+}
+
+/// A change to the text, with what it takes to make and unmake it
+struct Edit {
+    at: usize,
+    removed: String,
+    inserted: String,
+}
+
+/// Many lines of editable text
+pub struct TextArea {
+    buffer: GapBuffer,
+    /// Where each line starts
+    lines: Vec<usize>,
+    /// Byte offset the next character goes in at
+    cursor: usize,
+    /// Other end of the selection; the same as `cursor` without one
+    anchor: usize,
+    /// Column up and down keep to across shorter lines
+    goal: Option<usize>,
+    /// First line and column shown
+    top: usize,
+    left: usize,
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    /// The last edit was typing, which the next character joins
+    typing: bool,
+    /// Text copied or cut, until the application takes it
+    copied: Option<String>,
+}
+
+impl TextArea {
+    pub fn new() -> Self {
+        Self {
+            buffer: GapBuffer::new(),
+            lines: vec![0],
+            cursor: 0,
+            anchor: 0,
+            goal: None,
+            top: 0,
+            left: 0,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            typing: false,
+            copied: None,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.buffer.text(0..self.buffer.len())
+    }
+
+    /// Replace the text, with the cursor at the start and nothing to undo
+    pub fn set_text(&mut self, text: &str) {
+        self.buffer = GapBuffer::new();
+        self.buffer.insert(0, &filter(text));
+        self.index_lines();
+        self.cursor = 0;
+        self.anchor = 0;
+        self.goal = None;
+        self.top = 0;
+        self.left = 0;
+        self.undo.clear();
+        self.redo.clear();
+        self.typing = false;
+    }
+
+    /// Length of the text in bytes
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.len() == 0
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Line and column of the cursor, from 0
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let line = self.line_of(self.cursor);
+        (line, self.cursor - self.lines[line])
+    }
+
+    /// Move the cursor to byte offset `at`, dropping the selection
+    pub fn set_cursor(&mut self, at: usize) {
+        self.cursor = at.min(self.len());
+        self.anchor = self.cursor;
+        self.goal = None;
+    }
+
+    /// The selected bytes, if any are
+    pub fn selection(&self) -> Option<Range<usize>> {
+        (self.anchor != self.cursor)
+            .then(|| self.anchor.min(self.cursor)..self.anchor.max(self.cursor))
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        self.selection().map(|range| self.buffer.text(range))
+    }
+
+    /// Select `range`, with the cursor at its end
+    pub fn select(&mut self, range: Range<usize>) {
+        self.anchor = range.start.min(self.len());
+        self.cursor = range.end.min(self.len());
+        self.goal = None;
+    }
+
+    /// Put `text` in place of the selection, or at the cursor
+    pub fn insert(&mut self, text: &str) {
+        let range = self.selection().unwrap_or(self.cursor..self.cursor);
+        self.replace(range, &filter(text));
+        self.typing = false;
+    }
+
+    /// Remove the selected text; false if nothing is selected
+    pub fn delete_selection(&mut self) -> bool {
+        let Some(range) = self.selection() else {
+            return false;
+        };
+        self.replace(range, &[]);
+        self.typing = false;
+        true
+    }
+
+    /// Text Ctrl+C or Ctrl+X copied since it was last taken, for the
+    /// application to put on the clipboard
+    pub fn take_copied(&mut self) -> Option<String> {
+        self.copied.take()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Take back the last edit; false if there is none
+    pub fn undo(&mut self) -> bool {
+        let Some(edit) = self.undo.pop() else {
+            return false;
+        };
+        self.buffer.remove(edit.at..edit.at + edit.inserted.len());
+        self.buffer.insert(edit.at, edit.removed.as_bytes());
+        self.set_cursor(edit.at + edit.removed.len());
+        self.index_lines();
+        self.redo.push(edit);
+        self.typing = false;
+        true
+    }
+
+    /// Make the last edit taken back again; false if there is none
+    pub fn redo(&mut self) -> bool {
+        let Some(edit) = self.redo.pop() else {
+            return false;
+        };
+        self.buffer.remove(edit.at..edit.at + edit.removed.len());
+        self.buffer.insert(edit.at, edit.inserted.as_bytes());
+        self.set_cursor(edit.at + edit.inserted.len());
+        self.index_lines();
+        self.undo.push(edit);
+        self.typing = false;
+        true
+    }
+
+    /// Replace `range` with `text` as an edit that can be undone, and put
+    /// the cursor after it
+    fn replace(&mut self, range: Range<usize>, text: &[u8]) {
+        if range.is_empty() && text.is_empty() {
+            return;
+        }
+        let removed = self.buffer.remove(range.clone());
+        self.buffer.insert(range.start, text);
+        let inserted = text.iter().map(|&byte| byte as char).collect();
+        self.push_undo(Edit { at: range.start, removed, inserted });
+        self.set_cursor(range.start + text.len());
+        self.index_lines();
+    }
+
+    /// Type `byte` at the cursor, joining the edit of the typing before it
+    fn type_byte(&mut self, byte: u8, typing: bool) {
+        let joins = typing && self.selection().is_none();
+        match self.undo.last_mut() {
+            Some(last) if joins && last.at + last.inserted.len() == self.cursor => {
+                self.buffer.insert(self.cursor, &[byte]);
+                last.inserted.push(byte as char);
+                self.redo.clear();
+                self.set_cursor(self.cursor + 1);
+                self.index_lines();
+            }
+            _ => {
+                let range = self.selection().unwrap_or(self.cursor..self.cursor);
+                self.replace(range, &[byte]);
+            }
+        }
+        self.typing = true;
+    }
+
+    fn push_undo(&mut self, edit: Edit) {
+        if self.undo.len() == MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.undo.push(edit);
+        self.redo.clear();
+    }
+
+    fn index_lines(&mut self) {
+        self.lines.clear();
+        self.lines.push(0);
+        for at in 0..self.buffer.len() {
+            if self.buffer.byte(at) == b'\n' {
+                self.lines.push(at + 1);
+            }
+        }
+    }
+
+    fn line_of(&self, at: usize) -> usize {
+        self.lines.partition_point(|&start| start <= at) - 1
+    }
+
+    /// Offset of the end of `line`, before its newline
+    fn line_end(&self, line: usize) -> usize {
+        self.lines.get(line + 1).map_or(self.len(), |next| next - 1)
+    }
+
+    /// Move the cursor to `at`, extending the selection if `extend`
+    fn move_to(&mut self, at: usize, extend: bool) -> Response {
+        let (cursor, anchor) = (self.cursor, self.anchor);
+        self.cursor = at.min(self.len());
+        self.goal = None;
+        if !extend {
+            self.anchor = self.cursor;
+        }
+        if (self.cursor, self.anchor) == (cursor, anchor) {
+            Response::Handled
+        } else {
+            Response::Repaint
+        }
+    }
+
+    /// Move the cursor `lines` up or down, keeping to its column
+    fn move_lines(&mut self, lines: isize, extend: bool) -> Response {
+        let (line, column) = self.cursor_position();
+        let goal = *self.goal.get_or_insert(column);
+        let target = line.saturating_add_signed(lines).min(self.lines.len() - 1);
+        let at = (self.lines[target] + goal).min(self.line_end(target));
+        let response = self.move_to(at, extend);
+        self.goal = Some(goal);
+        response
+    }
+
+    /// Text offset of the character nearest to (x, y)
+    fn offset_at(&self, bounds: Rect, x: i32, y: i32) -> usize {
+        let inner = shrink(bounds, INSET);
+        let row = (y - inner.y).max(0) as usize / FONT_HEIGHT as usize;
+        let line = (self.top + row).min(self.lines.len() - 1);
+        let half = FONT_WIDTH as i32 / 2;
+        let column = self.left + (x - inner.x + half).max(0) as usize / FONT_WIDTH as usize;
+        (self.lines[line] + column).min(self.line_end(line))
+    }
+
+    /// Lines and columns that fit in `bounds`
+    fn visible(bounds: Rect) -> (usize, usize) {
+        let inner = shrink(bounds, INSET);
+        let rows = (inner.height / FONT_HEIGHT).max(1);
+        let columns = (inner.width / FONT_WIDTH).max(1);
+        (rows as usize, columns as usize)
+    }
+
+    /// Scroll so the cursor is shown
+    fn reveal(&mut self, bounds: Rect) {
+        let (rows, columns) = Self::visible(bounds);
+        let (line, column) = self.cursor_position();
+        self.top = self.top.clamp((line + 1).saturating_sub(rows), line);
+        self.left = self.left.clamp((column + 1).saturating_sub(columns), column);
+    }
+
+    fn key(&mut self, key: KeyEvent, typing: bool) -> Response {
+        let extend = key.modifiers.shift;
+        if key.modifiers.ctrl {
+            return match key.scancode {
+                keys::A => {
+                    self.select(0..self.len());
+                    Response::Repaint
+                }
+                keys::C | keys::X => {
+                    let Some(text) = self.selected_text() else {
+                        return Response::Handled;
+                    };
+                    self.copied = Some(text);
+                    if key.scancode == keys::X {
+                        self.delete_selection();
+                    }
+                    Response::Action(Action::Copy)
+                }
+                keys::V => Response::Action(Action::Paste),
+                keys::Z if !extend => edited(self.undo()),
+                keys::Z | keys::Y => edited(self.redo()),
+                keys::HOME => self.move_to(0, extend),
+                keys::END => self.move_to(self.len(), extend),
+                _ => Response::Ignored,
+            };
+        }
+
+        if let Some(ch) = key.as_char() {
+            self.type_byte(ch as u8, typing);
+            return Response::Action(Action::Changed);
+        }
+        let selection = self.selection();
+        let line = self.line_of(self.cursor);
+        match (key.character, key.scancode) {
+            (b'\n', _) => {
+                self.insert("\n");
+                Response::Action(Action::Changed)
+            }
+            (0x08, _) => {
+                let start = self.cursor.saturating_sub(1);
+                edited(match selection {
+                    Some(_) => self.delete_selection(),
+                    None => self.cut_range(start..self.cursor),
+                })
+            }
+            (_, keys::DELETE) => {
+                let end = (self.cursor + 1).min(self.len());
+                edited(match selection {
+                    Some(_) => self.delete_selection(),
+                    None => self.cut_range(self.cursor..end),
+                })
+            }
+            // Without shift, left and right leave the selection at its ends
+            (_, keys::LEFT) => match selection {
+                Some(range) if !extend => self.move_to(range.start, false),
+                _ => self.move_to(self.cursor.saturating_sub(1), extend),
+            },
+            (_, keys::RIGHT) => match selection {
+                Some(range) if !extend => self.move_to(range.end, false),
+                _ => self.move_to(self.cursor + 1, extend),
+            },
+            (_, keys::UP) => self.move_lines(-1, extend),
+            (_, keys::DOWN) => self.move_lines(1, extend),
+            (_, keys::HOME) => self.move_to(self.lines[line], extend),
+            (_, keys::END) => self.move_to(self.line_end(line), extend),
+            _ => Response::Ignored,
+        }
+    }
+
+    /// Remove `range` as an edit; false if it is empty
+    fn cut_range(&mut self, range: Range<usize>) -> bool {
+        if range.is_empty() {
+            return false;
+        }
+        self.replace(range, &[]);
+        true
+    }
+}
+
+impl Default for TextArea {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Widget for TextArea {
+    fn measure(&self, _children: &[Child]) -> Size {
+        Size::new(40 * FONT_WIDTH + 2 * INSET, 8 * FONT_HEIGHT + 2 * INSET)
+    }
+
+    fn draw(&self, canvas: &mut Canvas<'_>, bounds: Rect, state: State, style: &Style) {
+        let frame = if state.focused { style.accent } else { style.border };
+        canvas.fill_rect(bounds, style.control);
+        canvas.draw_frame(bounds, style.border_width, frame);
+
+        let inner = shrink(bounds, INSET);
+        let (rows, columns) = Self::visible(bounds);
+        let selection = self.selection();
+        let highlight = if state.focused { style.accent } else { style.border };
+        // Only the rows in the clip rectangle
+        let clip = canvas.clip();
+        let height = FONT_HEIGHT as i32;
+        let first = ((clip.y - inner.y).max(0) / height) as usize;
+        let last = (((clip.bottom() - inner.y).max(0) / height + 1) as usize).min(rows);
+        for row in first..last {
+            let line = self.top + row;
+            if line >= self.lines.len() {
+                break;
+            }
+            let (start, end) = (self.lines[line], self.line_end(line));
+            let y = inner.y + row as i32 * height;
+            let column_x = |at: usize| {
+                let column = (at - start).saturating_sub(self.left).min(columns);
+                inner.x + (column as u32 * FONT_WIDTH) as i32
+            };
+
+            // A selected newline shows as one more column
+            if let Some(range) = &selection {
+                let (from, to) = (range.start.max(start), range.end.min(end + 1));
+                if from < to {
+                    let x = column_x(from);
+                    let width = (column_x(to.min(end)) - x) as u32
+                        + if to > end { FONT_WIDTH } else { 0 };
+                    canvas.fill_rect(Rect::new(x, y, width, FONT_HEIGHT), highlight);
+                }
+            }
+
+            let shown = (start + self.left).min(end)..(start + self.left + columns).min(end);
+            canvas.draw_text(inner.x, y, &self.buffer.text(shown), style.text);
+
+            if state.focused && self.line_of(self.cursor) == line {
+                let x = column_x(self.cursor);
+                canvas.fill_rect(Rect::new(x, y, 1, FONT_HEIGHT), style.text);
+            }
+        }
+    }
+
+    fn event(&mut self, event: &WidgetEvent, bounds: Rect) -> Response {
+        let typing = core::mem::take(&mut self.typing);
+        let response = match *event {
+            WidgetEvent::Key(key) => self.key(key, typing),
+            WidgetEvent::MouseDown { x, y } => {
+                self.set_cursor(self.offset_at(bounds, x, y));
+                Response::Repaint
+            }
+            WidgetEvent::MouseDrag { x, y } => self.move_to(self.offset_at(bounds, x, y), true),
+            WidgetEvent::Scroll { delta } => {
+                let step = WHEEL_LINES * delta.unsigned_abs() as usize;
+                let top = if delta > 0 {
+                    self.top.saturating_sub(step)
+                } else {
+                    (self.top + step).min(self.lines.len() - 1)
+                };
+                if top == self.top {
+                    return Response::Ignored;
+                }
+                self.top = top;
+                return Response::Repaint;
+            }
+            WidgetEvent::FocusIn | WidgetEvent::FocusOut => return Response::Repaint,
+            _ => return Response::Ignored,
+        };
+        self.reveal(bounds);
+        response
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+
+    fn role(&self) -> Role {
+        Role::TextArea
+    }
+}
+
+/// What an edit that may have changed nothing did
+fn edited(changed: bool) -> Response {
+    if changed {
+        Response::Action(Action::Changed)
+    } else {
+        Response::Handled
+    }
+}
+
+/// The bytes of `text` a text area keeps: printable ASCII and newlines,
+/// with tabs as spaces
+fn filter(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\t' => bytes.extend_from_slice(b"    "),
+            '\n' => bytes.push(b'\n'),
+            _ if ch.is_ascii() && !ch.is_ascii_control() => bytes.push(ch as u8),
+            _ => {}
+        }
+    }
+    bytes
+}
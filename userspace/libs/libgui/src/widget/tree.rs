@@ -0,0 +1,530 @@
+//! Widget Tree
+//!
+//! `Ui` owns the widgets of a window and everything that happens between
+//! them: the tree they form, where layout put them, which one has focus,
+//! which is under the pointer and which the left button went down on.
+//!
+//! Drawing is retained: a widget is only redrawn when something marks its
+//! rectangle damaged - an event it handled, a change through `widget_mut`,
+//! a layout that moved it or an expose from the compositor. `render` draws
+//! what is damaged and presents that rectangle alone.
+//!
+//! The focused widget is drawn with a ring in the style's focus color on
+//! top of whatever it draws itself, so focus shows the same everywhere.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+
+use libipc::messages::{Rect, ThemeSpec};
+
+use super::{
+    Action, Canvas, Child, Description, Response, Size, State, Style, Widget, WidgetEvent,
+    WidgetId,
+};
+use crate::event::{Event, MouseButton, MouseEvent, WindowEvent};
+use crate::surface::Surface;
+
+struct Node {
+    widget: Box<dyn Widget>,
+    parent: Option<WidgetId>,
+    children: Vec<WidgetId>,
+    flex: u32,
+    /// Size the widget asked for at the last layout
+    size: Size,
+    /// Where the last layout put the widget
+    bounds: Rect,
+    /// Name for assistive tools in place of the widget's own
+    label: Option<String>,
+}
+
+/// The widgets of a window
+pub struct Ui {
+    /// Widgets by id; removed ones leave a hole that the next one fills
+    nodes: Vec<Option<Node>>,
+    root: WidgetId,
+    focus: Option<WidgetId>,
+    hover: Option<WidgetId>,
+    pressed: Option<WidgetId>,
+    style: Style,
+    width: u32,
+    height: u32,
+    needs_layout: bool,
+    /// Part of the window to redraw at the next render
+    damage: Option<Rect>,
+}
+
+impl Ui {
+    /// Widgets filling a `width` by `height` window, under `root`
+    pub fn new(root: impl Widget, width: u32, height: u32) -> Self {
+        let bounds = Rect::new(0, 0, width, height);
+        let node = Node {
+            widget: Box::new(root),
+            parent: None,
+            children: Vec::new(),
+            flex: 1,
+            size: Size::default(),
+            bounds,
+            label: None,
+        };
+        Self {
+            nodes: vec![Some(node)],
+            root: 0,
+            focus: None,
+            hover: None,
+            pressed: None,
+            style: Style::default(),
+            width,
+            height,
+            needs_layout: true,
+            damage: Some(bounds),
+        }
+    }
+
+    pub fn root(&self) -> WidgetId {
+        self.root
+    }
+
+    /// Add `widget` as the last child of `parent`
+    pub fn add(&mut self, parent: WidgetId, widget: impl Widget) -> WidgetId {
+        let node = Node {
+            widget: Box::new(widget),
+            parent: Some(parent),
+            children: Vec::new(),
+            flex: 0,
+            size: Size::default(),
+            bounds: Rect::new(0, 0, 0, 0),
+            label: None,
+        };
+        let id = match self.nodes.iter().position(Option::is_none) {
+            Some(free) => {
+                self.nodes[free] = Some(node);
+                free
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        if let Some(parent) = self.node_mut(parent) {
+            parent.children.push(id);
+        }
+        self.needs_layout = true;
+        id
+    }
+
+    /// Have `id` take `flex` shares of the space its container has left
+    /// over; 0, the default, keeps it at the size it asks for
+    pub fn set_flex(&mut self, id: WidgetId, flex: u32) {
+        if let Some(node) = self.node_mut(id) {
+            node.flex = flex;
+            self.needs_layout = true;
+        }
+    }
+
+    /// Remove `id` and its children; the root stays
+    pub fn remove(&mut self, id: WidgetId) {
+        if id == self.root {
+            return;
+        }
+        let Some(node) = self.node(id) else {
+            return;
+        };
+        let (parent, bounds) = (node.parent, node.bounds);
+        if let Some(parent) = parent.and_then(|parent| self.node_mut(parent)) {
+            parent.children.retain(|&child| child != id);
+        }
+        self.damage(bounds);
+        self.needs_layout = true;
+
+        let mut doomed = vec![id];
+        while let Some(id) = doomed.pop() {
+            if let Some(node) = self.nodes[id].take() {
+                doomed.extend(node.children);
+            }
+            for slot in [&mut self.focus, &mut self.hover, &mut self.pressed] {
+                if *slot == Some(id) {
+                    *slot = None;
+                }
+            }
+        }
+    }
+
+    /// The widget `id`, if it is a `W`
+    pub fn widget<W: Widget>(&self, id: WidgetId) -> Option<&W> {
+        let widget: &dyn Any = self.node(id)?.widget.as_ref();
+        widget.downcast_ref()
+    }
+
+    /// The widget `id`, if it is a `W`, to change; it is redrawn and laid
+    /// out again
+    pub fn widget_mut<W: Widget>(&mut self, id: WidgetId) -> Option<&mut W> {
+        self.invalidate(id);
+        self.needs_layout = true;
+        let widget: &mut dyn Any = self.node_mut(id)?.widget.as_mut();
+        widget.downcast_mut()
+    }
+
+    /// Size the widgets ask for, e.g. to open a window that fits them
+    pub fn preferred_size(&mut self) -> Size {
+        self.measure(self.root).size
+    }
+
+    /// Where the last layout put `id`
+    pub fn bounds(&self, id: WidgetId) -> Option<Rect> {
+        self.node(id).map(|node| node.bounds)
+    }
+
+    /// The widgets `id` contains, in order
+    pub fn children(&self, id: WidgetId) -> &[WidgetId] {
+        self.node(id).map_or(&[], |node| &node.children)
+    }
+
+    /// Name `id` for assistive tools, e.g. a text input after the label
+    /// next to it, in place of what it says of itself
+    pub fn set_label(&mut self, id: WidgetId, label: &str) {
+        if let Some(node) = self.node_mut(id) {
+            node.label = Some(String::from(label));
+        }
+    }
+
+    /// `id` as assistive tools see it
+    pub fn describe(&self, id: WidgetId) -> Option<Description> {
+        let node = self.node(id)?;
+        let label = node.label.as_deref().unwrap_or(node.widget.label());
+        Some(Description {
+            role: node.widget.role(),
+            label: String::from(label),
+            bounds: node.bounds,
+            focused: self.focus == Some(id),
+            focusable: node.widget.focusable(),
+        })
+    }
+
+    pub fn focus(&self) -> Option<WidgetId> {
+        self.focus
+    }
+
+    /// Give keys to `id`, or to no widget
+    pub fn set_focus(&mut self, id: Option<WidgetId>) {
+        if id == self.focus || id.is_some_and(|id| self.node(id).is_none()) {
+            return;
+        }
+        if let Some(old) = self.focus {
+            self.deliver(old, WidgetEvent::FocusOut, false);
+            self.invalidate(old);
+        }
+        self.focus = id;
+        if let Some(new) = id {
+            self.deliver(new, WidgetEvent::FocusIn, false);
+            self.invalidate(new);
+        }
+    }
+
+    pub fn style(&self) -> &Style {
+        &self.style
+    }
+
+    /// Draw with colors from `theme`
+    pub fn set_theme(&mut self, theme: &ThemeSpec) {
+        self.style = Style::from_theme(theme);
+        self.invalidate_all();
+    }
+
+    /// Fill a window resized to `width` by `height`
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.needs_layout = true;
+        self.invalidate_all();
+    }
+
+    /// Redraw `id` at the next render
+    pub fn invalidate(&mut self, id: WidgetId) {
+        if let Some(bounds) = self.node(id).map(|node| node.bounds) {
+            self.damage(bounds);
+        }
+    }
+
+    /// Redraw everything at the next render
+    pub fn invalidate_all(&mut self) {
+        self.damage(Rect::new(0, 0, self.width, self.height));
+    }
+
+    /// Hand an event to the widgets; returns the widget that acted on it
+    /// and what it did
+    pub fn handle_event(&mut self, event: &Event) -> Option<(WidgetId, Action)> {
+        match event {
+            Event::Key(key) if key.pressed => {
+                if key.character == b'\t' {
+                    self.move_focus(!key.modifiers.shift);
+                    return None;
+                }
+                let focus = self.focus?;
+                action(self.deliver(focus, WidgetEvent::Key(*key), true))
+            }
+            Event::Mouse(MouseEvent::ButtonDown { button: MouseButton::Left, x, y }) => {
+                let target = self.hit(*x, *y)?;
+                let focusable = self.ancestors(target).find(|&id| {
+                    self.node(id).is_some_and(|node| node.widget.focusable())
+                });
+                self.set_focus(focusable);
+
+                let handled = self.deliver(target, WidgetEvent::MouseDown { x: *x, y: *y }, true);
+                self.pressed = handled.map(|(id, _)| id);
+                if let Some(pressed) = self.pressed {
+                    self.invalidate(pressed);
+                }
+                action(handled)
+            }
+            Event::Mouse(MouseEvent::ButtonUp { button: MouseButton::Left, x, y }) => {
+                let pressed = self.pressed.take()?;
+                self.invalidate(pressed);
+                action(self.deliver(pressed, WidgetEvent::MouseUp { x: *x, y: *y }, false))
+            }
+            Event::Mouse(MouseEvent::Move { x, y, .. }) => {
+                let hover = self.hit(*x, *y);
+                if hover != self.hover {
+                    for id in [self.hover, hover].into_iter().flatten() {
+                        self.invalidate(id);
+                    }
+                    self.hover = hover;
+                }
+                let pressed = self.pressed?;
+                action(self.deliver(pressed, WidgetEvent::MouseDrag { x: *x, y: *y }, false))
+            }
+            Event::Mouse(MouseEvent::Scroll { delta, x, y }) => {
+                let target = self.hit(*x, *y)?;
+                action(self.deliver(target, WidgetEvent::Scroll { delta: *delta }, true))
+            }
+            Event::Window(WindowEvent::Resize { width, height }) => {
+                self.resize(*width, *height);
+                None
+            }
+            Event::Window(WindowEvent::Expose { x, y, width, height }) => {
+                self.damage(Rect::new(*x, *y, *width, *height));
+                None
+            }
+            Event::Window(WindowEvent::PointerLeave) => {
+                if let Some(hover) = self.hover.take() {
+                    self.invalidate(hover);
+                }
+                None
+            }
+            Event::ThemeChanged(theme) => {
+                self.set_theme(theme);
+                None
+            }
+            Event::Redraw => {
+                self.invalidate_all();
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Lay the widgets out if needed, draw the damaged ones to `surface`
+    /// and present them; false if nothing needed drawing
+    pub fn render(&mut self, surface: &mut Surface) -> bool {
+        match self.paint(surface) {
+            Some(damage) => {
+                surface.present_rect(damage);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Lay the widgets out if needed and draw the damaged ones to
+    /// `surface` without presenting them, as in `Handler::draw`; returns
+    /// the area drawn
+    pub fn paint(&mut self, surface: &mut Surface) -> Option<Rect> {
+        if self.needs_layout {
+            self.layout();
+        }
+        let window = Rect::new(0, 0, surface.width(), surface.height());
+        let damage = self.damage.take()?.intersection(&window)?;
+        self.draw(self.root, damage, surface);
+        Some(damage)
+    }
+
+    fn node(&self, id: WidgetId) -> Option<&Node> {
+        self.nodes.get(id)?.as_ref()
+    }
+
+    fn node_mut(&mut self, id: WidgetId) -> Option<&mut Node> {
+        self.nodes.get_mut(id)?.as_mut()
+    }
+
+    fn damage(&mut self, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+        self.damage = Some(match self.damage {
+            Some(damage) => damage.union(&rect),
+            None => rect,
+        });
+    }
+
+    /// `id` and the containers it is in, innermost first
+    fn ancestors(&self, id: WidgetId) -> impl Iterator<Item = WidgetId> + '_ {
+        core::iter::successors(Some(id), |&id| self.node(id)?.parent)
+    }
+
+    /// Innermost widget at (x, y)
+    fn hit(&self, x: i32, y: i32) -> Option<WidgetId> {
+        let mut id = self.root;
+        if !self.node(id)?.bounds.contains(x, y) {
+            return None;
+        }
+        // Later children are drawn over earlier ones
+        while let Some(&child) = self.node(id)?.children.iter().rev().find(|&&child| {
+            self.node(child).is_some_and(|node| node.bounds.contains(x, y))
+        }) {
+            id = child;
+        }
+        Some(id)
+    }
+
+    /// Hand `event` to `id`, and to its containers in turn while they
+    /// ignore it if `bubble`; returns the widget that handled it and how
+    fn deliver(
+        &mut self,
+        id: WidgetId,
+        event: WidgetEvent,
+        bubble: bool,
+    ) -> Option<(WidgetId, Response)> {
+        let mut next = Some(id);
+        while let Some(id) = next {
+            let node = self.node_mut(id)?;
+            let response = node.widget.event(&event, node.bounds);
+            next = node.parent.filter(|_| bubble);
+            match response {
+                Response::Ignored => continue,
+                Response::Handled => {}
+                Response::Repaint | Response::Action(_) => self.invalidate(id),
+                Response::Relayout => {
+                    self.invalidate(id);
+                    self.needs_layout = true;
+                }
+            }
+            return Some((id, response));
+        }
+        None
+    }
+
+    /// Give focus to the next focusable widget in tree order, or the one
+    /// before it; widgets laid out with no room, such as those a scroll
+    /// view hides, are passed over
+    fn move_focus(&mut self, forward: bool) {
+        let mut order = Vec::new();
+        let mut pending = vec![self.root];
+        while let Some(id) = pending.pop() {
+            let Some(node) = self.node(id) else {
+                continue;
+            };
+            if node.bounds.is_empty() {
+                continue;
+            }
+            if node.widget.focusable() {
+                order.push(id);
+            }
+            pending.extend(node.children.iter().rev());
+        }
+        if order.is_empty() {
+            return;
+        }
+
+        let current = self.focus.and_then(|focus| order.iter().position(|&id| id == focus));
+        let next = match (current, forward) {
+            (None, true) => 0,
+            (None, false) => order.len() - 1,
+            (Some(index), true) => (index + 1) % order.len(),
+            (Some(index), false) => (index + order.len() - 1) % order.len(),
+        };
+        self.set_focus(Some(order[next]));
+    }
+
+    fn layout(&mut self) {
+        self.needs_layout = false;
+        self.measure(self.root);
+        self.place(self.root, Rect::new(0, 0, self.width, self.height));
+    }
+
+    /// Record the size every widget under `id` asks for, children first
+    fn measure(&mut self, id: WidgetId) -> Child {
+        let Some(node) = self.node(id) else {
+            return Child { size: Size::default(), flex: 0 };
+        };
+        let children = node.children.clone();
+        let infos: Vec<Child> = children.into_iter().map(|child| self.measure(child)).collect();
+
+        let Some(node) = self.node_mut(id) else {
+            return Child { size: Size::default(), flex: 0 };
+        };
+        node.size = node.widget.measure(&infos);
+        Child { size: node.size, flex: node.flex }
+    }
+
+    /// Put `id` in `bounds` and its children where it says, damaging
+    /// every widget that moved where it was and where it goes
+    fn place(&mut self, id: WidgetId, bounds: Rect) {
+        let Some(node) = self.node(id) else {
+            return;
+        };
+        let children = node.children.clone();
+        let infos: Vec<Child> = children
+            .iter()
+            .filter_map(|&child| self.node(child))
+            .map(|child| Child { size: child.size, flex: child.flex })
+            .collect();
+
+        let Some(node) = self.node_mut(id) else {
+            return;
+        };
+        let old = core::mem::replace(&mut node.bounds, bounds);
+        let rects = node.widget.arrange(bounds, &infos);
+        if old != bounds {
+            self.damage(old);
+            self.damage(bounds);
+        }
+        for (child, rect) in children.into_iter().zip(rects) {
+            self.place(child, rect);
+        }
+    }
+
+    /// Draw `id` and its children, limited to `clip`
+    fn draw(&self, id: WidgetId, clip: Rect, surface: &mut Surface) {
+        let Some(node) = self.node(id) else {
+            return;
+        };
+        let Some(area) = node.bounds.intersection(&clip) else {
+            return;
+        };
+        let state = State {
+            focused: self.focus == Some(id),
+            hovered: self.hover == Some(id),
+            pressed: self.pressed == Some(id),
+        };
+        node.widget.draw(&mut Canvas::new(surface, area), node.bounds, state, &self.style);
+        for &child in &node.children {
+            self.draw(child, area, surface);
+        }
+        if state.focused {
+            let mut canvas = Canvas::new(surface, area);
+            canvas.draw_frame(node.bounds, self.style.focus_width, self.style.focus_ring);
+        }
+    }
+}
+
+/// The action a widget took in handling an event, if it took one
+fn action(handled: Option<(WidgetId, Response)>) -> Option<(WidgetId, Action)> {
+    match handled? {
+        (id, Response::Action(action)) => Some((id, action)),
+        _ => None,
+    }
+}
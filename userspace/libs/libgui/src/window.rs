@@ -0,0 +1,167 @@
+//! Windows
+//!
+//! A `Window` is a compositor window with the state `Application::run`
+//! keeps for it: its surface, whether it has focus, what needs redrawing
+//! and whether the last frame is on screen yet. Applications hand the loop
+//! a `Handler` that reacts to events and draws frames.
+//!
+//! ```ignore
+//! struct Echo { last: u8 }
+//!
+//! impl Handler for Echo {
+//!     fn event(&mut self, _app: &mut Application, window: &mut Window, event: &Event) {
+//!         if let Event::Key(key) = event {
+//!             if key.is_printable() {
+//!                 self.last = key.character;
+//!                 window.invalidate_all();
+//!             }
+//!         }
+//!     }
+//!
+//!     fn draw(&mut self, surface: &mut Surface, damage: Rect) -> Option<Rect> {
+//!         surface.clear(Color::NORD_BG);
+//!         surface.draw_string_transparent(8, 8, "Last key:", Color::NORD_FG);
+//!         surface.draw_char_transparent(88, 8, self.last, Color::NORD_ACCENT);
+//!         Some(damage)
+//!     }
+//! }
+//!
+//! let mut app = Application::new("Echo")?;
+//! let mut window = Window::open(&mut app, 320, 200)?;
+//! app.run(&mut window, &mut Echo { last: b' ' });
+//! ```
+
+use atom_syscall::SyscallResult;
+use libipc::messages::{Rect, WindowId};
+
+use crate::application::Application;
+use crate::event::{Event, WindowEvent};
+use crate::surface::Surface;
+
+/// What an application does with its window
+pub trait Handler {
+    /// Draw a frame covering at least `damage`; returns the area that
+    /// changed, which is presented, or `None` if nothing did
+    fn draw(&mut self, surface: &mut Surface, damage: Rect) -> Option<Rect>;
+
+    /// An event arrived; resizes, focus changes and frame pacing are
+    /// already taken care of. Changes to what is shown are asked for with
+    /// `Window::invalidate`.
+    fn event(&mut self, _app: &mut Application, _window: &mut Window, _event: &Event) {}
+
+    /// The user asked to close the window; returning false keeps it open,
+    /// e.g. to ask about unsaved work first
+    fn close_requested(&mut self, _app: &mut Application, _window: &mut Window) -> bool {
+        true
+    }
+}
+
+/// A window on the desktop
+pub struct Window {
+    surface: Surface,
+    focused: bool,
+    open: bool,
+    /// Part of the surface to draw at the next frame
+    damage: Option<Rect>,
+    /// A frame was presented that the compositor has not shown yet
+    frame_pending: bool,
+}
+
+impl Window {
+    /// Open a window with a `width` x `height` client area
+    pub fn open(app: &mut Application, width: u32, height: u32) -> SyscallResult<Self> {
+        app.create_surface(width, height).map(Self::new)
+    }
+
+    /// Open a window whose surface is in physical pixels; see
+    /// `Application::create_native_surface`
+    pub fn open_native(app: &mut Application, width: u32, height: u32) -> SyscallResult<Self> {
+        app.create_native_surface(width, height).map(Self::new)
+    }
+
+    fn new(surface: Surface) -> Self {
+        Self { surface, focused: false, open: true, damage: None, frame_pending: false }
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.surface.id()
+    }
+
+    pub fn surface(&self) -> &Surface {
+        &self.surface
+    }
+
+    pub fn surface_mut(&mut self) -> &mut Surface {
+        &mut self.surface
+    }
+
+    /// Size of the surface, in its pixels
+    pub fn size(&self) -> (u32, u32) {
+        (self.surface.width(), self.surface.height())
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// End `Application::run`; the window goes away when dropped
+    pub fn close(&mut self) {
+        self.open = false;
+    }
+
+    /// Draw `rect` at the next frame
+    pub fn invalidate(&mut self, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+        self.damage = Some(match self.damage {
+            Some(damage) => damage.union(&rect),
+            None => rect,
+        });
+    }
+
+    /// Draw the whole surface at the next frame
+    pub fn invalidate_all(&mut self) {
+        let (width, height) = self.size();
+        self.invalidate(Rect::new(0, 0, width, height));
+    }
+
+    /// Keep track of what `event` changes about the window
+    pub(crate) fn apply(&mut self, event: &Event) {
+        match *event {
+            Event::Window(WindowEvent::Focus) => self.focused = true,
+            Event::Window(WindowEvent::Unfocus) => self.focused = false,
+            Event::Window(WindowEvent::Expose { x, y, width, height }) => {
+                self.invalidate(Rect::new(x, y, width, height));
+            }
+            Event::ThemeChanged(_) | Event::ScaleChanged(_) | Event::Redraw => {
+                self.invalidate_all();
+            }
+            Event::FrameDone { window, .. } if window == self.id() => self.frame_pending = false,
+            // Frames presented to the old compositor will never be shown
+            Event::CompositorLost => self.frame_pending = false,
+            _ => {}
+        }
+    }
+
+    /// Have `handler` draw the next frame and present it, if there is
+    /// something to draw and the last frame is on screen; returns whether
+    /// it did
+    pub(crate) fn draw_frame(&mut self, handler: &mut impl Handler) -> bool {
+        if self.frame_pending {
+            return false;
+        }
+        let Some(damage) = self.damage.take() else {
+            return false;
+        };
+        if let Some(drawn) = handler.draw(&mut self.surface, damage) {
+            self.surface.present_rect(drawn);
+            self.frame_pending = true;
+        }
+        true
+    }
+}
@@ -0,0 +1,243 @@
+//! Service Connections
+//!
+//! A `Connection` is a client's link to one service: the service's port,
+//! looked up by name, and a port of the client's own that replies and
+//! events arrive on. It makes the `Hello` handshake when asked, has the
+//! kernel report the service exiting, and looks the service up again when
+//! it is next used, so a client keeps one connection across restarts.
+//!
+//! ```ignore
+//! let mut desktop = Connection::new(ServiceId::Desktop);
+//! desktop.handshake()?;
+//! desktop.on(MessageType::ThemeChanged, |payload| apply_theme(payload));
+//! desktop.send(MessageType::SetTitle, &title.to_bytes())?;
+//!
+//! let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+//! while let Some((header, len)) = desktop.try_recv(&mut buffer)? {
+//!     // Messages without a handler come back here
+//! }
+//! ```
+//!
+//! The connection lives in libipc rather than next to the raw port calls
+//! in atom_syscall, which the handshake and message headers build on.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+use atom_syscall::ipc::{close_port, create_port, watch_port, PortId};
+use atom_syscall::SyscallError;
+
+use crate::discovery::{self, STARTUP_TIMEOUT_MS};
+use crate::messages::{MessageHeader, MessageType};
+use crate::protocol::{get_payload, negotiate, recv_message, send_message, try_recv_message};
+use crate::status::StatusResult;
+use crate::ServiceId;
+
+/// Takes the payload of a message routed to it
+pub type Handler<'a> = Box<dyn FnMut(&[u8]) + 'a>;
+
+/// A client's link to a service, found again after the service restarts
+pub struct Connection<'a> {
+    service: ServiceId,
+    /// The service's port, while it is thought to be up
+    peer: Cell<Option<PortId>>,
+    /// Port replies and events arrive on; created on first use
+    port: Cell<Option<PortId>>,
+    /// Protocol version agreed with the current peer
+    version: Cell<Option<u16>>,
+    /// A handshake was asked for, so a new peer gets one too
+    greeted: Cell<bool>,
+    /// The port hears of the current peer exiting
+    watching: Cell<bool>,
+    /// How long to wait for the service when looking it up
+    timeout_ms: u64,
+    handlers: Vec<(MessageType, Handler<'a>)>,
+}
+
+impl<'a> Connection<'a> {
+    /// A connection to `service`, looked up when first used
+    pub fn new(service: ServiceId) -> Self {
+        Self {
+            service,
+            peer: Cell::new(None),
+            port: Cell::new(None),
+            version: Cell::new(None),
+            greeted: Cell::new(false),
+            watching: Cell::new(false),
+            timeout_ms: STARTUP_TIMEOUT_MS,
+            handlers: Vec::new(),
+        }
+    }
+
+    /// A connection to `service` at `peer`, a port found some other way
+    pub fn with_peer(service: ServiceId, peer: PortId) -> Self {
+        let connection = Self::new(service);
+        connection.peer.set(Some(peer));
+        connection
+    }
+
+    /// Wait up to `timeout_ms` for the service when looking it up; 0 to
+    /// fail at once if it is not registered
+    pub fn set_lookup_timeout(&mut self, timeout_ms: u64) {
+        self.timeout_ms = timeout_ms;
+    }
+
+    pub fn service(&self) -> ServiceId {
+        self.service
+    }
+
+    /// The service's port, if it is known and not reported gone
+    pub fn peer(&self) -> Option<PortId> {
+        self.peer.get()
+    }
+
+    /// Protocol version agreed in the handshake with the current peer
+    pub fn version(&self) -> Option<u16> {
+        self.version.get()
+    }
+
+    /// This side's port, created if there is none yet
+    pub fn port(&self) -> StatusResult<PortId> {
+        if let Some(port) = self.port.get() {
+            return Ok(port);
+        }
+        let port = create_port()?;
+        self.port.set(Some(port));
+        Ok(port)
+    }
+
+    /// This side's port, if it was created
+    pub fn local_port(&self) -> Option<PortId> {
+        self.port.get()
+    }
+
+    /// The service's port, looking it up (and greeting it, if a handshake
+    /// was made before) when the last one is gone
+    pub fn connect(&self) -> StatusResult<PortId> {
+        if let Some(peer) = self.peer.get() {
+            return Ok(peer);
+        }
+        let peer = discovery::lookup_service(self.service, self.timeout_ms)?;
+        self.peer.set(Some(peer));
+        if self.greeted.get() {
+            self.greet(peer)?;
+        }
+        Ok(peer)
+    }
+
+    /// Agree on a protocol version with the service, once per peer, and
+    /// have the kernel say when it exits
+    ///
+    /// Nothing else may be waiting on the port while the answer comes.
+    pub fn handshake(&self) -> StatusResult<u16> {
+        self.greeted.set(true);
+        let peer = self.connect()?;
+        self.greet(peer)
+    }
+
+    fn greet(&self, peer: PortId) -> StatusResult<u16> {
+        let port = self.port()?;
+        let version = match self.version.get() {
+            Some(version) => version,
+            None => negotiate(peer, port)?,
+        };
+        self.version.set(Some(version));
+        if !self.watching.get() {
+            self.watching.set(watch_port(peer, port).is_ok());
+        }
+        Ok(version)
+    }
+
+    /// Forget the peer; the next use looks the service up again
+    pub fn disconnect(&self) {
+        self.peer.set(None);
+        self.version.set(None);
+        self.watching.set(false);
+    }
+
+    /// Close this side's port; a later use creates another
+    pub fn close_port(&self) {
+        if let Some(port) = self.port.take() {
+            let _ = close_port(port);
+        }
+        self.watching.set(false);
+    }
+
+    /// Send a message to the service; if its port is gone, look the
+    /// service up again and send once more
+    pub fn send(&self, msg_type: MessageType, payload: &[u8]) -> StatusResult<()> {
+        let peer = self.connect()?;
+        match send_message(peer, msg_type, payload) {
+            Err(SyscallError::InvalidArgument) => {
+                self.disconnect();
+                let peer = self.connect()?;
+                Ok(send_message(peer, msg_type, payload)?)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    /// Hand messages of `msg_type` to `handler`, replacing any handler it
+    /// had
+    pub fn on(&mut self, msg_type: MessageType, handler: impl FnMut(&[u8]) + 'a) -> &mut Self {
+        self.handlers.retain(|(registered, _)| *registered != msg_type);
+        self.handlers.push((msg_type, Box::new(handler)));
+        self
+    }
+
+    /// Route a message received some other way; false if it has no
+    /// handler and is the caller's to deal with
+    ///
+    /// `PortDied` for the peer makes the connection forget it and is still
+    /// passed on, so the caller can tell its user the service went away.
+    pub fn dispatch(&mut self, header: &MessageHeader, payload: &[u8]) -> bool {
+        if header.msg_type == MessageType::PortDied {
+            let died = payload.get(..8).and_then(|bytes| bytes.try_into().ok());
+            if died.map(u64::from_le_bytes).is_some_and(|port| Some(port) == self.peer.get()) {
+                self.disconnect();
+            }
+        }
+        let msg_type = header.msg_type;
+        match self.handlers.iter_mut().find(|(registered, _)| *registered == msg_type) {
+            Some((_, handler)) => {
+                handler(payload);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Route the messages waiting on the port and return the first one
+    /// without a handler, which is left in `buffer`
+    pub fn try_recv(&mut self, buffer: &mut [u8]) -> StatusResult<Option<(MessageHeader, usize)>> {
+        let Some(port) = self.port.get() else {
+            return Ok(None);
+        };
+        while let Some((header, len)) = try_recv_message(port, buffer)? {
+            if !self.dispatch(&header, get_payload(buffer, len)) {
+                return Ok(Some((header, len)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Wait for a message without a handler, routing the others meanwhile
+    pub fn recv(&mut self, buffer: &mut [u8]) -> StatusResult<(MessageHeader, usize)> {
+        let port = self.port()?;
+        loop {
+            let (header, len) = recv_message(port, buffer)?;
+            if !self.dispatch(&header, get_payload(buffer, len)) {
+                return Ok((header, len));
+            }
+        }
+    }
+}
+
+impl Drop for Connection<'_> {
+    fn drop(&mut self) {
+        self.close_port();
+    }
+}
@@ -0,0 +1,73 @@
+//! Service Discovery
+//!
+//! Services publish their port with the kernel's port-name registry, and
+//! clients look it up by name instead of assuming a port number. A client
+//! started alongside a service may look before the service has registered,
+//! so `lookup` keeps trying, waiting a little longer each time, until the
+//! timeout runs out.
+//!
+//! ```ignore
+//! // Service
+//! let port = create_port()?;
+//! discovery::register_service(ServiceId::Audio, port)?;
+//!
+//! // Client
+//! let server = discovery::lookup_service(ServiceId::Audio, STARTUP_TIMEOUT_MS)?;
+//! ```
+//!
+//! A name is freed when its port is closed, so a restarted service can
+//! register it again.
+
+use atom_syscall::ipc::{lookup_name, register_name, PortId};
+use atom_syscall::thread::{get_time_ms, sleep_ms};
+use atom_syscall::{SyscallError, SyscallResult};
+
+use crate::ServiceId;
+
+/// How long a client started with the system waits for a service
+pub const STARTUP_TIMEOUT_MS: u64 = 5000;
+
+/// First wait between lookups; each one after waits twice as long
+const FIRST_RETRY_MS: u64 = 10;
+
+/// Longest wait between lookups
+const MAX_RETRY_MS: u64 = 250;
+
+/// Publish `port`, which the caller owns, as `service`
+pub fn register_service(service: ServiceId, port: PortId) -> SyscallResult<()> {
+    register(service.name(), port)
+}
+
+/// Publish `port`, which the caller owns, under `name`; fails with `Busy`
+/// if a live port has the name already
+pub fn register(name: &str, port: PortId) -> SyscallResult<()> {
+    register_name(name, port)
+}
+
+/// The port of `service`, waiting up to `timeout_ms` for it to register
+pub fn lookup_service(service: ServiceId, timeout_ms: u64) -> SyscallResult<PortId> {
+    lookup(service.name(), timeout_ms)
+}
+
+/// The port published under `name`, waiting up to `timeout_ms` for it;
+/// `TimedOut` if it never showed up
+pub fn lookup(name: &str, timeout_ms: u64) -> SyscallResult<PortId> {
+    let deadline = get_time_ms().saturating_add(timeout_ms);
+    let mut wait = FIRST_RETRY_MS;
+    loop {
+        if let Some(port) = lookup_name(name)? {
+            return Ok(port);
+        }
+        let now = get_time_ms();
+        if now >= deadline {
+            return Err(SyscallError::TimedOut);
+        }
+        sleep_ms(wait.min(deadline - now));
+        wait = (wait * 2).min(MAX_RETRY_MS);
+    }
+}
+
+/// The port of `service` if it is registered now, without waiting
+pub fn find_service(service: ServiceId) -> Option<PortId> {
+    lookup_name(service.name()).ok().flatten()
+}
@@ -0,0 +1,520 @@
+//! Hardware-Independent Key Codes
+//!
+//! `KeyCode` names a physical key position (US labels), independent of the
+//! bus it came from or the active layout. The PS/2 and USB HID drivers both
+//! emit it, so consumers never deal with scancode sets or E0 prefixes.
+//!
+//! Discriminants 0x00..=0x53 deliberately equal the PS/2 set-1 make codes
+//! of the main block, so set-1 decoding of non-prefixed keys is an identity
+//! mapping and layout tables can stay indexed by the familiar values.
+//!
+//! `ScancodeDecoder` turns a raw PS/2 byte stream (set 1 or set 2, with E0
+//! and E1 prefixes) into `(KeyCode, pressed)` transitions.
+
+/// Physical key identifier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum KeyCode {
+    Unknown = 0x00,
+    Escape = 0x01,
+    Digit1 = 0x02,
+    Digit2 = 0x03,
+    Digit3 = 0x04,
+    Digit4 = 0x05,
+    Digit5 = 0x06,
+    Digit6 = 0x07,
+    Digit7 = 0x08,
+    Digit8 = 0x09,
+    Digit9 = 0x0A,
+    Digit0 = 0x0B,
+    Minus = 0x0C,
+    Equal = 0x0D,
+    Backspace = 0x0E,
+    Tab = 0x0F,
+    KeyQ = 0x10,
+    KeyW = 0x11,
+    KeyE = 0x12,
+    KeyR = 0x13,
+    KeyT = 0x14,
+    KeyY = 0x15,
+    KeyU = 0x16,
+    KeyI = 0x17,
+    KeyO = 0x18,
+    KeyP = 0x19,
+    BracketLeft = 0x1A,
+    BracketRight = 0x1B,
+    Enter = 0x1C,
+    ControlLeft = 0x1D,
+    KeyA = 0x1E,
+    KeyS = 0x1F,
+    KeyD = 0x20,
+    KeyF = 0x21,
+    KeyG = 0x22,
+    KeyH = 0x23,
+    KeyJ = 0x24,
+    KeyK = 0x25,
+    KeyL = 0x26,
+    Semicolon = 0x27,
+    Quote = 0x28,
+    Backquote = 0x29,
+    ShiftLeft = 0x2A,
+    Backslash = 0x2B,
+    KeyZ = 0x2C,
+    KeyX = 0x2D,
+    KeyC = 0x2E,
+    KeyV = 0x2F,
+    KeyB = 0x30,
+    KeyN = 0x31,
+    KeyM = 0x32,
+    Comma = 0x33,
+    Period = 0x34,
+    Slash = 0x35,
+    ShiftRight = 0x36,
+    NumpadMultiply = 0x37,
+    AltLeft = 0x38,
+    Space = 0x39,
+    CapsLock = 0x3A,
+    F1 = 0x3B,
+    F2 = 0x3C,
+    F3 = 0x3D,
+    F4 = 0x3E,
+    F5 = 0x3F,
+    F6 = 0x40,
+    F7 = 0x41,
+    F8 = 0x42,
+    F9 = 0x43,
+    F10 = 0x44,
+    NumLock = 0x45,
+    ScrollLock = 0x46,
+    Numpad7 = 0x47,
+    Numpad8 = 0x48,
+    Numpad9 = 0x49,
+    NumpadSubtract = 0x4A,
+    Numpad4 = 0x4B,
+    Numpad5 = 0x4C,
+    Numpad6 = 0x4D,
+    NumpadAdd = 0x4E,
+    Numpad1 = 0x4F,
+    Numpad2 = 0x50,
+    Numpad3 = 0x51,
+    Numpad0 = 0x52,
+    NumpadDecimal = 0x53,
+    /// Extra key left of Z on ISO keyboards
+    IntlBackslash = 0x54,
+    F11 = 0x55,
+    F12 = 0x56,
+    /// Extra key right of the slash on ABNT2 / JIS keyboards
+    IntlRo = 0x57,
+    NumpadEnter = 0x58,
+    ControlRight = 0x59,
+    NumpadDivide = 0x5A,
+    PrintScreen = 0x5B,
+    AltRight = 0x5C,
+    Home = 0x5D,
+    ArrowUp = 0x5E,
+    PageUp = 0x5F,
+    ArrowLeft = 0x60,
+    ArrowRight = 0x61,
+    End = 0x62,
+    ArrowDown = 0x63,
+    PageDown = 0x64,
+    Insert = 0x65,
+    Delete = 0x66,
+    MetaLeft = 0x67,
+    MetaRight = 0x68,
+    Menu = 0x69,
+    Pause = 0x6A,
+}
+
+/// Number of defined key codes (valid values are `0..KEYCODE_COUNT`)
+pub const KEYCODE_COUNT: usize = 0x6B;
+
+impl KeyCode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        if (value as usize) < KEYCODE_COUNT {
+            // SAFETY: the enum is repr(u8) with contiguous discriminants
+            // 0..KEYCODE_COUNT, checked above
+            Some(unsafe { core::mem::transmute::<u8, KeyCode>(value) })
+        } else {
+            None
+        }
+    }
+
+    pub fn is_modifier(&self) -> bool {
+        matches!(
+            self,
+            Self::ShiftLeft
+                | Self::ShiftRight
+                | Self::ControlLeft
+                | Self::ControlRight
+                | Self::AltLeft
+                | Self::AltRight
+                | Self::MetaLeft
+                | Self::MetaRight
+                | Self::CapsLock
+        )
+    }
+
+    /// Map a PS/2 set-1 make code (without the 0x80 release bit)
+    pub fn from_set1(code: u8, extended: bool) -> Option<Self> {
+        if extended {
+            return Some(match code {
+                0x1C => Self::NumpadEnter,
+                0x1D => Self::ControlRight,
+                0x35 => Self::NumpadDivide,
+                0x37 => Self::PrintScreen,
+                0x38 => Self::AltRight,
+                0x47 => Self::Home,
+                0x48 => Self::ArrowUp,
+                0x49 => Self::PageUp,
+                0x4B => Self::ArrowLeft,
+                0x4D => Self::ArrowRight,
+                0x4F => Self::End,
+                0x50 => Self::ArrowDown,
+                0x51 => Self::PageDown,
+                0x52 => Self::Insert,
+                0x53 => Self::Delete,
+                0x5B => Self::MetaLeft,
+                0x5C => Self::MetaRight,
+                0x5D => Self::Menu,
+                // 0x2A/0x36 are "fake shift" codes around PrintScreen etc.
+                _ => return None,
+            });
+        }
+
+        match code {
+            0x01..=0x53 => Self::from_u8(code),
+            0x56 => Some(Self::IntlBackslash),
+            0x57 => Some(Self::F11),
+            0x58 => Some(Self::F12),
+            0x73 => Some(Self::IntlRo),
+            _ => None,
+        }
+    }
+
+    /// PS/2 set-1 make code of the key and whether it is E0-prefixed; the
+    /// inverse of `from_set1`, for code written against set-1 scancodes
+    pub fn to_set1(&self) -> Option<(u8, bool)> {
+        let code = *self as u8;
+        if (0x01..=0x53).contains(&code) {
+            return Some((code, false));
+        }
+        Some(match self {
+            Self::IntlBackslash => (0x56, false),
+            Self::F11 => (0x57, false),
+            Self::F12 => (0x58, false),
+            Self::IntlRo => (0x73, false),
+            Self::NumpadEnter => (0x1C, true),
+            Self::ControlRight => (0x1D, true),
+            Self::NumpadDivide => (0x35, true),
+            Self::PrintScreen => (0x37, true),
+            Self::AltRight => (0x38, true),
+            Self::Home => (0x47, true),
+            Self::ArrowUp => (0x48, true),
+            Self::PageUp => (0x49, true),
+            Self::ArrowLeft => (0x4B, true),
+            Self::ArrowRight => (0x4D, true),
+            Self::End => (0x4F, true),
+            Self::ArrowDown => (0x50, true),
+            Self::PageDown => (0x51, true),
+            Self::Insert => (0x52, true),
+            Self::Delete => (0x53, true),
+            Self::MetaLeft => (0x5B, true),
+            Self::MetaRight => (0x5C, true),
+            Self::Menu => (0x5D, true),
+            _ => return None,
+        })
+    }
+
+    /// Map a PS/2 set-2 make code
+    pub fn from_set2(code: u8, extended: bool) -> Option<Self> {
+        if extended {
+            return Some(match code {
+                0x11 => Self::AltRight,
+                0x14 => Self::ControlRight,
+                0x1F => Self::MetaLeft,
+                0x27 => Self::MetaRight,
+                0x2F => Self::Menu,
+                0x4A => Self::NumpadDivide,
+                0x5A => Self::NumpadEnter,
+                0x69 => Self::End,
+                0x6B => Self::ArrowLeft,
+                0x6C => Self::Home,
+                0x70 => Self::Insert,
+                0x71 => Self::Delete,
+                0x72 => Self::ArrowDown,
+                0x74 => Self::ArrowRight,
+                0x75 => Self::ArrowUp,
+                0x7A => Self::PageDown,
+                0x7C => Self::PrintScreen,
+                0x7D => Self::PageUp,
+                // 0x12/0x59 are "fake shift" codes around PrintScreen etc.
+                _ => return None,
+            });
+        }
+
+        Some(match code {
+            0x01 => Self::F9,
+            0x03 => Self::F5,
+            0x04 => Self::F3,
+            0x05 => Self::F1,
+            0x06 => Self::F2,
+            0x07 => Self::F12,
+            0x09 => Self::F10,
+            0x0A => Self::F8,
+            0x0B => Self::F6,
+            0x0C => Self::F4,
+            0x0D => Self::Tab,
+            0x0E => Self::Backquote,
+            0x11 => Self::AltLeft,
+            0x12 => Self::ShiftLeft,
+            0x14 => Self::ControlLeft,
+            0x15 => Self::KeyQ,
+            0x16 => Self::Digit1,
+            0x1A => Self::KeyZ,
+            0x1B => Self::KeyS,
+            0x1C => Self::KeyA,
+            0x1D => Self::KeyW,
+            0x1E => Self::Digit2,
+            0x21 => Self::KeyC,
+            0x22 => Self::KeyX,
+            0x23 => Self::KeyD,
+            0x24 => Self::KeyE,
+            0x25 => Self::Digit4,
+            0x26 => Self::Digit3,
+            0x29 => Self::Space,
+            0x2A => Self::KeyV,
+            0x2B => Self::KeyF,
+            0x2C => Self::KeyT,
+            0x2D => Self::KeyR,
+            0x2E => Self::Digit5,
+            0x31 => Self::KeyN,
+            0x32 => Self::KeyB,
+            0x33 => Self::KeyH,
+            0x34 => Self::KeyG,
+            0x35 => Self::KeyY,
+            0x36 => Self::Digit6,
+            0x3A => Self::KeyM,
+            0x3B => Self::KeyJ,
+            0x3C => Self::KeyU,
+            0x3D => Self::Digit7,
+            0x3E => Self::Digit8,
+            0x41 => Self::Comma,
+            0x42 => Self::KeyK,
+            0x43 => Self::KeyI,
+            0x44 => Self::KeyO,
+            0x45 => Self::Digit0,
+            0x46 => Self::Digit9,
+            0x49 => Self::Period,
+            0x4A => Self::Slash,
+            0x4B => Self::KeyL,
+            0x4C => Self::Semicolon,
+            0x4D => Self::KeyP,
+            0x4E => Self::Minus,
+            0x51 => Self::IntlRo,
+            0x52 => Self::Quote,
+            0x54 => Self::BracketLeft,
+            0x55 => Self::Equal,
+            0x58 => Self::CapsLock,
+            0x59 => Self::ShiftRight,
+            0x5A => Self::Enter,
+            0x5B => Self::BracketRight,
+            0x5D => Self::Backslash,
+            0x61 => Self::IntlBackslash,
+            0x66 => Self::Backspace,
+            0x69 => Self::Numpad1,
+            0x6B => Self::Numpad4,
+            0x6C => Self::Numpad7,
+            0x70 => Self::Numpad0,
+            0x71 => Self::NumpadDecimal,
+            0x72 => Self::Numpad2,
+            0x73 => Self::Numpad5,
+            0x74 => Self::Numpad6,
+            0x75 => Self::Numpad8,
+            0x76 => Self::Escape,
+            0x77 => Self::NumLock,
+            0x78 => Self::F11,
+            0x79 => Self::NumpadAdd,
+            0x7A => Self::Numpad3,
+            0x7B => Self::NumpadSubtract,
+            0x7C => Self::NumpadMultiply,
+            0x7D => Self::Numpad9,
+            0x7E => Self::ScrollLock,
+            0x83 => Self::F7,
+            _ => return None,
+        })
+    }
+
+    /// Map a USB HID keyboard usage (page 0x07)
+    pub fn from_hid_usage(usage: u8) -> Option<Self> {
+        const LETTERS: [KeyCode; 26] = [
+            KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE,
+            KeyCode::KeyF, KeyCode::KeyG, KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ,
+            KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN, KeyCode::KeyO,
+            KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT,
+            KeyCode::KeyU, KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY,
+            KeyCode::KeyZ,
+        ];
+        const KEYPAD: [KeyCode; 10] = [
+            KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+            KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8,
+            KeyCode::Numpad9, KeyCode::Numpad0,
+        ];
+
+        Some(match usage {
+            0x04..=0x1D => LETTERS[(usage - 0x04) as usize],
+            // 1..9, 0 are contiguous in both numberings
+            0x1E..=0x27 => Self::from_u8(usage - 0x1E + Self::Digit1 as u8)?,
+            0x28 => Self::Enter,
+            0x29 => Self::Escape,
+            0x2A => Self::Backspace,
+            0x2B => Self::Tab,
+            0x2C => Self::Space,
+            0x2D => Self::Minus,
+            0x2E => Self::Equal,
+            0x2F => Self::BracketLeft,
+            0x30 => Self::BracketRight,
+            0x31 | 0x32 => Self::Backslash, // \ and non-US #
+            0x33 => Self::Semicolon,
+            0x34 => Self::Quote,
+            0x35 => Self::Backquote,
+            0x36 => Self::Comma,
+            0x37 => Self::Period,
+            0x38 => Self::Slash,
+            0x39 => Self::CapsLock,
+            0x3A..=0x43 => Self::from_u8(usage - 0x3A + Self::F1 as u8)?,
+            0x44 => Self::F11,
+            0x45 => Self::F12,
+            0x46 => Self::PrintScreen,
+            0x47 => Self::ScrollLock,
+            0x48 => Self::Pause,
+            0x49 => Self::Insert,
+            0x4A => Self::Home,
+            0x4B => Self::PageUp,
+            0x4C => Self::Delete,
+            0x4D => Self::End,
+            0x4E => Self::PageDown,
+            0x4F => Self::ArrowRight,
+            0x50 => Self::ArrowLeft,
+            0x51 => Self::ArrowDown,
+            0x52 => Self::ArrowUp,
+            0x53 => Self::NumLock,
+            0x54 => Self::NumpadDivide,
+            0x55 => Self::NumpadMultiply,
+            0x56 => Self::NumpadSubtract,
+            0x57 => Self::NumpadAdd,
+            0x58 => Self::NumpadEnter,
+            0x59..=0x62 => KEYPAD[(usage - 0x59) as usize],
+            0x63 => Self::NumpadDecimal,
+            0x64 => Self::IntlBackslash,
+            0x65 => Self::Menu,
+            0x87 => Self::IntlRo,
+            0xE0 => Self::ControlLeft,
+            0xE1 => Self::ShiftLeft,
+            0xE2 => Self::AltLeft,
+            0xE3 => Self::MetaLeft,
+            0xE4 => Self::ControlRight,
+            0xE5 => Self::ShiftRight,
+            0xE6 => Self::AltRight,
+            0xE7 => Self::MetaRight,
+            _ => return None,
+        })
+    }
+}
+
+// ============================================================================
+// PS/2 Scancode Decoding
+// ============================================================================
+
+/// Scancode set the keyboard is sending
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScancodeSet {
+    /// XT set (what the i8042 produces with translation enabled)
+    Set1,
+    /// AT set (untranslated; breaks are prefixed with 0xF0)
+    Set2,
+}
+
+const PREFIX_EXTENDED: u8 = 0xE0;
+const PREFIX_PAUSE: u8 = 0xE1;
+const SET2_BREAK: u8 = 0xF0;
+
+/// Bytes following E1 in the Pause sequence (make only; Pause has no break)
+const SET1_PAUSE_TAIL: u8 = 5; // E1 1D 45 E1 9D C5
+const SET2_PAUSE_TAIL: u8 = 7; // E1 14 77 E1 F0 14 F0 77
+
+/// Stateful PS/2 byte-stream decoder
+///
+/// Starts in set 1. A 0xF0 byte never occurs in set 1, so seeing one
+/// switches the decoder to set 2 permanently.
+pub struct ScancodeDecoder {
+    set: ScancodeSet,
+    extended: bool,
+    release: bool,
+    pause_remaining: u8,
+}
+
+impl ScancodeDecoder {
+    pub const fn new() -> Self {
+        Self::with_set(ScancodeSet::Set1)
+    }
+
+    pub const fn with_set(set: ScancodeSet) -> Self {
+        Self {
+            set,
+            extended: false,
+            release: false,
+            pause_remaining: 0,
+        }
+    }
+
+    pub fn set(&self) -> ScancodeSet {
+        self.set
+    }
+
+    /// Feed one byte; returns a `(key, pressed)` transition when complete
+    pub fn feed(&mut self, byte: u8) -> Option<(KeyCode, bool)> {
+        if self.pause_remaining > 0 {
+            self.pause_remaining -= 1;
+            return None;
+        }
+
+        match byte {
+            PREFIX_EXTENDED => {
+                self.extended = true;
+                return None;
+            }
+            PREFIX_PAUSE => {
+                self.pause_remaining = match self.set {
+                    ScancodeSet::Set1 => SET1_PAUSE_TAIL,
+                    ScancodeSet::Set2 => SET2_PAUSE_TAIL,
+                };
+                return Some((KeyCode::Pause, true));
+            }
+            SET2_BREAK => {
+                self.set = ScancodeSet::Set2;
+                self.release = true;
+                return None;
+            }
+            _ => {}
+        }
+
+        let extended = core::mem::take(&mut self.extended);
+
+        let (key, pressed) = match self.set {
+            ScancodeSet::Set1 => (KeyCode::from_set1(byte & 0x7F, extended), byte & 0x80 == 0),
+            ScancodeSet::Set2 => (
+                KeyCode::from_set2(byte, extended),
+                !core::mem::take(&mut self.release),
+            ),
+        };
+
+        key.map(|key| (key, pressed))
+    }
+}
+
+impl Default for ScancodeDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
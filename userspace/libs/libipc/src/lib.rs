@@ -29,12 +29,20 @@ extern crate alloc;
 
 use alloc::vec::Vec;
 
+pub mod connection;
+pub mod discovery;
+pub mod keycode;
 pub mod messages;
 pub mod protocol;
 pub mod ports;
+pub mod ring;
+pub mod rpc;
 pub mod serialization;
+pub mod status;
+pub mod stream;
 
 // Re-exports for convenience
+pub use keycode::*;
 pub use messages::*;
 pub use protocol::*;
 pub use ports::*;
@@ -56,6 +64,10 @@ pub enum ServiceId {
     Graphics = 4,
     /// Terminal application
     Terminal = 5,
+    /// Audio mixer / sound server
+    Audio = 6,
+    /// Filesystem service
+    Filesystem = 7,
 }
 
 impl ServiceId {
@@ -66,7 +78,22 @@ impl ServiceId {
             3 => Some(ServiceId::Mouse),
             4 => Some(ServiceId::Graphics),
             5 => Some(ServiceId::Terminal),
+            6 => Some(ServiceId::Audio),
+            7 => Some(ServiceId::Filesystem),
             _ => None,
         }
     }
+
+    /// Name the service registers its port under
+    pub fn name(self) -> &'static str {
+        match self {
+            ServiceId::Desktop => "desktop",
+            ServiceId::Keyboard => "keyboard",
+            ServiceId::Mouse => "mouse",
+            ServiceId::Graphics => "graphics",
+            ServiceId::Terminal => "terminal",
+            ServiceId::Audio => "audio",
+            ServiceId::Filesystem => "vfs",
+        }
+    }
 }
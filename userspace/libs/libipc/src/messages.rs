@@ -7,15 +7,32 @@ extern crate alloc;
 
 use alloc::string::String;
 use alloc::vec::Vec;
+use crate::keycode::KeyCode;
+use crate::status::StatusCode;
 
 // ============================================================================
 // Message Header
 // ============================================================================
 
+/// First two bytes of every message, "AT"; anything else is not one
+pub const MESSAGE_MAGIC: u16 = u16::from_le_bytes(*b"AT");
+
+/// Version of the message layouts this library speaks
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// Oldest version this library still speaks
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
 /// Common header for all IPC messages
+///
+/// On the wire: magic, protocol version (u16 each), then message type,
+/// payload size and sequence number (u32 each), all little-endian. The
+/// version is the one the sender writes; `Hello` is how two sides agree
+/// on it.
 #[derive(Debug, Clone, Copy)]
-#[repr(C)]
 pub struct MessageHeader {
+    /// Protocol version the message is laid out for
+    pub version: u16,
     /// Message type identifier
     pub msg_type: MessageType,
     /// Message payload size in bytes
@@ -25,11 +42,12 @@ pub struct MessageHeader {
 }
 
 impl MessageHeader {
-    pub const SIZE: usize = core::mem::size_of::<Self>();
+    pub const SIZE: usize = 16;
 
     pub fn new(msg_type: MessageType, payload_size: u32) -> Self {
         static SEQUENCE: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
         Self {
+            version: PROTOCOL_VERSION,
             msg_type,
             payload_size,
             sequence: SEQUENCE.fetch_add(1, core::sync::atomic::Ordering::Relaxed),
@@ -38,21 +56,29 @@ impl MessageHeader {
 
     pub fn to_bytes(&self) -> [u8; Self::SIZE] {
         let mut bytes = [0u8; Self::SIZE];
-        bytes[0..4].copy_from_slice(&(self.msg_type as u32).to_le_bytes());
-        bytes[4..8].copy_from_slice(&self.payload_size.to_le_bytes());
-        bytes[8..12].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[0..2].copy_from_slice(&MESSAGE_MAGIC.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.version.to_le_bytes());
+        bytes[4..8].copy_from_slice(&(self.msg_type as u32).to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.payload_size.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.sequence.to_le_bytes());
         bytes
     }
 
+    /// Read a header; `None` without the magic or for an unknown type
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
         if bytes.len() < Self::SIZE {
             return None;
         }
-        let msg_type = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
-        let payload_size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
-        let sequence = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        if u16::from_le_bytes([bytes[0], bytes[1]]) != MESSAGE_MAGIC {
+            return None;
+        }
+        let version = u16::from_le_bytes([bytes[2], bytes[3]]);
+        let msg_type = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let payload_size = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        let sequence = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
 
         Some(Self {
+            version,
             msg_type: MessageType::from_u32(msg_type)?,
             payload_size,
             sequence,
@@ -60,6 +86,64 @@ impl MessageHeader {
     }
 }
 
+/// Opens a conversation with a service: the protocol versions the sender
+/// speaks and the port the `HelloAck` goes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hello {
+    pub reply_port: u64,
+    pub min_version: u16,
+    pub max_version: u16,
+}
+
+impl Hello {
+    /// A hello offering the versions this library speaks
+    pub fn new(reply_port: u64) -> Self {
+        Self { reply_port, min_version: MIN_PROTOCOL_VERSION, max_version: PROTOCOL_VERSION }
+    }
+
+    /// The newest version both the sender and this library speak
+    pub fn negotiate(&self) -> Option<u16> {
+        let version = self.max_version.min(PROTOCOL_VERSION);
+        (version >= self.min_version.max(MIN_PROTOCOL_VERSION)).then_some(version)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 12] {
+        let mut bytes = [0u8; 12];
+        bytes[0..8].copy_from_slice(&self.reply_port.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.min_version.to_le_bytes());
+        bytes[10..12].copy_from_slice(&self.max_version.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 12 {
+            return None;
+        }
+        Some(Self {
+            reply_port: u64::from_le_bytes(bytes[0..8].try_into().ok()?),
+            min_version: u16::from_le_bytes([bytes[8], bytes[9]]),
+            max_version: u16::from_le_bytes([bytes[10], bytes[11]]),
+        })
+    }
+}
+
+/// Answer to `Hello`: the version both sides use from now on, or 0 if
+/// they have none in common
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HelloAck {
+    pub version: u16,
+}
+
+impl HelloAck {
+    pub fn to_bytes(&self) -> [u8; 2] {
+        self.version.to_le_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self { version: u16::from_le_bytes([*bytes.first()?, *bytes.get(1)?]) })
+    }
+}
+
 // ============================================================================
 // Message Types
 // ============================================================================
@@ -76,24 +160,78 @@ pub enum MessageType {
     MouseButtonDown = 11,
     MouseButtonUp = 12,
     MouseScroll = 13,
+    SetKeyboardLayout = 20,
+    SetPointerSettings = 21,
+    SetShortcut = 22,
 
     // Window Management (100-199)
     CreateWindow = 100,
     CreateWindowResponse = 101,
     DestroyWindow = 102,
+    /// Replace a window's surface with one of another size; `WindowResize`
+    /// payload, answered with `SurfaceRegion` on the window's event port
     ResizeWindow = 103,
     MoveWindow = 104,
     FocusWindow = 105,
     WindowEvent = 106,
+    CommitFrame = 107,
+    SurfaceRegion = 108,
+    SetWindowOpacity = 109,
+    /// Turn window animations on (1) or off (0); one-byte payload
+    SetAnimations = 110,
+    /// Change the output scale; one-byte `ScaleFactor` payload
+    SetScale = 111,
+    /// Sent to every window after the output scale changed; one-byte
+    /// `ScaleFactor` payload
+    ScaleChanged = 112,
+    /// Pointer shape over a window's client area; `WindowCursor` payload
+    SetCursor = 113,
+    /// Parent, modality and stacking layer of a window; `WindowRole` payload
+    SetWindowRole = 114,
+    /// Re-register a window with a restarted compositor; `ReattachRequest`
+    /// payload, answered with `SurfaceRegion` like `CreateWindow`
+    ReattachWindow = 115,
+    /// Sent to a window's port once a frame with its last commit is on
+    /// screen; `FrameDone` payload
+    FrameDone = 116,
+    /// Text shown in a window's title bar; `WindowTitle` payload
+    SetTitle = 117,
 
     // Graphics (200-299)
+    /// Payload is the u64 port to send the `FramebufferInfo` reply to
     GetFramebuffer = 200,
     FramebufferInfo = 201,
+    /// Show an area of the screen again at the next present without
+    /// blitting to it; `Rect` payload
     InvalidateRect = 202,
+    /// Copy everything blitted since the last present to the screen;
+    /// payload is the u64 port to send `PresentDone` to, or 0 for none
     Present = 203,
+    /// Payload is the u64 port to send the `DisplayList` reply to
+    GetDisplays = 204,
+    DisplayList = 205,
+    /// `CreateSurfaceRequest` payload, answered with `SurfaceCreated`
     CreateSurface = 210,
+    /// Payload is the u32 id of the surface to free
     DestroySurface = 211,
+    /// `BlitRequest` payload
     BlitSurface = 212,
+    /// Reply to `CreateSurface`; `SurfaceInfo` payload
+    SurfaceCreated = 213,
+    /// Sent once a present is on screen; `PresentDone` payload
+    PresentDone = 214,
+    /// Payload is the u64 port to send the `ModeList` reply to
+    GetModes = 215,
+    ModeList = 216,
+    /// Change the screen resolution; `SetModeRequest` payload, answered
+    /// with `ModeChanged`. Accepted by the graphics service and by the
+    /// desktop, which also lays the desktop out again.
+    SetMode = 217,
+    /// Reply to `SetMode`; `ModeChanged` payload
+    ModeChanged = 218,
+    /// Payload is the u64 port to send the `HeadList` reply to
+    GetHeads = 219,
+    HeadList = 220,
 
     // Service Discovery (300-399)
     RegisterService = 300,
@@ -104,7 +242,114 @@ pub enum MessageType {
     Ping = 400,
     Pong = 401,
     Shutdown = 402,
+    /// Sent by the kernel to ports watching one that was closed or whose
+    /// owner exited; u64 port id payload
+    PortDied = 403,
+    /// Sent before anything else to agree on a protocol version; `Hello`
+    /// payload
+    Hello = 404,
+    /// Reply to `Hello`; `HelloAck` payload
+    HelloAck = 405,
+    /// Answer to a request made through `rpc::Client`; `rpc::ReplyHeader`
+    /// then the result
+    RpcReply = 406,
+    /// Sent by a `ring::RingProducer` when it writes to a ring whose
+    /// consumer is waiting; u64 region id payload
+    RingDoorbell = 407,
+    /// Answer to a request the service failed, in place of its usual
+    /// reply; `status::ErrorReply` payload
     Error = 499,
+
+    // Audio (500-599)
+    AudioOpenStream = 500,
+    AudioStreamOpened = 501,
+    AudioCloseStream = 502,
+    AudioSetVolume = 503,
+    AudioBeep = 504,
+
+    // Clipboard (600-699)
+    SetClipboard = 600,
+    GetClipboard = 601,
+    ClipboardData = 602,
+    ClipboardChanged = 603,
+
+    // Drag and Drop (700-799)
+    DragStart = 700,
+    DragEnter = 701,
+    DragMotion = 702,
+    DragLeave = 703,
+    Drop = 704,
+    DragEnd = 705,
+
+    // Screen Capture (800-899)
+    CaptureScreen = 800,
+    CaptureWindow = 801,
+    CaptureResult = 802,
+
+    // Notifications (900-999)
+    Notify = 900,
+    GetNotificationHistory = 901,
+    NotificationHistory = 902,
+    /// Turn do-not-disturb on (1) or off (0); one-byte payload
+    SetDoNotDisturb = 903,
+
+    // Panel Widgets (1000-1099)
+    SetPanelWidget = 1000,
+    /// Payload is the widget's u32 id
+    RemovePanelWidget = 1001,
+
+    // Desktop Background (1100-1199)
+    SetWallpaper = 1100,
+    /// Back to the plain background colour; no payload
+    ClearWallpaper = 1101,
+    /// Reply to `SetWallpaper`: one byte, 1 if the wallpaper was applied
+    WallpaperResult = 1102,
+
+    // Theming (1200-1299)
+    /// Payload is a theme config file (UTF-8 text)
+    SetTheme = 1200,
+    /// Payload is the new `ThemeSpec`; also sent after a window's surface
+    ThemeChanged = 1201,
+
+    // Program Output (1300-1399)
+    /// Sent by a spawned program to its output port; payload is text,
+    /// which may contain ANSI escape sequences
+    ProgramOutput = 1300,
+    /// Like `ProgramOutput`, for error messages
+    ProgramError = 1301,
+    /// Sent by a program to its output port to change how the terminal
+    /// handles what is typed; payload is `TerminalMode`
+    SetTerminalMode = 1302,
+    /// Sent by the terminal to the input port named in the program's
+    /// `TerminalMode`; payload is UTF-8 text, with escape sequences for
+    /// keys that are not text
+    ProgramInput = 1303,
+
+    // Event Streams (1400-1499), see `stream`
+    /// Sent by a consumer to a producer; payload is `StreamControl` with
+    /// the port events go to and the credits it starts with
+    Subscribe = 1400,
+    /// Payload is `StreamControl`; its credits are unused
+    Unsubscribe = 1401,
+    /// Sent by a consumer as it handles events; payload is `StreamControl`
+    /// with the credits to add
+    GrantCredits = 1402,
+    /// Sent to a consumer ahead of the events that follow a gap; payload is
+    /// the number of events dropped (u32)
+    EventsDropped = 1403,
+
+    // Files (1500-1599), `rpc` requests to the filesystem service
+    /// Args are `FileOpen`; the result is the file's handle (u64)
+    FileOpen = 1500,
+    /// Args are `FileRead`; the result is the bytes read, none at the end
+    FileRead = 1501,
+    /// Args are the handle (u64) then the data; the result is the number
+    /// of bytes written (u32)
+    FileWrite = 1502,
+    /// Args are the handle (u64)
+    FileClose = 1503,
+    /// Args are the path (UTF-8); the result is `FileStat`
+    FileStat = 1504,
 }
 
 impl MessageType {
@@ -117,6 +362,9 @@ impl MessageType {
             11 => Some(Self::MouseButtonDown),
             12 => Some(Self::MouseButtonUp),
             13 => Some(Self::MouseScroll),
+            20 => Some(Self::SetKeyboardLayout),
+            21 => Some(Self::SetPointerSettings),
+            22 => Some(Self::SetShortcut),
             100 => Some(Self::CreateWindow),
             101 => Some(Self::CreateWindowResponse),
             102 => Some(Self::DestroyWindow),
@@ -124,20 +372,88 @@ impl MessageType {
             104 => Some(Self::MoveWindow),
             105 => Some(Self::FocusWindow),
             106 => Some(Self::WindowEvent),
+            107 => Some(Self::CommitFrame),
+            108 => Some(Self::SurfaceRegion),
+            109 => Some(Self::SetWindowOpacity),
+            110 => Some(Self::SetAnimations),
+            111 => Some(Self::SetScale),
+            112 => Some(Self::ScaleChanged),
+            113 => Some(Self::SetCursor),
+            114 => Some(Self::SetWindowRole),
+            115 => Some(Self::ReattachWindow),
+            116 => Some(Self::FrameDone),
+            117 => Some(Self::SetTitle),
             200 => Some(Self::GetFramebuffer),
             201 => Some(Self::FramebufferInfo),
             202 => Some(Self::InvalidateRect),
             203 => Some(Self::Present),
+            204 => Some(Self::GetDisplays),
+            205 => Some(Self::DisplayList),
             210 => Some(Self::CreateSurface),
             211 => Some(Self::DestroySurface),
             212 => Some(Self::BlitSurface),
+            213 => Some(Self::SurfaceCreated),
+            214 => Some(Self::PresentDone),
+            215 => Some(Self::GetModes),
+            216 => Some(Self::ModeList),
+            217 => Some(Self::SetMode),
+            218 => Some(Self::ModeChanged),
+            219 => Some(Self::GetHeads),
+            220 => Some(Self::HeadList),
             300 => Some(Self::RegisterService),
             301 => Some(Self::LookupService),
             302 => Some(Self::ServiceInfo),
             400 => Some(Self::Ping),
             401 => Some(Self::Pong),
             402 => Some(Self::Shutdown),
+            403 => Some(Self::PortDied),
+            404 => Some(Self::Hello),
+            405 => Some(Self::HelloAck),
+            406 => Some(Self::RpcReply),
+            407 => Some(Self::RingDoorbell),
             499 => Some(Self::Error),
+            500 => Some(Self::AudioOpenStream),
+            501 => Some(Self::AudioStreamOpened),
+            502 => Some(Self::AudioCloseStream),
+            503 => Some(Self::AudioSetVolume),
+            504 => Some(Self::AudioBeep),
+            600 => Some(Self::SetClipboard),
+            601 => Some(Self::GetClipboard),
+            602 => Some(Self::ClipboardData),
+            603 => Some(Self::ClipboardChanged),
+            700 => Some(Self::DragStart),
+            701 => Some(Self::DragEnter),
+            702 => Some(Self::DragMotion),
+            703 => Some(Self::DragLeave),
+            704 => Some(Self::Drop),
+            705 => Some(Self::DragEnd),
+            800 => Some(Self::CaptureScreen),
+            801 => Some(Self::CaptureWindow),
+            802 => Some(Self::CaptureResult),
+            900 => Some(Self::Notify),
+            901 => Some(Self::GetNotificationHistory),
+            902 => Some(Self::NotificationHistory),
+            903 => Some(Self::SetDoNotDisturb),
+            1000 => Some(Self::SetPanelWidget),
+            1001 => Some(Self::RemovePanelWidget),
+            1100 => Some(Self::SetWallpaper),
+            1101 => Some(Self::ClearWallpaper),
+            1102 => Some(Self::WallpaperResult),
+            1200 => Some(Self::SetTheme),
+            1201 => Some(Self::ThemeChanged),
+            1300 => Some(Self::ProgramOutput),
+            1301 => Some(Self::ProgramError),
+            1302 => Some(Self::SetTerminalMode),
+            1303 => Some(Self::ProgramInput),
+            1400 => Some(Self::Subscribe),
+            1401 => Some(Self::Unsubscribe),
+            1402 => Some(Self::GrantCredits),
+            1403 => Some(Self::EventsDropped),
+            1500 => Some(Self::FileOpen),
+            1501 => Some(Self::FileRead),
+            1502 => Some(Self::FileWrite),
+            1503 => Some(Self::FileClose),
+            1504 => Some(Self::FileStat),
             _ => None,
         }
     }
@@ -147,6 +463,17 @@ impl MessageType {
 // Input Event Messages
 // ============================================================================
 
+/// Kernel timer ticks (`atom_syscall::thread::get_ticks`) at which an input
+/// event was captured. Lets receivers detect double clicks and measure
+/// input latency independently of when the message was delivered.
+pub type Timestamp = u64;
+
+fn read_timestamp(bytes: &[u8]) -> Timestamp {
+    let mut raw = [0u8; 8];
+    raw.copy_from_slice(&bytes[..8]);
+    Timestamp::from_le_bytes(raw)
+}
+
 /// Key modifier flags
 #[derive(Debug, Clone, Copy, Default)]
 pub struct KeyModifiers {
@@ -179,27 +506,249 @@ impl KeyModifiers {
 /// Keyboard event
 #[derive(Debug, Clone, Copy)]
 pub struct KeyEvent {
-    /// Scancode from hardware
-    pub scancode: u8,
-    /// ASCII character (if applicable)
+    /// Physical key (independent of bus and scancode set)
+    pub keycode: KeyCode,
+    /// Character in ISO-8859-1 (Latin-1), 0 if the key produces none
     pub character: u8,
     /// Key modifiers
     pub modifiers: KeyModifiers,
+    /// Timer ticks when the driver saw the key (see `Timestamp`)
+    pub timestamp: Timestamp,
 }
 
 impl KeyEvent {
-    pub fn to_bytes(&self) -> [u8; 3] {
-        [self.scancode, self.character, self.modifiers.to_u8()]
+    pub fn to_bytes(&self) -> [u8; 11] {
+        let mut bytes = [0u8; 11];
+        bytes[0] = self.keycode as u8;
+        bytes[1] = self.character;
+        bytes[2] = self.modifiers.to_u8();
+        bytes[3..11].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() < 3 {
+        if bytes.len() < 11 {
             return None;
         }
         Some(Self {
-            scancode: bytes[0],
+            keycode: KeyCode::from_u8(bytes[0])?,
             character: bytes[1],
             modifiers: KeyModifiers::from_u8(bytes[2]),
+            timestamp: read_timestamp(&bytes[3..11]),
+        })
+    }
+}
+
+/// Keyboard layouts understood by the keyboard driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum KeyboardLayout {
+    /// US QWERTY
+    Us = 0,
+    /// Brazilian ABNT2
+    Abnt2 = 1,
+    /// German QWERTZ
+    De = 2,
+    /// French AZERTY
+    Fr = 3,
+}
+
+impl KeyboardLayout {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Us),
+            1 => Some(Self::Abnt2),
+            2 => Some(Self::De),
+            3 => Some(Self::Fr),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Us => "us",
+            Self::Abnt2 => "br-abnt2",
+            Self::De => "de",
+            Self::Fr => "fr",
+        }
+    }
+}
+
+/// Request to switch the active keyboard layout
+#[derive(Debug, Clone, Copy)]
+pub struct SetKeyboardLayoutRequest {
+    pub layout: KeyboardLayout,
+}
+
+impl SetKeyboardLayoutRequest {
+    pub fn to_bytes(&self) -> [u8; 1] {
+        [self.layout as u8]
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            layout: KeyboardLayout::from_u8(*bytes.first()?)?,
+        })
+    }
+}
+
+/// Pointer acceleration profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AccelProfile {
+    /// Constant gain: cursor distance is proportional to mouse distance
+    Flat = 0,
+    /// Gain grows with pointer speed (precise when slow, far when fast)
+    Adaptive = 1,
+}
+
+impl AccelProfile {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Flat),
+            1 => Some(Self::Adaptive),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Flat => "flat",
+            Self::Adaptive => "adaptive",
+        }
+    }
+}
+
+/// Pointer speed settings, sent to the compositor with `SetPointerSettings`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PointerSettings {
+    pub profile: AccelProfile,
+    /// Base speed multiplier in percent (100 = 1:1)
+    pub sensitivity: u16,
+}
+
+impl PointerSettings {
+    pub const MIN_SENSITIVITY: u16 = 10;
+    pub const MAX_SENSITIVITY: u16 = 400;
+
+    /// Flat 1:1, matching the driver's raw counts
+    pub const DEFAULT: Self = Self {
+        profile: AccelProfile::Flat,
+        sensitivity: 100,
+    };
+
+    pub fn to_bytes(&self) -> [u8; 3] {
+        let sensitivity = self.sensitivity.to_le_bytes();
+        [self.profile as u8, sensitivity[0], sensitivity[1]]
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 3 {
+            return None;
+        }
+        Some(Self {
+            profile: AccelProfile::from_u8(bytes[0])?,
+            sensitivity: u16::from_le_bytes([bytes[1], bytes[2]]),
+        })
+    }
+}
+
+impl Default for PointerSettings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Compositor actions that can be bound to a key combination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ShortcutAction {
+    /// Cycle forward through windows (Alt+Tab)
+    SwitchWindow = 0,
+    /// Cycle backward through windows (Alt+Shift+Tab)
+    SwitchWindowReverse = 1,
+    CloseWindow = 2,
+    SnapLeft = 3,
+    SnapRight = 4,
+    /// Maximize, or move a snapped window up to a quarter (Super+Up)
+    Maximize = 5,
+    /// Minimize, or move a snapped window down a tile (Super+Down)
+    Minimize = 6,
+    /// Show workspace 1-4 (Super+1..4)
+    Workspace1 = 7,
+    Workspace2 = 8,
+    Workspace3 = 9,
+    Workspace4 = 10,
+    /// Move the focused window to workspace 1-4 (Super+Shift+1..4)
+    MoveToWorkspace1 = 11,
+    MoveToWorkspace2 = 12,
+    MoveToWorkspace3 = 13,
+    MoveToWorkspace4 = 14,
+    /// Capture the screen (PrintScreen)
+    Screenshot = 15,
+    /// Show or hide the performance HUD (Super+Shift+P)
+    TogglePerformanceHud = 16,
+}
+
+impl ShortcutAction {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::SwitchWindow),
+            1 => Some(Self::SwitchWindowReverse),
+            2 => Some(Self::CloseWindow),
+            3 => Some(Self::SnapLeft),
+            4 => Some(Self::SnapRight),
+            5 => Some(Self::Maximize),
+            6 => Some(Self::Minimize),
+            7 => Some(Self::Workspace1),
+            8 => Some(Self::Workspace2),
+            9 => Some(Self::Workspace3),
+            10 => Some(Self::Workspace4),
+            11 => Some(Self::MoveToWorkspace1),
+            12 => Some(Self::MoveToWorkspace2),
+            13 => Some(Self::MoveToWorkspace3),
+            14 => Some(Self::MoveToWorkspace4),
+            15 => Some(Self::Screenshot),
+            16 => Some(Self::TogglePerformanceHud),
+            _ => None,
+        }
+    }
+}
+
+/// Key combination for a compositor action, sent with `SetShortcut`
+///
+/// `modifiers` uses the `MOD_*` bits, which match `KeyModifiers::to_u8`
+/// plus one for the Super (Windows) key. Binding an action again replaces
+/// its previous combination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShortcutBinding {
+    pub action: ShortcutAction,
+    pub modifiers: u8,
+    pub key: KeyCode,
+}
+
+impl ShortcutBinding {
+    pub const MOD_SHIFT: u8 = 0x01;
+    pub const MOD_CTRL: u8 = 0x02;
+    pub const MOD_ALT: u8 = 0x04;
+    pub const MOD_SUPER: u8 = 0x10;
+
+    pub const fn new(action: ShortcutAction, modifiers: u8, key: KeyCode) -> Self {
+        Self { action, modifiers, key }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 3] {
+        [self.action as u8, self.modifiers, self.key as u8]
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 3 {
+            return None;
+        }
+        Some(Self {
+            action: ShortcutAction::from_u8(bytes[0])?,
+            modifiers: bytes[1],
+            key: KeyCode::from_u8(bytes[2])?,
         })
     }
 }
@@ -211,6 +760,10 @@ pub enum MouseButton {
     Left = 0,
     Right = 1,
     Middle = 2,
+    /// Fourth (thumb) button, usually "back"
+    Back = 3,
+    /// Fifth (thumb) button, usually "forward"
+    Forward = 4,
 }
 
 impl MouseButton {
@@ -219,6 +772,8 @@ impl MouseButton {
             0 => Some(Self::Left),
             1 => Some(Self::Right),
             2 => Some(Self::Middle),
+            3 => Some(Self::Back),
+            4 => Some(Self::Forward),
             _ => None,
         }
     }
@@ -235,20 +790,23 @@ pub struct MouseMoveEvent {
     pub dx: i16,
     /// Delta Y (relative movement)
     pub dy: i16,
+    /// Ticks of the newest motion folded into this event
+    pub timestamp: Timestamp,
 }
 
 impl MouseMoveEvent {
-    pub fn to_bytes(&self) -> [u8; 12] {
-        let mut bytes = [0u8; 12];
+    pub fn to_bytes(&self) -> [u8; 20] {
+        let mut bytes = [0u8; 20];
         bytes[0..4].copy_from_slice(&self.x.to_le_bytes());
         bytes[4..8].copy_from_slice(&self.y.to_le_bytes());
         bytes[8..10].copy_from_slice(&self.dx.to_le_bytes());
         bytes[10..12].copy_from_slice(&self.dy.to_le_bytes());
+        bytes[12..20].copy_from_slice(&self.timestamp.to_le_bytes());
         bytes
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() < 12 {
+        if bytes.len() < 20 {
             return None;
         }
         Some(Self {
@@ -256,35 +814,117 @@ impl MouseMoveEvent {
             y: i32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
             dx: i16::from_le_bytes([bytes[8], bytes[9]]),
             dy: i16::from_le_bytes([bytes[10], bytes[11]]),
+            timestamp: read_timestamp(&bytes[12..20]),
         })
     }
 }
 
+/// Folds consecutive motion into one `MouseMoveEvent`
+///
+/// Drivers add every decoded packet and send the result once per poll
+/// cycle, so the compositor handles at most one motion per frame no matter
+/// how high the device's report rate is. Button and wheel events are never
+/// coalesced; flush pending motion before sending them to keep ordering.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MotionCoalescer {
+    dx: i32,
+    dy: i32,
+    timestamp: Timestamp,
+    pending: bool,
+}
+
+impl MotionCoalescer {
+    pub const fn new() -> Self {
+        Self { dx: 0, dy: 0, timestamp: 0, pending: false }
+    }
+
+    pub fn add(&mut self, dx: i16, dy: i16, timestamp: Timestamp) {
+        self.dx += dx as i32;
+        self.dy += dy as i32;
+        self.timestamp = timestamp;
+        self.pending = true;
+    }
+
+    /// Take the accumulated motion, if any
+    pub fn take(&mut self) -> Option<MouseMoveEvent> {
+        if !self.pending {
+            return None;
+        }
+        let event = MouseMoveEvent {
+            x: 0,
+            y: 0,
+            dx: self.dx.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+            dy: self.dy.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+            timestamp: self.timestamp,
+        };
+        *self = Self::new();
+        Some(event)
+    }
+}
+
 /// Mouse button event
 #[derive(Debug, Clone, Copy)]
 pub struct MouseButtonEvent {
     pub button: MouseButton,
     pub x: i32,
     pub y: i32,
+    pub timestamp: Timestamp,
 }
 
 impl MouseButtonEvent {
-    pub fn to_bytes(&self) -> [u8; 9] {
-        let mut bytes = [0u8; 9];
+    pub fn to_bytes(&self) -> [u8; 17] {
+        let mut bytes = [0u8; 17];
         bytes[0] = self.button as u8;
         bytes[1..5].copy_from_slice(&self.x.to_le_bytes());
         bytes[5..9].copy_from_slice(&self.y.to_le_bytes());
+        bytes[9..17].copy_from_slice(&self.timestamp.to_le_bytes());
         bytes
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() < 9 {
+        if bytes.len() < 17 {
             return None;
         }
         Some(Self {
             button: MouseButton::from_u8(bytes[0])?,
             x: i32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]),
             y: i32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]),
+            timestamp: read_timestamp(&bytes[9..17]),
+        })
+    }
+}
+
+/// Mouse wheel event
+#[derive(Debug, Clone, Copy)]
+pub struct MouseScrollEvent {
+    /// Cursor X position when the wheel moved
+    pub x: i32,
+    /// Cursor Y position when the wheel moved
+    pub y: i32,
+    /// Wheel detents, positive = away from the user (scroll up)
+    pub delta: i16,
+    pub timestamp: Timestamp,
+}
+
+impl MouseScrollEvent {
+    pub fn to_bytes(&self) -> [u8; 18] {
+        let mut bytes = [0u8; 18];
+        bytes[0..4].copy_from_slice(&self.x.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.y.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.delta.to_le_bytes());
+        bytes[10..18].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 18 {
+            return None;
+        }
+        Some(Self {
+            x: i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            y: i32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            delta: i16::from_le_bytes([bytes[8], bytes[9]]),
+            timestamp: read_timestamp(&bytes[10..18]),
         })
     }
 }
@@ -293,50 +933,188 @@ impl MouseButtonEvent {
 // Window Management Messages
 // ============================================================================
 
+// An application sends `CreateWindow` to the compositor's port, which it
+// looks up as `ServiceId::Desktop`, and gets a `SurfaceRegion` back on its
+// reply port. Everything about the window after that (`WindowEvent`s,
+// `FrameDone`, replies) goes to the same port; requests about it name its
+// `WindowId`, and `DestroyWindow` ends it.
+
 /// Window handle (assigned by desktop compositor)
 pub type WindowId = u32;
 
+/// Output scale factor for HiDPI screens, stored in half steps
+///
+/// Window decorations, the cursor and the compositor's text are drawn this
+/// many times larger. Sizes applications ask for are in logical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ScaleFactor {
+    X1 = 2,
+    X1_5 = 3,
+    X2 = 4,
+}
+
+impl ScaleFactor {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            2 => Some(Self::X1),
+            3 => Some(Self::X1_5),
+            4 => Some(Self::X2),
+            _ => None,
+        }
+    }
+
+    /// Logical length to physical pixels
+    pub fn apply(self, value: u32) -> u32 {
+        value * self as u32 / 2
+    }
+
+    /// Logical coordinate to physical pixels
+    pub fn apply_i32(self, value: i32) -> i32 {
+        value * self as i32 / 2
+    }
+
+    /// A length measured at scale `from`, measured at this scale instead
+    pub fn convert(self, value: i32, from: ScaleFactor) -> i32 {
+        value * self as i32 / from as i32
+    }
+}
+
 /// Request to create a new window
+///
+/// The compositor answers on `reply_port` with a `SurfaceRegion` and sends
+/// all later events for the window to the same port.
 #[derive(Debug, Clone)]
 pub struct CreateWindowRequest {
+    pub reply_port: u64,
+    /// Size of the client area, excluding decorations, in logical pixels
     pub width: u32,
     pub height: u32,
+    /// The application renders at the output scale, into a surface that
+    /// many times larger; otherwise the compositor stretches its surface
+    pub native_scale: bool,
     pub title: String,
+    /// Identifies the application across launches, so the compositor can
+    /// put its window back where it was; empty for none
+    pub app_id: String,
 }
 
 impl CreateWindowRequest {
     pub fn to_bytes(&self) -> Vec<u8> {
         let title_bytes = self.title.as_bytes();
-        let mut bytes = Vec::with_capacity(12 + title_bytes.len());
+        let app_id_bytes = self.app_id.as_bytes();
+        let mut bytes = Vec::with_capacity(25 + title_bytes.len() + app_id_bytes.len());
+        bytes.extend_from_slice(&self.reply_port.to_le_bytes());
         bytes.extend_from_slice(&self.width.to_le_bytes());
         bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.push(self.native_scale as u8);
         bytes.extend_from_slice(&(title_bytes.len() as u32).to_le_bytes());
         bytes.extend_from_slice(title_bytes);
+        bytes.extend_from_slice(&(app_id_bytes.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(app_id_bytes);
         bytes
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() < 12 {
+        if bytes.len() < 21 {
             return None;
         }
-        let width = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
-        let height = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
-        let title_len = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+        let reply_port = u64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let width = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        let height = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]);
+        let native_scale = bytes[16] != 0;
+        let title_len = u32::from_le_bytes([bytes[17], bytes[18], bytes[19], bytes[20]]) as usize;
 
-        if bytes.len() < 12 + title_len {
+        let app_id_at = 21 + title_len;
+        if bytes.len() < app_id_at + 4 {
             return None;
         }
 
-        let title = core::str::from_utf8(&bytes[12..12 + title_len]).ok()?;
+        let title = core::str::from_utf8(&bytes[21..app_id_at]).ok()?;
+
+        let app_id_len = u32::from_le_bytes([
+            bytes[app_id_at],
+            bytes[app_id_at + 1],
+            bytes[app_id_at + 2],
+            bytes[app_id_at + 3],
+        ]) as usize;
+        let app_id = bytes.get(app_id_at + 4..app_id_at + 4 + app_id_len)?;
+        let app_id = core::str::from_utf8(app_id).ok()?;
 
         Some(Self {
+            reply_port,
             width,
             height,
+            native_scale,
+            title: String::from(title),
+            app_id: String::from(app_id),
+        })
+    }
+}
+
+/// A window whose compositor went away, offered to its replacement with
+/// the surface the application still has mapped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReattachRequest {
+    pub reply_port: u64,
+    pub region_id: u64,
+    /// Surface size in pixels
+    pub width: u32,
+    pub height: u32,
+    /// Row length in pixels
+    pub stride: u32,
+    /// Scale the surface is rendered at
+    pub scale: ScaleFactor,
+    pub title: String,
+    pub app_id: String,
+}
+
+impl ReattachRequest {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (title, app_id) = (self.title.as_bytes(), self.app_id.as_bytes());
+        let mut bytes = Vec::with_capacity(37 + title.len() + app_id.len());
+        bytes.extend_from_slice(&self.reply_port.to_le_bytes());
+        bytes.extend_from_slice(&self.region_id.to_le_bytes());
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.extend_from_slice(&self.stride.to_le_bytes());
+        bytes.push(self.scale as u8);
+        bytes.extend_from_slice(&(title.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(title);
+        bytes.extend_from_slice(&(app_id.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(app_id);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 37 {
+            return None;
+        }
+        let u32_at = |at: usize| Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+        let u64_at = |at: usize| Some(u64::from_le_bytes(bytes.get(at..at + 8)?.try_into().ok()?));
+        let str_at = |at: usize, len: usize| core::str::from_utf8(bytes.get(at..at + len)?).ok();
+
+        let title_len = u32_at(29)? as usize;
+        let title = str_at(33, title_len)?;
+        let app_id_len = u32_at(33 + title_len)? as usize;
+        let app_id = str_at(37 + title_len, app_id_len)?;
+
+        Some(Self {
+            reply_port: u64_at(0)?,
+            region_id: u64_at(8)?,
+            width: u32_at(16)?,
+            height: u32_at(20)?,
+            stride: u32_at(24)?,
+            scale: ScaleFactor::from_u8(bytes[28])?,
             title: String::from(title),
+            app_id: String::from(app_id),
         })
     }
 }
 
+/// What the compositor answers `CreateWindow` and `ReattachWindow` with
+pub type CreateWindowReply = SurfaceRegion;
+
 /// Response to create window request
 #[derive(Debug, Clone, Copy)]
 pub struct CreateWindowResponse {
@@ -373,6 +1151,12 @@ pub enum WindowEventType {
     Unfocus = 4,
     Close = 5,
     Expose = 6,  // Area needs redraw
+    /// The user resized the window; redraw the surface at width x height
+    ResizeRequested = 7,
+    /// The pointer moved onto the client area, at x, y in it
+    PointerEnter = 8,
+    /// The pointer left the client area
+    PointerLeave = 9,
 }
 
 impl WindowEventType {
@@ -384,6 +1168,9 @@ impl WindowEventType {
             4 => Some(Self::Unfocus),
             5 => Some(Self::Close),
             6 => Some(Self::Expose),
+            7 => Some(Self::ResizeRequested),
+            8 => Some(Self::PointerEnter),
+            9 => Some(Self::PointerLeave),
             _ => None,
         }
     }
@@ -401,6 +1188,38 @@ pub struct WindowEventMsg {
 }
 
 impl WindowEventMsg {
+    /// An event that carries nothing but its type
+    pub fn new(window_id: WindowId, event_type: WindowEventType) -> Self {
+        Self { window_id, event_type, x: 0, y: 0, width: 0, height: 0 }
+    }
+
+    /// The window gained or lost focus
+    pub fn focus_changed(window_id: WindowId, focused: bool) -> Self {
+        let event_type = if focused { WindowEventType::Focus } else { WindowEventType::Unfocus };
+        Self::new(window_id, event_type)
+    }
+
+    /// The user asked to close the window; the application answers with
+    /// `DestroyWindow`, or not to keep it open
+    pub fn close_requested(window_id: WindowId) -> Self {
+        Self::new(window_id, WindowEventType::Close)
+    }
+
+    /// The user resized the window; `width` x `height` are in the
+    /// surface's pixels
+    pub fn resize_requested(window_id: WindowId, width: u32, height: u32) -> Self {
+        Self { width, height, ..Self::new(window_id, WindowEventType::ResizeRequested) }
+    }
+
+    /// The pointer moved onto the client area, at (x, y) in it
+    pub fn pointer_enter(window_id: WindowId, x: i32, y: i32) -> Self {
+        Self { x, y, ..Self::new(window_id, WindowEventType::PointerEnter) }
+    }
+
+    pub fn pointer_leave(window_id: WindowId) -> Self {
+        Self::new(window_id, WindowEventType::PointerLeave)
+    }
+
     pub fn to_bytes(&self) -> [u8; 21] {
         let mut bytes = [0u8; 21];
         bytes[0..4].copy_from_slice(&self.window_id.to_le_bytes());
@@ -427,35 +1246,403 @@ impl WindowEventMsg {
     }
 }
 
-// ============================================================================
-// Graphics Messages
-// ============================================================================
+/// Bytes per surface pixel (32-bit BGRX, the framebuffer's format)
+pub const SURFACE_BYTES_PER_PIXEL: u32 = 4;
 
-/// Framebuffer information
+/// Largest surface the compositor will allocate
+pub const MAX_SURFACE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Reply to `CreateWindow`: the shared-memory surface backing the window
+///
+/// The application maps `region_id` read-write and draws into it, then
+/// sends `CommitFrame`. `stride` is in pixels. `window_id` is 0 when the
+/// window could not be created.
 #[derive(Debug, Clone, Copy)]
-pub struct FramebufferInfo {
-    pub address: u64,
+pub struct SurfaceRegion {
+    pub window_id: WindowId,
+    pub region_id: u64,
     pub width: u32,
     pub height: u32,
     pub stride: u32,
-    pub bytes_per_pixel: u32,
-    pub size: u64,
+    /// Scale the surface is drawn at; the output scale for windows asking
+    /// for `native_scale`, 1x for the rest
+    pub scale: ScaleFactor,
 }
 
-impl FramebufferInfo {
-    pub fn to_bytes(&self) -> [u8; 32] {
-        let mut bytes = [0u8; 32];
-        bytes[0..8].copy_from_slice(&self.address.to_le_bytes());
-        bytes[8..12].copy_from_slice(&self.width.to_le_bytes());
-        bytes[12..16].copy_from_slice(&self.height.to_le_bytes());
-        bytes[16..20].copy_from_slice(&self.stride.to_le_bytes());
-        bytes[20..24].copy_from_slice(&self.bytes_per_pixel.to_le_bytes());
-        bytes[24..32].copy_from_slice(&self.size.to_le_bytes());
+impl SurfaceRegion {
+    pub fn to_bytes(&self) -> [u8; 25] {
+        let mut bytes = [0u8; 25];
+        bytes[0..4].copy_from_slice(&self.window_id.to_le_bytes());
+        bytes[4..12].copy_from_slice(&self.region_id.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.width.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.height.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.stride.to_le_bytes());
+        bytes[24] = self.scale as u8;
         bytes
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
-        if bytes.len() < 32 {
+        if bytes.len() < 25 {
+            return None;
+        }
+        Some(Self {
+            window_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            region_id: u64::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7], bytes[8], bytes[9], bytes[10], bytes[11]]),
+            width: u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+            height: u32::from_le_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]),
+            stride: u32::from_le_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]),
+            scale: ScaleFactor::from_u8(bytes[24])?,
+        })
+    }
+}
+
+/// Most damage rectangles a `CommitFrame` carries; senders with more
+/// commit their bounding rectangle instead
+pub const MAX_COMMIT_RECTS: usize = 32;
+
+/// A new frame is ready in the window's surface
+///
+/// `damage` is the changed areas in surface coordinates, none for a frame
+/// that changed nothing but still wants its `FrameDone`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitFrame {
+    pub window_id: WindowId,
+    pub damage: Vec<Rect>,
+}
+
+impl CommitFrame {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let count = self.damage.len().min(MAX_COMMIT_RECTS);
+        let mut bytes = Vec::with_capacity(5 + count * 16);
+        bytes.extend_from_slice(&self.window_id.to_le_bytes());
+        bytes.push(count as u8);
+        for rect in &self.damage[..count] {
+            bytes.extend_from_slice(&rect.to_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 5 {
+            return None;
+        }
+        let count = bytes[4] as usize;
+        if count > MAX_COMMIT_RECTS {
+            return None;
+        }
+        let damage = (0..count)
+            .map(|i| Rect::from_bytes(bytes.get(5 + i * 16..5 + (i + 1) * 16)?))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            window_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            damage,
+        })
+    }
+}
+
+/// Ask the compositor to close a window, either unprompted or answering
+/// `WindowEventType::Close`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DestroyWindow {
+    pub window_id: WindowId,
+}
+
+impl DestroyWindow {
+    pub fn to_bytes(&self) -> [u8; 4] {
+        self.window_id.to_le_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self { window_id: u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?) })
+    }
+}
+
+/// Longest window title, in bytes; longer ones are cut at a character
+pub const MAX_TITLE_BYTES: usize = 256;
+
+/// Change the text in a window's title bar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowTitle {
+    pub window_id: WindowId,
+    pub title: String,
+}
+
+impl WindowTitle {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut end = self.title.len().min(MAX_TITLE_BYTES);
+        while !self.title.is_char_boundary(end) {
+            end -= 1;
+        }
+        let mut bytes = Vec::with_capacity(4 + end);
+        bytes.extend_from_slice(&self.window_id.to_le_bytes());
+        bytes.extend_from_slice(&self.title.as_bytes()[..end]);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let window_id = u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?);
+        let title = bytes.get(4..)?;
+        if title.len() > MAX_TITLE_BYTES {
+            return None;
+        }
+        Some(Self { window_id, title: String::from(core::str::from_utf8(title).ok()?) })
+    }
+}
+
+/// Ask for a new surface for a window, `width` x `height` in the
+/// surface's own pixels (as `ResizeRequested` gives them)
+///
+/// The old surface is freed, so the application unmaps it first; the new
+/// one starts out blank.
+#[derive(Debug, Clone, Copy)]
+pub struct WindowResize {
+    pub window_id: WindowId,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowResize {
+    pub fn to_bytes(&self) -> [u8; 12] {
+        let mut bytes = [0u8; 12];
+        bytes[0..4].copy_from_slice(&self.window_id.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.width.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.height.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 12 {
+            return None;
+        }
+        Some(Self {
+            window_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            width: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            height: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+        })
+    }
+}
+
+/// A composited frame included the window's committed content; the
+/// application can draw and commit its next frame
+#[derive(Debug, Clone, Copy)]
+pub struct FrameDone {
+    pub window_id: WindowId,
+    /// When the frame was presented, in milliseconds since boot
+    pub time_ms: u64,
+}
+
+impl FrameDone {
+    pub fn to_bytes(&self) -> [u8; 12] {
+        let mut bytes = [0u8; 12];
+        bytes[0..4].copy_from_slice(&self.window_id.to_le_bytes());
+        bytes[4..12].copy_from_slice(&self.time_ms.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 12 {
+            return None;
+        }
+        Some(Self {
+            window_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            time_ms: u64::from_le_bytes(bytes[4..12].try_into().ok()?),
+        })
+    }
+}
+
+/// How a window's content is combined with what is behind it
+///
+/// `opacity` fades the whole client area (255 = opaque). With
+/// `per_pixel_alpha` the top byte of each surface pixel is its alpha
+/// (ARGB); otherwise that byte is ignored.
+#[derive(Debug, Clone, Copy)]
+pub struct WindowOpacity {
+    pub window_id: WindowId,
+    pub opacity: u8,
+    pub per_pixel_alpha: bool,
+}
+
+impl WindowOpacity {
+    pub fn to_bytes(&self) -> [u8; 6] {
+        let mut bytes = [0u8; 6];
+        bytes[0..4].copy_from_slice(&self.window_id.to_le_bytes());
+        bytes[4] = self.opacity;
+        bytes[5] = self.per_pixel_alpha as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 6 {
+            return None;
+        }
+        Some(Self {
+            window_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            opacity: bytes[4],
+            per_pixel_alpha: bytes[5] != 0,
+        })
+    }
+}
+
+/// Pointer shapes the compositor can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CursorShape {
+    Arrow = 0,
+    /// Text insertion
+    IBeam = 1,
+    /// Something clickable
+    Hand = 2,
+    /// Left/right edge resize
+    ResizeHorizontal = 3,
+    /// Top/bottom edge resize
+    ResizeVertical = 4,
+    /// Top-left/bottom-right corner resize
+    ResizeDiagonal = 5,
+    /// Top-right/bottom-left corner resize
+    ResizeAntiDiagonal = 6,
+}
+
+impl CursorShape {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Arrow),
+            1 => Some(Self::IBeam),
+            2 => Some(Self::Hand),
+            3 => Some(Self::ResizeHorizontal),
+            4 => Some(Self::ResizeVertical),
+            5 => Some(Self::ResizeDiagonal),
+            6 => Some(Self::ResizeAntiDiagonal),
+            _ => None,
+        }
+    }
+}
+
+/// Shape the pointer takes over a window's client area; the title bar
+/// and edges keep the compositor's own shapes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowCursor {
+    pub window_id: WindowId,
+    pub shape: CursorShape,
+}
+
+impl WindowCursor {
+    pub fn to_bytes(&self) -> [u8; 5] {
+        let mut bytes = [0u8; 5];
+        bytes[0..4].copy_from_slice(&self.window_id.to_le_bytes());
+        bytes[4] = self.shape as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 5 {
+            return None;
+        }
+        Some(Self {
+            window_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            shape: CursorShape::from_u8(bytes[4])?,
+        })
+    }
+}
+
+/// Stacking class of a window; a window is always above every window of a
+/// lower layer, whatever has focus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum WindowLayer {
+    /// Below all application windows, e.g. desktop icons
+    Desktop = 0,
+    Normal = 1,
+    /// Docks and panels applications provide
+    Dock = 2,
+    /// On-screen displays and similar always-on-top windows
+    Overlay = 3,
+    Tooltip = 4,
+}
+
+impl WindowLayer {
+    /// Bottom to top
+    pub const ALL: [Self; 5] =
+        [Self::Desktop, Self::Normal, Self::Dock, Self::Overlay, Self::Tooltip];
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Desktop),
+            1 => Some(Self::Normal),
+            2 => Some(Self::Dock),
+            3 => Some(Self::Overlay),
+            4 => Some(Self::Tooltip),
+            _ => None,
+        }
+    }
+}
+
+/// How a window relates to the others
+///
+/// A transient window (`parent` non-zero, e.g. a dialog) is stacked right
+/// above its parent, in the parent's layer, and follows it between
+/// workspaces. A modal one also takes all input meant for its parent
+/// until it closes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowRole {
+    pub window_id: WindowId,
+    /// Window this one is transient for, 0 for none
+    pub parent: WindowId,
+    pub modal: bool,
+    pub layer: WindowLayer,
+}
+
+impl WindowRole {
+    pub fn to_bytes(&self) -> [u8; 10] {
+        let mut bytes = [0u8; 10];
+        bytes[0..4].copy_from_slice(&self.window_id.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.parent.to_le_bytes());
+        bytes[8] = self.modal as u8;
+        bytes[9] = self.layer as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 10 {
+            return None;
+        }
+        Some(Self {
+            window_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            parent: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            modal: bytes[8] != 0,
+            layer: WindowLayer::from_u8(bytes[9])?,
+        })
+    }
+}
+
+// ============================================================================
+// Graphics Messages
+// ============================================================================
+
+/// Framebuffer information
+#[derive(Debug, Clone, Copy)]
+pub struct FramebufferInfo {
+    pub address: u64,
+    pub width: u32,
+    pub height: u32,
+    pub stride: u32,
+    pub bytes_per_pixel: u32,
+    pub size: u64,
+}
+
+impl FramebufferInfo {
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        bytes[0..8].copy_from_slice(&self.address.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.width.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.height.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.stride.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.bytes_per_pixel.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.size.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 32 {
             return None;
         }
         Some(Self {
@@ -470,7 +1657,7 @@ impl FramebufferInfo {
 }
 
 /// Rectangle for damage/invalidation
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Rect {
     pub x: i32,
     pub y: i32,
@@ -483,6 +1670,57 @@ impl Rect {
         Self { x, y, width, height }
     }
 
+    /// One past the rightmost column
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    /// One past the bottom row
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// Overlapping area, or `None` if the rectangles do not overlap
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+
+        if right <= x || bottom <= y {
+            return None;
+        }
+        Some(Rect::new(x, y, (right - x) as u32, (bottom - y) as u32))
+    }
+
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// Smallest rectangle containing both
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect::new(x, y, (right - x) as u32, (bottom - y) as u32)
+    }
+
     pub fn to_bytes(&self) -> [u8; 16] {
         let mut bytes = [0u8; 16];
         bytes[0..4].copy_from_slice(&self.x.to_le_bytes());
@@ -504,3 +1742,1551 @@ impl Rect {
         })
     }
 }
+
+// The graphics service (`ServiceId::Graphics`, the display driver) owns the
+// framebuffer. A client creates surfaces, draws into them through shared
+// memory, and blits the changed parts to the screen with `BlitSurface`.
+// Nothing blitted shows until `Present`, which copies the areas blitted
+// since the last present to the framebuffer in one pass.
+
+/// Pixel layout of a surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PixelFormat {
+    /// 32-bit, in the framebuffer's own channel order (`Color::to_bgr32`)
+    Native = 0,
+    /// 32-bit with red in bits 16-23 and blue in bits 0-7
+    /// (`Color::to_rgb32`)
+    Rgb32 = 1,
+    /// `Native` with alpha in the top byte, blended over what is below
+    Argb32 = 2,
+    /// 16-bit with 5 bits of red, 6 of green and 5 of blue, red on top
+    Rgb565 = 3,
+}
+
+impl PixelFormat {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Native),
+            1 => Some(Self::Rgb32),
+            2 => Some(Self::Argb32),
+            3 => Some(Self::Rgb565),
+            _ => None,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> u32 {
+        match self {
+            Self::Rgb565 => 2,
+            _ => 4,
+        }
+    }
+}
+
+/// Request for a surface of `width` x `height` pixels
+///
+/// The service answers on `reply_port` with `SurfaceCreated`.
+#[derive(Debug, Clone, Copy)]
+pub struct CreateSurfaceRequest {
+    pub reply_port: u64,
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+}
+
+impl CreateSurfaceRequest {
+    pub fn to_bytes(&self) -> [u8; 17] {
+        let mut bytes = [0u8; 17];
+        bytes[0..8].copy_from_slice(&self.reply_port.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.width.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.height.to_le_bytes());
+        bytes[16] = self.format as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 17 {
+            return None;
+        }
+        Some(Self {
+            reply_port: u64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]),
+            width: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            height: u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+            format: PixelFormat::from_u8(bytes[16])?,
+        })
+    }
+}
+
+/// Reply to `CreateSurface`: the shared region backing the surface
+///
+/// The client maps `region_id` read-write and draws into it. `stride` is
+/// in pixels. `surface_id` is 0 when the surface could not be created.
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceInfo {
+    pub surface_id: u32,
+    pub region_id: u64,
+    pub width: u32,
+    pub height: u32,
+    pub stride: u32,
+    pub format: PixelFormat,
+}
+
+impl SurfaceInfo {
+    pub fn to_bytes(&self) -> [u8; 25] {
+        let mut bytes = [0u8; 25];
+        bytes[0..4].copy_from_slice(&self.surface_id.to_le_bytes());
+        bytes[4..12].copy_from_slice(&self.region_id.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.width.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.height.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.stride.to_le_bytes());
+        bytes[24] = self.format as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 25 {
+            return None;
+        }
+        Some(Self {
+            surface_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            region_id: u64::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7], bytes[8], bytes[9], bytes[10], bytes[11]]),
+            width: u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+            height: u32::from_le_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]),
+            stride: u32::from_le_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]),
+            format: PixelFormat::from_u8(bytes[24])?,
+        })
+    }
+}
+
+/// Most rectangles a `BlitRequest` carries; senders with more send their
+/// bounding rectangle instead
+pub const MAX_BLIT_RECTS: usize = 32;
+
+/// Copy areas of a surface to the screen at the next present
+///
+/// Each rectangle of `rects`, in surface coordinates, lands on the screen
+/// moved by (`x`, `y`): a full-screen surface blits with (0, 0), a cursor
+/// surface with its whole area at the pointer position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlitRequest {
+    pub surface_id: u32,
+    pub x: i32,
+    pub y: i32,
+    pub rects: Vec<Rect>,
+}
+
+impl BlitRequest {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let count = self.rects.len().min(MAX_BLIT_RECTS);
+        let mut bytes = Vec::with_capacity(13 + count * 16);
+        bytes.extend_from_slice(&self.surface_id.to_le_bytes());
+        bytes.extend_from_slice(&self.x.to_le_bytes());
+        bytes.extend_from_slice(&self.y.to_le_bytes());
+        bytes.push(count as u8);
+        for rect in &self.rects[..count] {
+            bytes.extend_from_slice(&rect.to_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 13 {
+            return None;
+        }
+        let count = bytes[12] as usize;
+        if count > MAX_BLIT_RECTS {
+            return None;
+        }
+        let rects = (0..count)
+            .map(|i| Rect::from_bytes(bytes.get(13 + i * 16..13 + (i + 1) * 16)?))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            surface_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            x: i32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            y: i32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            rects,
+        })
+    }
+}
+
+/// Sent to the port named in a `Present` once its frame is on screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentDone {
+    /// Pixels copied to the screen
+    pub pixels: u64,
+}
+
+impl PresentDone {
+    pub fn to_bytes(&self) -> [u8; 8] {
+        self.pixels.to_le_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            pixels: u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?),
+        })
+    }
+}
+
+// The screen's resolution can change while surfaces exist. Surfaces are
+// kept, but the screen starts out blank in the new mode: whoever draws the
+// screen blits all of it again. Only modes `GetModes` lists can be set.
+
+/// A screen resolution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayMode {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DisplayMode {
+    const SIZE: usize = 8;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.width.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.height.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE {
+            return None;
+        }
+        Some(Self {
+            width: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            height: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        })
+    }
+}
+
+/// Reply to `GetModes`: the modes the screen can switch to, the current
+/// one first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModeList {
+    pub modes: Vec<DisplayMode>,
+}
+
+impl ModeList {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let count = self.modes.len().min(u8::MAX as usize);
+        let mut bytes = Vec::with_capacity(1 + count * DisplayMode::SIZE);
+        bytes.push(count as u8);
+        for mode in &self.modes[..count] {
+            bytes.extend_from_slice(&mode.to_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let count = *bytes.first()? as usize;
+        let modes = (0..count)
+            .map(|i| DisplayMode::from_bytes(bytes.get(1 + i * DisplayMode::SIZE..)?))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { modes })
+    }
+}
+
+/// Request to switch the screen to `width` x `height`
+///
+/// The receiver answers on `reply_port` with `ModeChanged`.
+#[derive(Debug, Clone, Copy)]
+pub struct SetModeRequest {
+    pub reply_port: u64,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl SetModeRequest {
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[0..8].copy_from_slice(&self.reply_port.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.width.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.height.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 16 {
+            return None;
+        }
+        Some(Self {
+            reply_port: u64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]),
+            width: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            height: u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+        })
+    }
+}
+
+/// Reply to `SetMode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModeChanged {
+    /// Whether the requested mode was set
+    pub success: bool,
+    /// The mode in effect now, the old one if the switch failed
+    pub mode: DisplayMode,
+}
+
+impl ModeChanged {
+    pub fn to_bytes(&self) -> [u8; 9] {
+        let mut bytes = [0u8; 9];
+        bytes[0] = self.success as u8;
+        bytes[1..9].copy_from_slice(&self.mode.to_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let success = match *bytes.first()? {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        Some(Self {
+            success,
+            mode: DisplayMode::from_bytes(bytes.get(1..)?)?,
+        })
+    }
+}
+
+// A screen can span several monitors (heads) side by side, each showing
+// its own columns of it. The graphics service lists them so the desktop
+// can lay out one output per head.
+
+/// A monitor and the part of the screen it shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayHead {
+    /// The device's number for the head
+    pub scanout: u32,
+    /// Left edge on the screen; every head starts at the top
+    pub x: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DisplayHead {
+    const SIZE: usize = 16;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.scanout.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.x.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.width.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.height.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE {
+            return None;
+        }
+        Some(Self {
+            scanout: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            x: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            width: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            height: u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+        })
+    }
+}
+
+/// Reply to `GetHeads`: every head, left to right
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadList {
+    pub heads: Vec<DisplayHead>,
+}
+
+impl HeadList {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let count = self.heads.len().min(u8::MAX as usize);
+        let mut bytes = Vec::with_capacity(1 + count * DisplayHead::SIZE);
+        bytes.push(count as u8);
+        for head in &self.heads[..count] {
+            bytes.extend_from_slice(&head.to_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let count = *bytes.first()? as usize;
+        let heads = (0..count)
+            .map(|i| DisplayHead::from_bytes(bytes.get(1 + i * DisplayHead::SIZE..)?))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { heads })
+    }
+}
+
+// ============================================================================
+// Audio Messages
+// ============================================================================
+
+/// Request to open a PCM playback stream
+///
+/// The server answers on `reply_port` with `AudioStreamOpened`.
+#[derive(Debug, Clone, Copy)]
+pub struct AudioOpenStreamRequest {
+    pub reply_port: u64,
+    pub sample_rate: u32,
+    pub channels: u8,
+}
+
+impl AudioOpenStreamRequest {
+    pub fn to_bytes(&self) -> [u8; 13] {
+        let mut bytes = [0u8; 13];
+        bytes[0..8].copy_from_slice(&self.reply_port.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.sample_rate.to_le_bytes());
+        bytes[12] = self.channels;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 13 {
+            return None;
+        }
+        Some(Self {
+            reply_port: u64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]),
+            sample_rate: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            channels: bytes[12],
+        })
+    }
+}
+
+/// Reply describing an opened stream and its shared-memory ring
+///
+/// `stream_id` is 0 when the server could not open the stream.
+#[derive(Debug, Clone, Copy)]
+pub struct AudioStreamInfo {
+    pub stream_id: u32,
+    pub region_id: u64,
+    pub ring_size: u32,
+}
+
+impl AudioStreamInfo {
+    pub fn to_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[0..4].copy_from_slice(&self.stream_id.to_le_bytes());
+        bytes[4..12].copy_from_slice(&self.region_id.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.ring_size.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 16 {
+            return None;
+        }
+        Some(Self {
+            stream_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            region_id: u64::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7], bytes[8], bytes[9], bytes[10], bytes[11]]),
+            ring_size: u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+        })
+    }
+}
+
+/// Volume change for a stream (0 = master), 0-100
+#[derive(Debug, Clone, Copy)]
+pub struct AudioVolume {
+    pub stream_id: u32,
+    pub volume: u8,
+}
+
+impl AudioVolume {
+    pub fn to_bytes(&self) -> [u8; 5] {
+        let mut bytes = [0u8; 5];
+        bytes[0..4].copy_from_slice(&self.stream_id.to_le_bytes());
+        bytes[4] = self.volume;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 5 {
+            return None;
+        }
+        Some(Self {
+            stream_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            volume: bytes[4],
+        })
+    }
+}
+
+/// Request a generated tone, mixed by the server
+#[derive(Debug, Clone, Copy)]
+pub struct AudioBeep {
+    pub frequency_hz: u32,
+    pub duration_ms: u32,
+}
+
+impl AudioBeep {
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0u8; 8];
+        bytes[0..4].copy_from_slice(&self.frequency_hz.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.duration_ms.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 8 {
+            return None;
+        }
+        Some(Self {
+            frequency_hz: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            duration_ms: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        })
+    }
+}
+
+// ============================================================================
+// Clipboard Messages
+// ============================================================================
+
+/// Largest clipboard content sent inside a message; anything bigger goes
+/// through a shared region
+pub const CLIPBOARD_INLINE_MAX: usize = 1024;
+
+/// Largest clipboard content the compositor keeps
+pub const MAX_CLIPBOARD_BYTES: usize = 16 * 1024 * 1024;
+
+/// Clipboard content types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ClipboardMime {
+    /// UTF-8 text
+    TextPlain = 0,
+}
+
+impl ClipboardMime {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::TextPlain),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TextPlain => "text/plain;charset=utf-8",
+        }
+    }
+}
+
+/// Where clipboard bytes travel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardContent {
+    /// Up to `CLIPBOARD_INLINE_MAX` bytes in the message itself
+    Inline(Vec<u8>),
+    /// The first `len` bytes of a shared region created by the sender.
+    /// The receiver maps it read-only, copies the bytes and unmaps it right
+    /// away. The region stays the sender's: a client can destroy it once
+    /// `ClipboardChanged` confirms the copy, the compositor replaces its
+    /// own when the content changes.
+    Shared { region_id: u64, len: u32 },
+}
+
+/// Clipboard content with its type
+///
+/// Sent with `SetClipboard`, where `port` is the new owner's port (told
+/// with `ClipboardChanged` when it loses ownership), and as the
+/// `ClipboardData` reply to `GetClipboard`, where `port` is unused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardData {
+    pub port: u64,
+    pub mime: ClipboardMime,
+    pub content: ClipboardContent,
+}
+
+impl ClipboardData {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(14 + CLIPBOARD_INLINE_MAX);
+        bytes.extend_from_slice(&self.port.to_le_bytes());
+        bytes.push(self.mime as u8);
+        match &self.content {
+            ClipboardContent::Inline(data) => {
+                bytes.push(0);
+                bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(data);
+            }
+            ClipboardContent::Shared { region_id, len } => {
+                bytes.push(1);
+                bytes.extend_from_slice(&len.to_le_bytes());
+                bytes.extend_from_slice(&region_id.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 14 {
+            return None;
+        }
+        let port = u64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let mime = ClipboardMime::from_u8(bytes[8])?;
+        let len = u32::from_le_bytes([bytes[10], bytes[11], bytes[12], bytes[13]]);
+
+        let content = match bytes[9] {
+            0 => {
+                let data = bytes.get(14..14 + len as usize)?;
+                ClipboardContent::Inline(Vec::from(data))
+            }
+            1 => {
+                let id = bytes.get(14..22)?;
+                let region_id = u64::from_le_bytes([id[0], id[1], id[2], id[3], id[4], id[5], id[6], id[7]]);
+                ClipboardContent::Shared { region_id, len }
+            }
+            _ => return None,
+        };
+
+        Some(Self { port, mime, content })
+    }
+}
+
+/// Request for the clipboard content, answered with `ClipboardData` on
+/// `reply_port` (empty inline content if there is none of that type)
+#[derive(Debug, Clone, Copy)]
+pub struct ClipboardRequest {
+    pub reply_port: u64,
+    pub mime: ClipboardMime,
+}
+
+impl ClipboardRequest {
+    pub fn to_bytes(&self) -> [u8; 9] {
+        let mut bytes = [0u8; 9];
+        bytes[0..8].copy_from_slice(&self.reply_port.to_le_bytes());
+        bytes[8] = self.mime as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 9 {
+            return None;
+        }
+        Some(Self {
+            reply_port: u64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]),
+            mime: ClipboardMime::from_u8(bytes[8])?,
+        })
+    }
+}
+
+/// Sent to every window, the previous owner and the new owner (confirming
+/// its content was copied) when the clipboard changes; `owner` is the new
+/// owner's port
+#[derive(Debug, Clone, Copy)]
+pub struct ClipboardChanged {
+    pub owner: u64,
+    pub mime: ClipboardMime,
+}
+
+impl ClipboardChanged {
+    pub fn to_bytes(&self) -> [u8; 9] {
+        let mut bytes = [0u8; 9];
+        bytes[0..8].copy_from_slice(&self.owner.to_le_bytes());
+        bytes[8] = self.mime as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 9 {
+            return None;
+        }
+        Some(Self {
+            owner: u64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]),
+            mime: ClipboardMime::from_u8(bytes[8])?,
+        })
+    }
+}
+
+// ============================================================================
+// Drag and Drop Messages
+// ============================================================================
+//
+// A window starts a drag while the left button is held. The compositor
+// then sends DragEnter / DragMotion / DragLeave to the windows under the
+// cursor, Drop (with the data) to the one it is released over, and
+// DragEnd to the source. Data is described like clipboard content; a
+// shared region stays the source's until DragEnd.
+
+/// Start dragging `data` out of `window_id` (`data.port` is unused)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DragStart {
+    pub window_id: WindowId,
+    pub data: ClipboardData,
+}
+
+impl DragStart {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::from(self.window_id.to_le_bytes());
+        bytes.extend_from_slice(&self.data.to_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 4 {
+            return None;
+        }
+        Some(Self {
+            window_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            data: ClipboardData::from_bytes(&bytes[4..])?,
+        })
+    }
+}
+
+/// DragEnter / DragMotion / DragLeave for the window under the cursor,
+/// with the position in its client area
+#[derive(Debug, Clone, Copy)]
+pub struct DragEvent {
+    pub window_id: WindowId,
+    pub x: i32,
+    pub y: i32,
+    pub mime: ClipboardMime,
+}
+
+impl DragEvent {
+    pub fn to_bytes(&self) -> [u8; 13] {
+        let mut bytes = [0u8; 13];
+        bytes[0..4].copy_from_slice(&self.window_id.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.x.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.y.to_le_bytes());
+        bytes[12] = self.mime as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 13 {
+            return None;
+        }
+        Some(Self {
+            window_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            x: i32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            y: i32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            mime: ClipboardMime::from_u8(bytes[12])?,
+        })
+    }
+}
+
+/// The dragged data, released over `window_id` at (`x`, `y`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DropEvent {
+    pub window_id: WindowId,
+    pub x: i32,
+    pub y: i32,
+    pub data: ClipboardData,
+}
+
+impl DropEvent {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::from(self.window_id.to_le_bytes());
+        bytes.extend_from_slice(&self.x.to_le_bytes());
+        bytes.extend_from_slice(&self.y.to_le_bytes());
+        bytes.extend_from_slice(&self.data.to_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 12 {
+            return None;
+        }
+        Some(Self {
+            window_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            x: i32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            y: i32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            data: ClipboardData::from_bytes(&bytes[12..])?,
+        })
+    }
+}
+
+/// Sent to the source when its drag ends; `dropped` is false when the
+/// button was released outside any window
+#[derive(Debug, Clone, Copy)]
+pub struct DragEnd {
+    pub window_id: WindowId,
+    pub dropped: bool,
+}
+
+impl DragEnd {
+    pub fn to_bytes(&self) -> [u8; 5] {
+        let mut bytes = [0u8; 5];
+        bytes[0..4].copy_from_slice(&self.window_id.to_le_bytes());
+        bytes[4] = self.dropped as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 5 {
+            return None;
+        }
+        Some(Self {
+            window_id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            dropped: bytes[4] != 0,
+        })
+    }
+}
+
+// ============================================================================
+// Screen Capture Messages
+// ============================================================================
+//
+// `CaptureScreen` copies the composed screen (without the cursor) and
+// `CaptureWindow` one window's surface into a shared region the caller
+// created, answered with `CaptureResult`. Pixels are 32-bit in the
+// framebuffer's format, rows packed (`width` pixels each). Only programs
+// whose manifest entry grants `ScreenshotCap` may capture; the compositor
+// asks the kernel whether the request's sender holds it.
+
+/// Capture request, for `CaptureScreen` (`window_id` unused) or
+/// `CaptureWindow`
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureRequest {
+    pub reply_port: u64,
+    pub window_id: WindowId,
+    /// Region to copy the pixels into, and its size in bytes
+    pub region_id: u64,
+    pub region_len: u32,
+}
+
+impl CaptureRequest {
+    pub fn to_bytes(&self) -> [u8; 24] {
+        let mut bytes = [0u8; 24];
+        bytes[0..8].copy_from_slice(&self.reply_port.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.window_id.to_le_bytes());
+        bytes[12..20].copy_from_slice(&self.region_id.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.region_len.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 24 {
+            return None;
+        }
+        Some(Self {
+            reply_port: u64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]),
+            window_id: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            region_id: u64::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15], bytes[16], bytes[17], bytes[18], bytes[19]]),
+            region_len: u32::from_le_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]),
+        })
+    }
+}
+
+/// Outcome of a capture request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CaptureStatus {
+    Ok = 0,
+    /// The sender does not hold the Screenshot capability
+    Denied = 1,
+    /// No such window, or it has no surface
+    NoWindow = 2,
+    /// The region is smaller than `width * height * 4` bytes
+    RegionTooSmall = 3,
+    /// The region could not be mapped
+    Failed = 4,
+}
+
+impl CaptureStatus {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Ok),
+            1 => Some(Self::Denied),
+            2 => Some(Self::NoWindow),
+            3 => Some(Self::RegionTooSmall),
+            4 => Some(Self::Failed),
+            _ => None,
+        }
+    }
+}
+
+impl From<CaptureStatus> for StatusCode {
+    fn from(status: CaptureStatus) -> Self {
+        match status {
+            CaptureStatus::Ok => Self::Ok,
+            CaptureStatus::Denied => Self::Denied,
+            CaptureStatus::NoWindow => Self::NotFound,
+            CaptureStatus::RegionTooSmall => Self::TooLarge,
+            CaptureStatus::Failed => Self::Failed,
+        }
+    }
+}
+
+/// Reply to a capture request; `width` and `height` are the captured size,
+/// also filled in with `RegionTooSmall` so the caller can retry
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureResult {
+    pub status: CaptureStatus,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CaptureResult {
+    pub fn to_bytes(&self) -> [u8; 9] {
+        let mut bytes = [0u8; 9];
+        bytes[0] = self.status as u8;
+        bytes[1..5].copy_from_slice(&self.width.to_le_bytes());
+        bytes[5..9].copy_from_slice(&self.height.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 9 {
+            return None;
+        }
+        Some(Self {
+            status: CaptureStatus::from_u8(bytes[0])?,
+            width: u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]),
+            height: u32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]),
+        })
+    }
+}
+
+// ============================================================================
+// Notification Messages
+// ============================================================================
+
+/// How insistent a notification is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Urgency {
+    Low = 0,
+    Normal = 1,
+    /// Shown even in do-not-disturb mode, and kept until dismissed
+    Critical = 2,
+}
+
+impl Urgency {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Low),
+            1 => Some(Self::Normal),
+            2 => Some(Self::Critical),
+            _ => None,
+        }
+    }
+}
+
+/// A notification to show as a toast, sent with `Notify`
+///
+/// `timeout_ms` of 0 uses the compositor's default for the urgency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub app_name: String,
+    pub title: String,
+    pub body: String,
+    pub urgency: Urgency,
+    pub timeout_ms: u32,
+}
+
+impl Notification {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(11 + self.app_name.len() + self.title.len() + self.body.len());
+        bytes.push(self.urgency as u8);
+        bytes.extend_from_slice(&self.timeout_ms.to_le_bytes());
+        for text in [&self.app_name, &self.title, &self.body] {
+            bytes.extend_from_slice(&(text.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(text.as_bytes());
+        }
+        bytes
+    }
+
+    /// Parse a notification, returning it and the number of bytes it used
+    pub fn from_bytes(bytes: &[u8]) -> Option<(Self, usize)> {
+        if bytes.len() < 5 {
+            return None;
+        }
+        let urgency = Urgency::from_u8(bytes[0])?;
+        let timeout_ms = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+
+        let mut offset = 5;
+        let mut texts = [String::new(), String::new(), String::new()];
+        for text in texts.iter_mut() {
+            let len = bytes.get(offset..offset + 2)?;
+            let len = u16::from_le_bytes([len[0], len[1]]) as usize;
+            let data = bytes.get(offset + 2..offset + 2 + len)?;
+            *text = String::from(core::str::from_utf8(data).ok()?);
+            offset += 2 + len;
+        }
+        let [app_name, title, body] = texts;
+
+        Some((Self { app_name, title, body, urgency, timeout_ms }, offset))
+    }
+}
+
+/// A past notification, as kept in the history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationRecord {
+    pub id: u32,
+    /// Timer ticks when it arrived
+    pub timestamp: u64,
+    pub notification: Notification,
+}
+
+/// Reply to `GetNotificationHistory` (whose payload is the reply port):
+/// the most recent notifications, newest first, as many as fit in one
+/// message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationHistory {
+    pub records: Vec<NotificationRecord>,
+}
+
+impl NotificationHistory {
+    /// Bytes one record takes after its notification
+    const RECORD_HEADER: usize = 12;
+
+    /// Pack `records` (newest first), dropping the oldest ones that do not
+    /// fit in `max_len` bytes
+    pub fn pack(records: &[NotificationRecord], max_len: usize) -> Vec<u8> {
+        let mut bytes = Vec::from([0u8, 0u8]);
+        let mut count: u16 = 0;
+        for record in records {
+            let notification = record.notification.to_bytes();
+            if bytes.len() + Self::RECORD_HEADER + notification.len() > max_len {
+                break;
+            }
+            bytes.extend_from_slice(&record.id.to_le_bytes());
+            bytes.extend_from_slice(&record.timestamp.to_le_bytes());
+            bytes.extend_from_slice(&notification);
+            count += 1;
+        }
+        bytes[0..2].copy_from_slice(&count.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 2 {
+            return None;
+        }
+        let count = u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+
+        let mut records = Vec::with_capacity(count);
+        let mut offset = 2;
+        for _ in 0..count {
+            let header = bytes.get(offset..offset + Self::RECORD_HEADER)?;
+            let id = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            let timestamp = u64::from_le_bytes([header[4], header[5], header[6], header[7], header[8], header[9], header[10], header[11]]);
+            let (notification, len) = Notification::from_bytes(&bytes[offset + Self::RECORD_HEADER..])?;
+            records.push(NotificationRecord { id, timestamp, notification });
+            offset += Self::RECORD_HEADER + len;
+        }
+
+        Some(Self { records })
+    }
+}
+
+// ============================================================================
+// Panel Widgets
+// ============================================================================
+
+/// Short status text a service shows in the panel (e.g. memory usage),
+/// replaced whenever it publishes again with the same id
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanelWidget {
+    /// Chosen by the publisher
+    pub id: u32,
+    pub text: String,
+}
+
+impl PanelWidget {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(6 + self.text.len());
+        bytes.extend_from_slice(&self.id.to_le_bytes());
+        bytes.extend_from_slice(&(self.text.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(self.text.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 6 {
+            return None;
+        }
+        let id = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let len = u16::from_le_bytes([bytes[4], bytes[5]]) as usize;
+        let text = core::str::from_utf8(bytes.get(6..6 + len)?).ok()?;
+        Some(Self { id, text: String::from(text) })
+    }
+}
+
+// ============================================================================
+// Desktop Background
+// ============================================================================
+
+/// Largest image file accepted as a wallpaper
+pub const MAX_WALLPAPER_BYTES: usize = 16 * 1024 * 1024;
+
+/// How a wallpaper image is fitted to the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum WallpaperMode {
+    /// Scaled to cover the screen, cropping the overflow
+    Fill = 0,
+    /// Scaled to fit inside the screen, bars in the background colour
+    Fit = 1,
+    /// Original size in the middle of the screen
+    Center = 2,
+    /// Original size, repeated from the top-left corner
+    Tile = 3,
+}
+
+impl WallpaperMode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Fill),
+            1 => Some(Self::Fit),
+            2 => Some(Self::Center),
+            3 => Some(Self::Tile),
+            _ => None,
+        }
+    }
+}
+
+/// Set the desktop wallpaper from an image file (BMP or PNG) the sender put in a
+/// shared region; the compositor decodes it on arrival, after which the
+/// sender may destroy the region. With `region_id` 0 only the mode changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SetWallpaper {
+    /// Port for the `WallpaperResult` reply, 0 for none
+    pub reply_port: u64,
+    pub region_id: u64,
+    /// Size of the image file in bytes
+    pub len: u32,
+    pub mode: WallpaperMode,
+}
+
+impl SetWallpaper {
+    pub fn to_bytes(&self) -> [u8; 21] {
+        let mut bytes = [0u8; 21];
+        bytes[0..8].copy_from_slice(&self.reply_port.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.region_id.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.len.to_le_bytes());
+        bytes[20] = self.mode as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 21 {
+            return None;
+        }
+        Some(Self {
+            reply_port: u64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]),
+            region_id: u64::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11], bytes[12], bytes[13], bytes[14], bytes[15]]),
+            len: u32::from_le_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]),
+            mode: WallpaperMode::from_u8(bytes[20])?,
+        })
+    }
+}
+
+// ============================================================================
+// Theming
+// ============================================================================
+
+/// Colours and metrics of the desktop theme, as the compositor broadcasts
+/// them; colours are 0xRRGGBB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThemeSpec {
+    pub desktop_bg: u32,
+    pub panel_bg: u32,
+    pub panel_text: u32,
+    pub accent: u32,
+    pub window_bg: u32,
+    pub window_header: u32,
+    pub window_header_focused: u32,
+    pub window_border: u32,
+    pub dock_bg: u32,
+    pub dock_separator: u32,
+    pub dock_item_minimized: u32,
+    /// Secondary text (hints, inactive labels)
+    pub text_dim: u32,
+    pub cursor_fill: u32,
+    pub cursor_outline: u32,
+    pub shadow: u32,
+    pub urgent: u32,
+    /// Shadow alpha right next to a window, fading to zero outwards
+    pub shadow_alpha: u8,
+    /// Darkening of unfocused windows' content
+    pub inactive_dim: u8,
+    /// Corner radius of buttons and popups, in pixels
+    pub corner_radius: u8,
+    /// Width of frames around windows and controls, in pixels
+    pub border_width: u8,
+    /// Font size in pixels
+    pub font_size: u8,
+    /// Applications draw with the strongest contrast they can: solid
+    /// colours, no dimmed text and wide focus rings
+    pub high_contrast: bool,
+}
+
+impl ThemeSpec {
+    const COLORS: usize = 16;
+    const SIZE: usize = Self::COLORS * 4 + 6;
+
+    /// Nord-inspired dark theme, the default
+    pub const NORD: Self = Self {
+        desktop_bg: 0x2E3440,
+        panel_bg: 0x242933,
+        panel_text: 0xECEFF4,
+        accent: 0x88C0D0,
+        window_bg: 0x2E3440,
+        window_header: 0x3B4252,
+        window_header_focused: 0x4C566A,
+        window_border: 0x434C5E,
+        dock_bg: 0x242933,
+        dock_separator: 0x4C566A,
+        dock_item_minimized: 0x2E3440,
+        text_dim: 0x818A99,
+        cursor_fill: 0xFFFFFF,
+        cursor_outline: 0x000000,
+        shadow: 0x080A0E,
+        urgent: 0xBF616A,
+        shadow_alpha: 96,
+        inactive_dim: 24,
+        corner_radius: 0,
+        border_width: 1,
+        font_size: 8,
+        high_contrast: false,
+    };
+
+    /// Light theme on Nord's snow storm colours
+    pub const LIGHT: Self = Self {
+        desktop_bg: 0xD8DEE9,
+        panel_bg: 0xECEFF4,
+        panel_text: 0x2E3440,
+        accent: 0x5E81AC,
+        window_bg: 0xFFFFFF,
+        window_header: 0xE5E9F0,
+        window_header_focused: 0xC8D0E0,
+        window_border: 0xAEB7C6,
+        dock_bg: 0xECEFF4,
+        dock_separator: 0xAEB7C6,
+        dock_item_minimized: 0xD8DEE9,
+        text_dim: 0x6B7385,
+        cursor_fill: 0xFFFFFF,
+        cursor_outline: 0x000000,
+        shadow: 0x4C566A,
+        urgent: 0xBF616A,
+        shadow_alpha: 64,
+        inactive_dim: 16,
+        corner_radius: 0,
+        border_width: 1,
+        font_size: 8,
+        high_contrast: false,
+    };
+
+    /// White and yellow on black, for low vision
+    pub const HIGH_CONTRAST: Self = Self {
+        desktop_bg: 0x000000,
+        panel_bg: 0x000000,
+        panel_text: 0xFFFFFF,
+        accent: 0xFFFF00,
+        window_bg: 0x000000,
+        window_header: 0x000000,
+        window_header_focused: 0x0000C0,
+        window_border: 0xFFFFFF,
+        dock_bg: 0x000000,
+        dock_separator: 0xFFFFFF,
+        dock_item_minimized: 0x404040,
+        text_dim: 0xFFFFFF,
+        cursor_fill: 0xFFFFFF,
+        cursor_outline: 0x000000,
+        shadow: 0x000000,
+        urgent: 0xFF4040,
+        shadow_alpha: 0,
+        inactive_dim: 0,
+        corner_radius: 0,
+        border_width: 2,
+        font_size: 8,
+        high_contrast: true,
+    };
+
+    fn colors(&self) -> [u32; Self::COLORS] {
+        [
+            self.desktop_bg,
+            self.panel_bg,
+            self.panel_text,
+            self.accent,
+            self.window_bg,
+            self.window_header,
+            self.window_header_focused,
+            self.window_border,
+            self.dock_bg,
+            self.dock_separator,
+            self.dock_item_minimized,
+            self.text_dim,
+            self.cursor_fill,
+            self.cursor_outline,
+            self.shadow,
+            self.urgent,
+        ]
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        for (i, color) in self.colors().iter().enumerate() {
+            bytes[i * 4..i * 4 + 4].copy_from_slice(&color.to_le_bytes());
+        }
+        let metrics = Self::COLORS * 4;
+        bytes[metrics] = self.shadow_alpha;
+        bytes[metrics + 1] = self.inactive_dim;
+        bytes[metrics + 2] = self.corner_radius;
+        bytes[metrics + 3] = self.border_width;
+        bytes[metrics + 4] = self.font_size;
+        bytes[metrics + 5] = self.high_contrast as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE {
+            return None;
+        }
+        let color = |i: usize| {
+            u32::from_le_bytes([bytes[i * 4], bytes[i * 4 + 1], bytes[i * 4 + 2], bytes[i * 4 + 3]])
+        };
+        let metrics = Self::COLORS * 4;
+        Some(Self {
+            desktop_bg: color(0),
+            panel_bg: color(1),
+            panel_text: color(2),
+            accent: color(3),
+            window_bg: color(4),
+            window_header: color(5),
+            window_header_focused: color(6),
+            window_border: color(7),
+            dock_bg: color(8),
+            dock_separator: color(9),
+            dock_item_minimized: color(10),
+            text_dim: color(11),
+            cursor_fill: color(12),
+            cursor_outline: color(13),
+            shadow: color(14),
+            urgent: color(15),
+            shadow_alpha: bytes[metrics],
+            inactive_dim: bytes[metrics + 1],
+            corner_radius: bytes[metrics + 2],
+            border_width: bytes[metrics + 3],
+            font_size: bytes[metrics + 4],
+            high_contrast: bytes[metrics + 5] != 0,
+        })
+    }
+}
+
+// ============================================================================
+// Displays
+// ============================================================================
+
+/// One output (monitor) in the desktop's coordinate space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayInfo {
+    pub id: u32,
+    /// Position of the top-left corner on the desktop
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale: ScaleFactor,
+    /// Holds the panel clock, the dock and notifications
+    pub primary: bool,
+}
+
+impl DisplayInfo {
+    const SIZE: usize = 22;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.id.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.x.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.y.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.width.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.height.to_le_bytes());
+        bytes[20] = self.scale as u8;
+        bytes[21] = self.primary as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE {
+            return None;
+        }
+        Some(Self {
+            id: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            x: i32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            y: i32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            width: u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+            height: u32::from_le_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]),
+            scale: ScaleFactor::from_u8(bytes[20])?,
+            primary: bytes[21] != 0,
+        })
+    }
+}
+
+/// Reply to `GetDisplays`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisplayList {
+    pub displays: Vec<DisplayInfo>,
+}
+
+impl DisplayList {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + self.displays.len() * DisplayInfo::SIZE);
+        bytes.push(self.displays.len() as u8);
+        for display in &self.displays {
+            bytes.extend_from_slice(&display.to_bytes());
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let count = *bytes.first()? as usize;
+        let displays = (0..count)
+            .map(|i| DisplayInfo::from_bytes(bytes.get(1 + i * DisplayInfo::SIZE..)?))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self { displays })
+    }
+}
+
+// ============================================================================
+// Terminal Modes
+// ============================================================================
+//
+// A program started from the terminal reads nothing typed until it creates
+// a port and names it in a `TerminalMode`. In cooked mode the terminal
+// edits a line and sends it when Enter is pressed; in raw mode every key
+// is sent as it is pressed, so a full-screen program can do its own
+// editing. Ctrl+C stops the program in either mode.
+
+/// How the terminal handles what is typed while a program runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalMode {
+    /// Send each key as it is pressed instead of whole lines
+    pub raw: bool,
+    /// Show what is typed
+    pub echo: bool,
+    /// In raw mode, wrap pasted text in ESC[200~ and ESC[201~ so it is
+    /// not taken for typed keys
+    pub bracketed_paste: bool,
+    /// Port `ProgramInput` messages go to; 0 while the program reads
+    /// nothing
+    pub input_port: u64,
+}
+
+impl TerminalMode {
+    pub const SIZE: usize = 9;
+
+    /// Line editing with echo, which programs start in
+    pub const COOKED: Self = Self {
+        raw: false,
+        echo: true,
+        bracketed_paste: false,
+        input_port: 0,
+    };
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0] = self.raw as u8 | (self.echo as u8) << 1 | (self.bracketed_paste as u8) << 2;
+        bytes[1..9].copy_from_slice(&self.input_port.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < Self::SIZE {
+            return None;
+        }
+        Some(Self {
+            raw: bytes[0] & 1 != 0,
+            echo: bytes[0] & 2 != 0,
+            bracketed_paste: bytes[0] & 4 != 0,
+            input_port: u64::from_le_bytes(bytes[1..9].try_into().ok()?),
+        })
+    }
+}
+
+// ============================================================================
+// File Messages
+// ============================================================================
+//
+// Files are reached through the filesystem service with `rpc` calls. Opening
+// a path gives a handle that reads, writes and the close refer to; a handle
+// reads and writes from where the last call left off.
+
+/// Longest path in a `FileOpen` or `FileStat`
+pub const MAX_PATH_BYTES: usize = 1024;
+
+/// Open for reading
+pub const OPEN_READ: u32 = 1 << 0;
+/// Open for writing
+pub const OPEN_WRITE: u32 = 1 << 1;
+/// Create the file if it does not exist
+pub const OPEN_CREATE: u32 = 1 << 2;
+/// Empty the file when opening it
+pub const OPEN_TRUNCATE: u32 = 1 << 3;
+/// Write at the end of the file
+pub const OPEN_APPEND: u32 = 1 << 4;
+
+/// Arguments of `FileOpen`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileOpen {
+    /// `OPEN_*` flags
+    pub flags: u32,
+    pub path: String,
+}
+
+impl FileOpen {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let path = &self.path.as_bytes()[..self.path.len().min(MAX_PATH_BYTES)];
+        let mut bytes = Vec::with_capacity(4 + path.len());
+        bytes.extend_from_slice(&self.flags.to_le_bytes());
+        bytes.extend_from_slice(path);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let flags = u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?);
+        let path = core::str::from_utf8(bytes.get(4..)?).ok()?;
+        if path.len() > MAX_PATH_BYTES {
+            return None;
+        }
+        Some(Self { flags, path: String::from(path) })
+    }
+}
+
+/// Arguments of `FileRead`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileRead {
+    pub handle: u64,
+    /// Most bytes to read
+    pub len: u32,
+}
+
+impl FileRead {
+    pub const SIZE: usize = 12;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.handle.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.len.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            handle: u64::from_le_bytes(bytes.get(0..8)?.try_into().ok()?),
+            len: u32::from_le_bytes(bytes.get(8..12)?.try_into().ok()?),
+        })
+    }
+}
+
+/// Result of `FileStat`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStat {
+    /// Bytes in the file; 0 for a directory
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+impl FileStat {
+    pub const SIZE: usize = 9;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.size.to_le_bytes());
+        bytes[8] = self.is_dir as u8;
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            size: u64::from_le_bytes(bytes.get(0..8)?.try_into().ok()?),
+            is_dir: *bytes.get(8)? != 0,
+        })
+    }
+}
@@ -1,7 +1,8 @@
-//! Well-Known Port Definitions
+//! Service Ports
 //!
-//! This module defines well-known port names and provides utilities
-//! for service discovery.
+//! Ports services listen on and sets of ports to wait on together. Ports
+//! are numbered by the kernel; services publish theirs through
+//! `discovery` for clients to find.
 
 extern crate alloc;
 
@@ -9,23 +10,6 @@ use alloc::string::String;
 use atom_syscall::ipc::{PortId, create_port};
 use atom_syscall::SyscallResult;
 
-/// Well-known port identifiers
-///
-/// These are reserved port IDs that services register with.
-/// Port 0 is invalid, ports 1-255 are reserved for system services.
-pub mod well_known {
-    /// Desktop environment service port
-    pub const DESKTOP_SERVICE: u64 = 1;
-    /// Keyboard driver input port
-    pub const KEYBOARD_INPUT: u64 = 2;
-    /// Mouse driver input port
-    pub const MOUSE_INPUT: u64 = 3;
-    /// Graphics service port
-    pub const GRAPHICS_SERVICE: u64 = 4;
-    /// Terminal service port
-    pub const TERMINAL_SERVICE: u64 = 5;
-}
-
 /// Port configuration for a service
 #[derive(Debug, Clone)]
 pub struct ServicePort {
@@ -52,6 +36,11 @@ impl ServicePort {
     pub fn id(&self) -> PortId {
         self.port_id
     }
+
+    /// Publish the port under the service's name for clients to find
+    pub fn register(&self) -> SyscallResult<()> {
+        crate::discovery::register_service(self.service_id, self.port_id)
+    }
 }
 
 /// Port set for waiting on multiple ports
@@ -8,7 +8,9 @@ extern crate alloc;
 use alloc::vec::Vec;
 use atom_syscall::ipc::{PortId, send, recv, send_async, try_recv};
 use atom_syscall::SyscallResult;
-use crate::messages::{MessageHeader, MessageType};
+use crate::messages::{Hello, HelloAck, MessageHeader, MessageType};
+use crate::status::{StatusCode, StatusResult};
+use crate::MAX_MESSAGE_SIZE;
 
 /// Send a typed message with header
 pub fn send_message(port: PortId, msg_type: MessageType, payload: &[u8]) -> SyscallResult<()> {
@@ -37,30 +39,44 @@ pub fn send_message_async(port: PortId, msg_type: MessageType, payload: &[u8]) -
 /// Receive a message and parse its header
 pub fn recv_message(port: PortId, buffer: &mut [u8]) -> SyscallResult<(MessageHeader, usize)> {
     let len = recv(port, buffer)?;
-
-    if len < MessageHeader::SIZE {
-        return Err(atom_syscall::SyscallError::InvalidArgument);
-    }
-
-    let header = MessageHeader::from_bytes(&buffer[..MessageHeader::SIZE])
-        .ok_or(atom_syscall::SyscallError::InvalidArgument)?;
-
+    let header = parse_header(&buffer[..len])?;
     Ok((header, len))
 }
 
 /// Try to receive a message without blocking
 pub fn try_recv_message(port: PortId, buffer: &mut [u8]) -> SyscallResult<Option<(MessageHeader, usize)>> {
     match try_recv(port, buffer)? {
-        Some(len) if len >= MessageHeader::SIZE => {
-            let header = MessageHeader::from_bytes(&buffer[..MessageHeader::SIZE])
-                .ok_or(atom_syscall::SyscallError::InvalidArgument)?;
-            Ok(Some((header, len)))
-        }
-        Some(_) => Err(atom_syscall::SyscallError::InvalidArgument),
+        Some(len) => Ok(Some((parse_header(&buffer[..len])?, len))),
         None => Ok(None),
     }
 }
 
+/// Header of a received message, which must hold the payload it claims
+fn parse_header(message: &[u8]) -> SyscallResult<MessageHeader> {
+    MessageHeader::from_bytes(message)
+        .filter(|header| header.payload_size as usize <= message.len() - MessageHeader::SIZE)
+        .ok_or(atom_syscall::SyscallError::InvalidArgument)
+}
+
+/// Agree on a protocol version with the service on `port`, before
+/// anything else is sent to it; the answer comes to `reply_port`, which
+/// must have nothing else waiting. Fails with `VersionMismatch` if the two
+/// have no version in common.
+pub fn negotiate(port: PortId, reply_port: PortId) -> StatusResult<u16> {
+    send_message(port, MessageType::Hello, &Hello::new(reply_port).to_bytes())?;
+
+    let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+    let (header, len) = recv_message(reply_port, &mut buffer)?;
+    if header.msg_type != MessageType::HelloAck {
+        return Err(StatusCode::InvalidArgument);
+    }
+    match HelloAck::from_bytes(get_payload(&buffer, len)) {
+        Some(HelloAck { version: 0 }) => Err(StatusCode::VersionMismatch),
+        Some(ack) => Ok(ack.version),
+        None => Err(StatusCode::InvalidArgument),
+    }
+}
+
 /// Get the payload portion of a received message
 pub fn get_payload(buffer: &[u8], total_len: usize) -> &[u8] {
     if total_len > MessageHeader::SIZE {
@@ -0,0 +1,346 @@
+//! Shared-Memory Ring Channels
+//!
+//! A single-producer/single-consumer ring of fixed-size records in a shared
+//! region, for producers that would otherwise send a message per event,
+//! like the mouse driver or an audio client. Records are written and read
+//! straight from the region; the head and tail are free-running counters
+//! in cache lines of their own, so neither side takes a lock and "full" and
+//! "empty" never alias.
+//!
+//! A consumer with nothing left to read arms the ring before it blocks on
+//! its port. The next write finds the ring armed and sends one
+//! `RingDoorbell` to the doorbell port, so a busy stream costs no messages
+//! and an idle consumer still wakes up.
+//!
+//! ```ignore
+//! // Consumer: create the channel and hand `info` to the producer
+//! let (info, consumer) = RingConsumer::<MouseMoveEvent>::create(256, port)?;
+//!
+//! // Producer
+//! let producer = RingProducer::<MouseMoveEvent>::open(&info)?;
+//! producer.push(&[event]);
+//!
+//! // Consumer, on `RingDoorbell` or whenever it polls
+//! let count = consumer.pop(&mut events);
+//! if count == 0 && consumer.arm() { /* block on the port */ }
+//! ```
+//!
+//! # Layout
+//!
+//! ```text
+//! offset 0:   write position (u32, producer-owned)
+//! offset 64:  read position  (u32, consumer-owned)
+//! offset 128: capacity in records (u32, power of two)
+//! offset 132: record size in bytes (u32)
+//! offset 136: flags (u32, see FLAG_*)
+//! offset 192: records
+//! ```
+
+use core::marker::PhantomData;
+use core::mem::size_of;
+use core::sync::atomic::{fence, AtomicU32, Ordering};
+
+use atom_syscall::ipc::PortId;
+use atom_syscall::shm::{self, RegionFlags, RegionId};
+use atom_syscall::{SyscallError, SyscallResult};
+
+use crate::messages::MessageType;
+use crate::protocol::send_message_async;
+
+/// Bytes reserved for the ring header
+pub const HEADER_SIZE: usize = 192;
+
+const WRITE_POS: usize = 0;
+const READ_POS: usize = 64;
+const CAPACITY: usize = 128;
+const RECORD_SIZE: usize = 132;
+const FLAGS: usize = 136;
+
+/// Producer will not write any more records
+pub const FLAG_CLOSED: u32 = 1 << 0;
+/// Consumer found the ring empty and waits for a doorbell
+pub const FLAG_ARMED: u32 = 1 << 1;
+
+/// What the producer needs to open a channel; sent to it by the consumer
+/// in whatever message sets the stream up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RingInfo {
+    pub region_id: RegionId,
+    /// Bytes to map
+    pub size: u64,
+    /// Port `RingDoorbell` goes to
+    pub doorbell: u64,
+}
+
+impl RingInfo {
+    pub const SIZE: usize = 24;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.region_id.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.size.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.doorbell.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            region_id: u64::from_le_bytes(bytes.get(0..8)?.try_into().ok()?),
+            size: u64::from_le_bytes(bytes.get(8..16)?.try_into().ok()?),
+            doorbell: u64::from_le_bytes(bytes.get(16..24)?.try_into().ok()?),
+        })
+    }
+}
+
+/// View over a ring of `T` in shared memory
+pub struct Ring<T: Copy> {
+    base: *mut u8,
+    capacity: u32,
+    _records: PhantomData<T>,
+}
+
+impl<T: Copy> Ring<T> {
+    /// Bytes a region needs for `capacity` records
+    pub const fn bytes_for(capacity: usize) -> usize {
+        HEADER_SIZE + capacity * size_of::<T>()
+    }
+
+    /// Initialize a fresh ring in `size` bytes at `base`
+    ///
+    /// # Safety
+    /// `base` must be page-aligned and point to at least `size` writable
+    /// bytes that stay mapped for the lifetime of the returned ring.
+    pub unsafe fn init(base: *mut u8, size: usize) -> Option<Self> {
+        if size_of::<T>() == 0 {
+            return None;
+        }
+        let records = size.checked_sub(HEADER_SIZE)? / size_of::<T>();
+        if records == 0 {
+            return None;
+        }
+        // Round down to a power of two so wrapping is a mask
+        let capacity = 1u32 << (31 - (records.min(u32::MAX as usize) as u32).leading_zeros());
+
+        let ring = Self { base, capacity, _records: PhantomData };
+        ring.field(WRITE_POS).store(0, Ordering::Relaxed);
+        ring.field(READ_POS).store(0, Ordering::Relaxed);
+        ring.field(CAPACITY).store(capacity, Ordering::Relaxed);
+        ring.field(RECORD_SIZE).store(size_of::<T>() as u32, Ordering::Relaxed);
+        ring.field(FLAGS).store(0, Ordering::Release);
+        Some(ring)
+    }
+
+    /// Attach to a ring initialized by the other side; `None` if it does
+    /// not hold records of `T` or does not fit in `size` bytes
+    ///
+    /// # Safety
+    /// `base` must point to `size` mapped bytes previously set up with
+    /// `init`, which stay mapped for the lifetime of the returned ring.
+    pub unsafe fn attach(base: *mut u8, size: usize) -> Option<Self> {
+        let ring = Self { base, capacity: 0, _records: PhantomData };
+        let capacity = ring.field(CAPACITY).load(Ordering::Acquire);
+        let record_size = ring.field(RECORD_SIZE).load(Ordering::Relaxed);
+        if capacity == 0
+            || !capacity.is_power_of_two()
+            || record_size as usize != size_of::<T>()
+            || Self::bytes_for(capacity as usize) > size
+        {
+            return None;
+        }
+        Some(Self { capacity, ..ring })
+    }
+
+    fn field(&self, offset: usize) -> &AtomicU32 {
+        unsafe { &*(self.base.add(offset) as *const AtomicU32) }
+    }
+
+    fn records(&self) -> *mut T {
+        unsafe { self.base.add(HEADER_SIZE) as *mut T }
+    }
+
+    /// Capacity in records
+    pub fn capacity(&self) -> usize {
+        self.capacity as usize
+    }
+
+    /// Records ready to be read
+    pub fn available(&self) -> usize {
+        let write = self.field(WRITE_POS).load(Ordering::Acquire);
+        let read = self.field(READ_POS).load(Ordering::Relaxed);
+        write.wrapping_sub(read) as usize
+    }
+
+    /// Records that can be written without overwriting unread ones
+    pub fn free_space(&self) -> usize {
+        let write = self.field(WRITE_POS).load(Ordering::Relaxed);
+        let read = self.field(READ_POS).load(Ordering::Acquire);
+        self.capacity as usize - write.wrapping_sub(read) as usize
+    }
+
+    /// Write as many records as fit; returns the number written
+    pub fn write(&self, records: &[T]) -> usize {
+        let count = records.len().min(self.free_space());
+        let write = self.field(WRITE_POS).load(Ordering::Relaxed);
+        let mask = self.capacity - 1;
+
+        for (i, record) in records[..count].iter().enumerate() {
+            let index = (write.wrapping_add(i as u32) & mask) as usize;
+            unsafe { self.records().add(index).write_volatile(*record) };
+        }
+
+        self.field(WRITE_POS).store(write.wrapping_add(count as u32), Ordering::Release);
+        count
+    }
+
+    /// Read up to `out.len()` records; returns the number read
+    pub fn read(&self, out: &mut [T]) -> usize {
+        let count = out.len().min(self.available());
+        let read = self.field(READ_POS).load(Ordering::Relaxed);
+        let mask = self.capacity - 1;
+
+        for (i, slot) in out[..count].iter_mut().enumerate() {
+            let index = (read.wrapping_add(i as u32) & mask) as usize;
+            *slot = unsafe { self.records().add(index).read_volatile() };
+        }
+
+        self.field(READ_POS).store(read.wrapping_add(count as u32), Ordering::Release);
+        count
+    }
+
+    /// Mark the stream as finished (producer side)
+    pub fn close(&self) {
+        self.field(FLAGS).fetch_or(FLAG_CLOSED, Ordering::Release);
+    }
+
+    /// Whether the producer has closed the stream
+    pub fn is_closed(&self) -> bool {
+        self.field(FLAGS).load(Ordering::Acquire) & FLAG_CLOSED != 0
+    }
+
+    /// Ask for a doorbell on the next write (consumer side); false if
+    /// records arrived meanwhile, so the consumer reads instead of blocking
+    pub fn arm(&self) -> bool {
+        self.field(FLAGS).fetch_or(FLAG_ARMED, Ordering::SeqCst);
+        // Pairs with the fence in `take_armed`: either the producer sees
+        // the flag or this sees its records
+        fence(Ordering::SeqCst);
+        self.available() == 0
+    }
+
+    /// Check and clear the doorbell request (producer side)
+    pub fn take_armed(&self) -> bool {
+        fence(Ordering::SeqCst);
+        self.field(FLAGS).fetch_and(!FLAG_ARMED, Ordering::SeqCst) & FLAG_ARMED != 0
+    }
+}
+
+/// The reading end of a channel, which creates and owns the region
+pub struct RingConsumer<T: Copy> {
+    ring: Ring<T>,
+    region: RegionId,
+}
+
+impl<T: Copy> RingConsumer<T> {
+    /// Create a channel of at least `capacity` records, ringing `doorbell`
+    /// when records arrive at an empty ring; returns what the producer
+    /// needs to open it
+    pub fn create(capacity: usize, doorbell: PortId) -> SyscallResult<(RingInfo, Self)> {
+        let size = Ring::<T>::bytes_for(capacity.next_power_of_two());
+        let region = shm::create_region(size)?;
+        let ring = shm::map_anywhere(region, RegionFlags::read_write()).and_then(|base| {
+            unsafe { Ring::init(base, size) }.ok_or(SyscallError::InvalidArgument)
+        });
+        match ring {
+            Ok(ring) => {
+                let info = RingInfo { region_id: region, size: size as u64, doorbell };
+                Ok((info, Self { ring, region }))
+            }
+            Err(error) => {
+                let _ = shm::unmap_region(region);
+                let _ = shm::destroy_region(region);
+                Err(error)
+            }
+        }
+    }
+
+    pub fn region(&self) -> RegionId {
+        self.region
+    }
+
+    /// Read up to `out.len()` records in one go; returns the number read
+    pub fn pop(&self, out: &mut [T]) -> usize {
+        self.ring.read(out)
+    }
+
+    /// Ask for a doorbell before blocking; false if records arrived
+    /// meanwhile and should be read first
+    pub fn arm(&self) -> bool {
+        self.ring.arm()
+    }
+
+    /// Whether the producer has closed the stream
+    pub fn is_closed(&self) -> bool {
+        self.ring.is_closed()
+    }
+}
+
+impl<T: Copy> Drop for RingConsumer<T> {
+    fn drop(&mut self) {
+        let _ = shm::unmap_region(self.region);
+        // Fails while the producer still has it mapped, so producers are
+        // dropped first where the consumer can arrange it
+        let _ = shm::destroy_region(self.region);
+    }
+}
+
+/// The writing end of a channel
+pub struct RingProducer<T: Copy> {
+    ring: Ring<T>,
+    region: RegionId,
+    doorbell: PortId,
+}
+
+impl<T: Copy> RingProducer<T> {
+    /// Map the channel described by `info`
+    pub fn open(info: &RingInfo) -> SyscallResult<Self> {
+        let base = shm::map_anywhere(info.region_id, RegionFlags::read_write())?;
+        match unsafe { Ring::attach(base, info.size as usize) } {
+            Some(ring) => Ok(Self { ring, region: info.region_id, doorbell: info.doorbell }),
+            None => {
+                let _ = shm::unmap_region(info.region_id);
+                Err(SyscallError::InvalidArgument)
+            }
+        }
+    }
+
+    /// Write as many records as fit, ringing the doorbell if the consumer
+    /// is waiting; returns the number written
+    pub fn push(&self, records: &[T]) -> usize {
+        let count = self.ring.write(records);
+        if count > 0 {
+            self.ring_doorbell();
+        }
+        count
+    }
+
+    /// Records that can be written now
+    pub fn free_space(&self) -> usize {
+        self.ring.free_space()
+    }
+
+    fn ring_doorbell(&self) {
+        if self.ring.take_armed() {
+            let region = self.region.to_le_bytes();
+            let _ = send_message_async(self.doorbell, MessageType::RingDoorbell, &region);
+        }
+    }
+}
+
+impl<T: Copy> Drop for RingProducer<T> {
+    fn drop(&mut self) {
+        self.ring.close();
+        // Wake the consumer so it sees the stream closed
+        self.ring_doorbell();
+        let _ = shm::unmap_region(self.region);
+    }
+}
@@ -0,0 +1,302 @@
+//! Request/Reply Calls
+//!
+//! A thin RPC layer over ports for services that answer requests, such as
+//! the display service, the VFS or the network stack. A request is an
+//! ordinary typed message whose payload starts with a `RequestHeader`: an
+//! id chosen by the client and the port to answer on. The service answers
+//! with an `RpcReply` message carrying the same id, so a client can have
+//! several calls in flight on one reply port.
+//!
+//! ```ignore
+//! // Client
+//! let mut client = Client::new(service_port)?;
+//! let reply = client.call(MessageType::Ping, &[], 500)?;
+//!
+//! // Server
+//! let mut server = Server::new(port);
+//! server.on(MessageType::Ping, |_args| Ok(Vec::new()));
+//! server.run()?;
+//! ```
+//!
+//! Handlers return a `StatusResult`; an error travels back to the caller as
+//! the error of its call. Requests for methods without a handler fail with
+//! `Unsupported`.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use atom_syscall::ipc::{close_port, create_port, PortId};
+use atom_syscall::thread::{get_time_ms, yield_now};
+use atom_syscall::SyscallResult;
+
+use crate::messages::{MessageHeader, MessageType};
+use crate::protocol::{
+    get_payload, recv_message, send_message, send_message_async, try_recv_message,
+};
+use crate::status::{StatusCode, StatusResult};
+use crate::MAX_MESSAGE_SIZE;
+
+/// Wait forever for a reply
+pub const NO_TIMEOUT: u64 = u64::MAX;
+
+/// Identifies a call among those a client has in flight
+pub type RequestId = u32;
+
+/// Start of every request payload; the method's arguments follow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestHeader {
+    pub request_id: RequestId,
+    /// Port the reply goes to
+    pub reply_port: u64,
+}
+
+impl RequestHeader {
+    pub const SIZE: usize = 12;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.request_id.to_le_bytes());
+        bytes[4..12].copy_from_slice(&self.reply_port.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            request_id: u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?),
+            reply_port: u64::from_le_bytes(bytes.get(4..12)?.try_into().ok()?),
+        })
+    }
+}
+
+/// Start of every `RpcReply` payload; the result follows when the call
+/// succeeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplyHeader {
+    pub request_id: RequestId,
+    /// `StatusCode::Ok`, or what the call failed with
+    pub status: StatusCode,
+}
+
+impl ReplyHeader {
+    pub const SIZE: usize = 8;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.request_id.to_le_bytes());
+        bytes[4..8].copy_from_slice(&(self.status as u32).to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let status = u32::from_le_bytes(bytes.get(4..8)?.try_into().ok()?);
+        Some(Self {
+            request_id: u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?),
+            status: StatusCode::from_u32(status)?,
+        })
+    }
+}
+
+/// Calls a service, with a reply port of its own
+pub struct Client {
+    service: PortId,
+    reply_port: PortId,
+    next_id: RequestId,
+    /// Calls sent and not answered yet
+    outstanding: Vec<RequestId>,
+    /// Replies that arrived while waiting for another call
+    replies: Vec<(RequestId, StatusResult<Vec<u8>>)>,
+}
+
+impl Client {
+    pub fn new(service: PortId) -> SyscallResult<Self> {
+        Ok(Self {
+            service,
+            reply_port: create_port()?,
+            next_id: 1,
+            outstanding: Vec::new(),
+            replies: Vec::new(),
+        })
+    }
+
+    pub fn service(&self) -> PortId {
+        self.service
+    }
+
+    /// Call `method` with `args` and wait up to `timeout_ms` for the reply
+    /// (`NO_TIMEOUT` for as long as it takes); `TimedOut` if none came
+    pub fn call(
+        &mut self,
+        method: MessageType,
+        args: &[u8],
+        timeout_ms: u64,
+    ) -> StatusResult<Vec<u8>> {
+        let id = self.send(method, args, false)?;
+        self.wait(id, timeout_ms)
+    }
+
+    /// Send a call without waiting for it; the reply is picked up with
+    /// `poll` or `wait`
+    pub fn call_async(&mut self, method: MessageType, args: &[u8]) -> StatusResult<RequestId> {
+        self.send(method, args, true)
+    }
+
+    /// The reply to call `id`, if it has arrived
+    pub fn poll(&mut self, id: RequestId) -> Option<StatusResult<Vec<u8>>> {
+        self.receive_waiting();
+        let pos = self.replies.iter().position(|(reply, _)| *reply == id)?;
+        Some(self.replies.swap_remove(pos).1)
+    }
+
+    /// Wait up to `timeout_ms` for the reply to call `id`; a call that
+    /// timed out is forgotten, and its reply dropped if it comes later
+    pub fn wait(&mut self, id: RequestId, timeout_ms: u64) -> StatusResult<Vec<u8>> {
+        let deadline = get_time_ms().saturating_add(timeout_ms);
+        loop {
+            if let Some(result) = self.poll(id) {
+                return result;
+            }
+            if !self.outstanding.contains(&id) {
+                return Err(StatusCode::InvalidArgument);
+            }
+            if get_time_ms() >= deadline {
+                self.outstanding.retain(|&call| call != id);
+                return Err(StatusCode::TimedOut);
+            }
+            yield_now();
+        }
+    }
+
+    fn send(
+        &mut self,
+        method: MessageType,
+        args: &[u8],
+        async_send: bool,
+    ) -> StatusResult<RequestId> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+
+        let header = RequestHeader { request_id: id, reply_port: self.reply_port };
+        let mut payload = Vec::with_capacity(RequestHeader::SIZE + args.len());
+        payload.extend_from_slice(&header.to_bytes());
+        payload.extend_from_slice(args);
+        if async_send {
+            send_message_async(self.service, method, &payload)?;
+        } else {
+            send_message(self.service, method, &payload)?;
+        }
+        self.outstanding.push(id);
+        Ok(id)
+    }
+
+    /// Move replies waiting on the port to `replies`, dropping those to
+    /// calls that were given up on
+    fn receive_waiting(&mut self) {
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        while let Ok(Some((header, len))) = try_recv_message(self.reply_port, &mut buffer) {
+            if header.msg_type != MessageType::RpcReply {
+                continue;
+            }
+            let payload = get_payload(&buffer, len);
+            let Some(reply) = ReplyHeader::from_bytes(payload) else {
+                continue;
+            };
+            let Some(pos) = self.outstanding.iter().position(|&id| id == reply.request_id) else {
+                continue;
+            };
+            self.outstanding.swap_remove(pos);
+            let body = &payload[ReplyHeader::SIZE..];
+            let result = reply.status.into_result().map(|()| body.to_vec());
+            self.replies.push((reply.request_id, result));
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        let _ = close_port(self.reply_port);
+    }
+}
+
+/// Answers one method: takes the arguments, returns the result
+pub type Handler<'a> = Box<dyn FnMut(&[u8]) -> StatusResult<Vec<u8>> + 'a>;
+
+/// Answers requests arriving on a port with the handler registered for
+/// their message type
+pub struct Server<'a> {
+    port: PortId,
+    handlers: Vec<(MessageType, Handler<'a>)>,
+}
+
+impl<'a> Server<'a> {
+    pub fn new(port: PortId) -> Self {
+        Self { port, handlers: Vec::new() }
+    }
+
+    pub fn port(&self) -> PortId {
+        self.port
+    }
+
+    /// Answer `method` with `handler`, replacing any handler it had
+    pub fn on(
+        &mut self,
+        method: MessageType,
+        handler: impl FnMut(&[u8]) -> StatusResult<Vec<u8>> + 'a,
+    ) -> &mut Self {
+        self.handlers.retain(|(registered, _)| *registered != method);
+        self.handlers.push((method, Box::new(handler)));
+        self
+    }
+
+    /// Answer requests until receiving from the port fails
+    pub fn run(&mut self) -> SyscallResult<()> {
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        loop {
+            let (header, len) = recv_message(self.port, &mut buffer)?;
+            self.dispatch(&header, get_payload(&buffer, len));
+        }
+    }
+
+    /// Answer the requests waiting on the port without blocking; returns
+    /// how many there were
+    pub fn poll(&mut self) -> SyscallResult<usize> {
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+        let mut count = 0;
+        while let Some((header, len)) = try_recv_message(self.port, &mut buffer)? {
+            self.dispatch(&header, get_payload(&buffer, len));
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Answer a request received some other way, e.g. by a service that
+    /// waits on more than one port; false if `payload` is too short to be
+    /// a request
+    pub fn dispatch(&mut self, header: &MessageHeader, payload: &[u8]) -> bool {
+        let Some(request) = RequestHeader::from_bytes(payload) else {
+            return false;
+        };
+        let args = &payload[RequestHeader::SIZE..];
+        let handler = self.handlers.iter_mut().find(|(method, _)| *method == header.msg_type);
+        let result = match handler {
+            Some((_, handler)) => handler(args),
+            None => Err(StatusCode::Unsupported),
+        };
+
+        let (status, body) = match result {
+            Ok(body) if MessageHeader::SIZE + ReplyHeader::SIZE + body.len() > MAX_MESSAGE_SIZE => {
+                (StatusCode::TooLarge, Vec::new())
+            }
+            Ok(body) => (StatusCode::Ok, body),
+            Err(error) => (error, Vec::new()),
+        };
+        let reply = ReplyHeader { request_id: request.request_id, status };
+        let mut message = Vec::with_capacity(ReplyHeader::SIZE + body.len());
+        message.extend_from_slice(&reply.to_bytes());
+        message.extend_from_slice(&body);
+        // A client that went away just misses its reply
+        let _ = send_message_async(request.reply_port, MessageType::RpcReply, &message);
+        true
+    }
+}
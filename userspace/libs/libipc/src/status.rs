@@ -0,0 +1,151 @@
+//! Reply Status Codes
+//!
+//! Every service answers failures with the same codes, whether the reply is
+//! an `rpc` reply, a `HelloAck` without a common version or a bare `Error`
+//! message, so a client handles "not found" or "denied" the same way for
+//! the compositor, the VFS or the network stack.
+//!
+//! `StatusCode` is a superset of `SyscallError`: a failed syscall inside a
+//! service turns into a status with `?`, and a status turns back into the
+//! nearest `SyscallError` for callers that only deal in those.
+
+use atom_syscall::SyscallError;
+
+/// Result of a request to a service
+pub type StatusResult<T> = Result<T, StatusCode>;
+
+/// Outcome of a request, carried in replies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum StatusCode {
+    Ok = 0,
+    /// The caller may not do this
+    Denied = 1,
+    /// No such object: window, file, stream, name...
+    NotFound = 2,
+    /// The object is in use; trying later may work
+    Busy = 3,
+    /// The service does not do this
+    Unsupported = 4,
+    /// The two sides have no protocol version in common
+    VersionMismatch = 5,
+    /// The request is malformed or its arguments out of range
+    InvalidArgument = 6,
+    /// The service ran out of memory
+    NoMemory = 7,
+    /// The request or its answer does not fit in a message
+    TooLarge = 8,
+    TimedOut = 9,
+    /// Nothing to answer yet; asking again later may work
+    WouldBlock = 10,
+    Deadlock = 11,
+    /// Any other failure
+    Failed = 12,
+}
+
+impl StatusCode {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(Self::Ok),
+            1 => Some(Self::Denied),
+            2 => Some(Self::NotFound),
+            3 => Some(Self::Busy),
+            4 => Some(Self::Unsupported),
+            5 => Some(Self::VersionMismatch),
+            6 => Some(Self::InvalidArgument),
+            7 => Some(Self::NoMemory),
+            8 => Some(Self::TooLarge),
+            9 => Some(Self::TimedOut),
+            10 => Some(Self::WouldBlock),
+            11 => Some(Self::Deadlock),
+            12 => Some(Self::Failed),
+            _ => None,
+        }
+    }
+
+    pub fn is_ok(self) -> bool {
+        self == Self::Ok
+    }
+
+    /// `Ok(())` for `StatusCode::Ok`, the status as the error otherwise
+    pub fn into_result(self) -> StatusResult<()> {
+        match self {
+            Self::Ok => Ok(()),
+            error => Err(error),
+        }
+    }
+
+    /// The status a result is reported with
+    pub fn of<T>(result: &StatusResult<T>) -> Self {
+        match result {
+            Ok(_) => Self::Ok,
+            Err(status) => *status,
+        }
+    }
+}
+
+impl From<SyscallError> for StatusCode {
+    fn from(error: SyscallError) -> Self {
+        match error {
+            SyscallError::Success => Self::Ok,
+            SyscallError::InvalidArgument => Self::InvalidArgument,
+            SyscallError::NotImplemented => Self::Unsupported,
+            SyscallError::OutOfMemory => Self::NoMemory,
+            SyscallError::PermissionDenied => Self::Denied,
+            SyscallError::Busy => Self::Busy,
+            SyscallError::MessageTooLarge => Self::TooLarge,
+            SyscallError::TimedOut => Self::TimedOut,
+            SyscallError::WouldBlock => Self::WouldBlock,
+            SyscallError::Deadlock => Self::Deadlock,
+        }
+    }
+}
+
+/// The nearest syscall error; statuses without one of their own become
+/// `InvalidArgument` or, for version mismatches, `NotImplemented`
+impl From<StatusCode> for SyscallError {
+    fn from(status: StatusCode) -> Self {
+        match status {
+            StatusCode::Ok => Self::Success,
+            StatusCode::Denied => Self::PermissionDenied,
+            StatusCode::Busy => Self::Busy,
+            StatusCode::Unsupported | StatusCode::VersionMismatch => Self::NotImplemented,
+            StatusCode::NoMemory => Self::OutOfMemory,
+            StatusCode::TooLarge => Self::MessageTooLarge,
+            StatusCode::TimedOut => Self::TimedOut,
+            StatusCode::WouldBlock => Self::WouldBlock,
+            StatusCode::Deadlock => Self::Deadlock,
+            StatusCode::NotFound | StatusCode::InvalidArgument | StatusCode::Failed => {
+                Self::InvalidArgument
+            }
+        }
+    }
+}
+
+/// Payload of `Error`: a request the service could not answer with its
+/// usual reply, and why
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorReply {
+    /// Type of the request being answered
+    pub request: u32,
+    pub status: StatusCode,
+}
+
+impl ErrorReply {
+    pub const SIZE: usize = 8;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..4].copy_from_slice(&self.request.to_le_bytes());
+        bytes[4..8].copy_from_slice(&(self.status as u32).to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let status = u32::from_le_bytes(bytes.get(4..8)?.try_into().ok()?);
+        Some(Self {
+            request: u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?),
+            status: StatusCode::from_u32(status)?,
+        })
+    }
+}
@@ -0,0 +1,278 @@
+//! Event Streams
+//!
+//! Input travels from drivers to the compositor and from the compositor to
+//! applications as streams of events. A consumer subscribes to a producer
+//! with a number of credits, each good for one event: the producer spends
+//! one per event it sends and stops when they run out, and the consumer
+//! grants more as it works through what it received. However slow the
+//! consumer, its port never holds more events than it granted credits for.
+//!
+//! While a subscriber has no credits, its events wait in a backlog. An
+//! event published with `Delivery::Coalesce`, such as pointer motion,
+//! replaces one of the same type at the end of the backlog instead of
+//! adding to it, so a stalled consumer gets the latest position rather
+//! than every step on the way. Other events queue up to `MAX_BACKLOG`;
+//! past that the oldest are dropped, and the subscriber is sent an
+//! `EventsDropped` ahead of the events that follow so it can resync.
+//!
+//! ```ignore
+//! // Producer, for each message on its control port
+//! if !publisher.handle(&header, payload) { /* not a stream message */ }
+//! publisher.publish(MessageType::MouseMove, &event.to_bytes(), Delivery::Coalesce);
+//!
+//! // Consumer
+//! let mut stream = Subscription::subscribe(producer_port, event_port, 16)?;
+//! // after handling each event from the stream:
+//! stream.consumed()?;
+//! ```
+
+extern crate alloc;
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use atom_syscall::ipc::PortId;
+use atom_syscall::{SyscallError, SyscallResult};
+
+use crate::messages::{MessageHeader, MessageType};
+use crate::protocol::send_message_async;
+
+/// Most credits a subscriber can hold; well under the kernel's queue depth
+/// so a stream leaves room on the port for other messages
+pub const MAX_CREDITS: u32 = 32;
+
+/// Most events waiting for credit per subscriber
+pub const MAX_BACKLOG: usize = 64;
+
+/// How a published event is held while its subscriber has no credit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Only the latest matters: replaces a waiting event of the same type
+    /// if it is the last one waiting
+    Coalesce,
+    /// Every one matters: waits in order behind the others
+    Queue,
+}
+
+/// Payload of `Subscribe`, `Unsubscribe` and `GrantCredits`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamControl {
+    /// Port the events go to
+    pub port: u64,
+    /// Credits granted; unused by `Unsubscribe`
+    pub credits: u32,
+}
+
+impl StreamControl {
+    pub const SIZE: usize = 12;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[0..8].copy_from_slice(&self.port.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.credits.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            port: u64::from_le_bytes(bytes.get(0..8)?.try_into().ok()?),
+            credits: u32::from_le_bytes(bytes.get(8..12)?.try_into().ok()?),
+        })
+    }
+}
+
+struct Subscriber {
+    port: PortId,
+    credits: u32,
+    backlog: VecDeque<(MessageType, Vec<u8>)>,
+    /// Events dropped from the backlog since the last `EventsDropped`
+    dropped: u32,
+}
+
+impl Subscriber {
+    fn new(port: PortId, credits: u32) -> Self {
+        Self { port, credits: credits.min(MAX_CREDITS), backlog: VecDeque::new(), dropped: 0 }
+    }
+
+    fn grant(&mut self, credits: u32) {
+        self.credits = self.credits.saturating_add(credits).min(MAX_CREDITS);
+    }
+
+    fn hold(&mut self, msg_type: MessageType, payload: &[u8], delivery: Delivery) {
+        if delivery == Delivery::Coalesce {
+            if let Some((last_type, last)) = self.backlog.back_mut() {
+                if *last_type == msg_type {
+                    last.clear();
+                    last.extend_from_slice(payload);
+                    return;
+                }
+            }
+        }
+        self.backlog.push_back((msg_type, payload.to_vec()));
+        if self.backlog.len() > MAX_BACKLOG {
+            self.backlog.pop_front();
+            self.dropped = self.dropped.saturating_add(1);
+        }
+    }
+
+    /// Send what the credits allow; `Err` only if the port is gone
+    fn flush(&mut self) -> SyscallResult<()> {
+        if self.dropped > 0 && self.credits > 0 {
+            let dropped = self.dropped.to_le_bytes();
+            if !self.send(MessageType::EventsDropped, &dropped)? {
+                return Ok(());
+            }
+            self.dropped = 0;
+        }
+        while self.credits > 0 {
+            let Some((msg_type, payload)) = self.backlog.pop_front() else {
+                break;
+            };
+            if !self.send(msg_type, &payload)? {
+                self.backlog.push_front((msg_type, payload));
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Spend a credit on one event; false if the port was full, which
+    /// leaves the subscriber waiting for credit like having none
+    fn send(&mut self, msg_type: MessageType, payload: &[u8]) -> SyscallResult<bool> {
+        match send_message_async(self.port, msg_type, payload) {
+            Ok(()) => {
+                self.credits -= 1;
+                Ok(true)
+            }
+            Err(SyscallError::WouldBlock) => {
+                self.credits = 0;
+                Ok(false)
+            }
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// The producer's end: subscribers and what each still has credit for
+pub struct Publisher {
+    subscribers: Vec<Subscriber>,
+}
+
+impl Publisher {
+    pub const fn new() -> Self {
+        Self { subscribers: Vec::new() }
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        !self.subscribers.is_empty()
+    }
+
+    /// Handle a stream control message received by the producer; false
+    /// if `header` is not one, so the caller handles it itself
+    pub fn handle(&mut self, header: &MessageHeader, payload: &[u8]) -> bool {
+        if !matches!(
+            header.msg_type,
+            MessageType::Subscribe | MessageType::Unsubscribe | MessageType::GrantCredits
+        ) {
+            return false;
+        }
+        if let Some(control) = StreamControl::from_bytes(payload) {
+            match header.msg_type {
+                MessageType::Subscribe => self.subscribe(control.port, control.credits),
+                MessageType::Unsubscribe => self.unsubscribe(control.port),
+                _ => self.grant(control.port, control.credits),
+            }
+        }
+        true
+    }
+
+    /// Add a subscriber with `credits` to start with; subscribing again
+    /// starts over, dropping what was waiting
+    pub fn subscribe(&mut self, port: PortId, credits: u32) {
+        self.unsubscribe(port);
+        self.subscribers.push(Subscriber::new(port, credits));
+    }
+
+    pub fn unsubscribe(&mut self, port: PortId) {
+        self.subscribers.retain(|subscriber| subscriber.port != port);
+    }
+
+    /// Give a subscriber more credit and send what was waiting for it
+    pub fn grant(&mut self, port: PortId, credits: u32) {
+        let Some(pos) = self.subscribers.iter().position(|s| s.port == port) else {
+            return;
+        };
+        let subscriber = &mut self.subscribers[pos];
+        subscriber.grant(credits);
+        if subscriber.flush().is_err() {
+            self.subscribers.swap_remove(pos);
+        }
+    }
+
+    /// Send an event to every subscriber with credit, holding it for the
+    /// others; subscribers whose port is gone are dropped
+    pub fn publish(&mut self, msg_type: MessageType, payload: &[u8], delivery: Delivery) {
+        self.subscribers.retain_mut(|subscriber| {
+            // Behind a backlog the event waits its turn
+            if subscriber.credits > 0 && subscriber.backlog.is_empty() && subscriber.dropped == 0 {
+                match subscriber.send(msg_type, payload) {
+                    Ok(true) => return true,
+                    Ok(false) => {}
+                    Err(_) => return false,
+                }
+            }
+            subscriber.hold(msg_type, payload, delivery);
+            true
+        });
+    }
+}
+
+impl Default for Publisher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The consumer's end of a stream: hands credits back as events are
+/// handled
+pub struct Subscription {
+    publisher: PortId,
+    port: PortId,
+    window: u32,
+    /// Events handled since credit was last granted
+    consumed: u32,
+}
+
+impl Subscription {
+    /// Subscribe `port` to the events of `publisher`, allowing `window`
+    /// events (at most `MAX_CREDITS`) to be on their way at once
+    pub fn subscribe(publisher: PortId, port: PortId, window: u32) -> SyscallResult<Self> {
+        let window = window.clamp(1, MAX_CREDITS);
+        let control = StreamControl { port, credits: window };
+        send_message_async(publisher, MessageType::Subscribe, &control.to_bytes())?;
+        Ok(Self { publisher, port, window, consumed: 0 })
+    }
+
+    pub fn publisher(&self) -> PortId {
+        self.publisher
+    }
+
+    /// Count an event from the stream as handled; credit goes back to the
+    /// publisher in batches of half the window
+    pub fn consumed(&mut self) -> SyscallResult<()> {
+        self.consumed += 1;
+        if self.consumed < (self.window / 2).max(1) {
+            return Ok(());
+        }
+        let control = StreamControl { port: self.port, credits: self.consumed };
+        send_message_async(self.publisher, MessageType::GrantCredits, &control.to_bytes())?;
+        self.consumed = 0;
+        Ok(())
+    }
+
+    /// Stop the events; ones already sent still arrive
+    pub fn unsubscribe(self) -> SyscallResult<()> {
+        let control = StreamControl { port: self.port, credits: 0 };
+        send_message_async(self.publisher, MessageType::Unsubscribe, &control.to_bytes())
+    }
+}
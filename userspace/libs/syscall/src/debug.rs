@@ -1,6 +1,67 @@
 // Debug and logging syscalls
 
-use crate::raw::{syscall2, numbers::*};
+use crate::error::{EINVAL, EPERM, SyscallError, SyscallResult};
+use crate::raw::{syscall2, syscall3, syscall4, numbers::*};
+
+/// Kernel log severity levels (matches the kernel's `LogLevel`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LogLevel {
+    Debug = 0,
+    Info = 1,
+    Warn = 2,
+    Error = 3,
+    Panic = 4,
+}
+
+impl LogLevel {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(LogLevel::Debug),
+            1 => Some(LogLevel::Info),
+            2 => Some(LogLevel::Warn),
+            3 => Some(LogLevel::Error),
+            4 => Some(LogLevel::Panic),
+            _ => None,
+        }
+    }
+
+    /// A level by number (0-4) or name, as in "warn"
+    pub fn parse(text: &str) -> Option<Self> {
+        if let Ok(value) = text.parse::<u8>() {
+            return Self::from_u8(value);
+        }
+        const NAMES: [(&str, LogLevel); 5] = [
+            ("debug", LogLevel::Debug),
+            ("info", LogLevel::Info),
+            ("warn", LogLevel::Warn),
+            ("error", LogLevel::Error),
+            ("panic", LogLevel::Panic),
+        ];
+        NAMES.iter().find(|(name, _)| name.eq_ignore_ascii_case(text)).map(|&(_, level)| level)
+    }
+
+    /// The tag the kernel puts on log lines of this level, as in "[WARN ]"
+    pub fn tag(self) -> &'static str {
+        match self {
+            LogLevel::Debug => "[DEBUG]",
+            LogLevel::Info => "[INFO ]",
+            LogLevel::Warn => "[WARN ]",
+            LogLevel::Error => "[ERROR]",
+            LogLevel::Panic => "[PANIC]",
+        }
+    }
+}
+
+/// Kernel log sinks whose level can be changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum LogSink {
+    /// Entries below this level are discarded entirely
+    Capture = 0,
+    /// Entries below this level are kept in the ring but not sent to COM1
+    Serial = 1,
+}
 
 /// Send a debug log message to the kernel
 ///
@@ -12,6 +73,29 @@ pub fn log(message: &str) {
     }
 }
 
+/// Name the function around a code address in this program, with the
+/// offset into it
+///
+/// The name is read into `buffer` and cut short if it does not fit. None
+/// if the program was built without symbols (`elf2atxf --symbols`) or no
+/// function covers `address`.
+pub fn symbolize(address: u64, buffer: &mut [u8]) -> Option<(&str, u64)> {
+    let offset = unsafe {
+        syscall3(SYS_SYMBOLIZE, address, buffer.as_mut_ptr() as u64, buffer.len() as u64)
+    };
+    if offset == EINVAL {
+        return None;
+    }
+
+    let len = buffer.iter().position(|&byte| byte == 0).unwrap_or(buffer.len());
+    let name = match core::str::from_utf8(&buffer[..len]) {
+        Ok(name) => name,
+        // Cut inside a character
+        Err(error) => core::str::from_utf8(&buffer[..error.valid_up_to()]).unwrap_or(""),
+    };
+    Some((name, offset))
+}
+
 /// Log a message with a prefix tag
 pub fn log_tagged(tag: &str, message: &str) {
     // Simple implementation - just log the message
@@ -26,3 +110,40 @@ macro_rules! debug_print {
         // For now, just a stub - would need alloc for formatting
     }};
 }
+
+/// Read kernel log lines at or above `min_level` into `buffer`
+///
+/// `pos` is a byte offset into the log, updated to continue where this read
+/// stopped; start at 0 to get the oldest entries still held. Only whole
+/// lines are read, unless one is too long for `buffer` on its own. Returns
+/// the number of bytes read, 0 once caught up.
+pub fn klog_read(buffer: &mut [u8], pos: &mut u64, min_level: LogLevel) -> SyscallResult<usize> {
+    let result = unsafe {
+        syscall4(
+            SYS_KLOG_READ,
+            buffer.as_mut_ptr() as u64,
+            buffer.len() as u64,
+            pos as *mut u64 as u64,
+            min_level as u64,
+        )
+    };
+
+    if result == EINVAL {
+        Err(SyscallError::InvalidArgument)
+    } else {
+        Ok(result as usize)
+    }
+}
+
+/// Set the minimum level for a kernel log sink
+///
+/// Needs `DebugCap`; without it this fails with `PermissionDenied`.
+pub fn set_log_level(sink: LogSink, level: LogLevel) -> SyscallResult<()> {
+    let result = unsafe { syscall2(SYS_KLOG_SET_LEVEL, sink as u64, level as u64) };
+
+    match result {
+        0 => Ok(()),
+        EPERM => Err(SyscallError::PermissionDenied),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
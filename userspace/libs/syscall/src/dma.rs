@@ -0,0 +1,72 @@
+// DMA memory allocation for userspace drivers
+//
+// Devices that bus-master (audio, USB, network) need physically contiguous
+// buffers and their physical address. The kernel hands out zeroed pages,
+// mapped into a window of its choosing, and reports both the address the
+// driver uses and the one the device uses.
+
+use crate::error::{EINVAL, ENOMEM, EPERM, ESUCCESS, SyscallError, SyscallResult};
+use crate::raw::{syscall1, syscall2, numbers::*};
+
+/// Size of a DMA page
+pub const DMA_PAGE_SIZE: usize = 4096;
+
+/// A physically contiguous buffer owned by the calling driver
+#[derive(Debug, Clone, Copy)]
+pub struct DmaBuffer {
+    /// Address the driver reads and writes through
+    pub virt: usize,
+    /// Address programmed into the device
+    pub phys: u64,
+    /// Size in bytes (whole pages)
+    pub size: usize,
+}
+
+impl DmaBuffer {
+    /// Pointer to the start of the buffer
+    pub fn as_mut_ptr(&self) -> *mut u8 {
+        self.virt as *mut u8
+    }
+
+    /// Physical address of the byte at `offset`
+    pub fn phys_at(&self, offset: usize) -> u64 {
+        self.phys + offset as u64
+    }
+}
+
+/// Allocate `pages` zeroed, physically contiguous pages
+///
+/// Only services whose manifest entry grants `DMABufferCap` may allocate;
+/// anyone else gets `PermissionDenied`.
+pub fn dma_alloc(pages: usize) -> SyscallResult<DmaBuffer> {
+    let mut phys = 0u64;
+    let result = unsafe {
+        syscall2(SYS_DMA_ALLOC, pages as u64, &mut phys as *mut u64 as u64)
+    };
+
+    match result {
+        EPERM => Err(SyscallError::PermissionDenied),
+        ENOMEM => Err(SyscallError::OutOfMemory),
+        EINVAL => Err(SyscallError::InvalidArgument),
+        v if v >= u64::MAX - 10 => Err(SyscallError::InvalidArgument),
+        virt => Ok(DmaBuffer {
+            virt: virt as usize,
+            phys,
+            size: pages * DMA_PAGE_SIZE,
+        }),
+    }
+}
+
+/// Give a buffer from `dma_alloc` back to the kernel
+///
+/// The device must no longer be using it: the pages are reused straight
+/// away.
+pub fn dma_free(buffer: DmaBuffer) -> SyscallResult<()> {
+    let result = unsafe { syscall1(SYS_DMA_FREE, buffer.virt as u64) };
+
+    match result {
+        ESUCCESS => Ok(()),
+        EPERM => Err(SyscallError::PermissionDenied),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
@@ -0,0 +1,115 @@
+// Program arguments and boot parameters
+//
+// A program's arguments come from its entry in the boot manifest, e.g.
+// `args = ["--scale=2", "--no-animations"]`; boot parameters are the words
+// given to the loader, e.g. `verbose loglevel=debug`. Both are read into a
+// buffer the caller provides:
+//
+//     let mut buffer = [0u8; 256];
+//     let args = env::args(&mut buffer);
+//     let scale = args.value::<u32>("scale").unwrap_or(1);
+//     let animate = !args.flag("no-animations");
+//
+// Names are matched with or without a leading `--`, so `--scale=2` and
+// `scale=2` both answer `value("scale")`.
+
+use core::str::FromStr;
+
+use crate::error::EINVAL;
+use crate::raw::{syscall2, numbers::*};
+
+/// Arguments as the kernel writes them, each followed by a NUL byte
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Args<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Args<'a> {
+    /// Arguments in the kernel's format: each one followed by a NUL byte
+    pub fn from_bytes(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// The arguments in order; any that are not UTF-8 are skipped
+    pub fn iter(&self) -> impl Iterator<Item = &'a str> + 'a {
+        self.data
+            .split(|&byte| byte == 0)
+            .filter(|arg| !arg.is_empty())
+            .filter_map(|arg| core::str::from_utf8(arg).ok())
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// What follows `=` in the last `name=value` argument
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.iter()
+            .filter_map(|arg| strip_dashes(arg).split_once('='))
+            .filter(|(key, _)| *key == name)
+            .last()
+            .map(|(_, value)| value)
+    }
+
+    /// `get`, parsed; None if it is missing or does not parse
+    pub fn value<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.get(name)?.parse().ok()
+    }
+
+    /// Whether `name` was given on its own, without a value
+    pub fn flag(&self, name: &str) -> bool {
+        self.iter().any(|arg| strip_dashes(arg) == name)
+    }
+}
+
+fn strip_dashes(arg: &str) -> &str {
+    arg.strip_prefix("--").unwrap_or(arg)
+}
+
+/// Read the calling program's arguments into `buffer`
+///
+/// Arguments that do not fit are left out; threads started with
+/// `thread::spawn` see the arguments of their program.
+pub fn args(buffer: &mut [u8]) -> Args<'_> {
+    read(SYS_PROC_ARGS, buffer)
+}
+
+/// Read the boot parameters into `buffer`
+///
+/// Parameters that do not fit are left out.
+pub fn boot_args(buffer: &mut [u8]) -> Args<'_> {
+    read(SYS_BOOT_ARGS, buffer)
+}
+
+/// Bytes `args` needs to return every argument
+pub fn args_len() -> usize {
+    read_len(SYS_PROC_ARGS)
+}
+
+/// Bytes `boot_args` needs to return every parameter
+pub fn boot_args_len() -> usize {
+    read_len(SYS_BOOT_ARGS)
+}
+
+fn read(number: u64, buffer: &mut [u8]) -> Args<'_> {
+    let total = unsafe { syscall2(number, buffer.as_mut_ptr() as u64, buffer.len() as u64) };
+    if total == EINVAL {
+        return Args::default();
+    }
+
+    // An argument cut off at the end of the buffer is dropped whole
+    let written = (total as usize).min(buffer.len());
+    let complete = buffer[..written].iter().rposition(|&byte| byte == 0).map_or(0, |nul| nul + 1);
+    Args::from_bytes(&buffer[..complete])
+}
+
+fn read_len(number: u64) -> usize {
+    match unsafe { syscall2(number, 0, 0) } {
+        EINVAL => 0,
+        total => total as usize,
+    }
+}
@@ -1,7 +1,14 @@
 // Framebuffer and graphics syscalls
 
-use crate::error::{ESUCCESS, EPERM};
-use crate::raw::{syscall1, numbers::*};
+use core::arch::x86_64::{
+    __m128i, _mm_add_epi16, _mm_and_si128, _mm_loadu_si128, _mm_mullo_epi16, _mm_packus_epi16,
+    _mm_set1_epi16, _mm_set1_epi32, _mm_setzero_si128, _mm_srli_epi16, _mm_storeu_si128,
+    _mm_unpackhi_epi8, _mm_unpacklo_epi8,
+};
+use core::cell::Cell;
+
+use crate::error::{EBUSY, EINVAL, ENOSYS, ESUCCESS, EPERM, SyscallError, SyscallResult};
+use crate::raw::{syscall1, syscall2, numbers::*};
 
 // ============================================================================
 // Framebuffer Information
@@ -81,6 +88,48 @@ pub fn map_framebuffer() -> Option<FramebufferInfo> {
     }
 }
 
+// ============================================================================
+// Video Modes
+// ============================================================================
+
+/// A screen resolution
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VideoMode {
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Fill `modes` with the resolutions the screen can switch to, the
+/// current one first
+///
+/// Returns how many there are, which may be more than fit. Without a
+/// mode-setting backend only the current mode is listed.
+pub fn video_modes(modes: &mut [VideoMode]) -> usize {
+    let result = unsafe {
+        syscall2(SYS_VIDEO_MODES, modes.as_mut_ptr() as u64, modes.len() as u64)
+    };
+    if result == EINVAL { 0 } else { result as usize }
+}
+
+/// Switch the screen to `width` x `height`, one of the `video_modes`
+///
+/// What was on screen is lost. `Framebuffer` handles keep the geometry
+/// they were made with; make a new one after switching. Only services
+/// whose manifest entry grants `FrameBufferCap` may switch; anyone else
+/// gets `PermissionDenied`.
+pub fn set_video_mode(width: u32, height: u32) -> SyscallResult<()> {
+    let result = unsafe { syscall2(SYS_SET_VIDEO_MODE, width as u64, height as u64) };
+
+    match result {
+        ESUCCESS => Ok(()),
+        EPERM => Err(SyscallError::PermissionDenied),
+        ENOSYS => Err(SyscallError::NotImplemented),
+        EBUSY => Err(SyscallError::Busy),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
 // ============================================================================
 // Color Types
 // ============================================================================
@@ -126,24 +175,161 @@ impl Color {
     }
 }
 
+/// Mix two 32-bit pixels: `alpha` parts of `src` over `dst`, out of 255.
+/// Works for either channel order; the top byte of the result is zero.
+#[inline]
+pub fn blend_pixel(dst: u32, src: u32, alpha: u32) -> u32 {
+    let inv = 255 - alpha;
+    let rb = (((src & 0xFF00FF) * alpha + (dst & 0xFF00FF) * inv) >> 8) & 0xFF00FF;
+    let g = (((src & 0x00FF00) * alpha + (dst & 0x00FF00) * inv) >> 8) & 0x00FF00;
+    rb | g
+}
+
+// ============================================================================
+// Pixel Views
+// ============================================================================
+
+/// A borrowed, read-only rectangle of 32-bit pixels laid out in rows of
+/// `stride`, e.g. a decoded image, a window surface or a framebuffer.
+/// Every access is bounds-checked against the rectangle.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Pixels<'a> {
+    data: &'a [u32],
+    width: u32,
+    height: u32,
+    stride: u32,
+}
+
+impl<'a> Pixels<'a> {
+    /// View `data` as `height` rows of `width` pixels, `stride` apart, or
+    /// `None` if the rows do not fit in `data`
+    pub fn new(data: &'a [u32], width: u32, height: u32, stride: u32) -> Option<Self> {
+        if width > stride {
+            return None;
+        }
+        let needed = match height {
+            0 => 0,
+            _ => (height as usize - 1) * stride as usize + width as usize,
+        };
+        if needed > data.len() {
+            return None;
+        }
+        Some(Self { data, width, height, stride })
+    }
+
+    /// View packed rows (`stride` equal to `width`)
+    pub fn packed(data: &'a [u32], width: u32) -> Option<Self> {
+        let height = data.len().checked_div(width as usize).unwrap_or(0);
+        Self::new(data, width, u32::try_from(height).ok()?, width)
+    }
+
+    #[inline]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The `width` pixels of row `y`
+    #[inline]
+    pub fn row(&self, y: u32) -> Option<&'a [u32]> {
+        if y >= self.height {
+            return None;
+        }
+        let start = y as usize * self.stride as usize;
+        self.data.get(start..start + self.width as usize)
+    }
+
+    #[inline]
+    pub fn pixel(&self, x: u32, y: u32) -> Option<u32> {
+        if x >= self.width {
+            return None;
+        }
+        self.row(y).map(|row| row[x as usize])
+    }
+
+    /// The part of this view inside the given rectangle; empty if they do
+    /// not overlap
+    pub fn sub(&self, x: u32, y: u32, width: u32, height: u32) -> Pixels<'a> {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+        let start = (y as usize * self.stride as usize + x as usize).min(self.data.len());
+        Self { data: &self.data[start..], width, height, stride: self.stride }
+    }
+
+    /// Rows from top to bottom
+    pub fn rows(&self) -> impl Iterator<Item = &'a [u32]> {
+        let view = *self;
+        (0..view.height).filter_map(move |y| view.row(y))
+    }
+}
+
 // ============================================================================
 // Framebuffer Handle
 // ============================================================================
 
 /// Framebuffer handle for drawing operations
+///
+/// Drawing is limited to the clip rectangle, which covers the whole screen
+/// unless narrowed with `set_clip`. Reads and the `Pixels` view see the
+/// whole buffer. The handle stands for the mapping: pixels are 32-bit, and
+/// views borrowed from it cannot outlive it.
+///
+/// Fills, copies and blends are clipped once per rectangle and then done a
+/// row at a time with SSE2, four pixels per store.
 pub struct Framebuffer {
     info: FramebufferInfo,
+    /// Clip as (left, top, right, bottom), right/bottom exclusive
+    clip: Cell<(u32, u32, u32, u32)>,
 }
 
 impl Framebuffer {
     /// Create a new framebuffer handle
     pub fn new() -> Option<Self> {
-        get_framebuffer().map(|info| Self { info })
+        // The kernel maps the framebuffer for the life of the process
+        get_framebuffer().map(|info| unsafe { Self::from_info(info) })
     }
 
     /// Create from mapped framebuffer
     pub fn from_mapped() -> Option<Self> {
-        map_framebuffer().map(|info| Self { info })
+        map_framebuffer().map(|info| unsafe { Self::from_info(info) })
+    }
+
+    /// Wrap any pixel buffer laid out like a framebuffer (e.g. a back buffer)
+    ///
+    /// # Safety
+    ///
+    /// `info` must describe memory that stays mapped and writable for as
+    /// long as the handle lives: `height` rows of `stride` 32-bit pixels at
+    /// `address`, used by nothing else that assumes it is unaliased.
+    pub unsafe fn from_info(info: FramebufferInfo) -> Self {
+        Self {
+            info,
+            clip: Cell::new((0, 0, info.width, info.height)),
+        }
+    }
+
+    /// Restrict drawing to the given rectangle (intersected with the screen)
+    pub fn set_clip(&self, x: u32, y: u32, width: u32, height: u32) {
+        let right = x.saturating_add(width).min(self.info.width);
+        let bottom = y.saturating_add(height).min(self.info.height);
+        self.clip.set((x.min(right), y.min(bottom), right, bottom));
+    }
+
+    /// Allow drawing anywhere on screen again
+    pub fn reset_clip(&self) {
+        self.clip.set((0, 0, self.info.width, self.info.height));
+    }
+
+    /// Current clip as (left, top, right, bottom), right/bottom exclusive
+    #[inline]
+    pub fn clip(&self) -> (u32, u32, u32, u32) {
+        self.clip.get()
     }
 
     #[inline]
@@ -171,43 +357,212 @@ impl Framebuffer {
         self.info.bytes_per_pixel as usize
     }
 
-    /// Draw a single pixel (bounds checked)
+    /// The raw pixel at (x, y), or `None` off screen
     #[inline]
-    pub fn draw_pixel(&self, x: u32, y: u32, color: Color) {
+    pub fn pixel(&self, x: u32, y: u32) -> Option<u32> {
         if x >= self.info.width || y >= self.info.height {
+            return None;
+        }
+        Some(unsafe { core::ptr::read_volatile(self.info.pixel_ptr(x, y)) })
+    }
+
+    /// Set the raw pixel at (x, y) (clipped)
+    #[inline]
+    pub fn set_pixel(&self, x: u32, y: u32, pixel: u32) {
+        let (left, top, right, bottom) = self.clip.get();
+        if x < left || y < top || x >= right || y >= bottom {
             return;
         }
 
-        let ptr = self.info.pixel_ptr(x, y);
         unsafe {
-            core::ptr::write_volatile(ptr, color.to_bgr32());
+            core::ptr::write_volatile(self.info.pixel_ptr(x, y), pixel);
         }
     }
 
-    /// Fill a rectangle
+    /// The on-screen pixels of row `y`, or `None` off screen
+    pub fn row(&self, y: u32) -> Option<&[u32]> {
+        self.pixels().row(y)
+    }
+
+    /// The whole buffer as a read-only view, e.g. to copy it to another
+    /// buffer with `blit` or to take a screenshot
+    pub fn pixels(&self) -> Pixels<'_> {
+        let len = match self.info.height {
+            0 => 0,
+            height => (height - 1) as usize * self.info.stride as usize + self.info.width as usize,
+        };
+        let data = unsafe { core::slice::from_raw_parts(self.info.address as *const u32, len) };
+        Pixels { data, width: self.info.width, height: self.info.height, stride: self.info.stride }
+    }
+
+    /// Draw a single pixel (clipped)
+    #[inline]
+    pub fn draw_pixel(&self, x: u32, y: u32, color: Color) {
+        self.set_pixel(x, y, color.to_bgr32());
+    }
+
+    /// Fill a rectangle with a raw pixel value (clipped)
+    pub fn fill(&self, x: u32, y: u32, width: u32, height: u32, pixel: u32) {
+        let (left, top, right, bottom) = self.clip.get();
+
+        let x0 = x.max(left);
+        let y0 = y.max(top);
+        let x1 = x.saturating_add(width).min(right);
+        let y1 = y.saturating_add(height).min(bottom);
+        if x1 <= x0 {
+            return;
+        }
+
+        for py in y0..y1 {
+            unsafe {
+                fill_row(self.info.pixel_ptr(x0, py), (x1 - x0) as usize, pixel);
+            }
+        }
+    }
+
+    /// Fill a rectangle (clipped)
     pub fn fill_rect(&self, x: u32, y: u32, width: u32, height: u32, color: Color) {
-        let pixel = color.to_bgr32();
-        
-        for dy in 0..height {
-            let py = y + dy;
-            if py >= self.info.height {
-                break;
+        self.fill(x, y, width, height, color.to_bgr32());
+    }
+
+    /// Copy `src` with its top-left corner at (`x`, `y`) (clipped)
+    pub fn blit(&self, x: i32, y: i32, src: &Pixels<'_>) {
+        if let Some(span) = self.clip_span(x, y, src) {
+            self.copy_span(&span, src);
+        }
+    }
+
+    /// Copy each of `areas`, given as (x, y, width, height), from `src` to
+    /// the same place here, clipped: e.g. the damaged parts of a back
+    /// buffer. Returns the number of pixels copied.
+    pub fn copy_areas(
+        &self,
+        src: &Pixels<'_>,
+        areas: impl IntoIterator<Item = (u32, u32, u32, u32)>,
+    ) -> u64 {
+        let mut copied = 0;
+        for (x, y, width, height) in areas {
+            let part = src.sub(x, y, width, height);
+            if let Some(span) = self.clip_span(x as i32, y as i32, &part) {
+                self.copy_span(&span, &part);
+                copied += span.cols as u64 * span.rows as u64;
             }
-            
-            for dx in 0..width {
-                let px = x + dx;
-                if px >= self.info.width {
-                    break;
-                }
-                
-                let ptr = self.info.pixel_ptr(px, py);
+        }
+        copied
+    }
+
+    /// Copy the part of `src` that `span` says is visible
+    fn copy_span(&self, span: &Span, src: &Pixels<'_>) {
+        for row in 0..span.rows {
+            let line = span.source_row(src, row);
+            unsafe {
+                copy_row(line.as_ptr(), self.info.pixel_ptr(span.x, span.y + row), line.len());
+            }
+        }
+    }
+
+    /// Like `blit`, but each pixel written is `mix(below, pixel)` of the
+    /// pixel already there and the one from `src`
+    pub fn blit_with(
+        &self,
+        x: i32,
+        y: i32,
+        src: &Pixels<'_>,
+        mut mix: impl FnMut(u32, u32) -> u32,
+    ) {
+        let Some(span) = self.clip_span(x, y, src) else {
+            return;
+        };
+
+        for row in 0..span.rows {
+            let dst = self.info.pixel_ptr(span.x, span.y + row);
+            for (col, &pixel) in span.source_row(src, row).iter().enumerate() {
                 unsafe {
-                    core::ptr::write_volatile(ptr, pixel);
+                    let ptr = dst.add(col);
+                    ptr.write(mix(ptr.read(), pixel));
                 }
             }
         }
     }
 
+    /// Move the `width` x `height` rectangle at (`x`, `y`) so its corner is
+    /// at (`to_x`, `to_y`); the rectangles may overlap. The source is
+    /// clipped to the screen, the destination to the clip rectangle.
+    pub fn copy_rect(&self, x: u32, y: u32, width: u32, height: u32, to_x: u32, to_y: u32) {
+        let width = width.min(self.info.width.saturating_sub(x));
+        let height = height.min(self.info.height.saturating_sub(y));
+        let (left, top, right, bottom) = self.clip.get();
+
+        // Destination span, and how far into the source it starts
+        let x0 = to_x.max(left);
+        let y0 = to_y.max(top);
+        let x1 = to_x.saturating_add(width).min(right);
+        let y1 = to_y.saturating_add(height).min(bottom);
+        if x1 <= x0 || y1 <= y0 {
+            return;
+        }
+        let (sx, sy) = (x + (x0 - to_x), y + (y0 - to_y));
+        let cols = (x1 - x0) as usize;
+
+        // Go against the direction of the move so rows are read before
+        // they are overwritten
+        let rows = y1 - y0;
+        for i in 0..rows {
+            let row = if y0 > sy { rows - 1 - i } else { i };
+            unsafe {
+                core::ptr::copy(
+                    self.info.pixel_ptr(sx, sy + row),
+                    self.info.pixel_ptr(x0, y0 + row),
+                    cols,
+                );
+            }
+        }
+    }
+
+    /// Where `src` placed at (`x`, `y`) meets the clip, or `None` if
+    /// nothing of it shows
+    fn clip_span(&self, x: i32, y: i32, src: &Pixels<'_>) -> Option<Span> {
+        let (left, top, right, bottom) = self.clip.get();
+        let (x, y) = (x as i64, y as i64);
+
+        let x0 = x.max(left as i64);
+        let y0 = y.max(top as i64);
+        let x1 = (x + src.width as i64).min(right as i64);
+        let y1 = (y + src.height as i64).min(bottom as i64);
+        if x1 <= x0 || y1 <= y0 {
+            return None;
+        }
+        Some(Span {
+            x: x0 as u32,
+            y: y0 as u32,
+            src_x: (x0 - x) as u32,
+            src_y: (y0 - y) as u32,
+            cols: (x1 - x0) as u32,
+            rows: (y1 - y0) as u32,
+        })
+    }
+
+    /// Blend a color over a rectangle with `alpha` (0 = unchanged,
+    /// 255 = same as `fill_rect`), clipped
+    pub fn blend_rect(&self, x: u32, y: u32, width: u32, height: u32, color: Color, alpha: u8) {
+        let pixel = color.to_bgr32();
+        let (left, top, right, bottom) = self.clip.get();
+
+        let x0 = x.max(left);
+        let y0 = y.max(top);
+        let x1 = x.saturating_add(width).min(right);
+        let y1 = y.saturating_add(height).min(bottom);
+        if x1 <= x0 {
+            return;
+        }
+
+        for py in y0..y1 {
+            unsafe {
+                blend_row(self.info.pixel_ptr(x0, py), (x1 - x0) as usize, pixel, alpha as u32);
+            }
+        }
+    }
+
     /// Clear the entire screen
     pub fn clear(&self, color: Color) {
         self.fill_rect(0, 0, self.info.width, self.info.height, color);
@@ -215,15 +570,7 @@ impl Framebuffer {
 
     /// Draw a character using built-in 8x8 font
     pub fn draw_char(&self, x: u32, y: u32, ch: u8, fg: Color, bg: Color) {
-        let glyph = get_font_glyph(ch);
-
-        for row in 0..8 {
-            for col in 0..8 {
-                let bit = (glyph[row] >> col) & 1;
-                let color = if bit == 1 { fg } else { bg };
-                self.draw_pixel(x + col as u32, y + row as u32, color);
-            }
-        }
+        self.draw_char_sized(x, y, ch, fg, bg, 8);
     }
 
     /// Draw a string
@@ -238,6 +585,34 @@ impl Framebuffer {
         }
     }
 
+    /// Draw a character from the 8x8 font stretched to `size` x `size`
+    pub fn draw_char_sized(&self, x: u32, y: u32, ch: u8, fg: Color, bg: Color, size: u32) {
+        let glyph = get_font_glyph(ch);
+        let (fg, bg) = (fg.to_bgr32(), bg.to_bgr32());
+
+        // Stretched rows repeat and neighbouring bits often match, so each
+        // run of one color is filled as a single rectangle
+        for (dy, rows, line) in runs(size, |dy| dy * 8 / size) {
+            let bits = glyph[line as usize] as u32;
+            for (dx, cols, bit) in runs(size, |dx| (bits >> (dx * 8 / size)) & 1) {
+                let pixel = if bit == 1 { fg } else { bg };
+                self.fill(x + dx, y + dy, cols, rows, pixel);
+            }
+        }
+    }
+
+    /// Draw a string with `size` x `size` characters
+    pub fn draw_string_sized(&self, x: u32, y: u32, text: &str, fg: Color, bg: Color, size: u32) {
+        let mut offset_x = x;
+        for byte in text.bytes() {
+            if offset_x + size > self.info.width {
+                break;
+            }
+            self.draw_char_sized(offset_x, y, byte, fg, bg, size);
+            offset_x += size;
+        }
+    }
+
     /// Draw a horizontal line
     pub fn draw_hline(&self, x: u32, y: u32, width: u32, color: Color) {
         self.fill_rect(x, y, width, 1, color);
@@ -257,6 +632,137 @@ impl Framebuffer {
     }
 }
 
+/// The visible part of a blit: destination corner, source corner and size
+struct Span {
+    x: u32,
+    y: u32,
+    src_x: u32,
+    src_y: u32,
+    cols: u32,
+    rows: u32,
+}
+
+impl Span {
+    /// The pixels of `src` that land on destination row `row` of the span
+    fn source_row<'a>(&self, src: &Pixels<'a>, row: u32) -> &'a [u32] {
+        let line = src.row(self.src_y + row).unwrap_or(&[]);
+        &line[self.src_x as usize..][..self.cols as usize]
+    }
+}
+
+/// Split `0..len` into runs over which `key` stays the same, as
+/// (start, length, key)
+fn runs(len: u32, key: impl Fn(u32) -> u32) -> impl Iterator<Item = (u32, u32, u32)> {
+    let mut start = 0;
+    core::iter::from_fn(move || {
+        if start >= len {
+            return None;
+        }
+        let value = key(start);
+        let end = (start + 1..len).find(|&i| key(i) != value).unwrap_or(len);
+        let run = (start, end - start, value);
+        start = end;
+        Some(run)
+    })
+}
+
+// ============================================================================
+// Row Operations
+// ============================================================================
+//
+// SSE2 is part of x86_64 and the kernel keeps each thread's SSE registers
+// across switches, so these need no feature check. Stores are unaligned:
+// rows start wherever the clip puts them.
+
+/// Set `len` pixels from `dst` to `pixel`
+///
+/// # Safety
+///
+/// `dst` must be valid for `len` pixel writes.
+#[inline]
+unsafe fn fill_row(dst: *mut u32, len: usize, pixel: u32) {
+    unsafe { fill_row_sse2(dst, len, pixel) }
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn fill_row_sse2(dst: *mut u32, len: usize, pixel: u32) {
+    unsafe {
+        let value = _mm_set1_epi32(pixel as i32);
+        let mut i = 0;
+        while i + 4 <= len {
+            _mm_storeu_si128(dst.add(i) as *mut __m128i, value);
+            i += 4;
+        }
+        for i in i..len {
+            dst.add(i).write(pixel);
+        }
+    }
+}
+
+/// Copy `len` pixels from `src` to `dst`
+///
+/// # Safety
+///
+/// `src` must be valid for `len` reads, `dst` for `len` writes, and the two
+/// must not overlap.
+#[inline]
+unsafe fn copy_row(src: *const u32, dst: *mut u32, len: usize) {
+    unsafe { copy_row_sse2(src, dst, len) }
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn copy_row_sse2(src: *const u32, dst: *mut u32, len: usize) {
+    unsafe {
+        let mut i = 0;
+        while i + 8 <= len {
+            let a = _mm_loadu_si128(src.add(i) as *const __m128i);
+            let b = _mm_loadu_si128(src.add(i + 4) as *const __m128i);
+            _mm_storeu_si128(dst.add(i) as *mut __m128i, a);
+            _mm_storeu_si128(dst.add(i + 4) as *mut __m128i, b);
+            i += 8;
+        }
+        core::ptr::copy_nonoverlapping(src.add(i), dst.add(i), len - i);
+    }
+}
+
+/// Blend `pixel` over `len` pixels from `dst` like `blend_pixel`
+///
+/// # Safety
+///
+/// `dst` must be valid for `len` pixel reads and writes.
+#[inline]
+unsafe fn blend_row(dst: *mut u32, len: usize, pixel: u32, alpha: u32) {
+    unsafe { blend_row_sse2(dst, len, pixel, alpha) }
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn blend_row_sse2(dst: *mut u32, len: usize, pixel: u32, alpha: u32) {
+    unsafe {
+        // Channels widen to 16 bits, where `src * alpha + dst * inv` fits
+        let zero = _mm_setzero_si128();
+        let src = _mm_unpacklo_epi8(_mm_set1_epi32(pixel as i32), zero);
+        let src = _mm_mullo_epi16(src, _mm_set1_epi16(alpha as i16));
+        let inv = _mm_set1_epi16((255 - alpha) as i16);
+        let mask = _mm_set1_epi32(0x00FF_FFFF);
+
+        let mut i = 0;
+        while i + 4 <= len {
+            let ptr = dst.add(i) as *mut __m128i;
+            let below = _mm_loadu_si128(ptr);
+            let low = _mm_mullo_epi16(_mm_unpacklo_epi8(below, zero), inv);
+            let high = _mm_mullo_epi16(_mm_unpackhi_epi8(below, zero), inv);
+            let low = _mm_srli_epi16(_mm_add_epi16(low, src), 8);
+            let high = _mm_srli_epi16(_mm_add_epi16(high, src), 8);
+            _mm_storeu_si128(ptr, _mm_and_si128(_mm_packus_epi16(low, high), mask));
+            i += 4;
+        }
+        for i in i..len {
+            let ptr = dst.add(i);
+            ptr.write(blend_pixel(ptr.read(), pixel, alpha));
+        }
+    }
+}
+
 // ============================================================================
 // Built-in 8x8 Font
 // ============================================================================
@@ -0,0 +1,303 @@
+// Userspace heap allocator
+//
+// A first-fit allocator over an address-ordered free list. Freed blocks
+// are merged with their neighbours, so memory is reused for the lifetime
+// of the program instead of only ever growing.
+//
+// The heap starts with a static arena in the program's .bss. When that
+// runs out it grows by mapping fresh memory: a shared region the program
+// creates and maps only into itself, wherever the kernel finds room, which
+// the kernel backs with zeroed pages like anonymous memory.
+//
+// Programs install it with:
+//
+//     atom_syscall::define_global_allocator!();
+//
+// or `define_global_allocator!(ARENA_BYTES)` for a larger starting arena.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+use core::mem::size_of;
+use core::ptr::{self, null_mut};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::shm::{self, RegionFlags};
+use crate::thread::yield_now;
+
+/// Static arena size used by `define_global_allocator!()`
+pub const DEFAULT_ARENA_SIZE: usize = 64 * 1024;
+
+/// Smallest amount the heap grows by, so small allocations do not each
+/// cost a region
+const GROW_CHUNK: usize = 256 * 1024;
+
+const PAGE_SIZE: usize = 4096;
+
+/// Every block is a multiple of this and aligned to it, so a split never
+/// leaves a remainder too small to hold a free-list node
+const BLOCK_ALIGN: usize = 16;
+
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+const MIN_BLOCK: usize = size_of::<FreeBlock>();
+
+const fn align_up(value: usize, align: usize) -> usize {
+    (value + align - 1) & !(align - 1)
+}
+
+/// Size and alignment a layout takes up in the heap
+fn block_layout(layout: Layout) -> (usize, usize) {
+    let size = align_up(layout.size().max(MIN_BLOCK), BLOCK_ALIGN);
+    (size, layout.align().max(BLOCK_ALIGN))
+}
+
+/// Bytes handed out and bytes owned, for diagnostics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HeapStats {
+    pub total: usize,
+    pub used: usize,
+}
+
+/// Free-list heap over memory added with `add_memory`
+pub struct Heap {
+    head: *mut FreeBlock,
+    stats: HeapStats,
+}
+
+// The heap only hands out memory it owns; moving it between threads is
+// fine, sharing it needs the lock in `GlobalHeap`
+unsafe impl Send for Heap {}
+
+impl Heap {
+    /// A heap with no memory, growing when asked
+    pub const fn empty() -> Self {
+        Self { head: null_mut(), stats: HeapStats { total: 0, used: 0 } }
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.stats
+    }
+
+    /// Give the heap `size` bytes at `start`
+    ///
+    /// # Safety
+    /// The memory must be writable, unused by anything else and stay
+    /// mapped for as long as the heap is used.
+    pub unsafe fn add_memory(&mut self, start: *mut u8, size: usize) {
+        let first = align_up(start as usize, BLOCK_ALIGN);
+        let end = (start as usize + size) & !(BLOCK_ALIGN - 1);
+        if end <= first || end - first < MIN_BLOCK {
+            return;
+        }
+        self.stats.total += end - first;
+        self.insert(first, end - first);
+    }
+
+    /// Allocate a block for `layout`, growing the heap if no free block
+    /// fits; null if the memory could not be found
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = block_layout(layout);
+        if let Some(ptr) = self.take(size, align) {
+            return ptr;
+        }
+        if !self.grow(size + align) {
+            return null_mut();
+        }
+        self.take(size, align).unwrap_or(null_mut())
+    }
+
+    /// Return a block to the heap
+    ///
+    /// # Safety
+    /// `ptr` must have come from `allocate` on this heap with the same
+    /// `layout`, and not have been freed since.
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = block_layout(layout);
+        self.stats.used -= size;
+        self.insert(ptr as usize, size);
+    }
+
+    /// Carve `size` bytes aligned to `align` out of the first free block
+    /// they fit in
+    fn take(&mut self, size: usize, align: usize) -> Option<*mut u8> {
+        let mut link: *mut *mut FreeBlock = &mut self.head;
+        unsafe {
+            while !(*link).is_null() {
+                let block = *link;
+                let addr = block as usize;
+                let block_end = addr + (*block).size;
+
+                let mut start = align_up(addr, align);
+                if start != addr && start - addr < MIN_BLOCK {
+                    start = align_up(addr + MIN_BLOCK, align);
+                }
+                let end = start + size;
+                if end > block_end {
+                    link = &mut (*block).next;
+                    continue;
+                }
+
+                // Replace the block with what is left before and after
+                let mut next = (*block).next;
+                if block_end > end {
+                    let back = end as *mut FreeBlock;
+                    back.write(FreeBlock { size: block_end - end, next });
+                    next = back;
+                }
+                if start > addr {
+                    block.write(FreeBlock { size: start - addr, next });
+                    next = block;
+                }
+                *link = next;
+
+                self.stats.used += size;
+                return Some(start as *mut u8);
+            }
+        }
+        None
+    }
+
+    /// Put a free block in address order, merging it with its neighbours
+    unsafe fn insert(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut FreeBlock = null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < addr {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next });
+        if !next.is_null() && addr + size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+
+        if prev.is_null() {
+            self.head = block;
+        } else if prev as usize + (*prev).size == addr {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+
+    /// Map at least `bytes` more memory; false if the kernel refused
+    fn grow(&mut self, bytes: usize) -> bool {
+        let size = align_up(bytes.max(GROW_CHUNK), PAGE_SIZE);
+        let Ok(region) = shm::create_region(size) else {
+            return false;
+        };
+        match shm::map_anywhere(region, RegionFlags::read_write()) {
+            Ok(base) => {
+                unsafe { self.add_memory(base, size) };
+                true
+            }
+            Err(_) => {
+                let _ = shm::destroy_region(region);
+                false
+            }
+        }
+    }
+}
+
+#[repr(C, align(16))]
+struct Arena<const N: usize>([u8; N]);
+
+/// `Heap` behind a lock, starting from a static arena of `N` bytes; what
+/// `define_global_allocator!` installs
+pub struct GlobalHeap<const N: usize> {
+    locked: AtomicBool,
+    arena: UnsafeCell<Arena<N>>,
+    heap: UnsafeCell<Heap>,
+    started: UnsafeCell<bool>,
+}
+
+// All access to the cells goes through `with_heap`, which holds the lock
+unsafe impl<const N: usize> Sync for GlobalHeap<N> {}
+
+impl<const N: usize> GlobalHeap<N> {
+    pub const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            arena: UnsafeCell::new(Arena([0; N])),
+            heap: UnsafeCell::new(Heap::empty()),
+            started: UnsafeCell::new(false),
+        }
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.with_heap(|heap| heap.stats())
+    }
+
+    fn with_heap<R>(&self, f: impl FnOnce(&mut Heap) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            yield_now();
+        }
+
+        let heap = unsafe { &mut *self.heap.get() };
+        let started = unsafe { &mut *self.started.get() };
+        if !*started {
+            *started = true;
+            let arena = self.arena.get() as *mut u8;
+            unsafe { heap.add_memory(arena, N) };
+        }
+        let result = f(heap);
+
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+}
+
+impl<const N: usize> Default for GlobalHeap<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<const N: usize> GlobalAlloc for GlobalHeap<N> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.with_heap(|heap| heap.allocate(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.with_heap(|heap| heap.deallocate(ptr, layout))
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // Blocks are rounded up, so small growth often fits already
+        let (old_size, _) = block_layout(layout);
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        if block_layout(new_layout).0 == old_size {
+            return ptr;
+        }
+        let new_ptr = self.alloc(new_layout);
+        if !new_ptr.is_null() {
+            ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+            self.dealloc(ptr, layout);
+        }
+        new_ptr
+    }
+}
+
+/// Install the heap as the program's global allocator, starting from a
+/// static arena of `DEFAULT_ARENA_SIZE` bytes or the size given
+#[macro_export]
+macro_rules! define_global_allocator {
+    () => {
+        $crate::define_global_allocator!($crate::heap::DEFAULT_ARENA_SIZE);
+    };
+    ($arena_size:expr) => {
+        #[global_allocator]
+        static GLOBAL_ALLOCATOR: $crate::heap::GlobalHeap<{ $arena_size }> =
+            $crate::heap::GlobalHeap::new();
+    };
+}
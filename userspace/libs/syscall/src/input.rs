@@ -1,7 +1,9 @@
 // Input device syscalls (keyboard, mouse)
 
 use crate::error::EWOULDBLOCK;
+use crate::io::{ps2_write_command, ps2_write_data};
 use crate::raw::{syscall0, numbers::*};
+use crate::thread::yield_now;
 
 // ============================================================================
 // Mouse Input
@@ -10,7 +12,8 @@ use crate::raw::{syscall0, numbers::*};
 /// Poll for next raw mouse byte from PS/2 controller
 ///
 /// Returns Some(byte) if a mouse byte is available, None otherwise.
-/// The userspace driver must assemble these bytes into 3-byte packets.
+/// The userspace driver must assemble these bytes into 3- or 4-byte packets
+/// depending on the negotiated `MouseProtocol`.
 ///
 /// This is a non-blocking call.
 #[inline]
@@ -29,27 +32,112 @@ pub fn mouse_poll_byte() -> Option<u8> {
 pub struct MouseEvent {
     pub dx: i32,
     pub dy: i32,
+    /// Wheel detents, positive = away from the user (scroll up)
+    pub wheel: i32,
     pub left_button: bool,
     pub right_button: bool,
     pub middle_button: bool,
+    /// Buttons 4 and 5 (FiveButton protocol only)
+    pub back_button: bool,
+    pub forward_button: bool,
 }
 
+/// PS/2 mouse packet protocol, selected by the device ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseProtocol {
+    /// Plain PS/2 mouse (ID 0), 3-byte packets
+    Standard,
+    /// IntelliMouse (ID 3), 4-byte packets with an 8-bit wheel delta
+    Wheel,
+    /// IntelliMouse Explorer (ID 4), 4-byte packets with a 4-bit wheel
+    /// delta and buttons 4/5
+    FiveButton,
+}
+
+impl MouseProtocol {
+    pub fn from_device_id(id: u8) -> Self {
+        match id {
+            3 => Self::Wheel,
+            4 => Self::FiveButton,
+            _ => Self::Standard,
+        }
+    }
+
+    pub fn packet_size(self) -> usize {
+        match self {
+            Self::Standard => 3,
+            Self::Wheel | Self::FiveButton => 4,
+        }
+    }
+}
+
+// PS/2 mouse commands used for protocol negotiation
+const MOUSE_SET_SAMPLE_RATE: u8 = 0xF3;
+const MOUSE_GET_ID: u8 = 0xF2;
+const MOUSE_ACK: u8 = 0xFA;
+const CMD_AUX_PREFIX: u8 = 0xD4;
+
+/// Sample-rate "knock" sequences that unlock the wheel and 5-button modes
+const WHEEL_SEQUENCE: [u8; 3] = [200, 100, 80];
+const FIVE_BUTTON_SEQUENCE: [u8; 3] = [200, 200, 80];
+
+/// Sample rate restored after negotiation
+const DEFAULT_SAMPLE_RATE: u8 = 100;
+
 /// PS/2 Mouse driver that processes raw bytes into movement deltas and button states
 pub struct MouseDriver {
-    packet: [u8; 3],
+    packet: [u8; 4],
     cycle: u8,
     prev_left: bool,
+    protocol: MouseProtocol,
 }
 
 impl MouseDriver {
     pub const fn new() -> Self {
         Self {
-            packet: [0; 3],
+            packet: [0; 4],
             cycle: 0,
             prev_left: false,
+            protocol: MouseProtocol::Standard,
         }
     }
 
+    /// Current packet protocol
+    pub fn protocol(&self) -> MouseProtocol {
+        self.protocol
+    }
+
+    /// Switch packet format, e.g. after another process negotiated it
+    pub fn set_protocol(&mut self, protocol: MouseProtocol) {
+        self.protocol = protocol;
+        self.cycle = 0;
+    }
+
+    /// Enable the IntelliMouse wheel and 5-button extensions if supported.
+    ///
+    /// Sends the sample-rate sequences and reads the device ID after each.
+    /// Replies come back through the kernel's mouse buffer, so this must run
+    /// before anything else starts draining it.
+    pub fn enable_extensions(&mut self) -> MouseProtocol {
+        // Discard stale bytes so replies are not mistaken for packet data
+        while mouse_poll_byte().is_some() {}
+
+        let mut protocol = MouseProtocol::Standard;
+
+        if knock(&WHEEL_SEQUENCE) == Some(3) {
+            protocol = MouseProtocol::Wheel;
+            if knock(&FIVE_BUTTON_SEQUENCE) == Some(4) {
+                protocol = MouseProtocol::FiveButton;
+            }
+        }
+
+        let _ = mouse_command(MOUSE_SET_SAMPLE_RATE);
+        let _ = mouse_command(DEFAULT_SAMPLE_RATE);
+
+        self.set_protocol(protocol);
+        protocol
+    }
+
     /// Process available mouse data and return movement delta if a complete packet is ready
     pub fn poll(&mut self) -> Option<(i32, i32)> {
         while let Some(byte) = mouse_poll_byte() {
@@ -78,58 +166,90 @@ impl MouseDriver {
 
     /// Process a single mouse byte
     fn process_byte(&mut self, byte: u8) -> Option<MouseEvent> {
-        match self.cycle {
-            0 => {
-                // First byte must have bit 3 set (always 1 in PS/2)
-                if byte & 0x08 != 0 {
-                    self.packet[0] = byte;
-                    self.cycle = 1;
-                }
-                None
-            }
-            1 => {
-                self.packet[1] = byte;
-                self.cycle = 2;
-                None
-            }
-            2 => {
-                self.packet[2] = byte;
-                self.cycle = 0;
-                
-                // Decode packet
-                let flags = self.packet[0];
-                
-                // Check for overflow
-                if flags & 0xC0 != 0 {
-                    return None;
-                }
-                
-                // Extract deltas with sign extension
-                let mut dx = self.packet[1] as i32;
-                let mut dy = self.packet[2] as i32;
-                
-                if flags & 0x10 != 0 { dx -= 256; }
-                if flags & 0x20 != 0 { dy -= 256; }
-                
-                // Extract button states
-                let left_button = (flags & 0x01) != 0;
-                let right_button = (flags & 0x02) != 0;
-                let middle_button = (flags & 0x04) != 0;
-
-                Some(MouseEvent {
-                    dx,
-                    dy,
-                    left_button,
-                    right_button,
-                    middle_button,
-                })
-            }
-            _ => {
-                self.cycle = 0;
-                None
-            }
+        // First byte must have bit 3 set (always 1 in PS/2)
+        if self.cycle == 0 && byte & 0x08 == 0 {
+            return None;
+        }
+
+        self.packet[self.cycle as usize] = byte;
+        self.cycle += 1;
+
+        if (self.cycle as usize) < self.protocol.packet_size() {
+            return None;
+        }
+        self.cycle = 0;
+
+        // Decode packet
+        let flags = self.packet[0];
+
+        // Check for overflow
+        if flags & 0xC0 != 0 {
+            return None;
+        }
+
+        // Extract deltas with sign extension
+        let mut dx = self.packet[1] as i32;
+        let mut dy = self.packet[2] as i32;
+
+        if flags & 0x10 != 0 { dx -= 256; }
+        if flags & 0x20 != 0 { dy -= 256; }
+
+        // Fourth byte: Z delta (positive = toward the user) and extra buttons
+        let extra = self.packet[3];
+        let (z, back_button, forward_button) = match self.protocol {
+            MouseProtocol::Standard => (0, false, false),
+            MouseProtocol::Wheel => (extra as i8 as i32, false, false),
+            MouseProtocol::FiveButton => (
+                ((extra << 4) as i8 >> 4) as i32,
+                extra & 0x10 != 0,
+                extra & 0x20 != 0,
+            ),
+        };
+
+        // Extract button states
+        let left_button = (flags & 0x01) != 0;
+        let right_button = (flags & 0x02) != 0;
+        let middle_button = (flags & 0x04) != 0;
+
+        Some(MouseEvent {
+            dx,
+            dy,
+            wheel: -z,
+            left_button,
+            right_button,
+            middle_button,
+            back_button,
+            forward_button,
+        })
+    }
+}
+
+/// Send one byte to the mouse and wait for its ACK
+fn mouse_command(byte: u8) -> Option<()> {
+    ps2_write_command(CMD_AUX_PREFIX).ok()?;
+    ps2_write_data(byte).ok()?;
+    (mouse_read_reply()? == MOUSE_ACK).then_some(())
+}
+
+/// Wait for the next reply byte from the mouse
+fn mouse_read_reply() -> Option<u8> {
+    for _ in 0..1000 {
+        if let Some(byte) = mouse_poll_byte() {
+            return Some(byte);
         }
+        yield_now();
+    }
+    None
+}
+
+/// Send a sample-rate sequence and return the resulting device ID
+fn knock(rates: &[u8]) -> Option<u8> {
+    for &rate in rates {
+        mouse_command(MOUSE_SET_SAMPLE_RATE)?;
+        mouse_command(rate)?;
     }
+    mouse_command(MOUSE_GET_ID)?;
+    mouse_read_reply()
 }
 
 /// Legacy function for simple polling (returns delta if complete packet ready)
@@ -2,18 +2,18 @@
 //
 // These syscalls allow userspace drivers to access hardware I/O ports.
 // Access is controlled by the kernel's capability system - only authorized
-// ports can be accessed.
+// ports can be accessed: the PS/2 controller, COM2, and the I/O BARs of a
+// PCI device the caller's manifest entry grants with `DeviceCap`. Devices
+// whose registers are memory-mapped (PCI memory BARs) are reached through
+// `map_mmio` instead, and configuration space through the kernel (`pci`).
 
-use crate::error::{ESUCCESS, EPERM, EINVAL, SyscallError, SyscallResult};
-use crate::raw::{syscall2, numbers::*};
+use crate::error::{ESUCCESS, EPERM, EINVAL, ENOMEM, SyscallError, SyscallResult};
+use crate::raw::{syscall2, syscall3, numbers::*};
 
-/// Read a byte from an I/O port
-///
-/// Returns the byte read, or an error if access is denied.
-/// Only ports authorized by the kernel can be accessed.
-pub fn port_read_u8(port: u16) -> SyscallResult<u8> {
+/// Read from an I/O port with the given access width (1, 2 or 4 bytes)
+fn port_read(port: u16, size: u8) -> SyscallResult<u32> {
     let result = unsafe {
-        syscall2(SYS_IO_PORT_READ, port as u64, 1)
+        syscall2(SYS_IO_PORT_READ, port as u64, size as u64)
     };
 
     if result == EPERM {
@@ -21,30 +21,294 @@ pub fn port_read_u8(port: u16) -> SyscallResult<u8> {
     } else if result == EINVAL {
         Err(SyscallError::InvalidArgument)
     } else {
-        Ok(result as u8)
+        Ok(result as u32)
     }
 }
 
-/// Write a byte to an I/O port
-///
-/// Returns Ok(()) on success, or an error if access is denied.
-/// Only ports authorized by the kernel can be accessed.
-pub fn port_write_u8(port: u16, value: u8) -> SyscallResult<()> {
+/// Write to an I/O port with the given access width (1, 2 or 4 bytes)
+fn port_write(port: u16, value: u32, size: u8) -> SyscallResult<()> {
     let result = unsafe {
-        syscall2(SYS_IO_PORT_WRITE, port as u64, value as u64)
+        syscall3(SYS_IO_PORT_WRITE, port as u64, value as u64, size as u64)
     };
 
     if result == ESUCCESS {
         Ok(())
     } else if result == EPERM {
         Err(SyscallError::PermissionDenied)
-    } else if result == EINVAL {
-        Err(SyscallError::InvalidArgument)
     } else {
         Err(SyscallError::InvalidArgument)
     }
 }
 
+/// Read a byte from an I/O port
+///
+/// Returns the byte read, or an error if access is denied.
+/// Only ports authorized by the kernel can be accessed.
+pub fn port_read_u8(port: u16) -> SyscallResult<u8> {
+    port_read(port, 1).map(|v| v as u8)
+}
+
+/// Read a 16-bit word from an I/O port
+pub fn port_read_u16(port: u16) -> SyscallResult<u16> {
+    port_read(port, 2).map(|v| v as u16)
+}
+
+/// Read a 32-bit doubleword from an I/O port
+pub fn port_read_u32(port: u16) -> SyscallResult<u32> {
+    port_read(port, 4)
+}
+
+/// Write a byte to an I/O port
+///
+/// Returns Ok(()) on success, or an error if access is denied.
+/// Only ports authorized by the kernel can be accessed.
+pub fn port_write_u8(port: u16, value: u8) -> SyscallResult<()> {
+    port_write(port, value as u32, 1)
+}
+
+/// Write a 16-bit word to an I/O port
+pub fn port_write_u16(port: u16, value: u16) -> SyscallResult<()> {
+    port_write(port, value as u32, 2)
+}
+
+/// Write a 32-bit doubleword to an I/O port
+pub fn port_write_u32(port: u16, value: u32) -> SyscallResult<()> {
+    port_write(port, value, 4)
+}
+
+// ============================================================================
+// Memory-Mapped I/O
+// ============================================================================
+
+/// Map `size` bytes of device registers at physical address `phys`
+///
+/// Returns the address to access them through, uncached. The range must
+/// lie inside a memory BAR of a PCI device the caller's manifest entry
+/// grants with `DeviceCap`; anything else, and anything overlapping memory
+/// the firmware describes or devices the kernel drives itself, is refused.
+pub fn map_mmio(phys: u64, size: usize) -> SyscallResult<usize> {
+    let result = unsafe { syscall2(SYS_MAP_MMIO, phys, size as u64) };
+
+    match result {
+        EPERM => Err(SyscallError::PermissionDenied),
+        ENOMEM => Err(SyscallError::OutOfMemory),
+        v if v >= u64::MAX - 10 => Err(SyscallError::InvalidArgument),
+        virt => Ok(virt as usize),
+    }
+}
+
+// ============================================================================
+// PCI Configuration Space (Mechanism #1)
+// ============================================================================
+
+/// PCI configuration space access, made by the kernel on the caller's
+/// behalf
+///
+/// Anyone may read the registers that identify a function (vendor, device,
+/// class, header type), so devices can be found; everything else needs a
+/// `DeviceCap` for the function and fails with `PermissionDenied`.
+pub mod pci {
+    use crate::error::{ESUCCESS, EPERM, SyscallError, SyscallResult};
+    use crate::raw::{syscall2, syscall3, numbers::*};
+
+    /// Common configuration register offsets
+    pub const REG_VENDOR_DEVICE: u8 = 0x00;
+    pub const REG_COMMAND: u8 = 0x04;
+    pub const REG_CLASS: u8 = 0x08;
+    pub const REG_BAR0: u8 = 0x10;
+    pub const REG_CAPABILITIES: u8 = 0x34;
+    pub const REG_INTERRUPT: u8 = 0x3C;
+
+    /// Status register bit (upper half of REG_COMMAND): capability list
+    /// present
+    pub const STATUS_CAPABILITIES: u32 = 1 << 20;
+
+    /// Capability ID of vendor-specific capabilities
+    pub const CAP_VENDOR: u8 = 0x09;
+
+    /// Command register bits
+    pub const COMMAND_IO_SPACE: u16 = 1 << 0;
+    pub const COMMAND_MEMORY_SPACE: u16 = 1 << 1;
+    pub const COMMAND_BUS_MASTER: u16 = 1 << 2;
+
+    /// Location of a PCI function
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct PciAddress {
+        pub bus: u8,
+        pub device: u8,
+        pub function: u8,
+    }
+
+    impl PciAddress {
+        pub const fn new(bus: u8, device: u8, function: u8) -> Self {
+            Self { bus, device, function }
+        }
+
+        /// Bus, device and function packed the way the kernel names them
+        pub const fn bdf(&self) -> u16 {
+            ((self.bus as u16) << 8)
+                | ((self.device as u16 & 0x1F) << 3)
+                | (self.function as u16 & 0x07)
+        }
+
+        /// Read a 32-bit configuration register
+        pub fn read_u32(&self, offset: u8) -> SyscallResult<u32> {
+            let result = unsafe {
+                syscall2(SYS_PCI_CONFIG_READ, self.bdf() as u64, (offset & 0xFC) as u64)
+            };
+
+            match result {
+                EPERM => Err(SyscallError::PermissionDenied),
+                v if v >= u64::MAX - 10 => Err(SyscallError::InvalidArgument),
+                value => Ok(value as u32),
+            }
+        }
+
+        /// Write a 32-bit configuration register
+        ///
+        /// The kernel refuses BARs and the other registers that decide
+        /// where the function decodes, which stay as they were at claim time
+        pub fn write_u32(&self, offset: u8, value: u32) -> SyscallResult<()> {
+            let result = unsafe {
+                syscall3(
+                    SYS_PCI_CONFIG_WRITE,
+                    self.bdf() as u64,
+                    (offset & 0xFC) as u64,
+                    value as u64,
+                )
+            };
+
+            match result {
+                ESUCCESS => Ok(()),
+                EPERM => Err(SyscallError::PermissionDenied),
+                _ => Err(SyscallError::InvalidArgument),
+            }
+        }
+
+        /// Read one byte of configuration space
+        pub fn read_u8(&self, offset: u8) -> SyscallResult<u8> {
+            let value = self.read_u32(offset & !3)?;
+            Ok((value >> ((offset & 3) * 8)) as u8)
+        }
+
+        /// Vendor and device ID, or None if no function is present
+        pub fn ids(&self) -> Option<(u16, u16)> {
+            let value = self.read_u32(REG_VENDOR_DEVICE).ok()?;
+            let vendor = value as u16;
+            if vendor == 0xFFFF {
+                None
+            } else {
+                Some((vendor, (value >> 16) as u16))
+            }
+        }
+
+        /// (class, subclass, prog_if)
+        pub fn class(&self) -> SyscallResult<(u8, u8, u8)> {
+            let value = self.read_u32(REG_CLASS)?;
+            Ok(((value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8))
+        }
+
+        /// Raw value of base address register `index` (0-5)
+        pub fn bar(&self, index: u8) -> SyscallResult<u32> {
+            self.read_u32(REG_BAR0 + index * 4)
+        }
+
+        /// Physical address of memory BAR `index`, joining the next
+        /// register for 64-bit BARs; None for I/O BARs and unset ones
+        pub fn bar_address(&self, index: u8) -> SyscallResult<Option<u64>> {
+            let low = self.bar(index)?;
+            if low & 1 != 0 {
+                return Ok(None);
+            }
+
+            let mut address = (low & !0xF) as u64;
+            if (low >> 1) & 0x3 == 0x2 && index < 5 {
+                address |= (self.bar(index + 1)? as u64) << 32;
+            }
+            Ok(Some(address).filter(|&address| address != 0))
+        }
+
+        /// (ID, offset) of each entry of the capability list
+        pub fn capabilities(&self) -> Capabilities {
+            let status = self.read_u32(REG_COMMAND).unwrap_or(0);
+            let first = if status & STATUS_CAPABILITIES != 0 {
+                self.read_u8(REG_CAPABILITIES).unwrap_or(0)
+            } else {
+                0
+            };
+            Capabilities {
+                addr: *self,
+                next: first & !3,
+                remaining: MAX_CAPABILITIES,
+            }
+        }
+
+        /// Set bits in the command register
+        pub fn enable(&self, bits: u16) -> SyscallResult<()> {
+            let value = self.read_u32(REG_COMMAND)?;
+            self.write_u32(REG_COMMAND, value | bits as u32)
+        }
+    }
+
+    /// Capabilities that fit in the 256-byte configuration space; the
+    /// bound also stops a list that loops
+    const MAX_CAPABILITIES: u8 = 48;
+
+    /// Iterator over a function's capability list, see
+    /// `PciAddress::capabilities`
+    pub struct Capabilities {
+        addr: PciAddress,
+        next: u8,
+        remaining: u8,
+    }
+
+    impl Iterator for Capabilities {
+        type Item = (u8, u8);
+
+        fn next(&mut self) -> Option<(u8, u8)> {
+            if self.next == 0 || self.remaining == 0 {
+                return None;
+            }
+            let offset = self.next;
+            let id = self.addr.read_u8(offset).ok()?;
+            self.next = self.addr.read_u8(offset + 1).ok()? & !3;
+            self.remaining -= 1;
+            Some((id, offset))
+        }
+    }
+
+    /// Find the first function matching a class/subclass pair
+    pub fn find_class(class: u8, subclass: u8) -> Option<PciAddress> {
+        find(|addr, _| matches!(addr.class(), Ok((c, s, _)) if c == class && s == subclass))
+    }
+
+    /// Find the first function with the given vendor and device IDs
+    pub fn find_device(vendor: u16, device: u16) -> Option<PciAddress> {
+        find(|_, ids| ids == (vendor, device))
+    }
+
+    /// Scan every bus for the first present function `matches` accepts
+    fn find(mut matches: impl FnMut(&PciAddress, (u16, u16)) -> bool) -> Option<PciAddress> {
+        for bus in 0..=255u8 {
+            for device in 0..32u8 {
+                for function in 0..8u8 {
+                    let addr = PciAddress::new(bus, device, function);
+                    let Some(ids) = addr.ids() else {
+                        if function == 0 {
+                            break;
+                        }
+                        continue;
+                    };
+                    if matches(&addr, ids) {
+                        return Some(addr);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
 // ============================================================================
 // PS/2 Controller Helpers
 // ============================================================================
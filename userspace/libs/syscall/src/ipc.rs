@@ -1,6 +1,6 @@
 // IPC (Inter-Process Communication) syscalls
 
-use crate::error::{ESUCCESS, EPERM, EINVAL, EWOULDBLOCK, SyscallError, SyscallResult};
+use crate::error::{ESUCCESS, EBUSY, EPERM, EINVAL, EWOULDBLOCK, SyscallError, SyscallResult};
 use crate::raw::{syscall0, syscall1, syscall2, syscall3, numbers::*};
 
 /// Port identifier
@@ -30,6 +30,135 @@ pub fn close_port(port: PortId) -> SyscallResult<()> {
     }
 }
 
+/// Get a `PortDied` message on `notify` when `port` is closed or its owner
+/// exits
+///
+/// `notify` must be a port the caller owns.
+pub fn watch_port(port: PortId, notify: PortId) -> SyscallResult<()> {
+    let result = unsafe { syscall2(SYS_IPC_WATCH_PORT, port, notify) };
+
+    if result == ESUCCESS {
+        Ok(())
+    } else if result == EPERM {
+        Err(SyscallError::PermissionDenied)
+    } else {
+        Err(SyscallError::InvalidArgument)
+    }
+}
+
+/// Longest port name, in bytes
+pub const MAX_PORT_NAME: usize = 64;
+
+/// Publish `port` under `name`, so others can find it with `lookup_name`
+///
+/// `port` must be one the caller owns. Fails with `Busy` if the name
+/// belongs to another port; the name is freed when the port is closed.
+pub fn register_name(name: &str, port: PortId) -> SyscallResult<()> {
+    if name.is_empty() || name.len() > MAX_PORT_NAME {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let result = unsafe {
+        syscall3(SYS_IPC_REGISTER_NAME, name.as_ptr() as u64, name.len() as u64, port)
+    };
+
+    match result {
+        ESUCCESS => Ok(()),
+        EPERM => Err(SyscallError::PermissionDenied),
+        EBUSY => Err(SyscallError::Busy),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+/// The port published under `name`, if there is one
+pub fn lookup_name(name: &str) -> SyscallResult<Option<PortId>> {
+    if name.is_empty() || name.len() > MAX_PORT_NAME {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let result = unsafe { syscall2(SYS_IPC_LOOKUP_NAME, name.as_ptr() as u64, name.len() as u64) };
+
+    match result {
+        0 => Ok(None),
+        EINVAL => Err(SyscallError::InvalidArgument),
+        port => Ok(Some(port)),
+    }
+}
+
+/// Traffic counters for a port
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PortStats {
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub min_latency_ms: u64,
+    pub max_latency_ms: u64,
+    pub avg_latency_ms: u64,
+    pub messages_per_second: u64,
+    /// Messages waiting to be received
+    pub queued: u64,
+}
+
+/// Get the traffic counters of a port
+pub fn port_stats(port: PortId) -> SyscallResult<PortStats> {
+    let mut stats = PortStats::default();
+    let result = unsafe {
+        syscall2(SYS_IPC_PORT_STATS, port, &mut stats as *mut PortStats as u64)
+    };
+
+    if result == ESUCCESS {
+        Ok(stats)
+    } else {
+        Err(SyscallError::InvalidArgument)
+    }
+}
+
+/// A message sent or received, as the kernel's IPC trace recorded it
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceEvent {
+    /// Milliseconds since boot
+    pub timestamp_ms: u64,
+    /// 0 = send, 1 = receive
+    pub kind: u64,
+    pub port: PortId,
+    /// Thread that sent the message
+    pub sender: u64,
+    /// Thread that received it, or 0
+    pub receiver: u64,
+    pub size: u64,
+}
+
+impl TraceEvent {
+    pub const EMPTY: Self = Self {
+        timestamp_ms: 0,
+        kind: 0,
+        port: 0,
+        sender: 0,
+        receiver: 0,
+        size: 0,
+    };
+
+    pub fn is_send(&self) -> bool {
+        self.kind == 0
+    }
+}
+
+/// Read the newest events of the IPC trace, oldest first
+///
+/// Returns the number of events written to `events`.
+pub fn read_trace(events: &mut [TraceEvent]) -> SyscallResult<usize> {
+    let result = unsafe {
+        syscall2(SYS_IPC_TRACE_READ, events.as_mut_ptr() as u64, events.len() as u64)
+    };
+
+    if result >= u64::MAX - 10 {
+        Err(SyscallError::InvalidArgument)
+    } else {
+        Ok((result as usize).min(events.len()))
+    }
+}
+
 /// Send a message to a port
 ///
 /// Blocks until the message is delivered.
@@ -84,6 +213,39 @@ pub fn try_recv(port: PortId, buffer: &mut [u8]) -> SyscallResult<Option<usize>>
     }
 }
 
+/// Kinds of kernel capability, as `sender_holds` names them
+#[repr(u64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapKind {
+    Thread = 0,
+    MemoryRegion = 1,
+    IpcPort = 2,
+    Irq = 3,
+    Device = 4,
+    DmaBuffer = 5,
+    SharedMemory = 6,
+    /// Reading what other programs' windows show (`ScreenshotCap`)
+    Screenshot = 7,
+    /// Driving the screen, such as switching its mode (`FrameBufferCap`)
+    Framebuffer = 8,
+    /// Changing kernel log levels, tracing and profiling (`DebugCap`)
+    Debug = 9,
+}
+
+/// Whether the thread that sent the message last taken with `recv` or
+/// `try_recv` holds a capability of `kind`
+///
+/// Fails if nothing was received since the last batch receive.
+pub fn sender_holds(kind: CapKind) -> SyscallResult<bool> {
+    let result = unsafe { syscall1(SYS_IPC_SENDER_HOLDS, kind as u64) };
+
+    match result {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
 /// Send a message asynchronously
 ///
 /// Returns immediately without waiting for delivery.
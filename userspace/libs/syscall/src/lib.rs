@@ -18,8 +18,17 @@ pub mod input;
 pub mod graphics;
 pub mod io;
 pub mod ipc;
+pub mod shm;
+pub mod heap;
+pub mod panic;
+pub mod dma;
 pub mod debug;
+pub mod process;
+pub mod system;
+pub mod env;
 pub mod error;
+pub mod trace;
+pub mod profile;
 
 // Re-export common types at crate root
 pub use error::{SyscallError, SyscallResult};
@@ -0,0 +1,182 @@
+// Panic reporting
+//
+// A shared panic handler for userspace programs. The message and location
+// are formatted into a stack buffer, since the heap may be what failed,
+// and logged. With the `backtrace` feature the frame-pointer chain is
+// walked for return addresses too; that needs the program built with
+// `-C force-frame-pointers=yes`, or the chain ends after a frame or two.
+// Return addresses are logged with the function they are in when the
+// program was converted with `elf2atxf --symbols`.
+//
+// A crash report then goes to the collector registered under
+// `CRASH_COLLECTOR`, if one is running, and the program exits with
+// `PANIC_EXIT_CODE`.
+//
+// Programs install it with:
+//
+//     atom_syscall::define_panic_handler!("Desktop");
+//
+// # Crash report layout
+//
+// ```text
+// offset 0: magic (u32, CRASH_REPORT_MAGIC)
+// offset 4: number of frames (u32)
+// offset 8: return addresses (u64 each), innermost first
+// then:     the logged panic line (UTF-8)
+// ```
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::debug::{log, symbolize};
+use crate::ipc::{lookup_name, send_async};
+use crate::thread::exit;
+
+/// Name the crash collector registers its port under
+pub const CRASH_COLLECTOR: &str = "crash";
+
+/// Exit code of a program that panicked
+pub const PANIC_EXIT_CODE: u64 = 0xFF;
+
+/// First bytes of a crash report ("CRSH")
+pub const CRASH_REPORT_MAGIC: u32 = 0x4853_5243;
+
+/// Most return addresses a report carries
+pub const MAX_FRAMES: usize = 16;
+
+/// Longest panic line; longer messages are cut short
+const MESSAGE_SIZE: usize = 512;
+
+/// Set by the first panic, so a panic while reporting does not recurse
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// `fmt::Write` into a fixed buffer, dropping what does not fit
+pub struct StackWriter<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> StackWriter<N> {
+    pub const fn new() -> Self {
+        Self { buffer: [0; N], len: 0 }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only whole characters are ever copied in
+        core::str::from_utf8(&self.buffer[..self.len]).unwrap_or("")
+    }
+}
+
+impl<const N: usize> Default for StackWriter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Write for StackWriter<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut take = s.len().min(N - self.len);
+        while !s.is_char_boundary(take) {
+            take -= 1;
+        }
+        self.buffer[self.len..self.len + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+        Ok(())
+    }
+}
+
+/// Log a panic of `program`, report it to the crash collector and exit
+pub fn report(program: &str, info: &PanicInfo) -> ! {
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        log(program);
+        log("  panicked while reporting a panic");
+        exit(PANIC_EXIT_CODE);
+    }
+
+    let mut text = StackWriter::<MESSAGE_SIZE>::new();
+    let _ = write!(text, "{}: panicked", program);
+    if let Some(location) = info.location() {
+        let _ = write!(text, " at {}:{}:{}", location.file(), location.line(), location.column());
+    }
+    let _ = write!(text, ": {}", info.message());
+    log(text.as_str());
+
+    let mut frames = [0u64; MAX_FRAMES];
+    let count = backtrace(&mut frames);
+    for (i, frame) in frames[..count].iter().enumerate() {
+        let mut line = StackWriter::<160>::new();
+        let _ = write!(line, "  #{:<2} 0x{:016x}", i, frame);
+        let mut name = [0u8; 120];
+        if let Some((function, offset)) = symbolize(*frame, &mut name) {
+            let _ = write!(line, " {}+0x{:x}", function, offset);
+        }
+        log(line.as_str());
+    }
+
+    send_report(&frames[..count], text.as_str());
+    exit(PANIC_EXIT_CODE)
+}
+
+/// Send a crash report to the collector, if one is registered
+fn send_report(frames: &[u64], text: &str) {
+    let Ok(Some(collector)) = lookup_name(CRASH_COLLECTOR) else {
+        return;
+    };
+
+    let mut report = [0u8; 8 + MAX_FRAMES * 8 + MESSAGE_SIZE];
+    report[0..4].copy_from_slice(&CRASH_REPORT_MAGIC.to_le_bytes());
+    report[4..8].copy_from_slice(&(frames.len() as u32).to_le_bytes());
+    let mut len = 8;
+    for frame in frames {
+        report[len..len + 8].copy_from_slice(&frame.to_le_bytes());
+        len += 8;
+    }
+    report[len..len + text.len()].copy_from_slice(text.as_bytes());
+    len += text.len();
+
+    let _ = send_async(collector, &report[..len]);
+}
+
+/// Return addresses up the frame-pointer chain, innermost first
+#[cfg(feature = "backtrace")]
+fn backtrace(frames: &mut [u64]) -> usize {
+    /// Largest gap between frames still taken for a real frame
+    const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+    let mut fp: usize;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) fp, options(nomem, nostack)) };
+
+    let mut count = 0;
+    while count < frames.len() && fp != 0 && fp & 7 == 0 {
+        let (next, ret) = unsafe { (*(fp as *const usize), *((fp + 8) as *const usize)) };
+        if ret == 0 {
+            break;
+        }
+        frames[count] = ret as u64;
+        count += 1;
+        // The chain runs up the stack; anything else is not a frame
+        if next <= fp || next - fp > MAX_FRAME_SIZE {
+            break;
+        }
+        fp = next;
+    }
+    count
+}
+
+#[cfg(not(feature = "backtrace"))]
+fn backtrace(_frames: &mut [u64]) -> usize {
+    0
+}
+
+/// Define the program's `#[panic_handler]` as `panic::report`, naming the
+/// program in the log as `$program`
+#[macro_export]
+macro_rules! define_panic_handler {
+    ($program:expr) => {
+        #[panic_handler]
+        fn panic(info: &core::panic::PanicInfo) -> ! {
+            $crate::panic::report($program, info)
+        }
+    };
+}
@@ -0,0 +1,83 @@
+// Process management syscalls
+
+use crate::error::{
+    EINVAL, ENOMEM, EPERM, ESUCCESS, ETIMEDOUT, EWOULDBLOCK, SyscallError, SyscallResult,
+};
+use crate::ipc::PortId;
+use crate::raw::{syscall0, syscall1, syscall2, syscall3, numbers::*};
+
+/// Thread identifier of a started program
+pub type ProcessId = u64;
+
+/// Exit code `wait` reports for a program stopped with `kill`
+pub const EXIT_KILLED: u64 = 137;
+
+/// Start the program whose binary is at `path`
+///
+/// The program must be declared in the boot manifest; it runs with the
+/// capabilities the manifest grants it. Returns the new program's thread ID.
+pub fn spawn(path: &str) -> SyscallResult<ProcessId> {
+    spawn_raw(path, 0)
+}
+
+/// Start the program whose binary is at `path`, with its output sent to
+/// `output`
+///
+/// `output` must be a port the caller owns; the program finds it with
+/// `output_port`.
+pub fn spawn_with_output(path: &str, output: PortId) -> SyscallResult<ProcessId> {
+    spawn_raw(path, output)
+}
+
+fn spawn_raw(path: &str, output: PortId) -> SyscallResult<ProcessId> {
+    let (ptr, len) = (path.as_ptr() as u64, path.len() as u64);
+    let result = unsafe { syscall3(SYS_PROC_SPAWN, ptr, len, output) };
+
+    match result {
+        EINVAL => Err(SyscallError::InvalidArgument),
+        ENOMEM => Err(SyscallError::OutOfMemory),
+        EPERM => Err(SyscallError::PermissionDenied),
+        tid => Ok(tid),
+    }
+}
+
+/// Wait up to `timeout_ms` for a program the caller started to exit
+///
+/// Returns its exit code; 0 polls without waiting, `u64::MAX` waits for
+/// as long as it runs. Once the exit code has been returned the program
+/// is forgotten, and waiting for it again fails.
+pub fn wait(pid: ProcessId, timeout_ms: u64) -> SyscallResult<u64> {
+    let result = unsafe { syscall2(SYS_PROC_WAIT, pid, timeout_ms) };
+
+    match result {
+        EINVAL => Err(SyscallError::InvalidArgument),
+        EPERM => Err(SyscallError::PermissionDenied),
+        ETIMEDOUT => Err(SyscallError::TimedOut),
+        EWOULDBLOCK => Err(SyscallError::WouldBlock),
+        code => Ok(code),
+    }
+}
+
+/// Stop a program the caller started; `wait` then reports `EXIT_KILLED`
+pub fn kill(pid: ProcessId) -> SyscallResult<()> {
+    let result = unsafe { syscall1(SYS_PROC_KILL, pid) };
+
+    match result {
+        ESUCCESS => Ok(()),
+        EPERM => Err(SyscallError::PermissionDenied),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+/// Port this program's output goes to, if whoever started it gave one
+///
+/// Output is sent as `ProgramOutput` and `ProgramError` messages.
+pub fn output_port() -> Option<PortId> {
+    let result = unsafe { syscall0(SYS_PROC_OUTPUT_PORT) };
+
+    if result == EINVAL {
+        None
+    } else {
+        Some(result)
+    }
+}
@@ -0,0 +1,93 @@
+// Sampling profiler syscalls
+//
+// While a profile runs, the kernel samples the interrupted instruction
+// pointer on every timer tick (100 Hz) into one ring per CPU. These calls
+// start and stop a profile and read the samples back, with the function
+// around each address already named where the program has symbols.
+
+use crate::error::{EBUSY, EINVAL, EPERM, SyscallError, SyscallResult};
+use crate::raw::{syscall0, syscall1, syscall4, numbers::*};
+
+/// Longest function name a sample carries
+pub const MAX_NAME: usize = 40;
+
+/// One profiler sample
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub rip: u64,
+    /// Thread that was running, or 0
+    pub thread: u64,
+    /// Offset of `rip` into `function()`, or u64::MAX if it was not named
+    pub offset: u64,
+    /// 1 if the CPU was running user code
+    pub user: u32,
+    name_len: u32,
+    name: [u8; MAX_NAME],
+}
+
+impl Sample {
+    pub const EMPTY: Self = Self {
+        rip: 0,
+        thread: 0,
+        offset: u64::MAX,
+        user: 0,
+        name_len: 0,
+        name: [0; MAX_NAME],
+    };
+
+    /// Function the sample hit, if its program has symbols
+    pub fn function(&self) -> Option<&str> {
+        if self.offset == u64::MAX {
+            return None;
+        }
+        let len = (self.name_len as usize).min(MAX_NAME);
+        core::str::from_utf8(&self.name[..len]).ok()
+    }
+
+    pub fn is_user(&self) -> bool {
+        self.user != 0
+    }
+}
+
+/// Start a profile of `thread`, or of every thread if 0, dropping the
+/// samples of the last profile
+///
+/// Needs `DebugCap`; without it this fails with `PermissionDenied`.
+pub fn start(thread: u64) -> SyscallResult<()> {
+    match unsafe { syscall1(SYS_PROFILE_START, thread) } {
+        EBUSY => Err(SyscallError::Busy),
+        EPERM => Err(SyscallError::PermissionDenied),
+        _ => Ok(()),
+    }
+}
+
+/// Stop the running profile; returns how many samples it took, including
+/// any the rings have since overwritten
+pub fn stop() -> u64 {
+    unsafe { syscall0(SYS_PROFILE_STOP) }
+}
+
+/// Read samples of `cpu` into `samples`, oldest first
+///
+/// `cursor` works as in `trace::read`: start at 0 for the oldest sample
+/// held and keep passing it back. Returns the number of samples read, 0
+/// once caught up; the kernel copies at most 64 per call. Fails with
+/// `InvalidArgument` past the last CPU.
+pub fn read(cpu: usize, samples: &mut [Sample], cursor: &mut u64) -> SyscallResult<usize> {
+    let result = unsafe {
+        syscall4(
+            SYS_PROFILE_READ,
+            samples.as_mut_ptr() as u64,
+            samples.len() as u64,
+            cursor as *mut u64 as u64,
+            cpu as u64,
+        )
+    };
+
+    if result == EINVAL {
+        Err(SyscallError::InvalidArgument)
+    } else {
+        Ok(result as usize)
+    }
+}
@@ -52,6 +52,37 @@ pub mod numbers {
     pub const SYS_UNREGISTER_IRQ_HANDLER: u64 = 42;
     pub const SYS_IPC_WAIT_ANY: u64 = 43;
     pub const SYS_GET_IRQ_COUNT: u64 = 44;
+    pub const SYS_DMA_ALLOC: u64 = 45;
+    pub const SYS_KLOG_READ: u64 = 46;
+    pub const SYS_KLOG_SET_LEVEL: u64 = 47;
+    pub const SYS_PROC_SPAWN: u64 = 48;
+    pub const SYS_GET_TIME: u64 = 49;
+    pub const SYS_IPC_WATCH_PORT: u64 = 50;
+    pub const SYS_PROC_WAIT: u64 = 51;
+    pub const SYS_PROC_KILL: u64 = 52;
+    pub const SYS_PROC_OUTPUT_PORT: u64 = 53;
+    pub const SYS_MEM_STATS: u64 = 54;
+    pub const SYS_SCHED_STATS: u64 = 55;
+    pub const SYS_THREAD_LIST: u64 = 56;
+    pub const SYS_CAP_AUDIT_READ: u64 = 57;
+    pub const SYS_IPC_REGISTER_NAME: u64 = 58;
+    pub const SYS_IPC_LOOKUP_NAME: u64 = 59;
+    pub const SYS_THREAD_JOIN: u64 = 60;
+    pub const SYS_PROC_ARGS: u64 = 61;
+    pub const SYS_BOOT_ARGS: u64 = 62;
+    pub const SYS_SYMBOLIZE: u64 = 63;
+    pub const SYS_TRACE_READ: u64 = 64;
+    pub const SYS_TRACE_CONTROL: u64 = 65;
+    pub const SYS_PROFILE_START: u64 = 66;
+    pub const SYS_PROFILE_STOP: u64 = 67;
+    pub const SYS_PROFILE_READ: u64 = 68;
+    pub const SYS_VIDEO_MODES: u64 = 69;
+    pub const SYS_SET_VIDEO_MODE: u64 = 70;
+    pub const SYS_MAP_MMIO: u64 = 71;
+    pub const SYS_IPC_SENDER_HOLDS: u64 = 72;
+    pub const SYS_PCI_CONFIG_READ: u64 = 73;
+    pub const SYS_PCI_CONFIG_WRITE: u64 = 74;
+    pub const SYS_DMA_FREE: u64 = 75;
 }
 
 /// Raw syscall with no arguments
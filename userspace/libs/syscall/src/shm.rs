@@ -0,0 +1,100 @@
+// Shared memory region syscalls
+//
+// Shared regions are page-aligned blocks of physical memory that several
+// threads can map at the same time. They are the zero-copy transport used by
+// audio streams, window surfaces and other bulk-data channels.
+//
+// Lifecycle: create -> map (by each participant) -> unmap -> destroy (owner).
+//
+// All programs share one set of page tables, so an address picked by hand
+// may already be in use by another program. `map_anywhere` lets the kernel
+// choose a window nothing else has.
+
+use core::ptr::null_mut;
+
+use crate::error::{ESUCCESS, EBUSY, ENOMEM, EPERM, SyscallError, SyscallResult};
+use crate::raw::{syscall1, syscall3, numbers::*};
+
+/// Shared region identifier (assigned by the kernel)
+pub type RegionId = u64;
+
+/// Mapping permissions for a shared region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionFlags(u64);
+
+impl RegionFlags {
+    const READ: u64 = 0x1;
+    const WRITE: u64 = 0x2;
+    const EXECUTE: u64 = 0x4;
+    // The kernel guesses between ELF-style and native bit order when only the
+    // low three bits are set; this marker pins the native layout.
+    const NATIVE: u64 = 0x8;
+
+    pub const fn read_only() -> Self {
+        Self(Self::NATIVE | Self::READ)
+    }
+
+    pub const fn read_write() -> Self {
+        Self(Self::NATIVE | Self::READ | Self::WRITE)
+    }
+
+    pub const fn read_exec() -> Self {
+        Self(Self::NATIVE | Self::READ | Self::EXECUTE)
+    }
+
+    pub const fn raw(&self) -> u64 {
+        self.0
+    }
+}
+
+fn check(result: u64) -> SyscallResult<()> {
+    match result {
+        ESUCCESS => Ok(()),
+        EPERM => Err(SyscallError::PermissionDenied),
+        EBUSY => Err(SyscallError::Busy),
+        ENOMEM => Err(SyscallError::OutOfMemory),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+/// Create a shared region of at least `size` bytes
+pub fn create_region(size: usize) -> SyscallResult<RegionId> {
+    let result = unsafe { syscall1(SYS_SHARED_REGION_CREATE, size as u64) };
+
+    if result == ENOMEM {
+        Err(SyscallError::OutOfMemory)
+    } else if result == 0 || result >= u64::MAX - 10 {
+        Err(SyscallError::InvalidArgument)
+    } else {
+        Ok(result)
+    }
+}
+
+/// Map a region at a page-aligned virtual address chosen by the caller
+pub fn map_region(region: RegionId, virt_addr: usize, flags: RegionFlags) -> SyscallResult<*mut u8> {
+    let result = unsafe {
+        syscall3(SYS_SHARED_REGION_MAP, region, virt_addr as u64, flags.raw())
+    };
+
+    match result {
+        v if v >= u64::MAX - 10 => check(v).map(|_| null_mut()),
+        mapped => Ok(mapped as *mut u8),
+    }
+}
+
+/// Map a region wherever the kernel finds room, returning the address
+///
+/// The window is the caller's until it unmaps the region.
+pub fn map_anywhere(region: RegionId, flags: RegionFlags) -> SyscallResult<*mut u8> {
+    map_region(region, 0, flags)
+}
+
+/// Unmap a region from the calling thread
+pub fn unmap_region(region: RegionId) -> SyscallResult<()> {
+    check(unsafe { syscall1(SYS_SHARED_REGION_UNMAP, region) })
+}
+
+/// Destroy a region (owner only, once no thread has it mapped)
+pub fn destroy_region(region: RegionId) -> SyscallResult<()> {
+    check(unsafe { syscall1(SYS_SHARED_REGION_DESTROY, region) })
+}
@@ -0,0 +1,172 @@
+// System statistics syscalls
+
+use crate::error::{ESUCCESS, SyscallError, SyscallResult};
+use crate::raw::{syscall1, syscall2, numbers::*};
+
+/// Physical memory and kernel heap usage
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemStats {
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+    pub heap_bytes: u64,
+    pub heap_used_bytes: u64,
+}
+
+impl MemStats {
+    pub fn used_bytes(&self) -> u64 {
+        self.total_bytes.saturating_sub(self.free_bytes)
+    }
+}
+
+/// Scheduler counters since boot and how many threads are in each state
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SchedStats {
+    /// Timer ticks since boot
+    pub ticks: u64,
+    /// Ticks no thread had work to do
+    pub idle_ticks: u64,
+    pub context_switches: u64,
+    pub threads: u64,
+    pub running: u64,
+    pub ready: u64,
+    pub blocked: u64,
+    pub exited: u64,
+}
+
+/// Longest thread name `thread_list` reports; longer names are cut
+pub const THREAD_NAME_LEN: usize = 24;
+
+/// A thread as `thread_list` reports it
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThreadInfo {
+    pub id: u64,
+    /// 0 = running, 1 = ready, 2 = blocked, 3 = exited
+    pub state: u32,
+    /// 0 = idle .. 3 = high
+    pub priority: u32,
+    /// Timer ticks the thread has run for
+    pub ticks: u64,
+    name: [u8; THREAD_NAME_LEN],
+    name_len: u64,
+}
+
+impl ThreadInfo {
+    pub fn name(&self) -> &str {
+        let len = (self.name_len as usize).min(THREAD_NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+
+    pub fn state_name(&self) -> &'static str {
+        match self.state {
+            0 => "running",
+            1 => "ready",
+            2 => "blocked",
+            3 => "exited",
+            _ => "unknown",
+        }
+    }
+
+    pub fn priority_name(&self) -> &'static str {
+        match self.priority {
+            0 => "idle",
+            1 => "low",
+            2 => "normal",
+            3 => "high",
+            _ => "unknown",
+        }
+    }
+}
+
+/// A capability event from the kernel's audit log
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CapAuditEntry {
+    /// Timer ticks since boot
+    pub timestamp: u64,
+    /// 0 = create, 1 = derive, 2 = transfer, 3 = revoke
+    pub event: u64,
+    /// Thread that acted on the capability
+    pub thread: u64,
+    pub cap_handle: u64,
+    /// Capability it was derived from, or 0
+    pub parent: u64,
+    /// Thread it was transferred to, or 0
+    pub target: u64,
+}
+
+impl CapAuditEntry {
+    pub const EMPTY: Self = Self {
+        timestamp: 0,
+        event: 0,
+        thread: 0,
+        cap_handle: 0,
+        parent: 0,
+        target: 0,
+    };
+
+    pub fn event_name(&self) -> &'static str {
+        match self.event {
+            0 => "create",
+            1 => "derive",
+            2 => "transfer",
+            3 => "revoke",
+            _ => "unknown",
+        }
+    }
+}
+
+/// Get physical memory and kernel heap usage
+pub fn mem_stats() -> SyscallResult<MemStats> {
+    let mut stats = MemStats::default();
+    let result = unsafe { syscall1(SYS_MEM_STATS, &mut stats as *mut MemStats as u64) };
+
+    if result == ESUCCESS {
+        Ok(stats)
+    } else {
+        Err(SyscallError::InvalidArgument)
+    }
+}
+
+/// Get scheduler counters and thread states
+pub fn sched_stats() -> SyscallResult<SchedStats> {
+    let mut stats = SchedStats::default();
+    let result = unsafe { syscall1(SYS_SCHED_STATS, &mut stats as *mut SchedStats as u64) };
+
+    if result == ESUCCESS {
+        Ok(stats)
+    } else {
+        Err(SyscallError::InvalidArgument)
+    }
+}
+
+/// Describe every thread, filling `threads` from the start
+///
+/// Returns the number of threads, which is more than `threads.len()` when
+/// some did not fit.
+pub fn thread_list(threads: &mut [ThreadInfo]) -> SyscallResult<usize> {
+    let ptr = threads.as_mut_ptr() as u64;
+    let result = unsafe { syscall2(SYS_THREAD_LIST, ptr, threads.len() as u64) };
+
+    if result >= u64::MAX - 10 {
+        Err(SyscallError::InvalidArgument)
+    } else {
+        Ok(result as usize)
+    }
+}
+
+/// Read the newest entries of the capability audit log, oldest first
+///
+/// Returns the number of entries written to `entries`.
+pub fn cap_audit(entries: &mut [CapAuditEntry]) -> SyscallResult<usize> {
+    let ptr = entries.as_mut_ptr() as u64;
+    let result = unsafe { syscall2(SYS_CAP_AUDIT_READ, ptr, entries.len() as u64) };
+
+    if result >= u64::MAX - 10 {
+        Err(SyscallError::InvalidArgument)
+    } else {
+        Ok(result as usize)
+    }
+}
@@ -1,6 +1,35 @@
 // Thread management syscalls
+//
+// Besides the basic scheduler calls, `spawn` starts a closure on a thread
+// of its own:
+//
+//     let worker = thread::spawn(|| expensive_sum(&data))?;
+//     let sum = worker.join()?;
+//
+// Each thread's stack is a shared region the program maps only into
+// itself, wherever the kernel finds room. The closure and the slot for its
+// result are kept at the top of that stack, so spawning does not need the
+// heap. The kernel leaves an unmapped page between windows, so a thread
+// that overflows its stack faults instead of running into other memory.
 
-use crate::raw::{syscall0, syscall1, numbers::*};
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::ptr;
+
+use crate::error::{EINVAL, ENOMEM, EPERM, ETIMEDOUT, EWOULDBLOCK, SyscallError, SyscallResult};
+use crate::raw::{syscall0, syscall1, syscall2, syscall3, numbers::*};
+use crate::shm::{self, RegionFlags, RegionId};
+
+/// Thread identifier
+pub type ThreadId = u64;
+
+/// Stack size `spawn` gives a thread
+pub const DEFAULT_STACK_SIZE: usize = 64 * 1024;
+
+/// Largest stack a thread can have
+pub const MAX_STACK_SIZE: usize = 1024 * 1024;
+
+const PAGE_SIZE: usize = 4096;
 
 /// Yield CPU to scheduler
 /// 
@@ -55,3 +84,168 @@ pub fn get_ticks() -> u64 {
 pub fn get_time_ms() -> u64 {
     get_ticks() * 10  // Assuming 100Hz timer (10ms per tick)
 }
+
+/// Get the wall-clock time in seconds since the Unix epoch (UTC)
+///
+/// Read from the real-time clock at boot and advanced with the timer.
+#[inline]
+pub fn get_time() -> u64 {
+    unsafe {
+        syscall0(SYS_GET_TIME)
+    }
+}
+
+// ============================================================================
+// Thread Creation
+// ============================================================================
+
+/// Start a thread at `entry`, with `stack` as its stack pointer and `arg`
+/// as the first argument
+///
+/// # Safety
+///
+/// `entry` must be a function that takes one `u64` and never returns
+/// (it ends with `exit`), and `stack` must point into memory that nothing
+/// else uses for as long as the thread runs.
+pub unsafe fn create_raw(entry: u64, stack: u64, arg: u64) -> SyscallResult<ThreadId> {
+    let result = unsafe { syscall3(SYS_THREAD_CREATE, entry, stack, arg) };
+
+    match result {
+        EINVAL => Err(SyscallError::InvalidArgument),
+        ENOMEM => Err(SyscallError::OutOfMemory),
+        EPERM => Err(SyscallError::PermissionDenied),
+        tid => Ok(tid),
+    }
+}
+
+/// Wait up to `timeout_ms` for a thread this one created to exit
+///
+/// Returns its exit code; 0 polls without waiting, `u64::MAX` waits for
+/// as long as it runs. Once the exit code has been returned the thread
+/// is forgotten, and waiting for it again fails.
+pub fn wait(tid: ThreadId, timeout_ms: u64) -> SyscallResult<u64> {
+    let result = unsafe { syscall2(SYS_THREAD_JOIN, tid, timeout_ms) };
+
+    match result {
+        EINVAL => Err(SyscallError::InvalidArgument),
+        EPERM => Err(SyscallError::PermissionDenied),
+        ETIMEDOUT => Err(SyscallError::TimedOut),
+        EWOULDBLOCK => Err(SyscallError::WouldBlock),
+        code => Ok(code),
+    }
+}
+
+/// Run `f` on a new thread with a `DEFAULT_STACK_SIZE` stack
+pub fn spawn<F, T>(f: F) -> SyscallResult<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_with_stack_size(DEFAULT_STACK_SIZE, f)
+}
+
+/// Run `f` on a new thread with a stack of `stack_size` bytes, rounded up
+/// to whole pages; fails with `InvalidArgument` above `MAX_STACK_SIZE`
+/// and `OutOfMemory` if the kernel cannot give it a stack
+pub fn spawn_with_stack_size<F, T>(stack_size: usize, f: F) -> SyscallResult<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let size = stack_size.max(PAGE_SIZE).next_multiple_of(PAGE_SIZE);
+    let start_size = size_of::<Start<F, T>>();
+    if size > MAX_STACK_SIZE || start_size + 64 > size {
+        return Err(SyscallError::InvalidArgument);
+    }
+
+    let (stack, base) = map_stack(size)?;
+
+    // The closure goes at the top of the stack, the stack pointer below
+    // it, 8 bytes off a 16-byte boundary as if a call had pushed a return
+    // address
+    let top = base + size;
+    let align = align_of::<Start<F, T>>().max(16);
+    let start = ((top - start_size) & !(align - 1)) as *mut Start<F, T>;
+    let stack_pointer = (start as usize & !15) - 8;
+    unsafe { ptr::write(start, Start { f: Some(f), result: None }) };
+
+    let entry = trampoline::<F, T> as *const () as u64;
+    match unsafe { create_raw(entry, stack_pointer as u64, start as u64) } {
+        Ok(tid) => Ok(JoinHandle {
+            tid,
+            stack,
+            result: unsafe { ptr::addr_of_mut!((*start).result) },
+            _marker: PhantomData,
+        }),
+        Err(error) => {
+            unsafe { ptr::drop_in_place(start) };
+            release_stack(stack);
+            Err(error)
+        }
+    }
+}
+
+/// What a new thread finds at the top of its stack
+struct Start<F, T> {
+    f: Option<F>,
+    result: Option<T>,
+}
+
+extern "C" fn trampoline<F, T>(start: u64) -> !
+where
+    F: FnOnce() -> T,
+{
+    let start = unsafe { &mut *(start as *mut Start<F, T>) };
+    if let Some(f) = start.f.take() {
+        start.result = Some(f());
+    }
+    exit(0)
+}
+
+/// A thread started with `spawn`
+///
+/// Dropping the handle lets the thread run on by itself; its stack then
+/// stays mapped, since nothing learns when the thread is done with it.
+pub struct JoinHandle<T> {
+    tid: ThreadId,
+    stack: RegionId,
+    /// Where the thread leaves what its closure returned
+    result: *mut Option<T>,
+    _marker: PhantomData<T>,
+}
+
+unsafe impl<T: Send> Send for JoinHandle<T> {}
+
+impl<T> JoinHandle<T> {
+    pub fn id(&self) -> ThreadId {
+        self.tid
+    }
+
+    /// Wait for the thread to finish and take what its closure returned;
+    /// fails with `InvalidArgument` if it ended without returning, e.g.
+    /// because it panicked
+    pub fn join(self) -> SyscallResult<T> {
+        wait(self.tid, u64::MAX)?;
+        let result = unsafe { (*self.result).take() };
+        release_stack(self.stack);
+        result.ok_or(SyscallError::InvalidArgument)
+    }
+}
+
+/// Create a stack of `size` bytes and map it where the kernel chooses,
+/// returning the region and its lowest address
+fn map_stack(size: usize) -> SyscallResult<(RegionId, usize)> {
+    let region = shm::create_region(size)?;
+    match shm::map_anywhere(region, RegionFlags::read_write()) {
+        Ok(base) => Ok((region, base as usize)),
+        Err(error) => {
+            let _ = shm::destroy_region(region);
+            Err(error)
+        }
+    }
+}
+
+fn release_stack(stack: RegionId) {
+    let _ = shm::unmap_region(stack);
+    let _ = shm::destroy_region(stack);
+}
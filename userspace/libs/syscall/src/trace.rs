@@ -0,0 +1,170 @@
+// Kernel trace syscalls
+//
+// The kernel records scheduler, memory, IPC and syscall events into one
+// ring; these calls read it back and choose which subsystems are recorded.
+// Subsystem and event numbers must match kernel/src/trace.rs.
+
+use crate::error::{EINVAL, EPERM, SyscallError, SyscallResult};
+use crate::raw::{syscall2, syscall3, numbers::*};
+
+/// Arguments a record carries
+pub const MAX_ARGS: usize = 4;
+
+/// Part of the kernel that records events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Subsystem {
+    Sched = 0,
+    Mm = 1,
+    Ipc = 2,
+    Syscall = 3,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 4] =
+        [Subsystem::Sched, Subsystem::Mm, Subsystem::Ipc, Subsystem::Syscall];
+
+    pub fn from_u32(value: u32) -> Option<Self> {
+        Self::ALL.into_iter().find(|subsystem| *subsystem as u32 == value)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsystem::Sched => "sched",
+            Subsystem::Mm => "mm",
+            Subsystem::Ipc => "ipc",
+            Subsystem::Syscall => "syscall",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|subsystem| subsystem.name() == name)
+    }
+
+    /// This subsystem's bit in a trace mask
+    pub fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// Scheduler events
+pub mod sched {
+    /// A thread was switched in: (from thread or 0, to thread)
+    pub const SWITCH: u32 = 0;
+    /// A thread was put on a ready queue: (thread)
+    pub const WAKE: u32 = 1;
+}
+
+/// Memory events
+pub mod mm {
+    /// Physical pages allocated: (address, pages)
+    pub const PAGE_ALLOC: u32 = 0;
+    /// A physical page freed: (address, pages)
+    pub const PAGE_FREE: u32 = 1;
+    /// A page fault the kernel could not resolve: (address, error code, rip)
+    pub const PAGE_FAULT: u32 = 2;
+}
+
+/// IPC events
+pub mod ipc {
+    /// A message queued on a port: (port, sender, size)
+    pub const SEND: u32 = 0;
+    /// A message taken off a port: (port, sender, receiver, size)
+    pub const RECV: u32 = 1;
+}
+
+/// Syscall events
+pub mod syscall {
+    /// A syscall entered: (number, arg0, arg1, arg2)
+    pub const ENTER: u32 = 0;
+    /// A syscall returned: (number, result)
+    pub const EXIT: u32 = 1;
+}
+
+/// One event from the kernel trace
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Record {
+    /// Nanoseconds since boot
+    pub timestamp_ns: u64,
+    /// Thread running when the event happened, or 0
+    pub thread: u64,
+    pub subsystem: u32,
+    pub event: u32,
+    /// Meaning depends on the event; see the event constants
+    pub args: [u64; MAX_ARGS],
+}
+
+impl Record {
+    pub const EMPTY: Self = Self {
+        timestamp_ns: 0,
+        thread: 0,
+        subsystem: 0,
+        event: 0,
+        args: [0; MAX_ARGS],
+    };
+
+    pub fn subsystem(&self) -> Option<Subsystem> {
+        Subsystem::from_u32(self.subsystem)
+    }
+
+    /// Whether this is `event` of `subsystem`
+    pub fn is(&self, subsystem: Subsystem, event: u32) -> bool {
+        self.subsystem == subsystem as u32 && self.event == event
+    }
+
+    pub fn event_name(&self) -> &'static str {
+        match (self.subsystem(), self.event) {
+            (Some(Subsystem::Sched), sched::SWITCH) => "switch",
+            (Some(Subsystem::Sched), sched::WAKE) => "wake",
+            (Some(Subsystem::Mm), mm::PAGE_ALLOC) => "alloc",
+            (Some(Subsystem::Mm), mm::PAGE_FREE) => "free",
+            (Some(Subsystem::Mm), mm::PAGE_FAULT) => "fault",
+            (Some(Subsystem::Ipc), ipc::SEND) => "send",
+            (Some(Subsystem::Ipc), ipc::RECV) => "recv",
+            (Some(Subsystem::Syscall), syscall::ENTER) => "enter",
+            (Some(Subsystem::Syscall), syscall::EXIT) => "exit",
+            _ => "unknown",
+        }
+    }
+}
+
+/// Read trace records into `records`, oldest first
+///
+/// `cursor` is the sequence number of the next record, updated to continue
+/// where this read stopped; start at 0 to get the oldest record still held.
+/// Records overwritten since the last read are skipped, so the cursor may
+/// move by more than the count returned. Returns the number of records
+/// read, 0 once caught up; the kernel copies at most 64 per call.
+pub fn read(records: &mut [Record], cursor: &mut u64) -> SyscallResult<usize> {
+    let result = unsafe {
+        syscall3(
+            SYS_TRACE_READ,
+            records.as_mut_ptr() as u64,
+            records.len() as u64,
+            cursor as *mut u64 as u64,
+        )
+    };
+
+    if result == EINVAL {
+        Err(SyscallError::InvalidArgument)
+    } else {
+        Ok(result as usize)
+    }
+}
+
+/// Turn the subsystems in `enable` on and those in `disable` off, as masks
+/// of `Subsystem::bit`; returns the mask now in effect
+///
+/// Needs `DebugCap`; without it this fails with `PermissionDenied`.
+pub fn control(enable: u32, disable: u32) -> SyscallResult<u32> {
+    match unsafe { syscall2(SYS_TRACE_CONTROL, enable as u64, disable as u64) } {
+        EPERM => Err(SyscallError::PermissionDenied),
+        mask => Ok(mask as u32),
+    }
+}
+
+/// The subsystems being traced, as a mask of `Subsystem::bit`
+pub fn mask() -> u32 {
+    unsafe { syscall2(SYS_TRACE_CONTROL, 0, 0) as u32 }
+}